@@ -46,10 +46,12 @@ cargo build --verbose --color always --features rest-client
 cargo test --verbose --color always --features rest-client
 cargo build --verbose --color always --features rpc-client
 cargo test --verbose --color always --features rpc-client
+cargo build --verbose --color always --features p2p-client
+cargo test --verbose --color always --features p2p-client
 cargo build --verbose --color always --features rpc-client,rest-client
 cargo test --verbose --color always --features rpc-client,rest-client
-cargo build --verbose --color always --features rpc-client,rest-client,tokio
-cargo test --verbose --color always --features rpc-client,rest-client,tokio
+cargo build --verbose --color always --features rpc-client,rest-client,p2p-client,tokio
+cargo test --verbose --color always --features rpc-client,rest-client,p2p-client,tokio
 popd
 
 if [[ $RUSTC_MINOR_VERSION -gt 67 && "$HOST_PLATFORM" != *windows* ]]; then
@@ -61,6 +63,8 @@ if [[ $RUSTC_MINOR_VERSION -gt 67 && "$HOST_PLATFORM" != *windows* ]]; then
 	cargo test --verbose --color always --features esplora-async
 	cargo build --verbose --color always --features esplora-async-https
 	cargo test --verbose --color always --features esplora-async-https
+	cargo build --verbose --color always --features electrum
+	cargo test --verbose --color always --features electrum
 	popd
 fi
 
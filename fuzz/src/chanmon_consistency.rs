@@ -755,7 +755,7 @@ pub fn do_test<Out: Output>(data: &[u8], underlying_out: Out) {
 				for event in &mut events_iter {
 					had_events = true;
 					match event {
-						events::MessageSendEvent::UpdateHTLCs { node_id, updates: CommitmentUpdate { update_add_htlcs, update_fail_htlcs, update_fulfill_htlcs, update_fail_malformed_htlcs, update_fee, commitment_signed } } => {
+						events::MessageSendEvent::UpdateHTLCs { node_id, updates: CommitmentUpdate { update_add_htlcs, update_fail_htlcs, update_fulfill_htlcs, update_fail_malformed_htlcs, update_fee, commitment_signed, splice_commitment_signed } } => {
 							for (idx, dest) in nodes.iter().enumerate() {
 								if dest.get_our_node_id() == node_id {
 									for update_add in update_add_htlcs.iter() {
@@ -799,12 +799,16 @@ pub fn do_test<Out: Output>(data: &[u8], underlying_out: Out) {
 											update_fulfill_htlcs: Vec::new(),
 											update_fail_malformed_htlcs: Vec::new(),
 											update_fee: None,
-											commitment_signed
+											commitment_signed,
+											splice_commitment_signed,
 										} });
 										break;
 									}
 									out.locked_write(format!("Delivering commitment_signed to node {}.\n", idx).as_bytes());
 									dest.handle_commitment_signed(&nodes[$node].get_our_node_id(), &commitment_signed);
+									for msg in splice_commitment_signed.iter() {
+										dest.handle_commitment_signed(&nodes[$node].get_our_node_id(), msg);
+									}
 									break;
 								}
 							}
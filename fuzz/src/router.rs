@@ -271,6 +271,7 @@ pub fn do_test<Out: test_logger::Output>(data: &[u8], out: Out) {
 								config: None,
 								feerate_sat_per_1000_weight: None,
 								channel_shutdown_state: Some(channelmanager::ChannelShutdownState::NotShuttingDown),
+								channel_quiescence_state: Some(channelmanager::ChannelQuiescenceState::NotQuiescent),
 							});
 						}
 						Some(&first_hops_vec[..])
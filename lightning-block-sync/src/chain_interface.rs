@@ -0,0 +1,252 @@
+//! [`FeeEstimator`] and [`BroadcasterInterface`] implementations against a Bitcoin Core RPC
+//! endpoint via an [`RpcClient`].
+
+use crate::BlockSourceResult;
+use crate::gossip::FutureSpawner;
+use crate::rpc::RpcClient;
+
+use bitcoin::blockdata::transaction::Transaction;
+use bitcoin::consensus::encode;
+
+use lightning::chain::chaininterface::{BroadcasterInterface, ConfirmationTarget, FeeEstimator, FEERATE_FLOOR_SATS_PER_KW};
+use lightning::util::logger::Logger;
+use lightning::{log_debug, log_error};
+
+use std::collections::HashMap;
+use std::future::Future;
+use std::ops::Deref;
+use std::sync::RwLock;
+use std::time::Duration;
+
+/// The confirmation targets for which [`BitcoindFeeEstimator`] keeps feerate estimates.
+const CONFIRMATION_TARGETS: [ConfirmationTarget; 4] = [
+	ConfirmationTarget::MempoolMinimum,
+	ConfirmationTarget::Background,
+	ConfirmationTarget::Normal,
+	ConfirmationTarget::HighPriority,
+];
+
+/// Returns the feerate in satoshis per 1000 weight units to use for the given target if no
+/// estimate could be retrieved yet.
+fn fallback_sat_per_1000_weight(confirmation_target: ConfirmationTarget) -> u32 {
+	match confirmation_target {
+		ConfirmationTarget::MempoolMinimum => FEERATE_FLOOR_SATS_PER_KW,
+		ConfirmationTarget::Background => FEERATE_FLOOR_SATS_PER_KW,
+		ConfirmationTarget::Normal => 2000,
+		ConfirmationTarget::HighPriority => 5000,
+	}
+}
+
+/// Converts a feerate in BTC per 1000 virtual bytes, as used by Bitcoin Core, into satoshis per
+/// 1000 weight units.
+fn btc_per_kvb_to_sat_per_1000_weight(btc_per_kvb: f64) -> u32 {
+	let sat_per_1000_weight = (btc_per_kvb * 100_000_000.0 / 4.0).round();
+	if sat_per_1000_weight >= u32::max_value() as f64 {
+		u32::max_value()
+	} else {
+		core::cmp::max(sat_per_1000_weight as u32, FEERATE_FLOOR_SATS_PER_KW)
+	}
+}
+
+/// Parses the feerate from a response to the `estimatesmartfee` and `getmempoolinfo` RPC calls,
+/// which carry it in the given `field`, if present.
+fn parse_feerate(response: &serde_json::Value, field: &str) -> Option<u32> {
+	response.get(field)?.as_f64().map(btc_per_kvb_to_sat_per_1000_weight)
+}
+
+/// A [`FeeEstimator`] which caches feerate estimates retrieved from Bitcoin Core.
+///
+/// As [`FeeEstimator::get_est_sat_per_1000_weight`] must not block, estimates are only retrieved
+/// upon calls to [`update_fee_estimates`], which should be made once upon startup and then
+/// periodically in the background, e.g., every few minutes, such as by spawning the future
+/// returned by [`update_fee_estimates_periodically`]. Until an estimate could be retrieved for a
+/// given [`ConfirmationTarget`], a conservative fallback feerate is returned.
+///
+/// [`ConfirmationTarget::MempoolMinimum`] is served by the node's current minimum mempool feerate,
+/// while any other target is mapped to an `estimatesmartfee` call with a number of blocks matching
+/// its description.
+///
+/// [`update_fee_estimates`]: Self::update_fee_estimates
+/// [`update_fee_estimates_periodically`]: Self::update_fee_estimates_periodically
+pub struct BitcoindFeeEstimator<B: Deref<Target=RpcClient>> {
+	rpc_client: B,
+	fee_rate_cache: RwLock<HashMap<ConfirmationTarget, u32>>,
+}
+
+impl<B: Deref<Target=RpcClient>> BitcoindFeeEstimator<B> {
+	/// Creates a new fee estimator querying the given RPC client.
+	pub fn new(rpc_client: B) -> Self {
+		Self { rpc_client, fee_rate_cache: RwLock::new(HashMap::new()) }
+	}
+
+	/// Retrieves new feerate estimates for all [`ConfirmationTarget`]s.
+	///
+	/// Each target is updated independently. Targets for which no estimate is available, e.g., as
+	/// Bitcoin Core hasn't seen enough blocks yet, or for which the RPC endpoint could not be
+	/// queried keep their previous estimate or fallback. Returns the first error encountered, if
+	/// any, after all targets have been attempted.
+	pub async fn update_fee_estimates(&self) -> BlockSourceResult<()> {
+		let mut result = Ok(());
+		for confirmation_target in CONFIRMATION_TARGETS.iter() {
+			match self.fetch_fee_estimate(*confirmation_target).await {
+				Ok(Some(fee_rate)) => {
+					self.fee_rate_cache.write().unwrap().insert(*confirmation_target, fee_rate);
+				},
+				Ok(None) => {},
+				Err(e) => if result.is_ok() { result = Err(e); },
+			}
+		}
+		result
+	}
+
+	/// Calls [`update_fee_estimates`] right away and then every `interval`, waiting for the futures
+	/// returned by `sleeper` in between, until one of them resolves to `true`.
+	///
+	/// Failed updates are retried upon the next interval, with the previous estimates being kept
+	/// in the meantime. The returned future is meant to be spawned as a background task.
+	///
+	/// [`update_fee_estimates`]: Self::update_fee_estimates
+	pub async fn update_fee_estimates_periodically<
+		SleepFuture: Future<Output = bool>, Sleeper: Fn(Duration) -> SleepFuture
+	>(&self, interval: Duration, sleeper: Sleeper) {
+		loop {
+			let _ = self.update_fee_estimates().await;
+			if sleeper(interval).await { return; }
+		}
+	}
+
+	/// Queries the feerate estimate for the given target, if Bitcoin Core has one available.
+	async fn fetch_fee_estimate(&self, confirmation_target: ConfirmationTarget) -> BlockSourceResult<Option<u32>> {
+		let (num_blocks, estimate_mode) = match confirmation_target {
+			ConfirmationTarget::MempoolMinimum => {
+				let response: serde_json::Value = self.rpc_client.call_method("getmempoolinfo", &[]).await?;
+				return Ok(parse_feerate(&response, "mempoolminfee"));
+			},
+			ConfirmationTarget::Background => (144, "ECONOMICAL"),
+			ConfirmationTarget::Normal => (18, "ECONOMICAL"),
+			ConfirmationTarget::HighPriority => (6, "CONSERVATIVE"),
+		};
+		let params = [serde_json::json!(num_blocks), serde_json::json!(estimate_mode)];
+		let response: serde_json::Value = self.rpc_client.call_method("estimatesmartfee", &params).await?;
+		Ok(parse_feerate(&response, "feerate"))
+	}
+}
+
+impl<B: Deref<Target=RpcClient>> FeeEstimator for BitcoindFeeEstimator<B> {
+	fn get_est_sat_per_1000_weight(&self, confirmation_target: ConfirmationTarget) -> u32 {
+		self.fee_rate_cache.read().unwrap().get(&confirmation_target).copied()
+			.unwrap_or_else(|| fallback_sat_per_1000_weight(confirmation_target))
+	}
+}
+
+/// A [`BroadcasterInterface`] which hands transactions to Bitcoin Core.
+///
+/// Transactions are broadcast in the background using the given [`FutureSpawner`]. Single
+/// transactions are submitted via `sendrawtransaction`, while packages of multiple transactions,
+/// e.g., a commitment transaction and an anchor spend bumping its fee, are submitted via
+/// `submitpackage`. If the latter fails, e.g., as it isn't supported by the node, the package's
+/// transactions are submitted one by one in the given order instead.
+///
+/// Failures are logged but otherwise ignored, as LDK will rebroadcast transactions as needed.
+pub struct BitcoindBroadcaster<B: Deref<Target=RpcClient> + Clone + Send + Sync + 'static, S: FutureSpawner, L: Deref + Clone + Send + Sync + 'static>
+where L::Target: Logger {
+	rpc_client: B,
+	spawn: S,
+	logger: L,
+}
+
+impl<B: Deref<Target=RpcClient> + Clone + Send + Sync + 'static, S: FutureSpawner, L: Deref + Clone + Send + Sync + 'static> BitcoindBroadcaster<B, S, L>
+where L::Target: Logger {
+	/// Creates a new broadcaster submitting transactions via the given RPC client from futures
+	/// spawned by `spawn`.
+	pub fn new(rpc_client: B, spawn: S, logger: L) -> Self {
+		Self { rpc_client, spawn, logger }
+	}
+}
+
+/// Submits the given transaction via `sendrawtransaction`, logging any failure.
+async fn send_raw_transaction<L: Deref>(rpc_client: &RpcClient, tx: &Transaction, logger: &L)
+where L::Target: Logger {
+	let tx_hex = serde_json::json!(encode::serialize_hex(tx));
+	match rpc_client.call_method::<serde_json::Value>("sendrawtransaction", &[tx_hex]).await {
+		Ok(_) => log_debug!(logger, "Broadcast transaction {}", tx.txid()),
+		Err(e) => log_error!(logger, "Failed to broadcast transaction {}: {}", tx.txid(), e),
+	}
+}
+
+impl<B: Deref<Target=RpcClient> + Clone + Send + Sync + 'static, S: FutureSpawner, L: Deref + Clone + Send + Sync + 'static> BroadcasterInterface for BitcoindBroadcaster<B, S, L>
+where L::Target: Logger {
+	fn broadcast_transactions(&self, txs: &[&Transaction]) {
+		let txs: Vec<Transaction> = txs.iter().map(|tx| (*tx).clone()).collect();
+		let rpc_client = self.rpc_client.clone();
+		let logger = self.logger.clone();
+		self.spawn.spawn(async move {
+			if txs.len() > 1 {
+				let package: Vec<String> = txs.iter().map(encode::serialize_hex).collect();
+				match rpc_client.call_method::<serde_json::Value>("submitpackage", &[serde_json::json!(package)]).await {
+					Ok(_) => {
+						log_debug!(logger, "Broadcast package of {} transactions", txs.len());
+						return;
+					},
+					Err(e) => log_debug!(logger, "Failed to submit package, broadcasting transactions individually: {}", e),
+				}
+			}
+
+			for tx in txs.iter() {
+				send_raw_transaction(&rpc_client, tx, &logger).await;
+			}
+		});
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::http::client_tests::{HttpServer, MessageBody};
+
+	#[test]
+	fn converts_feerates_to_sat_per_1000_weight() {
+		assert_eq!(btc_per_kvb_to_sat_per_1000_weight(0.00001), FEERATE_FLOOR_SATS_PER_KW);
+		assert_eq!(btc_per_kvb_to_sat_per_1000_weight(0.0001), 2500);
+		assert_eq!(btc_per_kvb_to_sat_per_1000_weight(1000.0), u32::max_value());
+
+		let response = serde_json::json!({ "feerate": 0.0002, "blocks": 6 });
+		assert_eq!(parse_feerate(&response, "feerate"), Some(5000));
+		let response = serde_json::json!({ "errors": ["Insufficient data or no feerate found"], "blocks": 0 });
+		assert_eq!(parse_feerate(&response, "feerate"), None);
+	}
+
+	#[tokio::test]
+	async fn falls_back_when_estimates_are_unavailable() {
+		let server = HttpServer::responding_with_not_found();
+		let rpc_client = RpcClient::new("dXNlcjpwYXNzd29yZA==", server.endpoint()).unwrap();
+		let fee_estimator = BitcoindFeeEstimator::new(&rpc_client);
+
+		assert!(fee_estimator.update_fee_estimates().await.is_err());
+		for confirmation_target in CONFIRMATION_TARGETS.iter() {
+			assert_eq!(fee_estimator.get_est_sat_per_1000_weight(*confirmation_target),
+				fallback_sat_per_1000_weight(*confirmation_target));
+		}
+
+		fee_estimator.fee_rate_cache.write().unwrap().insert(ConfirmationTarget::Normal, 1000);
+		assert!(fee_estimator.update_fee_estimates().await.is_err());
+		assert_eq!(fee_estimator.get_est_sat_per_1000_weight(ConfirmationTarget::Normal), 1000);
+	}
+
+	#[tokio::test]
+	async fn updates_targets_independently() {
+		// Every call is answered with an `estimatesmartfee`-style response, which doesn't carry the
+		// mempool minimum feerate, so only that target is expected to keep its fallback.
+		let response = serde_json::json!({ "result": { "feerate": 0.0002, "blocks": 6 } });
+		let server = HttpServer::responding_with_ok(MessageBody::Content(response));
+		let rpc_client = RpcClient::new("dXNlcjpwYXNzd29yZA==", server.endpoint()).unwrap();
+		let fee_estimator = BitcoindFeeEstimator::new(&rpc_client);
+
+		fee_estimator.update_fee_estimates_periodically(Duration::from_secs(60), |_| core::future::ready(true)).await;
+		assert_eq!(fee_estimator.get_est_sat_per_1000_weight(ConfirmationTarget::MempoolMinimum),
+			fallback_sat_per_1000_weight(ConfirmationTarget::MempoolMinimum));
+		assert_eq!(fee_estimator.get_est_sat_per_1000_weight(ConfirmationTarget::Background), 5000);
+		assert_eq!(fee_estimator.get_est_sat_per_1000_weight(ConfirmationTarget::Normal), 5000);
+		assert_eq!(fee_estimator.get_est_sat_per_1000_weight(ConfirmationTarget::HighPriority), 5000);
+	}
+}
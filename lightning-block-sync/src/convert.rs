@@ -1,31 +1,21 @@
+use crate::filter::BlockFilterData;
 use crate::http::{BinaryResponse, JsonResponse};
 use crate::utils::hex_to_uint256;
-use crate::{BlockHeaderData, BlockSourceError};
+use crate::BlockHeaderData;
 
 use bitcoin::blockdata::block::{Block, BlockHeader};
 use bitcoin::consensus::encode;
-use bitcoin::hash_types::{BlockHash, TxMerkleNode, Txid};
+use bitcoin::hash_types::{BlockHash, FilterHeader, TxMerkleNode, Txid};
 use bitcoin::hashes::hex::FromHex;
+use bitcoin::util::bip158::BlockFilter;
 use bitcoin::Transaction;
 
 use serde_json;
 
-use std::convert::From;
 use std::convert::TryFrom;
 use std::convert::TryInto;
 use bitcoin::hashes::Hash;
 
-/// Conversion from `std::io::Error` into `BlockSourceError`.
-impl From<std::io::Error> for BlockSourceError {
-	fn from(e: std::io::Error) -> BlockSourceError {
-		match e.kind() {
-			std::io::ErrorKind::InvalidData => BlockSourceError::persistent(e),
-			std::io::ErrorKind::InvalidInput => BlockSourceError::persistent(e),
-			_ => BlockSourceError::transient(e),
-		}
-	}
-}
-
 /// Parses binary data as a block.
 impl TryInto<Block> for BinaryResponse {
 	type Error = std::io::Error;
@@ -138,6 +128,128 @@ impl TryInto<(BlockHash, Option<u32>)> for JsonResponse {
 	}
 }
 
+/// Converts a JSON value into a block filter. Assumes the filter is hex-encoded in the `filter`
+/// field of a JSON object.
+impl TryInto<BlockFilter> for JsonResponse {
+	type Error = std::io::Error;
+
+	fn try_into(self) -> std::io::Result<BlockFilter> {
+		if !self.0.is_object() {
+			return Err(std::io::Error::new(std::io::ErrorKind::InvalidData, "expected JSON object"));
+		}
+
+		match &self.0["filter"] {
+			serde_json::Value::String(hex_data) => match Vec::<u8>::from_hex(hex_data) {
+				Err(_) => Err(std::io::Error::new(std::io::ErrorKind::InvalidData, "invalid hex data")),
+				Ok(filter_data) => Ok(BlockFilter::new(&filter_data)),
+			},
+			_ => Err(std::io::Error::new(std::io::ErrorKind::InvalidData, "expected JSON string")),
+		}
+	}
+}
+
+/// Converts a JSON value into a filter header. The JSON value may be a hex-encoded string or an
+/// array of such strings. In the latter case, the first string is converted.
+impl TryInto<FilterHeader> for JsonResponse {
+	type Error = std::io::Error;
+
+	fn try_into(self) -> std::io::Result<FilterHeader> {
+		let filter_header = match self.0 {
+			serde_json::Value::Array(mut array) if !array.is_empty() => array.drain(..).next().unwrap(),
+			serde_json::Value::String(_) => self.0,
+			_ => return Err(std::io::Error::new(std::io::ErrorKind::InvalidData, "unexpected JSON type")),
+		};
+
+		match filter_header.as_str() {
+			None => Err(std::io::Error::new(std::io::ErrorKind::InvalidData, "expected JSON string")),
+			Some(hex_data) => match FilterHeader::from_hex(hex_data) {
+				Err(_) => Err(std::io::Error::new(std::io::ErrorKind::InvalidData, "invalid hex data")),
+				Ok(filter_header) => Ok(filter_header),
+			},
+		}
+	}
+}
+
+/// Converts a JSON value into a block filter and its filter header. Assumes both are hex-encoded
+/// in the `filter` and `header` fields of a JSON object, respectively.
+impl TryInto<BlockFilterData> for JsonResponse {
+	type Error = std::io::Error;
+
+	fn try_into(mut self) -> std::io::Result<BlockFilterData> {
+		if !self.0.is_object() {
+			return Err(std::io::Error::new(std::io::ErrorKind::InvalidData, "expected JSON object"));
+		}
+
+		let filter_header = JsonResponse(self.0["header"].take()).try_into()?;
+		let filter = self.try_into()?;
+		Ok(BlockFilterData { filter, filter_header })
+	}
+}
+
+/// Converts a JSON value into a block hash. The JSON value may be a hex-encoded string or an
+/// object containing one in its `blockhash` field.
+impl TryInto<BlockHash> for JsonResponse {
+	type Error = std::io::Error;
+
+	fn try_into(self) -> std::io::Result<BlockHash> {
+		let hex_data = match &self.0 {
+			serde_json::Value::String(hex_data) => hex_data,
+			serde_json::Value::Object(_) => match &self.0["blockhash"] {
+				serde_json::Value::String(hex_data) => hex_data,
+				_ => return Err(std::io::Error::new(std::io::ErrorKind::InvalidData, "expected JSON string")),
+			},
+			_ => return Err(std::io::Error::new(std::io::ErrorKind::InvalidData, "unexpected JSON type")),
+		};
+
+		match BlockHash::from_hex(hex_data) {
+			Err(_) => Err(std::io::Error::new(std::io::ErrorKind::InvalidData, "invalid hex data")),
+			Ok(block_hash) => Ok(block_hash),
+		}
+	}
+}
+
+/// The response to a REST `getutxos` request, indicating whether any of the requested outputs is
+/// unspent.
+pub(crate) struct GetUtxosResponse {
+	pub(crate) hit_bitmap_nonempty: bool,
+}
+
+/// Converts a JSON value into a `GetUtxosResponse`. Assumes the `bitmap` field of the JSON object
+/// contains one digit per requested output, which is non-zero if the output is unspent.
+impl TryInto<GetUtxosResponse> for JsonResponse {
+	type Error = std::io::Error;
+
+	fn try_into(self) -> std::io::Result<GetUtxosResponse> {
+		if !self.0.is_object() {
+			return Err(std::io::Error::new(std::io::ErrorKind::InvalidData, "expected JSON object"));
+		}
+
+		let bitmap = match &self.0["bitmap"] {
+			serde_json::Value::String(bitmap) => bitmap,
+			_ => return Err(std::io::Error::new(std::io::ErrorKind::InvalidData, "expected JSON string")),
+		};
+
+		let mut hit_bitmap_nonempty = false;
+		for c in bitmap.chars() {
+			match c {
+				'0' => {},
+				'1'..='9' => hit_bitmap_nonempty = true,
+				_ => return Err(std::io::Error::new(std::io::ErrorKind::InvalidData, "invalid bitmap")),
+			}
+		}
+		Ok(GetUtxosResponse { hit_bitmap_nonempty })
+	}
+}
+
+/// Passes through a JSON value, e.g., for results which may be `null`.
+impl TryInto<serde_json::Value> for JsonResponse {
+	type Error = std::io::Error;
+
+	fn try_into(self) -> std::io::Result<serde_json::Value> {
+		Ok(self.0)
+	}
+}
+
 impl TryInto<Txid> for JsonResponse {
 	type Error = std::io::Error;
 	fn try_into(self) -> std::io::Result<Txid> {
@@ -543,6 +655,111 @@ pub(crate) mod tests {
 		}
 	}
 
+	#[test]
+	fn into_block_filter_data_from_json_response_with_unexpected_type() {
+		let response = JsonResponse(serde_json::json!("foo"));
+		match TryInto::<BlockFilterData>::try_into(response) {
+			Err(e) => {
+				assert_eq!(e.kind(), std::io::ErrorKind::InvalidData);
+				assert_eq!(e.get_ref().unwrap().to_string(), "expected JSON object");
+			},
+			Ok(_) => panic!("Expected error"),
+		}
+	}
+
+	#[test]
+	fn into_block_filter_data_from_json_response_with_invalid_hex_data() {
+		let response = JsonResponse(serde_json::json!({
+			"filter": "foobar",
+			"header": FilterHeader::all_zeros().to_hex(),
+		}));
+		match TryInto::<BlockFilterData>::try_into(response) {
+			Err(e) => {
+				assert_eq!(e.kind(), std::io::ErrorKind::InvalidData);
+				assert_eq!(e.get_ref().unwrap().to_string(), "invalid hex data");
+			},
+			Ok(_) => panic!("Expected error"),
+		}
+	}
+
+	#[test]
+	fn into_block_filter_data_from_json_response_without_header() {
+		let response = JsonResponse(serde_json::json!({ "filter": "00" }));
+		match TryInto::<BlockFilterData>::try_into(response) {
+			Err(e) => {
+				assert_eq!(e.kind(), std::io::ErrorKind::InvalidData);
+				assert_eq!(e.get_ref().unwrap().to_string(), "unexpected JSON type");
+			},
+			Ok(_) => panic!("Expected error"),
+		}
+	}
+
+	#[test]
+	fn into_block_filter_data_from_json_response_with_valid_filter_data() {
+		let block = genesis_block(Network::Bitcoin);
+		let filter = BlockFilter::new_script_filter(&block, |_| unreachable!()).unwrap();
+		let filter_header = filter.filter_header(&FilterHeader::all_zeros());
+		let response = JsonResponse(serde_json::json!({
+			"filter": filter.content.to_hex(),
+			"header": filter_header.to_hex(),
+		}));
+		match TryInto::<BlockFilterData>::try_into(response) {
+			Err(e) => panic!("Unexpected error: {:?}", e),
+			Ok(filter_data) => {
+				assert_eq!(filter_data.filter, filter);
+				assert_eq!(filter_data.filter_header, filter_header);
+			},
+		}
+	}
+
+	#[test]
+	fn into_filter_header_from_json_response_with_valid_header_array() {
+		let filter_header = FilterHeader::hash(&[42; 32]);
+		let response = JsonResponse(serde_json::json!([filter_header.to_hex()]));
+		match TryInto::<FilterHeader>::try_into(response) {
+			Err(e) => panic!("Unexpected error: {:?}", e),
+			Ok(header) => assert_eq!(header, filter_header),
+		}
+	}
+
+	#[test]
+	fn into_block_hash_from_json_response_with_valid_hash() {
+		let block_hash = genesis_block(Network::Bitcoin).block_hash();
+		for response in [
+			serde_json::json!(block_hash.to_hex()),
+			serde_json::json!({ "blockhash": block_hash.to_hex() }),
+		].iter() {
+			match TryInto::<BlockHash>::try_into(JsonResponse(response.clone())) {
+				Err(e) => panic!("Unexpected error: {:?}", e),
+				Ok(hash) => assert_eq!(hash, block_hash),
+			}
+		}
+	}
+
+	#[test]
+	fn into_get_utxos_response_from_json_response() {
+		let response = JsonResponse(serde_json::json!({ "bitmap": "01" }));
+		match TryInto::<GetUtxosResponse>::try_into(response) {
+			Err(e) => panic!("Unexpected error: {:?}", e),
+			Ok(response) => assert!(response.hit_bitmap_nonempty),
+		}
+
+		let response = JsonResponse(serde_json::json!({ "bitmap": "0" }));
+		match TryInto::<GetUtxosResponse>::try_into(response) {
+			Err(e) => panic!("Unexpected error: {:?}", e),
+			Ok(response) => assert!(!response.hit_bitmap_nonempty),
+		}
+
+		let response = JsonResponse(serde_json::json!({ "bitmap": "x" }));
+		match TryInto::<GetUtxosResponse>::try_into(response) {
+			Err(e) => {
+				assert_eq!(e.kind(), std::io::ErrorKind::InvalidData);
+				assert_eq!(e.get_ref().unwrap().to_string(), "invalid bitmap");
+			},
+			Ok(_) => panic!("Expected error"),
+		}
+	}
+
 	#[test]
 	fn into_txid_from_json_response_with_unexpected_type() {
 		let response = JsonResponse(serde_json::json!({ "result": "foo" }));
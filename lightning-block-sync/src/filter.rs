@@ -0,0 +1,342 @@
+//! Utilities for syncing the chain using compact block filters ([BIP 157]/[BIP 158]).
+//!
+//! Defines a [`BlockFilterSource`] trait, which extends [`BlockSource`] with an interface for
+//! retrieving the basic block filter of a block along with its filter header. A
+//! [`FilteredBlockSource`] wraps such a source, implementing [`chain::Filter`] to learn which
+//! scripts are relevant and [`BlockSource`] to only fetch full blocks when their filters match any
+//! of them. It may thus be given to a [`ChainPoller`] to keep [`chain::Listen`] implementations in
+//! sync via an [`SpvClient`], while [`ConfirmListener`] allows for doing the same for
+//! [`chain::Confirm`] implementations.
+//!
+//! [BIP 157]: https://github.com/bitcoin/bips/blob/master/bip-0157.mediawiki
+//! [BIP 158]: https://github.com/bitcoin/bips/blob/master/bip-0158.mediawiki
+//! [`ChainPoller`]: crate::poll::ChainPoller
+//! [`SpvClient`]: crate::SpvClient
+
+use crate::{AsyncBlockSourceResult, BlockData, BlockHeaderData, BlockSource, BlockSourceError};
+
+use bitcoin::blockdata::block::BlockHeader;
+use bitcoin::blockdata::script::Script;
+use bitcoin::hash_types::{BlockHash, FilterHeader, Txid};
+use bitcoin::hashes::Hash;
+use bitcoin::util::bip158::BlockFilter;
+
+use lightning::chain;
+use lightning::chain::{Confirm, WatchedOutput};
+use lightning::chain::transaction::TransactionData;
+
+use std::collections::{HashMap, HashSet};
+use std::ops::Deref;
+use std::sync::Mutex;
+
+/// The number of blocks below the most recently fetched block for which filter headers are kept
+/// around, allowing filters to be checked against them when reorganizations occur.
+const FILTER_HEADER_CACHE_DEPTH: u32 = 144;
+
+/// Abstract type for retrieving compact block filters in addition to block headers and data.
+pub trait BlockFilterSource : BlockSource {
+	/// Returns the basic block filter ([BIP 158]) and its filter header ([BIP 157]) for the block
+	/// with the given hash.
+	///
+	/// [BIP 157]: https://github.com/bitcoin/bips/blob/master/bip-0157.mediawiki
+	/// [BIP 158]: https://github.com/bitcoin/bips/blob/master/bip-0158.mediawiki
+	fn get_block_filter<'a>(&'a self, header_hash: &'a BlockHash) -> AsyncBlockSourceResult<'a, BlockFilterData>;
+}
+
+/// A basic block filter and its position in the chain of filter headers.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct BlockFilterData {
+	/// The Golomb-coded set of scripts created and spent in the block.
+	pub filter: BlockFilter,
+
+	/// The filter header committing to the filter and all filters of preceding blocks.
+	pub filter_header: FilterHeader,
+}
+
+/// A [`BlockSource`] which only returns full blocks when their compact block filter matches any
+/// of the scripts registered via its [`chain::Filter`] implementation and otherwise returns
+/// [`BlockData::HeaderOnly`].
+///
+/// Each filter is checked to be consistent with its filter header and that of the preceding block,
+/// if it was seen before. Hence, the filter headers of all blocks fetched through this source form
+/// a chain which may be compared against those of other sources to detect a source withholding
+/// relevant transactions. When created via [`FilteredBlockSource::with_checkpoint`], the chain must
+/// instead extend from a trusted filter header, allowing filters to be fetched from untrusted
+/// sources such as peers queried via `p2p::P2PClient` (requires feature `p2p-client`).
+///
+/// Note that scripts must be registered before any block creating or spending them is fetched, as
+/// is the case when given as the [`chain::Filter`] of a [`ChainMonitor`]. Blocks which have
+/// already been fetched are not rescanned upon registration.
+///
+/// [`ChainMonitor`]: lightning::chain::chainmonitor::ChainMonitor
+pub struct FilteredBlockSource<B: Deref<Target=T> + Sized + Send + Sync, T: BlockFilterSource + ?Sized> {
+	block_source: B,
+	watched_scripts: Mutex<HashSet<Script>>,
+	filter_headers: Mutex<HashMap<BlockHash, (u32, FilterHeader)>>,
+	checkpointed: bool,
+}
+
+impl<B: Deref<Target=T> + Sized + Send + Sync, T: BlockFilterSource + ?Sized> FilteredBlockSource<B, T> {
+	/// Creates a new filtered block source wrapping the given block filter source.
+	pub fn new(block_source: B) -> Self {
+		Self {
+			block_source,
+			watched_scripts: Mutex::new(HashSet::new()),
+			filter_headers: Mutex::new(HashMap::new()),
+			checkpointed: false,
+		}
+	}
+
+	/// Creates a new filtered block source wrapping the given block filter source, which verifies
+	/// the chain of filter headers starting from the trusted `filter_header` of the block with the
+	/// given hash and height.
+	///
+	/// Any block fetched must descend from the checkpoint and have its parent fetched beforehand,
+	/// as is the case when polled by an [`SpvClient`] whose chain tip is the checkpoint. Otherwise,
+	/// or if the filter header chain diverges from the checkpoint, fetching the block fails.
+	/// Blocks reorganized out of the chain more than `FILTER_HEADER_CACHE_DEPTH` (144) blocks deep
+	/// can't be verified, so the checkpoint should be updated via [`Self::filter_header`] as the
+	/// chain progresses, e.g., when persisting the chain tip.
+	///
+	/// [`SpvClient`]: crate::SpvClient
+	pub fn with_checkpoint(
+		block_source: B, block_hash: BlockHash, height: u32, filter_header: FilterHeader
+	) -> Self {
+		let mut filter_headers = HashMap::new();
+		filter_headers.insert(block_hash, (height, filter_header));
+		Self {
+			block_source,
+			watched_scripts: Mutex::new(HashSet::new()),
+			filter_headers: Mutex::new(filter_headers),
+			checkpointed: true,
+		}
+	}
+
+	/// Returns the filter header of the block with the given hash if it was recently fetched.
+	pub fn filter_header(&self, block_hash: &BlockHash) -> Option<FilterHeader> {
+		self.filter_headers.lock().unwrap().get(block_hash).map(|(_, filter_header)| *filter_header)
+	}
+
+	/// Checks that the filter commits to its filter header given the filter header of the
+	/// preceding block, if known, and records the filter header for the block. The preceding
+	/// block's filter header must be known if the source was created with a checkpoint.
+	fn check_filter_header(
+		&self, header_data: &BlockHeaderData, filter_data: &BlockFilterData
+	) -> Result<(), BlockSourceError> {
+		let mut filter_headers = self.filter_headers.lock().unwrap();
+		let prev_blockhash = header_data.header.prev_blockhash;
+		let prev_filter_header = if prev_blockhash == BlockHash::all_zeros() {
+			Some(FilterHeader::all_zeros())
+		} else {
+			filter_headers.get(&prev_blockhash).map(|(_, filter_header)| *filter_header)
+		};
+		match prev_filter_header {
+			Some(prev_filter_header)
+				if filter_data.filter.filter_header(&prev_filter_header) != filter_data.filter_header => {
+				return Err(BlockSourceError::persistent("invalid filter header"));
+			},
+			None if self.checkpointed => {
+				return Err(BlockSourceError::persistent("unknown previous filter header"));
+			},
+			_ => {},
+		}
+
+		let height = header_data.height;
+		filter_headers.retain(|_, (filter_height, _)| *filter_height + FILTER_HEADER_CACHE_DEPTH >= height);
+		filter_headers.insert(header_data.header.block_hash(), (height, filter_data.filter_header));
+		Ok(())
+	}
+}
+
+impl<B: Deref<Target=T> + Sized + Send + Sync, T: BlockFilterSource + ?Sized> BlockSource for FilteredBlockSource<B, T> {
+	fn get_header<'a>(&'a self, header_hash: &'a BlockHash, height_hint: Option<u32>) -> AsyncBlockSourceResult<'a, BlockHeaderData> {
+		self.block_source.get_header(header_hash, height_hint)
+	}
+
+	fn get_block<'a>(&'a self, header_hash: &'a BlockHash) -> AsyncBlockSourceResult<'a, BlockData> {
+		Box::pin(async move {
+			let header_data = self.block_source.get_header(header_hash, None).await?;
+			if header_data.header.block_hash() != *header_hash {
+				return Err(BlockSourceError::persistent("invalid block hash"));
+			}
+
+			let filter_data = self.block_source.get_block_filter(header_hash).await?;
+			self.check_filter_header(&header_data, &filter_data)?;
+
+			let watched_scripts: Vec<Script> = self.watched_scripts.lock().unwrap().iter().cloned().collect();
+			if watched_scripts.is_empty() {
+				return Ok(BlockData::HeaderOnly(header_data.header));
+			}
+
+			let mut query = watched_scripts.iter().map(|script| script.as_bytes());
+			match filter_data.filter.match_any(header_hash, &mut query) {
+				Err(_) => Err(BlockSourceError::persistent("invalid block filter")),
+				Ok(false) => Ok(BlockData::HeaderOnly(header_data.header)),
+				Ok(true) => self.block_source.get_block(header_hash).await,
+			}
+		})
+	}
+
+	fn get_best_block<'a>(&'a self) -> AsyncBlockSourceResult<'a, (BlockHash, Option<u32>)> {
+		self.block_source.get_best_block()
+	}
+}
+
+impl<B: Deref<Target=T> + Sized + Send + Sync, T: BlockFilterSource + ?Sized> chain::Filter for FilteredBlockSource<B, T> {
+	fn register_tx(&self, _txid: &Txid, script_pubkey: &Script) {
+		self.watched_scripts.lock().unwrap().insert(script_pubkey.clone());
+	}
+
+	fn register_output(&self, output: WatchedOutput) {
+		// Basic block filters include the scripts of spent outputs, so watching the output's
+		// script suffices to find any spending transaction.
+		self.watched_scripts.lock().unwrap().insert(output.script_pubkey);
+	}
+}
+
+/// Adapts a [`chain::Confirm`] implementation to a [`chain::Listen`] implementation, allowing it
+/// to be kept in sync by an [`SpvClient`], e.g., using a [`FilteredBlockSource`].
+///
+/// Transactions of connected blocks are given as confirmed, after which the best block is updated.
+/// Any relevant transactions confirmed in a disconnected block are given as unconfirmed. As blocks
+/// are only disconnected when switching to a chain with more work, the best block will be updated
+/// once the first block of the new chain is connected.
+///
+/// [`SpvClient`]: crate::SpvClient
+pub struct ConfirmListener<C: Deref>(pub C) where C::Target: Confirm;
+
+impl<C: Deref> chain::Listen for ConfirmListener<C> where C::Target: Confirm {
+	fn filtered_block_connected(&self, header: &BlockHeader, txdata: &TransactionData, height: u32) {
+		self.0.transactions_confirmed(header, txdata, height);
+		self.0.best_block_updated(header, height);
+	}
+
+	fn block_disconnected(&self, header: &BlockHeader, _height: u32) {
+		let block_hash = header.block_hash();
+		for (txid, confirmation_block_hash) in self.0.get_relevant_txids() {
+			if confirmation_block_hash == Some(block_hash) {
+				self.0.transaction_unconfirmed(&txid);
+			}
+		}
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::test_utils::{Blockchain, MockChainListener};
+	use crate::poll::{ChainPoller, Poll};
+	use crate::{SpvClient, UnboundedCache};
+
+	use bitcoin::network::constants::Network;
+
+	use lightning::chain::Filter;
+
+	#[tokio::test]
+	async fn fetches_only_blocks_matching_watched_scripts() {
+		let script_pubkey = Script::new_op_return(&[42; 20]).to_p2sh();
+		let chain = Blockchain::default().with_height(3).with_script_pubkey_at_height(2, script_pubkey.clone());
+		let filtered_source = FilteredBlockSource::new(&chain);
+		filtered_source.register_tx(&Txid::all_zeros(), &script_pubkey);
+
+		let poller = ChainPoller::new(&filtered_source, Network::Testnet);
+		let mut cache = UnboundedCache::new();
+		let listener = MockChainListener::new()
+			.expect_filtered_block_connected(*chain.at_height(1))
+			.expect_block_connected(*chain.at_height(2))
+			.expect_filtered_block_connected(*chain.at_height(3));
+		let mut client = SpvClient::new(chain.at_height(0), poller, &mut cache, &listener);
+		match client.poll_best_tip().await {
+			Err(e) => panic!("Unexpected error: {:?}", e),
+			Ok((_, blocks_connected)) => assert!(blocks_connected),
+		}
+		assert_eq!(filtered_source.filter_header(&chain.tip().block_hash), Some(chain.filter_header_at_height(3)));
+	}
+
+	#[tokio::test]
+	async fn fetches_header_only_blocks_without_watched_scripts() {
+		let chain = Blockchain::default().with_height(2);
+		let filtered_source = FilteredBlockSource::new(&chain);
+
+		let poller = ChainPoller::new(&filtered_source, Network::Testnet);
+		let header = chain.at_height(1);
+		match poller.fetch_block(&header).await {
+			Err(e) => panic!("Unexpected error: {:?}", e),
+			Ok(block) => match *block {
+				BlockData::HeaderOnly(header) => assert_eq!(header, chain.at_height(1).header),
+				BlockData::FullBlock(_) => panic!("Expected header-only block"),
+			},
+		}
+	}
+
+	#[tokio::test]
+	async fn fails_on_invalid_filter_header() {
+		let chain = Blockchain::default().with_height(2).malformed_filter_headers();
+		let filtered_source = FilteredBlockSource::new(&chain);
+
+		let poller = ChainPoller::new(&filtered_source, Network::Testnet);
+		// The filter header of the first block fetched can't be checked without that of its parent.
+		if let Err(e) = poller.fetch_block(&chain.at_height(1)).await {
+			panic!("Unexpected error: {:?}", e);
+		}
+		match poller.fetch_block(&chain.at_height(2)).await {
+			Err(e) => {
+				assert_eq!(e.kind(), crate::BlockSourceErrorKind::Persistent);
+				assert_eq!(e.into_inner().as_ref().to_string(), "invalid filter header");
+			},
+			Ok(_) => panic!("Expected error"),
+		}
+		assert_eq!(filtered_source.filter_header(&chain.at_height(2).block_hash), None);
+	}
+
+	#[tokio::test]
+	async fn verifies_filter_headers_from_checkpoint() {
+		let chain = Blockchain::default().with_height(3);
+		let checkpoint = chain.at_height(1);
+		let filtered_source = FilteredBlockSource::with_checkpoint(
+			&chain, checkpoint.block_hash, checkpoint.height, chain.filter_header_at_height(1));
+
+		let poller = ChainPoller::new(&filtered_source, Network::Testnet);
+		for height in 2..=3 {
+			if let Err(e) = poller.fetch_block(&chain.at_height(height)).await {
+				panic!("Unexpected error: {:?}", e);
+			}
+		}
+		assert_eq!(filtered_source.filter_header(&chain.tip().block_hash), Some(chain.filter_header_at_height(3)));
+	}
+
+	#[tokio::test]
+	async fn fails_on_filter_header_diverging_from_checkpoint() {
+		let chain = Blockchain::default().with_height(2);
+		let checkpoint = chain.at_height(1);
+		let filtered_source = FilteredBlockSource::with_checkpoint(
+			&chain, checkpoint.block_hash, checkpoint.height, FilterHeader::all_zeros());
+
+		let poller = ChainPoller::new(&filtered_source, Network::Testnet);
+		match poller.fetch_block(&chain.at_height(2)).await {
+			Err(e) => {
+				assert_eq!(e.kind(), crate::BlockSourceErrorKind::Persistent);
+				assert_eq!(e.into_inner().as_ref().to_string(), "invalid filter header");
+			},
+			Ok(_) => panic!("Expected error"),
+		}
+	}
+
+	#[tokio::test]
+	async fn fails_on_block_not_descending_from_checkpoint() {
+		let chain = Blockchain::default().with_height(3);
+		let checkpoint = chain.at_height(1);
+		let filtered_source = FilteredBlockSource::with_checkpoint(
+			&chain, checkpoint.block_hash, checkpoint.height, chain.filter_header_at_height(1));
+
+		// The filter of a block whose parent wasn't fetched can't be verified.
+		let poller = ChainPoller::new(&filtered_source, Network::Testnet);
+		match poller.fetch_block(&chain.at_height(3)).await {
+			Err(e) => {
+				assert_eq!(e.kind(), crate::BlockSourceErrorKind::Persistent);
+				assert_eq!(e.into_inner().as_ref().to_string(), "unknown previous filter header");
+			},
+			Ok(_) => panic!("Expected error"),
+		}
+	}
+}
@@ -0,0 +1,296 @@
+//! When fetching gossip from peers, lightning nodes need to validate that gossip against the
+//! current UTXO set. This module defines an implementation of the LDK API required to do so
+//! against a [`BlockSource`] which implements a few additional methods for accessing the UTXO set.
+
+use crate::{AsyncBlockSourceResult, BlockData, BlockSource};
+
+use bitcoin::blockdata::block::Block;
+use bitcoin::blockdata::transaction::{OutPoint, TxOut};
+use bitcoin::hash_types::BlockHash;
+
+use lightning::ln::peer_handler::{CustomMessageHandler, PeerManager, SocketDescriptor};
+use lightning::ln::msgs::{ChannelMessageHandler, OnionMessageHandler};
+use lightning::routing::gossip::{NetworkGraph, P2PGossipSync};
+use lightning::routing::utxo::{UtxoFuture, UtxoLookup, UtxoResult, UtxoLookupError};
+use lightning::sign::NodeSigner;
+use lightning::util::logger::Logger;
+
+use std::sync::{Arc, Mutex};
+use std::collections::VecDeque;
+use std::future::Future;
+use std::ops::Deref;
+
+/// A trait which extends [`BlockSource`] and can be queried to fetch the block at a given height
+/// as well as whether a given output is unspent (i.e. a member of the current UTXO set).
+///
+/// Note that while this is implementable for a [`BlockSource`] which returns filtered block data
+/// (i.e. [`BlockData::HeaderOnly`] for [`BlockSource::get_block`] requests), such an
+/// implementation will reject all gossip as it is not fully able to verify the UTXOs referenced.
+pub trait UtxoSource : BlockSource + 'static {
+	/// Fetches the block hash of the block at the given height.
+	///
+	/// This will, in turn, be passed to to [`BlockSource::get_block`] to fetch the block needed
+	/// for gossip validation.
+	fn get_block_hash_by_height<'a>(&'a self, block_height: u32) -> AsyncBlockSourceResult<'a, BlockHash>;
+
+	/// Returns true if the given output has *not* been spent, i.e. is a member of the current UTXO
+	/// set.
+	fn is_output_unspent<'a>(&'a self, outpoint: OutPoint) -> AsyncBlockSourceResult<'a, bool>;
+}
+
+/// A generic trait which is able to spawn futures in the background.
+///
+/// If the `tokio` feature is enabled, this is implemented on `TokioSpawner` struct which
+/// delegates to `tokio::spawn()`.
+pub trait FutureSpawner : Send + Sync + 'static {
+	/// Spawns the given future as a background task.
+	///
+	/// This method MUST NOT block on the given future immediately.
+	fn spawn<T: Future<Output = ()> + Send + 'static>(&self, future: T);
+}
+
+#[cfg(feature = "tokio")]
+/// A trivial [`FutureSpawner`] which delegates to `tokio::spawn`.
+pub struct TokioSpawner;
+#[cfg(feature = "tokio")]
+impl FutureSpawner for TokioSpawner {
+	fn spawn<T: Future<Output = ()> + Send + 'static>(&self, future: T) {
+		tokio::spawn(future);
+	}
+}
+
+/// The number of recently fetched blocks kept around to answer lookups for channels funded in the
+/// same block without refetching it.
+const BLOCK_CACHE_SIZE: usize = 5;
+
+/// Returns the output at the given indices of the given block, if any.
+fn output_from_block(
+	block: &Block, transaction_index: u32, output_index: u16,
+) -> Result<(OutPoint, TxOut), UtxoLookupError> {
+	let transaction = block.txdata.get(transaction_index as usize).ok_or(UtxoLookupError::UnknownTx)?;
+	let output = transaction.output.get(output_index as usize).ok_or(UtxoLookupError::UnknownTx)?;
+	Ok((OutPoint::new(transaction.txid(), output_index.into()), output.clone()))
+}
+
+/// Looks up the output referenced by the given short channel id, first in the given cache of
+/// recently fetched blocks and then by fetching its block from the given source, and checks that
+/// it is unspent.
+async fn retrieve_utxo<B: Deref>(
+	source: B, block_cache: Arc<Mutex<VecDeque<(u32, Block)>>>, short_channel_id: u64,
+) -> Result<TxOut, UtxoLookupError> where B::Target: UtxoSource {
+	let block_height = (short_channel_id >> (5 * 8)) as u32; // block height is most significant three bytes
+	let transaction_index = ((short_channel_id >> (2 * 8)) & 0xffffff) as u32;
+	let output_index = (short_channel_id & 0xffff) as u16;
+
+	let cached_output = block_cache.lock().unwrap().iter()
+		.find(|(height, _)| *height == block_height)
+		.map(|(_, block)| output_from_block(block, transaction_index, output_index));
+
+	let (outpoint, output) = match cached_output {
+		Some(res) => res?,
+		None => {
+			let block_hash = source.get_block_hash_by_height(block_height).await
+				.map_err(|_| UtxoLookupError::UnknownTx)?;
+			let block_data = source.get_block(&block_hash).await
+				.map_err(|_| UtxoLookupError::UnknownTx)?;
+			let block = match block_data {
+				BlockData::HeaderOnly(_) => return Err(UtxoLookupError::UnknownTx),
+				BlockData::FullBlock(block) => block,
+			};
+			if block.block_hash() != block_hash {
+				return Err(UtxoLookupError::UnknownTx);
+			}
+
+			let res = output_from_block(&block, transaction_index, output_index)?;
+			let mut recent_blocks = block_cache.lock().unwrap();
+			if !recent_blocks.iter().any(|(height, _)| *height == block_height) {
+				if recent_blocks.len() >= BLOCK_CACHE_SIZE {
+					recent_blocks.pop_front();
+				}
+				recent_blocks.push_back((block_height, block));
+			}
+			res
+		},
+	};
+
+	let outpoint_unspent =
+		source.is_output_unspent(outpoint).await.map_err(|_| UtxoLookupError::UnknownTx)?;
+	if outpoint_unspent {
+		Ok(output)
+	} else {
+		Err(UtxoLookupError::UnknownTx)
+	}
+}
+
+/// A struct which wraps a [`UtxoSource`] and a few LDK objects and implements the LDK
+/// [`UtxoLookup`] trait.
+///
+/// Note that if you're using this against a Bitcoin Core REST or RPC server, you likely wish to
+/// increase the `rpcworkqueue` setting in Bitcoin Core as LDK attempts to parallelize requests (a
+/// value of 1024 should more than suffice), and ensure you have sufficient file descriptors
+/// available on both Bitcoin Core and your LDK application for each request to hold its own
+/// connection.
+///
+/// As the [`P2PGossipSync`] needs to be constructed before this, create it without a
+/// [`UtxoLookup`] and pass the verifier to [`P2PGossipSync::add_utxo_lookup`] once created.
+pub struct GossipVerifier<S: FutureSpawner,
+	Blocks: Deref + Send + Sync + 'static + Clone,
+	L: Deref + Send + Sync + 'static,
+	Descriptor: SocketDescriptor + Send + Sync + 'static,
+	CM: Deref + Send + Sync + 'static,
+	OM: Deref + Send + Sync + 'static,
+	CMH: Deref + Send + Sync + 'static,
+	NS: Deref + Send + Sync + 'static,
+> where
+	Blocks::Target: UtxoSource,
+	L::Target: Logger,
+	CM::Target: ChannelMessageHandler,
+	OM::Target: OnionMessageHandler,
+	CMH::Target: CustomMessageHandler,
+	NS::Target: NodeSigner,
+{
+	source: Blocks,
+	peer_manager: Arc<PeerManager<Descriptor, CM, Arc<P2PGossipSync<Arc<NetworkGraph<L>>, Self, L>>, OM, L, CMH, NS>>,
+	gossiper: Arc<P2PGossipSync<Arc<NetworkGraph<L>>, Self, L>>,
+	spawn: S,
+	block_cache: Arc<Mutex<VecDeque<(u32, Block)>>>,
+}
+
+impl<S: FutureSpawner,
+	Blocks: Deref + Send + Sync + Clone,
+	L: Deref + Send + Sync,
+	Descriptor: SocketDescriptor + Send + Sync,
+	CM: Deref + Send + Sync,
+	OM: Deref + Send + Sync,
+	CMH: Deref + Send + Sync,
+	NS: Deref + Send + Sync,
+> GossipVerifier<S, Blocks, L, Descriptor, CM, OM, CMH, NS> where
+	Blocks::Target: UtxoSource,
+	L::Target: Logger,
+	CM::Target: ChannelMessageHandler,
+	OM::Target: OnionMessageHandler,
+	CMH::Target: CustomMessageHandler,
+	NS::Target: NodeSigner,
+{
+	/// Constructs a new [`GossipVerifier`].
+	///
+	/// This is expected to be given to a [`P2PGossipSync`] (initially constructed with `None` for
+	/// the UTXO lookup) via [`P2PGossipSync::add_utxo_lookup`].
+	pub fn new(source: Blocks, spawn: S, gossiper: Arc<P2PGossipSync<Arc<NetworkGraph<L>>, Self, L>>, peer_manager: Arc<PeerManager<Descriptor, CM, Arc<P2PGossipSync<Arc<NetworkGraph<L>>, Self, L>>, OM, L, CMH, NS>>) -> Self {
+		Self {
+			source, spawn, gossiper, peer_manager,
+			block_cache: Arc::new(Mutex::new(VecDeque::with_capacity(BLOCK_CACHE_SIZE))),
+		}
+	}
+}
+
+impl<S: FutureSpawner,
+	Blocks: Deref + Send + Sync + Clone,
+	L: Deref + Send + Sync,
+	Descriptor: SocketDescriptor + Send + Sync,
+	CM: Deref + Send + Sync,
+	OM: Deref + Send + Sync,
+	CMH: Deref + Send + Sync,
+	NS: Deref + Send + Sync,
+> Deref for GossipVerifier<S, Blocks, L, Descriptor, CM, OM, CMH, NS> where
+	Blocks::Target: UtxoSource,
+	L::Target: Logger,
+	CM::Target: ChannelMessageHandler,
+	OM::Target: OnionMessageHandler,
+	CMH::Target: CustomMessageHandler,
+	NS::Target: NodeSigner,
+{
+	type Target = Self;
+	fn deref(&self) -> &Self { self }
+}
+
+
+impl<S: FutureSpawner,
+	Blocks: Deref + Send + Sync + Clone,
+	L: Deref + Send + Sync,
+	Descriptor: SocketDescriptor + Send + Sync,
+	CM: Deref + Send + Sync,
+	OM: Deref + Send + Sync,
+	CMH: Deref + Send + Sync,
+	NS: Deref + Send + Sync,
+> UtxoLookup for GossipVerifier<S, Blocks, L, Descriptor, CM, OM, CMH, NS> where
+	Blocks::Target: UtxoSource,
+	L::Target: Logger,
+	CM::Target: ChannelMessageHandler,
+	OM::Target: OnionMessageHandler,
+	CMH::Target: CustomMessageHandler,
+	NS::Target: NodeSigner,
+{
+	fn get_utxo(&self, _genesis_hash: &BlockHash, short_channel_id: u64) -> UtxoResult {
+		let res = UtxoFuture::new();
+		let fut = res.clone();
+		let source = self.source.clone();
+		let gossiper = Arc::clone(&self.gossiper);
+		let block_cache = Arc::clone(&self.block_cache);
+		let pm = Arc::clone(&self.peer_manager);
+		self.spawn.spawn(async move {
+			let res = retrieve_utxo(source, block_cache, short_channel_id).await;
+			fut.resolve(gossiper.network_graph(), &*gossiper, res);
+			pm.process_events();
+		});
+		UtxoResult::Async(res)
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::test_utils::Blockchain;
+
+	use bitcoin::blockdata::script::Script;
+
+	use std::sync::atomic::Ordering;
+
+	fn scid_from_parts(block: u64, tx_index: u64, vout_index: u64) -> u64 {
+		(block << 40) | (tx_index << 16) | vout_index
+	}
+
+	#[tokio::test]
+	async fn retrieves_unspent_utxo() {
+		let script_pubkey = Script::new_op_return(&[42; 20]).to_p2sh();
+		let chain = Arc::new(Blockchain::default().with_height(3).with_script_pubkey_at_height(2, script_pubkey.clone()));
+		let block_cache = Arc::new(Mutex::new(VecDeque::new()));
+
+		let short_channel_id = scid_from_parts(2, 0, 0);
+		match retrieve_utxo(Arc::clone(&chain), Arc::clone(&block_cache), short_channel_id).await {
+			Err(e) => panic!("Unexpected error: {:?}", e),
+			Ok(output) => assert_eq!(output.script_pubkey, script_pubkey),
+		}
+		assert_eq!(block_cache.lock().unwrap().len(), 1);
+		assert_eq!(chain.block_requests.load(Ordering::Relaxed), 2);
+
+		// Subsequent lookups in the same block are answered from the cache without refetching it.
+		match retrieve_utxo(Arc::clone(&chain), Arc::clone(&block_cache), short_channel_id).await {
+			Err(e) => panic!("Unexpected error: {:?}", e),
+			Ok(output) => assert_eq!(output.script_pubkey, script_pubkey),
+		}
+		let short_channel_id = scid_from_parts(2, 0, 1);
+		match retrieve_utxo(Arc::clone(&chain), Arc::clone(&block_cache), short_channel_id).await {
+			Err(UtxoLookupError::UnknownTx) => {},
+			_ => panic!("Expected UnknownTx"),
+		}
+		assert_eq!(block_cache.lock().unwrap().len(), 1);
+		assert_eq!(chain.block_requests.load(Ordering::Relaxed), 2);
+	}
+
+	#[tokio::test]
+	async fn fails_to_retrieve_spent_or_unknown_utxo() {
+		let script_pubkey = Script::new_op_return(&[42; 20]).to_p2sh();
+		let chain = Blockchain::default().with_height(3).with_script_pubkey_at_height(2, script_pubkey);
+		let spent_outpoint = OutPoint::new(chain.blocks[2].txdata[0].txid(), 0);
+		let chain = Arc::new(chain.with_spent_outputs(vec![spent_outpoint]));
+		let block_cache = Arc::new(Mutex::new(VecDeque::new()));
+
+		for short_channel_id in [scid_from_parts(2, 0, 0), scid_from_parts(2, 1, 0), scid_from_parts(4, 0, 0)].iter() {
+			match retrieve_utxo(Arc::clone(&chain), Arc::clone(&block_cache), *short_channel_id).await {
+				Err(UtxoLookupError::UnknownTx) => {},
+				_ => panic!("Expected UnknownTx"),
+			}
+		}
+	}
+}
@@ -7,11 +7,18 @@
 //! Defines a [`BlockSource`] trait, which is an asynchronous interface for retrieving block headers
 //! and data.
 //!
+//! The [`filter`] module allows for only fetching blocks containing relevant transactions using
+//! compact block filters (BIP 157/158).
+//!
 //! Enabling feature `rest-client` or `rpc-client` allows configuring the client to fetch blocks
-//! using Bitcoin Core's REST or RPC interface, respectively.
+//! using Bitcoin Core's REST or RPC interface, respectively. The latter also provides fee
+//! estimation and transaction broadcasting via Bitcoin Core in the `chain_interface` module.
+//!
+//! Enabling feature `p2p-client` allows fetching blocks and compact block filters from a Bitcoin
+//! peer via the `p2p` module.
 //!
-//! Both features support either blocking I/O using `std::net::TcpStream` or, with feature `tokio`,
-//! non-blocking I/O using `tokio::net::TcpStream` from inside a Tokio runtime.
+//! All three features support either blocking I/O using `std::net::TcpStream` or, with feature
+//! `tokio`, non-blocking I/O using `tokio::net::TcpStream` from inside a Tokio runtime.
 
 // Prefix these with `rustdoc::` when we update our MSRV to be >= 1.52 to remove warnings.
 #![deny(broken_intra_doc_links)]
@@ -25,6 +32,8 @@
 #[cfg(any(feature = "rest-client", feature = "rpc-client"))]
 pub mod http;
 
+pub mod filter;
+pub mod gossip;
 pub mod init;
 pub mod poll;
 
@@ -34,6 +43,12 @@ pub mod rest;
 #[cfg(feature = "rpc-client")]
 pub mod rpc;
 
+#[cfg(feature = "rpc-client")]
+pub mod chain_interface;
+
+#[cfg(feature = "p2p-client")]
+pub mod p2p;
+
 #[cfg(any(feature = "rest-client", feature = "rpc-client"))]
 mod convert;
 
@@ -140,6 +155,17 @@ impl BlockSourceError {
 	}
 }
 
+/// Conversion from `std::io::Error` into `BlockSourceError`.
+impl From<std::io::Error> for BlockSourceError {
+	fn from(e: std::io::Error) -> BlockSourceError {
+		match e.kind() {
+			std::io::ErrorKind::InvalidData => BlockSourceError::persistent(e),
+			std::io::ErrorKind::InvalidInput => BlockSourceError::persistent(e),
+			_ => BlockSourceError::transient(e),
+		}
+	}
+}
+
 /// A block header and some associated data. This information should be available from most block
 /// sources (and, notably, is available in Bitcoin Core's RPC and REST interfaces).
 #[derive(Clone, Copy, Debug, PartialEq, Eq)]
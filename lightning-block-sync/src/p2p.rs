@@ -0,0 +1,470 @@
+//! Simple peer-to-peer client implementation which implements [`BlockFilterSource`] by requesting
+//! blocks and compact block filters ([BIP 157]) from a Bitcoin peer.
+//!
+//! [BIP 157]: https://github.com/bitcoin/bips/blob/master/bip-0157.mediawiki
+
+use crate::{AsyncBlockSourceResult, BlockData, BlockHeaderData, BlockSource};
+use crate::filter::{BlockFilterData, BlockFilterSource};
+
+use bitcoin::blockdata::block::Block;
+use bitcoin::consensus::encode::{deserialize, serialize};
+use bitcoin::hash_types::BlockHash;
+use bitcoin::network::Address;
+use bitcoin::network::constants::{Network, ServiceFlags};
+use bitcoin::network::message::{NetworkMessage, RawNetworkMessage};
+use bitcoin::network::message_blockdata::Inventory;
+use bitcoin::network::message_filter::{GetCFHeaders, GetCFilters};
+use bitcoin::network::message_network::VersionMessage;
+use bitcoin::util::bip158::BlockFilter;
+
+#[cfg(feature = "tokio")]
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+#[cfg(feature = "tokio")]
+use tokio::net::TcpStream;
+
+#[cfg(not(feature = "tokio"))]
+use std::io::{Read, Write};
+#[cfg(not(feature = "tokio"))]
+use std::net::TcpStream;
+
+use std::net::SocketAddr;
+use std::ops::Deref;
+use std::sync::Mutex;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/// Timeout for connecting to the peer and for each read from or write to it.
+const TCP_STREAM_TIMEOUT: Duration = Duration::from_secs(60);
+
+/// Size of the header preceding the payload of each message.
+const MESSAGE_HEADER_SIZE: usize = 24;
+
+/// Maximum size of a message payload, large enough for any block.
+const MAX_MESSAGE_PAYLOAD_SIZE: usize = 4 * 1024 * 1024;
+
+/// Maximum number of unrelated messages read while waiting for the response to a request.
+const MAX_UNRELATED_MESSAGES: usize = 1000;
+
+/// The filter type of basic block filters ([BIP 158]).
+///
+/// [BIP 158]: https://github.com/bitcoin/bips/blob/master/bip-0158.mediawiki
+const BASIC_FILTER_TYPE: u8 = 0;
+
+const USER_AGENT: &str = concat!("/lightning-block-sync:", env!("CARGO_PKG_VERSION"), "/");
+
+/// A client requesting blocks via `getdata` and basic block filters via `getcfheaders` and
+/// `getcfilters` from a single Bitcoin peer, such as Bitcoin Core run with `-peerblockfilters`.
+///
+/// As filters are requested by block height, block headers along with their heights and the best
+/// chain tip are retrieved from a separate header source.
+///
+/// The filters returned are only checked to be consistent with the filter headers returned by the
+/// peer. As the peer isn't trusted, the client should be wrapped in a [`FilteredBlockSource`]
+/// created via [`FilteredBlockSource::with_checkpoint`] in order to verify the chain of filter
+/// headers from a trusted checkpoint.
+///
+/// [`FilteredBlockSource`]: crate::filter::FilteredBlockSource
+/// [`FilteredBlockSource::with_checkpoint`]: crate::filter::FilteredBlockSource::with_checkpoint
+pub struct P2PClient<B: Deref<Target=T> + Sized + Send + Sync, T: BlockSource + ?Sized> {
+	header_source: B,
+	address: SocketAddr,
+	network: Network,
+	connection: Mutex<Option<PeerConnection>>,
+}
+
+impl<B: Deref<Target=T> + Sized + Send + Sync, T: BlockSource + ?Sized> P2PClient<B, T> {
+	/// Creates a new client for the peer at the given address, connecting to it upon the first
+	/// request. The peer must signal support for serving witness blocks and compact block filters.
+	pub fn new(header_source: B, address: SocketAddr, network: Network) -> Self {
+		Self { header_source, address, network, connection: Mutex::new(None) }
+	}
+
+	/// Returns the current connection to the peer, connecting to it if needed. The connection
+	/// should be returned via [`Self::return_connection`] once a request completed successfully.
+	async fn take_connection(&self) -> std::io::Result<PeerConnection> {
+		let connection = self.connection.lock().unwrap().take();
+		match connection {
+			Some(connection) => Ok(connection),
+			None => PeerConnection::connect(&self.address, self.network).await,
+		}
+	}
+
+	fn return_connection(&self, connection: PeerConnection) {
+		*self.connection.lock().unwrap() = Some(connection);
+	}
+}
+
+impl<B: Deref<Target=T> + Sized + Send + Sync, T: BlockSource + ?Sized> BlockSource for P2PClient<B, T> {
+	fn get_header<'a>(&'a self, header_hash: &'a BlockHash, height_hint: Option<u32>) -> AsyncBlockSourceResult<'a, BlockHeaderData> {
+		self.header_source.get_header(header_hash, height_hint)
+	}
+
+	fn get_block<'a>(&'a self, header_hash: &'a BlockHash) -> AsyncBlockSourceResult<'a, BlockData> {
+		Box::pin(async move {
+			let mut connection = self.take_connection().await?;
+			let block = connection.get_block(header_hash).await?;
+			self.return_connection(connection);
+			Ok(BlockData::FullBlock(block))
+		})
+	}
+
+	fn get_best_block<'a>(&'a self) -> AsyncBlockSourceResult<'a, (BlockHash, Option<u32>)> {
+		self.header_source.get_best_block()
+	}
+}
+
+impl<B: Deref<Target=T> + Sized + Send + Sync, T: BlockSource + ?Sized> BlockFilterSource for P2PClient<B, T> {
+	fn get_block_filter<'a>(&'a self, header_hash: &'a BlockHash) -> AsyncBlockSourceResult<'a, BlockFilterData> {
+		Box::pin(async move {
+			let height = self.header_source.get_header(header_hash, None).await?.height;
+			let mut connection = self.take_connection().await?;
+			let filter_data = connection.get_block_filter(header_hash, height).await?;
+			self.return_connection(connection);
+			Ok(filter_data)
+		})
+	}
+}
+
+/// A connection to a peer which completed the version handshake.
+struct PeerConnection {
+	stream: TcpStream,
+	magic: u32,
+}
+
+impl PeerConnection {
+	/// Connects to the peer at the given address and performs the version handshake.
+	async fn connect(address: &SocketAddr, network: Network) -> std::io::Result<Self> {
+		let stream = std::net::TcpStream::connect_timeout(address, TCP_STREAM_TIMEOUT)?;
+		stream.set_read_timeout(Some(TCP_STREAM_TIMEOUT))?;
+		stream.set_write_timeout(Some(TCP_STREAM_TIMEOUT))?;
+
+		#[cfg(feature = "tokio")]
+		let stream = {
+			stream.set_nonblocking(true)?;
+			TcpStream::from_std(stream)?
+		};
+
+		let mut connection = Self { stream, magic: network.magic() };
+		connection.handshake(address).await?;
+		Ok(connection)
+	}
+
+	async fn handshake(&mut self, address: &SocketAddr) -> std::io::Result<()> {
+		let now = SystemTime::now().duration_since(UNIX_EPOCH).expect("Time must be > 1970");
+		let version = VersionMessage::new(
+			ServiceFlags::NONE,
+			now.as_secs() as i64,
+			Address::new(address, ServiceFlags::NONE),
+			Address::new(&SocketAddr::from(([0, 0, 0, 0], 0)), ServiceFlags::NONE),
+			now.as_nanos() as u64,
+			USER_AGENT.to_string(),
+			0,
+		);
+		self.send(NetworkMessage::Version(version)).await?;
+
+		let mut received_version = false;
+		let mut received_verack = false;
+		for _ in 0..MAX_UNRELATED_MESSAGES {
+			match self.receive().await? {
+				NetworkMessage::Version(version) => {
+					if !version.services.has(ServiceFlags::WITNESS) ||
+						!version.services.has(ServiceFlags::COMPACT_FILTERS)
+					{
+						return Err(std::io::Error::new(std::io::ErrorKind::InvalidInput,
+							"peer does not serve witness blocks and compact block filters"));
+					}
+					received_version = true;
+					self.send(NetworkMessage::Verack).await?;
+				},
+				NetworkMessage::Verack => received_verack = true,
+				NetworkMessage::Ping(nonce) => self.send(NetworkMessage::Pong(nonce)).await?,
+				_ => {},
+			}
+			if received_version && received_verack {
+				return Ok(());
+			}
+		}
+		Err(std::io::Error::new(std::io::ErrorKind::TimedOut, "peer did not complete handshake"))
+	}
+
+	/// Requests the block with the given hash.
+	async fn get_block(&mut self, block_hash: &BlockHash) -> std::io::Result<Block> {
+		self.send(NetworkMessage::GetData(vec![Inventory::WitnessBlock(*block_hash)])).await?;
+		self.receive_response(|message| match message {
+			NetworkMessage::Block(block) if block.block_hash() == *block_hash => Some(Ok(block)),
+			NetworkMessage::NotFound(inventory) if inventory.contains(&Inventory::WitnessBlock(*block_hash)) => {
+				Some(Err(std::io::Error::new(std::io::ErrorKind::NotFound, "block not found")))
+			},
+			_ => None,
+		}).await
+	}
+
+	/// Requests the basic block filter and filter header for the block with the given hash at the
+	/// given height, checking that the filter matches the filter header.
+	async fn get_block_filter(&mut self, block_hash: &BlockHash, height: u32) -> std::io::Result<BlockFilterData> {
+		self.send(NetworkMessage::GetCFHeaders(GetCFHeaders {
+			filter_type: BASIC_FILTER_TYPE,
+			start_height: height,
+			stop_hash: *block_hash,
+		})).await?;
+		let filter_headers = self.receive_response(|message| match message {
+			NetworkMessage::CFHeaders(filter_headers)
+				if filter_headers.filter_type == BASIC_FILTER_TYPE && filter_headers.stop_hash == *block_hash
+				=> Some(Ok(filter_headers)),
+			_ => None,
+		}).await?;
+		if filter_headers.filter_hashes.len() != 1 {
+			return Err(std::io::Error::new(std::io::ErrorKind::InvalidData, "unexpected number of filter hashes"));
+		}
+		let previous_filter_header = filter_headers.previous_filter_header;
+		let filter_header = filter_headers.filter_hashes[0].filter_header(&previous_filter_header);
+
+		self.send(NetworkMessage::GetCFilters(GetCFilters {
+			filter_type: BASIC_FILTER_TYPE,
+			start_height: height,
+			stop_hash: *block_hash,
+		})).await?;
+		let filter = self.receive_response(|message| match message {
+			NetworkMessage::CFilter(filter)
+				if filter.filter_type == BASIC_FILTER_TYPE && filter.block_hash == *block_hash
+				=> Some(Ok(BlockFilter::new(&filter.filter))),
+			_ => None,
+		}).await?;
+		if filter.filter_header(&previous_filter_header) != filter_header {
+			return Err(std::io::Error::new(std::io::ErrorKind::InvalidData, "filter does not match filter header"));
+		}
+
+		Ok(BlockFilterData { filter, filter_header })
+	}
+
+	/// Reads messages until `response` returns a result for one of them, answering pings and
+	/// skipping any other messages in the meantime.
+	async fn receive_response<R, F>(&mut self, mut response: F) -> std::io::Result<R>
+	where F: FnMut(NetworkMessage) -> Option<std::io::Result<R>> {
+		for _ in 0..MAX_UNRELATED_MESSAGES {
+			match self.receive().await? {
+				NetworkMessage::Ping(nonce) => self.send(NetworkMessage::Pong(nonce)).await?,
+				message => if let Some(result) = response(message) {
+					return result;
+				},
+			}
+		}
+		Err(std::io::Error::new(std::io::ErrorKind::TimedOut, "peer did not respond"))
+	}
+
+	/// Writes a message with the given payload.
+	async fn send(&mut self, payload: NetworkMessage) -> std::io::Result<()> {
+		let message = serialize(&RawNetworkMessage { magic: self.magic, payload });
+		#[cfg(feature = "tokio")]
+		{
+			self.stream.write_all(&message).await?;
+			self.stream.flush().await
+		}
+		#[cfg(not(feature = "tokio"))]
+		{
+			self.stream.write_all(&message)?;
+			self.stream.flush()
+		}
+	}
+
+	/// Reads a message, returning its payload.
+	async fn receive(&mut self) -> std::io::Result<NetworkMessage> {
+		let mut message = vec![0; MESSAGE_HEADER_SIZE];
+		self.read_exact(&mut message).await?;
+		let payload_size = u32::from_le_bytes([message[16], message[17], message[18], message[19]]) as usize;
+		if payload_size > MAX_MESSAGE_PAYLOAD_SIZE {
+			return Err(std::io::Error::new(std::io::ErrorKind::InvalidData, "message too large"));
+		}
+		message.resize(MESSAGE_HEADER_SIZE + payload_size, 0);
+		self.read_exact(&mut message[MESSAGE_HEADER_SIZE..]).await?;
+
+		let message: RawNetworkMessage = deserialize(&message)
+			.map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidData, e))?;
+		if message.magic != self.magic {
+			return Err(std::io::Error::new(std::io::ErrorKind::InvalidData, "unexpected network magic"));
+		}
+		Ok(message.payload)
+	}
+
+	async fn read_exact(&mut self, buf: &mut [u8]) -> std::io::Result<()> {
+		#[cfg(feature = "tokio")]
+		{
+			match tokio::time::timeout(TCP_STREAM_TIMEOUT, self.stream.read_exact(buf)).await {
+				Ok(result) => result.map(|_| ()),
+				Err(_) => Err(std::io::Error::new(std::io::ErrorKind::TimedOut, "timed out reading from peer")),
+			}
+		}
+		#[cfg(not(feature = "tokio"))]
+		{
+			self.stream.read_exact(buf)
+		}
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::filter::FilteredBlockSource;
+	use crate::test_utils::Blockchain;
+
+	use bitcoin::consensus::Decodable;
+	use bitcoin::hash_types::{FilterHash, FilterHeader};
+	use bitcoin::hashes::Hash;
+	use bitcoin::network::message_filter::{CFHeaders, CFilter};
+
+	use std::io::Write;
+
+	/// A block along with its basic filter and filter header.
+	struct ServedBlock {
+		block: Block,
+		filter_data: BlockFilterData,
+	}
+
+	/// A peer serving the given blocks from a separate thread, stopping once the connection is
+	/// closed.
+	struct MockPeer {
+		address: SocketAddr,
+	}
+
+	impl MockPeer {
+		async fn serving(chain: &Blockchain, services: ServiceFlags) -> Self {
+			let mut blocks = Vec::new();
+			for height in 0..=chain.tip().height as usize {
+				let block_hash = chain.at_height(height).block_hash;
+				let block = match chain.get_block(&block_hash).await.unwrap() {
+					BlockData::FullBlock(block) => block,
+					BlockData::HeaderOnly(_) => panic!("Expected full block"),
+				};
+				let filter_data = chain.get_block_filter(&block_hash).await.unwrap();
+				blocks.push(ServedBlock { block, filter_data });
+			}
+
+			let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+			let address = listener.local_addr().unwrap();
+			std::thread::spawn(move || {
+				let mut stream = listener.incoming().next().unwrap().unwrap();
+				let magic = Network::Testnet.magic();
+				let send = |stream: &mut std::net::TcpStream, payload| {
+					stream.write_all(&serialize(&RawNetworkMessage { magic, payload })).unwrap();
+				};
+				loop {
+					let message = match RawNetworkMessage::consensus_decode(&mut stream) {
+						Ok(message) => message.payload,
+						Err(_) => return,
+					};
+					match message {
+						NetworkMessage::Version(_) => {
+							let receiver = Address::new(&address, ServiceFlags::NONE);
+							let version = VersionMessage::new(services, 0, receiver.clone(), receiver, 0, String::new(), 0);
+							send(&mut stream, NetworkMessage::Version(version));
+							send(&mut stream, NetworkMessage::Verack);
+						},
+						NetworkMessage::GetCFHeaders(request) => {
+							let height = request.start_height as usize;
+							assert_eq!(blocks[height].block.block_hash(), request.stop_hash);
+							// Ensure the client answers pings while waiting for a response.
+							send(&mut stream, NetworkMessage::Ping(42));
+							let previous_filter_header = match height {
+								0 => FilterHeader::all_zeros(),
+								_ => blocks[height - 1].filter_data.filter_header,
+							};
+							let filter_hash = FilterHash::hash(&blocks[height].filter_data.filter.content);
+							send(&mut stream, NetworkMessage::CFHeaders(CFHeaders {
+								filter_type: BASIC_FILTER_TYPE,
+								stop_hash: request.stop_hash,
+								previous_filter_header,
+								filter_hashes: vec![filter_hash],
+							}));
+						},
+						NetworkMessage::GetCFilters(request) => {
+							let height = request.start_height as usize;
+							send(&mut stream, NetworkMessage::CFilter(CFilter {
+								filter_type: BASIC_FILTER_TYPE,
+								block_hash: request.stop_hash,
+								filter: blocks[height].filter_data.filter.content.clone(),
+							}));
+						},
+						NetworkMessage::GetData(inventory) => {
+							for inv in inventory {
+								match blocks.iter().find(|b| Inventory::WitnessBlock(b.block.block_hash()) == inv) {
+									Some(served) => send(&mut stream, NetworkMessage::Block(served.block.clone())),
+									None => send(&mut stream, NetworkMessage::NotFound(vec![inv])),
+								}
+							}
+						},
+						_ => {},
+					}
+				}
+			});
+
+			Self { address }
+		}
+	}
+
+	#[tokio::test]
+	async fn get_block_filter_from_peer() {
+		let chain = Blockchain::default().with_height(2);
+		let peer = MockPeer::serving(&chain, ServiceFlags::WITNESS | ServiceFlags::COMPACT_FILTERS).await;
+		let client = P2PClient::new(&chain, peer.address, Network::Testnet);
+
+		for height in 1..=2 {
+			let block_hash = chain.at_height(height).block_hash;
+			match client.get_block_filter(&block_hash).await {
+				Err(e) => panic!("Unexpected error: {:?}", e),
+				Ok(filter_data) => assert_eq!(filter_data, chain.get_block_filter(&block_hash).await.unwrap()),
+			}
+		}
+	}
+
+	#[tokio::test]
+	async fn get_block_from_peer() {
+		let chain = Blockchain::default().with_height(1);
+		let peer = MockPeer::serving(&chain, ServiceFlags::WITNESS | ServiceFlags::COMPACT_FILTERS).await;
+		let client = P2PClient::new(&chain, peer.address, Network::Testnet);
+
+		let block_hash = chain.at_height(1).block_hash;
+		match client.get_block(&block_hash).await {
+			Err(e) => panic!("Unexpected error: {:?}", e),
+			Ok(BlockData::FullBlock(block)) => assert_eq!(block.block_hash(), block_hash),
+			Ok(BlockData::HeaderOnly(_)) => panic!("Expected full block"),
+		}
+
+		// Blocks unknown to the peer result in an error.
+		let unknown_block_hash = Blockchain::default().with_height(2).at_height(2).block_hash;
+		match client.get_block(&unknown_block_hash).await {
+			Err(e) => assert_eq!(e.kind(), crate::BlockSourceErrorKind::Transient),
+			Ok(_) => panic!("Expected error"),
+		}
+	}
+
+	#[tokio::test]
+	async fn verifies_peer_filters_from_checkpoint() {
+		let chain = Blockchain::default().with_height(3);
+		let peer = MockPeer::serving(&chain, ServiceFlags::WITNESS | ServiceFlags::COMPACT_FILTERS).await;
+		let client = P2PClient::new(&chain, peer.address, Network::Testnet);
+		let checkpoint = chain.at_height(1);
+		let filtered_source = FilteredBlockSource::with_checkpoint(
+			&client, checkpoint.block_hash, checkpoint.height, chain.filter_header_at_height(1));
+
+		for height in 2..=3 {
+			if let Err(e) = filtered_source.get_block(&chain.at_height(height).block_hash).await {
+				panic!("Unexpected error: {:?}", e);
+			}
+		}
+		assert_eq!(filtered_source.filter_header(&chain.tip().block_hash), Some(chain.filter_header_at_height(3)));
+	}
+
+	#[tokio::test]
+	async fn fails_connecting_to_peer_without_compact_filters() {
+		let chain = Blockchain::default().with_height(1);
+		let peer = MockPeer::serving(&chain, ServiceFlags::WITNESS).await;
+		let client = P2PClient::new(&chain, peer.address, Network::Testnet);
+
+		match client.get_block_filter(&chain.at_height(1).block_hash).await {
+			Err(e) => {
+				assert_eq!(e.kind(), crate::BlockSourceErrorKind::Persistent);
+				assert_eq!(e.into_inner().as_ref().to_string(), "peer does not serve witness blocks and compact block filters");
+			},
+			Ok(_) => panic!("Expected error"),
+		}
+	}
+}
@@ -2,10 +2,14 @@
 //! endpoint.
 
 use crate::{BlockData, BlockHeaderData, BlockSource, AsyncBlockSourceResult};
+use crate::convert::GetUtxosResponse;
+use crate::filter::{BlockFilterData, BlockFilterSource};
+use crate::gossip::UtxoSource;
 use crate::http::{BinaryResponse, HttpEndpoint, HttpClient, JsonResponse};
 
 use bitcoin::hash_types::BlockHash;
 use bitcoin::hashes::hex::ToHex;
+use bitcoin::OutPoint;
 
 use std::convert::TryFrom;
 use std::convert::TryInto;
@@ -60,6 +64,37 @@ impl BlockSource for RestClient {
 	}
 }
 
+/// Requires Bitcoin Core to be run with `-blockfilterindex`.
+impl BlockFilterSource for RestClient {
+	fn get_block_filter<'a>(&'a self, header_hash: &'a BlockHash) -> AsyncBlockSourceResult<'a, BlockFilterData> {
+		Box::pin(async move {
+			let resource_path = format!("blockfilter/basic/{}.json", header_hash.to_hex());
+			let filter = self.request_resource::<JsonResponse, _>(&resource_path).await?;
+			let resource_path = format!("blockfilterheaders/basic/1/{}.json", header_hash.to_hex());
+			let filter_header = self.request_resource::<JsonResponse, _>(&resource_path).await?;
+			Ok(BlockFilterData { filter, filter_header })
+		})
+	}
+}
+
+impl UtxoSource for RestClient {
+	fn get_block_hash_by_height<'a>(&'a self, block_height: u32) -> AsyncBlockSourceResult<'a, BlockHash> {
+		Box::pin(async move {
+			let resource_path = format!("blockhashbyheight/{}.json", block_height);
+			Ok(self.request_resource::<JsonResponse, _>(&resource_path).await?)
+		})
+	}
+
+	fn is_output_unspent<'a>(&'a self, outpoint: OutPoint) -> AsyncBlockSourceResult<'a, bool> {
+		Box::pin(async move {
+			let resource_path = format!("getutxos/{}-{}.json", outpoint.txid.to_hex(), outpoint.vout);
+			let utxo_result =
+				self.request_resource::<JsonResponse, GetUtxosResponse>(&resource_path).await?;
+			Ok(utxo_result.hit_bitmap_nonempty)
+		})
+	}
+}
+
 #[cfg(test)]
 mod tests {
 	use super::*;
@@ -2,10 +2,13 @@
 //! endpoint.
 
 use crate::{BlockData, BlockHeaderData, BlockSource, AsyncBlockSourceResult};
+use crate::filter::{BlockFilterData, BlockFilterSource};
+use crate::gossip::UtxoSource;
 use crate::http::{HttpClient, HttpEndpoint, HttpError, JsonResponse};
 
 use bitcoin::hash_types::BlockHash;
 use bitcoin::hashes::hex::ToHex;
+use bitcoin::OutPoint;
 
 use std::sync::Mutex;
 
@@ -105,12 +108,13 @@ impl RpcClient {
 			return Err(std::io::Error::new(std::io::ErrorKind::Other, rpc_error));
 		}
 
-		let result = &mut response["result"];
-		if result.is_null() {
-			return Err(std::io::Error::new(std::io::ErrorKind::InvalidData, "expected JSON result"));
-		}
+		// Note that the result may be `null` for some methods, e.g., `gettxout` for spent outputs.
+		let result = match response.get_mut("result") {
+			Some(result) => result.take(),
+			None => return Err(std::io::Error::new(std::io::ErrorKind::InvalidData, "expected JSON result")),
+		};
 
-		JsonResponse(result.take()).try_into()
+		JsonResponse(result).try_into()
 	}
 }
 
@@ -137,6 +141,37 @@ impl BlockSource for RpcClient {
 	}
 }
 
+/// Requires Bitcoin Core to be run with `-blockfilterindex`.
+impl BlockFilterSource for RpcClient {
+	fn get_block_filter<'a>(&'a self, header_hash: &'a BlockHash) -> AsyncBlockSourceResult<'a, BlockFilterData> {
+		Box::pin(async move {
+			let header_hash = serde_json::json!(header_hash.to_hex());
+			let filter_type = serde_json::json!("basic");
+			Ok(self.call_method("getblockfilter", &[header_hash, filter_type]).await?)
+		})
+	}
+}
+
+impl UtxoSource for RpcClient {
+	fn get_block_hash_by_height<'a>(&'a self, block_height: u32) -> AsyncBlockSourceResult<'a, BlockHash> {
+		Box::pin(async move {
+			let height_param = serde_json::json!(block_height);
+			Ok(self.call_method("getblockhash", &[height_param]).await?)
+		})
+	}
+
+	fn is_output_unspent<'a>(&'a self, outpoint: OutPoint) -> AsyncBlockSourceResult<'a, bool> {
+		Box::pin(async move {
+			let txid_param = serde_json::json!(outpoint.txid.to_hex());
+			let vout_param = serde_json::json!(outpoint.vout);
+			let include_mempool = serde_json::json!(false);
+			let utxo_opt: serde_json::Value = self.call_method(
+				"gettxout", &[txid_param, vout_param, include_mempool]).await?;
+			Ok(!utxo_opt.is_null())
+		})
+	}
+}
+
 #[cfg(test)]
 mod tests {
 	use super::*;
@@ -205,7 +240,7 @@ mod tests {
 
 	#[tokio::test]
 	async fn call_method_returning_missing_result() {
-		let response = serde_json::json!({ "result": null });
+		let response = serde_json::json!({});
 		let server = HttpServer::responding_with_ok(MessageBody::Content(response));
 		let client = RpcClient::new(CREDENTIALS, server.endpoint()).unwrap();
 
@@ -218,6 +253,25 @@ mod tests {
 		}
 	}
 
+	#[tokio::test]
+	async fn call_method_returning_null_result() {
+		let response = serde_json::json!({ "result": null });
+		let server = HttpServer::responding_with_ok(MessageBody::Content(response.clone()));
+		let client = RpcClient::new(CREDENTIALS, server.endpoint()).unwrap();
+
+		match client.call_method::<u64>("getblockcount", &[]).await {
+			Err(e) => assert_eq!(e.kind(), std::io::ErrorKind::InvalidData),
+			Ok(_) => panic!("Expected error"),
+		}
+
+		let server = HttpServer::responding_with_ok(MessageBody::Content(response));
+		let client = RpcClient::new(CREDENTIALS, server.endpoint()).unwrap();
+		match client.call_method::<serde_json::Value>("gettxout", &[]).await {
+			Err(e) => panic!("Unexpected error: {:?}", e),
+			Ok(result) => assert!(result.is_null()),
+		}
+	}
+
 	#[tokio::test]
 	async fn call_method_returning_malformed_result() {
 		let response = serde_json::json!({ "result": "foo" });
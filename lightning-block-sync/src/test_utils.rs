@@ -1,18 +1,24 @@
 use crate::{AsyncBlockSourceResult, BlockData, BlockHeaderData, BlockSource, BlockSourceError, UnboundedCache};
+use crate::filter::{BlockFilterData, BlockFilterSource};
+use crate::gossip::UtxoSource;
 use crate::poll::{Validate, ValidatedBlockHeader};
 
 use bitcoin::blockdata::block::{Block, BlockHeader};
 use bitcoin::blockdata::constants::genesis_block;
-use bitcoin::hash_types::BlockHash;
+use bitcoin::blockdata::script::Script;
+use bitcoin::hash_types::{BlockHash, FilterHeader};
+use bitcoin::hashes::Hash;
 use bitcoin::network::constants::Network;
+use bitcoin::util::bip158::BlockFilter;
 use bitcoin::util::uint::Uint256;
 use bitcoin::util::hash::bitcoin_merkle_root;
-use bitcoin::{PackedLockTime, Transaction};
+use bitcoin::{OutPoint, PackedLockTime, Transaction, TxOut};
 
 use lightning::chain;
 
 use std::cell::RefCell;
 use std::collections::VecDeque;
+use std::sync::atomic::{AtomicUsize, Ordering};
 
 #[derive(Default)]
 pub struct Blockchain {
@@ -20,7 +26,11 @@ pub struct Blockchain {
 	without_blocks: Option<std::ops::RangeFrom<usize>>,
 	without_headers: bool,
 	malformed_headers: bool,
+	malformed_filter_headers: bool,
 	filtered_blocks: bool,
+	spent_outputs: Vec<OutPoint>,
+	// The number of blocks and block hashes requested via `BlockSource` or `UtxoSource`.
+	pub block_requests: AtomicUsize,
 }
 
 impl Blockchain {
@@ -66,6 +76,19 @@ impl Blockchain {
 		self
 	}
 
+	pub fn with_script_pubkey_at_height(mut self, height: usize, script_pubkey: Script) -> Self {
+		assert!(height > 0 && height < self.blocks.len());
+		let block = &mut self.blocks[height];
+		block.txdata[0].output.push(TxOut { value: 0, script_pubkey });
+		block.header.merkle_root = block.compute_merkle_root().unwrap();
+		let mut prev_blockhash = block.block_hash();
+		for block in self.blocks.iter_mut().skip(height + 1) {
+			block.header.prev_blockhash = prev_blockhash;
+			prev_blockhash = block.block_hash();
+		}
+		self
+	}
+
 	pub fn without_blocks(self, range: std::ops::RangeFrom<usize>) -> Self {
 		Self { without_blocks: Some(range), ..self }
 	}
@@ -78,10 +101,18 @@ impl Blockchain {
 		Self { malformed_headers: true, ..self }
 	}
 
+	pub fn malformed_filter_headers(self) -> Self {
+		Self { malformed_filter_headers: true, ..self }
+	}
+
 	pub fn filtered_blocks(self) -> Self {
 		Self { filtered_blocks: true, ..self }
 	}
 
+	pub fn with_spent_outputs(self, spent_outputs: Vec<OutPoint>) -> Self {
+		Self { spent_outputs, ..self }
+	}
+
 	pub fn fork_at_height(&self, height: usize) -> Self {
 		assert!(height + 1 < self.blocks.len());
 		let mut blocks = self.blocks.clone();
@@ -91,7 +122,10 @@ impl Blockchain {
 			block.header.nonce += 1;
 			prev_blockhash = block.block_hash();
 		}
-		Self { blocks, without_blocks: None, ..*self }
+		Self {
+			blocks, without_blocks: None, spent_outputs: self.spent_outputs.clone(),
+			block_requests: AtomicUsize::new(0), ..*self
+		}
 	}
 
 	pub fn at_height(&self, height: usize) -> ValidatedBlockHeader {
@@ -110,6 +144,19 @@ impl Blockchain {
 		}
 	}
 
+	fn filter_at_height(&self, height: usize) -> BlockFilter {
+		// Test blocks only contain a coinbase transaction, whose inputs are not part of the filter.
+		BlockFilter::new_script_filter(&self.blocks[height], |_| unreachable!()).unwrap()
+	}
+
+	pub fn filter_header_at_height(&self, height: usize) -> FilterHeader {
+		let mut filter_header = FilterHeader::all_zeros();
+		for i in 0..=height {
+			filter_header = self.filter_at_height(i).filter_header(&filter_header);
+		}
+		filter_header
+	}
+
 	pub fn tip(&self) -> ValidatedBlockHeader {
 		assert!(!self.blocks.is_empty());
 		self.at_height(self.blocks.len() - 1)
@@ -153,6 +200,7 @@ impl BlockSource for Blockchain {
 
 	fn get_block<'a>(&'a self, header_hash: &'a BlockHash) -> AsyncBlockSourceResult<'a, BlockData> {
 		Box::pin(async move {
+			self.block_requests.fetch_add(1, Ordering::Relaxed);
 			for (height, block) in self.blocks.iter().enumerate() {
 				if block.header.block_hash() == *header_hash {
 					if let Some(without_blocks) = &self.without_blocks {
@@ -185,6 +233,43 @@ impl BlockSource for Blockchain {
 	}
 }
 
+impl BlockFilterSource for Blockchain {
+	fn get_block_filter<'a>(&'a self, header_hash: &'a BlockHash) -> AsyncBlockSourceResult<'a, BlockFilterData> {
+		Box::pin(async move {
+			for (height, block) in self.blocks.iter().enumerate() {
+				if block.header.block_hash() == *header_hash {
+					let filter = self.filter_at_height(height);
+					let filter_header = if self.malformed_filter_headers {
+						FilterHeader::all_zeros()
+					} else {
+						self.filter_header_at_height(height)
+					};
+					return Ok(BlockFilterData { filter, filter_header });
+				}
+			}
+			Err(BlockSourceError::transient("filter not found"))
+		})
+	}
+}
+
+impl UtxoSource for Blockchain {
+	fn get_block_hash_by_height<'a>(&'a self, block_height: u32) -> AsyncBlockSourceResult<'a, BlockHash> {
+		Box::pin(async move {
+			self.block_requests.fetch_add(1, Ordering::Relaxed);
+			match self.blocks.get(block_height as usize) {
+				None => Err(BlockSourceError::transient("block not found")),
+				Some(block) => Ok(block.block_hash()),
+			}
+		})
+	}
+
+	fn is_output_unspent<'a>(&'a self, outpoint: OutPoint) -> AsyncBlockSourceResult<'a, bool> {
+		Box::pin(async move {
+			Ok(!self.spent_outputs.contains(&outpoint))
+		})
+	}
+}
+
 pub struct NullChainListener;
 
 impl chain::Listen for NullChainListener {
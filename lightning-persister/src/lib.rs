@@ -16,19 +16,15 @@ extern crate lightning;
 extern crate bitcoin;
 extern crate libc;
 
-use bitcoin::hash_types::{BlockHash, Txid};
-use bitcoin::hashes::hex::FromHex;
-use lightning::chain::channelmonitor::ChannelMonitor;
-use lightning::sign::{EntropySource, SignerProvider};
-use lightning::util::ser::{ReadableArgs, Writeable};
-use lightning::util::persist::KVStorePersister;
+use lightning::util::persist::KVStore;
+use std::collections::HashMap;
 use std::fs;
-use std::io::Cursor;
-use std::ops::Deref;
+use std::io::{Error, ErrorKind};
 use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex, RwLock};
 
-/// FilesystemPersister persists channel data on disk, where each channel's
-/// data is stored in a file named after its funding outpoint.
+/// FilesystemStore is a [`KVStore`] implementation that persists data on disk, where each key is
+/// stored in a file of the same name, located in a directory per namespace and sub-namespace.
 ///
 /// Warning: this module does the best it can with calls to persist data, but it
 /// can only guarantee that the data is passed to the drive. It is up to the
@@ -38,95 +34,118 @@ use std::path::{Path, PathBuf};
 /// persistent.
 /// Corollary: especially when dealing with larger amounts of money, it is best
 /// practice to have multiple channel data backups and not rely only on one
-/// FilesystemPersister.
-pub struct FilesystemPersister {
-	path_to_channel_data: String,
+/// FilesystemStore.
+pub struct FilesystemStore {
+	data_dir: PathBuf,
+	// Per-path locks which ensure that we don't concurrently write to (or remove) the same file.
+	locks: Mutex<HashMap<PathBuf, Arc<RwLock<()>>>>,
 }
 
-impl FilesystemPersister {
-	/// Initialize a new FilesystemPersister and set the path to the individual channels'
-	/// files.
-	pub fn new(path_to_channel_data: String) -> Self {
-		Self {
-			path_to_channel_data,
+impl FilesystemStore {
+	/// Initialize a new FilesystemStore which stores its data below the given directory.
+	pub fn new(data_dir: PathBuf) -> Self {
+		let locks = Mutex::new(HashMap::new());
+		Self { data_dir, locks }
+	}
+
+	/// Get the directory which was provided when this store was initialized.
+	pub fn get_data_dir(&self) -> PathBuf {
+		self.data_dir.clone()
+	}
+
+	fn get_dest_dir_path(&self, namespace: &str, sub_namespace: &str) -> PathBuf {
+		let mut dest_dir_path = self.data_dir.clone();
+		if !namespace.is_empty() {
+			dest_dir_path.push(namespace);
+		}
+		if !sub_namespace.is_empty() {
+			dest_dir_path.push(sub_namespace);
 		}
+		dest_dir_path
 	}
 
-	/// Get the directory which was provided when this persister was initialized.
-	pub fn get_data_dir(&self) -> String {
-		self.path_to_channel_data.clone()
+	fn get_lock(&self, path: &Path) -> Arc<RwLock<()>> {
+		Arc::clone(self.locks.lock().unwrap().entry(path.to_path_buf()).or_default())
 	}
+}
 
-	/// Read `ChannelMonitor`s from disk.
-	pub fn read_channelmonitors<ES: Deref, SP: Deref> (
-		&self, entropy_source: ES, signer_provider: SP
-	) -> std::io::Result<Vec<(BlockHash, ChannelMonitor<<SP::Target as SignerProvider>::Signer>)>>
-		where
-			ES::Target: EntropySource + Sized,
-			SP::Target: SignerProvider + Sized
-	{
-		let mut path = PathBuf::from(&self.path_to_channel_data);
-		path.push("monitors");
-		if !Path::new(&path).exists() {
-			return Ok(Vec::new());
+impl KVStore for FilesystemStore {
+	fn read(&self, namespace: &str, sub_namespace: &str, key: &str) -> std::io::Result<Vec<u8>> {
+		util::check_namespace_key_validity(namespace, sub_namespace, Some(key), "read")?;
+
+		let mut dest_file_path = self.get_dest_dir_path(namespace, sub_namespace);
+		dest_file_path.push(key);
+
+		let inner_lock_ref = self.get_lock(&dest_file_path);
+		let _guard = inner_lock_ref.read().unwrap();
+		fs::read(&dest_file_path)
+	}
+
+	fn write(&self, namespace: &str, sub_namespace: &str, key: &str, buf: &[u8]) -> std::io::Result<()> {
+		util::check_namespace_key_validity(namespace, sub_namespace, Some(key), "write")?;
+
+		let mut dest_file_path = self.get_dest_dir_path(namespace, sub_namespace);
+		dest_file_path.push(key);
+
+		let inner_lock_ref = self.get_lock(&dest_file_path);
+		let _guard = inner_lock_ref.write().unwrap();
+		util::write_to_file(dest_file_path.clone(), buf)
+	}
+
+	fn remove(&self, namespace: &str, sub_namespace: &str, key: &str, lazy: bool) -> std::io::Result<()> {
+		util::check_namespace_key_validity(namespace, sub_namespace, Some(key), "remove")?;
+
+		let mut dest_file_path = self.get_dest_dir_path(namespace, sub_namespace);
+		dest_file_path.push(key);
+
+		let inner_lock_ref = self.get_lock(&dest_file_path);
+		{
+			let _guard = inner_lock_ref.write().unwrap();
+			util::remove_file(&dest_file_path, lazy)?;
 		}
-		let mut res = Vec::new();
-		for file_option in fs::read_dir(path)? {
-			let file = file_option.unwrap();
-			let owned_file_name = file.file_name();
-			let filename = owned_file_name.to_str()
-				.ok_or_else(|| std::io::Error::new(std::io::ErrorKind::InvalidData,
-					"File name is not a valid utf8 string"))?;
-			if !filename.is_ascii() || filename.len() < 65 {
-				return Err(std::io::Error::new(
-					std::io::ErrorKind::InvalidData,
-					"Invalid ChannelMonitor file name",
-				));
+
+		// Drop the lock for this path unless someone else is currently holding on to it.
+		let mut outer_lock = self.locks.lock().unwrap();
+		if Arc::strong_count(&inner_lock_ref) == 2 {
+			outer_lock.remove(&dest_file_path);
+		}
+		Ok(())
+	}
+
+	fn list(&self, namespace: &str, sub_namespace: &str) -> std::io::Result<Vec<String>> {
+		util::check_namespace_key_validity(namespace, sub_namespace, None, "list")?;
+
+		let prefixed_dest = self.get_dest_dir_path(namespace, sub_namespace);
+		let mut keys = Vec::new();
+
+		if !prefixed_dest.exists() {
+			return Ok(keys);
+		}
+
+		for entry in fs::read_dir(&prefixed_dest)? {
+			let entry = entry?;
+			// Skip any (sub-)namespaces stored below the given one.
+			if entry.file_type()?.is_dir() {
+				continue;
 			}
-			if filename.ends_with(".tmp") {
+
+			let file_name = entry.file_name();
+			let key = file_name.to_str().ok_or_else(||
+				Error::new(ErrorKind::InvalidData, "File name is not a valid utf8 string"))?;
+			if key.ends_with(".tmp") {
 				// If we were in the middle of committing an new update and crashed, it should be
 				// safe to ignore the update - we should never have returned to the caller and
 				// irrevocably committed to the new state in any way.
 				continue;
 			}
-
-			let txid: Txid = Txid::from_hex(filename.split_at(64).0)
-				.map_err(|_| std::io::Error::new(
-					std::io::ErrorKind::InvalidData,
-					"Invalid tx ID in filename",
-				))?;
-
-			let index: u16 = filename.split_at(65).1.parse()
-				.map_err(|_| std::io::Error::new(
-					std::io::ErrorKind::InvalidData,
-					"Invalid tx index in filename",
-				))?;
-
-			let contents = fs::read(&file.path())?;
-			let mut buffer = Cursor::new(&contents);
-			match <(BlockHash, ChannelMonitor<<SP::Target as SignerProvider>::Signer>)>::read(&mut buffer, (&*entropy_source, &*signer_provider)) {
-				Ok((blockhash, channel_monitor)) => {
-					if channel_monitor.get_funding_txo().0.txid != txid || channel_monitor.get_funding_txo().0.index != index {
-						return Err(std::io::Error::new(std::io::ErrorKind::InvalidData,
-									       "ChannelMonitor was stored in the wrong file"));
-					}
-					res.push((blockhash, channel_monitor));
-				}
-				Err(e) => return Err(std::io::Error::new(
-					std::io::ErrorKind::InvalidData,
-					format!("Failed to deserialize ChannelMonitor: {}", e),
-				))
+			if !util::is_valid_kvstore_str(key) || key.is_empty() {
+				return Err(Error::new(ErrorKind::InvalidData,
+					format!("Found file with invalid key name: {}", key)));
 			}
+			keys.push(key.to_string());
 		}
-		Ok(res)
-	}
-}
 
-impl KVStorePersister for FilesystemPersister {
-	fn persist<W: Writeable>(&self, key: &str, object: &W) -> std::io::Result<()> {
-		let mut dest_file = PathBuf::from(self.path_to_channel_data.clone());
-		dest_file.push(key);
-		util::write_to_file(dest_file, object)
+		Ok(keys)
 	}
 }
 
@@ -134,7 +153,7 @@ impl KVStorePersister for FilesystemPersister {
 mod tests {
 	extern crate lightning;
 	extern crate bitcoin;
-	use crate::FilesystemPersister;
+	use crate::FilesystemStore;
 	use bitcoin::hashes::hex::FromHex;
 	use bitcoin::Txid;
 	use lightning::chain::ChannelMonitorUpdateStatus;
@@ -144,6 +163,7 @@ mod tests {
 	use lightning::{check_closed_broadcast, check_closed_event, check_added_monitors};
 	use lightning::events::{ClosureReason, MessageSendEventsProvider};
 	use lightning::ln::functional_test_utils::*;
+	use lightning::util::persist::{KVStore, read_channel_monitors};
 	use lightning::util::test_utils;
 	use std::fs;
 	#[cfg(target_os = "windows")]
@@ -152,11 +172,11 @@ mod tests {
 		lightning::ln::msgs::ChannelMessageHandler,
 	};
 
-	impl Drop for FilesystemPersister {
+	impl Drop for FilesystemStore {
 		fn drop(&mut self) {
 			// We test for invalid directory names, so it's OK if directory removal
 			// fails.
-			match fs::remove_dir_all(&self.path_to_channel_data) {
+			match fs::remove_dir_all(&self.data_dir) {
 				Err(e) => println!("Failed to remove test persister directory: {}", e),
 				_ => {}
 			}
@@ -165,10 +185,10 @@ mod tests {
 
 	#[test]
 	fn test_if_monitors_is_not_dir() {
-		let persister = FilesystemPersister::new("test_monitors_is_not_dir".to_string());
+		let persister = FilesystemStore::new("test_monitors_is_not_dir".into());
 
-		fs::create_dir_all(&persister.path_to_channel_data).unwrap();
-		let mut path = std::path::PathBuf::from(&persister.path_to_channel_data);
+		fs::create_dir_all(&persister.data_dir).unwrap();
+		let mut path = persister.data_dir.clone();
 		path.push("monitors");
 		fs::File::create(path).unwrap();
 
@@ -179,19 +199,56 @@ mod tests {
 		let node_chanmgrs = create_node_chanmgrs(1, &node_cfgs, &[None]);
 		let nodes = create_network(1, &node_cfgs, &node_chanmgrs);
 
-		// Check that read_channelmonitors() returns error if monitors/ is not a
+		// Check that read_channel_monitors() returns error if monitors/ is not a
 		// directory.
-		assert!(persister.read_channelmonitors(nodes[0].keys_manager, nodes[0].keys_manager).is_err());
+		assert!(read_channel_monitors(&persister, nodes[0].keys_manager, nodes[0].keys_manager).is_err());
+	}
+
+	#[test]
+	fn read_write_remove_list() {
+		let store = FilesystemStore::new("test_read_write_remove_list".into());
+		let data = [42u8; 32];
+
+		// A missing key results in NotFound and an unknown namespace is empty.
+		assert_eq!(store.read("testspace", "testsubspace", "testkey").unwrap_err().kind(), std::io::ErrorKind::NotFound);
+		assert!(store.list("testspace", "testsubspace").unwrap().is_empty());
+
+		store.write("testspace", "testsubspace", "testkey", &data).unwrap();
+		store.write("testspace", "", "otherkey", &data).unwrap();
+		assert_eq!(store.read("testspace", "testsubspace", "testkey").unwrap(), data);
+
+		// Listing returns only the keys directly within the given (sub-)namespace.
+		assert_eq!(store.list("testspace", "testsubspace").unwrap(), vec!["testkey".to_string()]);
+		assert_eq!(store.list("testspace", "").unwrap(), vec!["otherkey".to_string()]);
+
+		// Leftover temporary files are ignored.
+		let mut tmp_file = store.get_data_dir();
+		tmp_file.push("testspace");
+		tmp_file.push("testsubspace");
+		tmp_file.push("newkey.tmp");
+		fs::write(&tmp_file, data).unwrap();
+		assert_eq!(store.list("testspace", "testsubspace").unwrap(), vec!["testkey".to_string()]);
+
+		store.remove("testspace", "testsubspace", "testkey", false).unwrap();
+		assert!(store.list("testspace", "testsubspace").unwrap().is_empty());
+		assert_eq!(store.read("testspace", "testsubspace", "testkey").unwrap_err().kind(), std::io::ErrorKind::NotFound);
+		// Removing a key which doesn't exist succeeds.
+		store.remove("testspace", "testsubspace", "testkey", true).unwrap();
+
+		// Invalid namespaces and keys are rejected.
+		assert_eq!(store.write("", "testsubspace", "testkey", &data).unwrap_err().kind(), std::io::ErrorKind::InvalidInput);
+		assert_eq!(store.write("testspace", "", "../testkey", &data).unwrap_err().kind(), std::io::ErrorKind::InvalidInput);
+		assert_eq!(store.list("test/space", "").unwrap_err().kind(), std::io::ErrorKind::InvalidInput);
 	}
 
-	// Integration-test the FilesystemPersister. Test relaying a few payments
+	// Integration-test the FilesystemStore. Test relaying a few payments
 	// and check that the persisted data is updated the appropriate number of
 	// times.
 	#[test]
-	fn test_filesystem_persister() {
-		// Create the nodes, giving them FilesystemPersisters for data persisters.
-		let persister_0 = FilesystemPersister::new("test_filesystem_persister_0".to_string());
-		let persister_1 = FilesystemPersister::new("test_filesystem_persister_1".to_string());
+	fn test_filesystem_store() {
+		// Create the nodes, giving them FilesystemStores for data persisters.
+		let persister_0 = FilesystemStore::new("test_filesystem_store_0".into());
+		let persister_1 = FilesystemStore::new("test_filesystem_store_1".into());
 		let chanmon_cfgs = create_chanmon_cfgs(2);
 		let mut node_cfgs = create_node_cfgs(2, &chanmon_cfgs);
 		let chain_mon_0 = test_utils::TestChainMonitor::new(Some(&chanmon_cfgs[0].chain_source), &chanmon_cfgs[0].tx_broadcaster, &chanmon_cfgs[0].logger, &chanmon_cfgs[0].fee_estimator, &persister_0, node_cfgs[0].keys_manager);
@@ -203,20 +260,20 @@ mod tests {
 
 		// Check that the persisted channel data is empty before any channels are
 		// open.
-		let mut persisted_chan_data_0 = persister_0.read_channelmonitors(nodes[0].keys_manager, nodes[0].keys_manager).unwrap();
+		let mut persisted_chan_data_0 = read_channel_monitors(&persister_0, nodes[0].keys_manager, nodes[0].keys_manager).unwrap();
 		assert_eq!(persisted_chan_data_0.len(), 0);
-		let mut persisted_chan_data_1 = persister_1.read_channelmonitors(nodes[1].keys_manager, nodes[1].keys_manager).unwrap();
+		let mut persisted_chan_data_1 = read_channel_monitors(&persister_1, nodes[1].keys_manager, nodes[1].keys_manager).unwrap();
 		assert_eq!(persisted_chan_data_1.len(), 0);
 
 		// Helper to make sure the channel is on the expected update ID.
 		macro_rules! check_persisted_data {
 			($expected_update_id: expr) => {
-				persisted_chan_data_0 = persister_0.read_channelmonitors(nodes[0].keys_manager, nodes[0].keys_manager).unwrap();
+				persisted_chan_data_0 = read_channel_monitors(&persister_0, nodes[0].keys_manager, nodes[0].keys_manager).unwrap();
 				assert_eq!(persisted_chan_data_0.len(), 1);
 				for (_, mon) in persisted_chan_data_0.iter() {
 					assert_eq!(mon.get_latest_update_id(), $expected_update_id);
 				}
-				persisted_chan_data_1 = persister_1.read_channelmonitors(nodes[1].keys_manager, nodes[1].keys_manager).unwrap();
+				persisted_chan_data_1 = read_channel_monitors(&persister_1, nodes[1].keys_manager, nodes[1].keys_manager).unwrap();
 				assert_eq!(persisted_chan_data_1.len(), 1);
 				for (_, mon) in persisted_chan_data_1.iter() {
 					assert_eq!(mon.get_latest_update_id(), $expected_update_id);
@@ -259,8 +316,8 @@ mod tests {
 	#[cfg(not(target_os = "windows"))]
 	#[test]
 	fn test_readonly_dir_perm_failure() {
-		let persister = FilesystemPersister::new("test_readonly_dir_perm_failure".to_string());
-		fs::create_dir_all(&persister.path_to_channel_data).unwrap();
+		let persister = FilesystemStore::new("test_readonly_dir_perm_failure".into());
+		fs::create_dir_all(&persister.data_dir).unwrap();
 
 		// Set up a dummy channel and force close. This will produce a monitor
 		// that we can then use to test persistence.
@@ -278,7 +335,7 @@ mod tests {
 		// Set the persister's directory to read-only, which should result in
 		// returning a permanent failure when we then attempt to persist a
 		// channel update.
-		let path = &persister.path_to_channel_data;
+		let path = &persister.data_dir;
 		let mut perms = fs::metadata(path).unwrap().permissions();
 		perms.set_readonly(true);
 		fs::set_permissions(path, perms).unwrap();
@@ -318,7 +375,7 @@ mod tests {
 		// channel fails to open because the directories fail to be created. There
 		// don't seem to be invalid filename characters on Unix that Rust doesn't
 		// handle, hence why the test is Windows-only.
-		let persister = FilesystemPersister::new(":<>/".to_string());
+		let persister = FilesystemStore::new(":<>/".into());
 
 		let test_txo = OutPoint {
 			txid: Txid::from_hex("8984484a580b825b9972d7adb15050b3ab624ccd731946b3eeddb92f4e7ef6be").unwrap(),
@@ -341,8 +398,8 @@ pub mod bench {
 
 	/// Bench!
 	pub fn bench_sends(bench: &mut Criterion) {
-		let persister_a = super::FilesystemPersister::new("bench_filesystem_persister_a".to_string());
-		let persister_b = super::FilesystemPersister::new("bench_filesystem_persister_b".to_string());
+		let persister_a = super::FilesystemStore::new("bench_filesystem_persister_a".into());
+		let persister_b = super::FilesystemStore::new("bench_filesystem_persister_b".into());
 		lightning::ln::channelmanager::bench::bench_two_sends(
 			bench, "bench_filesystem_persisted_sends", persister_a, persister_b);
 	}
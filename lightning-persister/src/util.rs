@@ -2,13 +2,13 @@
 extern crate winapi;
 
 use std::fs;
-use std::path::PathBuf;
-use std::io::BufWriter;
+use std::path::{Path, PathBuf};
+use std::io::{BufWriter, Write};
 
 #[cfg(not(target_os = "windows"))]
 use std::os::unix::io::AsRawFd;
 
-use lightning::util::ser::Writeable;
+use lightning::util::persist::{KVSTORE_NAMESPACE_KEY_ALPHABET, KVSTORE_NAMESPACE_KEY_MAX_LEN};
 
 #[cfg(target_os = "windows")]
 use {
@@ -32,8 +32,40 @@ fn path_to_windows_str<T: AsRef<OsStr>>(path: T) -> Vec<winapi::shared::ntdef::W
 	path.as_ref().encode_wide().chain(Some(0)).collect()
 }
 
-#[allow(bare_trait_objects)]
-pub(crate) fn write_to_file<W: Writeable>(dest_file: PathBuf, data: &W) -> std::io::Result<()> {
+pub(crate) fn is_valid_kvstore_str(key: &str) -> bool {
+	key.len() <= KVSTORE_NAMESPACE_KEY_MAX_LEN && key.chars().all(|c| KVSTORE_NAMESPACE_KEY_ALPHABET.contains(c))
+}
+
+/// Checks that the given namespace, sub-namespace and (optional) key follow the rules laid out by
+/// [`KVStore`], returning an [`std::io::ErrorKind::InvalidInput`] error otherwise.
+///
+/// [`KVStore`]: lightning::util::persist::KVStore
+pub(crate) fn check_namespace_key_validity(namespace: &str, sub_namespace: &str, key: Option<&str>, operation: &str) -> std::io::Result<()> {
+	if let Some(key) = key {
+		if key.is_empty() {
+			let msg = format!("Failed to {} {}/{}/{}: key may not be empty.", operation, namespace, sub_namespace, key);
+			return Err(std::io::Error::new(std::io::ErrorKind::InvalidInput, msg));
+		}
+		if !is_valid_kvstore_str(key) {
+			let msg = format!("Failed to {} {}/{}/{}: key contains invalid characters or is too long.", operation, namespace, sub_namespace, key);
+			return Err(std::io::Error::new(std::io::ErrorKind::InvalidInput, msg));
+		}
+	}
+
+	if namespace.is_empty() && !sub_namespace.is_empty() {
+		let msg = format!("Failed to {} {}/{}: namespace may not be empty if a non-empty sub-namespace is given.", operation, namespace, sub_namespace);
+		return Err(std::io::Error::new(std::io::ErrorKind::InvalidInput, msg));
+	}
+
+	if !is_valid_kvstore_str(namespace) || !is_valid_kvstore_str(sub_namespace) {
+		let msg = format!("Failed to {} {}/{}: namespace contains invalid characters or is too long.", operation, namespace, sub_namespace);
+		return Err(std::io::Error::new(std::io::ErrorKind::InvalidInput, msg));
+	}
+
+	Ok(())
+}
+
+pub(crate) fn write_to_file(dest_file: PathBuf, data: &[u8]) -> std::io::Result<()> {
 	let mut tmp_file = dest_file.clone();
 	tmp_file.set_extension("tmp");
 
@@ -48,7 +80,7 @@ pub(crate) fn write_to_file<W: Writeable>(dest_file: PathBuf, data: &W) -> std::
 		// Note that going by rust-lang/rust@d602a6b, on MacOS it is only safe to use
 		// rust stdlib 1.36 or higher.
 		let mut buf = BufWriter::new(fs::File::create(&tmp_file)?);
-		data.write(&mut buf)?;
+		buf.write_all(data)?;
 		buf.into_inner()?.sync_all()?;
 	}
 	// Fsync the parent directory on Unix.
@@ -77,29 +109,40 @@ pub(crate) fn write_to_file<W: Writeable>(dest_file: PathBuf, data: &W) -> std::
 	Ok(())
 }
 
+pub(crate) fn remove_file(dest_file: &Path, lazy: bool) -> std::io::Result<()> {
+	if !dest_file.is_file() {
+		return Ok(());
+	}
+
+	fs::remove_file(dest_file)?;
+	// Unless the removal may happen lazily, fsync the parent directory on Unix to make sure the
+	// removal hits the disk before we return.
+	#[cfg(not(target_os = "windows"))]
+	{
+		if !lazy {
+			let parent_directory = dest_file.parent().unwrap();
+			let dir_file = fs::OpenOptions::new().read(true).open(parent_directory)?;
+			unsafe { libc::fsync(dir_file.as_raw_fd()); }
+		}
+	}
+	#[cfg(target_os = "windows")]
+	let _ = lazy;
+	Ok(())
+}
+
 #[cfg(test)]
 mod tests {
-	use lightning::util::ser::{Writer, Writeable};
-
-	use super::{write_to_file};
+	use super::{check_namespace_key_validity, write_to_file};
 	use std::fs;
 	use std::io;
 	use std::path::PathBuf;
 
-	struct TestWriteable{}
-	impl Writeable for TestWriteable {
-		fn write<W: Writer>(&self, writer: &mut W) -> Result<(), std::io::Error> {
-			writer.write_all(&[42; 1])
-		}
-	}
-
 	// Test that if the persister's path to channel data is read-only, writing
 	// data to it fails. Windows ignores the read-only flag for folders, so this
 	// test is Unix-only.
 	#[cfg(not(target_os = "windows"))]
 	#[test]
 	fn test_readonly_dir() {
-		let test_writeable = TestWriteable{};
 		let filename = "test_readonly_dir_persister_filename".to_string();
 		let path = "test_readonly_dir_persister_dir";
 		fs::create_dir_all(path).unwrap();
@@ -108,7 +151,7 @@ mod tests {
 		fs::set_permissions(path, perms).unwrap();
 		let mut dest_file = PathBuf::from(path);
 		dest_file.push(filename);
-		match write_to_file(dest_file, &test_writeable) {
+		match write_to_file(dest_file, &[42; 1]) {
 			Err(e) => assert_eq!(e.kind(), io::ErrorKind::PermissionDenied),
 			_ => panic!("Unexpected error message")
 		}
@@ -124,50 +167,24 @@ mod tests {
 	#[cfg(not(target_os = "windows"))]
 	#[test]
 	fn test_rename_failure() {
-		let test_writeable = TestWriteable{};
 		let filename = "test_rename_failure_filename";
 		let path = "test_rename_failure_dir";
 		let mut dest_file = PathBuf::from(path);
 		dest_file.push(filename);
 		// Create the channel data file and make it a directory.
 		fs::create_dir_all(dest_file.clone()).unwrap();
-		match write_to_file(dest_file, &test_writeable) {
+		match write_to_file(dest_file, &[42; 1]) {
 			Err(e) => assert_eq!(e.raw_os_error(), Some(libc::EISDIR)),
 			_ => panic!("Unexpected Ok(())")
 		}
 		fs::remove_dir_all(path).unwrap();
 	}
 
-	#[test]
-	fn test_diskwriteable_failure() {
-		struct FailingWriteable {}
-		impl Writeable for FailingWriteable {
-			fn write<W: Writer>(&self, _writer: &mut W) -> Result<(), std::io::Error> {
-				Err(std::io::Error::new(std::io::ErrorKind::Other, "expected failure"))
-			}
-		}
-
-		let filename = "test_diskwriteable_failure";
-		let path = "test_diskwriteable_failure_dir";
-		let test_writeable = FailingWriteable{};
-		let mut dest_file = PathBuf::from(path);
-		dest_file.push(filename);
-		match write_to_file(dest_file, &test_writeable) {
-			Err(e) => {
-				assert_eq!(e.kind(), std::io::ErrorKind::Other);
-				assert_eq!(e.get_ref().unwrap().to_string(), "expected failure");
-			},
-			_ => panic!("unexpected result")
-		}
-		fs::remove_dir_all(path).unwrap();
-	}
-
 	// Test failure to create the temporary file in the persistence process.
 	// We induce this failure by having the temp file already exist and be a
 	// directory.
 	#[test]
 	fn test_tmp_file_creation_failure() {
-		let test_writeable = TestWriteable{};
 		let filename = "test_tmp_file_creation_failure_filename".to_string();
 		let path = "test_tmp_file_creation_failure_dir";
 		let mut dest_file = PathBuf::from(path);
@@ -175,7 +192,7 @@ mod tests {
 		let mut tmp_file = dest_file.clone();
 		tmp_file.set_extension("tmp");
 		fs::create_dir_all(tmp_file).unwrap();
-		match write_to_file(dest_file, &test_writeable) {
+		match write_to_file(dest_file, &[42; 1]) {
 			Err(e) => {
 				#[cfg(not(target_os = "windows"))]
 				assert_eq!(e.raw_os_error(), Some(libc::EISDIR));
@@ -185,4 +202,21 @@ mod tests {
 			_ => panic!("Unexpected error message")
 		}
 	}
+
+	#[test]
+	fn test_namespace_key_validity() {
+		assert!(check_namespace_key_validity("", "", Some("manager"), "read").is_ok());
+		assert!(check_namespace_key_validity("monitors", "", Some("abc_0"), "read").is_ok());
+		assert!(check_namespace_key_validity("namespace", "sub-namespace", None, "list").is_ok());
+
+		// Keys may neither be empty nor contain characters outside the allowed alphabet.
+		assert!(check_namespace_key_validity("", "", Some(""), "write").is_err());
+		assert!(check_namespace_key_validity("", "", Some("../escape"), "write").is_err());
+		assert!(check_namespace_key_validity("", "", Some("key.tmp"), "write").is_err());
+		assert!(check_namespace_key_validity("", "", Some(&"a".repeat(121)), "write").is_err());
+
+		// Sub-namespaces require a namespace.
+		assert!(check_namespace_key_validity("", "sub", Some("key"), "write").is_err());
+		assert!(check_namespace_key_validity("name/space", "", None, "list").is_err());
+	}
 }
@@ -0,0 +1,283 @@
+use crate::error::TxSyncError;
+use crate::esplora::EsploraClientType;
+
+use lightning::chain::chaininterface::{BroadcasterInterface, ConfirmationTarget, FeeEstimator, FEERATE_FLOOR_SATS_PER_KW};
+use lightning::util::logger::Logger;
+use lightning::{log_debug, log_error};
+
+use bitcoin::Transaction;
+
+use esplora_client::Builder;
+
+use std::collections::HashMap;
+use std::sync::RwLock;
+#[cfg(not(feature = "async-interface"))]
+use std::sync::Arc;
+#[cfg(feature = "async-interface")]
+use std::sync::Mutex;
+use core::ops::Deref;
+use core::time::Duration;
+
+/// The confirmation targets for which [`EsploraFeeEstimator`] keeps feerate estimates, along with
+/// the number of blocks the respective estimate is looked up for.
+const CONFIRMATION_TARGETS: [(ConfirmationTarget, u16); 4] = [
+	(ConfirmationTarget::MempoolMinimum, 1008),
+	(ConfirmationTarget::Background, 144),
+	(ConfirmationTarget::Normal, 18),
+	(ConfirmationTarget::HighPriority, 6),
+];
+
+/// Returns the feerate in satoshis per 1000 weight units to use for the given target if no
+/// estimate could be retrieved yet.
+fn fallback_sat_per_1000_weight(confirmation_target: ConfirmationTarget) -> u32 {
+	match confirmation_target {
+		ConfirmationTarget::MempoolMinimum => FEERATE_FLOOR_SATS_PER_KW,
+		ConfirmationTarget::Background => FEERATE_FLOOR_SATS_PER_KW,
+		ConfirmationTarget::Normal => 2000,
+		ConfirmationTarget::HighPriority => 5000,
+	}
+}
+
+/// Picks the estimate for the largest number of blocks not exceeding `num_blocks` from the given
+/// Esplora fee estimates, which map numbers of blocks to feerates in satoshis per virtual byte, and
+/// converts it to satoshis per 1000 weight units.
+fn sat_per_1000_weight_for_blocks(estimates: &HashMap<String, f64>, num_blocks: u16) -> Option<u32> {
+	let sat_per_vbyte = estimates.iter()
+		.filter_map(|(blocks, sat_per_vbyte)| blocks.parse::<u16>().ok().map(|blocks| (blocks, *sat_per_vbyte)))
+		.filter(|(blocks, _)| *blocks <= num_blocks)
+		.max_by_key(|(blocks, _)| *blocks)
+		.map(|(_, sat_per_vbyte)| sat_per_vbyte)?;
+
+	let sat_per_1000_weight = (sat_per_vbyte * 250.0).round();
+	if sat_per_1000_weight >= u32::max_value() as f64 {
+		Some(u32::max_value())
+	} else {
+		Some(core::cmp::max(sat_per_1000_weight as u32, FEERATE_FLOOR_SATS_PER_KW))
+	}
+}
+
+/// A [`FeeEstimator`] which caches feerate estimates retrieved from an [`Esplora`] server.
+///
+/// As [`FeeEstimator::get_est_sat_per_1000_weight`] must not block, estimates are only retrieved
+/// upon calls to [`update_fee_estimates`], which should be made once upon startup and then
+/// periodically in the background, e.g., every few minutes. With the `esplora-blocking` feature,
+/// [`start_background_updates`] spawns a thread doing so, while with the `esplora-async` feature,
+/// [`update_fee_estimates_periodically`] returns a future doing so which is to be spawned on the
+/// runtime of choice. Until an estimate could be retrieved for a given [`ConfirmationTarget`], a
+/// conservative fallback feerate is returned.
+///
+/// Note that this trusts the server to provide sane estimates. See [`FeeEstimator`] for the
+/// implications of relying on third parties for feerate estimation.
+///
+/// This uses and exposes either a blocking or async client variant dependent on whether the
+/// `esplora-blocking` or the `esplora-async` feature is enabled.
+///
+/// [`Esplora`]: https://github.com/Blockstream/electrs
+/// [`update_fee_estimates`]: Self::update_fee_estimates
+/// [`start_background_updates`]: Self::start_background_updates
+/// [`update_fee_estimates_periodically`]: Self::update_fee_estimates_periodically
+pub struct EsploraFeeEstimator {
+	client: EsploraClientType,
+	fee_rate_cache: RwLock<HashMap<ConfirmationTarget, u32>>,
+}
+
+impl EsploraFeeEstimator {
+	/// Returns a new [`EsploraFeeEstimator`] object, or an error if no client for the given server
+	/// URL could be built.
+	pub fn new(server_url: String) -> Result<Self, TxSyncError> {
+		let builder = Builder::new(&server_url);
+		#[cfg(not(feature = "async-interface"))]
+		let client = builder.build_blocking()?;
+		#[cfg(feature = "async-interface")]
+		let client = builder.build_async()?;
+
+		Ok(EsploraFeeEstimator::from_client(client))
+	}
+
+	/// Returns a new [`EsploraFeeEstimator`] object using the given Esplora client.
+	pub fn from_client(client: EsploraClientType) -> Self {
+		Self { client, fee_rate_cache: RwLock::new(HashMap::new()) }
+	}
+
+	/// Retrieves new feerate estimates for all [`ConfirmationTarget`]s.
+	///
+	/// Targets for which the server doesn't provide an estimate keep their previous estimate or
+	/// fallback. Returns an error if the server could not be queried, in which case all previous
+	/// estimates are kept.
+	#[maybe_async]
+	pub fn update_fee_estimates(&self) -> Result<(), TxSyncError> {
+		let estimates = maybe_await!(self.client.get_fee_estimates())?;
+
+		let mut fee_rate_cache = self.fee_rate_cache.write().unwrap();
+		for (confirmation_target, num_blocks) in CONFIRMATION_TARGETS.iter() {
+			if let Some(fee_rate) = sat_per_1000_weight_for_blocks(&estimates, *num_blocks) {
+				fee_rate_cache.insert(*confirmation_target, fee_rate);
+			}
+		}
+		Ok(())
+	}
+
+	/// Spawns a thread calling [`update_fee_estimates`] right away and then every `interval`.
+	///
+	/// Failed updates are retried upon the next interval, with the previous estimates being kept
+	/// in the meantime. The thread only holds a weak reference to the estimator and exits once it
+	/// has been dropped.
+	///
+	/// [`update_fee_estimates`]: Self::update_fee_estimates
+	#[cfg(not(feature = "async-interface"))]
+	pub fn start_background_updates(estimator: &Arc<Self>, interval: Duration) -> std::thread::JoinHandle<()> {
+		let estimator = Arc::downgrade(estimator);
+		std::thread::spawn(move || {
+			loop {
+				match estimator.upgrade() {
+					Some(estimator) => { let _ = estimator.update_fee_estimates(); },
+					None => return,
+				}
+				std::thread::sleep(interval);
+			}
+		})
+	}
+
+	/// Calls [`update_fee_estimates`] right away and then every `interval`, waiting for the futures
+	/// returned by `sleeper` in between, until one of them resolves to `true`.
+	///
+	/// Failed updates are retried upon the next interval, with the previous estimates being kept
+	/// in the meantime. The returned future is meant to be spawned as a background task.
+	///
+	/// [`update_fee_estimates`]: Self::update_fee_estimates
+	#[cfg(feature = "async-interface")]
+	pub async fn update_fee_estimates_periodically<
+		SleepFuture: core::future::Future<Output = bool>, Sleeper: Fn(Duration) -> SleepFuture
+	>(&self, interval: Duration, sleeper: Sleeper) {
+		loop {
+			let _ = self.update_fee_estimates().await;
+			if sleeper(interval).await { return; }
+		}
+	}
+
+	/// Returns a reference to the underlying esplora client.
+	pub fn client(&self) -> &EsploraClientType {
+		&self.client
+	}
+}
+
+impl FeeEstimator for EsploraFeeEstimator {
+	fn get_est_sat_per_1000_weight(&self, confirmation_target: ConfirmationTarget) -> u32 {
+		self.fee_rate_cache.read().unwrap().get(&confirmation_target).copied()
+			.unwrap_or_else(|| fallback_sat_per_1000_weight(confirmation_target))
+	}
+}
+
+/// A [`BroadcasterInterface`] which hands transactions to an [`Esplora`] server.
+///
+/// As Esplora doesn't support package relay, transactions given together are submitted one by one
+/// in the given order, i.e., parents before their children. Failures are logged but otherwise
+/// ignored, as LDK will rebroadcast transactions as needed.
+///
+/// With the `esplora-blocking` feature, transactions are broadcast right away. With the
+/// `esplora-async` feature, as [`BroadcasterInterface::broadcast_transactions`] must not block and
+/// no runtime to spawn futures on is known, they are queued instead and only broadcast upon calls
+/// to [`process_broadcast_queue`].
+///
+/// **Note:** With the `esplora-async` feature, transactions are never broadcast unless
+/// [`process_broadcast_queue`] is called, which hence is required to happen regularly, e.g., by
+/// spawning the future returned by [`process_broadcast_queue_periodically`] as a background task.
+///
+/// [`Esplora`]: https://github.com/Blockstream/electrs
+/// [`process_broadcast_queue`]: Self::process_broadcast_queue
+/// [`process_broadcast_queue_periodically`]: Self::process_broadcast_queue_periodically
+pub struct EsploraBroadcaster<L: Deref>
+where
+	L::Target: Logger,
+{
+	client: EsploraClientType,
+	#[cfg(feature = "async-interface")]
+	queue: Mutex<Vec<Vec<Transaction>>>,
+	logger: L,
+}
+
+impl<L: Deref> EsploraBroadcaster<L>
+where
+	L::Target: Logger,
+{
+	/// Returns a new [`EsploraBroadcaster`] object, or an error if no client for the given server
+	/// URL could be built.
+	///
+	/// With the `esplora-async` feature, [`Self::process_broadcast_queue`] must be called regularly
+	/// for any transactions to be broadcast.
+	pub fn new(server_url: String, logger: L) -> Result<Self, TxSyncError> {
+		let builder = Builder::new(&server_url);
+		#[cfg(not(feature = "async-interface"))]
+		let client = builder.build_blocking()?;
+		#[cfg(feature = "async-interface")]
+		let client = builder.build_async()?;
+
+		Ok(EsploraBroadcaster::from_client(client, logger))
+	}
+
+	/// Returns a new [`EsploraBroadcaster`] object using the given Esplora client.
+	///
+	/// With the `esplora-async` feature, [`Self::process_broadcast_queue`] must be called regularly
+	/// for any transactions to be broadcast.
+	pub fn from_client(client: EsploraClientType, logger: L) -> Self {
+		Self {
+			client,
+			#[cfg(feature = "async-interface")]
+			queue: Mutex::new(Vec::new()),
+			logger,
+		}
+	}
+
+	/// Broadcasts all transactions queued since the last call.
+	#[cfg(feature = "async-interface")]
+	pub async fn process_broadcast_queue(&self) {
+		let packages = core::mem::take(&mut *self.queue.lock().unwrap());
+		for package in packages {
+			self.broadcast_package(&package).await;
+		}
+	}
+
+	/// Calls [`process_broadcast_queue`] every `interval`, waiting for the futures returned by
+	/// `sleeper` in between, until one of them resolves to `true`.
+	///
+	/// The returned future is meant to be spawned as a background task.
+	///
+	/// [`process_broadcast_queue`]: Self::process_broadcast_queue
+	#[cfg(feature = "async-interface")]
+	pub async fn process_broadcast_queue_periodically<
+		SleepFuture: core::future::Future<Output = bool>, Sleeper: Fn(Duration) -> SleepFuture
+	>(&self, interval: Duration, sleeper: Sleeper) {
+		loop {
+			self.process_broadcast_queue().await;
+			if sleeper(interval).await { return; }
+		}
+	}
+
+	#[maybe_async]
+	fn broadcast_package(&self, package: &[Transaction]) {
+		for tx in package {
+			match maybe_await!(self.client.broadcast(tx)) {
+				Ok(()) => log_debug!(self.logger, "Broadcast transaction {}", tx.txid()),
+				Err(e) => log_error!(self.logger, "Failed to broadcast transaction {}: {}", tx.txid(), e),
+			}
+		}
+	}
+
+	/// Returns a reference to the underlying esplora client.
+	pub fn client(&self) -> &EsploraClientType {
+		&self.client
+	}
+}
+
+impl<L: Deref> BroadcasterInterface for EsploraBroadcaster<L>
+where
+	L::Target: Logger,
+{
+	fn broadcast_transactions(&self, txs: &[&Transaction]) {
+		let package: Vec<Transaction> = txs.iter().map(|tx| (*tx).clone()).collect();
+		#[cfg(not(feature = "async-interface"))]
+		self.broadcast_package(&package);
+		#[cfg(feature = "async-interface")]
+		self.queue.lock().unwrap().push(package);
+	}
+}
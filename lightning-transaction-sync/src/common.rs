@@ -1,4 +1,4 @@
-use lightning::chain::WatchedOutput;
+use lightning::chain::{Confirm, WatchedOutput};
 use bitcoin::{Txid, BlockHash, Transaction, BlockHeader, OutPoint};
 
 use std::collections::{HashSet, HashMap};
@@ -27,6 +27,42 @@ impl SyncState {
 			pending_sync: false,
 		}
 	}
+
+	// Informs the given `confirmables` of the given unconfirmed transactions, which we'll watch
+	// for reconfirmation from now on.
+	pub fn sync_unconfirmed_transactions(
+		&mut self, confirmables: &Vec<&(dyn Confirm + Sync + Send)>, unconfirmed_txs: Vec<Txid>,
+	) {
+		for txid in unconfirmed_txs {
+			for c in confirmables {
+				c.transaction_unconfirmed(&txid);
+			}
+
+			self.watched_transactions.insert(txid);
+		}
+	}
+
+	// Informs the given `confirmables` of the given confirmed transactions, after which we can
+	// stop watching them and any outputs they spend.
+	pub fn sync_confirmed_transactions(
+		&mut self, confirmables: &Vec<&(dyn Confirm + Sync + Send)>, confirmed_txs: Vec<ConfirmedTx>,
+	) {
+		for ctx in confirmed_txs {
+			for c in confirmables {
+				c.transactions_confirmed(
+					&ctx.block_header,
+					&[(ctx.pos, &ctx.tx)],
+					ctx.block_height,
+				);
+			}
+
+			self.watched_transactions.remove(&ctx.tx.txid());
+
+			for input in &ctx.tx.input {
+				self.watched_outputs.remove(&input.previous_output);
+			}
+		}
+	}
 }
 
 
@@ -0,0 +1,782 @@
+use crate::common::{ConfirmedTx, SyncState, FilterQueue};
+use crate::error::{TxSyncError, InternalError};
+
+use electrum_client::Client as ElectrumClient;
+use electrum_client::ElectrumApi;
+use electrum_client::{GetHistoryRes, GetMerkleRes, HeaderNotification};
+
+use lightning::util::logger::Logger;
+use lightning::{log_error, log_info, log_debug, log_trace};
+use lightning::chain::WatchedOutput;
+use lightning::chain::{Confirm, Filter};
+
+use bitcoin::{BlockHash, BlockHeader, Script, Transaction, TxMerkleNode, Txid};
+use bitcoin::hashes::Hash;
+use bitcoin::hashes::sha256d::Hash as Sha256d;
+
+use std::ops::Deref;
+use std::sync::Mutex;
+use std::collections::HashSet;
+
+mod sealed {
+	use super::*;
+
+	/// The Electrum server calls [`ElectrumSyncClient`] relies on, abstracted so that syncing can
+	/// be tested against a mocked server.
+	pub trait ElectrumSource {
+		fn block_headers_subscribe(&self) -> Result<HeaderNotification, electrum_client::Error>;
+		fn block_headers_pop(&self) -> Result<Option<HeaderNotification>, electrum_client::Error>;
+		fn block_header(&self, height: usize) -> Result<BlockHeader, electrum_client::Error>;
+		fn transaction_get(&self, txid: &Txid) -> Result<Transaction, electrum_client::Error>;
+		fn transaction_get_merkle(&self, txid: &Txid, height: usize) -> Result<GetMerkleRes, electrum_client::Error>;
+		fn script_get_history(&self, script: &Script) -> Result<Vec<GetHistoryRes>, electrum_client::Error>;
+		fn batch_script_get_history(&self, scripts: &[Script]) -> Result<Vec<Vec<GetHistoryRes>>, electrum_client::Error>;
+	}
+
+	impl ElectrumSource for ElectrumClient {
+		fn block_headers_subscribe(&self) -> Result<HeaderNotification, electrum_client::Error> {
+			ElectrumApi::block_headers_subscribe(self)
+		}
+
+		fn block_headers_pop(&self) -> Result<Option<HeaderNotification>, electrum_client::Error> {
+			ElectrumApi::block_headers_pop(self)
+		}
+
+		fn block_header(&self, height: usize) -> Result<BlockHeader, electrum_client::Error> {
+			ElectrumApi::block_header(self, height)
+		}
+
+		fn transaction_get(&self, txid: &Txid) -> Result<Transaction, electrum_client::Error> {
+			ElectrumApi::transaction_get(self, txid)
+		}
+
+		fn transaction_get_merkle(&self, txid: &Txid, height: usize) -> Result<GetMerkleRes, electrum_client::Error> {
+			ElectrumApi::transaction_get_merkle(self, txid, height)
+		}
+
+		fn script_get_history(&self, script: &Script) -> Result<Vec<GetHistoryRes>, electrum_client::Error> {
+			ElectrumApi::script_get_history(self, script)
+		}
+
+		fn batch_script_get_history(&self, scripts: &[Script]) -> Result<Vec<Vec<GetHistoryRes>>, electrum_client::Error> {
+			ElectrumApi::batch_script_get_history(self, scripts.iter())
+		}
+	}
+}
+use sealed::ElectrumSource;
+
+/// Synchronizes LDK with a given Electrum server.
+///
+/// Needs to be registered with a [`ChainMonitor`] via the [`Filter`] interface to be informed of
+/// transactions and outputs to monitor for on-chain confirmation, unconfirmation, and
+/// reconfirmation.
+///
+/// Confirmations are discovered by querying the history of the script hashes of watched
+/// transactions and outputs, and their position in the block is established by verifying the
+/// Merkle proofs retrieved via `blockchain.transaction.get_merkle` against the respective block
+/// header.
+///
+/// Note that registration via [`Filter`] needs to happen before any calls to
+/// [`Watch::watch_channel`] to ensure we get notified of the items to monitor.
+///
+/// [`ChainMonitor`]: lightning::chain::chainmonitor::ChainMonitor
+/// [`Watch::watch_channel`]: lightning::chain::Watch::watch_channel
+/// [`Filter`]: lightning::chain::Filter
+pub struct ElectrumSyncClient<L: Deref, C: ElectrumSource = ElectrumClient>
+where
+	L::Target: Logger,
+{
+	sync_state: Mutex<SyncState>,
+	queue: Mutex<FilterQueue>,
+	client: C,
+	logger: L,
+}
+
+impl<L: Deref> ElectrumSyncClient<L>
+where
+	L::Target: Logger,
+{
+	/// Returns a new [`ElectrumSyncClient`] object.
+	pub fn new(server_url: String, logger: L) -> Result<Self, TxSyncError> {
+		let client = ElectrumClient::new(&server_url).map_err(|e| {
+			log_error!(logger, "Failed to connect to electrum server '{}': {}", server_url, e);
+			e
+		})?;
+
+		Ok(Self::from_client(client, logger))
+	}
+}
+
+impl<L: Deref, C: ElectrumSource> ElectrumSyncClient<L, C>
+where
+	L::Target: Logger,
+{
+	/// Returns a new [`ElectrumSyncClient`] object using the given Electrum client.
+	pub fn from_client(client: C, logger: L) -> Self {
+		let sync_state = Mutex::new(SyncState::new());
+		let queue = Mutex::new(FilterQueue::new());
+		Self {
+			sync_state,
+			queue,
+			client,
+			logger,
+		}
+	}
+
+	/// Synchronizes the given `confirmables` via their [`Confirm`] interface implementations. This
+	/// method should be called regularly to keep LDK up-to-date with current chain data.
+	///
+	/// For example, instances of [`ChannelManager`] and [`ChainMonitor`] can be informed about the
+	/// newest on-chain activity related to the items previously registered via the [`Filter`]
+	/// interface.
+	///
+	/// [`Confirm`]: lightning::chain::Confirm
+	/// [`ChainMonitor`]: lightning::chain::chainmonitor::ChainMonitor
+	/// [`ChannelManager`]: lightning::ln::channelmanager::ChannelManager
+	/// [`Filter`]: lightning::chain::Filter
+	pub fn sync(&self, confirmables: Vec<&(dyn Confirm + Sync + Send)>) -> Result<(), TxSyncError> {
+		// This lock makes sure we're syncing once at a time.
+		let mut sync_state = self.sync_state.lock().unwrap();
+
+		log_info!(self.logger, "Starting transaction sync.");
+
+		// Clear any header notifications we might have gotten to keep the queue count low.
+		while self.client.block_headers_pop()?.is_some() {}
+
+		let tip_notification = self.client.block_headers_subscribe()?;
+		let mut tip_header = tip_notification.header;
+		let mut tip_height = tip_notification.height as u32;
+
+		loop {
+			let pending_registrations = self.queue.lock().unwrap().process_queues(&mut sync_state);
+			let tip_is_new = Some(tip_header.block_hash()) != sync_state.last_sync_hash;
+
+			// We loop until any registered transactions have been processed at least once, or the
+			// tip hasn't been updated during the last iteration.
+			if !sync_state.pending_sync && !pending_registrations && !tip_is_new {
+				// Nothing to do.
+				break;
+			} else {
+				// Update the known tip to the newest one.
+				if tip_is_new {
+					// First check for any unconfirmed transactions and act on it immediately.
+					match self.get_unconfirmed_transactions(&confirmables) {
+						Ok(unconfirmed_txs) => {
+							// Double-check the tip hash. If it changed, a reorg happened since
+							// we started syncing and we need to restart last-minute.
+							match self.check_update_tip(&mut tip_header, &mut tip_height) {
+								Ok(false) => {
+									sync_state.sync_unconfirmed_transactions(&confirmables, unconfirmed_txs);
+								}
+								Ok(true) => {
+									log_debug!(self.logger, "Encountered inconsistency during transaction sync, restarting.");
+									sync_state.pending_sync = true;
+									continue;
+								}
+								Err(err) => {
+									// (Semi-)permanent failure, retry later.
+									log_error!(self.logger, "Failed during transaction sync, aborting.");
+									sync_state.pending_sync = true;
+									return Err(TxSyncError::from(err));
+								}
+							}
+						},
+						Err(InternalError::Inconsistency) => {
+							// Immediately restart syncing when we encounter any inconsistencies.
+							log_debug!(self.logger, "Encountered inconsistency during transaction sync, restarting.");
+							sync_state.pending_sync = true;
+							continue;
+						}
+						Err(err) => {
+							// (Semi-)permanent failure, retry later.
+							log_error!(self.logger, "Failed during transaction sync, aborting.");
+							sync_state.pending_sync = true;
+							return Err(TxSyncError::from(err));
+						}
+					}
+
+					// Update the best block.
+					for c in &confirmables {
+						c.best_block_updated(&tip_header, tip_height);
+					}
+				}
+
+				match self.get_confirmed_transactions(&sync_state) {
+					Ok(confirmed_txs) => {
+						// Double-check the tip hash. If it changed, a reorg happened since
+						// we started syncing and we need to restart last-minute.
+						match self.check_update_tip(&mut tip_header, &mut tip_height) {
+							Ok(false) => {
+								sync_state.sync_confirmed_transactions(&confirmables, confirmed_txs);
+							}
+							Ok(true) => {
+								log_debug!(self.logger, "Encountered inconsistency during transaction sync, restarting.");
+								sync_state.pending_sync = true;
+								continue;
+							}
+							Err(err) => {
+								// (Semi-)permanent failure, retry later.
+								log_error!(self.logger, "Failed during transaction sync, aborting.");
+								sync_state.pending_sync = true;
+								return Err(TxSyncError::from(err));
+							}
+						}
+					}
+					Err(InternalError::Inconsistency) => {
+						// Immediately restart syncing when we encounter any inconsistencies.
+						log_debug!(self.logger, "Encountered inconsistency during transaction sync, restarting.");
+						sync_state.pending_sync = true;
+						continue;
+					}
+					Err(err) => {
+						// (Semi-)permanent failure, retry later.
+						log_error!(self.logger, "Failed during transaction sync, aborting.");
+						sync_state.pending_sync = true;
+						return Err(TxSyncError::from(err));
+					}
+				}
+				sync_state.last_sync_hash = Some(tip_header.block_hash());
+				sync_state.pending_sync = false;
+			}
+		}
+		log_info!(self.logger, "Finished transaction sync.");
+		Ok(())
+	}
+
+	// Checks whether the tip changed since we last looked at it, updating the given tip if so.
+	//
+	// Returns `true` if the tip changed and we therefore need to restart syncing.
+	fn check_update_tip(&self, cur_tip_header: &mut BlockHeader, cur_tip_height: &mut u32)
+		-> Result<bool, InternalError>
+	{
+		let check_notification = self.client.block_headers_subscribe()?;
+		let check_tip_hash = check_notification.header.block_hash();
+
+		// Restart if either the tip changed or we got some divergent tip change notification
+		// since we started. In the latter case we make sure we clear the queue before continuing.
+		let mut restart_sync = check_tip_hash != cur_tip_header.block_hash();
+		while let Some(queued_notif) = self.client.block_headers_pop()? {
+			if queued_notif.header.block_hash() != check_tip_hash {
+				restart_sync = true
+			}
+		}
+
+		if restart_sync {
+			*cur_tip_header = check_notification.header;
+			*cur_tip_height = check_notification.height as u32;
+			Ok(true)
+		} else {
+			Ok(false)
+		}
+	}
+
+	fn get_confirmed_transactions(
+		&self, sync_state: &SyncState,
+	) -> Result<Vec<ConfirmedTx>, InternalError> {
+
+		// First, check the confirmation status of registered transactions as well as the
+		// status of dependent transactions of registered outputs.
+		let mut confirmed_txs = Vec::new();
+		let mut watched_script_pubkeys = Vec::with_capacity(
+			sync_state.watched_transactions.len() + sync_state.watched_outputs.len());
+		let mut watched_txs = Vec::with_capacity(sync_state.watched_transactions.len());
+
+		for txid in &sync_state.watched_transactions {
+			match self.client.transaction_get(&txid) {
+				Ok(tx) => {
+					if let Some(tx_out) = tx.output.first() {
+						// We watch an arbitrary output of the transaction of interest in order to
+						// retrieve the associated script history, before narrowing down our search
+						// by `txid` below.
+						watched_script_pubkeys.push(tx_out.script_pubkey.clone());
+						watched_txs.push((txid, tx));
+					} else {
+						log_error!(self.logger, "Retrieved transaction {} without outputs. Please verify server integrity.", txid);
+						return Err(InternalError::Failed);
+					}
+				}
+				Err(electrum_client::Error::Protocol(_)) => {
+					// We couldn't find the tx, it probably hasn't been broadcast yet.
+				}
+				Err(e) => {
+					log_error!(self.logger, "Failed to look up transaction {}: {}.", txid, e);
+					return Err(InternalError::Failed);
+				}
+			}
+		}
+
+		let num_tx_lookups = watched_script_pubkeys.len();
+
+		for output in sync_state.watched_outputs.values() {
+			watched_script_pubkeys.push(output.script_pubkey.clone());
+		}
+
+		let results = self.client.batch_script_get_history(&watched_script_pubkeys)?;
+		if results.len() != watched_script_pubkeys.len() {
+			log_error!(self.logger, "Retrieved an unexpected number of script histories. Please verify server integrity.");
+			return Err(InternalError::Failed);
+		}
+		let (tx_results, output_results) = results.split_at(num_tx_lookups);
+
+		for ((txid, tx), script_history) in watched_txs.iter().zip(tx_results) {
+			let history = script_history.iter().find(|h| h.tx_hash == **txid);
+			if let Some(history) = history {
+				if history.height <= 0 {
+					// Still unconfirmed.
+					continue;
+				}
+
+				let confirmed_tx = self.get_confirmed_tx(tx, history.height as u32)?;
+				confirmed_txs.push(confirmed_tx);
+			}
+		}
+
+		for (watched_output, script_history) in sync_state.watched_outputs.values().zip(output_results) {
+			for possible_output_spend in script_history {
+				if possible_output_spend.height <= 0 {
+					continue;
+				}
+
+				let txid = possible_output_spend.tx_hash;
+				match self.client.transaction_get(&txid) {
+					Ok(tx) => {
+						let watched_outpoint = watched_output.outpoint.into_bitcoin_outpoint();
+						let is_spend = tx.input.iter().any(|txin| txin.previous_output == watched_outpoint);
+						if !is_spend {
+							continue;
+						}
+
+						let confirmed_tx = self.get_confirmed_tx(&tx, possible_output_spend.height as u32)?;
+						confirmed_txs.push(confirmed_tx);
+					}
+					Err(e) => {
+						log_trace!(self.logger, "Inconsistency: Tx {} was unconfirmed during syncing: {}", txid, e);
+						return Err(InternalError::Inconsistency);
+					}
+				}
+			}
+		}
+
+		// Sort all confirmed transactions first by block height, then by in-block
+		// position, and finally feed them to the interface in order.
+		confirmed_txs.sort_unstable_by(|tx1, tx2| {
+			tx1.block_height.cmp(&tx2.block_height).then_with(|| tx1.pos.cmp(&tx2.pos))
+		});
+
+		Ok(confirmed_txs)
+	}
+
+	fn get_unconfirmed_transactions(
+		&self, confirmables: &Vec<&(dyn Confirm + Sync + Send)>,
+	) -> Result<Vec<Txid>, InternalError> {
+		// Query the interface for relevant txids and check whether the relevant blocks are still
+		// in the best chain, mark them unconfirmed otherwise
+		let relevant_txids = confirmables
+			.iter()
+			.flat_map(|c| c.get_relevant_txids())
+			.collect::<HashSet<(Txid, Option<BlockHash>)>>();
+
+		let mut unconfirmed_txs = Vec::new();
+
+		for (txid, block_hash_opt) in relevant_txids {
+			if let Some(block_hash) = block_hash_opt {
+				if let Some(conf_height) = self.get_tx_confirmation_height(&txid)? {
+					let block_header = self.client.block_header(conf_height as usize)?;
+					if block_header.block_hash() == block_hash {
+						// Skip if the tx is still confirmed in the block in question.
+						continue;
+					}
+				}
+
+				unconfirmed_txs.push(txid);
+			} else {
+				log_error!(self.logger, "Untracked confirmation of funding transaction. Please ensure none of your channels had been created with LDK prior to version 0.0.113!");
+				panic!("Untracked confirmation of funding transaction. Please ensure none of your channels had been created with LDK prior to version 0.0.113!");
+			}
+		}
+		Ok(unconfirmed_txs)
+	}
+
+	// Returns the height at which the given transaction is currently confirmed, if any.
+	fn get_tx_confirmation_height(&self, txid: &Txid) -> Result<Option<u32>, InternalError> {
+		let tx = match self.client.transaction_get(txid) {
+			Ok(tx) => tx,
+			// The transaction is not known to the server (anymore).
+			Err(electrum_client::Error::Protocol(_)) => return Ok(None),
+			Err(e) => {
+				log_error!(self.logger, "Failed to look up transaction {}: {}.", txid, e);
+				return Err(InternalError::Failed);
+			}
+		};
+
+		let script_pubkey = match tx.output.first() {
+			Some(tx_out) => &tx_out.script_pubkey,
+			None => {
+				log_error!(self.logger, "Retrieved transaction {} without outputs. Please verify server integrity.", txid);
+				return Err(InternalError::Failed);
+			}
+		};
+
+		let script_history = self.client.script_get_history(script_pubkey)?;
+		Ok(script_history.iter()
+			.find(|h| h.tx_hash == *txid && h.height > 0)
+			.map(|h| h.height as u32))
+	}
+
+	fn get_confirmed_tx(&self, tx: &Transaction, prob_conf_height: u32) -> Result<ConfirmedTx, InternalError> {
+		let txid = tx.txid();
+		match self.client.transaction_get_merkle(&txid, prob_conf_height as usize) {
+			Ok(merkle_res) => {
+				if merkle_res.block_height as u32 != prob_conf_height {
+					log_trace!(self.logger, "Inconsistency: Tx {} expected at height {}, but got a Merkle proof for height {}", txid, prob_conf_height, merkle_res.block_height);
+					return Err(InternalError::Inconsistency);
+				}
+
+				let block_header = self.client.block_header(prob_conf_height as usize)?;
+				let pos = merkle_res.pos;
+				if !Self::validate_merkle_proof(&txid, &block_header.merkle_root, merkle_res) {
+					log_trace!(self.logger, "Inconsistency: Block {} was unconfirmed during syncing.", block_header.block_hash());
+					return Err(InternalError::Inconsistency);
+				}
+
+				Ok(ConfirmedTx { tx: tx.clone(), block_header, block_height: prob_conf_height, pos })
+			}
+			Err(e) => {
+				log_trace!(self.logger, "Inconsistency: Failed to retrieve Merkle proof for tx {} at height {}: {}", txid, prob_conf_height, e);
+				Err(InternalError::Inconsistency)
+			}
+		}
+	}
+
+	// Checks that the given Merkle proof commits to the given transaction at the claimed position.
+	fn validate_merkle_proof(txid: &Txid, merkle_root: &TxMerkleNode, merkle_res: GetMerkleRes) -> bool {
+		let mut index = merkle_res.pos;
+		let mut cur = txid.as_hash();
+		for mut bytes in merkle_res.merkle {
+			// The proof's hashes are given in the reversed (display) byte order.
+			bytes.reverse();
+			let next_hash = Sha256d::from_inner(bytes);
+			let (left, right) = if index % 2 == 0 {
+				(cur, next_hash)
+			} else {
+				(next_hash, cur)
+			};
+
+			let data = [&left[..], &right[..]].concat();
+			cur = Sha256d::hash(&data);
+			index /= 2;
+		}
+
+		cur == merkle_root.as_hash()
+	}
+
+	/// Returns a reference to the underlying Electrum client.
+	pub fn client(&self) -> &C {
+		&self.client
+	}
+}
+
+impl<L: Deref, C: ElectrumSource> Filter for ElectrumSyncClient<L, C>
+where
+	L::Target: Logger,
+{
+	fn register_tx(&self, txid: &Txid, _script_pubkey: &Script) {
+		let mut locked_queue = self.queue.lock().unwrap();
+		locked_queue.transactions.insert(*txid);
+	}
+
+	fn register_output(&self, output: WatchedOutput) {
+		let mut locked_queue = self.queue.lock().unwrap();
+		locked_queue.outputs.insert(output.outpoint.into_bitcoin_outpoint(), output);
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	use lightning::chain::transaction::{OutPoint, TransactionData};
+	use lightning::util::logger::Record;
+
+	use bitcoin::{OutPoint as BitcoinOutPoint, PackedLockTime, Sequence, TxIn, TxOut, Witness};
+
+	use std::collections::HashMap;
+
+	struct TestLogger {}
+
+	impl Logger for TestLogger {
+		fn log(&self, record: &Record) {
+			println!("{} -- {}", record.level, record.args);
+		}
+	}
+
+	#[derive(Debug, PartialEq, Eq)]
+	enum TestConfirmableEvent {
+		Confirmed(Txid, BlockHash, u32),
+		Unconfirmed(Txid),
+		BestBlockUpdated(BlockHash, u32),
+	}
+
+	struct TestConfirmable {
+		confirmed_txs: Mutex<HashMap<Txid, (BlockHash, u32)>>,
+		events: Mutex<Vec<TestConfirmableEvent>>,
+	}
+
+	impl TestConfirmable {
+		fn new() -> Self {
+			Self { confirmed_txs: Mutex::new(HashMap::new()), events: Mutex::new(Vec::new()) }
+		}
+
+		fn take_events(&self) -> Vec<TestConfirmableEvent> {
+			std::mem::take(&mut *self.events.lock().unwrap())
+		}
+	}
+
+	impl Confirm for TestConfirmable {
+		fn transactions_confirmed(&self, header: &BlockHeader, txdata: &TransactionData<'_>, height: u32) {
+			for (_, tx) in txdata {
+				let txid = tx.txid();
+				self.confirmed_txs.lock().unwrap().insert(txid, (header.block_hash(), height));
+				self.events.lock().unwrap().push(TestConfirmableEvent::Confirmed(txid, header.block_hash(), height));
+			}
+		}
+
+		fn transaction_unconfirmed(&self, txid: &Txid) {
+			self.confirmed_txs.lock().unwrap().remove(txid);
+			self.events.lock().unwrap().push(TestConfirmableEvent::Unconfirmed(*txid));
+		}
+
+		fn best_block_updated(&self, header: &BlockHeader, height: u32) {
+			self.events.lock().unwrap().push(TestConfirmableEvent::BestBlockUpdated(header.block_hash(), height));
+		}
+
+		fn get_relevant_txids(&self) -> Vec<(Txid, Option<BlockHash>)> {
+			self.confirmed_txs.lock().unwrap().iter().map(|(&txid, (hash, _))| (txid, Some(*hash))).collect()
+		}
+	}
+
+	/// A mocked Electrum server tracking a chain in which each block confirms at most one
+	/// transaction, so that the Merkle root of a block is simply the txid it confirms.
+	struct MockElectrumServer {
+		headers: Mutex<Vec<BlockHeader>>,
+		// All transactions known to the server, along with the height they're confirmed at, if any.
+		txs: Mutex<Vec<(Transaction, Option<u32>)>>,
+	}
+
+	impl MockElectrumServer {
+		fn new() -> Self {
+			let server = Self { headers: Mutex::new(Vec::new()), txs: Mutex::new(Vec::new()) };
+			server.mine_block(None);
+			server
+		}
+
+		fn tip(&self) -> HeaderNotification {
+			let headers = self.headers.lock().unwrap();
+			HeaderNotification { height: headers.len() - 1, header: *headers.last().unwrap() }
+		}
+
+		fn mine_block(&self, tx: Option<&Transaction>) -> u32 {
+			let mut headers = self.headers.lock().unwrap();
+			let height = headers.len() as u32;
+			let merkle_root = match tx {
+				Some(tx) => TxMerkleNode::from_hash(tx.txid().as_hash()),
+				None => TxMerkleNode::from_hash(Sha256d::hash(&height.to_be_bytes())),
+			};
+			let prev_blockhash = headers.last().map_or(BlockHash::all_zeros(), |h| h.block_hash());
+			headers.push(BlockHeader {
+				version: 2,
+				prev_blockhash,
+				merkle_root,
+				// Vary the timestamp so blocks mined after a reorg differ from the ones they replace.
+				time: 42 + height + self.txs.lock().unwrap().len() as u32 * 1000,
+				bits: 0x207fffff,
+				nonce: 0,
+			});
+			if let Some(tx) = tx {
+				let mut txs = self.txs.lock().unwrap();
+				txs.retain(|(known_tx, _)| known_tx.txid() != tx.txid());
+				txs.push((tx.clone(), Some(height)));
+			}
+			height
+		}
+
+		fn broadcast(&self, tx: &Transaction) {
+			self.txs.lock().unwrap().push((tx.clone(), None));
+		}
+
+		// Disconnects all blocks above the given height, returning their transactions to the
+		// mempool.
+		fn reorg_to(&self, height: u32) {
+			self.headers.lock().unwrap().truncate(height as usize + 1);
+			for (_, conf_height) in self.txs.lock().unwrap().iter_mut() {
+				if conf_height.map_or(false, |h| h > height) {
+					*conf_height = None;
+				}
+			}
+		}
+	}
+
+	impl ElectrumSource for MockElectrumServer {
+		fn block_headers_subscribe(&self) -> Result<HeaderNotification, electrum_client::Error> {
+			Ok(self.tip())
+		}
+
+		fn block_headers_pop(&self) -> Result<Option<HeaderNotification>, electrum_client::Error> {
+			Ok(None)
+		}
+
+		fn block_header(&self, height: usize) -> Result<BlockHeader, electrum_client::Error> {
+			self.headers.lock().unwrap().get(height).cloned()
+				.ok_or_else(|| electrum_client::Error::Message("Unknown block".to_owned()))
+		}
+
+		fn transaction_get(&self, txid: &Txid) -> Result<Transaction, electrum_client::Error> {
+			self.txs.lock().unwrap().iter().find(|(tx, _)| tx.txid() == *txid).map(|(tx, _)| tx.clone())
+				.ok_or_else(|| electrum_client::Error::Message("Unknown transaction".to_owned()))
+		}
+
+		fn transaction_get_merkle(&self, txid: &Txid, height: usize) -> Result<GetMerkleRes, electrum_client::Error> {
+			let txs = self.txs.lock().unwrap();
+			match txs.iter().find(|(tx, _)| tx.txid() == *txid) {
+				Some((_, Some(conf_height))) if *conf_height as usize == height => {
+					Ok(GetMerkleRes { block_height: height, pos: 0, merkle: Vec::new() })
+				},
+				_ => Err(electrum_client::Error::Message("Transaction not confirmed at height".to_owned())),
+			}
+		}
+
+		fn script_get_history(&self, script: &Script) -> Result<Vec<GetHistoryRes>, electrum_client::Error> {
+			// A script's history consists of all transactions paying to it and all transactions
+			// spending outputs paying to it.
+			let txs = self.txs.lock().unwrap();
+			let pays_to_script = |tx: &Transaction| tx.output.iter().any(|o| o.script_pubkey == *script);
+			let spends_script = |tx: &Transaction| tx.input.iter().any(|i| txs.iter().any(|(prev_tx, _)| {
+				prev_tx.txid() == i.previous_output.txid &&
+					prev_tx.output.get(i.previous_output.vout as usize).map_or(false, |o| o.script_pubkey == *script)
+			}));
+			Ok(txs.iter().filter(|(tx, _)| pays_to_script(tx) || spends_script(tx))
+				.map(|(tx, conf_height)| GetHistoryRes {
+					height: conf_height.map_or(0, |h| h as i32),
+					tx_hash: tx.txid(),
+					fee: None,
+				}).collect())
+		}
+
+		fn batch_script_get_history(&self, scripts: &[Script]) -> Result<Vec<Vec<GetHistoryRes>>, electrum_client::Error> {
+			scripts.iter().map(|script| self.script_get_history(script)).collect()
+		}
+	}
+
+	fn build_tx(seed: u8, prev_output: BitcoinOutPoint) -> Transaction {
+		Transaction {
+			version: 2,
+			lock_time: PackedLockTime(seed as u32),
+			input: vec![TxIn {
+				previous_output: prev_output,
+				script_sig: Script::new(),
+				sequence: Sequence::MAX,
+				witness: Witness::new(),
+			}],
+			output: vec![TxOut {
+				value: 5000,
+				script_pubkey: Script::new_v0_p2wsh(&bitcoin::WScriptHash::hash(&[seed])),
+			}],
+		}
+	}
+
+	#[test]
+	fn test_electrum_syncs_script_histories() {
+		let server = MockElectrumServer::new();
+		let logger = TestLogger {};
+		let tx_sync = ElectrumSyncClient::from_client(server, &logger);
+		let confirmable = TestConfirmable::new();
+
+		tx_sync.sync(vec![&confirmable]).unwrap();
+		let tip = tx_sync.client().tip();
+		assert_eq!(confirmable.take_events(), vec![
+			TestConfirmableEvent::BestBlockUpdated(tip.header.block_hash(), 0),
+		]);
+
+		// Register a transaction in the mempool and an output which hasn't been created yet.
+		let tx = build_tx(1, BitcoinOutPoint::null());
+		tx_sync.client().broadcast(&tx);
+		tx_sync.register_tx(&tx.txid(), &tx.output[0].script_pubkey);
+		let funding_tx = build_tx(2, BitcoinOutPoint::null());
+		tx_sync.register_output(WatchedOutput {
+			block_hash: None,
+			outpoint: OutPoint { txid: funding_tx.txid(), index: 0 },
+			script_pubkey: funding_tx.output[0].script_pubkey.clone(),
+		});
+		tx_sync.sync(vec![&confirmable]).unwrap();
+		assert!(confirmable.take_events().is_empty());
+
+		// Neither the output being created nor an unconfirmed spend of it is reported.
+		let spending_tx = build_tx(3, BitcoinOutPoint { txid: funding_tx.txid(), vout: 0 });
+		tx_sync.client().mine_block(Some(&funding_tx));
+		tx_sync.client().broadcast(&spending_tx);
+		tx_sync.sync(vec![&confirmable]).unwrap();
+		let tip = tx_sync.client().tip();
+		assert_eq!(confirmable.take_events(), vec![
+			TestConfirmableEvent::BestBlockUpdated(tip.header.block_hash(), 1),
+		]);
+
+		// Once confirmed, both the registered transaction and the spend of the registered output
+		// are found via the histories of their scripts, in the order they confirmed.
+		let tx_height = tx_sync.client().mine_block(Some(&tx));
+		let spending_tx_height = tx_sync.client().mine_block(Some(&spending_tx));
+		tx_sync.sync(vec![&confirmable]).unwrap();
+		let headers = tx_sync.client().headers.lock().unwrap().clone();
+		assert_eq!(confirmable.take_events(), vec![
+			TestConfirmableEvent::BestBlockUpdated(headers[3].block_hash(), 3),
+			TestConfirmableEvent::Confirmed(tx.txid(), headers[2].block_hash(), tx_height),
+			TestConfirmableEvent::Confirmed(spending_tx.txid(), headers[3].block_hash(), spending_tx_height),
+		]);
+
+		// Nothing changes if the chain doesn't.
+		tx_sync.sync(vec![&confirmable]).unwrap();
+		assert!(confirmable.take_events().is_empty());
+	}
+
+	#[test]
+	fn test_electrum_handles_reorgs() {
+		let server = MockElectrumServer::new();
+		let logger = TestLogger {};
+		let tx_sync = ElectrumSyncClient::from_client(server, &logger);
+		let confirmable = TestConfirmable::new();
+
+		let tx = build_tx(1, BitcoinOutPoint::null());
+		tx_sync.register_tx(&tx.txid(), &tx.output[0].script_pubkey);
+		tx_sync.client().mine_block(None);
+		let tx_height = tx_sync.client().mine_block(Some(&tx));
+		tx_sync.sync(vec![&confirmable]).unwrap();
+		let old_block_hash = tx_sync.client().tip().header.block_hash();
+		assert_eq!(confirmable.take_events(), vec![
+			TestConfirmableEvent::BestBlockUpdated(old_block_hash, 2),
+			TestConfirmableEvent::Confirmed(tx.txid(), old_block_hash, tx_height),
+		]);
+
+		// Reorg the transaction out and back into the chain one block later.
+		tx_sync.client().reorg_to(1);
+		tx_sync.client().mine_block(None);
+		let new_tx_height = tx_sync.client().mine_block(Some(&tx));
+		tx_sync.sync(vec![&confirmable]).unwrap();
+		let headers = tx_sync.client().headers.lock().unwrap().clone();
+		assert_ne!(headers[2].block_hash(), old_block_hash);
+		assert_eq!(confirmable.take_events(), vec![
+			TestConfirmableEvent::Unconfirmed(tx.txid()),
+			TestConfirmableEvent::BestBlockUpdated(headers[3].block_hash(), 3),
+			TestConfirmableEvent::Confirmed(tx.txid(), headers[3].block_hash(), new_tx_height),
+		]);
+
+		// Reorg the transaction out entirely, leaving it in the mempool.
+		tx_sync.client().reorg_to(2);
+		tx_sync.client().mine_block(None);
+		tx_sync.sync(vec![&confirmable]).unwrap();
+		let tip = tx_sync.client().tip();
+		assert_eq!(confirmable.take_events(), vec![
+			TestConfirmableEvent::Unconfirmed(tx.txid()),
+			TestConfirmableEvent::BestBlockUpdated(tip.header.block_hash(), 3),
+		]);
+		assert!(confirmable.confirmed_txs.lock().unwrap().is_empty());
+	}
+}
@@ -18,7 +18,7 @@ impl fmt::Display for TxSyncError {
 }
 
 #[derive(Debug)]
-#[cfg(any(feature = "esplora-blocking", feature = "esplora-async"))]
+#[cfg(any(feature = "esplora-blocking", feature = "esplora-async", feature = "electrum"))]
 pub(crate) enum InternalError {
 	/// A transaction sync failed and needs to be retried eventually.
 	Failed,
@@ -26,7 +26,7 @@ pub(crate) enum InternalError {
 	Inconsistency,
 }
 
-#[cfg(any(feature = "esplora-blocking", feature = "esplora-async"))]
+#[cfg(any(feature = "esplora-blocking", feature = "esplora-async", feature = "electrum"))]
 impl fmt::Display for InternalError {
 	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
 		match *self {
@@ -38,7 +38,7 @@ impl fmt::Display for InternalError {
 	}
 }
 
-#[cfg(any(feature = "esplora-blocking", feature = "esplora-async"))]
+#[cfg(any(feature = "esplora-blocking", feature = "esplora-async", feature = "electrum"))]
 impl std::error::Error for InternalError {}
 
 #[cfg(any(feature = "esplora-blocking", feature = "esplora-async"))]
@@ -55,7 +55,21 @@ impl From<esplora_client::Error> for InternalError {
 	}
 }
 
-#[cfg(any(feature = "esplora-blocking", feature = "esplora-async"))]
+#[cfg(feature = "electrum")]
+impl From<electrum_client::Error> for InternalError {
+	fn from(_e: electrum_client::Error) -> Self {
+		Self::Failed
+	}
+}
+
+#[cfg(feature = "electrum")]
+impl From<electrum_client::Error> for TxSyncError {
+	fn from(_e: electrum_client::Error) -> Self {
+		Self::Failed
+	}
+}
+
+#[cfg(any(feature = "esplora-blocking", feature = "esplora-async", feature = "electrum"))]
 impl From<InternalError> for TxSyncError {
 	fn from(_e: InternalError) -> Self {
 		Self::Failed
@@ -116,7 +116,7 @@ where
 								continue;
 							}
 
-							self.sync_unconfirmed_transactions(&mut sync_state, &confirmables, unconfirmed_txs);
+							sync_state.sync_unconfirmed_transactions(&confirmables, unconfirmed_txs);
 						},
 						Err(err) => {
 							// (Semi-)permanent failure, retry later.
@@ -152,11 +152,7 @@ where
 							continue;
 						}
 
-						self.sync_confirmed_transactions(
-							&mut sync_state,
-							&confirmables,
-							confirmed_txs,
-						);
+						sync_state.sync_confirmed_transactions(&confirmables, confirmed_txs);
 					}
 					Err(InternalError::Inconsistency) => {
 						// Immediately restart syncing when we encounter any inconsistencies.
@@ -199,26 +195,6 @@ where
 		Ok(())
 	}
 
-	fn sync_confirmed_transactions(
-		&self, sync_state: &mut SyncState, confirmables: &Vec<&(dyn Confirm + Sync + Send)>, confirmed_txs: Vec<ConfirmedTx>,
-	) {
-		for ctx in confirmed_txs {
-			for c in confirmables {
-				c.transactions_confirmed(
-					&ctx.block_header,
-					&[(ctx.pos, &ctx.tx)],
-					ctx.block_height,
-				);
-			}
-
-			sync_state.watched_transactions.remove(&ctx.tx.txid());
-
-			for input in &ctx.tx.input {
-				sync_state.watched_outputs.remove(&input.previous_output);
-			}
-		}
-	}
-
 	#[maybe_async]
 	fn get_confirmed_transactions(
 		&self, sync_state: &SyncState,
@@ -337,18 +313,6 @@ where
 		Ok(unconfirmed_txs)
 	}
 
-	fn sync_unconfirmed_transactions(
-		&self, sync_state: &mut SyncState, confirmables: &Vec<&(dyn Confirm + Sync + Send)>, unconfirmed_txs: Vec<Txid>,
-	) {
-		for txid in unconfirmed_txs {
-			for c in confirmables {
-				c.transaction_unconfirmed(&txid);
-			}
-
-			sync_state.watched_transactions.insert(txid);
-		}
-	}
-
 	/// Returns a reference to the underlying esplora client.
 	pub fn client(&self) -> &EsploraClientType {
 		&self.client
@@ -362,9 +326,9 @@ type MutexType<I> = std::sync::Mutex<I>;
 
 // The underlying client type.
 #[cfg(feature = "async-interface")]
-type EsploraClientType = AsyncClient;
+pub(crate) type EsploraClientType = AsyncClient;
 #[cfg(not(feature = "async-interface"))]
-type EsploraClientType = BlockingClient;
+pub(crate) type EsploraClientType = BlockingClient;
 
 
 impl<L: Deref> Filter for EsploraSyncClient<L>
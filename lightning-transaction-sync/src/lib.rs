@@ -10,6 +10,11 @@
 //!- `esplora-blocking` enables syncing against an Esplora backend based on a blocking client.
 //!- `esplora-async` enables syncing against an Esplora backend based on an async client.
 //!- `esplora-async-https` enables the async Esplora client with support for HTTPS.
+//!- `electrum` enables syncing against an Electrum backend.
+//!
+//! With either of the Esplora features, `EsploraFeeEstimator` and `EsploraBroadcaster` are
+//! further provided, implementing LDK's [`FeeEstimator`] and [`BroadcasterInterface`] against the
+//! same backend.
 //!
 //! ## Version Compatibility
 //!
@@ -57,6 +62,8 @@
 //! [`Filter`]: lightning::chain::Filter
 //! [`ChainMonitor`]: lightning::chain::chainmonitor::ChainMonitor
 //! [`ChannelManager`]: lightning::ln::channelmanager::ChannelManager
+//! [`FeeEstimator`]: lightning::chain::chaininterface::FeeEstimator
+//! [`BroadcasterInterface`]: lightning::chain::chaininterface::BroadcasterInterface
 
 // Prefix these with `rustdoc::` when we update our MSRV to be >= 1.52 to remove warnings.
 #![deny(broken_intra_doc_links)]
@@ -75,6 +82,12 @@ extern crate bdk_macros;
 mod esplora;
 
 #[cfg(any(feature = "esplora-blocking", feature = "esplora-async"))]
+mod chain_interface;
+
+#[cfg(feature = "electrum")]
+mod electrum;
+
+#[cfg(any(feature = "esplora-blocking", feature = "esplora-async", feature = "electrum"))]
 mod common;
 
 mod error;
@@ -82,3 +95,9 @@ pub use error::TxSyncError;
 
 #[cfg(any(feature = "esplora-blocking", feature = "esplora-async"))]
 pub use esplora::EsploraSyncClient;
+
+#[cfg(any(feature = "esplora-blocking", feature = "esplora-async"))]
+pub use chain_interface::{EsploraBroadcaster, EsploraFeeEstimator};
+
+#[cfg(feature = "electrum")]
+pub use electrum::ElectrumSyncClient;
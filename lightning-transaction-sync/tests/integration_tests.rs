@@ -1,6 +1,14 @@
-#![cfg(any(feature = "esplora-blocking", feature = "esplora-async"))]
+#![cfg(any(feature = "esplora-blocking", feature = "esplora-async", feature = "electrum"))]
+#[cfg(any(feature = "esplora-blocking", feature = "esplora-async"))]
 use lightning_transaction_sync::EsploraSyncClient;
-use lightning::chain::{Confirm, Filter};
+#[cfg(feature = "esplora-blocking")]
+use lightning_transaction_sync::{EsploraBroadcaster, EsploraFeeEstimator};
+#[cfg(feature = "esplora-blocking")]
+use lightning::chain::chaininterface::{BroadcasterInterface, ConfirmationTarget, FeeEstimator, FEERATE_FLOOR_SATS_PER_KW};
+#[cfg(feature = "electrum")]
+use lightning_transaction_sync::ElectrumSyncClient;
+use lightning::chain::{Confirm, Filter, WatchedOutput};
+use lightning::chain::transaction::OutPoint;
 use lightning::chain::transaction::TransactionData;
 use lightning::util::logger::{Logger, Record};
 
@@ -322,6 +330,34 @@ async fn test_esplora_syncs() {
 	}
 }
 
+#[test]
+#[cfg(feature = "esplora-blocking")]
+fn test_esplora_estimates_fees_and_broadcasts() {
+	let (bitcoind, electrsd) = setup_bitcoind_and_electrsd();
+	generate_blocks_and_wait(&bitcoind, &electrsd, 101);
+	let logger = TestLogger {};
+	let esplora_url = format!("http://{}", electrsd.esplora_url.as_ref().unwrap());
+	let fee_estimator = EsploraFeeEstimator::new(esplora_url.clone()).unwrap();
+	let broadcaster = EsploraBroadcaster::new(esplora_url, &logger).unwrap();
+
+	// Check we get sane feerates both before and after retrieving estimates.
+	assert_eq!(fee_estimator.get_est_sat_per_1000_weight(ConfirmationTarget::Normal), 2000);
+	fee_estimator.update_fee_estimates().unwrap();
+	assert!(fee_estimator.get_est_sat_per_1000_weight(ConfirmationTarget::HighPriority) >= FEERATE_FLOOR_SATS_PER_KW);
+
+	// Check a transaction handed to the broadcaster makes it into the mempool.
+	let new_address = bitcoind.client.get_new_address(Some("test"), Some(AddressType::Legacy)).unwrap();
+	let mut outputs = HashMap::new();
+	outputs.insert(new_address.to_string(), Amount::from_sat(5000));
+	let raw_tx = bitcoind.client.create_raw_transaction_hex(&[], &outputs, None, None).unwrap();
+	let funded_tx = bitcoind.client.fund_raw_transaction(raw_tx, None, None).unwrap();
+	let signed_tx = bitcoind.client.sign_raw_transaction_with_wallet(&funded_tx.hex, None, None).unwrap();
+	let tx = signed_tx.transaction().unwrap();
+
+	broadcaster.broadcast_transactions(&[&tx]);
+	assert!(bitcoind.client.get_raw_mempool().unwrap().contains(&tx.txid()));
+}
+
 #[tokio::test]
 #[cfg(any(feature = "esplora-async-https", feature = "esplora-blocking"))]
 async fn test_esplora_connects_to_public_server() {
@@ -338,3 +374,103 @@ async fn test_esplora_connects_to_public_server() {
 	tx_sync.sync(vec![&confirmable]).unwrap();
 	assert_ne!(confirmable.best_block.lock().unwrap().1, 0);
 }
+
+#[test]
+#[cfg(feature = "electrum")]
+fn test_electrum_syncs() {
+	let (bitcoind, electrsd) = setup_bitcoind_and_electrsd();
+	generate_blocks_and_wait(&bitcoind, &electrsd, 101);
+	let mut logger = TestLogger {};
+	let electrum_url = format!("tcp://{}", electrsd.electrum_url);
+	let tx_sync = ElectrumSyncClient::new(electrum_url, &mut logger).unwrap();
+	let confirmable = TestConfirmable::new();
+
+	// Check we pick up on new best blocks
+	assert_eq!(confirmable.best_block.lock().unwrap().1, 0);
+
+	tx_sync.sync(vec![&confirmable]).unwrap();
+	assert_eq!(confirmable.best_block.lock().unwrap().1, 102);
+
+	let events = std::mem::take(&mut *confirmable.events.lock().unwrap());
+	assert_eq!(events.len(), 1);
+
+	// Check registered confirmed transactions are marked confirmed
+	let new_address = bitcoind.client.get_new_address(Some("test"), Some(AddressType::Legacy)).unwrap();
+	let txid = bitcoind.client.send_to_address(&new_address, Amount::from_sat(5000), None, None, None, None, None, None).unwrap();
+	tx_sync.register_tx(&txid, &new_address.script_pubkey());
+
+	// Check registered outputs are picked up when spent.
+	let second_address = bitcoind.client.get_new_address(Some("test"), Some(AddressType::Legacy)).unwrap();
+	let second_txid = bitcoind.client.send_to_address(&second_address, Amount::from_sat(5000), None, None, None, None, None, None).unwrap();
+	let second_tx = bitcoind.client.get_transaction(&second_txid, None).unwrap().transaction().unwrap();
+	let spent_outpoint = second_tx.input[0].previous_output;
+	let funding_tx = bitcoind.client.get_transaction(&spent_outpoint.txid, None).unwrap().transaction().unwrap();
+	let spent_output = &funding_tx.output[spent_outpoint.vout as usize];
+	tx_sync.register_output(WatchedOutput {
+		block_hash: None,
+		outpoint: OutPoint { txid: spent_outpoint.txid, index: spent_outpoint.vout as u16 },
+		script_pubkey: spent_output.script_pubkey.clone(),
+	});
+
+	tx_sync.sync(vec![&confirmable]).unwrap();
+
+	let events = std::mem::take(&mut *confirmable.events.lock().unwrap());
+	assert_eq!(events.len(), 0);
+	assert!(confirmable.confirmed_txs.lock().unwrap().is_empty());
+	assert!(confirmable.unconfirmed_txs.lock().unwrap().is_empty());
+
+	generate_blocks_and_wait(&bitcoind, &electrsd, 1);
+	tx_sync.sync(vec![&confirmable]).unwrap();
+
+	let events = std::mem::take(&mut *confirmable.events.lock().unwrap());
+	assert_eq!(events.len(), 3);
+	assert!(confirmable.confirmed_txs.lock().unwrap().contains_key(&txid));
+	assert!(confirmable.confirmed_txs.lock().unwrap().contains_key(&second_txid));
+	assert!(confirmable.unconfirmed_txs.lock().unwrap().is_empty());
+
+	// Check previously confirmed transactions are marked unconfirmed when they are reorged.
+	let best_block_hash = bitcoind.client.get_best_block_hash().unwrap();
+	bitcoind.client.invalidate_block(&best_block_hash).unwrap();
+
+	// We're getting back to the previous height with a new tip, but best block shouldn't change.
+	generate_blocks_and_wait(&bitcoind, &electrsd, 1);
+	assert_ne!(bitcoind.client.get_best_block_hash().unwrap(), best_block_hash);
+	tx_sync.sync(vec![&confirmable]).unwrap();
+	let events = std::mem::take(&mut *confirmable.events.lock().unwrap());
+	assert_eq!(events.len(), 0);
+
+	// Now we're surpassing previous height, getting new tip.
+	generate_blocks_and_wait(&bitcoind, &electrsd, 1);
+	assert_ne!(bitcoind.client.get_best_block_hash().unwrap(), best_block_hash);
+	tx_sync.sync(vec![&confirmable]).unwrap();
+
+	// Transactions still confirmed but under new tip.
+	assert!(confirmable.confirmed_txs.lock().unwrap().contains_key(&txid));
+	assert!(confirmable.confirmed_txs.lock().unwrap().contains_key(&second_txid));
+	assert!(confirmable.unconfirmed_txs.lock().unwrap().is_empty());
+
+	// Check we got unconfirmed, then reconfirmed in the meantime.
+	let events = std::mem::take(&mut *confirmable.events.lock().unwrap());
+	assert_eq!(events.len(), 5);
+
+	let mut unconfirmed_txids = HashSet::new();
+	for event in &events[0..2] {
+		match event {
+			TestConfirmableEvent::Unconfirmed(t) => { unconfirmed_txids.insert(*t); },
+			_ => panic!("Unexpected event"),
+		}
+	}
+	assert!(unconfirmed_txids.contains(&txid) && unconfirmed_txids.contains(&second_txid));
+
+	match events[2] {
+		TestConfirmableEvent::BestBlockUpdated(..) => {},
+		_ => panic!("Unexpected event"),
+	}
+
+	for event in &events[3..5] {
+		match event {
+			TestConfirmableEvent::Confirmed(t, _, _) => assert!(*t == txid || *t == second_txid),
+			_ => panic!("Unexpected event"),
+		}
+	}
+}
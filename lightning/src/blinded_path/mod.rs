@@ -9,6 +9,7 @@
 
 //! Creating blinded paths and related utilities live here.
 
+pub mod payment;
 pub(crate) mod utils;
 
 use bitcoin::hashes::{Hash, HashEngine};
@@ -18,6 +19,7 @@ use bitcoin::secp256k1::{self, PublicKey, Scalar, Secp256k1, SecretKey};
 use crate::sign::{EntropySource, NodeSigner, Recipient};
 use crate::onion_message::ControlTlvs;
 use crate::ln::msgs::DecodeError;
+use crate::offers::invoice::BlindedPayInfo;
 use crate::ln::onion_utils;
 use crate::util::chacha20poly1305rfc::{ChaChaPolyReadAdapter, ChaChaPolyWriteAdapter};
 use crate::util::ser::{FixedLengthReader, LengthReadableArgs, Readable, VecWriter, Writeable, Writer};
@@ -64,7 +66,7 @@ impl BlindedPath {
 	///
 	/// Errors if less than two hops are provided or if `node_pk`(s) are invalid.
 	//  TODO: make all payloads the same size with padding + add dummy hops
-	pub fn new_for_message<ES: EntropySource, T: secp256k1::Signing + secp256k1::Verification>
+	pub fn new_for_message<ES: EntropySource + ?Sized, T: secp256k1::Signing + secp256k1::Verification>
 		(node_pks: &[PublicKey], entropy_source: &ES, secp_ctx: &Secp256k1<T>) -> Result<Self, ()>
 	{
 		if node_pks.len() < 2 { return Err(()) }
@@ -79,6 +81,53 @@ impl BlindedPath {
 		})
 	}
 
+	/// Create a one-hop blinded path for a payment to `payee_node_id`, where `payee_node_id` is the
+	/// introduction node. Returns the [`BlindedPayInfo`] used by the payer for routing along with
+	/// the path itself.
+	///
+	/// Errors if `payee_node_id` is invalid.
+	pub fn one_hop_for_payment<ES: EntropySource + ?Sized, T: secp256k1::Signing + secp256k1::Verification>(
+		payee_node_id: PublicKey, payee_tlvs: payment::ReceiveTlvs, entropy_source: &ES,
+		secp_ctx: &Secp256k1<T>
+	) -> Result<(BlindedPayInfo, Self), ()> {
+		// This value is not considered in pathfinding for 1-hop blinded paths, as the payer is
+		// limited by the channel with the introduction node.
+		let htlc_maximum_msat = u64::max_value();
+		Self::new_for_payment(&[], payee_node_id, payee_tlvs, htlc_maximum_msat, entropy_source, secp_ctx)
+	}
+
+	/// Create a blinded path for a payment, to be forwarded along `intermediate_nodes`. The last
+	/// node, `payee_node_id`, will be the destination. Returns the [`BlindedPayInfo`] aggregating
+	/// the fees and constraints of every hop, for use by the payer for routing, along with the path
+	/// itself.
+	///
+	/// Errors if:
+	/// * a provided node id is invalid
+	/// * [`BlindedPayInfo`] calculation results in an integer overflow
+	/// * any unknown features are required in the provided [`ForwardTlvs`]
+	/// * `htlc_maximum_msat` is less than the aggregated HTLC minimum of the path
+	///
+	/// [`ForwardTlvs`]: payment::ForwardTlvs
+	//  TODO: make all payloads the same size with padding + add dummy hops
+	pub fn new_for_payment<ES: EntropySource + ?Sized, T: secp256k1::Signing + secp256k1::Verification>(
+		intermediate_nodes: &[payment::ForwardNode], payee_node_id: PublicKey,
+		payee_tlvs: payment::ReceiveTlvs, htlc_maximum_msat: u64, entropy_source: &ES,
+		secp_ctx: &Secp256k1<T>
+	) -> Result<(BlindedPayInfo, Self), ()> {
+		let blinding_secret_bytes = entropy_source.get_secure_random_bytes();
+		let blinding_secret = SecretKey::from_slice(&blinding_secret_bytes[..]).expect("RNG is busted");
+
+		let blinded_payinfo = payment::compute_payinfo(intermediate_nodes, &payee_tlvs, htlc_maximum_msat)?;
+		let blinded_path = BlindedPath {
+			introduction_node_id: intermediate_nodes.first().map_or(payee_node_id, |n| n.node_id),
+			blinding_point: PublicKey::from_secret_key(secp_ctx, &blinding_secret),
+			blinded_hops: payment::blinded_hops(
+				secp_ctx, intermediate_nodes, payee_node_id, &payee_tlvs, &blinding_secret
+			).map_err(|_| ())?,
+		};
+		Ok((blinded_payinfo, blinded_path))
+	}
+
 	// Advance the blinded onion message path by one hop, so make the second hop into the new
 	// introduction node.
 	pub(super) fn advance_message_path_by_one<NS: Deref, T: secp256k1::Signing + secp256k1::Verification>
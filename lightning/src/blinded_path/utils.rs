@@ -16,8 +16,11 @@ use bitcoin::secp256k1::{self, PublicKey, Secp256k1, SecretKey, Scalar};
 use bitcoin::secp256k1::ecdh::SharedSecret;
 
 use super::BlindedPath;
+use crate::io;
+use crate::ln::msgs::DecodeError;
 use crate::ln::onion_utils;
 use crate::onion_message::Destination;
+use crate::util::ser::Readable;
 
 use crate::prelude::*;
 
@@ -96,3 +99,16 @@ pub(crate) fn construct_keys_callback<T: secp256k1::Signing + secp256k1::Verific
 	}
 	Ok(())
 }
+
+/// Reads padding to the end, ignoring what's read.
+pub(crate) struct Padding {}
+impl Readable for Padding {
+	#[inline]
+	fn read<R: io::Read>(reader: &mut R) -> Result<Self, DecodeError> {
+		loop {
+			let mut buf = [0; 8192];
+			if reader.read(&mut buf[..])? == 0 { break; }
+		}
+		Ok(Self {})
+	}
+}
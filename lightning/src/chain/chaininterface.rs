@@ -11,7 +11,8 @@
 //! blockchain.
 //!
 //! Includes traits for monitoring and receiving notifications of new blocks and block
-//! disconnections, transaction broadcasting, and feerate information requests.
+//! disconnections, transaction broadcasting, feerate information requests, and fee-bumping of
+//! on-chain claims.
 
 use core::{cmp, ops::Deref};
 use core::convert::TryInto;
@@ -115,9 +116,127 @@ impl<F: Deref> LowerBoundedFeeEstimator<F> where F::Target: FeeEstimator {
 	}
 }
 
+/// A trait deciding how quickly the feerate of claims of on-chain funds escalates as their
+/// deadline approaches, allowing to trade off fees spent against the risk of a claim failing to
+/// confirm in time.
+///
+/// Claims are initially broadcast at a feerate given by the [`FeeEstimator`]. If a claim hasn't
+/// confirmed after [`blocks_until_bump`], it is fee-bumped to the greater of the
+/// [`FeeEstimator`]'s current estimate and [`bumped_feerate_sat_per_1000_weight`]. In any case, the
+/// feerate will be raised further as required to replace the previous claim as per BIP 125.
+///
+/// Strategies are set via [`ChainMonitor::set_fee_bump_strategy`] and default to
+/// [`DefaultFeeBumpStrategy`].
+///
+/// Note that all of the functions implemented here *must* be reentrant-safe (obviously - they're
+/// called from inside the library in response to chain events, P2P events, or timer events).
+///
+/// [`blocks_until_bump`]: Self::blocks_until_bump
+/// [`bumped_feerate_sat_per_1000_weight`]: Self::bumped_feerate_sat_per_1000_weight
+/// [`ChainMonitor::set_fee_bump_strategy`]: crate::chain::chainmonitor::ChainMonitor::set_fee_bump_strategy
+pub trait FeeBumpStrategy {
+	/// Returns the number of blocks after which a claim which must confirm by `deadline_height`
+	/// should be fee-bumped if it hasn't confirmed yet.
+	///
+	/// Must be at least 1.
+	fn blocks_until_bump(&self, current_height: u32, deadline_height: u32) -> u32;
+	/// Returns the feerate, in satoshis per 1000 weight units, to bump a claim which previously
+	/// used `previous_feerate_sat_per_1000_weight` to, given it must confirm by `deadline_height`
+	/// and is claiming `value_at_stake_sat`.
+	///
+	/// If the returned feerate is not above the previous one, the claim will only be rebroadcast.
+	/// Bumps which would have the claim's fee exceed the value it claims are abandoned.
+	fn bumped_feerate_sat_per_1000_weight(
+		&self, previous_feerate_sat_per_1000_weight: u64, current_height: u32, deadline_height: u32,
+		value_at_stake_sat: u64,
+	) -> u64;
+}
+
+/// Returns `low_frequency_interval` blocks until the next bump, or `middle_frequency_interval` or
+/// `high_frequency_interval` blocks as the deadline gets closer.
+fn bump_interval(
+	current_height: u32, deadline_height: u32, low_frequency_interval: u32,
+	middle_frequency_interval: u32, high_frequency_interval: u32,
+) -> u32 {
+	if deadline_height <= current_height + middle_frequency_interval {
+		high_frequency_interval
+	} else if deadline_height - current_height <= low_frequency_interval {
+		middle_frequency_interval
+	} else {
+		low_frequency_interval
+	}
+}
+
+/// The default [`FeeBumpStrategy`], bumping claims by 25% every 15 blocks, or every 3 blocks once
+/// their deadline is within 15 blocks, and every block once it's within 3 blocks.
+pub struct DefaultFeeBumpStrategy;
+
+impl FeeBumpStrategy for DefaultFeeBumpStrategy {
+	fn blocks_until_bump(&self, current_height: u32, deadline_height: u32) -> u32 {
+		bump_interval(current_height, deadline_height, 15, 3, 1)
+	}
+
+	fn bumped_feerate_sat_per_1000_weight(
+		&self, previous_feerate_sat_per_1000_weight: u64, _current_height: u32, _deadline_height: u32,
+		_value_at_stake_sat: u64,
+	) -> u64 {
+		// Increase the previous feerate by 25% (because that's a nice number)
+		previous_feerate_sat_per_1000_weight + previous_feerate_sat_per_1000_weight / 4
+	}
+}
+
+/// A [`FeeBumpStrategy`] favoring timely confirmation over fees, bumping claims by 50% every 6
+/// blocks, or every 2 blocks once their deadline is within 6 blocks, and every block once it's
+/// within 2 blocks, at which point the feerate is doubled with each bump.
+pub struct AggressiveFeeBumpStrategy;
+
+impl FeeBumpStrategy for AggressiveFeeBumpStrategy {
+	fn blocks_until_bump(&self, current_height: u32, deadline_height: u32) -> u32 {
+		bump_interval(current_height, deadline_height, 6, 2, 1)
+	}
+
+	fn bumped_feerate_sat_per_1000_weight(
+		&self, previous_feerate_sat_per_1000_weight: u64, current_height: u32, deadline_height: u32,
+		_value_at_stake_sat: u64,
+	) -> u64 {
+		if deadline_height <= current_height + 2 {
+			previous_feerate_sat_per_1000_weight * 2
+		} else {
+			previous_feerate_sat_per_1000_weight + previous_feerate_sat_per_1000_weight / 2
+		}
+	}
+}
+
+/// A [`FeeBumpStrategy`] favoring lower fees over timely confirmation, bumping claims by 10% every
+/// 30 blocks, or every 6 blocks once their deadline is within 30 blocks, and every block once
+/// it's within 6 blocks, at which point claims are bumped by 25% as done by the
+/// [`DefaultFeeBumpStrategy`].
+///
+/// Note that this increases the risk of claims not confirming before the counterparty is able to
+/// claim the funds instead.
+pub struct ConservativeFeeBumpStrategy;
+
+impl FeeBumpStrategy for ConservativeFeeBumpStrategy {
+	fn blocks_until_bump(&self, current_height: u32, deadline_height: u32) -> u32 {
+		bump_interval(current_height, deadline_height, 30, 6, 1)
+	}
+
+	fn bumped_feerate_sat_per_1000_weight(
+		&self, previous_feerate_sat_per_1000_weight: u64, current_height: u32, deadline_height: u32,
+		_value_at_stake_sat: u64,
+	) -> u64 {
+		if deadline_height <= current_height + 6 {
+			previous_feerate_sat_per_1000_weight + previous_feerate_sat_per_1000_weight / 4
+		} else {
+			previous_feerate_sat_per_1000_weight + previous_feerate_sat_per_1000_weight / 10
+		}
+	}
+}
+
 #[cfg(test)]
 mod tests {
 	use super::{FEERATE_FLOOR_SATS_PER_KW, LowerBoundedFeeEstimator, ConfirmationTarget, FeeEstimator};
+	use super::{AggressiveFeeBumpStrategy, ConservativeFeeBumpStrategy, DefaultFeeBumpStrategy, FeeBumpStrategy};
 
 	struct TestFeeEstimator {
 		sat_per_kw: u32,
@@ -146,4 +265,34 @@ mod tests {
 
 		assert_eq!(fee_estimator.bounded_sat_per_1000_weight(ConfirmationTarget::Background), sat_per_kw);
 	}
+
+	#[test]
+	fn test_fee_bump_strategies() {
+		let strategies: [&dyn FeeBumpStrategy; 3] =
+			[&DefaultFeeBumpStrategy, &AggressiveFeeBumpStrategy, &ConservativeFeeBumpStrategy];
+		for strategy in strategies.iter() {
+			// Bumps only ever get more frequent and larger as the deadline approaches.
+			let mut prev_interval = u32::max_value();
+			let mut prev_feerate = 0;
+			for deadline_height in (100..=200).rev() {
+				let interval = strategy.blocks_until_bump(100, deadline_height);
+				let feerate = strategy.bumped_feerate_sat_per_1000_weight(1000, 100, deadline_height, 10_000);
+				assert!(interval >= 1 && interval <= prev_interval);
+				assert!(feerate > 1000 && feerate >= prev_feerate);
+				prev_interval = interval;
+				prev_feerate = feerate;
+			}
+		}
+
+		// The default strategy matches our historical behavior.
+		assert_eq!(DefaultFeeBumpStrategy.blocks_until_bump(100, 200), 15);
+		assert_eq!(DefaultFeeBumpStrategy.blocks_until_bump(100, 115), 3);
+		assert_eq!(DefaultFeeBumpStrategy.blocks_until_bump(100, 103), 1);
+		assert_eq!(DefaultFeeBumpStrategy.bumped_feerate_sat_per_1000_weight(1000, 100, 200, 10_000), 1250);
+
+		// The aggressive strategy bumps more often and by more than the conservative one.
+		assert!(AggressiveFeeBumpStrategy.blocks_until_bump(100, 200) < ConservativeFeeBumpStrategy.blocks_until_bump(100, 200));
+		assert!(AggressiveFeeBumpStrategy.bumped_feerate_sat_per_1000_weight(1000, 100, 200, 10_000) >
+			ConservativeFeeBumpStrategy.bumped_feerate_sat_per_1000_weight(1000, 100, 200, 10_000));
+	}
 }
@@ -24,16 +24,21 @@
 //! servicing [`ChannelMonitor`] updates from the client.
 
 use bitcoin::blockdata::block::BlockHeader;
+use bitcoin::blockdata::constants::WITNESS_SCALE_FACTOR;
+use bitcoin::blockdata::script::Script;
+use bitcoin::blockdata::transaction::{Transaction, TxIn, TxOut};
 use bitcoin::hash_types::{Txid, BlockHash};
+use bitcoin::{PackedLockTime, Sequence, Witness};
 
 use crate::chain;
-use crate::chain::{ChannelMonitorUpdateStatus, Filter, WatchedOutput};
-use crate::chain::chaininterface::{BroadcasterInterface, FeeEstimator};
+use crate::chain::{ChannelMonitorUpdateStatus, ClaimId, Filter, WatchedOutput};
+use crate::chain::chaininterface::{BroadcasterInterface, ConfirmationTarget, DefaultFeeBumpStrategy, FeeBumpStrategy, FeeEstimator, LowerBoundedFeeEstimator, MIN_RELAY_FEE_SAT_PER_1000_WEIGHT};
 use crate::chain::channelmonitor::{ChannelMonitor, ChannelMonitorUpdate, Balance, MonitorEvent, TransactionOutputs, LATENCY_GRACE_PERIOD_BLOCKS};
+use crate::chain::onchaintx::{AggregableClaim, AggregatedClaimTx};
 use crate::chain::transaction::{OutPoint, TransactionData};
 use crate::sign::WriteableEcdsaChannelSigner;
 use crate::events;
-use crate::events::{Event, EventHandler};
+use crate::events::{Event, EventHandler, ReplayEvent};
 use crate::util::atomic_counter::AtomicCounter;
 use crate::util::logger::Logger;
 use crate::util::errors::APIError;
@@ -42,8 +47,11 @@ use crate::ln::channelmanager::ChannelDetails;
 
 use crate::prelude::*;
 use crate::sync::{RwLock, RwLockReadGuard, Mutex, MutexGuard};
+use core::cmp;
 use core::ops::Deref;
+use alloc::sync::Arc;
 use core::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use bitcoin::hashes::hex::ToHex;
 use bitcoin::secp256k1::PublicKey;
 
 #[derive(Clone, Copy, Hash, PartialEq, Eq)]
@@ -194,6 +202,13 @@ impl<ChannelSigner: WriteableEcdsaChannelSigner> MonitorHolder<ChannelSigner> {
 	}
 }
 
+/// A transaction built by a [`ChainMonitor`] aggregating claims across channels.
+struct AggregatedClaims {
+	/// The aggregated claims, along with the funding outpoint of their channel.
+	claims: Vec<(OutPoint, ClaimId)>,
+	tx: AggregatedClaimTx,
+}
+
 /// A read-only reference to a current ChannelMonitor.
 ///
 /// Note that this holds a mutex in [`ChainMonitor`] and may block other events until it is
@@ -223,9 +238,13 @@ impl<ChannelSigner: WriteableEcdsaChannelSigner> Deref for LockedChannelMonitor<
 /// broadcasting fails. We recommend invoking this every 30 seconds, or lower if running in an
 /// environment with spotty connections, like on mobile.
 ///
+/// If enabled via [`set_claim_aggregation`], claims which aren't time-sensitive are aggregated
+/// across channels, saving on fees when closing many channels at once.
+///
 /// [`ChannelManager`]: crate::ln::channelmanager::ChannelManager
 /// [module-level documentation]: crate::chain::chainmonitor
 /// [`rebroadcast_pending_claims`]: Self::rebroadcast_pending_claims
+/// [`set_claim_aggregation`]: Self::set_claim_aggregation
 pub struct ChainMonitor<ChannelSigner: WriteableEcdsaChannelSigner, C: Deref, T: Deref, F: Deref, L: Deref, P: Deref>
 	where C::Target: chain::Filter,
         T::Target: BroadcasterInterface,
@@ -249,6 +268,16 @@ pub struct ChainMonitor<ChannelSigner: WriteableEcdsaChannelSigner, C: Deref, T:
 	/// The best block height seen, used as a proxy for the passage of time.
 	highest_chain_height: AtomicUsize,
 
+	/// Whether claims which aren't time-sensitive are aggregated across channels.
+	aggregate_claims: AtomicBool,
+	/// The transactions currently pending confirmation which aggregate claims across channels.
+	///
+	/// Each claim's latest transaction is also tracked by its [`ChannelMonitor`], from which this is
+	/// rebuilt upon restart.
+	aggregated_claim_txn: Mutex<Vec<AggregatedClaims>>,
+	/// Decides how quickly the feerate of claims escalates as their deadline approaches.
+	fee_bump_strategy: Mutex<Arc<dyn FeeBumpStrategy + Send + Sync>>,
+
 	event_notifier: Notifier,
 }
 
@@ -286,38 +315,8 @@ where C::Target: chain::Filter,
 		}
 
 		for (funding_outpoint, monitor_state) in monitor_states.iter() {
-			let monitor = &monitor_state.monitor;
-			let mut txn_outputs;
-			{
-				txn_outputs = process(monitor, txdata);
-				let update_id = MonitorUpdateId {
-					contents: UpdateOrigin::ChainSync(self.sync_persistence_id.get_increment()),
-				};
-				let mut pending_monitor_updates = monitor_state.pending_monitor_updates.lock().unwrap();
-				if let Some(height) = best_height {
-					if !monitor_state.has_pending_chainsync_updates(&pending_monitor_updates) {
-						// If there are not ChainSync persists awaiting completion, go ahead and
-						// set last_chain_persist_height here - we wouldn't want the first
-						// InProgress to always immediately be considered "overly delayed".
-						monitor_state.last_chain_persist_height.store(height as usize, Ordering::Release);
-					}
-				}
-
-				log_trace!(self.logger, "Syncing Channel Monitor for channel {}", log_funding_info!(monitor));
-				match self.persister.update_persisted_channel(*funding_outpoint, None, monitor, update_id) {
-					ChannelMonitorUpdateStatus::Completed =>
-						log_trace!(self.logger, "Finished syncing Channel Monitor for channel {}", log_funding_info!(monitor)),
-					ChannelMonitorUpdateStatus::PermanentFailure => {
-						monitor_state.channel_perm_failed.store(true, Ordering::Release);
-						self.pending_monitor_events.lock().unwrap().push((*funding_outpoint, vec![MonitorEvent::UpdateFailed(*funding_outpoint)], monitor.get_counterparty_node_id()));
-						self.event_notifier.notify();
-					},
-					ChannelMonitorUpdateStatus::InProgress => {
-						log_debug!(self.logger, "Channel Monitor sync for channel {} in progress, holding events until completion!", log_funding_info!(monitor));
-						pending_monitor_updates.push(update_id);
-					},
-				}
-			}
+			let mut txn_outputs = process(&monitor_state.monitor, txdata);
+			self.persist_chain_sync(*funding_outpoint, monitor_state, best_height);
 
 			// Register any new outputs with the chain source for filtering, storing any dependent
 			// transactions from within the block that previously had not been included in txdata.
@@ -336,6 +335,240 @@ where C::Target: chain::Filter,
 				}
 			}
 		}
+
+		self.aggregate_claims(&monitor_states, false);
+	}
+
+	/// Persists the given monitor after it changed outside of a [`ChannelMonitorUpdate`], e.g., due
+	/// to chain data. Calls which represent a new blockchain tip height should set `best_height`.
+	fn persist_chain_sync(&self, funding_outpoint: OutPoint, monitor_state: &MonitorHolder<ChannelSigner>, best_height: Option<u32>) {
+		let monitor = &monitor_state.monitor;
+		let update_id = MonitorUpdateId {
+			contents: UpdateOrigin::ChainSync(self.sync_persistence_id.get_increment()),
+		};
+		let mut pending_monitor_updates = monitor_state.pending_monitor_updates.lock().unwrap();
+		if let Some(height) = best_height {
+			if !monitor_state.has_pending_chainsync_updates(&pending_monitor_updates) {
+				// If there are not ChainSync persists awaiting completion, go ahead and
+				// set last_chain_persist_height here - we wouldn't want the first
+				// InProgress to always immediately be considered "overly delayed".
+				monitor_state.last_chain_persist_height.store(height as usize, Ordering::Release);
+			}
+		}
+
+		log_trace!(self.logger, "Syncing Channel Monitor for channel {}", log_funding_info!(monitor));
+		match self.persister.update_persisted_channel(funding_outpoint, None, monitor, update_id) {
+			ChannelMonitorUpdateStatus::Completed =>
+				log_trace!(self.logger, "Finished syncing Channel Monitor for channel {}", log_funding_info!(monitor)),
+			ChannelMonitorUpdateStatus::PermanentFailure => {
+				monitor_state.channel_perm_failed.store(true, Ordering::Release);
+				self.pending_monitor_events.lock().unwrap().push((funding_outpoint, vec![MonitorEvent::UpdateFailed(funding_outpoint)], monitor.get_counterparty_node_id()));
+				self.event_notifier.notify();
+			},
+			ChannelMonitorUpdateStatus::InProgress => {
+				log_debug!(self.logger, "Channel Monitor sync for channel {} in progress, holding events until completion!", log_funding_info!(monitor));
+				pending_monitor_updates.push(update_id);
+			},
+		}
+	}
+
+	/// Builds and broadcasts transactions aggregating the claims which the given monitors left to
+	/// us. Claims are only aggregated with those of compatible types, i.e., those we'd also
+	/// aggregate within a single channel. If nothing changed, the previous transactions are only
+	/// broadcast again if `rebroadcast` is set.
+	///
+	/// This is only done upon chain events and calls to [`Self::rebroadcast_pending_claims`], such
+	/// that claims are aggregated as they come up without rebuilding the transactions on every
+	/// channel update.
+	fn aggregate_claims(&self, monitors: &HashMap<OutPoint, MonitorHolder<ChannelSigner>>, rebroadcast: bool) {
+		if !self.aggregate_claims.load(Ordering::Acquire) {
+			return;
+		}
+		let mut claims = Vec::new();
+		for (funding_outpoint, monitor_state) in monitors.iter() {
+			for claim in monitor_state.monitor.get_aggregable_claims() {
+				claims.push((*funding_outpoint, claim));
+			}
+		}
+
+		let mut aggregated_claim_txn = self.aggregated_claim_txn.lock().unwrap();
+		// Forget about any transactions whose claims have all been satisfied.
+		aggregated_claim_txn.retain(|aggregated_tx| aggregated_tx.claims.iter().any(|(funding_outpoint, claim_id)|
+			claims.iter().any(|(outpoint, claim)| outpoint == funding_outpoint && claim.claim_id == *claim_id)));
+
+		let mut claim_groups: Vec<Vec<(OutPoint, AggregableClaim)>> = Vec::new();
+		for claim in claims {
+			match claim_groups.iter_mut().find(|group| group[0].1.package.can_aggregate_with(&claim.1.package)) {
+				Some(group) => group.push(claim),
+				None => claim_groups.push(vec![claim]),
+			}
+		}
+		for claims in claim_groups {
+			self.broadcast_aggregated_claim(monitors, claims, &mut aggregated_claim_txn, rebroadcast);
+		}
+	}
+
+	fn broadcast_aggregated_claim(
+		&self, monitors: &HashMap<OutPoint, MonitorHolder<ChannelSigner>>,
+		mut claims: Vec<(OutPoint, AggregableClaim)>, aggregated_claim_txn: &mut Vec<AggregatedClaims>,
+		rebroadcast: bool,
+	) {
+		// Any previous transactions spending the outputs of the claims will be replaced.
+		let replaced_txn = aggregated_claim_txn.iter().enumerate()
+			.filter(|(_, aggregated_tx)| aggregated_tx.claims.iter().any(|(funding_outpoint, claim_id)|
+				claims.iter().any(|(outpoint, claim)| outpoint == funding_outpoint && claim.claim_id == *claim_id)))
+			.map(|(idx, _)| idx)
+			.collect::<Vec<_>>();
+
+		let fee_estimator = LowerBoundedFeeEstimator::new(&*self.fee_estimator);
+		let previous_feerate = claims.iter().map(|(_, claim)| claim.package.previous_feerate()).max().unwrap_or(0);
+		let mut feerate = cmp::max(
+			fee_estimator.bounded_sat_per_1000_weight(ConfirmationTarget::Normal) as u64, previous_feerate
+		);
+		if feerate == previous_feerate && claims.iter().any(|(_, claim)| claim.needs_bump) {
+			// Bump the previous feerate as done for claims within a single channel.
+			let deadline_height = claims.iter().map(|(_, claim)| claim.package.timelock()).min().unwrap_or(0);
+			let value_at_stake_sat = claims.iter().map(|(_, claim)| claim.package.package_amount()).sum();
+			feerate = cmp::max(feerate, self.fee_bump_strategy.lock().unwrap().bumped_feerate_sat_per_1000_weight(
+				previous_feerate, self.highest_chain_height.load(Ordering::Acquire) as u32, deadline_height,
+				value_at_stake_sat,
+			));
+		}
+
+		if let [replaced_idx] = replaced_txn[..] {
+			let replaced_tx = &aggregated_claim_txn[replaced_idx];
+			if replaced_tx.tx.feerate == feerate && replaced_tx.claims.len() == claims.len() &&
+				claims.iter().all(|(outpoint, claim)| replaced_tx.claims.contains(&(*outpoint, claim.claim_id)))
+			{
+				if rebroadcast {
+					log_info!(self.logger, "Rebroadcasting aggregated claim transaction {}", replaced_tx.tx.tx.txid());
+					self.broadcaster.broadcast_transactions(&[&replaced_tx.tx.tx]);
+				}
+				return;
+			}
+		}
+		let replaced_fee: u64 = replaced_txn.iter().map(|idx| aggregated_claim_txn[*idx].tx.fee).sum();
+
+		// Claimed funds are sent back to each channel's destination script, with every script
+		// paying for its share of the transaction's weight.
+		let (outputs, fee) = loop {
+			if claims.is_empty() {
+				return;
+			}
+			let mut outputs: Vec<(Script, u64, u64)> = Vec::new();
+			for (_, claim) in claims.iter() {
+				let amount = claim.package.package_amount();
+				let weight = claim.package.inputs_weight() as u64;
+				match outputs.iter_mut().find(|(script, _, _)| *script == claim.destination_script) {
+					Some((_, output_amount, output_weight)) => {
+						*output_amount += amount;
+						*output_weight += weight;
+					},
+					None => {
+						// value: 8 bytes ; var_int: 1 byte ; pk_script: `destination_script.len()`
+						let output_weight = (8 + 1 + claim.destination_script.len() as u64) * WITNESS_SCALE_FACTOR as u64;
+						outputs.push((claim.destination_script.clone(), amount, weight + output_weight));
+					},
+				}
+			}
+			let claims_weight: u64 = outputs.iter().map(|(_, _, weight)| *weight).sum();
+			// version: 4 bytes ; count_tx_in: 1 byte ; count_tx_out: 1 byte ; lock_time: 4 bytes ;
+			// segwit marker & flag: 2 WU
+			let weight = 10 * WITNESS_SCALE_FACTOR as u64 + 2 + claims_weight;
+			let mut fee = feerate * weight / 1000;
+			if !replaced_txn.is_empty() {
+				// BIP 125 requires us to pay for the replaced transactions and our own bandwidth.
+				let min_replacement_fee = replaced_fee + MIN_RELAY_FEE_SAT_PER_1000_WEIGHT * weight / 1000;
+				if fee < min_replacement_fee {
+					fee = min_replacement_fee;
+					feerate = cmp::max(feerate, fee * 1000 / weight);
+				}
+			}
+
+			// Each output pays for the share of the fee its claims account for, with the first
+			// output also covering any remainder due to rounding.
+			let fee_shares = outputs.iter().skip(1)
+				.map(|(_, _, output_weight)| fee * *output_weight / claims_weight)
+				.collect::<Vec<_>>();
+			let first_fee_share = fee - fee_shares.iter().sum::<u64>();
+			let mut dust_scripts = Vec::new();
+			let fee_shares = core::iter::once(first_fee_share).chain(fee_shares);
+			for ((script, amount, _), fee_share) in outputs.iter_mut().zip(fee_shares) {
+				if *amount <= fee_share || *amount - fee_share < script.dust_value().to_sat() {
+					dust_scripts.push(script.clone());
+				}
+				*amount = amount.saturating_sub(fee_share);
+			}
+			if dust_scripts.is_empty() {
+				break (outputs, fee);
+			}
+			log_warn!(self.logger, "Not aggregating claims whose funds wouldn't cover their share of fees at {} sat/kW", feerate);
+			claims.retain(|(_, claim)| !dust_scripts.contains(&claim.destination_script));
+		};
+
+		let mut tx = Transaction {
+			version: 2,
+			lock_time: PackedLockTime(self.highest_chain_height.load(Ordering::Acquire) as u32),
+			input: claims.iter().flat_map(|(_, claim)| claim.package.outpoints()).map(|outpoint| TxIn {
+				previous_output: *outpoint,
+				script_sig: Script::new(),
+				sequence: Sequence::ENABLE_RBF_NO_LOCKTIME,
+				witness: Witness::new(),
+			}).collect(),
+			output: outputs.into_iter().map(|(script_pubkey, value, _)| TxOut { script_pubkey, value }).collect(),
+		};
+		for (funding_outpoint, claim) in claims.iter() {
+			let signed = monitors.get(funding_outpoint)
+				.map(|monitor_state| monitor_state.monitor.sign_aggregated_claim(&claim.claim_id, &mut tx, feerate))
+				.unwrap_or(false);
+			if !signed {
+				log_error!(self.logger, "Failed to sign aggregated claim transaction for channel {}", funding_outpoint.to_channel_id().to_hex());
+				return;
+			}
+		}
+
+		log_info!(self.logger, "Broadcasting transaction {} aggregating {} claims across channels at {} sat/kW",
+			tx.txid(), claims.len(), feerate);
+		self.broadcaster.broadcast_transactions(&[&tx]);
+		for idx in replaced_txn.into_iter().rev() {
+			aggregated_claim_txn.remove(idx);
+		}
+
+		// Track the transaction in each claim's monitor, such that we can rebuild our view upon
+		// restart and a claim made by the monitor on its own will replace it.
+		let aggregated_tx = AggregatedClaimTx { tx, feerate, fee };
+		let mut updated_monitors: Vec<OutPoint> = Vec::new();
+		for (funding_outpoint, claim) in claims.iter() {
+			if let Some(monitor_state) = monitors.get(funding_outpoint) {
+				monitor_state.monitor.track_aggregated_claim(&claim.claim_id, aggregated_tx.clone());
+				if !updated_monitors.contains(funding_outpoint) {
+					updated_monitors.push(*funding_outpoint);
+				}
+			}
+		}
+		for funding_outpoint in updated_monitors {
+			self.persist_chain_sync(funding_outpoint, &monitors[&funding_outpoint], None);
+		}
+		aggregated_claim_txn.push(AggregatedClaims {
+			claims: claims.into_iter().map(|(funding_outpoint, claim)| (funding_outpoint, claim.claim_id)).collect(),
+			tx: aggregated_tx,
+		});
+	}
+
+	/// Picks up the aggregated claim transactions tracked by the given monitor, e.g., when it is
+	/// loaded upon restart, merging claims of the same transaction across monitors.
+	fn load_aggregated_claims(&self, funding_outpoint: OutPoint, monitor: &ChannelMonitor<ChannelSigner>) {
+		let mut aggregated_claim_txn = self.aggregated_claim_txn.lock().unwrap();
+		for (claim_id, aggregated_tx) in monitor.get_aggregated_claim_txn() {
+			let txid = aggregated_tx.tx.txid();
+			match aggregated_claim_txn.iter_mut().find(|aggregated_claims| aggregated_claims.tx.tx.txid() == txid) {
+				Some(aggregated_claims) => aggregated_claims.claims.push((funding_outpoint, claim_id)),
+				None => aggregated_claim_txn.push(AggregatedClaims {
+					claims: vec![(funding_outpoint, claim_id)],
+					tx: aggregated_tx,
+				}),
+			}
+		}
 	}
 
 	/// Creates a new `ChainMonitor` used to watch on-chain activity pertaining to channels.
@@ -356,10 +589,52 @@ where C::Target: chain::Filter,
 			persister,
 			pending_monitor_events: Mutex::new(Vec::new()),
 			highest_chain_height: AtomicUsize::new(0),
+			aggregate_claims: AtomicBool::new(false),
+			aggregated_claim_txn: Mutex::new(Vec::new()),
+			fee_bump_strategy: Mutex::new(Arc::new(DefaultFeeBumpStrategy)),
 			event_notifier: Notifier::new(),
 		}
 	}
 
+	/// Sets whether claims of on-chain funds which aren't time-sensitive should be aggregated
+	/// across channels, rather than each [`ChannelMonitor`] claiming them on its own. This saves
+	/// on fees when many channels are closed at once, e.g., if a peer with many channels
+	/// misbehaves.
+	///
+	/// Only claims which a [`ChannelMonitor`] would aggregate within its own channel are
+	/// considered, e.g., claims of HTLCs we know the preimage for, while claims of revoked outputs
+	/// are never aggregated as our counterparty may race us to spend them. Once the expiration of
+	/// their timelock becomes imminent, claims are again claimed by their [`ChannelMonitor`]
+	/// separately, replacing any aggregated transaction which failed to confirm in time. Note that
+	/// outputs which are ours after a delay are not claimed by the [`ChannelMonitor`] in the first
+	/// place but provided via [`Event::SpendableOutputs`], and thus may already be swept in
+	/// batches, e.g., by an [`OutputSweeper`].
+	///
+	/// Claims are (re)aggregated upon new blocks and calls to [`Self::rebroadcast_pending_claims`].
+	///
+	/// This is disabled by default and needs to be set on every startup.
+	///
+	/// [`OutputSweeper`]: crate::util::sweep::OutputSweeper
+	pub fn set_claim_aggregation(&self, enabled: bool) {
+		let monitors = self.monitors.read().unwrap();
+		self.aggregate_claims.store(enabled, Ordering::Release);
+		for monitor_state in monitors.values() {
+			monitor_state.monitor.set_aggregate_claims_across_channels(enabled);
+		}
+	}
+
+	/// Sets the [`FeeBumpStrategy`] deciding how quickly the feerate of claims of on-chain funds
+	/// escalates as their deadline approaches, for all current and future [`ChannelMonitor`]s.
+	///
+	/// Defaults to the [`DefaultFeeBumpStrategy`] and needs to be set on every startup.
+	pub fn set_fee_bump_strategy(&self, fee_bump_strategy: Arc<dyn FeeBumpStrategy + Send + Sync>) {
+		let monitors = self.monitors.read().unwrap();
+		for monitor_state in monitors.values() {
+			monitor_state.monitor.set_fee_bump_strategy(Arc::clone(&fee_bump_strategy));
+		}
+		*self.fee_bump_strategy.lock().unwrap() = fee_bump_strategy;
+	}
+
 	/// Gets the balances in the contained [`ChannelMonitor`]s which are claimable on-chain or
 	/// claims which are awaiting confirmation.
 	///
@@ -506,7 +781,10 @@ where C::Target: chain::Filter,
 	pub fn get_and_clear_pending_events(&self) -> Vec<events::Event> {
 		use crate::events::EventsProvider;
 		let events = core::cell::RefCell::new(Vec::new());
-		let event_handler = |event: events::Event| events.borrow_mut().push(event);
+		let event_handler = |event: events::Event| {
+			events.borrow_mut().push(event);
+			Ok(())
+		};
 		self.process_pending_events(&event_handler);
 		events.into_inner()
 	}
@@ -514,10 +792,13 @@ where C::Target: chain::Filter,
 	/// Processes any events asynchronously in the order they were generated since the last call
 	/// using the given event handler.
 	///
+	/// If the handler returns a [`ReplayEvent`] error, the failed event and any events queued after
+	/// it for the same channel are kept and will be passed to the handler again on the next call.
+	///
 	/// See the trait-level documentation of [`EventsProvider`] for requirements.
 	///
 	/// [`EventsProvider`]: crate::events::EventsProvider
-	pub async fn process_pending_events_async<Future: core::future::Future, H: Fn(Event) -> Future>(
+	pub async fn process_pending_events_async<Future: core::future::Future<Output = Result<(), ReplayEvent>>, H: Fn(Event) -> Future>(
 		&self, handler: H
 	) {
 		// Sadly we can't hold the monitors read lock through an async call. Thus we have to do a
@@ -554,6 +835,7 @@ where C::Target: chain::Filter,
 				&*self.broadcaster, &*self.fee_estimator, &*self.logger
 			)
 		}
+		self.aggregate_claims(&monitors, true);
 	}
 }
 
@@ -581,6 +863,7 @@ where
 			monitor_state.monitor.block_disconnected(
 				header, height, &*self.broadcaster, &*self.fee_estimator, &*self.logger);
 		}
+		self.aggregate_claims(&monitor_states, false);
 	}
 }
 
@@ -607,6 +890,7 @@ where
 		for monitor_state in monitor_states.values() {
 			monitor_state.monitor.transaction_unconfirmed(txid, &*self.broadcaster, &*self.fee_estimator, &*self.logger);
 		}
+		self.aggregate_claims(&monitor_states, false);
 	}
 
 	fn best_block_updated(&self, header: &BlockHeader, height: u32) {
@@ -676,6 +960,9 @@ where C::Target: chain::Filter,
 		if let Some(ref chain_source) = self.chain_source {
 			monitor.load_outputs_to_watch(chain_source);
 		}
+		monitor.set_aggregate_claims_across_channels(self.aggregate_claims.load(Ordering::Acquire));
+		monitor.set_fee_bump_strategy(Arc::clone(&self.fee_bump_strategy.lock().unwrap()));
+		self.load_aggregated_claims(funding_outpoint, &monitor);
 		entry.insert(MonitorHolder {
 			monitor,
 			pending_monitor_updates: Mutex::new(pending_monitor_updates),
@@ -805,16 +1092,20 @@ impl<ChannelSigner: WriteableEcdsaChannelSigner, C: Deref, T: Deref, F: Deref, L
 
 #[cfg(test)]
 mod tests {
-	use crate::{check_added_monitors, check_closed_broadcast, check_closed_event};
+	use crate::{check_added_monitors, check_closed_broadcast, check_closed_event, check_spends};
 	use crate::{expect_payment_sent, expect_payment_claimed, expect_payment_sent_without_paths, expect_payment_path_successful, get_event_msg};
 	use crate::{get_htlc_update_msgs, get_local_commitment_txn, get_revoke_commit_msgs, get_route_and_payment_hash, unwrap_send_err};
 	use crate::chain::{ChannelMonitorUpdateStatus, Confirm, Watch};
-	use crate::chain::channelmonitor::LATENCY_GRACE_PERIOD_BLOCKS;
-	use crate::events::{Event, ClosureReason, MessageSendEvent, MessageSendEventsProvider};
+	use crate::chain::chaininterface::{AggressiveFeeBumpStrategy, MIN_RELAY_FEE_SAT_PER_1000_WEIGHT};
+	use crate::chain::channelmonitor::{ANTI_REORG_DELAY, CLTV_SHARED_CLAIM_BUFFER, LATENCY_GRACE_PERIOD_BLOCKS};
+	use crate::events::{Event, EventsProvider, ClosureReason, MessageSendEvent, MessageSendEventsProvider, ReplayEvent};
 	use crate::ln::channelmanager::{PaymentSendFailure, PaymentId, RecipientOnionFields};
 	use crate::ln::functional_test_utils::*;
 	use crate::ln::msgs::ChannelMessageHandler;
 	use crate::util::errors::APIError;
+	use crate::sync::Arc;
+
+	use bitcoin::blockdata::transaction::Transaction;
 
 	#[test]
 	fn test_async_ooo_offchain_updates() {
@@ -1016,4 +1307,215 @@ mod tests {
 		check_closed_event!(nodes[0], 1, ClosureReason::ProcessingError { err: "Failed to persist ChannelMonitor update during chain sync".to_string() });
 		check_added_monitors!(nodes[0], 1);
 	}
+
+	#[test]
+	fn failed_event_handling_replays_events() {
+		// Test that if the event handler fails to handle a `SpendableOutputs` event, the event is
+		// kept by the `ChannelMonitor` and replayed on the next call to `process_pending_events`.
+		let chanmon_cfgs = create_chanmon_cfgs(2);
+		let node_cfgs = create_node_cfgs(2, &chanmon_cfgs);
+		let node_chanmgrs = create_node_chanmgrs(2, &node_cfgs, &[None, None]);
+		let nodes = create_network(2, &node_cfgs, &node_chanmgrs);
+		let chan_id = create_announced_chan_between_nodes(&nodes, 0, 1).2;
+		send_payment(&nodes[0], &[&nodes[1]], 10_000_000);
+
+		nodes[0].node.force_close_broadcasting_latest_txn(&chan_id, &nodes[1].node.get_our_node_id()).unwrap();
+		check_added_monitors(&nodes[0], 1);
+		check_closed_broadcast(&nodes[0], 1, true);
+		check_closed_event(&nodes[0], 1, ClosureReason::HolderForceClosed, false);
+		let commitment_tx = {
+			let mut txn = nodes[0].tx_broadcaster.txn_broadcast();
+			assert_eq!(txn.len(), 1);
+			txn.pop().unwrap()
+		};
+
+		mine_transaction(&nodes[1], &commitment_tx);
+		check_added_monitors(&nodes[1], 1);
+		check_closed_broadcast(&nodes[1], 1, true);
+		check_closed_event(&nodes[1], 1, ClosureReason::CommitmentTxConfirmed, false);
+		connect_blocks(&nodes[1], ANTI_REORG_DELAY - 1);
+
+		let attempts = core::cell::RefCell::new(0);
+		let failing_handler = |event: Event| {
+			assert!(matches!(event, Event::SpendableOutputs { .. }));
+			*attempts.borrow_mut() += 1;
+			Err(ReplayEvent())
+		};
+		nodes[1].chain_monitor.chain_monitor.process_pending_events(&failing_handler);
+		nodes[1].chain_monitor.chain_monitor.process_pending_events(&failing_handler);
+		assert_eq!(*attempts.borrow(), 2);
+
+		let events = nodes[1].chain_monitor.chain_monitor.get_and_clear_pending_events();
+		assert_eq!(events.len(), 1);
+		assert!(matches!(events[0], Event::SpendableOutputs { .. }));
+		assert!(nodes[1].chain_monitor.chain_monitor.get_and_clear_pending_events().is_empty());
+	}
+
+	#[test]
+	fn aggregates_claims_across_channels() {
+		// Tests that, with claim aggregation enabled, the preimage claims of HTLCs on the
+		// counterparty's commitment transactions are aggregated across channels into a single
+		// transaction.
+		let chanmon_cfgs = create_chanmon_cfgs(2);
+		let node_cfgs = create_node_cfgs(2, &chanmon_cfgs);
+		let node_chanmgrs = create_node_chanmgrs(2, &node_cfgs, &[None, None]);
+		let nodes = create_network(2, &node_cfgs, &node_chanmgrs);
+		let (_, _, chan_id_a, _) = create_announced_chan_between_nodes_with_value(&nodes, 0, 1, 1_000_000, 0);
+		let (_, _, chan_id_b, _) = create_announced_chan_between_nodes_with_value(&nodes, 0, 1, 1_000_000, 0);
+
+		// HTLCs are sent with a CLTV expiry relative to the height of the next block.
+		let htlc_cltv_expiry = nodes[0].best_block_info().1 + 1 + TEST_FINAL_CLTV;
+		let mut payment_preimages = Vec::new();
+		for chan_id in [chan_id_a, chan_id_b].iter() {
+			let (mut route, payment_hash, payment_preimage, payment_secret) =
+				get_route_and_payment_hash!(nodes[0], nodes[1], 50_000_000);
+			route.paths[0].hops[0].short_channel_id = nodes[0].node.list_channels().iter()
+				.find(|chan| chan.channel_id == *chan_id).unwrap().short_channel_id.unwrap();
+			send_along_route_with_secret(&nodes[0], route, &[&[&nodes[1]]], 50_000_000, payment_hash, payment_secret);
+			payment_preimages.push((payment_preimage, payment_hash));
+		}
+
+		// Grab the commitment transactions of both channels, which include the HTLCs, before
+		// nodes[1] learns their preimages.
+		let commitment_txn_a = get_local_commitment_txn!(nodes[0], chan_id_a);
+		let commitment_txn_b = get_local_commitment_txn!(nodes[0], chan_id_b);
+		for (payment_preimage, payment_hash) in payment_preimages {
+			nodes[1].node.claim_funds(payment_preimage);
+			check_added_monitors!(nodes[1], 1);
+			expect_payment_claimed!(nodes[1], payment_hash, 50_000_000);
+		}
+		// Drop the fulfills as nodes[0] will go on-chain before receiving them.
+		assert_eq!(nodes[1].node.get_and_clear_pending_msg_events().len(), 2);
+
+		nodes[1].chain_monitor.chain_monitor.set_claim_aggregation(true);
+		mine_transactions(&nodes[1], &[&commitment_txn_a[0], &commitment_txn_b[0]]);
+		check_closed_broadcast(&nodes[1], 2, true);
+		check_added_monitors!(nodes[1], 2);
+		check_closed_event!(nodes[1], 2, ClosureReason::CommitmentTxConfirmed);
+
+		let commitment_txids = [commitment_txn_a[0].txid(), commitment_txn_b[0].txid()];
+		let claim_txn = nodes[1].tx_broadcaster.txn_broadcast().into_iter()
+			.filter(|tx| tx.input.iter().any(|input| commitment_txids.contains(&input.previous_output.txid)))
+			.collect::<Vec<_>>();
+		assert_eq!(claim_txn.len(), 1);
+		for commitment_txid in commitment_txids.iter() {
+			assert!(claim_txn[0].input.iter().any(|input| input.previous_output.txid == *commitment_txid));
+		}
+		check_spends!(claim_txn[0], commitment_txn_a[0], commitment_txn_b[0]);
+
+		// Once the claims become time-sensitive, each channel claims its outputs on its own, paying
+		// enough fees to replace the latest aggregated claim.
+		let fee = |tx: &Transaction| {
+			let input_value: u64 = tx.input.iter().map(|input| {
+				let commitment_tx = if input.previous_output.txid == commitment_txids[0] {
+					&commitment_txn_a[0]
+				} else {
+					&commitment_txn_b[0]
+				};
+				commitment_tx.output[input.previous_output.vout as usize].value
+			}).sum();
+			input_value - tx.output.iter().map(|output| output.value).sum::<u64>()
+		};
+		let deadline_height = htlc_cltv_expiry - CLTV_SHARED_CLAIM_BUFFER;
+		connect_blocks(&nodes[1], deadline_height - 1 - nodes[1].best_block_info().1);
+		nodes[1].tx_broadcaster.txn_broadcast();
+		let funding_outpoint = nodes[1].chain_monitor.chain_monitor.list_monitors()[0];
+		let aggregated_claim_tx = nodes[1].chain_monitor.chain_monitor.get_monitor(funding_outpoint).unwrap()
+			.get_aggregated_claim_txn().pop().unwrap().1.tx;
+		check_spends!(aggregated_claim_tx, commitment_txn_a[0], commitment_txn_b[0]);
+
+		connect_blocks(&nodes[1], 1);
+		let own_claim_txn = nodes[1].tx_broadcaster.txn_broadcast().into_iter()
+			.filter(|tx| tx.input.iter().any(|input| commitment_txids.contains(&input.previous_output.txid)))
+			.collect::<Vec<_>>();
+		assert_eq!(own_claim_txn.len(), 2);
+		for own_claim_tx in own_claim_txn.iter() {
+			assert_eq!(own_claim_tx.input.len(), 1);
+			let min_fee = fee(&aggregated_claim_tx) +
+				MIN_RELAY_FEE_SAT_PER_1000_WEIGHT * own_claim_tx.weight() as u64 / 1000;
+			assert!(fee(own_claim_tx) >= min_fee);
+		}
+
+		// Should the aggregated claim confirm regardless, neither channel claims its outputs again.
+		mine_transaction(&nodes[1], &aggregated_claim_tx);
+		connect_blocks(&nodes[1], ANTI_REORG_DELAY);
+		nodes[1].chain_monitor.chain_monitor.rebroadcast_pending_claims();
+		assert!(nodes[1].tx_broadcaster.txn_broadcast().is_empty());
+	}
+
+	#[test]
+	fn does_not_aggregate_revoked_claims() {
+		// Tests that claims of revoked outputs are never deferred to the `ChainMonitor`, as the
+		// counterparty may race us to spend them.
+		let chanmon_cfgs = create_chanmon_cfgs(2);
+		let node_cfgs = create_node_cfgs(2, &chanmon_cfgs);
+		let node_chanmgrs = create_node_chanmgrs(2, &node_cfgs, &[None, None]);
+		let nodes = create_network(2, &node_cfgs, &node_chanmgrs);
+		let (_, _, chan_id_a, _) = create_announced_chan_between_nodes(&nodes, 0, 1);
+		let (_, _, chan_id_b, _) = create_announced_chan_between_nodes(&nodes, 0, 1);
+
+		let revoked_txn_a = get_local_commitment_txn!(nodes[0], chan_id_a);
+		let revoked_txn_b = get_local_commitment_txn!(nodes[0], chan_id_b);
+		for chan_id in [chan_id_a, chan_id_b].iter() {
+			let mut route = get_route_and_payment_hash!(nodes[0], nodes[1], 1_000_000).0;
+			route.paths[0].hops[0].short_channel_id = nodes[0].node.list_channels().iter()
+				.find(|chan| chan.channel_id == *chan_id).unwrap().short_channel_id.unwrap();
+			send_along_route(&nodes[0], route, &[&nodes[1]], 1_000_000);
+		}
+
+		nodes[1].chain_monitor.chain_monitor.set_claim_aggregation(true);
+		mine_transactions(&nodes[1], &[&revoked_txn_a[0], &revoked_txn_b[0]]);
+		check_closed_broadcast(&nodes[1], 2, true);
+		check_added_monitors!(nodes[1], 2);
+		check_closed_event!(nodes[1], 2, ClosureReason::CommitmentTxConfirmed);
+
+		let txn = nodes[1].tx_broadcaster.txn_broadcast();
+		for revoked_tx in [&revoked_txn_a[0], &revoked_txn_b[0]].iter() {
+			let claim_txn = txn.iter()
+				.filter(|tx| tx.input.iter().any(|input| input.previous_output.txid == revoked_tx.txid()))
+				.collect::<Vec<_>>();
+			assert_eq!(claim_txn.len(), 1);
+			check_spends!(claim_txn[0], revoked_tx);
+		}
+	}
+
+	#[test]
+	fn fee_bump_strategy_is_consulted() {
+		// Tests that claims are fee-bumped as decided by the `FeeBumpStrategy` set on the
+		// `ChainMonitor`, rather than the default one.
+		let chanmon_cfgs = create_chanmon_cfgs(2);
+		let node_cfgs = create_node_cfgs(2, &chanmon_cfgs);
+		let node_chanmgrs = create_node_chanmgrs(2, &node_cfgs, &[None, None]);
+		let nodes = create_network(2, &node_cfgs, &node_chanmgrs);
+		let (_, _, chan_id, _) = create_announced_chan_between_nodes(&nodes, 0, 1);
+
+		let revoked_txn = get_local_commitment_txn!(nodes[0], chan_id);
+		send_payment(&nodes[0], &[&nodes[1]], 1_000_000);
+
+		nodes[1].chain_monitor.chain_monitor.set_fee_bump_strategy(Arc::new(AggressiveFeeBumpStrategy));
+		mine_transaction(&nodes[1], &revoked_txn[0]);
+		check_closed_broadcast(&nodes[1], 1, true);
+		check_added_monitors!(nodes[1], 1);
+		check_closed_event!(nodes[1], 1, ClosureReason::CommitmentTxConfirmed);
+
+		let spends_revoked_tx = |tx: &Transaction| tx.input.iter()
+			.any(|input| input.previous_output.txid == revoked_txn[0].txid());
+		let claim_txn = nodes[1].tx_broadcaster.txn_broadcast().into_iter()
+			.filter(spends_revoked_tx).collect::<Vec<_>>();
+		assert_eq!(claim_txn.len(), 1);
+		check_spends!(claim_txn[0], revoked_txn[0]);
+
+		// The default strategy would wait 15 blocks before bumping the claim, while the aggressive
+		// one only waits 6 blocks and bumps its feerate by 50%.
+		connect_blocks(&nodes[1], 5);
+		assert!(!nodes[1].tx_broadcaster.txn_broadcast().iter().any(spends_revoked_tx));
+		connect_blocks(&nodes[1], 1);
+		let bumped_claim_txn = nodes[1].tx_broadcaster.txn_broadcast().into_iter()
+			.filter(spends_revoked_tx).collect::<Vec<_>>();
+		assert_eq!(bumped_claim_txn.len(), 1);
+		check_spends!(bumped_claim_txn[0], revoked_txn[0]);
+		let fee = revoked_txn[0].output[claim_txn[0].input[0].previous_output.vout as usize].value - claim_txn[0].output[0].value;
+		let bumped_fee = revoked_txn[0].output[claim_txn[0].input[0].previous_output.vout as usize].value - bumped_claim_txn[0].output[0].value;
+		assert!(bumped_fee >= fee + fee / 2);
+	}
 }
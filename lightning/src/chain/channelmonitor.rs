@@ -38,18 +38,19 @@ use crate::ln::msgs::DecodeError;
 use crate::ln::chan_utils;
 use crate::ln::chan_utils::{CounterpartyCommitmentSecrets, HTLCOutputInCommitment, HTLCClaim, ChannelTransactionParameters, HolderCommitmentTransaction};
 use crate::ln::channelmanager::{HTLCSource, SentHTLCId};
+use crate::ln::features::ChannelTypeFeatures;
 use crate::chain;
-use crate::chain::{BestBlock, WatchedOutput};
-use crate::chain::chaininterface::{BroadcasterInterface, FeeEstimator, LowerBoundedFeeEstimator};
+use crate::chain::{BestBlock, ClaimId, WatchedOutput};
+use crate::chain::chaininterface::{BroadcasterInterface, FeeBumpStrategy, FeeEstimator, LowerBoundedFeeEstimator};
 use crate::chain::transaction::{OutPoint, TransactionData};
 use crate::sign::{SpendableOutputDescriptor, StaticPaymentOutputDescriptor, DelayedPaymentOutputDescriptor, WriteableEcdsaChannelSigner, SignerProvider, EntropySource};
-use crate::chain::onchaintx::{ClaimEvent, OnchainTxHandler};
+use crate::chain::onchaintx::{AggregableClaim, AggregatedClaimTx, ClaimEvent, OnchainTxHandler};
 use crate::chain::package::{CounterpartyOfferedHTLCOutput, CounterpartyReceivedHTLCOutput, HolderFundingOutput, HolderHTLCOutput, PackageSolvingData, PackageTemplate, RevokedOutput, RevokedHTLCOutput};
 use crate::chain::Filter;
 use crate::util::logger::Logger;
 use crate::util::ser::{Readable, ReadableArgs, RequiredWrapper, MaybeReadable, UpgradableRequired, Writer, Writeable, U48};
 use crate::util::byte_utils;
-use crate::events::{Event, EventHandler};
+use crate::events::{Event, EventHandler, ReplayEvent};
 use crate::events::bump_transaction::{ChannelDerivationParameters, AnchorDescriptor, HTLCDescriptor, BumpTransactionEvent};
 
 use crate::prelude::*;
@@ -57,6 +58,7 @@ use core::{cmp, mem};
 use crate::io::{self, Error};
 use core::convert::TryInto;
 use core::ops::Deref;
+use alloc::sync::Arc;
 use crate::sync::{Mutex, LockTestExt};
 
 /// An update generated by the underlying channel itself which contains some new information the
@@ -496,12 +498,17 @@ pub(crate) enum ChannelMonitorUpdateStep {
 		htlc_outputs: Vec<(HTLCOutputInCommitment, Option<Signature>, Option<HTLCSource>)>,
 		claimed_htlcs: Vec<(SentHTLCId, PaymentPreimage)>,
 		nondust_htlc_sources: Vec<HTLCSource>,
+		/// The commitment transactions spending the new funding outputs of a pending splice,
+		/// matching `commitment_tx` other than for the funding output they spend.
+		splice_commitment_txs: Vec<HolderCommitmentTransaction>,
 	},
 	LatestCounterpartyCommitmentTXInfo {
 		commitment_txid: Txid,
 		htlc_outputs: Vec<(HTLCOutputInCommitment, Option<Box<HTLCSource>>)>,
 		commitment_number: u64,
 		their_per_commitment_point: PublicKey,
+		/// The commitment transactions spending the new funding outputs of a pending splice.
+		splice_commitment_txs: Vec<SpliceCounterpartyCommitmentTx>,
 	},
 	PaymentPreimage {
 		payment_preimage: PaymentPreimage,
@@ -520,8 +527,32 @@ pub(crate) enum ChannelMonitorUpdateStep {
 	ShutdownScript {
 		scriptpubkey: Script,
 	},
+	/// Used to indicate that the channel was upgraded to a new channel type, e.g. to anchor
+	/// outputs, while open. Always provided along with the first holder commitment transaction
+	/// using the new type.
+	ChannelTypeUpgrade {
+		channel_type_features: ChannelTypeFeatures,
+	},
 }
 
+/// A counterparty commitment transaction spending the new funding output of a pending splice. It
+/// only differs from the one spending the channel's current funding output in the position of its
+/// outputs, so we only track the position of its non-dust HTLC outputs.
+#[derive(Clone, PartialEq, Eq)]
+pub(crate) struct SpliceCounterpartyCommitmentTx {
+	pub(crate) funding_txo: OutPoint,
+	pub(crate) commitment_txid: Txid,
+	/// The output index of each non-dust HTLC, in the order they appear in the update's
+	/// `htlc_outputs`.
+	pub(crate) htlc_output_indices: Vec<u32>,
+}
+
+impl_writeable_tlv_based!(SpliceCounterpartyCommitmentTx, {
+	(0, funding_txo, required),
+	(2, commitment_txid, required),
+	(4, htlc_output_indices, optional_vec),
+});
+
 impl ChannelMonitorUpdateStep {
 	fn variant_name(&self) -> &'static str {
 		match self {
@@ -531,6 +562,7 @@ impl ChannelMonitorUpdateStep {
 			ChannelMonitorUpdateStep::CommitmentSecret { .. } => "CommitmentSecret",
 			ChannelMonitorUpdateStep::ChannelForceClosed { .. } => "ChannelForceClosed",
 			ChannelMonitorUpdateStep::ShutdownScript { .. } => "ShutdownScript",
+			ChannelMonitorUpdateStep::ChannelTypeUpgrade { .. } => "ChannelTypeUpgrade",
 		}
 	}
 }
@@ -541,12 +573,14 @@ impl_writeable_tlv_based_enum_upgradable!(ChannelMonitorUpdateStep,
 		(1, claimed_htlcs, optional_vec),
 		(2, htlc_outputs, required_vec),
 		(4, nondust_htlc_sources, optional_vec),
+		(5, splice_commitment_txs, optional_vec),
 	},
 	(1, LatestCounterpartyCommitmentTXInfo) => {
 		(0, commitment_txid, required),
 		(2, commitment_number, required),
 		(4, their_per_commitment_point, required),
 		(6, htlc_outputs, required_vec),
+		(7, splice_commitment_txs, optional_vec),
 	},
 	(2, PaymentPreimage) => {
 		(0, payment_preimage, required),
@@ -561,6 +595,9 @@ impl_writeable_tlv_based_enum_upgradable!(ChannelMonitorUpdateStep,
 	(5, ShutdownScript) => {
 		(0, scriptpubkey, required),
 	},
+	(6, ChannelTypeUpgrade) => {
+		(0, channel_type_features, required),
+	},
 );
 
 /// Details about the balance(s) available for spending once the channel appears on chain.
@@ -886,6 +923,10 @@ pub(crate) struct ChannelMonitorImpl<Signer: WriteableEcdsaChannelSigner> {
 	/// [`ANTI_REORG_DELAY`], so we have to track them here.
 	spendable_txids_confirmed: Vec<Txid>,
 
+	/// If the channel's type was upgraded while it was open, the channel type it used before. The
+	/// revoked counterparty commitment transactions we may have to claim could be using it.
+	pre_upgrade_channel_type_features: Option<ChannelTypeFeatures>,
+
 	// We simply modify best_block in Channel's block_connected so that serialization is
 	// consistent but hopefully the users' copy handles block_connected in a consistent way.
 	// (we do *not*, however, update them in update_monitor to ensure any local user copies keep
@@ -1085,6 +1126,7 @@ impl<Signer: WriteableEcdsaChannelSigner> Writeable for ChannelMonitorImpl<Signe
 			(11, self.confirmed_commitment_tx_counterparty_output, option),
 			(13, self.spendable_txids_confirmed, required_vec),
 			(15, self.counterparty_fulfilled_htlcs, required),
+			(17, self.pre_upgrade_channel_type_features, option),
 		});
 
 		Ok(())
@@ -1105,18 +1147,35 @@ macro_rules! _process_events_body {
 				pending_events = inner.pending_events.clone();
 				repeated_events = inner.get_repeated_events();
 			} else { break; }
-			let num_events = pending_events.len();
+			let mut num_handled_events = 0;
+			let mut handling_failed = false;
 
-			for event in pending_events.into_iter().chain(repeated_events.into_iter()) {
+			for event in pending_events {
 				$event_to_handle = event;
-				$handle_event;
+				match $handle_event {
+					Ok(()) => num_handled_events += 1,
+					Err(ReplayEvent()) => {
+						// Stop handling events, leaving the failed event (and any after it) queued
+						// to be replayed on the next invocation.
+						handling_failed = true;
+						break;
+					},
+				}
+			}
+			if !handling_failed {
+				for event in repeated_events {
+					// Repeated events are regenerated on each invocation, so there's nothing to
+					// keep around if the handler fails.
+					$event_to_handle = event;
+					let _ = $handle_event;
+				}
 			}
 
 			if let Some(us) = $self_opt {
 				let mut inner = us.inner.lock().unwrap();
-				inner.pending_events.drain(..num_events);
+				inner.pending_events.drain(..num_handled_events);
 				inner.is_processing_pending_events = false;
-				if !inner.pending_events.is_empty() {
+				if !handling_failed && !inner.pending_events.is_empty() {
 					// If there's more events to process, go ahead and do so.
 					continue;
 				}
@@ -1232,6 +1291,7 @@ impl<Signer: WriteableEcdsaChannelSigner> ChannelMonitor<Signer> {
 			confirmed_commitment_tx_counterparty_output: None,
 			htlcs_resolved_on_chain: Vec::new(),
 			spendable_txids_confirmed: Vec::new(),
+			pre_upgrade_channel_type_features: None,
 
 			best_block,
 			counterparty_node_id: Some(counterparty_node_id),
@@ -1243,6 +1303,37 @@ impl<Signer: WriteableEcdsaChannelSigner> ChannelMonitor<Signer> {
 		self.inner.lock().unwrap().provide_secret(idx, secret)
 	}
 
+	/// Carries over the update id and revocation secrets of the monitor for a channel's current
+	/// funding output to a freshly created monitor watching the new funding output of a splice, so
+	/// that both monitors can keep receiving the same [`ChannelMonitorUpdate`]s until the splice
+	/// locks. As HTLCs may be pending when splicing, their details for the initial holder
+	/// commitment transaction are provided here as well, along with the preimages of any we've
+	/// claimed but not yet removed from the channel.
+	pub(crate) fn inherit_state_for_splice(
+		&self, latest_update_id: u64, commitment_secrets: CounterpartyCommitmentSecrets,
+		holder_htlc_outputs: Vec<(HTLCOutputInCommitment, Option<Signature>, Option<HTLCSource>)>,
+		payment_preimages: Vec<PaymentPreimage>,
+	) {
+		let mut inner = self.inner.lock().unwrap();
+		inner.latest_update_id = latest_update_id;
+		inner.commitment_secrets = commitment_secrets;
+		inner.current_holder_commitment_tx.htlc_outputs = holder_htlc_outputs;
+		for payment_preimage in payment_preimages {
+			inner.payment_preimages.insert(PaymentHash(Sha256::hash(&payment_preimage.0[..]).into_inner()), payment_preimage);
+		}
+	}
+
+	/// Watches the outputs spent by the splice transaction whose new funding output this monitor
+	/// was created for, other than the channel's current funding output. This ensures that users
+	/// of [`chain::Filter`] learn of any transaction double-spending the splice transaction, upon
+	/// which the `ChannelManager` abandons the splice.
+	pub(crate) fn watch_splice_inputs(&self, inputs: Vec<(BitcoinOutPoint, Script)>) {
+		let mut inner = self.inner.lock().unwrap();
+		for (outpoint, script_pubkey) in inputs {
+			inner.outputs_to_watch.entry(outpoint.txid).or_insert_with(Vec::new).push((outpoint.vout, script_pubkey));
+		}
+	}
+
 	/// Informs this monitor of the latest counterparty (ie non-broadcastable) commitment transaction.
 	/// The monitor watches for it to be broadcasted and then uses the HTLC information (and
 	/// possibly future revocation/preimage information) to claim outputs where possible.
@@ -1367,7 +1458,7 @@ impl<Signer: WriteableEcdsaChannelSigner> ChannelMonitor<Signer> {
 	/// Processes any events asynchronously.
 	///
 	/// See [`Self::process_pending_events`] for more information.
-	pub async fn process_pending_events_async<Future: core::future::Future, H: Fn(Event) -> Future>(
+	pub async fn process_pending_events_async<Future: core::future::Future<Output = Result<(), ReplayEvent>>, H: Fn(Event) -> Future>(
 		&self, handler: &H
 	) {
 		let mut ev;
@@ -1395,6 +1486,11 @@ impl<Signer: WriteableEcdsaChannelSigner> ChannelMonitor<Signer> {
 		self.inner.lock().unwrap().get_cur_holder_commitment_number()
 	}
 
+	#[cfg(test)]
+	pub(crate) fn get_cur_counterparty_commitment_txid(&self) -> Option<Txid> {
+		self.inner.lock().unwrap().current_counterparty_commitment_txid
+	}
+
 	/// Gets the `node_id` of the counterparty for this channel.
 	///
 	/// Will be `None` for channels constructed on LDK versions prior to 0.0.110 and always `Some`
@@ -1591,6 +1687,53 @@ impl<Signer: WriteableEcdsaChannelSigner> ChannelMonitor<Signer> {
 			current_height, &broadcaster, &fee_estimator, &logger,
 		);
 	}
+
+	/// Sets whether non-time-sensitive claims are left to the [`ChainMonitor`] to aggregate them
+	/// with claims of other channels, see [`ChainMonitor::set_claim_aggregation`].
+	///
+	/// [`ChainMonitor`]: crate::chain::chainmonitor::ChainMonitor
+	/// [`ChainMonitor::set_claim_aggregation`]: crate::chain::chainmonitor::ChainMonitor::set_claim_aggregation
+	pub(crate) fn set_aggregate_claims_across_channels(&self, aggregate_claims_across_channels: bool) {
+		self.inner.lock().unwrap().onchain_tx_handler
+			.set_aggregate_claims_across_channels(aggregate_claims_across_channels);
+	}
+
+	/// Sets the [`FeeBumpStrategy`] deciding how quickly the feerate of our claims escalates, see
+	/// [`ChainMonitor::set_fee_bump_strategy`].
+	///
+	/// [`ChainMonitor::set_fee_bump_strategy`]: crate::chain::chainmonitor::ChainMonitor::set_fee_bump_strategy
+	pub(crate) fn set_fee_bump_strategy(&self, fee_bump_strategy: Arc<dyn FeeBumpStrategy + Send + Sync>) {
+		self.inner.lock().unwrap().onchain_tx_handler.set_fee_bump_strategy(fee_bump_strategy);
+	}
+
+	/// Gets the claims left to the [`ChainMonitor`] to aggregate with claims of other channels.
+	///
+	/// [`ChainMonitor`]: crate::chain::chainmonitor::ChainMonitor
+	pub(crate) fn get_aggregable_claims(&self) -> Vec<AggregableClaim> {
+		let inner = self.inner.lock().unwrap();
+		inner.onchain_tx_handler.get_aggregable_claims(inner.best_block.height)
+	}
+
+	/// Signs the inputs of an aggregated claim transaction spending the outputs of the given claim
+	/// previously returned by [`Self::get_aggregable_claims`].
+	pub(crate) fn sign_aggregated_claim(&self, claim_id: &ClaimId, tx: &mut Transaction, feerate: u64) -> bool {
+		let mut inner = self.inner.lock().unwrap();
+		let current_height = inner.best_block.height;
+		inner.onchain_tx_handler.sign_aggregated_claim(claim_id, tx, feerate, current_height)
+	}
+
+	/// Tracks the fully signed aggregated claim transaction spending the outputs of the given claim.
+	pub(crate) fn track_aggregated_claim(&self, claim_id: &ClaimId, aggregated_tx: AggregatedClaimTx) {
+		self.inner.lock().unwrap().onchain_tx_handler.track_aggregated_claim(claim_id, aggregated_tx);
+	}
+
+	/// Gets the latest aggregated claim transactions tracked for claims left to the
+	/// [`ChainMonitor`], allowing it to pick up where it left off upon restart.
+	///
+	/// [`ChainMonitor`]: crate::chain::chainmonitor::ChainMonitor
+	pub(crate) fn get_aggregated_claim_txn(&self) -> Vec<(ClaimId, AggregatedClaimTx)> {
+		self.inner.lock().unwrap().onchain_tx_handler.get_aggregated_claim_txn()
+	}
 }
 
 impl<Signer: WriteableEcdsaChannelSigner> ChannelMonitorImpl<Signer> {
@@ -2239,6 +2382,92 @@ impl<Signer: WriteableEcdsaChannelSigner> ChannelMonitorImpl<Signer> {
 		Ok(())
 	}
 
+	fn upgrade_channel_type(&mut self, channel_type_features: ChannelTypeFeatures) {
+		// We only support upgrading a channel's type once, e.g. from `static_remote_key` to
+		// anchor outputs.
+		debug_assert!(self.pre_upgrade_channel_type_features.is_none());
+		let prev_channel_type_features = self.onchain_tx_handler.channel_type_features().clone();
+		self.onchain_tx_handler.upgrade_channel_type(channel_type_features);
+		self.pre_upgrade_channel_type_features = Some(prev_channel_type_features);
+	}
+
+	/// Returns the channel type used to build the given counterparty commitment transaction,
+	/// which may predate an upgrade of the channel's type. Upgrades are only to channel types with
+	/// anchor outputs, so a transaction lacking our counterparty's anchor must use the previous
+	/// type. Note that the anchor is only ever omitted if our counterparty has no balance or HTLCs
+	/// we could claim.
+	fn counterparty_commitment_tx_channel_type_features(&self, tx: &Transaction) -> &ChannelTypeFeatures {
+		if let Some(pre_upgrade_channel_type_features) = self.pre_upgrade_channel_type_features.as_ref() {
+			let counterparty_funding_pubkey = &self.onchain_tx_handler.channel_transaction_parameters
+				.counterparty_parameters.as_ref().expect("Monitors always have counterparty parameters")
+				.pubkeys.funding_pubkey;
+			let anchor_script_pubkey = chan_utils::get_anchor_redeemscript(counterparty_funding_pubkey).to_v0_p2wsh();
+			if !tx.output.iter().any(|outp| outp.script_pubkey == anchor_script_pubkey) {
+				return pre_upgrade_channel_type_features;
+			}
+		}
+		self.onchain_tx_handler.channel_type_features()
+	}
+
+	/// Until a pending splice locks, the monitors watching its new funding outputs receive the same
+	/// updates as the monitor watching the channel's current funding output, with the commitment
+	/// transactions spending each funding output provided alongside. Returns the holder commitment
+	/// transaction spending the funding output we watch, along with `htlc_outputs` updated to the
+	/// position and signature of each non-dust HTLC in it.
+	fn select_holder_commitment_tx(
+		&self, commitment_tx: &HolderCommitmentTransaction,
+		htlc_outputs: &[(HTLCOutputInCommitment, Option<Signature>, Option<HTLCSource>)],
+		splice_commitment_txs: &[HolderCommitmentTransaction],
+	) -> (HolderCommitmentTransaction, Vec<(HTLCOutputInCommitment, Option<Signature>, Option<HTLCSource>)>) {
+		let funding_outpoint = self.funding_info.0.into_bitcoin_outpoint();
+		let splice_commitment_tx = match splice_commitment_txs.iter().find(|tx|
+			tx.trust().built_transaction().transaction.input[0].previous_output == funding_outpoint
+		) {
+			Some(tx) => tx,
+			None => return (commitment_tx.clone(), htlc_outputs.to_vec()),
+		};
+		// Both commitment transactions have the same HTLC outputs, in the same order.
+		let trusted_tx = splice_commitment_tx.trust();
+		let mut nondust_htlcs = trusted_tx.htlcs().iter()
+			.zip(splice_commitment_tx.counterparty_htlc_sigs.iter());
+		let htlc_outputs = htlc_outputs.iter().map(|(htlc, signature, source)| {
+			if htlc.transaction_output_index.is_none() {
+				return (htlc.clone(), *signature, source.clone());
+			}
+			match nondust_htlcs.next() {
+				Some((splice_htlc, splice_signature)) => {
+					debug_assert!(signature.is_some());
+					(splice_htlc.clone(), Some(*splice_signature), source.clone())
+				},
+				None => (htlc.clone(), *signature, source.clone()),
+			}
+		}).collect();
+		(splice_commitment_tx.clone(), htlc_outputs)
+	}
+
+	/// Returns the txid of the counterparty commitment transaction spending the funding output we
+	/// watch, along with `htlc_outputs` updated to the position of each non-dust HTLC in it. See
+	/// [`Self::select_holder_commitment_tx`].
+	fn select_counterparty_commitment_tx(
+		&self, commitment_txid: Txid, htlc_outputs: &[(HTLCOutputInCommitment, Option<Box<HTLCSource>>)],
+		splice_commitment_txs: &[SpliceCounterpartyCommitmentTx],
+	) -> (Txid, Vec<(HTLCOutputInCommitment, Option<Box<HTLCSource>>)>) {
+		let splice_commitment_tx = match splice_commitment_txs.iter().find(|tx| tx.funding_txo == self.funding_info.0) {
+			Some(tx) => tx,
+			None => return (commitment_txid, htlc_outputs.to_vec()),
+		};
+		let mut htlc_output_indices = splice_commitment_tx.htlc_output_indices.iter();
+		let htlc_outputs = htlc_outputs.iter().map(|(htlc, source)| {
+			let mut htlc = htlc.clone();
+			if htlc.transaction_output_index.is_some() {
+				htlc.transaction_output_index = htlc_output_indices.next().copied();
+				debug_assert!(htlc.transaction_output_index.is_some());
+			}
+			(htlc, source.clone())
+		}).collect();
+		(splice_commitment_tx.commitment_txid, htlc_outputs)
+	}
+
 	pub(crate) fn provide_latest_counterparty_commitment_tx<L: Deref>(&mut self, txid: Txid, htlc_outputs: Vec<(HTLCOutputInCommitment, Option<Box<HTLCSource>>)>, commitment_number: u64, their_per_commitment_point: PublicKey, logger: &L) where L::Target: Logger {
 		// TODO: Encrypt the htlc_outputs data with the single-hash of the commitment transaction
 		// so that a remote monitor doesn't learn anything unless there is a malicious close.
@@ -2249,7 +2478,13 @@ impl<Signer: WriteableEcdsaChannelSigner> ChannelMonitorImpl<Signer> {
 		}
 
 		log_trace!(logger, "Tracking new counterparty commitment transaction with txid {} at commitment number {} with {} HTLC outputs", txid, commitment_number, htlc_outputs.len());
-		self.prev_counterparty_commitment_txid = self.current_counterparty_commitment_txid.take();
+		// If the channel reverted an upgrade of its type, the counterparty commitment transaction
+		// we signed for the new type is replaced by one at the same commitment number using the
+		// previous type. Our counterparty has yet to revoke their previous commitment transaction
+		// in that case, so we keep tracking it as such.
+		if commitment_number != self.current_counterparty_commitment_number || self.current_counterparty_commitment_txid.is_none() {
+			self.prev_counterparty_commitment_txid = self.current_counterparty_commitment_txid.take();
+		}
 		self.current_counterparty_commitment_txid = Some(txid);
 		self.counterparty_claimable_outpoints.insert(txid, htlc_outputs.clone());
 		self.current_counterparty_commitment_number = commitment_number;
@@ -2475,18 +2710,20 @@ impl<Signer: WriteableEcdsaChannelSigner> ChannelMonitorImpl<Signer> {
 		let bounded_fee_estimator = LowerBoundedFeeEstimator::new(&*fee_estimator);
 		for update in updates.updates.iter() {
 			match update {
-				ChannelMonitorUpdateStep::LatestHolderCommitmentTXInfo { commitment_tx, htlc_outputs, claimed_htlcs, nondust_htlc_sources } => {
+				ChannelMonitorUpdateStep::LatestHolderCommitmentTXInfo { commitment_tx, htlc_outputs, claimed_htlcs, nondust_htlc_sources, splice_commitment_txs } => {
 					log_trace!(logger, "Updating ChannelMonitor with latest holder commitment transaction info");
 					if self.lockdown_from_offchain { panic!(); }
-					if let Err(e) = self.provide_latest_holder_commitment_tx(commitment_tx.clone(), htlc_outputs.clone(), &claimed_htlcs, nondust_htlc_sources.clone()) {
+					let (commitment_tx, htlc_outputs) = self.select_holder_commitment_tx(commitment_tx, htlc_outputs, splice_commitment_txs);
+					if let Err(e) = self.provide_latest_holder_commitment_tx(commitment_tx, htlc_outputs, claimed_htlcs, nondust_htlc_sources.clone()) {
 						log_error!(logger, "Providing latest holder commitment transaction failed/was refused:");
 						log_error!(logger, "    {}", e);
 						ret = Err(());
 					}
 				}
-				ChannelMonitorUpdateStep::LatestCounterpartyCommitmentTXInfo { commitment_txid, htlc_outputs, commitment_number, their_per_commitment_point } => {
+				ChannelMonitorUpdateStep::LatestCounterpartyCommitmentTXInfo { commitment_txid, htlc_outputs, commitment_number, their_per_commitment_point, splice_commitment_txs } => {
 					log_trace!(logger, "Updating ChannelMonitor with latest counterparty commitment transaction info");
-					self.provide_latest_counterparty_commitment_tx(*commitment_txid, htlc_outputs.clone(), *commitment_number, *their_per_commitment_point, logger)
+					let (commitment_txid, htlc_outputs) = self.select_counterparty_commitment_tx(*commitment_txid, htlc_outputs, splice_commitment_txs);
+					self.provide_latest_counterparty_commitment_tx(commitment_txid, htlc_outputs, *commitment_number, *their_per_commitment_point, logger)
 				},
 				ChannelMonitorUpdateStep::PaymentPreimage { payment_preimage } => {
 					log_trace!(logger, "Updating ChannelMonitor with payment preimage");
@@ -2553,6 +2790,11 @@ impl<Signer: WriteableEcdsaChannelSigner> ChannelMonitorImpl<Signer> {
 						panic!("Attempted to replace shutdown script {} with {}", shutdown_script, scriptpubkey);
 					}
 				},
+				ChannelMonitorUpdateStep::ChannelTypeUpgrade { channel_type_features } => {
+					log_trace!(logger, "Updating ChannelMonitor with upgraded channel type {}", channel_type_features);
+					if self.lockdown_from_offchain { panic!(); }
+					self.upgrade_channel_type(channel_type_features.clone());
+				},
 			}
 		}
 
@@ -2721,10 +2963,12 @@ impl<Signer: WriteableEcdsaChannelSigner> ChannelMonitorImpl<Signer> {
 			let revokeable_redeemscript = chan_utils::get_revokeable_redeemscript(&revocation_pubkey, self.counterparty_commitment_params.on_counterparty_tx_csv, &delayed_key);
 			let revokeable_p2wsh = revokeable_redeemscript.to_v0_p2wsh();
 
+			let channel_type_features = self.counterparty_commitment_tx_channel_type_features(tx);
+
 			// First, process non-htlc outputs (to_holder & to_counterparty)
 			for (idx, outp) in tx.output.iter().enumerate() {
 				if outp.script_pubkey == revokeable_p2wsh {
-					let revk_outp = RevokedOutput::build(per_commitment_point, self.counterparty_commitment_params.counterparty_delayed_payment_base_key, self.counterparty_commitment_params.counterparty_htlc_base_key, per_commitment_key, outp.value, self.counterparty_commitment_params.on_counterparty_tx_csv, self.onchain_tx_handler.channel_type_features().supports_anchors_zero_fee_htlc_tx());
+					let revk_outp = RevokedOutput::build(per_commitment_point, self.counterparty_commitment_params.counterparty_delayed_payment_base_key, self.counterparty_commitment_params.counterparty_htlc_base_key, per_commitment_key, outp.value, self.counterparty_commitment_params.on_counterparty_tx_csv, channel_type_features.supports_anchors_zero_fee_htlc_tx());
 					let justice_package = PackageTemplate::build_package(commitment_txid, idx as u32, PackageSolvingData::RevokedOutput(revk_outp), height + self.counterparty_commitment_params.on_counterparty_tx_csv as u32, height);
 					claimable_outpoints.push(justice_package);
 					to_counterparty_output_info =
@@ -2742,7 +2986,7 @@ impl<Signer: WriteableEcdsaChannelSigner> ChannelMonitorImpl<Signer> {
 							return (claimable_outpoints, (commitment_txid, watch_outputs),
 								to_counterparty_output_info);
 						}
-						let revk_htlc_outp = RevokedHTLCOutput::build(per_commitment_point, self.counterparty_commitment_params.counterparty_delayed_payment_base_key, self.counterparty_commitment_params.counterparty_htlc_base_key, per_commitment_key, htlc.amount_msat / 1000, htlc.clone(), &self.onchain_tx_handler.channel_transaction_parameters.channel_type_features);
+						let revk_htlc_outp = RevokedHTLCOutput::build(per_commitment_point, self.counterparty_commitment_params.counterparty_delayed_payment_base_key, self.counterparty_commitment_params.counterparty_htlc_base_key, per_commitment_key, htlc.amount_msat / 1000, htlc.clone(), channel_type_features);
 						let justice_package = PackageTemplate::build_package(commitment_txid, transaction_output_index, PackageSolvingData::RevokedHTLCOutput(revk_htlc_outp), htlc.cltv_expiry, height);
 						claimable_outpoints.push(justice_package);
 					}
@@ -3187,6 +3431,28 @@ impl<Signer: WriteableEcdsaChannelSigner> ChannelMonitorImpl<Signer> {
 				}
 			}
 
+			if tx.input.len() > 1 && tx.input.iter().any(|input| {
+				input.previous_output.txid == self.funding_info.0.txid &&
+					input.previous_output.vout == self.funding_info.0.index as u32
+			}) {
+				// Commitment transactions only ever have one input, so any transaction spending
+				// the funding output alongside other inputs must be a splice (or a cooperative
+				// transaction we don't otherwise know about). Either way there is nothing left to
+				// claim for this funding output.
+				log_info!(logger, "Funding output of channel {} spent in multi-input txid {}, likely a splice.",
+					log_bytes!(self.funding_info.0.to_channel_id()), txid);
+				self.funding_spend_seen = true;
+				self.onchain_events_awaiting_threshold_conf.push(OnchainEventEntry {
+					txid,
+					transaction: Some((*tx).clone()),
+					height,
+					block_hash: Some(block_hash),
+					event: OnchainEvent::FundingSpendConfirmation {
+						on_local_output_csv: None,
+						commitment_tx_to_counterparty_output: None,
+					},
+				});
+			}
 			if tx.input.len() == 1 {
 				// Assuming our keys were not leaked (in which case we're screwed no matter what),
 				// commitment transactions and HTLC transactions will all only ever have one input
@@ -4125,6 +4391,7 @@ impl<'a, 'b, ES: EntropySource, SP: SignerProvider> ReadableArgs<(&'a ES, &'b SP
 		let mut confirmed_commitment_tx_counterparty_output = None;
 		let mut spendable_txids_confirmed = Some(Vec::new());
 		let mut counterparty_fulfilled_htlcs = Some(HashMap::new());
+		let mut pre_upgrade_channel_type_features = None;
 		read_tlv_fields!(reader, {
 			(1, funding_spend_confirmed, option),
 			(3, htlcs_resolved_on_chain, optional_vec),
@@ -4134,6 +4401,7 @@ impl<'a, 'b, ES: EntropySource, SP: SignerProvider> ReadableArgs<(&'a ES, &'b SP
 			(11, confirmed_commitment_tx_counterparty_output, option),
 			(13, spendable_txids_confirmed, optional_vec),
 			(15, counterparty_fulfilled_htlcs, option),
+			(17, pre_upgrade_channel_type_features, option),
 		});
 
 		Ok((best_block.block_hash(), ChannelMonitor::from_impl(ChannelMonitorImpl {
@@ -4186,6 +4454,7 @@ impl<'a, 'b, ES: EntropySource, SP: SignerProvider> ReadableArgs<(&'a ES, &'b SP
 			confirmed_commitment_tx_counterparty_output,
 			htlcs_resolved_on_chain: htlcs_resolved_on_chain.unwrap(),
 			spendable_txids_confirmed: spendable_txids_confirmed.unwrap(),
+			pre_upgrade_channel_type_features,
 
 			best_block,
 			counterparty_node_id,
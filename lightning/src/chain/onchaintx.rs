@@ -28,7 +28,7 @@ use crate::ln::msgs::DecodeError;
 use crate::ln::PaymentPreimage;
 use crate::ln::chan_utils::{self, ChannelTransactionParameters, HTLCOutputInCommitment, HolderCommitmentTransaction};
 use crate::chain::ClaimId;
-use crate::chain::chaininterface::{ConfirmationTarget, FeeEstimator, BroadcasterInterface, LowerBoundedFeeEstimator};
+use crate::chain::chaininterface::{ConfirmationTarget, DefaultFeeBumpStrategy, FeeBumpStrategy, FeeEstimator, BroadcasterInterface, LowerBoundedFeeEstimator, MIN_RELAY_FEE_SAT_PER_1000_WEIGHT};
 use crate::chain::channelmonitor::{ANTI_REORG_DELAY, CLTV_SHARED_CLAIM_BUFFER};
 use crate::sign::WriteableEcdsaChannelSigner;
 use crate::chain::package::{PackageSolvingData, PackageTemplate};
@@ -38,6 +38,7 @@ use crate::util::ser::{Readable, ReadableArgs, MaybeReadable, UpgradableRequired
 use crate::io;
 use crate::prelude::*;
 use alloc::collections::BTreeMap;
+use alloc::sync::Arc;
 use core::cmp;
 use core::ops::Deref;
 use core::mem::replace;
@@ -201,6 +202,39 @@ pub(crate) enum ClaimEvent {
 	},
 }
 
+/// A claim whose transaction is built by the [`ChainMonitor`] to aggregate it with claims of other
+/// channels. Claims are only deferred to the [`ChainMonitor`] while they aren't time-sensitive.
+///
+/// [`ChainMonitor`]: crate::chain::chainmonitor::ChainMonitor
+#[derive(Clone)]
+pub(crate) struct AggregableClaim {
+	pub(crate) claim_id: ClaimId,
+	pub(crate) package: PackageTemplate,
+	/// The script the claimed funds must be sent to.
+	pub(crate) destination_script: Script,
+	/// Whether the claim's height timer expired and its feerate should be bumped.
+	pub(crate) needs_bump: bool,
+}
+
+/// The latest transaction built by the [`ChainMonitor`] aggregating a deferred claim with claims of
+/// other channels. It is tracked such that the [`ChainMonitor`] can rebuild its view of aggregated
+/// claims upon restart, and such that a claim we end up making on our own replaces it.
+///
+/// [`ChainMonitor`]: crate::chain::chainmonitor::ChainMonitor
+#[derive(Clone, PartialEq, Eq)]
+pub(crate) struct AggregatedClaimTx {
+	pub(crate) tx: Transaction,
+	pub(crate) feerate: u64,
+	/// The absolute fee paid by the transaction.
+	pub(crate) fee: u64,
+}
+
+impl_writeable_tlv_based!(AggregatedClaimTx, {
+	(0, tx, required),
+	(2, feerate, required),
+	(4, fee, required),
+});
+
 /// Represents the different ways an output can be claimed (i.e., spent to an address under our
 /// control) onchain.
 pub(crate) enum OnchainClaim {
@@ -270,12 +304,27 @@ pub struct OnchainTxHandler<ChannelSigner: WriteableEcdsaChannelSigner> {
 
 	onchain_events_awaiting_threshold_conf: Vec<OnchainEventEntry>,
 
+	// Whether the `ChainMonitor` aggregates non-time-sensitive claims across channels, in which case
+	// we don't broadcast claims for such requests ourselves and track them in `deferred_claims`
+	// instead. Set by the `ChainMonitor` on each startup.
+	aggregate_claims_across_channels: bool,
+	// The pending claim requests we've deferred to the `ChainMonitor`, until they either are
+	// satisfied or become time-sensitive.
+	deferred_claims: HashSet<ClaimId>,
+	// The latest transactions the `ChainMonitor` built for deferred claims.
+	aggregated_claim_txn: HashMap<ClaimId, AggregatedClaimTx>,
+
+	// Decides how quickly the feerate of our claims escalates as their deadline approaches. Set by
+	// the `ChainMonitor` on each startup.
+	fee_bump_strategy: Arc<dyn FeeBumpStrategy + Send + Sync>,
+
 	pub(super) secp_ctx: Secp256k1<secp256k1::All>,
 }
 
 impl<ChannelSigner: WriteableEcdsaChannelSigner> PartialEq for OnchainTxHandler<ChannelSigner> {
 	fn eq(&self, other: &Self) -> bool {
-		// `signer`, `secp_ctx`, and `pending_claim_events` are excluded on purpose.
+		// `signer`, `secp_ctx`, `pending_claim_events`, `aggregate_claims_across_channels`, and
+		// `fee_bump_strategy` are excluded on purpose.
 		self.destination_script == other.destination_script &&
 			self.holder_commitment == other.holder_commitment &&
 			self.holder_htlc_sigs == other.holder_htlc_sigs &&
@@ -285,7 +334,9 @@ impl<ChannelSigner: WriteableEcdsaChannelSigner> PartialEq for OnchainTxHandler<
 			self.pending_claim_requests == other.pending_claim_requests &&
 			self.claimable_outpoints == other.claimable_outpoints &&
 			self.locktimed_packages == other.locktimed_packages &&
-			self.onchain_events_awaiting_threshold_conf == other.onchain_events_awaiting_threshold_conf
+			self.onchain_events_awaiting_threshold_conf == other.onchain_events_awaiting_threshold_conf &&
+			self.deferred_claims == other.deferred_claims &&
+			self.aggregated_claim_txn == other.aggregated_claim_txn
 	}
 }
 
@@ -338,7 +389,13 @@ impl<ChannelSigner: WriteableEcdsaChannelSigner> OnchainTxHandler<ChannelSigner>
 			entry.write(writer)?;
 		}
 
-		write_tlv_fields!(writer, {});
+		let deferred_claims: Vec<ClaimId> = self.deferred_claims.iter().cloned().collect();
+		let aggregated_claim_txn: Vec<(ClaimId, AggregatedClaimTx)> = self.aggregated_claim_txn.iter()
+			.map(|(claim_id, aggregated_tx)| (*claim_id, aggregated_tx.clone())).collect();
+		write_tlv_fields!(writer, {
+			(1, deferred_claims, optional_vec),
+			(3, aggregated_claim_txn, optional_vec),
+		});
 		Ok(())
 	}
 }
@@ -412,7 +469,12 @@ impl<'a, 'b, ES: EntropySource, SP: SignerProvider> ReadableArgs<(&'a ES, &'b SP
 			}
 		}
 
-		read_tlv_fields!(reader, {});
+		let mut deferred_claims: Option<Vec<ClaimId>> = Some(Vec::new());
+		let mut aggregated_claim_txn: Option<Vec<(ClaimId, AggregatedClaimTx)>> = Some(Vec::new());
+		read_tlv_fields!(reader, {
+			(1, deferred_claims, optional_vec),
+			(3, aggregated_claim_txn, optional_vec),
+		});
 
 		let mut secp_ctx = Secp256k1::new();
 		secp_ctx.seeded_randomize(&entropy_source.get_secure_random_bytes());
@@ -430,6 +492,10 @@ impl<'a, 'b, ES: EntropySource, SP: SignerProvider> ReadableArgs<(&'a ES, &'b SP
 			pending_claim_requests,
 			onchain_events_awaiting_threshold_conf,
 			pending_claim_events: Vec::new(),
+			aggregate_claims_across_channels: false,
+			deferred_claims: deferred_claims.unwrap().into_iter().collect(),
+			aggregated_claim_txn: aggregated_claim_txn.unwrap().into_iter().collect(),
+			fee_bump_strategy: Arc::new(DefaultFeeBumpStrategy),
 			secp_ctx,
 		})
 	}
@@ -450,6 +516,10 @@ impl<ChannelSigner: WriteableEcdsaChannelSigner> OnchainTxHandler<ChannelSigner>
 			locktimed_packages: BTreeMap::new(),
 			onchain_events_awaiting_threshold_conf: Vec::new(),
 			pending_claim_events: Vec::new(),
+			aggregate_claims_across_channels: false,
+			deferred_claims: HashSet::new(),
+			aggregated_claim_txn: HashMap::new(),
+			fee_bump_strategy: Arc::new(DefaultFeeBumpStrategy),
 			secp_ctx,
 		}
 	}
@@ -468,6 +538,122 @@ impl<ChannelSigner: WriteableEcdsaChannelSigner> OnchainTxHandler<ChannelSigner>
 		events
 	}
 
+	pub(crate) fn set_aggregate_claims_across_channels(&mut self, aggregate_claims_across_channels: bool) {
+		self.aggregate_claims_across_channels = aggregate_claims_across_channels;
+	}
+
+	pub(crate) fn set_fee_bump_strategy(&mut self, fee_bump_strategy: Arc<dyn FeeBumpStrategy + Send + Sync>) {
+		self.fee_bump_strategy = fee_bump_strategy;
+	}
+
+	/// Returns whether the claim for the given request should be left to the `ChainMonitor` to
+	/// aggregate it with claims of other channels. This is only the case for malleable, aggregable
+	/// requests not requiring external funding whose timelock expiration isn't imminent, i.e., the
+	/// same requests we'd aggregate within the channel. Revoked outputs are never deferred, as our
+	/// counterparty may spend them at any time.
+	fn should_defer_claim(&self, request: &PackageTemplate, cur_height: u32) -> bool {
+		self.aggregate_claims_across_channels && request.is_malleable() && request.aggregable() &&
+			!request.requires_external_funding() && !request.claims_revoked_output() &&
+			request.timelock() > cur_height + CLTV_SHARED_CLAIM_BUFFER
+	}
+
+	/// Returns whether the given claim is still deferred to the `ChainMonitor`, no longer deferring
+	/// it if it became time-sensitive such that we'll claim it on our own.
+	///
+	/// In the latter case, if the `ChainMonitor` already broadcast a transaction for the claim, the
+	/// feerate of the given `request` is raised such that our own claim replaces it.
+	fn is_claim_deferred<L: Deref>(
+		&mut self, claim_id: &ClaimId, request: &mut PackageTemplate, cur_height: u32, logger: &L,
+	) -> bool
+	where L::Target: Logger {
+		if !self.deferred_claims.contains(claim_id) {
+			return false;
+		}
+		if let Some(pending_request) = self.pending_claim_requests.get(claim_id) {
+			if self.should_defer_claim(pending_request, cur_height) {
+				return true;
+			}
+			log_info!(logger, "Claiming inputs {:?} on our own as their claim is no longer aggregated across channels",
+				pending_request.outpoints());
+		}
+		self.deferred_claims.remove(claim_id);
+		if let Some(aggregated_tx) = self.aggregated_claim_txn.remove(claim_id) {
+			// BIP 125 requires our claim to pay at least the absolute fee of the aggregated
+			// transaction it conflicts with, plus its own bandwidth at the incremental relay feerate.
+			let weight = request.package_weight(&self.destination_script) as u64;
+			let min_fee = aggregated_tx.fee + MIN_RELAY_FEE_SAT_PER_1000_WEIGHT * weight / 1000;
+			let min_feerate = min_fee * 1000 / weight + 1;
+			if request.previous_feerate() < min_feerate {
+				log_debug!(logger, "Raising feerate of claim to {} sat/kW to replace aggregated claim transaction {}",
+					min_feerate, aggregated_tx.tx.txid());
+				request.set_feerate(min_feerate);
+				if let Some(pending_request) = self.pending_claim_requests.get_mut(claim_id) {
+					pending_request.set_feerate(min_feerate);
+				}
+			}
+		}
+		false
+	}
+
+	/// Returns the claims deferred to the `ChainMonitor` which it should currently (re)build a
+	/// transaction for.
+	pub(crate) fn get_aggregable_claims(&self, cur_height: u32) -> Vec<AggregableClaim> {
+		let mut claims = Vec::with_capacity(self.deferred_claims.len());
+		for claim_id in self.deferred_claims.iter() {
+			if let Some(request) = self.pending_claim_requests.get(claim_id) {
+				if request.outpoints().is_empty() || !self.should_defer_claim(request, cur_height) ||
+					self.all_inputs_have_confirmed_spend(request)
+				{
+					continue;
+				}
+				claims.push(AggregableClaim {
+					claim_id: *claim_id,
+					package: request.clone(),
+					destination_script: self.destination_script.clone(),
+					needs_bump: cur_height >= request.timer(),
+				});
+			}
+		}
+		claims
+	}
+
+	/// Signs the inputs of a transaction built by the `ChainMonitor` spending the outputs of a
+	/// deferred claim, tracking the transaction's feerate to bump it in the future. Returns false
+	/// if the claim is no longer pending or signing failed.
+	///
+	/// Once all inputs are signed, the transaction should be tracked via
+	/// [`Self::track_aggregated_claim`].
+	pub(crate) fn sign_aggregated_claim(
+		&mut self, claim_id: &ClaimId, tx: &mut Transaction, feerate: u64, cur_height: u32,
+	) -> bool {
+		let request = match self.pending_claim_requests.get(claim_id) {
+			Some(request) if self.deferred_claims.contains(claim_id) => request.clone(),
+			_ => return false,
+		};
+		if !request.finalize_aggregated_inputs(tx, self) {
+			return false;
+		}
+		if let Some(request) = self.pending_claim_requests.get_mut(claim_id) {
+			request.set_feerate(feerate);
+			request.set_timer(request.get_height_timer(cur_height, &*self.fee_bump_strategy));
+		}
+		true
+	}
+
+	/// Tracks the fully signed transaction the `ChainMonitor` broadcast for a deferred claim.
+	pub(crate) fn track_aggregated_claim(&mut self, claim_id: &ClaimId, aggregated_tx: AggregatedClaimTx) {
+		if self.deferred_claims.contains(claim_id) {
+			self.aggregated_claim_txn.insert(*claim_id, aggregated_tx);
+		}
+	}
+
+	/// Returns the latest transactions the `ChainMonitor` broadcast for deferred claims.
+	pub(crate) fn get_aggregated_claim_txn(&self) -> Vec<(ClaimId, AggregatedClaimTx)> {
+		self.deferred_claims.iter().filter_map(|claim_id|
+			self.aggregated_claim_txn.get(claim_id).map(|aggregated_tx| (*claim_id, aggregated_tx.clone()))
+		).collect()
+	}
+
 	/// Triggers rebroadcasts/fee-bumps of pending claims from a force-closed channel. This is
 	/// crucial in preventing certain classes of pinning attacks, detecting substantial mempool
 	/// feerate changes between blocks, and ensuring reliability if broadcasting fails. We recommend
@@ -484,11 +670,13 @@ impl<ChannelSigner: WriteableEcdsaChannelSigner> OnchainTxHandler<ChannelSigner>
 	{
 		let mut bump_requests = Vec::with_capacity(self.pending_claim_requests.len());
 		for (claim_id, request) in self.pending_claim_requests.iter() {
-			let inputs = request.outpoints();
-			log_info!(logger, "Triggering rebroadcast/fee-bump for request with inputs {:?}", inputs);
 			bump_requests.push((*claim_id, request.clone()));
 		}
-		for (claim_id, request) in bump_requests {
+		for (claim_id, mut request) in bump_requests {
+			if self.is_claim_deferred(&claim_id, &mut request, current_height, logger) {
+				continue;
+			}
+			log_info!(logger, "Triggering rebroadcast/fee-bump for request with inputs {:?}", request.outpoints());
 			self.generate_claim(current_height, &request, false /* force_feerate_bump */, fee_estimator, logger)
 				.map(|(_, new_feerate, claim)| {
 					let mut bumped_feerate = false;
@@ -520,6 +708,33 @@ impl<ChannelSigner: WriteableEcdsaChannelSigner> OnchainTxHandler<ChannelSigner>
 		}
 	}
 
+	/// Returns whether we've seen a spend of all of the request's outpoints confirm.
+	fn all_inputs_have_confirmed_spend(&self, request: &PackageTemplate) -> bool {
+		let mut all_inputs_have_confirmed_spend = true;
+		for outpoint in request.outpoints() {
+			if let Some((request_claim_id, _)) = self.claimable_outpoints.get(outpoint) {
+				// We check for outpoint spends within claims individually rather than as a set
+				// since requests can have outpoints split off.
+				if !self.onchain_events_awaiting_threshold_conf.iter()
+					.any(|event_entry| if let OnchainEvent::Claim { claim_id } = event_entry.event {
+						*request_claim_id == claim_id
+					} else {
+						// The onchain event is not a claim, keep seeking until we find one.
+						false
+					})
+				{
+					// Either we had no `OnchainEvent::Claim`, or we did but none matched the
+					// outpoint's registered spend.
+					all_inputs_have_confirmed_spend = false;
+				}
+			} else {
+				// The request's outpoint spend does not exist yet.
+				all_inputs_have_confirmed_spend = false;
+			}
+		}
+		all_inputs_have_confirmed_spend
+	}
+
 	/// Lightning security model (i.e being able to redeem/timeout HTLC or penalize counterparty
 	/// onchain) lays on the assumption of claim transactions getting confirmed before timelock
 	/// expiration (CSV or CLTV following cases). In case of high-fee spikes, claim tx may get stuck
@@ -548,39 +763,18 @@ impl<ChannelSigner: WriteableEcdsaChannelSigner> OnchainTxHandler<ChannelSigner>
 		// don't need to continue generating more claims. We'll keep tracking the request to fully
 		// remove it once it reaches the confirmation threshold, or to generate a new claim if the
 		// transaction is reorged out.
-		let mut all_inputs_have_confirmed_spend = true;
-		for outpoint in request_outpoints.iter() {
-			if let Some((request_claim_id, _)) = self.claimable_outpoints.get(*outpoint) {
-				// We check for outpoint spends within claims individually rather than as a set
-				// since requests can have outpoints split off.
-				if !self.onchain_events_awaiting_threshold_conf.iter()
-					.any(|event_entry| if let OnchainEvent::Claim { claim_id } = event_entry.event {
-						*request_claim_id == claim_id
-					} else {
-						// The onchain event is not a claim, keep seeking until we find one.
-						false
-					})
-				{
-					// Either we had no `OnchainEvent::Claim`, or we did but none matched the
-					// outpoint's registered spend.
-					all_inputs_have_confirmed_spend = false;
-				}
-			} else {
-				// The request's outpoint spend does not exist yet.
-				all_inputs_have_confirmed_spend = false;
-			}
-		}
-		if all_inputs_have_confirmed_spend {
+		if self.all_inputs_have_confirmed_spend(cached_request) {
 			return None;
 		}
 
 		// Compute new height timer to decide when we need to regenerate a new bumped version of the claim tx (if we
 		// didn't receive confirmation of it before, or not enough reorg-safe depth on top of it).
-		let new_timer = cached_request.get_height_timer(cur_height);
+		let new_timer = cached_request.get_height_timer(cur_height, &*self.fee_bump_strategy);
 		if cached_request.is_malleable() {
 			if cached_request.requires_external_funding() {
 				let target_feerate_sat_per_1000_weight = cached_request.compute_package_feerate(
-					fee_estimator, ConfirmationTarget::HighPriority, force_feerate_bump
+					cur_height, fee_estimator, ConfirmationTarget::HighPriority, force_feerate_bump,
+					&*self.fee_bump_strategy,
 				);
 				if let Some(htlcs) = cached_request.construct_malleable_package_with_external_funding(self) {
 					return Some((
@@ -599,8 +793,8 @@ impl<ChannelSigner: WriteableEcdsaChannelSigner> OnchainTxHandler<ChannelSigner>
 
 			let predicted_weight = cached_request.package_weight(&self.destination_script);
 			if let Some((output_value, new_feerate)) = cached_request.compute_package_output(
-				predicted_weight, self.destination_script.dust_value().to_sat(),
-				force_feerate_bump, fee_estimator, logger,
+				cur_height, predicted_weight, self.destination_script.dust_value().to_sat(),
+				force_feerate_bump, fee_estimator, &*self.fee_bump_strategy, logger,
 			) {
 				assert!(new_feerate != 0);
 
@@ -632,8 +826,9 @@ impl<ChannelSigner: WriteableEcdsaChannelSigner> OnchainTxHandler<ChannelSigner>
 						"Holder commitment transaction mismatch");
 
 					let conf_target = ConfirmationTarget::HighPriority;
-					let package_target_feerate_sat_per_1000_weight = cached_request
-						.compute_package_feerate(fee_estimator, conf_target, force_feerate_bump);
+					let package_target_feerate_sat_per_1000_weight = cached_request.compute_package_feerate(
+						cur_height, fee_estimator, conf_target, force_feerate_bump, &*self.fee_bump_strategy,
+					);
 					if let Some(input_amount_sat) = output.funding_amount {
 						let fee_sat = input_amount_sat - tx.output.iter().map(|output| output.value).sum::<u64>();
 						let commitment_tx_feerate_sat_per_1000_weight =
@@ -751,6 +946,27 @@ impl<ChannelSigner: WriteableEcdsaChannelSigner> OnchainTxHandler<ChannelSigner>
 		// Generate claim transactions and track them to bump if necessary at
 		// height timer expiration (i.e in how many blocks we're going to take action).
 		for mut req in preprocessed_requests {
+			if self.should_defer_claim(&req, cur_height) {
+				// Leave it to the `ChainMonitor` to build a transaction for the claim once it
+				// aggregates it with claims of other channels. As there's no transaction to derive
+				// the claim ID from yet, we commit to the set of outpoints to claim instead.
+				let mut engine = Sha256::engine();
+				for outpoint in req.outpoints() {
+					engine.input(&outpoint.txid.into_inner());
+					engine.input(&outpoint.vout.to_be_bytes());
+				}
+				let claim_id = ClaimId(Sha256::from_engine(engine).into_inner());
+				log_info!(logger, "Deferring claim of inputs {:?} to aggregate it with claims of other channels", req.outpoints());
+				req.set_timer(cur_height);
+				debug_assert!(!self.pending_claim_requests.contains_key(&claim_id));
+				for k in req.outpoints() {
+					log_info!(logger, "Registering claiming request for {}:{}", k.txid, k.vout);
+					self.claimable_outpoints.insert(*k, (claim_id, conf_height));
+				}
+				self.pending_claim_requests.insert(claim_id, req);
+				self.deferred_claims.insert(claim_id);
+				continue;
+			}
 			if let Some((new_timer, new_feerate, claim)) = self.generate_claim(
 				cur_height, &req, true /* force_feerate_bump */, &*fee_estimator, &*logger,
 			) {
@@ -927,6 +1143,8 @@ impl<ChannelSigner: WriteableEcdsaChannelSigner> OnchainTxHandler<ChannelSigner>
 								assert!(num_existing == 0 || num_existing == 1);
 							}
 							self.pending_claim_events.retain(|(id, _)| *id != claim_id);
+							self.deferred_claims.remove(&claim_id);
+							self.aggregated_claim_txn.remove(&claim_id);
 						}
 					},
 					OnchainEvent::ContentiousOutpoint { package } => {
@@ -940,16 +1158,20 @@ impl<ChannelSigner: WriteableEcdsaChannelSigner> OnchainTxHandler<ChannelSigner>
 			}
 		}
 
-		// Check if any pending claim request must be rescheduled
+		// Check if any pending claim request must be rescheduled. Deferred claims are included to
+		// claim them on our own as soon as they become time-sensitive.
 		for (claim_id, request) in self.pending_claim_requests.iter() {
-			if cur_height >= request.timer() {
+			if cur_height >= request.timer() || self.deferred_claims.contains(claim_id) {
 				bump_candidates.insert(*claim_id, request.clone());
 			}
 		}
 
 		// Build, bump and rebroadcast tx accordingly
 		log_trace!(logger, "Bumping {} candidates", bump_candidates.len());
-		for (claim_id, request) in bump_candidates.iter() {
+		for (claim_id, request) in bump_candidates.iter_mut() {
+			if self.is_claim_deferred(claim_id, request, cur_height, logger) {
+				continue;
+			}
 			if let Some((new_timer, new_feerate, bump_claim)) = self.generate_claim(
 				cur_height, &request, true /* force_feerate_bump */, &*fee_estimator, &*logger,
 			) {
@@ -1033,6 +1255,9 @@ impl<ChannelSigner: WriteableEcdsaChannelSigner> OnchainTxHandler<ChannelSigner>
 		for ((_claim_id, _), ref mut request) in bump_candidates.iter_mut() {
 			// `height` is the height being disconnected, so our `current_height` is 1 lower.
 			let current_height = height - 1;
+			if self.is_claim_deferred(_claim_id, request, current_height, &logger) {
+				continue;
+			}
 			if let Some((new_timer, new_feerate, bump_claim)) = self.generate_claim(
 				current_height, &request, true /* force_feerate_bump */, fee_estimator, &&*logger
 			) {
@@ -1069,6 +1294,8 @@ impl<ChannelSigner: WriteableEcdsaChannelSigner> OnchainTxHandler<ChannelSigner>
 			} else { true });
 		for req in remove_request {
 			self.pending_claim_requests.remove(&req);
+			self.deferred_claims.remove(&req);
+			self.aggregated_claim_txn.remove(&req);
 		}
 	}
 
@@ -1206,6 +1433,15 @@ impl<ChannelSigner: WriteableEcdsaChannelSigner> OnchainTxHandler<ChannelSigner>
 		&self.channel_transaction_parameters.channel_type_features
 	}
 
+	/// Switches the channel to a new commitment transaction format, e.g. upon an upgrade to anchor
+	/// outputs.
+	///
+	/// Our signer keeps the channel parameters it was provided with, as the channel type is passed
+	/// along with any signing request depending on it.
+	pub(crate) fn upgrade_channel_type(&mut self, channel_type_features: ChannelTypeFeatures) {
+		self.channel_transaction_parameters.channel_type_features = channel_type_features;
+	}
+
 	#[cfg(any(test,feature = "unsafe_revoked_tx_signing"))]
 	pub(crate) fn unsafe_get_fully_signed_htlc_tx(&mut self, outp: &::bitcoin::OutPoint, preimage: &Option<PaymentPreimage>) -> Option<Transaction> {
 		let latest_had_sigs = self.holder_htlc_sigs.is_some();
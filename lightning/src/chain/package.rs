@@ -24,7 +24,7 @@ use crate::ln::PaymentPreimage;
 use crate::ln::chan_utils::{TxCreationKeys, HTLCOutputInCommitment};
 use crate::ln::chan_utils;
 use crate::ln::msgs::DecodeError;
-use crate::chain::chaininterface::{FeeEstimator, ConfirmationTarget, MIN_RELAY_FEE_SAT_PER_1000_WEIGHT};
+use crate::chain::chaininterface::{FeeBumpStrategy, FeeEstimator, ConfirmationTarget, MIN_RELAY_FEE_SAT_PER_1000_WEIGHT};
 use crate::sign::WriteableEcdsaChannelSigner;
 use crate::chain::onchaintx::{ExternalHTLCClaim, OnchainTxHandler};
 use crate::util::logger::Logger;
@@ -99,13 +99,6 @@ pub(crate) fn verify_channel_type_features(channel_type_features: &Option<Channe
 // number_of_witness_elements + sig_length + revocation_sig + true_length + op_true + witness_script_length + witness_script
 pub(crate) const WEIGHT_REVOKED_OUTPUT: u64 = 1 + 1 + 73 + 1 + 1 + 1 + 77;
 
-/// Height delay at which transactions are fee-bumped/rebroadcasted with a low priority.
-const LOW_FREQUENCY_BUMP_INTERVAL: u32 = 15;
-/// Height delay at which transactions are fee-bumped/rebroadcasted with a middle priority.
-const MIDDLE_FREQUENCY_BUMP_INTERVAL: u32 = 3;
-/// Height delay at which transactions are fee-bumped/rebroadcasted with a high priority.
-const HIGH_FREQUENCY_BUMP_INTERVAL: u32 = 1;
-
 /// A struct to describe a revoked output and corresponding information to generate a solving
 /// witness spending a commitment `to_local` output or a second-stage HTLC transaction output.
 ///
@@ -166,6 +159,10 @@ pub(crate) struct RevokedHTLCOutput {
 	weight: u64,
 	amount: u64,
 	htlc: HTLCOutputInCommitment,
+	/// The channel type of the revoked commitment transaction, which may differ from the
+	/// channel's current one if it was upgraded since. Only `None` for outputs tracked prior to
+	/// LDK 0.0.117, in which case the channel's current type applies.
+	channel_type_features: Option<ChannelTypeFeatures>,
 }
 
 impl RevokedHTLCOutput {
@@ -178,7 +175,8 @@ impl RevokedHTLCOutput {
 			per_commitment_key,
 			weight,
 			amount,
-			htlc
+			htlc,
+			channel_type_features: Some(channel_type_features.clone()),
 		}
 	}
 }
@@ -191,6 +189,7 @@ impl_writeable_tlv_based!(RevokedHTLCOutput, {
 	(8, weight, required),
 	(10, amount, required),
 	(12, htlc, required),
+	(13, channel_type_features, option),
 });
 
 /// A struct to describe a HTLC output on a counterparty commitment transaction.
@@ -567,9 +566,11 @@ impl PackageSolvingData {
 			},
 			PackageSolvingData::RevokedHTLCOutput(ref outp) => {
 				let chan_keys = TxCreationKeys::derive_new(&onchain_handler.secp_ctx, &outp.per_commitment_point, &outp.counterparty_delayed_payment_base_key, &outp.counterparty_htlc_base_key, &onchain_handler.signer.pubkeys().revocation_basepoint, &onchain_handler.signer.pubkeys().htlc_basepoint);
-				let witness_script = chan_utils::get_htlc_redeemscript_with_explicit_keys(&outp.htlc, &onchain_handler.channel_type_features(), &chan_keys.broadcaster_htlc_key, &chan_keys.countersignatory_htlc_key, &chan_keys.revocation_key);
+				let channel_type_features = outp.channel_type_features.clone()
+					.unwrap_or_else(|| onchain_handler.channel_type_features().clone());
+				let witness_script = chan_utils::get_htlc_redeemscript_with_explicit_keys(&outp.htlc, &channel_type_features, &chan_keys.broadcaster_htlc_key, &chan_keys.countersignatory_htlc_key, &chan_keys.revocation_key);
 				//TODO: should we panic on signer failure ?
-				if let Ok(sig) = onchain_handler.signer.sign_justice_revoked_htlc(&bumped_tx, i, outp.amount, &outp.per_commitment_key, &outp.htlc, &onchain_handler.secp_ctx) {
+				if let Ok(sig) = onchain_handler.signer.sign_justice_revoked_htlc(bumped_tx, i, outp.amount, &outp.per_commitment_key, &outp.htlc, &channel_type_features, &onchain_handler.secp_ctx) {
 					let mut ser_sig = sig.serialize_der().to_vec();
 					ser_sig.push(EcdsaSighashType::All as u8);
 					bumped_tx.input[i].witness.push(ser_sig);
@@ -579,9 +580,9 @@ impl PackageSolvingData {
 			},
 			PackageSolvingData::CounterpartyOfferedHTLCOutput(ref outp) => {
 				let chan_keys = TxCreationKeys::derive_new(&onchain_handler.secp_ctx, &outp.per_commitment_point, &outp.counterparty_delayed_payment_base_key, &outp.counterparty_htlc_base_key, &onchain_handler.signer.pubkeys().revocation_basepoint, &onchain_handler.signer.pubkeys().htlc_basepoint);
-				let witness_script = chan_utils::get_htlc_redeemscript_with_explicit_keys(&outp.htlc, &onchain_handler.channel_type_features(), &chan_keys.broadcaster_htlc_key, &chan_keys.countersignatory_htlc_key, &chan_keys.revocation_key);
+				let witness_script = chan_utils::get_htlc_redeemscript_with_explicit_keys(&outp.htlc, &outp.channel_type_features, &chan_keys.broadcaster_htlc_key, &chan_keys.countersignatory_htlc_key, &chan_keys.revocation_key);
 
-				if let Ok(sig) = onchain_handler.signer.sign_counterparty_htlc_transaction(&bumped_tx, i, &outp.htlc.amount_msat / 1000, &outp.per_commitment_point, &outp.htlc, &onchain_handler.secp_ctx) {
+				if let Ok(sig) = onchain_handler.signer.sign_counterparty_htlc_transaction(bumped_tx, i, &outp.htlc.amount_msat / 1000, &outp.per_commitment_point, &outp.htlc, &outp.channel_type_features, &onchain_handler.secp_ctx) {
 					let mut ser_sig = sig.serialize_der().to_vec();
 					ser_sig.push(EcdsaSighashType::All as u8);
 					bumped_tx.input[i].witness.push(ser_sig);
@@ -591,9 +592,9 @@ impl PackageSolvingData {
 			},
 			PackageSolvingData::CounterpartyReceivedHTLCOutput(ref outp) => {
 				let chan_keys = TxCreationKeys::derive_new(&onchain_handler.secp_ctx, &outp.per_commitment_point, &outp.counterparty_delayed_payment_base_key, &outp.counterparty_htlc_base_key, &onchain_handler.signer.pubkeys().revocation_basepoint, &onchain_handler.signer.pubkeys().htlc_basepoint);
-				let witness_script = chan_utils::get_htlc_redeemscript_with_explicit_keys(&outp.htlc, &onchain_handler.channel_type_features(), &chan_keys.broadcaster_htlc_key, &chan_keys.countersignatory_htlc_key, &chan_keys.revocation_key);
+				let witness_script = chan_utils::get_htlc_redeemscript_with_explicit_keys(&outp.htlc, &outp.channel_type_features, &chan_keys.broadcaster_htlc_key, &chan_keys.countersignatory_htlc_key, &chan_keys.revocation_key);
 
-				if let Ok(sig) = onchain_handler.signer.sign_counterparty_htlc_transaction(&bumped_tx, i, &outp.htlc.amount_msat / 1000, &outp.per_commitment_point, &outp.htlc, &onchain_handler.secp_ctx) {
+				if let Ok(sig) = onchain_handler.signer.sign_counterparty_htlc_transaction(bumped_tx, i, &outp.htlc.amount_msat / 1000, &outp.per_commitment_point, &outp.htlc, &outp.channel_type_features, &onchain_handler.secp_ctx) {
 					let mut ser_sig = sig.serialize_der().to_vec();
 					ser_sig.push(EcdsaSighashType::All as u8);
 					bumped_tx.input[i].witness.push(ser_sig);
@@ -863,6 +864,40 @@ impl PackageTemplate {
 		let output_weight = (8 + 1 + destination_script.len()) * WITNESS_SCALE_FACTOR;
 		inputs_weight + witnesses_weight + transaction_weight + output_weight
 	}
+	/// Gets the weight of the inputs claiming the package's outputs, including their witnesses,
+	/// as they'd appear in any transaction, only valid for malleable packages.
+	pub(crate) fn inputs_weight(&self) -> usize {
+		debug_assert!(self.is_malleable());
+		// previous_out_point: 36 bytes ; var_int: 1 byte ; sequence: 4 bytes
+		self.inputs.iter().map(|(_, outp)| 41 * WITNESS_SCALE_FACTOR + outp.weight()).sum()
+	}
+	/// Checks whether the package may share a transaction with `other`, which may belong to a
+	/// different channel.
+	pub(crate) fn can_aggregate_with(&self, other: &PackageTemplate) -> bool {
+		if !self.is_malleable() || !other.is_malleable() || !self.aggregable || !other.aggregable {
+			return false;
+		}
+		match (self.inputs.first(), other.inputs.first()) {
+			(Some((_, lead_input)), Some((_, other_lead_input))) => lead_input.is_compatible(other_lead_input),
+			_ => false,
+		}
+	}
+	/// Signs the inputs of `tx` claiming the package's outputs, which may only be a subset of all
+	/// of its inputs if the package was aggregated with those of other channels. Returns false if
+	/// any of the package's outputs is not spent by `tx` or if signing failed.
+	pub(crate) fn finalize_aggregated_inputs<Signer: WriteableEcdsaChannelSigner>(
+		&self, tx: &mut Transaction, onchain_handler: &mut OnchainTxHandler<Signer>,
+	) -> bool {
+		debug_assert!(self.is_malleable());
+		for (outpoint, out) in self.inputs.iter() {
+			let idx = match tx.input.iter().position(|input| input.previous_output == *outpoint) {
+				Some(idx) => idx,
+				None => return false,
+			};
+			if !out.finalize_input(tx, idx, onchain_handler) { return false; }
+		}
+		true
+	}
 	pub(crate) fn construct_malleable_package_with_external_funding<Signer: WriteableEcdsaChannelSigner>(
 		&self, onchain_handler: &mut OnchainTxHandler<Signer>,
 	) -> Option<Vec<ExternalHTLCClaim>> {
@@ -927,22 +962,18 @@ impl PackageTemplate {
 	/// output detection, we generate a first version of a claim tx and associate to it a height timer. A height timer is an absolute block
 	/// height that once reached we should generate a new bumped "version" of the claim tx to be sure that we safely claim outputs before
 	/// that our counterparty can do so. If timelock expires soon, height timer is going to be scaled down in consequence to increase
-	/// frequency of the bump and so increase our bets of success.
-	pub(crate) fn get_height_timer(&self, current_height: u32) -> u32 {
-		if self.soonest_conf_deadline <= current_height + MIDDLE_FREQUENCY_BUMP_INTERVAL {
-			return current_height + HIGH_FREQUENCY_BUMP_INTERVAL
-		} else if self.soonest_conf_deadline - current_height <= LOW_FREQUENCY_BUMP_INTERVAL {
-			return current_height + MIDDLE_FREQUENCY_BUMP_INTERVAL
-		}
-		current_height + LOW_FREQUENCY_BUMP_INTERVAL
+	/// frequency of the bump and so increase our bets of success, as decided by the `fee_bump_strategy`.
+	pub(crate) fn get_height_timer(&self, current_height: u32, fee_bump_strategy: &dyn FeeBumpStrategy) -> u32 {
+		current_height + cmp::max(fee_bump_strategy.blocks_until_bump(current_height, self.soonest_conf_deadline), 1)
 	}
 
 	/// Returns value in satoshis to be included as package outgoing output amount and feerate
 	/// which was used to generate the value. Will not return less than `dust_limit_sats` for the
 	/// value.
 	pub(crate) fn compute_package_output<F: Deref, L: Deref>(
-		&self, predicted_weight: usize, dust_limit_sats: u64, force_feerate_bump: bool,
-		fee_estimator: &LowerBoundedFeeEstimator<F>, logger: &L,
+		&self, current_height: u32, predicted_weight: usize, dust_limit_sats: u64,
+		force_feerate_bump: bool, fee_estimator: &LowerBoundedFeeEstimator<F>,
+		fee_bump_strategy: &dyn FeeBumpStrategy, logger: &L,
 	) -> Option<(u64, u64)>
 	where
 		F::Target: FeeEstimator,
@@ -953,8 +984,13 @@ impl PackageTemplate {
 		assert!(dust_limit_sats as i64 > 0, "Output script must be broadcastable/have a 'real' dust limit.");
 		// If old feerate is 0, first iteration of this claim, use normal fee calculation
 		if self.feerate_previous != 0 {
+			let forced_feerate = if force_feerate_bump {
+				Some(fee_bump_strategy.bumped_feerate_sat_per_1000_weight(
+					self.feerate_previous, current_height, self.soonest_conf_deadline, input_amounts,
+				))
+			} else { None };
 			if let Some((new_fee, feerate)) = feerate_bump(
-				predicted_weight, input_amounts, self.feerate_previous, force_feerate_bump,
+				predicted_weight, input_amounts, self.feerate_previous, forced_feerate,
 				fee_estimator, logger,
 			) {
 				return Some((cmp::max(input_amounts as i64 - new_fee as i64, dust_limit_sats as i64) as u64, feerate));
@@ -968,11 +1004,12 @@ impl PackageTemplate {
 	}
 
 	/// Computes a feerate based on the given confirmation target. If a previous feerate was used,
-	/// the new feerate is below it, and `force_feerate_bump` is set, we'll use a 25% increase of
-	/// the previous feerate instead of the new feerate.
+	/// the new feerate is below it, and `force_feerate_bump` is set, we'll bump the previous
+	/// feerate as decided by the `fee_bump_strategy` instead of using the new feerate.
 	pub(crate) fn compute_package_feerate<F: Deref>(
-		&self, fee_estimator: &LowerBoundedFeeEstimator<F>, conf_target: ConfirmationTarget,
-		force_feerate_bump: bool,
+		&self, current_height: u32, fee_estimator: &LowerBoundedFeeEstimator<F>,
+		conf_target: ConfirmationTarget, force_feerate_bump: bool,
+		fee_bump_strategy: &dyn FeeBumpStrategy,
 	) -> u32 where F::Target: FeeEstimator {
 		let feerate_estimate = fee_estimator.bounded_sat_per_1000_weight(conf_target);
 		if self.feerate_previous != 0 {
@@ -982,14 +1019,24 @@ impl PackageTemplate {
 			} else if !force_feerate_bump {
 				self.feerate_previous.try_into().unwrap_or(u32::max_value())
 			} else {
-				// ...else just increase the previous feerate by 25% (because that's a nice number)
-				(self.feerate_previous + (self.feerate_previous / 4)).try_into().unwrap_or(u32::max_value())
+				// ...else bump the previous feerate as decided by the strategy.
+				let bumped_feerate = fee_bump_strategy.bumped_feerate_sat_per_1000_weight(
+					self.feerate_previous, current_height, self.soonest_conf_deadline, self.package_amount(),
+				);
+				cmp::max(bumped_feerate, self.feerate_previous).try_into().unwrap_or(u32::max_value())
 			}
 		} else {
 			feerate_estimate
 		}
 	}
 
+	/// Determines whether a package claims any revoked output, which our counterparty may race us
+	/// to spend regardless of the package's timelock.
+	pub(crate) fn claims_revoked_output(&self) -> bool {
+		self.inputs.iter().any(|input| matches!(input.1,
+			PackageSolvingData::RevokedOutput(..) | PackageSolvingData::RevokedHTLCOutput(..)))
+	}
+
 	/// Determines whether a package contains an input which must have additional external inputs
 	/// attached to help the spending transaction reach confirmation.
 	pub(crate) fn requires_external_funding(&self) -> bool {
@@ -1110,12 +1157,12 @@ fn compute_fee_from_spent_amounts<F: Deref, L: Deref>(input_amounts: u64, predic
 
 /// Attempt to propose a bumping fee for a transaction from its spent output's values and predicted
 /// weight. If feerates proposed by the fee-estimator have been increasing since last fee-bumping
-/// attempt, use them. If `force_feerate_bump` is set, we bump the feerate by 25% of the previous
-/// feerate, or just use the previous feerate otherwise. If a feerate bump did happen, we also
+/// attempt, use them. Otherwise, we use the `forced_feerate` given by the [`FeeBumpStrategy`] if
+/// any, or just use the previous feerate. If a feerate bump did happen, we also
 /// verify that those bumping heuristics respect BIP125 rules 3) and 4) and if required adjust the
 /// new fee to meet the RBF policy requirement.
 fn feerate_bump<F: Deref, L: Deref>(
-	predicted_weight: usize, input_amounts: u64, previous_feerate: u64, force_feerate_bump: bool,
+	predicted_weight: usize, input_amounts: u64, previous_feerate: u64, forced_feerate: Option<u64>,
 	fee_estimator: &LowerBoundedFeeEstimator<F>, logger: &L,
 ) -> Option<(u64, u64)>
 where
@@ -1126,18 +1173,18 @@ where
 	let (new_fee, new_feerate) = if let Some((new_fee, new_feerate)) = compute_fee_from_spent_amounts(input_amounts, predicted_weight, fee_estimator, logger) {
 		if new_feerate > previous_feerate {
 			(new_fee, new_feerate)
-		} else if !force_feerate_bump {
-			let previous_fee = previous_feerate * (predicted_weight as u64) / 1000;
-			(previous_fee, previous_feerate)
-		} else {
-			// ...else just increase the previous feerate by 25% (because that's a nice number)
-			let bumped_feerate = previous_feerate + (previous_feerate / 4);
+		} else if let Some(forced_feerate) = forced_feerate {
+			// ...else bump the previous feerate as decided by the strategy.
+			let bumped_feerate = cmp::max(forced_feerate, previous_feerate);
 			let bumped_fee = bumped_feerate * (predicted_weight as u64) / 1000;
 			if input_amounts <= bumped_fee {
-				log_warn!(logger, "Can't 25% bump new claiming tx, amount {} is too small", input_amounts);
+				log_warn!(logger, "Can't bump new claiming tx to {} sat/kW, amount {} is too small", bumped_feerate, input_amounts);
 				return None;
 			}
 			(bumped_fee, bumped_feerate)
+		} else {
+			let previous_fee = previous_feerate * (predicted_weight as u64) / 1000;
+			(previous_fee, previous_feerate)
 		}
 	} else {
 		log_warn!(logger, "Can't new-estimation bump new claiming tx, amount {} is too small", input_amounts);
@@ -15,9 +15,11 @@ use alloc::collections::BTreeMap;
 use core::ops::Deref;
 
 use crate::chain::chaininterface::{BroadcasterInterface, compute_feerate_sat_per_1000_weight, fee_for_weight, FEERATE_FLOOR_SATS_PER_KW};
-use crate::chain::ClaimId;
+use crate::chain::{ClaimId, Listen};
+use crate::chain::channelmonitor::ANTI_REORG_DELAY;
+use crate::chain::transaction::TransactionData;
 use crate::io_extras::sink;
-use crate::ln::channel::ANCHOR_OUTPUT_VALUE_SATOSHI;
+use crate::ln::channel::{ANCHOR_OUTPUT_VALUE_SATOSHI, COMMITMENT_TX_WEIGHT_PER_HTLC, commitment_tx_base_weight};
 use crate::ln::chan_utils;
 use crate::ln::chan_utils::{
 	ANCHOR_INPUT_WITNESS_WEIGHT, HTLC_SUCCESS_INPUT_ANCHOR_WITNESS_WEIGHT,
@@ -31,8 +33,10 @@ use crate::sync::Mutex;
 use crate::util::logger::Logger;
 
 use bitcoin::{OutPoint, PackedLockTime, PubkeyHash, Sequence, Script, Transaction, Txid, TxIn, TxOut, Witness, WPubkeyHash};
+use bitcoin::blockdata::block::BlockHeader;
 use bitcoin::blockdata::constants::WITNESS_SCALE_FACTOR;
 use bitcoin::consensus::Encodable;
+use bitcoin::hashes::Hash;
 use bitcoin::secp256k1;
 use bitcoin::secp256k1::{PublicKey, Secp256k1};
 use bitcoin::secp256k1::ecdsa::Signature;
@@ -43,6 +47,40 @@ const BASE_INPUT_SIZE: u64 = 32 /* txid */ + 4 /* vout */ + 4 /* sequence */;
 
 const BASE_INPUT_WEIGHT: u64 = BASE_INPUT_SIZE * WITNESS_SCALE_FACTOR as u64;
 
+const P2WPKH_TXOUT_WEIGHT: u64 = (8 /* value */ + 1 /* script len */ + 22 /* script */) * WITNESS_SCALE_FACTOR as u64;
+
+const P2WPKH_INPUT_WEIGHT: u64 = BASE_INPUT_WEIGHT + EMPTY_SCRIPT_SIG_WEIGHT + Utxo::P2WPKH_WITNESS_WEIGHT;
+
+const TX_OVERHEAD_WEIGHT: u64 = (4 /* version */ + 1 /* input count */ + 1 /* output count */ +
+	4 /* locktime */) * WITNESS_SCALE_FACTOR as u64 + 2 /* segwit marker & flag */;
+
+/// Returns the worst-case amount of on-chain funds, in satoshis, required to get the transactions
+/// of a single anchor channel with `num_htlcs` pending HTLCs confirmed at the given feerate after
+/// our commitment transaction was broadcast.
+///
+/// This assumes that neither the commitment transaction nor its HTLC transactions carry any fees
+/// on their own and that each of them is bumped by spending a separate P2WPKH output of the
+/// wallet and paying change back to a P2WPKH script. Note that, as UTXOs are locked for the
+/// duration of a claim (see [`Wallet`]), the funds should be spread across multiple UTXOs to
+/// allow bumping several transactions at once.
+pub fn anchor_channel_reserve_sat(num_htlcs: usize, feerate_sat_per_1000_weight: u32) -> u64 {
+	let channel_type = ChannelTypeFeatures::anchors_zero_htlc_fee_and_dependencies();
+
+	let commitment_tx_weight = commitment_tx_base_weight(&channel_type) +
+		num_htlcs as u64 * COMMITMENT_TX_WEIGHT_PER_HTLC;
+	let anchor_tx_weight = TX_OVERHEAD_WEIGHT + BASE_INPUT_WEIGHT + EMPTY_SCRIPT_SIG_WEIGHT +
+		ANCHOR_INPUT_WITNESS_WEIGHT + P2WPKH_INPUT_WEIGHT + P2WPKH_TXOUT_WEIGHT;
+	let commitment_package_fee = fee_for_weight(feerate_sat_per_1000_weight,
+		commitment_tx_weight + anchor_tx_weight);
+
+	let htlc_tx_weight = core::cmp::max(
+		chan_utils::htlc_success_tx_weight(&channel_type), chan_utils::htlc_timeout_tx_weight(&channel_type)
+	) + P2WPKH_INPUT_WEIGHT + P2WPKH_TXOUT_WEIGHT;
+	let htlc_tx_fee = fee_for_weight(feerate_sat_per_1000_weight, htlc_tx_weight);
+
+	commitment_package_fee + num_htlcs as u64 * htlc_tx_fee
+}
+
 /// The parameters required to derive a channel signer via [`SignerProvider`].
 #[derive(Clone, Debug, PartialEq, Eq)]
 pub struct ChannelDerivationParameters {
@@ -424,8 +462,9 @@ impl Utxo {
 /// to cover its fees.
 #[derive(Clone, Debug)]
 pub struct CoinSelection {
-	/// The set of UTXOs (with at least 1 confirmation) to spend and use within a transaction
-	/// requiring additional fees.
+	/// The set of UTXOs (with at least 1 confirmation, unless they are the change of an earlier
+	/// transaction of ours, see [`WalletSource::can_sign_unconfirmed_change`]) to spend and use
+	/// within a transaction requiring additional fees.
 	pub confirmed_utxos: Vec<Utxo>,
 	/// An additional output tracking whether any change remained after coin selection. This output
 	/// should always have a value above dust for its given `script_pubkey`. It should not be
@@ -484,13 +523,175 @@ pub trait WalletSource {
 	fn get_change_script(&self) -> Result<Script, ()>;
 	/// Signs and provides the full [`TxIn::script_sig`] and [`TxIn::witness`] for all inputs within
 	/// the transaction known to the wallet (i.e., any provided via
-	/// [`WalletSource::list_confirmed_utxos`]).
+	/// [`WalletSource::list_confirmed_utxos`], as well as any unconfirmed change if
+	/// [`WalletSource::can_sign_unconfirmed_change`] returns true).
 	fn sign_tx(&self, tx: Transaction) -> Result<Transaction, ()>;
+	/// Returns whether [`WalletSource::sign_tx`] is able to sign for outputs of transactions it
+	/// previously signed which pay to a script returned by [`WalletSource::get_change_script`],
+	/// even before they confirm. If so, [`Wallet`] may use the change of its own earlier, still
+	/// unconfirmed transactions to fund new ones.
+	///
+	/// Returns false by default.
+	fn can_sign_unconfirmed_change(&self) -> bool {
+		false
+	}
+}
+
+/// The incremental relay feerate of Bitcoin Core's default mempool policy. A replacement
+/// transaction must pay for its own size at this feerate on top of the fees of the transaction it
+/// replaces.
+const INCREMENTAL_RELAY_FEE_SAT_PER_1000_WEIGHT: u32 = 250;
+
+/// The feerate at which we assume UTXOs could be spent in the future, used to weigh spending
+/// additional inputs now against creating change.
+const LONG_TERM_FEERATE_SAT_PER_1000_WEIGHT: u32 = 2500;
+
+/// The maximum number of branches explored by [`select_utxos_bnb`].
+const BNB_MAX_TRIES: usize = 100_000;
+
+/// The weight of the empty `OP_RETURN` output added by [`BumpTransactionEventHandler`] to
+/// transactions which would otherwise have no outputs.
+const DUMMY_OUTPUT_WEIGHT: u64 = (8 /* value */ + 1 /* script len */ + 1 /* OP_RETURN */) * WITNESS_SCALE_FACTOR as u64;
+
+/// Searches for a subset of `utxos`, each given along with the fee to spend it at the target
+/// feerate and at [`LONG_TERM_FEERATE_SAT_PER_1000_WEIGHT`], whose total value after fees lies
+/// within `target_sat` and `target_sat + cost_of_change_sat`, such that no change output is
+/// needed. Returns the indices of the subset found minimizing the waste metric, i.e., the excess
+/// value given up to fees plus the difference in fees between spending the inputs now and at the
+/// long-term feerate.
+///
+/// This is a depth-first branch-and-bound search exploring larger UTXOs first, as done by Bitcoin
+/// Core.
+fn select_utxos_bnb(utxos: &[(&Utxo, u64, u64)], target_sat: u64, cost_of_change_sat: u64) -> Option<Vec<usize>> {
+	// Each entry consists of the index into `utxos`, the UTXO's value after fees, and the waste of
+	// spending it now.
+	let mut pool: Vec<(usize, u64, i64)> = utxos.iter().enumerate()
+		.map(|(idx, (utxo, fee, long_term_fee))| (idx, utxo.output.value - fee, *fee as i64 - *long_term_fee as i64))
+		.collect();
+	pool.sort_unstable_by_key(|(_, value, _)| core::cmp::Reverse(*value));
+
+	let mut available_sat: u64 = pool.iter().map(|(_, value, _)| value).sum();
+	if available_sat < target_sat {
+		return None;
+	}
+	// If spending inputs now is more expensive than in the future, adding more of them only
+	// increases the waste, allowing us to prune branches early.
+	let is_feerate_high = pool.first().map(|(_, _, waste)| *waste > 0).unwrap_or(false);
+
+	let mut selection: Vec<usize> = Vec::new();
+	let mut selected_sat = 0;
+	let mut selection_waste = 0;
+	let mut best_selection: Option<(Vec<usize>, i64)> = None;
+	let mut pool_idx = 0;
+	for _ in 0..BNB_MAX_TRIES {
+		let best_waste = best_selection.as_ref().map(|(_, waste)| *waste).unwrap_or(i64::max_value());
+		let mut backtrack = false;
+		if selected_sat + available_sat < target_sat || selected_sat > target_sat + cost_of_change_sat ||
+			(selection_waste > best_waste && is_feerate_high)
+		{
+			backtrack = true;
+		} else if selected_sat >= target_sat {
+			let waste = selection_waste + (selected_sat - target_sat) as i64;
+			if waste <= best_waste {
+				best_selection = Some((selection.clone(), waste));
+			}
+			backtrack = true;
+		}
+
+		if backtrack {
+			let last_selected_idx = match selection.last() {
+				Some(idx) => *idx,
+				None => break,
+			};
+			// Make the UTXOs after the last selected one available again before exploring the
+			// branch omitting it.
+			pool_idx -= 1;
+			while pool_idx > last_selected_idx {
+				available_sat += pool[pool_idx].1;
+				pool_idx -= 1;
+			}
+			selected_sat -= pool[pool_idx].1;
+			selection_waste -= pool[pool_idx].2;
+			selection.pop();
+		} else {
+			let (_, value, waste) = pool[pool_idx];
+			available_sat -= value;
+			// Including a UTXO equivalent to the previous, omitted one would only explore a branch
+			// we've already explored.
+			if selection.is_empty() || selection.last() == Some(&(pool_idx - 1)) ||
+				value != pool[pool_idx - 1].1 || waste != pool[pool_idx - 1].2
+			{
+				selection.push(pool_idx);
+				selected_sat += value;
+				selection_waste += waste;
+			}
+		}
+		pool_idx += 1;
+	}
+	best_selection.map(|(selection, _)| selection.into_iter().map(|pool_idx| pool[pool_idx].0).collect())
+}
+
+/// A transaction built by [`Wallet`] for a claim.
+#[derive(Clone)]
+struct ClaimTx {
+	/// The outpoints spent by the transaction along with their values.
+	inputs: Vec<(OutPoint, u64)>,
+	/// The UTXOs selected from the wallet to fund the transaction.
+	wallet_utxos: Vec<Utxo>,
+	/// The estimated weight of the fully signed transaction, including any weight attributed to
+	/// the inputs the claim must spend, such as that of the commitment transaction for anchor
+	/// spends.
+	weight: u64,
+	/// The feerate requested for the claim, which may be lower than the one targeted by coin
+	/// selection if the transaction replaced another.
+	requested_feerate_sat_per_1000_weight: u32,
+	/// The feerate targeted by coin selection.
+	feerate_sat_per_1000_weight: u32,
+	/// The absolute fee paid by the transaction.
+	fee_sat: u64,
+	/// The change output of the transaction, if any.
+	change_output: Option<TxOut>,
+}
+
+/// The transactions built by [`Wallet`] for a claim.
+#[derive(Default)]
+struct ClaimState {
+	/// The transaction resulting from the latest coin selection attempt, which has yet to be
+	/// signed.
+	pending_tx: Option<ClaimTx>,
+	/// The latest transaction signed for the claim along with its change, which any new
+	/// transaction for the claim must replace.
+	latest_tx: Option<(ClaimTx, Option<Utxo>)>,
+	/// The height of the block in which any input of the latest transaction was spent, either by
+	/// the transaction itself or a conflicting one.
+	spent_height: Option<u32>,
 }
 
 /// A wrapper over [`WalletSource`] that implements [`CoinSelection`] by preferring UTXOs that would
 /// avoid conflicting double spends. If not enough UTXOs are available to do so, conflicting double
 /// spends may happen.
+///
+/// Coin selection first searches for a set of UTXOs not requiring a change output via
+/// branch-and-bound, minimizing the value lost to fees, before falling back to spending the
+/// smallest UTXOs first and creating change.
+///
+/// Once a transaction was signed for a claim, later coin selection attempts for the same claim
+/// requesting a feerate no higher than that requested for the signed transaction yield the same
+/// selection, such that the same transaction is rebroadcast. Otherwise, they yield a replacement
+/// for it, i.e., they target at least the replaced transaction's feerate plus the incremental relay
+/// feerate and pay the absolute fee increase required by BIP 125.
+///
+/// If [`WalletSource::can_sign_unconfirmed_change`] returns true, the change of transactions
+/// built for other claims may be spent before it confirms, as long as those transactions targeted
+/// at least the same feerate. Note that replacing such a transaction invalidates any transaction
+/// spending its change, which will then be rebuilt upon its next fee bump.
+///
+/// Claims are forgotten once a transaction spending any input of their latest transaction, i.e.,
+/// either the latest transaction itself or a conflicting one, reached [`ANTI_REORG_DELAY`]
+/// confirmations, which requires blocks to be given via the [`Listen`] implementation. They are
+/// further forgotten once the change of their latest transaction is returned as a confirmed UTXO
+/// by the [`WalletSource`], or once a transaction for another claim spending any of the same
+/// non-wallet inputs was signed, replacing theirs.
 pub struct Wallet<W: Deref, L: Deref>
 where
 	W::Target: WalletSource,
@@ -498,10 +699,9 @@ where
 {
 	source: W,
 	logger: L,
-	// TODO: Do we care about cleaning this up once the UTXOs have a confirmed spend? We can do so
-	// by checking whether any UTXOs that exist in the map are no longer returned in
-	// `list_confirmed_utxos`.
+	// UTXOs are unlocked once the claim they were locked for is forgotten.
 	locked_utxos: Mutex<HashMap<OutPoint, ClaimId>>,
+	claims: Mutex<HashMap<ClaimId, ClaimState>>,
 }
 
 impl<W: Deref, L: Deref> Wallet<W, L>
@@ -512,22 +712,28 @@ where
 	/// Returns a new instance backed by the given [`WalletSource`] that serves as an implementation
 	/// of [`CoinSelectionSource`].
 	pub fn new(source: W, logger: L) -> Self {
-		Self { source, logger, locked_utxos: Mutex::new(HashMap::new()) }
+		Self { source, logger, locked_utxos: Mutex::new(HashMap::new()), claims: Mutex::new(HashMap::new()) }
 	}
 
-	/// Performs coin selection on the set of UTXOs obtained from
-	/// [`WalletSource::list_confirmed_utxos`]. Its algorithm can be described as "smallest
-	/// above-dust-after-spend first", with a slight twist: we may skip UTXOs that are above dust at
-	/// the target feerate after having spent them in a separate claim transaction if
-	/// `force_conflicting_utxo_spend` is unset to avoid producing conflicting transactions. If
-	/// `tolerate_high_network_feerates` is set, we'll attempt to spend UTXOs that contribute at
-	/// least 1 satoshi at the current feerate, otherwise, we'll only attempt to spend those which
-	/// contribute at least twice their fee.
+	/// Performs coin selection on the given set of UTXOs, returning the estimated weight and
+	/// absolute fee of the resulting transaction along with the selection.
+	///
+	/// We first search for a set of UTXOs not requiring change via [`select_utxos_bnb`]. If none
+	/// exists, we select the "smallest above-dust-after-spend first", with a slight twist: we may
+	/// skip UTXOs that are above dust at the target feerate after having spent them in a separate
+	/// claim transaction if `force_conflicting_utxo_spend` is unset to avoid producing conflicting
+	/// transactions. If `tolerate_high_network_feerates` is set, we'll attempt to spend UTXOs that
+	/// contribute at least 1 satoshi at the current feerate, otherwise, we'll only attempt to spend
+	/// those which contribute at least twice their fee.
+	///
+	/// If a `replaced_tx` is given, we make sure to pay at least its fee plus our own size at the
+	/// incremental relay feerate.
 	fn select_confirmed_utxos_internal(
 		&self, utxos: &[Utxo], claim_id: ClaimId, force_conflicting_utxo_spend: bool,
 		tolerate_high_network_feerates: bool, target_feerate_sat_per_1000_weight: u32,
-		preexisting_tx_weight: u64, target_amount_sat: u64,
-	) -> Result<CoinSelection, ()> {
+		preexisting_tx_weight: u64, input_amount_sat: u64, target_amount_sat: u64,
+		has_outputs: bool, replaced_tx: Option<&ClaimTx>,
+	) -> Result<(CoinSelection, u64, u64), ()> {
 		let mut locked_utxos = self.locked_utxos.lock().unwrap();
 		let mut eligible_utxos = utxos.iter().filter_map(|utxo| {
 			if let Some(utxo_claim_id) = locked_utxos.get(&utxo.outpoint) {
@@ -536,63 +742,125 @@ where
 					return None;
 				}
 			}
-			let fee_to_spend_utxo = fee_for_weight(
-				target_feerate_sat_per_1000_weight, BASE_INPUT_WEIGHT as u64 + utxo.satisfaction_weight,
-			);
+			let input_weight = BASE_INPUT_WEIGHT + utxo.satisfaction_weight;
+			let fee_to_spend_utxo = fee_for_weight(target_feerate_sat_per_1000_weight, input_weight);
 			let should_spend = if tolerate_high_network_feerates {
 				utxo.output.value > fee_to_spend_utxo
 			} else {
 				utxo.output.value >= fee_to_spend_utxo * 2
 			};
 			if should_spend {
-				Some((utxo, fee_to_spend_utxo))
+				let long_term_fee_to_spend_utxo = fee_for_weight(LONG_TERM_FEERATE_SAT_PER_1000_WEIGHT, input_weight);
+				Some((utxo, fee_to_spend_utxo, long_term_fee_to_spend_utxo))
 			} else {
 				log_trace!(self.logger, "Skipping UTXO {} due to dust proximity after spend", utxo.outpoint);
 				None
 			}
 		}).collect::<Vec<_>>();
-		eligible_utxos.sort_unstable_by_key(|(utxo, _)| utxo.output.value);
-
-		let mut selected_amount = 0;
-		let mut total_fees = fee_for_weight(target_feerate_sat_per_1000_weight, preexisting_tx_weight);
-		let mut selected_utxos = Vec::new();
-		for (utxo, fee_to_spend_utxo) in eligible_utxos {
-			if selected_amount >= target_amount_sat + total_fees {
-				break;
+		eligible_utxos.sort_unstable_by_key(|(utxo, _, _)| utxo.output.value);
+
+		let cost_of_change_sat = fee_for_weight(target_feerate_sat_per_1000_weight, P2WPKH_TXOUT_WEIGHT) +
+			fee_for_weight(LONG_TERM_FEERATE_SAT_PER_1000_WEIGHT, P2WPKH_INPUT_WEIGHT);
+		let mut change_script = None;
+		// Any additional fees we need to pay to replace `replaced_tx`.
+		let mut extra_fee_sat = 0;
+		loop {
+			let base_fee_sat = fee_for_weight(target_feerate_sat_per_1000_weight, preexisting_tx_weight) + extra_fee_sat;
+			let dummy_output_fee_sat = if has_outputs {
+				0
+			} else {
+				fee_for_weight(target_feerate_sat_per_1000_weight, DUMMY_OUTPUT_WEIGHT)
+			};
+
+			let (selected_utxos, change_output, output_weight) = if let Some(selected_idxs) = select_utxos_bnb(
+				&eligible_utxos, (target_amount_sat + base_fee_sat + dummy_output_fee_sat).saturating_sub(input_amount_sat),
+				cost_of_change_sat,
+			).filter(|selected_idxs| has_outputs || !selected_idxs.is_empty()) {
+				log_debug!(self.logger, "Found input set not requiring change via branch-and-bound");
+				let selected_utxos = selected_idxs.into_iter().map(|idx| eligible_utxos[idx].0.clone()).collect::<Vec<_>>();
+				(selected_utxos, None, if has_outputs { 0 } else { DUMMY_OUTPUT_WEIGHT })
+			} else {
+				let mut selected_amount = input_amount_sat;
+				let mut total_fees = base_fee_sat;
+				let mut selected_utxos = Vec::new();
+				for (utxo, fee_to_spend_utxo, _) in eligible_utxos.iter() {
+					// We need at least one output, so make sure we'll have enough to create change if
+					// there are none yet.
+					if (has_outputs || !selected_utxos.is_empty()) &&
+						selected_amount >= target_amount_sat + total_fees
+					{
+						break;
+					}
+					selected_amount += utxo.output.value;
+					total_fees += fee_to_spend_utxo;
+					selected_utxos.push((*utxo).clone());
+				}
+				if selected_amount < target_amount_sat + total_fees {
+					log_debug!(self.logger, "Insufficient funds to meet target feerate {} sat/kW",
+						target_feerate_sat_per_1000_weight);
+					return Err(());
+				}
+
+				let remaining_amount = selected_amount - target_amount_sat - total_fees;
+				if change_script.is_none() {
+					change_script = Some(self.source.get_change_script()?);
+				}
+				let change_script = change_script.clone().unwrap();
+				let change_output_weight = (8 /* value */ + change_script.consensus_encode(&mut sink()).unwrap() as u64) *
+					WITNESS_SCALE_FACTOR as u64;
+				let change_output_fee = fee_for_weight(target_feerate_sat_per_1000_weight, change_output_weight);
+				let change_output_amount = remaining_amount.saturating_sub(change_output_fee);
+				if change_output_amount < change_script.dust_value().to_sat() {
+					log_debug!(self.logger, "Coin selection attempt did not yield change output");
+					(selected_utxos, None, if has_outputs { 0 } else { DUMMY_OUTPUT_WEIGHT })
+				} else {
+					let change_output = TxOut { script_pubkey: change_script, value: change_output_amount };
+					(selected_utxos, Some(change_output), change_output_weight)
+				}
+			};
+
+			let tx_weight = preexisting_tx_weight + output_weight + selected_utxos.iter()
+				.map(|utxo| BASE_INPUT_WEIGHT + utxo.satisfaction_weight).sum::<u64>();
+			let selected_amount = selected_utxos.iter().map(|utxo| utxo.output.value).sum::<u64>();
+			let change_amount = change_output.as_ref().map(|output| output.value).unwrap_or(0);
+			let fee_sat = input_amount_sat + selected_amount - target_amount_sat - change_amount;
+			if let Some(replaced_tx) = replaced_tx {
+				let min_fee_sat = replaced_tx.fee_sat +
+					fee_for_weight(INCREMENTAL_RELAY_FEE_SAT_PER_1000_WEIGHT, tx_weight);
+				if fee_sat < min_fee_sat {
+					log_trace!(self.logger, "Coin selection attempt paying {} sat does not meet replacement fee of {} sat",
+						fee_sat, min_fee_sat);
+					extra_fee_sat += min_fee_sat - fee_sat;
+					continue;
+				}
 			}
-			selected_amount += utxo.output.value;
-			total_fees += fee_to_spend_utxo;
-			selected_utxos.push(utxo.clone());
-		}
-		if selected_amount < target_amount_sat + total_fees {
-			log_debug!(self.logger, "Insufficient funds to meet target feerate {} sat/kW",
-				target_feerate_sat_per_1000_weight);
-			return Err(());
-		}
-		for utxo in &selected_utxos {
-			locked_utxos.insert(utxo.outpoint, claim_id);
+
+			// Any UTXOs spent by a previous transaction for the claim are freed up as we replace it.
+			locked_utxos.retain(|_, utxo_claim_id| *utxo_claim_id != claim_id);
+			for utxo in &selected_utxos {
+				locked_utxos.insert(utxo.outpoint, claim_id);
+			}
+			let coin_selection = CoinSelection {
+				confirmed_utxos: selected_utxos,
+				change_output,
+			};
+			return Ok((coin_selection, tx_weight, fee_sat));
 		}
-		core::mem::drop(locked_utxos);
-
-		let remaining_amount = selected_amount - target_amount_sat - total_fees;
-		let change_script = self.source.get_change_script()?;
-		let change_output_fee = fee_for_weight(
-			target_feerate_sat_per_1000_weight,
-			(8 /* value */ + change_script.consensus_encode(&mut sink()).unwrap() as u64) *
-				WITNESS_SCALE_FACTOR as u64,
-		);
-		let change_output_amount = remaining_amount.saturating_sub(change_output_fee);
-		let change_output = if change_output_amount < change_script.dust_value().to_sat() {
-			log_debug!(self.logger, "Coin selection attempt did not yield change output");
-			None
-		} else {
-			Some(TxOut { script_pubkey: change_script, value: change_output_amount })
-		};
+	}
 
-		Ok(CoinSelection {
-			confirmed_utxos: selected_utxos,
-			change_output,
-		})
+	/// Returns a [`Utxo`] for the given change output if we know how to estimate its satisfaction
+	/// weight.
+	fn change_utxo(outpoint: OutPoint, output: &TxOut) -> Option<Utxo> {
+		let script = &output.script_pubkey;
+		if script.is_v0_p2wpkh() {
+			let pubkey_hash = WPubkeyHash::from_slice(&script.as_bytes()[2..]).ok()?;
+			Some(Utxo::new_v0_p2wpkh(outpoint, output.value, &pubkey_hash))
+		} else if script.is_p2pkh() {
+			let pubkey_hash = PubkeyHash::from_slice(&script.as_bytes()[3..23]).ok()?;
+			Some(Utxo::new_p2pkh(outpoint, output.value, &pubkey_hash))
+		} else {
+			None
+		}
 	}
 }
 
@@ -605,7 +873,66 @@ where
 		&self, claim_id: ClaimId, must_spend: Vec<Input>, must_pay_to: &[TxOut],
 		target_feerate_sat_per_1000_weight: u32,
 	) -> Result<CoinSelection, ()> {
-		let utxos = self.source.list_confirmed_utxos()?;
+		let mut utxos = self.source.list_confirmed_utxos()?;
+		let replaced_tx = {
+			let mut claims = self.claims.lock().unwrap();
+			claims.retain(|_, claim| match &claim.latest_tx {
+				Some((_, Some(change_utxo))) => !utxos.iter().any(|utxo| utxo.outpoint == change_utxo.outpoint),
+				_ => true,
+			});
+			self.locked_utxos.lock().unwrap().retain(|_, utxo_claim_id| claims.contains_key(utxo_claim_id));
+			if self.source.can_sign_unconfirmed_change() {
+				for (other_claim_id, claim) in claims.iter().filter(|(id, _)| **id != claim_id) {
+					if let Some((tx, Some(change_utxo))) = claim.latest_tx.as_ref() {
+						// Spending the change of a transaction at a lower feerate would drag ours down.
+						if tx.feerate_sat_per_1000_weight >= target_feerate_sat_per_1000_weight {
+							log_trace!(self.logger, "Considering unconfirmed change {} of claim {}",
+								change_utxo.outpoint, log_bytes!(other_claim_id.0));
+							utxos.push(change_utxo.clone());
+						}
+					}
+				}
+			}
+			claims.get(&claim_id).and_then(|claim| claim.latest_tx.as_ref()).map(|(tx, _)| tx.clone())
+		};
+
+		if let Some(replaced_tx) = replaced_tx.as_ref() {
+			// If we're not asked to bump the fee of the claim and the transaction we built for it
+			// previously is still valid, simply hand out the same selection again.
+			let reuse_selection = target_feerate_sat_per_1000_weight <= replaced_tx.requested_feerate_sat_per_1000_weight &&
+				replaced_tx.inputs.len() == must_spend.len() + replaced_tx.wallet_utxos.len() &&
+				must_spend.iter().all(|input| replaced_tx.inputs.iter().any(|(outpoint, _)| *outpoint == input.outpoint)) &&
+				replaced_tx.wallet_utxos.iter().all(|wallet_utxo| utxos.iter().any(|utxo| utxo == wallet_utxo)) && {
+					// Another claim may have been forced to spend the same UTXOs since.
+					let locked_utxos = self.locked_utxos.lock().unwrap();
+					replaced_tx.wallet_utxos.iter().all(|utxo| locked_utxos.get(&utxo.outpoint) == Some(&claim_id))
+				};
+			if reuse_selection {
+				log_debug!(self.logger, "Reusing previous coin selection for claim {} targeting {} sat/kW",
+					log_bytes!(claim_id.0), replaced_tx.feerate_sat_per_1000_weight);
+				let coin_selection = CoinSelection {
+					confirmed_utxos: replaced_tx.wallet_utxos.clone(),
+					change_output: replaced_tx.change_output.clone(),
+				};
+				self.claims.lock().unwrap().entry(claim_id).or_default().pending_tx = Some(replaced_tx.clone());
+				return Ok(coin_selection);
+			}
+		}
+
+		let requested_feerate_sat_per_1000_weight = target_feerate_sat_per_1000_weight;
+		let mut target_feerate_sat_per_1000_weight = target_feerate_sat_per_1000_weight;
+		if let Some(replaced_tx) = replaced_tx.as_ref() {
+			let replaced_feerate_sat_per_1000_weight =
+				compute_feerate_sat_per_1000_weight(replaced_tx.fee_sat, replaced_tx.weight);
+			let min_feerate_sat_per_1000_weight =
+				replaced_feerate_sat_per_1000_weight.saturating_add(INCREMENTAL_RELAY_FEE_SAT_PER_1000_WEIGHT);
+			if target_feerate_sat_per_1000_weight < min_feerate_sat_per_1000_weight {
+				log_debug!(self.logger, "Increasing target feerate to {} sat/kW to replace previous transaction for claim {}",
+					min_feerate_sat_per_1000_weight, log_bytes!(claim_id.0));
+				target_feerate_sat_per_1000_weight = min_feerate_sat_per_1000_weight;
+			}
+		}
+
 		// TODO: Use fee estimation utils when we upgrade to bitcoin v0.30.0.
 		const BASE_TX_SIZE: u64 = 4 /* version */ + 1 /* input count */ + 1 /* output count */ + 4 /* locktime */;
 		let total_output_size: u64 = must_pay_to.iter().map(|output|
@@ -616,23 +943,108 @@ where
 
 		let preexisting_tx_weight = 2 /* segwit marker & flag */ + total_input_weight +
 			((BASE_TX_SIZE + total_output_size) * WITNESS_SCALE_FACTOR as u64);
+		let input_amount_sat = must_spend.iter().map(|input| input.previous_utxo.value).sum();
 		let target_amount_sat = must_pay_to.iter().map(|output| output.value).sum();
 		let do_coin_selection = |force_conflicting_utxo_spend: bool, tolerate_high_network_feerates: bool| {
 			log_debug!(self.logger, "Attempting coin selection targeting {} sat/kW (force_conflicting_utxo_spend = {}, tolerate_high_network_feerates = {})",
 				target_feerate_sat_per_1000_weight, force_conflicting_utxo_spend, tolerate_high_network_feerates);
 			self.select_confirmed_utxos_internal(
 				&utxos, claim_id, force_conflicting_utxo_spend, tolerate_high_network_feerates,
-				target_feerate_sat_per_1000_weight, preexisting_tx_weight, target_amount_sat,
+				target_feerate_sat_per_1000_weight, preexisting_tx_weight, input_amount_sat,
+				target_amount_sat, !must_pay_to.is_empty(), replaced_tx.as_ref(),
 			)
 		};
-		do_coin_selection(false, false)
+		let (coin_selection, weight, fee_sat) = do_coin_selection(false, false)
 			.or_else(|_| do_coin_selection(false, true))
 			.or_else(|_| do_coin_selection(true, false))
-			.or_else(|_| do_coin_selection(true, true))
+			.or_else(|_| do_coin_selection(true, true))?;
+
+		let inputs = must_spend.iter().map(|input| (input.outpoint, input.previous_utxo.value))
+			.chain(coin_selection.confirmed_utxos.iter().map(|utxo| (utxo.outpoint, utxo.output.value)))
+			.collect();
+		self.claims.lock().unwrap().entry(claim_id).or_default().pending_tx = Some(ClaimTx {
+			inputs,
+			wallet_utxos: coin_selection.confirmed_utxos.clone(),
+			requested_feerate_sat_per_1000_weight,
+			weight,
+			feerate_sat_per_1000_weight: target_feerate_sat_per_1000_weight,
+			fee_sat,
+			change_output: coin_selection.change_output.clone(),
+		});
+		Ok(coin_selection)
 	}
 
 	fn sign_tx(&self, tx: Transaction) -> Result<Transaction, ()> {
-		self.source.sign_tx(tx)
+		let tx = self.source.sign_tx(tx)?;
+
+		// Track the transaction as the latest one for its claim, which we identify by the inputs
+		// selected for it.
+		let mut claims = self.claims.lock().unwrap();
+		let claim_id = claims.iter().find(|(_, claim)| match claim.pending_tx.as_ref() {
+			Some(pending_tx) => pending_tx.inputs.len() == tx.input.len() && tx.input.iter().all(|input|
+				pending_tx.inputs.iter().any(|(outpoint, _)| *outpoint == input.previous_output)),
+			None => false,
+		}).map(|(claim_id, _)| *claim_id);
+		if let Some(claim_id) = claim_id {
+			let claim = claims.get_mut(&claim_id).unwrap();
+			let claim_tx = claim.pending_tx.take().unwrap();
+			let change_utxo = claim_tx.change_output.as_ref().and_then(|change_output| {
+				let vout = tx.output.iter().position(|output| output == change_output)?;
+				Self::change_utxo(OutPoint { txid: tx.txid(), vout: vout as u32 }, change_output)
+			});
+			claim.latest_tx = Some((claim_tx, change_utxo));
+			claim.spent_height = None;
+
+			// The transactions of other claims spending any of the same non-wallet inputs, e.g.,
+			// HTLC outputs now claimed along with others under a new claim, are replaced by this one.
+			claims.retain(|other_claim_id, other_claim| *other_claim_id == claim_id || match other_claim.latest_tx.as_ref() {
+				Some((other_tx, _)) => !other_tx.inputs.iter().any(|(outpoint, _)|
+					!other_tx.wallet_utxos.iter().any(|utxo| utxo.outpoint == *outpoint) &&
+					tx.input.iter().any(|input| input.previous_output == *outpoint)),
+				None => true,
+			});
+		}
+		Ok(tx)
+	}
+}
+
+impl<W: Deref, L: Deref> Listen for Wallet<W, L>
+where
+	W::Target: WalletSource,
+	L::Target: Logger
+{
+	fn filtered_block_connected(&self, _header: &BlockHeader, txdata: &TransactionData, height: u32) {
+		let mut claims = self.claims.lock().unwrap();
+		for (_, tx) in txdata.iter() {
+			for claim in claims.values_mut().filter(|claim| claim.spent_height.is_none()) {
+				let spends_claim_input = match claim.latest_tx.as_ref() {
+					Some((claim_tx, _)) => tx.input.iter().any(|input|
+						claim_tx.inputs.iter().any(|(outpoint, _)| *outpoint == input.previous_output)),
+					None => false,
+				};
+				if spends_claim_input {
+					claim.spent_height = Some(height);
+				}
+			}
+		}
+
+		// Forget claims once their inputs' spend can't be reorged out anymore.
+		claims.retain(|claim_id, claim| match claim.spent_height {
+			Some(spent_height) if height >= spent_height + ANTI_REORG_DELAY - 1 => {
+				log_debug!(self.logger, "Forgetting claim {} as its inputs were spent irrevocably", log_bytes!(claim_id.0));
+				false
+			},
+			_ => true,
+		});
+		self.locked_utxos.lock().unwrap().retain(|_, utxo_claim_id| claims.contains_key(utxo_claim_id));
+	}
+
+	fn block_disconnected(&self, _header: &BlockHeader, height: u32) {
+		for claim in self.claims.lock().unwrap().values_mut() {
+			if matches!(claim.spent_height, Some(spent_height) if spent_height >= height) {
+				claim.spent_height = None;
+			}
+		}
 	}
 }
 
@@ -875,3 +1287,176 @@ where
 		}
 	}
 }
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	use crate::util::test_utils::TestLogger;
+
+	struct TestUtxoSource {
+		utxos: Vec<Utxo>,
+		can_sign_unconfirmed_change: bool,
+	}
+
+	impl WalletSource for TestUtxoSource {
+		fn list_confirmed_utxos(&self) -> Result<Vec<Utxo>, ()> {
+			Ok(self.utxos.clone())
+		}
+		fn get_change_script(&self) -> Result<Script, ()> {
+			Ok(Script::new_v0_p2wpkh(&WPubkeyHash::from_inner([1; 20])))
+		}
+		fn sign_tx(&self, tx: Transaction) -> Result<Transaction, ()> {
+			Ok(tx)
+		}
+		fn can_sign_unconfirmed_change(&self) -> bool {
+			self.can_sign_unconfirmed_change
+		}
+	}
+
+	fn utxo(idx: u8, value: u64) -> Utxo {
+		let outpoint = OutPoint { txid: Txid::from_inner([idx; 32]), vout: 0 };
+		Utxo::new_v0_p2wpkh(outpoint, value, &WPubkeyHash::from_inner([0; 20]))
+	}
+
+	fn tx_for_selection(coin_selection: &CoinSelection, must_pay_to: &[TxOut]) -> Transaction {
+		Transaction {
+			version: 2,
+			lock_time: PackedLockTime::ZERO,
+			input: coin_selection.confirmed_utxos.iter().map(|utxo| TxIn {
+				previous_output: utxo.outpoint,
+				..Default::default()
+			}).collect(),
+			output: must_pay_to.iter().cloned().chain(coin_selection.change_output.clone()).collect(),
+		}
+	}
+
+	#[test]
+	fn test_selects_changeless_input_set() {
+		let logger = TestLogger::new();
+		let source = TestUtxoSource {
+			utxos: vec![utxo(1, 10_000), utxo(2, 20_000), utxo(3, 50_000), utxo(4, 100_000)],
+			can_sign_unconfirmed_change: false,
+		};
+		let wallet = Wallet::new(&source, &logger);
+
+		// Pick an output value such that the 50k UTXO exactly covers it along with the fees of the
+		// transaction at 1000 sat/kW, while spending the smallest UTXOs first would require change.
+		let output_script = Script::new_v0_p2wpkh(&WPubkeyHash::from_inner([2; 20]));
+		let preexisting_tx_weight = TX_OVERHEAD_WEIGHT + P2WPKH_TXOUT_WEIGHT;
+		let output_value = 50_000 - fee_for_weight(1000, preexisting_tx_weight + P2WPKH_INPUT_WEIGHT);
+		let must_pay_to = [TxOut { value: output_value, script_pubkey: output_script }];
+		let coin_selection = wallet.select_confirmed_utxos(ClaimId([0; 32]), Vec::new(), &must_pay_to, 1000).unwrap();
+		assert_eq!(coin_selection.confirmed_utxos, vec![utxo(3, 50_000)]);
+		assert!(coin_selection.change_output.is_none());
+
+		// If no subset matches closely enough, we fall back to creating change.
+		let must_pay_to = [TxOut { value: 25_000, ..must_pay_to[0].clone() }];
+		let coin_selection = wallet.select_confirmed_utxos(ClaimId([1; 32]), Vec::new(), &must_pay_to, 1000).unwrap();
+		assert!(coin_selection.change_output.is_some());
+	}
+
+	#[test]
+	fn test_replacement_pays_incremental_relay_fee() {
+		let logger = TestLogger::new();
+		let source = TestUtxoSource {
+			utxos: vec![utxo(1, 100_000), utxo(2, 200_000)],
+			can_sign_unconfirmed_change: true,
+		};
+		let wallet = Wallet::new(&source, &logger);
+		let claim_id = ClaimId([0; 32]);
+		let must_pay_to = [TxOut { value: 50_000, script_pubkey: Script::new_v0_p2wpkh(&WPubkeyHash::from_inner([2; 20])) }];
+		let fee_for_selection = |coin_selection: &CoinSelection| {
+			coin_selection.confirmed_utxos.iter().map(|utxo| utxo.output.value).sum::<u64>() - 50_000 -
+				coin_selection.change_output.as_ref().map(|output| output.value).unwrap_or(0)
+		};
+
+		let coin_selection = wallet.select_confirmed_utxos(claim_id, Vec::new(), &must_pay_to, 1000).unwrap();
+		assert_eq!(coin_selection.confirmed_utxos, vec![utxo(1, 100_000)]);
+		let first_fee = fee_for_selection(&coin_selection);
+		let first_tx = wallet.sign_tx(tx_for_selection(&coin_selection, &must_pay_to)).unwrap();
+
+		// Retrying the claim at the same feerate yields the same transaction.
+		let coin_selection = wallet.select_confirmed_utxos(claim_id, Vec::new(), &must_pay_to, 1000).unwrap();
+		assert_eq!(wallet.sign_tx(tx_for_selection(&coin_selection, &must_pay_to)).unwrap(), first_tx);
+
+		// Bumping the claim yields a valid replacement, even if the feerate increase is smaller than
+		// the incremental relay feerate.
+		let coin_selection = wallet.select_confirmed_utxos(claim_id, Vec::new(), &must_pay_to, 1100).unwrap();
+		let second_tx = tx_for_selection(&coin_selection, &must_pay_to);
+		let second_tx_weight = second_tx.weight() as u64 +
+			coin_selection.confirmed_utxos.iter().map(|utxo| utxo.satisfaction_weight).sum::<u64>();
+		let second_fee = fee_for_selection(&coin_selection);
+		assert!(second_fee >= first_fee + fee_for_weight(INCREMENTAL_RELAY_FEE_SAT_PER_1000_WEIGHT, second_tx_weight));
+		assert_ne!(second_tx.txid(), first_tx.txid());
+		wallet.sign_tx(second_tx.clone()).unwrap();
+
+		// The change of the replaced transaction is gone, while that of its replacement may be
+		// spent by other claims at a feerate not exceeding its own.
+		let other_claim_id = ClaimId([1; 32]);
+		// Prevent the other claim from spending any of the confirmed UTXOs without conflicting.
+		for utxo in source.utxos.iter() {
+			wallet.locked_utxos.lock().unwrap().insert(utxo.outpoint, claim_id);
+		}
+		let must_pay_to = [TxOut { value: 10_000, ..must_pay_to[0].clone() }];
+		let coin_selection = wallet.select_confirmed_utxos(other_claim_id, Vec::new(), &must_pay_to, 1000).unwrap();
+		assert_eq!(coin_selection.confirmed_utxos.len(), 1);
+		assert_eq!(coin_selection.confirmed_utxos[0].outpoint.txid, second_tx.txid());
+		assert_ne!(coin_selection.confirmed_utxos[0].outpoint.txid, first_tx.txid());
+	}
+
+	#[test]
+	fn test_forgets_resolved_claims() {
+		let logger = TestLogger::new();
+		let source = TestUtxoSource {
+			utxos: vec![utxo(1, 100_000), utxo(2, 200_000), utxo(3, 300_000)],
+			can_sign_unconfirmed_change: false,
+		};
+		let wallet = Wallet::new(&source, &logger);
+		let must_pay_to = [TxOut { value: 50_000, script_pubkey: Script::new_v0_p2wpkh(&WPubkeyHash::from_inner([2; 20])) }];
+		let header = BlockHeader {
+			version: 2,
+			prev_blockhash: bitcoin::BlockHash::all_zeros(),
+			merkle_root: bitcoin::TxMerkleNode::all_zeros(),
+			time: 42,
+			bits: 42,
+			nonce: 42,
+		};
+
+		// A claim whose transaction confirms is forgotten once it reached ANTI_REORG_DELAY
+		// confirmations, unless the block is disconnected in the meantime.
+		let claim_id = ClaimId([0; 32]);
+		let coin_selection = wallet.select_confirmed_utxos(claim_id, Vec::new(), &must_pay_to, 1000).unwrap();
+		let tx = wallet.sign_tx(tx_for_selection(&coin_selection, &must_pay_to)).unwrap();
+		wallet.filtered_block_connected(&header, &[(1, &tx)], 100);
+		wallet.block_disconnected(&header, 100);
+		wallet.filtered_block_connected(&header, &[], 100 + ANTI_REORG_DELAY - 1);
+		assert!(wallet.claims.lock().unwrap().contains_key(&claim_id));
+		wallet.filtered_block_connected(&header, &[(1, &tx)], 101);
+		wallet.filtered_block_connected(&header, &[], 101 + ANTI_REORG_DELAY - 2);
+		assert!(wallet.claims.lock().unwrap().contains_key(&claim_id));
+		wallet.filtered_block_connected(&header, &[], 101 + ANTI_REORG_DELAY - 1);
+		assert!(!wallet.claims.lock().unwrap().contains_key(&claim_id));
+		assert!(wallet.locked_utxos.lock().unwrap().is_empty());
+
+		// A claim whose non-wallet inputs are spent by the transaction of another claim is replaced.
+		let input = Input {
+			outpoint: OutPoint { txid: Txid::from_inner([42; 32]), vout: 0 },
+			previous_utxo: TxOut { value: 1000, script_pubkey: Script::new() },
+			satisfaction_weight: 0,
+		};
+		let replaced_claim_id = ClaimId([1; 32]);
+		let coin_selection = wallet.select_confirmed_utxos(replaced_claim_id, vec![input.clone()], &must_pay_to, 1000).unwrap();
+		let mut replaced_tx = tx_for_selection(&coin_selection, &must_pay_to);
+		replaced_tx.input.push(TxIn { previous_output: input.outpoint, ..Default::default() });
+		wallet.sign_tx(replaced_tx).unwrap();
+		assert!(wallet.claims.lock().unwrap().contains_key(&replaced_claim_id));
+
+		let coin_selection = wallet.select_confirmed_utxos(claim_id, vec![input.clone()], &must_pay_to, 2000).unwrap();
+		let mut tx = tx_for_selection(&coin_selection, &must_pay_to);
+		tx.input.push(TxIn { previous_output: input.outpoint, ..Default::default() });
+		wallet.sign_tx(tx).unwrap();
+		assert!(!wallet.claims.lock().unwrap().contains_key(&replaced_claim_id));
+		assert!(wallet.claims.lock().unwrap().contains_key(&claim_id));
+	}
+}
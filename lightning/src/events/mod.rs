@@ -168,6 +168,9 @@ pub enum ClosureReason {
 	/// The counterparty requested a cooperative close of a channel that had not been funded yet.
 	/// The channel has been immediately closed.
 	CounterpartyCoopClosedUnfundedChannel,
+	/// Another channel in the same funding batch closed before the funding transaction was ready
+	/// to be broadcast.
+	FundingBatchClosure,
 }
 
 impl core::fmt::Display for ClosureReason {
@@ -188,6 +191,7 @@ impl core::fmt::Display for ClosureReason {
 			ClosureReason::DisconnectedPeer => f.write_str("the peer disconnected prior to the channel being funded"),
 			ClosureReason::OutdatedChannelManager => f.write_str("the ChannelManager read from disk was stale compared to ChannelMonitor(s)"),
 			ClosureReason::CounterpartyCoopClosedUnfundedChannel => f.write_str("the peer requested the unfunded channel be closed"),
+			ClosureReason::FundingBatchClosure => f.write_str("another channel in the same funding batch closed"),
 		}
 	}
 }
@@ -202,6 +206,7 @@ impl_writeable_tlv_based_enum_upgradable!(ClosureReason,
 	(10, DisconnectedPeer) => {},
 	(12, OutdatedChannelManager) => {},
 	(13, CounterpartyCoopClosedUnfundedChannel) => {},
+	(15, FundingBatchClosure) => {},
 );
 
 /// Intended destination of a failed HTLC as indicated in [`Event::HTLCHandlingFailed`].
@@ -313,6 +318,19 @@ impl_writeable_tlv_based_enum!(PaymentFailureReason,
 	(10, UnexpectedError) => {}, ;
 );
 
+/// The funds contributed by the counterparty to a channel they requested to open, as indicated in
+/// [`Event::OpenChannelRequest`].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum InboundChannelFunds {
+	/// For a non-dual-funded channel, the `push_msat` value from the channel initiator to us.
+	PushMsat(u64),
+	/// Indicates the open request is for a dual funded channel.
+	///
+	/// Note that these channels do not support starting with initial funds pushed from the
+	/// counterparty, who is the channel opener in this case.
+	DualFunded,
+}
+
 /// An Event which you should probably take some action in response to.
 ///
 /// Note that while Writeable and Readable are implemented for Event, you probably shouldn't use
@@ -506,6 +524,18 @@ pub enum Event {
 		/// by versions prior to 0.0.115.
 		reason: Option<PaymentFailureReason>,
 	},
+	/// Indicates a request for an invoice failed to yield a response in a reasonable amount of time
+	/// or was explicitly abandoned by [`ChannelManager::abandon_payment`]. This may be for an
+	/// [`InvoiceRequest`] sent for an [`Offer`] or for a [`Refund`] that hasn't been redeemed.
+	///
+	/// [`ChannelManager::abandon_payment`]: crate::ln::channelmanager::ChannelManager::abandon_payment
+	/// [`InvoiceRequest`]: crate::offers::invoice_request::InvoiceRequest
+	/// [`Offer`]: crate::offers::offer::Offer
+	/// [`Refund`]: crate::offers::refund::Refund
+	InvoiceRequestFailed {
+		/// The `payment_id` to have been associated with payment for the requested invoice.
+		payment_id: PaymentId,
+	},
 	/// Indicates that a path for an outbound payment was successful.
 	///
 	/// Always generated after [`Event::PaymentSent`] and thus useful for scoring channels. See
@@ -654,6 +684,11 @@ pub enum Event {
 	/// Such an output will *not* ever be spent by rust-lightning, and are not at risk of your
 	/// counterparty spending them due to some kind of timeout. Thus, you need to store them
 	/// somewhere and spend them when you create on-chain transactions.
+	///
+	/// You may hand them to the [`OutputSweeper`] utility which takes care of sweeping them to your
+	/// wallet, persisting them until their spending transaction is irrevocably confirmed.
+	///
+	/// [`OutputSweeper`]: crate::util::sweep::OutputSweeper
 	SpendableOutputs {
 		/// The outputs which you should store as spendable by you.
 		outputs: Vec<SpendableOutputDescriptor>,
@@ -716,6 +751,31 @@ pub enum Event {
 		/// The outpoint of the channel's funding transaction.
 		funding_txo: OutPoint,
 	},
+	/// Used to indicate that the client should provide signatures for the inputs it contributed
+	/// to the funding transaction of a dual-funded channel by calling
+	/// [`ChannelManager::funding_transaction_signed`] with the fully signed transaction.
+	///
+	/// Generated once the funding transaction has been negotiated with our counterparty and we
+	/// have received their initial commitment signature, so that the channel may be safely
+	/// funded. Only the witnesses of the inputs we contributed should be added; the transaction
+	/// must not otherwise be modified.
+	///
+	/// [`ChannelManager::funding_transaction_signed`]: crate::ln::channelmanager::ChannelManager::funding_transaction_signed
+	FundingTransactionReadyForSigning {
+		/// The `channel_id` of the channel which the funding transaction belongs to.
+		channel_id: [u8; 32],
+		/// The `node_id` of the channel counterparty.
+		counterparty_node_id: PublicKey,
+		/// The `user_channel_id` value passed in to [`ChannelManager::create_dual_funded_channel`]
+		/// for outbound channels, or to [`ChannelManager::accept_inbound_channel_with_contribution`]
+		/// for inbound channels.
+		///
+		/// [`ChannelManager::create_dual_funded_channel`]: crate::ln::channelmanager::ChannelManager::create_dual_funded_channel
+		/// [`ChannelManager::accept_inbound_channel_with_contribution`]: crate::ln::channelmanager::ChannelManager::accept_inbound_channel_with_contribution
+		user_channel_id: u128,
+		/// The unsigned funding transaction which we should add witnesses to for our inputs.
+		unsigned_transaction: Transaction,
+	},
 	/// Used to indicate that a channel with the given `channel_id` is ready to
 	/// be used. This event is emitted either when the funding transaction has been confirmed
 	/// on-chain, or, in case of a 0conf channel, when both parties have confirmed the channel
@@ -768,12 +828,15 @@ pub enum Event {
 	/// Indicates a request to open a new channel by a peer.
 	///
 	/// To accept the request, call [`ChannelManager::accept_inbound_channel`]. To reject the
-	/// request, call [`ChannelManager::force_close_without_broadcasting_txn`].
+	/// request, call [`ChannelManager::force_close_without_broadcasting_txn`]. For dual-funded
+	/// channels, funds may also be contributed to the channel by accepting the request through
+	/// [`ChannelManager::accept_inbound_channel_with_contribution`].
 	///
 	/// The event is only triggered when a new open channel request is received and the
 	/// [`UserConfig::manually_accept_inbound_channels`] config flag is set to true.
 	///
 	/// [`ChannelManager::accept_inbound_channel`]: crate::ln::channelmanager::ChannelManager::accept_inbound_channel
+	/// [`ChannelManager::accept_inbound_channel_with_contribution`]: crate::ln::channelmanager::ChannelManager::accept_inbound_channel_with_contribution
 	/// [`ChannelManager::force_close_without_broadcasting_txn`]: crate::ln::channelmanager::ChannelManager::force_close_without_broadcasting_txn
 	/// [`UserConfig::manually_accept_inbound_channels`]: crate::util::config::UserConfig::manually_accept_inbound_channels
 	OpenChannelRequest {
@@ -797,9 +860,15 @@ pub enum Event {
 		/// [`ChannelManager::force_close_without_broadcasting_txn`]: crate::ln::channelmanager::ChannelManager::force_close_without_broadcasting_txn
 		counterparty_node_id: PublicKey,
 		/// The channel value of the requested channel.
+		///
+		/// For dual-funded channels, this is only the counterparty's contribution to the channel.
 		funding_satoshis: u64,
-		/// Our starting balance in the channel if the request is accepted, in milli-satoshi.
-		push_msat: u64,
+		/// If `channel_negotiation_type` is `InboundChannelFunds::PushMsat`, this is our starting
+		/// balance in the channel if the request is accepted, in milli-satoshi.
+		///
+		/// If `channel_negotiation_type` is `InboundChannelFunds::DualFunded`, the request is for
+		/// a dual-funded channel, to which we may contribute our own funds.
+		channel_negotiation_type: InboundChannelFunds,
 		/// The features that this channel will operate with. If you reject the channel, a
 		/// well-behaved counterparty may automatically re-attempt the channel with a new set of
 		/// feature flags.
@@ -1063,6 +1132,21 @@ impl Writeable for Event {
 					(8, funding_txo, required),
 				});
 			},
+			&Event::FundingTransactionReadyForSigning { ref channel_id, ref counterparty_node_id, ref user_channel_id, ref unsigned_transaction } => {
+				33u8.write(writer)?;
+				write_tlv_fields!(writer, {
+					(0, channel_id, required),
+					(2, counterparty_node_id, required),
+					(4, user_channel_id, required),
+					(6, unsigned_transaction, required),
+				});
+			},
+			&Event::InvoiceRequestFailed { ref payment_id } => {
+				35u8.write(writer)?;
+				write_tlv_fields!(writer, {
+					(0, payment_id, required),
+				})
+			},
 			// Note that, going forward, all new events must only write data inside of
 			// `write_tlv_fields`. Versions 0.0.101+ will ignore odd-numbered events that write
 			// data via `write_tlv_fields`.
@@ -1437,6 +1521,40 @@ impl MaybeReadable for Event {
 				};
 				f()
 			},
+			33u8 => {
+				let f = || {
+					let mut channel_id = [0; 32];
+					let mut counterparty_node_id = RequiredWrapper(None);
+					let mut user_channel_id: u128 = 0;
+					let mut unsigned_transaction = RequiredWrapper(None);
+					read_tlv_fields!(reader, {
+						(0, channel_id, required),
+						(2, counterparty_node_id, required),
+						(4, user_channel_id, required),
+						(6, unsigned_transaction, required),
+					});
+
+					Ok(Some(Event::FundingTransactionReadyForSigning {
+						channel_id,
+						counterparty_node_id: counterparty_node_id.0.unwrap(),
+						user_channel_id,
+						unsigned_transaction: unsigned_transaction.0.unwrap(),
+					}))
+				};
+				f()
+			},
+			35u8 => {
+				let f = || {
+					let mut payment_id = PaymentId([0; 32]);
+					read_tlv_fields!(reader, {
+						(0, payment_id, required),
+					});
+					Ok(Some(Event::InvoiceRequestFailed {
+						payment_id,
+					}))
+				};
+				f()
+			},
 			// Versions prior to 0.0.100 did not ignore odd types, instead returning InvalidValue.
 			// Version 0.0.100 failed to properly ignore odd types, possibly resulting in corrupt
 			// reads.
@@ -1568,7 +1686,56 @@ pub enum MessageSendEvent {
 		/// The node_id of the node which should receive this message
 		node_id: PublicKey,
 		/// The message which should be sent.
-		msg: msgs::TxAddInput,
+		msg: msgs::TxAbort,
+	},
+	/// Used to indicate that a splice message should be sent to the peer with the given node_id.
+	SendSplice {
+		/// The node_id of the node which should receive this message
+		node_id: PublicKey,
+		/// The message which should be sent.
+		msg: msgs::Splice,
+	},
+	/// Used to indicate that a splice_ack message should be sent to the peer with the given node_id.
+	SendSpliceAck {
+		/// The node_id of the node which should receive this message
+		node_id: PublicKey,
+		/// The message which should be sent.
+		msg: msgs::SpliceAck,
+	},
+	/// Used to indicate that a splice_locked message should be sent to the peer with the given node_id.
+	SendSpliceLocked {
+		/// The node_id of the node which should receive this message
+		node_id: PublicKey,
+		/// The message which should be sent.
+		msg: msgs::SpliceLocked,
+	},
+	/// Used to indicate that an stfu message should be sent to the peer with the given node_id.
+	SendStfu {
+		/// The node_id of the node which should receive this message
+		node_id: PublicKey,
+		/// The message which should be sent.
+		msg: msgs::Stfu,
+	},
+	/// Used to indicate that a dyn_propose message should be sent to the peer with the given node_id.
+	SendDynPropose {
+		/// The node_id of the node which should receive this message
+		node_id: PublicKey,
+		/// The message which should be sent.
+		msg: msgs::DynPropose,
+	},
+	/// Used to indicate that a dyn_ack message should be sent to the peer with the given node_id.
+	SendDynAck {
+		/// The node_id of the node which should receive this message
+		node_id: PublicKey,
+		/// The message which should be sent.
+		msg: msgs::DynAck,
+	},
+	/// Used to indicate that a dyn_reject message should be sent to the peer with the given node_id.
+	SendDynReject {
+		/// The node_id of the node which should receive this message
+		node_id: PublicKey,
+		/// The message which should be sent.
+		msg: msgs::DynReject,
 	},
 	/// Used to indicate that a channel_ready message should be sent to the peer with the given node_id.
 	SendChannelReady {
@@ -1736,6 +1903,11 @@ pub trait OnionMessageProvider {
 /// [`process_pending_events`] returns, thus handlers MUST fully handle [`Event`]s and persist any
 /// relevant changes to disk *before* returning.
 ///
+/// If a handler is unable to handle an [`Event`] (e.g., because the database it persists to is
+/// temporarily unavailable), it may return a [`ReplayEvent`] error. In that case, the [`Event`] and
+/// any [`Event`]s queued after it will not be considered handled, and will be passed to the
+/// handler again on the next call to [`process_pending_events`].
+///
 /// Further, because an application may crash between an [`Event`] being handled and the
 /// implementor of this trait being re-serialized, [`Event`] handling must be idempotent - in
 /// effect, [`Event`]s may be replayed.
@@ -1765,22 +1937,35 @@ pub trait EventsProvider {
 ///
 /// An async variation also exists for implementations of [`EventsProvider`] that support async
 /// event handling. The async event handler should satisfy the generic bounds: `F:
-/// core::future::Future, H: Fn(Event) -> F`.
+/// core::future::Future<Output = Result<(), ReplayEvent>>, H: Fn(Event) -> F`.
 pub trait EventHandler {
 	/// Handles the given [`Event`].
 	///
 	/// See [`EventsProvider`] for details that must be considered when implementing this method.
-	fn handle_event(&self, event: Event);
+	///
+	/// If [`ReplayEvent`] is returned, the [`Event`] will be kept queued and will be passed to the
+	/// handler again on the next call to [`EventsProvider::process_pending_events`].
+	fn handle_event(&self, event: Event) -> Result<(), ReplayEvent>;
 }
 
-impl<F> EventHandler for F where F: Fn(Event) {
-	fn handle_event(&self, event: Event) {
+impl<F> EventHandler for F where F: Fn(Event) -> Result<(), ReplayEvent> {
+	fn handle_event(&self, event: Event) -> Result<(), ReplayEvent> {
 		self(event)
 	}
 }
 
 impl<T: EventHandler> EventHandler for Arc<T> {
-	fn handle_event(&self, event: Event) {
+	fn handle_event(&self, event: Event) -> Result<(), ReplayEvent> {
 		self.deref().handle_event(event)
 	}
 }
+
+/// An error type which may be returned by an [`EventHandler`] to indicate that it failed to
+/// handle an [`Event`] and that the [`Event`] should be replayed.
+///
+/// The failed [`Event`], as well as any [`Event`]s queued after it, will be kept by the
+/// [`EventsProvider`] and passed to the handler again on the next call to
+/// [`EventsProvider::process_pending_events`]. Thus, handlers should only return this if they
+/// expect the failure to be transient.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct ReplayEvent();
@@ -0,0 +1,199 @@
+// This file is Copyright its original authors, visible in version control
+// history.
+//
+// This file is licensed under the Apache License, Version 2.0 <LICENSE-APACHE
+// or http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your option.
+// You may not use this file except in accordance with one or both of these
+// licenses.
+
+//! Tests for sending, forwarding, and receiving payments over blinded paths.
+
+use bitcoin::secp256k1::{PublicKey, Secp256k1};
+use crate::blinded_path::BlindedPath;
+use crate::blinded_path::payment::{ForwardNode, ForwardTlvs, PaymentConstraints, PaymentRelay, ReceiveTlvs};
+use crate::events::Event;
+use crate::ln::PaymentSecret;
+use crate::ln::channelmanager::{PaymentId, RecipientOnionFields, Retry};
+use crate::ln::features::BlindedHopFeatures;
+use crate::ln::functional_test_utils::*;
+use crate::ln::msgs::{ChannelMessageHandler, UnsignedChannelUpdate};
+use crate::ln::onion_utils::INVALID_ONION_BLINDING;
+use crate::offers::invoice::BlindedPayInfo;
+use crate::routing::router::{PaymentParameters, RouteParameters};
+use crate::util::test_utils;
+
+use crate::prelude::*;
+
+/// Builds a blinded path through `node_ids` to the last node, forwarding over the channels of
+/// `channel_upds`, where each update is for the channel out of the corresponding node.
+fn blinded_payment_path(
+	payment_secret: PaymentSecret, node_ids: Vec<PublicKey>,
+	channel_upds: &[&UnsignedChannelUpdate], keys_manager: &test_utils::TestKeysInterface
+) -> (BlindedPayInfo, BlindedPath) {
+	let mut intermediate_nodes = Vec::new();
+	for (node_id, channel_upd) in node_ids.iter().zip(channel_upds) {
+		intermediate_nodes.push(ForwardNode {
+			node_id: *node_id,
+			tlvs: ForwardTlvs {
+				short_channel_id: channel_upd.short_channel_id,
+				payment_relay: PaymentRelay {
+					cltv_expiry_delta: channel_upd.cltv_expiry_delta,
+					fee_base_msat: channel_upd.fee_base_msat,
+					fee_proportional_millionths: channel_upd.fee_proportional_millionths,
+				},
+				payment_constraints: PaymentConstraints {
+					max_cltv_expiry: u32::max_value(),
+					htlc_minimum_msat: channel_upd.htlc_minimum_msat,
+				},
+				features: BlindedHopFeatures::empty(),
+			},
+			htlc_maximum_msat: channel_upd.htlc_maximum_msat,
+		});
+	}
+	let payee_tlvs = ReceiveTlvs {
+		payment_secret,
+		payment_constraints: PaymentConstraints {
+			max_cltv_expiry: u32::max_value(),
+			htlc_minimum_msat: channel_upds.last().map_or(1, |upd| upd.htlc_minimum_msat),
+		},
+	};
+	let htlc_maximum_msat = channel_upds.last().map_or(u64::max_value(), |upd| upd.htlc_maximum_msat);
+	let secp_ctx = Secp256k1::new();
+	BlindedPath::new_for_payment(
+		&intermediate_nodes[..], *node_ids.last().unwrap(), payee_tlvs, htlc_maximum_msat,
+		keys_manager, &secp_ctx
+	).unwrap()
+}
+
+#[test]
+fn one_hop_blinded_path() {
+	let chanmon_cfgs = create_chanmon_cfgs(2);
+	let node_cfgs = create_node_cfgs(2, &chanmon_cfgs);
+	let node_chanmgrs = create_node_chanmgrs(2, &node_cfgs, &[None, None]);
+	let nodes = create_network(2, &node_cfgs, &node_chanmgrs);
+	create_announced_chan_between_nodes_with_value(&nodes, 0, 1, 1_000_000, 0);
+
+	let amt_msat = 5000;
+	let (payment_preimage, payment_hash, payment_secret) = get_payment_preimage_hash(&nodes[1], Some(amt_msat), None);
+	let blinded_path = blinded_payment_path(
+		payment_secret, vec![nodes[1].node.get_our_node_id()], &[], &chanmon_cfgs[1].keys_manager
+	);
+
+	let route_params = RouteParameters {
+		payment_params: PaymentParameters::blinded(vec![blinded_path]),
+		final_value_msat: amt_msat,
+	};
+	nodes[0].node.send_payment(payment_hash, RecipientOnionFields::spontaneous_empty(),
+		PaymentId(payment_hash.0), route_params, Retry::Attempts(0)).unwrap();
+	check_added_monitors(&nodes[0], 1);
+	pass_along_route(&nodes[0], &[&[&nodes[1]]], amt_msat, payment_hash, payment_secret);
+	claim_payment(&nodes[0], &[&nodes[1]], payment_preimage);
+}
+
+#[test]
+fn multi_hop_blinded_path() {
+	let chanmon_cfgs = create_chanmon_cfgs(4);
+	let node_cfgs = create_node_cfgs(4, &chanmon_cfgs);
+	let node_chanmgrs = create_node_chanmgrs(4, &node_cfgs, &[None, None, None, None]);
+	let nodes = create_network(4, &node_cfgs, &node_chanmgrs);
+	create_announced_chan_between_nodes_with_value(&nodes, 0, 1, 1_000_000, 0);
+	let chan_upd_1_2 = create_announced_chan_between_nodes_with_value(&nodes, 1, 2, 1_000_000, 0).0.contents;
+	let chan_upd_2_3 = create_announced_chan_between_nodes_with_value(&nodes, 2, 3, 1_000_000, 0).0.contents;
+
+	let amt_msat = 5000;
+	let (payment_preimage, payment_hash, payment_secret) = get_payment_preimage_hash(&nodes[3], Some(amt_msat), None);
+	let blinded_path = blinded_payment_path(
+		payment_secret,
+		vec![nodes[1].node.get_our_node_id(), nodes[2].node.get_our_node_id(), nodes[3].node.get_our_node_id()],
+		&[&chan_upd_1_2, &chan_upd_2_3], &chanmon_cfgs[3].keys_manager
+	);
+
+	let route_params = RouteParameters {
+		payment_params: PaymentParameters::blinded(vec![blinded_path]),
+		final_value_msat: amt_msat,
+	};
+	nodes[0].node.send_payment(payment_hash, RecipientOnionFields::spontaneous_empty(),
+		PaymentId(payment_hash.0), route_params, Retry::Attempts(0)).unwrap();
+	check_added_monitors(&nodes[0], 1);
+	pass_along_route(&nodes[0], &[&[&nodes[1], &nodes[2], &nodes[3]]], amt_msat, payment_hash, payment_secret);
+	claim_payment(&nodes[0], &[&nodes[1], &nodes[2], &nodes[3]], payment_preimage);
+}
+
+#[test]
+fn retries_after_blinded_intro_node_failure() {
+	// If the introduction node of a blinded path fails the HTLC, the payer can't tell where within
+	// the blinded path the failure happened, so it should avoid the whole path when retrying.
+	let chanmon_cfgs = create_chanmon_cfgs(4);
+	let node_cfgs = create_node_cfgs(4, &chanmon_cfgs);
+	let node_chanmgrs = create_node_chanmgrs(4, &node_cfgs, &[None, None, None, None]);
+	let nodes = create_network(4, &node_cfgs, &node_chanmgrs);
+	create_announced_chan_between_nodes_with_value(&nodes, 0, 1, 1_000_000, 0);
+	create_announced_chan_between_nodes_with_value(&nodes, 0, 2, 1_000_000, 0);
+	let mut chan_upd_1_3 = create_announced_chan_between_nodes_with_value(&nodes, 1, 3, 1_000_000, 0).0.contents;
+	let chan_upd_2_3 = create_announced_chan_between_nodes_with_value(&nodes, 2, 3, 1_000_000, 0).0.contents;
+
+	let amt_msat = 5000;
+	let (payment_preimage, payment_hash, payment_secret) = get_payment_preimage_hash(&nodes[3], Some(amt_msat), None);
+
+	// The first blinded path asks its introduction node to forward over a channel it doesn't have,
+	// and is cheaper than the second so the router tries it first.
+	let unknown_scid = 0xdeadbeef;
+	chan_upd_1_3.short_channel_id = unknown_scid;
+	chan_upd_1_3.fee_base_msat = 0;
+	let failing_path = blinded_payment_path(
+		payment_secret, vec![nodes[1].node.get_our_node_id(), nodes[3].node.get_our_node_id()],
+		&[&chan_upd_1_3], &chanmon_cfgs[3].keys_manager
+	);
+	let working_path = blinded_payment_path(
+		payment_secret, vec![nodes[2].node.get_our_node_id(), nodes[3].node.get_our_node_id()],
+		&[&chan_upd_2_3], &chanmon_cfgs[3].keys_manager
+	);
+
+	let route_params = RouteParameters {
+		payment_params: PaymentParameters::blinded(vec![failing_path, working_path]),
+		final_value_msat: amt_msat,
+	};
+	nodes[0].node.send_payment(payment_hash, RecipientOnionFields::spontaneous_empty(),
+		PaymentId(payment_hash.0), route_params, Retry::Attempts(1)).unwrap();
+	check_added_monitors(&nodes[0], 1);
+
+	let payment_event = SendEvent::from_node(&nodes[0]);
+	assert_eq!(payment_event.node_id, nodes[1].node.get_our_node_id());
+	nodes[1].node.handle_update_add_htlc(&nodes[0].node.get_our_node_id(), &payment_event.msgs[0]);
+	check_added_monitors(&nodes[1], 0);
+	// The introduction node fails the HTLC back as soon as it's irrevocably committed, as it has no
+	// channel to forward it over.
+	commitment_signed_dance!(nodes[1], nodes[0], payment_event.commitment_msg, false, true);
+	assert!(nodes[1].node.get_and_clear_pending_events().is_empty());
+
+	let updates = get_htlc_update_msgs!(nodes[1], nodes[0].node.get_our_node_id());
+	assert_eq!(updates.update_fail_htlcs.len(), 1);
+	assert!(updates.update_fail_malformed_htlcs.is_empty());
+	nodes[0].node.handle_update_fail_htlc(&nodes[1].node.get_our_node_id(), &updates.update_fail_htlcs[0]);
+	commitment_signed_dance!(nodes[0], nodes[1], updates.commitment_signed, false);
+
+	let events = nodes[0].node.get_and_clear_pending_events();
+	assert_eq!(events.len(), 2);
+	match events[0] {
+		Event::PaymentPathFailed {
+			payment_hash: ev_payment_hash, payment_failed_permanently, ref path, error_code, ..
+		} => {
+			assert_eq!(ev_payment_hash, payment_hash);
+			assert!(!payment_failed_permanently);
+			assert!(path.blinded_tail.is_some());
+			assert_eq!(error_code, Some(INVALID_ONION_BLINDING));
+		},
+		_ => panic!("Unexpected event {:?}", events[0]),
+	}
+	match events[1] {
+		Event::PendingHTLCsForwardable { .. } => {},
+		_ => panic!("Unexpected event {:?}", events[1]),
+	}
+
+	// The retry avoids the failed blinded path.
+	nodes[0].node.process_pending_htlc_forwards();
+	check_added_monitors(&nodes[0], 1);
+	pass_along_route(&nodes[0], &[&[&nodes[2], &nodes[3]]], amt_msat, payment_hash, payment_secret);
+	claim_payment(&nodes[0], &[&nodes[2], &nodes[3]], payment_preimage);
+}
@@ -8,14 +8,15 @@
 // licenses.
 
 use bitcoin::blockdata::script::{Script,Builder};
-use bitcoin::blockdata::transaction::{Transaction, EcdsaSighashType};
+use bitcoin::blockdata::transaction::{Transaction, TxIn, TxOut, EcdsaSighashType};
+use bitcoin::PackedLockTime;
 use bitcoin::util::sighash;
 use bitcoin::consensus::encode;
 
-use bitcoin::hashes::Hash;
+use bitcoin::hashes::{Hash, HashEngine};
 use bitcoin::hashes::sha256::Hash as Sha256;
 use bitcoin::hashes::sha256d::Hash as Sha256d;
-use bitcoin::hash_types::{Txid, BlockHash};
+use bitcoin::hash_types::{Txid, BlockHash, WScriptHash};
 
 use bitcoin::secp256k1::constants::PUBLIC_KEY_SIZE;
 use bitcoin::secp256k1::{PublicKey,SecretKey};
@@ -27,22 +28,24 @@ use crate::ln::features::{ChannelTypeFeatures, InitFeatures};
 use crate::ln::msgs;
 use crate::ln::msgs::DecodeError;
 use crate::ln::script::{self, ShutdownScript};
-use crate::ln::channelmanager::{self, CounterpartyForwardingInfo, PendingHTLCStatus, HTLCSource, SentHTLCId, HTLCFailureMsg, PendingHTLCInfo, RAACommitmentOrder, BREAKDOWN_TIMEOUT, MIN_CLTV_EXPIRY_DELTA, MAX_LOCAL_BREAKDOWN_TIMEOUT, ChannelShutdownState};
+use crate::ln::channelmanager::{self, CounterpartyForwardingInfo, PendingHTLCStatus, HTLCSource, SentHTLCId, HTLCFailureMsg, PendingHTLCInfo, RAACommitmentOrder, BREAKDOWN_TIMEOUT, MIN_CLTV_EXPIRY_DELTA, MAX_LOCAL_BREAKDOWN_TIMEOUT, ChannelShutdownState, ChannelQuiescenceState};
 use crate::ln::chan_utils::{CounterpartyCommitmentSecrets, TxCreationKeys, HTLCOutputInCommitment, htlc_success_tx_weight, htlc_timeout_tx_weight, make_funding_redeemscript, ChannelPublicKeys, CommitmentTransaction, HolderCommitmentTransaction, ChannelTransactionParameters, CounterpartyChannelTransactionParameters, MAX_HTLCS, get_commitment_transaction_number_obscure_factor, ClosingTransaction};
 use crate::ln::chan_utils;
+use crate::ln::interactivetxs::{AbortReason, ConstructedTransaction, InteractiveTxConstructor, InteractiveTxMessageSend, InteractiveTxSigningSession, SharedInput, SharedInputSignature, SharedOutput, estimate_contribution_weight};
 use crate::ln::onion_utils::HTLCFailReason;
 use crate::chain::BestBlock;
-use crate::chain::chaininterface::{FeeEstimator, ConfirmationTarget, LowerBoundedFeeEstimator};
-use crate::chain::channelmonitor::{ChannelMonitor, ChannelMonitorUpdate, ChannelMonitorUpdateStep, LATENCY_GRACE_PERIOD_BLOCKS, CLOSED_CHANNEL_UPDATE_ID};
+use crate::chain::chaininterface::{FeeEstimator, ConfirmationTarget, LowerBoundedFeeEstimator, fee_for_weight};
+use crate::chain::channelmonitor::{ChannelMonitor, ChannelMonitorUpdate, ChannelMonitorUpdateStep, SpliceCounterpartyCommitmentTx, LATENCY_GRACE_PERIOD_BLOCKS, CLOSED_CHANNEL_UPDATE_ID, ANTI_REORG_DELAY};
 use crate::chain::transaction::{OutPoint, TransactionData};
 use crate::sign::{WriteableEcdsaChannelSigner, EntropySource, ChannelSigner, SignerProvider, NodeSigner, Recipient};
 use crate::events::ClosureReason;
 use crate::routing::gossip::NodeId;
-use crate::util::ser::{Readable, ReadableArgs, Writeable, Writer, VecWriter};
+use crate::util::ser::{Readable, ReadableArgs, TransactionU16LenLimited, Writeable, Writer, VecWriter};
 use crate::util::logger::Logger;
 use crate::util::errors::APIError;
 use crate::util::config::{UserConfig, ChannelConfig, LegacyChannelConfig, ChannelHandshakeConfig, ChannelHandshakeLimits, MaxDustHTLCExposure};
 use crate::util::scid_utils::scid_from_parts;
+use crate::util::string::PrintableString;
 
 use crate::io;
 use crate::prelude::*;
@@ -224,6 +227,7 @@ struct OutboundHTLCOutput {
 	payment_hash: PaymentHash,
 	state: OutboundHTLCState,
 	source: HTLCSource,
+	blinding_point: Option<PublicKey>,
 	skimmed_fee_msat: Option<u64>,
 }
 
@@ -238,6 +242,7 @@ enum HTLCUpdateAwaitingACK {
 		onion_routing_packet: msgs::OnionPacket,
 		// The extra fee we're skimming off the top of this HTLC.
 		skimmed_fee_msat: Option<u64>,
+		blinding_point: Option<PublicKey>,
 	},
 	ClaimHTLC {
 		payment_preimage: PaymentPreimage,
@@ -247,6 +252,67 @@ enum HTLCUpdateAwaitingACK {
 		htlc_id: u64,
 		err_packet: msgs::OnionErrorPacket,
 	},
+	FailMalformedHTLC {
+		htlc_id: u64,
+		failure_code: u16,
+		sha256_of_onion: [u8; 32],
+	},
+}
+
+/// The contents of a failure we may send to our counterparty for an inbound HTLC, allowing the
+/// same failure logic to be used for both `update_fail_htlc` and `update_fail_malformed_htlc`.
+trait FailHTLCContents {
+	type Message: FailHTLCMessageName;
+	fn to_message(self, htlc_id: u64, channel_id: [u8; 32]) -> Self::Message;
+	fn to_inbound_htlc_state(self) -> InboundHTLCState;
+	fn to_htlc_update_awaiting_ack(self, htlc_id: u64) -> HTLCUpdateAwaitingACK;
+}
+impl FailHTLCContents for msgs::OnionErrorPacket {
+	type Message = msgs::UpdateFailHTLC;
+	fn to_message(self, htlc_id: u64, channel_id: [u8; 32]) -> Self::Message {
+		msgs::UpdateFailHTLC { htlc_id, channel_id, reason: self }
+	}
+	fn to_inbound_htlc_state(self) -> InboundHTLCState {
+		InboundHTLCState::LocalRemoved(InboundHTLCRemovalReason::FailRelay(self))
+	}
+	fn to_htlc_update_awaiting_ack(self, htlc_id: u64) -> HTLCUpdateAwaitingACK {
+		HTLCUpdateAwaitingACK::FailHTLC { htlc_id, err_packet: self }
+	}
+}
+impl FailHTLCContents for ([u8; 32], u16) {
+	type Message = msgs::UpdateFailMalformedHTLC;
+	fn to_message(self, htlc_id: u64, channel_id: [u8; 32]) -> Self::Message {
+		msgs::UpdateFailMalformedHTLC {
+			htlc_id,
+			channel_id,
+			sha256_of_onion: self.0,
+			failure_code: self.1
+		}
+	}
+	fn to_inbound_htlc_state(self) -> InboundHTLCState {
+		InboundHTLCState::LocalRemoved(InboundHTLCRemovalReason::FailMalformed(self))
+	}
+	fn to_htlc_update_awaiting_ack(self, htlc_id: u64) -> HTLCUpdateAwaitingACK {
+		HTLCUpdateAwaitingACK::FailMalformedHTLC {
+			htlc_id,
+			sha256_of_onion: self.0,
+			failure_code: self.1
+		}
+	}
+}
+
+trait FailHTLCMessageName {
+	fn name() -> &'static str;
+}
+impl FailHTLCMessageName for msgs::UpdateFailHTLC {
+	fn name() -> &'static str {
+		"update_fail_htlc"
+	}
+}
+impl FailHTLCMessageName for msgs::UpdateFailMalformedHTLC {
+	fn name() -> &'static str {
+		"update_fail_malformed_htlc"
+	}
 }
 
 /// There are a few "states" and then a number of flags which can be applied:
@@ -256,6 +322,8 @@ enum HTLCUpdateAwaitingACK {
 /// Note that `PeerDisconnected` can be set on both `ChannelReady` and `FundingSent`.
 /// `ChannelReady` can then get all remaining flags set on it, until we finish shutdown, then we
 /// move on to `ShutdownComplete`, at which point most calls into this channel are disallowed.
+/// The quiescence flags (`AwaitingQuiescence` through `Quiescent`) may only be set on a live
+/// `ChannelReady` channel and are never persisted.
 enum ChannelState {
 	/// Implies we have (or are prepared to) send our open_channel/accept_channel message
 	OurInitSent = 1 << 0,
@@ -301,9 +369,25 @@ enum ChannelState {
 	/// We've successfully negotiated a closing_signed dance. At this point ChannelManager is about
 	/// to drop us, but we store this anyway.
 	ShutdownComplete = 4096,
+	/// Flag which is set on `ChannelReady` when we wish to make the channel quiescent but cannot
+	/// send our `stfu` yet as we still have updates pending. While set, we won't propose any new
+	/// updates, placing them in the holding cell instead.
+	AwaitingQuiescence = 1 << 13,
+	/// Flag which is set on `ChannelReady` after we've sent `stfu` but before we've received one
+	/// from our counterparty. We may not send any update messages once set.
+	LocalStfuSent = 1 << 14,
+	/// Flag which is set on `ChannelReady` after receiving `stfu` from our counterparty but before
+	/// we've been able to respond with our own. Our counterparty may not send us any update
+	/// messages once set.
+	RemoteStfuSent = 1 << 15,
+	/// Flag which is set on `ChannelReady` once both sides have exchanged `stfu`. Neither side may
+	/// send update messages until the protocol which required quiescence completes or the peer
+	/// disconnects.
+	Quiescent = 1 << 16,
 }
 const BOTH_SIDES_SHUTDOWN_MASK: u32 = ChannelState::LocalShutdownSent as u32 | ChannelState::RemoteShutdownSent as u32;
-const MULTI_STATE_FLAGS: u32 = BOTH_SIDES_SHUTDOWN_MASK | ChannelState::PeerDisconnected as u32 | ChannelState::MonitorUpdateInProgress as u32;
+const QUIESCENCE_STATE_FLAGS: u32 = ChannelState::AwaitingQuiescence as u32 | ChannelState::LocalStfuSent as u32 | ChannelState::RemoteStfuSent as u32 | ChannelState::Quiescent as u32;
+const MULTI_STATE_FLAGS: u32 = BOTH_SIDES_SHUTDOWN_MASK | ChannelState::PeerDisconnected as u32 | ChannelState::MonitorUpdateInProgress as u32 | QUIESCENCE_STATE_FLAGS;
 
 pub const INITIAL_COMMITMENT_NUMBER: u64 = (1 << 48) - 1;
 
@@ -316,7 +400,7 @@ pub(crate) fn commitment_tx_base_weight(channel_type_features: &ChannelTypeFeatu
 }
 
 #[cfg(not(test))]
-const COMMITMENT_TX_WEIGHT_PER_HTLC: u64 = 172;
+pub(crate) const COMMITMENT_TX_WEIGHT_PER_HTLC: u64 = 172;
 #[cfg(test)]
 pub const COMMITMENT_TX_WEIGHT_PER_HTLC: u64 = 172;
 
@@ -364,6 +448,9 @@ pub const MIN_THEIR_CHAN_RESERVE_SATOSHIS: u64 = 1000;
 pub(super) enum ChannelError {
 	Ignore(String),
 	Warn(String),
+	/// Sends a warning and disconnects the peer, resetting any uncommitted channel state without
+	/// closing the channel.
+	WarnAndDisconnect(String),
 	Close(String),
 }
 
@@ -372,6 +459,7 @@ impl fmt::Debug for ChannelError {
 		match self {
 			&ChannelError::Ignore(ref e) => write!(f, "Ignore : {}", e),
 			&ChannelError::Warn(ref e) => write!(f, "Warn : {}", e),
+			&ChannelError::WarnAndDisconnect(ref e) => write!(f, "Disconnecting with warning : {}", e),
 			&ChannelError::Close(ref e) => write!(f, "Close : {}", e),
 		}
 	}
@@ -382,6 +470,7 @@ impl fmt::Display for ChannelError {
 		match self {
 			&ChannelError::Ignore(ref e) => write!(f, "{}", e),
 			&ChannelError::Warn(ref e) => write!(f, "{}", e),
+			&ChannelError::WarnAndDisconnect(ref e) => write!(f, "{}", e),
 			&ChannelError::Close(ref e) => write!(f, "{}", e),
 		}
 	}
@@ -514,6 +603,7 @@ pub(super) struct MonitorRestoreUpdates {
 	pub funding_broadcastable: Option<Transaction>,
 	pub channel_ready: Option<msgs::ChannelReady>,
 	pub announcement_sigs: Option<msgs::AnnouncementSignatures>,
+	pub tx_signatures: Option<msgs::TxSignatures>,
 }
 
 /// The return value of `channel_reestablish`
@@ -524,16 +614,23 @@ pub(super) struct ReestablishResponses {
 	pub order: RAACommitmentOrder,
 	pub announcement_sigs: Option<msgs::AnnouncementSignatures>,
 	pub shutdown_msg: Option<msgs::Shutdown>,
+	pub tx_signatures: Option<msgs::TxSignatures>,
+	pub splice_locked: Option<msgs::SpliceLocked>,
+	pub monitor_update: Option<ChannelMonitorUpdate>,
 }
 
 /// The return type of `force_shutdown`
 ///
 /// Contains a (counterparty_node_id, funding_txo, [`ChannelMonitorUpdate`]) tuple
 /// followed by a list of HTLCs to fail back in the form of the (source, payment hash, and this
-/// channel's counterparty_node_id and channel_id).
+/// channel's counterparty_node_id and channel_id), (funding_txo, [`ChannelMonitorUpdate`])
+/// tuples for the monitors of a pending splice's new funding outputs, and finally the txid of the
+/// batch funding transaction this channel was part of, if it has not yet been broadcast.
 pub(crate) type ShutdownResult = (
 	Option<(PublicKey, OutPoint, ChannelMonitorUpdate)>,
-	Vec<(HTLCSource, PaymentHash, PublicKey, [u8; 32])>
+	Vec<(HTLCSource, PaymentHash, PublicKey, [u8; 32])>,
+	Vec<(OutPoint, ChannelMonitorUpdate)>,
+	Option<Txid>,
 );
 
 /// If the majority of the channels funds are to the fundee and the initiator holds only just
@@ -590,6 +687,14 @@ pub(crate) const EXPIRE_PREV_CONFIG_TICKS: usize = 5;
 /// See [`ChannelContext::sent_message_awaiting_response`] for more information.
 pub(crate) const DISCONNECT_PEER_AWAITING_RESPONSE_TICKS: usize = 2;
 
+/// The number of ticks that may elapse after either side proposed quiescence before we give up
+/// waiting for the channel to become quiescent and disconnect the peer, as well as the number of
+/// ticks we wait on our counterparty during a protocol we run while quiescent, such as a channel
+/// type upgrade. If we proposed quiescence, it will be proposed again once the peer reconnects.
+///
+/// See [`ChannelContext::quiescence_timer_ticks`] for more information.
+pub(crate) const DISCONNECT_PEER_AWAITING_QUIESCENCE_TICKS: usize = 3;
+
 /// The number of ticks that may elapse while we're waiting for an unfunded outbound/inbound channel
 /// to be promoted to a [`Channel`] since the unfunded channel was created. An unfunded channel
 /// exceeding this age limit will be force-closed and purged from memory.
@@ -800,6 +905,10 @@ pub(super) struct ChannelContext<Signer: ChannelSigner> {
 
 	pub(crate) channel_transaction_parameters: ChannelTransactionParameters,
 	funding_transaction: Option<Transaction>,
+	/// Set if the funding transaction also funds other channels, in which case it is only
+	/// broadcast (and `funding_transaction` cleared) once all channels in the batch have received
+	/// their counterparty's `funding_signed`.
+	is_batch_funding: Option<()>,
 
 	counterparty_cur_commitment_point: Option<PublicKey>,
 	counterparty_prev_commitment_point: Option<PublicKey>,
@@ -853,6 +962,24 @@ pub(super) struct ChannelContext<Signer: ChannelSigner> {
 	/// [`msgs::RevokeAndACK`] message from the counterparty.
 	sent_message_awaiting_response: Option<usize>,
 
+	/// The number of ticks elapsed since either side proposed quiescence, or since we started
+	/// waiting on our counterparty during a channel type upgrade, or `None` if we're not waiting
+	/// on them. If the channel is still not quiescent after
+	/// `DISCONNECT_PEER_AWAITING_QUIESCENCE_TICKS`, we disconnect the peer, as our counterparty
+	/// keeps updating the channel or isn't responding to our `stfu`. Likewise, we disconnect the
+	/// peer if the upgrade doesn't progress in time, as the channel can't be used until then.
+	///
+	/// Once quiescent for a protocol run outside of the channel, the channel remains so for as
+	/// long as that protocol takes, and thus isn't timed out.
+	///
+	/// Ticks don't elapse while the peer is disconnected.
+	quiescence_timer_ticks: Option<usize>,
+
+	/// Whether we are the initiator of the quiescence session, i.e. the side which gets to run the
+	/// protocol requiring quiescence. Only set while both sides are exchanging `stfu` or once the
+	/// channel is quiescent.
+	is_holder_quiescence_initiator: Option<bool>,
+
 	#[cfg(any(test, fuzzing))]
 	// When we receive an HTLC fulfill on an outbound path, we may immediately fulfill the
 	// corresponding HTLC on the inbound path. If, then, the outbound path channel is
@@ -896,6 +1023,26 @@ pub(super) struct ChannelContext<Signer: ChannelSigner> {
 	/// If we can't release a [`ChannelMonitorUpdate`] until some external action completes, we
 	/// store it here and only release it to the `ChannelManager` once it asks for it.
 	blocked_monitor_updates: Vec<PendingChannelMonitorUpdate>,
+
+	/// For channels whose funding transaction was negotiated interactively (i.e. dual-funded
+	/// channels), tracks the exchange of `tx_signatures` for the funding transaction.
+	interactive_tx_signing_session: Option<InteractiveTxSigningSession>,
+	/// Set if we were ready to send our `tx_signatures` while a [`ChannelMonitor`] update was in
+	/// progress, in which case we hold them until the update completes.
+	monitor_pending_tx_signatures: bool,
+
+	/// A splice of this channel which has yet to be locked by both parties, if any.
+	pending_splice: Option<PendingSplice<Signer>>,
+	/// The `commitment_signed` messages of a batch our counterparty is sending us while a splice is
+	/// pending, which we only handle once the whole batch has been received.
+	pending_commitment_signed_batch: Vec<msgs::CommitmentSigned>,
+	/// Set when we promote a splice upon receiving our counterparty's `splice_locked`, as they may
+	/// not have received ours, in which case we retransmit it upon reconnection. Cleared once they
+	/// send a `commitment_signed` for the new funding output.
+	splice_locked_pending_ack: bool,
+
+	/// An upgrade of this channel's type which has yet to be committed to by both parties, if any.
+	pending_channel_type_upgrade: Option<PendingChannelTypeUpgrade<Signer>>,
 }
 
 impl<Signer: ChannelSigner> ChannelContext<Signer> {
@@ -951,6 +1098,17 @@ impl<Signer: ChannelSigner> ChannelContext<Signer> {
 		return ChannelShutdownState::NotShuttingDown;
 	}
 
+	/// Returns the state of the channel with respect to quiescence.
+	pub fn quiescence_state(&self) -> ChannelQuiescenceState {
+		if self.channel_state & (ChannelState::Quiescent as u32) != 0 {
+			return ChannelQuiescenceState::Quiescent;
+		}
+		if self.channel_state & QUIESCENCE_STATE_FLAGS != 0 {
+			return ChannelQuiescenceState::AwaitingQuiescence;
+		}
+		ChannelQuiescenceState::NotQuiescent
+	}
+
 	fn closing_negotiation_ready(&self) -> bool {
 		self.pending_inbound_htlcs.is_empty() &&
 		self.pending_outbound_htlcs.is_empty() &&
@@ -997,6 +1155,11 @@ impl<Signer: ChannelSigner> ChannelContext<Signer> {
 		&self.channel_type
 	}
 
+	/// Gets the number of HTLCs currently pending in either direction.
+	pub fn get_pending_htlc_count(&self) -> usize {
+		self.pending_inbound_htlcs.len() + self.pending_outbound_htlcs.len()
+	}
+
 	/// Gets the channel's `short_channel_id`.
 	///
 	/// Will return `None` if the channel hasn't been confirmed yet.
@@ -1032,6 +1195,263 @@ impl<Signer: ChannelSigner> ChannelContext<Signer> {
 		self.funding_tx_confirmed_in
 	}
 
+	/// Returns true if a splice of this channel is pending, from its initiation until its new
+	/// funding transaction is locked.
+	pub fn has_pending_splice(&self) -> bool {
+		self.pending_splice.is_some()
+	}
+
+	/// Returns the funding outputs of the candidate funding transactions of a pending splice for
+	/// which a [`ChannelMonitor`] has been created.
+	pub fn get_pending_splice_funding_txos(&self) -> Vec<OutPoint> {
+		self.pending_splice.as_ref().map_or(Vec::new(), |splice| splice.candidates.iter()
+			.filter(|candidate| candidate.monitor_created)
+			.filter_map(|candidate| candidate.funding_txo)
+			.collect())
+	}
+
+	/// Returns the confirmed candidate funding transactions of a pending splice, as well as any
+	/// confirmed transactions double-spending them, along with the block hash in which they were
+	/// confirmed.
+	pub fn get_pending_splice_txs_confirmed_in(&self) -> Vec<(Txid, BlockHash)> {
+		let mut res = Vec::new();
+		if let Some(splice) = self.pending_splice.as_ref() {
+			for candidate in splice.candidates.iter() {
+				if let (Some(funding_txo), Some(block_hash)) = (candidate.funding_txo, candidate.funding_tx_confirmed_in) {
+					res.push((funding_txo.txid, block_hash));
+				}
+				if let (Some(txid), Some(block_hash)) = (candidate.conflicting_txid, candidate.conflicting_tx_confirmed_in) {
+					res.push((txid, block_hash));
+				}
+			}
+		}
+		res
+	}
+
+	/// Switches the channel over to the given channel type as part of an upgrade, along with the
+	/// signer whose channel parameters were provided with it, returning the previous signer.
+	fn apply_channel_type_upgrade(&mut self, channel_type: ChannelTypeFeatures, holder_signer: Signer) -> Signer {
+		self.channel_type = channel_type.clone();
+		self.channel_transaction_parameters.channel_type_features = channel_type;
+		mem::replace(&mut self.holder_signer, holder_signer)
+	}
+
+	/// Returns the signing session of the latest candidate funding transaction of a pending
+	/// splice, if it has been negotiated.
+	fn pending_splice_signing_session(&self) -> Option<&InteractiveTxSigningSession> {
+		self.pending_splice.as_ref().and_then(|splice| splice.candidates.last())
+			.and_then(|candidate| candidate.signing_session.as_ref())
+	}
+
+	fn get_funding_balances(&self) -> FundingBalances {
+		FundingBalances {
+			channel_value_satoshis: self.channel_value_satoshis,
+			value_to_self_msat: self.value_to_self_msat,
+			holder_selected_channel_reserve_satoshis: self.holder_selected_channel_reserve_satoshis,
+			counterparty_selected_channel_reserve_satoshis: self.counterparty_selected_channel_reserve_satoshis,
+		}
+	}
+
+	/// Returns the channel value, our balance and the reserves once the pending splice, if any,
+	/// is promoted. These are the same for all of its candidate funding transactions.
+	fn get_splice_funding_balances(&self) -> Option<FundingBalances> {
+		let splice = self.pending_splice.as_ref()?;
+		let channel_value_satoshis = (self.channel_value_satoshis as i64 +
+			splice.our_funding_contribution_satoshis + splice.their_funding_contribution_satoshis) as u64;
+		Some(FundingBalances {
+			channel_value_satoshis,
+			value_to_self_msat: (self.value_to_self_msat as i64 + splice.our_funding_contribution_satoshis * 1000) as u64,
+			holder_selected_channel_reserve_satoshis:
+				get_v2_channel_reserve_satoshis(channel_value_satoshis, self.counterparty_dust_limit_satoshis),
+			counterparty_selected_channel_reserve_satoshis:
+				Some(get_v2_channel_reserve_satoshis(channel_value_satoshis, self.holder_dust_limit_satoshis)),
+		})
+	}
+
+	fn replace_funding_balances(&mut self, balances: FundingBalances) -> FundingBalances {
+		let prev_balances = self.get_funding_balances();
+		self.channel_value_satoshis = balances.channel_value_satoshis;
+		self.value_to_self_msat = balances.value_to_self_msat;
+		self.holder_selected_channel_reserve_satoshis = balances.holder_selected_channel_reserve_satoshis;
+		self.counterparty_selected_channel_reserve_satoshis = balances.counterparty_selected_channel_reserve_satoshis;
+		prev_balances
+	}
+
+	/// Runs `f` with the funding output, channel value, balance, reserves and signer of the channel
+	/// swapped for those of the candidate funding transaction of the pending splice at index `idx`,
+	/// allowing the commitment transactions spending its funding output to be built and signed.
+	///
+	/// Panics if there is no such candidate.
+	fn with_splice_funding<R, F: FnOnce(&mut Self) -> R>(&mut self, idx: usize, f: F) -> R {
+		let balances = self.get_splice_funding_balances().expect("No pending splice");
+		let mut splice = self.pending_splice.take().unwrap();
+		let candidate = &mut splice.candidates[idx];
+		let prev_balances = self.replace_funding_balances(balances);
+		mem::swap(&mut self.channel_transaction_parameters.funding_outpoint, &mut candidate.funding_txo);
+		mem::swap(&mut self.holder_signer, &mut candidate.holder_signer);
+		// The commitment transactions spending the new funding output may pay less to either
+		// party than the current ones, which is fine as they are never an update of the latter.
+		#[cfg(debug_assertions)]
+		let max_commitment_tx_outputs = (
+			mem::replace(&mut *self.holder_max_commitment_tx_output.lock().unwrap(), (0, 0)),
+			mem::replace(&mut *self.counterparty_max_commitment_tx_output.lock().unwrap(), (0, 0)),
+		);
+		let res = f(self);
+		#[cfg(debug_assertions)] {
+			*self.holder_max_commitment_tx_output.lock().unwrap() = max_commitment_tx_outputs.0;
+			*self.counterparty_max_commitment_tx_output.lock().unwrap() = max_commitment_tx_outputs.1;
+		}
+		mem::swap(&mut self.channel_transaction_parameters.funding_outpoint, &mut candidate.funding_txo);
+		mem::swap(&mut self.holder_signer, &mut candidate.holder_signer);
+		self.replace_funding_balances(prev_balances);
+		self.pending_splice = Some(splice);
+		res
+	}
+
+	/// Checks that our counterparty can afford an HTLC of `amount_msat` they're adding to the
+	/// channel, given the balances of the funding output the commitment transactions spend.
+	/// Returns whether it violates the fee spike buffer we require, in which case we fail it
+	/// back.
+	fn check_remote_htlc_add_balance(
+		&self, balances: &FundingBalances, amount_msat: u64, pending_inbound_htlcs_value_msat: u64,
+		removed_outbound_total_msat: u64,
+	) -> Result<bool, ChannelError> {
+		let pending_value_to_self_msat =
+			balances.value_to_self_msat + pending_inbound_htlcs_value_msat - removed_outbound_total_msat;
+		let pending_remote_value_msat =
+			balances.channel_value_satoshis * 1000 - pending_value_to_self_msat;
+		if pending_remote_value_msat < amount_msat {
+			return Err(ChannelError::Close("Remote HTLC add would overdraw remaining funds".to_owned()));
+		}
+
+		// Check that the remote can afford to pay for this HTLC on-chain at the current
+		// feerate_per_kw, while maintaining their channel reserve (as required by the spec).
+		let remote_commit_tx_fee_msat = if self.is_outbound() { 0 } else {
+			let htlc_candidate = HTLCCandidate::new(amount_msat, HTLCInitiator::RemoteOffered);
+			self.next_remote_commit_tx_fee_msat(htlc_candidate, None) // Don't include the extra fee spike buffer HTLC in calculations
+		};
+		if pending_remote_value_msat - amount_msat < remote_commit_tx_fee_msat {
+			return Err(ChannelError::Close("Remote HTLC add would not leave enough to pay for fees".to_owned()));
+		};
+
+		if pending_remote_value_msat - amount_msat - remote_commit_tx_fee_msat < balances.holder_selected_channel_reserve_satoshis * 1000 {
+			return Err(ChannelError::Close("Remote HTLC add would put them under remote reserve value".to_owned()));
+		}
+
+		if !self.is_outbound() {
+			// `2 *` and `Some(())` is for the fee spike buffer we keep for the remote. This deviates from
+			// the spec because in the spec, the fee spike buffer requirement doesn't exist on the
+			// receiver's side, only on the sender's.
+			// Note that when we eventually remove support for fee updates and switch to anchor output
+			// fees, we will drop the `2 *`, since we no longer be as sensitive to fee spikes. But, keep
+			// the extra htlc when calculating the next remote commitment transaction fee as we should
+			// still be able to afford adding this HTLC plus one more future HTLC, regardless of being
+			// sensitive to fee spikes.
+			let htlc_candidate = HTLCCandidate::new(amount_msat, HTLCInitiator::RemoteOffered);
+			let remote_fee_cost_incl_stuck_buffer_msat = 2 * self.next_remote_commit_tx_fee_msat(htlc_candidate, Some(()));
+			Ok(pending_remote_value_msat - amount_msat - balances.holder_selected_channel_reserve_satoshis * 1000 < remote_fee_cost_incl_stuck_buffer_msat)
+		} else {
+			// Check that they won't violate our local required channel reserve by adding this HTLC.
+			let htlc_candidate = HTLCCandidate::new(amount_msat, HTLCInitiator::RemoteOffered);
+			let local_commit_tx_fee_msat = self.next_local_commit_tx_fee_msat(htlc_candidate, None);
+			if balances.value_to_self_msat < balances.counterparty_selected_channel_reserve_satoshis.unwrap() * 1000 + local_commit_tx_fee_msat {
+				return Err(ChannelError::Close("Cannot accept HTLC that would put our balance under counterparty-announced channel reserve value".to_owned()));
+			}
+			Ok(false)
+		}
+	}
+
+	/// Returns the index and txid of each candidate funding transaction of a pending splice whose
+	/// funding output is watched by a [`ChannelMonitor`]. Every commitment transaction we exchange
+	/// with our counterparty must be signed for each of them as well until the splice locks.
+	fn get_splice_candidates_with_monitor(&self) -> Vec<(usize, Txid)> {
+		self.pending_splice.as_ref().map_or(Vec::new(), |splice| splice.candidates.iter().enumerate()
+			.filter(|(_, candidate)| candidate.monitor_created)
+			.filter_map(|(idx, candidate)| candidate.funding_txo.map(|txo| (idx, txo.txid)))
+			.collect())
+	}
+
+	/// Builds the counterparty commitment transaction at the given commitment number spending the
+	/// funding output of each candidate funding transaction of a pending splice we have a
+	/// [`ChannelMonitor`] for, for the monitors to track alongside the one spending the current
+	/// funding output.
+	fn build_splice_counterparty_commitment_txs<L: Deref>(
+		&mut self, commitment_number: u64, per_commitment_point: &PublicKey, logger: &L
+	) -> Vec<SpliceCounterpartyCommitmentTx> where L::Target: Logger {
+		self.get_splice_candidates_with_monitor().into_iter().map(|(idx, _)| {
+			self.with_splice_funding(idx, |context| {
+				let counterparty_keys = context.build_remote_transaction_keys_for_point(per_commitment_point);
+				let commitment_stats = context.build_commitment_transaction(commitment_number, &counterparty_keys, false, true, logger);
+				SpliceCounterpartyCommitmentTx {
+					funding_txo: context.get_funding_txo().unwrap(),
+					commitment_txid: commitment_stats.tx.trust().txid(),
+					htlc_output_indices: commitment_stats.htlcs_included.iter()
+						.filter_map(|(htlc, _)| htlc.transaction_output_index)
+						.collect(),
+				}
+			})
+		}).collect()
+	}
+
+	/// Checks the `commitment_signed` from our counterparty for the holder commitment transaction
+	/// at the given commitment number spending the funding output of the candidate funding
+	/// transaction of the pending splice at index `idx`, returning it along with its HTLCs.
+	fn validate_splice_holder_commitment_signed<L: Deref>(
+		&mut self, idx: usize, commitment_number: u64, msg: &msgs::CommitmentSigned, check_update_fee: bool, logger: &L
+	) -> Result<(HolderCommitmentTransaction, Vec<(HTLCOutputInCommitment, Option<Signature>, Option<HTLCSource>)>), ChannelError>
+	where L::Target: Logger {
+		let funding_script = self.get_funding_redeemscript();
+		self.with_splice_funding(idx, |context| {
+			let keys = context.build_holder_transaction_keys(commitment_number);
+			let commitment_stats = context.build_commitment_transaction(commitment_number, &keys, true, false, logger);
+			let commitment_txid = {
+				let trusted_tx = commitment_stats.tx.trust();
+				let bitcoin_tx = trusted_tx.built_transaction();
+				let sighash = bitcoin_tx.get_sighash_all(&funding_script, context.channel_value_satoshis);
+				if context.secp_ctx.verify_ecdsa(&sighash, &msg.signature, context.counterparty_funding_pubkey()).is_err() {
+					return Err(ChannelError::Close("Invalid splice commitment tx signature from peer".to_owned()));
+				}
+				bitcoin_tx.txid
+			};
+			if check_update_fee {
+				let counterparty_reserve_we_require_msat = context.holder_selected_channel_reserve_satoshis * 1000;
+				if commitment_stats.remote_balance_msat < commitment_stats.total_fee_sat * 1000 + counterparty_reserve_we_require_msat {
+					return Err(ChannelError::Close("Funding remote cannot afford proposed new fee".to_owned()));
+				}
+			}
+			if msg.htlc_signatures.len() != commitment_stats.num_nondust_htlcs {
+				return Err(ChannelError::Close(format!("Got wrong number of HTLC signatures ({}) from remote. It must be {}", msg.htlc_signatures.len(), commitment_stats.num_nondust_htlcs)));
+			}
+			let mut htlc_outputs = Vec::with_capacity(commitment_stats.htlcs_included.len());
+			for (idx, (htlc, source)) in commitment_stats.htlcs_included.iter().enumerate() {
+				let signature = if htlc.transaction_output_index.is_some() {
+					let htlc_tx = chan_utils::build_htlc_transaction(&commitment_txid, commitment_stats.feerate_per_kw,
+						context.get_counterparty_selected_contest_delay().unwrap(), htlc, &context.channel_type,
+						&keys.broadcaster_delayed_payment_key, &keys.revocation_key);
+					let htlc_redeemscript = chan_utils::get_htlc_redeemscript(htlc, &context.channel_type, &keys);
+					let htlc_sighashtype = if context.channel_type.supports_anchors_zero_fee_htlc_tx() { EcdsaSighashType::SinglePlusAnyoneCanPay } else { EcdsaSighashType::All };
+					let htlc_sighash = hash_to_message!(&sighash::SighashCache::new(&htlc_tx).segwit_signature_hash(0, &htlc_redeemscript, htlc.amount_msat / 1000, htlc_sighashtype).unwrap()[..]);
+					if context.secp_ctx.verify_ecdsa(&htlc_sighash, &msg.htlc_signatures[idx], &keys.countersignatory_htlc_key).is_err() {
+						return Err(ChannelError::Close("Invalid splice HTLC tx signature from peer".to_owned()));
+					}
+					Some(msg.htlc_signatures[idx])
+				} else { None };
+				htlc_outputs.push((htlc.clone(), signature, source.cloned()));
+			}
+
+			let holder_commitment_tx = HolderCommitmentTransaction::new(
+				commitment_stats.tx,
+				msg.signature,
+				msg.htlc_signatures.clone(),
+				&context.get_holder_pubkeys().funding_pubkey,
+				context.counterparty_funding_pubkey()
+			);
+			context.holder_signer.validate_holder_commitment(&holder_commitment_tx, commitment_stats.preimages)
+				.map_err(|_| ChannelError::Close("Failed to validate our splice commitment".to_owned()))?;
+			Ok((holder_commitment_tx, htlc_outputs))
+		})
+	}
+
 	/// Returns the current number of confirmations on the funding transaction.
 	pub fn get_funding_tx_confirmations(&self, height: u32) -> u32 {
 		if self.funding_tx_confirmation_height == 0 {
@@ -1137,9 +1557,27 @@ impl<Signer: ChannelSigner> ChannelContext<Signer> {
 		self.prev_config.map(|prev_config| prev_config.0)
 	}
 
-	// Checks whether we should emit a `ChannelPending` event.
+	// Checks whether we should emit a `ChannelPending` event. For interactively funded channels
+	// this requires that the funding transaction has been fully signed.
 	pub(crate) fn should_emit_channel_pending_event(&mut self) -> bool {
-		self.is_funding_initiated() && !self.channel_pending_event_emitted
+		self.is_funding_initiated() && !self.channel_pending_event_emitted &&
+			!self.is_awaiting_batch_broadcast() &&
+			self.interactive_tx_signing_session.as_ref().map_or(true, |session|
+				session.has_holder_tx_signatures() && session.has_received_tx_signatures())
+	}
+
+	/// Returns whether this channel is funded as part of a batch whose funding transaction has not
+	/// yet been broadcast.
+	pub fn is_awaiting_batch_broadcast(&self) -> bool {
+		self.is_batch_funding.is_some() && self.funding_transaction.is_some()
+	}
+
+	/// Returns the txid of the batch funding transaction this channel is part of, if it has not
+	/// yet been broadcast.
+	pub fn unbroadcasted_batch_funding_txid(&self) -> Option<Txid> {
+		if self.is_awaiting_batch_broadcast() {
+			self.get_funding_txo().map(|txo| txo.txid)
+		} else { None }
 	}
 
 	// Returns whether we already emitted a `ChannelPending` event.
@@ -1469,13 +1907,20 @@ impl<Signer: ChannelSigner> ChannelContext<Signer> {
 	/// will sign and send to our counterparty.
 	/// If an Err is returned, it is a ChannelError::Close (for get_funding_created)
 	fn build_remote_transaction_keys(&self) -> TxCreationKeys {
+		self.build_remote_transaction_keys_for_point(&self.counterparty_cur_commitment_point.unwrap())
+	}
+
+	/// Creates a set of keys for build_commitment_transaction to generate a transaction which we
+	/// will sign and send to our counterparty, using the given per-commitment point of theirs.
+	#[inline]
+	fn build_remote_transaction_keys_for_point(&self, per_commitment_point: &PublicKey) -> TxCreationKeys {
 		//TODO: Ensure that the payment_key derived here ends up in the library users' wallet as we
 		//may see payments to it!
 		let revocation_basepoint = &self.get_holder_pubkeys().revocation_basepoint;
 		let htlc_basepoint = &self.get_holder_pubkeys().htlc_basepoint;
 		let counterparty_pubkeys = self.get_counterparty_pubkeys();
 
-		TxCreationKeys::derive_new(&self.secp_ctx, &self.counterparty_cur_commitment_point.unwrap(), &counterparty_pubkeys.delayed_payment_basepoint, &counterparty_pubkeys.htlc_basepoint, revocation_basepoint, htlc_basepoint)
+		TxCreationKeys::derive_new(&self.secp_ctx, per_commitment_point, &counterparty_pubkeys.delayed_payment_basepoint, &counterparty_pubkeys.htlc_basepoint, revocation_basepoint, htlc_basepoint)
 	}
 
 	/// Gets the redeemscript for the funding transaction output (ie the funding transaction output
@@ -1605,13 +2050,35 @@ impl<Signer: ChannelSigner> ChannelContext<Signer> {
 	pub fn get_available_balances<F: Deref>(&self, fee_estimator: &LowerBoundedFeeEstimator<F>)
 	-> AvailableBalances
 	where F::Target: FeeEstimator
+	{
+		let mut available_balances = self.get_available_balances_for_funding(&self.get_funding_balances(), fee_estimator);
+		// While a splice is pending, HTLCs must fit in the commitment transactions spending both the
+		// current and the new funding outputs.
+		if let Some(splice_balances) = self.get_splice_funding_balances() {
+			let splice_available_balances = self.get_available_balances_for_funding(&splice_balances, fee_estimator);
+			available_balances = AvailableBalances {
+				inbound_capacity_msat: cmp::min(available_balances.inbound_capacity_msat, splice_available_balances.inbound_capacity_msat),
+				outbound_capacity_msat: cmp::min(available_balances.outbound_capacity_msat, splice_available_balances.outbound_capacity_msat),
+				next_outbound_htlc_limit_msat: cmp::min(available_balances.next_outbound_htlc_limit_msat,
+					splice_available_balances.next_outbound_htlc_limit_msat),
+				next_outbound_htlc_minimum_msat: cmp::max(available_balances.next_outbound_htlc_minimum_msat,
+					splice_available_balances.next_outbound_htlc_minimum_msat),
+				balance_msat: cmp::min(available_balances.balance_msat, splice_available_balances.balance_msat),
+			};
+		}
+		available_balances
+	}
+
+	fn get_available_balances_for_funding<F: Deref>(&self, balances: &FundingBalances, fee_estimator: &LowerBoundedFeeEstimator<F>)
+	-> AvailableBalances
+	where F::Target: FeeEstimator
 	{
 		let context = &self;
 		// Note that we have to handle overflow due to the above case.
 		let inbound_stats = context.get_inbound_pending_htlc_stats(None);
 		let outbound_stats = context.get_outbound_pending_htlc_stats(None);
 
-		let mut balance_msat = context.value_to_self_msat;
+		let mut balance_msat = balances.value_to_self_msat;
 		for ref htlc in context.pending_inbound_htlcs.iter() {
 			if let InboundHTLCState::LocalRemoved(InboundHTLCRemovalReason::Fulfill(_)) = htlc.state {
 				balance_msat += htlc.amount_msat;
@@ -1619,10 +2086,10 @@ impl<Signer: ChannelSigner> ChannelContext<Signer> {
 		}
 		balance_msat -= outbound_stats.pending_htlcs_value_msat;
 
-		let outbound_capacity_msat = context.value_to_self_msat
+		let outbound_capacity_msat = balances.value_to_self_msat
 				.saturating_sub(outbound_stats.pending_htlcs_value_msat)
 				.saturating_sub(
-					context.counterparty_selected_channel_reserve_satoshis.unwrap_or(0) * 1000);
+					balances.counterparty_selected_channel_reserve_satoshis.unwrap_or(0) * 1000);
 
 		let mut available_capacity_msat = outbound_capacity_msat;
 
@@ -1668,8 +2135,8 @@ impl<Signer: ChannelSigner> ChannelContext<Signer> {
 			let htlc_above_dust = HTLCCandidate::new(real_dust_limit_success_sat * 1000, HTLCInitiator::LocalOffered);
 			let max_reserved_commit_tx_fee_msat = context.next_remote_commit_tx_fee_msat(htlc_above_dust, None);
 
-			let holder_selected_chan_reserve_msat = context.holder_selected_channel_reserve_satoshis * 1000;
-			let remote_balance_msat = (context.channel_value_satoshis * 1000 - context.value_to_self_msat)
+			let holder_selected_chan_reserve_msat = balances.holder_selected_channel_reserve_satoshis * 1000;
+			let remote_balance_msat = (balances.channel_value_satoshis * 1000 - balances.value_to_self_msat)
 				.saturating_sub(inbound_stats.pending_htlcs_value_msat);
 
 			if remote_balance_msat < max_reserved_commit_tx_fee_msat + holder_selected_chan_reserve_msat {
@@ -1727,10 +2194,10 @@ impl<Signer: ChannelSigner> ChannelContext<Signer> {
 		}
 
 		AvailableBalances {
-			inbound_capacity_msat: cmp::max(context.channel_value_satoshis as i64 * 1000
-					- context.value_to_self_msat as i64
+			inbound_capacity_msat: cmp::max(balances.channel_value_satoshis as i64 * 1000
+					- balances.value_to_self_msat as i64
 					- context.get_inbound_pending_htlc_stats(None).pending_htlcs_value_msat as i64
-					- context.holder_selected_channel_reserve_satoshis as i64 * 1000,
+					- balances.holder_selected_channel_reserve_satoshis as i64 * 1000,
 				0) as u64,
 			outbound_capacity_msat,
 			next_outbound_htlc_limit_msat: available_capacity_msat,
@@ -1940,7 +2407,15 @@ impl<Signer: ChannelSigner> ChannelContext<Signer> {
 
 	/// Returns transaction if there is pending funding transaction that is yet to broadcast
 	pub fn unbroadcasted_funding(&self) -> Option<Transaction> {
-		if self.channel_state & (ChannelState::FundingCreated as u32) != 0 {
+		if let Some(session) = &self.interactive_tx_signing_session {
+			// Until we've provided our signatures for a dual-funded channel's funding transaction,
+			// neither party can broadcast it, so the inputs we contributed may be spent elsewhere.
+			if session.holder_has_inputs() && !session.has_holder_tx_signatures() {
+				return Some(session.unsigned_tx().clone());
+			}
+			return None;
+		}
+		if self.channel_state & (ChannelState::FundingCreated as u32) != 0 || self.is_awaiting_batch_broadcast() {
 			self.funding_transaction.clone()
 		} else {
 			None
@@ -1988,9 +2463,21 @@ impl<Signer: ChannelSigner> ChannelContext<Signer> {
 			} else { None }
 		} else { None };
 
+		// The monitors for the funding outputs of a pending splice must be closed as well, as any
+		// of its candidate funding transactions may still confirm.
+		let splice_monitor_updates = self.get_pending_splice_funding_txos().into_iter().map(|funding_txo| {
+			(funding_txo, ChannelMonitorUpdate {
+				update_id: CLOSED_CHANNEL_UPDATE_ID,
+				updates: vec![ChannelMonitorUpdateStep::ChannelForceClosed { should_broadcast }],
+			})
+		}).collect();
+		self.pending_splice = None;
+
+		let unbroadcasted_batch_funding_txid = self.unbroadcasted_batch_funding_txid();
+
 		self.channel_state = ChannelState::ShutdownComplete as u32;
 		self.update_time_counter += 1;
-		(monitor_update, dropped_outbound_htlcs)
+		(monitor_update, dropped_outbound_htlcs, splice_monitor_updates, unbroadcasted_batch_funding_txid)
 	}
 }
 
@@ -2036,6 +2523,82 @@ pub(crate) fn get_legacy_default_holder_selected_channel_reserve_satoshis(channe
 	cmp::min(channel_value_satoshis, cmp::max(q, 1000))
 }
 
+/// Returns the channel reserve both parties must maintain in a channel established using V2
+/// channel establishment, which is fixed at 1% of the total channel value or the dust limit of the
+/// party the reserve is selected for, whichever is greater.
+///
+/// Guaranteed to return a value no larger than channel_value_satoshis
+pub(crate) fn get_v2_channel_reserve_satoshis(channel_value_satoshis: u64, dust_limit_satoshis: u64) -> u64 {
+	let channel_reserve_proportional_satoshis = channel_value_satoshis / 100;
+	cmp::min(channel_value_satoshis, cmp::max(channel_reserve_proportional_satoshis, dust_limit_satoshis))
+}
+
+/// Returns the minimum feerate a transaction replacing a candidate funding transaction of a
+/// splice paying `prev_feerate_sat_per_1000_weight` must pay, i.e. 25/24 of it, rounded up.
+fn min_splice_rbf_feerate(prev_feerate_sat_per_1000_weight: u32) -> u64 {
+	(prev_feerate_sat_per_1000_weight as u64 * 25 + 23) / 24
+}
+
+fn is_sufficient_splice_rbf_feerate(prev_feerate_sat_per_1000_weight: u32, feerate_sat_per_1000_weight: u32) -> bool {
+	feerate_sat_per_1000_weight as u64 >= min_splice_rbf_feerate(prev_feerate_sat_per_1000_weight)
+}
+
+/// Derives the channel id of a channel established using V2 channel establishment from both
+/// parties' revocation basepoints. The temporary channel id is derived in the same way, with the
+/// acceptor's (as yet unknown) basepoint zeroed out.
+fn get_v2_channel_id(holder_revocation_basepoint: &[u8; 33], counterparty_revocation_basepoint: &[u8; 33]) -> [u8; 32] {
+	let (lesser, greater) = if holder_revocation_basepoint < counterparty_revocation_basepoint {
+		(holder_revocation_basepoint, counterparty_revocation_basepoint)
+	} else {
+		(counterparty_revocation_basepoint, holder_revocation_basepoint)
+	};
+	let mut engine = Sha256::engine();
+	engine.input(&lesser[..]);
+	engine.input(&greater[..]);
+	Sha256::from_engine(engine).into_inner()
+}
+
+/// Computes the change output, if any, for our contribution of `funding_contribution_satoshis`
+/// from the given inputs to an interactively constructed funding transaction, with
+/// `funding_outputs` being the other outputs we will contribute. When splicing funds out of a
+/// channel, the contribution is negative and the removed funds are paid to the change output.
+///
+/// Fails if the inputs can't cover the contribution along with the fees for our inputs and
+/// outputs.
+fn calculate_change_output(
+	is_initiator: bool, spends_shared_input: bool, funding_contribution_satoshis: i64,
+	funding_inputs: &[(TxIn, TransactionU16LenLimited)], funding_outputs: &[TxOut],
+	funding_feerate_sat_per_1000_weight: u32, change_script: Script,
+) -> Result<Option<TxOut>, ()> {
+	let mut total_input_satoshis = 0u64;
+	for (input, prevtx) in funding_inputs.iter() {
+		match prevtx.as_transaction().output.get(input.previous_output.vout as usize) {
+			Some(prev_output) if prevtx.as_transaction().txid() == input.previous_output.txid =>
+				total_input_satoshis = total_input_satoshis.saturating_add(prev_output.value),
+			_ => return Err(()),
+		}
+	}
+	let fee_without_change = fee_for_weight(funding_feerate_sat_per_1000_weight,
+		estimate_contribution_weight(is_initiator, spends_shared_input, funding_inputs, funding_outputs));
+	// A negative contribution (i.e. a splice-out) is paid out to us through the change output.
+	let remaining_satoshis = (total_input_satoshis as i64).checked_sub(funding_contribution_satoshis)
+		.filter(|remaining| *remaining >= 0)
+		.and_then(|remaining| (remaining as u64).checked_sub(fee_without_change))
+		.ok_or(())?;
+
+	let mut outputs_with_change = funding_outputs.to_vec();
+	outputs_with_change.push(TxOut { value: 0, script_pubkey: change_script.clone() });
+	let fee_with_change = fee_for_weight(funding_feerate_sat_per_1000_weight,
+		estimate_contribution_weight(is_initiator, spends_shared_input, funding_inputs, &outputs_with_change));
+	let change_fee = fee_with_change - fee_without_change;
+	if remaining_satoshis >= change_fee + change_script.dust_value().to_sat() {
+		Ok(Some(TxOut { value: remaining_satoshis - change_fee, script_pubkey: change_script }))
+	} else {
+		// The remainder isn't worth a change output, so just leave it to fees.
+		Ok(None)
+	}
+}
+
 // Get the fee cost in SATS of a commitment tx with a given number of HTLC outputs.
 // Note that num_htlcs should not include dust HTLCs.
 #[inline]
@@ -2051,115 +2614,379 @@ fn commit_tx_fee_msat(feerate_per_kw: u32, num_htlcs: usize, channel_type_featur
 	(commitment_tx_base_weight(channel_type_features) + num_htlcs as u64 * COMMITMENT_TX_WEIGHT_PER_HTLC) * feerate_per_kw as u64 / 1000 * 1000
 }
 
-// TODO: We should refactor this to be an Inbound/OutboundChannel until initial setup handshaking
-// has been completed, and then turn into a Channel to get compiler-time enforcement of things like
-// calling channel_id() before we're set up or things like get_funding_signed on an
-// inbound channel.
-//
-// Holder designates channel data owned for the benefit of the user client.
-// Counterparty designates channel data owned by the another channel participant entity.
-pub(super) struct Channel<Signer: ChannelSigner> {
-	pub context: ChannelContext<Signer>,
-}
+impl<Signer: WriteableEcdsaChannelSigner> ChannelContext<Signer> {
+	/// Builds and signs the counterparty commitment transaction at the given commitment number
+	/// spending the funding output of the candidate funding transaction of the pending splice at
+	/// index `idx`, returning the `commitment_signed` for it along with its txid and HTLCs.
+	fn get_splice_counterparty_commitment_signed<L: Deref>(
+		&mut self, idx: usize, commitment_number: u64, per_commitment_point: &PublicKey, generated_by_local: bool, logger: &L
+	) -> Result<(msgs::CommitmentSigned, Txid, Vec<(HTLCOutputInCommitment, Option<Box<HTLCSource>>)>), ChannelError>
+	where L::Target: Logger {
+		self.with_splice_funding(idx, |context| {
+			let counterparty_keys = context.build_remote_transaction_keys_for_point(per_commitment_point);
+			let commitment_stats = context.build_commitment_transaction(commitment_number, &counterparty_keys, false, generated_by_local, logger);
+			let commitment_txid = commitment_stats.tx.trust().txid();
+			let (signature, htlc_signatures) = context.holder_signer.sign_counterparty_commitment(&commitment_stats.tx, commitment_stats.preimages, &context.secp_ctx)
+				.map_err(|_| ChannelError::Close("Failed to get signatures for splice commitment_signed".to_owned()))?;
+			log_trace!(logger, "Signed remote commitment tx {} spending splice funding output {} in channel {}",
+				&commitment_txid, context.get_funding_txo().unwrap().txid, log_bytes!(context.channel_id()));
+			let htlcs = commitment_stats.htlcs_included.into_iter()
+				.map(|(htlc, source)| (htlc, source.map(|source| Box::new(source.clone()))))
+				.collect();
+			Ok((msgs::CommitmentSigned {
+				channel_id: context.channel_id,
+				signature,
+				htlc_signatures,
+				batch: None,
+				#[cfg(taproot)]
+				partial_signature_with_nonce: None,
+			}, commitment_txid, htlcs))
+		})
+	}
 
-#[cfg(any(test, fuzzing))]
-struct CommitmentTxInfoCached {
-	fee: u64,
-	total_pending_htlcs: usize,
-	next_holder_htlc_id: u64,
-	next_counterparty_htlc_id: u64,
-	feerate: u32,
-}
+	/// Checks an accept_channel message against our handshake limits and updates our state with
+	/// the counterparty's channel parameters. Shared by V1 and V2 outbound channel establishment.
+	fn do_accept_channel_checks_and_update(&mut self, msg: &msgs::AcceptChannel, default_limits: &ChannelHandshakeLimits, their_features: &InitFeatures) -> Result<(), ChannelError> {
+		let peer_limits = if let Some(ref limits) = self.inbound_handshake_limits_override { limits } else { default_limits };
 
-impl<Signer: WriteableEcdsaChannelSigner> Channel<Signer> {
-	fn check_remote_fee<F: Deref, L: Deref>(
-		channel_type: &ChannelTypeFeatures, fee_estimator: &LowerBoundedFeeEstimator<F>,
-		feerate_per_kw: u32, cur_feerate_per_kw: Option<u32>, logger: &L
-	) -> Result<(), ChannelError> where F::Target: FeeEstimator, L::Target: Logger,
-	{
-		// We only bound the fee updates on the upper side to prevent completely absurd feerates,
-		// always accepting up to 25 sat/vByte or 10x our fee estimator's "High Priority" fee.
-		// We generally don't care too much if they set the feerate to something very high, but it
-		// could result in the channel being useless due to everything being dust. This doesn't
-		// apply to channels supporting anchor outputs since HTLC transactions are pre-signed with a
-		// zero fee, so their fee is no longer considered to determine dust limits.
-		if !channel_type.supports_anchors_zero_fee_htlc_tx() {
-			let upper_limit = cmp::max(250 * 25,
-				fee_estimator.bounded_sat_per_1000_weight(ConfirmationTarget::HighPriority) as u64 * 10);
-			if feerate_per_kw as u64 > upper_limit {
-				return Err(ChannelError::Close(format!("Peer's feerate much too high. Actual: {}. Our expected upper limit: {}", feerate_per_kw, upper_limit)));
-			}
+		// Check sanity of message fields:
+		if !self.is_outbound() {
+			return Err(ChannelError::Close("Got an accept_channel message from an inbound peer".to_owned()));
 		}
-
-		// We can afford to use a lower bound with anchors than previously since we can now bump
-		// fees when broadcasting our commitment. However, we must still make sure we meet the
-		// minimum mempool feerate, until package relay is deployed, such that we can ensure the
-		// commitment transaction propagates throughout node mempools on its own.
-		let lower_limit_conf_target = if channel_type.supports_anchors_zero_fee_htlc_tx() {
-			ConfirmationTarget::MempoolMinimum
-		} else {
-			ConfirmationTarget::Background
-		};
-		let lower_limit = fee_estimator.bounded_sat_per_1000_weight(lower_limit_conf_target);
-		// Some fee estimators round up to the next full sat/vbyte (ie 250 sats per kw), causing
-		// occasional issues with feerate disagreements between an initiator that wants a feerate
-		// of 1.1 sat/vbyte and a receiver that wants 1.1 rounded up to 2. Thus, we always add 250
-		// sat/kw before the comparison here.
-		if feerate_per_kw + 250 < lower_limit {
-			if let Some(cur_feerate) = cur_feerate_per_kw {
-				if feerate_per_kw > cur_feerate {
-					log_warn!(logger,
-						"Accepting feerate that may prevent us from closing this channel because it's higher than what we have now. Had {} s/kW, now {} s/kW.",
-						cur_feerate, feerate_per_kw);
-					return Ok(());
-				}
-			}
-			return Err(ChannelError::Close(format!("Peer's feerate much too low. Actual: {}. Our expected lower limit: {} (- 250)", feerate_per_kw, lower_limit)));
+		if self.channel_state != ChannelState::OurInitSent as u32 {
+			return Err(ChannelError::Close("Got an accept_channel message at a strange time".to_owned()));
 		}
-		Ok(())
-	}
-
-	#[inline]
-	fn get_closing_scriptpubkey(&self) -> Script {
-		// The shutdown scriptpubkey is set on channel opening when option_upfront_shutdown_script
-		// is signaled. Otherwise, it is set when sending a shutdown message. Calling this method
-		// outside of those situations will fail.
-		self.context.shutdown_scriptpubkey.clone().unwrap().into_inner()
-	}
-
-	#[inline]
-	fn get_closing_transaction_weight(&self, a_scriptpubkey: Option<&Script>, b_scriptpubkey: Option<&Script>) -> u64 {
-		let mut ret =
-		(4 +                                                   // version
-		 1 +                                                   // input count
-		 36 +                                                  // prevout
-		 1 +                                                   // script length (0)
-		 4 +                                                   // sequence
-		 1 +                                                   // output count
-		 4                                                     // lock time
-		 )*4 +                                                 // * 4 for non-witness parts
-		2 +                                                    // witness marker and flag
-		1 +                                                    // witness element count
-		4 +                                                    // 4 element lengths (2 sigs, multisig dummy, and witness script)
-		self.context.get_funding_redeemscript().len() as u64 + // funding witness script
-		2*(1 + 71);                                            // two signatures + sighash type flags
-		if let Some(spk) = a_scriptpubkey {
-			ret += ((8+1) +                                    // output values and script length
-				spk.len() as u64) * 4;                         // scriptpubkey and witness multiplier
+		if msg.dust_limit_satoshis > 21000000 * 100000000 {
+			return Err(ChannelError::Close(format!("Peer never wants payout outputs? dust_limit_satoshis was {}", msg.dust_limit_satoshis)));
 		}
-		if let Some(spk) = b_scriptpubkey {
-			ret += ((8+1) +                                    // output values and script length
-				spk.len() as u64) * 4;                         // scriptpubkey and witness multiplier
+		if msg.channel_reserve_satoshis > self.channel_value_satoshis {
+			return Err(ChannelError::Close(format!("Bogus channel_reserve_satoshis ({}). Must not be greater than ({})", msg.channel_reserve_satoshis, self.channel_value_satoshis)));
 		}
-		ret
-	}
-
-	#[inline]
-	fn build_closing_transaction(&self, proposed_total_fee_satoshis: u64, skip_remote_output: bool) -> (ClosingTransaction, u64) {
-		assert!(self.context.pending_inbound_htlcs.is_empty());
-		assert!(self.context.pending_outbound_htlcs.is_empty());
-		assert!(self.context.pending_update_fee.is_none());
-
+		if msg.dust_limit_satoshis > self.holder_selected_channel_reserve_satoshis {
+			return Err(ChannelError::Close(format!("Dust limit ({}) is bigger than our channel reserve ({})", msg.dust_limit_satoshis, self.holder_selected_channel_reserve_satoshis)));
+		}
+		if msg.channel_reserve_satoshis > self.channel_value_satoshis - self.holder_selected_channel_reserve_satoshis {
+			return Err(ChannelError::Close(format!("Bogus channel_reserve_satoshis ({}). Must not be greater than channel value minus our reserve ({})",
+				msg.channel_reserve_satoshis, self.channel_value_satoshis - self.holder_selected_channel_reserve_satoshis)));
+		}
+		let full_channel_value_msat = (self.channel_value_satoshis - msg.channel_reserve_satoshis) * 1000;
+		if msg.htlc_minimum_msat >= full_channel_value_msat {
+			return Err(ChannelError::Close(format!("Minimum htlc value ({}) is full channel value ({})", msg.htlc_minimum_msat, full_channel_value_msat)));
+		}
+		let max_delay_acceptable = u16::min(peer_limits.their_to_self_delay, MAX_LOCAL_BREAKDOWN_TIMEOUT);
+		if msg.to_self_delay > max_delay_acceptable {
+			return Err(ChannelError::Close(format!("They wanted our payments to be delayed by a needlessly long period. Upper limit: {}. Actual: {}", max_delay_acceptable, msg.to_self_delay)));
+		}
+		if msg.max_accepted_htlcs < 1 {
+			return Err(ChannelError::Close("0 max_accepted_htlcs makes for a useless channel".to_owned()));
+		}
+		if msg.max_accepted_htlcs > MAX_HTLCS {
+			return Err(ChannelError::Close(format!("max_accepted_htlcs was {}. It must not be larger than {}", msg.max_accepted_htlcs, MAX_HTLCS)));
+		}
+
+		// Now check against optional parameters as set by config...
+		if msg.htlc_minimum_msat > peer_limits.max_htlc_minimum_msat {
+			return Err(ChannelError::Close(format!("htlc_minimum_msat ({}) is higher than the user specified limit ({})", msg.htlc_minimum_msat, peer_limits.max_htlc_minimum_msat)));
+		}
+		if msg.max_htlc_value_in_flight_msat < peer_limits.min_max_htlc_value_in_flight_msat {
+			return Err(ChannelError::Close(format!("max_htlc_value_in_flight_msat ({}) is less than the user specified limit ({})", msg.max_htlc_value_in_flight_msat, peer_limits.min_max_htlc_value_in_flight_msat)));
+		}
+		if msg.channel_reserve_satoshis > peer_limits.max_channel_reserve_satoshis {
+			return Err(ChannelError::Close(format!("channel_reserve_satoshis ({}) is higher than the user specified limit ({})", msg.channel_reserve_satoshis, peer_limits.max_channel_reserve_satoshis)));
+		}
+		if msg.max_accepted_htlcs < peer_limits.min_max_accepted_htlcs {
+			return Err(ChannelError::Close(format!("max_accepted_htlcs ({}) is less than the user specified limit ({})", msg.max_accepted_htlcs, peer_limits.min_max_accepted_htlcs)));
+		}
+		if msg.dust_limit_satoshis < MIN_CHAN_DUST_LIMIT_SATOSHIS {
+			return Err(ChannelError::Close(format!("dust_limit_satoshis ({}) is less than the implementation limit ({})", msg.dust_limit_satoshis, MIN_CHAN_DUST_LIMIT_SATOSHIS)));
+		}
+		if msg.dust_limit_satoshis > MAX_CHAN_DUST_LIMIT_SATOSHIS {
+			return Err(ChannelError::Close(format!("dust_limit_satoshis ({}) is greater than the implementation limit ({})", msg.dust_limit_satoshis, MAX_CHAN_DUST_LIMIT_SATOSHIS)));
+		}
+		if msg.minimum_depth > peer_limits.max_minimum_depth {
+			return Err(ChannelError::Close(format!("We consider the minimum depth to be unreasonably large. Expected minimum: ({}). Actual: ({})", peer_limits.max_minimum_depth, msg.minimum_depth)));
+		}
+
+		if let Some(ty) = &msg.channel_type {
+			if *ty != self.channel_type {
+				return Err(ChannelError::Close("Channel Type in accept_channel didn't match the one sent in open_channel.".to_owned()));
+			}
+		} else if their_features.supports_channel_type() {
+			// Assume they've accepted the channel type as they said they understand it.
+		} else {
+			let channel_type = ChannelTypeFeatures::from_init(&their_features);
+			if channel_type != ChannelTypeFeatures::only_static_remote_key() {
+				return Err(ChannelError::Close("Only static_remote_key is supported for non-negotiated channel types".to_owned()));
+			}
+			self.channel_type = channel_type.clone();
+			self.channel_transaction_parameters.channel_type_features = channel_type;
+		}
+
+		let counterparty_shutdown_scriptpubkey = if their_features.supports_upfront_shutdown_script() {
+			match &msg.shutdown_scriptpubkey {
+				&Some(ref script) => {
+					// Peer is signaling upfront_shutdown and has opt-out with a 0-length script. We don't enforce anything
+					if script.len() == 0 {
+						None
+					} else {
+						if !script::is_bolt2_compliant(&script, their_features) {
+							return Err(ChannelError::Close(format!("Peer is signaling upfront_shutdown but has provided an unacceptable scriptpubkey format: {}", script)));
+						}
+						Some(script.clone())
+					}
+				},
+				// Peer is signaling upfront shutdown but don't opt-out with correct mechanism (a.k.a 0-length script). Peer looks buggy, we fail the channel
+				&None => {
+					return Err(ChannelError::Close("Peer is signaling upfront_shutdown but we don't get any script. Use 0-length script to opt-out".to_owned()));
+				}
+			}
+		} else { None };
+
+		self.counterparty_dust_limit_satoshis = msg.dust_limit_satoshis;
+		self.counterparty_max_htlc_value_in_flight_msat = cmp::min(msg.max_htlc_value_in_flight_msat, self.channel_value_satoshis * 1000);
+		self.counterparty_selected_channel_reserve_satoshis = Some(msg.channel_reserve_satoshis);
+		self.counterparty_htlc_minimum_msat = msg.htlc_minimum_msat;
+		self.counterparty_max_accepted_htlcs = msg.max_accepted_htlcs;
+
+		if peer_limits.trust_own_funding_0conf {
+			self.minimum_depth = Some(msg.minimum_depth);
+		} else {
+			self.minimum_depth = Some(cmp::max(1, msg.minimum_depth));
+		}
+
+		let counterparty_pubkeys = ChannelPublicKeys {
+			funding_pubkey: msg.funding_pubkey,
+			revocation_basepoint: msg.revocation_basepoint,
+			payment_point: msg.payment_point,
+			delayed_payment_basepoint: msg.delayed_payment_basepoint,
+			htlc_basepoint: msg.htlc_basepoint
+		};
+
+		self.channel_transaction_parameters.counterparty_parameters = Some(CounterpartyChannelTransactionParameters {
+			selected_contest_delay: msg.to_self_delay,
+			pubkeys: counterparty_pubkeys,
+		});
+
+		self.counterparty_cur_commitment_point = Some(msg.first_per_commitment_point);
+		self.counterparty_shutdown_scriptpubkey = counterparty_shutdown_scriptpubkey;
+
+		self.channel_state = ChannelState::OurInitSent as u32 | ChannelState::TheirInitSent as u32;
+		self.inbound_handshake_limits_override = None; // We're done enforcing limits on our peer's handshake now.
+
+		Ok(())
+	}
+
+	/// Gets our signature for the counterparty's commitment transaction with the given number,
+	/// which may not have any HTLCs, as sent in `funding_created` or an initial
+	/// `commitment_signed`.
+	fn get_initial_counterparty_commitment_signature<L: Deref>(&self, commitment_number: u64, logger: &L) -> Result<Signature, ChannelError> where L::Target: Logger {
+		let counterparty_keys = self.build_remote_transaction_keys();
+		let counterparty_initial_commitment_tx = self.build_commitment_transaction(commitment_number, &counterparty_keys, false, false, logger).tx;
+		Ok(self.holder_signer.sign_counterparty_commitment(&counterparty_initial_commitment_tx, Vec::new(), &self.secp_ctx)
+				.map_err(|_| ChannelError::Close("Failed to get signatures for new commitment_signed".to_owned()))?.0)
+	}
+
+	/// Updates our state once the funding transaction of a channel using V2 channel establishment
+	/// has been negotiated, returning the initial `commitment_signed` to send to our counterparty.
+	///
+	/// If an Err is returned, it is a ChannelError::Close.
+	fn funding_tx_constructed<L: Deref>(
+		&mut self, constructed_tx: ConstructedTransaction, holder_node_id: &PublicKey, logger: &L
+	) -> Result<msgs::CommitmentSigned, ChannelError> where L::Target: Logger {
+		if self.channel_state != (ChannelState::OurInitSent as u32 | ChannelState::TheirInitSent as u32) {
+			return Err(ChannelError::Close("Completed funding transaction negotiation at a strange time".to_owned()));
+		}
+		if self.commitment_secrets.get_min_seen_secret() != (1 << 48) ||
+				self.cur_counterparty_commitment_transaction_number != INITIAL_COMMITMENT_NUMBER ||
+				self.cur_holder_commitment_transaction_number != INITIAL_COMMITMENT_NUMBER {
+			panic!("Should not have advanced channel commitment tx numbers prior to funding transaction negotiation");
+		}
+
+		let funding_script = self.get_funding_redeemscript().to_v0_p2wsh();
+		let funding_output_index = match constructed_tx.tx.output.iter().position(|output|
+			output.script_pubkey == funding_script && output.value == self.channel_value_satoshis
+		) {
+			Some(idx) => idx,
+			None => return Err(ChannelError::Close("Negotiated funding transaction is missing the funding output".to_owned())),
+		};
+		let funding_txo = OutPoint { txid: constructed_tx.tx.txid(), index: funding_output_index as u16 };
+		self.channel_transaction_parameters.funding_outpoint = Some(funding_txo);
+		self.holder_signer.provide_channel_parameters(&self.channel_transaction_parameters);
+
+		let signature = match self.get_initial_counterparty_commitment_signature(self.cur_counterparty_commitment_transaction_number, logger) {
+			Ok(res) => res,
+			Err(e) => {
+				log_error!(logger, "Got bad signatures: {:?}!", e);
+				self.channel_transaction_parameters.funding_outpoint = None;
+				return Err(e);
+			}
+		};
+
+		// The party which contributed less to the inputs of the funding transaction sends their
+		// `tx_signatures` first, with ties broken by the lexicographically lesser node id.
+		let holder_sends_tx_signatures_first = if constructed_tx.holder_inputs_value == constructed_tx.counterparty_inputs_value {
+			holder_node_id.serialize()[..] < self.counterparty_node_id.serialize()[..]
+		} else {
+			constructed_tx.holder_inputs_value < constructed_tx.counterparty_inputs_value
+		};
+
+		log_info!(logger, "Negotiated funding transaction {} for channel {}", funding_txo.txid, log_bytes!(self.channel_id));
+
+		self.channel_state = ChannelState::FundingCreated as u32;
+		self.interactive_tx_signing_session = Some(InteractiveTxSigningSession::new(
+			self.channel_id, constructed_tx, holder_sends_tx_signatures_first, None));
+
+		Ok(msgs::CommitmentSigned {
+			channel_id: self.channel_id,
+			signature,
+			htlc_signatures: Vec::new(),
+			batch: None,
+			#[cfg(taproot)]
+			partial_signature_with_nonce: None,
+		})
+	}
+
+	/// Updates the channel value, balances and reserves once both parties' contributions to a
+	/// channel using V2 channel establishment are known.
+	fn set_dual_funded_channel_value<SP: Deref>(
+		&mut self, holder_funding_satoshis: u64, counterparty_funding_satoshis: u64, signer_provider: &SP
+	) where SP::Target: SignerProvider<Signer = Signer> {
+		let prev_channel_value_satoshis = self.channel_value_satoshis;
+		let channel_value_satoshis = holder_funding_satoshis + counterparty_funding_satoshis;
+		self.channel_value_satoshis = channel_value_satoshis;
+		// Our signer commits to the channel value it was derived with, so derive it again now that
+		// the final value is known.
+		self.holder_signer = signer_provider.derive_channel_signer(channel_value_satoshis, self.channel_keys_id);
+		self.value_to_self_msat = holder_funding_satoshis * 1000;
+		self.holder_selected_channel_reserve_satoshis =
+			get_v2_channel_reserve_satoshis(channel_value_satoshis, self.counterparty_dust_limit_satoshis);
+		self.counterparty_selected_channel_reserve_satoshis =
+			Some(get_v2_channel_reserve_satoshis(channel_value_satoshis, self.holder_dust_limit_satoshis));
+		// Our in-flight limit was picked as a proportion of the initial channel value, so scale it
+		// along with the channel value.
+		if prev_channel_value_satoshis != 0 {
+			self.holder_max_htlc_value_in_flight_msat = (self.holder_max_htlc_value_in_flight_msat as u128
+				* channel_value_satoshis as u128 / prev_channel_value_satoshis as u128) as u64;
+		}
+		#[cfg(debug_assertions)] {
+			let counterparty_value_msat = counterparty_funding_satoshis * 1000;
+			*self.holder_max_commitment_tx_output.lock().unwrap() = (self.value_to_self_msat, counterparty_value_msat);
+			*self.counterparty_max_commitment_tx_output.lock().unwrap() = (self.value_to_self_msat, counterparty_value_msat);
+		}
+	}
+}
+
+// TODO: We should refactor this to be an Inbound/OutboundChannel until initial setup handshaking
+// has been completed, and then turn into a Channel to get compiler-time enforcement of things like
+// calling channel_id() before we're set up or things like get_funding_signed on an
+// inbound channel.
+//
+// Holder designates channel data owned for the benefit of the user client.
+// Counterparty designates channel data owned by the another channel participant entity.
+pub(super) struct Channel<Signer: ChannelSigner> {
+	pub context: ChannelContext<Signer>,
+	/// Drives the negotiation of a new funding transaction while splicing.
+	interactive_tx_constructor: Option<InteractiveTxConstructor>,
+}
+
+#[cfg(any(test, fuzzing))]
+struct CommitmentTxInfoCached {
+	fee: u64,
+	total_pending_htlcs: usize,
+	next_holder_htlc_id: u64,
+	next_counterparty_htlc_id: u64,
+	feerate: u32,
+}
+
+impl<Signer: WriteableEcdsaChannelSigner> Channel<Signer> {
+	fn check_remote_fee<F: Deref, L: Deref>(
+		channel_type: &ChannelTypeFeatures, fee_estimator: &LowerBoundedFeeEstimator<F>,
+		feerate_per_kw: u32, cur_feerate_per_kw: Option<u32>, logger: &L
+	) -> Result<(), ChannelError> where F::Target: FeeEstimator, L::Target: Logger,
+	{
+		// We only bound the fee updates on the upper side to prevent completely absurd feerates,
+		// always accepting up to 25 sat/vByte or 10x our fee estimator's "High Priority" fee.
+		// We generally don't care too much if they set the feerate to something very high, but it
+		// could result in the channel being useless due to everything being dust. This doesn't
+		// apply to channels supporting anchor outputs since HTLC transactions are pre-signed with a
+		// zero fee, so their fee is no longer considered to determine dust limits.
+		if !channel_type.supports_anchors_zero_fee_htlc_tx() {
+			let upper_limit = cmp::max(250 * 25,
+				fee_estimator.bounded_sat_per_1000_weight(ConfirmationTarget::HighPriority) as u64 * 10);
+			if feerate_per_kw as u64 > upper_limit {
+				return Err(ChannelError::Close(format!("Peer's feerate much too high. Actual: {}. Our expected upper limit: {}", feerate_per_kw, upper_limit)));
+			}
+		}
+
+		// We can afford to use a lower bound with anchors than previously since we can now bump
+		// fees when broadcasting our commitment. However, we must still make sure we meet the
+		// minimum mempool feerate, until package relay is deployed, such that we can ensure the
+		// commitment transaction propagates throughout node mempools on its own.
+		let lower_limit_conf_target = if channel_type.supports_anchors_zero_fee_htlc_tx() {
+			ConfirmationTarget::MempoolMinimum
+		} else {
+			ConfirmationTarget::Background
+		};
+		let lower_limit = fee_estimator.bounded_sat_per_1000_weight(lower_limit_conf_target);
+		// Some fee estimators round up to the next full sat/vbyte (ie 250 sats per kw), causing
+		// occasional issues with feerate disagreements between an initiator that wants a feerate
+		// of 1.1 sat/vbyte and a receiver that wants 1.1 rounded up to 2. Thus, we always add 250
+		// sat/kw before the comparison here.
+		if feerate_per_kw + 250 < lower_limit {
+			if let Some(cur_feerate) = cur_feerate_per_kw {
+				if feerate_per_kw > cur_feerate {
+					log_warn!(logger,
+						"Accepting feerate that may prevent us from closing this channel because it's higher than what we have now. Had {} s/kW, now {} s/kW.",
+						cur_feerate, feerate_per_kw);
+					return Ok(());
+				}
+			}
+			return Err(ChannelError::Close(format!("Peer's feerate much too low. Actual: {}. Our expected lower limit: {} (- 250)", feerate_per_kw, lower_limit)));
+		}
+		Ok(())
+	}
+
+	#[inline]
+	fn get_closing_scriptpubkey(&self) -> Script {
+		// The shutdown scriptpubkey is set on channel opening when option_upfront_shutdown_script
+		// is signaled. Otherwise, it is set when sending a shutdown message. Calling this method
+		// outside of those situations will fail.
+		self.context.shutdown_scriptpubkey.clone().unwrap().into_inner()
+	}
+
+	#[inline]
+	fn get_closing_transaction_weight(&self, a_scriptpubkey: Option<&Script>, b_scriptpubkey: Option<&Script>) -> u64 {
+		let mut ret =
+		(4 +                                                   // version
+		 1 +                                                   // input count
+		 36 +                                                  // prevout
+		 1 +                                                   // script length (0)
+		 4 +                                                   // sequence
+		 1 +                                                   // output count
+		 4                                                     // lock time
+		 )*4 +                                                 // * 4 for non-witness parts
+		2 +                                                    // witness marker and flag
+		1 +                                                    // witness element count
+		4 +                                                    // 4 element lengths (2 sigs, multisig dummy, and witness script)
+		self.context.get_funding_redeemscript().len() as u64 + // funding witness script
+		2*(1 + 71);                                            // two signatures + sighash type flags
+		if let Some(spk) = a_scriptpubkey {
+			ret += ((8+1) +                                    // output values and script length
+				spk.len() as u64) * 4;                         // scriptpubkey and witness multiplier
+		}
+		if let Some(spk) = b_scriptpubkey {
+			ret += ((8+1) +                                    // output values and script length
+				spk.len() as u64) * 4;                         // scriptpubkey and witness multiplier
+		}
+		ret
+	}
+
+	#[inline]
+	fn build_closing_transaction(&self, proposed_total_fee_satoshis: u64, skip_remote_output: bool) -> (ClosingTransaction, u64) {
+		assert!(self.context.pending_inbound_htlcs.is_empty());
+		assert!(self.context.pending_outbound_htlcs.is_empty());
+		assert!(self.context.pending_update_fee.is_none());
+
 		let mut total_fee_satoshis = proposed_total_fee_satoshis;
 		let mut value_to_holder: i64 = (self.context.value_to_self_msat as i64) / 1000 - if self.context.is_outbound() { total_fee_satoshis as i64 } else { 0 };
 		let mut value_to_counterparty: i64 = ((self.context.channel_value_satoshis * 1000 - self.context.value_to_self_msat) as i64 / 1000) - if self.context.is_outbound() { 0 } else { total_fee_satoshis as i64 };
@@ -2206,7 +3033,7 @@ impl<Signer: WriteableEcdsaChannelSigner> Channel<Signer> {
 	where L::Target: Logger {
 		// Assert that we'll add the HTLC claim to the holding cell in `get_update_fulfill_htlc`
 		// (see equivalent if condition there).
-		assert!(self.context.channel_state & (ChannelState::AwaitingRemoteRevoke as u32 | ChannelState::PeerDisconnected as u32 | ChannelState::MonitorUpdateInProgress as u32) != 0);
+		assert!(self.context.channel_state & (ChannelState::AwaitingRemoteRevoke as u32 | ChannelState::PeerDisconnected as u32 | ChannelState::MonitorUpdateInProgress as u32 | QUIESCENCE_STATE_FLAGS) != 0);
 		let mon_update_id = self.context.latest_monitor_update_id; // Forget the ChannelMonitor update
 		let fulfill_resp = self.get_update_fulfill_htlc(htlc_id_arg, payment_preimage_arg, logger);
 		self.context.latest_monitor_update_id = mon_update_id;
@@ -2276,7 +3103,7 @@ impl<Signer: WriteableEcdsaChannelSigner> Channel<Signer> {
 			}],
 		};
 
-		if (self.context.channel_state & (ChannelState::AwaitingRemoteRevoke as u32 | ChannelState::PeerDisconnected as u32 | ChannelState::MonitorUpdateInProgress as u32)) != 0 {
+		if (self.context.channel_state & (ChannelState::AwaitingRemoteRevoke as u32 | ChannelState::PeerDisconnected as u32 | ChannelState::MonitorUpdateInProgress as u32 | QUIESCENCE_STATE_FLAGS)) != 0 {
 			// Note that this condition is the same as the assertion in
 			// `claim_htlc_while_disconnected_dropping_mon_update` and must match exactly -
 			// `claim_htlc_while_disconnected_dropping_mon_update` would not work correctly if we
@@ -2292,7 +3119,9 @@ impl<Signer: WriteableEcdsaChannelSigner> Channel<Signer> {
 							return UpdateFulfillFetch::DuplicateClaim {};
 						}
 					},
-					&HTLCUpdateAwaitingACK::FailHTLC { htlc_id, .. } => {
+					&HTLCUpdateAwaitingACK::FailHTLC { htlc_id, .. } |
+						&HTLCUpdateAwaitingACK::FailMalformedHTLC { htlc_id, .. } =>
+					{
 						if htlc_id_arg == htlc_id {
 							log_warn!(logger, "Have preimage and want to fulfill HTLC with pending failure against channel {}", log_bytes!(self.context.channel_id()));
 							// TODO: We may actually be able to switch to a fulfill here, though its
@@ -2389,6 +3218,17 @@ impl<Signer: WriteableEcdsaChannelSigner> Channel<Signer> {
 			.map(|msg_opt| assert!(msg_opt.is_none(), "We forced holding cell?"))
 	}
 
+	/// Used for failing back with [`msgs::UpdateFailMalformedHTLC`]. For now, this is used when we
+	/// want to fail blinded HTLCs where we are not the intro node.
+	///
+	/// See [`Self::queue_fail_htlc`] for more info.
+	pub fn queue_fail_malformed_htlc<L: Deref>(
+		&mut self, htlc_id_arg: u64, failure_code: u16, sha256_of_onion: [u8; 32], logger: &L
+	) -> Result<(), ChannelError> where L::Target: Logger {
+		self.fail_htlc(htlc_id_arg, (sha256_of_onion, failure_code), true, logger)
+			.map(|msg_opt| assert!(msg_opt.is_none(), "We forced holding cell?"))
+	}
+
 	/// We can only have one resolution per HTLC. In some cases around reconnect, we may fulfill
 	/// an HTLC more than once or fulfill once and then attempt to fail after reconnect. We cannot,
 	/// however, fail more than once as we wait for an upstream failure to be irrevocably committed
@@ -2397,8 +3237,9 @@ impl<Signer: WriteableEcdsaChannelSigner> Channel<Signer> {
 	/// If we do fail twice, we `debug_assert!(false)` and return `Ok(None)`. Thus, this will always
 	/// return `Ok(_)` if preconditions are met. In any case, `Err`s will only be
 	/// [`ChannelError::Ignore`].
-	fn fail_htlc<L: Deref>(&mut self, htlc_id_arg: u64, err_packet: msgs::OnionErrorPacket, mut force_holding_cell: bool, logger: &L)
-	-> Result<Option<msgs::UpdateFailHTLC>, ChannelError> where L::Target: Logger {
+	fn fail_htlc<L: Deref, E: FailHTLCContents + Clone>(
+		&mut self, htlc_id_arg: u64, err_packet: E, mut force_holding_cell: bool, logger: &L
+	) -> Result<Option<E::Message>, ChannelError> where L::Target: Logger {
 		if (self.context.channel_state & (ChannelState::ChannelReady as u32)) != (ChannelState::ChannelReady as u32) {
 			panic!("Was asked to fail an HTLC when channel was not in an operational state");
 		}
@@ -2436,7 +3277,7 @@ impl<Signer: WriteableEcdsaChannelSigner> Channel<Signer> {
 			return Ok(None);
 		}
 
-		if (self.context.channel_state & (ChannelState::AwaitingRemoteRevoke as u32 | ChannelState::PeerDisconnected as u32 | ChannelState::MonitorUpdateInProgress as u32)) != 0 {
+		if (self.context.channel_state & (ChannelState::AwaitingRemoteRevoke as u32 | ChannelState::PeerDisconnected as u32 | ChannelState::MonitorUpdateInProgress as u32 | QUIESCENCE_STATE_FLAGS)) != 0 {
 			debug_assert!(force_holding_cell, "!force_holding_cell is only called when emptying the holding cell, so we shouldn't end up back in it!");
 			force_holding_cell = true;
 		}
@@ -2452,7 +3293,9 @@ impl<Signer: WriteableEcdsaChannelSigner> Channel<Signer> {
 							return Ok(None);
 						}
 					},
-					&HTLCUpdateAwaitingACK::FailHTLC { htlc_id, .. } => {
+					&HTLCUpdateAwaitingACK::FailHTLC { htlc_id, .. } |
+						&HTLCUpdateAwaitingACK::FailMalformedHTLC { htlc_id, .. } =>
+					{
 						if htlc_id_arg == htlc_id {
 							debug_assert!(false, "Tried to fail an HTLC that was already failed");
 							return Err(ChannelError::Ignore("Unable to find a pending HTLC which matched the given HTLC ID".to_owned()));
@@ -2462,24 +3305,18 @@ impl<Signer: WriteableEcdsaChannelSigner> Channel<Signer> {
 				}
 			}
 			log_trace!(logger, "Placing failure for HTLC ID {} in holding cell in channel {}.", htlc_id_arg, log_bytes!(self.context.channel_id()));
-			self.context.holding_cell_htlc_updates.push(HTLCUpdateAwaitingACK::FailHTLC {
-				htlc_id: htlc_id_arg,
-				err_packet,
-			});
+			self.context.holding_cell_htlc_updates.push(err_packet.to_htlc_update_awaiting_ack(htlc_id_arg));
 			return Ok(None);
 		}
 
-		log_trace!(logger, "Failing HTLC ID {} back with a update_fail_htlc message in channel {}.", htlc_id_arg, log_bytes!(self.context.channel_id()));
+		log_trace!(logger, "Failing HTLC ID {} back with {} message in channel {}.", htlc_id_arg,
+			E::Message::name(), log_bytes!(self.context.channel_id()));
 		{
 			let htlc = &mut self.context.pending_inbound_htlcs[pending_idx];
-			htlc.state = InboundHTLCState::LocalRemoved(InboundHTLCRemovalReason::FailRelay(err_packet.clone()));
+			htlc.state = err_packet.clone().to_inbound_htlc_state();
 		}
 
-		Ok(Some(msgs::UpdateFailHTLC {
-			channel_id: self.context.channel_id(),
-			htlc_id: htlc_id_arg,
-			reason: err_packet
-		}))
+		Ok(Some(err_packet.to_message(htlc_id_arg, self.context.channel_id())))
 	}
 
 	// Message handlers:
@@ -2568,3048 +3405,5516 @@ impl<Signer: WriteableEcdsaChannelSigner> Channel<Signer> {
 		Ok(channel_monitor)
 	}
 
-	/// Handles a channel_ready message from our peer. If we've already sent our channel_ready
-	/// and the channel is now usable (and public), this may generate an announcement_signatures to
-	/// reply with.
-	pub fn channel_ready<NS: Deref, L: Deref>(
-		&mut self, msg: &msgs::ChannelReady, node_signer: &NS, genesis_block_hash: BlockHash,
-		user_config: &UserConfig, best_block: &BestBlock, logger: &L
-	) -> Result<Option<msgs::AnnouncementSignatures>, ChannelError>
-	where
-		NS::Target: NodeSigner,
-		L::Target: Logger
+	/// Indicates that all channels in this channel's funding batch have received their
+	/// counterparty's `funding_signed` and had their initial monitor persisted. Returns the
+	/// funding transaction, which may now be broadcast, and a `channel_ready` to send if the
+	/// channel is 0-conf.
+	pub fn set_batch_ready<L: Deref>(&mut self, logger: &L) -> (Option<Transaction>, Option<msgs::ChannelReady>)
+	where L::Target: Logger
 	{
-		if self.context.channel_state & (ChannelState::PeerDisconnected as u32) == ChannelState::PeerDisconnected as u32 {
-			self.context.workaround_lnd_bug_4006 = Some(msg.clone());
-			return Err(ChannelError::Ignore("Peer sent channel_ready when we needed a channel_reestablish. The peer is likely lnd, see https://github.com/lightningnetwork/lnd/issues/4006".to_owned()));
+		if !self.context.is_awaiting_batch_broadcast() {
+			return (None, None);
 		}
+		log_info!(logger, "Funding batch for channel {} is ready", log_bytes!(self.context.channel_id()));
+		let funding_tx = self.context.funding_transaction.take();
+		(funding_tx, self.check_get_channel_ready(0))
+	}
 
-		if let Some(scid_alias) = msg.short_channel_id_alias {
-			if Some(scid_alias) != self.context.short_channel_id {
-				// The scid alias provided can be used to route payments *from* our counterparty,
-				// i.e. can be used for inbound payments and provided in invoices, but is not used
-				// when routing outbound payments.
-				self.context.latest_inbound_scid_alias = Some(scid_alias);
-			}
+	/// Returns true if this channel's funding transaction was negotiated interactively and we're
+	/// waiting on our counterparty's initial `commitment_signed`.
+	pub fn is_awaiting_initial_commitment_signed(&self) -> bool {
+		self.context.interactive_tx_signing_session.is_some() &&
+			self.context.channel_state & !(ChannelState::MonitorUpdateInProgress as u32) == ChannelState::FundingCreated as u32
+	}
+
+	/// Returns the interactively negotiated funding transaction if we contributed inputs to it but
+	/// have yet to provide signatures for them.
+	pub fn unsigned_funding_transaction_to_sign(&self) -> Option<&Transaction> {
+		self.context.pending_splice_signing_session()
+			.or(self.context.interactive_tx_signing_session.as_ref())
+			.filter(|session| session.holder_has_inputs() && !session.has_holder_tx_signatures())
+			.map(|session| session.unsigned_tx())
+	}
+
+	/// Handles the initial commitment_signed message from the remote end for a channel whose
+	/// funding transaction was negotiated interactively. Plays the same role as `funding_signed`
+	/// (or `funding_created`) for channels funded using V1 channel establishment.
+	pub fn initial_commitment_signed<SP: Deref, L: Deref>(
+		&mut self, msg: &msgs::CommitmentSigned, best_block: BestBlock, signer_provider: &SP, logger: &L
+	) -> Result<ChannelMonitor<Signer>, ChannelError>
+	where
+		SP::Target: SignerProvider<Signer = Signer>,
+		L::Target: Logger
+	{
+		if !self.is_awaiting_initial_commitment_signed() {
+			return Err(ChannelError::Close("Received initial commitment_signed in strange state!".to_owned()));
+		}
+		if self.context.commitment_secrets.get_min_seen_secret() != (1 << 48) ||
+				self.context.cur_counterparty_commitment_transaction_number != INITIAL_COMMITMENT_NUMBER ||
+				self.context.cur_holder_commitment_transaction_number != INITIAL_COMMITMENT_NUMBER {
+			panic!("Should not have advanced channel commitment tx numbers prior to initial commitment_signed");
+		}
+		if !msg.htlc_signatures.is_empty() {
+			return Err(ChannelError::Close("Got HTLC signatures in an initial commitment_signed".to_owned()));
 		}
 
-		let non_shutdown_state = self.context.channel_state & (!MULTI_STATE_FLAGS);
+		let funding_script = self.context.get_funding_redeemscript();
 
-		if non_shutdown_state == ChannelState::FundingSent as u32 {
-			self.context.channel_state |= ChannelState::TheirChannelReady as u32;
-		} else if non_shutdown_state == (ChannelState::FundingSent as u32 | ChannelState::OurChannelReady as u32) {
-			self.context.channel_state = ChannelState::ChannelReady as u32 | (self.context.channel_state & MULTI_STATE_FLAGS);
-			self.context.update_time_counter += 1;
-		} else if self.context.channel_state & (ChannelState::ChannelReady as u32) != 0 ||
-			// If we reconnected before sending our `channel_ready` they may still resend theirs:
-			(self.context.channel_state & (ChannelState::FundingSent as u32 | ChannelState::TheirChannelReady as u32) ==
-			                      (ChannelState::FundingSent as u32 | ChannelState::TheirChannelReady as u32))
+		let counterparty_keys = self.context.build_remote_transaction_keys();
+		let counterparty_initial_commitment_tx = self.context.build_commitment_transaction(self.context.cur_counterparty_commitment_transaction_number, &counterparty_keys, false, false, logger).tx;
+		let counterparty_trusted_tx = counterparty_initial_commitment_tx.trust();
+		let counterparty_initial_bitcoin_tx = counterparty_trusted_tx.built_transaction();
+
+		let holder_keys = self.context.build_holder_transaction_keys(self.context.cur_holder_commitment_transaction_number);
+		let initial_commitment_tx = self.context.build_commitment_transaction(self.context.cur_holder_commitment_transaction_number, &holder_keys, true, false, logger).tx;
 		{
-			// They probably disconnected/reconnected and re-sent the channel_ready, which is
-			// required, or they're sending a fresh SCID alias.
-			let expected_point =
-				if self.context.cur_counterparty_commitment_transaction_number == INITIAL_COMMITMENT_NUMBER - 1 {
-					// If they haven't ever sent an updated point, the point they send should match
-					// the current one.
-					self.context.counterparty_cur_commitment_point
-				} else if self.context.cur_counterparty_commitment_transaction_number == INITIAL_COMMITMENT_NUMBER - 2 {
-					// If we've advanced the commitment number once, the second commitment point is
-					// at `counterparty_prev_commitment_point`, which is not yet revoked.
-					debug_assert!(self.context.counterparty_prev_commitment_point.is_some());
-					self.context.counterparty_prev_commitment_point
-				} else {
-					// If they have sent updated points, channel_ready is always supposed to match
-					// their "first" point, which we re-derive here.
-					Some(PublicKey::from_secret_key(&self.context.secp_ctx, &SecretKey::from_slice(
-							&self.context.commitment_secrets.get_secret(INITIAL_COMMITMENT_NUMBER - 1).expect("We should have all prev secrets available")
-						).expect("We already advanced, so previous secret keys should have been validated already")))
-				};
-			if expected_point != Some(msg.next_per_commitment_point) {
-				return Err(ChannelError::Close("Peer sent a reconnect channel_ready with a different point".to_owned()));
+			let trusted_tx = initial_commitment_tx.trust();
+			let initial_commitment_bitcoin_tx = trusted_tx.built_transaction();
+			let sighash = initial_commitment_bitcoin_tx.get_sighash_all(&funding_script, self.context.channel_value_satoshis);
+			// They sign our commitment transaction, allowing us to broadcast the tx if we wish.
+			if let Err(_) = self.context.secp_ctx.verify_ecdsa(&sighash, &msg.signature, &self.context.get_counterparty_pubkeys().funding_pubkey) {
+				return Err(ChannelError::Close("Invalid initial commitment_signed signature from peer".to_owned()));
 			}
-			return Ok(None);
-		} else {
-			return Err(ChannelError::Close("Peer sent a channel_ready at a strange time".to_owned()));
 		}
 
-		self.context.counterparty_prev_commitment_point = self.context.counterparty_cur_commitment_point;
-		self.context.counterparty_cur_commitment_point = Some(msg.next_per_commitment_point);
+		let holder_commitment_tx = HolderCommitmentTransaction::new(
+			initial_commitment_tx,
+			msg.signature,
+			Vec::new(),
+			&self.context.get_holder_pubkeys().funding_pubkey,
+			self.context.counterparty_funding_pubkey()
+		);
 
-		log_info!(logger, "Received channel_ready from peer for channel {}", log_bytes!(self.context.channel_id()));
+		self.context.holder_signer.validate_holder_commitment(&holder_commitment_tx, Vec::new())
+			.map_err(|_| ChannelError::Close("Failed to validate our commitment".to_owned()))?;
 
-		Ok(self.get_announcement_sigs(node_signer, genesis_block_hash, user_config, best_block.height(), logger))
+		let funding_redeemscript = self.context.get_funding_redeemscript();
+		let funding_txo = self.context.get_funding_txo().unwrap();
+		let funding_txo_script = funding_redeemscript.to_v0_p2wsh();
+		let obscure_factor = get_commitment_transaction_number_obscure_factor(&self.context.get_holder_pubkeys().payment_point, &self.context.get_counterparty_pubkeys().payment_point, self.context.is_outbound());
+		let shutdown_script = self.context.shutdown_scriptpubkey.clone().map(|script| script.into_inner());
+		let mut monitor_signer = signer_provider.derive_channel_signer(self.context.channel_value_satoshis, self.context.channel_keys_id);
+		monitor_signer.provide_channel_parameters(&self.context.channel_transaction_parameters);
+		let channel_monitor = ChannelMonitor::new(self.context.secp_ctx.clone(), monitor_signer,
+		                                          shutdown_script, self.context.get_holder_selected_contest_delay(),
+		                                          &self.context.destination_script, (funding_txo, funding_txo_script),
+		                                          &self.context.channel_transaction_parameters,
+		                                          funding_redeemscript.clone(), self.context.channel_value_satoshis,
+		                                          obscure_factor,
+		                                          holder_commitment_tx, best_block, self.context.counterparty_node_id);
+
+		channel_monitor.provide_latest_counterparty_commitment_tx(counterparty_initial_bitcoin_tx.txid, Vec::new(), self.context.cur_counterparty_commitment_transaction_number, self.context.counterparty_cur_commitment_point.unwrap(), logger);
+
+		assert_eq!(self.context.channel_state & (ChannelState::MonitorUpdateInProgress as u32), 0); // We have no had any monitor(s) yet to fail update!
+		self.context.channel_state = ChannelState::FundingSent as u32;
+		self.context.cur_holder_commitment_transaction_number -= 1;
+		self.context.cur_counterparty_commitment_transaction_number -= 1;
+
+		// We may now release our tx_signatures, but only once the monitor has been persisted.
+		let session = self.context.interactive_tx_signing_session.as_mut().unwrap();
+		self.context.monitor_pending_tx_signatures = session.received_commitment_signed().is_some();
+
+		log_info!(logger, "Received initial commitment_signed from peer for channel {}", log_bytes!(self.context.channel_id()));
+
+		self.monitor_updating_paused(false, false, false, Vec::new(), Vec::new(), Vec::new());
+		Ok(channel_monitor)
 	}
 
-	pub fn update_add_htlc<F, FE: Deref, L: Deref>(
-		&mut self, msg: &msgs::UpdateAddHTLC, mut pending_forward_status: PendingHTLCStatus,
-		create_pending_htlc_status: F, fee_estimator: &LowerBoundedFeeEstimator<FE>, logger: &L
-	) -> Result<(), ChannelError>
-	where F: for<'a> Fn(&'a Self, PendingHTLCStatus, u16) -> PendingHTLCStatus,
-		FE::Target: FeeEstimator, L::Target: Logger,
-	{
-		// We can't accept HTLCs sent after we've sent a shutdown.
-		let local_sent_shutdown = (self.context.channel_state & (ChannelState::ChannelReady as u32 | ChannelState::LocalShutdownSent as u32)) != (ChannelState::ChannelReady as u32);
-		if local_sent_shutdown {
-			pending_forward_status = create_pending_htlc_status(self, pending_forward_status, 0x4000|8);
-		}
-		// If the remote has sent a shutdown prior to adding this HTLC, then they are in violation of the spec.
-		let remote_sent_shutdown = (self.context.channel_state & (ChannelState::ChannelReady as u32 | ChannelState::RemoteShutdownSent as u32)) != (ChannelState::ChannelReady as u32);
-		if remote_sent_shutdown {
-			return Err(ChannelError::Close("Got add HTLC message when channel was not in an operational state".to_owned()));
-		}
-		if self.context.channel_state & (ChannelState::PeerDisconnected as u32) == ChannelState::PeerDisconnected as u32 {
-			return Err(ChannelError::Close("Peer sent update_add_htlc when we needed a channel_reestablish".to_owned()));
+	/// Provides the signatures for the inputs we contributed to an interactively constructed
+	/// funding transaction, taken from the given signed transaction.
+	///
+	/// Returns our `tx_signatures` if they may be sent now and the fully signed funding
+	/// transaction if it may be broadcast now.
+	pub fn funding_transaction_signed<L: Deref>(&mut self, signed_tx: &Transaction, logger: &L)
+	-> Result<(Option<msgs::TxSignatures>, Option<Transaction>), APIError> where L::Target: Logger {
+		let channel_id = self.context.channel_id;
+		// The latest candidate funding transaction of a pending splice takes precedence over the
+		// (long since signed) funding transaction of a dual-funded channel.
+		let is_splice = self.context.pending_splice_signing_session().is_some();
+		let session = match self.context.pending_splice.as_mut().and_then(|splice| splice.candidates.last_mut())
+			.and_then(|candidate| candidate.signing_session.as_mut())
+			.or(self.context.interactive_tx_signing_session.as_mut())
+		{
+			Some(session) => session,
+			None => return Err(APIError::APIMisuseError {
+				err: format!("Channel {} does not have a funding transaction to sign", log_bytes!(channel_id)),
+			}),
+		};
+		if !session.holder_has_inputs() || session.has_holder_tx_signatures() {
+			return Err(APIError::APIMisuseError {
+				err: format!("Channel {} is not awaiting funding transaction signatures", log_bytes!(channel_id)),
+			});
 		}
-		if msg.amount_msat > self.context.channel_value_satoshis * 1000 {
-			return Err(ChannelError::Close("Remote side tried to send more than the total value of the channel".to_owned()));
+		let tx_signatures = session.provide_holder_witnesses(channel_id, signed_tx)
+			.map_err(|_| APIError::APIMisuseError {
+				err: "The provided transaction does not match the negotiated funding transaction or is missing witnesses".to_owned(),
+			})?;
+		log_debug!(logger, "Received funding transaction signatures for channel {}", log_bytes!(channel_id));
+		if is_splice {
+			Ok(self.maybe_release_splice_tx_signatures(tx_signatures, logger))
+		} else {
+			Ok(self.maybe_release_tx_signatures(tx_signatures))
 		}
-		if msg.amount_msat == 0 {
-			return Err(ChannelError::Close("Remote side tried to send a 0-msat HTLC".to_owned()));
+	}
+
+	/// Handles a tx_signatures message from our counterparty for an interactively constructed
+	/// funding transaction.
+	///
+	/// Returns our `tx_signatures` if they may be sent now and the fully signed funding
+	/// transaction if it may be broadcast now.
+	pub fn tx_signatures<L: Deref>(&mut self, msg: &msgs::TxSignatures, logger: &L)
+	-> Result<(Option<msgs::TxSignatures>, Option<Transaction>), ChannelError> where L::Target: Logger {
+		if self.context.pending_splice_signing_session().is_some() {
+			return self.splice_tx_signatures(msg, logger);
+		}
+		if self.context.channel_state & !(MULTI_STATE_FLAGS | ChannelState::OurChannelReady as u32 | ChannelState::TheirChannelReady as u32) != ChannelState::FundingSent as u32 {
+			return Err(ChannelError::Close("Received tx_signatures in strange state!".to_owned()));
+		}
+		let session = match self.context.interactive_tx_signing_session.as_mut() {
+			Some(session) => session,
+			None => return Err(ChannelError::Close("Received tx_signatures for a channel which was not funded interactively".to_owned())),
+		};
+		if !session.has_received_commitment_signed() {
+			return Err(ChannelError::Close("Received tx_signatures before initial commitment_signed".to_owned()));
 		}
-		if msg.amount_msat < self.context.holder_htlc_minimum_msat {
-			return Err(ChannelError::Close(format!("Remote side tried to send less than our minimum HTLC value. Lower limit: ({}). Actual: ({})", self.context.holder_htlc_minimum_msat, msg.amount_msat)));
+		let already_finalized = session.has_received_tx_signatures();
+		let tx_signatures = session.received_tx_signatures(msg)
+			.map_err(|_| ChannelError::Close("Received invalid tx_signatures for the funding transaction".to_owned()))?;
+		if already_finalized {
+			return Ok((None, None));
 		}
+		log_debug!(logger, "Received tx_signatures from peer for channel {}", log_bytes!(self.context.channel_id()));
+		Ok(self.maybe_release_tx_signatures(tx_signatures))
+	}
 
-		let inbound_stats = self.context.get_inbound_pending_htlc_stats(None);
-		let outbound_stats = self.context.get_outbound_pending_htlc_stats(None);
-		if inbound_stats.pending_htlcs + 1 > self.context.holder_max_accepted_htlcs as u32 {
-			return Err(ChannelError::Close(format!("Remote tried to push more than our max accepted HTLCs ({})", self.context.holder_max_accepted_htlcs)));
+	/// Holds our `tx_signatures` and the fully signed funding transaction, if available, until any
+	/// in-progress monitor update (i.e. the initial one) completes, returning them otherwise.
+	fn maybe_release_tx_signatures(&mut self, tx_signatures: Option<msgs::TxSignatures>)
+	-> (Option<msgs::TxSignatures>, Option<Transaction>) {
+		let funding_tx = self.context.interactive_tx_signing_session.as_ref()
+			.and_then(|session| session.finalized_tx());
+		if self.context.channel_state & (ChannelState::MonitorUpdateInProgress as u32) != 0 {
+			self.context.monitor_pending_tx_signatures |= tx_signatures.is_some();
+			if funding_tx.is_some() {
+				// We'll broadcast the funding transaction once the monitor update completes.
+				self.context.funding_transaction = funding_tx;
+			}
+			return (None, None);
 		}
-		if inbound_stats.pending_htlcs_value_msat + msg.amount_msat > self.context.holder_max_htlc_value_in_flight_msat {
-			return Err(ChannelError::Close(format!("Remote HTLC add would put them over our max HTLC value ({})", self.context.holder_max_htlc_value_in_flight_msat)));
+		if self.context.channel_state & (ChannelState::PeerDisconnected as u32) != 0 {
+			// Our signatures will be retransmitted upon reconnection.
+			return (None, funding_tx);
 		}
-		// Check holder_selected_channel_reserve_satoshis (we're getting paid, so they have to at least meet
-		// the reserve_satoshis we told them to always have as direct payment so that they lose
-		// something if we punish them for broadcasting an old state).
-		// Note that we don't really care about having a small/no to_remote output in our local
-		// commitment transactions, as the purpose of the channel reserve is to ensure we can
-		// punish *them* if they misbehave, so we discount any outbound HTLCs which will not be
-		// present in the next commitment transaction we send them (at least for fulfilled ones,
-		// failed ones won't modify value_to_self).
-		// Note that we will send HTLCs which another instance of rust-lightning would think
-		// violate the reserve value if we do not do this (as we forget inbound HTLCs from the
-		// Channel state once they will not be present in the next received commitment
-		// transaction).
-		let mut removed_outbound_total_msat = 0;
-		for ref htlc in self.context.pending_outbound_htlcs.iter() {
-			if let OutboundHTLCState::AwaitingRemoteRevokeToRemove(OutboundHTLCOutcome::Success(_)) = htlc.state {
-				removed_outbound_total_msat += htlc.amount_msat;
-			} else if let OutboundHTLCState::AwaitingRemovedRemoteRevoke(OutboundHTLCOutcome::Success(_)) = htlc.state {
-				removed_outbound_total_msat += htlc.amount_msat;
-			}
+		(tx_signatures, funding_tx)
+	}
+
+	/// Gets the initial commitment_signed and tx_signatures we may need to retransmit upon
+	/// reconnection for a channel whose funding transaction was negotiated interactively.
+	fn get_interactive_funding_retransmissions<L: Deref>(&mut self, msg: &msgs::ChannelReestablish, logger: &L)
+	-> Result<(Option<msgs::CommitmentUpdate>, Option<msgs::TxSignatures>), ChannelError> where L::Target: Logger {
+		let session = match self.context.interactive_tx_signing_session.as_ref() {
+			Some(session) => session,
+			None => return Ok((None, None)),
+		};
+		let funding_txid = session.unsigned_tx().txid();
+		match msg.next_funding_txid {
+			Some(next_funding_txid) if next_funding_txid == funding_txid => {},
+			Some(_) => return Err(ChannelError::Close("Peer sent a channel_reestablish for an unknown funding transaction".to_owned())),
+			None => return Ok((None, None)),
 		}
 
-		let max_dust_htlc_exposure_msat = self.context.get_max_dust_htlc_exposure_msat(fee_estimator);
-		let (htlc_timeout_dust_limit, htlc_success_dust_limit) = if self.context.get_channel_type().supports_anchors_zero_fee_htlc_tx() {
-			(0, 0)
+		let commitment_update = if !session.has_received_tx_signatures() &&
+			self.context.channel_state & (ChannelState::TheirChannelReady as u32) == 0
+		{
+			let signature = self.context.get_initial_counterparty_commitment_signature(INITIAL_COMMITMENT_NUMBER, logger)?;
+			Some(msgs::CommitmentUpdate {
+				update_add_htlcs: Vec::new(),
+				update_fulfill_htlcs: Vec::new(),
+				update_fail_htlcs: Vec::new(),
+				update_fail_malformed_htlcs: Vec::new(),
+				update_fee: None,
+				commitment_signed: msgs::CommitmentSigned {
+					channel_id: self.context.channel_id,
+					signature,
+					htlc_signatures: Vec::new(),
+					batch: None,
+					#[cfg(taproot)]
+					partial_signature_with_nonce: None,
+				},
+				splice_commitment_signed: Vec::new(),
+			})
+		} else { None };
+
+		let tx_signatures = if self.context.channel_state & (ChannelState::MonitorUpdateInProgress as u32) != 0 {
+			self.context.monitor_pending_tx_signatures = true;
+			None
 		} else {
-			let dust_buffer_feerate = self.context.get_dust_buffer_feerate(None) as u64;
-			(dust_buffer_feerate * htlc_timeout_tx_weight(self.context.get_channel_type()) / 1000,
-				dust_buffer_feerate * htlc_success_tx_weight(self.context.get_channel_type()) / 1000)
+			session.holder_tx_signatures_to_send()
 		};
-		let exposure_dust_limit_timeout_sats = htlc_timeout_dust_limit + self.context.counterparty_dust_limit_satoshis;
-		if msg.amount_msat / 1000 < exposure_dust_limit_timeout_sats {
-			let on_counterparty_tx_dust_htlc_exposure_msat = inbound_stats.on_counterparty_tx_dust_exposure_msat + outbound_stats.on_counterparty_tx_dust_exposure_msat + msg.amount_msat;
-			if on_counterparty_tx_dust_htlc_exposure_msat > max_dust_htlc_exposure_msat {
-				log_info!(logger, "Cannot accept value that would put our exposure to dust HTLCs at {} over the limit {} on counterparty commitment tx",
-					on_counterparty_tx_dust_htlc_exposure_msat, max_dust_htlc_exposure_msat);
-				pending_forward_status = create_pending_htlc_status(self, pending_forward_status, 0x1000|7);
-			}
+		log_debug!(logger, "Retransmitting {}{} for interactively funded channel {}",
+			if commitment_update.is_some() { "initial commitment_signed" } else { "" },
+			if tx_signatures.is_some() { " tx_signatures" } else { "" }, log_bytes!(self.context.channel_id()));
+		Ok((commitment_update, tx_signatures))
+	}
+
+	// Splicing
+
+	/// Computes the channel value and our balance after a splice with the given contributions,
+	/// checking that any party withdrawing funds from the channel is left with enough to cover its
+	/// reserve and, if it is the funder, the commitment transaction fee, even if all HTLCs it
+	/// offered are claimed.
+	fn get_splice_channel_value(
+		&self, our_contribution_satoshis: i64, their_contribution_satoshis: i64
+	) -> Result<(u64, u64), String> {
+		let max_contribution = TOTAL_BITCOIN_SUPPLY_SATOSHIS as i64;
+		if our_contribution_satoshis <= -max_contribution || our_contribution_satoshis >= max_contribution ||
+			their_contribution_satoshis <= -max_contribution || their_contribution_satoshis >= max_contribution
+		{
+			return Err("Splice contributions must be smaller than the total bitcoin supply".to_owned());
 		}
-
-		let exposure_dust_limit_success_sats = htlc_success_dust_limit + self.context.holder_dust_limit_satoshis;
-		if msg.amount_msat / 1000 < exposure_dust_limit_success_sats {
-			let on_holder_tx_dust_htlc_exposure_msat = inbound_stats.on_holder_tx_dust_exposure_msat + outbound_stats.on_holder_tx_dust_exposure_msat + msg.amount_msat;
-			if on_holder_tx_dust_htlc_exposure_msat > max_dust_htlc_exposure_msat {
-				log_info!(logger, "Cannot accept value that would put our exposure to dust HTLCs at {} over the limit {} on holder commitment tx",
-					on_holder_tx_dust_htlc_exposure_msat, max_dust_htlc_exposure_msat);
-				pending_forward_status = create_pending_htlc_status(self, pending_forward_status, 0x1000|7);
-			}
+		let channel_value_satoshis = self.context.channel_value_satoshis as i64 + our_contribution_satoshis + their_contribution_satoshis;
+		if channel_value_satoshis <= 0 || channel_value_satoshis as u64 >= TOTAL_BITCOIN_SUPPLY_SATOSHIS {
+			return Err(format!("Splice would result in an invalid channel value of {} sats", channel_value_satoshis));
 		}
+		let channel_value_satoshis = channel_value_satoshis as u64;
+		let value_to_self_msat = self.context.value_to_self_msat as i64 + our_contribution_satoshis * 1000;
+		let value_to_remote_msat = channel_value_satoshis as i64 * 1000 - value_to_self_msat;
 
-		let pending_value_to_self_msat =
-			self.context.value_to_self_msat + inbound_stats.pending_htlcs_value_msat - removed_outbound_total_msat;
-		let pending_remote_value_msat =
-			self.context.channel_value_satoshis * 1000 - pending_value_to_self_msat;
-		if pending_remote_value_msat < msg.amount_msat {
-			return Err(ChannelError::Close("Remote HTLC add would overdraw remaining funds".to_owned()));
+		let inbound_stats = self.context.get_inbound_pending_htlc_stats(None);
+		let outbound_stats = self.context.get_outbound_pending_htlc_stats(None);
+		let num_htlcs = (inbound_stats.pending_htlcs + outbound_stats.pending_htlcs) as usize;
+		let funder_fee_msat = (commit_tx_fee_sat(self.context.feerate_per_kw, num_htlcs, &self.context.channel_type) +
+			if self.context.channel_type.supports_anchors_zero_fee_htlc_tx() { ANCHOR_OUTPUT_VALUE_SATOSHI * 2 } else { 0 }) * 1000;
+		if our_contribution_satoshis < 0 {
+			let mut required_msat = get_v2_channel_reserve_satoshis(channel_value_satoshis, self.context.holder_dust_limit_satoshis) * 1000;
+			if self.context.is_outbound() { required_msat += funder_fee_msat; }
+			if value_to_self_msat - (outbound_stats.pending_htlcs_value_msat as i64) < required_msat as i64 {
+				return Err(format!("Splicing out {} sats would leave our balance below our reserve", -our_contribution_satoshis));
+			}
+		}
+		if their_contribution_satoshis < 0 {
+			let mut required_msat = get_v2_channel_reserve_satoshis(channel_value_satoshis, self.context.counterparty_dust_limit_satoshis) * 1000;
+			if !self.context.is_outbound() { required_msat += funder_fee_msat; }
+			if value_to_remote_msat - (inbound_stats.pending_htlcs_value_msat as i64) < required_msat as i64 {
+				return Err(format!("Splicing out {} sats would leave our counterparty's balance below their reserve", -their_contribution_satoshis));
+			}
+		}
+		Ok((channel_value_satoshis, value_to_self_msat as u64))
+	}
+
+	/// Gets the current funding output of the channel as the shared input of a splice's new
+	/// funding transaction.
+	fn get_splice_shared_input(&self) -> SharedInput {
+		SharedInput {
+			outpoint: self.context.get_funding_txo().unwrap().into_bitcoin_outpoint(),
+			prev_output: TxOut {
+				value: self.context.channel_value_satoshis,
+				script_pubkey: self.context.get_funding_redeemscript().to_v0_p2wsh(),
+			},
+			holder_value: self.context.value_to_self_msat / 1000,
 		}
+	}
 
-		// Check that the remote can afford to pay for this HTLC on-chain at the current
-		// feerate_per_kw, while maintaining their channel reserve (as required by the spec).
-		let remote_commit_tx_fee_msat = if self.context.is_outbound() { 0 } else {
-			let htlc_candidate = HTLCCandidate::new(msg.amount_msat, HTLCInitiator::RemoteOffered);
-			self.context.next_remote_commit_tx_fee_msat(htlc_candidate, None) // Don't include the extra fee spike buffer HTLC in calculations
+	/// Checks that the given inputs can cover our contribution to a splice's new funding
+	/// transaction and our share of its fees.
+	fn check_splice_funding_inputs(
+		&self, our_funding_contribution_satoshis: i64, funding_inputs: &[(TxIn, TransactionU16LenLimited)],
+		channel_value_satoshis: u64, funding_feerate_sat_per_1000_weight: u32,
+	) -> Result<(), APIError> {
+		let funding_output = TxOut {
+			value: channel_value_satoshis,
+			script_pubkey: self.context.get_funding_redeemscript().to_v0_p2wsh(),
 		};
-		if pending_remote_value_msat - msg.amount_msat < remote_commit_tx_fee_msat {
-			return Err(ChannelError::Close("Remote HTLC add would not leave enough to pay for fees".to_owned()));
-		};
-
-		if pending_remote_value_msat - msg.amount_msat - remote_commit_tx_fee_msat < self.context.holder_selected_channel_reserve_satoshis * 1000 {
-			return Err(ChannelError::Close("Remote HTLC add would put them under remote reserve value".to_owned()));
+		if calculate_change_output(true, true, our_funding_contribution_satoshis, funding_inputs, &[funding_output],
+			funding_feerate_sat_per_1000_weight, self.context.destination_script.clone()).is_err()
+		{
+			return Err(APIError::APIMisuseError {
+				err: format!("Provided inputs are insufficient to fund our contribution of {} sats and fees", our_funding_contribution_satoshis),
+			});
 		}
+		Ok(())
+	}
 
-		if !self.context.is_outbound() {
-			// `2 *` and `Some(())` is for the fee spike buffer we keep for the remote. This deviates from
-			// the spec because in the spec, the fee spike buffer requirement doesn't exist on the
-			// receiver's side, only on the sender's.
-			// Note that when we eventually remove support for fee updates and switch to anchor output
-			// fees, we will drop the `2 *`, since we no longer be as sensitive to fee spikes. But, keep
-			// the extra htlc when calculating the next remote commitment transaction fee as we should
-			// still be able to afford adding this HTLC plus one more future HTLC, regardless of being
-			// sensitive to fee spikes.
-			let htlc_candidate = HTLCCandidate::new(msg.amount_msat, HTLCInitiator::RemoteOffered);
-			let remote_fee_cost_incl_stuck_buffer_msat = 2 * self.context.next_remote_commit_tx_fee_msat(htlc_candidate, Some(()));
-			if pending_remote_value_msat - msg.amount_msat - self.context.holder_selected_channel_reserve_satoshis * 1000 < remote_fee_cost_incl_stuck_buffer_msat {
-				// Note that if the pending_forward_status is not updated here, then it's because we're already failing
-				// the HTLC, i.e. its status is already set to failing.
-				log_info!(logger, "Attempting to fail HTLC due to fee spike buffer violation in channel {}. Rebalancing is required.", log_bytes!(self.context.channel_id()));
-				pending_forward_status = create_pending_htlc_status(self, pending_forward_status, 0x1000|7);
-			}
-		} else {
-			// Check that they won't violate our local required channel reserve by adding this HTLC.
-			let htlc_candidate = HTLCCandidate::new(msg.amount_msat, HTLCInitiator::RemoteOffered);
-			let local_commit_tx_fee_msat = self.context.next_local_commit_tx_fee_msat(htlc_candidate, None);
-			if self.context.value_to_self_msat < self.context.counterparty_selected_channel_reserve_satoshis.unwrap() * 1000 + local_commit_tx_fee_msat {
-				return Err(ChannelError::Close("Cannot accept HTLC that would put our balance under counterparty-announced channel reserve value".to_owned()));
-			}
+	/// Returns true if the latest candidate funding transaction of a pending splice is fully signed
+	/// and our `tx_signatures` for it were handed out, at which point the quiescence required to
+	/// negotiate and sign it ends.
+	fn is_splice_signing_complete(&self) -> bool {
+		self.context.pending_splice.as_ref().map_or(false, |splice|
+			splice.pending_request.is_none() && splice.candidates.last().map_or(false, |candidate| candidate.is_signed())) &&
+			!self.context.monitor_pending_tx_signatures &&
+			self.context.channel_state & (ChannelState::MonitorUpdateInProgress as u32) == 0
+	}
+
+	/// Exits quiescence once the latest candidate funding transaction of a pending splice has been
+	/// signed by both parties, see [`Self::is_splice_signing_complete`].
+	fn maybe_exit_splice_quiescence<L: Deref>(&mut self, logger: &L) where L::Target: Logger {
+		if self.context.channel_state & (ChannelState::Quiescent as u32) != 0 && self.is_splice_signing_complete() {
+			log_debug!(logger, "Exchanged tx_signatures for splice of channel {}, exiting quiescence", log_bytes!(self.context.channel_id()));
+			self.exit_quiescence();
+		}
+	}
+
+	/// Requests a splice of this channel, adding `our_funding_contribution_satoshis` to the channel
+	/// from the given inputs or, if negative, withdrawing it from our balance to a change output.
+	/// Returns the `stfu` message to send to our counterparty if the channel can begin becoming
+	/// quiescent immediately. Once it is quiescent with us as the initiator,
+	/// [`Self::maybe_propose_splice`] returns the `splice` message proposing it.
+	pub fn splice_channel<L: Deref>(
+		&mut self, our_funding_contribution_satoshis: i64, funding_inputs: Vec<(TxIn, TransactionU16LenLimited)>,
+		funding_feerate_sat_per_1000_weight: u32, current_chain_height: u32, logger: &L,
+	) -> Result<Option<msgs::Stfu>, APIError> where L::Target: Logger {
+		if self.context.pending_splice.is_some() {
+			return Err(APIError::APIMisuseError {
+				err: format!("Channel {} already has a pending splice", log_bytes!(self.context.channel_id)),
+			});
 		}
-		if self.context.next_counterparty_htlc_id != msg.htlc_id {
-			return Err(ChannelError::Close(format!("Remote skipped HTLC ID (skipped ID: {})", self.context.next_counterparty_htlc_id)));
+		if self.context.pending_channel_type_upgrade.is_some() {
+			return Err(APIError::ChannelUnavailable {
+				err: format!("Channel {} cannot be spliced while its channel type is being upgraded", log_bytes!(self.context.channel_id)),
+			});
 		}
-		if msg.cltv_expiry >= 500000000 {
-			return Err(ChannelError::Close("Remote provided CLTV expiry in seconds instead of block height".to_owned()));
+		if our_funding_contribution_satoshis == 0 {
+			return Err(APIError::APIMisuseError { err: "A splice must add funds to or remove funds from the channel".to_owned() });
+		}
+		let (channel_value_satoshis, _) = self.get_splice_channel_value(our_funding_contribution_satoshis, 0)
+			.map_err(|err| APIError::APIMisuseError { err })?;
+		// Make sure our inputs can cover our contribution and our share of the fees before we go
+		// any further.
+		self.check_splice_funding_inputs(our_funding_contribution_satoshis, &funding_inputs,
+			channel_value_satoshis, funding_feerate_sat_per_1000_weight)?;
+
+		let stfu = self.propose_quiescence(logger)?;
+		log_info!(logger, "Requesting splice of channel {} with a contribution of {} sats", log_bytes!(self.context.channel_id),
+			our_funding_contribution_satoshis);
+		self.context.pending_splice = Some(PendingSplice {
+			is_initiator: true,
+			our_funding_contribution_satoshis,
+			their_funding_contribution_satoshis: 0,
+			pending_request: Some(SpliceRequest {
+				funding_feerate_sat_per_1000_weight,
+				funding_tx_locktime: current_chain_height,
+				our_funding_inputs: funding_inputs,
+				proposed: false,
+			}),
+			candidates: Vec::new(),
+			sent_splice_locked: None,
+			received_splice_locked: None,
+		});
+		Ok(stfu)
+	}
+
+	/// Requests a replacement of the latest candidate funding transaction of the splice we
+	/// initiated, paying at least 25/24 of its feerate, with the same contributions to the
+	/// channel. Returns the `stfu` message to send to our counterparty if the
+	/// channel can begin becoming quiescent immediately. Once it is quiescent with us as the
+	/// initiator, [`Self::maybe_propose_splice_rbf`] returns the `tx_init_rbf` message proposing
+	/// it.
+	pub fn rbf_splice_channel<L: Deref>(
+		&mut self, funding_inputs: Vec<(TxIn, TransactionU16LenLimited)>, funding_feerate_sat_per_1000_weight: u32,
+		current_chain_height: u32, logger: &L,
+	) -> Result<Option<msgs::Stfu>, APIError> where L::Target: Logger {
+		let (our_funding_contribution_satoshis, prev_feerate) = match self.context.pending_splice.as_ref() {
+			Some(splice) if splice.is_initiator && splice.pending_request.is_none() &&
+				splice.unsigned_candidate().is_none() && splice.sent_splice_locked.is_none() &&
+				splice.received_splice_locked.is_none() =>
+			{
+				match splice.candidates.last() {
+					Some(candidate) => (splice.our_funding_contribution_satoshis, candidate.funding_feerate_sat_per_1000_weight),
+					None => return Err(APIError::APIMisuseError {
+						err: format!("Channel {} does not have a signed splice transaction to replace", log_bytes!(self.context.channel_id)),
+					}),
+				}
+			},
+			_ => return Err(APIError::APIMisuseError {
+				err: format!("Channel {} does not have a signed and unlocked splice transaction we initiated to replace", log_bytes!(self.context.channel_id)),
+			}),
+		};
+		if !is_sufficient_splice_rbf_feerate(prev_feerate, funding_feerate_sat_per_1000_weight) {
+			return Err(APIError::APIMisuseError {
+				err: format!("Replacing a splice transaction paying a feerate of {} sat/kw requires a feerate of at least {} sat/kw",
+					prev_feerate, min_splice_rbf_feerate(prev_feerate)),
+			});
 		}
+		let channel_value_satoshis = self.context.get_splice_funding_balances().unwrap().channel_value_satoshis;
+		self.check_splice_funding_inputs(our_funding_contribution_satoshis, &funding_inputs,
+			channel_value_satoshis, funding_feerate_sat_per_1000_weight)?;
+
+		let stfu = self.propose_quiescence(logger)?;
+		log_info!(logger, "Requesting replacement of the splice transaction of channel {} at a feerate of {} sat/kw",
+			log_bytes!(self.context.channel_id), funding_feerate_sat_per_1000_weight);
+		self.context.pending_splice.as_mut().unwrap().pending_request = Some(SpliceRequest {
+			funding_feerate_sat_per_1000_weight,
+			funding_tx_locktime: current_chain_height,
+			our_funding_inputs: funding_inputs,
+			proposed: false,
+		});
+		Ok(stfu)
+	}
 
-		if self.context.channel_state & ChannelState::LocalShutdownSent as u32 != 0 {
-			if let PendingHTLCStatus::Forward(_) = pending_forward_status {
-				panic!("ChannelManager shouldn't be trying to add a forwardable HTLC after we've started closing");
-			}
+	/// Returns our request for a new funding transaction if the channel just became quiescent with
+	/// us as the initiator and it has yet to be proposed, after checking it is still valid.
+	fn take_splice_request_to_propose<L: Deref>(&mut self, propose_rbf: bool, logger: &L) -> Option<&mut SpliceRequest>
+	where L::Target: Logger {
+		if self.context.channel_state & (ChannelState::Quiescent as u32) == 0 ||
+			self.context.is_holder_quiescence_initiator != Some(true)
+		{
+			return None;
 		}
-
-		// Now update local state:
-		self.context.next_counterparty_htlc_id += 1;
-		self.context.pending_inbound_htlcs.push(InboundHTLCOutput {
-			htlc_id: msg.htlc_id,
-			amount_msat: msg.amount_msat,
-			payment_hash: msg.payment_hash,
-			cltv_expiry: msg.cltv_expiry,
-			state: InboundHTLCState::RemoteAnnounced(pending_forward_status),
-		});
-		Ok(())
+		let splice = self.context.pending_splice.as_ref()?;
+		if splice.pending_request.as_ref().map_or(true, |request| request.proposed) || splice.candidates.is_empty() == propose_rbf {
+			return None;
+		}
+		// HTLCs may have been added while the channel was becoming quiescent, and the splice may
+		// have locked since we requested a replacement of its transaction.
+		let check_res = if splice.sent_splice_locked.is_some() || splice.received_splice_locked.is_some() {
+			Err("The splice transaction has already been locked".to_owned())
+		} else {
+			self.get_splice_channel_value(splice.our_funding_contribution_satoshis, splice.their_funding_contribution_satoshis)
+		};
+		if let Err(err) = check_res {
+			log_info!(logger, "Abandoning {} of channel {}: {}", if propose_rbf { "splice replacement" } else { "splice" },
+				log_bytes!(self.context.channel_id), err);
+			let splice = self.context.pending_splice.as_mut().unwrap();
+			splice.pending_request = None;
+			if splice.candidates.is_empty() {
+				self.context.pending_splice = None;
+			}
+			self.exit_quiescence();
+			return None;
+		}
+		self.context.quiescence_timer_ticks = Some(0);
+		let request = self.context.pending_splice.as_mut().unwrap().pending_request.as_mut().unwrap();
+		request.proposed = true;
+		Some(request)
 	}
 
-	/// Marks an outbound HTLC which we have received update_fail/fulfill/malformed
-	#[inline]
-	fn mark_outbound_htlc_removed(&mut self, htlc_id: u64, check_preimage: Option<PaymentPreimage>, fail_reason: Option<HTLCFailReason>) -> Result<&OutboundHTLCOutput, ChannelError> {
-		assert!(!(check_preimage.is_some() && fail_reason.is_some()), "cannot fail while we have a preimage");
-		for htlc in self.context.pending_outbound_htlcs.iter_mut() {
-			if htlc.htlc_id == htlc_id {
-				let outcome = match check_preimage {
-					None => fail_reason.into(),
-					Some(payment_preimage) => {
-						let payment_hash = PaymentHash(Sha256::hash(&payment_preimage.0[..]).into_inner());
-						if payment_hash != htlc.payment_hash {
-							return Err(ChannelError::Close(format!("Remote tried to fulfill HTLC ({}) with an incorrect preimage", htlc_id)));
-						}
-						OutboundHTLCOutcome::Success(Some(payment_preimage))
-					}
-				};
-				match htlc.state {
-					OutboundHTLCState::LocalAnnounced(_) =>
-						return Err(ChannelError::Close(format!("Remote tried to fulfill/fail HTLC ({}) before it had been committed", htlc_id))),
-					OutboundHTLCState::Committed => {
-						htlc.state = OutboundHTLCState::RemoteRemoved(outcome);
-					},
-					OutboundHTLCState::AwaitingRemoteRevokeToRemove(_) | OutboundHTLCState::AwaitingRemovedRemoteRevoke(_) | OutboundHTLCState::RemoteRemoved(_) =>
-						return Err(ChannelError::Close(format!("Remote tried to fulfill/fail HTLC ({}) that they'd already fulfilled/failed", htlc_id))),
-				}
-				return Ok(htlc);
-			}
-		}
-		Err(ChannelError::Close("Remote tried to fulfill/fail an HTLC we couldn't find".to_owned()))
+	/// Returns the `splice` message to send to our counterparty if we requested a splice of the
+	/// channel and it just became quiescent with us as the initiator.
+	pub fn maybe_propose_splice<L: Deref>(&mut self, chain_hash: BlockHash, logger: &L) -> Option<msgs::Splice>
+	where L::Target: Logger {
+		let channel_id = self.context.channel_id;
+		let funding_pubkey = self.context.get_holder_pubkeys().funding_pubkey;
+		let our_funding_contribution_satoshis = self.context.pending_splice.as_ref()?.our_funding_contribution_satoshis;
+		let request = self.take_splice_request_to_propose(false, logger)?;
+		let msg = msgs::Splice {
+			channel_id,
+			chain_hash,
+			relative_satoshis: our_funding_contribution_satoshis,
+			funding_feerate_perkw: request.funding_feerate_sat_per_1000_weight,
+			locktime: request.funding_tx_locktime,
+			funding_pubkey,
+		};
+		log_debug!(logger, "Proposing splice of channel {}", log_bytes!(channel_id));
+		Some(msg)
 	}
 
-	pub fn update_fulfill_htlc(&mut self, msg: &msgs::UpdateFulfillHTLC) -> Result<(HTLCSource, u64), ChannelError> {
-		if (self.context.channel_state & (ChannelState::ChannelReady as u32)) != (ChannelState::ChannelReady as u32) {
-			return Err(ChannelError::Close("Got fulfill HTLC message when channel was not in an operational state".to_owned()));
-		}
+	/// Returns the `tx_init_rbf` message to send to our counterparty if we requested a replacement
+	/// of the latest candidate funding transaction of our splice and the channel just became
+	/// quiescent with us as the initiator.
+	pub fn maybe_propose_splice_rbf<L: Deref>(&mut self, logger: &L) -> Option<msgs::TxInitRbf>
+	where L::Target: Logger {
+		let channel_id = self.context.channel_id;
+		let our_funding_contribution_satoshis = self.context.pending_splice.as_ref()?.our_funding_contribution_satoshis;
+		let request = self.take_splice_request_to_propose(true, logger)?;
+		let msg = msgs::TxInitRbf {
+			channel_id,
+			locktime: request.funding_tx_locktime,
+			feerate_sat_per_1000_weight: request.funding_feerate_sat_per_1000_weight,
+			funding_output_contribution: Some(our_funding_contribution_satoshis),
+		};
+		log_debug!(logger, "Proposing replacement of the splice transaction of channel {}", log_bytes!(channel_id));
+		Some(msg)
+	}
+
+	/// Checks that our counterparty may propose a new funding transaction for a splice, i.e. that
+	/// the channel is quiescent with them as the initiator.
+	fn check_splice_proposal_quiescence(&self, msg_name: &str) -> Result<(), ChannelError> {
 		if self.context.channel_state & (ChannelState::PeerDisconnected as u32) == ChannelState::PeerDisconnected as u32 {
-			return Err(ChannelError::Close("Peer sent update_fulfill_htlc when we needed a channel_reestablish".to_owned()));
+			return Err(ChannelError::Close(format!("Peer sent {} when we needed a channel_reestablish", msg_name)));
+		}
+		if self.context.channel_state & (ChannelState::Quiescent as u32) == 0 ||
+			self.context.is_holder_quiescence_initiator != Some(false) ||
+			self.interactive_tx_constructor.is_some() || self.context.pending_splice_signing_session()
+				.map_or(false, |session| !session.has_holder_tx_signatures() || !session.has_received_tx_signatures())
+		{
+			return Err(ChannelError::WarnAndDisconnect(format!("Peer sent {} without being the quiescence initiator", msg_name)));
 		}
+		Ok(())
+	}
 
-		self.mark_outbound_htlc_removed(msg.htlc_id, Some(msg.payment_preimage), None).map(|htlc| (htlc.source.clone(), htlc.amount_msat))
+	/// Derives our signer for a new candidate funding transaction of a pending splice, and begins
+	/// negotiating it with our counterparty, returning the first message to send if we initiated
+	/// the negotiation.
+	fn begin_splice_negotiation<ES: Deref, SP: Deref>(
+		&mut self, funding_feerate_sat_per_1000_weight: u32, funding_tx_locktime: u32,
+		our_funding_inputs: Vec<(TxIn, TransactionU16LenLimited)>, entropy_source: &ES, signer_provider: &SP
+	) -> Result<Option<InteractiveTxMessageSend>, ChannelError>
+	where ES::Target: EntropySource,
+	      SP::Target: SignerProvider<Signer = Signer>,
+	{
+		let balances = self.context.get_splice_funding_balances().unwrap();
+		let splice = self.context.pending_splice.as_ref().unwrap();
+		let is_initiator = splice.is_initiator;
+		let our_funding_contribution_satoshis = splice.our_funding_contribution_satoshis;
+		let funding_output = TxOut {
+			value: balances.channel_value_satoshis,
+			script_pubkey: self.context.get_funding_redeemscript().to_v0_p2wsh(),
+		};
+		let mut funding_outputs = Vec::new();
+		if is_initiator {
+			let change_script = signer_provider.get_destination_script()
+				.map_err(|_| ChannelError::Warn("Failed to get change script".to_owned()))?;
+			funding_outputs.push(funding_output.clone());
+			match calculate_change_output(true, true, our_funding_contribution_satoshis, &our_funding_inputs,
+				&funding_outputs, funding_feerate_sat_per_1000_weight, change_script)
+			{
+				Ok(Some(change_output)) => funding_outputs.push(change_output),
+				Ok(None) => {},
+				Err(_) => return Err(ChannelError::Warn("Insufficient inputs to fund our contribution to the splice".to_owned())),
+			}
+		}
+
+		let holder_signer = signer_provider.derive_channel_signer(balances.channel_value_satoshis, self.context.channel_keys_id);
+		let shared_input = self.get_splice_shared_input();
+		let (tx_constructor, msg_send) = InteractiveTxConstructor::new(
+			entropy_source, self.context.channel_id, funding_feerate_sat_per_1000_weight, is_initiator,
+			PackedLockTime(funding_tx_locktime), our_funding_inputs, funding_outputs,
+			SharedOutput { tx_out: funding_output, holder_value: balances.value_to_self_msat / 1000 }, Some(shared_input));
+		self.interactive_tx_constructor = Some(tx_constructor);
+		self.context.pending_splice.as_mut().unwrap().candidates
+			.push(SpliceCandidate::new(funding_feerate_sat_per_1000_weight, holder_signer));
+		self.context.quiescence_timer_ticks = Some(0);
+		Ok(msg_send)
+	}
+
+	/// Handles a `splice` from our counterparty, accepting it without contributing to the new
+	/// funding transaction. Returns the `splice_ack` to send in response.
+	///
+	/// A [`ChannelError::Warn`] indicates that we reject the splice, which should be communicated
+	/// to our counterparty with a `tx_abort`. The channel is no longer quiescent in that case.
+	pub fn splice<ES: Deref, SP: Deref, L: Deref>(
+		&mut self, msg: &msgs::Splice, chain_hash: BlockHash, entropy_source: &ES, signer_provider: &SP, logger: &L
+	) -> Result<msgs::SpliceAck, ChannelError>
+	where ES::Target: EntropySource,
+	      SP::Target: SignerProvider<Signer = Signer>,
+	      L::Target: Logger,
+	{
+		if msg.chain_hash != chain_hash {
+			return Err(ChannelError::Close("Peer sent a splice for a different chain".to_owned()));
+		}
+		self.check_splice_proposal_quiescence("splice")?;
+		let check_res = if self.context.pending_splice.is_some() {
+			Err("Got a splice while a splice was already pending".to_owned())
+		} else if self.context.pending_channel_type_upgrade.is_some() {
+			Err("Got a splice while the channel type was being upgraded".to_owned())
+		} else if msg.funding_pubkey != *self.context.counterparty_funding_pubkey() {
+			Err("Changing the funding pubkey when splicing is not supported".to_owned())
+		} else if msg.relative_satoshis == 0 {
+			Err("Got a splice which doesn't change the channel value".to_owned())
+		} else {
+			self.get_splice_channel_value(0, msg.relative_satoshis).map(|_| ())
+		};
+		if let Err(err) = check_res {
+			self.exit_quiescence();
+			return Err(ChannelError::Warn(err));
+		}
+
+		self.context.pending_splice = Some(PendingSplice {
+			is_initiator: false,
+			our_funding_contribution_satoshis: 0,
+			their_funding_contribution_satoshis: msg.relative_satoshis,
+			pending_request: None,
+			candidates: Vec::new(),
+			sent_splice_locked: None,
+			received_splice_locked: None,
+		});
+		self.begin_splice_negotiation(msg.funding_feerate_perkw, msg.locktime, Vec::new(), entropy_source, signer_provider)?;
+		log_debug!(logger, "Accepting splice of channel {} with a contribution of {} sats from our counterparty",
+			log_bytes!(self.context.channel_id), msg.relative_satoshis);
+
+		Ok(msgs::SpliceAck {
+			channel_id: self.context.channel_id,
+			chain_hash,
+			relative_satoshis: 0,
+			funding_pubkey: self.context.get_holder_pubkeys().funding_pubkey,
+		})
 	}
 
-	pub fn update_fail_htlc(&mut self, msg: &msgs::UpdateFailHTLC, fail_reason: HTLCFailReason) -> Result<(), ChannelError> {
-		if (self.context.channel_state & (ChannelState::ChannelReady as u32)) != (ChannelState::ChannelReady as u32) {
-			return Err(ChannelError::Close("Got fail HTLC message when channel was not in an operational state".to_owned()));
+	/// Handles a `splice_ack` from our counterparty, returning the first message of the new
+	/// funding transaction negotiation which we initiate.
+	///
+	/// Any error other than a [`ChannelError::Close`] abandons the splice and should be
+	/// communicated to our counterparty with a `tx_abort`.
+	pub fn splice_ack<ES: Deref, SP: Deref>(
+		&mut self, msg: &msgs::SpliceAck, chain_hash: BlockHash, entropy_source: &ES, signer_provider: &SP
+	) -> Result<InteractiveTxMessageSend, ChannelError>
+	where ES::Target: EntropySource,
+	      SP::Target: SignerProvider<Signer = Signer>,
+	{
+		match &self.context.pending_splice {
+			Some(splice) if splice.is_initiator && splice.candidates.is_empty() &&
+				splice.pending_request.as_ref().map_or(false, |request| request.proposed) => {},
+			_ => return Err(ChannelError::Ignore("Got an unexpected splice_ack".to_owned())),
 		}
-		if self.context.channel_state & (ChannelState::PeerDisconnected as u32) == ChannelState::PeerDisconnected as u32 {
-			return Err(ChannelError::Close("Peer sent update_fail_htlc when we needed a channel_reestablish".to_owned()));
+		if msg.chain_hash != chain_hash {
+			return Err(ChannelError::Close("Peer sent a splice_ack for a different chain".to_owned()));
+		}
+		let check_res = if msg.funding_pubkey != *self.context.counterparty_funding_pubkey() {
+			Err("Changing the funding pubkey when splicing is not supported".to_owned())
+		} else {
+			let our_funding_contribution_satoshis = self.context.pending_splice.as_ref().unwrap().our_funding_contribution_satoshis;
+			self.get_splice_channel_value(our_funding_contribution_satoshis, msg.relative_satoshis).map(|_| ())
+		};
+		let res = check_res.map_err(ChannelError::Warn).and_then(|()| {
+			let splice = self.context.pending_splice.as_mut().unwrap();
+			splice.their_funding_contribution_satoshis = msg.relative_satoshis;
+			let request = splice.pending_request.take().unwrap();
+			self.begin_splice_negotiation(request.funding_feerate_sat_per_1000_weight, request.funding_tx_locktime,
+				request.our_funding_inputs, entropy_source, signer_provider)
+		});
+		match res {
+			Ok(msg_send) => Ok(msg_send.expect("The initiator always sends the first message of a negotiation")),
+			Err(e) => {
+				self.context.pending_splice = None;
+				self.interactive_tx_constructor = None;
+				self.exit_quiescence();
+				Err(e)
+			},
 		}
+	}
 
-		self.mark_outbound_htlc_removed(msg.htlc_id, None, Some(fail_reason))?;
-		Ok(())
+	/// Handles a `tx_init_rbf` from our counterparty, proposing to replace the latest candidate
+	/// funding transaction of the splice they initiated. We accept it without contributing to the
+	/// new funding transaction, returning the `tx_ack_rbf` to send in response.
+	///
+	/// A [`ChannelError::Warn`] indicates that we reject the replacement, which should be
+	/// communicated to our counterparty with a `tx_abort`. The channel is no longer quiescent in
+	/// that case.
+	pub fn tx_init_rbf<ES: Deref, SP: Deref, L: Deref>(
+		&mut self, msg: &msgs::TxInitRbf, entropy_source: &ES, signer_provider: &SP, logger: &L
+	) -> Result<msgs::TxAckRbf, ChannelError>
+	where ES::Target: EntropySource,
+	      SP::Target: SignerProvider<Signer = Signer>,
+	      L::Target: Logger,
+	{
+		self.check_splice_proposal_quiescence("tx_init_rbf")?;
+		let check_res = match self.context.pending_splice.as_ref() {
+			Some(splice) if !splice.is_initiator && splice.unsigned_candidate().is_none() => {
+				let prev_feerate = splice.candidates.last().unwrap().funding_feerate_sat_per_1000_weight;
+				if splice.sent_splice_locked.is_some() || splice.received_splice_locked.is_some() {
+					Err("Got a tx_init_rbf for a splice which was already locked".to_owned())
+				} else if msg.funding_output_contribution.unwrap_or(0) != splice.their_funding_contribution_satoshis {
+					Err("Changing the contributions of a splice when replacing its transaction is not supported".to_owned())
+				} else if !is_sufficient_splice_rbf_feerate(prev_feerate, msg.feerate_sat_per_1000_weight) {
+					Err(format!("Got a tx_init_rbf with a feerate of {} sat/kw while at least {} sat/kw is required",
+						msg.feerate_sat_per_1000_weight, min_splice_rbf_feerate(prev_feerate)))
+				} else {
+					Ok(())
+				}
+			},
+			_ => Err("Got a tx_init_rbf without a signed splice transaction initiated by our counterparty".to_owned()),
+		};
+		if let Err(err) = check_res {
+			self.exit_quiescence();
+			return Err(ChannelError::Warn(err));
+		}
+
+		self.begin_splice_negotiation(msg.feerate_sat_per_1000_weight, msg.locktime, Vec::new(), entropy_source, signer_provider)?;
+		log_debug!(logger, "Accepting replacement of the splice transaction of channel {} at a feerate of {} sat/kw",
+			log_bytes!(self.context.channel_id), msg.feerate_sat_per_1000_weight);
+		Ok(msgs::TxAckRbf {
+			channel_id: self.context.channel_id,
+			funding_output_contribution: Some(0),
+		})
 	}
 
-	pub fn update_fail_malformed_htlc(&mut self, msg: &msgs::UpdateFailMalformedHTLC, fail_reason: HTLCFailReason) -> Result<(), ChannelError> {
-		if (self.context.channel_state & (ChannelState::ChannelReady as u32)) != (ChannelState::ChannelReady as u32) {
-			return Err(ChannelError::Close("Got fail malformed HTLC message when channel was not in an operational state".to_owned()));
+	/// Handles a `tx_ack_rbf` from our counterparty, returning the first message of the
+	/// negotiation of the new funding transaction replacing the latest candidate of our splice.
+	///
+	/// Any error other than a [`ChannelError::Close`] abandons the replacement and should be
+	/// communicated to our counterparty with a `tx_abort`.
+	pub fn tx_ack_rbf<ES: Deref, SP: Deref>(
+		&mut self, msg: &msgs::TxAckRbf, entropy_source: &ES, signer_provider: &SP
+	) -> Result<InteractiveTxMessageSend, ChannelError>
+	where ES::Target: EntropySource,
+	      SP::Target: SignerProvider<Signer = Signer>,
+	{
+		let splice = match self.context.pending_splice.as_mut() {
+			Some(splice) if splice.is_initiator && !splice.candidates.is_empty() &&
+				splice.pending_request.as_ref().map_or(false, |request| request.proposed) => splice,
+			_ => return Err(ChannelError::Ignore("Got an unexpected tx_ack_rbf".to_owned())),
+		};
+		let request = splice.pending_request.take().unwrap();
+		let res = if msg.funding_output_contribution.unwrap_or(0) != splice.their_funding_contribution_satoshis {
+			Err(ChannelError::Warn("Changing the contributions of a splice when replacing its transaction is not supported".to_owned()))
+		} else {
+			self.begin_splice_negotiation(request.funding_feerate_sat_per_1000_weight, request.funding_tx_locktime,
+				request.our_funding_inputs, entropy_source, signer_provider)
+		};
+		match res {
+			Ok(msg_send) => Ok(msg_send.expect("The initiator always sends the first message of a negotiation")),
+			Err(e) => {
+				self.interactive_tx_constructor = None;
+				self.exit_quiescence();
+				Err(e)
+			},
 		}
-		if self.context.channel_state & (ChannelState::PeerDisconnected as u32) == ChannelState::PeerDisconnected as u32 {
-			return Err(ChannelError::Close("Peer sent update_fail_malformed_htlc when we needed a channel_reestablish".to_owned()));
+	}
+
+	/// Abandons the latest candidate funding transaction of a pending splice if it has yet to be
+	/// fully negotiated, e.g. upon receiving a `tx_abort` or failing the negotiation, along with
+	/// any request of ours to negotiate one, returning whether there was one to abandon. The
+	/// splice itself is abandoned if no other candidate remains, and the channel is no longer
+	/// quiescent.
+	///
+	/// Once we've started exchanging signatures for a candidate, the splice may only be abandoned
+	/// by closing the channel.
+	pub fn abandon_unsigned_splice<L: Deref>(&mut self, logger: &L) -> bool where L::Target: Logger {
+		let channel_id = self.context.channel_id;
+		let splice = match self.context.pending_splice.as_mut() {
+			Some(splice) => splice,
+			None => return false,
+		};
+		let abandon_candidate = splice.unsigned_candidate().map_or(false, |candidate|
+			candidate.signing_session.as_ref().map_or(true, |session| !session.has_received_commitment_signed()));
+		if !abandon_candidate && (splice.unsigned_candidate().is_some() || splice.pending_request.is_none()) {
+			return false;
 		}
+		log_info!(logger, "Abandoning pending {} of channel {}", if splice.candidates.len() > 1 || !abandon_candidate {
+			"splice transaction replacement" } else { "splice" }, log_bytes!(channel_id));
+		splice.pending_request = None;
+		if abandon_candidate {
+			splice.candidates.pop();
+		}
+		if splice.candidates.is_empty() {
+			self.context.pending_splice = None;
+		}
+		self.interactive_tx_constructor = None;
+		if self.context.channel_state & (ChannelState::Quiescent as u32) != 0 {
+			self.exit_quiescence();
+		}
+		true
+	}
 
-		self.mark_outbound_htlc_removed(msg.htlc_id, None, Some(fail_reason))?;
-		Ok(())
+	/// Updates our state once the latest candidate funding transaction of a splice has been
+	/// negotiated, returning the `commitment_signed` for its funding output to send to our
+	/// counterparty.
+	///
+	/// If an Err is returned, it is a ChannelError::Close.
+	pub fn splice_tx_constructed<L: Deref>(
+		&mut self, constructed_tx: ConstructedTransaction, holder_node_id: &PublicKey, logger: &L
+	) -> Result<msgs::CommitmentSigned, ChannelError> where L::Target: Logger {
+		let candidate_idx = match &self.context.pending_splice {
+			Some(splice) if splice.candidates.last().map_or(false, |candidate| candidate.signing_session.is_none()) =>
+				splice.candidates.len() - 1,
+			_ => return Err(ChannelError::Close("Completed splice negotiation at a strange time".to_owned())),
+		};
+		let channel_value_satoshis = self.context.get_splice_funding_balances().unwrap().channel_value_satoshis;
+		let shared_input_index = match constructed_tx.shared_input_index {
+			Some(idx) => idx as usize,
+			None => return Err(ChannelError::Close("Negotiated splice transaction doesn't spend the funding output".to_owned())),
+		};
+		let funding_redeemscript = self.context.get_funding_redeemscript();
+		let funding_script = funding_redeemscript.to_v0_p2wsh();
+		let funding_output_index = match constructed_tx.tx.output.iter().position(|output|
+			output.script_pubkey == funding_script && output.value == channel_value_satoshis
+		) {
+			Some(idx) => idx,
+			None => return Err(ChannelError::Close("Negotiated splice transaction is missing the new funding output".to_owned())),
+		};
+		let funding_txo = OutPoint { txid: constructed_tx.tx.txid(), index: funding_output_index as u16 };
+
+		// We sign the current funding output with our current signer, as it commits to the
+		// current channel value.
+		let holder_signature = self.context.holder_signer.sign_splicing_funding_input(
+			&constructed_tx.tx, shared_input_index, self.context.channel_value_satoshis, &self.context.secp_ctx
+		).map_err(|_| ChannelError::Close("Failed to sign the shared input of the splice transaction".to_owned()))?;
+		let holder_signature_first = self.context.get_holder_pubkeys().funding_pubkey.serialize()[..] <
+			self.context.counterparty_funding_pubkey().serialize()[..];
+
+		// As when funding a dual-funded channel, the party which contributed less to the inputs
+		// sends their `tx_signatures` first, with ties broken by the lexicographically lesser node id.
+		let holder_sends_tx_signatures_first = if constructed_tx.holder_inputs_value == constructed_tx.counterparty_inputs_value {
+			holder_node_id.serialize()[..] < self.context.counterparty_node_id.serialize()[..]
+		} else {
+			constructed_tx.holder_inputs_value < constructed_tx.counterparty_inputs_value
+		};
+
+		let mut channel_parameters = self.context.channel_transaction_parameters.clone();
+		channel_parameters.funding_outpoint = Some(funding_txo);
+		let candidate = &mut self.context.pending_splice.as_mut().unwrap().candidates[candidate_idx];
+		candidate.holder_signer.provide_channel_parameters(&channel_parameters);
+		candidate.funding_txo = Some(funding_txo);
+
+		// The channel is quiescent, so the commitment transactions spending the new funding output
+		// are those matching the latest ones we've exchanged.
+		let counterparty_commitment_number = self.context.cur_counterparty_commitment_transaction_number + 1;
+		let counterparty_commitment_point = self.context.counterparty_prev_commitment_point.unwrap();
+		let (commitment_signed, _, _) = self.context.get_splice_counterparty_commitment_signed(candidate_idx,
+			counterparty_commitment_number, &counterparty_commitment_point, false, logger)?;
+
+		log_info!(logger, "Negotiated splice transaction {} for channel {}", funding_txo.txid, log_bytes!(self.context.channel_id));
+
+		let signing_session = InteractiveTxSigningSession::new(self.context.channel_id, constructed_tx,
+			holder_sends_tx_signatures_first, Some(SharedInputSignature {
+				holder_signature, witness_script: funding_redeemscript, holder_signature_first,
+			}));
+		self.context.pending_splice.as_mut().unwrap().candidates[candidate_idx].signing_session = Some(signing_session);
+		// Signing the new funding transaction may take a while, e.g. if the user has to sign their
+		// inputs, so we no longer time out the quiescence here.
+		self.context.quiescence_timer_ticks = None;
+
+		Ok(commitment_signed)
 	}
 
-	pub fn commitment_signed<L: Deref>(&mut self, msg: &msgs::CommitmentSigned, logger: &L) -> Result<Option<ChannelMonitorUpdate>, ChannelError>
-		where L::Target: Logger
+	/// Returns true if we're waiting on our counterparty's initial `commitment_signed` for the
+	/// funding output of the latest candidate funding transaction of a pending splice.
+	pub fn is_awaiting_splice_commitment_signed(&self) -> bool {
+		self.context.pending_splice_signing_session()
+			.map_or(false, |session| !session.has_received_commitment_signed())
+	}
+
+	/// Handles the initial `commitment_signed` from our counterparty for the funding output of the
+	/// latest candidate funding transaction of a pending splice, returning the [`ChannelMonitor`]
+	/// which must watch it.
+	///
+	/// If an Err is returned, it is a ChannelError::Close.
+	pub fn splice_commitment_signed<SP: Deref, L: Deref>(
+		&mut self, msg: &msgs::CommitmentSigned, best_block: BestBlock, signer_provider: &SP, logger: &L
+	) -> Result<ChannelMonitor<Signer>, ChannelError>
+	where
+		SP::Target: SignerProvider<Signer = Signer>,
+		L::Target: Logger
 	{
-		if (self.context.channel_state & (ChannelState::ChannelReady as u32)) != (ChannelState::ChannelReady as u32) {
-			return Err(ChannelError::Close("Got commitment signed message when channel was not in an operational state".to_owned()));
+		if !self.is_awaiting_splice_commitment_signed() {
+			return Err(ChannelError::Close("Received splice commitment_signed in strange state!".to_owned()));
 		}
 		if self.context.channel_state & (ChannelState::PeerDisconnected as u32) == ChannelState::PeerDisconnected as u32 {
 			return Err(ChannelError::Close("Peer sent commitment_signed when we needed a channel_reestablish".to_owned()));
 		}
-		if self.context.channel_state & BOTH_SIDES_SHUTDOWN_MASK == BOTH_SIDES_SHUTDOWN_MASK && self.context.last_sent_closing_fee.is_some() {
-			return Err(ChannelError::Close("Peer sent commitment_signed after we'd started exchanging closing_signeds".to_owned()));
-		}
+		let candidate_idx = self.context.pending_splice.as_ref().unwrap().candidates.len() - 1;
 
-		let funding_script = self.context.get_funding_redeemscript();
+		let holder_commitment_number = self.context.cur_holder_commitment_transaction_number + 1;
+		let (holder_commitment_tx, holder_htlc_outputs) = self.context.validate_splice_holder_commitment_signed(
+			candidate_idx, holder_commitment_number, msg, false, logger)?;
+		let counterparty_commitment_number = self.context.cur_counterparty_commitment_transaction_number + 1;
+		let counterparty_commitment_point = self.context.counterparty_prev_commitment_point.unwrap();
+		let (_, counterparty_commitment_txid, counterparty_htlc_outputs) = self.context.get_splice_counterparty_commitment_signed(
+			candidate_idx, counterparty_commitment_number, &counterparty_commitment_point, false, logger)?;
+		let (channel_parameters, channel_value_satoshis) = self.context.with_splice_funding(candidate_idx, |context|
+			(context.channel_transaction_parameters.clone(), context.channel_value_satoshis));
 
-		let keys = self.context.build_holder_transaction_keys(self.context.cur_holder_commitment_transaction_number);
+		let funding_redeemscript = self.context.get_funding_redeemscript();
+		let funding_txo = channel_parameters.funding_outpoint.unwrap();
+		let funding_txo_script = funding_redeemscript.to_v0_p2wsh();
+		let obscure_factor = get_commitment_transaction_number_obscure_factor(&self.context.get_holder_pubkeys().payment_point, &self.context.get_counterparty_pubkeys().payment_point, self.context.is_outbound());
+		let shutdown_script = self.context.shutdown_scriptpubkey.clone().map(|script| script.into_inner());
+		let mut monitor_signer = signer_provider.derive_channel_signer(channel_value_satoshis, self.context.channel_keys_id);
+		monitor_signer.provide_channel_parameters(&channel_parameters);
+		let channel_monitor = ChannelMonitor::new(self.context.secp_ctx.clone(), monitor_signer,
+		                                          shutdown_script, self.context.get_holder_selected_contest_delay(),
+		                                          &self.context.destination_script, (funding_txo, funding_txo_script),
+		                                          &channel_parameters,
+		                                          funding_redeemscript, channel_value_satoshis,
+		                                          obscure_factor,
+		                                          holder_commitment_tx, best_block, self.context.counterparty_node_id);
+		// All monitors will receive the same updates until the splice locks, so they must agree on
+		// the channel's update id and revocation secrets. The preimages of any HTLCs we claimed
+		// while the channel was quiescent are only in the holding cell so far.
+		let payment_preimages = self.context.holding_cell_htlc_updates.iter().filter_map(|update| match update {
+			HTLCUpdateAwaitingACK::ClaimHTLC { payment_preimage, .. } => Some(*payment_preimage),
+			_ => None,
+		}).collect();
+		channel_monitor.inherit_state_for_splice(self.context.latest_monitor_update_id,
+			self.context.commitment_secrets.clone(), holder_htlc_outputs, payment_preimages);
+		channel_monitor.provide_latest_counterparty_commitment_tx(counterparty_commitment_txid, counterparty_htlc_outputs,
+			counterparty_commitment_number, counterparty_commitment_point, logger);
+
+		// We may now release our tx_signatures, but only once the monitor has been persisted.
+		let candidate = self.context.pending_splice.as_mut().unwrap().candidates.last_mut().unwrap();
+		candidate.monitor_created = true;
+		let session = candidate.signing_session.as_mut().unwrap();
+		channel_monitor.watch_splice_inputs(session.contributed_inputs());
+		self.context.monitor_pending_tx_signatures = session.received_commitment_signed().is_some();
+
+		log_info!(logger, "Received splice commitment_signed from peer for channel {}", log_bytes!(self.context.channel_id()));
+
+		self.monitor_updating_paused(false, false, false, Vec::new(), Vec::new(), Vec::new());
+		Ok(channel_monitor)
+	}
 
-		let commitment_stats = self.context.build_commitment_transaction(self.context.cur_holder_commitment_transaction_number, &keys, true, false, logger);
-		let commitment_txid = {
-			let trusted_tx = commitment_stats.tx.trust();
-			let bitcoin_tx = trusted_tx.built_transaction();
-			let sighash = bitcoin_tx.get_sighash_all(&funding_script, self.context.channel_value_satoshis);
+	/// Handles a tx_signatures message from our counterparty for the latest candidate funding
+	/// transaction of a pending splice.
+	fn splice_tx_signatures<L: Deref>(&mut self, msg: &msgs::TxSignatures, logger: &L)
+	-> Result<(Option<msgs::TxSignatures>, Option<Transaction>), ChannelError> where L::Target: Logger {
+		let funding_redeemscript = self.context.get_funding_redeemscript();
+		let counterparty_funding_pubkey = *self.context.counterparty_funding_pubkey();
+		let prev_channel_value_satoshis = self.context.channel_value_satoshis;
+		let session = self.context.pending_splice.as_mut().and_then(|splice| splice.candidates.last_mut())
+			.and_then(|candidate| candidate.signing_session.as_mut()).unwrap();
+		if !session.has_received_commitment_signed() {
+			return Err(ChannelError::Close("Received tx_signatures before splice commitment_signed".to_owned()));
+		}
+		let already_finalized = session.has_received_tx_signatures();
+		if !already_finalized {
+			// Our counterparty's signature for the shared input is only checked by us.
+			let shared_input_index = session.shared_input_index().unwrap() as usize;
+			let signature = msg.funding_outpoint_sig
+				.ok_or_else(|| ChannelError::Close("Received tx_signatures for a splice without a shared input signature".to_owned()))?;
+			let sighash = sighash::SighashCache::new(session.unsigned_tx()).segwit_signature_hash(
+				shared_input_index, &funding_redeemscript, prev_channel_value_satoshis, EcdsaSighashType::All
+			).map_err(|_| ChannelError::Close("Failed to compute the splice transaction's sighash".to_owned()))?;
+			if self.context.secp_ctx.verify_ecdsa(&hash_to_message!(&sighash[..]), &signature, &counterparty_funding_pubkey).is_err() {
+				return Err(ChannelError::Close("Invalid shared input signature in tx_signatures".to_owned()));
+			}
+		}
+		let tx_signatures = session.received_tx_signatures(msg)
+			.map_err(|_| ChannelError::Close("Received invalid tx_signatures for the splice transaction".to_owned()))?;
+		if already_finalized {
+			return Ok((None, None));
+		}
+		log_debug!(logger, "Received splice tx_signatures from peer for channel {}", log_bytes!(self.context.channel_id()));
+		Ok(self.maybe_release_splice_tx_signatures(tx_signatures, logger))
+	}
 
-			log_trace!(logger, "Checking commitment tx signature {} by key {} against tx {} (sighash {}) with redeemscript {} in channel {}",
-				log_bytes!(msg.signature.serialize_compact()[..]),
-				log_bytes!(self.context.counterparty_funding_pubkey().serialize()), encode::serialize_hex(&bitcoin_tx.transaction),
-				log_bytes!(sighash[..]), encode::serialize_hex(&funding_script), log_bytes!(self.context.channel_id()));
-			if let Err(_) = self.context.secp_ctx.verify_ecdsa(&sighash, &msg.signature, &self.context.counterparty_funding_pubkey()) {
-				return Err(ChannelError::Close("Invalid commitment tx signature from peer".to_owned()));
+	/// Holds our `tx_signatures` for the latest candidate funding transaction of a splice until any
+	/// in-progress monitor update (i.e. the one for its funding output) completes, returning them
+	/// along with the fully signed transaction, if available, otherwise.
+	fn maybe_release_splice_tx_signatures<L: Deref>(&mut self, tx_signatures: Option<msgs::TxSignatures>, logger: &L)
+	-> (Option<msgs::TxSignatures>, Option<Transaction>) where L::Target: Logger {
+		if self.context.channel_state & (ChannelState::MonitorUpdateInProgress as u32) != 0 {
+			// We'll broadcast the transaction, if possible, once the monitor update completes.
+			self.context.monitor_pending_tx_signatures |= tx_signatures.is_some();
+			return (None, None);
+		}
+		let splice_tx = self.context.pending_splice_signing_session().and_then(|session| session.finalized_tx());
+		if self.context.channel_state & (ChannelState::PeerDisconnected as u32) != 0 {
+			// Our signatures will be retransmitted upon reconnection.
+			return (None, splice_tx);
+		}
+		self.maybe_exit_splice_quiescence(logger);
+		(tx_signatures, splice_tx)
+	}
+
+	/// Gets the `commitment_signed` and `tx_signatures` for the latest candidate funding
+	/// transaction of a splice we may need to retransmit upon reconnection, abandoning the
+	/// candidate if our counterparty has forgotten about it before it could have been signed.
+	///
+	/// As the channel was quiescent while the candidate was negotiated, it becomes quiescent again
+	/// until both parties have exchanged their `tx_signatures` for it.
+	fn get_splice_retransmissions<L: Deref>(&mut self, msg: &msgs::ChannelReestablish, logger: &L)
+	-> Result<(Option<msgs::CommitmentUpdate>, Option<msgs::TxSignatures>), ChannelError> where L::Target: Logger {
+		let (splice_txid, has_received_commitment_signed, has_received_tx_signatures) = match self.context.pending_splice_signing_session() {
+			Some(session) => (session.unsigned_tx().txid(), session.has_received_commitment_signed(), session.has_received_tx_signatures()),
+			None => return Ok((None, None)),
+		};
+		if msg.next_funding_txid != Some(splice_txid) {
+			if !has_received_commitment_signed {
+				self.abandon_unsigned_splice(logger);
+				return Ok((None, None));
 			}
-			bitcoin_tx.txid
+		}
+		let splice = self.context.pending_splice.as_ref().unwrap();
+		if !splice.candidates.last().unwrap().is_signed() {
+			self.context.channel_state |= ChannelState::Quiescent as u32;
+			self.context.is_holder_quiescence_initiator = Some(splice.is_initiator);
+		}
+		if msg.next_funding_txid != Some(splice_txid) {
+			return Ok((None, None));
+		}
+
+		let commitment_update = if !has_received_tx_signatures {
+			let candidate_idx = splice.candidates.len() - 1;
+			let counterparty_commitment_number = self.context.cur_counterparty_commitment_transaction_number + 1;
+			let counterparty_commitment_point = self.context.counterparty_prev_commitment_point.unwrap();
+			let (commitment_signed, _, _) = self.context.get_splice_counterparty_commitment_signed(candidate_idx,
+				counterparty_commitment_number, &counterparty_commitment_point, false, logger)?;
+			Some(msgs::CommitmentUpdate {
+				update_add_htlcs: Vec::new(),
+				update_fulfill_htlcs: Vec::new(),
+				update_fail_htlcs: Vec::new(),
+				update_fail_malformed_htlcs: Vec::new(),
+				update_fee: None,
+				commitment_signed,
+				splice_commitment_signed: Vec::new(),
+			})
+		} else { None };
+
+		let tx_signatures = if self.context.channel_state & (ChannelState::MonitorUpdateInProgress as u32) != 0 {
+			self.context.monitor_pending_tx_signatures = true;
+			None
+		} else {
+			self.context.pending_splice_signing_session().and_then(|session| session.holder_tx_signatures_to_send())
 		};
-		let mut htlcs_cloned: Vec<_> = commitment_stats.htlcs_included.iter().map(|htlc| (htlc.0.clone(), htlc.1.map(|h| h.clone()))).collect();
+		log_debug!(logger, "Retransmitting {}{} for splice of channel {}",
+			if commitment_update.is_some() { "commitment_signed" } else { "" },
+			if tx_signatures.is_some() { " tx_signatures" } else { "" }, log_bytes!(self.context.channel_id()));
+		if tx_signatures.is_some() {
+			self.maybe_exit_splice_quiescence(logger);
+		}
+		Ok((commitment_update, tx_signatures))
+	}
 
-		// If our counterparty updated the channel fee in this commitment transaction, check that
-		// they can actually afford the new fee now.
-		let update_fee = if let Some((_, update_state)) = self.context.pending_update_fee {
-			update_state == FeeUpdateState::RemoteAnnounced
-		} else { false };
-		if update_fee {
-			debug_assert!(!self.context.is_outbound());
-			let counterparty_reserve_we_require_msat = self.context.holder_selected_channel_reserve_satoshis * 1000;
-			if commitment_stats.remote_balance_msat < commitment_stats.total_fee_sat * 1000 + counterparty_reserve_we_require_msat {
-				return Err(ChannelError::Close("Funding remote cannot afford proposed new fee".to_owned()));
+	/// Checks whether any candidate funding transaction of a pending splice has reached our
+	/// required depth, returning the `splice_locked` to send if so.
+	///
+	/// Should be called after [`Self::transactions_confirmed`] or [`Self::best_block_updated`],
+	/// followed by [`Self::maybe_promote_splice`].
+	pub fn check_get_splice_locked(&mut self, height: u32) -> Option<msgs::SpliceLocked> {
+		let minimum_depth = cmp::max(1, self.context.minimum_depth.unwrap_or(0)) as i64;
+		let channel_id = self.context.channel_id;
+		let splice = self.context.pending_splice.as_mut()?;
+		if splice.sent_splice_locked.is_some() {
+			return None;
+		}
+		let mut splice_txid = None;
+		for candidate in splice.candidates.iter_mut() {
+			if candidate.funding_tx_confirmation_height == 0 || !candidate.is_signed() {
+				continue;
+			}
+			let funding_tx_confirmations = height as i64 - candidate.funding_tx_confirmation_height as i64 + 1;
+			if funding_tx_confirmations <= 0 {
+				// The splice transaction was reorged out before we locked it, wait for it to confirm again.
+				candidate.funding_tx_confirmation_height = 0;
+				candidate.funding_tx_confirmed_in = None;
+				candidate.short_channel_id = None;
+			} else if funding_tx_confirmations >= minimum_depth {
+				splice_txid = candidate.funding_txo.map(|txo| txo.txid);
 			}
 		}
-		#[cfg(any(test, fuzzing))]
-		{
-			if self.context.is_outbound() {
-				let projected_commit_tx_info = self.context.next_local_commitment_tx_fee_info_cached.lock().unwrap().take();
-				*self.context.next_remote_commitment_tx_fee_info_cached.lock().unwrap() = None;
-				if let Some(info) = projected_commit_tx_info {
-					let total_pending_htlcs = self.context.pending_inbound_htlcs.len() + self.context.pending_outbound_htlcs.len()
-						+ self.context.holding_cell_htlc_updates.len();
-					if info.total_pending_htlcs == total_pending_htlcs
-						&& info.next_holder_htlc_id == self.context.next_holder_htlc_id
-						&& info.next_counterparty_htlc_id == self.context.next_counterparty_htlc_id
-						&& info.feerate == self.context.feerate_per_kw {
-							assert_eq!(commitment_stats.total_fee_sat, info.fee / 1000);
-						}
+		let splice_txid = splice_txid?;
+		splice.sent_splice_locked = Some(splice_txid);
+		Some(msgs::SpliceLocked { channel_id, splice_txid })
+	}
+
+	/// Checks whether transactions double-spending every candidate funding transaction of a
+	/// pending splice have reached [`ANTI_REORG_DELAY`] confirmations, abandoning the splice if so
+	/// as it can never lock. Returns the funding outputs and the updates closing the
+	/// [`ChannelMonitor`]s watching them.
+	///
+	/// As long as any candidate may still confirm, we keep signing commitment transactions for all
+	/// of them, so that our counterparty and we agree on the funding outputs they spend.
+	///
+	/// Should be called after [`Self::transactions_confirmed`] or [`Self::best_block_updated`].
+	pub fn check_abandon_double_spent_splice<L: Deref>(&mut self, height: u32, logger: &L)
+	-> Vec<(OutPoint, ChannelMonitorUpdate)> where L::Target: Logger {
+		let splice = match self.context.pending_splice.as_mut() {
+			Some(splice) => splice,
+			None => return Vec::new(),
+		};
+		let mut can_abandon = !splice.candidates.is_empty();
+		for candidate in splice.candidates.iter_mut() {
+			if candidate.conflicting_tx_confirmation_height != 0 && height < candidate.conflicting_tx_confirmation_height {
+				// The double-spend was reorged out, the splice transaction may yet confirm.
+				candidate.conflicting_txid = None;
+				candidate.conflicting_tx_confirmed_in = None;
+				candidate.conflicting_tx_confirmation_height = 0;
+			}
+			if candidate.conflicting_tx_confirmation_height == 0 ||
+				height + 1 < candidate.conflicting_tx_confirmation_height + ANTI_REORG_DELAY
+			{
+				can_abandon = false;
+			}
+		}
+		if !can_abandon {
+			return Vec::new();
+		}
+		let splice_monitor_updates = self.context.get_pending_splice_funding_txos().into_iter().map(|funding_txo| {
+			(funding_txo, ChannelMonitorUpdate {
+				update_id: CLOSED_CHANNEL_UPDATE_ID,
+				updates: vec![ChannelMonitorUpdateStep::ChannelForceClosed { should_broadcast: false }],
+			})
+		}).collect();
+		let splice = self.context.pending_splice.take().unwrap();
+		self.context.monitor_pending_tx_signatures = false;
+		log_info!(logger, "Abandoning splice of channel {} as its transaction was double-spent by {}",
+			log_bytes!(self.context.channel_id()), splice.candidates.last().unwrap().conflicting_txid.unwrap());
+		if self.context.channel_state & (ChannelState::Quiescent as u32) != 0 {
+			self.exit_quiescence();
+		}
+		splice_monitor_updates
+	}
+
+	/// Indicates a candidate funding transaction of a pending splice, or a transaction
+	/// double-spending one, is no longer confirmed in the main chain. As we only lock a splice
+	/// after it reaches our required depth, this is harmless unless we already did so.
+	pub fn splice_transaction_unconfirmed(&mut self, txid: &Txid) -> Result<(), ClosureReason> {
+		if let Some(splice) = self.context.pending_splice.as_mut() {
+			for candidate in splice.candidates.iter_mut() {
+				if candidate.conflicting_txid == Some(*txid) {
+					candidate.conflicting_txid = None;
+					candidate.conflicting_tx_confirmed_in = None;
+					candidate.conflicting_tx_confirmation_height = 0;
+				}
+				if candidate.funding_txo.map(|txo| txo.txid) == Some(*txid) {
+					if splice.sent_splice_locked == Some(*txid) {
+						return Err(ClosureReason::ProcessingError {
+							err: "Splice transaction was un-confirmed after we locked it".to_owned(),
+						});
+					}
+					candidate.funding_tx_confirmation_height = 0;
+					candidate.funding_tx_confirmed_in = None;
+					candidate.short_channel_id = None;
 				}
 			}
 		}
+		Ok(())
+	}
 
-		if msg.htlc_signatures.len() != commitment_stats.num_nondust_htlcs {
-			return Err(ChannelError::Close(format!("Got wrong number of HTLC signatures ({}) from remote. It must be {}", msg.htlc_signatures.len(), commitment_stats.num_nondust_htlcs)));
+	/// Handles a `splice_locked` from our counterparty.
+	pub fn splice_locked(&mut self, msg: &msgs::SpliceLocked) -> Result<(), ChannelError> {
+		if self.context.channel_state & (ChannelState::PeerDisconnected as u32) == ChannelState::PeerDisconnected as u32 {
+			return Err(ChannelError::Close("Peer sent splice_locked when we needed a channel_reestablish".to_owned()));
+		}
+		let funding_txid = self.context.get_funding_txo().map(|txo| txo.txid);
+		match self.context.pending_splice.as_mut() {
+			Some(splice) => {
+				match splice.candidate_index(&msg.splice_txid) {
+					Some(idx) if splice.candidates[idx].is_signed() => {},
+					_ => return Err(ChannelError::Close("Peer sent splice_locked for an unknown splice transaction".to_owned())),
+				}
+				if splice.received_splice_locked.map_or(false, |txid| txid != msg.splice_txid) {
+					return Err(ChannelError::Close("Peer sent splice_locked for two different splice transactions".to_owned()));
+				}
+				splice.received_splice_locked = Some(msg.splice_txid);
+				Ok(())
+			},
+			// Our counterparty may retransmit their `splice_locked` after we've promoted the splice.
+			None if funding_txid == Some(msg.splice_txid) =>
+				Err(ChannelError::Ignore("Got a splice_locked for a splice we already promoted".to_owned())),
+			None => Err(ChannelError::Ignore("Got a splice_locked without a pending splice".to_owned())),
 		}
+	}
 
-		// Up to LDK 0.0.115, HTLC information was required to be duplicated in the
-		// `htlcs_and_sigs` vec and in the `holder_commitment_tx` itself, both of which were passed
-		// in the `ChannelMonitorUpdate`. In 0.0.115, support for having a separate set of
-		// outbound-non-dust-HTLCSources in the `ChannelMonitorUpdate` was added, however for
-		// backwards compatibility, we never use it in production. To provide test coverage, here,
-		// we randomly decide (in test/fuzzing builds) to use the new vec sometimes.
-		#[allow(unused_assignments, unused_mut)]
-		let mut separate_nondust_htlc_sources = false;
-		#[cfg(all(feature = "std", any(test, fuzzing)))] {
-			use core::hash::{BuildHasher, Hasher};
-			// Get a random value using the only std API to do so - the DefaultHasher
-			let rand_val = std::collections::hash_map::RandomState::new().build_hasher().finish();
-			separate_nondust_htlc_sources = rand_val % 2 == 0;
-		}
+	/// Replaces the channel's current funding with that of the candidate funding transaction of
+	/// the pending splice once both parties have sent `splice_locked` for it. Returns the previous
+	/// funding output and short channel id if so, which the `ChannelManager` must stop tracking,
+	/// along with the funding outputs of the other candidates and the updates closing the
+	/// [`ChannelMonitor`]s watching them.
+	///
+	/// If both parties locked different candidates, the funding transactions of at least one of
+	/// them must have been reorged out, so we wait until we agree.
+	pub fn maybe_promote_splice<L: Deref>(&mut self, logger: &L)
+	-> Option<(OutPoint, Option<u64>, Vec<(OutPoint, ChannelMonitorUpdate)>)> where L::Target: Logger {
+		let candidate_idx = match &self.context.pending_splice {
+			Some(splice) if splice.sent_splice_locked.is_some() && splice.sent_splice_locked == splice.received_splice_locked =>
+				splice.candidate_index(&splice.sent_splice_locked.unwrap()).unwrap(),
+			_ => return None,
+		};
+		let balances = self.context.get_splice_funding_balances().unwrap();
+		let mut splice = self.context.pending_splice.take().unwrap();
+		let candidate = splice.candidates.remove(candidate_idx);
+		let prev_funding_txo = self.context.get_funding_txo().unwrap();
+		let prev_short_channel_id = self.context.short_channel_id;
+		let channel_value_satoshis = balances.channel_value_satoshis;
+		self.context.replace_funding_balances(balances);
+		self.context.channel_transaction_parameters.funding_outpoint = candidate.funding_txo;
+		self.context.holder_signer = candidate.holder_signer;
+		self.context.funding_tx_confirmed_in = candidate.funding_tx_confirmed_in;
+		self.context.funding_tx_confirmation_height = candidate.funding_tx_confirmation_height;
+		self.context.short_channel_id = candidate.short_channel_id;
+		self.context.funding_transaction = None;
+		#[cfg(debug_assertions)] {
+			let counterparty_value_msat = channel_value_satoshis * 1000 - self.context.value_to_self_msat;
+			*self.context.holder_max_commitment_tx_output.lock().unwrap() = (self.context.value_to_self_msat, counterparty_value_msat);
+			*self.context.counterparty_max_commitment_tx_output.lock().unwrap() = (self.context.value_to_self_msat, counterparty_value_msat);
+		}
+		// The channel must be announced again under its new short channel id.
+		self.context.announcement_sigs_state = AnnouncementSigsState::NotSent;
+		self.context.announcement_sigs = None;
+		self.context.update_time_counter += 1;
+		self.context.splice_locked_pending_ack = true;
+
+		// The other candidates can never confirm now, so their monitors are no longer needed.
+		let replaced_monitor_updates = splice.candidates.iter()
+			.filter(|candidate| candidate.monitor_created)
+			.filter_map(|candidate| candidate.funding_txo)
+			.map(|funding_txo| (funding_txo, ChannelMonitorUpdate {
+				update_id: CLOSED_CHANNEL_UPDATE_ID,
+				updates: vec![ChannelMonitorUpdateStep::ChannelForceClosed { should_broadcast: false }],
+			}))
+			.collect();
 
-		let mut nondust_htlc_sources = Vec::with_capacity(htlcs_cloned.len());
-		let mut htlcs_and_sigs = Vec::with_capacity(htlcs_cloned.len());
-		for (idx, (htlc, mut source_opt)) in htlcs_cloned.drain(..).enumerate() {
-			if let Some(_) = htlc.transaction_output_index {
-				let htlc_tx = chan_utils::build_htlc_transaction(&commitment_txid, commitment_stats.feerate_per_kw,
-					self.context.get_counterparty_selected_contest_delay().unwrap(), &htlc, &self.context.channel_type,
-					&keys.broadcaster_delayed_payment_key, &keys.revocation_key);
+		log_info!(logger, "Promoted splice of channel {} to funding outpoint {}:{}, with a new channel value of {} sats",
+			log_bytes!(self.context.channel_id()), candidate.funding_txo.unwrap().txid, candidate.funding_txo.unwrap().index,
+			channel_value_satoshis);
+		Some((prev_funding_txo, prev_short_channel_id, replaced_monitor_updates))
+	}
 
-				let htlc_redeemscript = chan_utils::get_htlc_redeemscript(&htlc, &self.context.channel_type, &keys);
-				let htlc_sighashtype = if self.context.channel_type.supports_anchors_zero_fee_htlc_tx() { EcdsaSighashType::SinglePlusAnyoneCanPay } else { EcdsaSighashType::All };
-				let htlc_sighash = hash_to_message!(&sighash::SighashCache::new(&htlc_tx).segwit_signature_hash(0, &htlc_redeemscript, htlc.amount_msat / 1000, htlc_sighashtype).unwrap()[..]);
-				log_trace!(logger, "Checking HTLC tx signature {} by key {} against tx {} (sighash {}) with redeemscript {} in channel {}.",
-					log_bytes!(msg.htlc_signatures[idx].serialize_compact()[..]), log_bytes!(keys.countersignatory_htlc_key.serialize()),
-					encode::serialize_hex(&htlc_tx), log_bytes!(htlc_sighash[..]), encode::serialize_hex(&htlc_redeemscript), log_bytes!(self.context.channel_id()));
-				if let Err(_) = self.context.secp_ctx.verify_ecdsa(&htlc_sighash, &msg.htlc_signatures[idx], &keys.countersignatory_htlc_key) {
-					return Err(ChannelError::Close("Invalid HTLC tx signature from peer".to_owned()));
-				}
-				if !separate_nondust_htlc_sources {
-					htlcs_and_sigs.push((htlc, Some(msg.htlc_signatures[idx]), source_opt.take()));
-				}
-			} else {
-				htlcs_and_sigs.push((htlc, None, source_opt.take()));
+	/// Handles a channel_ready message from our peer. If we've already sent our channel_ready
+	/// and the channel is now usable (and public), this may generate an announcement_signatures to
+	/// reply with.
+	pub fn channel_ready<NS: Deref, L: Deref>(
+		&mut self, msg: &msgs::ChannelReady, node_signer: &NS, genesis_block_hash: BlockHash,
+		user_config: &UserConfig, best_block: &BestBlock, logger: &L
+	) -> Result<Option<msgs::AnnouncementSignatures>, ChannelError>
+	where
+		NS::Target: NodeSigner,
+		L::Target: Logger
+	{
+		if self.context.channel_state & (ChannelState::PeerDisconnected as u32) == ChannelState::PeerDisconnected as u32 {
+			self.context.workaround_lnd_bug_4006 = Some(msg.clone());
+			return Err(ChannelError::Ignore("Peer sent channel_ready when we needed a channel_reestablish. The peer is likely lnd, see https://github.com/lightningnetwork/lnd/issues/4006".to_owned()));
+		}
+
+		if let Some(scid_alias) = msg.short_channel_id_alias {
+			if Some(scid_alias) != self.context.short_channel_id {
+				// The scid alias provided can be used to route payments *from* our counterparty,
+				// i.e. can be used for inbound payments and provided in invoices, but is not used
+				// when routing outbound payments.
+				self.context.latest_inbound_scid_alias = Some(scid_alias);
 			}
-			if separate_nondust_htlc_sources {
-				if let Some(source) = source_opt.take() {
-					nondust_htlc_sources.push(source);
-				}
+		}
+
+		let non_shutdown_state = self.context.channel_state & (!MULTI_STATE_FLAGS);
+
+		if non_shutdown_state == ChannelState::FundingSent as u32 {
+			self.context.channel_state |= ChannelState::TheirChannelReady as u32;
+		} else if non_shutdown_state == (ChannelState::FundingSent as u32 | ChannelState::OurChannelReady as u32) {
+			self.context.channel_state = ChannelState::ChannelReady as u32 | (self.context.channel_state & MULTI_STATE_FLAGS);
+			self.context.update_time_counter += 1;
+		} else if self.context.channel_state & (ChannelState::ChannelReady as u32) != 0 ||
+			// If we reconnected before sending our `channel_ready` they may still resend theirs:
+			(self.context.channel_state & (ChannelState::FundingSent as u32 | ChannelState::TheirChannelReady as u32) ==
+			                      (ChannelState::FundingSent as u32 | ChannelState::TheirChannelReady as u32))
+		{
+			// They probably disconnected/reconnected and re-sent the channel_ready, which is
+			// required, or they're sending a fresh SCID alias.
+			let expected_point =
+				if self.context.cur_counterparty_commitment_transaction_number == INITIAL_COMMITMENT_NUMBER - 1 {
+					// If they haven't ever sent an updated point, the point they send should match
+					// the current one.
+					self.context.counterparty_cur_commitment_point
+				} else if self.context.cur_counterparty_commitment_transaction_number == INITIAL_COMMITMENT_NUMBER - 2 {
+					// If we've advanced the commitment number once, the second commitment point is
+					// at `counterparty_prev_commitment_point`, which is not yet revoked.
+					debug_assert!(self.context.counterparty_prev_commitment_point.is_some());
+					self.context.counterparty_prev_commitment_point
+				} else {
+					// If they have sent updated points, channel_ready is always supposed to match
+					// their "first" point, which we re-derive here.
+					Some(PublicKey::from_secret_key(&self.context.secp_ctx, &SecretKey::from_slice(
+							&self.context.commitment_secrets.get_secret(INITIAL_COMMITMENT_NUMBER - 1).expect("We should have all prev secrets available")
+						).expect("We already advanced, so previous secret keys should have been validated already")))
+				};
+			if expected_point != Some(msg.next_per_commitment_point) {
+				return Err(ChannelError::Close("Peer sent a reconnect channel_ready with a different point".to_owned()));
 			}
-			debug_assert!(source_opt.is_none(), "HTLCSource should have been put somewhere");
+			return Ok(None);
+		} else {
+			return Err(ChannelError::Close("Peer sent a channel_ready at a strange time".to_owned()));
 		}
 
-		let holder_commitment_tx = HolderCommitmentTransaction::new(
-			commitment_stats.tx,
-			msg.signature,
-			msg.htlc_signatures.clone(),
-			&self.context.get_holder_pubkeys().funding_pubkey,
-			self.context.counterparty_funding_pubkey()
-		);
+		self.context.counterparty_prev_commitment_point = self.context.counterparty_cur_commitment_point;
+		self.context.counterparty_cur_commitment_point = Some(msg.next_per_commitment_point);
 
-		self.context.holder_signer.validate_holder_commitment(&holder_commitment_tx, commitment_stats.preimages)
-			.map_err(|_| ChannelError::Close("Failed to validate our commitment".to_owned()))?;
+		log_info!(logger, "Received channel_ready from peer for channel {}", log_bytes!(self.context.channel_id()));
 
-		// Update state now that we've passed all the can-fail calls...
-		let mut need_commitment = false;
-		if let &mut Some((_, ref mut update_state)) = &mut self.context.pending_update_fee {
-			if *update_state == FeeUpdateState::RemoteAnnounced {
-				*update_state = FeeUpdateState::AwaitingRemoteRevokeToAnnounce;
-				need_commitment = true;
-			}
+		Ok(self.get_announcement_sigs(node_signer, genesis_block_hash, user_config, best_block.height(), logger))
+	}
+
+	pub fn update_add_htlc<F, FE: Deref, L: Deref>(
+		&mut self, msg: &msgs::UpdateAddHTLC, mut pending_forward_status: PendingHTLCStatus,
+		create_pending_htlc_status: F, fee_estimator: &LowerBoundedFeeEstimator<FE>, logger: &L
+	) -> Result<(), ChannelError>
+	where F: for<'a> Fn(&'a Self, PendingHTLCStatus, u16) -> PendingHTLCStatus,
+		FE::Target: FeeEstimator, L::Target: Logger,
+	{
+		self.check_update_during_quiescence("update_add_htlc")?;
+		// We can't accept HTLCs sent after we've sent a shutdown.
+		let local_sent_shutdown = (self.context.channel_state & (ChannelState::ChannelReady as u32 | ChannelState::LocalShutdownSent as u32)) != (ChannelState::ChannelReady as u32);
+		if local_sent_shutdown {
+			pending_forward_status = create_pending_htlc_status(self, pending_forward_status, 0x4000|8);
+		}
+		// If the remote has sent a shutdown prior to adding this HTLC, then they are in violation of the spec.
+		let remote_sent_shutdown = (self.context.channel_state & (ChannelState::ChannelReady as u32 | ChannelState::RemoteShutdownSent as u32)) != (ChannelState::ChannelReady as u32);
+		if remote_sent_shutdown {
+			return Err(ChannelError::Close("Got add HTLC message when channel was not in an operational state".to_owned()));
+		}
+		if self.context.channel_state & (ChannelState::PeerDisconnected as u32) == ChannelState::PeerDisconnected as u32 {
+			return Err(ChannelError::Close("Peer sent update_add_htlc when we needed a channel_reestablish".to_owned()));
+		}
+		if msg.amount_msat > self.context.channel_value_satoshis * 1000 {
+			return Err(ChannelError::Close("Remote side tried to send more than the total value of the channel".to_owned()));
+		}
+		if msg.amount_msat == 0 {
+			return Err(ChannelError::Close("Remote side tried to send a 0-msat HTLC".to_owned()));
+		}
+		if msg.amount_msat < self.context.holder_htlc_minimum_msat {
+			return Err(ChannelError::Close(format!("Remote side tried to send less than our minimum HTLC value. Lower limit: ({}). Actual: ({})", self.context.holder_htlc_minimum_msat, msg.amount_msat)));
 		}
 
-		for htlc in self.context.pending_inbound_htlcs.iter_mut() {
-			let new_forward = if let &InboundHTLCState::RemoteAnnounced(ref forward_info) = &htlc.state {
-				Some(forward_info.clone())
-			} else { None };
-			if let Some(forward_info) = new_forward {
-				log_trace!(logger, "Updating HTLC {} to AwaitingRemoteRevokeToAnnounce due to commitment_signed in channel {}.",
-					log_bytes!(htlc.payment_hash.0), log_bytes!(self.context.channel_id));
-				htlc.state = InboundHTLCState::AwaitingRemoteRevokeToAnnounce(forward_info);
-				need_commitment = true;
-			}
+		let inbound_stats = self.context.get_inbound_pending_htlc_stats(None);
+		let outbound_stats = self.context.get_outbound_pending_htlc_stats(None);
+		if inbound_stats.pending_htlcs + 1 > self.context.holder_max_accepted_htlcs as u32 {
+			return Err(ChannelError::Close(format!("Remote tried to push more than our max accepted HTLCs ({})", self.context.holder_max_accepted_htlcs)));
 		}
-		let mut claimed_htlcs = Vec::new();
-		for htlc in self.context.pending_outbound_htlcs.iter_mut() {
-			if let &mut OutboundHTLCState::RemoteRemoved(ref mut outcome) = &mut htlc.state {
-				log_trace!(logger, "Updating HTLC {} to AwaitingRemoteRevokeToRemove due to commitment_signed in channel {}.",
-					log_bytes!(htlc.payment_hash.0), log_bytes!(self.context.channel_id));
-				// Grab the preimage, if it exists, instead of cloning
-				let mut reason = OutboundHTLCOutcome::Success(None);
-				mem::swap(outcome, &mut reason);
-				if let OutboundHTLCOutcome::Success(Some(preimage)) = reason {
-					// If a user (a) receives an HTLC claim using LDK 0.0.104 or before, then (b)
-					// upgrades to LDK 0.0.114 or later before the HTLC is fully resolved, we could
-					// have a `Success(None)` reason. In this case we could forget some HTLC
-					// claims, but such an upgrade is unlikely and including claimed HTLCs here
-					// fixes a bug which the user was exposed to on 0.0.104 when they started the
-					// claim anyway.
-					claimed_htlcs.push((SentHTLCId::from_source(&htlc.source), preimage));
-				}
-				htlc.state = OutboundHTLCState::AwaitingRemoteRevokeToRemove(reason);
-				need_commitment = true;
+		if inbound_stats.pending_htlcs_value_msat + msg.amount_msat > self.context.holder_max_htlc_value_in_flight_msat {
+			return Err(ChannelError::Close(format!("Remote HTLC add would put them over our max HTLC value ({})", self.context.holder_max_htlc_value_in_flight_msat)));
+		}
+		// Check holder_selected_channel_reserve_satoshis (we're getting paid, so they have to at least meet
+		// the reserve_satoshis we told them to always have as direct payment so that they lose
+		// something if we punish them for broadcasting an old state).
+		// Note that we don't really care about having a small/no to_remote output in our local
+		// commitment transactions, as the purpose of the channel reserve is to ensure we can
+		// punish *them* if they misbehave, so we discount any outbound HTLCs which will not be
+		// present in the next commitment transaction we send them (at least for fulfilled ones,
+		// failed ones won't modify value_to_self).
+		// Note that we will send HTLCs which another instance of rust-lightning would think
+		// violate the reserve value if we do not do this (as we forget inbound HTLCs from the
+		// Channel state once they will not be present in the next received commitment
+		// transaction).
+		let mut removed_outbound_total_msat = 0;
+		for ref htlc in self.context.pending_outbound_htlcs.iter() {
+			if let OutboundHTLCState::AwaitingRemoteRevokeToRemove(OutboundHTLCOutcome::Success(_)) = htlc.state {
+				removed_outbound_total_msat += htlc.amount_msat;
+			} else if let OutboundHTLCState::AwaitingRemovedRemoteRevoke(OutboundHTLCOutcome::Success(_)) = htlc.state {
+				removed_outbound_total_msat += htlc.amount_msat;
 			}
 		}
 
-		self.context.latest_monitor_update_id += 1;
-		let mut monitor_update = ChannelMonitorUpdate {
-			update_id: self.context.latest_monitor_update_id,
-			updates: vec![ChannelMonitorUpdateStep::LatestHolderCommitmentTXInfo {
-				commitment_tx: holder_commitment_tx,
-				htlc_outputs: htlcs_and_sigs,
-				claimed_htlcs,
-				nondust_htlc_sources,
-			}]
+		let max_dust_htlc_exposure_msat = self.context.get_max_dust_htlc_exposure_msat(fee_estimator);
+		let (htlc_timeout_dust_limit, htlc_success_dust_limit) = if self.context.get_channel_type().supports_anchors_zero_fee_htlc_tx() {
+			(0, 0)
+		} else {
+			let dust_buffer_feerate = self.context.get_dust_buffer_feerate(None) as u64;
+			(dust_buffer_feerate * htlc_timeout_tx_weight(self.context.get_channel_type()) / 1000,
+				dust_buffer_feerate * htlc_success_tx_weight(self.context.get_channel_type()) / 1000)
 		};
-
-		self.context.cur_holder_commitment_transaction_number -= 1;
-		// Note that if we need_commitment & !AwaitingRemoteRevoke we'll call
-		// build_commitment_no_status_check() next which will reset this to RAAFirst.
-		self.context.resend_order = RAACommitmentOrder::CommitmentFirst;
-
-		if (self.context.channel_state & ChannelState::MonitorUpdateInProgress as u32) != 0 {
-			// In case we initially failed monitor updating without requiring a response, we need
-			// to make sure the RAA gets sent first.
-			self.context.monitor_pending_revoke_and_ack = true;
-			if need_commitment && (self.context.channel_state & (ChannelState::AwaitingRemoteRevoke as u32)) == 0 {
-				// If we were going to send a commitment_signed after the RAA, go ahead and do all
-				// the corresponding HTLC status updates so that get_last_commitment_update
-				// includes the right HTLCs.
-				self.context.monitor_pending_commitment_signed = true;
-				let mut additional_update = self.build_commitment_no_status_check(logger);
-				// build_commitment_no_status_check may bump latest_monitor_id but we want them to be
-				// strictly increasing by one, so decrement it here.
-				self.context.latest_monitor_update_id = monitor_update.update_id;
-				monitor_update.updates.append(&mut additional_update.updates);
+		let exposure_dust_limit_timeout_sats = htlc_timeout_dust_limit + self.context.counterparty_dust_limit_satoshis;
+		if msg.amount_msat / 1000 < exposure_dust_limit_timeout_sats {
+			let on_counterparty_tx_dust_htlc_exposure_msat = inbound_stats.on_counterparty_tx_dust_exposure_msat + outbound_stats.on_counterparty_tx_dust_exposure_msat + msg.amount_msat;
+			if on_counterparty_tx_dust_htlc_exposure_msat > max_dust_htlc_exposure_msat {
+				log_info!(logger, "Cannot accept value that would put our exposure to dust HTLCs at {} over the limit {} on counterparty commitment tx",
+					on_counterparty_tx_dust_htlc_exposure_msat, max_dust_htlc_exposure_msat);
+				pending_forward_status = create_pending_htlc_status(self, pending_forward_status, 0x1000|7);
 			}
-			log_debug!(logger, "Received valid commitment_signed from peer in channel {}, updated HTLC state but awaiting a monitor update resolution to reply.",
-				log_bytes!(self.context.channel_id));
-			return Ok(self.push_ret_blockable_mon_update(monitor_update));
 		}
 
-		let need_commitment_signed = if need_commitment && (self.context.channel_state & (ChannelState::AwaitingRemoteRevoke as u32)) == 0 {
-			// If we're AwaitingRemoteRevoke we can't send a new commitment here, but that's ok -
-			// we'll send one right away when we get the revoke_and_ack when we
-			// free_holding_cell_htlcs().
-			let mut additional_update = self.build_commitment_no_status_check(logger);
-			// build_commitment_no_status_check may bump latest_monitor_id but we want them to be
-			// strictly increasing by one, so decrement it here.
-			self.context.latest_monitor_update_id = monitor_update.update_id;
-			monitor_update.updates.append(&mut additional_update.updates);
-			true
-		} else { false };
-
-		log_debug!(logger, "Received valid commitment_signed from peer in channel {}, updating HTLC state and responding with{} a revoke_and_ack.",
-			log_bytes!(self.context.channel_id()), if need_commitment_signed { " our own commitment_signed and" } else { "" });
-		self.monitor_updating_paused(true, need_commitment_signed, false, Vec::new(), Vec::new(), Vec::new());
-		return Ok(self.push_ret_blockable_mon_update(monitor_update));
-	}
+		let exposure_dust_limit_success_sats = htlc_success_dust_limit + self.context.holder_dust_limit_satoshis;
+		if msg.amount_msat / 1000 < exposure_dust_limit_success_sats {
+			let on_holder_tx_dust_htlc_exposure_msat = inbound_stats.on_holder_tx_dust_exposure_msat + outbound_stats.on_holder_tx_dust_exposure_msat + msg.amount_msat;
+			if on_holder_tx_dust_htlc_exposure_msat > max_dust_htlc_exposure_msat {
+				log_info!(logger, "Cannot accept value that would put our exposure to dust HTLCs at {} over the limit {} on holder commitment tx",
+					on_holder_tx_dust_htlc_exposure_msat, max_dust_htlc_exposure_msat);
+				pending_forward_status = create_pending_htlc_status(self, pending_forward_status, 0x1000|7);
+			}
+		}
 
-	/// Public version of the below, checking relevant preconditions first.
-	/// If we're not in a state where freeing the holding cell makes sense, this is a no-op and
-	/// returns `(None, Vec::new())`.
-	pub fn maybe_free_holding_cell_htlcs<F: Deref, L: Deref>(
-		&mut self, fee_estimator: &LowerBoundedFeeEstimator<F>, logger: &L
-	) -> (Option<ChannelMonitorUpdate>, Vec<(HTLCSource, PaymentHash)>)
-	where F::Target: FeeEstimator, L::Target: Logger
-	{
-		if self.context.channel_state >= ChannelState::ChannelReady as u32 &&
-		   (self.context.channel_state & (ChannelState::AwaitingRemoteRevoke as u32 | ChannelState::PeerDisconnected as u32 | ChannelState::MonitorUpdateInProgress as u32)) == 0 {
-			self.free_holding_cell_htlcs(fee_estimator, logger)
-		} else { (None, Vec::new()) }
-	}
+		// While a splice is pending, the HTLC must fit in the commitment transactions spending both
+		// the current and the new funding outputs.
+		let mut fee_spike_buffer_violated = self.context.check_remote_htlc_add_balance(&self.context.get_funding_balances(),
+			msg.amount_msat, inbound_stats.pending_htlcs_value_msat, removed_outbound_total_msat)?;
+		if let Some(splice_balances) = self.context.get_splice_funding_balances() {
+			fee_spike_buffer_violated |= self.context.check_remote_htlc_add_balance(&splice_balances,
+				msg.amount_msat, inbound_stats.pending_htlcs_value_msat, removed_outbound_total_msat)?;
+		}
+		if fee_spike_buffer_violated {
+			// Note that if the pending_forward_status is not updated here, then it's because we're already failing
+			// the HTLC, i.e. its status is already set to failing.
+			log_info!(logger, "Attempting to fail HTLC due to fee spike buffer violation in channel {}. Rebalancing is required.", log_bytes!(self.context.channel_id()));
+			pending_forward_status = create_pending_htlc_status(self, pending_forward_status, 0x1000|7);
+		}
+		if self.context.next_counterparty_htlc_id != msg.htlc_id {
+			return Err(ChannelError::Close(format!("Remote skipped HTLC ID (skipped ID: {})", self.context.next_counterparty_htlc_id)));
+		}
+		if msg.cltv_expiry >= 500000000 {
+			return Err(ChannelError::Close("Remote provided CLTV expiry in seconds instead of block height".to_owned()));
+		}
 
-	/// Frees any pending commitment updates in the holding cell, generating the relevant messages
-	/// for our counterparty.
-	fn free_holding_cell_htlcs<F: Deref, L: Deref>(
-		&mut self, fee_estimator: &LowerBoundedFeeEstimator<F>, logger: &L
-	) -> (Option<ChannelMonitorUpdate>, Vec<(HTLCSource, PaymentHash)>)
-	where F::Target: FeeEstimator, L::Target: Logger
-	{
-		assert_eq!(self.context.channel_state & ChannelState::MonitorUpdateInProgress as u32, 0);
-		if self.context.holding_cell_htlc_updates.len() != 0 || self.context.holding_cell_update_fee.is_some() {
-			log_trace!(logger, "Freeing holding cell with {} HTLC updates{} in channel {}", self.context.holding_cell_htlc_updates.len(),
-				if self.context.holding_cell_update_fee.is_some() { " and a fee update" } else { "" }, log_bytes!(self.context.channel_id()));
+		if self.context.channel_state & ChannelState::LocalShutdownSent as u32 != 0 {
+			if let PendingHTLCStatus::Forward(_) = pending_forward_status {
+				panic!("ChannelManager shouldn't be trying to add a forwardable HTLC after we've started closing");
+			}
+		}
 
-			let mut monitor_update = ChannelMonitorUpdate {
-				update_id: self.context.latest_monitor_update_id + 1, // We don't increment this yet!
-				updates: Vec::new(),
-			};
+		// Now update local state:
+		self.context.next_counterparty_htlc_id += 1;
+		self.context.pending_inbound_htlcs.push(InboundHTLCOutput {
+			htlc_id: msg.htlc_id,
+			amount_msat: msg.amount_msat,
+			payment_hash: msg.payment_hash,
+			cltv_expiry: msg.cltv_expiry,
+			state: InboundHTLCState::RemoteAnnounced(pending_forward_status),
+		});
+		Ok(())
+	}
 
-			let mut htlc_updates = Vec::new();
-			mem::swap(&mut htlc_updates, &mut self.context.holding_cell_htlc_updates);
-			let mut update_add_htlcs = Vec::with_capacity(htlc_updates.len());
-			let mut update_fulfill_htlcs = Vec::with_capacity(htlc_updates.len());
-			let mut update_fail_htlcs = Vec::with_capacity(htlc_updates.len());
-			let mut htlcs_to_fail = Vec::new();
-			for htlc_update in htlc_updates.drain(..) {
-				// Note that this *can* fail, though it should be due to rather-rare conditions on
-				// fee races with adding too many outputs which push our total payments just over
-				// the limit. In case it's less rare than I anticipate, we may want to revisit
-				// handling this case better and maybe fulfilling some of the HTLCs while attempting
-				// to rebalance channels.
-				match &htlc_update {
-					&HTLCUpdateAwaitingACK::AddHTLC {
-						amount_msat, cltv_expiry, ref payment_hash, ref source, ref onion_routing_packet,
-						skimmed_fee_msat, ..
-					} => {
-						match self.send_htlc(amount_msat, *payment_hash, cltv_expiry, source.clone(),
-							onion_routing_packet.clone(), false, skimmed_fee_msat, fee_estimator, logger)
-						{
-							Ok(update_add_msg_option) => update_add_htlcs.push(update_add_msg_option.unwrap()),
-							Err(e) => {
-								match e {
-									ChannelError::Ignore(ref msg) => {
-										log_info!(logger, "Failed to send HTLC with payment_hash {} due to {} in channel {}",
-											log_bytes!(payment_hash.0), msg, log_bytes!(self.context.channel_id()));
-										// If we fail to send here, then this HTLC should
-										// be failed backwards. Failing to send here
-										// indicates that this HTLC may keep being put back
-										// into the holding cell without ever being
-										// successfully forwarded/failed/fulfilled, causing
-										// our counterparty to eventually close on us.
-										htlcs_to_fail.push((source.clone(), *payment_hash));
-									},
-									_ => {
-										panic!("Got a non-IgnoreError action trying to send holding cell HTLC");
-									},
-								}
-							}
-						}
-					},
-					&HTLCUpdateAwaitingACK::ClaimHTLC { ref payment_preimage, htlc_id, .. } => {
-						// If an HTLC claim was previously added to the holding cell (via
-						// `get_update_fulfill_htlc`, then generating the claim message itself must
-						// not fail - any in between attempts to claim the HTLC will have resulted
-						// in it hitting the holding cell again and we cannot change the state of a
-						// holding cell HTLC from fulfill to anything else.
-						let (update_fulfill_msg_option, mut additional_monitor_update) =
-							if let UpdateFulfillFetch::NewClaim { msg, monitor_update, .. } = self.get_update_fulfill_htlc(htlc_id, *payment_preimage, logger) {
-								(msg, monitor_update)
-							} else { unreachable!() };
-						update_fulfill_htlcs.push(update_fulfill_msg_option.unwrap());
-						monitor_update.updates.append(&mut additional_monitor_update.updates);
-					},
-					&HTLCUpdateAwaitingACK::FailHTLC { htlc_id, ref err_packet } => {
-						match self.fail_htlc(htlc_id, err_packet.clone(), false, logger) {
-							Ok(update_fail_msg_option) => {
-								// If an HTLC failure was previously added to the holding cell (via
-								// `queue_fail_htlc`) then generating the fail message itself must
-								// not fail - we should never end up in a state where we double-fail
-								// an HTLC or fail-then-claim an HTLC as it indicates we didn't wait
-								// for a full revocation before failing.
-								update_fail_htlcs.push(update_fail_msg_option.unwrap())
-							},
-							Err(e) => {
-								if let ChannelError::Ignore(_) = e {}
-								else {
-									panic!("Got a non-IgnoreError action trying to fail holding cell HTLC");
-								}
-							}
+	/// Marks an outbound HTLC which we have received update_fail/fulfill/malformed
+	#[inline]
+	fn mark_outbound_htlc_removed(&mut self, htlc_id: u64, check_preimage: Option<PaymentPreimage>, fail_reason: Option<HTLCFailReason>) -> Result<&OutboundHTLCOutput, ChannelError> {
+		assert!(!(check_preimage.is_some() && fail_reason.is_some()), "cannot fail while we have a preimage");
+		for htlc in self.context.pending_outbound_htlcs.iter_mut() {
+			if htlc.htlc_id == htlc_id {
+				let outcome = match check_preimage {
+					None => fail_reason.into(),
+					Some(payment_preimage) => {
+						let payment_hash = PaymentHash(Sha256::hash(&payment_preimage.0[..]).into_inner());
+						if payment_hash != htlc.payment_hash {
+							return Err(ChannelError::Close(format!("Remote tried to fulfill HTLC ({}) with an incorrect preimage", htlc_id)));
 						}
+						OutboundHTLCOutcome::Success(Some(payment_preimage))
+					}
+				};
+				match htlc.state {
+					OutboundHTLCState::LocalAnnounced(_) =>
+						return Err(ChannelError::Close(format!("Remote tried to fulfill/fail HTLC ({}) before it had been committed", htlc_id))),
+					OutboundHTLCState::Committed => {
+						htlc.state = OutboundHTLCState::RemoteRemoved(outcome);
 					},
+					OutboundHTLCState::AwaitingRemoteRevokeToRemove(_) | OutboundHTLCState::AwaitingRemovedRemoteRevoke(_) | OutboundHTLCState::RemoteRemoved(_) =>
+						return Err(ChannelError::Close(format!("Remote tried to fulfill/fail HTLC ({}) that they'd already fulfilled/failed", htlc_id))),
 				}
+				return Ok(htlc);
 			}
-			if update_add_htlcs.is_empty() && update_fulfill_htlcs.is_empty() && update_fail_htlcs.is_empty() && self.context.holding_cell_update_fee.is_none() {
-				return (None, htlcs_to_fail);
-			}
-			let update_fee = if let Some(feerate) = self.context.holding_cell_update_fee.take() {
-				self.send_update_fee(feerate, false, fee_estimator, logger)
-			} else {
-				None
-			};
-
-			let mut additional_update = self.build_commitment_no_status_check(logger);
-			// build_commitment_no_status_check and get_update_fulfill_htlc may bump latest_monitor_id
-			// but we want them to be strictly increasing by one, so reset it here.
-			self.context.latest_monitor_update_id = monitor_update.update_id;
-			monitor_update.updates.append(&mut additional_update.updates);
-
-			log_debug!(logger, "Freeing holding cell in channel {} resulted in {}{} HTLCs added, {} HTLCs fulfilled, and {} HTLCs failed.",
-				log_bytes!(self.context.channel_id()), if update_fee.is_some() { "a fee update, " } else { "" },
-				update_add_htlcs.len(), update_fulfill_htlcs.len(), update_fail_htlcs.len());
-
-			self.monitor_updating_paused(false, true, false, Vec::new(), Vec::new(), Vec::new());
-			(self.push_ret_blockable_mon_update(monitor_update), htlcs_to_fail)
-		} else {
-			(None, Vec::new())
 		}
+		Err(ChannelError::Close("Remote tried to fulfill/fail an HTLC we couldn't find".to_owned()))
 	}
 
-	/// Handles receiving a remote's revoke_and_ack. Note that we may return a new
-	/// commitment_signed message here in case we had pending outbound HTLCs to add which were
-	/// waiting on this revoke_and_ack. The generation of this new commitment_signed may also fail,
-	/// generating an appropriate error *after* the channel state has been updated based on the
-	/// revoke_and_ack message.
-	pub fn revoke_and_ack<F: Deref, L: Deref>(&mut self, msg: &msgs::RevokeAndACK,
-		fee_estimator: &LowerBoundedFeeEstimator<F>, logger: &L
-	) -> Result<(Vec<(HTLCSource, PaymentHash)>, Option<ChannelMonitorUpdate>), ChannelError>
-	where F::Target: FeeEstimator, L::Target: Logger,
-	{
+	pub fn update_fulfill_htlc(&mut self, msg: &msgs::UpdateFulfillHTLC) -> Result<(HTLCSource, u64), ChannelError> {
 		if (self.context.channel_state & (ChannelState::ChannelReady as u32)) != (ChannelState::ChannelReady as u32) {
-			return Err(ChannelError::Close("Got revoke/ACK message when channel was not in an operational state".to_owned()));
+			return Err(ChannelError::Close("Got fulfill HTLC message when channel was not in an operational state".to_owned()));
 		}
 		if self.context.channel_state & (ChannelState::PeerDisconnected as u32) == ChannelState::PeerDisconnected as u32 {
-			return Err(ChannelError::Close("Peer sent revoke_and_ack when we needed a channel_reestablish".to_owned()));
-		}
-		if self.context.channel_state & BOTH_SIDES_SHUTDOWN_MASK == BOTH_SIDES_SHUTDOWN_MASK && self.context.last_sent_closing_fee.is_some() {
-			return Err(ChannelError::Close("Peer sent revoke_and_ack after we'd started exchanging closing_signeds".to_owned()));
+			return Err(ChannelError::Close("Peer sent update_fulfill_htlc when we needed a channel_reestablish".to_owned()));
 		}
+		self.check_update_during_quiescence("update_fulfill_htlc")?;
 
-		let secret = secp_check!(SecretKey::from_slice(&msg.per_commitment_secret), "Peer provided an invalid per_commitment_secret".to_owned());
+		self.mark_outbound_htlc_removed(msg.htlc_id, Some(msg.payment_preimage), None).map(|htlc| (htlc.source.clone(), htlc.amount_msat))
+	}
 
-		if let Some(counterparty_prev_commitment_point) = self.context.counterparty_prev_commitment_point {
-			if PublicKey::from_secret_key(&self.context.secp_ctx, &secret) != counterparty_prev_commitment_point {
-				return Err(ChannelError::Close("Got a revoke commitment secret which didn't correspond to their current pubkey".to_owned()));
-			}
+	pub fn update_fail_htlc(&mut self, msg: &msgs::UpdateFailHTLC, fail_reason: HTLCFailReason) -> Result<(), ChannelError> {
+		if (self.context.channel_state & (ChannelState::ChannelReady as u32)) != (ChannelState::ChannelReady as u32) {
+			return Err(ChannelError::Close("Got fail HTLC message when channel was not in an operational state".to_owned()));
 		}
-
-		if self.context.channel_state & ChannelState::AwaitingRemoteRevoke as u32 == 0 {
-			// Our counterparty seems to have burned their coins to us (by revoking a state when we
-			// haven't given them a new commitment transaction to broadcast). We should probably
-			// take advantage of this by updating our channel monitor, sending them an error, and
-			// waiting for them to broadcast their latest (now-revoked claim). But, that would be a
-			// lot of work, and there's some chance this is all a misunderstanding anyway.
-			// We have to do *something*, though, since our signer may get mad at us for otherwise
-			// jumping a remote commitment number, so best to just force-close and move on.
-			return Err(ChannelError::Close("Received an unexpected revoke_and_ack".to_owned()));
+		if self.context.channel_state & (ChannelState::PeerDisconnected as u32) == ChannelState::PeerDisconnected as u32 {
+			return Err(ChannelError::Close("Peer sent update_fail_htlc when we needed a channel_reestablish".to_owned()));
 		}
+		self.check_update_during_quiescence("update_fail_htlc")?;
 
-		#[cfg(any(test, fuzzing))]
-		{
-			*self.context.next_local_commitment_tx_fee_info_cached.lock().unwrap() = None;
-			*self.context.next_remote_commitment_tx_fee_info_cached.lock().unwrap() = None;
+		self.mark_outbound_htlc_removed(msg.htlc_id, None, Some(fail_reason))?;
+		Ok(())
+	}
+
+	pub fn update_fail_malformed_htlc(&mut self, msg: &msgs::UpdateFailMalformedHTLC, fail_reason: HTLCFailReason) -> Result<(), ChannelError> {
+		if (self.context.channel_state & (ChannelState::ChannelReady as u32)) != (ChannelState::ChannelReady as u32) {
+			return Err(ChannelError::Close("Got fail malformed HTLC message when channel was not in an operational state".to_owned()));
+		}
+		if self.context.channel_state & (ChannelState::PeerDisconnected as u32) == ChannelState::PeerDisconnected as u32 {
+			return Err(ChannelError::Close("Peer sent update_fail_malformed_htlc when we needed a channel_reestablish".to_owned()));
 		}
+		self.check_update_during_quiescence("update_fail_malformed_htlc")?;
 
-		self.context.holder_signer.validate_counterparty_revocation(
-			self.context.cur_counterparty_commitment_transaction_number + 1,
-			&secret
-		).map_err(|_| ChannelError::Close("Failed to validate revocation from peer".to_owned()))?;
+		self.mark_outbound_htlc_removed(msg.htlc_id, None, Some(fail_reason))?;
+		Ok(())
+	}
 
-		self.context.commitment_secrets.provide_secret(self.context.cur_counterparty_commitment_transaction_number + 1, msg.per_commitment_secret)
-			.map_err(|_| ChannelError::Close("Previous secrets did not match new one".to_owned()))?;
-		self.context.latest_monitor_update_id += 1;
-		let mut monitor_update = ChannelMonitorUpdate {
-			update_id: self.context.latest_monitor_update_id,
-			updates: vec![ChannelMonitorUpdateStep::CommitmentSecret {
-				idx: self.context.cur_counterparty_commitment_transaction_number + 1,
-				secret: msg.per_commitment_secret,
-			}],
+	pub fn commitment_signed<L: Deref>(&mut self, msg: &msgs::CommitmentSigned, logger: &L) -> Result<Option<ChannelMonitorUpdate>, ChannelError>
+		where L::Target: Logger
+	{
+		if self.context.interactive_tx_signing_session.is_some() &&
+			self.context.channel_state & (ChannelState::ChannelReady as u32) != (ChannelState::ChannelReady as u32) &&
+			self.context.cur_holder_commitment_transaction_number == INITIAL_COMMITMENT_NUMBER - 1
+		{
+			// Our counterparty may retransmit their initial commitment_signed upon reconnection if
+			// they haven't yet received our tx_signatures, which we can safely ignore.
+			log_debug!(logger, "Ignoring retransmitted initial commitment_signed for channel {}", log_bytes!(self.context.channel_id()));
+			return Ok(None);
+		}
+		if msg.batch.is_none() &&
+			self.context.pending_splice_signing_session().map_or(false, |session| session.has_received_commitment_signed()) &&
+			self.context.pending_splice_signing_session().map_or(false, |session| !session.has_received_tx_signatures())
+		{
+			// Likewise, our counterparty may retransmit their commitment_signed for a splice's new
+			// funding output.
+			log_debug!(logger, "Ignoring retransmitted splice commitment_signed for channel {}", log_bytes!(self.context.channel_id()));
+			return Ok(None);
+		}
+		if (self.context.channel_state & (ChannelState::ChannelReady as u32)) != (ChannelState::ChannelReady as u32) {
+			return Err(ChannelError::Close("Got commitment signed message when channel was not in an operational state".to_owned()));
+		}
+		if self.context.channel_state & (ChannelState::PeerDisconnected as u32) == ChannelState::PeerDisconnected as u32 {
+			return Err(ChannelError::Close("Peer sent commitment_signed when we needed a channel_reestablish".to_owned()));
+		}
+		// While a splice is pending, our counterparty sends one commitment_signed for the funding
+		// output of each candidate funding transaction we have a monitor for, in a batch along with
+		// the one for the current funding output. We only process them once the whole batch is in.
+		let splice_candidates = self.context.get_splice_candidates_with_monitor();
+		let mut splice_commitment_signed = Vec::with_capacity(splice_candidates.len());
+		let batch_msg;
+		let msg = match &msg.batch {
+			Some(batch) => {
+				if self.context.pending_commitment_signed_batch.len() + 1 < batch.batch_size as usize {
+					self.context.pending_commitment_signed_batch.push(msg.clone());
+					return Ok(None);
+				}
+				let mut batch_msgs = mem::take(&mut self.context.pending_commitment_signed_batch);
+				batch_msgs.push(msg.clone());
+				let find_batch_msg = |batch_msgs: &mut Vec<msgs::CommitmentSigned>, funding_txid: Txid| {
+					batch_msgs.iter().position(|msg| msg.batch.as_ref().map(|batch| batch.funding_txid) == Some(funding_txid))
+						.map(|idx| batch_msgs.swap_remove(idx))
+						.ok_or_else(|| ChannelError::WarnAndDisconnect(format!("Peer sent a commitment_signed batch missing funding transaction {}", funding_txid)))
+				};
+				for (_, funding_txid) in splice_candidates.iter() {
+					splice_commitment_signed.push(find_batch_msg(&mut batch_msgs, *funding_txid)?);
+				}
+				batch_msg = find_batch_msg(&mut batch_msgs, self.context.get_funding_txo().unwrap().txid)?;
+				&batch_msg
+			},
+			None if !splice_candidates.is_empty() =>
+				return Err(ChannelError::WarnAndDisconnect("Peer sent a single commitment_signed while a splice was pending".to_owned())),
+			None => msg,
 		};
+		// Any commitment_signed for our new funding output implies our splice_locked was received.
+		self.context.splice_locked_pending_ack = false;
+		if self.context.channel_state & BOTH_SIDES_SHUTDOWN_MASK == BOTH_SIDES_SHUTDOWN_MASK && self.context.last_sent_closing_fee.is_some() {
+			return Err(ChannelError::Close("Peer sent commitment_signed after we'd started exchanging closing_signeds".to_owned()));
+		}
 
-		// Update state now that we've passed all the can-fail calls...
-		// (note that we may still fail to generate the new commitment_signed message, but that's
-		// OK, we step the channel here and *then* if the new generation fails we can fail the
-		// channel based on that, but stepping stuff here should be safe either way.
-		self.context.channel_state &= !(ChannelState::AwaitingRemoteRevoke as u32);
-		self.context.sent_message_awaiting_response = None;
-		self.context.counterparty_prev_commitment_point = self.context.counterparty_cur_commitment_point;
-		self.context.counterparty_cur_commitment_point = Some(msg.next_per_commitment_point);
-		self.context.cur_counterparty_commitment_transaction_number -= 1;
+		// The first commitment transaction our counterparty signs after we agreed on a channel
+		// type upgrade uses the new channel type. If we accepted the upgrade, we only switch over
+		// to it now.
+		let channel_type_upgrade = match self.context.pending_channel_type_upgrade.take() {
+			Some(mut upgrade) if upgrade.state == ChannelTypeUpgradeState::Accepted => {
+				let holder_signer = upgrade.holder_signer.take().expect("Signer is derived when accepting an upgrade");
+				self.context.apply_channel_type_upgrade(upgrade.channel_type.clone(), holder_signer);
+				Some((upgrade.channel_type, true))
+			},
+			Some(upgrade) if upgrade.state == ChannelTypeUpgradeState::AwaitingCommitment => Some((upgrade.channel_type, false)),
+			upgrade => {
+				self.context.pending_channel_type_upgrade = upgrade;
+				None
+			},
+		};
 
-		if self.context.announcement_sigs_state == AnnouncementSigsState::Committed {
-			self.context.announcement_sigs_state = AnnouncementSigsState::PeerReceived;
-		}
+		let funding_script = self.context.get_funding_redeemscript();
 
-		log_trace!(logger, "Updating HTLCs on receipt of RAA in channel {}...", log_bytes!(self.context.channel_id()));
-		let mut to_forward_infos = Vec::new();
-		let mut revoked_htlcs = Vec::new();
-		let mut finalized_claimed_htlcs = Vec::new();
-		let mut update_fail_htlcs = Vec::new();
-		let mut update_fail_malformed_htlcs = Vec::new();
-		let mut require_commitment = false;
-		let mut value_to_self_msat_diff: i64 = 0;
+		let keys = self.context.build_holder_transaction_keys(self.context.cur_holder_commitment_transaction_number);
 
-		{
-			// Take references explicitly so that we can hold multiple references to self.context.
-			let pending_inbound_htlcs: &mut Vec<_> = &mut self.context.pending_inbound_htlcs;
-			let pending_outbound_htlcs: &mut Vec<_> = &mut self.context.pending_outbound_htlcs;
+		let commitment_stats = self.context.build_commitment_transaction(self.context.cur_holder_commitment_transaction_number, &keys, true, false, logger);
+		let commitment_txid = {
+			let trusted_tx = commitment_stats.tx.trust();
+			let bitcoin_tx = trusted_tx.built_transaction();
+			let sighash = bitcoin_tx.get_sighash_all(&funding_script, self.context.channel_value_satoshis);
 
-			// We really shouldnt have two passes here, but retain gives a non-mutable ref (Rust bug)
-			pending_inbound_htlcs.retain(|htlc| {
-				if let &InboundHTLCState::LocalRemoved(ref reason) = &htlc.state {
-					log_trace!(logger, " ...removing inbound LocalRemoved {}", log_bytes!(htlc.payment_hash.0));
-					if let &InboundHTLCRemovalReason::Fulfill(_) = reason {
-						value_to_self_msat_diff += htlc.amount_msat as i64;
-					}
-					false
-				} else { true }
-			});
-			pending_outbound_htlcs.retain(|htlc| {
-				if let &OutboundHTLCState::AwaitingRemovedRemoteRevoke(ref outcome) = &htlc.state {
-					log_trace!(logger, " ...removing outbound AwaitingRemovedRemoteRevoke {}", log_bytes!(htlc.payment_hash.0));
-					if let OutboundHTLCOutcome::Failure(reason) = outcome.clone() { // We really want take() here, but, again, non-mut ref :(
-						revoked_htlcs.push((htlc.source.clone(), htlc.payment_hash, reason));
-					} else {
-						finalized_claimed_htlcs.push(htlc.source.clone());
-						// They fulfilled, so we sent them money
-						value_to_self_msat_diff -= htlc.amount_msat as i64;
-					}
-					false
-				} else { true }
-			});
-			for htlc in pending_inbound_htlcs.iter_mut() {
-				let swap = if let &InboundHTLCState::AwaitingRemoteRevokeToAnnounce(_) = &htlc.state {
-					true
-				} else if let &InboundHTLCState::AwaitingAnnouncedRemoteRevoke(_) = &htlc.state {
-					true
-				} else { false };
-				if swap {
-					let mut state = InboundHTLCState::Committed;
-					mem::swap(&mut state, &mut htlc.state);
+			log_trace!(logger, "Checking commitment tx signature {} by key {} against tx {} (sighash {}) with redeemscript {} in channel {}",
+				log_bytes!(msg.signature.serialize_compact()[..]),
+				log_bytes!(self.context.counterparty_funding_pubkey().serialize()), encode::serialize_hex(&bitcoin_tx.transaction),
+				log_bytes!(sighash[..]), encode::serialize_hex(&funding_script), log_bytes!(self.context.channel_id()));
+			if let Err(_) = self.context.secp_ctx.verify_ecdsa(&sighash, &msg.signature, &self.context.counterparty_funding_pubkey()) {
+				return Err(ChannelError::Close("Invalid commitment tx signature from peer".to_owned()));
+			}
+			bitcoin_tx.txid
+		};
+		let mut htlcs_cloned: Vec<_> = commitment_stats.htlcs_included.iter().map(|htlc| (htlc.0.clone(), htlc.1.map(|h| h.clone()))).collect();
 
-					if let InboundHTLCState::AwaitingRemoteRevokeToAnnounce(forward_info) = state {
-						log_trace!(logger, " ...promoting inbound AwaitingRemoteRevokeToAnnounce {} to AwaitingAnnouncedRemoteRevoke", log_bytes!(htlc.payment_hash.0));
-						htlc.state = InboundHTLCState::AwaitingAnnouncedRemoteRevoke(forward_info);
-						require_commitment = true;
-					} else if let InboundHTLCState::AwaitingAnnouncedRemoteRevoke(forward_info) = state {
-						match forward_info {
-							PendingHTLCStatus::Fail(fail_msg) => {
-								log_trace!(logger, " ...promoting inbound AwaitingAnnouncedRemoteRevoke {} to LocalRemoved due to PendingHTLCStatus indicating failure", log_bytes!(htlc.payment_hash.0));
-								require_commitment = true;
-								match fail_msg {
-									HTLCFailureMsg::Relay(msg) => {
-										htlc.state = InboundHTLCState::LocalRemoved(InboundHTLCRemovalReason::FailRelay(msg.reason.clone()));
-										update_fail_htlcs.push(msg)
-									},
-									HTLCFailureMsg::Malformed(msg) => {
-										htlc.state = InboundHTLCState::LocalRemoved(InboundHTLCRemovalReason::FailMalformed((msg.sha256_of_onion, msg.failure_code)));
-										update_fail_malformed_htlcs.push(msg)
-									},
-								}
-							},
-							PendingHTLCStatus::Forward(forward_info) => {
-								log_trace!(logger, " ...promoting inbound AwaitingAnnouncedRemoteRevoke {} to Committed", log_bytes!(htlc.payment_hash.0));
-								to_forward_infos.push((forward_info, htlc.htlc_id));
-								htlc.state = InboundHTLCState::Committed;
-							}
+		// If our counterparty updated the channel fee in this commitment transaction, check that
+		// they can actually afford the new fee now.
+		let update_fee = if let Some((_, update_state)) = self.context.pending_update_fee {
+			update_state == FeeUpdateState::RemoteAnnounced
+		} else { false };
+		if update_fee {
+			debug_assert!(!self.context.is_outbound());
+			let counterparty_reserve_we_require_msat = self.context.holder_selected_channel_reserve_satoshis * 1000;
+			if commitment_stats.remote_balance_msat < commitment_stats.total_fee_sat * 1000 + counterparty_reserve_we_require_msat {
+				return Err(ChannelError::Close("Funding remote cannot afford proposed new fee".to_owned()));
+			}
+		}
+		#[cfg(any(test, fuzzing))]
+		{
+			if self.context.is_outbound() {
+				let projected_commit_tx_info = self.context.next_local_commitment_tx_fee_info_cached.lock().unwrap().take();
+				*self.context.next_remote_commitment_tx_fee_info_cached.lock().unwrap() = None;
+				if let Some(info) = projected_commit_tx_info {
+					let total_pending_htlcs = self.context.pending_inbound_htlcs.len() + self.context.pending_outbound_htlcs.len()
+						+ self.context.holding_cell_htlc_updates.len();
+					if info.total_pending_htlcs == total_pending_htlcs
+						&& info.next_holder_htlc_id == self.context.next_holder_htlc_id
+						&& info.next_counterparty_htlc_id == self.context.next_counterparty_htlc_id
+						&& info.feerate == self.context.feerate_per_kw {
+							assert_eq!(commitment_stats.total_fee_sat, info.fee / 1000);
 						}
-					}
 				}
 			}
-			for htlc in pending_outbound_htlcs.iter_mut() {
-				if let OutboundHTLCState::LocalAnnounced(_) = htlc.state {
-					log_trace!(logger, " ...promoting outbound LocalAnnounced {} to Committed", log_bytes!(htlc.payment_hash.0));
-					htlc.state = OutboundHTLCState::Committed;
+		}
+
+		if msg.htlc_signatures.len() != commitment_stats.num_nondust_htlcs {
+			return Err(ChannelError::Close(format!("Got wrong number of HTLC signatures ({}) from remote. It must be {}", msg.htlc_signatures.len(), commitment_stats.num_nondust_htlcs)));
+		}
+
+		// Up to LDK 0.0.115, HTLC information was required to be duplicated in the
+		// `htlcs_and_sigs` vec and in the `holder_commitment_tx` itself, both of which were passed
+		// in the `ChannelMonitorUpdate`. In 0.0.115, support for having a separate set of
+		// outbound-non-dust-HTLCSources in the `ChannelMonitorUpdate` was added, however for
+		// backwards compatibility, we never use it in production. To provide test coverage, here,
+		// we randomly decide (in test/fuzzing builds) to use the new vec sometimes.
+		#[allow(unused_assignments, unused_mut)]
+		let mut separate_nondust_htlc_sources = false;
+		#[cfg(all(feature = "std", any(test, fuzzing)))] {
+			use core::hash::{BuildHasher, Hasher};
+			// Get a random value using the only std API to do so - the DefaultHasher
+			let rand_val = std::collections::hash_map::RandomState::new().build_hasher().finish();
+			separate_nondust_htlc_sources = rand_val % 2 == 0;
+		}
+
+		let mut nondust_htlc_sources = Vec::with_capacity(htlcs_cloned.len());
+		let mut htlcs_and_sigs = Vec::with_capacity(htlcs_cloned.len());
+		for (idx, (htlc, mut source_opt)) in htlcs_cloned.drain(..).enumerate() {
+			if let Some(_) = htlc.transaction_output_index {
+				let htlc_tx = chan_utils::build_htlc_transaction(&commitment_txid, commitment_stats.feerate_per_kw,
+					self.context.get_counterparty_selected_contest_delay().unwrap(), &htlc, &self.context.channel_type,
+					&keys.broadcaster_delayed_payment_key, &keys.revocation_key);
+
+				let htlc_redeemscript = chan_utils::get_htlc_redeemscript(&htlc, &self.context.channel_type, &keys);
+				let htlc_sighashtype = if self.context.channel_type.supports_anchors_zero_fee_htlc_tx() { EcdsaSighashType::SinglePlusAnyoneCanPay } else { EcdsaSighashType::All };
+				let htlc_sighash = hash_to_message!(&sighash::SighashCache::new(&htlc_tx).segwit_signature_hash(0, &htlc_redeemscript, htlc.amount_msat / 1000, htlc_sighashtype).unwrap()[..]);
+				log_trace!(logger, "Checking HTLC tx signature {} by key {} against tx {} (sighash {}) with redeemscript {} in channel {}.",
+					log_bytes!(msg.htlc_signatures[idx].serialize_compact()[..]), log_bytes!(keys.countersignatory_htlc_key.serialize()),
+					encode::serialize_hex(&htlc_tx), log_bytes!(htlc_sighash[..]), encode::serialize_hex(&htlc_redeemscript), log_bytes!(self.context.channel_id()));
+				if let Err(_) = self.context.secp_ctx.verify_ecdsa(&htlc_sighash, &msg.htlc_signatures[idx], &keys.countersignatory_htlc_key) {
+					return Err(ChannelError::Close("Invalid HTLC tx signature from peer".to_owned()));
 				}
-				if let &mut OutboundHTLCState::AwaitingRemoteRevokeToRemove(ref mut outcome) = &mut htlc.state {
-					log_trace!(logger, " ...promoting outbound AwaitingRemoteRevokeToRemove {} to AwaitingRemovedRemoteRevoke", log_bytes!(htlc.payment_hash.0));
-					// Grab the preimage, if it exists, instead of cloning
-					let mut reason = OutboundHTLCOutcome::Success(None);
-					mem::swap(outcome, &mut reason);
-					htlc.state = OutboundHTLCState::AwaitingRemovedRemoteRevoke(reason);
-					require_commitment = true;
+				if !separate_nondust_htlc_sources {
+					htlcs_and_sigs.push((htlc, Some(msg.htlc_signatures[idx]), source_opt.take()));
+				}
+			} else {
+				htlcs_and_sigs.push((htlc, None, source_opt.take()));
+			}
+			if separate_nondust_htlc_sources {
+				if let Some(source) = source_opt.take() {
+					nondust_htlc_sources.push(source);
 				}
 			}
+			debug_assert!(source_opt.is_none(), "HTLCSource should have been put somewhere");
 		}
-		self.context.value_to_self_msat = (self.context.value_to_self_msat as i64 + value_to_self_msat_diff) as u64;
 
-		if let Some((feerate, update_state)) = self.context.pending_update_fee {
-			match update_state {
-				FeeUpdateState::Outbound => {
-					debug_assert!(self.context.is_outbound());
-					log_trace!(logger, " ...promoting outbound fee update {} to Committed", feerate);
-					self.context.feerate_per_kw = feerate;
-					self.context.pending_update_fee = None;
-				},
-				FeeUpdateState::RemoteAnnounced => { debug_assert!(!self.context.is_outbound()); },
-				FeeUpdateState::AwaitingRemoteRevokeToAnnounce => {
-					debug_assert!(!self.context.is_outbound());
-					log_trace!(logger, " ...promoting inbound AwaitingRemoteRevokeToAnnounce fee update {} to Committed", feerate);
-					require_commitment = true;
-					self.context.feerate_per_kw = feerate;
-					self.context.pending_update_fee = None;
-				},
+		let holder_commitment_tx = HolderCommitmentTransaction::new(
+			commitment_stats.tx,
+			msg.signature,
+			msg.htlc_signatures.clone(),
+			&self.context.get_holder_pubkeys().funding_pubkey,
+			self.context.counterparty_funding_pubkey()
+		);
+
+		self.context.holder_signer.validate_holder_commitment(&holder_commitment_tx, commitment_stats.preimages)
+			.map_err(|_| ChannelError::Close("Failed to validate our commitment".to_owned()))?;
+
+		let mut splice_commitment_txs = Vec::with_capacity(splice_candidates.len());
+		for ((candidate_idx, _), splice_msg) in splice_candidates.iter().zip(splice_commitment_signed.iter()) {
+			let (splice_commitment_tx, _) = self.context.validate_splice_holder_commitment_signed(*candidate_idx,
+				self.context.cur_holder_commitment_transaction_number, splice_msg, update_fee, logger)?;
+			splice_commitment_txs.push(splice_commitment_tx);
+		}
+
+		// Update state now that we've passed all the can-fail calls...
+		let mut need_commitment = false;
+		if let &mut Some((_, ref mut update_state)) = &mut self.context.pending_update_fee {
+			if *update_state == FeeUpdateState::RemoteAnnounced {
+				*update_state = FeeUpdateState::AwaitingRemoteRevokeToAnnounce;
+				need_commitment = true;
 			}
 		}
 
-		if (self.context.channel_state & ChannelState::MonitorUpdateInProgress as u32) == ChannelState::MonitorUpdateInProgress as u32 {
-			// We can't actually generate a new commitment transaction (incl by freeing holding
-			// cells) while we can't update the monitor, so we just return what we have.
-			if require_commitment {
-				self.context.monitor_pending_commitment_signed = true;
-				// When the monitor updating is restored we'll call get_last_commitment_update(),
-				// which does not update state, but we're definitely now awaiting a remote revoke
-				// before we can step forward any more, so set it here.
+		for htlc in self.context.pending_inbound_htlcs.iter_mut() {
+			let new_forward = if let &InboundHTLCState::RemoteAnnounced(ref forward_info) = &htlc.state {
+				Some(forward_info.clone())
+			} else { None };
+			if let Some(forward_info) = new_forward {
+				log_trace!(logger, "Updating HTLC {} to AwaitingRemoteRevokeToAnnounce due to commitment_signed in channel {}.",
+					log_bytes!(htlc.payment_hash.0), log_bytes!(self.context.channel_id));
+				htlc.state = InboundHTLCState::AwaitingRemoteRevokeToAnnounce(forward_info);
+				need_commitment = true;
+			}
+		}
+		let mut claimed_htlcs = Vec::new();
+		for htlc in self.context.pending_outbound_htlcs.iter_mut() {
+			if let &mut OutboundHTLCState::RemoteRemoved(ref mut outcome) = &mut htlc.state {
+				log_trace!(logger, "Updating HTLC {} to AwaitingRemoteRevokeToRemove due to commitment_signed in channel {}.",
+					log_bytes!(htlc.payment_hash.0), log_bytes!(self.context.channel_id));
+				// Grab the preimage, if it exists, instead of cloning
+				let mut reason = OutboundHTLCOutcome::Success(None);
+				mem::swap(outcome, &mut reason);
+				if let OutboundHTLCOutcome::Success(Some(preimage)) = reason {
+					// If a user (a) receives an HTLC claim using LDK 0.0.104 or before, then (b)
+					// upgrades to LDK 0.0.114 or later before the HTLC is fully resolved, we could
+					// have a `Success(None)` reason. In this case we could forget some HTLC
+					// claims, but such an upgrade is unlikely and including claimed HTLCs here
+					// fixes a bug which the user was exposed to on 0.0.104 when they started the
+					// claim anyway.
+					claimed_htlcs.push((SentHTLCId::from_source(&htlc.source), preimage));
+				}
+				htlc.state = OutboundHTLCState::AwaitingRemoteRevokeToRemove(reason);
+				need_commitment = true;
+			}
+		}
+
+		self.context.latest_monitor_update_id += 1;
+		let mut monitor_update = ChannelMonitorUpdate {
+			update_id: self.context.latest_monitor_update_id,
+			updates: vec![ChannelMonitorUpdateStep::LatestHolderCommitmentTXInfo {
+				commitment_tx: holder_commitment_tx,
+				htlc_outputs: htlcs_and_sigs,
+				claimed_htlcs,
+				nondust_htlc_sources,
+				splice_commitment_txs,
+			}]
+		};
+
+		if let Some((channel_type, accepted_upgrade)) = channel_type_upgrade {
+			log_info!(logger, "Upgraded channel {} to channel type {}", log_bytes!(self.context.channel_id()), channel_type);
+			monitor_update.updates.insert(0, ChannelMonitorUpdateStep::ChannelTypeUpgrade {
+				channel_type_features: channel_type,
+			});
+			// Our counterparty is still waiting on a commitment transaction using the new channel
+			// type from us.
+			need_commitment |= accepted_upgrade;
+			self.exit_quiescence();
+		}
+
+		self.context.cur_holder_commitment_transaction_number -= 1;
+		// Note that if we need_commitment & !AwaitingRemoteRevoke we'll call
+		// build_commitment_no_status_check() next which will reset this to RAAFirst.
+		self.context.resend_order = RAACommitmentOrder::CommitmentFirst;
+
+		if (self.context.channel_state & ChannelState::MonitorUpdateInProgress as u32) != 0 {
+			// In case we initially failed monitor updating without requiring a response, we need
+			// to make sure the RAA gets sent first.
+			self.context.monitor_pending_revoke_and_ack = true;
+			if need_commitment && (self.context.channel_state & (ChannelState::AwaitingRemoteRevoke as u32)) == 0 {
+				// If we were going to send a commitment_signed after the RAA, go ahead and do all
+				// the corresponding HTLC status updates so that get_last_commitment_update
+				// includes the right HTLCs.
+				self.context.monitor_pending_commitment_signed = true;
 				let mut additional_update = self.build_commitment_no_status_check(logger);
 				// build_commitment_no_status_check may bump latest_monitor_id but we want them to be
 				// strictly increasing by one, so decrement it here.
 				self.context.latest_monitor_update_id = monitor_update.update_id;
 				monitor_update.updates.append(&mut additional_update.updates);
 			}
-			self.context.monitor_pending_forwards.append(&mut to_forward_infos);
-			self.context.monitor_pending_failures.append(&mut revoked_htlcs);
-			self.context.monitor_pending_finalized_fulfills.append(&mut finalized_claimed_htlcs);
-			log_debug!(logger, "Received a valid revoke_and_ack for channel {} but awaiting a monitor update resolution to reply.", log_bytes!(self.context.channel_id()));
-			return Ok((Vec::new(), self.push_ret_blockable_mon_update(monitor_update)));
+			log_debug!(logger, "Received valid commitment_signed from peer in channel {}, updated HTLC state but awaiting a monitor update resolution to reply.",
+				log_bytes!(self.context.channel_id));
+			return Ok(self.push_ret_blockable_mon_update(monitor_update));
 		}
 
-		match self.free_holding_cell_htlcs(fee_estimator, logger) {
-			(Some(mut additional_update), htlcs_to_fail) => {
-				// free_holding_cell_htlcs may bump latest_monitor_id multiple times but we want them to be
-				// strictly increasing by one, so decrement it here.
-				self.context.latest_monitor_update_id = monitor_update.update_id;
-				monitor_update.updates.append(&mut additional_update.updates);
-
-				self.monitor_updating_paused(false, true, false, to_forward_infos, revoked_htlcs, finalized_claimed_htlcs);
-				Ok((htlcs_to_fail, self.push_ret_blockable_mon_update(monitor_update)))
-			},
-			(None, htlcs_to_fail) => {
-				if require_commitment {
-					let mut additional_update = self.build_commitment_no_status_check(logger);
-
-					// build_commitment_no_status_check may bump latest_monitor_id but we want them to be
-					// strictly increasing by one, so decrement it here.
-					self.context.latest_monitor_update_id = monitor_update.update_id;
-					monitor_update.updates.append(&mut additional_update.updates);
+		let need_commitment_signed = if need_commitment && (self.context.channel_state & (ChannelState::AwaitingRemoteRevoke as u32)) == 0 {
+			// If we're AwaitingRemoteRevoke we can't send a new commitment here, but that's ok -
+			// we'll send one right away when we get the revoke_and_ack when we
+			// free_holding_cell_htlcs().
+			let mut additional_update = self.build_commitment_no_status_check(logger);
+			// build_commitment_no_status_check may bump latest_monitor_id but we want them to be
+			// strictly increasing by one, so decrement it here.
+			self.context.latest_monitor_update_id = monitor_update.update_id;
+			monitor_update.updates.append(&mut additional_update.updates);
+			true
+		} else { false };
 
-					log_debug!(logger, "Received a valid revoke_and_ack for channel {}. Responding with a commitment update with {} HTLCs failed.",
-						log_bytes!(self.context.channel_id()), update_fail_htlcs.len() + update_fail_malformed_htlcs.len());
-					self.monitor_updating_paused(false, true, false, to_forward_infos, revoked_htlcs, finalized_claimed_htlcs);
-					Ok((htlcs_to_fail, self.push_ret_blockable_mon_update(monitor_update)))
-				} else {
-					log_debug!(logger, "Received a valid revoke_and_ack for channel {} with no reply necessary.", log_bytes!(self.context.channel_id()));
-					self.monitor_updating_paused(false, false, false, to_forward_infos, revoked_htlcs, finalized_claimed_htlcs);
-					Ok((htlcs_to_fail, self.push_ret_blockable_mon_update(monitor_update)))
-				}
-			}
-		}
+		log_debug!(logger, "Received valid commitment_signed from peer in channel {}, updating HTLC state and responding with{} a revoke_and_ack.",
+			log_bytes!(self.context.channel_id()), if need_commitment_signed { " our own commitment_signed and" } else { "" });
+		self.monitor_updating_paused(true, need_commitment_signed, false, Vec::new(), Vec::new(), Vec::new());
+		return Ok(self.push_ret_blockable_mon_update(monitor_update));
 	}
 
-	/// Queues up an outbound update fee by placing it in the holding cell. You should call
-	/// [`Self::maybe_free_holding_cell_htlcs`] in order to actually generate and send the
-	/// commitment update.
-	pub fn queue_update_fee<F: Deref, L: Deref>(&mut self, feerate_per_kw: u32,
-		fee_estimator: &LowerBoundedFeeEstimator<F>, logger: &L)
+	/// Public version of the below, checking relevant preconditions first.
+	/// If we're not in a state where freeing the holding cell makes sense, this is a no-op and
+	/// returns `(None, Vec::new())`.
+	pub fn maybe_free_holding_cell_htlcs<F: Deref, L: Deref>(
+		&mut self, fee_estimator: &LowerBoundedFeeEstimator<F>, logger: &L
+	) -> (Option<ChannelMonitorUpdate>, Vec<(HTLCSource, PaymentHash)>)
 	where F::Target: FeeEstimator, L::Target: Logger
 	{
-		let msg_opt = self.send_update_fee(feerate_per_kw, true, fee_estimator, logger);
-		assert!(msg_opt.is_none(), "We forced holding cell?");
+		if self.context.channel_state >= ChannelState::ChannelReady as u32 &&
+		   (self.context.channel_state & (ChannelState::AwaitingRemoteRevoke as u32 | ChannelState::PeerDisconnected as u32 | ChannelState::MonitorUpdateInProgress as u32 | QUIESCENCE_STATE_FLAGS)) == 0 {
+			self.free_holding_cell_htlcs(fee_estimator, logger)
+		} else { (None, Vec::new()) }
 	}
 
-	/// Adds a pending update to this channel. See the doc for send_htlc for
-	/// further details on the optionness of the return value.
-	/// If our balance is too low to cover the cost of the next commitment transaction at the
-	/// new feerate, the update is cancelled.
-	///
-	/// You MUST call [`Self::send_commitment_no_state_update`] prior to any other calls on this
-	/// [`Channel`] if `force_holding_cell` is false.
-	fn send_update_fee<F: Deref, L: Deref>(
-		&mut self, feerate_per_kw: u32, mut force_holding_cell: bool,
-		fee_estimator: &LowerBoundedFeeEstimator<F>, logger: &L
-	) -> Option<msgs::UpdateFee>
+	/// Frees any pending commitment updates in the holding cell, generating the relevant messages
+	/// for our counterparty.
+	fn free_holding_cell_htlcs<F: Deref, L: Deref>(
+		&mut self, fee_estimator: &LowerBoundedFeeEstimator<F>, logger: &L
+	) -> (Option<ChannelMonitorUpdate>, Vec<(HTLCSource, PaymentHash)>)
 	where F::Target: FeeEstimator, L::Target: Logger
 	{
-		if !self.context.is_outbound() {
-			panic!("Cannot send fee from inbound channel");
-		}
-		if !self.context.is_usable() {
-			panic!("Cannot update fee until channel is fully established and we haven't started shutting down");
-		}
-		if !self.context.is_live() {
-			panic!("Cannot update fee while peer is disconnected/we're awaiting a monitor update (ChannelManager should have caught this)");
-		}
-
-		// Before proposing a feerate update, check that we can actually afford the new fee.
-		let inbound_stats = self.context.get_inbound_pending_htlc_stats(Some(feerate_per_kw));
-		let outbound_stats = self.context.get_outbound_pending_htlc_stats(Some(feerate_per_kw));
-		let keys = self.context.build_holder_transaction_keys(self.context.cur_holder_commitment_transaction_number);
-		let commitment_stats = self.context.build_commitment_transaction(self.context.cur_holder_commitment_transaction_number, &keys, true, true, logger);
-		let buffer_fee_msat = commit_tx_fee_sat(feerate_per_kw, commitment_stats.num_nondust_htlcs + outbound_stats.on_holder_tx_holding_cell_htlcs_count as usize + CONCURRENT_INBOUND_HTLC_FEE_BUFFER as usize, self.context.get_channel_type()) * 1000;
-		let holder_balance_msat = commitment_stats.local_balance_msat - outbound_stats.holding_cell_msat;
-		if holder_balance_msat < buffer_fee_msat  + self.context.counterparty_selected_channel_reserve_satoshis.unwrap() * 1000 {
-			//TODO: auto-close after a number of failures?
-			log_debug!(logger, "Cannot afford to send new feerate at {}", feerate_per_kw);
-			return None;
-		}
-
-		// Note, we evaluate pending htlc "preemptive" trimmed-to-dust threshold at the proposed `feerate_per_kw`.
-		let holder_tx_dust_exposure = inbound_stats.on_holder_tx_dust_exposure_msat + outbound_stats.on_holder_tx_dust_exposure_msat;
-		let counterparty_tx_dust_exposure = inbound_stats.on_counterparty_tx_dust_exposure_msat + outbound_stats.on_counterparty_tx_dust_exposure_msat;
-		let max_dust_htlc_exposure_msat = self.context.get_max_dust_htlc_exposure_msat(fee_estimator);
-		if holder_tx_dust_exposure > max_dust_htlc_exposure_msat {
-			log_debug!(logger, "Cannot afford to send new feerate at {} without infringing max dust htlc exposure", feerate_per_kw);
-			return None;
-		}
-		if counterparty_tx_dust_exposure > max_dust_htlc_exposure_msat {
-			log_debug!(logger, "Cannot afford to send new feerate at {} without infringing max dust htlc exposure", feerate_per_kw);
-			return None;
-		}
-
-		if (self.context.channel_state & (ChannelState::AwaitingRemoteRevoke as u32 | ChannelState::MonitorUpdateInProgress as u32)) != 0 {
-			force_holding_cell = true;
-		}
-
-		if force_holding_cell {
-			self.context.holding_cell_update_fee = Some(feerate_per_kw);
-			return None;
-		}
-
-		debug_assert!(self.context.pending_update_fee.is_none());
-		self.context.pending_update_fee = Some((feerate_per_kw, FeeUpdateState::Outbound));
-
-		Some(msgs::UpdateFee {
-			channel_id: self.context.channel_id,
-			feerate_per_kw,
-		})
-	}
-
-	/// Removes any uncommitted inbound HTLCs and resets the state of uncommitted outbound HTLC
-	/// updates, to be used on peer disconnection. After this, update_*_htlc messages need to be
-	/// resent.
-	/// No further message handling calls may be made until a channel_reestablish dance has
-	/// completed.
-	pub fn remove_uncommitted_htlcs_and_mark_paused<L: Deref>(&mut self, logger: &L)  where L::Target: Logger {
-		assert_eq!(self.context.channel_state & ChannelState::ShutdownComplete as u32, 0);
-		if self.context.channel_state < ChannelState::FundingSent as u32 {
-			self.context.channel_state = ChannelState::ShutdownComplete as u32;
-			return;
-		}
-
-		if self.context.channel_state & (ChannelState::PeerDisconnected as u32) == (ChannelState::PeerDisconnected as u32) {
-			// While the below code should be idempotent, it's simpler to just return early, as
-			// redundant disconnect events can fire, though they should be rare.
-			return;
-		}
-
-		if self.context.announcement_sigs_state == AnnouncementSigsState::MessageSent || self.context.announcement_sigs_state == AnnouncementSigsState::Committed {
-			self.context.announcement_sigs_state = AnnouncementSigsState::NotSent;
+		assert_eq!(self.context.channel_state & ChannelState::MonitorUpdateInProgress as u32, 0);
+		if self.context.channel_state & QUIESCENCE_STATE_FLAGS != 0 {
+			// The holding cell will be freed once the channel is no longer quiescent.
+			return (None, Vec::new());
 		}
+		if self.context.holding_cell_htlc_updates.len() != 0 || self.context.holding_cell_update_fee.is_some() {
+			log_trace!(logger, "Freeing holding cell with {} HTLC updates{} in channel {}", self.context.holding_cell_htlc_updates.len(),
+				if self.context.holding_cell_update_fee.is_some() { " and a fee update" } else { "" }, log_bytes!(self.context.channel_id()));
 
-		// Upon reconnect we have to start the closing_signed dance over, but shutdown messages
-		// will be retransmitted.
-		self.context.last_sent_closing_fee = None;
-		self.context.pending_counterparty_closing_signed = None;
-		self.context.closing_fee_limits = None;
+			let mut monitor_update = ChannelMonitorUpdate {
+				update_id: self.context.latest_monitor_update_id + 1, // We don't increment this yet!
+				updates: Vec::new(),
+			};
 
-		let mut inbound_drop_count = 0;
-		self.context.pending_inbound_htlcs.retain(|htlc| {
-			match htlc.state {
-				InboundHTLCState::RemoteAnnounced(_) => {
-					// They sent us an update_add_htlc but we never got the commitment_signed.
-					// We'll tell them what commitment_signed we're expecting next and they'll drop
-					// this HTLC accordingly
-					inbound_drop_count += 1;
-					false
-				},
-				InboundHTLCState::AwaitingRemoteRevokeToAnnounce(_)|InboundHTLCState::AwaitingAnnouncedRemoteRevoke(_) => {
-					// We received a commitment_signed updating this HTLC and (at least hopefully)
-					// sent a revoke_and_ack (which we can re-transmit) and have heard nothing
-					// in response to it yet, so don't touch it.
-					true
-				},
-				InboundHTLCState::Committed => true,
-				InboundHTLCState::LocalRemoved(_) => {
-					// We (hopefully) sent a commitment_signed updating this HTLC (which we can
-					// re-transmit if needed) and they may have even sent a revoke_and_ack back
-					// (that we missed). Keep this around for now and if they tell us they missed
-					// the commitment_signed we can re-transmit the update then.
-					true
-				},
-			}
-		});
-		self.context.next_counterparty_htlc_id -= inbound_drop_count;
-
-		if let Some((_, update_state)) = self.context.pending_update_fee {
-			if update_state == FeeUpdateState::RemoteAnnounced {
-				debug_assert!(!self.context.is_outbound());
-				self.context.pending_update_fee = None;
+			let mut htlc_updates = Vec::new();
+			mem::swap(&mut htlc_updates, &mut self.context.holding_cell_htlc_updates);
+			let mut update_add_htlcs = Vec::with_capacity(htlc_updates.len());
+			let mut update_fulfill_htlcs = Vec::with_capacity(htlc_updates.len());
+			let mut update_fail_htlcs = Vec::with_capacity(htlc_updates.len());
+			let mut update_fail_malformed_htlcs = Vec::with_capacity(htlc_updates.len());
+			let mut htlcs_to_fail = Vec::new();
+			for htlc_update in htlc_updates.drain(..) {
+				// Note that this *can* fail, though it should be due to rather-rare conditions on
+				// fee races with adding too many outputs which push our total payments just over
+				// the limit. In case it's less rare than I anticipate, we may want to revisit
+				// handling this case better and maybe fulfilling some of the HTLCs while attempting
+				// to rebalance channels.
+				match &htlc_update {
+					&HTLCUpdateAwaitingACK::AddHTLC {
+						amount_msat, cltv_expiry, ref payment_hash, ref source, ref onion_routing_packet,
+						skimmed_fee_msat, blinding_point, ..
+					} => {
+						match self.send_htlc(amount_msat, *payment_hash, cltv_expiry, source.clone(),
+							onion_routing_packet.clone(), false, skimmed_fee_msat, blinding_point, fee_estimator,
+							logger)
+						{
+							Ok(update_add_msg_option) => update_add_htlcs.push(update_add_msg_option.unwrap()),
+							Err(e) => {
+								match e {
+									ChannelError::Ignore(ref msg) => {
+										log_info!(logger, "Failed to send HTLC with payment_hash {} due to {} in channel {}",
+											log_bytes!(payment_hash.0), msg, log_bytes!(self.context.channel_id()));
+										// If we fail to send here, then this HTLC should
+										// be failed backwards. Failing to send here
+										// indicates that this HTLC may keep being put back
+										// into the holding cell without ever being
+										// successfully forwarded/failed/fulfilled, causing
+										// our counterparty to eventually close on us.
+										htlcs_to_fail.push((source.clone(), *payment_hash));
+									},
+									_ => {
+										panic!("Got a non-IgnoreError action trying to send holding cell HTLC");
+									},
+								}
+							}
+						}
+					},
+					&HTLCUpdateAwaitingACK::ClaimHTLC { ref payment_preimage, htlc_id, .. } => {
+						// If an HTLC claim was previously added to the holding cell (via
+						// `get_update_fulfill_htlc`, then generating the claim message itself must
+						// not fail - any in between attempts to claim the HTLC will have resulted
+						// in it hitting the holding cell again and we cannot change the state of a
+						// holding cell HTLC from fulfill to anything else.
+						let (update_fulfill_msg_option, mut additional_monitor_update) =
+							if let UpdateFulfillFetch::NewClaim { msg, monitor_update, .. } = self.get_update_fulfill_htlc(htlc_id, *payment_preimage, logger) {
+								(msg, monitor_update)
+							} else { unreachable!() };
+						update_fulfill_htlcs.push(update_fulfill_msg_option.unwrap());
+						monitor_update.updates.append(&mut additional_monitor_update.updates);
+					},
+					&HTLCUpdateAwaitingACK::FailHTLC { htlc_id, ref err_packet } => {
+						match self.fail_htlc(htlc_id, err_packet.clone(), false, logger) {
+							Ok(update_fail_msg_option) => {
+								// If an HTLC failure was previously added to the holding cell (via
+								// `queue_fail_htlc`) then generating the fail message itself must
+								// not fail - we should never end up in a state where we double-fail
+								// an HTLC or fail-then-claim an HTLC as it indicates we didn't wait
+								// for a full revocation before failing.
+								update_fail_htlcs.push(update_fail_msg_option.unwrap())
+							},
+							Err(e) => {
+								if let ChannelError::Ignore(_) = e {}
+								else {
+									panic!("Got a non-IgnoreError action trying to fail holding cell HTLC");
+								}
+							}
+						}
+					},
+					&HTLCUpdateAwaitingACK::FailMalformedHTLC { htlc_id, failure_code, sha256_of_onion } => {
+						match self.fail_htlc(htlc_id, (sha256_of_onion, failure_code), false, logger) {
+							// As above, generating the fail message from the holding cell must not fail.
+							Ok(update_fail_malformed_opt) => update_fail_malformed_htlcs.push(update_fail_malformed_opt.unwrap()),
+							Err(e) => {
+								if let ChannelError::Ignore(_) = e {}
+								else {
+									panic!("Got a non-IgnoreError action trying to fail holding cell HTLC");
+								}
+							}
+						}
+					},
+				}
 			}
-		}
-
-		for htlc in self.context.pending_outbound_htlcs.iter_mut() {
-			if let OutboundHTLCState::RemoteRemoved(_) = htlc.state {
-				// They sent us an update to remove this but haven't yet sent the corresponding
-				// commitment_signed, we need to move it back to Committed and they can re-send
-				// the update upon reconnection.
-				htlc.state = OutboundHTLCState::Committed;
+			if update_add_htlcs.is_empty() && update_fulfill_htlcs.is_empty() && update_fail_htlcs.is_empty() &&
+				update_fail_malformed_htlcs.is_empty() && self.context.holding_cell_update_fee.is_none()
+			{
+				return (None, htlcs_to_fail);
 			}
-		}
+			let update_fee = if let Some(feerate) = self.context.holding_cell_update_fee.take() {
+				self.send_update_fee(feerate, false, fee_estimator, logger)
+			} else {
+				None
+			};
 
-		self.context.sent_message_awaiting_response = None;
+			let mut additional_update = self.build_commitment_no_status_check(logger);
+			// build_commitment_no_status_check and get_update_fulfill_htlc may bump latest_monitor_id
+			// but we want them to be strictly increasing by one, so reset it here.
+			self.context.latest_monitor_update_id = monitor_update.update_id;
+			monitor_update.updates.append(&mut additional_update.updates);
 
-		self.context.channel_state |= ChannelState::PeerDisconnected as u32;
-		log_trace!(logger, "Peer disconnection resulted in {} remote-announced HTLC drops on channel {}", inbound_drop_count, log_bytes!(self.context.channel_id()));
-	}
+			log_debug!(logger, "Freeing holding cell in channel {} resulted in {}{} HTLCs added, {} HTLCs fulfilled, and {} HTLCs failed.",
+				log_bytes!(self.context.channel_id()), if update_fee.is_some() { "a fee update, " } else { "" },
+				update_add_htlcs.len(), update_fulfill_htlcs.len(), update_fail_htlcs.len() + update_fail_malformed_htlcs.len());
 
-	/// Indicates that a ChannelMonitor update is in progress and has not yet been fully persisted.
-	/// This must be called before we return the [`ChannelMonitorUpdate`] back to the
-	/// [`ChannelManager`], which will call [`Self::monitor_updating_restored`] once the monitor
-	/// update completes (potentially immediately).
-	/// The messages which were generated with the monitor update must *not* have been sent to the
-	/// remote end, and must instead have been dropped. They will be regenerated when
-	/// [`Self::monitor_updating_restored`] is called.
-	///
-	/// [`ChannelManager`]: super::channelmanager::ChannelManager
-	/// [`chain::Watch`]: crate::chain::Watch
-	/// [`ChannelMonitorUpdateStatus::InProgress`]: crate::chain::ChannelMonitorUpdateStatus::InProgress
-	fn monitor_updating_paused(&mut self, resend_raa: bool, resend_commitment: bool,
-		resend_channel_ready: bool, mut pending_forwards: Vec<(PendingHTLCInfo, u64)>,
-		mut pending_fails: Vec<(HTLCSource, PaymentHash, HTLCFailReason)>,
-		mut pending_finalized_claimed_htlcs: Vec<HTLCSource>
-	) {
-		self.context.monitor_pending_revoke_and_ack |= resend_raa;
-		self.context.monitor_pending_commitment_signed |= resend_commitment;
-		self.context.monitor_pending_channel_ready |= resend_channel_ready;
-		self.context.monitor_pending_forwards.append(&mut pending_forwards);
-		self.context.monitor_pending_failures.append(&mut pending_fails);
-		self.context.monitor_pending_finalized_fulfills.append(&mut pending_finalized_claimed_htlcs);
-		self.context.channel_state |= ChannelState::MonitorUpdateInProgress as u32;
+			self.monitor_updating_paused(false, true, false, Vec::new(), Vec::new(), Vec::new());
+			(self.push_ret_blockable_mon_update(monitor_update), htlcs_to_fail)
+		} else {
+			(None, Vec::new())
+		}
 	}
 
-	/// Indicates that the latest ChannelMonitor update has been committed by the client
-	/// successfully and we should restore normal operation. Returns messages which should be sent
-	/// to the remote side.
-	pub fn monitor_updating_restored<L: Deref, NS: Deref>(
-		&mut self, logger: &L, node_signer: &NS, genesis_block_hash: BlockHash,
-		user_config: &UserConfig, best_block_height: u32
-	) -> MonitorRestoreUpdates
-	where
-		L::Target: Logger,
-		NS::Target: NodeSigner
+	/// Handles receiving a remote's revoke_and_ack. Note that we may return a new
+	/// commitment_signed message here in case we had pending outbound HTLCs to add which were
+	/// waiting on this revoke_and_ack. The generation of this new commitment_signed may also fail,
+	/// generating an appropriate error *after* the channel state has been updated based on the
+	/// revoke_and_ack message.
+	pub fn revoke_and_ack<F: Deref, L: Deref>(&mut self, msg: &msgs::RevokeAndACK,
+		fee_estimator: &LowerBoundedFeeEstimator<F>, logger: &L
+	) -> Result<(Vec<(HTLCSource, PaymentHash)>, Option<ChannelMonitorUpdate>), ChannelError>
+	where F::Target: FeeEstimator, L::Target: Logger,
 	{
-		assert_eq!(self.context.channel_state & ChannelState::MonitorUpdateInProgress as u32, ChannelState::MonitorUpdateInProgress as u32);
-		self.context.channel_state &= !(ChannelState::MonitorUpdateInProgress as u32);
-
-		// If we're past (or at) the FundingSent stage on an outbound channel, try to
-		// (re-)broadcast the funding transaction as we may have declined to broadcast it when we
-		// first received the funding_signed.
-		let mut funding_broadcastable =
-			if self.context.is_outbound() && self.context.channel_state & !MULTI_STATE_FLAGS >= ChannelState::FundingSent as u32 {
-				self.context.funding_transaction.take()
-			} else { None };
-		// That said, if the funding transaction is already confirmed (ie we're active with a
-		// minimum_depth over 0) don't bother re-broadcasting the confirmed funding tx.
-		if self.context.channel_state & !MULTI_STATE_FLAGS >= ChannelState::ChannelReady as u32 && self.context.minimum_depth != Some(0) {
-			funding_broadcastable = None;
+		if (self.context.channel_state & (ChannelState::ChannelReady as u32)) != (ChannelState::ChannelReady as u32) {
+			return Err(ChannelError::Close("Got revoke/ACK message when channel was not in an operational state".to_owned()));
 		}
-
-		// We will never broadcast the funding transaction when we're in MonitorUpdateInProgress
-		// (and we assume the user never directly broadcasts the funding transaction and waits for
-		// us to do it). Thus, we can only ever hit monitor_pending_channel_ready when we're
-		// * an inbound channel that failed to persist the monitor on funding_created and we got
-		//   the funding transaction confirmed before the monitor was persisted, or
-		// * a 0-conf channel and intended to send the channel_ready before any broadcast at all.
-		let channel_ready = if self.context.monitor_pending_channel_ready {
-			assert!(!self.context.is_outbound() || self.context.minimum_depth == Some(0),
-				"Funding transaction broadcast by the local client before it should have - LDK didn't do it!");
-			self.context.monitor_pending_channel_ready = false;
-			let next_per_commitment_point = self.context.holder_signer.get_per_commitment_point(self.context.cur_holder_commitment_transaction_number, &self.context.secp_ctx);
-			Some(msgs::ChannelReady {
-				channel_id: self.context.channel_id(),
-				next_per_commitment_point,
-				short_channel_id_alias: Some(self.context.outbound_scid_alias),
-			})
-		} else { None };
-
-		let announcement_sigs = self.get_announcement_sigs(node_signer, genesis_block_hash, user_config, best_block_height, logger);
-
-		let mut accepted_htlcs = Vec::new();
-		mem::swap(&mut accepted_htlcs, &mut self.context.monitor_pending_forwards);
-		let mut failed_htlcs = Vec::new();
-		mem::swap(&mut failed_htlcs, &mut self.context.monitor_pending_failures);
-		let mut finalized_claimed_htlcs = Vec::new();
-		mem::swap(&mut finalized_claimed_htlcs, &mut self.context.monitor_pending_finalized_fulfills);
-
-		if self.context.channel_state & (ChannelState::PeerDisconnected as u32) != 0 {
-			self.context.monitor_pending_revoke_and_ack = false;
-			self.context.monitor_pending_commitment_signed = false;
-			return MonitorRestoreUpdates {
-				raa: None, commitment_update: None, order: RAACommitmentOrder::RevokeAndACKFirst,
-				accepted_htlcs, failed_htlcs, finalized_claimed_htlcs, funding_broadcastable, channel_ready, announcement_sigs
-			};
+		if self.context.channel_state & (ChannelState::PeerDisconnected as u32) == ChannelState::PeerDisconnected as u32 {
+			return Err(ChannelError::Close("Peer sent revoke_and_ack when we needed a channel_reestablish".to_owned()));
+		}
+		if self.context.channel_state & BOTH_SIDES_SHUTDOWN_MASK == BOTH_SIDES_SHUTDOWN_MASK && self.context.last_sent_closing_fee.is_some() {
+			return Err(ChannelError::Close("Peer sent revoke_and_ack after we'd started exchanging closing_signeds".to_owned()));
 		}
 
-		let raa = if self.context.monitor_pending_revoke_and_ack {
-			Some(self.get_last_revoke_and_ack())
-		} else { None };
-		let commitment_update = if self.context.monitor_pending_commitment_signed {
-			self.mark_awaiting_response();
-			Some(self.get_last_commitment_update(logger))
-		} else { None };
+		let secret = secp_check!(SecretKey::from_slice(&msg.per_commitment_secret), "Peer provided an invalid per_commitment_secret".to_owned());
 
-		self.context.monitor_pending_revoke_and_ack = false;
-		self.context.monitor_pending_commitment_signed = false;
-		let order = self.context.resend_order.clone();
-		log_debug!(logger, "Restored monitor updating in channel {} resulting in {}{} commitment update and {} RAA, with {} first",
-			log_bytes!(self.context.channel_id()), if funding_broadcastable.is_some() { "a funding broadcastable, " } else { "" },
-			if commitment_update.is_some() { "a" } else { "no" }, if raa.is_some() { "an" } else { "no" },
-			match order { RAACommitmentOrder::CommitmentFirst => "commitment", RAACommitmentOrder::RevokeAndACKFirst => "RAA"});
-		MonitorRestoreUpdates {
-			raa, commitment_update, order, accepted_htlcs, failed_htlcs, finalized_claimed_htlcs, funding_broadcastable, channel_ready, announcement_sigs
+		if let Some(counterparty_prev_commitment_point) = self.context.counterparty_prev_commitment_point {
+			if PublicKey::from_secret_key(&self.context.secp_ctx, &secret) != counterparty_prev_commitment_point {
+				return Err(ChannelError::Close("Got a revoke commitment secret which didn't correspond to their current pubkey".to_owned()));
+			}
 		}
-	}
 
-	pub fn update_fee<F: Deref, L: Deref>(&mut self, fee_estimator: &LowerBoundedFeeEstimator<F>, msg: &msgs::UpdateFee, logger: &L) -> Result<(), ChannelError>
-		where F::Target: FeeEstimator, L::Target: Logger
-	{
-		if self.context.is_outbound() {
-			return Err(ChannelError::Close("Non-funding remote tried to update channel fee".to_owned()));
+		if self.context.channel_state & ChannelState::AwaitingRemoteRevoke as u32 == 0 {
+			// Our counterparty seems to have burned their coins to us (by revoking a state when we
+			// haven't given them a new commitment transaction to broadcast). We should probably
+			// take advantage of this by updating our channel monitor, sending them an error, and
+			// waiting for them to broadcast their latest (now-revoked claim). But, that would be a
+			// lot of work, and there's some chance this is all a misunderstanding anyway.
+			// We have to do *something*, though, since our signer may get mad at us for otherwise
+			// jumping a remote commitment number, so best to just force-close and move on.
+			return Err(ChannelError::Close("Received an unexpected revoke_and_ack".to_owned()));
 		}
-		if self.context.channel_state & (ChannelState::PeerDisconnected as u32) == ChannelState::PeerDisconnected as u32 {
-			return Err(ChannelError::Close("Peer sent update_fee when we needed a channel_reestablish".to_owned()));
+
+		#[cfg(any(test, fuzzing))]
+		{
+			*self.context.next_local_commitment_tx_fee_info_cached.lock().unwrap() = None;
+			*self.context.next_remote_commitment_tx_fee_info_cached.lock().unwrap() = None;
 		}
-		Channel::<Signer>::check_remote_fee(&self.context.channel_type, fee_estimator, msg.feerate_per_kw, Some(self.context.feerate_per_kw), logger)?;
-		let feerate_over_dust_buffer = msg.feerate_per_kw > self.context.get_dust_buffer_feerate(None);
 
-		self.context.pending_update_fee = Some((msg.feerate_per_kw, FeeUpdateState::RemoteAnnounced));
+		self.context.holder_signer.validate_counterparty_revocation(
+			self.context.cur_counterparty_commitment_transaction_number + 1,
+			&secret
+		).map_err(|_| ChannelError::Close("Failed to validate revocation from peer".to_owned()))?;
+
+		self.context.commitment_secrets.provide_secret(self.context.cur_counterparty_commitment_transaction_number + 1, msg.per_commitment_secret)
+			.map_err(|_| ChannelError::Close("Previous secrets did not match new one".to_owned()))?;
+		self.context.latest_monitor_update_id += 1;
+		let mut monitor_update = ChannelMonitorUpdate {
+			update_id: self.context.latest_monitor_update_id,
+			updates: vec![ChannelMonitorUpdateStep::CommitmentSecret {
+				idx: self.context.cur_counterparty_commitment_transaction_number + 1,
+				secret: msg.per_commitment_secret,
+			}],
+		};
+
+		// Update state now that we've passed all the can-fail calls...
+		// (note that we may still fail to generate the new commitment_signed message, but that's
+		// OK, we step the channel here and *then* if the new generation fails we can fail the
+		// channel based on that, but stepping stuff here should be safe either way.
+		self.context.channel_state &= !(ChannelState::AwaitingRemoteRevoke as u32);
+		self.context.sent_message_awaiting_response = None;
+		self.context.counterparty_prev_commitment_point = self.context.counterparty_cur_commitment_point;
+		self.context.counterparty_cur_commitment_point = Some(msg.next_per_commitment_point);
+		self.context.cur_counterparty_commitment_transaction_number -= 1;
+
+		if self.context.announcement_sigs_state == AnnouncementSigsState::Committed {
+			self.context.announcement_sigs_state = AnnouncementSigsState::PeerReceived;
+		}
+
+		log_trace!(logger, "Updating HTLCs on receipt of RAA in channel {}...", log_bytes!(self.context.channel_id()));
+		let mut to_forward_infos = Vec::new();
+		let mut revoked_htlcs = Vec::new();
+		let mut finalized_claimed_htlcs = Vec::new();
+		let mut update_fail_htlcs = Vec::new();
+		let mut update_fail_malformed_htlcs = Vec::new();
+		let mut require_commitment = false;
+		let mut value_to_self_msat_diff: i64 = 0;
+
+		{
+			// Take references explicitly so that we can hold multiple references to self.context.
+			let pending_inbound_htlcs: &mut Vec<_> = &mut self.context.pending_inbound_htlcs;
+			let pending_outbound_htlcs: &mut Vec<_> = &mut self.context.pending_outbound_htlcs;
+
+			// We really shouldnt have two passes here, but retain gives a non-mutable ref (Rust bug)
+			pending_inbound_htlcs.retain(|htlc| {
+				if let &InboundHTLCState::LocalRemoved(ref reason) = &htlc.state {
+					log_trace!(logger, " ...removing inbound LocalRemoved {}", log_bytes!(htlc.payment_hash.0));
+					if let &InboundHTLCRemovalReason::Fulfill(_) = reason {
+						value_to_self_msat_diff += htlc.amount_msat as i64;
+					}
+					false
+				} else { true }
+			});
+			pending_outbound_htlcs.retain(|htlc| {
+				if let &OutboundHTLCState::AwaitingRemovedRemoteRevoke(ref outcome) = &htlc.state {
+					log_trace!(logger, " ...removing outbound AwaitingRemovedRemoteRevoke {}", log_bytes!(htlc.payment_hash.0));
+					if let OutboundHTLCOutcome::Failure(reason) = outcome.clone() { // We really want take() here, but, again, non-mut ref :(
+						revoked_htlcs.push((htlc.source.clone(), htlc.payment_hash, reason));
+					} else {
+						finalized_claimed_htlcs.push(htlc.source.clone());
+						// They fulfilled, so we sent them money
+						value_to_self_msat_diff -= htlc.amount_msat as i64;
+					}
+					false
+				} else { true }
+			});
+			for htlc in pending_inbound_htlcs.iter_mut() {
+				let swap = if let &InboundHTLCState::AwaitingRemoteRevokeToAnnounce(_) = &htlc.state {
+					true
+				} else if let &InboundHTLCState::AwaitingAnnouncedRemoteRevoke(_) = &htlc.state {
+					true
+				} else { false };
+				if swap {
+					let mut state = InboundHTLCState::Committed;
+					mem::swap(&mut state, &mut htlc.state);
+
+					if let InboundHTLCState::AwaitingRemoteRevokeToAnnounce(forward_info) = state {
+						log_trace!(logger, " ...promoting inbound AwaitingRemoteRevokeToAnnounce {} to AwaitingAnnouncedRemoteRevoke", log_bytes!(htlc.payment_hash.0));
+						htlc.state = InboundHTLCState::AwaitingAnnouncedRemoteRevoke(forward_info);
+						require_commitment = true;
+					} else if let InboundHTLCState::AwaitingAnnouncedRemoteRevoke(forward_info) = state {
+						match forward_info {
+							PendingHTLCStatus::Fail(fail_msg) => {
+								log_trace!(logger, " ...promoting inbound AwaitingAnnouncedRemoteRevoke {} to LocalRemoved due to PendingHTLCStatus indicating failure", log_bytes!(htlc.payment_hash.0));
+								require_commitment = true;
+								match fail_msg {
+									HTLCFailureMsg::Relay(msg) => {
+										htlc.state = InboundHTLCState::LocalRemoved(InboundHTLCRemovalReason::FailRelay(msg.reason.clone()));
+										update_fail_htlcs.push(msg)
+									},
+									HTLCFailureMsg::Malformed(msg) => {
+										htlc.state = InboundHTLCState::LocalRemoved(InboundHTLCRemovalReason::FailMalformed((msg.sha256_of_onion, msg.failure_code)));
+										update_fail_malformed_htlcs.push(msg)
+									},
+								}
+							},
+							PendingHTLCStatus::Forward(forward_info) => {
+								log_trace!(logger, " ...promoting inbound AwaitingAnnouncedRemoteRevoke {} to Committed", log_bytes!(htlc.payment_hash.0));
+								to_forward_infos.push((forward_info, htlc.htlc_id));
+								htlc.state = InboundHTLCState::Committed;
+							}
+						}
+					}
+				}
+			}
+			for htlc in pending_outbound_htlcs.iter_mut() {
+				if let OutboundHTLCState::LocalAnnounced(_) = htlc.state {
+					log_trace!(logger, " ...promoting outbound LocalAnnounced {} to Committed", log_bytes!(htlc.payment_hash.0));
+					htlc.state = OutboundHTLCState::Committed;
+				}
+				if let &mut OutboundHTLCState::AwaitingRemoteRevokeToRemove(ref mut outcome) = &mut htlc.state {
+					log_trace!(logger, " ...promoting outbound AwaitingRemoteRevokeToRemove {} to AwaitingRemovedRemoteRevoke", log_bytes!(htlc.payment_hash.0));
+					// Grab the preimage, if it exists, instead of cloning
+					let mut reason = OutboundHTLCOutcome::Success(None);
+					mem::swap(outcome, &mut reason);
+					htlc.state = OutboundHTLCState::AwaitingRemovedRemoteRevoke(reason);
+					require_commitment = true;
+				}
+			}
+		}
+		self.context.value_to_self_msat = (self.context.value_to_self_msat as i64 + value_to_self_msat_diff) as u64;
+
+		if let Some((feerate, update_state)) = self.context.pending_update_fee {
+			match update_state {
+				FeeUpdateState::Outbound => {
+					debug_assert!(self.context.is_outbound());
+					log_trace!(logger, " ...promoting outbound fee update {} to Committed", feerate);
+					self.context.feerate_per_kw = feerate;
+					self.context.pending_update_fee = None;
+				},
+				FeeUpdateState::RemoteAnnounced => { debug_assert!(!self.context.is_outbound()); },
+				FeeUpdateState::AwaitingRemoteRevokeToAnnounce => {
+					debug_assert!(!self.context.is_outbound());
+					log_trace!(logger, " ...promoting inbound AwaitingRemoteRevokeToAnnounce fee update {} to Committed", feerate);
+					require_commitment = true;
+					self.context.feerate_per_kw = feerate;
+					self.context.pending_update_fee = None;
+				},
+			}
+		}
+
+		if (self.context.channel_state & ChannelState::MonitorUpdateInProgress as u32) == ChannelState::MonitorUpdateInProgress as u32 {
+			// We can't actually generate a new commitment transaction (incl by freeing holding
+			// cells) while we can't update the monitor, so we just return what we have.
+			if require_commitment {
+				self.context.monitor_pending_commitment_signed = true;
+				// When the monitor updating is restored we'll call get_last_commitment_update(),
+				// which does not update state, but we're definitely now awaiting a remote revoke
+				// before we can step forward any more, so set it here.
+				let mut additional_update = self.build_commitment_no_status_check(logger);
+				// build_commitment_no_status_check may bump latest_monitor_id but we want them to be
+				// strictly increasing by one, so decrement it here.
+				self.context.latest_monitor_update_id = monitor_update.update_id;
+				monitor_update.updates.append(&mut additional_update.updates);
+			}
+			self.context.monitor_pending_forwards.append(&mut to_forward_infos);
+			self.context.monitor_pending_failures.append(&mut revoked_htlcs);
+			self.context.monitor_pending_finalized_fulfills.append(&mut finalized_claimed_htlcs);
+			log_debug!(logger, "Received a valid revoke_and_ack for channel {} but awaiting a monitor update resolution to reply.", log_bytes!(self.context.channel_id()));
+			return Ok((Vec::new(), self.push_ret_blockable_mon_update(monitor_update)));
+		}
+
+		match self.free_holding_cell_htlcs(fee_estimator, logger) {
+			(Some(mut additional_update), htlcs_to_fail) => {
+				// free_holding_cell_htlcs may bump latest_monitor_id multiple times but we want them to be
+				// strictly increasing by one, so decrement it here.
+				self.context.latest_monitor_update_id = monitor_update.update_id;
+				monitor_update.updates.append(&mut additional_update.updates);
+
+				self.monitor_updating_paused(false, true, false, to_forward_infos, revoked_htlcs, finalized_claimed_htlcs);
+				Ok((htlcs_to_fail, self.push_ret_blockable_mon_update(monitor_update)))
+			},
+			(None, htlcs_to_fail) => {
+				if require_commitment {
+					let mut additional_update = self.build_commitment_no_status_check(logger);
+
+					// build_commitment_no_status_check may bump latest_monitor_id but we want them to be
+					// strictly increasing by one, so decrement it here.
+					self.context.latest_monitor_update_id = monitor_update.update_id;
+					monitor_update.updates.append(&mut additional_update.updates);
+
+					log_debug!(logger, "Received a valid revoke_and_ack for channel {}. Responding with a commitment update with {} HTLCs failed.",
+						log_bytes!(self.context.channel_id()), update_fail_htlcs.len() + update_fail_malformed_htlcs.len());
+					self.monitor_updating_paused(false, true, false, to_forward_infos, revoked_htlcs, finalized_claimed_htlcs);
+					Ok((htlcs_to_fail, self.push_ret_blockable_mon_update(monitor_update)))
+				} else {
+					log_debug!(logger, "Received a valid revoke_and_ack for channel {} with no reply necessary.", log_bytes!(self.context.channel_id()));
+					self.monitor_updating_paused(false, false, false, to_forward_infos, revoked_htlcs, finalized_claimed_htlcs);
+					Ok((htlcs_to_fail, self.push_ret_blockable_mon_update(monitor_update)))
+				}
+			}
+		}
+	}
+
+	/// Queues up an outbound update fee by placing it in the holding cell. You should call
+	/// [`Self::maybe_free_holding_cell_htlcs`] in order to actually generate and send the
+	/// commitment update.
+	pub fn queue_update_fee<F: Deref, L: Deref>(&mut self, feerate_per_kw: u32,
+		fee_estimator: &LowerBoundedFeeEstimator<F>, logger: &L)
+	where F::Target: FeeEstimator, L::Target: Logger
+	{
+		let msg_opt = self.send_update_fee(feerate_per_kw, true, fee_estimator, logger);
+		assert!(msg_opt.is_none(), "We forced holding cell?");
+	}
+
+	/// Adds a pending update to this channel. See the doc for send_htlc for
+	/// further details on the optionness of the return value.
+	/// If our balance is too low to cover the cost of the next commitment transaction at the
+	/// new feerate, the update is cancelled.
+	///
+	/// You MUST call [`Self::send_commitment_no_state_update`] prior to any other calls on this
+	/// [`Channel`] if `force_holding_cell` is false.
+	fn send_update_fee<F: Deref, L: Deref>(
+		&mut self, feerate_per_kw: u32, mut force_holding_cell: bool,
+		fee_estimator: &LowerBoundedFeeEstimator<F>, logger: &L
+	) -> Option<msgs::UpdateFee>
+	where F::Target: FeeEstimator, L::Target: Logger
+	{
+		if !self.context.is_outbound() {
+			panic!("Cannot send fee from inbound channel");
+		}
+		if !self.context.is_usable() {
+			panic!("Cannot update fee until channel is fully established and we haven't started shutting down");
+		}
+		if !self.context.is_live() {
+			panic!("Cannot update fee while peer is disconnected/we're awaiting a monitor update (ChannelManager should have caught this)");
+		}
+		// Before proposing a feerate update, check that we can actually afford the new fee.
+		let inbound_stats = self.context.get_inbound_pending_htlc_stats(Some(feerate_per_kw));
+		let outbound_stats = self.context.get_outbound_pending_htlc_stats(Some(feerate_per_kw));
+		let keys = self.context.build_holder_transaction_keys(self.context.cur_holder_commitment_transaction_number);
+		let commitment_stats = self.context.build_commitment_transaction(self.context.cur_holder_commitment_transaction_number, &keys, true, true, logger);
+		let buffer_fee_msat = commit_tx_fee_sat(feerate_per_kw, commitment_stats.num_nondust_htlcs + outbound_stats.on_holder_tx_holding_cell_htlcs_count as usize + CONCURRENT_INBOUND_HTLC_FEE_BUFFER as usize, self.context.get_channel_type()) * 1000;
+		let holder_balance_msat = commitment_stats.local_balance_msat - outbound_stats.holding_cell_msat;
+		if holder_balance_msat < buffer_fee_msat  + self.context.counterparty_selected_channel_reserve_satoshis.unwrap() * 1000 {
+			//TODO: auto-close after a number of failures?
+			log_debug!(logger, "Cannot afford to send new feerate at {}", feerate_per_kw);
+			return None;
+		}
+		// The commitment transactions spending the new funding output of a pending splice only
+		// differ in our balance and the reserve we must keep.
+		if let Some(splice_balances) = self.context.get_splice_funding_balances() {
+			let splice_holder_balance_msat = (holder_balance_msat + splice_balances.value_to_self_msat)
+				.saturating_sub(self.context.value_to_self_msat);
+			if splice_holder_balance_msat < buffer_fee_msat + splice_balances.counterparty_selected_channel_reserve_satoshis.unwrap() * 1000 {
+				log_debug!(logger, "Cannot afford to send new feerate at {} once the pending splice locks", feerate_per_kw);
+				return None;
+			}
+		}
+
+		// Note, we evaluate pending htlc "preemptive" trimmed-to-dust threshold at the proposed `feerate_per_kw`.
+		let holder_tx_dust_exposure = inbound_stats.on_holder_tx_dust_exposure_msat + outbound_stats.on_holder_tx_dust_exposure_msat;
+		let counterparty_tx_dust_exposure = inbound_stats.on_counterparty_tx_dust_exposure_msat + outbound_stats.on_counterparty_tx_dust_exposure_msat;
+		let max_dust_htlc_exposure_msat = self.context.get_max_dust_htlc_exposure_msat(fee_estimator);
+		if holder_tx_dust_exposure > max_dust_htlc_exposure_msat {
+			log_debug!(logger, "Cannot afford to send new feerate at {} without infringing max dust htlc exposure", feerate_per_kw);
+			return None;
+		}
+		if counterparty_tx_dust_exposure > max_dust_htlc_exposure_msat {
+			log_debug!(logger, "Cannot afford to send new feerate at {} without infringing max dust htlc exposure", feerate_per_kw);
+			return None;
+		}
+
+		if (self.context.channel_state & (ChannelState::AwaitingRemoteRevoke as u32 | ChannelState::MonitorUpdateInProgress as u32 | QUIESCENCE_STATE_FLAGS)) != 0 {
+			force_holding_cell = true;
+		}
+
+		if force_holding_cell {
+			self.context.holding_cell_update_fee = Some(feerate_per_kw);
+			return None;
+		}
+
+		debug_assert!(self.context.pending_update_fee.is_none());
+		self.context.pending_update_fee = Some((feerate_per_kw, FeeUpdateState::Outbound));
+
+		Some(msgs::UpdateFee {
+			channel_id: self.context.channel_id,
+			feerate_per_kw,
+		})
+	}
+
+	/// Removes any uncommitted inbound HTLCs and resets the state of uncommitted outbound HTLC
+	/// updates, to be used on peer disconnection. After this, update_*_htlc messages need to be
+	/// resent.
+	/// No further message handling calls may be made until a channel_reestablish dance has
+	/// completed.
+	pub fn remove_uncommitted_htlcs_and_mark_paused<L: Deref>(&mut self, logger: &L)  where L::Target: Logger {
+		assert_eq!(self.context.channel_state & ChannelState::ShutdownComplete as u32, 0);
+		if self.context.channel_state < ChannelState::FundingSent as u32 {
+			self.context.channel_state = ChannelState::ShutdownComplete as u32;
+			return;
+		}
+
+		if self.context.channel_state & (ChannelState::PeerDisconnected as u32) == (ChannelState::PeerDisconnected as u32) {
+			// While the below code should be idempotent, it's simpler to just return early, as
+			// redundant disconnect events can fire, though they should be rare.
+			return;
+		}
+
+		if self.context.announcement_sigs_state == AnnouncementSigsState::MessageSent || self.context.announcement_sigs_state == AnnouncementSigsState::Committed {
+			self.context.announcement_sigs_state = AnnouncementSigsState::NotSent;
+		}
+
+		// A candidate funding transaction of a splice we haven't started signing has to be
+		// negotiated from scratch after reconnecting, while any request of ours to negotiate one
+		// will be proposed again.
+		if let Some(splice) = self.context.pending_splice.as_mut() {
+			if splice.candidates.last().map_or(false, |candidate| candidate.signing_session.is_none()) {
+				log_info!(logger, "Abandoning splice transaction negotiation of channel {} upon disconnection", log_bytes!(self.context.channel_id));
+				splice.candidates.pop();
+				self.interactive_tx_constructor = None;
+			}
+			if let Some(request) = splice.pending_request.as_mut() {
+				request.proposed = false;
+			}
+			if splice.candidates.is_empty() && splice.pending_request.is_none() {
+				self.context.pending_splice = None;
+			}
+		}
+		self.context.pending_commitment_signed_batch.clear();
+
+		// Upon reconnect we have to start the closing_signed dance over, but shutdown messages
+		// will be retransmitted.
+		self.context.last_sent_closing_fee = None;
+		self.context.pending_counterparty_closing_signed = None;
+		self.context.closing_fee_limits = None;
+
+		let mut inbound_drop_count = 0;
+		self.context.pending_inbound_htlcs.retain(|htlc| {
+			match htlc.state {
+				InboundHTLCState::RemoteAnnounced(_) => {
+					// They sent us an update_add_htlc but we never got the commitment_signed.
+					// We'll tell them what commitment_signed we're expecting next and they'll drop
+					// this HTLC accordingly
+					inbound_drop_count += 1;
+					false
+				},
+				InboundHTLCState::AwaitingRemoteRevokeToAnnounce(_)|InboundHTLCState::AwaitingAnnouncedRemoteRevoke(_) => {
+					// We received a commitment_signed updating this HTLC and (at least hopefully)
+					// sent a revoke_and_ack (which we can re-transmit) and have heard nothing
+					// in response to it yet, so don't touch it.
+					true
+				},
+				InboundHTLCState::Committed => true,
+				InboundHTLCState::LocalRemoved(_) => {
+					// We (hopefully) sent a commitment_signed updating this HTLC (which we can
+					// re-transmit if needed) and they may have even sent a revoke_and_ack back
+					// (that we missed). Keep this around for now and if they tell us they missed
+					// the commitment_signed we can re-transmit the update then.
+					true
+				},
+			}
+		});
+		self.context.next_counterparty_htlc_id -= inbound_drop_count;
+
+		if let Some((_, update_state)) = self.context.pending_update_fee {
+			if update_state == FeeUpdateState::RemoteAnnounced {
+				debug_assert!(!self.context.is_outbound());
+				self.context.pending_update_fee = None;
+			}
+		}
+
+		for htlc in self.context.pending_outbound_htlcs.iter_mut() {
+			if let OutboundHTLCState::RemoteRemoved(_) = htlc.state {
+				// They sent us an update to remove this but haven't yet sent the corresponding
+				// commitment_signed, we need to move it back to Committed and they can re-send
+				// the update upon reconnection.
+				htlc.state = OutboundHTLCState::Committed;
+			}
+		}
+
+		self.context.sent_message_awaiting_response = None;
+
+		// Quiescence does not survive a disconnection. If we were still waiting on the channel to
+		// become quiescent for our own purposes, we'll propose it again once we've reconnected.
+		// The same goes for a channel type upgrade our counterparty has yet to accept.
+		let mut was_awaiting_quiescence =
+			self.context.channel_state & (ChannelState::AwaitingQuiescence as u32 | ChannelState::LocalStfuSent as u32) != 0 &&
+			self.context.channel_state & (ChannelState::Quiescent as u32) == 0;
+		if let Some(upgrade) = self.context.pending_channel_type_upgrade.as_mut() {
+			if upgrade.state == ChannelTypeUpgradeState::Proposed {
+				upgrade.state = ChannelTypeUpgradeState::Requested;
+			}
+			was_awaiting_quiescence |= upgrade.state == ChannelTypeUpgradeState::Requested;
+		}
+		was_awaiting_quiescence |= self.context.pending_splice.as_ref()
+			.map_or(false, |splice| splice.pending_request.is_some());
+		self.context.channel_state &= !QUIESCENCE_STATE_FLAGS;
+		self.context.is_holder_quiescence_initiator = None;
+		if was_awaiting_quiescence {
+			self.context.channel_state |= ChannelState::AwaitingQuiescence as u32;
+			self.context.quiescence_timer_ticks = Some(0);
+		} else {
+			self.context.quiescence_timer_ticks = None;
+		}
+
+		self.context.channel_state |= ChannelState::PeerDisconnected as u32;
+		log_trace!(logger, "Peer disconnection resulted in {} remote-announced HTLC drops on channel {}", inbound_drop_count, log_bytes!(self.context.channel_id()));
+	}
+
+	/// Indicates that a ChannelMonitor update is in progress and has not yet been fully persisted.
+	/// This must be called before we return the [`ChannelMonitorUpdate`] back to the
+	/// [`ChannelManager`], which will call [`Self::monitor_updating_restored`] once the monitor
+	/// update completes (potentially immediately).
+	/// The messages which were generated with the monitor update must *not* have been sent to the
+	/// remote end, and must instead have been dropped. They will be regenerated when
+	/// [`Self::monitor_updating_restored`] is called.
+	///
+	/// [`ChannelManager`]: super::channelmanager::ChannelManager
+	/// [`chain::Watch`]: crate::chain::Watch
+	/// [`ChannelMonitorUpdateStatus::InProgress`]: crate::chain::ChannelMonitorUpdateStatus::InProgress
+	fn monitor_updating_paused(&mut self, resend_raa: bool, resend_commitment: bool,
+		resend_channel_ready: bool, mut pending_forwards: Vec<(PendingHTLCInfo, u64)>,
+		mut pending_fails: Vec<(HTLCSource, PaymentHash, HTLCFailReason)>,
+		mut pending_finalized_claimed_htlcs: Vec<HTLCSource>
+	) {
+		self.context.monitor_pending_revoke_and_ack |= resend_raa;
+		self.context.monitor_pending_commitment_signed |= resend_commitment;
+		self.context.monitor_pending_channel_ready |= resend_channel_ready;
+		self.context.monitor_pending_forwards.append(&mut pending_forwards);
+		self.context.monitor_pending_failures.append(&mut pending_fails);
+		self.context.monitor_pending_finalized_fulfills.append(&mut pending_finalized_claimed_htlcs);
+		self.context.channel_state |= ChannelState::MonitorUpdateInProgress as u32;
+	}
+
+	/// Indicates that the latest ChannelMonitor update has been committed by the client
+	/// successfully and we should restore normal operation. Returns messages which should be sent
+	/// to the remote side.
+	pub fn monitor_updating_restored<L: Deref, NS: Deref>(
+		&mut self, logger: &L, node_signer: &NS, genesis_block_hash: BlockHash,
+		user_config: &UserConfig, best_block_height: u32
+	) -> MonitorRestoreUpdates
+	where
+		L::Target: Logger,
+		NS::Target: NodeSigner
+	{
+		assert_eq!(self.context.channel_state & ChannelState::MonitorUpdateInProgress as u32, ChannelState::MonitorUpdateInProgress as u32);
+		self.context.channel_state &= !(ChannelState::MonitorUpdateInProgress as u32);
+
+		// If we're past (or at) the FundingSent stage on an outbound channel, try to
+		// (re-)broadcast the funding transaction as we may have declined to broadcast it when we
+		// first received the funding_signed. Dual-funded channels hold a funding transaction on
+		// both sides once it has been fully signed, so we may broadcast it if we're inbound too.
+		// Batch funding transactions are instead broadcast by the `ChannelManager` once every
+		// channel in the batch is ready.
+		let mut funding_broadcastable =
+			if self.context.channel_state & !MULTI_STATE_FLAGS >= ChannelState::FundingSent as u32 &&
+				!self.context.is_awaiting_batch_broadcast()
+			{
+				self.context.funding_transaction.take()
+			} else { None };
+		// That said, if the funding transaction is already confirmed (ie we're active with a
+		// minimum_depth over 0) don't bother re-broadcasting the confirmed funding tx.
+		if self.context.channel_state & !MULTI_STATE_FLAGS >= ChannelState::ChannelReady as u32 && self.context.minimum_depth != Some(0) {
+			funding_broadcastable = None;
+		}
+
+		// We will never broadcast the funding transaction when we're in MonitorUpdateInProgress
+		// (and we assume the user never directly broadcasts the funding transaction and waits for
+		// us to do it). Thus, we can only ever hit monitor_pending_channel_ready when we're
+		// * an inbound channel that failed to persist the monitor on funding_created and we got
+		//   the funding transaction confirmed before the monitor was persisted, or
+		// * a 0-conf channel and intended to send the channel_ready before any broadcast at all.
+		let channel_ready = if self.context.monitor_pending_channel_ready {
+			assert!(!self.context.is_outbound() || self.context.minimum_depth == Some(0) ||
+				self.context.interactive_tx_signing_session.is_some(),
+				"Funding transaction broadcast by the local client before it should have - LDK didn't do it!");
+			self.context.monitor_pending_channel_ready = false;
+			let next_per_commitment_point = self.context.holder_signer.get_per_commitment_point(self.context.cur_holder_commitment_transaction_number, &self.context.secp_ctx);
+			Some(msgs::ChannelReady {
+				channel_id: self.context.channel_id(),
+				next_per_commitment_point,
+				short_channel_id_alias: Some(self.context.outbound_scid_alias),
+			})
+		} else { None };
+
+		let announcement_sigs = self.get_announcement_sigs(node_signer, genesis_block_hash, user_config, best_block_height, logger);
+
+		let mut accepted_htlcs = Vec::new();
+		mem::swap(&mut accepted_htlcs, &mut self.context.monitor_pending_forwards);
+		let mut failed_htlcs = Vec::new();
+		mem::swap(&mut failed_htlcs, &mut self.context.monitor_pending_failures);
+		let mut finalized_claimed_htlcs = Vec::new();
+		mem::swap(&mut finalized_claimed_htlcs, &mut self.context.monitor_pending_finalized_fulfills);
+
+		// Once the monitor for a splice's new funding output has been persisted, its funding
+		// transaction may be broadcast if we already have our counterparty's signatures.
+		if self.context.monitor_pending_tx_signatures {
+			if let Some(splice_tx) = self.context.pending_splice_signing_session().and_then(|session| session.finalized_tx()) {
+				funding_broadcastable = Some(splice_tx);
+			}
+		}
+
+		if self.context.channel_state & (ChannelState::PeerDisconnected as u32) != 0 {
+			self.context.monitor_pending_revoke_and_ack = false;
+			self.context.monitor_pending_commitment_signed = false;
+			self.context.monitor_pending_tx_signatures = false;
+			return MonitorRestoreUpdates {
+				raa: None, commitment_update: None, order: RAACommitmentOrder::RevokeAndACKFirst,
+				accepted_htlcs, failed_htlcs, finalized_claimed_htlcs, funding_broadcastable, channel_ready, announcement_sigs,
+				tx_signatures: None,
+			};
+		}
+
+		let tx_signatures = if self.context.monitor_pending_tx_signatures {
+			self.context.monitor_pending_tx_signatures = false;
+			self.context.pending_splice_signing_session()
+				.or(self.context.interactive_tx_signing_session.as_ref())
+				.and_then(|session| session.holder_tx_signatures_to_send())
+		} else { None };
+		self.maybe_exit_splice_quiescence(logger);
+
+		let raa = if self.context.monitor_pending_revoke_and_ack {
+			Some(self.get_last_revoke_and_ack())
+		} else { None };
+		let commitment_update = if self.context.monitor_pending_commitment_signed {
+			self.mark_awaiting_response();
+			Some(self.get_last_commitment_update(logger))
+		} else { None };
+
+		self.context.monitor_pending_revoke_and_ack = false;
+		self.context.monitor_pending_commitment_signed = false;
+		let order = self.context.resend_order.clone();
+		log_debug!(logger, "Restored monitor updating in channel {} resulting in {}{} commitment update and {} RAA, with {} first",
+			log_bytes!(self.context.channel_id()), if funding_broadcastable.is_some() { "a funding broadcastable, " } else { "" },
+			if commitment_update.is_some() { "a" } else { "no" }, if raa.is_some() { "an" } else { "no" },
+			match order { RAACommitmentOrder::CommitmentFirst => "commitment", RAACommitmentOrder::RevokeAndACKFirst => "RAA"});
+		MonitorRestoreUpdates {
+			raa, commitment_update, order, accepted_htlcs, failed_htlcs, finalized_claimed_htlcs, funding_broadcastable, channel_ready, announcement_sigs,
+			tx_signatures,
+		}
+	}
+
+	pub fn update_fee<F: Deref, L: Deref>(&mut self, fee_estimator: &LowerBoundedFeeEstimator<F>, msg: &msgs::UpdateFee, logger: &L) -> Result<(), ChannelError>
+		where F::Target: FeeEstimator, L::Target: Logger
+	{
+		if self.context.is_outbound() {
+			return Err(ChannelError::Close("Non-funding remote tried to update channel fee".to_owned()));
+		}
+		if self.context.channel_state & (ChannelState::PeerDisconnected as u32) == ChannelState::PeerDisconnected as u32 {
+			return Err(ChannelError::Close("Peer sent update_fee when we needed a channel_reestablish".to_owned()));
+		}
+		self.check_update_during_quiescence("update_fee")?;
+		Channel::<Signer>::check_remote_fee(&self.context.channel_type, fee_estimator, msg.feerate_per_kw, Some(self.context.feerate_per_kw), logger)?;
+		let feerate_over_dust_buffer = msg.feerate_per_kw > self.context.get_dust_buffer_feerate(None);
+
+		self.context.pending_update_fee = Some((msg.feerate_per_kw, FeeUpdateState::RemoteAnnounced));
+		self.context.update_time_counter += 1;
+		// If the feerate has increased over the previous dust buffer (note that
+		// `get_dust_buffer_feerate` considers the `pending_update_fee` status), check that we
+		// won't be pushed over our dust exposure limit by the feerate increase.
+		if feerate_over_dust_buffer {
+			let inbound_stats = self.context.get_inbound_pending_htlc_stats(None);
+			let outbound_stats = self.context.get_outbound_pending_htlc_stats(None);
+			let holder_tx_dust_exposure = inbound_stats.on_holder_tx_dust_exposure_msat + outbound_stats.on_holder_tx_dust_exposure_msat;
+			let counterparty_tx_dust_exposure = inbound_stats.on_counterparty_tx_dust_exposure_msat + outbound_stats.on_counterparty_tx_dust_exposure_msat;
+			let max_dust_htlc_exposure_msat = self.context.get_max_dust_htlc_exposure_msat(fee_estimator);
+			if holder_tx_dust_exposure > max_dust_htlc_exposure_msat {
+				return Err(ChannelError::Close(format!("Peer sent update_fee with a feerate ({}) which may over-expose us to dust-in-flight on our own transactions (totaling {} msat)",
+					msg.feerate_per_kw, holder_tx_dust_exposure)));
+			}
+			if counterparty_tx_dust_exposure > max_dust_htlc_exposure_msat {
+				return Err(ChannelError::Close(format!("Peer sent update_fee with a feerate ({}) which may over-expose us to dust-in-flight on our counterparty's transactions (totaling {} msat)",
+					msg.feerate_per_kw, counterparty_tx_dust_exposure)));
+			}
+		}
+		Ok(())
+	}
+
+	fn get_last_revoke_and_ack(&self) -> msgs::RevokeAndACK {
+		let next_per_commitment_point = self.context.holder_signer.get_per_commitment_point(self.context.cur_holder_commitment_transaction_number, &self.context.secp_ctx);
+		let per_commitment_secret = self.context.holder_signer.release_commitment_secret(self.context.cur_holder_commitment_transaction_number + 2);
+		msgs::RevokeAndACK {
+			channel_id: self.context.channel_id,
+			per_commitment_secret,
+			next_per_commitment_point,
+			#[cfg(taproot)]
+			next_local_nonce: None,
+		}
+	}
+
+	fn get_last_commitment_update<L: Deref>(&mut self, logger: &L) -> msgs::CommitmentUpdate where L::Target: Logger {
+		let mut update_add_htlcs = Vec::new();
+		let mut update_fulfill_htlcs = Vec::new();
+		let mut update_fail_htlcs = Vec::new();
+		let mut update_fail_malformed_htlcs = Vec::new();
+
+		for htlc in self.context.pending_outbound_htlcs.iter() {
+			if let &OutboundHTLCState::LocalAnnounced(ref onion_packet) = &htlc.state {
+				update_add_htlcs.push(msgs::UpdateAddHTLC {
+					channel_id: self.context.channel_id(),
+					htlc_id: htlc.htlc_id,
+					amount_msat: htlc.amount_msat,
+					payment_hash: htlc.payment_hash,
+					cltv_expiry: htlc.cltv_expiry,
+					onion_routing_packet: (**onion_packet).clone(),
+					skimmed_fee_msat: htlc.skimmed_fee_msat,
+					blinding_point: htlc.blinding_point,
+				});
+			}
+		}
+
+		for htlc in self.context.pending_inbound_htlcs.iter() {
+			if let &InboundHTLCState::LocalRemoved(ref reason) = &htlc.state {
+				match reason {
+					&InboundHTLCRemovalReason::FailRelay(ref err_packet) => {
+						update_fail_htlcs.push(msgs::UpdateFailHTLC {
+							channel_id: self.context.channel_id(),
+							htlc_id: htlc.htlc_id,
+							reason: err_packet.clone()
+						});
+					},
+					&InboundHTLCRemovalReason::FailMalformed((ref sha256_of_onion, ref failure_code)) => {
+						update_fail_malformed_htlcs.push(msgs::UpdateFailMalformedHTLC {
+							channel_id: self.context.channel_id(),
+							htlc_id: htlc.htlc_id,
+							sha256_of_onion: sha256_of_onion.clone(),
+							failure_code: failure_code.clone(),
+						});
+					},
+					&InboundHTLCRemovalReason::Fulfill(ref payment_preimage) => {
+						update_fulfill_htlcs.push(msgs::UpdateFulfillHTLC {
+							channel_id: self.context.channel_id(),
+							htlc_id: htlc.htlc_id,
+							payment_preimage: payment_preimage.clone(),
+						});
+					},
+				}
+			}
+		}
+
+		let update_fee = if self.context.is_outbound() && self.context.pending_update_fee.is_some() {
+			Some(msgs::UpdateFee {
+				channel_id: self.context.channel_id(),
+				feerate_per_kw: self.context.pending_update_fee.unwrap().0,
+			})
+		} else { None };
+
+		log_trace!(logger, "Regenerated latest commitment update in channel {} with{} {} update_adds, {} update_fulfills, {} update_fails, and {} update_fail_malformeds",
+				log_bytes!(self.context.channel_id()), if update_fee.is_some() { " update_fee," } else { "" },
+				update_add_htlcs.len(), update_fulfill_htlcs.len(), update_fail_htlcs.len(), update_fail_malformed_htlcs.len());
+		let mut commitment_signed = self.send_commitment_no_state_update(logger).expect("It looks like we failed to re-generate a commitment_signed we had previously sent?").0;
+		// While a splice is pending, we sign the commitment transaction spending the funding output
+		// of each of its candidate funding transactions we have a monitor for as well, sending
+		// them all in a batch.
+		let splice_candidates = self.context.get_splice_candidates_with_monitor();
+		let mut splice_commitment_signed = Vec::with_capacity(splice_candidates.len());
+		if !splice_candidates.is_empty() {
+			let batch_size = splice_candidates.len() as u16 + 1;
+			let counterparty_commitment_point = self.context.counterparty_cur_commitment_point.unwrap();
+			for (idx, funding_txid) in splice_candidates {
+				let (mut msg, _, _) = self.context.get_splice_counterparty_commitment_signed(idx,
+					self.context.cur_counterparty_commitment_transaction_number, &counterparty_commitment_point, true, logger)
+					.expect("It looks like we failed to re-generate a commitment_signed we had previously sent?");
+				msg.batch = Some(msgs::CommitmentSignedBatch { batch_size, funding_txid });
+				splice_commitment_signed.push(msg);
+			}
+			commitment_signed.batch = Some(msgs::CommitmentSignedBatch {
+				batch_size, funding_txid: self.context.get_funding_txo().unwrap().txid,
+			});
+		}
+		msgs::CommitmentUpdate {
+			update_add_htlcs, update_fulfill_htlcs, update_fail_htlcs, update_fail_malformed_htlcs, update_fee,
+			commitment_signed, splice_commitment_signed,
+		}
+	}
+
+	/// May panic if some calls other than message-handling calls (which will all Err immediately)
+	/// have been called between remove_uncommitted_htlcs_and_mark_paused and this call.
+	///
+	/// Some links printed in log lines are included here to check them during build (when run with
+	/// `cargo doc --document-private-items`):
+	/// [`super::channelmanager::ChannelManager::force_close_without_broadcasting_txn`] and
+	/// [`super::channelmanager::ChannelManager::force_close_all_channels_without_broadcasting_txn`].
+	pub fn channel_reestablish<L: Deref, NS: Deref>(
+		&mut self, msg: &msgs::ChannelReestablish, logger: &L, node_signer: &NS,
+		genesis_block_hash: BlockHash, user_config: &UserConfig, best_block: &BestBlock
+	) -> Result<ReestablishResponses, ChannelError>
+	where
+		L::Target: Logger,
+		NS::Target: NodeSigner
+	{
+		if self.context.channel_state & (ChannelState::PeerDisconnected as u32) == 0 {
+			// While BOLT 2 doesn't indicate explicitly we should error this channel here, it
+			// almost certainly indicates we are going to end up out-of-sync in some way, so we
+			// just close here instead of trying to recover.
+			return Err(ChannelError::Close("Peer sent a loose channel_reestablish not after reconnect".to_owned()));
+		}
+
+		if msg.next_local_commitment_number >= INITIAL_COMMITMENT_NUMBER || msg.next_remote_commitment_number >= INITIAL_COMMITMENT_NUMBER ||
+			msg.next_local_commitment_number == 0 {
+			return Err(ChannelError::Close("Peer sent a garbage channel_reestablish (usually an lnd node with lost state asking us to force-close for them)".to_owned()));
+		}
+
+		if msg.next_remote_commitment_number > 0 {
+			let expected_point = self.context.holder_signer.get_per_commitment_point(INITIAL_COMMITMENT_NUMBER - msg.next_remote_commitment_number + 1, &self.context.secp_ctx);
+			let given_secret = SecretKey::from_slice(&msg.your_last_per_commitment_secret)
+				.map_err(|_| ChannelError::Close("Peer sent a garbage channel_reestablish with unparseable secret key".to_owned()))?;
+			if expected_point != PublicKey::from_secret_key(&self.context.secp_ctx, &given_secret) {
+				return Err(ChannelError::Close("Peer sent a garbage channel_reestablish with secret key not matching the commitment height provided".to_owned()));
+			}
+			if msg.next_remote_commitment_number > INITIAL_COMMITMENT_NUMBER - self.context.cur_holder_commitment_transaction_number {
+				macro_rules! log_and_panic {
+					($err_msg: expr) => {
+						log_error!(logger, $err_msg, log_bytes!(self.context.channel_id), log_pubkey!(self.context.counterparty_node_id));
+						panic!($err_msg, log_bytes!(self.context.channel_id), log_pubkey!(self.context.counterparty_node_id));
+					}
+				}
+				log_and_panic!("We have fallen behind - we have received proof that if we broadcast our counterparty is going to claim all our funds.\n\
+					This implies you have restarted with lost ChannelMonitor and ChannelManager state, the first of which is a violation of the LDK chain::Watch requirements.\n\
+					More specifically, this means you have a bug in your implementation that can cause loss of funds, or you are running with an old backup, which is unsafe.\n\
+					If you have restored from an old backup and wish to force-close channels and return to operation, you should start up, call\n\
+					ChannelManager::force_close_without_broadcasting_txn on channel {} with counterparty {} or\n\
+					ChannelManager::force_close_all_channels_without_broadcasting_txn, then reconnect to peer(s).\n\
+					Note that due to a long-standing bug in lnd you may have to reach out to peers running lnd-based nodes to ask them to manually force-close channels\n\
+					See https://github.com/lightningdevkit/rust-lightning/issues/1565 for more info.");
+			}
+		}
+
+		// Before we change the state of the channel, we check if the peer is sending a very old
+		// commitment transaction number, if yes we send a warning message.
+		let our_commitment_transaction = INITIAL_COMMITMENT_NUMBER - self.context.cur_holder_commitment_transaction_number - 1;
+		if  msg.next_remote_commitment_number + 1 < our_commitment_transaction {
+			return Err(
+				ChannelError::Warn(format!("Peer attempted to reestablish channel with a very old local commitment transaction: {} (received) vs {} (expected)", msg.next_remote_commitment_number, our_commitment_transaction))
+			);
+		}
+
+		// Go ahead and unmark PeerDisconnected as various calls we may make check for it (and all
+		// remaining cases either succeed or ErrorMessage-fail).
+		self.context.channel_state &= !(ChannelState::PeerDisconnected as u32);
+		self.context.sent_message_awaiting_response = None;
+
+		// A channel type upgrade we accepted only survives the disconnection if our counterparty
+		// switched over to the new channel type before it, in which case their `commitment_signed`
+		// for it is next. The channel remains quiescent until then.
+		//
+		// Similarly, if our counterparty accepted our upgrade but never persisted doing so, they
+		// reestablish the channel with its previous type and we have to revert to it. Our
+		// `ChannelMonitor` only switches over once we receive their `commitment_signed` for the new
+		// type, but it is already tracking the counterparty commitment transaction we signed using
+		// it. Thus, we provide it with the one rebuilt using the previous type, and only retransmit
+		// our `commitment_signed` for it below once it has been persisted.
+		let mut monitor_update = None;
+		match self.context.pending_channel_type_upgrade.take() {
+			Some(upgrade) if upgrade.state == ChannelTypeUpgradeState::Accepted => {
+				if msg.channel_type.as_ref() == Some(&upgrade.channel_type) {
+					self.context.channel_state |= ChannelState::Quiescent as u32;
+					self.context.is_holder_quiescence_initiator = Some(false);
+					self.context.quiescence_timer_ticks = Some(0);
+					self.context.pending_channel_type_upgrade = Some(upgrade);
+				} else {
+					log_debug!(logger, "Abandoning upgrade of channel {} to channel type {} as our counterparty did not switch to it",
+						log_bytes!(self.context.channel_id()), upgrade.channel_type);
+				}
+			},
+			Some(mut upgrade) if upgrade.state == ChannelTypeUpgradeState::AwaitingCommitment => {
+				if msg.channel_type.as_ref() == Some(&upgrade.channel_type) {
+					self.context.pending_channel_type_upgrade = Some(upgrade);
+				} else {
+					log_debug!(logger, "Reverting upgrade of channel {} to channel type {} as our counterparty did not switch to it",
+						log_bytes!(self.context.channel_id()), upgrade.channel_type);
+					let mut previous_channel_type = upgrade.channel_type.clone();
+					previous_channel_type.clear_anchors_zero_fee_htlc_tx();
+					let previous_holder_signer = upgrade.holder_signer.take().expect("Previous signer is kept until the upgrade completes");
+					self.context.apply_channel_type_upgrade(previous_channel_type, previous_holder_signer);
+					if self.context.channel_state & (ChannelState::AwaitingRemoteRevoke as u32) == 0 {
+						return Err(ChannelError::Close("Peer attempted to revert a channel type upgrade after revoking their commitment transaction for it".to_owned()));
+					}
+					let update = self.build_reverted_counterparty_commitment_monitor_update(logger);
+					self.monitor_updating_paused(false, false, false, Vec::new(), Vec::new(), Vec::new());
+					monitor_update = self.push_ret_blockable_mon_update(update);
+				}
+			},
+			upgrade => self.context.pending_channel_type_upgrade = upgrade,
+		}
+
+		let shutdown_msg = if self.context.channel_state & (ChannelState::LocalShutdownSent as u32) != 0 {
+			assert!(self.context.shutdown_scriptpubkey.is_some());
+			Some(msgs::Shutdown {
+				channel_id: self.context.channel_id,
+				scriptpubkey: self.get_closing_scriptpubkey(),
+			})
+		} else { None };
+
+		let announcement_sigs = self.get_announcement_sigs(node_signer, genesis_block_hash, user_config, best_block.height(), logger);
+
+		if self.context.channel_state & (ChannelState::FundingSent as u32) == ChannelState::FundingSent as u32 {
+			// If the funding transaction was negotiated interactively, we may need to resend our
+			// initial commitment_signed and/or tx_signatures.
+			let (commitment_update, tx_signatures) = self.get_interactive_funding_retransmissions(msg, logger)?;
+
+			// If we're waiting on a monitor update, we shouldn't re-send any channel_ready's.
+			if self.context.channel_state & (ChannelState::OurChannelReady as u32) == 0 ||
+					self.context.channel_state & (ChannelState::MonitorUpdateInProgress as u32) != 0 {
+				if msg.next_remote_commitment_number != 0 {
+					return Err(ChannelError::Close("Peer claimed they saw a revoke_and_ack but we haven't sent channel_ready yet".to_owned()));
+				}
+				// Short circuit the whole handler as there is nothing else we can resend them
+				return Ok(ReestablishResponses {
+					channel_ready: None,
+					raa: None, commitment_update,
+					order: RAACommitmentOrder::CommitmentFirst,
+					shutdown_msg, announcement_sigs, tx_signatures,
+					splice_locked: None,
+					monitor_update,
+				});
+			}
+
+			// We have OurChannelReady set!
+			let next_per_commitment_point = self.context.holder_signer.get_per_commitment_point(self.context.cur_holder_commitment_transaction_number, &self.context.secp_ctx);
+			return Ok(ReestablishResponses {
+				channel_ready: Some(msgs::ChannelReady {
+					channel_id: self.context.channel_id(),
+					next_per_commitment_point,
+					short_channel_id_alias: Some(self.context.outbound_scid_alias),
+				}),
+				raa: None, commitment_update,
+				order: RAACommitmentOrder::CommitmentFirst,
+				shutdown_msg, announcement_sigs, tx_signatures,
+				splice_locked: None,
+				monitor_update,
+			});
+		}
+
+		let required_revoke = if msg.next_remote_commitment_number + 1 == INITIAL_COMMITMENT_NUMBER - self.context.cur_holder_commitment_transaction_number {
+			// Remote isn't waiting on any RevokeAndACK from us!
+			// Note that if we need to repeat our ChannelReady we'll do that in the next if block.
+			None
+		} else if msg.next_remote_commitment_number + 1 == (INITIAL_COMMITMENT_NUMBER - 1) - self.context.cur_holder_commitment_transaction_number {
+			if self.context.channel_state & (ChannelState::MonitorUpdateInProgress as u32) != 0 {
+				self.context.monitor_pending_revoke_and_ack = true;
+				None
+			} else {
+				Some(self.get_last_revoke_and_ack())
+			}
+		} else {
+			return Err(ChannelError::Close("Peer attempted to reestablish channel with a very old local commitment transaction".to_owned()));
+		};
+
+		// We increment cur_counterparty_commitment_transaction_number only upon receipt of
+		// revoke_and_ack, not on sending commitment_signed, so we add one if have
+		// AwaitingRemoteRevoke set, which indicates we sent a commitment_signed but haven't gotten
+		// the corresponding revoke_and_ack back yet.
+		let is_awaiting_remote_revoke = self.context.channel_state & ChannelState::AwaitingRemoteRevoke as u32 != 0;
+		if is_awaiting_remote_revoke && !self.is_awaiting_monitor_update() {
+			self.mark_awaiting_response();
+		}
+		let next_counterparty_commitment_number = INITIAL_COMMITMENT_NUMBER - self.context.cur_counterparty_commitment_transaction_number + if is_awaiting_remote_revoke { 1 } else { 0 };
+
+		let channel_ready = if msg.next_local_commitment_number == 1 && INITIAL_COMMITMENT_NUMBER - self.context.cur_holder_commitment_transaction_number == 1 {
+			// We should never have to worry about MonitorUpdateInProgress resending ChannelReady
+			let next_per_commitment_point = self.context.holder_signer.get_per_commitment_point(self.context.cur_holder_commitment_transaction_number, &self.context.secp_ctx);
+			Some(msgs::ChannelReady {
+				channel_id: self.context.channel_id(),
+				next_per_commitment_point,
+				short_channel_id_alias: Some(self.context.outbound_scid_alias),
+			})
+		} else { None };
+
+		if msg.next_local_commitment_number == next_counterparty_commitment_number {
+			if required_revoke.is_some() {
+				log_debug!(logger, "Reconnected channel {} with only lost outbound RAA", log_bytes!(self.context.channel_id()));
+			} else {
+				log_debug!(logger, "Reconnected channel {} with no loss", log_bytes!(self.context.channel_id()));
+			}
+
+			// A pending splice may require us to resend our commitment_signed and/or tx_signatures
+			// for its new funding transaction, as well as our splice_locked.
+			let (commitment_update, tx_signatures) = self.get_splice_retransmissions(msg, logger)?;
+			let splice_locked = if let Some(splice_txid) = self.context.pending_splice.as_ref().and_then(|splice| splice.sent_splice_locked) {
+				Some(msgs::SpliceLocked { channel_id: self.context.channel_id, splice_txid })
+			} else if self.context.splice_locked_pending_ack {
+				Some(msgs::SpliceLocked { channel_id: self.context.channel_id, splice_txid: self.context.get_funding_txo().unwrap().txid })
+			} else { None };
+
+			Ok(ReestablishResponses {
+				channel_ready, shutdown_msg, announcement_sigs,
+				raa: required_revoke,
+				commitment_update,
+				order: self.context.resend_order.clone(),
+				tx_signatures,
+				splice_locked,
+				monitor_update,
+			})
+		} else if msg.next_local_commitment_number == next_counterparty_commitment_number - 1 {
+			if required_revoke.is_some() {
+				log_debug!(logger, "Reconnected channel {} with lost outbound RAA and lost remote commitment tx", log_bytes!(self.context.channel_id()));
+			} else {
+				log_debug!(logger, "Reconnected channel {} with only lost remote commitment tx", log_bytes!(self.context.channel_id()));
+			}
+
+			if self.context.channel_state & (ChannelState::MonitorUpdateInProgress as u32) != 0 {
+				self.context.monitor_pending_commitment_signed = true;
+				Ok(ReestablishResponses {
+					channel_ready, shutdown_msg, announcement_sigs,
+					commitment_update: None, raa: None,
+					order: self.context.resend_order.clone(),
+					tx_signatures: None,
+					splice_locked: None,
+					monitor_update,
+				})
+			} else {
+				Ok(ReestablishResponses {
+					channel_ready, shutdown_msg, announcement_sigs,
+					raa: required_revoke,
+					commitment_update: Some(self.get_last_commitment_update(logger)),
+					order: self.context.resend_order.clone(),
+					tx_signatures: None,
+					splice_locked: None,
+					monitor_update,
+				})
+			}
+		} else {
+			Err(ChannelError::Close("Peer attempted to reestablish channel with a very old remote commitment transaction".to_owned()))
+		}
+	}
+
+	/// Calculates and returns our minimum and maximum closing transaction fee amounts, in whole
+	/// satoshis. The amounts remain consistent unless a peer disconnects/reconnects or we restart,
+	/// at which point they will be recalculated.
+	fn calculate_closing_fee_limits<F: Deref>(&mut self, fee_estimator: &LowerBoundedFeeEstimator<F>)
+		-> (u64, u64)
+		where F::Target: FeeEstimator
+	{
+		if let Some((min, max)) = self.context.closing_fee_limits { return (min, max); }
+
+		// Propose a range from our current Background feerate to our Normal feerate plus our
+		// force_close_avoidance_max_fee_satoshis.
+		// If we fail to come to consensus, we'll have to force-close.
+		let mut proposed_feerate = fee_estimator.bounded_sat_per_1000_weight(ConfirmationTarget::Background);
+		let normal_feerate = fee_estimator.bounded_sat_per_1000_weight(ConfirmationTarget::Normal);
+		let mut proposed_max_feerate = if self.context.is_outbound() { normal_feerate } else { u32::max_value() };
+
+		// The spec requires that (when the channel does not have anchors) we only send absolute
+		// channel fees no greater than the absolute channel fee on the current commitment
+		// transaction. It's unclear *which* commitment transaction this refers to, and there isn't
+		// very good reason to apply such a limit in any case. We don't bother doing so, risking
+		// some force-closure by old nodes, but we wanted to close the channel anyway.
+
+		if let Some(target_feerate) = self.context.target_closing_feerate_sats_per_kw {
+			let min_feerate = if self.context.is_outbound() { target_feerate } else { cmp::min(self.context.feerate_per_kw, target_feerate) };
+			proposed_feerate = cmp::max(proposed_feerate, min_feerate);
+			proposed_max_feerate = cmp::max(proposed_max_feerate, min_feerate);
+		}
+
+		// Note that technically we could end up with a lower minimum fee if one sides' balance is
+		// below our dust limit, causing the output to disappear. We don't bother handling this
+		// case, however, as this should only happen if a channel is closed before any (material)
+		// payments have been made on it. This may cause slight fee overpayment and/or failure to
+		// come to consensus with our counterparty on appropriate fees, however it should be a
+		// relatively rare case. We can revisit this later, though note that in order to determine
+		// if the funders' output is dust we have to know the absolute fee we're going to use.
+		let tx_weight = self.get_closing_transaction_weight(Some(&self.get_closing_scriptpubkey()), Some(self.context.counterparty_shutdown_scriptpubkey.as_ref().unwrap()));
+		let proposed_total_fee_satoshis = proposed_feerate as u64 * tx_weight / 1000;
+		let proposed_max_total_fee_satoshis = if self.context.is_outbound() {
+				// We always add force_close_avoidance_max_fee_satoshis to our normal
+				// feerate-calculated fee, but allow the max to be overridden if we're using a
+				// target feerate-calculated fee.
+				cmp::max(normal_feerate as u64 * tx_weight / 1000 + self.context.config.options.force_close_avoidance_max_fee_satoshis,
+					proposed_max_feerate as u64 * tx_weight / 1000)
+			} else {
+				self.context.channel_value_satoshis - (self.context.value_to_self_msat + 999) / 1000
+			};
+
+		self.context.closing_fee_limits = Some((proposed_total_fee_satoshis, proposed_max_total_fee_satoshis));
+		self.context.closing_fee_limits.clone().unwrap()
+	}
+
+	/// Returns true if we're ready to commence the closing_signed negotiation phase. This is true
+	/// after both sides have exchanged a `shutdown` message and all HTLCs have been drained. At
+	/// this point if we're the funder we should send the initial closing_signed, and in any case
+	/// shutdown should complete within a reasonable timeframe.
+	fn closing_negotiation_ready(&self) -> bool {
+		self.context.closing_negotiation_ready()
+	}
+
+	/// Checks if the closing_signed negotiation is making appropriate progress, possibly returning
+	/// an Err if no progress is being made and the channel should be force-closed instead.
+	/// Should be called on a one-minute timer.
+	pub fn timer_check_closing_negotiation_progress(&mut self) -> Result<(), ChannelError> {
+		if self.closing_negotiation_ready() {
+			if self.context.closing_signed_in_flight {
+				return Err(ChannelError::Close("closing_signed negotiation failed to finish within two timer ticks".to_owned()));
+			} else {
+				self.context.closing_signed_in_flight = true;
+			}
+		}
+		Ok(())
+	}
+
+	pub fn maybe_propose_closing_signed<F: Deref, L: Deref>(
+		&mut self, fee_estimator: &LowerBoundedFeeEstimator<F>, logger: &L)
+		-> Result<(Option<msgs::ClosingSigned>, Option<Transaction>), ChannelError>
+		where F::Target: FeeEstimator, L::Target: Logger
+	{
+		if self.context.last_sent_closing_fee.is_some() || !self.closing_negotiation_ready() {
+			return Ok((None, None));
+		}
+
+		if !self.context.is_outbound() {
+			if let Some(msg) = &self.context.pending_counterparty_closing_signed.take() {
+				return self.closing_signed(fee_estimator, &msg);
+			}
+			return Ok((None, None));
+		}
+
+		let (our_min_fee, our_max_fee) = self.calculate_closing_fee_limits(fee_estimator);
+
+		assert!(self.context.shutdown_scriptpubkey.is_some());
+		let (closing_tx, total_fee_satoshis) = self.build_closing_transaction(our_min_fee, false);
+		log_trace!(logger, "Proposing initial closing_signed for our counterparty with a fee range of {}-{} sat (with initial proposal {} sats)",
+			our_min_fee, our_max_fee, total_fee_satoshis);
+
+		let sig = self.context.holder_signer
+			.sign_closing_transaction(&closing_tx, &self.context.secp_ctx)
+			.map_err(|()| ChannelError::Close("Failed to get signature for closing transaction.".to_owned()))?;
+
+		self.context.last_sent_closing_fee = Some((total_fee_satoshis, sig.clone()));
+		Ok((Some(msgs::ClosingSigned {
+			channel_id: self.context.channel_id,
+			fee_satoshis: total_fee_satoshis,
+			signature: sig,
+			fee_range: Some(msgs::ClosingSignedFeeRange {
+				min_fee_satoshis: our_min_fee,
+				max_fee_satoshis: our_max_fee,
+			}),
+		}), None))
+	}
+
+	// Marks a channel as waiting for a response from the counterparty. If it's not received
+	// [`DISCONNECT_PEER_AWAITING_RESPONSE_TICKS`] after sending our own to them, then we'll attempt
+	// a reconnection.
+	fn mark_awaiting_response(&mut self) {
+		self.context.sent_message_awaiting_response = Some(0);
+	}
+
+	/// Determines whether we should disconnect the counterparty due to not receiving a response
+	/// within our expected timeframe.
+	///
+	/// This should be called on every [`super::channelmanager::ChannelManager::timer_tick_occurred`].
+	pub fn should_disconnect_peer_awaiting_response(&mut self) -> bool {
+		let ticks_elapsed = if let Some(ticks_elapsed) = self.context.sent_message_awaiting_response.as_mut() {
+			ticks_elapsed
+		} else {
+			// Don't disconnect when we're not waiting on a response.
+			return false;
+		};
+		*ticks_elapsed += 1;
+		*ticks_elapsed >= DISCONNECT_PEER_AWAITING_RESPONSE_TICKS
+	}
+
+	// Quiescence
+
+	/// Determines whether we should disconnect the counterparty as the channel has not become
+	/// quiescent within our expected timeframe after either side proposed it, or a channel type
+	/// upgrade run while quiescent did not progress in time.
+	///
+	/// This should be called on every [`super::channelmanager::ChannelManager::timer_tick_occurred`].
+	pub fn should_disconnect_peer_awaiting_quiescence(&mut self) -> bool {
+		if self.context.channel_state & (ChannelState::PeerDisconnected as u32) != 0 {
+			return false;
+		}
+		let ticks_elapsed = if let Some(ticks_elapsed) = self.context.quiescence_timer_ticks.as_mut() {
+			ticks_elapsed
+		} else {
+			return false;
+		};
+		*ticks_elapsed += 1;
+		*ticks_elapsed >= DISCONNECT_PEER_AWAITING_QUIESCENCE_TICKS
+	}
+
+	/// Returns true if any update to the channel has yet to be irrevocably committed by both
+	/// sides, in which case we can't send `stfu` yet.
+	fn has_pending_channel_update(&self) -> bool {
+		self.context.channel_state & (ChannelState::AwaitingRemoteRevoke as u32 | ChannelState::MonitorUpdateInProgress as u32) != 0 ||
+			self.context.monitor_pending_revoke_and_ack || self.context.monitor_pending_commitment_signed ||
+			self.context.pending_update_fee.is_some() ||
+			self.context.pending_inbound_htlcs.iter().any(|htlc| !matches!(htlc.state, InboundHTLCState::Committed)) ||
+			self.context.pending_outbound_htlcs.iter().any(|htlc| !matches!(htlc.state, OutboundHTLCState::Committed))
+	}
+
+	/// Begins making the channel quiescent, returning the `stfu` message to send to our
+	/// counterparty if we don't have to wait on any pending updates first. Otherwise, our `stfu`
+	/// will be sent by [`Self::try_send_stfu`] once those updates have been committed.
+	pub fn propose_quiescence<L: Deref>(&mut self, logger: &L) -> Result<Option<msgs::Stfu>, APIError>
+	where L::Target: Logger {
+		if !self.context.is_live() {
+			return Err(APIError::ChannelUnavailable {
+				err: format!("Channel {} cannot be made quiescent while disconnected or shutting down", log_bytes!(self.context.channel_id)),
+			});
+		}
+		if self.context.channel_state & QUIESCENCE_STATE_FLAGS != 0 {
+			return Err(APIError::APIMisuseError {
+				err: format!("Channel {} is already quiescent or awaiting quiescence", log_bytes!(self.context.channel_id)),
+			});
+		}
+		if self.context.pending_splice.as_ref().map_or(false, |splice| splice.pending_request.is_some() || splice.unsigned_candidate().is_some()) {
+			return Err(APIError::ChannelUnavailable {
+				err: format!("Channel {} cannot be made quiescent while a splice transaction is being negotiated", log_bytes!(self.context.channel_id)),
+			});
+		}
+		log_debug!(logger, "Proposing quiescence for channel {}", log_bytes!(self.context.channel_id));
+		self.context.channel_state |= ChannelState::AwaitingQuiescence as u32;
+		self.context.quiescence_timer_ticks = Some(0);
+		Ok(self.try_send_stfu(logger))
+	}
+
+	/// Returns the `stfu` message to send to our counterparty if we owe them one and all pending
+	/// updates to the channel have been irrevocably committed.
+	pub fn try_send_stfu<L: Deref>(&mut self, logger: &L) -> Option<msgs::Stfu> where L::Target: Logger {
+		if !self.context.is_live() ||
+			self.context.channel_state & (ChannelState::AwaitingQuiescence as u32 | ChannelState::RemoteStfuSent as u32) == 0 ||
+			self.context.channel_state & (ChannelState::LocalStfuSent as u32 | ChannelState::Quiescent as u32) != 0 ||
+			self.has_pending_channel_update()
+		{
+			return None;
+		}
+
+		let initiator = if self.context.channel_state & (ChannelState::RemoteStfuSent as u32) != 0 {
+			// Our counterparty has already sent their `stfu`, so ours completes the handshake.
+			self.context.channel_state &= !(ChannelState::AwaitingQuiescence as u32 | ChannelState::RemoteStfuSent as u32);
+			self.context.channel_state |= ChannelState::Quiescent as u32;
+			self.context.quiescence_timer_ticks = None;
+			log_debug!(logger, "Channel {} is now quiescent", log_bytes!(self.context.channel_id));
+			self.context.is_holder_quiescence_initiator.unwrap_or(false)
+		} else {
+			self.context.channel_state &= !(ChannelState::AwaitingQuiescence as u32);
+			self.context.channel_state |= ChannelState::LocalStfuSent as u32;
+			self.context.is_holder_quiescence_initiator = Some(true);
+			true
+		};
+		log_debug!(logger, "Sending stfu for channel {}", log_bytes!(self.context.channel_id));
+		Some(msgs::Stfu { channel_id: self.context.channel_id, initiator })
+	}
+
+	/// Handles an `stfu` message from our counterparty, returning our own `stfu` to send in
+	/// response if we're able to do so immediately.
+	pub fn stfu<L: Deref>(&mut self, msg: &msgs::Stfu, logger: &L) -> Result<Option<msgs::Stfu>, ChannelError>
+	where L::Target: Logger {
+		if self.context.channel_state & (ChannelState::PeerDisconnected as u32) == ChannelState::PeerDisconnected as u32 {
+			return Err(ChannelError::Close("Peer sent stfu when we needed a channel_reestablish".to_owned()));
+		}
+		if !self.context.is_usable() {
+			return Err(ChannelError::WarnAndDisconnect("Peer sent stfu for a channel which is not usable".to_owned()));
+		}
+		if self.context.channel_state & (ChannelState::RemoteStfuSent as u32 | ChannelState::Quiescent as u32) != 0 {
+			return Err(ChannelError::WarnAndDisconnect("Peer sent stfu while the channel was already quiescent".to_owned()));
+		}
+
+		if self.context.channel_state & (ChannelState::LocalStfuSent as u32) == 0 {
+			if !msg.initiator {
+				return Err(ChannelError::WarnAndDisconnect("Peer responded with stfu we never sent".to_owned()));
+			}
+			// If we were also waiting to propose quiescence, the funder of the channel gets to be the
+			// initiator.
+			let awaiting_quiescence = self.context.channel_state & (ChannelState::AwaitingQuiescence as u32) != 0;
+			self.context.is_holder_quiescence_initiator = Some(awaiting_quiescence && self.context.is_outbound());
+			self.context.channel_state |= ChannelState::RemoteStfuSent as u32;
+			if !awaiting_quiescence {
+				self.context.quiescence_timer_ticks = Some(0);
+			}
+			log_debug!(logger, "Received stfu proposing quiescence for channel {}", log_bytes!(self.context.channel_id));
+			return Ok(self.try_send_stfu(logger));
+		}
+
+		// We've already sent our `stfu`, so we were the one proposing quiescence, unless both of
+		// us did so concurrently, in which case the funder of the channel is the initiator.
+		if self.has_pending_channel_update() {
+			return Err(ChannelError::WarnAndDisconnect("Peer sent stfu while channel updates were pending".to_owned()));
+		}
+		self.context.is_holder_quiescence_initiator = Some(!msg.initiator || self.context.is_outbound());
+		self.context.channel_state &= !(ChannelState::LocalStfuSent as u32);
+		self.context.channel_state |= ChannelState::Quiescent as u32;
+		self.context.quiescence_timer_ticks = None;
+		log_debug!(logger, "Channel {} is now quiescent", log_bytes!(self.context.channel_id));
+		Ok(None)
+	}
+
+	/// Checks that our counterparty isn't sending us an update after they've sent `stfu`.
+	fn check_update_during_quiescence(&self, msg_name: &str) -> Result<(), ChannelError> {
+		if self.context.channel_state & (ChannelState::RemoteStfuSent as u32 | ChannelState::Quiescent as u32) != 0 {
+			return Err(ChannelError::WarnAndDisconnect(format!("Peer sent {} while the channel was quiescent", msg_name)));
+		}
+		Ok(())
+	}
+
+	/// Exits quiescence on behalf of a protocol run outside of the channel once it has completed,
+	/// allowing updates to the channel to resume.
+	pub fn unquiesce<L: Deref>(&mut self, logger: &L) -> Result<(), APIError> where L::Target: Logger {
+		if self.context.channel_state & (ChannelState::Quiescent as u32) == 0 {
+			return Err(APIError::APIMisuseError {
+				err: format!("Channel {} is not quiescent", log_bytes!(self.context.channel_id)),
+			});
+		}
+		if matches!(self.context.pending_channel_type_upgrade, Some(ref upgrade) if upgrade.state != ChannelTypeUpgradeState::Requested) {
+			return Err(APIError::APIMisuseError {
+				err: format!("Channel {} is quiescent to upgrade its channel type", log_bytes!(self.context.channel_id)),
+			});
+		}
+		if self.context.pending_splice.as_ref().map_or(false, |splice| splice.pending_request.is_some() || splice.unsigned_candidate().is_some()) {
+			return Err(APIError::APIMisuseError {
+				err: format!("Channel {} is quiescent to negotiate a splice transaction", log_bytes!(self.context.channel_id)),
+			});
+		}
+		log_debug!(logger, "Exiting quiescence for channel {}", log_bytes!(self.context.channel_id));
+		self.exit_quiescence();
+		Ok(())
+	}
+
+	/// Exits quiescence once the protocol which required it has completed, allowing updates to the
+	/// channel to resume. If we still want to upgrade the channel's type or negotiate a splice
+	/// transaction, e.g. as our counterparty was the quiescence initiator, quiescence is proposed
+	/// again.
+	fn exit_quiescence(&mut self) {
+		self.context.channel_state &= !QUIESCENCE_STATE_FLAGS;
+		self.context.is_holder_quiescence_initiator = None;
+		self.context.quiescence_timer_ticks = None;
+		if self.context.pending_channel_type_upgrade.as_ref().map_or(false, |upgrade| upgrade.state == ChannelTypeUpgradeState::Requested) ||
+			self.context.pending_splice.as_ref().map_or(false, |splice| splice.pending_request.as_ref().map_or(false, |request| !request.proposed))
+		{
+			self.context.channel_state |= ChannelState::AwaitingQuiescence as u32;
+			self.context.quiescence_timer_ticks = Some(0);
+		}
+	}
+
+	// Channel type upgrades
+
+	/// Checks whether the channel can be upgraded to the given channel type, which must be its
+	/// current type with anchor outputs added. We only upgrade channels without any HTLCs, so that
+	/// no HTLC outputs need to be claimed from the commitment transactions using the old type.
+	fn check_channel_type_upgrade(&self, channel_type: &ChannelTypeFeatures) -> Result<(), String> {
+		if self.context.channel_type.supports_anchors_zero_fee_htlc_tx() {
+			return Err("Channel already uses anchor outputs".to_owned());
+		}
+		let mut anchors_channel_type = self.context.channel_type.clone();
+		anchors_channel_type.set_anchors_zero_fee_htlc_tx_required();
+		if *channel_type != anchors_channel_type {
+			return Err(format!("Upgrading to channel type {} is not supported", channel_type));
+		}
+		if !self.context.pending_inbound_htlcs.is_empty() || !self.context.pending_outbound_htlcs.is_empty() {
+			return Err("Channel type cannot be upgraded while HTLCs are pending".to_owned());
+		}
+		if self.context.pending_splice.is_some() {
+			return Err("Channel type cannot be upgraded while a splice is pending".to_owned());
+		}
+		if !self.can_funder_afford_channel_type(channel_type) {
+			return Err(format!("Funder cannot afford the commitment transaction fee of channel type {}", channel_type));
+		}
+		Ok(())
+	}
+
+	/// Returns whether the funder of the channel can afford the fee of a commitment transaction
+	/// without HTLCs using the given channel type while keeping its reserve.
+	fn can_funder_afford_channel_type(&self, channel_type: &ChannelTypeFeatures) -> bool {
+		let fee_msat = (commit_tx_fee_sat(self.context.feerate_per_kw, 0, channel_type) +
+			if channel_type.supports_anchors_zero_fee_htlc_tx() { ANCHOR_OUTPUT_VALUE_SATOSHI * 2 } else { 0 }) * 1000;
+		let (funder_balance_msat, funder_reserve_msat) = if self.context.is_outbound() {
+			(self.context.value_to_self_msat, self.context.counterparty_selected_channel_reserve_satoshis.unwrap_or(0) * 1000)
+		} else {
+			(self.context.channel_value_satoshis * 1000 - self.context.value_to_self_msat, self.context.holder_selected_channel_reserve_satoshis * 1000)
+		};
+		funder_balance_msat >= fee_msat + funder_reserve_msat
+	}
+
+	/// Derives the signer to use once the channel has been upgraded to the given channel type.
+	fn derive_channel_type_upgrade_signer<SP: Deref>(
+		&self, channel_type: &ChannelTypeFeatures, signer_provider: &SP
+	) -> Signer where SP::Target: SignerProvider<Signer = Signer> {
+		let mut holder_signer = signer_provider.derive_channel_signer(self.context.channel_value_satoshis, self.context.channel_keys_id);
+		let mut channel_parameters = self.context.channel_transaction_parameters.clone();
+		channel_parameters.channel_type_features = channel_type.clone();
+		holder_signer.provide_channel_parameters(&channel_parameters);
+		holder_signer
+	}
+
+	/// Requests an upgrade of the channel to anchor outputs, returning the `stfu` message to send
+	/// to our counterparty if the channel can begin becoming quiescent immediately. Once it is
+	/// quiescent with us as the initiator, [`Self::maybe_propose_channel_type_upgrade`] returns
+	/// the `dyn_propose` message proposing the upgrade.
+	pub fn upgrade_to_anchors<L: Deref>(&mut self, logger: &L) -> Result<Option<msgs::Stfu>, APIError>
+	where L::Target: Logger {
+		if self.context.pending_channel_type_upgrade.is_some() {
+			return Err(APIError::APIMisuseError {
+				err: format!("Channel {} already has a pending channel type upgrade", log_bytes!(self.context.channel_id)),
+			});
+		}
+		let mut channel_type = self.context.channel_type.clone();
+		channel_type.set_anchors_zero_fee_htlc_tx_required();
+		self.check_channel_type_upgrade(&channel_type)
+			.map_err(|err| APIError::ChannelUnavailable { err })?;
+		let stfu = self.propose_quiescence(logger)?;
+		log_info!(logger, "Requesting upgrade of channel {} to channel type {}", log_bytes!(self.context.channel_id), channel_type);
+		self.context.pending_channel_type_upgrade = Some(PendingChannelTypeUpgrade {
+			channel_type,
+			state: ChannelTypeUpgradeState::Requested,
+			holder_signer: None,
+		});
+		Ok(stfu)
+	}
+
+	/// Returns the `dyn_propose` message to send to our counterparty if we requested an upgrade of
+	/// the channel's type and the channel just became quiescent with us as the initiator.
+	pub fn maybe_propose_channel_type_upgrade<L: Deref>(&mut self, logger: &L) -> Option<msgs::DynPropose>
+	where L::Target: Logger {
+		if self.context.channel_state & (ChannelState::Quiescent as u32) == 0 ||
+			self.context.is_holder_quiescence_initiator != Some(true)
+		{
+			return None;
+		}
+		let channel_type = match self.context.pending_channel_type_upgrade.as_ref() {
+			Some(upgrade) if upgrade.state == ChannelTypeUpgradeState::Requested => upgrade.channel_type.clone(),
+			_ => return None,
+		};
+		// HTLCs may have been added while the channel was becoming quiescent.
+		if let Err(err) = self.check_channel_type_upgrade(&channel_type) {
+			log_info!(logger, "Abandoning upgrade of channel {} to channel type {}: {}",
+				log_bytes!(self.context.channel_id), channel_type, err);
+			self.context.pending_channel_type_upgrade = None;
+			self.exit_quiescence();
+			return None;
+		}
+		log_debug!(logger, "Proposing upgrade of channel {} to channel type {}", log_bytes!(self.context.channel_id), channel_type);
+		self.context.pending_channel_type_upgrade.as_mut().unwrap().state = ChannelTypeUpgradeState::Proposed;
+		self.context.quiescence_timer_ticks = Some(0);
+		Some(msgs::DynPropose { channel_id: self.context.channel_id, channel_type })
+	}
+
+	/// Handles a `dyn_propose` from our counterparty, returning the `dyn_ack` to send in response
+	/// if we accept the upgrade. We switch over to the new channel type once we receive our
+	/// counterparty's `commitment_signed` for it.
+	///
+	/// A [`ChannelError::Warn`] indicates that we reject the upgrade, which should be communicated
+	/// to our counterparty with a `dyn_reject`. The channel is no longer quiescent in that case.
+	pub fn dyn_propose<SP: Deref, L: Deref>(
+		&mut self, msg: &msgs::DynPropose, signer_provider: &SP, user_config: &UserConfig,
+		has_anchor_channel_reserve: bool, logger: &L
+	) -> Result<msgs::DynAck, ChannelError>
+	where SP::Target: SignerProvider<Signer = Signer>, L::Target: Logger {
+		if self.context.channel_state & (ChannelState::PeerDisconnected as u32) == ChannelState::PeerDisconnected as u32 {
+			return Err(ChannelError::Close("Peer sent dyn_propose when we needed a channel_reestablish".to_owned()));
+		}
+		if self.context.channel_state & (ChannelState::Quiescent as u32) == 0 ||
+			self.context.is_holder_quiescence_initiator != Some(false) ||
+			self.context.pending_channel_type_upgrade.as_ref().map_or(false, |upgrade| upgrade.state != ChannelTypeUpgradeState::Requested)
+		{
+			return Err(ChannelError::WarnAndDisconnect("Peer sent dyn_propose without being the quiescence initiator".to_owned()));
+		}
+		let check_res = if !user_config.channel_handshake_config.negotiate_anchors_zero_fee_htlc_tx {
+			Err("We do not accept channels with anchor outputs".to_owned())
+		} else if !has_anchor_channel_reserve {
+			Err("Insufficient on-chain funds to bump the transactions of another anchor channel".to_owned())
+		} else {
+			self.check_channel_type_upgrade(&msg.channel_type)
+		};
+		if let Err(err) = check_res {
+			self.exit_quiescence();
+			return Err(ChannelError::Warn(err));
+		}
+
+		log_debug!(logger, "Accepting upgrade of channel {} to channel type {}", log_bytes!(self.context.channel_id), msg.channel_type);
+		let holder_signer = self.derive_channel_type_upgrade_signer(&msg.channel_type, signer_provider);
+		self.context.pending_channel_type_upgrade = Some(PendingChannelTypeUpgrade {
+			channel_type: msg.channel_type.clone(),
+			state: ChannelTypeUpgradeState::Accepted,
+			holder_signer: Some(holder_signer),
+		});
+		self.context.quiescence_timer_ticks = Some(0);
+		Ok(msgs::DynAck { channel_id: self.context.channel_id })
+	}
+
+	/// Handles a `dyn_ack` from our counterparty, switching the channel over to the channel type
+	/// we proposed. Quiescence ends here, and the [`ChannelMonitorUpdate`] returned, if any, comes
+	/// with our `commitment_signed` for the first commitment transaction using the new type.
+	pub fn dyn_ack<SP: Deref, L: Deref>(
+		&mut self, msg: &msgs::DynAck, signer_provider: &SP, logger: &L
+	) -> Result<Option<ChannelMonitorUpdate>, ChannelError>
+	where SP::Target: SignerProvider<Signer = Signer>, L::Target: Logger {
+		if self.context.channel_state & (ChannelState::PeerDisconnected as u32) == ChannelState::PeerDisconnected as u32 {
+			return Err(ChannelError::Close("Peer sent dyn_ack when we needed a channel_reestablish".to_owned()));
+		}
+		let channel_type = match self.context.pending_channel_type_upgrade.as_ref() {
+			Some(upgrade) if upgrade.state == ChannelTypeUpgradeState::Proposed => upgrade.channel_type.clone(),
+			_ => return Err(ChannelError::WarnAndDisconnect("Peer sent dyn_ack for a channel type upgrade we did not propose".to_owned())),
+		};
+		debug_assert_eq!(msg.channel_id, self.context.channel_id);
+
+		// Our counterparty may not have persisted their acceptance of the upgrade before we get
+		// disconnected, so we hold on to our previous signer in case we have to revert to it upon
+		// reestablishing the channel.
+		let holder_signer = self.derive_channel_type_upgrade_signer(&channel_type, signer_provider);
+		let previous_holder_signer = self.context.apply_channel_type_upgrade(channel_type.clone(), holder_signer);
+		let upgrade = self.context.pending_channel_type_upgrade.as_mut().unwrap();
+		upgrade.state = ChannelTypeUpgradeState::AwaitingCommitment;
+		upgrade.holder_signer = Some(previous_holder_signer);
+		self.exit_quiescence();
+		log_debug!(logger, "Counterparty accepted upgrade of channel {} to channel type {}, sending commitment_signed",
+			log_bytes!(self.context.channel_id), channel_type);
+
+		let monitor_update = self.build_commitment_no_status_check(logger);
+		self.monitor_updating_paused(false, true, false, Vec::new(), Vec::new(), Vec::new());
+		Ok(self.push_ret_blockable_mon_update(monitor_update))
+	}
+
+	/// Handles a `dyn_reject` from our counterparty, abandoning the channel type upgrade we
+	/// proposed.
+	pub fn dyn_reject<L: Deref>(&mut self, msg: &msgs::DynReject, logger: &L) -> Result<(), ChannelError>
+	where L::Target: Logger {
+		if self.context.channel_state & (ChannelState::PeerDisconnected as u32) == ChannelState::PeerDisconnected as u32 {
+			return Err(ChannelError::Close("Peer sent dyn_reject when we needed a channel_reestablish".to_owned()));
+		}
+		let channel_type = match self.context.pending_channel_type_upgrade.take() {
+			Some(upgrade) if upgrade.state == ChannelTypeUpgradeState::Proposed => upgrade.channel_type,
+			upgrade => {
+				self.context.pending_channel_type_upgrade = upgrade;
+				return Err(ChannelError::WarnAndDisconnect("Peer sent dyn_reject for a channel type upgrade we did not propose".to_owned()));
+			},
+		};
+		log_info!(logger, "Counterparty rejected upgrade of channel {} to channel type {}: {}", log_bytes!(self.context.channel_id),
+			channel_type, PrintableString(&String::from_utf8_lossy(&msg.data)));
+		self.exit_quiescence();
+		Ok(())
+	}
+
+	pub fn shutdown<SP: Deref>(
+		&mut self, signer_provider: &SP, their_features: &InitFeatures, msg: &msgs::Shutdown
+	) -> Result<(Option<msgs::Shutdown>, Option<ChannelMonitorUpdate>, Vec<(HTLCSource, PaymentHash)>), ChannelError>
+	where SP::Target: SignerProvider
+	{
+		if self.context.channel_state & (ChannelState::PeerDisconnected as u32) == ChannelState::PeerDisconnected as u32 {
+			return Err(ChannelError::Close("Peer sent shutdown when we needed a channel_reestablish".to_owned()));
+		}
+		self.check_update_during_quiescence("shutdown")?;
+		if self.context.channel_state < ChannelState::FundingSent as u32 {
+			// Spec says we should fail the connection, not the channel, but that's nonsense, there
+			// are plenty of reasons you may want to fail a channel pre-funding, and spec says you
+			// can do that via error message without getting a connection fail anyway...
+			return Err(ChannelError::Close("Peer sent shutdown pre-funding generation".to_owned()));
+		}
+		if self.context.is_awaiting_batch_broadcast() {
+			// The batch funding transaction was never broadcast, so there is nothing to cooperatively
+			// close. Failing the channel will fail the rest of the batch along with it.
+			return Err(ChannelError::Close("Peer sent shutdown before the batch funding transaction was broadcast".to_owned()));
+		}
+		for htlc in self.context.pending_inbound_htlcs.iter() {
+			if let InboundHTLCState::RemoteAnnounced(_) = htlc.state {
+				return Err(ChannelError::Close("Got shutdown with remote pending HTLCs".to_owned()));
+			}
+		}
+		assert_eq!(self.context.channel_state & ChannelState::ShutdownComplete as u32, 0);
+		match &self.context.pending_splice {
+			// Our counterparty sent their shutdown before we could propose our splice, which we give
+			// up on.
+			Some(splice) if splice.candidates.is_empty() => self.context.pending_splice = None,
+			Some(_) => return Err(ChannelError::Warn("Got shutdown while a splice was pending".to_owned())),
+			None => {},
+		}
+
+		if !script::is_bolt2_compliant(&msg.scriptpubkey, their_features) {
+			return Err(ChannelError::Warn(format!("Got a nonstandard scriptpubkey ({}) from remote peer", msg.scriptpubkey.to_bytes().to_hex())));
+		}
+
+		if self.context.counterparty_shutdown_scriptpubkey.is_some() {
+			if Some(&msg.scriptpubkey) != self.context.counterparty_shutdown_scriptpubkey.as_ref() {
+				return Err(ChannelError::Warn(format!("Got shutdown request with a scriptpubkey ({}) which did not match their previous scriptpubkey.", msg.scriptpubkey.to_bytes().to_hex())));
+			}
+		} else {
+			self.context.counterparty_shutdown_scriptpubkey = Some(msg.scriptpubkey.clone());
+		}
+
+		// If we have any LocalAnnounced updates we'll probably just get back an update_fail_htlc
+		// immediately after the commitment dance, but we can send a Shutdown because we won't send
+		// any further commitment updates after we set LocalShutdownSent.
+		let send_shutdown = (self.context.channel_state & ChannelState::LocalShutdownSent as u32) != ChannelState::LocalShutdownSent as u32;
+
+		let update_shutdown_script = match self.context.shutdown_scriptpubkey {
+			Some(_) => false,
+			None => {
+				assert!(send_shutdown);
+				let shutdown_scriptpubkey = match signer_provider.get_shutdown_scriptpubkey() {
+					Ok(scriptpubkey) => scriptpubkey,
+					Err(_) => return Err(ChannelError::Close("Failed to get shutdown scriptpubkey".to_owned())),
+				};
+				if !shutdown_scriptpubkey.is_compatible(their_features) {
+					return Err(ChannelError::Close(format!("Provided a scriptpubkey format not accepted by peer: {}", shutdown_scriptpubkey)));
+				}
+				self.context.shutdown_scriptpubkey = Some(shutdown_scriptpubkey);
+				true
+			},
+		};
+
+		// From here on out, we may not fail!
+
+		self.context.channel_state |= ChannelState::RemoteShutdownSent as u32;
 		self.context.update_time_counter += 1;
-		// If the feerate has increased over the previous dust buffer (note that
-		// `get_dust_buffer_feerate` considers the `pending_update_fee` status), check that we
-		// won't be pushed over our dust exposure limit by the feerate increase.
-		if feerate_over_dust_buffer {
-			let inbound_stats = self.context.get_inbound_pending_htlc_stats(None);
-			let outbound_stats = self.context.get_outbound_pending_htlc_stats(None);
-			let holder_tx_dust_exposure = inbound_stats.on_holder_tx_dust_exposure_msat + outbound_stats.on_holder_tx_dust_exposure_msat;
-			let counterparty_tx_dust_exposure = inbound_stats.on_counterparty_tx_dust_exposure_msat + outbound_stats.on_counterparty_tx_dust_exposure_msat;
-			let max_dust_htlc_exposure_msat = self.context.get_max_dust_htlc_exposure_msat(fee_estimator);
-			if holder_tx_dust_exposure > max_dust_htlc_exposure_msat {
-				return Err(ChannelError::Close(format!("Peer sent update_fee with a feerate ({}) which may over-expose us to dust-in-flight on our own transactions (totaling {} msat)",
-					msg.feerate_per_kw, holder_tx_dust_exposure)));
+
+		let monitor_update = if update_shutdown_script {
+			self.context.latest_monitor_update_id += 1;
+			let monitor_update = ChannelMonitorUpdate {
+				update_id: self.context.latest_monitor_update_id,
+				updates: vec![ChannelMonitorUpdateStep::ShutdownScript {
+					scriptpubkey: self.get_closing_scriptpubkey(),
+				}],
+			};
+			self.monitor_updating_paused(false, false, false, Vec::new(), Vec::new(), Vec::new());
+			self.push_ret_blockable_mon_update(monitor_update)
+		} else { None };
+		let shutdown = if send_shutdown {
+			Some(msgs::Shutdown {
+				channel_id: self.context.channel_id,
+				scriptpubkey: self.get_closing_scriptpubkey(),
+			})
+		} else { None };
+
+		// We can't send our shutdown until we've committed all of our pending HTLCs, but the
+		// remote side is unlikely to accept any new HTLCs, so we go ahead and "free" any holding
+		// cell HTLCs and return them to fail the payment.
+		self.context.holding_cell_update_fee = None;
+		let mut dropped_outbound_htlcs = Vec::with_capacity(self.context.holding_cell_htlc_updates.len());
+		self.context.holding_cell_htlc_updates.retain(|htlc_update| {
+			match htlc_update {
+				&HTLCUpdateAwaitingACK::AddHTLC { ref payment_hash, ref source, .. } => {
+					dropped_outbound_htlcs.push((source.clone(), payment_hash.clone()));
+					false
+				},
+				_ => true
 			}
-			if counterparty_tx_dust_exposure > max_dust_htlc_exposure_msat {
-				return Err(ChannelError::Close(format!("Peer sent update_fee with a feerate ({}) which may over-expose us to dust-in-flight on our counterparty's transactions (totaling {} msat)",
-					msg.feerate_per_kw, counterparty_tx_dust_exposure)));
+		});
+
+		self.context.channel_state |= ChannelState::LocalShutdownSent as u32;
+		self.context.update_time_counter += 1;
+
+		Ok((shutdown, monitor_update, dropped_outbound_htlcs))
+	}
+
+	fn build_signed_closing_transaction(&self, closing_tx: &ClosingTransaction, counterparty_sig: &Signature, sig: &Signature) -> Transaction {
+		let mut tx = closing_tx.trust().built_transaction().clone();
+
+		tx.input[0].witness.push(Vec::new()); // First is the multisig dummy
+
+		let funding_key = self.context.get_holder_pubkeys().funding_pubkey.serialize();
+		let counterparty_funding_key = self.context.counterparty_funding_pubkey().serialize();
+		let mut holder_sig = sig.serialize_der().to_vec();
+		holder_sig.push(EcdsaSighashType::All as u8);
+		let mut cp_sig = counterparty_sig.serialize_der().to_vec();
+		cp_sig.push(EcdsaSighashType::All as u8);
+		if funding_key[..] < counterparty_funding_key[..] {
+			tx.input[0].witness.push(holder_sig);
+			tx.input[0].witness.push(cp_sig);
+		} else {
+			tx.input[0].witness.push(cp_sig);
+			tx.input[0].witness.push(holder_sig);
+		}
+
+		tx.input[0].witness.push(self.context.get_funding_redeemscript().into_bytes());
+		tx
+	}
+
+	pub fn closing_signed<F: Deref>(
+		&mut self, fee_estimator: &LowerBoundedFeeEstimator<F>, msg: &msgs::ClosingSigned)
+		-> Result<(Option<msgs::ClosingSigned>, Option<Transaction>), ChannelError>
+		where F::Target: FeeEstimator
+	{
+		if self.context.channel_state & BOTH_SIDES_SHUTDOWN_MASK != BOTH_SIDES_SHUTDOWN_MASK {
+			return Err(ChannelError::Close("Remote end sent us a closing_signed before both sides provided a shutdown".to_owned()));
+		}
+		if self.context.channel_state & (ChannelState::PeerDisconnected as u32) == ChannelState::PeerDisconnected as u32 {
+			return Err(ChannelError::Close("Peer sent closing_signed when we needed a channel_reestablish".to_owned()));
+		}
+		if !self.context.pending_inbound_htlcs.is_empty() || !self.context.pending_outbound_htlcs.is_empty() {
+			return Err(ChannelError::Close("Remote end sent us a closing_signed while there were still pending HTLCs".to_owned()));
+		}
+		if msg.fee_satoshis > TOTAL_BITCOIN_SUPPLY_SATOSHIS { // this is required to stop potential overflow in build_closing_transaction
+			return Err(ChannelError::Close("Remote tried to send us a closing tx with > 21 million BTC fee".to_owned()));
+		}
+
+		if self.context.is_outbound() && self.context.last_sent_closing_fee.is_none() {
+			return Err(ChannelError::Close("Remote tried to send a closing_signed when we were supposed to propose the first one".to_owned()));
+		}
+
+		if self.context.channel_state & ChannelState::MonitorUpdateInProgress as u32 != 0 {
+			self.context.pending_counterparty_closing_signed = Some(msg.clone());
+			return Ok((None, None));
+		}
+
+		let funding_redeemscript = self.context.get_funding_redeemscript();
+		let (mut closing_tx, used_total_fee) = self.build_closing_transaction(msg.fee_satoshis, false);
+		if used_total_fee != msg.fee_satoshis {
+			return Err(ChannelError::Close(format!("Remote sent us a closing_signed with a fee other than the value they can claim. Fee in message: {}. Actual closing tx fee: {}", msg.fee_satoshis, used_total_fee)));
+		}
+		let sighash = closing_tx.trust().get_sighash_all(&funding_redeemscript, self.context.channel_value_satoshis);
+
+		match self.context.secp_ctx.verify_ecdsa(&sighash, &msg.signature, &self.context.get_counterparty_pubkeys().funding_pubkey) {
+			Ok(_) => {},
+			Err(_e) => {
+				// The remote end may have decided to revoke their output due to inconsistent dust
+				// limits, so check for that case by re-checking the signature here.
+				closing_tx = self.build_closing_transaction(msg.fee_satoshis, true).0;
+				let sighash = closing_tx.trust().get_sighash_all(&funding_redeemscript, self.context.channel_value_satoshis);
+				secp_check!(self.context.secp_ctx.verify_ecdsa(&sighash, &msg.signature, self.context.counterparty_funding_pubkey()), "Invalid closing tx signature from peer".to_owned());
+			},
+		};
+
+		for outp in closing_tx.trust().built_transaction().output.iter() {
+			if !outp.script_pubkey.is_witness_program() && outp.value < MAX_STD_OUTPUT_DUST_LIMIT_SATOSHIS {
+				return Err(ChannelError::Close("Remote sent us a closing_signed with a dust output. Always use segwit closing scripts!".to_owned()));
+			}
+		}
+
+		assert!(self.context.shutdown_scriptpubkey.is_some());
+		if let Some((last_fee, sig)) = self.context.last_sent_closing_fee {
+			if last_fee == msg.fee_satoshis {
+				let tx = self.build_signed_closing_transaction(&mut closing_tx, &msg.signature, &sig);
+				self.context.channel_state = ChannelState::ShutdownComplete as u32;
+				self.context.update_time_counter += 1;
+				return Ok((None, Some(tx)));
+			}
+		}
+
+		let (our_min_fee, our_max_fee) = self.calculate_closing_fee_limits(fee_estimator);
+
+		macro_rules! propose_fee {
+			($new_fee: expr) => {
+				let (closing_tx, used_fee) = if $new_fee == msg.fee_satoshis {
+					(closing_tx, $new_fee)
+				} else {
+					self.build_closing_transaction($new_fee, false)
+				};
+
+				let sig = self.context.holder_signer
+					.sign_closing_transaction(&closing_tx, &self.context.secp_ctx)
+					.map_err(|_| ChannelError::Close("External signer refused to sign closing transaction".to_owned()))?;
+
+				let signed_tx = if $new_fee == msg.fee_satoshis {
+					self.context.channel_state = ChannelState::ShutdownComplete as u32;
+					self.context.update_time_counter += 1;
+					let tx = self.build_signed_closing_transaction(&closing_tx, &msg.signature, &sig);
+					Some(tx)
+				} else { None };
+
+				self.context.last_sent_closing_fee = Some((used_fee, sig.clone()));
+				return Ok((Some(msgs::ClosingSigned {
+					channel_id: self.context.channel_id,
+					fee_satoshis: used_fee,
+					signature: sig,
+					fee_range: Some(msgs::ClosingSignedFeeRange {
+						min_fee_satoshis: our_min_fee,
+						max_fee_satoshis: our_max_fee,
+					}),
+				}), signed_tx))
+			}
+		}
+
+		if let Some(msgs::ClosingSignedFeeRange { min_fee_satoshis, max_fee_satoshis }) = msg.fee_range {
+			if msg.fee_satoshis < min_fee_satoshis || msg.fee_satoshis > max_fee_satoshis {
+				return Err(ChannelError::Close(format!("Peer sent a bogus closing_signed - suggested fee of {} sat was not in their desired range of {} sat - {} sat", msg.fee_satoshis, min_fee_satoshis, max_fee_satoshis)));
+			}
+			if max_fee_satoshis < our_min_fee {
+				return Err(ChannelError::Warn(format!("Unable to come to consensus about closing feerate, remote's max fee ({} sat) was smaller than our min fee ({} sat)", max_fee_satoshis, our_min_fee)));
+			}
+			if min_fee_satoshis > our_max_fee {
+				return Err(ChannelError::Warn(format!("Unable to come to consensus about closing feerate, remote's min fee ({} sat) was greater than our max fee ({} sat)", min_fee_satoshis, our_max_fee)));
+			}
+
+			if !self.context.is_outbound() {
+				// They have to pay, so pick the highest fee in the overlapping range.
+				// We should never set an upper bound aside from their full balance
+				debug_assert_eq!(our_max_fee, self.context.channel_value_satoshis - (self.context.value_to_self_msat + 999) / 1000);
+				propose_fee!(cmp::min(max_fee_satoshis, our_max_fee));
+			} else {
+				if msg.fee_satoshis < our_min_fee || msg.fee_satoshis > our_max_fee {
+					return Err(ChannelError::Close(format!("Peer sent a bogus closing_signed - suggested fee of {} sat was not in our desired range of {} sat - {} sat after we informed them of our range.",
+						msg.fee_satoshis, our_min_fee, our_max_fee)));
+				}
+				// The proposed fee is in our acceptable range, accept it and broadcast!
+				propose_fee!(msg.fee_satoshis);
+			}
+		} else {
+			// Old fee style negotiation. We don't bother to enforce whether they are complying
+			// with the "making progress" requirements, we just comply and hope for the best.
+			if let Some((last_fee, _)) = self.context.last_sent_closing_fee {
+				if msg.fee_satoshis > last_fee {
+					if msg.fee_satoshis < our_max_fee {
+						propose_fee!(msg.fee_satoshis);
+					} else if last_fee < our_max_fee {
+						propose_fee!(our_max_fee);
+					} else {
+						return Err(ChannelError::Close(format!("Unable to come to consensus about closing feerate, remote wants something ({} sat) higher than our max fee ({} sat)", msg.fee_satoshis, our_max_fee)));
+					}
+				} else {
+					if msg.fee_satoshis > our_min_fee {
+						propose_fee!(msg.fee_satoshis);
+					} else if last_fee > our_min_fee {
+						propose_fee!(our_min_fee);
+					} else {
+						return Err(ChannelError::Close(format!("Unable to come to consensus about closing feerate, remote wants something ({} sat) lower than our min fee ({} sat)", msg.fee_satoshis, our_min_fee)));
+					}
+				}
+			} else {
+				if msg.fee_satoshis < our_min_fee {
+					propose_fee!(our_min_fee);
+				} else if msg.fee_satoshis > our_max_fee {
+					propose_fee!(our_max_fee);
+				} else {
+					propose_fee!(msg.fee_satoshis);
+				}
 			}
 		}
-		Ok(())
 	}
 
-	fn get_last_revoke_and_ack(&self) -> msgs::RevokeAndACK {
-		let next_per_commitment_point = self.context.holder_signer.get_per_commitment_point(self.context.cur_holder_commitment_transaction_number, &self.context.secp_ctx);
-		let per_commitment_secret = self.context.holder_signer.release_commitment_secret(self.context.cur_holder_commitment_transaction_number + 2);
-		msgs::RevokeAndACK {
-			channel_id: self.context.channel_id,
-			per_commitment_secret,
-			next_per_commitment_point,
-			#[cfg(taproot)]
-			next_local_nonce: None,
+	fn internal_htlc_satisfies_config(
+		&self, htlc: &msgs::UpdateAddHTLC, amt_to_forward: u64, outgoing_cltv_value: u32, config: &ChannelConfig,
+	) -> Result<(), (&'static str, u16)> {
+		let fee = amt_to_forward.checked_mul(config.forwarding_fee_proportional_millionths as u64)
+			.and_then(|prop_fee| (prop_fee / 1000000).checked_add(config.forwarding_fee_base_msat as u64));
+		if fee.is_none() || htlc.amount_msat < fee.unwrap() ||
+			(htlc.amount_msat - fee.unwrap()) < amt_to_forward {
+			return Err((
+				"Prior hop has deviated from specified fees parameters or origin node has obsolete ones",
+				0x1000 | 12, // fee_insufficient
+			));
+		}
+		if (htlc.cltv_expiry as u64) < outgoing_cltv_value as u64 + config.cltv_expiry_delta as u64 {
+			return Err((
+				"Forwarding node has tampered with the intended HTLC values or origin node has an obsolete cltv_expiry_delta",
+				0x1000 | 13, // incorrect_cltv_expiry
+			));
 		}
+		Ok(())
 	}
 
-	fn get_last_commitment_update<L: Deref>(&self, logger: &L) -> msgs::CommitmentUpdate where L::Target: Logger {
-		let mut update_add_htlcs = Vec::new();
-		let mut update_fulfill_htlcs = Vec::new();
-		let mut update_fail_htlcs = Vec::new();
-		let mut update_fail_malformed_htlcs = Vec::new();
+	/// Determines whether the parameters of an incoming HTLC to be forwarded satisfy the channel's
+	/// [`ChannelConfig`]. This first looks at the channel's current [`ChannelConfig`], and if
+	/// unsuccessful, falls back to the previous one if one exists.
+	pub fn htlc_satisfies_config(
+		&self, htlc: &msgs::UpdateAddHTLC, amt_to_forward: u64, outgoing_cltv_value: u32,
+	) -> Result<(), (&'static str, u16)> {
+		self.internal_htlc_satisfies_config(&htlc, amt_to_forward, outgoing_cltv_value, &self.context.config())
+			.or_else(|err| {
+				if let Some(prev_config) = self.context.prev_config() {
+					self.internal_htlc_satisfies_config(htlc, amt_to_forward, outgoing_cltv_value, &prev_config)
+				} else {
+					Err(err)
+				}
+			})
+	}
 
-		for htlc in self.context.pending_outbound_htlcs.iter() {
-			if let &OutboundHTLCState::LocalAnnounced(ref onion_packet) = &htlc.state {
-				update_add_htlcs.push(msgs::UpdateAddHTLC {
-					channel_id: self.context.channel_id(),
-					htlc_id: htlc.htlc_id,
-					amount_msat: htlc.amount_msat,
-					payment_hash: htlc.payment_hash,
-					cltv_expiry: htlc.cltv_expiry,
-					onion_routing_packet: (**onion_packet).clone(),
-					skimmed_fee_msat: htlc.skimmed_fee_msat,
-				});
-			}
-		}
+	pub fn get_cur_holder_commitment_transaction_number(&self) -> u64 {
+		self.context.cur_holder_commitment_transaction_number + 1
+	}
 
-		for htlc in self.context.pending_inbound_htlcs.iter() {
-			if let &InboundHTLCState::LocalRemoved(ref reason) = &htlc.state {
-				match reason {
-					&InboundHTLCRemovalReason::FailRelay(ref err_packet) => {
-						update_fail_htlcs.push(msgs::UpdateFailHTLC {
-							channel_id: self.context.channel_id(),
-							htlc_id: htlc.htlc_id,
-							reason: err_packet.clone()
-						});
-					},
-					&InboundHTLCRemovalReason::FailMalformed((ref sha256_of_onion, ref failure_code)) => {
-						update_fail_malformed_htlcs.push(msgs::UpdateFailMalformedHTLC {
-							channel_id: self.context.channel_id(),
-							htlc_id: htlc.htlc_id,
-							sha256_of_onion: sha256_of_onion.clone(),
-							failure_code: failure_code.clone(),
-						});
-					},
-					&InboundHTLCRemovalReason::Fulfill(ref payment_preimage) => {
-						update_fulfill_htlcs.push(msgs::UpdateFulfillHTLC {
-							channel_id: self.context.channel_id(),
-							htlc_id: htlc.htlc_id,
-							payment_preimage: payment_preimage.clone(),
-						});
-					},
+	pub fn get_cur_counterparty_commitment_transaction_number(&self) -> u64 {
+		self.context.cur_counterparty_commitment_transaction_number + 1 - if self.context.channel_state & (ChannelState::AwaitingRemoteRevoke as u32) != 0 { 1 } else { 0 }
+	}
+
+	pub fn get_revoked_counterparty_commitment_transaction_number(&self) -> u64 {
+		self.context.cur_counterparty_commitment_transaction_number + 2
+	}
+
+	#[cfg(test)]
+	pub fn get_signer(&self) -> &Signer {
+		&self.context.holder_signer
+	}
+
+	#[cfg(test)]
+	pub fn get_value_stat(&self) -> ChannelValueStat {
+		ChannelValueStat {
+			value_to_self_msat: self.context.value_to_self_msat,
+			channel_value_msat: self.context.channel_value_satoshis * 1000,
+			channel_reserve_msat: self.context.counterparty_selected_channel_reserve_satoshis.unwrap() * 1000,
+			pending_outbound_htlcs_amount_msat: self.context.pending_outbound_htlcs.iter().map(|ref h| h.amount_msat).sum::<u64>(),
+			pending_inbound_htlcs_amount_msat: self.context.pending_inbound_htlcs.iter().map(|ref h| h.amount_msat).sum::<u64>(),
+			holding_cell_outbound_amount_msat: {
+				let mut res = 0;
+				for h in self.context.holding_cell_htlc_updates.iter() {
+					match h {
+						&HTLCUpdateAwaitingACK::AddHTLC{amount_msat, .. } => {
+							res += amount_msat;
+						}
+						_ => {}
+					}
 				}
-			}
+				res
+			},
+			counterparty_max_htlc_value_in_flight_msat: self.context.counterparty_max_htlc_value_in_flight_msat,
+			counterparty_dust_limit_msat: self.context.counterparty_dust_limit_satoshis * 1000,
 		}
+	}
 
-		let update_fee = if self.context.is_outbound() && self.context.pending_update_fee.is_some() {
-			Some(msgs::UpdateFee {
-				channel_id: self.context.channel_id(),
-				feerate_per_kw: self.context.pending_update_fee.unwrap().0,
-			})
-		} else { None };
+	/// Returns true if this channel has been marked as awaiting a monitor update to move forward.
+	/// Allowed in any state (including after shutdown)
+	pub fn is_awaiting_monitor_update(&self) -> bool {
+		(self.context.channel_state & ChannelState::MonitorUpdateInProgress as u32) != 0
+	}
 
-		log_trace!(logger, "Regenerated latest commitment update in channel {} with{} {} update_adds, {} update_fulfills, {} update_fails, and {} update_fail_malformeds",
-				log_bytes!(self.context.channel_id()), if update_fee.is_some() { " update_fee," } else { "" },
-				update_add_htlcs.len(), update_fulfill_htlcs.len(), update_fail_htlcs.len(), update_fail_malformed_htlcs.len());
-		msgs::CommitmentUpdate {
-			update_add_htlcs, update_fulfill_htlcs, update_fail_htlcs, update_fail_malformed_htlcs, update_fee,
-			commitment_signed: self.send_commitment_no_state_update(logger).expect("It looks like we failed to re-generate a commitment_signed we had previously sent?").0,
-		}
+	/// Gets the latest [`ChannelMonitorUpdate`] ID which has been released and is in-flight.
+	pub fn get_latest_unblocked_monitor_update_id(&self) -> u64 {
+		if self.context.blocked_monitor_updates.is_empty() { return self.context.get_latest_monitor_update_id(); }
+		self.context.blocked_monitor_updates[0].update.update_id - 1
 	}
 
-	/// May panic if some calls other than message-handling calls (which will all Err immediately)
-	/// have been called between remove_uncommitted_htlcs_and_mark_paused and this call.
-	///
-	/// Some links printed in log lines are included here to check them during build (when run with
-	/// `cargo doc --document-private-items`):
-	/// [`super::channelmanager::ChannelManager::force_close_without_broadcasting_txn`] and
-	/// [`super::channelmanager::ChannelManager::force_close_all_channels_without_broadcasting_txn`].
-	pub fn channel_reestablish<L: Deref, NS: Deref>(
-		&mut self, msg: &msgs::ChannelReestablish, logger: &L, node_signer: &NS,
-		genesis_block_hash: BlockHash, user_config: &UserConfig, best_block: &BestBlock
-	) -> Result<ReestablishResponses, ChannelError>
-	where
-		L::Target: Logger,
-		NS::Target: NodeSigner
-	{
-		if self.context.channel_state & (ChannelState::PeerDisconnected as u32) == 0 {
-			// While BOLT 2 doesn't indicate explicitly we should error this channel here, it
-			// almost certainly indicates we are going to end up out-of-sync in some way, so we
-			// just close here instead of trying to recover.
-			return Err(ChannelError::Close("Peer sent a loose channel_reestablish not after reconnect".to_owned()));
-		}
+	/// Returns the next blocked monitor update, if one exists, and a bool which indicates a
+	/// further blocked monitor update exists after the next.
+	pub fn unblock_next_blocked_monitor_update(&mut self) -> Option<(ChannelMonitorUpdate, bool)> {
+		if self.context.blocked_monitor_updates.is_empty() { return None; }
+		Some((self.context.blocked_monitor_updates.remove(0).update,
+			!self.context.blocked_monitor_updates.is_empty()))
+	}
 
-		if msg.next_local_commitment_number >= INITIAL_COMMITMENT_NUMBER || msg.next_remote_commitment_number >= INITIAL_COMMITMENT_NUMBER ||
-			msg.next_local_commitment_number == 0 {
-			return Err(ChannelError::Close("Peer sent a garbage channel_reestablish (usually an lnd node with lost state asking us to force-close for them)".to_owned()));
+	/// Pushes a new monitor update into our monitor update queue, returning it if it should be
+	/// immediately given to the user for persisting or `None` if it should be held as blocked.
+	fn push_ret_blockable_mon_update(&mut self, update: ChannelMonitorUpdate)
+	-> Option<ChannelMonitorUpdate> {
+		let release_monitor = self.context.blocked_monitor_updates.is_empty();
+		if !release_monitor {
+			self.context.blocked_monitor_updates.push(PendingChannelMonitorUpdate {
+				update,
+			});
+			None
+		} else {
+			Some(update)
 		}
+	}
 
-		if msg.next_remote_commitment_number > 0 {
-			let expected_point = self.context.holder_signer.get_per_commitment_point(INITIAL_COMMITMENT_NUMBER - msg.next_remote_commitment_number + 1, &self.context.secp_ctx);
-			let given_secret = SecretKey::from_slice(&msg.your_last_per_commitment_secret)
-				.map_err(|_| ChannelError::Close("Peer sent a garbage channel_reestablish with unparseable secret key".to_owned()))?;
-			if expected_point != PublicKey::from_secret_key(&self.context.secp_ctx, &given_secret) {
-				return Err(ChannelError::Close("Peer sent a garbage channel_reestablish with secret key not matching the commitment height provided".to_owned()));
-			}
-			if msg.next_remote_commitment_number > INITIAL_COMMITMENT_NUMBER - self.context.cur_holder_commitment_transaction_number {
-				macro_rules! log_and_panic {
-					($err_msg: expr) => {
-						log_error!(logger, $err_msg, log_bytes!(self.context.channel_id), log_pubkey!(self.context.counterparty_node_id));
-						panic!($err_msg, log_bytes!(self.context.channel_id), log_pubkey!(self.context.counterparty_node_id));
-					}
-				}
-				log_and_panic!("We have fallen behind - we have received proof that if we broadcast our counterparty is going to claim all our funds.\n\
-					This implies you have restarted with lost ChannelMonitor and ChannelManager state, the first of which is a violation of the LDK chain::Watch requirements.\n\
-					More specifically, this means you have a bug in your implementation that can cause loss of funds, or you are running with an old backup, which is unsafe.\n\
-					If you have restored from an old backup and wish to force-close channels and return to operation, you should start up, call\n\
-					ChannelManager::force_close_without_broadcasting_txn on channel {} with counterparty {} or\n\
-					ChannelManager::force_close_all_channels_without_broadcasting_txn, then reconnect to peer(s).\n\
-					Note that due to a long-standing bug in lnd you may have to reach out to peers running lnd-based nodes to ask them to manually force-close channels\n\
-					See https://github.com/lightningdevkit/rust-lightning/issues/1565 for more info.");
-			}
-		}
+	pub fn blocked_monitor_updates_pending(&self) -> usize {
+		self.context.blocked_monitor_updates.len()
+	}
 
-		// Before we change the state of the channel, we check if the peer is sending a very old
-		// commitment transaction number, if yes we send a warning message.
-		let our_commitment_transaction = INITIAL_COMMITMENT_NUMBER - self.context.cur_holder_commitment_transaction_number - 1;
-		if  msg.next_remote_commitment_number + 1 < our_commitment_transaction {
-			return Err(
-				ChannelError::Warn(format!("Peer attempted to reestablish channel with a very old local commitment transaction: {} (received) vs {} (expected)", msg.next_remote_commitment_number, our_commitment_transaction))
-			);
+	/// Returns true if the channel is awaiting the persistence of the initial ChannelMonitor.
+	/// If the channel is outbound, this implies we have not yet broadcasted the funding
+	/// transaction. If the channel is inbound, this implies simply that the channel has not
+	/// advanced state.
+	pub fn is_awaiting_initial_mon_persist(&self) -> bool {
+		if !self.is_awaiting_monitor_update() { return false; }
+		if self.context.channel_state &
+			!(ChannelState::TheirChannelReady as u32 | ChannelState::PeerDisconnected as u32 | ChannelState::MonitorUpdateInProgress as u32)
+				== ChannelState::FundingSent as u32 {
+			// If we're not a 0conf channel, we'll be waiting on a monitor update with only
+			// FundingSent set, though our peer could have sent their channel_ready.
+			debug_assert!(self.context.minimum_depth.unwrap_or(1) > 0);
+			return true;
+		}
+		if self.context.cur_holder_commitment_transaction_number == INITIAL_COMMITMENT_NUMBER - 1 &&
+			self.context.cur_counterparty_commitment_transaction_number == INITIAL_COMMITMENT_NUMBER - 1 {
+			// If we're a 0-conf channel, we'll move beyond FundingSent immediately even while
+			// waiting for the initial monitor persistence. Thus, we check if our commitment
+			// transaction numbers have both been iterated only exactly once (for the
+			// funding_signed), and we're awaiting monitor update.
+			//
+			// If we got here, we shouldn't have yet broadcasted the funding transaction (as the
+			// only way to get an awaiting-monitor-update state during initial funding is if the
+			// initial monitor persistence is still pending).
+			//
+			// Because deciding we're awaiting initial broadcast spuriously could result in
+			// funds-loss (as we don't have a monitor, but have the funding transaction confirmed),
+			// we hard-assert here, even in production builds.
+			// Dual-funded channels will only have the funding transaction once it has been fully
+			// signed, which cannot happen until the initial monitor has been persisted.
+			if self.context.is_outbound() && self.context.interactive_tx_signing_session.is_none() {
+				assert!(self.context.funding_transaction.is_some());
+			}
+			assert!(self.context.monitor_pending_channel_ready);
+			assert_eq!(self.context.latest_monitor_update_id, 0);
+			return true;
 		}
+		false
+	}
 
-		// Go ahead and unmark PeerDisconnected as various calls we may make check for it (and all
-		// remaining cases either succeed or ErrorMessage-fail).
-		self.context.channel_state &= !(ChannelState::PeerDisconnected as u32);
-		self.context.sent_message_awaiting_response = None;
+	/// Returns true if our channel_ready has been sent
+	pub fn is_our_channel_ready(&self) -> bool {
+		(self.context.channel_state & ChannelState::OurChannelReady as u32) != 0 || self.context.channel_state >= ChannelState::ChannelReady as u32
+	}
 
-		let shutdown_msg = if self.context.channel_state & (ChannelState::LocalShutdownSent as u32) != 0 {
-			assert!(self.context.shutdown_scriptpubkey.is_some());
-			Some(msgs::Shutdown {
-				channel_id: self.context.channel_id,
-				scriptpubkey: self.get_closing_scriptpubkey(),
-			})
-		} else { None };
+	/// Returns true if our peer has either initiated or agreed to shut down the channel.
+	pub fn received_shutdown(&self) -> bool {
+		(self.context.channel_state & ChannelState::RemoteShutdownSent as u32) != 0
+	}
 
-		let announcement_sigs = self.get_announcement_sigs(node_signer, genesis_block_hash, user_config, best_block.height(), logger);
+	/// Returns true if we either initiated or agreed to shut down the channel.
+	pub fn sent_shutdown(&self) -> bool {
+		(self.context.channel_state & ChannelState::LocalShutdownSent as u32) != 0
+	}
 
-		if self.context.channel_state & (ChannelState::FundingSent as u32) == ChannelState::FundingSent as u32 {
-			// If we're waiting on a monitor update, we shouldn't re-send any channel_ready's.
-			if self.context.channel_state & (ChannelState::OurChannelReady as u32) == 0 ||
-					self.context.channel_state & (ChannelState::MonitorUpdateInProgress as u32) != 0 {
-				if msg.next_remote_commitment_number != 0 {
-					return Err(ChannelError::Close("Peer claimed they saw a revoke_and_ack but we haven't sent channel_ready yet".to_owned()));
-				}
-				// Short circuit the whole handler as there is nothing we can resend them
-				return Ok(ReestablishResponses {
-					channel_ready: None,
-					raa: None, commitment_update: None,
-					order: RAACommitmentOrder::CommitmentFirst,
-					shutdown_msg, announcement_sigs,
-				});
-			}
+	/// Returns true if this channel is fully shut down. True here implies that no further actions
+	/// may/will be taken on this channel, and thus this object should be freed. Any future changes
+	/// will be handled appropriately by the chain monitor.
+	pub fn is_shutdown(&self) -> bool {
+		if (self.context.channel_state & ChannelState::ShutdownComplete as u32) == ChannelState::ShutdownComplete as u32  {
+			assert!(self.context.channel_state == ChannelState::ShutdownComplete as u32);
+			true
+		} else { false }
+	}
 
-			// We have OurChannelReady set!
-			let next_per_commitment_point = self.context.holder_signer.get_per_commitment_point(self.context.cur_holder_commitment_transaction_number, &self.context.secp_ctx);
-			return Ok(ReestablishResponses {
-				channel_ready: Some(msgs::ChannelReady {
-					channel_id: self.context.channel_id(),
-					next_per_commitment_point,
-					short_channel_id_alias: Some(self.context.outbound_scid_alias),
-				}),
-				raa: None, commitment_update: None,
-				order: RAACommitmentOrder::CommitmentFirst,
-				shutdown_msg, announcement_sigs,
-			});
-		}
+	pub fn channel_update_status(&self) -> ChannelUpdateStatus {
+		self.context.channel_update_status
+	}
 
-		let required_revoke = if msg.next_remote_commitment_number + 1 == INITIAL_COMMITMENT_NUMBER - self.context.cur_holder_commitment_transaction_number {
-			// Remote isn't waiting on any RevokeAndACK from us!
-			// Note that if we need to repeat our ChannelReady we'll do that in the next if block.
-			None
-		} else if msg.next_remote_commitment_number + 1 == (INITIAL_COMMITMENT_NUMBER - 1) - self.context.cur_holder_commitment_transaction_number {
-			if self.context.channel_state & (ChannelState::MonitorUpdateInProgress as u32) != 0 {
-				self.context.monitor_pending_revoke_and_ack = true;
-				None
-			} else {
-				Some(self.get_last_revoke_and_ack())
-			}
-		} else {
-			return Err(ChannelError::Close("Peer attempted to reestablish channel with a very old local commitment transaction".to_owned()));
-		};
+	pub fn set_channel_update_status(&mut self, status: ChannelUpdateStatus) {
+		self.context.update_time_counter += 1;
+		self.context.channel_update_status = status;
+	}
 
-		// We increment cur_counterparty_commitment_transaction_number only upon receipt of
-		// revoke_and_ack, not on sending commitment_signed, so we add one if have
-		// AwaitingRemoteRevoke set, which indicates we sent a commitment_signed but haven't gotten
-		// the corresponding revoke_and_ack back yet.
-		let is_awaiting_remote_revoke = self.context.channel_state & ChannelState::AwaitingRemoteRevoke as u32 != 0;
-		if is_awaiting_remote_revoke && !self.is_awaiting_monitor_update() {
-			self.mark_awaiting_response();
+	fn check_get_channel_ready(&mut self, height: u32) -> Option<msgs::ChannelReady> {
+		// Called:
+		//  * always when a new block/transactions are confirmed with the new height
+		//  * when funding is signed with a height of 0
+		if self.context.funding_tx_confirmation_height == 0 && self.context.minimum_depth != Some(0) {
+			return None;
+		}
+		// A 0-conf channel in a batch may not be used until the batch funding transaction has
+		// been broadcast.
+		if self.context.is_awaiting_batch_broadcast() {
+			return None;
 		}
-		let next_counterparty_commitment_number = INITIAL_COMMITMENT_NUMBER - self.context.cur_counterparty_commitment_transaction_number + if is_awaiting_remote_revoke { 1 } else { 0 };
 
-		let channel_ready = if msg.next_local_commitment_number == 1 && INITIAL_COMMITMENT_NUMBER - self.context.cur_holder_commitment_transaction_number == 1 {
-			// We should never have to worry about MonitorUpdateInProgress resending ChannelReady
-			let next_per_commitment_point = self.context.holder_signer.get_per_commitment_point(self.context.cur_holder_commitment_transaction_number, &self.context.secp_ctx);
-			Some(msgs::ChannelReady {
-				channel_id: self.context.channel_id(),
-				next_per_commitment_point,
-				short_channel_id_alias: Some(self.context.outbound_scid_alias),
-			})
-		} else { None };
+		let funding_tx_confirmations = height as i64 - self.context.funding_tx_confirmation_height as i64 + 1;
+		if funding_tx_confirmations <= 0 {
+			self.context.funding_tx_confirmation_height = 0;
+		}
 
-		if msg.next_local_commitment_number == next_counterparty_commitment_number {
-			if required_revoke.is_some() {
-				log_debug!(logger, "Reconnected channel {} with only lost outbound RAA", log_bytes!(self.context.channel_id()));
-			} else {
-				log_debug!(logger, "Reconnected channel {} with no loss", log_bytes!(self.context.channel_id()));
+		if funding_tx_confirmations < self.context.minimum_depth.unwrap_or(0) as i64 {
+			return None;
+		}
+
+		let non_shutdown_state = self.context.channel_state & (!MULTI_STATE_FLAGS);
+		let need_commitment_update = if non_shutdown_state == ChannelState::FundingSent as u32 {
+			self.context.channel_state |= ChannelState::OurChannelReady as u32;
+			true
+		} else if non_shutdown_state == (ChannelState::FundingSent as u32 | ChannelState::TheirChannelReady as u32) {
+			self.context.channel_state = ChannelState::ChannelReady as u32 | (self.context.channel_state & MULTI_STATE_FLAGS);
+			self.context.update_time_counter += 1;
+			true
+		} else if non_shutdown_state == (ChannelState::FundingSent as u32 | ChannelState::OurChannelReady as u32) {
+			// We got a reorg but not enough to trigger a force close, just ignore.
+			false
+		} else {
+			if self.context.funding_tx_confirmation_height != 0 && self.context.channel_state < ChannelState::ChannelReady as u32 {
+				// We should never see a funding transaction on-chain until we've received
+				// funding_signed (if we're an outbound channel), or seen funding_generated (if we're
+				// an inbound channel - before that we have no known funding TXID). The fuzzer,
+				// however, may do this and we shouldn't treat it as a bug.
+				#[cfg(not(fuzzing))]
+				panic!("Started confirming a channel in a state pre-FundingSent: {}.\n\
+					Do NOT broadcast a funding transaction manually - let LDK do it for you!",
+					self.context.channel_state);
 			}
+			// We got a reorg but not enough to trigger a force close, just ignore.
+			false
+		};
 
-			Ok(ReestablishResponses {
-				channel_ready, shutdown_msg, announcement_sigs,
-				raa: required_revoke,
-				commitment_update: None,
-				order: self.context.resend_order.clone(),
-			})
-		} else if msg.next_local_commitment_number == next_counterparty_commitment_number - 1 {
-			if required_revoke.is_some() {
-				log_debug!(logger, "Reconnected channel {} with lost outbound RAA and lost remote commitment tx", log_bytes!(self.context.channel_id()));
+		if need_commitment_update {
+			if self.context.channel_state & (ChannelState::MonitorUpdateInProgress as u32) == 0 {
+				if self.context.channel_state & (ChannelState::PeerDisconnected as u32) == 0 {
+					let next_per_commitment_point =
+						self.context.holder_signer.get_per_commitment_point(INITIAL_COMMITMENT_NUMBER - 1, &self.context.secp_ctx);
+					return Some(msgs::ChannelReady {
+						channel_id: self.context.channel_id,
+						next_per_commitment_point,
+						short_channel_id_alias: Some(self.context.outbound_scid_alias),
+					});
+				}
 			} else {
-				log_debug!(logger, "Reconnected channel {} with only lost remote commitment tx", log_bytes!(self.context.channel_id()));
+				self.context.monitor_pending_channel_ready = true;
 			}
+		}
+		None
+	}
 
-			if self.context.channel_state & (ChannelState::MonitorUpdateInProgress as u32) != 0 {
-				self.context.monitor_pending_commitment_signed = true;
-				Ok(ReestablishResponses {
-					channel_ready, shutdown_msg, announcement_sigs,
-					commitment_update: None, raa: None,
-					order: self.context.resend_order.clone(),
-				})
-			} else {
-				Ok(ReestablishResponses {
-					channel_ready, shutdown_msg, announcement_sigs,
-					raa: required_revoke,
-					commitment_update: Some(self.get_last_commitment_update(logger)),
-					order: self.context.resend_order.clone(),
-				})
+	/// When a transaction is confirmed, we check whether it is or spends the funding transaction
+	/// In the first case, we store the confirmation height and calculating the short channel id.
+	/// In the second, we simply return an Err indicating we need to be force-closed now.
+	pub fn transactions_confirmed<NS: Deref, L: Deref>(
+		&mut self, block_hash: &BlockHash, height: u32, txdata: &TransactionData,
+		genesis_block_hash: BlockHash, node_signer: &NS, user_config: &UserConfig, logger: &L
+	) -> Result<(Option<msgs::ChannelReady>, Option<msgs::AnnouncementSignatures>), ClosureReason>
+	where
+		NS::Target: NodeSigner,
+		L::Target: Logger
+	{
+		if let Some(funding_txo) = self.context.get_funding_txo() {
+			for &(index_in_block, tx) in txdata.iter() {
+				// The new funding transaction of a pending splice spends our current funding output
+				// but doesn't close the channel.
+				// The candidate funding transactions of a pending splice all spend our current funding
+				// output but don't close the channel.
+				if let Some(splice) = self.context.pending_splice.as_mut() {
+					if let Some(candidate) = splice.candidates.iter_mut()
+						.find(|candidate| candidate.funding_txo.map_or(false, |txo| txo.txid == tx.txid()))
+					{
+						let splice_txo = candidate.funding_txo.unwrap();
+						if candidate.funding_tx_confirmation_height == 0 {
+							log_info!(logger, "Splice transaction {} for channel {} confirmed at height {}",
+								splice_txo.txid, log_bytes!(self.context.channel_id), height);
+							candidate.funding_tx_confirmation_height = height;
+							candidate.funding_tx_confirmed_in = Some(*block_hash);
+							candidate.short_channel_id = match scid_from_parts(height as u64, index_in_block as u64, splice_txo.index as u64) {
+								Ok(scid) => Some(scid),
+								Err(_) => panic!("Block was bogus - either height was > 16 million, had > 16 million transactions, or had > 65k outputs"),
+							};
+						}
+						continue;
+					}
+					// Once none of the candidates can confirm anymore, we'll abandon the splice.
+					for candidate in splice.candidates.iter_mut() {
+						if candidate.conflicting_txid.is_none() && candidate.funding_tx_confirmation_height == 0 &&
+							candidate.signing_session.as_ref().map_or(false, |session| session.is_double_spent_by(tx))
+						{
+							log_info!(logger, "Splice transaction {} for channel {} was double-spent by {} at height {}",
+								candidate.signing_session.as_ref().unwrap().unsigned_tx().txid(),
+								log_bytes!(self.context.channel_id), tx.txid(), height);
+							candidate.conflicting_txid = Some(tx.txid());
+							candidate.conflicting_tx_confirmed_in = Some(*block_hash);
+							candidate.conflicting_tx_confirmation_height = height;
+						}
+					}
+				}
+				// Check if the transaction is the expected funding transaction, and if it is,
+				// check that it pays the right amount to the right script.
+				if self.context.funding_tx_confirmation_height == 0 {
+					if tx.txid() == funding_txo.txid {
+						let txo_idx = funding_txo.index as usize;
+						if txo_idx >= tx.output.len() || tx.output[txo_idx].script_pubkey != self.context.get_funding_redeemscript().to_v0_p2wsh() ||
+								tx.output[txo_idx].value != self.context.channel_value_satoshis {
+							if self.context.is_outbound() {
+								// If we generated the funding transaction and it doesn't match what it
+								// should, the client is really broken and we should just panic and
+								// tell them off. That said, because hash collisions happen with high
+								// probability in fuzzing mode, if we're fuzzing we just close the
+								// channel and move on.
+								#[cfg(not(fuzzing))]
+								panic!("Client called ChannelManager::funding_transaction_generated with bogus transaction!");
+							}
+							self.context.update_time_counter += 1;
+							let err_reason = "funding tx had wrong script/value or output index";
+							return Err(ClosureReason::ProcessingError { err: err_reason.to_owned() });
+						} else {
+							if self.context.is_outbound() {
+								for input in tx.input.iter() {
+									if input.witness.is_empty() {
+										// We generated a malleable funding transaction, implying we've
+										// just exposed ourselves to funds loss to our counterparty.
+										#[cfg(not(fuzzing))]
+										panic!("Client called ChannelManager::funding_transaction_generated with bogus transaction!");
+									}
+								}
+							}
+							self.context.funding_tx_confirmation_height = height;
+							self.context.funding_tx_confirmed_in = Some(*block_hash);
+							self.context.short_channel_id = match scid_from_parts(height as u64, index_in_block as u64, txo_idx as u64) {
+								Ok(scid) => Some(scid),
+								Err(_) => panic!("Block was bogus - either height was > 16 million, had > 16 million transactions, or had > 65k outputs"),
+							}
+						}
+					}
+					// If we allow 1-conf funding, we may need to check for channel_ready here and
+					// send it immediately instead of waiting for a best_block_updated call (which
+					// may have already happened for this block).
+					if let Some(channel_ready) = self.check_get_channel_ready(height) {
+						log_info!(logger, "Sending a channel_ready to our peer for channel {}", log_bytes!(self.context.channel_id));
+						let announcement_sigs = self.get_announcement_sigs(node_signer, genesis_block_hash, user_config, height, logger);
+						return Ok((Some(channel_ready), announcement_sigs));
+					}
+				}
+				for inp in tx.input.iter() {
+					if inp.previous_output == funding_txo.into_bitcoin_outpoint() {
+						log_info!(logger, "Detected channel-closing tx {} spending {}:{}, closing channel {}", tx.txid(), inp.previous_output.txid, inp.previous_output.vout, log_bytes!(self.context.channel_id()));
+						return Err(ClosureReason::CommitmentTxConfirmed);
+					}
+				}
 			}
-		} else {
-			Err(ChannelError::Close("Peer attempted to reestablish channel with a very old remote commitment transaction".to_owned()))
 		}
+		Ok((None, None))
 	}
 
-	/// Calculates and returns our minimum and maximum closing transaction fee amounts, in whole
-	/// satoshis. The amounts remain consistent unless a peer disconnects/reconnects or we restart,
-	/// at which point they will be recalculated.
-	fn calculate_closing_fee_limits<F: Deref>(&mut self, fee_estimator: &LowerBoundedFeeEstimator<F>)
-		-> (u64, u64)
-		where F::Target: FeeEstimator
+	/// When a new block is connected, we check the height of the block against outbound holding
+	/// cell HTLCs in case we need to give up on them prematurely and time them out. Everything
+	/// else (e.g. commitment transaction broadcasts, HTLC transaction broadcasting, etc) is
+	/// handled by the ChannelMonitor.
+	///
+	/// If we return Err, the channel may have been closed, at which point the standard
+	/// requirements apply - no calls may be made except those explicitly stated to be allowed
+	/// post-shutdown.
+	///
+	/// May return some HTLCs (and their payment_hash) which have timed out and should be failed
+	/// back.
+	pub fn best_block_updated<NS: Deref, L: Deref>(
+		&mut self, height: u32, highest_header_time: u32, genesis_block_hash: BlockHash,
+		node_signer: &NS, user_config: &UserConfig, logger: &L
+	) -> Result<(Option<msgs::ChannelReady>, Vec<(HTLCSource, PaymentHash)>, Option<msgs::AnnouncementSignatures>), ClosureReason>
+	where
+		NS::Target: NodeSigner,
+		L::Target: Logger
 	{
-		if let Some((min, max)) = self.context.closing_fee_limits { return (min, max); }
+		self.do_best_block_updated(height, highest_header_time, Some((genesis_block_hash, node_signer, user_config)), logger)
+	}
 
-		// Propose a range from our current Background feerate to our Normal feerate plus our
-		// force_close_avoidance_max_fee_satoshis.
-		// If we fail to come to consensus, we'll have to force-close.
-		let mut proposed_feerate = fee_estimator.bounded_sat_per_1000_weight(ConfirmationTarget::Background);
-		let normal_feerate = fee_estimator.bounded_sat_per_1000_weight(ConfirmationTarget::Normal);
-		let mut proposed_max_feerate = if self.context.is_outbound() { normal_feerate } else { u32::max_value() };
+	fn do_best_block_updated<NS: Deref, L: Deref>(
+		&mut self, height: u32, highest_header_time: u32,
+		genesis_node_signer: Option<(BlockHash, &NS, &UserConfig)>, logger: &L
+	) -> Result<(Option<msgs::ChannelReady>, Vec<(HTLCSource, PaymentHash)>, Option<msgs::AnnouncementSignatures>), ClosureReason>
+	where
+		NS::Target: NodeSigner,
+		L::Target: Logger
+	{
+		let mut timed_out_htlcs = Vec::new();
+		// This mirrors the check in ChannelManager::decode_update_add_htlc_onion, refusing to
+		// forward an HTLC when our counterparty should almost certainly just fail it for expiring
+		// ~now.
+		let unforwarded_htlc_cltv_limit = height + LATENCY_GRACE_PERIOD_BLOCKS;
+		self.context.holding_cell_htlc_updates.retain(|htlc_update| {
+			match htlc_update {
+				&HTLCUpdateAwaitingACK::AddHTLC { ref payment_hash, ref source, ref cltv_expiry, .. } => {
+					if *cltv_expiry <= unforwarded_htlc_cltv_limit {
+						timed_out_htlcs.push((source.clone(), payment_hash.clone()));
+						false
+					} else { true }
+				},
+				_ => true
+			}
+		});
 
-		// The spec requires that (when the channel does not have anchors) we only send absolute
-		// channel fees no greater than the absolute channel fee on the current commitment
-		// transaction. It's unclear *which* commitment transaction this refers to, and there isn't
-		// very good reason to apply such a limit in any case. We don't bother doing so, risking
-		// some force-closure by old nodes, but we wanted to close the channel anyway.
+		self.context.update_time_counter = cmp::max(self.context.update_time_counter, highest_header_time);
 
-		if let Some(target_feerate) = self.context.target_closing_feerate_sats_per_kw {
-			let min_feerate = if self.context.is_outbound() { target_feerate } else { cmp::min(self.context.feerate_per_kw, target_feerate) };
-			proposed_feerate = cmp::max(proposed_feerate, min_feerate);
-			proposed_max_feerate = cmp::max(proposed_max_feerate, min_feerate);
+		if let Some(channel_ready) = self.check_get_channel_ready(height) {
+			let announcement_sigs = if let Some((genesis_block_hash, node_signer, user_config)) = genesis_node_signer {
+				self.get_announcement_sigs(node_signer, genesis_block_hash, user_config, height, logger)
+			} else { None };
+			log_info!(logger, "Sending a channel_ready to our peer for channel {}", log_bytes!(self.context.channel_id));
+			return Ok((Some(channel_ready), timed_out_htlcs, announcement_sigs));
 		}
 
-		// Note that technically we could end up with a lower minimum fee if one sides' balance is
-		// below our dust limit, causing the output to disappear. We don't bother handling this
-		// case, however, as this should only happen if a channel is closed before any (material)
-		// payments have been made on it. This may cause slight fee overpayment and/or failure to
-		// come to consensus with our counterparty on appropriate fees, however it should be a
-		// relatively rare case. We can revisit this later, though note that in order to determine
-		// if the funders' output is dust we have to know the absolute fee we're going to use.
-		let tx_weight = self.get_closing_transaction_weight(Some(&self.get_closing_scriptpubkey()), Some(self.context.counterparty_shutdown_scriptpubkey.as_ref().unwrap()));
-		let proposed_total_fee_satoshis = proposed_feerate as u64 * tx_weight / 1000;
-		let proposed_max_total_fee_satoshis = if self.context.is_outbound() {
-				// We always add force_close_avoidance_max_fee_satoshis to our normal
-				// feerate-calculated fee, but allow the max to be overridden if we're using a
-				// target feerate-calculated fee.
-				cmp::max(normal_feerate as u64 * tx_weight / 1000 + self.context.config.options.force_close_avoidance_max_fee_satoshis,
-					proposed_max_feerate as u64 * tx_weight / 1000)
-			} else {
-				self.context.channel_value_satoshis - (self.context.value_to_self_msat + 999) / 1000
-			};
+		let non_shutdown_state = self.context.channel_state & (!MULTI_STATE_FLAGS);
+		if non_shutdown_state >= ChannelState::ChannelReady as u32 ||
+		   (non_shutdown_state & ChannelState::OurChannelReady as u32) == ChannelState::OurChannelReady as u32 {
+			let mut funding_tx_confirmations = height as i64 - self.context.funding_tx_confirmation_height as i64 + 1;
+			if self.context.funding_tx_confirmation_height == 0 {
+				// Note that check_get_channel_ready may reset funding_tx_confirmation_height to
+				// zero if it has been reorged out, however in either case, our state flags
+				// indicate we've already sent a channel_ready
+				funding_tx_confirmations = 0;
+			}
 
-		self.context.closing_fee_limits = Some((proposed_total_fee_satoshis, proposed_max_total_fee_satoshis));
-		self.context.closing_fee_limits.clone().unwrap()
-	}
+			// If we've sent channel_ready (or have both sent and received channel_ready), and
+			// the funding transaction has become unconfirmed,
+			// close the channel and hope we can get the latest state on chain (because presumably
+			// the funding transaction is at least still in the mempool of most nodes).
+			//
+			// Note that ideally we wouldn't force-close if we see *any* reorg on a 1-conf or
+			// 0-conf channel, but not doing so may lead to the
+			// `ChannelManager::short_to_chan_info` map  being inconsistent, so we currently have
+			// to.
+			if funding_tx_confirmations == 0 && self.context.funding_tx_confirmed_in.is_some() {
+				let err_reason = format!("Funding transaction was un-confirmed. Locked at {} confs, now have {} confs.",
+					self.context.minimum_depth.unwrap(), funding_tx_confirmations);
+				return Err(ClosureReason::ProcessingError { err: err_reason });
+			}
+		} else if !self.context.is_outbound() && self.context.funding_tx_confirmed_in.is_none() &&
+				height >= self.context.channel_creation_height + FUNDING_CONF_DEADLINE_BLOCKS {
+			log_info!(logger, "Closing channel {} due to funding timeout", log_bytes!(self.context.channel_id));
+			// If funding_tx_confirmed_in is unset, the channel must not be active
+			assert!(non_shutdown_state <= ChannelState::ChannelReady as u32);
+			assert_eq!(non_shutdown_state & ChannelState::OurChannelReady as u32, 0);
+			return Err(ClosureReason::FundingTimedOut);
+		}
 
-	/// Returns true if we're ready to commence the closing_signed negotiation phase. This is true
-	/// after both sides have exchanged a `shutdown` message and all HTLCs have been drained. At
-	/// this point if we're the funder we should send the initial closing_signed, and in any case
-	/// shutdown should complete within a reasonable timeframe.
-	fn closing_negotiation_ready(&self) -> bool {
-		self.context.closing_negotiation_ready()
+		let announcement_sigs = if let Some((genesis_block_hash, node_signer, user_config)) = genesis_node_signer {
+			self.get_announcement_sigs(node_signer, genesis_block_hash, user_config, height, logger)
+		} else { None };
+		Ok((None, timed_out_htlcs, announcement_sigs))
 	}
 
-	/// Checks if the closing_signed negotiation is making appropriate progress, possibly returning
-	/// an Err if no progress is being made and the channel should be force-closed instead.
-	/// Should be called on a one-minute timer.
-	pub fn timer_check_closing_negotiation_progress(&mut self) -> Result<(), ChannelError> {
-		if self.closing_negotiation_ready() {
-			if self.context.closing_signed_in_flight {
-				return Err(ChannelError::Close("closing_signed negotiation failed to finish within two timer ticks".to_owned()));
-			} else {
-				self.context.closing_signed_in_flight = true;
+	/// Indicates the funding transaction is no longer confirmed in the main chain. This may
+	/// force-close the channel, but may also indicate a harmless reorganization of a block or two
+	/// before the channel has reached channel_ready and we can just wait for more blocks.
+	pub fn funding_transaction_unconfirmed<L: Deref>(&mut self, logger: &L) -> Result<(), ClosureReason> where L::Target: Logger {
+		if self.context.funding_tx_confirmation_height != 0 {
+			// We handle the funding disconnection by calling best_block_updated with a height one
+			// below where our funding was connected, implying a reorg back to conf_height - 1.
+			let reorg_height = self.context.funding_tx_confirmation_height - 1;
+			// We use the time field to bump the current time we set on channel updates if its
+			// larger. If we don't know that time has moved forward, we can just set it to the last
+			// time we saw and it will be ignored.
+			let best_time = self.context.update_time_counter;
+			match self.do_best_block_updated(reorg_height, best_time, None::<(BlockHash, &&NodeSigner, &UserConfig)>, logger) {
+				Ok((channel_ready, timed_out_htlcs, announcement_sigs)) => {
+					assert!(channel_ready.is_none(), "We can't generate a funding with 0 confirmations?");
+					assert!(timed_out_htlcs.is_empty(), "We can't have accepted HTLCs with a timeout before our funding confirmation?");
+					assert!(announcement_sigs.is_none(), "We can't generate an announcement_sigs with 0 confirmations?");
+					Ok(())
+				},
+				Err(e) => Err(e)
 			}
+		} else {
+			// We never learned about the funding confirmation anyway, just ignore
+			Ok(())
 		}
-		Ok(())
 	}
 
-	pub fn maybe_propose_closing_signed<F: Deref, L: Deref>(
-		&mut self, fee_estimator: &LowerBoundedFeeEstimator<F>, logger: &L)
-		-> Result<(Option<msgs::ClosingSigned>, Option<Transaction>), ChannelError>
-		where F::Target: FeeEstimator, L::Target: Logger
-	{
-		if self.context.last_sent_closing_fee.is_some() || !self.closing_negotiation_ready() {
-			return Ok((None, None));
-		}
+	// Methods to get unprompted messages to send to the remote end (or where we already returned
+	// something in the handler for the message that prompted this message):
 
-		if !self.context.is_outbound() {
-			if let Some(msg) = &self.context.pending_counterparty_closing_signed.take() {
-				return self.closing_signed(fee_estimator, &msg);
-			}
-			return Ok((None, None));
+	/// Gets an UnsignedChannelAnnouncement for this channel. The channel must be publicly
+	/// announceable and available for use (have exchanged [`ChannelReady`] messages in both
+	/// directions). Should be used for both broadcasted announcements and in response to an
+	/// AnnouncementSignatures message from the remote peer.
+	///
+	/// Will only fail if we're not in a state where channel_announcement may be sent (including
+	/// closing).
+	///
+	/// This will only return ChannelError::Ignore upon failure.
+	///
+	/// [`ChannelReady`]: crate::ln::msgs::ChannelReady
+	fn get_channel_announcement<NS: Deref>(
+		&self, node_signer: &NS, chain_hash: BlockHash, user_config: &UserConfig,
+	) -> Result<msgs::UnsignedChannelAnnouncement, ChannelError> where NS::Target: NodeSigner {
+		if !self.context.config.announced_channel {
+			return Err(ChannelError::Ignore("Channel is not available for public announcements".to_owned()));
+		}
+		if !self.context.is_usable() {
+			return Err(ChannelError::Ignore("Cannot get a ChannelAnnouncement if the channel is not currently usable".to_owned()));
 		}
 
-		let (our_min_fee, our_max_fee) = self.calculate_closing_fee_limits(fee_estimator);
-
-		assert!(self.context.shutdown_scriptpubkey.is_some());
-		let (closing_tx, total_fee_satoshis) = self.build_closing_transaction(our_min_fee, false);
-		log_trace!(logger, "Proposing initial closing_signed for our counterparty with a fee range of {}-{} sat (with initial proposal {} sats)",
-			our_min_fee, our_max_fee, total_fee_satoshis);
-
-		let sig = self.context.holder_signer
-			.sign_closing_transaction(&closing_tx, &self.context.secp_ctx)
-			.map_err(|()| ChannelError::Close("Failed to get signature for closing transaction.".to_owned()))?;
-
-		self.context.last_sent_closing_fee = Some((total_fee_satoshis, sig.clone()));
-		Ok((Some(msgs::ClosingSigned {
-			channel_id: self.context.channel_id,
-			fee_satoshis: total_fee_satoshis,
-			signature: sig,
-			fee_range: Some(msgs::ClosingSignedFeeRange {
-				min_fee_satoshis: our_min_fee,
-				max_fee_satoshis: our_max_fee,
-			}),
-		}), None))
-	}
-
-	// Marks a channel as waiting for a response from the counterparty. If it's not received
-	// [`DISCONNECT_PEER_AWAITING_RESPONSE_TICKS`] after sending our own to them, then we'll attempt
-	// a reconnection.
-	fn mark_awaiting_response(&mut self) {
-		self.context.sent_message_awaiting_response = Some(0);
-	}
+		let short_channel_id = self.context.get_short_channel_id()
+			.ok_or(ChannelError::Ignore("Cannot get a ChannelAnnouncement if the channel has not been confirmed yet".to_owned()))?;
+		let node_id = NodeId::from_pubkey(&node_signer.get_node_id(Recipient::Node)
+			.map_err(|_| ChannelError::Ignore("Failed to retrieve own public key".to_owned()))?);
+		let counterparty_node_id = NodeId::from_pubkey(&self.context.get_counterparty_node_id());
+		let were_node_one = node_id.as_slice() < counterparty_node_id.as_slice();
 
-	/// Determines whether we should disconnect the counterparty due to not receiving a response
-	/// within our expected timeframe.
-	///
-	/// This should be called on every [`super::channelmanager::ChannelManager::timer_tick_occurred`].
-	pub fn should_disconnect_peer_awaiting_response(&mut self) -> bool {
-		let ticks_elapsed = if let Some(ticks_elapsed) = self.context.sent_message_awaiting_response.as_mut() {
-			ticks_elapsed
-		} else {
-			// Don't disconnect when we're not waiting on a response.
-			return false;
+		let msg = msgs::UnsignedChannelAnnouncement {
+			features: channelmanager::provided_channel_features(&user_config),
+			chain_hash,
+			short_channel_id,
+			node_id_1: if were_node_one { node_id } else { counterparty_node_id },
+			node_id_2: if were_node_one { counterparty_node_id } else { node_id },
+			bitcoin_key_1: NodeId::from_pubkey(if were_node_one { &self.context.get_holder_pubkeys().funding_pubkey } else { self.context.counterparty_funding_pubkey() }),
+			bitcoin_key_2: NodeId::from_pubkey(if were_node_one { self.context.counterparty_funding_pubkey() } else { &self.context.get_holder_pubkeys().funding_pubkey }),
+			excess_data: Vec::new(),
 		};
-		*ticks_elapsed += 1;
-		*ticks_elapsed >= DISCONNECT_PEER_AWAITING_RESPONSE_TICKS
+
+		Ok(msg)
 	}
 
-	pub fn shutdown<SP: Deref>(
-		&mut self, signer_provider: &SP, their_features: &InitFeatures, msg: &msgs::Shutdown
-	) -> Result<(Option<msgs::Shutdown>, Option<ChannelMonitorUpdate>, Vec<(HTLCSource, PaymentHash)>), ChannelError>
-	where SP::Target: SignerProvider
+	fn get_announcement_sigs<NS: Deref, L: Deref>(
+		&mut self, node_signer: &NS, genesis_block_hash: BlockHash, user_config: &UserConfig,
+		best_block_height: u32, logger: &L
+	) -> Option<msgs::AnnouncementSignatures>
+	where
+		NS::Target: NodeSigner,
+		L::Target: Logger
 	{
-		if self.context.channel_state & (ChannelState::PeerDisconnected as u32) == ChannelState::PeerDisconnected as u32 {
-			return Err(ChannelError::Close("Peer sent shutdown when we needed a channel_reestablish".to_owned()));
-		}
-		if self.context.channel_state < ChannelState::FundingSent as u32 {
-			// Spec says we should fail the connection, not the channel, but that's nonsense, there
-			// are plenty of reasons you may want to fail a channel pre-funding, and spec says you
-			// can do that via error message without getting a connection fail anyway...
-			return Err(ChannelError::Close("Peer sent shutdown pre-funding generation".to_owned()));
-		}
-		for htlc in self.context.pending_inbound_htlcs.iter() {
-			if let InboundHTLCState::RemoteAnnounced(_) = htlc.state {
-				return Err(ChannelError::Close("Got shutdown with remote pending HTLCs".to_owned()));
-			}
+		if self.context.funding_tx_confirmation_height == 0 || self.context.funding_tx_confirmation_height + 5 > best_block_height {
+			return None;
 		}
-		assert_eq!(self.context.channel_state & ChannelState::ShutdownComplete as u32, 0);
 
-		if !script::is_bolt2_compliant(&msg.scriptpubkey, their_features) {
-			return Err(ChannelError::Warn(format!("Got a nonstandard scriptpubkey ({}) from remote peer", msg.scriptpubkey.to_bytes().to_hex())));
+		if !self.context.is_usable() {
+			return None;
 		}
 
-		if self.context.counterparty_shutdown_scriptpubkey.is_some() {
-			if Some(&msg.scriptpubkey) != self.context.counterparty_shutdown_scriptpubkey.as_ref() {
-				return Err(ChannelError::Warn(format!("Got shutdown request with a scriptpubkey ({}) which did not match their previous scriptpubkey.", msg.scriptpubkey.to_bytes().to_hex())));
-			}
-		} else {
-			self.context.counterparty_shutdown_scriptpubkey = Some(msg.scriptpubkey.clone());
+		if self.context.channel_state & ChannelState::PeerDisconnected as u32 != 0 {
+			log_trace!(logger, "Cannot create an announcement_signatures as our peer is disconnected");
+			return None;
 		}
 
-		// If we have any LocalAnnounced updates we'll probably just get back an update_fail_htlc
-		// immediately after the commitment dance, but we can send a Shutdown because we won't send
-		// any further commitment updates after we set LocalShutdownSent.
-		let send_shutdown = (self.context.channel_state & ChannelState::LocalShutdownSent as u32) != ChannelState::LocalShutdownSent as u32;
+		if self.context.announcement_sigs_state != AnnouncementSigsState::NotSent {
+			return None;
+		}
 
-		let update_shutdown_script = match self.context.shutdown_scriptpubkey {
-			Some(_) => false,
-			None => {
-				assert!(send_shutdown);
-				let shutdown_scriptpubkey = match signer_provider.get_shutdown_scriptpubkey() {
-					Ok(scriptpubkey) => scriptpubkey,
-					Err(_) => return Err(ChannelError::Close("Failed to get shutdown scriptpubkey".to_owned())),
-				};
-				if !shutdown_scriptpubkey.is_compatible(their_features) {
-					return Err(ChannelError::Close(format!("Provided a scriptpubkey format not accepted by peer: {}", shutdown_scriptpubkey)));
-				}
-				self.context.shutdown_scriptpubkey = Some(shutdown_scriptpubkey);
-				true
+		log_trace!(logger, "Creating an announcement_signatures message for channel {}", log_bytes!(self.context.channel_id()));
+		let announcement = match self.get_channel_announcement(node_signer, genesis_block_hash, user_config) {
+			Ok(a) => a,
+			Err(e) => {
+				log_trace!(logger, "{:?}", e);
+				return None;
+			}
+		};
+		let our_node_sig = match node_signer.sign_gossip_message(msgs::UnsignedGossipMessage::ChannelAnnouncement(&announcement)) {
+			Err(_) => {
+				log_error!(logger, "Failed to generate node signature for channel_announcement. Channel will not be announced!");
+				return None;
+			},
+			Ok(v) => v
+		};
+		let our_bitcoin_sig = match self.context.holder_signer.sign_channel_announcement_with_funding_key(&announcement, &self.context.secp_ctx) {
+			Err(_) => {
+				log_error!(logger, "Signer rejected channel_announcement signing. Channel will not be announced!");
+				return None;
 			},
+			Ok(v) => v
+		};
+		let short_channel_id = match self.context.get_short_channel_id() {
+			Some(scid) => scid,
+			None => return None,
 		};
 
-		// From here on out, we may not fail!
+		self.context.announcement_sigs_state = AnnouncementSigsState::MessageSent;
 
-		self.context.channel_state |= ChannelState::RemoteShutdownSent as u32;
-		self.context.update_time_counter += 1;
+		Some(msgs::AnnouncementSignatures {
+			channel_id: self.context.channel_id(),
+			short_channel_id,
+			node_signature: our_node_sig,
+			bitcoin_signature: our_bitcoin_sig,
+		})
+	}
 
-		let monitor_update = if update_shutdown_script {
-			self.context.latest_monitor_update_id += 1;
-			let monitor_update = ChannelMonitorUpdate {
-				update_id: self.context.latest_monitor_update_id,
-				updates: vec![ChannelMonitorUpdateStep::ShutdownScript {
-					scriptpubkey: self.get_closing_scriptpubkey(),
-				}],
-			};
-			self.monitor_updating_paused(false, false, false, Vec::new(), Vec::new(), Vec::new());
-			self.push_ret_blockable_mon_update(monitor_update)
-		} else { None };
-		let shutdown = if send_shutdown {
-			Some(msgs::Shutdown {
-				channel_id: self.context.channel_id,
-				scriptpubkey: self.get_closing_scriptpubkey(),
+	/// Signs the given channel announcement, returning a ChannelError::Ignore if no keys are
+	/// available.
+	fn sign_channel_announcement<NS: Deref>(
+		&self, node_signer: &NS, announcement: msgs::UnsignedChannelAnnouncement
+	) -> Result<msgs::ChannelAnnouncement, ChannelError> where NS::Target: NodeSigner {
+		if let Some((their_node_sig, their_bitcoin_sig)) = self.context.announcement_sigs {
+			let our_node_key = NodeId::from_pubkey(&node_signer.get_node_id(Recipient::Node)
+				.map_err(|_| ChannelError::Ignore("Signer failed to retrieve own public key".to_owned()))?);
+			let were_node_one = announcement.node_id_1 == our_node_key;
+
+			let our_node_sig = node_signer.sign_gossip_message(msgs::UnsignedGossipMessage::ChannelAnnouncement(&announcement))
+				.map_err(|_| ChannelError::Ignore("Failed to generate node signature for channel_announcement".to_owned()))?;
+			let our_bitcoin_sig = self.context.holder_signer.sign_channel_announcement_with_funding_key(&announcement, &self.context.secp_ctx)
+				.map_err(|_| ChannelError::Ignore("Signer rejected channel_announcement".to_owned()))?;
+			Ok(msgs::ChannelAnnouncement {
+				node_signature_1: if were_node_one { our_node_sig } else { their_node_sig },
+				node_signature_2: if were_node_one { their_node_sig } else { our_node_sig },
+				bitcoin_signature_1: if were_node_one { our_bitcoin_sig } else { their_bitcoin_sig },
+				bitcoin_signature_2: if were_node_one { their_bitcoin_sig } else { our_bitcoin_sig },
+				contents: announcement,
 			})
-		} else { None };
+		} else {
+			Err(ChannelError::Ignore("Attempted to sign channel announcement before we'd received announcement_signatures".to_string()))
+		}
+	}
 
-		// We can't send our shutdown until we've committed all of our pending HTLCs, but the
-		// remote side is unlikely to accept any new HTLCs, so we go ahead and "free" any holding
-		// cell HTLCs and return them to fail the payment.
-		self.context.holding_cell_update_fee = None;
-		let mut dropped_outbound_htlcs = Vec::with_capacity(self.context.holding_cell_htlc_updates.len());
-		self.context.holding_cell_htlc_updates.retain(|htlc_update| {
-			match htlc_update {
-				&HTLCUpdateAwaitingACK::AddHTLC { ref payment_hash, ref source, .. } => {
-					dropped_outbound_htlcs.push((source.clone(), payment_hash.clone()));
-					false
-				},
-				_ => true
-			}
-		});
+	/// Processes an incoming announcement_signatures message, providing a fully-signed
+	/// channel_announcement message which we can broadcast and storing our counterparty's
+	/// signatures for later reconstruction/rebroadcast of the channel_announcement.
+	pub fn announcement_signatures<NS: Deref>(
+		&mut self, node_signer: &NS, chain_hash: BlockHash, best_block_height: u32,
+		msg: &msgs::AnnouncementSignatures, user_config: &UserConfig
+	) -> Result<msgs::ChannelAnnouncement, ChannelError> where NS::Target: NodeSigner {
+		let announcement = self.get_channel_announcement(node_signer, chain_hash, user_config)?;
 
-		self.context.channel_state |= ChannelState::LocalShutdownSent as u32;
-		self.context.update_time_counter += 1;
+		let msghash = hash_to_message!(&Sha256d::hash(&announcement.encode()[..])[..]);
 
-		Ok((shutdown, monitor_update, dropped_outbound_htlcs))
+		if self.context.secp_ctx.verify_ecdsa(&msghash, &msg.node_signature, &self.context.get_counterparty_node_id()).is_err() {
+			return Err(ChannelError::Close(format!(
+				"Bad announcement_signatures. Failed to verify node_signature. UnsignedChannelAnnouncement used for verification is {:?}. their_node_key is {:?}",
+				 &announcement, self.context.get_counterparty_node_id())));
+		}
+		if self.context.secp_ctx.verify_ecdsa(&msghash, &msg.bitcoin_signature, self.context.counterparty_funding_pubkey()).is_err() {
+			return Err(ChannelError::Close(format!(
+				"Bad announcement_signatures. Failed to verify bitcoin_signature. UnsignedChannelAnnouncement used for verification is {:?}. their_bitcoin_key is ({:?})",
+				&announcement, self.context.counterparty_funding_pubkey())));
+		}
+
+		self.context.announcement_sigs = Some((msg.node_signature, msg.bitcoin_signature));
+		if self.context.funding_tx_confirmation_height == 0 || self.context.funding_tx_confirmation_height + 5 > best_block_height {
+			return Err(ChannelError::Ignore(
+				"Got announcement_signatures prior to the required six confirmations - we may not have received a block yet that our peer has".to_owned()));
+		}
+
+		self.sign_channel_announcement(node_signer, announcement)
 	}
 
-	fn build_signed_closing_transaction(&self, closing_tx: &ClosingTransaction, counterparty_sig: &Signature, sig: &Signature) -> Transaction {
-		let mut tx = closing_tx.trust().built_transaction().clone();
+	/// Gets a signed channel_announcement for this channel, if we previously received an
+	/// announcement_signatures from our counterparty.
+	pub fn get_signed_channel_announcement<NS: Deref>(
+		&self, node_signer: &NS, chain_hash: BlockHash, best_block_height: u32, user_config: &UserConfig
+	) -> Option<msgs::ChannelAnnouncement> where NS::Target: NodeSigner {
+		if self.context.funding_tx_confirmation_height == 0 || self.context.funding_tx_confirmation_height + 5 > best_block_height {
+			return None;
+		}
+		let announcement = match self.get_channel_announcement(node_signer, chain_hash, user_config) {
+			Ok(res) => res,
+			Err(_) => return None,
+		};
+		match self.sign_channel_announcement(node_signer, announcement) {
+			Ok(res) => Some(res),
+			Err(_) => None,
+		}
+	}
+
+	/// May panic if called on a channel that wasn't immediately-previously
+	/// self.remove_uncommitted_htlcs_and_mark_paused()'d
+	pub fn get_channel_reestablish<L: Deref>(&mut self, logger: &L) -> msgs::ChannelReestablish where L::Target: Logger {
+		assert_eq!(self.context.channel_state & ChannelState::PeerDisconnected as u32, ChannelState::PeerDisconnected as u32);
+		assert_ne!(self.context.cur_counterparty_commitment_transaction_number, INITIAL_COMMITMENT_NUMBER);
+		// Prior to static_remotekey, my_current_per_commitment_point was critical to claiming
+		// current to_remote balances. However, it no longer has any use, and thus is now simply
+		// set to a dummy (but valid, as required by the spec) public key.
+		// fuzzing mode marks a subset of pubkeys as invalid so that we can hit "invalid pubkey"
+		// branches, but we unwrap it below, so we arbitrarily select a dummy pubkey which is both
+		// valid, and valid in fuzzing mode's arbitrary validity criteria:
+		let mut pk = [2; 33]; pk[1] = 0xff;
+		let dummy_pubkey = PublicKey::from_slice(&pk).unwrap();
+		let remote_last_secret = if self.context.cur_counterparty_commitment_transaction_number + 1 < INITIAL_COMMITMENT_NUMBER {
+			let remote_last_secret = self.context.commitment_secrets.get_secret(self.context.cur_counterparty_commitment_transaction_number + 2).unwrap();
+			log_trace!(logger, "Enough info to generate a Data Loss Protect with per_commitment_secret {} for channel {}", log_bytes!(remote_last_secret), log_bytes!(self.context.channel_id()));
+			remote_last_secret
+		} else {
+			log_info!(logger, "Sending a data_loss_protect with no previous remote per_commitment_secret for channel {}", log_bytes!(self.context.channel_id()));
+			[0;32]
+		};
+		self.mark_awaiting_response();
+		msgs::ChannelReestablish {
+			channel_id: self.context.channel_id(),
+			// The protocol has two different commitment number concepts - the "commitment
+			// transaction number", which starts from 0 and counts up, and the "revocation key
+			// index" which starts at INITIAL_COMMITMENT_NUMBER and counts down. We track
+			// commitment transaction numbers by the index which will be used to reveal the
+			// revocation key for that commitment transaction, which means we have to convert them
+			// to protocol-level commitment numbers here...
+
+			// next_local_commitment_number is the next commitment_signed number we expect to
+			// receive (indicating if they need to resend one that we missed).
+			next_local_commitment_number: INITIAL_COMMITMENT_NUMBER - self.context.cur_holder_commitment_transaction_number,
+			// We have to set next_remote_commitment_number to the next revoke_and_ack we expect to
+			// receive, however we track it by the next commitment number for a remote transaction
+			// (which is one further, as they always revoke previous commitment transaction, not
+			// the one we send) so we have to decrement by 1. Note that if
+			// cur_counterparty_commitment_transaction_number is INITIAL_COMMITMENT_NUMBER we will have
+			// dropped this channel on disconnect as it hasn't yet reached FundingSent so we can't
+			// overflow here.
+			next_remote_commitment_number: INITIAL_COMMITMENT_NUMBER - self.context.cur_counterparty_commitment_transaction_number - 1,
+			your_last_per_commitment_secret: remote_last_secret,
+			my_current_per_commitment_point: dummy_pubkey,
+			// If we've sent `commitment_signed` for an interactive transaction construction but have
+			// not received `tx_signatures` we MUST set `next_funding_txid` to the txid of that
+			// interactive transaction, else we MUST NOT set it.
+			next_funding_txid: self.context.pending_splice_signing_session()
+				.or(self.context.interactive_tx_signing_session.as_ref())
+				.filter(|session| !session.has_received_tx_signatures())
+				.map(|session| session.unsigned_tx().txid()),
+			// Lets our counterparty know whether we switched to the channel type of an upgrade they
+			// accepted before we got disconnected, or whether we still know of having accepted
+			// theirs.
+			channel_type: match self.context.pending_channel_type_upgrade.as_ref() {
+				Some(upgrade) if upgrade.state == ChannelTypeUpgradeState::Accepted => Some(upgrade.channel_type.clone()),
+				_ => Some(self.context.channel_type.clone()),
+			},
+		}
+	}
 
-		tx.input[0].witness.push(Vec::new()); // First is the multisig dummy
 
-		let funding_key = self.context.get_holder_pubkeys().funding_pubkey.serialize();
-		let counterparty_funding_key = self.context.counterparty_funding_pubkey().serialize();
-		let mut holder_sig = sig.serialize_der().to_vec();
-		holder_sig.push(EcdsaSighashType::All as u8);
-		let mut cp_sig = counterparty_sig.serialize_der().to_vec();
-		cp_sig.push(EcdsaSighashType::All as u8);
-		if funding_key[..] < counterparty_funding_key[..] {
-			tx.input[0].witness.push(holder_sig);
-			tx.input[0].witness.push(cp_sig);
-		} else {
-			tx.input[0].witness.push(cp_sig);
-			tx.input[0].witness.push(holder_sig);
-		}
+	// Send stuff to our remote peers:
 
-		tx.input[0].witness.push(self.context.get_funding_redeemscript().into_bytes());
-		tx
+	/// Queues up an outbound HTLC to send by placing it in the holding cell. You should call
+	/// [`Self::maybe_free_holding_cell_htlcs`] in order to actually generate and send the
+	/// commitment update.
+	///
+	/// `Err`s will only be [`ChannelError::Ignore`].
+	pub fn queue_add_htlc<F: Deref, L: Deref>(
+		&mut self, amount_msat: u64, payment_hash: PaymentHash, cltv_expiry: u32, source: HTLCSource,
+		onion_routing_packet: msgs::OnionPacket, skimmed_fee_msat: Option<u64>,
+		blinding_point: Option<PublicKey>, fee_estimator: &LowerBoundedFeeEstimator<F>, logger: &L
+	) -> Result<(), ChannelError>
+	where F::Target: FeeEstimator, L::Target: Logger
+	{
+		self
+			.send_htlc(amount_msat, payment_hash, cltv_expiry, source, onion_routing_packet, true,
+				skimmed_fee_msat, blinding_point, fee_estimator, logger)
+			.map(|msg_opt| assert!(msg_opt.is_none(), "We forced holding cell?"))
+			.map_err(|err| {
+				if let ChannelError::Ignore(_) = err { /* fine */ }
+				else { debug_assert!(false, "Queueing cannot trigger channel failure"); }
+				err
+			})
 	}
 
-	pub fn closing_signed<F: Deref>(
-		&mut self, fee_estimator: &LowerBoundedFeeEstimator<F>, msg: &msgs::ClosingSigned)
-		-> Result<(Option<msgs::ClosingSigned>, Option<Transaction>), ChannelError>
-		where F::Target: FeeEstimator
+	/// Adds a pending outbound HTLC to this channel, note that you probably want
+	/// [`Self::send_htlc_and_commit`] instead cause you'll want both messages at once.
+	///
+	/// This returns an optional UpdateAddHTLC as we may be in a state where we cannot add HTLCs on
+	/// the wire:
+	/// * In cases where we're waiting on the remote peer to send us a revoke_and_ack, we
+	///   wouldn't be able to determine what they actually ACK'ed if we have two sets of updates
+	///   awaiting ACK.
+	/// * In cases where we're marked MonitorUpdateInProgress, we cannot commit to a new state as
+	///   we may not yet have sent the previous commitment update messages and will need to
+	///   regenerate them.
+	///
+	/// You MUST call [`Self::send_commitment_no_state_update`] prior to calling any other methods
+	/// on this [`Channel`] if `force_holding_cell` is false.
+	///
+	/// `Err`s will only be [`ChannelError::Ignore`].
+	fn send_htlc<F: Deref, L: Deref>(
+		&mut self, amount_msat: u64, payment_hash: PaymentHash, cltv_expiry: u32, source: HTLCSource,
+		onion_routing_packet: msgs::OnionPacket, mut force_holding_cell: bool,
+		skimmed_fee_msat: Option<u64>, blinding_point: Option<PublicKey>,
+		fee_estimator: &LowerBoundedFeeEstimator<F>, logger: &L
+	) -> Result<Option<msgs::UpdateAddHTLC>, ChannelError>
+	where F::Target: FeeEstimator, L::Target: Logger
 	{
-		if self.context.channel_state & BOTH_SIDES_SHUTDOWN_MASK != BOTH_SIDES_SHUTDOWN_MASK {
-			return Err(ChannelError::Close("Remote end sent us a closing_signed before both sides provided a shutdown".to_owned()));
-		}
-		if self.context.channel_state & (ChannelState::PeerDisconnected as u32) == ChannelState::PeerDisconnected as u32 {
-			return Err(ChannelError::Close("Peer sent closing_signed when we needed a channel_reestablish".to_owned()));
+		if (self.context.channel_state & (ChannelState::ChannelReady as u32 | BOTH_SIDES_SHUTDOWN_MASK)) != (ChannelState::ChannelReady as u32) {
+			return Err(ChannelError::Ignore("Cannot send HTLC until channel is fully established and we haven't started shutting down".to_owned()));
 		}
-		if !self.context.pending_inbound_htlcs.is_empty() || !self.context.pending_outbound_htlcs.is_empty() {
-			return Err(ChannelError::Close("Remote end sent us a closing_signed while there were still pending HTLCs".to_owned()));
+		let channel_total_msat = self.context.channel_value_satoshis * 1000;
+		if amount_msat > channel_total_msat {
+			return Err(ChannelError::Ignore(format!("Cannot send amount {}, because it is more than the total value of the channel {}", amount_msat, channel_total_msat)));
 		}
-		if msg.fee_satoshis > TOTAL_BITCOIN_SUPPLY_SATOSHIS { // this is required to stop potential overflow in build_closing_transaction
-			return Err(ChannelError::Close("Remote tried to send us a closing tx with > 21 million BTC fee".to_owned()));
+
+		if amount_msat == 0 {
+			return Err(ChannelError::Ignore("Cannot send 0-msat HTLC".to_owned()));
 		}
 
-		if self.context.is_outbound() && self.context.last_sent_closing_fee.is_none() {
-			return Err(ChannelError::Close("Remote tried to send a closing_signed when we were supposed to propose the first one".to_owned()));
+		let available_balances = self.context.get_available_balances(fee_estimator);
+		if amount_msat < available_balances.next_outbound_htlc_minimum_msat {
+			return Err(ChannelError::Ignore(format!("Cannot send less than our next-HTLC minimum - {} msat",
+				available_balances.next_outbound_htlc_minimum_msat)));
 		}
 
-		if self.context.channel_state & ChannelState::MonitorUpdateInProgress as u32 != 0 {
-			self.context.pending_counterparty_closing_signed = Some(msg.clone());
-			return Ok((None, None));
+		if amount_msat > available_balances.next_outbound_htlc_limit_msat {
+			return Err(ChannelError::Ignore(format!("Cannot send more than our next-HTLC maximum - {} msat",
+				available_balances.next_outbound_htlc_limit_msat)));
 		}
 
-		let funding_redeemscript = self.context.get_funding_redeemscript();
-		let (mut closing_tx, used_total_fee) = self.build_closing_transaction(msg.fee_satoshis, false);
-		if used_total_fee != msg.fee_satoshis {
-			return Err(ChannelError::Close(format!("Remote sent us a closing_signed with a fee other than the value they can claim. Fee in message: {}. Actual closing tx fee: {}", msg.fee_satoshis, used_total_fee)));
+		if (self.context.channel_state & (ChannelState::PeerDisconnected as u32)) != 0 {
+			// Note that this should never really happen, if we're !is_live() on receipt of an
+			// incoming HTLC for relay will result in us rejecting the HTLC and we won't allow
+			// the user to send directly into a !is_live() channel. However, if we
+			// disconnected during the time the previous hop was doing the commitment dance we may
+			// end up getting here after the forwarding delay. In any case, returning an
+			// IgnoreError will get ChannelManager to do the right thing and fail backwards now.
+			return Err(ChannelError::Ignore("Cannot send an HTLC while disconnected from channel counterparty".to_owned()));
 		}
-		let sighash = closing_tx.trust().get_sighash_all(&funding_redeemscript, self.context.channel_value_satoshis);
 
-		match self.context.secp_ctx.verify_ecdsa(&sighash, &msg.signature, &self.context.get_counterparty_pubkeys().funding_pubkey) {
-			Ok(_) => {},
-			Err(_e) => {
-				// The remote end may have decided to revoke their output due to inconsistent dust
-				// limits, so check for that case by re-checking the signature here.
-				closing_tx = self.build_closing_transaction(msg.fee_satoshis, true).0;
-				let sighash = closing_tx.trust().get_sighash_all(&funding_redeemscript, self.context.channel_value_satoshis);
-				secp_check!(self.context.secp_ctx.verify_ecdsa(&sighash, &msg.signature, self.context.counterparty_funding_pubkey()), "Invalid closing tx signature from peer".to_owned());
-			},
-		};
+		let need_holding_cell = (self.context.channel_state & (ChannelState::AwaitingRemoteRevoke as u32 | ChannelState::MonitorUpdateInProgress as u32 | QUIESCENCE_STATE_FLAGS)) != 0;
+		log_debug!(logger, "Pushing new outbound HTLC for {} msat {}", amount_msat,
+			if force_holding_cell { "into holding cell" }
+			else if need_holding_cell { "into holding cell as we're awaiting an RAA or monitor" }
+			else { "to peer" });
 
-		for outp in closing_tx.trust().built_transaction().output.iter() {
-			if !outp.script_pubkey.is_witness_program() && outp.value < MAX_STD_OUTPUT_DUST_LIMIT_SATOSHIS {
-				return Err(ChannelError::Close("Remote sent us a closing_signed with a dust output. Always use segwit closing scripts!".to_owned()));
-			}
+		if need_holding_cell {
+			force_holding_cell = true;
 		}
 
-		assert!(self.context.shutdown_scriptpubkey.is_some());
-		if let Some((last_fee, sig)) = self.context.last_sent_closing_fee {
-			if last_fee == msg.fee_satoshis {
-				let tx = self.build_signed_closing_transaction(&mut closing_tx, &msg.signature, &sig);
-				self.context.channel_state = ChannelState::ShutdownComplete as u32;
-				self.context.update_time_counter += 1;
-				return Ok((None, Some(tx)));
-			}
+		// Now update local state:
+		if force_holding_cell {
+			self.context.holding_cell_htlc_updates.push(HTLCUpdateAwaitingACK::AddHTLC {
+				amount_msat,
+				payment_hash,
+				cltv_expiry,
+				source,
+				onion_routing_packet,
+				skimmed_fee_msat,
+				blinding_point,
+			});
+			return Ok(None);
 		}
 
-		let (our_min_fee, our_max_fee) = self.calculate_closing_fee_limits(fee_estimator);
-
-		macro_rules! propose_fee {
-			($new_fee: expr) => {
-				let (closing_tx, used_fee) = if $new_fee == msg.fee_satoshis {
-					(closing_tx, $new_fee)
-				} else {
-					self.build_closing_transaction($new_fee, false)
-				};
-
-				let sig = self.context.holder_signer
-					.sign_closing_transaction(&closing_tx, &self.context.secp_ctx)
-					.map_err(|_| ChannelError::Close("External signer refused to sign closing transaction".to_owned()))?;
-
-				let signed_tx = if $new_fee == msg.fee_satoshis {
-					self.context.channel_state = ChannelState::ShutdownComplete as u32;
-					self.context.update_time_counter += 1;
-					let tx = self.build_signed_closing_transaction(&closing_tx, &msg.signature, &sig);
-					Some(tx)
-				} else { None };
+		self.context.pending_outbound_htlcs.push(OutboundHTLCOutput {
+			htlc_id: self.context.next_holder_htlc_id,
+			amount_msat,
+			payment_hash: payment_hash.clone(),
+			cltv_expiry,
+			state: OutboundHTLCState::LocalAnnounced(Box::new(onion_routing_packet.clone())),
+			source,
+			blinding_point,
+			skimmed_fee_msat,
+		});
 
-				self.context.last_sent_closing_fee = Some((used_fee, sig.clone()));
-				return Ok((Some(msgs::ClosingSigned {
-					channel_id: self.context.channel_id,
-					fee_satoshis: used_fee,
-					signature: sig,
-					fee_range: Some(msgs::ClosingSignedFeeRange {
-						min_fee_satoshis: our_min_fee,
-						max_fee_satoshis: our_max_fee,
-					}),
-				}), signed_tx))
-			}
-		}
+		let res = msgs::UpdateAddHTLC {
+			channel_id: self.context.channel_id,
+			htlc_id: self.context.next_holder_htlc_id,
+			amount_msat,
+			payment_hash,
+			cltv_expiry,
+			onion_routing_packet,
+			skimmed_fee_msat,
+			blinding_point,
+		};
+		self.context.next_holder_htlc_id += 1;
 
-		if let Some(msgs::ClosingSignedFeeRange { min_fee_satoshis, max_fee_satoshis }) = msg.fee_range {
-			if msg.fee_satoshis < min_fee_satoshis || msg.fee_satoshis > max_fee_satoshis {
-				return Err(ChannelError::Close(format!("Peer sent a bogus closing_signed - suggested fee of {} sat was not in their desired range of {} sat - {} sat", msg.fee_satoshis, min_fee_satoshis, max_fee_satoshis)));
-			}
-			if max_fee_satoshis < our_min_fee {
-				return Err(ChannelError::Warn(format!("Unable to come to consensus about closing feerate, remote's max fee ({} sat) was smaller than our min fee ({} sat)", max_fee_satoshis, our_min_fee)));
-			}
-			if min_fee_satoshis > our_max_fee {
-				return Err(ChannelError::Warn(format!("Unable to come to consensus about closing feerate, remote's min fee ({} sat) was greater than our max fee ({} sat)", min_fee_satoshis, our_max_fee)));
-			}
+		Ok(Some(res))
+	}
 
-			if !self.context.is_outbound() {
-				// They have to pay, so pick the highest fee in the overlapping range.
-				// We should never set an upper bound aside from their full balance
-				debug_assert_eq!(our_max_fee, self.context.channel_value_satoshis - (self.context.value_to_self_msat + 999) / 1000);
-				propose_fee!(cmp::min(max_fee_satoshis, our_max_fee));
-			} else {
-				if msg.fee_satoshis < our_min_fee || msg.fee_satoshis > our_max_fee {
-					return Err(ChannelError::Close(format!("Peer sent a bogus closing_signed - suggested fee of {} sat was not in our desired range of {} sat - {} sat after we informed them of our range.",
-						msg.fee_satoshis, our_min_fee, our_max_fee)));
-				}
-				// The proposed fee is in our acceptable range, accept it and broadcast!
-				propose_fee!(msg.fee_satoshis);
-			}
-		} else {
-			// Old fee style negotiation. We don't bother to enforce whether they are complying
-			// with the "making progress" requirements, we just comply and hope for the best.
-			if let Some((last_fee, _)) = self.context.last_sent_closing_fee {
-				if msg.fee_satoshis > last_fee {
-					if msg.fee_satoshis < our_max_fee {
-						propose_fee!(msg.fee_satoshis);
-					} else if last_fee < our_max_fee {
-						propose_fee!(our_max_fee);
-					} else {
-						return Err(ChannelError::Close(format!("Unable to come to consensus about closing feerate, remote wants something ({} sat) higher than our max fee ({} sat)", msg.fee_satoshis, our_max_fee)));
-					}
-				} else {
-					if msg.fee_satoshis > our_min_fee {
-						propose_fee!(msg.fee_satoshis);
-					} else if last_fee > our_min_fee {
-						propose_fee!(our_min_fee);
-					} else {
-						return Err(ChannelError::Close(format!("Unable to come to consensus about closing feerate, remote wants something ({} sat) lower than our min fee ({} sat)", msg.fee_satoshis, our_min_fee)));
-					}
-				}
-			} else {
-				if msg.fee_satoshis < our_min_fee {
-					propose_fee!(our_min_fee);
-				} else if msg.fee_satoshis > our_max_fee {
-					propose_fee!(our_max_fee);
-				} else {
-					propose_fee!(msg.fee_satoshis);
-				}
+	fn build_commitment_no_status_check<L: Deref>(&mut self, logger: &L) -> ChannelMonitorUpdate where L::Target: Logger {
+		log_trace!(logger, "Updating HTLC state for a newly-sent commitment_signed...");
+		// We can upgrade the status of some HTLCs that are waiting on a commitment, even if we
+		// fail to generate this, we still are at least at a position where upgrading their status
+		// is acceptable.
+		for htlc in self.context.pending_inbound_htlcs.iter_mut() {
+			let new_state = if let &InboundHTLCState::AwaitingRemoteRevokeToAnnounce(ref forward_info) = &htlc.state {
+				Some(InboundHTLCState::AwaitingAnnouncedRemoteRevoke(forward_info.clone()))
+			} else { None };
+			if let Some(state) = new_state {
+				log_trace!(logger, " ...promoting inbound AwaitingRemoteRevokeToAnnounce {} to AwaitingAnnouncedRemoteRevoke", log_bytes!(htlc.payment_hash.0));
+				htlc.state = state;
 			}
 		}
-	}
-
-	fn internal_htlc_satisfies_config(
-		&self, htlc: &msgs::UpdateAddHTLC, amt_to_forward: u64, outgoing_cltv_value: u32, config: &ChannelConfig,
-	) -> Result<(), (&'static str, u16)> {
-		let fee = amt_to_forward.checked_mul(config.forwarding_fee_proportional_millionths as u64)
-			.and_then(|prop_fee| (prop_fee / 1000000).checked_add(config.forwarding_fee_base_msat as u64));
-		if fee.is_none() || htlc.amount_msat < fee.unwrap() ||
-			(htlc.amount_msat - fee.unwrap()) < amt_to_forward {
-			return Err((
-				"Prior hop has deviated from specified fees parameters or origin node has obsolete ones",
-				0x1000 | 12, // fee_insufficient
-			));
+		for htlc in self.context.pending_outbound_htlcs.iter_mut() {
+			if let &mut OutboundHTLCState::AwaitingRemoteRevokeToRemove(ref mut outcome) = &mut htlc.state {
+				log_trace!(logger, " ...promoting outbound AwaitingRemoteRevokeToRemove {} to AwaitingRemovedRemoteRevoke", log_bytes!(htlc.payment_hash.0));
+				// Grab the preimage, if it exists, instead of cloning
+				let mut reason = OutboundHTLCOutcome::Success(None);
+				mem::swap(outcome, &mut reason);
+				htlc.state = OutboundHTLCState::AwaitingRemovedRemoteRevoke(reason);
+			}
 		}
-		if (htlc.cltv_expiry as u64) < outgoing_cltv_value as u64 + config.cltv_expiry_delta as u64 {
-			return Err((
-				"Forwarding node has tampered with the intended HTLC values or origin node has an obsolete cltv_expiry_delta",
-				0x1000 | 13, // incorrect_cltv_expiry
-			));
+		if let Some((feerate, update_state)) = self.context.pending_update_fee {
+			if update_state == FeeUpdateState::AwaitingRemoteRevokeToAnnounce {
+				debug_assert!(!self.context.is_outbound());
+				log_trace!(logger, " ...promoting inbound AwaitingRemoteRevokeToAnnounce fee update {} to Committed", feerate);
+				self.context.feerate_per_kw = feerate;
+				self.context.pending_update_fee = None;
+			}
 		}
-		Ok(())
-	}
+		self.context.resend_order = RAACommitmentOrder::RevokeAndACKFirst;
 
-	/// Determines whether the parameters of an incoming HTLC to be forwarded satisfy the channel's
-	/// [`ChannelConfig`]. This first looks at the channel's current [`ChannelConfig`], and if
-	/// unsuccessful, falls back to the previous one if one exists.
-	pub fn htlc_satisfies_config(
-		&self, htlc: &msgs::UpdateAddHTLC, amt_to_forward: u64, outgoing_cltv_value: u32,
-	) -> Result<(), (&'static str, u16)> {
-		self.internal_htlc_satisfies_config(&htlc, amt_to_forward, outgoing_cltv_value, &self.context.config())
-			.or_else(|err| {
-				if let Some(prev_config) = self.context.prev_config() {
-					self.internal_htlc_satisfies_config(htlc, amt_to_forward, outgoing_cltv_value, &prev_config)
-				} else {
-					Err(err)
-				}
-			})
-	}
+		let (counterparty_commitment_txid, mut htlcs_ref) = self.build_commitment_no_state_update(logger);
+		let htlcs: Vec<(HTLCOutputInCommitment, Option<Box<HTLCSource>>)> =
+			htlcs_ref.drain(..).map(|(htlc, htlc_source)| (htlc, htlc_source.map(|source_ref| Box::new(source_ref.clone())))).collect();
 
-	pub fn get_cur_holder_commitment_transaction_number(&self) -> u64 {
-		self.context.cur_holder_commitment_transaction_number + 1
-	}
+		if self.context.announcement_sigs_state == AnnouncementSigsState::MessageSent {
+			self.context.announcement_sigs_state = AnnouncementSigsState::Committed;
+		}
 
-	pub fn get_cur_counterparty_commitment_transaction_number(&self) -> u64 {
-		self.context.cur_counterparty_commitment_transaction_number + 1 - if self.context.channel_state & (ChannelState::AwaitingRemoteRevoke as u32) != 0 { 1 } else { 0 }
-	}
+		let splice_commitment_txs = self.context.build_splice_counterparty_commitment_txs(
+			self.context.cur_counterparty_commitment_transaction_number,
+			&self.context.counterparty_cur_commitment_point.unwrap(), logger);
 
-	pub fn get_revoked_counterparty_commitment_transaction_number(&self) -> u64 {
-		self.context.cur_counterparty_commitment_transaction_number + 2
+		self.context.latest_monitor_update_id += 1;
+		let monitor_update = ChannelMonitorUpdate {
+			update_id: self.context.latest_monitor_update_id,
+			updates: vec![ChannelMonitorUpdateStep::LatestCounterpartyCommitmentTXInfo {
+				commitment_txid: counterparty_commitment_txid,
+				htlc_outputs: htlcs.clone(),
+				commitment_number: self.context.cur_counterparty_commitment_transaction_number,
+				their_per_commitment_point: self.context.counterparty_cur_commitment_point.unwrap(),
+				splice_commitment_txs,
+			}]
+		};
+		self.context.channel_state |= ChannelState::AwaitingRemoteRevoke as u32;
+		monitor_update
 	}
 
-	#[cfg(test)]
-	pub fn get_signer(&self) -> &Signer {
-		&self.context.holder_signer
-	}
+	/// Builds the [`ChannelMonitorUpdate`] replacing the counterparty commitment transaction we
+	/// signed for a channel type upgrade with one using the channel's current (previous) type,
+	/// after reverting the upgrade. Both are at the same commitment number.
+	fn build_reverted_counterparty_commitment_monitor_update<L: Deref>(&mut self, logger: &L) -> ChannelMonitorUpdate where L::Target: Logger {
+		let (counterparty_commitment_txid, mut htlcs_ref) = self.build_commitment_no_state_update(logger);
+		let htlcs: Vec<(HTLCOutputInCommitment, Option<Box<HTLCSource>>)> =
+			htlcs_ref.drain(..).map(|(htlc, htlc_source)| (htlc, htlc_source.map(|source_ref| Box::new(source_ref.clone())))).collect();
 
-	#[cfg(test)]
-	pub fn get_value_stat(&self) -> ChannelValueStat {
-		ChannelValueStat {
-			value_to_self_msat: self.context.value_to_self_msat,
-			channel_value_msat: self.context.channel_value_satoshis * 1000,
-			channel_reserve_msat: self.context.counterparty_selected_channel_reserve_satoshis.unwrap() * 1000,
-			pending_outbound_htlcs_amount_msat: self.context.pending_outbound_htlcs.iter().map(|ref h| h.amount_msat).sum::<u64>(),
-			pending_inbound_htlcs_amount_msat: self.context.pending_inbound_htlcs.iter().map(|ref h| h.amount_msat).sum::<u64>(),
-			holding_cell_outbound_amount_msat: {
-				let mut res = 0;
-				for h in self.context.holding_cell_htlc_updates.iter() {
-					match h {
-						&HTLCUpdateAwaitingACK::AddHTLC{amount_msat, .. } => {
-							res += amount_msat;
-						}
-						_ => {}
-					}
-				}
-				res
-			},
-			counterparty_max_htlc_value_in_flight_msat: self.context.counterparty_max_htlc_value_in_flight_msat,
-			counterparty_dust_limit_msat: self.context.counterparty_dust_limit_satoshis * 1000,
+		self.context.latest_monitor_update_id += 1;
+		ChannelMonitorUpdate {
+			update_id: self.context.latest_monitor_update_id,
+			updates: vec![ChannelMonitorUpdateStep::LatestCounterpartyCommitmentTXInfo {
+				commitment_txid: counterparty_commitment_txid,
+				htlc_outputs: htlcs,
+				commitment_number: self.context.cur_counterparty_commitment_transaction_number,
+				their_per_commitment_point: self.context.counterparty_cur_commitment_point.unwrap(),
+				splice_commitment_txs: Vec::new(),
+			}]
 		}
 	}
 
-	/// Returns true if this channel has been marked as awaiting a monitor update to move forward.
-	/// Allowed in any state (including after shutdown)
-	pub fn is_awaiting_monitor_update(&self) -> bool {
-		(self.context.channel_state & ChannelState::MonitorUpdateInProgress as u32) != 0
-	}
-
-	/// Gets the latest [`ChannelMonitorUpdate`] ID which has been released and is in-flight.
-	pub fn get_latest_unblocked_monitor_update_id(&self) -> u64 {
-		if self.context.blocked_monitor_updates.is_empty() { return self.context.get_latest_monitor_update_id(); }
-		self.context.blocked_monitor_updates[0].update.update_id - 1
-	}
-
-	/// Returns the next blocked monitor update, if one exists, and a bool which indicates a
-	/// further blocked monitor update exists after the next.
-	pub fn unblock_next_blocked_monitor_update(&mut self) -> Option<(ChannelMonitorUpdate, bool)> {
-		if self.context.blocked_monitor_updates.is_empty() { return None; }
-		Some((self.context.blocked_monitor_updates.remove(0).update,
-			!self.context.blocked_monitor_updates.is_empty()))
-	}
+	fn build_commitment_no_state_update<L: Deref>(&self, logger: &L) -> (Txid, Vec<(HTLCOutputInCommitment, Option<&HTLCSource>)>) where L::Target: Logger {
+		let counterparty_keys = self.context.build_remote_transaction_keys();
+		let commitment_stats = self.context.build_commitment_transaction(self.context.cur_counterparty_commitment_transaction_number, &counterparty_keys, false, true, logger);
+		let counterparty_commitment_txid = commitment_stats.tx.trust().txid();
 
-	/// Pushes a new monitor update into our monitor update queue, returning it if it should be
-	/// immediately given to the user for persisting or `None` if it should be held as blocked.
-	fn push_ret_blockable_mon_update(&mut self, update: ChannelMonitorUpdate)
-	-> Option<ChannelMonitorUpdate> {
-		let release_monitor = self.context.blocked_monitor_updates.is_empty();
-		if !release_monitor {
-			self.context.blocked_monitor_updates.push(PendingChannelMonitorUpdate {
-				update,
-			});
-			None
-		} else {
-			Some(update)
+		#[cfg(any(test, fuzzing))]
+		{
+			if !self.context.is_outbound() {
+				let projected_commit_tx_info = self.context.next_remote_commitment_tx_fee_info_cached.lock().unwrap().take();
+				*self.context.next_local_commitment_tx_fee_info_cached.lock().unwrap() = None;
+				if let Some(info) = projected_commit_tx_info {
+					let total_pending_htlcs = self.context.pending_inbound_htlcs.len() + self.context.pending_outbound_htlcs.len();
+					if info.total_pending_htlcs == total_pending_htlcs
+						&& info.next_holder_htlc_id == self.context.next_holder_htlc_id
+						&& info.next_counterparty_htlc_id == self.context.next_counterparty_htlc_id
+						&& info.feerate == self.context.feerate_per_kw {
+							let actual_fee = commit_tx_fee_msat(self.context.feerate_per_kw, commitment_stats.num_nondust_htlcs, self.context.get_channel_type());
+							assert_eq!(actual_fee, info.fee);
+						}
+				}
+			}
 		}
-	}
 
-	pub fn blocked_monitor_updates_pending(&self) -> usize {
-		self.context.blocked_monitor_updates.len()
+		(counterparty_commitment_txid, commitment_stats.htlcs_included)
 	}
 
-	/// Returns true if the channel is awaiting the persistence of the initial ChannelMonitor.
-	/// If the channel is outbound, this implies we have not yet broadcasted the funding
-	/// transaction. If the channel is inbound, this implies simply that the channel has not
-	/// advanced state.
-	pub fn is_awaiting_initial_mon_persist(&self) -> bool {
-		if !self.is_awaiting_monitor_update() { return false; }
-		if self.context.channel_state &
-			!(ChannelState::TheirChannelReady as u32 | ChannelState::PeerDisconnected as u32 | ChannelState::MonitorUpdateInProgress as u32)
-				== ChannelState::FundingSent as u32 {
-			// If we're not a 0conf channel, we'll be waiting on a monitor update with only
-			// FundingSent set, though our peer could have sent their channel_ready.
-			debug_assert!(self.context.minimum_depth.unwrap_or(1) > 0);
-			return true;
-		}
-		if self.context.cur_holder_commitment_transaction_number == INITIAL_COMMITMENT_NUMBER - 1 &&
-			self.context.cur_counterparty_commitment_transaction_number == INITIAL_COMMITMENT_NUMBER - 1 {
-			// If we're a 0-conf channel, we'll move beyond FundingSent immediately even while
-			// waiting for the initial monitor persistence. Thus, we check if our commitment
-			// transaction numbers have both been iterated only exactly once (for the
-			// funding_signed), and we're awaiting monitor update.
-			//
-			// If we got here, we shouldn't have yet broadcasted the funding transaction (as the
-			// only way to get an awaiting-monitor-update state during initial funding is if the
-			// initial monitor persistence is still pending).
-			//
-			// Because deciding we're awaiting initial broadcast spuriously could result in
-			// funds-loss (as we don't have a monitor, but have the funding transaction confirmed),
-			// we hard-assert here, even in production builds.
-			if self.context.is_outbound() { assert!(self.context.funding_transaction.is_some()); }
-			assert!(self.context.monitor_pending_channel_ready);
-			assert_eq!(self.context.latest_monitor_update_id, 0);
-			return true;
-		}
-		false
-	}
+	/// Only fails in case of signer rejection. Used for channel_reestablish commitment_signed
+	/// generation when we shouldn't change HTLC/channel state.
+	fn send_commitment_no_state_update<L: Deref>(&self, logger: &L) -> Result<(msgs::CommitmentSigned, (Txid, Vec<(HTLCOutputInCommitment, Option<&HTLCSource>)>)), ChannelError> where L::Target: Logger {
+		// Get the fee tests from `build_commitment_no_state_update`
+		#[cfg(any(test, fuzzing))]
+		self.build_commitment_no_state_update(logger);
 
-	/// Returns true if our channel_ready has been sent
-	pub fn is_our_channel_ready(&self) -> bool {
-		(self.context.channel_state & ChannelState::OurChannelReady as u32) != 0 || self.context.channel_state >= ChannelState::ChannelReady as u32
-	}
+		let counterparty_keys = self.context.build_remote_transaction_keys();
+		let commitment_stats = self.context.build_commitment_transaction(self.context.cur_counterparty_commitment_transaction_number, &counterparty_keys, false, true, logger);
+		let counterparty_commitment_txid = commitment_stats.tx.trust().txid();
+		let (signature, htlc_signatures);
 
-	/// Returns true if our peer has either initiated or agreed to shut down the channel.
-	pub fn received_shutdown(&self) -> bool {
-		(self.context.channel_state & ChannelState::RemoteShutdownSent as u32) != 0
-	}
+		{
+			let mut htlcs = Vec::with_capacity(commitment_stats.htlcs_included.len());
+			for &(ref htlc, _) in commitment_stats.htlcs_included.iter() {
+				htlcs.push(htlc);
+			}
 
-	/// Returns true if we either initiated or agreed to shut down the channel.
-	pub fn sent_shutdown(&self) -> bool {
-		(self.context.channel_state & ChannelState::LocalShutdownSent as u32) != 0
-	}
+			let res = self.context.holder_signer.sign_counterparty_commitment(&commitment_stats.tx, commitment_stats.preimages, &self.context.secp_ctx)
+				.map_err(|_| ChannelError::Close("Failed to get signatures for new commitment_signed".to_owned()))?;
+			signature = res.0;
+			htlc_signatures = res.1;
 
-	/// Returns true if this channel is fully shut down. True here implies that no further actions
-	/// may/will be taken on this channel, and thus this object should be freed. Any future changes
-	/// will be handled appropriately by the chain monitor.
-	pub fn is_shutdown(&self) -> bool {
-		if (self.context.channel_state & ChannelState::ShutdownComplete as u32) == ChannelState::ShutdownComplete as u32  {
-			assert!(self.context.channel_state == ChannelState::ShutdownComplete as u32);
-			true
-		} else { false }
-	}
+			log_trace!(logger, "Signed remote commitment tx {} (txid {}) with redeemscript {} -> {} in channel {}",
+				encode::serialize_hex(&commitment_stats.tx.trust().built_transaction().transaction),
+				&counterparty_commitment_txid, encode::serialize_hex(&self.context.get_funding_redeemscript()),
+				log_bytes!(signature.serialize_compact()[..]), log_bytes!(self.context.channel_id()));
+
+			for (ref htlc_sig, ref htlc) in htlc_signatures.iter().zip(htlcs) {
+				log_trace!(logger, "Signed remote HTLC tx {} with redeemscript {} with pubkey {} -> {} in channel {}",
+					encode::serialize_hex(&chan_utils::build_htlc_transaction(&counterparty_commitment_txid, commitment_stats.feerate_per_kw, self.context.get_holder_selected_contest_delay(), htlc, &self.context.channel_type, &counterparty_keys.broadcaster_delayed_payment_key, &counterparty_keys.revocation_key)),
+					encode::serialize_hex(&chan_utils::get_htlc_redeemscript(&htlc, &self.context.channel_type, &counterparty_keys)),
+					log_bytes!(counterparty_keys.broadcaster_htlc_key.serialize()),
+					log_bytes!(htlc_sig.serialize_compact()[..]), log_bytes!(self.context.channel_id()));
+			}
+		}
 
-	pub fn channel_update_status(&self) -> ChannelUpdateStatus {
-		self.context.channel_update_status
+		Ok((msgs::CommitmentSigned {
+			channel_id: self.context.channel_id,
+			signature,
+			htlc_signatures,
+			batch: None,
+			#[cfg(taproot)]
+			partial_signature_with_nonce: None,
+		}, (counterparty_commitment_txid, commitment_stats.htlcs_included)))
 	}
 
-	pub fn set_channel_update_status(&mut self, status: ChannelUpdateStatus) {
-		self.context.update_time_counter += 1;
-		self.context.channel_update_status = status;
+	/// Adds a pending outbound HTLC to this channel, and builds a new remote commitment
+	/// transaction and generates the corresponding [`ChannelMonitorUpdate`] in one go.
+	///
+	/// Shorthand for calling [`Self::send_htlc`] followed by a commitment update, see docs on
+	/// [`Self::send_htlc`] and [`Self::build_commitment_no_state_update`] for more info.
+	pub fn send_htlc_and_commit<F: Deref, L: Deref>(
+		&mut self, amount_msat: u64, payment_hash: PaymentHash, cltv_expiry: u32,
+		source: HTLCSource, onion_routing_packet: msgs::OnionPacket, skimmed_fee_msat: Option<u64>,
+		fee_estimator: &LowerBoundedFeeEstimator<F>, logger: &L
+	) -> Result<Option<ChannelMonitorUpdate>, ChannelError>
+	where F::Target: FeeEstimator, L::Target: Logger
+	{
+		let send_res = self.send_htlc(amount_msat, payment_hash, cltv_expiry, source,
+			onion_routing_packet, false, skimmed_fee_msat, None, fee_estimator, logger);
+		if let Err(e) = &send_res { if let ChannelError::Ignore(_) = e {} else { debug_assert!(false, "Sending cannot trigger channel failure"); } }
+		match send_res? {
+			Some(_) => {
+				let monitor_update = self.build_commitment_no_status_check(logger);
+				self.monitor_updating_paused(false, true, false, Vec::new(), Vec::new(), Vec::new());
+				Ok(self.push_ret_blockable_mon_update(monitor_update))
+			},
+			None => Ok(None)
+		}
 	}
 
-	fn check_get_channel_ready(&mut self, height: u32) -> Option<msgs::ChannelReady> {
-		// Called:
-		//  * always when a new block/transactions are confirmed with the new height
-		//  * when funding is signed with a height of 0
-		if self.context.funding_tx_confirmation_height == 0 && self.context.minimum_depth != Some(0) {
-			return None;
+	pub fn channel_update(&mut self, msg: &msgs::ChannelUpdate) -> Result<(), ChannelError> {
+		if msg.contents.htlc_minimum_msat >= self.context.channel_value_satoshis * 1000 {
+			return Err(ChannelError::Close("Minimum htlc value is greater than channel value".to_string()));
 		}
+		self.context.counterparty_forwarding_info = Some(CounterpartyForwardingInfo {
+			fee_base_msat: msg.contents.fee_base_msat,
+			fee_proportional_millionths: msg.contents.fee_proportional_millionths,
+			cltv_expiry_delta: msg.contents.cltv_expiry_delta
+		});
 
-		let funding_tx_confirmations = height as i64 - self.context.funding_tx_confirmation_height as i64 + 1;
-		if funding_tx_confirmations <= 0 {
-			self.context.funding_tx_confirmation_height = 0;
-		}
+		Ok(())
+	}
 
-		if funding_tx_confirmations < self.context.minimum_depth.unwrap_or(0) as i64 {
-			return None;
+	/// Begins the shutdown process, getting a message for the remote peer and returning all
+	/// holding cell HTLCs for payment failure.
+	///
+	/// May jump to the channel being fully shutdown (see [`Self::is_shutdown`]) in which case no
+	/// [`ChannelMonitorUpdate`] will be returned).
+	pub fn get_shutdown<SP: Deref>(&mut self, signer_provider: &SP, their_features: &InitFeatures,
+		target_feerate_sats_per_kw: Option<u32>, override_shutdown_script: Option<ShutdownScript>)
+	-> Result<(msgs::Shutdown, Option<ChannelMonitorUpdate>, Vec<(HTLCSource, PaymentHash)>), APIError>
+	where SP::Target: SignerProvider {
+		for htlc in self.context.pending_outbound_htlcs.iter() {
+			if let OutboundHTLCState::LocalAnnounced(_) = htlc.state {
+				return Err(APIError::APIMisuseError{err: "Cannot begin shutdown with pending HTLCs. Process pending events first".to_owned()});
+			}
 		}
-
-		let non_shutdown_state = self.context.channel_state & (!MULTI_STATE_FLAGS);
-		let need_commitment_update = if non_shutdown_state == ChannelState::FundingSent as u32 {
-			self.context.channel_state |= ChannelState::OurChannelReady as u32;
-			true
-		} else if non_shutdown_state == (ChannelState::FundingSent as u32 | ChannelState::TheirChannelReady as u32) {
-			self.context.channel_state = ChannelState::ChannelReady as u32 | (self.context.channel_state & MULTI_STATE_FLAGS);
-			self.context.update_time_counter += 1;
-			true
-		} else if non_shutdown_state == (ChannelState::FundingSent as u32 | ChannelState::OurChannelReady as u32) {
-			// We got a reorg but not enough to trigger a force close, just ignore.
-			false
-		} else {
-			if self.context.funding_tx_confirmation_height != 0 && self.context.channel_state < ChannelState::ChannelReady as u32 {
-				// We should never see a funding transaction on-chain until we've received
-				// funding_signed (if we're an outbound channel), or seen funding_generated (if we're
-				// an inbound channel - before that we have no known funding TXID). The fuzzer,
-				// however, may do this and we shouldn't treat it as a bug.
-				#[cfg(not(fuzzing))]
-				panic!("Started confirming a channel in a state pre-FundingSent: {}.\n\
-					Do NOT broadcast a funding transaction manually - let LDK do it for you!",
-					self.context.channel_state);
+		if self.context.channel_state & (ChannelState::LocalStfuSent as u32 | ChannelState::Quiescent as u32) != 0 {
+			return Err(APIError::ChannelUnavailable{err: "Cannot begin shutdown while the channel is quiescent".to_owned()});
+		}
+		if self.context.channel_state & BOTH_SIDES_SHUTDOWN_MASK != 0 {
+			if (self.context.channel_state & ChannelState::LocalShutdownSent as u32) == ChannelState::LocalShutdownSent as u32 {
+				return Err(APIError::APIMisuseError{err: "Shutdown already in progress".to_owned()});
 			}
-			// We got a reorg but not enough to trigger a force close, just ignore.
-			false
-		};
-
-		if need_commitment_update {
-			if self.context.channel_state & (ChannelState::MonitorUpdateInProgress as u32) == 0 {
-				if self.context.channel_state & (ChannelState::PeerDisconnected as u32) == 0 {
-					let next_per_commitment_point =
-						self.context.holder_signer.get_per_commitment_point(INITIAL_COMMITMENT_NUMBER - 1, &self.context.secp_ctx);
-					return Some(msgs::ChannelReady {
-						channel_id: self.context.channel_id,
-						next_per_commitment_point,
-						short_channel_id_alias: Some(self.context.outbound_scid_alias),
-					});
-				}
-			} else {
-				self.context.monitor_pending_channel_ready = true;
+			else if (self.context.channel_state & ChannelState::RemoteShutdownSent as u32) == ChannelState::RemoteShutdownSent as u32 {
+				return Err(APIError::ChannelUnavailable{err: "Shutdown already in progress by remote".to_owned()});
 			}
 		}
-		None
-	}
+		if self.context.shutdown_scriptpubkey.is_some() && override_shutdown_script.is_some() {
+			return Err(APIError::APIMisuseError{err: "Cannot override shutdown script for a channel with one already set".to_owned()});
+		}
+		if self.context.pending_splice.is_some() {
+			return Err(APIError::ChannelUnavailable{err: "Cannot begin shutdown while a splice is pending".to_owned()});
+		}
+		assert_eq!(self.context.channel_state & ChannelState::ShutdownComplete as u32, 0);
+		if self.context.channel_state & (ChannelState::PeerDisconnected as u32 | ChannelState::MonitorUpdateInProgress as u32) != 0 {
+			return Err(APIError::ChannelUnavailable{err: "Cannot begin shutdown while peer is disconnected or we're waiting on a monitor update, maybe force-close instead?".to_owned()});
+		}
 
-	/// When a transaction is confirmed, we check whether it is or spends the funding transaction
-	/// In the first case, we store the confirmation height and calculating the short channel id.
-	/// In the second, we simply return an Err indicating we need to be force-closed now.
-	pub fn transactions_confirmed<NS: Deref, L: Deref>(
-		&mut self, block_hash: &BlockHash, height: u32, txdata: &TransactionData,
-		genesis_block_hash: BlockHash, node_signer: &NS, user_config: &UserConfig, logger: &L
-	) -> Result<(Option<msgs::ChannelReady>, Option<msgs::AnnouncementSignatures>), ClosureReason>
-	where
-		NS::Target: NodeSigner,
-		L::Target: Logger
-	{
-		if let Some(funding_txo) = self.context.get_funding_txo() {
-			for &(index_in_block, tx) in txdata.iter() {
-				// Check if the transaction is the expected funding transaction, and if it is,
-				// check that it pays the right amount to the right script.
-				if self.context.funding_tx_confirmation_height == 0 {
-					if tx.txid() == funding_txo.txid {
-						let txo_idx = funding_txo.index as usize;
-						if txo_idx >= tx.output.len() || tx.output[txo_idx].script_pubkey != self.context.get_funding_redeemscript().to_v0_p2wsh() ||
-								tx.output[txo_idx].value != self.context.channel_value_satoshis {
-							if self.context.is_outbound() {
-								// If we generated the funding transaction and it doesn't match what it
-								// should, the client is really broken and we should just panic and
-								// tell them off. That said, because hash collisions happen with high
-								// probability in fuzzing mode, if we're fuzzing we just close the
-								// channel and move on.
-								#[cfg(not(fuzzing))]
-								panic!("Client called ChannelManager::funding_transaction_generated with bogus transaction!");
-							}
-							self.context.update_time_counter += 1;
-							let err_reason = "funding tx had wrong script/value or output index";
-							return Err(ClosureReason::ProcessingError { err: err_reason.to_owned() });
-						} else {
-							if self.context.is_outbound() {
-								for input in tx.input.iter() {
-									if input.witness.is_empty() {
-										// We generated a malleable funding transaction, implying we've
-										// just exposed ourselves to funds loss to our counterparty.
-										#[cfg(not(fuzzing))]
-										panic!("Client called ChannelManager::funding_transaction_generated with bogus transaction!");
-									}
-								}
-							}
-							self.context.funding_tx_confirmation_height = height;
-							self.context.funding_tx_confirmed_in = Some(*block_hash);
-							self.context.short_channel_id = match scid_from_parts(height as u64, index_in_block as u64, txo_idx as u64) {
-								Ok(scid) => Some(scid),
-								Err(_) => panic!("Block was bogus - either height was > 16 million, had > 16 million transactions, or had > 65k outputs"),
-							}
+		// If we haven't funded the channel yet, we don't need to bother ensuring the shutdown
+		// script is set, we just force-close and call it a day.
+		let mut chan_closed = false;
+		if self.context.channel_state < ChannelState::FundingSent as u32 {
+			chan_closed = true;
+		}
+
+		let update_shutdown_script = match self.context.shutdown_scriptpubkey {
+			Some(_) => false,
+			None if !chan_closed => {
+				// use override shutdown script if provided
+				let shutdown_scriptpubkey = match override_shutdown_script {
+					Some(script) => script,
+					None => {
+						// otherwise, use the shutdown scriptpubkey provided by the signer
+						match signer_provider.get_shutdown_scriptpubkey() {
+							Ok(scriptpubkey) => scriptpubkey,
+							Err(_) => return Err(APIError::ChannelUnavailable{err: "Failed to get shutdown scriptpubkey".to_owned()}),
 						}
-					}
-					// If we allow 1-conf funding, we may need to check for channel_ready here and
-					// send it immediately instead of waiting for a best_block_updated call (which
-					// may have already happened for this block).
-					if let Some(channel_ready) = self.check_get_channel_ready(height) {
-						log_info!(logger, "Sending a channel_ready to our peer for channel {}", log_bytes!(self.context.channel_id));
-						let announcement_sigs = self.get_announcement_sigs(node_signer, genesis_block_hash, user_config, height, logger);
-						return Ok((Some(channel_ready), announcement_sigs));
-					}
-				}
-				for inp in tx.input.iter() {
-					if inp.previous_output == funding_txo.into_bitcoin_outpoint() {
-						log_info!(logger, "Detected channel-closing tx {} spending {}:{}, closing channel {}", tx.txid(), inp.previous_output.txid, inp.previous_output.vout, log_bytes!(self.context.channel_id()));
-						return Err(ClosureReason::CommitmentTxConfirmed);
-					}
+					},
+				};
+				if !shutdown_scriptpubkey.is_compatible(their_features) {
+					return Err(APIError::IncompatibleShutdownScript { script: shutdown_scriptpubkey.clone() });
 				}
-			}
+				self.context.shutdown_scriptpubkey = Some(shutdown_scriptpubkey);
+				true
+			},
+			None => false,
+		};
+
+		// From here on out, we may not fail!
+		self.context.target_closing_feerate_sats_per_kw = target_feerate_sats_per_kw;
+		if self.context.channel_state < ChannelState::FundingSent as u32 {
+			self.context.channel_state = ChannelState::ShutdownComplete as u32;
+		} else {
+			self.context.channel_state |= ChannelState::LocalShutdownSent as u32;
 		}
-		Ok((None, None))
-	}
+		self.context.update_time_counter += 1;
 
-	/// When a new block is connected, we check the height of the block against outbound holding
-	/// cell HTLCs in case we need to give up on them prematurely and time them out. Everything
-	/// else (e.g. commitment transaction broadcasts, HTLC transaction broadcasting, etc) is
-	/// handled by the ChannelMonitor.
-	///
-	/// If we return Err, the channel may have been closed, at which point the standard
-	/// requirements apply - no calls may be made except those explicitly stated to be allowed
-	/// post-shutdown.
-	///
-	/// May return some HTLCs (and their payment_hash) which have timed out and should be failed
-	/// back.
-	pub fn best_block_updated<NS: Deref, L: Deref>(
-		&mut self, height: u32, highest_header_time: u32, genesis_block_hash: BlockHash,
-		node_signer: &NS, user_config: &UserConfig, logger: &L
-	) -> Result<(Option<msgs::ChannelReady>, Vec<(HTLCSource, PaymentHash)>, Option<msgs::AnnouncementSignatures>), ClosureReason>
-	where
-		NS::Target: NodeSigner,
-		L::Target: Logger
-	{
-		self.do_best_block_updated(height, highest_header_time, Some((genesis_block_hash, node_signer, user_config)), logger)
-	}
+		let monitor_update = if update_shutdown_script {
+			self.context.latest_monitor_update_id += 1;
+			let monitor_update = ChannelMonitorUpdate {
+				update_id: self.context.latest_monitor_update_id,
+				updates: vec![ChannelMonitorUpdateStep::ShutdownScript {
+					scriptpubkey: self.get_closing_scriptpubkey(),
+				}],
+			};
+			self.monitor_updating_paused(false, false, false, Vec::new(), Vec::new(), Vec::new());
+			self.push_ret_blockable_mon_update(monitor_update)
+		} else { None };
+		let shutdown = msgs::Shutdown {
+			channel_id: self.context.channel_id,
+			scriptpubkey: self.get_closing_scriptpubkey(),
+		};
 
-	fn do_best_block_updated<NS: Deref, L: Deref>(
-		&mut self, height: u32, highest_header_time: u32,
-		genesis_node_signer: Option<(BlockHash, &NS, &UserConfig)>, logger: &L
-	) -> Result<(Option<msgs::ChannelReady>, Vec<(HTLCSource, PaymentHash)>, Option<msgs::AnnouncementSignatures>), ClosureReason>
-	where
-		NS::Target: NodeSigner,
-		L::Target: Logger
-	{
-		let mut timed_out_htlcs = Vec::new();
-		// This mirrors the check in ChannelManager::decode_update_add_htlc_onion, refusing to
-		// forward an HTLC when our counterparty should almost certainly just fail it for expiring
-		// ~now.
-		let unforwarded_htlc_cltv_limit = height + LATENCY_GRACE_PERIOD_BLOCKS;
+		// Go ahead and drop holding cell updates as we'd rather fail payments than wait to send
+		// our shutdown until we've committed all of the pending changes.
+		self.context.holding_cell_update_fee = None;
+		let mut dropped_outbound_htlcs = Vec::with_capacity(self.context.holding_cell_htlc_updates.len());
 		self.context.holding_cell_htlc_updates.retain(|htlc_update| {
 			match htlc_update {
-				&HTLCUpdateAwaitingACK::AddHTLC { ref payment_hash, ref source, ref cltv_expiry, .. } => {
-					if *cltv_expiry <= unforwarded_htlc_cltv_limit {
-						timed_out_htlcs.push((source.clone(), payment_hash.clone()));
-						false
-					} else { true }
+				&HTLCUpdateAwaitingACK::AddHTLC { ref payment_hash, ref source, .. } => {
+					dropped_outbound_htlcs.push((source.clone(), payment_hash.clone()));
+					false
 				},
 				_ => true
 			}
 		});
 
-		self.context.update_time_counter = cmp::max(self.context.update_time_counter, highest_header_time);
-
-		if let Some(channel_ready) = self.check_get_channel_ready(height) {
-			let announcement_sigs = if let Some((genesis_block_hash, node_signer, user_config)) = genesis_node_signer {
-				self.get_announcement_sigs(node_signer, genesis_block_hash, user_config, height, logger)
-			} else { None };
-			log_info!(logger, "Sending a channel_ready to our peer for channel {}", log_bytes!(self.context.channel_id));
-			return Ok((Some(channel_ready), timed_out_htlcs, announcement_sigs));
-		}
-
-		let non_shutdown_state = self.context.channel_state & (!MULTI_STATE_FLAGS);
-		if non_shutdown_state >= ChannelState::ChannelReady as u32 ||
-		   (non_shutdown_state & ChannelState::OurChannelReady as u32) == ChannelState::OurChannelReady as u32 {
-			let mut funding_tx_confirmations = height as i64 - self.context.funding_tx_confirmation_height as i64 + 1;
-			if self.context.funding_tx_confirmation_height == 0 {
-				// Note that check_get_channel_ready may reset funding_tx_confirmation_height to
-				// zero if it has been reorged out, however in either case, our state flags
-				// indicate we've already sent a channel_ready
-				funding_tx_confirmations = 0;
-			}
-
-			// If we've sent channel_ready (or have both sent and received channel_ready), and
-			// the funding transaction has become unconfirmed,
-			// close the channel and hope we can get the latest state on chain (because presumably
-			// the funding transaction is at least still in the mempool of most nodes).
-			//
-			// Note that ideally we wouldn't force-close if we see *any* reorg on a 1-conf or
-			// 0-conf channel, but not doing so may lead to the
-			// `ChannelManager::short_to_chan_info` map  being inconsistent, so we currently have
-			// to.
-			if funding_tx_confirmations == 0 && self.context.funding_tx_confirmed_in.is_some() {
-				let err_reason = format!("Funding transaction was un-confirmed. Locked at {} confs, now have {} confs.",
-					self.context.minimum_depth.unwrap(), funding_tx_confirmations);
-				return Err(ClosureReason::ProcessingError { err: err_reason });
-			}
-		} else if !self.context.is_outbound() && self.context.funding_tx_confirmed_in.is_none() &&
-				height >= self.context.channel_creation_height + FUNDING_CONF_DEADLINE_BLOCKS {
-			log_info!(logger, "Closing channel {} due to funding timeout", log_bytes!(self.context.channel_id));
-			// If funding_tx_confirmed_in is unset, the channel must not be active
-			assert!(non_shutdown_state <= ChannelState::ChannelReady as u32);
-			assert_eq!(non_shutdown_state & ChannelState::OurChannelReady as u32, 0);
-			return Err(ClosureReason::FundingTimedOut);
-		}
+		debug_assert!(!self.is_shutdown() || monitor_update.is_none(),
+			"we can't both complete shutdown and return a monitor update");
 
-		let announcement_sigs = if let Some((genesis_block_hash, node_signer, user_config)) = genesis_node_signer {
-			self.get_announcement_sigs(node_signer, genesis_block_hash, user_config, height, logger)
-		} else { None };
-		Ok((None, timed_out_htlcs, announcement_sigs))
+		Ok((shutdown, monitor_update, dropped_outbound_htlcs))
 	}
 
-	/// Indicates the funding transaction is no longer confirmed in the main chain. This may
-	/// force-close the channel, but may also indicate a harmless reorganization of a block or two
-	/// before the channel has reached channel_ready and we can just wait for more blocks.
-	pub fn funding_transaction_unconfirmed<L: Deref>(&mut self, logger: &L) -> Result<(), ClosureReason> where L::Target: Logger {
-		if self.context.funding_tx_confirmation_height != 0 {
-			// We handle the funding disconnection by calling best_block_updated with a height one
-			// below where our funding was connected, implying a reorg back to conf_height - 1.
-			let reorg_height = self.context.funding_tx_confirmation_height - 1;
-			// We use the time field to bump the current time we set on channel updates if its
-			// larger. If we don't know that time has moved forward, we can just set it to the last
-			// time we saw and it will be ignored.
-			let best_time = self.context.update_time_counter;
-			match self.do_best_block_updated(reorg_height, best_time, None::<(BlockHash, &&NodeSigner, &UserConfig)>, logger) {
-				Ok((channel_ready, timed_out_htlcs, announcement_sigs)) => {
-					assert!(channel_ready.is_none(), "We can't generate a funding with 0 confirmations?");
-					assert!(timed_out_htlcs.is_empty(), "We can't have accepted HTLCs with a timeout before our funding confirmation?");
-					assert!(announcement_sigs.is_none(), "We can't generate an announcement_sigs with 0 confirmations?");
-					Ok(())
-				},
-				Err(e) => Err(e)
-			}
-		} else {
-			// We never learned about the funding confirmation anyway, just ignore
-			Ok(())
-		}
+	pub fn inflight_htlc_sources(&self) -> impl Iterator<Item=(&HTLCSource, &PaymentHash)> {
+		self.context.holding_cell_htlc_updates.iter()
+			.flat_map(|htlc_update| {
+				match htlc_update {
+					HTLCUpdateAwaitingACK::AddHTLC { source, payment_hash, .. }
+						=> Some((source, payment_hash)),
+					_ => None,
+				}
+			})
+			.chain(self.context.pending_outbound_htlcs.iter().map(|htlc| (&htlc.source, &htlc.payment_hash)))
 	}
+}
 
-	// Methods to get unprompted messages to send to the remote end (or where we already returned
-	// something in the handler for the message that prompted this message):
+/// A not-yet-funded outbound (from holder) channel using V1 channel establishment.
+pub(super) struct OutboundV1Channel<Signer: ChannelSigner> {
+	pub context: ChannelContext<Signer>,
+	pub unfunded_context: UnfundedChannelContext,
+}
 
-	/// Gets an UnsignedChannelAnnouncement for this channel. The channel must be publicly
-	/// announceable and available for use (have exchanged [`ChannelReady`] messages in both
-	/// directions). Should be used for both broadcasted announcements and in response to an
-	/// AnnouncementSignatures message from the remote peer.
-	///
-	/// Will only fail if we're not in a state where channel_announcement may be sent (including
-	/// closing).
-	///
-	/// This will only return ChannelError::Ignore upon failure.
-	///
-	/// [`ChannelReady`]: crate::ln::msgs::ChannelReady
-	fn get_channel_announcement<NS: Deref>(
-		&self, node_signer: &NS, chain_hash: BlockHash, user_config: &UserConfig,
-	) -> Result<msgs::UnsignedChannelAnnouncement, ChannelError> where NS::Target: NodeSigner {
-		if !self.context.config.announced_channel {
-			return Err(ChannelError::Ignore("Channel is not available for public announcements".to_owned()));
+impl<Signer: WriteableEcdsaChannelSigner> OutboundV1Channel<Signer> {
+	pub fn new<ES: Deref, SP: Deref, F: Deref>(
+		fee_estimator: &LowerBoundedFeeEstimator<F>, entropy_source: &ES, signer_provider: &SP, counterparty_node_id: PublicKey, their_features: &InitFeatures,
+		channel_value_satoshis: u64, push_msat: u64, user_id: u128, config: &UserConfig, current_chain_height: u32,
+		outbound_scid_alias: u64
+	) -> Result<OutboundV1Channel<Signer>, APIError>
+	where ES::Target: EntropySource,
+	      SP::Target: SignerProvider<Signer = Signer>,
+	      F::Target: FeeEstimator,
+	{
+		let holder_selected_contest_delay = config.channel_handshake_config.our_to_self_delay;
+		let channel_keys_id = signer_provider.generate_channel_keys_id(false, channel_value_satoshis, user_id);
+		let holder_signer = signer_provider.derive_channel_signer(channel_value_satoshis, channel_keys_id);
+		let pubkeys = holder_signer.pubkeys().clone();
+
+		if !their_features.supports_wumbo() && channel_value_satoshis > MAX_FUNDING_SATOSHIS_NO_WUMBO {
+			return Err(APIError::APIMisuseError{err: format!("funding_value must not exceed {}, it was {}", MAX_FUNDING_SATOSHIS_NO_WUMBO, channel_value_satoshis)});
 		}
-		if !self.context.is_usable() {
-			return Err(ChannelError::Ignore("Cannot get a ChannelAnnouncement if the channel is not currently usable".to_owned()));
+		if channel_value_satoshis >= TOTAL_BITCOIN_SUPPLY_SATOSHIS {
+			return Err(APIError::APIMisuseError{err: format!("funding_value must be smaller than the total bitcoin supply, it was {}", channel_value_satoshis)});
+		}
+		let channel_value_msat = channel_value_satoshis * 1000;
+		if push_msat > channel_value_msat {
+			return Err(APIError::APIMisuseError { err: format!("Push value ({}) was larger than channel_value ({})", push_msat, channel_value_msat) });
+		}
+		if holder_selected_contest_delay < BREAKDOWN_TIMEOUT {
+			return Err(APIError::APIMisuseError {err: format!("Configured with an unreasonable our_to_self_delay ({}) putting user funds at risks", holder_selected_contest_delay)});
+		}
+		let holder_selected_channel_reserve_satoshis = get_holder_selected_channel_reserve_satoshis(channel_value_satoshis, config);
+		if holder_selected_channel_reserve_satoshis < MIN_CHAN_DUST_LIMIT_SATOSHIS {
+			// Protocol level safety check in place, although it should never happen because
+			// of `MIN_THEIR_CHAN_RESERVE_SATOSHIS`
+			return Err(APIError::APIMisuseError { err: format!("Holder selected channel  reserve below implemention limit dust_limit_satoshis {}", holder_selected_channel_reserve_satoshis) });
 		}
 
-		let short_channel_id = self.context.get_short_channel_id()
-			.ok_or(ChannelError::Ignore("Cannot get a ChannelAnnouncement if the channel has not been confirmed yet".to_owned()))?;
-		let node_id = NodeId::from_pubkey(&node_signer.get_node_id(Recipient::Node)
-			.map_err(|_| ChannelError::Ignore("Failed to retrieve own public key".to_owned()))?);
-		let counterparty_node_id = NodeId::from_pubkey(&self.context.get_counterparty_node_id());
-		let were_node_one = node_id.as_slice() < counterparty_node_id.as_slice();
+		let channel_type = Self::get_initial_channel_type(&config, their_features);
+		debug_assert!(channel_type.is_subset(&channelmanager::provided_channel_type_features(&config)));
 
-		let msg = msgs::UnsignedChannelAnnouncement {
-			features: channelmanager::provided_channel_features(&user_config),
-			chain_hash,
-			short_channel_id,
-			node_id_1: if were_node_one { node_id } else { counterparty_node_id },
-			node_id_2: if were_node_one { counterparty_node_id } else { node_id },
-			bitcoin_key_1: NodeId::from_pubkey(if were_node_one { &self.context.get_holder_pubkeys().funding_pubkey } else { self.context.counterparty_funding_pubkey() }),
-			bitcoin_key_2: NodeId::from_pubkey(if were_node_one { self.context.counterparty_funding_pubkey() } else { &self.context.get_holder_pubkeys().funding_pubkey }),
-			excess_data: Vec::new(),
+		let commitment_conf_target = if channel_type.supports_anchors_zero_fee_htlc_tx() {
+			ConfirmationTarget::MempoolMinimum
+		} else {
+			ConfirmationTarget::Normal
 		};
+		let commitment_feerate = fee_estimator.bounded_sat_per_1000_weight(commitment_conf_target);
 
-		Ok(msg)
-	}
-
-	fn get_announcement_sigs<NS: Deref, L: Deref>(
-		&mut self, node_signer: &NS, genesis_block_hash: BlockHash, user_config: &UserConfig,
-		best_block_height: u32, logger: &L
-	) -> Option<msgs::AnnouncementSignatures>
-	where
-		NS::Target: NodeSigner,
-		L::Target: Logger
-	{
-		if self.context.funding_tx_confirmation_height == 0 || self.context.funding_tx_confirmation_height + 5 > best_block_height {
-			return None;
-		}
-
-		if !self.context.is_usable() {
-			return None;
-		}
-
-		if self.context.channel_state & ChannelState::PeerDisconnected as u32 != 0 {
-			log_trace!(logger, "Cannot create an announcement_signatures as our peer is disconnected");
-			return None;
+		let value_to_self_msat = channel_value_satoshis * 1000 - push_msat;
+		let commitment_tx_fee = commit_tx_fee_msat(commitment_feerate, MIN_AFFORDABLE_HTLC_COUNT, &channel_type);
+		if value_to_self_msat < commitment_tx_fee {
+			return Err(APIError::APIMisuseError{ err: format!("Funding amount ({}) can't even pay fee for initial commitment transaction fee of {}.", value_to_self_msat / 1000, commitment_tx_fee / 1000) });
 		}
 
-		if self.context.announcement_sigs_state != AnnouncementSigsState::NotSent {
-			return None;
-		}
+		let mut secp_ctx = Secp256k1::new();
+		secp_ctx.seeded_randomize(&entropy_source.get_secure_random_bytes());
 
-		log_trace!(logger, "Creating an announcement_signatures message for channel {}", log_bytes!(self.context.channel_id()));
-		let announcement = match self.get_channel_announcement(node_signer, genesis_block_hash, user_config) {
-			Ok(a) => a,
-			Err(e) => {
-				log_trace!(logger, "{:?}", e);
-				return None;
+		let shutdown_scriptpubkey = if config.channel_handshake_config.commit_upfront_shutdown_pubkey {
+			match signer_provider.get_shutdown_scriptpubkey() {
+				Ok(scriptpubkey) => Some(scriptpubkey),
+				Err(_) => return Err(APIError::ChannelUnavailable { err: "Failed to get shutdown scriptpubkey".to_owned()}),
 			}
-		};
-		let our_node_sig = match node_signer.sign_gossip_message(msgs::UnsignedGossipMessage::ChannelAnnouncement(&announcement)) {
-			Err(_) => {
-				log_error!(logger, "Failed to generate node signature for channel_announcement. Channel will not be announced!");
-				return None;
-			},
-			Ok(v) => v
-		};
-		let our_bitcoin_sig = match self.context.holder_signer.sign_channel_announcement_with_funding_key(&announcement, &self.context.secp_ctx) {
-			Err(_) => {
-				log_error!(logger, "Signer rejected channel_announcement signing. Channel will not be announced!");
-				return None;
-			},
-			Ok(v) => v
-		};
-		let short_channel_id = match self.context.get_short_channel_id() {
-			Some(scid) => scid,
-			None => return None,
+		} else { None };
+
+		if let Some(shutdown_scriptpubkey) = &shutdown_scriptpubkey {
+			if !shutdown_scriptpubkey.is_compatible(&their_features) {
+				return Err(APIError::IncompatibleShutdownScript { script: shutdown_scriptpubkey.clone() });
+			}
+		}
+
+		let destination_script = match signer_provider.get_destination_script() {
+			Ok(script) => script,
+			Err(_) => return Err(APIError::ChannelUnavailable { err: "Failed to get destination script".to_owned()}),
 		};
 
-		self.context.announcement_sigs_state = AnnouncementSigsState::MessageSent;
+		let temporary_channel_id = entropy_source.get_secure_random_bytes();
 
-		Some(msgs::AnnouncementSignatures {
-			channel_id: self.context.channel_id(),
-			short_channel_id,
-			node_signature: our_node_sig,
-			bitcoin_signature: our_bitcoin_sig,
-		})
-	}
+		Ok(Self {
+			context: ChannelContext {
+				user_id,
 
-	/// Signs the given channel announcement, returning a ChannelError::Ignore if no keys are
-	/// available.
-	fn sign_channel_announcement<NS: Deref>(
-		&self, node_signer: &NS, announcement: msgs::UnsignedChannelAnnouncement
-	) -> Result<msgs::ChannelAnnouncement, ChannelError> where NS::Target: NodeSigner {
-		if let Some((their_node_sig, their_bitcoin_sig)) = self.context.announcement_sigs {
-			let our_node_key = NodeId::from_pubkey(&node_signer.get_node_id(Recipient::Node)
-				.map_err(|_| ChannelError::Ignore("Signer failed to retrieve own public key".to_owned()))?);
-			let were_node_one = announcement.node_id_1 == our_node_key;
+				config: LegacyChannelConfig {
+					options: config.channel_config.clone(),
+					announced_channel: config.channel_handshake_config.announced_channel,
+					commit_upfront_shutdown_pubkey: config.channel_handshake_config.commit_upfront_shutdown_pubkey,
+				},
 
-			let our_node_sig = node_signer.sign_gossip_message(msgs::UnsignedGossipMessage::ChannelAnnouncement(&announcement))
-				.map_err(|_| ChannelError::Ignore("Failed to generate node signature for channel_announcement".to_owned()))?;
-			let our_bitcoin_sig = self.context.holder_signer.sign_channel_announcement_with_funding_key(&announcement, &self.context.secp_ctx)
-				.map_err(|_| ChannelError::Ignore("Signer rejected channel_announcement".to_owned()))?;
-			Ok(msgs::ChannelAnnouncement {
-				node_signature_1: if were_node_one { our_node_sig } else { their_node_sig },
-				node_signature_2: if were_node_one { their_node_sig } else { our_node_sig },
-				bitcoin_signature_1: if were_node_one { our_bitcoin_sig } else { their_bitcoin_sig },
-				bitcoin_signature_2: if were_node_one { their_bitcoin_sig } else { our_bitcoin_sig },
-				contents: announcement,
-			})
-		} else {
-			Err(ChannelError::Ignore("Attempted to sign channel announcement before we'd received announcement_signatures".to_string()))
-		}
-	}
+				prev_config: None,
 
-	/// Processes an incoming announcement_signatures message, providing a fully-signed
-	/// channel_announcement message which we can broadcast and storing our counterparty's
-	/// signatures for later reconstruction/rebroadcast of the channel_announcement.
-	pub fn announcement_signatures<NS: Deref>(
-		&mut self, node_signer: &NS, chain_hash: BlockHash, best_block_height: u32,
-		msg: &msgs::AnnouncementSignatures, user_config: &UserConfig
-	) -> Result<msgs::ChannelAnnouncement, ChannelError> where NS::Target: NodeSigner {
-		let announcement = self.get_channel_announcement(node_signer, chain_hash, user_config)?;
+				inbound_handshake_limits_override: Some(config.channel_handshake_limits.clone()),
 
-		let msghash = hash_to_message!(&Sha256d::hash(&announcement.encode()[..])[..]);
+				channel_id: temporary_channel_id,
+				temporary_channel_id: Some(temporary_channel_id),
+				channel_state: ChannelState::OurInitSent as u32,
+				announcement_sigs_state: AnnouncementSigsState::NotSent,
+				secp_ctx,
+				channel_value_satoshis,
 
-		if self.context.secp_ctx.verify_ecdsa(&msghash, &msg.node_signature, &self.context.get_counterparty_node_id()).is_err() {
-			return Err(ChannelError::Close(format!(
-				"Bad announcement_signatures. Failed to verify node_signature. UnsignedChannelAnnouncement used for verification is {:?}. their_node_key is {:?}",
-				 &announcement, self.context.get_counterparty_node_id())));
-		}
-		if self.context.secp_ctx.verify_ecdsa(&msghash, &msg.bitcoin_signature, self.context.counterparty_funding_pubkey()).is_err() {
-			return Err(ChannelError::Close(format!(
-				"Bad announcement_signatures. Failed to verify bitcoin_signature. UnsignedChannelAnnouncement used for verification is {:?}. their_bitcoin_key is ({:?})",
-				&announcement, self.context.counterparty_funding_pubkey())));
-		}
+				latest_monitor_update_id: 0,
 
-		self.context.announcement_sigs = Some((msg.node_signature, msg.bitcoin_signature));
-		if self.context.funding_tx_confirmation_height == 0 || self.context.funding_tx_confirmation_height + 5 > best_block_height {
-			return Err(ChannelError::Ignore(
-				"Got announcement_signatures prior to the required six confirmations - we may not have received a block yet that our peer has".to_owned()));
-		}
+				holder_signer,
+				shutdown_scriptpubkey,
+				destination_script,
 
-		self.sign_channel_announcement(node_signer, announcement)
-	}
+				cur_holder_commitment_transaction_number: INITIAL_COMMITMENT_NUMBER,
+				cur_counterparty_commitment_transaction_number: INITIAL_COMMITMENT_NUMBER,
+				value_to_self_msat,
 
-	/// Gets a signed channel_announcement for this channel, if we previously received an
-	/// announcement_signatures from our counterparty.
-	pub fn get_signed_channel_announcement<NS: Deref>(
-		&self, node_signer: &NS, chain_hash: BlockHash, best_block_height: u32, user_config: &UserConfig
-	) -> Option<msgs::ChannelAnnouncement> where NS::Target: NodeSigner {
-		if self.context.funding_tx_confirmation_height == 0 || self.context.funding_tx_confirmation_height + 5 > best_block_height {
-			return None;
-		}
-		let announcement = match self.get_channel_announcement(node_signer, chain_hash, user_config) {
-			Ok(res) => res,
-			Err(_) => return None,
-		};
-		match self.sign_channel_announcement(node_signer, announcement) {
-			Ok(res) => Some(res),
-			Err(_) => None,
-		}
-	}
+				pending_inbound_htlcs: Vec::new(),
+				pending_outbound_htlcs: Vec::new(),
+				holding_cell_htlc_updates: Vec::new(),
+				pending_update_fee: None,
+				holding_cell_update_fee: None,
+				next_holder_htlc_id: 0,
+				next_counterparty_htlc_id: 0,
+				update_time_counter: 1,
 
-	/// May panic if called on a channel that wasn't immediately-previously
-	/// self.remove_uncommitted_htlcs_and_mark_paused()'d
-	pub fn get_channel_reestablish<L: Deref>(&mut self, logger: &L) -> msgs::ChannelReestablish where L::Target: Logger {
-		assert_eq!(self.context.channel_state & ChannelState::PeerDisconnected as u32, ChannelState::PeerDisconnected as u32);
-		assert_ne!(self.context.cur_counterparty_commitment_transaction_number, INITIAL_COMMITMENT_NUMBER);
-		// Prior to static_remotekey, my_current_per_commitment_point was critical to claiming
-		// current to_remote balances. However, it no longer has any use, and thus is now simply
-		// set to a dummy (but valid, as required by the spec) public key.
-		// fuzzing mode marks a subset of pubkeys as invalid so that we can hit "invalid pubkey"
-		// branches, but we unwrap it below, so we arbitrarily select a dummy pubkey which is both
-		// valid, and valid in fuzzing mode's arbitrary validity criteria:
-		let mut pk = [2; 33]; pk[1] = 0xff;
-		let dummy_pubkey = PublicKey::from_slice(&pk).unwrap();
-		let remote_last_secret = if self.context.cur_counterparty_commitment_transaction_number + 1 < INITIAL_COMMITMENT_NUMBER {
-			let remote_last_secret = self.context.commitment_secrets.get_secret(self.context.cur_counterparty_commitment_transaction_number + 2).unwrap();
-			log_trace!(logger, "Enough info to generate a Data Loss Protect with per_commitment_secret {} for channel {}", log_bytes!(remote_last_secret), log_bytes!(self.context.channel_id()));
-			remote_last_secret
-		} else {
-			log_info!(logger, "Sending a data_loss_protect with no previous remote per_commitment_secret for channel {}", log_bytes!(self.context.channel_id()));
-			[0;32]
-		};
-		self.mark_awaiting_response();
-		msgs::ChannelReestablish {
-			channel_id: self.context.channel_id(),
-			// The protocol has two different commitment number concepts - the "commitment
-			// transaction number", which starts from 0 and counts up, and the "revocation key
-			// index" which starts at INITIAL_COMMITMENT_NUMBER and counts down. We track
-			// commitment transaction numbers by the index which will be used to reveal the
-			// revocation key for that commitment transaction, which means we have to convert them
-			// to protocol-level commitment numbers here...
+				resend_order: RAACommitmentOrder::CommitmentFirst,
 
-			// next_local_commitment_number is the next commitment_signed number we expect to
-			// receive (indicating if they need to resend one that we missed).
-			next_local_commitment_number: INITIAL_COMMITMENT_NUMBER - self.context.cur_holder_commitment_transaction_number,
-			// We have to set next_remote_commitment_number to the next revoke_and_ack we expect to
-			// receive, however we track it by the next commitment number for a remote transaction
-			// (which is one further, as they always revoke previous commitment transaction, not
-			// the one we send) so we have to decrement by 1. Note that if
-			// cur_counterparty_commitment_transaction_number is INITIAL_COMMITMENT_NUMBER we will have
-			// dropped this channel on disconnect as it hasn't yet reached FundingSent so we can't
-			// overflow here.
-			next_remote_commitment_number: INITIAL_COMMITMENT_NUMBER - self.context.cur_counterparty_commitment_transaction_number - 1,
-			your_last_per_commitment_secret: remote_last_secret,
-			my_current_per_commitment_point: dummy_pubkey,
-			// TODO(dual_funding): If we've sent `commtiment_signed` for an interactive transaction
-			// construction but have not received `tx_signatures` we MUST set `next_funding_txid` to the
-			// txid of that interactive transaction, else we MUST NOT set it.
-			next_funding_txid: None,
-		}
-	}
+				monitor_pending_channel_ready: false,
+				monitor_pending_revoke_and_ack: false,
+				monitor_pending_commitment_signed: false,
+				monitor_pending_forwards: Vec::new(),
+				monitor_pending_failures: Vec::new(),
+				monitor_pending_finalized_fulfills: Vec::new(),
 
+				#[cfg(debug_assertions)]
+				holder_max_commitment_tx_output: Mutex::new((channel_value_satoshis * 1000 - push_msat, push_msat)),
+				#[cfg(debug_assertions)]
+				counterparty_max_commitment_tx_output: Mutex::new((channel_value_satoshis * 1000 - push_msat, push_msat)),
 
-	// Send stuff to our remote peers:
+				last_sent_closing_fee: None,
+				pending_counterparty_closing_signed: None,
+				closing_fee_limits: None,
+				target_closing_feerate_sats_per_kw: None,
 
-	/// Queues up an outbound HTLC to send by placing it in the holding cell. You should call
-	/// [`Self::maybe_free_holding_cell_htlcs`] in order to actually generate and send the
-	/// commitment update.
-	///
-	/// `Err`s will only be [`ChannelError::Ignore`].
-	pub fn queue_add_htlc<F: Deref, L: Deref>(
-		&mut self, amount_msat: u64, payment_hash: PaymentHash, cltv_expiry: u32, source: HTLCSource,
-		onion_routing_packet: msgs::OnionPacket, skimmed_fee_msat: Option<u64>,
-		fee_estimator: &LowerBoundedFeeEstimator<F>, logger: &L
-	) -> Result<(), ChannelError>
-	where F::Target: FeeEstimator, L::Target: Logger
-	{
-		self
-			.send_htlc(amount_msat, payment_hash, cltv_expiry, source, onion_routing_packet, true,
-				skimmed_fee_msat, fee_estimator, logger)
-			.map(|msg_opt| assert!(msg_opt.is_none(), "We forced holding cell?"))
-			.map_err(|err| {
-				if let ChannelError::Ignore(_) = err { /* fine */ }
-				else { debug_assert!(false, "Queueing cannot trigger channel failure"); }
-				err
-			})
-	}
+				inbound_awaiting_accept: false,
 
-	/// Adds a pending outbound HTLC to this channel, note that you probably want
-	/// [`Self::send_htlc_and_commit`] instead cause you'll want both messages at once.
-	///
-	/// This returns an optional UpdateAddHTLC as we may be in a state where we cannot add HTLCs on
-	/// the wire:
-	/// * In cases where we're waiting on the remote peer to send us a revoke_and_ack, we
-	///   wouldn't be able to determine what they actually ACK'ed if we have two sets of updates
-	///   awaiting ACK.
-	/// * In cases where we're marked MonitorUpdateInProgress, we cannot commit to a new state as
-	///   we may not yet have sent the previous commitment update messages and will need to
-	///   regenerate them.
-	///
-	/// You MUST call [`Self::send_commitment_no_state_update`] prior to calling any other methods
-	/// on this [`Channel`] if `force_holding_cell` is false.
-	///
-	/// `Err`s will only be [`ChannelError::Ignore`].
-	fn send_htlc<F: Deref, L: Deref>(
-		&mut self, amount_msat: u64, payment_hash: PaymentHash, cltv_expiry: u32, source: HTLCSource,
-		onion_routing_packet: msgs::OnionPacket, mut force_holding_cell: bool,
-		skimmed_fee_msat: Option<u64>, fee_estimator: &LowerBoundedFeeEstimator<F>, logger: &L
-	) -> Result<Option<msgs::UpdateAddHTLC>, ChannelError>
-	where F::Target: FeeEstimator, L::Target: Logger
-	{
-		if (self.context.channel_state & (ChannelState::ChannelReady as u32 | BOTH_SIDES_SHUTDOWN_MASK)) != (ChannelState::ChannelReady as u32) {
-			return Err(ChannelError::Ignore("Cannot send HTLC until channel is fully established and we haven't started shutting down".to_owned()));
-		}
-		let channel_total_msat = self.context.channel_value_satoshis * 1000;
-		if amount_msat > channel_total_msat {
-			return Err(ChannelError::Ignore(format!("Cannot send amount {}, because it is more than the total value of the channel {}", amount_msat, channel_total_msat)));
-		}
+				funding_tx_confirmed_in: None,
+				funding_tx_confirmation_height: 0,
+				short_channel_id: None,
+				channel_creation_height: current_chain_height,
+
+				feerate_per_kw: commitment_feerate,
+				counterparty_dust_limit_satoshis: 0,
+				holder_dust_limit_satoshis: MIN_CHAN_DUST_LIMIT_SATOSHIS,
+				counterparty_max_htlc_value_in_flight_msat: 0,
+				holder_max_htlc_value_in_flight_msat: get_holder_max_htlc_value_in_flight_msat(channel_value_satoshis, &config.channel_handshake_config),
+				counterparty_selected_channel_reserve_satoshis: None, // Filled in in accept_channel
+				holder_selected_channel_reserve_satoshis,
+				counterparty_htlc_minimum_msat: 0,
+				holder_htlc_minimum_msat: if config.channel_handshake_config.our_htlc_minimum_msat == 0 { 1 } else { config.channel_handshake_config.our_htlc_minimum_msat },
+				counterparty_max_accepted_htlcs: 0,
+				holder_max_accepted_htlcs: cmp::min(config.channel_handshake_config.our_max_accepted_htlcs, MAX_HTLCS),
+				minimum_depth: None, // Filled in in accept_channel
+
+				counterparty_forwarding_info: None,
+
+				channel_transaction_parameters: ChannelTransactionParameters {
+					holder_pubkeys: pubkeys,
+					holder_selected_contest_delay: config.channel_handshake_config.our_to_self_delay,
+					is_outbound_from_holder: true,
+					counterparty_parameters: None,
+					funding_outpoint: None,
+					channel_type_features: channel_type.clone()
+				},
+				funding_transaction: None,
+				is_batch_funding: None,
 
-		if amount_msat == 0 {
-			return Err(ChannelError::Ignore("Cannot send 0-msat HTLC".to_owned()));
-		}
+				counterparty_cur_commitment_point: None,
+				counterparty_prev_commitment_point: None,
+				counterparty_node_id,
 
-		let available_balances = self.context.get_available_balances(fee_estimator);
-		if amount_msat < available_balances.next_outbound_htlc_minimum_msat {
-			return Err(ChannelError::Ignore(format!("Cannot send less than our next-HTLC minimum - {} msat",
-				available_balances.next_outbound_htlc_minimum_msat)));
-		}
+				counterparty_shutdown_scriptpubkey: None,
 
-		if amount_msat > available_balances.next_outbound_htlc_limit_msat {
-			return Err(ChannelError::Ignore(format!("Cannot send more than our next-HTLC maximum - {} msat",
-				available_balances.next_outbound_htlc_limit_msat)));
-		}
+				commitment_secrets: CounterpartyCommitmentSecrets::new(),
 
-		if (self.context.channel_state & (ChannelState::PeerDisconnected as u32)) != 0 {
-			// Note that this should never really happen, if we're !is_live() on receipt of an
-			// incoming HTLC for relay will result in us rejecting the HTLC and we won't allow
-			// the user to send directly into a !is_live() channel. However, if we
-			// disconnected during the time the previous hop was doing the commitment dance we may
-			// end up getting here after the forwarding delay. In any case, returning an
-			// IgnoreError will get ChannelManager to do the right thing and fail backwards now.
-			return Err(ChannelError::Ignore("Cannot send an HTLC while disconnected from channel counterparty".to_owned()));
-		}
+				channel_update_status: ChannelUpdateStatus::Enabled,
+				closing_signed_in_flight: false,
 
-		let need_holding_cell = (self.context.channel_state & (ChannelState::AwaitingRemoteRevoke as u32 | ChannelState::MonitorUpdateInProgress as u32)) != 0;
-		log_debug!(logger, "Pushing new outbound HTLC for {} msat {}", amount_msat,
-			if force_holding_cell { "into holding cell" }
-			else if need_holding_cell { "into holding cell as we're awaiting an RAA or monitor" }
-			else { "to peer" });
+				announcement_sigs: None,
 
-		if need_holding_cell {
-			force_holding_cell = true;
-		}
+				#[cfg(any(test, fuzzing))]
+				next_local_commitment_tx_fee_info_cached: Mutex::new(None),
+				#[cfg(any(test, fuzzing))]
+				next_remote_commitment_tx_fee_info_cached: Mutex::new(None),
 
-		// Now update local state:
-		if force_holding_cell {
-			self.context.holding_cell_htlc_updates.push(HTLCUpdateAwaitingACK::AddHTLC {
-				amount_msat,
-				payment_hash,
-				cltv_expiry,
-				source,
-				onion_routing_packet,
-				skimmed_fee_msat,
-			});
-			return Ok(None);
-		}
+				workaround_lnd_bug_4006: None,
+				sent_message_awaiting_response: None,
+				quiescence_timer_ticks: None,
+				is_holder_quiescence_initiator: None,
 
-		self.context.pending_outbound_htlcs.push(OutboundHTLCOutput {
-			htlc_id: self.context.next_holder_htlc_id,
-			amount_msat,
-			payment_hash: payment_hash.clone(),
-			cltv_expiry,
-			state: OutboundHTLCState::LocalAnnounced(Box::new(onion_routing_packet.clone())),
-			source,
-			skimmed_fee_msat,
-		});
+				latest_inbound_scid_alias: None,
+				outbound_scid_alias,
 
-		let res = msgs::UpdateAddHTLC {
-			channel_id: self.context.channel_id,
-			htlc_id: self.context.next_holder_htlc_id,
-			amount_msat,
-			payment_hash,
-			cltv_expiry,
-			onion_routing_packet,
-			skimmed_fee_msat,
-		};
-		self.context.next_holder_htlc_id += 1;
+				channel_pending_event_emitted: false,
+				channel_ready_event_emitted: false,
 
-		Ok(Some(res))
-	}
+				#[cfg(any(test, fuzzing))]
+				historical_inbound_htlc_fulfills: HashSet::new(),
 
-	fn build_commitment_no_status_check<L: Deref>(&mut self, logger: &L) -> ChannelMonitorUpdate where L::Target: Logger {
-		log_trace!(logger, "Updating HTLC state for a newly-sent commitment_signed...");
-		// We can upgrade the status of some HTLCs that are waiting on a commitment, even if we
-		// fail to generate this, we still are at least at a position where upgrading their status
-		// is acceptable.
-		for htlc in self.context.pending_inbound_htlcs.iter_mut() {
-			let new_state = if let &InboundHTLCState::AwaitingRemoteRevokeToAnnounce(ref forward_info) = &htlc.state {
-				Some(InboundHTLCState::AwaitingAnnouncedRemoteRevoke(forward_info.clone()))
-			} else { None };
-			if let Some(state) = new_state {
-				log_trace!(logger, " ...promoting inbound AwaitingRemoteRevokeToAnnounce {} to AwaitingAnnouncedRemoteRevoke", log_bytes!(htlc.payment_hash.0));
-				htlc.state = state;
-			}
-		}
-		for htlc in self.context.pending_outbound_htlcs.iter_mut() {
-			if let &mut OutboundHTLCState::AwaitingRemoteRevokeToRemove(ref mut outcome) = &mut htlc.state {
-				log_trace!(logger, " ...promoting outbound AwaitingRemoteRevokeToRemove {} to AwaitingRemovedRemoteRevoke", log_bytes!(htlc.payment_hash.0));
-				// Grab the preimage, if it exists, instead of cloning
-				let mut reason = OutboundHTLCOutcome::Success(None);
-				mem::swap(outcome, &mut reason);
-				htlc.state = OutboundHTLCState::AwaitingRemovedRemoteRevoke(reason);
-			}
-		}
-		if let Some((feerate, update_state)) = self.context.pending_update_fee {
-			if update_state == FeeUpdateState::AwaitingRemoteRevokeToAnnounce {
-				debug_assert!(!self.context.is_outbound());
-				log_trace!(logger, " ...promoting inbound AwaitingRemoteRevokeToAnnounce fee update {} to Committed", feerate);
-				self.context.feerate_per_kw = feerate;
-				self.context.pending_update_fee = None;
-			}
-		}
-		self.context.resend_order = RAACommitmentOrder::RevokeAndACKFirst;
+				channel_type,
+				channel_keys_id,
 
-		let (counterparty_commitment_txid, mut htlcs_ref) = self.build_commitment_no_state_update(logger);
-		let htlcs: Vec<(HTLCOutputInCommitment, Option<Box<HTLCSource>>)> =
-			htlcs_ref.drain(..).map(|(htlc, htlc_source)| (htlc, htlc_source.map(|source_ref| Box::new(source_ref.clone())))).collect();
+				blocked_monitor_updates: Vec::new(),
 
-		if self.context.announcement_sigs_state == AnnouncementSigsState::MessageSent {
-			self.context.announcement_sigs_state = AnnouncementSigsState::Committed;
-		}
+				interactive_tx_signing_session: None,
+				monitor_pending_tx_signatures: false,
 
-		self.context.latest_monitor_update_id += 1;
-		let monitor_update = ChannelMonitorUpdate {
-			update_id: self.context.latest_monitor_update_id,
-			updates: vec![ChannelMonitorUpdateStep::LatestCounterpartyCommitmentTXInfo {
-				commitment_txid: counterparty_commitment_txid,
-				htlc_outputs: htlcs.clone(),
-				commitment_number: self.context.cur_counterparty_commitment_transaction_number,
-				their_per_commitment_point: self.context.counterparty_cur_commitment_point.unwrap()
-			}]
-		};
-		self.context.channel_state |= ChannelState::AwaitingRemoteRevoke as u32;
-		monitor_update
+				pending_splice: None,
+				pending_commitment_signed_batch: Vec::new(),
+				splice_locked_pending_ack: false,
+				pending_channel_type_upgrade: None,
+			},
+			unfunded_context: UnfundedChannelContext { unfunded_channel_age_ticks: 0 }
+		})
 	}
 
-	fn build_commitment_no_state_update<L: Deref>(&self, logger: &L) -> (Txid, Vec<(HTLCOutputInCommitment, Option<&HTLCSource>)>) where L::Target: Logger {
-		let counterparty_keys = self.context.build_remote_transaction_keys();
-		let commitment_stats = self.context.build_commitment_transaction(self.context.cur_counterparty_commitment_transaction_number, &counterparty_keys, false, true, logger);
-		let counterparty_commitment_txid = commitment_stats.tx.trust().txid();
+	/// If an Err is returned, it is a ChannelError::Close (for get_funding_created)
+	fn get_funding_created_signature<L: Deref>(&mut self, logger: &L) -> Result<Signature, ChannelError> where L::Target: Logger {
+		self.context.get_initial_counterparty_commitment_signature(self.context.cur_counterparty_commitment_transaction_number, logger)
+	}
 
-		#[cfg(any(test, fuzzing))]
-		{
-			if !self.context.is_outbound() {
-				let projected_commit_tx_info = self.context.next_remote_commitment_tx_fee_info_cached.lock().unwrap().take();
-				*self.context.next_local_commitment_tx_fee_info_cached.lock().unwrap() = None;
-				if let Some(info) = projected_commit_tx_info {
-					let total_pending_htlcs = self.context.pending_inbound_htlcs.len() + self.context.pending_outbound_htlcs.len();
-					if info.total_pending_htlcs == total_pending_htlcs
-						&& info.next_holder_htlc_id == self.context.next_holder_htlc_id
-						&& info.next_counterparty_htlc_id == self.context.next_counterparty_htlc_id
-						&& info.feerate == self.context.feerate_per_kw {
-							let actual_fee = commit_tx_fee_msat(self.context.feerate_per_kw, commitment_stats.num_nondust_htlcs, self.context.get_channel_type());
-							assert_eq!(actual_fee, info.fee);
-						}
-				}
-			}
+	/// Updates channel state with knowledge of the funding transaction's txid/index, and generates
+	/// a funding_created message for the remote peer.
+	/// Panics if called at some time other than immediately after initial handshake, if called twice,
+	/// or if called on an inbound channel.
+	/// Note that channel_id changes during this call!
+	/// Do NOT broadcast the funding transaction until after a successful funding_signed call!
+	/// If an Err is returned, it is a ChannelError::Close.
+	pub fn get_funding_created<L: Deref>(mut self, funding_transaction: Transaction, funding_txo: OutPoint, is_batch_funding: bool, logger: &L)
+	-> Result<(Channel<Signer>, msgs::FundingCreated), (Self, ChannelError)> where L::Target: Logger {
+		if !self.context.is_outbound() {
+			panic!("Tried to create outbound funding_created message on an inbound channel!");
+		}
+		if self.context.channel_state != (ChannelState::OurInitSent as u32 | ChannelState::TheirInitSent as u32) {
+			panic!("Tried to get a funding_created messsage at a time other than immediately after initial handshake completion (or tried to get funding_created twice)");
+		}
+		if self.context.commitment_secrets.get_min_seen_secret() != (1 << 48) ||
+				self.context.cur_counterparty_commitment_transaction_number != INITIAL_COMMITMENT_NUMBER ||
+				self.context.cur_holder_commitment_transaction_number != INITIAL_COMMITMENT_NUMBER {
+			panic!("Should not have advanced channel commitment tx numbers prior to funding_created");
 		}
 
-		(counterparty_commitment_txid, commitment_stats.htlcs_included)
-	}
+		self.context.channel_transaction_parameters.funding_outpoint = Some(funding_txo);
+		self.context.holder_signer.provide_channel_parameters(&self.context.channel_transaction_parameters);
 
-	/// Only fails in case of signer rejection. Used for channel_reestablish commitment_signed
-	/// generation when we shouldn't change HTLC/channel state.
-	fn send_commitment_no_state_update<L: Deref>(&self, logger: &L) -> Result<(msgs::CommitmentSigned, (Txid, Vec<(HTLCOutputInCommitment, Option<&HTLCSource>)>)), ChannelError> where L::Target: Logger {
-		// Get the fee tests from `build_commitment_no_state_update`
-		#[cfg(any(test, fuzzing))]
-		self.build_commitment_no_state_update(logger);
+		let signature = match self.get_funding_created_signature(logger) {
+			Ok(res) => res,
+			Err(e) => {
+				log_error!(logger, "Got bad signatures: {:?}!", e);
+				self.context.channel_transaction_parameters.funding_outpoint = None;
+				return Err((self, e));
+			}
+		};
 
-		let counterparty_keys = self.context.build_remote_transaction_keys();
-		let commitment_stats = self.context.build_commitment_transaction(self.context.cur_counterparty_commitment_transaction_number, &counterparty_keys, false, true, logger);
-		let counterparty_commitment_txid = commitment_stats.tx.trust().txid();
-		let (signature, htlc_signatures);
+		let temporary_channel_id = self.context.channel_id;
 
-		{
-			let mut htlcs = Vec::with_capacity(commitment_stats.htlcs_included.len());
-			for &(ref htlc, _) in commitment_stats.htlcs_included.iter() {
-				htlcs.push(htlc);
-			}
+		// Now that we're past error-generating stuff, update our local state:
 
-			let res = self.context.holder_signer.sign_counterparty_commitment(&commitment_stats.tx, commitment_stats.preimages, &self.context.secp_ctx)
-				.map_err(|_| ChannelError::Close("Failed to get signatures for new commitment_signed".to_owned()))?;
-			signature = res.0;
-			htlc_signatures = res.1;
+		self.context.channel_state = ChannelState::FundingCreated as u32;
+		self.context.channel_id = funding_txo.to_channel_id();
+		self.context.funding_transaction = Some(funding_transaction);
+		self.context.is_batch_funding = if is_batch_funding { Some(()) } else { None };
 
-			log_trace!(logger, "Signed remote commitment tx {} (txid {}) with redeemscript {} -> {} in channel {}",
-				encode::serialize_hex(&commitment_stats.tx.trust().built_transaction().transaction),
-				&counterparty_commitment_txid, encode::serialize_hex(&self.context.get_funding_redeemscript()),
-				log_bytes!(signature.serialize_compact()[..]), log_bytes!(self.context.channel_id()));
+		let channel = Channel {
+			context: self.context,
+			interactive_tx_constructor: None,
+		};
 
-			for (ref htlc_sig, ref htlc) in htlc_signatures.iter().zip(htlcs) {
-				log_trace!(logger, "Signed remote HTLC tx {} with redeemscript {} with pubkey {} -> {} in channel {}",
-					encode::serialize_hex(&chan_utils::build_htlc_transaction(&counterparty_commitment_txid, commitment_stats.feerate_per_kw, self.context.get_holder_selected_contest_delay(), htlc, &self.context.channel_type, &counterparty_keys.broadcaster_delayed_payment_key, &counterparty_keys.revocation_key)),
-					encode::serialize_hex(&chan_utils::get_htlc_redeemscript(&htlc, &self.context.channel_type, &counterparty_keys)),
-					log_bytes!(counterparty_keys.broadcaster_htlc_key.serialize()),
-					log_bytes!(htlc_sig.serialize_compact()[..]), log_bytes!(self.context.channel_id()));
-			}
+		Ok((channel, msgs::FundingCreated {
+			temporary_channel_id,
+			funding_txid: funding_txo.txid,
+			funding_output_index: funding_txo.index,
+			signature,
+			#[cfg(taproot)]
+			partial_signature_with_nonce: None,
+			#[cfg(taproot)]
+			next_local_nonce: None,
+		}))
+	}
+
+	fn get_initial_channel_type(config: &UserConfig, their_features: &InitFeatures) -> ChannelTypeFeatures {
+		// The default channel type (ie the first one we try) depends on whether the channel is
+		// public - if it is, we just go with `only_static_remotekey` as it's the only option
+		// available. If it's private, we first try `scid_privacy` as it provides better privacy
+		// with no other changes, and fall back to `only_static_remotekey`.
+		let mut ret = ChannelTypeFeatures::only_static_remote_key();
+		if !config.channel_handshake_config.announced_channel &&
+			config.channel_handshake_config.negotiate_scid_privacy &&
+			their_features.supports_scid_privacy() {
+			ret.set_scid_privacy_required();
+		}
+
+		// Optionally, if the user would like to negotiate the `anchors_zero_fee_htlc_tx` option, we
+		// set it now. If they don't understand it, we'll fall back to our default of
+		// `only_static_remotekey`.
+		if config.channel_handshake_config.negotiate_anchors_zero_fee_htlc_tx &&
+			their_features.supports_anchors_zero_fee_htlc_tx() {
+			ret.set_anchors_zero_fee_htlc_tx_required();
 		}
 
-		Ok((msgs::CommitmentSigned {
-			channel_id: self.context.channel_id,
-			signature,
-			htlc_signatures,
-			#[cfg(taproot)]
-			partial_signature_with_nonce: None,
-		}, (counterparty_commitment_txid, commitment_stats.htlcs_included)))
+		ret
 	}
 
-	/// Adds a pending outbound HTLC to this channel, and builds a new remote commitment
-	/// transaction and generates the corresponding [`ChannelMonitorUpdate`] in one go.
-	///
-	/// Shorthand for calling [`Self::send_htlc`] followed by a commitment update, see docs on
-	/// [`Self::send_htlc`] and [`Self::build_commitment_no_state_update`] for more info.
-	pub fn send_htlc_and_commit<F: Deref, L: Deref>(
-		&mut self, amount_msat: u64, payment_hash: PaymentHash, cltv_expiry: u32,
-		source: HTLCSource, onion_routing_packet: msgs::OnionPacket, skimmed_fee_msat: Option<u64>,
-		fee_estimator: &LowerBoundedFeeEstimator<F>, logger: &L
-	) -> Result<Option<ChannelMonitorUpdate>, ChannelError>
-	where F::Target: FeeEstimator, L::Target: Logger
+	/// If we receive an error message, it may only be a rejection of the channel type we tried,
+	/// not of our ability to open any channel at all. Thus, on error, we should first call this
+	/// and see if we get a new `OpenChannel` message, otherwise the channel is failed.
+	pub(crate) fn maybe_handle_error_without_close<F: Deref>(
+		&mut self, chain_hash: BlockHash, fee_estimator: &LowerBoundedFeeEstimator<F>
+	) -> Result<msgs::OpenChannel, ()>
+	where
+		F::Target: FeeEstimator
 	{
-		let send_res = self.send_htlc(amount_msat, payment_hash, cltv_expiry, source,
-			onion_routing_packet, false, skimmed_fee_msat, fee_estimator, logger);
-		if let Err(e) = &send_res { if let ChannelError::Ignore(_) = e {} else { debug_assert!(false, "Sending cannot trigger channel failure"); } }
-		match send_res? {
-			Some(_) => {
-				let monitor_update = self.build_commitment_no_status_check(logger);
-				self.monitor_updating_paused(false, true, false, Vec::new(), Vec::new(), Vec::new());
-				Ok(self.push_ret_blockable_mon_update(monitor_update))
-			},
-			None => Ok(None)
+		if !self.context.is_outbound() || self.context.channel_state != ChannelState::OurInitSent as u32 { return Err(()); }
+		if self.context.channel_type == ChannelTypeFeatures::only_static_remote_key() {
+			// We've exhausted our options
+			return Err(());
 		}
-	}
-
-	pub fn channel_update(&mut self, msg: &msgs::ChannelUpdate) -> Result<(), ChannelError> {
-		if msg.contents.htlc_minimum_msat >= self.context.channel_value_satoshis * 1000 {
-			return Err(ChannelError::Close("Minimum htlc value is greater than channel value".to_string()));
+		// We support opening a few different types of channels. Try removing our additional
+		// features one by one until we've either arrived at our default or the counterparty has
+		// accepted one.
+		//
+		// Due to the order below, we may not negotiate `option_anchors_zero_fee_htlc_tx` if the
+		// counterparty doesn't support `option_scid_privacy`. Since `get_initial_channel_type`
+		// checks whether the counterparty supports every feature, this would only happen if the
+		// counterparty is advertising the feature, but rejecting channels proposing the feature for
+		// whatever reason.
+		if self.context.channel_type.supports_anchors_zero_fee_htlc_tx() {
+			self.context.channel_type.clear_anchors_zero_fee_htlc_tx();
+			self.context.feerate_per_kw = fee_estimator.bounded_sat_per_1000_weight(ConfirmationTarget::Normal);
+			assert!(!self.context.channel_transaction_parameters.channel_type_features.supports_anchors_nonzero_fee_htlc_tx());
+		} else if self.context.channel_type.supports_scid_privacy() {
+			self.context.channel_type.clear_scid_privacy();
+		} else {
+			self.context.channel_type = ChannelTypeFeatures::only_static_remote_key();
 		}
-		self.context.counterparty_forwarding_info = Some(CounterpartyForwardingInfo {
-			fee_base_msat: msg.contents.fee_base_msat,
-			fee_proportional_millionths: msg.contents.fee_proportional_millionths,
-			cltv_expiry_delta: msg.contents.cltv_expiry_delta
-		});
-
-		Ok(())
+		self.context.channel_transaction_parameters.channel_type_features = self.context.channel_type.clone();
+		Ok(self.get_open_channel(chain_hash))
 	}
 
-	/// Begins the shutdown process, getting a message for the remote peer and returning all
-	/// holding cell HTLCs for payment failure.
-	///
-	/// May jump to the channel being fully shutdown (see [`Self::is_shutdown`]) in which case no
-	/// [`ChannelMonitorUpdate`] will be returned).
-	pub fn get_shutdown<SP: Deref>(&mut self, signer_provider: &SP, their_features: &InitFeatures,
-		target_feerate_sats_per_kw: Option<u32>, override_shutdown_script: Option<ShutdownScript>)
-	-> Result<(msgs::Shutdown, Option<ChannelMonitorUpdate>, Vec<(HTLCSource, PaymentHash)>), APIError>
-	where SP::Target: SignerProvider {
-		for htlc in self.context.pending_outbound_htlcs.iter() {
-			if let OutboundHTLCState::LocalAnnounced(_) = htlc.state {
-				return Err(APIError::APIMisuseError{err: "Cannot begin shutdown with pending HTLCs. Process pending events first".to_owned()});
-			}
-		}
-		if self.context.channel_state & BOTH_SIDES_SHUTDOWN_MASK != 0 {
-			if (self.context.channel_state & ChannelState::LocalShutdownSent as u32) == ChannelState::LocalShutdownSent as u32 {
-				return Err(APIError::APIMisuseError{err: "Shutdown already in progress".to_owned()});
-			}
-			else if (self.context.channel_state & ChannelState::RemoteShutdownSent as u32) == ChannelState::RemoteShutdownSent as u32 {
-				return Err(APIError::ChannelUnavailable{err: "Shutdown already in progress by remote".to_owned()});
-			}
-		}
-		if self.context.shutdown_scriptpubkey.is_some() && override_shutdown_script.is_some() {
-			return Err(APIError::APIMisuseError{err: "Cannot override shutdown script for a channel with one already set".to_owned()});
+	pub fn get_open_channel(&self, chain_hash: BlockHash) -> msgs::OpenChannel {
+		if !self.context.is_outbound() {
+			panic!("Tried to open a channel for an inbound channel?");
 		}
-		assert_eq!(self.context.channel_state & ChannelState::ShutdownComplete as u32, 0);
-		if self.context.channel_state & (ChannelState::PeerDisconnected as u32 | ChannelState::MonitorUpdateInProgress as u32) != 0 {
-			return Err(APIError::ChannelUnavailable{err: "Cannot begin shutdown while peer is disconnected or we're waiting on a monitor update, maybe force-close instead?".to_owned()});
+		if self.context.channel_state != ChannelState::OurInitSent as u32 {
+			panic!("Cannot generate an open_channel after we've moved forward");
 		}
 
-		// If we haven't funded the channel yet, we don't need to bother ensuring the shutdown
-		// script is set, we just force-close and call it a day.
-		let mut chan_closed = false;
-		if self.context.channel_state < ChannelState::FundingSent as u32 {
-			chan_closed = true;
+		if self.context.cur_holder_commitment_transaction_number != INITIAL_COMMITMENT_NUMBER {
+			panic!("Tried to send an open_channel for a channel that has already advanced");
 		}
 
-		let update_shutdown_script = match self.context.shutdown_scriptpubkey {
-			Some(_) => false,
-			None if !chan_closed => {
-				// use override shutdown script if provided
-				let shutdown_scriptpubkey = match override_shutdown_script {
-					Some(script) => script,
-					None => {
-						// otherwise, use the shutdown scriptpubkey provided by the signer
-						match signer_provider.get_shutdown_scriptpubkey() {
-							Ok(scriptpubkey) => scriptpubkey,
-							Err(_) => return Err(APIError::ChannelUnavailable{err: "Failed to get shutdown scriptpubkey".to_owned()}),
-						}
-					},
-				};
-				if !shutdown_scriptpubkey.is_compatible(their_features) {
-					return Err(APIError::IncompatibleShutdownScript { script: shutdown_scriptpubkey.clone() });
-				}
-				self.context.shutdown_scriptpubkey = Some(shutdown_scriptpubkey);
-				true
-			},
-			None => false,
-		};
+		let first_per_commitment_point = self.context.holder_signer.get_per_commitment_point(self.context.cur_holder_commitment_transaction_number, &self.context.secp_ctx);
+		let keys = self.context.get_holder_pubkeys();
 
-		// From here on out, we may not fail!
-		self.context.target_closing_feerate_sats_per_kw = target_feerate_sats_per_kw;
-		if self.context.channel_state < ChannelState::FundingSent as u32 {
-			self.context.channel_state = ChannelState::ShutdownComplete as u32;
-		} else {
-			self.context.channel_state |= ChannelState::LocalShutdownSent as u32;
+		msgs::OpenChannel {
+			chain_hash,
+			temporary_channel_id: self.context.channel_id,
+			funding_satoshis: self.context.channel_value_satoshis,
+			push_msat: self.context.channel_value_satoshis * 1000 - self.context.value_to_self_msat,
+			dust_limit_satoshis: self.context.holder_dust_limit_satoshis,
+			max_htlc_value_in_flight_msat: self.context.holder_max_htlc_value_in_flight_msat,
+			channel_reserve_satoshis: self.context.holder_selected_channel_reserve_satoshis,
+			htlc_minimum_msat: self.context.holder_htlc_minimum_msat,
+			feerate_per_kw: self.context.feerate_per_kw as u32,
+			to_self_delay: self.context.get_holder_selected_contest_delay(),
+			max_accepted_htlcs: self.context.holder_max_accepted_htlcs,
+			funding_pubkey: keys.funding_pubkey,
+			revocation_basepoint: keys.revocation_basepoint,
+			payment_point: keys.payment_point,
+			delayed_payment_basepoint: keys.delayed_payment_basepoint,
+			htlc_basepoint: keys.htlc_basepoint,
+			first_per_commitment_point,
+			channel_flags: if self.context.config.announced_channel {1} else {0},
+			shutdown_scriptpubkey: Some(match &self.context.shutdown_scriptpubkey {
+				Some(script) => script.clone().into_inner(),
+				None => Builder::new().into_script(),
+			}),
+			channel_type: Some(self.context.channel_type.clone()),
 		}
-		self.context.update_time_counter += 1;
+	}
 
-		let monitor_update = if update_shutdown_script {
-			self.context.latest_monitor_update_id += 1;
-			let monitor_update = ChannelMonitorUpdate {
-				update_id: self.context.latest_monitor_update_id,
-				updates: vec![ChannelMonitorUpdateStep::ShutdownScript {
-					scriptpubkey: self.get_closing_scriptpubkey(),
-				}],
-			};
-			self.monitor_updating_paused(false, false, false, Vec::new(), Vec::new(), Vec::new());
-			self.push_ret_blockable_mon_update(monitor_update)
-		} else { None };
-		let shutdown = msgs::Shutdown {
-			channel_id: self.context.channel_id,
-			scriptpubkey: self.get_closing_scriptpubkey(),
-		};
+	// Message handlers
+	pub fn accept_channel(&mut self, msg: &msgs::AcceptChannel, default_limits: &ChannelHandshakeLimits, their_features: &InitFeatures) -> Result<(), ChannelError> {
+		self.context.do_accept_channel_checks_and_update(msg, default_limits, their_features)
+	}
+}
 
-		// Go ahead and drop holding cell updates as we'd rather fail payments than wait to send
-		// our shutdown until we've committed all of the pending changes.
-		self.context.holding_cell_update_fee = None;
-		let mut dropped_outbound_htlcs = Vec::with_capacity(self.context.holding_cell_htlc_updates.len());
-		self.context.holding_cell_htlc_updates.retain(|htlc_update| {
-			match htlc_update {
-				&HTLCUpdateAwaitingACK::AddHTLC { ref payment_hash, ref source, .. } => {
-					dropped_outbound_htlcs.push((source.clone(), payment_hash.clone()));
-					false
-				},
-				_ => true
+/// A not-yet-funded inbound (from counterparty) channel using V1 channel establishment.
+pub(super) struct InboundV1Channel<Signer: ChannelSigner> {
+	pub context: ChannelContext<Signer>,
+	pub unfunded_context: UnfundedChannelContext,
+}
+
+impl<Signer: WriteableEcdsaChannelSigner> InboundV1Channel<Signer> {
+	/// Creates a new channel from a remote sides' request for one.
+	/// Assumes chain_hash has already been checked and corresponds with what we expect!
+	pub fn new<ES: Deref, SP: Deref, F: Deref, L: Deref>(
+		fee_estimator: &LowerBoundedFeeEstimator<F>, entropy_source: &ES, signer_provider: &SP,
+		counterparty_node_id: PublicKey, our_supported_features: &ChannelTypeFeatures,
+		their_features: &InitFeatures, msg: &msgs::OpenChannel, user_id: u128, config: &UserConfig,
+		current_chain_height: u32, logger: &L, outbound_scid_alias: u64
+	) -> Result<InboundV1Channel<Signer>, ChannelError>
+		where ES::Target: EntropySource,
+			  SP::Target: SignerProvider<Signer = Signer>,
+			  F::Target: FeeEstimator,
+			  L::Target: Logger,
+	{
+		let announced_channel = if (msg.channel_flags & 1) == 1 { true } else { false };
+
+		// First check the channel type is known, failing before we do anything else if we don't
+		// support this channel type.
+		let channel_type = if let Some(channel_type) = &msg.channel_type {
+			if channel_type.supports_any_optional_bits() {
+				return Err(ChannelError::Close("Channel Type field contained optional bits - this is not allowed".to_owned()));
 			}
-		});
 
-		debug_assert!(!self.is_shutdown() || monitor_update.is_none(),
-			"we can't both complete shutdown and return a monitor update");
+			// We only support the channel types defined by the `ChannelManager` in
+			// `provided_channel_type_features`. The channel type must always support
+			// `static_remote_key`.
+			if !channel_type.requires_static_remote_key() {
+				return Err(ChannelError::Close("Channel Type was not understood - we require static remote key".to_owned()));
+			}
+			// Make sure we support all of the features behind the channel type.
+			if !channel_type.is_subset(our_supported_features) {
+				return Err(ChannelError::Close("Channel Type contains unsupported features".to_owned()));
+			}
+			if channel_type.requires_scid_privacy() && announced_channel {
+				return Err(ChannelError::Close("SCID Alias/Privacy Channel Type cannot be set on a public channel".to_owned()));
+			}
+			channel_type.clone()
+		} else {
+			let channel_type = ChannelTypeFeatures::from_init(&their_features);
+			if channel_type != ChannelTypeFeatures::only_static_remote_key() {
+				return Err(ChannelError::Close("Only static_remote_key is supported for non-negotiated channel types".to_owned()));
+			}
+			channel_type
+		};
 
-		Ok((shutdown, monitor_update, dropped_outbound_htlcs))
-	}
+		let channel_keys_id = signer_provider.generate_channel_keys_id(true, msg.funding_satoshis, user_id);
+		let holder_signer = signer_provider.derive_channel_signer(msg.funding_satoshis, channel_keys_id);
+		let pubkeys = holder_signer.pubkeys().clone();
+		let counterparty_pubkeys = ChannelPublicKeys {
+			funding_pubkey: msg.funding_pubkey,
+			revocation_basepoint: msg.revocation_basepoint,
+			payment_point: msg.payment_point,
+			delayed_payment_basepoint: msg.delayed_payment_basepoint,
+			htlc_basepoint: msg.htlc_basepoint
+		};
 
-	pub fn inflight_htlc_sources(&self) -> impl Iterator<Item=(&HTLCSource, &PaymentHash)> {
-		self.context.holding_cell_htlc_updates.iter()
-			.flat_map(|htlc_update| {
-				match htlc_update {
-					HTLCUpdateAwaitingACK::AddHTLC { source, payment_hash, .. }
-						=> Some((source, payment_hash)),
-					_ => None,
-				}
-			})
-			.chain(self.context.pending_outbound_htlcs.iter().map(|htlc| (&htlc.source, &htlc.payment_hash)))
-	}
-}
+		if config.channel_handshake_config.our_to_self_delay < BREAKDOWN_TIMEOUT {
+			return Err(ChannelError::Close(format!("Configured with an unreasonable our_to_self_delay ({}) putting user funds at risks. It must be greater than {}", config.channel_handshake_config.our_to_self_delay, BREAKDOWN_TIMEOUT)));
+		}
 
-/// A not-yet-funded outbound (from holder) channel using V1 channel establishment.
-pub(super) struct OutboundV1Channel<Signer: ChannelSigner> {
-	pub context: ChannelContext<Signer>,
-	pub unfunded_context: UnfundedChannelContext,
-}
+		// Check sanity of message fields:
+		if msg.funding_satoshis > config.channel_handshake_limits.max_funding_satoshis {
+			return Err(ChannelError::Close(format!("Per our config, funding must be at most {}. It was {}", config.channel_handshake_limits.max_funding_satoshis, msg.funding_satoshis)));
+		}
+		if msg.funding_satoshis >= TOTAL_BITCOIN_SUPPLY_SATOSHIS {
+			return Err(ChannelError::Close(format!("Funding must be smaller than the total bitcoin supply. It was {}", msg.funding_satoshis)));
+		}
+		if msg.channel_reserve_satoshis > msg.funding_satoshis {
+			return Err(ChannelError::Close(format!("Bogus channel_reserve_satoshis ({}). Must be not greater than funding_satoshis: {}", msg.channel_reserve_satoshis, msg.funding_satoshis)));
+		}
+		let full_channel_value_msat = (msg.funding_satoshis - msg.channel_reserve_satoshis) * 1000;
+		if msg.push_msat > full_channel_value_msat {
+			return Err(ChannelError::Close(format!("push_msat {} was larger than channel amount minus reserve ({})", msg.push_msat, full_channel_value_msat)));
+		}
+		if msg.dust_limit_satoshis > msg.funding_satoshis {
+			return Err(ChannelError::Close(format!("dust_limit_satoshis {} was larger than funding_satoshis {}. Peer never wants payout outputs?", msg.dust_limit_satoshis, msg.funding_satoshis)));
+		}
+		if msg.htlc_minimum_msat >= full_channel_value_msat {
+			return Err(ChannelError::Close(format!("Minimum htlc value ({}) was larger than full channel value ({})", msg.htlc_minimum_msat, full_channel_value_msat)));
+		}
+		Channel::<Signer>::check_remote_fee(&channel_type, fee_estimator, msg.feerate_per_kw, None, logger)?;
 
-impl<Signer: WriteableEcdsaChannelSigner> OutboundV1Channel<Signer> {
-	pub fn new<ES: Deref, SP: Deref, F: Deref>(
-		fee_estimator: &LowerBoundedFeeEstimator<F>, entropy_source: &ES, signer_provider: &SP, counterparty_node_id: PublicKey, their_features: &InitFeatures,
-		channel_value_satoshis: u64, push_msat: u64, user_id: u128, config: &UserConfig, current_chain_height: u32,
-		outbound_scid_alias: u64
-	) -> Result<OutboundV1Channel<Signer>, APIError>
-	where ES::Target: EntropySource,
-	      SP::Target: SignerProvider<Signer = Signer>,
-	      F::Target: FeeEstimator,
-	{
-		let holder_selected_contest_delay = config.channel_handshake_config.our_to_self_delay;
-		let channel_keys_id = signer_provider.generate_channel_keys_id(false, channel_value_satoshis, user_id);
-		let holder_signer = signer_provider.derive_channel_signer(channel_value_satoshis, channel_keys_id);
-		let pubkeys = holder_signer.pubkeys().clone();
+		let max_counterparty_selected_contest_delay = u16::min(config.channel_handshake_limits.their_to_self_delay, MAX_LOCAL_BREAKDOWN_TIMEOUT);
+		if msg.to_self_delay > max_counterparty_selected_contest_delay {
+			return Err(ChannelError::Close(format!("They wanted our payments to be delayed by a needlessly long period. Upper limit: {}. Actual: {}", max_counterparty_selected_contest_delay, msg.to_self_delay)));
+		}
+		if msg.max_accepted_htlcs < 1 {
+			return Err(ChannelError::Close("0 max_accepted_htlcs makes for a useless channel".to_owned()));
+		}
+		if msg.max_accepted_htlcs > MAX_HTLCS {
+			return Err(ChannelError::Close(format!("max_accepted_htlcs was {}. It must not be larger than {}", msg.max_accepted_htlcs, MAX_HTLCS)));
+		}
 
-		if !their_features.supports_wumbo() && channel_value_satoshis > MAX_FUNDING_SATOSHIS_NO_WUMBO {
-			return Err(APIError::APIMisuseError{err: format!("funding_value must not exceed {}, it was {}", MAX_FUNDING_SATOSHIS_NO_WUMBO, channel_value_satoshis)});
+		// Now check against optional parameters as set by config...
+		if msg.funding_satoshis < config.channel_handshake_limits.min_funding_satoshis {
+			return Err(ChannelError::Close(format!("Funding satoshis ({}) is less than the user specified limit ({})", msg.funding_satoshis, config.channel_handshake_limits.min_funding_satoshis)));
 		}
-		if channel_value_satoshis >= TOTAL_BITCOIN_SUPPLY_SATOSHIS {
-			return Err(APIError::APIMisuseError{err: format!("funding_value must be smaller than the total bitcoin supply, it was {}", channel_value_satoshis)});
+		if msg.htlc_minimum_msat > config.channel_handshake_limits.max_htlc_minimum_msat {
+			return Err(ChannelError::Close(format!("htlc_minimum_msat ({}) is higher than the user specified limit ({})", msg.htlc_minimum_msat,  config.channel_handshake_limits.max_htlc_minimum_msat)));
 		}
-		let channel_value_msat = channel_value_satoshis * 1000;
-		if push_msat > channel_value_msat {
-			return Err(APIError::APIMisuseError { err: format!("Push value ({}) was larger than channel_value ({})", push_msat, channel_value_msat) });
+		if msg.max_htlc_value_in_flight_msat < config.channel_handshake_limits.min_max_htlc_value_in_flight_msat {
+			return Err(ChannelError::Close(format!("max_htlc_value_in_flight_msat ({}) is less than the user specified limit ({})", msg.max_htlc_value_in_flight_msat, config.channel_handshake_limits.min_max_htlc_value_in_flight_msat)));
 		}
-		if holder_selected_contest_delay < BREAKDOWN_TIMEOUT {
-			return Err(APIError::APIMisuseError {err: format!("Configured with an unreasonable our_to_self_delay ({}) putting user funds at risks", holder_selected_contest_delay)});
+		if msg.channel_reserve_satoshis > config.channel_handshake_limits.max_channel_reserve_satoshis {
+			return Err(ChannelError::Close(format!("channel_reserve_satoshis ({}) is higher than the user specified limit ({})", msg.channel_reserve_satoshis, config.channel_handshake_limits.max_channel_reserve_satoshis)));
 		}
-		let holder_selected_channel_reserve_satoshis = get_holder_selected_channel_reserve_satoshis(channel_value_satoshis, config);
+		if msg.max_accepted_htlcs < config.channel_handshake_limits.min_max_accepted_htlcs {
+			return Err(ChannelError::Close(format!("max_accepted_htlcs ({}) is less than the user specified limit ({})", msg.max_accepted_htlcs, config.channel_handshake_limits.min_max_accepted_htlcs)));
+		}
+		if msg.dust_limit_satoshis < MIN_CHAN_DUST_LIMIT_SATOSHIS {
+			return Err(ChannelError::Close(format!("dust_limit_satoshis ({}) is less than the implementation limit ({})", msg.dust_limit_satoshis, MIN_CHAN_DUST_LIMIT_SATOSHIS)));
+		}
+		if msg.dust_limit_satoshis >  MAX_CHAN_DUST_LIMIT_SATOSHIS {
+			return Err(ChannelError::Close(format!("dust_limit_satoshis ({}) is greater than the implementation limit ({})", msg.dust_limit_satoshis, MAX_CHAN_DUST_LIMIT_SATOSHIS)));
+		}
+
+		// Convert things into internal flags and prep our state:
+
+		if config.channel_handshake_limits.force_announced_channel_preference {
+			if config.channel_handshake_config.announced_channel != announced_channel {
+				return Err(ChannelError::Close("Peer tried to open channel but their announcement preference is different from ours".to_owned()));
+			}
+		}
+
+		let holder_selected_channel_reserve_satoshis = get_holder_selected_channel_reserve_satoshis(msg.funding_satoshis, config);
 		if holder_selected_channel_reserve_satoshis < MIN_CHAN_DUST_LIMIT_SATOSHIS {
 			// Protocol level safety check in place, although it should never happen because
 			// of `MIN_THEIR_CHAN_RESERVE_SATOSHIS`
-			return Err(APIError::APIMisuseError { err: format!("Holder selected channel  reserve below implemention limit dust_limit_satoshis {}", holder_selected_channel_reserve_satoshis) });
+			return Err(ChannelError::Close(format!("Suitable channel reserve not found. remote_channel_reserve was ({}). dust_limit_satoshis is ({}).", holder_selected_channel_reserve_satoshis, MIN_CHAN_DUST_LIMIT_SATOSHIS)));
+		}
+		if holder_selected_channel_reserve_satoshis * 1000 >= full_channel_value_msat {
+			return Err(ChannelError::Close(format!("Suitable channel reserve not found. remote_channel_reserve was ({})msats. Channel value is ({} - {})msats.", holder_selected_channel_reserve_satoshis * 1000, full_channel_value_msat, msg.push_msat)));
+		}
+		if msg.channel_reserve_satoshis < MIN_CHAN_DUST_LIMIT_SATOSHIS {
+			log_debug!(logger, "channel_reserve_satoshis ({}) is smaller than our dust limit ({}). We can broadcast stale states without any risk, implying this channel is very insecure for our counterparty.",
+				msg.channel_reserve_satoshis, MIN_CHAN_DUST_LIMIT_SATOSHIS);
+		}
+		if holder_selected_channel_reserve_satoshis < msg.dust_limit_satoshis {
+			return Err(ChannelError::Close(format!("Dust limit ({}) too high for the channel reserve we require the remote to keep ({})", msg.dust_limit_satoshis, holder_selected_channel_reserve_satoshis)));
 		}
 
-		let channel_type = Self::get_initial_channel_type(&config, their_features);
-		debug_assert!(channel_type.is_subset(&channelmanager::provided_channel_type_features(&config)));
-
-		let commitment_conf_target = if channel_type.supports_anchors_zero_fee_htlc_tx() {
-			ConfirmationTarget::MempoolMinimum
-		} else {
-			ConfirmationTarget::Normal
-		};
-		let commitment_feerate = fee_estimator.bounded_sat_per_1000_weight(commitment_conf_target);
+		// check if the funder's amount for the initial commitment tx is sufficient
+		// for full fee payment plus a few HTLCs to ensure the channel will be useful.
+		let funders_amount_msat = msg.funding_satoshis * 1000 - msg.push_msat;
+		let commitment_tx_fee = commit_tx_fee_msat(msg.feerate_per_kw, MIN_AFFORDABLE_HTLC_COUNT, &channel_type) / 1000;
+		if funders_amount_msat / 1000 < commitment_tx_fee {
+			return Err(ChannelError::Close(format!("Funding amount ({} sats) can't even pay fee for initial commitment transaction fee of {} sats.", funders_amount_msat / 1000, commitment_tx_fee)));
+		}
 
-		let value_to_self_msat = channel_value_satoshis * 1000 - push_msat;
-		let commitment_tx_fee = commit_tx_fee_msat(commitment_feerate, MIN_AFFORDABLE_HTLC_COUNT, &channel_type);
-		if value_to_self_msat < commitment_tx_fee {
-			return Err(APIError::APIMisuseError{ err: format!("Funding amount ({}) can't even pay fee for initial commitment transaction fee of {}.", value_to_self_msat / 1000, commitment_tx_fee / 1000) });
+		let to_remote_satoshis = funders_amount_msat / 1000 - commitment_tx_fee;
+		// While it's reasonable for us to not meet the channel reserve initially (if they don't
+		// want to push much to us), our counterparty should always have more than our reserve.
+		if to_remote_satoshis < holder_selected_channel_reserve_satoshis {
+			return Err(ChannelError::Close("Insufficient funding amount for initial reserve".to_owned()));
 		}
 
-		let mut secp_ctx = Secp256k1::new();
-		secp_ctx.seeded_randomize(&entropy_source.get_secure_random_bytes());
+		let counterparty_shutdown_scriptpubkey = if their_features.supports_upfront_shutdown_script() {
+			match &msg.shutdown_scriptpubkey {
+				&Some(ref script) => {
+					// Peer is signaling upfront_shutdown and has opt-out with a 0-length script. We don't enforce anything
+					if script.len() == 0 {
+						None
+					} else {
+						if !script::is_bolt2_compliant(&script, their_features) {
+							return Err(ChannelError::Close(format!("Peer is signaling upfront_shutdown but has provided an unacceptable scriptpubkey format: {}", script)))
+						}
+						Some(script.clone())
+					}
+				},
+				// Peer is signaling upfront shutdown but don't opt-out with correct mechanism (a.k.a 0-length script). Peer looks buggy, we fail the channel
+				&None => {
+					return Err(ChannelError::Close("Peer is signaling upfront_shutdown but we don't get any script. Use 0-length script to opt-out".to_owned()));
+				}
+			}
+		} else { None };
 
 		let shutdown_scriptpubkey = if config.channel_handshake_config.commit_upfront_shutdown_pubkey {
 			match signer_provider.get_shutdown_scriptpubkey() {
 				Ok(scriptpubkey) => Some(scriptpubkey),
-				Err(_) => return Err(APIError::ChannelUnavailable { err: "Failed to get shutdown scriptpubkey".to_owned()}),
+				Err(_) => return Err(ChannelError::Close("Failed to get upfront shutdown scriptpubkey".to_owned())),
 			}
 		} else { None };
 
 		if let Some(shutdown_scriptpubkey) = &shutdown_scriptpubkey {
 			if !shutdown_scriptpubkey.is_compatible(&their_features) {
-				return Err(APIError::IncompatibleShutdownScript { script: shutdown_scriptpubkey.clone() });
+				return Err(ChannelError::Close(format!("Provided a scriptpubkey format not accepted by peer: {}", shutdown_scriptpubkey)));
 			}
 		}
 
 		let destination_script = match signer_provider.get_destination_script() {
 			Ok(script) => script,
-			Err(_) => return Err(APIError::ChannelUnavailable { err: "Failed to get destination script".to_owned()}),
+			Err(_) => return Err(ChannelError::Close("Failed to get destination script".to_owned())),
 		};
 
-		let temporary_channel_id = entropy_source.get_secure_random_bytes();
+		let mut secp_ctx = Secp256k1::new();
+		secp_ctx.seeded_randomize(&entropy_source.get_secure_random_bytes());
 
-		Ok(Self {
+		let chan = Self {
 			context: ChannelContext {
 				user_id,
 
 				config: LegacyChannelConfig {
 					options: config.channel_config.clone(),
-					announced_channel: config.channel_handshake_config.announced_channel,
+					announced_channel,
 					commit_upfront_shutdown_pubkey: config.channel_handshake_config.commit_upfront_shutdown_pubkey,
 				},
 
 				prev_config: None,
 
-				inbound_handshake_limits_override: Some(config.channel_handshake_limits.clone()),
+				inbound_handshake_limits_override: None,
 
-				channel_id: temporary_channel_id,
-				temporary_channel_id: Some(temporary_channel_id),
-				channel_state: ChannelState::OurInitSent as u32,
+				temporary_channel_id: Some(msg.temporary_channel_id),
+				channel_id: msg.temporary_channel_id,
+				channel_state: (ChannelState::OurInitSent as u32) | (ChannelState::TheirInitSent as u32),
 				announcement_sigs_state: AnnouncementSigsState::NotSent,
 				secp_ctx,
-				channel_value_satoshis,
 
 				latest_monitor_update_id: 0,
 
@@ -5619,7 +8924,7 @@ impl<Signer: WriteableEcdsaChannelSigner> OutboundV1Channel<Signer> {
 
 				cur_holder_commitment_transaction_number: INITIAL_COMMITMENT_NUMBER,
 				cur_counterparty_commitment_transaction_number: INITIAL_COMMITMENT_NUMBER,
-				value_to_self_msat,
+				value_to_self_msat: msg.push_msat,
 
 				pending_inbound_htlcs: Vec::new(),
 				pending_outbound_htlcs: Vec::new(),
@@ -5640,52 +8945,57 @@ impl<Signer: WriteableEcdsaChannelSigner> OutboundV1Channel<Signer> {
 				monitor_pending_finalized_fulfills: Vec::new(),
 
 				#[cfg(debug_assertions)]
-				holder_max_commitment_tx_output: Mutex::new((channel_value_satoshis * 1000 - push_msat, push_msat)),
+				holder_max_commitment_tx_output: Mutex::new((msg.push_msat, msg.funding_satoshis * 1000 - msg.push_msat)),
 				#[cfg(debug_assertions)]
-				counterparty_max_commitment_tx_output: Mutex::new((channel_value_satoshis * 1000 - push_msat, push_msat)),
+				counterparty_max_commitment_tx_output: Mutex::new((msg.push_msat, msg.funding_satoshis * 1000 - msg.push_msat)),
 
 				last_sent_closing_fee: None,
 				pending_counterparty_closing_signed: None,
 				closing_fee_limits: None,
 				target_closing_feerate_sats_per_kw: None,
 
-				inbound_awaiting_accept: false,
+				inbound_awaiting_accept: true,
 
 				funding_tx_confirmed_in: None,
 				funding_tx_confirmation_height: 0,
 				short_channel_id: None,
 				channel_creation_height: current_chain_height,
 
-				feerate_per_kw: commitment_feerate,
-				counterparty_dust_limit_satoshis: 0,
+				feerate_per_kw: msg.feerate_per_kw,
+				channel_value_satoshis: msg.funding_satoshis,
+				counterparty_dust_limit_satoshis: msg.dust_limit_satoshis,
 				holder_dust_limit_satoshis: MIN_CHAN_DUST_LIMIT_SATOSHIS,
-				counterparty_max_htlc_value_in_flight_msat: 0,
-				holder_max_htlc_value_in_flight_msat: get_holder_max_htlc_value_in_flight_msat(channel_value_satoshis, &config.channel_handshake_config),
-				counterparty_selected_channel_reserve_satoshis: None, // Filled in in accept_channel
+				counterparty_max_htlc_value_in_flight_msat: cmp::min(msg.max_htlc_value_in_flight_msat, msg.funding_satoshis * 1000),
+				holder_max_htlc_value_in_flight_msat: get_holder_max_htlc_value_in_flight_msat(msg.funding_satoshis, &config.channel_handshake_config),
+				counterparty_selected_channel_reserve_satoshis: Some(msg.channel_reserve_satoshis),
 				holder_selected_channel_reserve_satoshis,
-				counterparty_htlc_minimum_msat: 0,
+				counterparty_htlc_minimum_msat: msg.htlc_minimum_msat,
 				holder_htlc_minimum_msat: if config.channel_handshake_config.our_htlc_minimum_msat == 0 { 1 } else { config.channel_handshake_config.our_htlc_minimum_msat },
-				counterparty_max_accepted_htlcs: 0,
+				counterparty_max_accepted_htlcs: msg.max_accepted_htlcs,
 				holder_max_accepted_htlcs: cmp::min(config.channel_handshake_config.our_max_accepted_htlcs, MAX_HTLCS),
-				minimum_depth: None, // Filled in in accept_channel
+				minimum_depth: Some(cmp::max(config.channel_handshake_config.minimum_depth, 1)),
 
 				counterparty_forwarding_info: None,
 
 				channel_transaction_parameters: ChannelTransactionParameters {
 					holder_pubkeys: pubkeys,
 					holder_selected_contest_delay: config.channel_handshake_config.our_to_self_delay,
-					is_outbound_from_holder: true,
-					counterparty_parameters: None,
+					is_outbound_from_holder: false,
+					counterparty_parameters: Some(CounterpartyChannelTransactionParameters {
+						selected_contest_delay: msg.to_self_delay,
+						pubkeys: counterparty_pubkeys,
+					}),
 					funding_outpoint: None,
 					channel_type_features: channel_type.clone()
 				},
 				funding_transaction: None,
+				is_batch_funding: None,
 
-				counterparty_cur_commitment_point: None,
+				counterparty_cur_commitment_point: Some(msg.first_per_commitment_point),
 				counterparty_prev_commitment_point: None,
 				counterparty_node_id,
 
-				counterparty_shutdown_scriptpubkey: None,
+				counterparty_shutdown_scriptpubkey,
 
 				commitment_secrets: CounterpartyCommitmentSecrets::new(),
 
@@ -5696,179 +9006,92 @@ impl<Signer: WriteableEcdsaChannelSigner> OutboundV1Channel<Signer> {
 
 				#[cfg(any(test, fuzzing))]
 				next_local_commitment_tx_fee_info_cached: Mutex::new(None),
-				#[cfg(any(test, fuzzing))]
-				next_remote_commitment_tx_fee_info_cached: Mutex::new(None),
-
-				workaround_lnd_bug_4006: None,
-				sent_message_awaiting_response: None,
-
-				latest_inbound_scid_alias: None,
-				outbound_scid_alias,
-
-				channel_pending_event_emitted: false,
-				channel_ready_event_emitted: false,
-
-				#[cfg(any(test, fuzzing))]
-				historical_inbound_htlc_fulfills: HashSet::new(),
-
-				channel_type,
-				channel_keys_id,
-
-				blocked_monitor_updates: Vec::new(),
-			},
-			unfunded_context: UnfundedChannelContext { unfunded_channel_age_ticks: 0 }
-		})
-	}
-
-	/// If an Err is returned, it is a ChannelError::Close (for get_funding_created)
-	fn get_funding_created_signature<L: Deref>(&mut self, logger: &L) -> Result<Signature, ChannelError> where L::Target: Logger {
-		let counterparty_keys = self.context.build_remote_transaction_keys();
-		let counterparty_initial_commitment_tx = self.context.build_commitment_transaction(self.context.cur_counterparty_commitment_transaction_number, &counterparty_keys, false, false, logger).tx;
-		Ok(self.context.holder_signer.sign_counterparty_commitment(&counterparty_initial_commitment_tx, Vec::new(), &self.context.secp_ctx)
-				.map_err(|_| ChannelError::Close("Failed to get signatures for new commitment_signed".to_owned()))?.0)
-	}
+				#[cfg(any(test, fuzzing))]
+				next_remote_commitment_tx_fee_info_cached: Mutex::new(None),
 
-	/// Updates channel state with knowledge of the funding transaction's txid/index, and generates
-	/// a funding_created message for the remote peer.
-	/// Panics if called at some time other than immediately after initial handshake, if called twice,
-	/// or if called on an inbound channel.
-	/// Note that channel_id changes during this call!
-	/// Do NOT broadcast the funding transaction until after a successful funding_signed call!
-	/// If an Err is returned, it is a ChannelError::Close.
-	pub fn get_funding_created<L: Deref>(mut self, funding_transaction: Transaction, funding_txo: OutPoint, logger: &L)
-	-> Result<(Channel<Signer>, msgs::FundingCreated), (Self, ChannelError)> where L::Target: Logger {
-		if !self.context.is_outbound() {
-			panic!("Tried to create outbound funding_created message on an inbound channel!");
-		}
-		if self.context.channel_state != (ChannelState::OurInitSent as u32 | ChannelState::TheirInitSent as u32) {
-			panic!("Tried to get a funding_created messsage at a time other than immediately after initial handshake completion (or tried to get funding_created twice)");
-		}
-		if self.context.commitment_secrets.get_min_seen_secret() != (1 << 48) ||
-				self.context.cur_counterparty_commitment_transaction_number != INITIAL_COMMITMENT_NUMBER ||
-				self.context.cur_holder_commitment_transaction_number != INITIAL_COMMITMENT_NUMBER {
-			panic!("Should not have advanced channel commitment tx numbers prior to funding_created");
-		}
+				workaround_lnd_bug_4006: None,
+				sent_message_awaiting_response: None,
+				quiescence_timer_ticks: None,
+				is_holder_quiescence_initiator: None,
 
-		self.context.channel_transaction_parameters.funding_outpoint = Some(funding_txo);
-		self.context.holder_signer.provide_channel_parameters(&self.context.channel_transaction_parameters);
+				latest_inbound_scid_alias: None,
+				outbound_scid_alias,
 
-		let signature = match self.get_funding_created_signature(logger) {
-			Ok(res) => res,
-			Err(e) => {
-				log_error!(logger, "Got bad signatures: {:?}!", e);
-				self.context.channel_transaction_parameters.funding_outpoint = None;
-				return Err((self, e));
-			}
-		};
+				channel_pending_event_emitted: false,
+				channel_ready_event_emitted: false,
 
-		let temporary_channel_id = self.context.channel_id;
+				#[cfg(any(test, fuzzing))]
+				historical_inbound_htlc_fulfills: HashSet::new(),
 
-		// Now that we're past error-generating stuff, update our local state:
+				channel_type,
+				channel_keys_id,
 
-		self.context.channel_state = ChannelState::FundingCreated as u32;
-		self.context.channel_id = funding_txo.to_channel_id();
-		self.context.funding_transaction = Some(funding_transaction);
+				blocked_monitor_updates: Vec::new(),
 
-		let channel = Channel {
-			context: self.context,
+				interactive_tx_signing_session: None,
+				monitor_pending_tx_signatures: false,
+
+				pending_splice: None,
+				pending_commitment_signed_batch: Vec::new(),
+				splice_locked_pending_ack: false,
+				pending_channel_type_upgrade: None,
+			},
+			unfunded_context: UnfundedChannelContext { unfunded_channel_age_ticks: 0 }
 		};
 
-		Ok((channel, msgs::FundingCreated {
-			temporary_channel_id,
-			funding_txid: funding_txo.txid,
-			funding_output_index: funding_txo.index,
-			signature,
-			#[cfg(taproot)]
-			partial_signature_with_nonce: None,
-			#[cfg(taproot)]
-			next_local_nonce: None,
-		}))
+		Ok(chan)
 	}
 
-	fn get_initial_channel_type(config: &UserConfig, their_features: &InitFeatures) -> ChannelTypeFeatures {
-		// The default channel type (ie the first one we try) depends on whether the channel is
-		// public - if it is, we just go with `only_static_remotekey` as it's the only option
-		// available. If it's private, we first try `scid_privacy` as it provides better privacy
-		// with no other changes, and fall back to `only_static_remotekey`.
-		let mut ret = ChannelTypeFeatures::only_static_remote_key();
-		if !config.channel_handshake_config.announced_channel &&
-			config.channel_handshake_config.negotiate_scid_privacy &&
-			their_features.supports_scid_privacy() {
-			ret.set_scid_privacy_required();
-		}
-
-		// Optionally, if the user would like to negotiate the `anchors_zero_fee_htlc_tx` option, we
-		// set it now. If they don't understand it, we'll fall back to our default of
-		// `only_static_remotekey`.
-		if config.channel_handshake_config.negotiate_anchors_zero_fee_htlc_tx &&
-			their_features.supports_anchors_zero_fee_htlc_tx() {
-			ret.set_anchors_zero_fee_htlc_tx_required();
-		}
-
-		ret
+	pub fn is_awaiting_accept(&self) -> bool {
+		self.context.inbound_awaiting_accept
 	}
 
-	/// If we receive an error message, it may only be a rejection of the channel type we tried,
-	/// not of our ability to open any channel at all. Thus, on error, we should first call this
-	/// and see if we get a new `OpenChannel` message, otherwise the channel is failed.
-	pub(crate) fn maybe_handle_error_without_close<F: Deref>(
-		&mut self, chain_hash: BlockHash, fee_estimator: &LowerBoundedFeeEstimator<F>
-	) -> Result<msgs::OpenChannel, ()>
-	where
-		F::Target: FeeEstimator
-	{
-		if !self.context.is_outbound() || self.context.channel_state != ChannelState::OurInitSent as u32 { return Err(()); }
-		if self.context.channel_type == ChannelTypeFeatures::only_static_remote_key() {
-			// We've exhausted our options
-			return Err(());
-		}
-		// We support opening a few different types of channels. Try removing our additional
-		// features one by one until we've either arrived at our default or the counterparty has
-		// accepted one.
-		//
-		// Due to the order below, we may not negotiate `option_anchors_zero_fee_htlc_tx` if the
-		// counterparty doesn't support `option_scid_privacy`. Since `get_initial_channel_type`
-		// checks whether the counterparty supports every feature, this would only happen if the
-		// counterparty is advertising the feature, but rejecting channels proposing the feature for
-		// whatever reason.
-		if self.context.channel_type.supports_anchors_zero_fee_htlc_tx() {
-			self.context.channel_type.clear_anchors_zero_fee_htlc_tx();
-			self.context.feerate_per_kw = fee_estimator.bounded_sat_per_1000_weight(ConfirmationTarget::Normal);
-			assert!(!self.context.channel_transaction_parameters.channel_type_features.supports_anchors_nonzero_fee_htlc_tx());
-		} else if self.context.channel_type.supports_scid_privacy() {
-			self.context.channel_type.clear_scid_privacy();
-		} else {
-			self.context.channel_type = ChannelTypeFeatures::only_static_remote_key();
-		}
-		self.context.channel_transaction_parameters.channel_type_features = self.context.channel_type.clone();
-		Ok(self.get_open_channel(chain_hash))
+	/// Sets this channel to accepting 0conf, must be done before `get_accept_channel`
+	pub fn set_0conf(&mut self) {
+		assert!(self.context.inbound_awaiting_accept);
+		self.context.minimum_depth = Some(0);
 	}
 
-	pub fn get_open_channel(&self, chain_hash: BlockHash) -> msgs::OpenChannel {
-		if !self.context.is_outbound() {
-			panic!("Tried to open a channel for an inbound channel?");
+	/// Marks an inbound channel as accepted and generates a [`msgs::AcceptChannel`] message which
+	/// should be sent back to the counterparty node.
+	///
+	/// [`msgs::AcceptChannel`]: crate::ln::msgs::AcceptChannel
+	pub fn accept_inbound_channel(&mut self, user_id: u128) -> msgs::AcceptChannel {
+		if self.context.is_outbound() {
+			panic!("Tried to send accept_channel for an outbound channel?");
 		}
-		if self.context.channel_state != ChannelState::OurInitSent as u32 {
-			panic!("Cannot generate an open_channel after we've moved forward");
+		if self.context.channel_state != (ChannelState::OurInitSent as u32) | (ChannelState::TheirInitSent as u32) {
+			panic!("Tried to send accept_channel after channel had moved forward");
 		}
-
 		if self.context.cur_holder_commitment_transaction_number != INITIAL_COMMITMENT_NUMBER {
-			panic!("Tried to send an open_channel for a channel that has already advanced");
+			panic!("Tried to send an accept_channel for a channel that has already advanced");
+		}
+		if !self.context.inbound_awaiting_accept {
+			panic!("The inbound channel has already been accepted");
 		}
 
+		self.context.user_id = user_id;
+		self.context.inbound_awaiting_accept = false;
+
+		self.generate_accept_channel_message()
+	}
+
+	/// This function is used to explicitly generate a [`msgs::AcceptChannel`] message for an
+	/// inbound channel. If the intention is to accept an inbound channel, use
+	/// [`InboundV1Channel::accept_inbound_channel`] instead.
+	///
+	/// [`msgs::AcceptChannel`]: crate::ln::msgs::AcceptChannel
+	fn generate_accept_channel_message(&self) -> msgs::AcceptChannel {
 		let first_per_commitment_point = self.context.holder_signer.get_per_commitment_point(self.context.cur_holder_commitment_transaction_number, &self.context.secp_ctx);
 		let keys = self.context.get_holder_pubkeys();
 
-		msgs::OpenChannel {
-			chain_hash,
+		msgs::AcceptChannel {
 			temporary_channel_id: self.context.channel_id,
-			funding_satoshis: self.context.channel_value_satoshis,
-			push_msat: self.context.channel_value_satoshis * 1000 - self.context.value_to_self_msat,
 			dust_limit_satoshis: self.context.holder_dust_limit_satoshis,
 			max_htlc_value_in_flight_msat: self.context.holder_max_htlc_value_in_flight_msat,
 			channel_reserve_satoshis: self.context.holder_selected_channel_reserve_satoshis,
 			htlc_minimum_msat: self.context.holder_htlc_minimum_msat,
-			feerate_per_kw: self.context.feerate_per_kw as u32,
+			minimum_depth: self.context.minimum_depth.unwrap(),
 			to_self_delay: self.context.get_holder_selected_contest_delay(),
 			max_accepted_htlcs: self.context.holder_max_accepted_htlcs,
 			funding_pubkey: keys.funding_pubkey,
@@ -5877,690 +9100,824 @@ impl<Signer: WriteableEcdsaChannelSigner> OutboundV1Channel<Signer> {
 			delayed_payment_basepoint: keys.delayed_payment_basepoint,
 			htlc_basepoint: keys.htlc_basepoint,
 			first_per_commitment_point,
-			channel_flags: if self.context.config.announced_channel {1} else {0},
 			shutdown_scriptpubkey: Some(match &self.context.shutdown_scriptpubkey {
 				Some(script) => script.clone().into_inner(),
 				None => Builder::new().into_script(),
 			}),
 			channel_type: Some(self.context.channel_type.clone()),
+			#[cfg(taproot)]
+			next_local_nonce: None,
 		}
 	}
 
-	// Message handlers
-	pub fn accept_channel(&mut self, msg: &msgs::AcceptChannel, default_limits: &ChannelHandshakeLimits, their_features: &InitFeatures) -> Result<(), ChannelError> {
-		let peer_limits = if let Some(ref limits) = self.context.inbound_handshake_limits_override { limits } else { default_limits };
-
-		// Check sanity of message fields:
-		if !self.context.is_outbound() {
-			return Err(ChannelError::Close("Got an accept_channel message from an inbound peer".to_owned()));
-		}
-		if self.context.channel_state != ChannelState::OurInitSent as u32 {
-			return Err(ChannelError::Close("Got an accept_channel message at a strange time".to_owned()));
-		}
-		if msg.dust_limit_satoshis > 21000000 * 100000000 {
-			return Err(ChannelError::Close(format!("Peer never wants payout outputs? dust_limit_satoshis was {}", msg.dust_limit_satoshis)));
-		}
-		if msg.channel_reserve_satoshis > self.context.channel_value_satoshis {
-			return Err(ChannelError::Close(format!("Bogus channel_reserve_satoshis ({}). Must not be greater than ({})", msg.channel_reserve_satoshis, self.context.channel_value_satoshis)));
-		}
-		if msg.dust_limit_satoshis > self.context.holder_selected_channel_reserve_satoshis {
-			return Err(ChannelError::Close(format!("Dust limit ({}) is bigger than our channel reserve ({})", msg.dust_limit_satoshis, self.context.holder_selected_channel_reserve_satoshis)));
-		}
-		if msg.channel_reserve_satoshis > self.context.channel_value_satoshis - self.context.holder_selected_channel_reserve_satoshis {
-			return Err(ChannelError::Close(format!("Bogus channel_reserve_satoshis ({}). Must not be greater than channel value minus our reserve ({})",
-				msg.channel_reserve_satoshis, self.context.channel_value_satoshis - self.context.holder_selected_channel_reserve_satoshis)));
-		}
-		let full_channel_value_msat = (self.context.channel_value_satoshis - msg.channel_reserve_satoshis) * 1000;
-		if msg.htlc_minimum_msat >= full_channel_value_msat {
-			return Err(ChannelError::Close(format!("Minimum htlc value ({}) is full channel value ({})", msg.htlc_minimum_msat, full_channel_value_msat)));
-		}
-		let max_delay_acceptable = u16::min(peer_limits.their_to_self_delay, MAX_LOCAL_BREAKDOWN_TIMEOUT);
-		if msg.to_self_delay > max_delay_acceptable {
-			return Err(ChannelError::Close(format!("They wanted our payments to be delayed by a needlessly long period. Upper limit: {}. Actual: {}", max_delay_acceptable, msg.to_self_delay)));
-		}
-		if msg.max_accepted_htlcs < 1 {
-			return Err(ChannelError::Close("0 max_accepted_htlcs makes for a useless channel".to_owned()));
-		}
-		if msg.max_accepted_htlcs > MAX_HTLCS {
-			return Err(ChannelError::Close(format!("max_accepted_htlcs was {}. It must not be larger than {}", msg.max_accepted_htlcs, MAX_HTLCS)));
-		}
-
-		// Now check against optional parameters as set by config...
-		if msg.htlc_minimum_msat > peer_limits.max_htlc_minimum_msat {
-			return Err(ChannelError::Close(format!("htlc_minimum_msat ({}) is higher than the user specified limit ({})", msg.htlc_minimum_msat, peer_limits.max_htlc_minimum_msat)));
-		}
-		if msg.max_htlc_value_in_flight_msat < peer_limits.min_max_htlc_value_in_flight_msat {
-			return Err(ChannelError::Close(format!("max_htlc_value_in_flight_msat ({}) is less than the user specified limit ({})", msg.max_htlc_value_in_flight_msat, peer_limits.min_max_htlc_value_in_flight_msat)));
-		}
-		if msg.channel_reserve_satoshis > peer_limits.max_channel_reserve_satoshis {
-			return Err(ChannelError::Close(format!("channel_reserve_satoshis ({}) is higher than the user specified limit ({})", msg.channel_reserve_satoshis, peer_limits.max_channel_reserve_satoshis)));
-		}
-		if msg.max_accepted_htlcs < peer_limits.min_max_accepted_htlcs {
-			return Err(ChannelError::Close(format!("max_accepted_htlcs ({}) is less than the user specified limit ({})", msg.max_accepted_htlcs, peer_limits.min_max_accepted_htlcs)));
-		}
-		if msg.dust_limit_satoshis < MIN_CHAN_DUST_LIMIT_SATOSHIS {
-			return Err(ChannelError::Close(format!("dust_limit_satoshis ({}) is less than the implementation limit ({})", msg.dust_limit_satoshis, MIN_CHAN_DUST_LIMIT_SATOSHIS)));
-		}
-		if msg.dust_limit_satoshis > MAX_CHAN_DUST_LIMIT_SATOSHIS {
-			return Err(ChannelError::Close(format!("dust_limit_satoshis ({}) is greater than the implementation limit ({})", msg.dust_limit_satoshis, MAX_CHAN_DUST_LIMIT_SATOSHIS)));
-		}
-		if msg.minimum_depth > peer_limits.max_minimum_depth {
-			return Err(ChannelError::Close(format!("We consider the minimum depth to be unreasonably large. Expected minimum: ({}). Actual: ({})", peer_limits.max_minimum_depth, msg.minimum_depth)));
-		}
-
-		if let Some(ty) = &msg.channel_type {
-			if *ty != self.context.channel_type {
-				return Err(ChannelError::Close("Channel Type in accept_channel didn't match the one sent in open_channel.".to_owned()));
-			}
-		} else if their_features.supports_channel_type() {
-			// Assume they've accepted the channel type as they said they understand it.
-		} else {
-			let channel_type = ChannelTypeFeatures::from_init(&their_features);
-			if channel_type != ChannelTypeFeatures::only_static_remote_key() {
-				return Err(ChannelError::Close("Only static_remote_key is supported for non-negotiated channel types".to_owned()));
-			}
-			self.context.channel_type = channel_type.clone();
-			self.context.channel_transaction_parameters.channel_type_features = channel_type;
-		}
-
-		let counterparty_shutdown_scriptpubkey = if their_features.supports_upfront_shutdown_script() {
-			match &msg.shutdown_scriptpubkey {
-				&Some(ref script) => {
-					// Peer is signaling upfront_shutdown and has opt-out with a 0-length script. We don't enforce anything
-					if script.len() == 0 {
-						None
-					} else {
-						if !script::is_bolt2_compliant(&script, their_features) {
-							return Err(ChannelError::Close(format!("Peer is signaling upfront_shutdown but has provided an unacceptable scriptpubkey format: {}", script)));
-						}
-						Some(script.clone())
-					}
-				},
-				// Peer is signaling upfront shutdown but don't opt-out with correct mechanism (a.k.a 0-length script). Peer looks buggy, we fail the channel
-				&None => {
-					return Err(ChannelError::Close("Peer is signaling upfront_shutdown but we don't get any script. Use 0-length script to opt-out".to_owned()));
-				}
-			}
-		} else { None };
+	/// Enables the possibility for tests to extract a [`msgs::AcceptChannel`] message for an
+	/// inbound channel without accepting it.
+	///
+	/// [`msgs::AcceptChannel`]: crate::ln::msgs::AcceptChannel
+	#[cfg(test)]
+	pub fn get_accept_channel_message(&self) -> msgs::AcceptChannel {
+		self.generate_accept_channel_message()
+	}
 
-		self.context.counterparty_dust_limit_satoshis = msg.dust_limit_satoshis;
-		self.context.counterparty_max_htlc_value_in_flight_msat = cmp::min(msg.max_htlc_value_in_flight_msat, self.context.channel_value_satoshis * 1000);
-		self.context.counterparty_selected_channel_reserve_satoshis = Some(msg.channel_reserve_satoshis);
-		self.context.counterparty_htlc_minimum_msat = msg.htlc_minimum_msat;
-		self.context.counterparty_max_accepted_htlcs = msg.max_accepted_htlcs;
+	fn funding_created_signature<L: Deref>(&mut self, sig: &Signature, logger: &L) -> Result<(Txid, CommitmentTransaction, Signature), ChannelError> where L::Target: Logger {
+		let funding_script = self.context.get_funding_redeemscript();
 
-		if peer_limits.trust_own_funding_0conf {
-			self.context.minimum_depth = Some(msg.minimum_depth);
-		} else {
-			self.context.minimum_depth = Some(cmp::max(1, msg.minimum_depth));
+		let keys = self.context.build_holder_transaction_keys(self.context.cur_holder_commitment_transaction_number);
+		let initial_commitment_tx = self.context.build_commitment_transaction(self.context.cur_holder_commitment_transaction_number, &keys, true, false, logger).tx;
+		{
+			let trusted_tx = initial_commitment_tx.trust();
+			let initial_commitment_bitcoin_tx = trusted_tx.built_transaction();
+			let sighash = initial_commitment_bitcoin_tx.get_sighash_all(&funding_script, self.context.channel_value_satoshis);
+			// They sign the holder commitment transaction...
+			log_trace!(logger, "Checking funding_created tx signature {} by key {} against tx {} (sighash {}) with redeemscript {} for channel {}.",
+				log_bytes!(sig.serialize_compact()[..]), log_bytes!(self.context.counterparty_funding_pubkey().serialize()),
+				encode::serialize_hex(&initial_commitment_bitcoin_tx.transaction), log_bytes!(sighash[..]),
+				encode::serialize_hex(&funding_script), log_bytes!(self.context.channel_id()));
+			secp_check!(self.context.secp_ctx.verify_ecdsa(&sighash, &sig, self.context.counterparty_funding_pubkey()), "Invalid funding_created signature from peer".to_owned());
 		}
 
-		let counterparty_pubkeys = ChannelPublicKeys {
-			funding_pubkey: msg.funding_pubkey,
-			revocation_basepoint: msg.revocation_basepoint,
-			payment_point: msg.payment_point,
-			delayed_payment_basepoint: msg.delayed_payment_basepoint,
-			htlc_basepoint: msg.htlc_basepoint
-		};
-
-		self.context.channel_transaction_parameters.counterparty_parameters = Some(CounterpartyChannelTransactionParameters {
-			selected_contest_delay: msg.to_self_delay,
-			pubkeys: counterparty_pubkeys,
-		});
+		let counterparty_keys = self.context.build_remote_transaction_keys();
+		let counterparty_initial_commitment_tx = self.context.build_commitment_transaction(self.context.cur_counterparty_commitment_transaction_number, &counterparty_keys, false, false, logger).tx;
 
-		self.context.counterparty_cur_commitment_point = Some(msg.first_per_commitment_point);
-		self.context.counterparty_shutdown_scriptpubkey = counterparty_shutdown_scriptpubkey;
+		let counterparty_trusted_tx = counterparty_initial_commitment_tx.trust();
+		let counterparty_initial_bitcoin_tx = counterparty_trusted_tx.built_transaction();
+		log_trace!(logger, "Initial counterparty tx for channel {} is: txid {} tx {}",
+			log_bytes!(self.context.channel_id()), counterparty_initial_bitcoin_tx.txid, encode::serialize_hex(&counterparty_initial_bitcoin_tx.transaction));
 
-		self.context.channel_state = ChannelState::OurInitSent as u32 | ChannelState::TheirInitSent as u32;
-		self.context.inbound_handshake_limits_override = None; // We're done enforcing limits on our peer's handshake now.
+		let counterparty_signature = self.context.holder_signer.sign_counterparty_commitment(&counterparty_initial_commitment_tx, Vec::new(), &self.context.secp_ctx)
+				.map_err(|_| ChannelError::Close("Failed to get signatures for new commitment_signed".to_owned()))?.0;
 
-		Ok(())
+		// We sign "counterparty" commitment transaction, allowing them to broadcast the tx if they wish.
+		Ok((counterparty_initial_bitcoin_tx.txid, initial_commitment_tx, counterparty_signature))
 	}
-}
-
-/// A not-yet-funded inbound (from counterparty) channel using V1 channel establishment.
-pub(super) struct InboundV1Channel<Signer: ChannelSigner> {
-	pub context: ChannelContext<Signer>,
-	pub unfunded_context: UnfundedChannelContext,
-}
 
-impl<Signer: WriteableEcdsaChannelSigner> InboundV1Channel<Signer> {
-	/// Creates a new channel from a remote sides' request for one.
-	/// Assumes chain_hash has already been checked and corresponds with what we expect!
-	pub fn new<ES: Deref, SP: Deref, F: Deref, L: Deref>(
-		fee_estimator: &LowerBoundedFeeEstimator<F>, entropy_source: &ES, signer_provider: &SP,
-		counterparty_node_id: PublicKey, our_supported_features: &ChannelTypeFeatures,
-		their_features: &InitFeatures, msg: &msgs::OpenChannel, user_id: u128, config: &UserConfig,
-		current_chain_height: u32, logger: &L, outbound_scid_alias: u64
-	) -> Result<InboundV1Channel<Signer>, ChannelError>
-		where ES::Target: EntropySource,
-			  SP::Target: SignerProvider<Signer = Signer>,
-			  F::Target: FeeEstimator,
-			  L::Target: Logger,
+	pub fn funding_created<SP: Deref, L: Deref>(
+		mut self, msg: &msgs::FundingCreated, best_block: BestBlock, signer_provider: &SP, logger: &L
+	) -> Result<(Channel<Signer>, msgs::FundingSigned, ChannelMonitor<Signer>), (Self, ChannelError)>
+	where
+		SP::Target: SignerProvider<Signer = Signer>,
+		L::Target: Logger
 	{
-		let announced_channel = if (msg.channel_flags & 1) == 1 { true } else { false };
+		if self.context.is_outbound() {
+			return Err((self, ChannelError::Close("Received funding_created for an outbound channel?".to_owned())));
+		}
+		if self.context.channel_state != (ChannelState::OurInitSent as u32 | ChannelState::TheirInitSent as u32) {
+			// BOLT 2 says that if we disconnect before we send funding_signed we SHOULD NOT
+			// remember the channel, so it's safe to just send an error_message here and drop the
+			// channel.
+			return Err((self, ChannelError::Close("Received funding_created after we got the channel!".to_owned())));
+		}
+		if self.context.inbound_awaiting_accept {
+			return Err((self, ChannelError::Close("FundingCreated message received before the channel was accepted".to_owned())));
+		}
+		if self.context.commitment_secrets.get_min_seen_secret() != (1 << 48) ||
+				self.context.cur_counterparty_commitment_transaction_number != INITIAL_COMMITMENT_NUMBER ||
+				self.context.cur_holder_commitment_transaction_number != INITIAL_COMMITMENT_NUMBER {
+			panic!("Should not have advanced channel commitment tx numbers prior to funding_created");
+		}
 
-		// First check the channel type is known, failing before we do anything else if we don't
-		// support this channel type.
-		let channel_type = if let Some(channel_type) = &msg.channel_type {
-			if channel_type.supports_any_optional_bits() {
-				return Err(ChannelError::Close("Channel Type field contained optional bits - this is not allowed".to_owned()));
-			}
+		let funding_txo = OutPoint { txid: msg.funding_txid, index: msg.funding_output_index };
+		self.context.channel_transaction_parameters.funding_outpoint = Some(funding_txo);
+		// This is an externally observable change before we finish all our checks.  In particular
+		// funding_created_signature may fail.
+		self.context.holder_signer.provide_channel_parameters(&self.context.channel_transaction_parameters);
 
-			// We only support the channel types defined by the `ChannelManager` in
-			// `provided_channel_type_features`. The channel type must always support
-			// `static_remote_key`.
-			if !channel_type.requires_static_remote_key() {
-				return Err(ChannelError::Close("Channel Type was not understood - we require static remote key".to_owned()));
-			}
-			// Make sure we support all of the features behind the channel type.
-			if !channel_type.is_subset(our_supported_features) {
-				return Err(ChannelError::Close("Channel Type contains unsupported features".to_owned()));
-			}
-			if channel_type.requires_scid_privacy() && announced_channel {
-				return Err(ChannelError::Close("SCID Alias/Privacy Channel Type cannot be set on a public channel".to_owned()));
-			}
-			channel_type.clone()
-		} else {
-			let channel_type = ChannelTypeFeatures::from_init(&their_features);
-			if channel_type != ChannelTypeFeatures::only_static_remote_key() {
-				return Err(ChannelError::Close("Only static_remote_key is supported for non-negotiated channel types".to_owned()));
+		let (counterparty_initial_commitment_txid, initial_commitment_tx, signature) = match self.funding_created_signature(&msg.signature, logger) {
+			Ok(res) => res,
+			Err(ChannelError::Close(e)) => {
+				self.context.channel_transaction_parameters.funding_outpoint = None;
+				return Err((self, ChannelError::Close(e)));
+			},
+			Err(e) => {
+				// The only error we know how to handle is ChannelError::Close, so we fall over here
+				// to make sure we don't continue with an inconsistent state.
+				panic!("unexpected error type from funding_created_signature {:?}", e);
 			}
-			channel_type
 		};
 
-		let channel_keys_id = signer_provider.generate_channel_keys_id(true, msg.funding_satoshis, user_id);
-		let holder_signer = signer_provider.derive_channel_signer(msg.funding_satoshis, channel_keys_id);
-		let pubkeys = holder_signer.pubkeys().clone();
-		let counterparty_pubkeys = ChannelPublicKeys {
-			funding_pubkey: msg.funding_pubkey,
-			revocation_basepoint: msg.revocation_basepoint,
-			payment_point: msg.payment_point,
-			delayed_payment_basepoint: msg.delayed_payment_basepoint,
-			htlc_basepoint: msg.htlc_basepoint
-		};
+		let holder_commitment_tx = HolderCommitmentTransaction::new(
+			initial_commitment_tx,
+			msg.signature,
+			Vec::new(),
+			&self.context.get_holder_pubkeys().funding_pubkey,
+			self.context.counterparty_funding_pubkey()
+		);
 
-		if config.channel_handshake_config.our_to_self_delay < BREAKDOWN_TIMEOUT {
-			return Err(ChannelError::Close(format!("Configured with an unreasonable our_to_self_delay ({}) putting user funds at risks. It must be greater than {}", config.channel_handshake_config.our_to_self_delay, BREAKDOWN_TIMEOUT)));
+		if let Err(_) = self.context.holder_signer.validate_holder_commitment(&holder_commitment_tx, Vec::new()) {
+			return Err((self, ChannelError::Close("Failed to validate our commitment".to_owned())));
 		}
 
-		// Check sanity of message fields:
-		if msg.funding_satoshis > config.channel_handshake_limits.max_funding_satoshis {
-			return Err(ChannelError::Close(format!("Per our config, funding must be at most {}. It was {}", config.channel_handshake_limits.max_funding_satoshis, msg.funding_satoshis)));
-		}
-		if msg.funding_satoshis >= TOTAL_BITCOIN_SUPPLY_SATOSHIS {
-			return Err(ChannelError::Close(format!("Funding must be smaller than the total bitcoin supply. It was {}", msg.funding_satoshis)));
-		}
-		if msg.channel_reserve_satoshis > msg.funding_satoshis {
-			return Err(ChannelError::Close(format!("Bogus channel_reserve_satoshis ({}). Must be not greater than funding_satoshis: {}", msg.channel_reserve_satoshis, msg.funding_satoshis)));
-		}
-		let full_channel_value_msat = (msg.funding_satoshis - msg.channel_reserve_satoshis) * 1000;
-		if msg.push_msat > full_channel_value_msat {
-			return Err(ChannelError::Close(format!("push_msat {} was larger than channel amount minus reserve ({})", msg.push_msat, full_channel_value_msat)));
-		}
-		if msg.dust_limit_satoshis > msg.funding_satoshis {
-			return Err(ChannelError::Close(format!("dust_limit_satoshis {} was larger than funding_satoshis {}. Peer never wants payout outputs?", msg.dust_limit_satoshis, msg.funding_satoshis)));
-		}
-		if msg.htlc_minimum_msat >= full_channel_value_msat {
-			return Err(ChannelError::Close(format!("Minimum htlc value ({}) was larger than full channel value ({})", msg.htlc_minimum_msat, full_channel_value_msat)));
-		}
-		Channel::<Signer>::check_remote_fee(&channel_type, fee_estimator, msg.feerate_per_kw, None, logger)?;
+		// Now that we're past error-generating stuff, update our local state:
 
-		let max_counterparty_selected_contest_delay = u16::min(config.channel_handshake_limits.their_to_self_delay, MAX_LOCAL_BREAKDOWN_TIMEOUT);
-		if msg.to_self_delay > max_counterparty_selected_contest_delay {
-			return Err(ChannelError::Close(format!("They wanted our payments to be delayed by a needlessly long period. Upper limit: {}. Actual: {}", max_counterparty_selected_contest_delay, msg.to_self_delay)));
-		}
-		if msg.max_accepted_htlcs < 1 {
-			return Err(ChannelError::Close("0 max_accepted_htlcs makes for a useless channel".to_owned()));
-		}
-		if msg.max_accepted_htlcs > MAX_HTLCS {
-			return Err(ChannelError::Close(format!("max_accepted_htlcs was {}. It must not be larger than {}", msg.max_accepted_htlcs, MAX_HTLCS)));
-		}
+		let funding_redeemscript = self.context.get_funding_redeemscript();
+		let funding_txo_script = funding_redeemscript.to_v0_p2wsh();
+		let obscure_factor = get_commitment_transaction_number_obscure_factor(&self.context.get_holder_pubkeys().payment_point, &self.context.get_counterparty_pubkeys().payment_point, self.context.is_outbound());
+		let shutdown_script = self.context.shutdown_scriptpubkey.clone().map(|script| script.into_inner());
+		let mut monitor_signer = signer_provider.derive_channel_signer(self.context.channel_value_satoshis, self.context.channel_keys_id);
+		monitor_signer.provide_channel_parameters(&self.context.channel_transaction_parameters);
+		let channel_monitor = ChannelMonitor::new(self.context.secp_ctx.clone(), monitor_signer,
+		                                          shutdown_script, self.context.get_holder_selected_contest_delay(),
+		                                          &self.context.destination_script, (funding_txo, funding_txo_script.clone()),
+		                                          &self.context.channel_transaction_parameters,
+		                                          funding_redeemscript.clone(), self.context.channel_value_satoshis,
+		                                          obscure_factor,
+		                                          holder_commitment_tx, best_block, self.context.counterparty_node_id);
 
-		// Now check against optional parameters as set by config...
-		if msg.funding_satoshis < config.channel_handshake_limits.min_funding_satoshis {
-			return Err(ChannelError::Close(format!("Funding satoshis ({}) is less than the user specified limit ({})", msg.funding_satoshis, config.channel_handshake_limits.min_funding_satoshis)));
-		}
-		if msg.htlc_minimum_msat > config.channel_handshake_limits.max_htlc_minimum_msat {
-			return Err(ChannelError::Close(format!("htlc_minimum_msat ({}) is higher than the user specified limit ({})", msg.htlc_minimum_msat,  config.channel_handshake_limits.max_htlc_minimum_msat)));
-		}
-		if msg.max_htlc_value_in_flight_msat < config.channel_handshake_limits.min_max_htlc_value_in_flight_msat {
-			return Err(ChannelError::Close(format!("max_htlc_value_in_flight_msat ({}) is less than the user specified limit ({})", msg.max_htlc_value_in_flight_msat, config.channel_handshake_limits.min_max_htlc_value_in_flight_msat)));
-		}
-		if msg.channel_reserve_satoshis > config.channel_handshake_limits.max_channel_reserve_satoshis {
-			return Err(ChannelError::Close(format!("channel_reserve_satoshis ({}) is higher than the user specified limit ({})", msg.channel_reserve_satoshis, config.channel_handshake_limits.max_channel_reserve_satoshis)));
-		}
-		if msg.max_accepted_htlcs < config.channel_handshake_limits.min_max_accepted_htlcs {
-			return Err(ChannelError::Close(format!("max_accepted_htlcs ({}) is less than the user specified limit ({})", msg.max_accepted_htlcs, config.channel_handshake_limits.min_max_accepted_htlcs)));
-		}
-		if msg.dust_limit_satoshis < MIN_CHAN_DUST_LIMIT_SATOSHIS {
-			return Err(ChannelError::Close(format!("dust_limit_satoshis ({}) is less than the implementation limit ({})", msg.dust_limit_satoshis, MIN_CHAN_DUST_LIMIT_SATOSHIS)));
-		}
-		if msg.dust_limit_satoshis >  MAX_CHAN_DUST_LIMIT_SATOSHIS {
-			return Err(ChannelError::Close(format!("dust_limit_satoshis ({}) is greater than the implementation limit ({})", msg.dust_limit_satoshis, MAX_CHAN_DUST_LIMIT_SATOSHIS)));
-		}
+		channel_monitor.provide_latest_counterparty_commitment_tx(counterparty_initial_commitment_txid, Vec::new(), self.context.cur_counterparty_commitment_transaction_number, self.context.counterparty_cur_commitment_point.unwrap(), logger);
 
-		// Convert things into internal flags and prep our state:
+		self.context.channel_state = ChannelState::FundingSent as u32;
+		self.context.channel_id = funding_txo.to_channel_id();
+		self.context.cur_counterparty_commitment_transaction_number -= 1;
+		self.context.cur_holder_commitment_transaction_number -= 1;
 
-		if config.channel_handshake_limits.force_announced_channel_preference {
-			if config.channel_handshake_config.announced_channel != announced_channel {
-				return Err(ChannelError::Close("Peer tried to open channel but their announcement preference is different from ours".to_owned()));
-			}
-		}
+		log_info!(logger, "Generated funding_signed for peer for channel {}", log_bytes!(self.context.channel_id()));
+
+		// Promote the channel to a full-fledged one now that we have updated the state and have a
+		// `ChannelMonitor`.
+		let mut channel = Channel {
+			context: self.context,
+			interactive_tx_constructor: None,
+		};
+		let channel_id = channel.context.channel_id.clone();
+		let need_channel_ready = channel.check_get_channel_ready(0).is_some();
+		channel.monitor_updating_paused(false, false, need_channel_ready, Vec::new(), Vec::new(), Vec::new());
+
+		Ok((channel, msgs::FundingSigned {
+			channel_id,
+			signature,
+			#[cfg(taproot)]
+			partial_signature_with_nonce: None,
+		}, channel_monitor))
+	}
+}
+
+/// Contains all state specific to channels using V2 channel establishment which have yet to
+/// negotiate their funding transaction.
+pub(super) struct DualFundingChannelContext {
+	/// The amount in satoshis we will be contributing to the channel.
+	pub our_funding_satoshis: u64,
+	/// The amount in satoshis our counterparty will be contributing to the channel.
+	pub their_funding_satoshis: u64,
+	/// The funding transaction locktime suggested by the initiator. If set by us, it is always set
+	/// to the current block height to align incentives against fee-sniping.
+	pub funding_tx_locktime: u32,
+	/// The feerate set by the initiator to be used for the funding transaction.
+	pub funding_feerate_sat_per_1000_weight: u32,
+	/// The inputs we will be contributing to the funding transaction, along with the transactions
+	/// they spend.
+	pub our_funding_inputs: Vec<(TxIn, TransactionU16LenLimited)>,
+}
+
+/// Tracks a splice of a funded channel, from its initiation until one of its candidate funding
+/// transactions has been locked by both parties, at which point it replaces the channel's current
+/// funding.
+///
+/// Only one splice may be pending at a time, but the channel remains usable while it is: every
+/// commitment transaction is signed for the current funding output as well as for the funding
+/// output of each candidate funding transaction, as any of them may confirm. Its initiator may
+/// replace the latest candidate through RBF, adding a candidate with the same contributions.
+pub(super) struct PendingSplice<Signer: ChannelSigner> {
+	/// Whether we initiated the splice.
+	is_initiator: bool,
+	/// The amount in satoshis we are adding to (or, if negative, removing from) the channel.
+	our_funding_contribution_satoshis: i64,
+	/// The amount in satoshis our counterparty is adding to (or removing from) the channel.
+	their_funding_contribution_satoshis: i64,
+	/// A new funding transaction we want to negotiate, either for the splice itself or to replace
+	/// the latest candidate, once the channel is quiescent with us as the initiator.
+	pending_request: Option<SpliceRequest>,
+	/// The candidate funding transactions, in the order they were negotiated. Only the last one
+	/// may still be under negotiation or awaiting signatures.
+	candidates: Vec<SpliceCandidate<Signer>>,
+	/// The candidate funding transaction we sent `splice_locked` for, if any.
+	sent_splice_locked: Option<Txid>,
+	/// The candidate funding transaction our counterparty sent `splice_locked` for, if any.
+	received_splice_locked: Option<Txid>,
+}
+
+/// A new funding transaction for a [`PendingSplice`] we initiate, which we have yet to begin
+/// negotiating.
+struct SpliceRequest {
+	/// The feerate to be used for the new funding transaction.
+	funding_feerate_sat_per_1000_weight: u32,
+	/// The locktime to be used for the new funding transaction.
+	funding_tx_locktime: u32,
+	/// The inputs we will be contributing to the new funding transaction, along with the
+	/// transactions they spend.
+	our_funding_inputs: Vec<(TxIn, TransactionU16LenLimited)>,
+	/// Whether we sent our `splice` or `tx_init_rbf` and are waiting on our counterparty's reply.
+	proposed: bool,
+}
+
+/// A candidate funding transaction of a [`PendingSplice`].
+struct SpliceCandidate<Signer: ChannelSigner> {
+	/// The feerate set by the initiator to be used for the funding transaction.
+	funding_feerate_sat_per_1000_weight: u32,
+	/// Our signer for the candidate's funding output.
+	holder_signer: Signer,
+	/// The candidate's funding output, once its funding transaction has been negotiated.
+	funding_txo: Option<OutPoint>,
+	/// Tracks the exchange of `tx_signatures` for the funding transaction, once it has been
+	/// negotiated.
+	signing_session: Option<InteractiveTxSigningSession>,
+	/// Whether we handed a [`ChannelMonitor`] for the funding output to the `ChannelManager`.
+	monitor_created: bool,
+	funding_tx_confirmed_in: Option<BlockHash>,
+	funding_tx_confirmation_height: u32,
+	short_channel_id: Option<u64>,
+	/// A confirmed transaction double-spending the funding transaction, if any, after which the
+	/// candidate can never lock.
+	conflicting_txid: Option<Txid>,
+	conflicting_tx_confirmed_in: Option<BlockHash>,
+	conflicting_tx_confirmation_height: u32,
+}
 
-		let holder_selected_channel_reserve_satoshis = get_holder_selected_channel_reserve_satoshis(msg.funding_satoshis, config);
-		if holder_selected_channel_reserve_satoshis < MIN_CHAN_DUST_LIMIT_SATOSHIS {
-			// Protocol level safety check in place, although it should never happen because
-			// of `MIN_THEIR_CHAN_RESERVE_SATOSHIS`
-			return Err(ChannelError::Close(format!("Suitable channel reserve not found. remote_channel_reserve was ({}). dust_limit_satoshis is ({}).", holder_selected_channel_reserve_satoshis, MIN_CHAN_DUST_LIMIT_SATOSHIS)));
-		}
-		if holder_selected_channel_reserve_satoshis * 1000 >= full_channel_value_msat {
-			return Err(ChannelError::Close(format!("Suitable channel reserve not found. remote_channel_reserve was ({})msats. Channel value is ({} - {})msats.", holder_selected_channel_reserve_satoshis * 1000, full_channel_value_msat, msg.push_msat)));
-		}
-		if msg.channel_reserve_satoshis < MIN_CHAN_DUST_LIMIT_SATOSHIS {
-			log_debug!(logger, "channel_reserve_satoshis ({}) is smaller than our dust limit ({}). We can broadcast stale states without any risk, implying this channel is very insecure for our counterparty.",
-				msg.channel_reserve_satoshis, MIN_CHAN_DUST_LIMIT_SATOSHIS);
-		}
-		if holder_selected_channel_reserve_satoshis < msg.dust_limit_satoshis {
-			return Err(ChannelError::Close(format!("Dust limit ({}) too high for the channel reserve we require the remote to keep ({})", msg.dust_limit_satoshis, holder_selected_channel_reserve_satoshis)));
+impl<Signer: ChannelSigner> SpliceCandidate<Signer> {
+	fn new(funding_feerate_sat_per_1000_weight: u32, holder_signer: Signer) -> Self {
+		Self {
+			funding_feerate_sat_per_1000_weight,
+			holder_signer,
+			funding_txo: None,
+			signing_session: None,
+			monitor_created: false,
+			funding_tx_confirmed_in: None,
+			funding_tx_confirmation_height: 0,
+			short_channel_id: None,
+			conflicting_txid: None,
+			conflicting_tx_confirmed_in: None,
+			conflicting_tx_confirmation_height: 0,
 		}
+	}
 
-		// check if the funder's amount for the initial commitment tx is sufficient
-		// for full fee payment plus a few HTLCs to ensure the channel will be useful.
-		let funders_amount_msat = msg.funding_satoshis * 1000 - msg.push_msat;
-		let commitment_tx_fee = commit_tx_fee_msat(msg.feerate_per_kw, MIN_AFFORDABLE_HTLC_COUNT, &channel_type) / 1000;
-		if funders_amount_msat / 1000 < commitment_tx_fee {
-			return Err(ChannelError::Close(format!("Funding amount ({} sats) can't even pay fee for initial commitment transaction fee of {} sats.", funders_amount_msat / 1000, commitment_tx_fee)));
-		}
+	/// Returns true if the candidate's funding transaction is fully signed by both parties.
+	fn is_signed(&self) -> bool {
+		self.signing_session.as_ref().map_or(false, |session|
+			session.has_holder_tx_signatures() && session.has_received_tx_signatures())
+	}
+}
 
-		let to_remote_satoshis = funders_amount_msat / 1000 - commitment_tx_fee;
-		// While it's reasonable for us to not meet the channel reserve initially (if they don't
-		// want to push much to us), our counterparty should always have more than our reserve.
-		if to_remote_satoshis < holder_selected_channel_reserve_satoshis {
-			return Err(ChannelError::Close("Insufficient funding amount for initial reserve".to_owned()));
-		}
+impl<Signer: ChannelSigner> PendingSplice<Signer> {
+	/// Returns the candidate whose funding transaction is still being negotiated or signed, if any.
+	fn unsigned_candidate(&self) -> Option<&SpliceCandidate<Signer>> {
+		self.candidates.last().filter(|candidate| !candidate.is_signed())
+	}
 
-		let counterparty_shutdown_scriptpubkey = if their_features.supports_upfront_shutdown_script() {
-			match &msg.shutdown_scriptpubkey {
-				&Some(ref script) => {
-					// Peer is signaling upfront_shutdown and has opt-out with a 0-length script. We don't enforce anything
-					if script.len() == 0 {
-						None
-					} else {
-						if !script::is_bolt2_compliant(&script, their_features) {
-							return Err(ChannelError::Close(format!("Peer is signaling upfront_shutdown but has provided an unacceptable scriptpubkey format: {}", script)))
-						}
-						Some(script.clone())
-					}
-				},
-				// Peer is signaling upfront shutdown but don't opt-out with correct mechanism (a.k.a 0-length script). Peer looks buggy, we fail the channel
-				&None => {
-					return Err(ChannelError::Close("Peer is signaling upfront_shutdown but we don't get any script. Use 0-length script to opt-out".to_owned()));
-				}
-			}
-		} else { None };
+	fn candidate_index(&self, txid: &Txid) -> Option<usize> {
+		self.candidates.iter().position(|candidate| candidate.funding_txo.map(|txo| txo.txid) == Some(*txid))
+	}
+}
 
-		let shutdown_scriptpubkey = if config.channel_handshake_config.commit_upfront_shutdown_pubkey {
-			match signer_provider.get_shutdown_scriptpubkey() {
-				Ok(scriptpubkey) => Some(scriptpubkey),
-				Err(_) => return Err(ChannelError::Close("Failed to get upfront shutdown scriptpubkey".to_owned())),
-			}
-		} else { None };
+/// The channel value, our balance and the reserves of either the current funding of a channel or
+/// that of a pending splice.
+struct FundingBalances {
+	channel_value_satoshis: u64,
+	value_to_self_msat: u64,
+	holder_selected_channel_reserve_satoshis: u64,
+	counterparty_selected_channel_reserve_satoshis: Option<u64>,
+}
 
-		if let Some(shutdown_scriptpubkey) = &shutdown_scriptpubkey {
-			if !shutdown_scriptpubkey.is_compatible(&their_features) {
-				return Err(ChannelError::Close(format!("Provided a scriptpubkey format not accepted by peer: {}", shutdown_scriptpubkey)));
-			}
-		}
+/// The persisted state of a [`PendingSplice`]. Candidates still being negotiated, along with any
+/// request we have yet to negotiate, are simply abandoned on restart, much like after a
+/// disconnection. Signers are re-derived upon deserialization.
+struct PendingSpliceState {
+	is_initiator: bool,
+	our_funding_contribution_satoshis: i64,
+	their_funding_contribution_satoshis: i64,
+	candidates: Vec<SpliceCandidateState>,
+	sent_splice_locked: Option<Txid>,
+	received_splice_locked: Option<Txid>,
+}
 
-		let destination_script = match signer_provider.get_destination_script() {
-			Ok(script) => script,
-			Err(_) => return Err(ChannelError::Close("Failed to get destination script".to_owned())),
-		};
+impl_writeable_tlv_based!(PendingSpliceState, {
+	(0, is_initiator, required),
+	(2, our_funding_contribution_satoshis, required),
+	(4, their_funding_contribution_satoshis, required),
+	(6, candidates, required_vec),
+	(8, sent_splice_locked, option),
+	(10, received_splice_locked, option),
+});
 
-		let mut secp_ctx = Secp256k1::new();
-		secp_ctx.seeded_randomize(&entropy_source.get_secure_random_bytes());
+struct SpliceCandidateState {
+	funding_feerate_sat_per_1000_weight: u32,
+	funding_txo: OutPoint,
+	signing_session: InteractiveTxSigningSession,
+	monitor_created: bool,
+	funding_tx_confirmed_in: Option<BlockHash>,
+	funding_tx_confirmation_height: u32,
+	short_channel_id: Option<u64>,
+	conflicting_txid: Option<Txid>,
+	conflicting_tx_confirmed_in: Option<BlockHash>,
+	conflicting_tx_confirmation_height: u32,
+}
 
-		let chan = Self {
-			context: ChannelContext {
-				user_id,
+impl_writeable_tlv_based!(SpliceCandidateState, {
+	(0, funding_feerate_sat_per_1000_weight, required),
+	(2, funding_txo, required),
+	(4, signing_session, required),
+	(6, monitor_created, required),
+	(8, funding_tx_confirmed_in, option),
+	(10, funding_tx_confirmation_height, required),
+	(12, short_channel_id, option),
+	(14, conflicting_txid, option),
+	(16, conflicting_tx_confirmed_in, option),
+	(18, conflicting_tx_confirmation_height, required),
+});
 
-				config: LegacyChannelConfig {
-					options: config.channel_config.clone(),
-					announced_channel,
-					commit_upfront_shutdown_pubkey: config.channel_handshake_config.commit_upfront_shutdown_pubkey,
-				},
+/// The progress of an upgrade of a channel's type, see [`PendingChannelTypeUpgrade`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum ChannelTypeUpgradeState {
+	/// We requested the upgrade and are waiting on the channel to become quiescent, with us as the
+	/// initiator, to propose it.
+	Requested,
+	/// We sent our `dyn_propose` and are waiting on our counterparty's `dyn_ack` or `dyn_reject`.
+	Proposed,
+	/// We accepted our counterparty's `dyn_propose` and are waiting on their `commitment_signed`
+	/// for the first commitment transaction using the new channel type.
+	Accepted,
+	/// Our counterparty accepted our `dyn_propose` and we switched over to the new channel type,
+	/// but are still waiting on their `commitment_signed` for a commitment transaction using it.
+	AwaitingCommitment,
+}
 
-				prev_config: None,
+impl_writeable_tlv_based_enum!(ChannelTypeUpgradeState,
+	(0, Requested) => {},
+	(2, Proposed) => {},
+	(4, Accepted) => {},
+	(6, AwaitingCommitment) => {}, ;
+);
 
-				inbound_handshake_limits_override: None,
+/// An upgrade of a channel's type, e.g. to anchor outputs, negotiated through `dyn_propose` while
+/// the channel is quiescent.
+pub(super) struct PendingChannelTypeUpgrade<Signer: ChannelSigner> {
+	/// The channel type we're upgrading to.
+	channel_type: ChannelTypeFeatures,
+	state: ChannelTypeUpgradeState,
+	/// Our signer for the new channel type, derived once we accept the upgrade, or, once we switched
+	/// over to the new channel type as the initiator, our signer for the previous one.
+	holder_signer: Option<Signer>,
+}
 
-				temporary_channel_id: Some(msg.temporary_channel_id),
-				channel_id: msg.temporary_channel_id,
-				channel_state: (ChannelState::OurInitSent as u32) | (ChannelState::TheirInitSent as u32),
-				announcement_sigs_state: AnnouncementSigsState::NotSent,
-				secp_ctx,
+/// The parts of a [`PendingChannelTypeUpgrade`] which are persisted. The signer is re-derived upon
+/// deserialization.
+struct PendingChannelTypeUpgradeState {
+	channel_type: ChannelTypeFeatures,
+	state: ChannelTypeUpgradeState,
+}
 
-				latest_monitor_update_id: 0,
+impl_writeable_tlv_based!(PendingChannelTypeUpgradeState, {
+	(0, channel_type, required),
+	(2, state, required),
+});
 
-				holder_signer,
-				shutdown_scriptpubkey,
-				destination_script,
+/// Message handling for the interactive construction of the funding transaction of channels using
+/// V2 channel establishment, common to both inbound and outbound channels.
+pub(super) trait InteractivelyFunded {
+	fn interactive_tx_constructor_mut(&mut self) -> &mut Option<InteractiveTxConstructor>;
 
-				cur_holder_commitment_transaction_number: INITIAL_COMMITMENT_NUMBER,
-				cur_counterparty_commitment_transaction_number: INITIAL_COMMITMENT_NUMBER,
-				value_to_self_msat: msg.push_msat,
+	fn tx_add_input(&mut self, msg: &msgs::TxAddInput) -> Result<InteractiveTxMessageSend, AbortReason> {
+		match self.interactive_tx_constructor_mut() {
+			Some(ref mut tx_constructor) => tx_constructor.handle_tx_add_input(msg),
+			None => Err(AbortReason::UnexpectedCounterpartyMessage),
+		}
+	}
 
-				pending_inbound_htlcs: Vec::new(),
-				pending_outbound_htlcs: Vec::new(),
-				holding_cell_htlc_updates: Vec::new(),
-				pending_update_fee: None,
-				holding_cell_update_fee: None,
-				next_holder_htlc_id: 0,
-				next_counterparty_htlc_id: 0,
-				update_time_counter: 1,
+	fn tx_remove_input(&mut self, msg: &msgs::TxRemoveInput) -> Result<InteractiveTxMessageSend, AbortReason> {
+		match self.interactive_tx_constructor_mut() {
+			Some(ref mut tx_constructor) => tx_constructor.handle_tx_remove_input(msg),
+			None => Err(AbortReason::UnexpectedCounterpartyMessage),
+		}
+	}
 
-				resend_order: RAACommitmentOrder::CommitmentFirst,
+	fn tx_add_output(&mut self, msg: &msgs::TxAddOutput) -> Result<InteractiveTxMessageSend, AbortReason> {
+		match self.interactive_tx_constructor_mut() {
+			Some(ref mut tx_constructor) => tx_constructor.handle_tx_add_output(msg),
+			None => Err(AbortReason::UnexpectedCounterpartyMessage),
+		}
+	}
 
-				monitor_pending_channel_ready: false,
-				monitor_pending_revoke_and_ack: false,
-				monitor_pending_commitment_signed: false,
-				monitor_pending_forwards: Vec::new(),
-				monitor_pending_failures: Vec::new(),
-				monitor_pending_finalized_fulfills: Vec::new(),
+	fn tx_remove_output(&mut self, msg: &msgs::TxRemoveOutput) -> Result<InteractiveTxMessageSend, AbortReason> {
+		match self.interactive_tx_constructor_mut() {
+			Some(ref mut tx_constructor) => tx_constructor.handle_tx_remove_output(msg),
+			None => Err(AbortReason::UnexpectedCounterpartyMessage),
+		}
+	}
 
-				#[cfg(debug_assertions)]
-				holder_max_commitment_tx_output: Mutex::new((msg.push_msat, msg.funding_satoshis * 1000 - msg.push_msat)),
-				#[cfg(debug_assertions)]
-				counterparty_max_commitment_tx_output: Mutex::new((msg.push_msat, msg.funding_satoshis * 1000 - msg.push_msat)),
+	/// Handles a `tx_complete`, returning our response, if any, and the negotiated funding
+	/// transaction once both parties have sent `tx_complete`.
+	fn tx_complete(&mut self, msg: &msgs::TxComplete)
+	-> Result<(Option<InteractiveTxMessageSend>, Option<ConstructedTransaction>), AbortReason> {
+		let res = match self.interactive_tx_constructor_mut() {
+			Some(ref mut tx_constructor) => tx_constructor.handle_tx_complete(msg)?,
+			None => return Err(AbortReason::UnexpectedCounterpartyMessage),
+		};
+		if res.1.is_some() {
+			// We're done negotiating, drop the constructor.
+			*self.interactive_tx_constructor_mut() = None;
+		}
+		Ok(res)
+	}
+}
 
-				last_sent_closing_fee: None,
-				pending_counterparty_closing_signed: None,
-				closing_fee_limits: None,
-				target_closing_feerate_sats_per_kw: None,
+/// A not-yet-funded outbound (from holder) channel using V2 channel establishment.
+pub(super) struct OutboundV2Channel<Signer: ChannelSigner> {
+	pub context: ChannelContext<Signer>,
+	pub unfunded_context: UnfundedChannelContext,
+	pub dual_funding_context: DualFundingChannelContext,
+	interactive_tx_constructor: Option<InteractiveTxConstructor>,
+}
 
-				inbound_awaiting_accept: true,
+impl<Signer: WriteableEcdsaChannelSigner> OutboundV2Channel<Signer> {
+	pub fn new<ES: Deref, SP: Deref, F: Deref>(
+		fee_estimator: &LowerBoundedFeeEstimator<F>, entropy_source: &ES, signer_provider: &SP,
+		counterparty_node_id: PublicKey, their_features: &InitFeatures, funding_satoshis: u64,
+		funding_inputs: Vec<(TxIn, TransactionU16LenLimited)>, user_id: u128, config: &UserConfig,
+		current_chain_height: u32, outbound_scid_alias: u64
+	) -> Result<OutboundV2Channel<Signer>, APIError>
+	where ES::Target: EntropySource,
+	      SP::Target: SignerProvider<Signer = Signer>,
+	      F::Target: FeeEstimator,
+	{
+		let OutboundV1Channel { mut context, unfunded_context } = OutboundV1Channel::new(
+			fee_estimator, entropy_source, signer_provider, counterparty_node_id, their_features,
+			funding_satoshis, 0, user_id, config, current_chain_height, outbound_scid_alias)?;
 
-				funding_tx_confirmed_in: None,
-				funding_tx_confirmation_height: 0,
-				short_channel_id: None,
-				channel_creation_height: current_chain_height,
+		let funding_feerate_sat_per_1000_weight = fee_estimator.bounded_sat_per_1000_weight(ConfirmationTarget::Normal);
 
-				feerate_per_kw: msg.feerate_per_kw,
-				channel_value_satoshis: msg.funding_satoshis,
-				counterparty_dust_limit_satoshis: msg.dust_limit_satoshis,
-				holder_dust_limit_satoshis: MIN_CHAN_DUST_LIMIT_SATOSHIS,
-				counterparty_max_htlc_value_in_flight_msat: cmp::min(msg.max_htlc_value_in_flight_msat, msg.funding_satoshis * 1000),
-				holder_max_htlc_value_in_flight_msat: get_holder_max_htlc_value_in_flight_msat(msg.funding_satoshis, &config.channel_handshake_config),
-				counterparty_selected_channel_reserve_satoshis: Some(msg.channel_reserve_satoshis),
-				holder_selected_channel_reserve_satoshis,
-				counterparty_htlc_minimum_msat: msg.htlc_minimum_msat,
-				holder_htlc_minimum_msat: if config.channel_handshake_config.our_htlc_minimum_msat == 0 { 1 } else { config.channel_handshake_config.our_htlc_minimum_msat },
-				counterparty_max_accepted_htlcs: msg.max_accepted_htlcs,
-				holder_max_accepted_htlcs: cmp::min(config.channel_handshake_config.our_max_accepted_htlcs, MAX_HTLCS),
-				minimum_depth: Some(cmp::max(config.channel_handshake_config.minimum_depth, 1)),
+		// Make sure our inputs can cover our contribution before we go any further. The actual
+		// funding output script isn't known yet, but any P2WSH script has the same weight.
+		let placeholder_funding_output = TxOut {
+			value: funding_satoshis,
+			script_pubkey: Script::new_v0_p2wsh(&WScriptHash::all_zeros()),
+		};
+		if calculate_change_output(true, false, funding_satoshis as i64, &funding_inputs, &[placeholder_funding_output],
+			funding_feerate_sat_per_1000_weight, context.destination_script.clone()).is_err()
+		{
+			return Err(APIError::APIMisuseError {
+				err: format!("Provided inputs are insufficient to fund our contribution of {} sats", funding_satoshis) });
+		}
 
-				counterparty_forwarding_info: None,
+		let temporary_channel_id = get_v2_channel_id(&[0; PUBLIC_KEY_SIZE],
+			&context.get_holder_pubkeys().revocation_basepoint.serialize());
+		context.channel_id = temporary_channel_id;
+		context.temporary_channel_id = Some(temporary_channel_id);
 
-				channel_transaction_parameters: ChannelTransactionParameters {
-					holder_pubkeys: pubkeys,
-					holder_selected_contest_delay: config.channel_handshake_config.our_to_self_delay,
-					is_outbound_from_holder: false,
-					counterparty_parameters: Some(CounterpartyChannelTransactionParameters {
-						selected_contest_delay: msg.to_self_delay,
-						pubkeys: counterparty_pubkeys,
-					}),
-					funding_outpoint: None,
-					channel_type_features: channel_type.clone()
-				},
-				funding_transaction: None,
+		Ok(Self {
+			context,
+			unfunded_context,
+			dual_funding_context: DualFundingChannelContext {
+				our_funding_satoshis: funding_satoshis,
+				their_funding_satoshis: 0,
+				funding_tx_locktime: current_chain_height,
+				funding_feerate_sat_per_1000_weight,
+				our_funding_inputs: funding_inputs,
+			},
+			interactive_tx_constructor: None,
+		})
+	}
 
-				counterparty_cur_commitment_point: Some(msg.first_per_commitment_point),
-				counterparty_prev_commitment_point: None,
-				counterparty_node_id,
+	pub fn get_open_channel_v2(&self, chain_hash: BlockHash) -> msgs::OpenChannelV2 {
+		if !self.context.is_outbound() {
+			panic!("Tried to open a channel for an inbound channel?");
+		}
+		if self.context.channel_state != ChannelState::OurInitSent as u32 {
+			panic!("Cannot generate an open_channel2 after we've moved forward");
+		}
 
-				counterparty_shutdown_scriptpubkey,
+		if self.context.cur_holder_commitment_transaction_number != INITIAL_COMMITMENT_NUMBER {
+			panic!("Tried to send an open_channel2 for a channel that has already advanced");
+		}
 
-				commitment_secrets: CounterpartyCommitmentSecrets::new(),
+		let first_per_commitment_point = self.context.holder_signer.get_per_commitment_point(
+			self.context.cur_holder_commitment_transaction_number, &self.context.secp_ctx);
+		let second_per_commitment_point = self.context.holder_signer.get_per_commitment_point(
+			self.context.cur_holder_commitment_transaction_number - 1, &self.context.secp_ctx);
+		let keys = self.context.get_holder_pubkeys();
 
-				channel_update_status: ChannelUpdateStatus::Enabled,
-				closing_signed_in_flight: false,
+		msgs::OpenChannelV2 {
+			chain_hash,
+			temporary_channel_id: self.context.channel_id,
+			funding_feerate_sat_per_1000_weight: self.dual_funding_context.funding_feerate_sat_per_1000_weight,
+			commitment_feerate_sat_per_1000_weight: self.context.feerate_per_kw,
+			funding_satoshis: self.dual_funding_context.our_funding_satoshis,
+			dust_limit_satoshis: self.context.holder_dust_limit_satoshis,
+			max_htlc_value_in_flight_msat: self.context.holder_max_htlc_value_in_flight_msat,
+			htlc_minimum_msat: self.context.holder_htlc_minimum_msat,
+			to_self_delay: self.context.get_holder_selected_contest_delay(),
+			max_accepted_htlcs: self.context.holder_max_accepted_htlcs,
+			locktime: self.dual_funding_context.funding_tx_locktime,
+			funding_pubkey: keys.funding_pubkey,
+			revocation_basepoint: keys.revocation_basepoint,
+			payment_basepoint: keys.payment_point,
+			delayed_payment_basepoint: keys.delayed_payment_basepoint,
+			htlc_basepoint: keys.htlc_basepoint,
+			first_per_commitment_point,
+			second_per_commitment_point,
+			channel_flags: if self.context.config.announced_channel {1} else {0},
+			shutdown_scriptpubkey: Some(match &self.context.shutdown_scriptpubkey {
+				Some(script) => script.clone().into_inner(),
+				None => Builder::new().into_script(),
+			}),
+			channel_type: Some(self.context.channel_type.clone()),
+			require_confirmed_inputs: None,
+		}
+	}
 
-				announcement_sigs: None,
+	// Message handlers
 
-				#[cfg(any(test, fuzzing))]
-				next_local_commitment_tx_fee_info_cached: Mutex::new(None),
-				#[cfg(any(test, fuzzing))]
-				next_remote_commitment_tx_fee_info_cached: Mutex::new(None),
+	/// Handles an `accept_channel2` from our counterparty, returning the first message of the
+	/// funding transaction negotiation which we initiate.
+	pub fn accept_channel_v2<ES: Deref, SP: Deref>(
+		&mut self, msg: &msgs::AcceptChannelV2, default_limits: &ChannelHandshakeLimits,
+		their_features: &InitFeatures, entropy_source: &ES, signer_provider: &SP
+	) -> Result<InteractiveTxMessageSend, ChannelError>
+	where ES::Target: EntropySource,
+	      SP::Target: SignerProvider<Signer = Signer>,
+	{
+		if msg.minimum_depth == 0 {
+			return Err(ChannelError::Close("Zero-conf is not supported for dual-funded channels".to_owned()));
+		}
+		let our_funding_satoshis = self.dual_funding_context.our_funding_satoshis;
+		let channel_value_satoshis = match our_funding_satoshis.checked_add(msg.funding_satoshis) {
+			Some(value) if value < TOTAL_BITCOIN_SUPPLY_SATOSHIS => value,
+			_ => return Err(ChannelError::Close(format!("Total channel value must be smaller than the total bitcoin supply. Counterparty contributed {} sats", msg.funding_satoshis))),
+		};
+		if !their_features.supports_wumbo() && channel_value_satoshis > MAX_FUNDING_SATOSHIS_NO_WUMBO {
+			return Err(ChannelError::Close(format!("Total channel value must not exceed {}, it was {}", MAX_FUNDING_SATOSHIS_NO_WUMBO, channel_value_satoshis)));
+		}
 
-				workaround_lnd_bug_4006: None,
-				sent_message_awaiting_response: None,
+		self.context.counterparty_dust_limit_satoshis = msg.dust_limit_satoshis;
+		self.context.set_dual_funded_channel_value(our_funding_satoshis, msg.funding_satoshis, signer_provider);
+		self.dual_funding_context.their_funding_satoshis = msg.funding_satoshis;
+
+		let accept_channel = msgs::AcceptChannel {
+			temporary_channel_id: msg.temporary_channel_id,
+			dust_limit_satoshis: msg.dust_limit_satoshis,
+			max_htlc_value_in_flight_msat: msg.max_htlc_value_in_flight_msat,
+			channel_reserve_satoshis: self.context.counterparty_selected_channel_reserve_satoshis.unwrap(),
+			htlc_minimum_msat: msg.htlc_minimum_msat,
+			minimum_depth: msg.minimum_depth,
+			to_self_delay: msg.to_self_delay,
+			max_accepted_htlcs: msg.max_accepted_htlcs,
+			funding_pubkey: msg.funding_pubkey,
+			revocation_basepoint: msg.revocation_basepoint,
+			payment_point: msg.payment_basepoint,
+			delayed_payment_basepoint: msg.delayed_payment_basepoint,
+			htlc_basepoint: msg.htlc_basepoint,
+			first_per_commitment_point: msg.first_per_commitment_point,
+			shutdown_scriptpubkey: msg.shutdown_scriptpubkey.clone(),
+			channel_type: msg.channel_type.clone(),
+			#[cfg(taproot)]
+			next_local_nonce: None,
+		};
+		self.context.do_accept_channel_checks_and_update(&accept_channel, default_limits, their_features)?;
+		// We never trust our counterparty's contribution to the funding transaction, so always wait
+		// for at least one confirmation.
+		self.context.minimum_depth = Some(cmp::max(1, msg.minimum_depth));
+
+		self.context.channel_id = get_v2_channel_id(
+			&self.context.get_holder_pubkeys().revocation_basepoint.serialize(),
+			&msg.revocation_basepoint.serialize());
+
+		let funding_output = TxOut {
+			value: channel_value_satoshis,
+			script_pubkey: self.context.get_funding_redeemscript().to_v0_p2wsh(),
+		};
+		let change_script = signer_provider.get_destination_script()
+			.map_err(|_| ChannelError::Close("Failed to get change script".to_owned()))?;
+		let mut funding_outputs = vec![funding_output.clone()];
+		match calculate_change_output(true, false, our_funding_satoshis as i64, &self.dual_funding_context.our_funding_inputs,
+			&funding_outputs, self.dual_funding_context.funding_feerate_sat_per_1000_weight, change_script)
+		{
+			Ok(Some(change_output)) => funding_outputs.push(change_output),
+			Ok(None) => {},
+			Err(_) => return Err(ChannelError::Close("Insufficient inputs to fund our contribution to the channel".to_owned())),
+		}
 
-				latest_inbound_scid_alias: None,
-				outbound_scid_alias,
+		let (tx_constructor, msg_send) = InteractiveTxConstructor::new(
+			entropy_source, self.context.channel_id,
+			self.dual_funding_context.funding_feerate_sat_per_1000_weight, true,
+			PackedLockTime(self.dual_funding_context.funding_tx_locktime),
+			mem::take(&mut self.dual_funding_context.our_funding_inputs), funding_outputs,
+			SharedOutput { tx_out: funding_output, holder_value: our_funding_satoshis }, None);
+		self.interactive_tx_constructor = Some(tx_constructor);
+		Ok(msg_send.expect("The initiator always sends the first message of a negotiation"))
+	}
 
-				channel_pending_event_emitted: false,
-				channel_ready_event_emitted: false,
+	/// Promotes this channel to a [`Channel`] once the funding transaction has been negotiated,
+	/// returning the initial `commitment_signed` to send to our counterparty.
+	///
+	/// If an Err is returned, it is a ChannelError::Close.
+	pub fn funding_tx_constructed<L: Deref>(
+		mut self, constructed_tx: ConstructedTransaction, holder_node_id: &PublicKey, logger: &L
+	) -> Result<(Channel<Signer>, msgs::CommitmentSigned), (Self, ChannelError)>
+	where L::Target: Logger
+	{
+		match self.context.funding_tx_constructed(constructed_tx, holder_node_id, logger) {
+			Ok(commitment_signed) => Ok((Channel { context: self.context, interactive_tx_constructor: None }, commitment_signed)),
+			Err(e) => Err((self, e)),
+		}
+	}
+}
 
-				#[cfg(any(test, fuzzing))]
-				historical_inbound_htlc_fulfills: HashSet::new(),
+impl<Signer: WriteableEcdsaChannelSigner> InteractivelyFunded for OutboundV2Channel<Signer> {
+	fn interactive_tx_constructor_mut(&mut self) -> &mut Option<InteractiveTxConstructor> {
+		&mut self.interactive_tx_constructor
+	}
+}
 
-				channel_type,
-				channel_keys_id,
+/// A not-yet-funded inbound (from counterparty) channel using V2 channel establishment.
+pub(super) struct InboundV2Channel<Signer: ChannelSigner> {
+	pub context: ChannelContext<Signer>,
+	pub unfunded_context: UnfundedChannelContext,
+	pub dual_funding_context: DualFundingChannelContext,
+	interactive_tx_constructor: Option<InteractiveTxConstructor>,
+}
 
-				blocked_monitor_updates: Vec::new(),
-			},
-			unfunded_context: UnfundedChannelContext { unfunded_channel_age_ticks: 0 }
+impl<Signer: WriteableEcdsaChannelSigner> InboundV2Channel<Signer> {
+	/// Creates a new dual-funded channel from a remote side's request for one.
+	/// Assumes chain_hash has already been checked and corresponds with what we expect!
+	pub fn new<ES: Deref, SP: Deref, F: Deref, L: Deref>(
+		fee_estimator: &LowerBoundedFeeEstimator<F>, entropy_source: &ES, signer_provider: &SP,
+		counterparty_node_id: PublicKey, our_supported_features: &ChannelTypeFeatures,
+		their_features: &InitFeatures, msg: &msgs::OpenChannelV2, user_id: u128, config: &UserConfig,
+		current_chain_height: u32, logger: &L, outbound_scid_alias: u64
+	) -> Result<InboundV2Channel<Signer>, ChannelError>
+		where ES::Target: EntropySource,
+			  SP::Target: SignerProvider<Signer = Signer>,
+			  F::Target: FeeEstimator,
+			  L::Target: Logger,
+	{
+		let min_funding_feerate = fee_estimator.bounded_sat_per_1000_weight(ConfirmationTarget::MempoolMinimum);
+		if msg.funding_feerate_sat_per_1000_weight < min_funding_feerate {
+			return Err(ChannelError::Close(format!("Funding feerate of {} sat/kw is below the minimum of {} sat/kw",
+				msg.funding_feerate_sat_per_1000_weight, min_funding_feerate)));
+		}
+
+		// Until we've decided on our own contribution, the channel is checked as if it were funded
+		// solely by our counterparty, which is the least they may expect from us.
+		let open_channel = msgs::OpenChannel {
+			chain_hash: msg.chain_hash,
+			temporary_channel_id: msg.temporary_channel_id,
+			funding_satoshis: msg.funding_satoshis,
+			push_msat: 0,
+			dust_limit_satoshis: msg.dust_limit_satoshis,
+			max_htlc_value_in_flight_msat: msg.max_htlc_value_in_flight_msat,
+			channel_reserve_satoshis: get_v2_channel_reserve_satoshis(msg.funding_satoshis, MIN_CHAN_DUST_LIMIT_SATOSHIS),
+			htlc_minimum_msat: msg.htlc_minimum_msat,
+			feerate_per_kw: msg.commitment_feerate_sat_per_1000_weight,
+			to_self_delay: msg.to_self_delay,
+			max_accepted_htlcs: msg.max_accepted_htlcs,
+			funding_pubkey: msg.funding_pubkey,
+			revocation_basepoint: msg.revocation_basepoint,
+			payment_point: msg.payment_basepoint,
+			delayed_payment_basepoint: msg.delayed_payment_basepoint,
+			htlc_basepoint: msg.htlc_basepoint,
+			first_per_commitment_point: msg.first_per_commitment_point,
+			channel_flags: msg.channel_flags,
+			shutdown_scriptpubkey: msg.shutdown_scriptpubkey.clone(),
+			channel_type: msg.channel_type.clone(),
 		};
+		let InboundV1Channel { mut context, unfunded_context } = InboundV1Channel::new(
+			fee_estimator, entropy_source, signer_provider, counterparty_node_id, our_supported_features,
+			their_features, &open_channel, user_id, config, current_chain_height, logger, outbound_scid_alias)?;
+		// The counterparty's in-flight limit is capped to the channel value once we know it.
+		context.counterparty_max_htlc_value_in_flight_msat = msg.max_htlc_value_in_flight_msat;
 
-		Ok(chan)
+		Ok(Self {
+			context,
+			unfunded_context,
+			dual_funding_context: DualFundingChannelContext {
+				our_funding_satoshis: 0,
+				their_funding_satoshis: msg.funding_satoshis,
+				funding_tx_locktime: msg.locktime,
+				funding_feerate_sat_per_1000_weight: msg.funding_feerate_sat_per_1000_weight,
+				our_funding_inputs: Vec::new(),
+			},
+			interactive_tx_constructor: None,
+		})
 	}
 
 	pub fn is_awaiting_accept(&self) -> bool {
 		self.context.inbound_awaiting_accept
 	}
 
-	/// Sets this channel to accepting 0conf, must be done before `get_accept_channel`
-	pub fn set_0conf(&mut self) {
-		assert!(self.context.inbound_awaiting_accept);
-		self.context.minimum_depth = Some(0);
-	}
-
-	/// Marks an inbound channel as accepted and generates a [`msgs::AcceptChannel`] message which
-	/// should be sent back to the counterparty node.
+	/// Marks an inbound channel as accepted, contributing `funding_satoshis` from the given inputs,
+	/// and generates a [`msgs::AcceptChannelV2`] message which should be sent back to the
+	/// counterparty node.
 	///
-	/// [`msgs::AcceptChannel`]: crate::ln::msgs::AcceptChannel
-	pub fn accept_inbound_channel(&mut self, user_id: u128) -> msgs::AcceptChannel {
+	/// [`msgs::AcceptChannelV2`]: crate::ln::msgs::AcceptChannelV2
+	pub fn accept_inbound_dual_funded_channel<ES: Deref, SP: Deref>(
+		&mut self, user_id: u128, funding_satoshis: u64, funding_inputs: Vec<(TxIn, TransactionU16LenLimited)>,
+		entropy_source: &ES, signer_provider: &SP
+	) -> Result<msgs::AcceptChannelV2, APIError>
+	where ES::Target: EntropySource,
+	      SP::Target: SignerProvider<Signer = Signer>,
+	{
 		if self.context.is_outbound() {
-			panic!("Tried to send accept_channel for an outbound channel?");
+			panic!("Tried to send accept_channel2 for an outbound channel?");
 		}
 		if self.context.channel_state != (ChannelState::OurInitSent as u32) | (ChannelState::TheirInitSent as u32) {
-			panic!("Tried to send accept_channel after channel had moved forward");
+			panic!("Tried to send accept_channel2 after channel had moved forward");
 		}
 		if self.context.cur_holder_commitment_transaction_number != INITIAL_COMMITMENT_NUMBER {
-			panic!("Tried to send an accept_channel for a channel that has already advanced");
+			panic!("Tried to send an accept_channel2 for a channel that has already advanced");
 		}
 		if !self.context.inbound_awaiting_accept {
 			panic!("The inbound channel has already been accepted");
 		}
 
+		let their_funding_satoshis = self.dual_funding_context.their_funding_satoshis;
+		if funding_satoshis.saturating_add(their_funding_satoshis) >= TOTAL_BITCOIN_SUPPLY_SATOSHIS {
+			return Err(APIError::APIMisuseError {
+				err: format!("Total channel value must be smaller than the total bitcoin supply, our contribution was {}", funding_satoshis) });
+		}
+		let change_script = signer_provider.get_destination_script()
+			.map_err(|_| APIError::ChannelUnavailable { err: "Failed to get change script".to_owned() })?;
+		let funding_outputs = match calculate_change_output(false, false, funding_satoshis as i64, &funding_inputs, &[],
+			self.dual_funding_context.funding_feerate_sat_per_1000_weight, change_script)
+		{
+			Ok(change_output) => change_output.into_iter().collect::<Vec<_>>(),
+			Err(_) => return Err(APIError::APIMisuseError {
+				err: format!("Provided inputs are insufficient to fund our contribution of {} sats", funding_satoshis) }),
+		};
+
+		self.context.set_dual_funded_channel_value(funding_satoshis, their_funding_satoshis, signer_provider);
+		self.context.counterparty_max_htlc_value_in_flight_msat = cmp::min(
+			self.context.counterparty_max_htlc_value_in_flight_msat, self.context.channel_value_satoshis * 1000);
+		self.dual_funding_context.our_funding_satoshis = funding_satoshis;
 		self.context.user_id = user_id;
 		self.context.inbound_awaiting_accept = false;
 
-		self.generate_accept_channel_message()
-	}
-
-	/// This function is used to explicitly generate a [`msgs::AcceptChannel`] message for an
-	/// inbound channel. If the intention is to accept an inbound channel, use
-	/// [`InboundV1Channel::accept_inbound_channel`] instead.
-	///
-	/// [`msgs::AcceptChannel`]: crate::ln::msgs::AcceptChannel
-	fn generate_accept_channel_message(&self) -> msgs::AcceptChannel {
-		let first_per_commitment_point = self.context.holder_signer.get_per_commitment_point(self.context.cur_holder_commitment_transaction_number, &self.context.secp_ctx);
-		let keys = self.context.get_holder_pubkeys();
+		let keys = self.context.get_holder_pubkeys().clone();
+		self.context.channel_id = get_v2_channel_id(&keys.revocation_basepoint.serialize(),
+			&self.context.get_counterparty_pubkeys().revocation_basepoint.serialize());
 
-		msgs::AcceptChannel {
-			temporary_channel_id: self.context.channel_id,
+		let funding_output = TxOut {
+			value: self.context.channel_value_satoshis,
+			script_pubkey: self.context.get_funding_redeemscript().to_v0_p2wsh(),
+		};
+		let (tx_constructor, _) = InteractiveTxConstructor::new(
+			entropy_source, self.context.channel_id,
+			self.dual_funding_context.funding_feerate_sat_per_1000_weight, false,
+			PackedLockTime(self.dual_funding_context.funding_tx_locktime), funding_inputs, funding_outputs,
+			SharedOutput { tx_out: funding_output, holder_value: funding_satoshis }, None);
+		self.interactive_tx_constructor = Some(tx_constructor);
+
+		let first_per_commitment_point = self.context.holder_signer.get_per_commitment_point(
+			self.context.cur_holder_commitment_transaction_number, &self.context.secp_ctx);
+		let second_per_commitment_point = self.context.holder_signer.get_per_commitment_point(
+			self.context.cur_holder_commitment_transaction_number - 1, &self.context.secp_ctx);
+
+		Ok(msgs::AcceptChannelV2 {
+			temporary_channel_id: self.context.temporary_channel_id.unwrap(),
+			funding_satoshis,
 			dust_limit_satoshis: self.context.holder_dust_limit_satoshis,
 			max_htlc_value_in_flight_msat: self.context.holder_max_htlc_value_in_flight_msat,
-			channel_reserve_satoshis: self.context.holder_selected_channel_reserve_satoshis,
 			htlc_minimum_msat: self.context.holder_htlc_minimum_msat,
 			minimum_depth: self.context.minimum_depth.unwrap(),
 			to_self_delay: self.context.get_holder_selected_contest_delay(),
 			max_accepted_htlcs: self.context.holder_max_accepted_htlcs,
 			funding_pubkey: keys.funding_pubkey,
 			revocation_basepoint: keys.revocation_basepoint,
-			payment_point: keys.payment_point,
+			payment_basepoint: keys.payment_point,
 			delayed_payment_basepoint: keys.delayed_payment_basepoint,
 			htlc_basepoint: keys.htlc_basepoint,
 			first_per_commitment_point,
+			second_per_commitment_point,
 			shutdown_scriptpubkey: Some(match &self.context.shutdown_scriptpubkey {
 				Some(script) => script.clone().into_inner(),
 				None => Builder::new().into_script(),
 			}),
 			channel_type: Some(self.context.channel_type.clone()),
-			#[cfg(taproot)]
-			next_local_nonce: None,
-		}
+			require_confirmed_inputs: None,
+		})
 	}
 
-	/// Enables the possibility for tests to extract a [`msgs::AcceptChannel`] message for an
-	/// inbound channel without accepting it.
+	/// Promotes this channel to a [`Channel`] once the funding transaction has been negotiated,
+	/// returning the initial `commitment_signed` to send to our counterparty.
 	///
-	/// [`msgs::AcceptChannel`]: crate::ln::msgs::AcceptChannel
-	#[cfg(test)]
-	pub fn get_accept_channel_message(&self) -> msgs::AcceptChannel {
-		self.generate_accept_channel_message()
-	}
-
-	fn funding_created_signature<L: Deref>(&mut self, sig: &Signature, logger: &L) -> Result<(Txid, CommitmentTransaction, Signature), ChannelError> where L::Target: Logger {
-		let funding_script = self.context.get_funding_redeemscript();
-
-		let keys = self.context.build_holder_transaction_keys(self.context.cur_holder_commitment_transaction_number);
-		let initial_commitment_tx = self.context.build_commitment_transaction(self.context.cur_holder_commitment_transaction_number, &keys, true, false, logger).tx;
-		{
-			let trusted_tx = initial_commitment_tx.trust();
-			let initial_commitment_bitcoin_tx = trusted_tx.built_transaction();
-			let sighash = initial_commitment_bitcoin_tx.get_sighash_all(&funding_script, self.context.channel_value_satoshis);
-			// They sign the holder commitment transaction...
-			log_trace!(logger, "Checking funding_created tx signature {} by key {} against tx {} (sighash {}) with redeemscript {} for channel {}.",
-				log_bytes!(sig.serialize_compact()[..]), log_bytes!(self.context.counterparty_funding_pubkey().serialize()),
-				encode::serialize_hex(&initial_commitment_bitcoin_tx.transaction), log_bytes!(sighash[..]),
-				encode::serialize_hex(&funding_script), log_bytes!(self.context.channel_id()));
-			secp_check!(self.context.secp_ctx.verify_ecdsa(&sighash, &sig, self.context.counterparty_funding_pubkey()), "Invalid funding_created signature from peer".to_owned());
-		}
-
-		let counterparty_keys = self.context.build_remote_transaction_keys();
-		let counterparty_initial_commitment_tx = self.context.build_commitment_transaction(self.context.cur_counterparty_commitment_transaction_number, &counterparty_keys, false, false, logger).tx;
-
-		let counterparty_trusted_tx = counterparty_initial_commitment_tx.trust();
-		let counterparty_initial_bitcoin_tx = counterparty_trusted_tx.built_transaction();
-		log_trace!(logger, "Initial counterparty tx for channel {} is: txid {} tx {}",
-			log_bytes!(self.context.channel_id()), counterparty_initial_bitcoin_tx.txid, encode::serialize_hex(&counterparty_initial_bitcoin_tx.transaction));
-
-		let counterparty_signature = self.context.holder_signer.sign_counterparty_commitment(&counterparty_initial_commitment_tx, Vec::new(), &self.context.secp_ctx)
-				.map_err(|_| ChannelError::Close("Failed to get signatures for new commitment_signed".to_owned()))?.0;
-
-		// We sign "counterparty" commitment transaction, allowing them to broadcast the tx if they wish.
-		Ok((counterparty_initial_bitcoin_tx.txid, initial_commitment_tx, counterparty_signature))
-	}
-
-	pub fn funding_created<SP: Deref, L: Deref>(
-		mut self, msg: &msgs::FundingCreated, best_block: BestBlock, signer_provider: &SP, logger: &L
-	) -> Result<(Channel<Signer>, msgs::FundingSigned, ChannelMonitor<Signer>), (Self, ChannelError)>
-	where
-		SP::Target: SignerProvider<Signer = Signer>,
-		L::Target: Logger
+	/// If an Err is returned, it is a ChannelError::Close.
+	pub fn funding_tx_constructed<L: Deref>(
+		mut self, constructed_tx: ConstructedTransaction, holder_node_id: &PublicKey, logger: &L
+	) -> Result<(Channel<Signer>, msgs::CommitmentSigned), (Self, ChannelError)>
+	where L::Target: Logger
 	{
-		if self.context.is_outbound() {
-			return Err((self, ChannelError::Close("Received funding_created for an outbound channel?".to_owned())));
-		}
-		if self.context.channel_state != (ChannelState::OurInitSent as u32 | ChannelState::TheirInitSent as u32) {
-			// BOLT 2 says that if we disconnect before we send funding_signed we SHOULD NOT
-			// remember the channel, so it's safe to just send an error_message here and drop the
-			// channel.
-			return Err((self, ChannelError::Close("Received funding_created after we got the channel!".to_owned())));
-		}
-		if self.context.inbound_awaiting_accept {
-			return Err((self, ChannelError::Close("FundingCreated message received before the channel was accepted".to_owned())));
-		}
-		if self.context.commitment_secrets.get_min_seen_secret() != (1 << 48) ||
-				self.context.cur_counterparty_commitment_transaction_number != INITIAL_COMMITMENT_NUMBER ||
-				self.context.cur_holder_commitment_transaction_number != INITIAL_COMMITMENT_NUMBER {
-			panic!("Should not have advanced channel commitment tx numbers prior to funding_created");
-		}
-
-		let funding_txo = OutPoint { txid: msg.funding_txid, index: msg.funding_output_index };
-		self.context.channel_transaction_parameters.funding_outpoint = Some(funding_txo);
-		// This is an externally observable change before we finish all our checks.  In particular
-		// funding_created_signature may fail.
-		self.context.holder_signer.provide_channel_parameters(&self.context.channel_transaction_parameters);
-
-		let (counterparty_initial_commitment_txid, initial_commitment_tx, signature) = match self.funding_created_signature(&msg.signature, logger) {
-			Ok(res) => res,
-			Err(ChannelError::Close(e)) => {
-				self.context.channel_transaction_parameters.funding_outpoint = None;
-				return Err((self, ChannelError::Close(e)));
-			},
-			Err(e) => {
-				// The only error we know how to handle is ChannelError::Close, so we fall over here
-				// to make sure we don't continue with an inconsistent state.
-				panic!("unexpected error type from funding_created_signature {:?}", e);
-			}
-		};
-
-		let holder_commitment_tx = HolderCommitmentTransaction::new(
-			initial_commitment_tx,
-			msg.signature,
-			Vec::new(),
-			&self.context.get_holder_pubkeys().funding_pubkey,
-			self.context.counterparty_funding_pubkey()
-		);
-
-		if let Err(_) = self.context.holder_signer.validate_holder_commitment(&holder_commitment_tx, Vec::new()) {
-			return Err((self, ChannelError::Close("Failed to validate our commitment".to_owned())));
+		match self.context.funding_tx_constructed(constructed_tx, holder_node_id, logger) {
+			Ok(commitment_signed) => Ok((Channel { context: self.context, interactive_tx_constructor: None }, commitment_signed)),
+			Err(e) => Err((self, e)),
 		}
+	}
+}
 
-		// Now that we're past error-generating stuff, update our local state:
-
-		let funding_redeemscript = self.context.get_funding_redeemscript();
-		let funding_txo_script = funding_redeemscript.to_v0_p2wsh();
-		let obscure_factor = get_commitment_transaction_number_obscure_factor(&self.context.get_holder_pubkeys().payment_point, &self.context.get_counterparty_pubkeys().payment_point, self.context.is_outbound());
-		let shutdown_script = self.context.shutdown_scriptpubkey.clone().map(|script| script.into_inner());
-		let mut monitor_signer = signer_provider.derive_channel_signer(self.context.channel_value_satoshis, self.context.channel_keys_id);
-		monitor_signer.provide_channel_parameters(&self.context.channel_transaction_parameters);
-		let channel_monitor = ChannelMonitor::new(self.context.secp_ctx.clone(), monitor_signer,
-		                                          shutdown_script, self.context.get_holder_selected_contest_delay(),
-		                                          &self.context.destination_script, (funding_txo, funding_txo_script.clone()),
-		                                          &self.context.channel_transaction_parameters,
-		                                          funding_redeemscript.clone(), self.context.channel_value_satoshis,
-		                                          obscure_factor,
-		                                          holder_commitment_tx, best_block, self.context.counterparty_node_id);
-
-		channel_monitor.provide_latest_counterparty_commitment_tx(counterparty_initial_commitment_txid, Vec::new(), self.context.cur_counterparty_commitment_transaction_number, self.context.counterparty_cur_commitment_point.unwrap(), logger);
-
-		self.context.channel_state = ChannelState::FundingSent as u32;
-		self.context.channel_id = funding_txo.to_channel_id();
-		self.context.cur_counterparty_commitment_transaction_number -= 1;
-		self.context.cur_holder_commitment_transaction_number -= 1;
-
-		log_info!(logger, "Generated funding_signed for peer for channel {}", log_bytes!(self.context.channel_id()));
-
-		// Promote the channel to a full-fledged one now that we have updated the state and have a
-		// `ChannelMonitor`.
-		let mut channel = Channel {
-			context: self.context,
-		};
-		let channel_id = channel.context.channel_id.clone();
-		let need_channel_ready = channel.check_get_channel_ready(0).is_some();
-		channel.monitor_updating_paused(false, false, need_channel_ready, Vec::new(), Vec::new(), Vec::new());
+impl<Signer: WriteableEcdsaChannelSigner> InteractivelyFunded for InboundV2Channel<Signer> {
+	fn interactive_tx_constructor_mut(&mut self) -> &mut Option<InteractiveTxConstructor> {
+		&mut self.interactive_tx_constructor
+	}
+}
 
-		Ok((channel, msgs::FundingSigned {
-			channel_id,
-			signature,
-			#[cfg(taproot)]
-			partial_signature_with_nonce: None,
-		}, channel_monitor))
+impl<Signer: WriteableEcdsaChannelSigner> InteractivelyFunded for Channel<Signer> {
+	fn interactive_tx_constructor_mut(&mut self) -> &mut Option<InteractiveTxConstructor> {
+		&mut self.interactive_tx_constructor
 	}
 }
 
@@ -6640,7 +9997,7 @@ impl<Signer: WriteableEcdsaChannelSigner> Writeable for Channel<Signer> {
 		writer.write_all(&[0; 8])?;
 
 		self.context.channel_id.write(writer)?;
-		(self.context.channel_state | ChannelState::PeerDisconnected as u32).write(writer)?;
+		((self.context.channel_state & !QUIESCENCE_STATE_FLAGS) | ChannelState::PeerDisconnected as u32).write(writer)?;
 		self.context.channel_value_satoshis.write(writer)?;
 
 		self.context.latest_monitor_update_id.write(writer)?;
@@ -6701,6 +10058,7 @@ impl<Signer: WriteableEcdsaChannelSigner> Writeable for Channel<Signer> {
 
 		let mut preimages: Vec<&Option<PaymentPreimage>> = vec![];
 		let mut pending_outbound_skimmed_fees: Vec<Option<u64>> = Vec::new();
+		let mut pending_outbound_blinding_points: Vec<Option<PublicKey>> = Vec::new();
 
 		(self.context.pending_outbound_htlcs.len() as u64).write(writer)?;
 		for (idx, htlc) in self.context.pending_outbound_htlcs.iter().enumerate() {
@@ -6747,15 +10105,19 @@ impl<Signer: WriteableEcdsaChannelSigner> Writeable for Channel<Signer> {
 			} else if !pending_outbound_skimmed_fees.is_empty() {
 				pending_outbound_skimmed_fees.push(None);
 			}
+			pending_outbound_blinding_points.push(htlc.blinding_point);
 		}
 
 		let mut holding_cell_skimmed_fees: Vec<Option<u64>> = Vec::new();
+		let mut holding_cell_blinding_points: Vec<Option<PublicKey>> = Vec::new();
+		// Vec of (htlc_id, failure_code, sha256_of_onion)
+		let mut malformed_htlcs: Vec<(u64, u16, [u8; 32])> = Vec::new();
 		(self.context.holding_cell_htlc_updates.len() as u64).write(writer)?;
 		for (idx, update) in self.context.holding_cell_htlc_updates.iter().enumerate() {
 			match update {
 				&HTLCUpdateAwaitingACK::AddHTLC {
 					ref amount_msat, ref cltv_expiry, ref payment_hash, ref source, ref onion_routing_packet,
-					skimmed_fee_msat,
+					blinding_point, skimmed_fee_msat,
 				} => {
 					0u8.write(writer)?;
 					amount_msat.write(writer)?;
@@ -6770,6 +10132,8 @@ impl<Signer: WriteableEcdsaChannelSigner> Writeable for Channel<Signer> {
 						}
 						holding_cell_skimmed_fees.push(Some(skimmed_fee));
 					} else if !holding_cell_skimmed_fees.is_empty() { holding_cell_skimmed_fees.push(None); }
+
+					holding_cell_blinding_points.push(blinding_point);
 				},
 				&HTLCUpdateAwaitingACK::ClaimHTLC { ref payment_preimage, ref htlc_id } => {
 					1u8.write(writer)?;
@@ -6780,6 +10144,16 @@ impl<Signer: WriteableEcdsaChannelSigner> Writeable for Channel<Signer> {
 					2u8.write(writer)?;
 					htlc_id.write(writer)?;
 					err_packet.write(writer)?;
+				},
+				&HTLCUpdateAwaitingACK::FailMalformedHTLC { htlc_id, failure_code, sha256_of_onion } => {
+					// We don't want to break downgrading by adding a new variant, so write a dummy
+					// `::FailHTLC` variant and write the real malformed error as an optional TLV.
+					malformed_htlcs.push((htlc_id, failure_code, sha256_of_onion));
+
+					let dummy_err_packet = msgs::OnionErrorPacket { data: Vec::new() };
+					2u8.write(writer)?;
+					htlc_id.write(writer)?;
+					dummy_err_packet.write(writer)?;
 				}
 			}
 		}
@@ -6907,6 +10281,51 @@ impl<Signer: WriteableEcdsaChannelSigner> Writeable for Channel<Signer> {
 
 		let holder_max_accepted_htlcs = if self.context.holder_max_accepted_htlcs == DEFAULT_MAX_HTLCS { None } else { Some(self.context.holder_max_accepted_htlcs) };
 
+		// A splice still being negotiated is simply abandoned on restart, much like a disconnection,
+		// so we only write it once its new funding transaction is known.
+		// Only the candidate funding transactions of a pending splice we've started signing are
+		// persisted, any other negotiation (or request of ours to start one) is abandoned on restart
+		// much like after a disconnection.
+		let pending_splice_state = self.context.pending_splice.as_ref().and_then(|splice| {
+			let candidates: Vec<_> = splice.candidates.iter().filter_map(|candidate| {
+				match (candidate.funding_txo, candidate.signing_session.as_ref()) {
+					(Some(funding_txo), Some(signing_session)) => Some(SpliceCandidateState {
+						funding_feerate_sat_per_1000_weight: candidate.funding_feerate_sat_per_1000_weight,
+						funding_txo,
+						signing_session: signing_session.clone(),
+						monitor_created: candidate.monitor_created,
+						funding_tx_confirmed_in: candidate.funding_tx_confirmed_in,
+						funding_tx_confirmation_height: candidate.funding_tx_confirmation_height,
+						short_channel_id: candidate.short_channel_id,
+						conflicting_txid: candidate.conflicting_txid,
+						conflicting_tx_confirmed_in: candidate.conflicting_tx_confirmed_in,
+						conflicting_tx_confirmation_height: candidate.conflicting_tx_confirmation_height,
+					}),
+					_ => None,
+				}
+			}).collect();
+			if candidates.is_empty() { return None; }
+			Some(PendingSpliceState {
+				is_initiator: splice.is_initiator,
+				our_funding_contribution_satoshis: splice.our_funding_contribution_satoshis,
+				their_funding_contribution_satoshis: splice.their_funding_contribution_satoshis,
+				candidates,
+				sent_splice_locked: splice.sent_splice_locked,
+				received_splice_locked: splice.received_splice_locked,
+			})
+		});
+
+		// An upgrade we've proposed but which hasn't been accepted yet is proposed again on restart,
+		// much like after a disconnection.
+		let pending_channel_type_upgrade_state = self.context.pending_channel_type_upgrade.as_ref().map(|upgrade| {
+			PendingChannelTypeUpgradeState {
+				channel_type: upgrade.channel_type.clone(),
+				state: if upgrade.state == ChannelTypeUpgradeState::Proposed {
+					ChannelTypeUpgradeState::Requested
+				} else { upgrade.state },
+			}
+		});
+
 		write_tlv_fields!(writer, {
 			(0, self.context.announcement_sigs, option),
 			// minimum_depth and counterparty_selected_channel_reserve_satoshis used to have a
@@ -6938,6 +10357,13 @@ impl<Signer: WriteableEcdsaChannelSigner> Writeable for Channel<Signer> {
 			(31, channel_pending_event_emitted, option),
 			(35, pending_outbound_skimmed_fees, optional_vec),
 			(37, holding_cell_skimmed_fees, optional_vec),
+			(38, self.context.interactive_tx_signing_session, option),
+			(39, pending_splice_state, option),
+			(41, pending_outbound_blinding_points, optional_vec),
+			(43, holding_cell_blinding_points, optional_vec),
+			(45, malformed_htlcs, optional_vec),
+			(47, self.context.is_batch_funding, option),
+			(49, pending_channel_type_upgrade_state, option),
 		});
 
 		Ok(())
@@ -7049,6 +10475,7 @@ impl<'a, 'b, 'c, ES: Deref, SP: Deref> ReadableArgs<(&'a ES, &'b SP, u32, &'c Ch
 					_ => return Err(DecodeError::InvalidValue),
 				},
 				skimmed_fee_msat: None,
+				blinding_point: None,
 			});
 		}
 
@@ -7063,6 +10490,7 @@ impl<'a, 'b, 'c, ES: Deref, SP: Deref> ReadableArgs<(&'a ES, &'b SP, u32, &'c Ch
 					source: Readable::read(reader)?,
 					onion_routing_packet: Readable::read(reader)?,
 					skimmed_fee_msat: None,
+					blinding_point: None,
 				},
 				1 => HTLCUpdateAwaitingACK::ClaimHTLC {
 					payment_preimage: Readable::read(reader)?,
@@ -7220,6 +10648,13 @@ impl<'a, 'b, 'c, ES: Deref, SP: Deref> ReadableArgs<(&'a ES, &'b SP, u32, &'c Ch
 
 		let mut pending_outbound_skimmed_fees_opt: Option<Vec<Option<u64>>> = None;
 		let mut holding_cell_skimmed_fees_opt: Option<Vec<Option<u64>>> = None;
+		let mut pending_outbound_blinding_points_opt: Option<Vec<Option<PublicKey>>> = None;
+		let mut holding_cell_blinding_points_opt: Option<Vec<Option<PublicKey>>> = None;
+		let mut malformed_htlcs: Option<Vec<(u64, u16, [u8; 32])>> = None;
+		let mut interactive_tx_signing_session: Option<InteractiveTxSigningSession> = None;
+		let mut pending_splice_state: Option<PendingSpliceState> = None;
+		let mut is_batch_funding: Option<()> = None;
+		let mut pending_channel_type_upgrade_state: Option<PendingChannelTypeUpgradeState> = None;
 
 		read_tlv_fields!(reader, {
 			(0, announcement_sigs, option),
@@ -7246,6 +10681,13 @@ impl<'a, 'b, 'c, ES: Deref, SP: Deref> ReadableArgs<(&'a ES, &'b SP, u32, &'c Ch
 			(31, channel_pending_event_emitted, option),
 			(35, pending_outbound_skimmed_fees_opt, optional_vec),
 			(37, holding_cell_skimmed_fees_opt, optional_vec),
+			(38, interactive_tx_signing_session, option),
+			(39, pending_splice_state, option),
+			(41, pending_outbound_blinding_points_opt, optional_vec),
+			(43, holding_cell_blinding_points_opt, optional_vec),
+			(45, malformed_htlcs, optional_vec),
+			(47, is_batch_funding, option),
+			(49, pending_channel_type_upgrade_state, option),
 		});
 
 		let (channel_keys_id, holder_signer) = if let Some(channel_keys_id) = channel_keys_id {
@@ -7264,6 +10706,39 @@ impl<'a, 'b, 'c, ES: Deref, SP: Deref> ReadableArgs<(&'a ES, &'b SP, u32, &'c Ch
 			(holder_signer.channel_keys_id(), holder_signer)
 		};
 
+		let pending_splice = pending_splice_state.map(|state| {
+			let splice_channel_value_satoshis = (channel_value_satoshis as i64 +
+				state.our_funding_contribution_satoshis + state.their_funding_contribution_satoshis) as u64;
+			let candidates = state.candidates.into_iter().map(|candidate| {
+				let mut holder_signer = signer_provider.derive_channel_signer(splice_channel_value_satoshis, channel_keys_id);
+				let mut splice_channel_parameters = channel_parameters.clone();
+				splice_channel_parameters.funding_outpoint = Some(candidate.funding_txo);
+				holder_signer.provide_channel_parameters(&splice_channel_parameters);
+				SpliceCandidate {
+					funding_feerate_sat_per_1000_weight: candidate.funding_feerate_sat_per_1000_weight,
+					holder_signer,
+					funding_txo: Some(candidate.funding_txo),
+					signing_session: Some(candidate.signing_session),
+					monitor_created: candidate.monitor_created,
+					funding_tx_confirmed_in: candidate.funding_tx_confirmed_in,
+					funding_tx_confirmation_height: candidate.funding_tx_confirmation_height,
+					short_channel_id: candidate.short_channel_id,
+					conflicting_txid: candidate.conflicting_txid,
+					conflicting_tx_confirmed_in: candidate.conflicting_tx_confirmed_in,
+					conflicting_tx_confirmation_height: candidate.conflicting_tx_confirmation_height,
+				}
+			}).collect();
+			PendingSplice {
+				is_initiator: state.is_initiator,
+				our_funding_contribution_satoshis: state.our_funding_contribution_satoshis,
+				their_funding_contribution_satoshis: state.their_funding_contribution_satoshis,
+				pending_request: None,
+				candidates,
+				sent_splice_locked: state.sent_splice_locked,
+				received_splice_locked: state.received_splice_locked,
+			}
+		});
+
 		if let Some(preimages) = preimages_opt {
 			let mut iter = preimages.into_iter();
 			for htlc in pending_outbound_htlcs.iter_mut() {
@@ -7294,6 +10769,30 @@ impl<'a, 'b, 'c, ES: Deref, SP: Deref> ReadableArgs<(&'a ES, &'b SP, u32, &'c Ch
 		// To account for that, we're proactively setting/overriding the field here.
 		channel_parameters.channel_type_features = chan_features.clone();
 
+		let pending_channel_type_upgrade = pending_channel_type_upgrade_state.map(|state| {
+			let upgrade_signer_channel_type = match state.state {
+				ChannelTypeUpgradeState::Accepted => Some(state.channel_type.clone()),
+				ChannelTypeUpgradeState::AwaitingCommitment => {
+					let mut previous_channel_type = state.channel_type.clone();
+					previous_channel_type.clear_anchors_zero_fee_htlc_tx();
+					Some(previous_channel_type)
+				},
+				_ => None,
+			};
+			let holder_signer = upgrade_signer_channel_type.map(|channel_type| {
+				let mut upgrade_holder_signer = signer_provider.derive_channel_signer(channel_value_satoshis, channel_keys_id);
+				let mut upgrade_channel_parameters = channel_parameters.clone();
+				upgrade_channel_parameters.channel_type_features = channel_type;
+				upgrade_holder_signer.provide_channel_parameters(&upgrade_channel_parameters);
+				upgrade_holder_signer
+			});
+			PendingChannelTypeUpgrade { channel_type: state.channel_type, state: state.state, holder_signer }
+		});
+		// A requested upgrade is proposed again once we're connected, which requires quiescence.
+		let channel_state = if pending_channel_type_upgrade.as_ref().map_or(false, |upgrade| upgrade.state == ChannelTypeUpgradeState::Requested) {
+			channel_state | ChannelState::AwaitingQuiescence as u32
+		} else { channel_state };
+
 		let mut secp_ctx = Secp256k1::new();
 		secp_ctx.seeded_randomize(&entropy_source.get_secure_random_bytes());
 
@@ -7322,6 +10821,40 @@ impl<'a, 'b, 'c, ES: Deref, SP: Deref> ReadableArgs<(&'a ES, &'b SP, u32, &'c Ch
 			// We expect all skimmed fees to be consumed above
 			if iter.next().is_some() { return Err(DecodeError::InvalidValue) }
 		}
+		if let Some(blinding_pts) = pending_outbound_blinding_points_opt {
+			let mut iter = blinding_pts.into_iter();
+			for htlc in pending_outbound_htlcs.iter_mut() {
+				htlc.blinding_point = iter.next().ok_or(DecodeError::InvalidValue)?;
+			}
+			// We expect all blinding points to be consumed above
+			if iter.next().is_some() { return Err(DecodeError::InvalidValue) }
+		}
+		if let Some(blinding_pts) = holding_cell_blinding_points_opt {
+			let mut iter = blinding_pts.into_iter();
+			for htlc in holding_cell_htlc_updates.iter_mut() {
+				if let HTLCUpdateAwaitingACK::AddHTLC { ref mut blinding_point, .. } = htlc {
+					*blinding_point = iter.next().ok_or(DecodeError::InvalidValue)?;
+				}
+			}
+			// We expect all blinding points to be consumed above
+			if iter.next().is_some() { return Err(DecodeError::InvalidValue) }
+		}
+
+		if let Some(malformed_htlcs) = malformed_htlcs {
+			for (malformed_htlc_id, failure_code, sha256_of_onion) in malformed_htlcs {
+				let htlc_idx = holding_cell_htlc_updates.iter().position(|htlc| {
+					if let HTLCUpdateAwaitingACK::FailHTLC { htlc_id, err_packet } = htlc {
+						let matches = *htlc_id == malformed_htlc_id;
+						if matches { debug_assert!(err_packet.data.is_empty()) }
+						matches
+					} else { false }
+				}).ok_or(DecodeError::InvalidValue)?;
+				let malformed_htlc = HTLCUpdateAwaitingACK::FailMalformedHTLC {
+					htlc_id: malformed_htlc_id, failure_code, sha256_of_onion
+				};
+				let _ = core::mem::replace(&mut holding_cell_htlc_updates[htlc_idx], malformed_htlc);
+			}
+		}
 
 		Ok(Channel {
 			context: ChannelContext {
@@ -7405,6 +10938,7 @@ impl<'a, 'b, 'c, ES: Deref, SP: Deref> ReadableArgs<(&'a ES, &'b SP, u32, &'c Ch
 
 				channel_transaction_parameters: channel_parameters,
 				funding_transaction,
+				is_batch_funding,
 
 				counterparty_cur_commitment_point,
 				counterparty_prev_commitment_point,
@@ -7426,6 +10960,8 @@ impl<'a, 'b, 'c, ES: Deref, SP: Deref> ReadableArgs<(&'a ES, &'b SP, u32, &'c Ch
 
 				workaround_lnd_bug_4006: None,
 				sent_message_awaiting_response: None,
+				quiescence_timer_ticks: None,
+				is_holder_quiescence_initiator: None,
 
 				latest_inbound_scid_alias,
 				// Later in the ChannelManager deserialization phase we scan for channels and assign scid aliases if its missing
@@ -7441,7 +10977,16 @@ impl<'a, 'b, 'c, ES: Deref, SP: Deref> ReadableArgs<(&'a ES, &'b SP, u32, &'c Ch
 				channel_keys_id,
 
 				blocked_monitor_updates: blocked_monitor_updates.unwrap(),
-			}
+
+				interactive_tx_signing_session,
+				monitor_pending_tx_signatures: false,
+
+				pending_splice,
+				pending_commitment_signed_batch: Vec::new(),
+				splice_locked_pending_ack: false,
+				pending_channel_type_upgrade,
+			},
+			interactive_tx_constructor: None,
 		})
 	}
 }
@@ -7638,7 +11183,7 @@ mod tests {
 			value: 10000000, script_pubkey: output_script.clone(),
 		}]};
 		let funding_outpoint = OutPoint{ txid: tx.txid(), index: 0 };
-		let (mut node_a_chan, funding_created_msg) = node_a_chan.get_funding_created(tx.clone(), funding_outpoint, &&logger).map_err(|_| ()).unwrap();
+		let (mut node_a_chan, funding_created_msg) = node_a_chan.get_funding_created(tx.clone(), funding_outpoint, false, &&logger).map_err(|_| ()).unwrap();
 		let (_, funding_signed_msg, _) = node_b_chan.funding_created(&funding_created_msg, best_block, &&keys_provider, &&logger).map_err(|_| ()).unwrap();
 
 		// Node B --> Node A: funding signed
@@ -7667,6 +11212,7 @@ mod tests {
 				payment_id: PaymentId([42; 32]),
 			},
 			skimmed_fee_msat: None,
+			blinding_point: None,
 		});
 
 		// Make sure when Node A calculates their local commitment transaction, none of the HTLCs pass
@@ -7765,7 +11311,7 @@ mod tests {
 			value: 10000000, script_pubkey: output_script.clone(),
 		}]};
 		let funding_outpoint = OutPoint{ txid: tx.txid(), index: 0 };
-		let (mut node_a_chan, funding_created_msg) = node_a_chan.get_funding_created(tx.clone(), funding_outpoint, &&logger).map_err(|_| ()).unwrap();
+		let (mut node_a_chan, funding_created_msg) = node_a_chan.get_funding_created(tx.clone(), funding_outpoint, false, &&logger).map_err(|_| ()).unwrap();
 		let (mut node_b_chan, funding_signed_msg, _) = node_b_chan.funding_created(&funding_created_msg, best_block, &&keys_provider, &&logger).map_err(|_| ()).unwrap();
 
 		// Node B --> Node A: funding signed
@@ -7953,7 +11499,7 @@ mod tests {
 			value: 10000000, script_pubkey: output_script.clone(),
 		}]};
 		let funding_outpoint = OutPoint{ txid: tx.txid(), index: 0 };
-		let (mut node_a_chan, funding_created_msg) = node_a_chan.get_funding_created(tx.clone(), funding_outpoint, &&logger).map_err(|_| ()).unwrap();
+		let (mut node_a_chan, funding_created_msg) = node_a_chan.get_funding_created(tx.clone(), funding_outpoint, false, &&logger).map_err(|_| ()).unwrap();
 		let (_, funding_signed_msg, _) = node_b_chan.funding_created(&funding_created_msg, best_block, &&keys_provider, &&logger).map_err(|_| ()).unwrap();
 
 		// Node B --> Node A: funding signed
@@ -8224,6 +11770,7 @@ mod tests {
 				state: OutboundHTLCState::Committed,
 				source: HTLCSource::dummy(),
 				skimmed_fee_msat: None,
+				blinding_point: None,
 			};
 			out.payment_hash.0 = Sha256::hash(&hex::decode("0202020202020202020202020202020202020202020202020202020202020202").unwrap()).into_inner();
 			out
@@ -8237,6 +11784,7 @@ mod tests {
 				state: OutboundHTLCState::Committed,
 				source: HTLCSource::dummy(),
 				skimmed_fee_msat: None,
+				blinding_point: None,
 			};
 			out.payment_hash.0 = Sha256::hash(&hex::decode("0303030303030303030303030303030303030303030303030303030303030303").unwrap()).into_inner();
 			out
@@ -8648,6 +12196,7 @@ mod tests {
 				state: OutboundHTLCState::Committed,
 				source: HTLCSource::dummy(),
 				skimmed_fee_msat: None,
+				blinding_point: None,
 			};
 			out.payment_hash.0 = Sha256::hash(&hex::decode("0505050505050505050505050505050505050505050505050505050505050505").unwrap()).into_inner();
 			out
@@ -8661,6 +12210,7 @@ mod tests {
 				state: OutboundHTLCState::Committed,
 				source: HTLCSource::dummy(),
 				skimmed_fee_msat: None,
+				blinding_point: None,
 			};
 			out.payment_hash.0 = Sha256::hash(&hex::decode("0505050505050505050505050505050505050505050505050505050505050505").unwrap()).into_inner();
 			out
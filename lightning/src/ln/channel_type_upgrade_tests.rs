@@ -0,0 +1,374 @@
+// This file is Copyright its original authors, visible in version control
+// history.
+//
+// This file is licensed under the Apache License, Version 2.0 <LICENSE-APACHE
+// or http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your option.
+// You may not use this file except in accordance with one or both of these
+// licenses.
+
+//! Tests that test upgrading the channel type of live channels, e.g. from `static_remote_key` to
+//! anchor outputs, through `dyn_propose`.
+
+use crate::events::{ClosureReason, MessageSendEvent, MessageSendEventsProvider};
+use crate::ln::channel::ANCHOR_OUTPUT_VALUE_SATOSHI;
+use crate::ln::channelmanager::ChannelQuiescenceState;
+use crate::ln::functional_test_utils::*;
+use crate::ln::msgs;
+use crate::ln::msgs::ChannelMessageHandler;
+use crate::util::config::UserConfig;
+use crate::util::errors::APIError;
+use crate::util::ser::Writeable;
+use crate::util::test_utils;
+
+use bitcoin::{OutPoint, Transaction, Txid};
+use bitcoin::hashes::Hash;
+use bitcoin::secp256k1::SecretKey;
+
+use crate::prelude::*;
+
+/// Returns a config which allows upgrading channels to anchor outputs.
+fn upgrade_config() -> UserConfig {
+	let mut config = test_default_channel_config();
+	config.channel_handshake_config.negotiate_anchors_zero_fee_htlc_tx = true;
+	config
+}
+
+/// Opens an announced channel from `nodes[a]` to `nodes[b]` without anchor outputs, as it would have
+/// been opened prior to enabling them, returning its id and funding transaction.
+fn create_legacy_announced_chan<'a, 'b, 'c, 'd>(nodes: &'a Vec<Node<'b, 'c, 'd>>, a: usize, b: usize) -> ([u8; 32], Transaction) {
+	let mut legacy_config = test_default_channel_config();
+	legacy_config.channel_handshake_config.negotiate_anchors_zero_fee_htlc_tx = false;
+	let temporary_channel_id = nodes[a].node.create_channel(nodes[b].node.get_our_node_id(), 100_000, 10_001, 42, Some(legacy_config)).unwrap();
+	let open_channel = get_event_msg!(nodes[a], MessageSendEvent::SendOpenChannel, nodes[b].node.get_our_node_id());
+	assert!(!open_channel.channel_type.as_ref().unwrap().supports_anchors_zero_fee_htlc_tx());
+	nodes[b].node.handle_open_channel(&nodes[a].node.get_our_node_id(), &open_channel);
+	let accept_channel = get_event_msg!(nodes[b], MessageSendEvent::SendAcceptChannel, nodes[a].node.get_our_node_id());
+	nodes[a].node.handle_accept_channel(&nodes[b].node.get_our_node_id(), &accept_channel);
+	let funding_tx = sign_funding_transaction(&nodes[a], &nodes[b], 100_000, temporary_channel_id);
+	let (funding_msgs, channel_id) = create_chan_between_nodes_with_value_confirm(&nodes[a], &nodes[b], &funding_tx);
+	let (announcement, as_update, bs_update) = create_chan_between_nodes_with_value_b(&nodes[a], &nodes[b], &funding_msgs);
+	update_nodes_with_chan_announce(nodes, a, b, &announcement, &as_update, &bs_update);
+	(channel_id, funding_tx)
+}
+
+fn expect_anchors<'a, 'b, 'c>(node: &Node<'a, 'b, 'c>, channel_id: &[u8; 32], anchors: bool) {
+	let channel = node.node.list_channels().into_iter().find(|channel| channel.channel_id == *channel_id).unwrap();
+	assert_eq!(channel.channel_type.unwrap().supports_anchors_zero_fee_htlc_tx(), anchors);
+	let monitor = get_monitor!(node, *channel_id);
+	assert_eq!(monitor.inner.lock().unwrap().onchain_tx_handler.channel_type_features().supports_anchors_zero_fee_htlc_tx(), anchors);
+}
+
+fn num_anchor_outputs(tx: &Transaction) -> usize {
+	tx.output.iter().filter(|output| output.value == ANCHOR_OUTPUT_VALUE_SATOSHI).count()
+}
+
+/// Makes the channel quiescent after `node_a` requested its upgrade, returning the resulting
+/// `dyn_propose`.
+fn quiesce_for_upgrade<'a, 'b, 'c>(node_a: &Node<'a, 'b, 'c>, node_b: &Node<'a, 'b, 'c>) -> msgs::DynPropose {
+	let stfu = get_event_msg!(node_a, MessageSendEvent::SendStfu, node_b.node.get_our_node_id());
+	node_b.node.handle_stfu(&node_a.node.get_our_node_id(), &stfu);
+	let stfu = get_event_msg!(node_b, MessageSendEvent::SendStfu, node_a.node.get_our_node_id());
+	node_a.node.handle_stfu(&node_b.node.get_our_node_id(), &stfu);
+	get_event_msg!(node_a, MessageSendEvent::SendDynPropose, node_b.node.get_our_node_id())
+}
+
+/// Delivers `node_a`'s `commitment_signed` for the first commitment transaction using the new
+/// channel type and completes the resulting commitment dance.
+fn complete_upgrade<'a, 'b, 'c>(node_a: &Node<'a, 'b, 'c>, node_b: &Node<'a, 'b, 'c>, commitment_signed: &msgs::CommitmentSigned) {
+	node_b.node.handle_commitment_signed(&node_a.node.get_our_node_id(), commitment_signed);
+	check_added_monitors!(node_b, 1);
+	let (bs_revoke_and_ack, bs_commitment_signed) = get_revoke_commit_msgs!(node_b, node_a.node.get_our_node_id());
+	node_a.node.handle_revoke_and_ack(&node_b.node.get_our_node_id(), &bs_revoke_and_ack);
+	check_added_monitors!(node_a, 1);
+	node_a.node.handle_commitment_signed(&node_b.node.get_our_node_id(), &bs_commitment_signed);
+	check_added_monitors!(node_a, 1);
+	let as_revoke_and_ack = get_event_msg!(node_a, MessageSendEvent::SendRevokeAndACK, node_b.node.get_our_node_id());
+	node_b.node.handle_revoke_and_ack(&node_a.node.get_our_node_id(), &as_revoke_and_ack);
+	check_added_monitors!(node_b, 1);
+}
+
+/// Returns the pending messages of `node` other than any `channel_update`s sent after
+/// reestablishing the channel.
+fn get_non_update_msg_events<'a, 'b, 'c>(node: &Node<'a, 'b, 'c>) -> Vec<MessageSendEvent> {
+	node.node.get_and_clear_pending_msg_events().into_iter()
+		.filter(|event| !matches!(event, MessageSendEvent::SendChannelUpdate { .. }))
+		.collect()
+}
+
+/// Reconnects the two nodes, which must have been disconnected, and delivers their
+/// `channel_reestablish` messages, leaving any messages generated in response pending.
+fn reconnect_and_reestablish<'a, 'b, 'c>(node_a: &Node<'a, 'b, 'c>, node_b: &Node<'a, 'b, 'c>) {
+	node_a.node.peer_connected(&node_b.node.get_our_node_id(), &msgs::Init {
+		features: node_b.node.init_features(), networks: None, remote_network_address: None
+	}, true).unwrap();
+	let reestablish_a = get_chan_reestablish_msgs!(node_a, node_b);
+	node_b.node.peer_connected(&node_a.node.get_our_node_id(), &msgs::Init {
+		features: node_a.node.init_features(), networks: None, remote_network_address: None
+	}, false).unwrap();
+	let reestablish_b = get_chan_reestablish_msgs!(node_b, node_a);
+	assert_eq!(reestablish_a.len(), 1);
+	assert_eq!(reestablish_b.len(), 1);
+
+	node_b.node.handle_channel_reestablish(&node_a.node.get_our_node_id(), &reestablish_a[0]);
+	node_a.node.handle_channel_reestablish(&node_b.node.get_our_node_id(), &reestablish_b[0]);
+}
+
+#[test]
+fn test_upgrade_channel_to_anchors() {
+	let chanmon_cfgs = create_chanmon_cfgs(2);
+	let node_cfgs = create_node_cfgs(2, &chanmon_cfgs);
+	let node_chanmgrs = create_node_chanmgrs(2, &node_cfgs, &[Some(upgrade_config()), Some(upgrade_config())]);
+	let nodes = create_network(2, &node_cfgs, &node_chanmgrs);
+	let (channel_id, funding_tx) = create_legacy_announced_chan(&nodes, 0, 1);
+	expect_anchors(&nodes[0], &channel_id, false);
+	expect_anchors(&nodes[1], &channel_id, false);
+
+	// Keep a commitment transaction of nodes[0] with an HTLC from before the upgrade around, so
+	// that we can check nodes[1] is still able to claim it once revoked.
+	let payment_preimage = route_payment(&nodes[0], &[&nodes[1]], 3_000_000).0;
+	let revoked_commitment_txn = get_local_commitment_txn!(nodes[0], channel_id);
+	assert_eq!(num_anchor_outputs(&revoked_commitment_txn[0]), 0);
+
+	// The upgrade is only possible without any HTLCs pending.
+	match nodes[0].node.upgrade_channel_to_anchors(&channel_id, &nodes[1].node.get_our_node_id()) {
+		Err(APIError::ChannelUnavailable { .. }) => {},
+		_ => panic!("Unexpected result"),
+	}
+	claim_payment(&nodes[0], &[&nodes[1]], payment_preimage);
+
+	nodes[0].node.upgrade_channel_to_anchors(&channel_id, &nodes[1].node.get_our_node_id()).unwrap();
+	match nodes[0].node.upgrade_channel_to_anchors(&channel_id, &nodes[1].node.get_our_node_id()) {
+		Err(APIError::APIMisuseError { .. }) => {},
+		_ => panic!("Unexpected result"),
+	}
+	let dyn_propose = quiesce_for_upgrade(&nodes[0], &nodes[1]);
+	assert!(dyn_propose.channel_type.requires_anchors_zero_fee_htlc_tx());
+
+	nodes[1].node.handle_dyn_propose(&nodes[0].node.get_our_node_id(), &dyn_propose);
+	let dyn_ack = get_event_msg!(nodes[1], MessageSendEvent::SendDynAck, nodes[0].node.get_our_node_id());
+	// nodes[1] only switches over once it receives nodes[0]'s commitment_signed.
+	expect_anchors(&nodes[1], &channel_id, false);
+
+	nodes[0].node.handle_dyn_ack(&nodes[1].node.get_our_node_id(), &dyn_ack);
+	check_added_monitors!(nodes[0], 1);
+	let updates = get_htlc_update_msgs!(nodes[0], nodes[1].node.get_our_node_id());
+	assert!(updates.update_add_htlcs.is_empty() && updates.update_fee.is_none());
+	complete_upgrade(&nodes[0], &nodes[1], &updates.commitment_signed);
+	expect_anchors(&nodes[0], &channel_id, true);
+	expect_anchors(&nodes[1], &channel_id, true);
+	assert_eq!(nodes[0].node.list_channels()[0].channel_quiescence_state, Some(ChannelQuiescenceState::NotQuiescent));
+	assert_eq!(nodes[1].node.list_channels()[0].channel_quiescence_state, Some(ChannelQuiescenceState::NotQuiescent));
+
+	// The channel keeps operating as usual, now with anchor outputs on both commitment
+	// transactions.
+	send_payment(&nodes[0], &[&nodes[1]], 1_000_000);
+	send_payment(&nodes[1], &[&nodes[0]], 500_000);
+	assert_eq!(num_anchor_outputs(&get_local_commitment_txn!(nodes[0], channel_id)[0]), 2);
+	assert_eq!(num_anchor_outputs(&get_local_commitment_txn!(nodes[1], channel_id)[0]), 2);
+	match nodes[0].node.upgrade_channel_to_anchors(&channel_id, &nodes[1].node.get_our_node_id()) {
+		Err(APIError::ChannelUnavailable { .. }) => {},
+		_ => panic!("Unexpected result"),
+	}
+
+	// Should nodes[0] broadcast its revoked commitment transaction from before the upgrade,
+	// nodes[1] still claims both its output and the HTLC output in the legacy format.
+	mine_transaction(&nodes[1], &revoked_commitment_txn[0]);
+	check_closed_broadcast!(nodes[1], true);
+	check_added_monitors!(nodes[1], 1);
+	check_closed_event!(nodes[1], 1, ClosureReason::CommitmentTxConfirmed);
+	let node_txn = nodes[1].tx_broadcaster.txn_broadcasted.lock().unwrap().clone();
+	assert_eq!(node_txn.len(), 1);
+	assert_eq!(node_txn[0].input.len(), 2);
+	check_spends!(node_txn[0], revoked_commitment_txn[0]);
+	check_spends!(revoked_commitment_txn[0], funding_tx);
+}
+
+#[test]
+fn test_channel_type_upgrade_rejected() {
+	let chanmon_cfgs = create_chanmon_cfgs(3);
+	let node_cfgs = create_node_cfgs(3, &chanmon_cfgs);
+	let mut no_reserve_config = upgrade_config();
+	no_reserve_config.anchor_channel_reserve_config.refuse_channels_without_reserve = true;
+	let node_chanmgrs = create_node_chanmgrs(3, &node_cfgs, &[Some(upgrade_config()), Some(no_reserve_config), None]);
+	let nodes = create_network(3, &node_cfgs, &node_chanmgrs);
+	let channel_id = create_legacy_announced_chan(&nodes, 0, 1).0;
+	let other_channel_id = create_legacy_announced_chan(&nodes, 0, 2).0;
+	send_payment(&nodes[0], &[&nodes[1]], 10_000_000);
+
+	// nodes[2] doesn't support anchor outputs at all.
+	match nodes[0].node.upgrade_channel_to_anchors(&other_channel_id, &nodes[2].node.get_our_node_id()) {
+		Err(APIError::ChannelUnavailable { .. }) => {},
+		_ => panic!("Unexpected result"),
+	}
+
+	// nodes[1] lacks the on-chain funds to bump the transactions of another anchor channel, so it
+	// rejects the upgrade, after which the channel is no longer quiescent.
+	nodes[0].node.upgrade_channel_to_anchors(&channel_id, &nodes[1].node.get_our_node_id()).unwrap();
+	let dyn_propose = quiesce_for_upgrade(&nodes[0], &nodes[1]);
+	nodes[1].node.handle_dyn_propose(&nodes[0].node.get_our_node_id(), &dyn_propose);
+	let dyn_reject = get_event_msg!(nodes[1], MessageSendEvent::SendDynReject, nodes[0].node.get_our_node_id());
+	nodes[0].node.handle_dyn_reject(&nodes[1].node.get_our_node_id(), &dyn_reject);
+	assert!(nodes[0].node.get_and_clear_pending_msg_events().is_empty());
+	expect_anchors(&nodes[0], &channel_id, false);
+	expect_anchors(&nodes[1], &channel_id, false);
+	send_payment(&nodes[1], &[&nodes[0]], 1_000_000);
+
+	// A further dyn_reject is a protocol violation.
+	nodes[0].node.handle_dyn_reject(&nodes[1].node.get_our_node_id(), &dyn_reject);
+	let events = nodes[0].node.get_and_clear_pending_msg_events();
+	assert_eq!(events.len(), 1);
+	match events[0] {
+		MessageSendEvent::HandleError { action: msgs::ErrorAction::DisconnectPeerWithWarning { .. }, .. } => {},
+		_ => panic!("Unexpected event"),
+	}
+
+	// Once nodes[1] has the funds, the upgrade succeeds.
+	let wallet = test_utils::TestWalletSource::new(SecretKey::from_slice(&[42; 32]).unwrap());
+	wallet.add_utxo(OutPoint { txid: Txid::from_inner([42; 32]), vout: 0 }, 1_000_000);
+	nodes[1].node.update_anchor_channel_reserve_funds(&wallet).unwrap();
+	nodes[0].node.upgrade_channel_to_anchors(&channel_id, &nodes[1].node.get_our_node_id()).unwrap();
+	let dyn_propose = quiesce_for_upgrade(&nodes[0], &nodes[1]);
+	nodes[1].node.handle_dyn_propose(&nodes[0].node.get_our_node_id(), &dyn_propose);
+	let dyn_ack = get_event_msg!(nodes[1], MessageSendEvent::SendDynAck, nodes[0].node.get_our_node_id());
+	nodes[0].node.handle_dyn_ack(&nodes[1].node.get_our_node_id(), &dyn_ack);
+	check_added_monitors!(nodes[0], 1);
+	let updates = get_htlc_update_msgs!(nodes[0], nodes[1].node.get_our_node_id());
+	complete_upgrade(&nodes[0], &nodes[1], &updates.commitment_signed);
+	expect_anchors(&nodes[0], &channel_id, true);
+	expect_anchors(&nodes[1], &channel_id, true);
+	send_payment(&nodes[0], &[&nodes[1]], 1_000_000);
+}
+
+#[test]
+fn test_channel_type_upgrade_reconnect() {
+	// Tests that an upgrade which wasn't accepted before a disconnection is proposed again upon
+	// reconnection, and that one which was survives a restart of the accepting node.
+	let chanmon_cfgs = create_chanmon_cfgs(2);
+	let node_cfgs = create_node_cfgs(2, &chanmon_cfgs);
+	let persister;
+	let new_chain_monitor;
+	let node_chanmgrs = create_node_chanmgrs(2, &node_cfgs, &[Some(upgrade_config()), Some(upgrade_config())]);
+	let nodes_1_deserialized;
+	let mut nodes = create_network(2, &node_cfgs, &node_chanmgrs);
+	let channel_id = create_legacy_announced_chan(&nodes, 0, 1).0;
+	send_payment(&nodes[0], &[&nodes[1]], 10_000_000);
+
+	// nodes[1] accepts the upgrade, but nodes[0] never receives its dyn_ack, so nodes[1] abandons
+	// the upgrade upon reconnection while nodes[0] proposes it again.
+	nodes[0].node.upgrade_channel_to_anchors(&channel_id, &nodes[1].node.get_our_node_id()).unwrap();
+	let dyn_propose = quiesce_for_upgrade(&nodes[0], &nodes[1]);
+	nodes[1].node.handle_dyn_propose(&nodes[0].node.get_our_node_id(), &dyn_propose);
+	get_event_msg!(nodes[1], MessageSendEvent::SendDynAck, nodes[0].node.get_our_node_id());
+
+	nodes[0].node.peer_disconnected(&nodes[1].node.get_our_node_id());
+	nodes[1].node.peer_disconnected(&nodes[0].node.get_our_node_id());
+	reconnect_and_reestablish(&nodes[0], &nodes[1]);
+	assert!(get_non_update_msg_events(&nodes[1]).is_empty());
+	let mut events = get_non_update_msg_events(&nodes[0]);
+	assert_eq!(events.len(), 1);
+	let stfu = match events.remove(0) {
+		MessageSendEvent::SendStfu { msg, .. } => msg,
+		_ => panic!("Unexpected event"),
+	};
+	nodes[1].node.handle_stfu(&nodes[0].node.get_our_node_id(), &stfu);
+	let stfu = get_event_msg!(nodes[1], MessageSendEvent::SendStfu, nodes[0].node.get_our_node_id());
+	nodes[0].node.handle_stfu(&nodes[1].node.get_our_node_id(), &stfu);
+	let dyn_propose = get_event_msg!(nodes[0], MessageSendEvent::SendDynPropose, nodes[1].node.get_our_node_id());
+
+	// This time nodes[0] switches over to anchor outputs, but nodes[1] restarts before receiving
+	// its commitment_signed. nodes[0] sends it again upon reconnection.
+	nodes[1].node.handle_dyn_propose(&nodes[0].node.get_our_node_id(), &dyn_propose);
+	let dyn_ack = get_event_msg!(nodes[1], MessageSendEvent::SendDynAck, nodes[0].node.get_our_node_id());
+	nodes[0].node.handle_dyn_ack(&nodes[1].node.get_our_node_id(), &dyn_ack);
+	check_added_monitors!(nodes[0], 1);
+	get_htlc_update_msgs!(nodes[0], nodes[1].node.get_our_node_id());
+	// nodes[0]'s ChannelMonitor only switches over along with its first commitment transaction
+	// using anchor outputs.
+	assert!(nodes[0].node.list_channels()[0].channel_type.as_ref().unwrap().supports_anchors_zero_fee_htlc_tx());
+	assert!(!get_monitor!(nodes[0], channel_id).inner.lock().unwrap().onchain_tx_handler.channel_type_features().supports_anchors_zero_fee_htlc_tx());
+
+	nodes[0].node.peer_disconnected(&nodes[1].node.get_our_node_id());
+	let monitor_serialized = get_monitor!(nodes[1], channel_id).encode();
+	reload_node!(nodes[1], upgrade_config(), &nodes[1].node.encode(), &[&monitor_serialized], persister, new_chain_monitor, nodes_1_deserialized);
+	reconnect_and_reestablish(&nodes[0], &nodes[1]);
+	assert!(get_non_update_msg_events(&nodes[1]).is_empty());
+	let mut events = get_non_update_msg_events(&nodes[0]);
+	assert_eq!(events.len(), 1);
+	let commitment_signed = match events.remove(0) {
+		MessageSendEvent::UpdateHTLCs { updates, .. } => updates.commitment_signed,
+		_ => panic!("Unexpected event"),
+	};
+	complete_upgrade(&nodes[0], &nodes[1], &commitment_signed);
+	expect_anchors(&nodes[0], &channel_id, true);
+	expect_anchors(&nodes[1], &channel_id, true);
+	send_payment(&nodes[0], &[&nodes[1]], 1_000_000);
+	send_payment(&nodes[1], &[&nodes[0]], 1_000_000);
+
+	assert_eq!(num_anchor_outputs(&get_local_commitment_txn!(nodes[0], channel_id)[0]), 2);
+	assert_eq!(num_anchor_outputs(&get_local_commitment_txn!(nodes[1], channel_id)[0]), 2);
+}
+
+#[test]
+fn test_channel_type_upgrade_reverted() {
+	// Tests that the initiator of an upgrade reverts to the previous channel type if the accepting
+	// node restarts without having persisted its acceptance, even across a restart of its own.
+	let chanmon_cfgs = create_chanmon_cfgs(2);
+	let node_cfgs = create_node_cfgs(2, &chanmon_cfgs);
+	let (persister_a, persister_b);
+	let (new_chain_monitor_a, new_chain_monitor_b);
+	let node_chanmgrs = create_node_chanmgrs(2, &node_cfgs, &[Some(upgrade_config()), Some(upgrade_config())]);
+	let (nodes_0_deserialized, nodes_1_deserialized);
+	let mut nodes = create_network(2, &node_cfgs, &node_chanmgrs);
+	let channel_id = create_legacy_announced_chan(&nodes, 0, 1).0;
+	send_payment(&nodes[0], &[&nodes[1]], 10_000_000);
+
+	nodes[0].node.upgrade_channel_to_anchors(&channel_id, &nodes[1].node.get_our_node_id()).unwrap();
+	let dyn_propose = quiesce_for_upgrade(&nodes[0], &nodes[1]);
+	let nodes_1_serialized = nodes[1].node.encode();
+	nodes[1].node.handle_dyn_propose(&nodes[0].node.get_our_node_id(), &dyn_propose);
+	let dyn_ack = get_event_msg!(nodes[1], MessageSendEvent::SendDynAck, nodes[0].node.get_our_node_id());
+	nodes[0].node.handle_dyn_ack(&nodes[1].node.get_our_node_id(), &dyn_ack);
+	check_added_monitors!(nodes[0], 1);
+	get_htlc_update_msgs!(nodes[0], nodes[1].node.get_our_node_id());
+	assert!(nodes[0].node.list_channels()[0].channel_type.as_ref().unwrap().supports_anchors_zero_fee_htlc_tx());
+	let upgraded_counterparty_commitment_txid = get_monitor!(nodes[0], channel_id).get_cur_counterparty_commitment_txid();
+
+	// Both nodes restart, with nodes[1] doing so from before it accepted the upgrade.
+	nodes[0].node.peer_disconnected(&nodes[1].node.get_our_node_id());
+	nodes[1].node.peer_disconnected(&nodes[0].node.get_our_node_id());
+	let monitor_serialized = get_monitor!(nodes[0], channel_id).encode();
+	reload_node!(nodes[0], upgrade_config(), &nodes[0].node.encode(), &[&monitor_serialized], persister_a, new_chain_monitor_a, nodes_0_deserialized);
+	let monitor_serialized = get_monitor!(nodes[1], channel_id).encode();
+	reload_node!(nodes[1], upgrade_config(), &nodes_1_serialized, &[&monitor_serialized], persister_b, new_chain_monitor_b, nodes_1_deserialized);
+
+	// nodes[0] reverts to the previous channel type, sending its commitment_signed again using it
+	// once its `ChannelMonitor` is tracking the rebuilt counterparty commitment transaction.
+	reconnect_and_reestablish(&nodes[0], &nodes[1]);
+	check_added_monitors!(nodes[0], 1);
+	assert!(get_non_update_msg_events(&nodes[1]).is_empty());
+	let mut events = get_non_update_msg_events(&nodes[0]);
+	assert_eq!(events.len(), 1);
+	let commitment_signed = match events.remove(0) {
+		MessageSendEvent::UpdateHTLCs { updates, .. } => updates.commitment_signed,
+		_ => panic!("Unexpected event"),
+	};
+	// As nothing changed for nodes[1], it only revokes its previous commitment transaction.
+	nodes[1].node.handle_commitment_signed(&nodes[0].node.get_our_node_id(), &commitment_signed);
+	check_added_monitors!(nodes[1], 1);
+	let reverted_counterparty_commitment_txid = get_monitor!(nodes[0], channel_id).get_cur_counterparty_commitment_txid();
+	assert_ne!(reverted_counterparty_commitment_txid, upgraded_counterparty_commitment_txid);
+	assert_eq!(reverted_counterparty_commitment_txid, Some(get_local_commitment_txn!(nodes[1], channel_id)[0].txid()));
+	let bs_revoke_and_ack = get_event_msg!(nodes[1], MessageSendEvent::SendRevokeAndACK, nodes[0].node.get_our_node_id());
+	nodes[0].node.handle_revoke_and_ack(&nodes[1].node.get_our_node_id(), &bs_revoke_and_ack);
+	check_added_monitors!(nodes[0], 1);
+	expect_anchors(&nodes[0], &channel_id, false);
+	expect_anchors(&nodes[1], &channel_id, false);
+	assert_eq!(nodes[0].node.list_channels()[0].channel_quiescence_state, Some(ChannelQuiescenceState::NotQuiescent));
+	assert_eq!(nodes[1].node.list_channels()[0].channel_quiescence_state, Some(ChannelQuiescenceState::NotQuiescent));
+	send_payment(&nodes[0], &[&nodes[1]], 1_000_000);
+	send_payment(&nodes[1], &[&nodes[0]], 1_000_000);
+
+	assert_eq!(num_anchor_outputs(&get_local_commitment_txn!(nodes[0], channel_id)[0]), 0);
+	assert_eq!(num_anchor_outputs(&get_local_commitment_txn!(nodes[1], channel_id)[0]), 0);
+}
@@ -18,30 +18,37 @@
 //! imply it needs to fail HTLCs/payments/channels it manages).
 
 use bitcoin::blockdata::block::BlockHeader;
-use bitcoin::blockdata::transaction::Transaction;
+use bitcoin::blockdata::transaction::{Transaction, TxIn};
 use bitcoin::blockdata::constants::{genesis_block, ChainHash};
 use bitcoin::network::constants::Network;
 
-use bitcoin::hashes::Hash;
+use bitcoin::hashes::{Hash, HashEngine};
+use bitcoin::hashes::hmac::{Hmac, HmacEngine};
 use bitcoin::hashes::sha256::Hash as Sha256;
 use bitcoin::hash_types::{BlockHash, Txid};
 
 use bitcoin::secp256k1::{SecretKey,PublicKey};
+use bitcoin::secp256k1::scalar::Scalar;
 use bitcoin::secp256k1::Secp256k1;
 use bitcoin::{LockTime, secp256k1, Sequence};
 
+use crate::blinded_path::BlindedPath;
+use crate::blinded_path::payment;
+use crate::blinded_path::payment::{PaymentConstraints, ReceiveTlvs};
 use crate::chain;
 use crate::chain::{Confirm, ChannelMonitorUpdateStatus, Watch, BestBlock};
 use crate::chain::chaininterface::{BroadcasterInterface, ConfirmationTarget, FeeEstimator, LowerBoundedFeeEstimator};
 use crate::chain::channelmonitor::{ChannelMonitor, ChannelMonitorUpdate, ChannelMonitorUpdateStep, HTLC_FAIL_BACK_BUFFER, CLTV_CLAIM_BUFFER, LATENCY_GRACE_PERIOD_BLOCKS, ANTI_REORG_DELAY, MonitorEvent, CLOSED_CHANNEL_UPDATE_ID};
 use crate::chain::transaction::{OutPoint, TransactionData};
 use crate::events;
-use crate::events::{Event, EventHandler, EventsProvider, MessageSendEvent, MessageSendEventsProvider, ClosureReason, HTLCDestination, PaymentFailureReason};
+use crate::events::bump_transaction::{anchor_channel_reserve_sat, WalletSource};
+use crate::events::{Event, EventHandler, EventsProvider, MessageSendEvent, ReplayEvent, MessageSendEventsProvider, ClosureReason, HTLCDestination, InboundChannelFunds, PaymentFailureReason};
 // Since this struct is returned in `list_channels` methods, expose it here in case users want to
 // construct one themselves.
 use crate::ln::{inbound_payment, PaymentHash, PaymentPreimage, PaymentSecret};
-use crate::ln::channel::{Channel, ChannelContext, ChannelError, ChannelUpdateStatus, ShutdownResult, UnfundedChannelContext, UpdateFulfillCommitFetch, OutboundV1Channel, InboundV1Channel};
+use crate::ln::channel::{Channel, ChannelContext, ChannelError, ChannelUpdateStatus, ShutdownResult, UnfundedChannelContext, UpdateFulfillCommitFetch, OutboundV1Channel, InboundV1Channel, OutboundV2Channel, InboundV2Channel, InteractivelyFunded};
 use crate::ln::features::{ChannelFeatures, ChannelTypeFeatures, InitFeatures, NodeFeatures};
+use crate::ln::interactivetxs::{AbortReason, InteractiveTxMessageSend};
 #[cfg(any(feature = "_test_utils", test))]
 use crate::ln::features::Bolt11InvoiceFeatures;
 use crate::routing::gossip::NetworkGraph;
@@ -49,18 +56,24 @@ use crate::routing::router::{BlindedTail, DefaultRouter, InFlightHtlcs, Path, Pa
 use crate::routing::scoring::{ProbabilisticScorer, ProbabilisticScoringFeeParameters};
 use crate::ln::msgs;
 use crate::ln::onion_utils;
-use crate::ln::onion_utils::HTLCFailReason;
+use crate::ln::onion_utils::{HTLCFailReason, INVALID_ONION_BLINDING};
 use crate::ln::msgs::{ChannelMessageHandler, DecodeError, LightningError};
 #[cfg(test)]
 use crate::ln::outbound_payment;
-use crate::ln::outbound_payment::{OutboundPayments, PaymentAttempts, PendingOutboundPayment, SendAlongPathArgs};
+use crate::ln::outbound_payment::{Bolt12PaymentError, OutboundPayments, PaymentAttempts, PendingOutboundPayment, SendAlongPathArgs, StaleExpiration};
 use crate::ln::wire::Encode;
+use crate::offers::invoice::{BlindedPayInfo, Bolt12Invoice, DerivedSigningPubkey, InvoiceBuilder, DEFAULT_RELATIVE_EXPIRY};
+use crate::offers::invoice_error::InvoiceError;
+use crate::offers::offer::{DerivedMetadata, Offer, OfferBuilder};
+use crate::offers::parse::Bolt12SemanticError;
+use crate::offers::refund::{Refund, RefundBuilder};
+use crate::onion_message::{Destination, OffersMessage, OffersMessageHandler, PendingOnionMessage};
 use crate::sign::{EntropySource, KeysManager, NodeSigner, Recipient, SignerProvider, ChannelSigner, WriteableEcdsaChannelSigner};
 use crate::util::config::{UserConfig, ChannelConfig, ChannelConfigUpdate};
 use crate::util::wakers::{Future, Notifier};
 use crate::util::scid_utils::fake_scid;
 use crate::util::string::UntrustedString;
-use crate::util::ser::{BigSize, FixedLengthReader, Readable, ReadableArgs, MaybeReadable, Writeable, Writer, VecWriter};
+use crate::util::ser::{BigSize, FixedLengthReader, Readable, ReadableArgs, MaybeReadable, TransactionU16LenLimited, Writeable, Writer, VecWriter};
 use crate::util::logger::{Level, Logger};
 use crate::util::errors::APIError;
 
@@ -104,12 +117,17 @@ pub(super) enum PendingHTLCRouting {
 		/// The SCID from the onion that we should forward to. This could be a real SCID or a fake one
 		/// generated using `get_fake_scid` from the scid_utils::fake_scid module.
 		short_channel_id: u64, // This should be NonZero<u64> eventually when we bump MSRV
+		/// Set if this HTLC is being forwarded within a blinded path.
+		blinded: Option<BlindedForward>,
 	},
 	Receive {
 		payment_data: msgs::FinalOnionHopData,
 		payment_metadata: Option<Vec<u8>>,
 		incoming_cltv_expiry: u32, // Used to track when we should expire pending HTLCs that go unclaimed
 		phantom_shared_secret: Option<[u8; 32]>,
+		/// Set if this HTLC is being received via a blinded path we aren't the introduction node of,
+		/// in which case any failure must be returned with `update_fail_malformed_htlc`.
+		requires_blinded_error: bool,
 	},
 	ReceiveKeysend {
 		/// This was added in 0.0.116 and will break deserialization on downgrades.
@@ -120,6 +138,29 @@ pub(super) enum PendingHTLCRouting {
 	},
 }
 
+/// Information used to forward or fail this HTLC that is being forwarded within a blinded path.
+#[derive(Clone, Copy, Hash, PartialEq, Eq)]
+pub(super) struct BlindedForward {
+	/// The `blinding_point` that was set in the inbound [`msgs::UpdateAddHTLC`], or in the inbound
+	/// onion payload if we're the introduction node. Useful for calculating the next hop's
+	/// [`msgs::UpdateAddHTLC::blinding_point`].
+	pub inbound_blinding_point: PublicKey,
+	/// How to fail this HTLC if it can't be forwarded, depending on whether we're the introduction
+	/// node of the blinded path.
+	pub failure: BlindedFailure,
+}
+
+impl PendingHTLCRouting {
+	// Used to override the onion failure code and data if the HTLC is blinded.
+	fn blinded_failure(&self) -> Option<BlindedFailure> {
+		match self {
+			Self::Forward { blinded: Some(BlindedForward { failure, .. }), .. } => Some(*failure),
+			Self::Receive { requires_blinded_error: true, .. } => Some(BlindedFailure::FromBlindedNode),
+			_ => None,
+		}
+	}
+}
+
 #[derive(Clone)] // See Channel::revoke_and_ack for why, tl;dr: Rust bug
 pub(super) struct PendingHTLCInfo {
 	pub(super) routing: PendingHTLCRouting,
@@ -170,6 +211,23 @@ pub(super) enum HTLCForwardInfo {
 		htlc_id: u64,
 		err_packet: msgs::OnionErrorPacket,
 	},
+	FailMalformedHTLC {
+		htlc_id: u64,
+		failure_code: u16,
+		sha256_of_onion: [u8; 32],
+	},
+}
+
+/// Whether this blinded HTLC is being failed backwards by the introduction node or a blinded node,
+/// which determines the failure message that should be used.
+#[derive(Clone, Copy, Hash, PartialEq, Eq)]
+pub(crate) enum BlindedFailure {
+	/// This HTLC is being failed backwards by the introduction node, and thus should be failed with
+	/// [`msgs::UpdateFailHTLC`] and error code `0x8000|0x4000|24`.
+	FromIntroductionNode,
+	/// This HTLC is being failed backwards by a blinded node within the path, and thus should be
+	/// failed with [`msgs::UpdateFailMalformedHTLC`] and error code `0x8000|0x4000|24`.
+	FromBlindedNode,
 }
 
 /// Tracks the inbound corresponding to an outbound HTLC
@@ -180,6 +238,7 @@ pub(crate) struct HTLCPreviousHopData {
 	htlc_id: u64,
 	incoming_packet_shared_secret: [u8; 32],
 	phantom_shared_secret: Option<[u8; 32]>,
+	blinded_failure: Option<BlindedFailure>,
 
 	// This field is consumed by `claim_funds_from_hop()` when updating a force-closed backwards
 	// channel with a preimage provided by the forward channel.
@@ -221,7 +280,12 @@ struct ClaimableHTLC {
 ///
 /// This is not exported to bindings users as we just use [u8; 32] directly
 #[derive(Hash, Copy, Clone, PartialEq, Eq, Debug)]
-pub struct PaymentId(pub [u8; 32]);
+pub struct PaymentId(pub [u8; Self::LENGTH]);
+
+impl PaymentId {
+	/// Number of bytes in the id.
+	pub const LENGTH: usize = 32;
+}
 
 impl Writeable for PaymentId {
 	fn write<W: Writer>(&self, w: &mut W) -> Result<(), io::Error> {
@@ -415,6 +479,15 @@ impl MsgHandleErrInternal {
 		}
 	}
 	#[inline]
+	fn from_tx_abort(err: String, channel_id: [u8; 32], user_channel_id: u128, shutdown_res: ShutdownResult) -> Self {
+		Self {
+			// Our counterparty is informed via the `tx_abort` we send them.
+			err: LightningError { err, action: msgs::ErrorAction::IgnoreError },
+			chan_id: Some((channel_id, user_channel_id)),
+			shutdown_finish: Some((shutdown_res, None)),
+		}
+	}
+	#[inline]
 	fn from_chan_no_close(err: ChannelError, channel_id: [u8; 32]) -> Self {
 		Self {
 			err: match err {
@@ -428,6 +501,15 @@ impl MsgHandleErrInternal {
 						log_level: Level::Warn,
 					},
 				},
+				ChannelError::WarnAndDisconnect(msg) => LightningError {
+					err: msg.clone(),
+					action: msgs::ErrorAction::DisconnectPeerWithWarning {
+						msg: msgs::WarningMessage {
+							channel_id,
+							data: msg
+						},
+					},
+				},
 				ChannelError::Ignore(msg) => LightningError {
 					err: msg,
 					action: msgs::ErrorAction::IgnoreError,
@@ -635,6 +717,18 @@ pub(super) struct PeerState<Signer: ChannelSigner> {
 	/// been assigned a `channel_id`, the entry in this map is removed and one is created in
 	/// `channel_by_id`.
 	pub(super) inbound_v1_channel_by_id: HashMap<[u8; 32], InboundV1Channel<Signer>>,
+	/// `channel_id` -> `OutboundV2Channel`.
+	///
+	/// Holds all outbound V2 channels where the peer is the counterparty. Channels are keyed by
+	/// their temporary channel id until the counterparty accepts the channel, and are moved to
+	/// `channel_by_id` once the funding transaction has been negotiated.
+	pub(super) outbound_v2_channel_by_id: HashMap<[u8; 32], OutboundV2Channel<Signer>>,
+	/// `channel_id` -> `InboundV2Channel`.
+	///
+	/// Holds all inbound V2 channels where the peer is the counterparty. Channels are keyed by
+	/// their temporary channel id until we accept the channel, and are moved to `channel_by_id`
+	/// once the funding transaction has been negotiated.
+	pub(super) inbound_v2_channel_by_id: HashMap<[u8; 32], InboundV2Channel<Signer>>,
 	/// The latest `InitFeatures` we heard from the peer.
 	latest_features: InitFeatures,
 	/// Messages to send to the peer - pushed to in the same lock that they are generated in (except
@@ -689,14 +783,18 @@ impl <Signer: ChannelSigner> PeerState<Signer> {
 	fn total_channel_count(&self) -> usize {
 		self.channel_by_id.len() +
 			self.outbound_v1_channel_by_id.len() +
-			self.inbound_v1_channel_by_id.len()
+			self.inbound_v1_channel_by_id.len() +
+			self.outbound_v2_channel_by_id.len() +
+			self.inbound_v2_channel_by_id.len()
 	}
 
 	// Returns a bool indicating if the given `channel_id` matches a channel we have with this peer.
 	fn has_channel(&self, channel_id: &[u8; 32]) -> bool {
 		self.channel_by_id.contains_key(channel_id) ||
 			self.outbound_v1_channel_by_id.contains_key(channel_id) ||
-			self.inbound_v1_channel_by_id.contains_key(channel_id)
+			self.inbound_v1_channel_by_id.contains_key(channel_id) ||
+			self.outbound_v2_channel_by_id.contains_key(channel_id) ||
+			self.inbound_v2_channel_by_id.contains_key(channel_id)
 	}
 }
 
@@ -915,6 +1013,8 @@ where
 //  |           |__`peer_state`
 //  |               |
 //  |               |__`id_to_peer`
+//  |               |   |
+//  |               |   |__`funding_txo_to_channel_id`
 //  |               |
 //  |               |__`short_to_chan_info`
 //  |               |
@@ -922,6 +1022,8 @@ where
 //  |               |
 //  |               |__`best_block`
 //  |               |
+//  |               |__`funding_batch_states`
+//  |               |
 //  |               |__`pending_events`
 //  |                   |
 //  |                   |__`pending_background_events`
@@ -1032,6 +1134,18 @@ where
 	/// See `ChannelManager` struct-level documentation for lock order requirements.
 	id_to_peer: Mutex<HashMap<[u8; 32], PublicKey>>,
 
+	/// Funding outpoint -> `channel_id`, for channels whose `channel_id` is not derived from their
+	/// funding outpoint, i.e. channels established using V2 channel establishment.
+	///
+	/// As with `id_to_peer`, this map is needed to find the channel a `MonitorEvent` refers to, as
+	/// `ChannelMonitor`s only know their funding outpoint. Channels not in this map have a
+	/// `channel_id` of [`OutPoint::to_channel_id`].
+	///
+	/// Entries are kept after a channel closes, as its `ChannelMonitor` may still generate events.
+	///
+	/// See `ChannelManager` struct-level documentation for lock order requirements.
+	funding_txo_to_channel_id: Mutex<HashMap<OutPoint, [u8; 32]>>,
+
 	/// SCIDs (and outbound SCID aliases) -> `counterparty_node_id`s and `channel_id`s.
 	///
 	/// Outbound SCID aliases are added here once the channel is available for normal use, with
@@ -1101,6 +1215,13 @@ where
 	/// A simple atomic flag to ensure only one task at a time can be processing events asynchronously.
 	pending_events_processor: AtomicBool,
 
+	/// BOLT 12 Offers messages which are waiting to be sent via an [`OnionMessenger`], either
+	/// initiating a payment flow or responding to a [`Refund`].
+	///
+	/// [`OnionMessenger`]: crate::onion_message::OnionMessenger
+	/// [`Refund`]: crate::offers::refund::Refund
+	pending_offers_messages: Mutex<Vec<PendingOnionMessage<OffersMessage>>>,
+
 	/// If we are running during init (either directly during the deserialization method or in
 	/// block connection methods which run after deserialization but before normal operation) we
 	/// cannot provide the user with [`ChannelMonitorUpdate`]s through the normal update flow -
@@ -1113,6 +1234,19 @@ where
 	///
 	/// [`ChainMonitor`]: crate::chain::chainmonitor::ChainMonitor
 	pending_background_events: Mutex<Vec<BackgroundEvent>>,
+	/// The channels funded by each batch funding transaction which has not yet been broadcast,
+	/// keyed by the funding transaction's txid. For each channel, we track whether our
+	/// counterparty's `funding_signed` has been received and the initial [`ChannelMonitor`]
+	/// persisted, broadcasting the funding transaction once this is the case for all channels.
+	///
+	/// This is not persisted - channels whose batch funding transaction has not been broadcast are
+	/// closed when the `ChannelManager` is read.
+	///
+	/// See `ChannelManager` struct-level documentation for lock order requirements.
+	funding_batch_states: Mutex<BTreeMap<Txid, Vec<([u8; 32], PublicKey, bool)>>>,
+	/// The on-chain funds, in satoshis, last reported via
+	/// [`ChannelManager::update_anchor_channel_reserve_funds`].
+	anchor_channel_reserve_funds_sat: Mutex<Option<u64>>,
 	/// Used when we have to take a BIG lock to make sure everything is self-consistent.
 	/// Essentially just when we're serializing ourselves out.
 	/// Taken first everywhere where we are making changes before any other locks.
@@ -1236,6 +1370,10 @@ pub const MIN_CLTV_EXPIRY_DELTA: u16 = 6*7;
 // routing failure for any HTLC sender picking up an LDK node among the first hops.
 pub(super) const CLTV_FAR_FAR_AWAY: u32 = 14 * 24 * 6;
 
+/// The maximum number of onion messages to enqueue for a single BOLT 12 [`Offer`] or [`Refund`],
+/// one per blinded path.
+const OFFERS_MESSAGE_REQUEST_LIMIT: usize = 10;
+
 /// Minimum CLTV difference between the current block height and received inbound payments.
 /// Invoices generated for payment to us must set their `min_final_cltv_expiry_delta` field to at least
 /// this value.
@@ -1265,7 +1403,7 @@ pub(crate) const MPP_TIMEOUT_TICKS: u8 = 3;
 
 /// The number of ticks of [`ChannelManager::timer_tick_occurred`] until we time-out the
 /// idempotency of payments by [`PaymentId`]. See
-/// [`OutboundPayments::remove_stale_resolved_payments`].
+/// [`OutboundPayments::remove_stale_payments`].
 pub(crate) const IDEMPOTENCY_TIMEOUT_TICKS: u8 = 7;
 
 /// The number of ticks of [`ChannelManager::timer_tick_occurred`] where a peer is disconnected
@@ -1489,6 +1627,10 @@ pub struct ChannelDetails {
 	/// The stage of the channel's shutdown.
 	/// `None` for `ChannelDetails` serialized on LDK versions prior to 0.0.116.
 	pub channel_shutdown_state: Option<ChannelShutdownState>,
+	/// Whether the channel is quiescent or in the process of becoming so, see
+	/// [`ChannelManager::quiesce_channel`].
+	/// `None` for `ChannelDetails` serialized on LDK versions prior to 0.0.117.
+	pub channel_quiescence_state: Option<ChannelQuiescenceState>,
 	/// True if the channel is (a) confirmed and channel_ready messages have been exchanged, (b)
 	/// the peer is connected, and (c) the channel is not currently negotiating a shutdown.
 	///
@@ -1580,6 +1722,7 @@ impl ChannelDetails {
 			inbound_htlc_maximum_msat: context.get_holder_htlc_maximum_msat(),
 			config: Some(context.config()),
 			channel_shutdown_state: Some(context.shutdown_state()),
+			channel_quiescence_state: Some(context.quiescence_state()),
 		}
 	}
 }
@@ -1606,10 +1749,31 @@ pub enum ChannelShutdownState {
 	ShutdownComplete,
 }
 
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+/// Further information on whether updates to a channel are paused through the quiescence (`stfu`)
+/// protocol. Quiescence never survives a disconnection, though if we were still waiting on the
+/// channel to become quiescent, we will propose it again upon reconnection.
+pub enum ChannelQuiescenceState {
+	/// Neither side has proposed quiescence, so the channel may be updated as usual.
+	NotQuiescent,
+	/// Either side has sent an `stfu` message or we're waiting on pending updates to be committed
+	/// before sending our own. No new updates will be proposed by us in the meantime.
+	AwaitingQuiescence,
+	/// Both sides have exchanged `stfu` and no updates may be made to the channel until the
+	/// protocol requiring quiescence completes (see [`ChannelManager::unquiesce_channel`]) or the
+	/// peer disconnects.
+	Quiescent,
+}
+
 /// Used by [`ChannelManager::list_recent_payments`] to express the status of recent payments.
 /// These include payments that have yet to find a successful path, or have unresolved HTLCs.
 #[derive(Debug, PartialEq)]
 pub enum RecentPaymentDetails {
+	/// When an invoice was requested and thus a payment has not yet been sent.
+	AwaitingInvoice {
+		/// Identifier for the payment to ensure idempotency.
+		payment_id: PaymentId,
+	},
 	/// When a payment is still being sent and awaiting successful delivery.
 	Pending {
 		/// Hash of the payment that is currently being sent but has yet to be fulfilled or
@@ -1737,6 +1901,9 @@ macro_rules! convert_chan_err {
 			ChannelError::Warn(msg) => {
 				(false, MsgHandleErrInternal::from_chan_no_close(ChannelError::Warn(msg), $channel_id.clone()))
 			},
+			ChannelError::WarnAndDisconnect(msg) => {
+				(false, MsgHandleErrInternal::from_chan_no_close(ChannelError::WarnAndDisconnect(msg), $channel_id.clone()))
+			},
 			ChannelError::Ignore(msg) => {
 				(false, MsgHandleErrInternal::from_chan_no_close(ChannelError::Ignore(msg), $channel_id.clone()))
 			},
@@ -1753,7 +1920,7 @@ macro_rules! convert_chan_err {
 		match $err {
 			// We should only ever have `ChannelError::Close` when unfunded channels error.
 			// In any case, just close the channel.
-			ChannelError::Warn(msg) | ChannelError::Ignore(msg) | ChannelError::Close(msg) => {
+			ChannelError::Warn(msg) | ChannelError::WarnAndDisconnect(msg) | ChannelError::Ignore(msg) | ChannelError::Close(msg) => {
 				log_error!($self.logger, "Closing unfunded channel {} due to an error: {}", log_bytes!($channel_id[..]), msg);
 				update_maps_on_chan_removal!($self, &$channel_context);
 				let shutdown_res = $channel_context.force_shutdown(false);
@@ -1892,11 +2059,27 @@ macro_rules! handle_monitor_update_completion {
 		let update_actions = $peer_state.monitor_update_blocked_actions
 			.remove(&$chan.context.channel_id()).unwrap_or(Vec::new());
 
+		// If the channel is part of a funding batch, it is now ready for the batch funding
+		// transaction to be broadcast. Once all channels in the batch are, we can broadcast it.
+		let mut completed_funding_batch = None;
+		if let Some(funding_txid) = $chan.context.unbroadcasted_batch_funding_txid() {
+			let mut funding_batch_states = $self.funding_batch_states.lock().unwrap();
+			let batch_completed = funding_batch_states.get_mut(&funding_txid).map_or(false, |batch_state| {
+				for (channel_id, _, ready) in batch_state.iter_mut() {
+					if *channel_id == $chan.context.channel_id() { *ready = true; }
+				}
+				batch_state.iter().all(|(_, _, ready)| *ready)
+			});
+			if batch_completed {
+				completed_funding_batch = funding_batch_states.remove(&funding_txid);
+			}
+		}
+
 		let htlc_forwards = $self.handle_channel_resumption(
 			&mut $peer_state.pending_msg_events, $chan, updates.raa,
 			updates.commitment_update, updates.order, updates.accepted_htlcs,
 			updates.funding_broadcastable, updates.channel_ready,
-			updates.announcement_sigs);
+			updates.announcement_sigs, updates.tx_signatures);
 		if let Some(upd) = channel_update {
 			$peer_state.pending_msg_events.push(upd);
 		}
@@ -1907,6 +2090,10 @@ macro_rules! handle_monitor_update_completion {
 
 		$self.handle_monitor_update_completion_actions(update_actions);
 
+		if let Some(batch_channels) = completed_funding_batch {
+			$self.complete_funding_batch(batch_channels);
+		}
+
 		if let Some(forwards) = htlc_forwards {
 			$self.forward_htlcs(&mut [forwards][..]);
 		}
@@ -1955,27 +2142,65 @@ macro_rules! handle_new_monitor_update {
 	($self: ident, $update_res: expr, $peer_state_lock: expr, $peer_state: expr, $per_peer_state_lock: expr, $chan_entry: expr, INITIAL_MONITOR) => {
 		handle_new_monitor_update!($self, $update_res, $peer_state_lock, $peer_state, $per_peer_state_lock, $chan_entry.get_mut(), MANUALLY_REMOVING_INITIAL_MONITOR, $chan_entry.remove_entry())
 	};
-	($self: ident, $funding_txo: expr, $update: expr, $peer_state_lock: expr, $peer_state: expr, $per_peer_state_lock: expr, $chan: expr, MANUALLY_REMOVING, $remove: expr) => { {
-		let in_flight_updates = $peer_state.in_flight_monitor_updates.entry($funding_txo)
-			.or_insert_with(Vec::new);
-		// During startup, we push monitor updates as background events through to here in
-		// order to replay updates that were in-flight when we shut down. Thus, we have to
-		// filter for uniqueness here.
-		let idx = in_flight_updates.iter().position(|upd| upd == &$update)
-			.unwrap_or_else(|| {
-				in_flight_updates.push($update);
-				in_flight_updates.len() - 1
-			});
-		let update_res = $self.chain_monitor.update_channel($funding_txo, &in_flight_updates[idx]);
+	($self: ident, $funding_txos: expr, $update: expr, $peer_state_lock: expr, $peer_state: expr, $per_peer_state_lock: expr, $chan: expr, _internal_for_funding_txos, $remove: expr) => { {
+		let funding_txos: Vec<OutPoint> = $funding_txos;
+		let update = $update;
+		let mut update_res = ChannelMonitorUpdateStatus::Completed;
+		for funding_txo in funding_txos.iter() {
+			let in_flight_updates = $peer_state.in_flight_monitor_updates.entry(*funding_txo)
+				.or_insert_with(Vec::new);
+			// During startup, we push monitor updates as background events through to here in
+			// order to replay updates that were in-flight when we shut down. Thus, we have to
+			// filter for uniqueness here.
+			let idx = in_flight_updates.iter().position(|upd| upd == &update)
+				.unwrap_or_else(|| {
+					in_flight_updates.push(update.clone());
+					in_flight_updates.len() - 1
+				});
+			match $self.chain_monitor.update_channel(*funding_txo, &in_flight_updates[idx]) {
+				ChannelMonitorUpdateStatus::Completed => { let _ = in_flight_updates.remove(idx); },
+				ChannelMonitorUpdateStatus::InProgress => {
+					if update_res == ChannelMonitorUpdateStatus::Completed {
+						update_res = ChannelMonitorUpdateStatus::InProgress;
+					}
+				},
+				ChannelMonitorUpdateStatus::PermanentFailure => {
+					update_res = ChannelMonitorUpdateStatus::PermanentFailure;
+				},
+			}
+		}
 		handle_new_monitor_update!($self, update_res, $peer_state_lock, $peer_state,
 			$per_peer_state_lock, $chan, _internal, $remove,
 			{
-				let _ = in_flight_updates.remove(idx);
-				if in_flight_updates.is_empty() && $chan.blocked_monitor_updates_pending() == 0 {
+				// Updates to the monitors watching any other funding output of the channel may still
+				// be in flight, e.g. when replaying them on startup.
+				let mut all_updates_completed = true;
+				let mut channel_funding_txos = $chan.context.get_pending_splice_funding_txos();
+				channel_funding_txos.extend($chan.context.get_funding_txo());
+				for funding_txo in channel_funding_txos.iter() {
+					if $peer_state.in_flight_monitor_updates.get(funding_txo).map_or(false, |updates| !updates.is_empty()) {
+						all_updates_completed = false;
+					}
+				}
+				if all_updates_completed && $chan.blocked_monitor_updates_pending() == 0 {
 					handle_monitor_update_completion!($self, $peer_state_lock, $peer_state, $per_peer_state_lock, $chan);
 				}
 			})
 	} };
+	($self: ident, $funding_txo: expr, $update: expr, $peer_state_lock: expr, $peer_state: expr, $per_peer_state_lock: expr, $chan: expr, MANUALLY_REMOVING, $remove: expr) => { {
+		// While a splice is pending, each of its candidate funding outputs is watched by its own
+		// `ChannelMonitor`, all of which need to see every update to the channel.
+		let mut funding_txos = vec![$funding_txo];
+		funding_txos.extend($chan.context.get_pending_splice_funding_txos());
+		handle_new_monitor_update!($self, funding_txos, $update, $peer_state_lock, $peer_state,
+			$per_peer_state_lock, $chan, _internal_for_funding_txos, $remove)
+	} };
+	($self: ident, $funding_txo: expr, $update: expr, $peer_state_lock: expr, $peer_state: expr, $per_peer_state_lock: expr, $chan_entry: expr, REPLAYING) => {
+		// Updates replayed on startup were tracked per funding output when we shut down, so they
+		// are only re-applied to the monitor they were originally in-flight for.
+		handle_new_monitor_update!($self, vec![$funding_txo], $update, $peer_state_lock, $peer_state,
+			$per_peer_state_lock, $chan_entry.get_mut(), _internal_for_funding_txos, $chan_entry.remove_entry())
+	};
 	($self: ident, $funding_txo: expr, $update: expr, $peer_state_lock: expr, $peer_state: expr, $per_peer_state_lock: expr, $chan_entry: expr) => {
 		handle_new_monitor_update!($self, $funding_txo, $update, $peer_state_lock, $peer_state, $per_peer_state_lock, $chan_entry.get_mut(), MANUALLY_REMOVING, $chan_entry.remove_entry())
 	}
@@ -2008,25 +2233,37 @@ macro_rules! process_events_body {
 			}
 
 			let pending_events = $self.pending_events.lock().unwrap().clone();
-			let num_events = pending_events.len();
-			if !pending_events.is_empty() {
-				result = NotifyOption::DoPersist;
-			}
+			let mut num_handled_events = 0;
+			let mut handling_failed = false;
 
 			let mut post_event_actions = Vec::new();
 
 			for (event, action_opt) in pending_events {
 				$event_to_handle = event;
-				$handle_event;
-				if let Some(action) = action_opt {
-					post_event_actions.push(action);
+				match $handle_event {
+					Ok(()) => {
+						num_handled_events += 1;
+						if let Some(action) = action_opt {
+							post_event_actions.push(action);
+						}
+					},
+					Err(ReplayEvent()) => {
+						// If the handler failed, we stop handling events and leave the failed event
+						// (and any after it) queued to be replayed on the next invocation.
+						handling_failed = true;
+						break;
+					},
 				}
 			}
+			if num_handled_events > 0 {
+				result = NotifyOption::DoPersist;
+			}
 
 			{
 				let mut pending_events = $self.pending_events.lock().unwrap();
-				pending_events.drain(..num_events);
-				processed_all_events = pending_events.is_empty();
+				pending_events.drain(..num_handled_events);
+				// Don't go around again if the handler failed, as it'd likely just fail again.
+				processed_all_events = handling_failed || pending_events.is_empty();
 				// Note that `push_pending_forwards_ev` relies on `pending_events_processor` being
 				// updated here with the `pending_events` lock acquired.
 				$self.pending_events_processor.store(false, Ordering::Release);
@@ -2034,8 +2271,9 @@ macro_rules! process_events_body {
 
 			if !post_event_actions.is_empty() {
 				$self.handle_post_event_actions(post_event_actions);
-				// If we had some actions, go around again as we may have more events now
-				processed_all_events = false;
+				// If we had some actions, go around again as we may have more events now, unless
+				// the handler failed, in which case we'll retry on the next invocation.
+				processed_all_events = handling_failed;
 			}
 
 			if result == NotifyOption::DoPersist {
@@ -2099,6 +2337,7 @@ where
 			claimable_payments: Mutex::new(ClaimablePayments { claimable_payments: HashMap::new(), pending_claiming_payments: HashMap::new() }),
 			pending_intercepted_htlcs: Mutex::new(HashMap::new()),
 			id_to_peer: Mutex::new(HashMap::new()),
+			funding_txo_to_channel_id: Mutex::new(HashMap::new()),
 			short_to_chan_info: FairRwLock::new(HashMap::new()),
 
 			our_network_pubkey: node_signer.get_node_id(Recipient::Node).unwrap(),
@@ -2115,7 +2354,10 @@ where
 
 			pending_events: Mutex::new(VecDeque::new()),
 			pending_events_processor: AtomicBool::new(false),
+			pending_offers_messages: Mutex::new(Vec::new()),
 			pending_background_events: Mutex::new(Vec::new()),
+			funding_batch_states: Mutex::new(BTreeMap::new()),
+			anchor_channel_reserve_funds_sat: Mutex::new(None),
 			total_consistency_lock: RwLock::new(()),
 			background_events_processed_since_startup: AtomicBool::new(false),
 			persistence_notifier: Notifier::new(),
@@ -2133,6 +2375,90 @@ where
 		&self.default_configuration
 	}
 
+	/// Gets the worst-case amount of on-chain funds, in satoshis, required to get the transactions
+	/// of all of our anchor channels confirmed if they were force-closed at once, as configured in
+	/// [`UserConfig::anchor_channel_reserve_config`].
+	///
+	/// This accounts for bumping the commitment transaction as well as the HTLC transactions of
+	/// each anchor channel we've opened or accepted, see [`anchor_channel_reserve_sat`] for
+	/// details. Note that channels which have already been closed but whose transactions are still
+	/// being claimed on-chain are not included.
+	///
+	/// [`anchor_channel_reserve_sat`]: crate::events::bump_transaction::anchor_channel_reserve_sat
+	pub fn get_anchor_channel_reserve_sat(&self) -> u64 {
+		let config = &self.default_configuration.anchor_channel_reserve_config;
+		let reserve_for_channel = |context: &ChannelContext<<SP::Target as SignerProvider>::Signer>| {
+			if !context.get_channel_type().supports_anchors_zero_fee_htlc_tx() {
+				return 0;
+			}
+			let num_htlcs = cmp::max(context.get_pending_htlc_count(), config.expected_max_htlcs_per_channel as usize);
+			anchor_channel_reserve_sat(num_htlcs, config.feerate_sat_per_1000_weight)
+		};
+
+		let mut reserve_sat = 0;
+		let per_peer_state = self.per_peer_state.read().unwrap();
+		for (_cp_id, peer_state_mutex) in per_peer_state.iter() {
+			let peer_state = peer_state_mutex.lock().unwrap();
+			reserve_sat += peer_state.channel_by_id.values()
+				.map(|channel| reserve_for_channel(&channel.context)).sum::<u64>();
+			reserve_sat += peer_state.outbound_v1_channel_by_id.values()
+				.map(|channel| reserve_for_channel(&channel.context)).sum::<u64>();
+			reserve_sat += peer_state.outbound_v2_channel_by_id.values()
+				.map(|channel| reserve_for_channel(&channel.context)).sum::<u64>();
+			// Inbound channels we haven't accepted yet don't require a reserve.
+			reserve_sat += peer_state.inbound_v1_channel_by_id.values()
+				.filter(|channel| !channel.is_awaiting_accept())
+				.map(|channel| reserve_for_channel(&channel.context)).sum::<u64>();
+			reserve_sat += peer_state.inbound_v2_channel_by_id.values()
+				.filter(|channel| !channel.is_awaiting_accept())
+				.map(|channel| reserve_for_channel(&channel.context)).sum::<u64>();
+		}
+		reserve_sat
+	}
+
+	/// Updates the on-chain funds available to bump the transactions of anchor channels to the
+	/// total value of the confirmed UTXOs reported by the given [`WalletSource`], returning them.
+	///
+	/// If [`AnchorChannelReserveConfig::refuse_channels_without_reserve`] is set, this should be
+	/// called regularly, e.g., whenever a new block is connected or the wallet's balance changes
+	/// otherwise, as new anchor channels are only opened or accepted if the funds last reported
+	/// cover [`Self::get_anchor_channel_reserve_sat`] plus the reserve required by the new
+	/// channel.
+	///
+	/// Returns `Err(())` if the UTXOs could not be listed, in which case the previously reported
+	/// funds are kept.
+	///
+	/// [`AnchorChannelReserveConfig::refuse_channels_without_reserve`]: crate::util::config::AnchorChannelReserveConfig::refuse_channels_without_reserve
+	pub fn update_anchor_channel_reserve_funds<W: Deref>(&self, wallet_source: W) -> Result<u64, ()>
+	where
+		W::Target: WalletSource,
+	{
+		let funds_sat = wallet_source.list_confirmed_utxos()?.iter().map(|utxo| utxo.output.value).sum();
+		*self.anchor_channel_reserve_funds_sat.lock().unwrap() = Some(funds_sat);
+		Ok(funds_sat)
+	}
+
+	/// Checks whether the on-chain funds last reported via
+	/// [`Self::update_anchor_channel_reserve_funds`] cover the reserve of all existing anchor
+	/// channels plus a new one, if we're configured to refuse new anchor channels otherwise.
+	///
+	/// Must not be called while holding any `per_peer_state` locks.
+	fn check_anchor_channel_reserve_for_new_channel(&self) -> Result<(), APIError> {
+		let config = &self.default_configuration.anchor_channel_reserve_config;
+		if !config.refuse_channels_without_reserve {
+			return Ok(());
+		}
+		let required_sat = self.get_anchor_channel_reserve_sat() + anchor_channel_reserve_sat(
+			config.expected_max_htlcs_per_channel as usize, config.feerate_sat_per_1000_weight);
+		let available_sat = self.anchor_channel_reserve_funds_sat.lock().unwrap().unwrap_or(0);
+		if available_sat < required_sat {
+			return Err(APIError::ChannelUnavailable { err: format!(
+				"Insufficient on-chain funds to bump the transactions of a new anchor channel: {} sat available, {} sat required",
+				available_sat, required_sat) });
+		}
+		Ok(())
+	}
+
 	fn create_and_insert_outbound_scid_alias(&self) -> u64 {
 		let height = self.best_block.read().unwrap().height();
 		let mut outbound_scid_alias = 0;
@@ -2165,7 +2491,9 @@ where
 	///
 	/// Raises [`APIError::ChannelUnavailable`] if the channel cannot be opened due to failing to
 	/// generate a shutdown scriptpubkey or destination script set by
-	/// [`SignerProvider::get_shutdown_scriptpubkey`] or [`SignerProvider::get_destination_script`].
+	/// [`SignerProvider::get_shutdown_scriptpubkey`] or [`SignerProvider::get_destination_script`],
+	/// or, if [`AnchorChannelReserveConfig::refuse_channels_without_reserve`] is set, because we
+	/// lack the on-chain funds to bump the transactions of a new anchor channel.
 	///
 	/// Note that we do not check if you are currently connected to the given peer. If no
 	/// connection is available, the outbound `open_channel` message may fail to send, resulting in
@@ -2181,6 +2509,7 @@ where
 	/// [`Event::FundingGenerationReady::user_channel_id`]: events::Event::FundingGenerationReady::user_channel_id
 	/// [`Event::FundingGenerationReady::temporary_channel_id`]: events::Event::FundingGenerationReady::temporary_channel_id
 	/// [`Event::ChannelClosed::channel_id`]: events::Event::ChannelClosed::channel_id
+	/// [`AnchorChannelReserveConfig::refuse_channels_without_reserve`]: crate::util::config::AnchorChannelReserveConfig::refuse_channels_without_reserve
 	pub fn create_channel(&self, their_network_key: PublicKey, channel_value_satoshis: u64, push_msat: u64, user_channel_id: u128, override_config: Option<UserConfig>) -> Result<[u8; 32], APIError> {
 		if channel_value_satoshis < 1000 {
 			return Err(APIError::APIMisuseError { err: format!("Channel value must be at least 1000 satoshis. It was {}", channel_value_satoshis) });
@@ -2190,6 +2519,7 @@ where
 		// We want to make sure the lock is actually acquired by PersistenceNotifierGuard.
 		debug_assert!(&self.total_consistency_lock.try_write().is_err());
 
+		let anchor_reserve_check = self.check_anchor_channel_reserve_for_new_channel();
 		let per_peer_state = self.per_peer_state.read().unwrap();
 
 		let peer_state_mutex = per_peer_state.get(&their_network_key)
@@ -2203,6 +2533,11 @@ where
 			match OutboundV1Channel::new(&self.fee_estimator, &self.entropy_source, &self.signer_provider, their_network_key,
 				their_features, channel_value_satoshis, push_msat, user_channel_id, config,
 				self.best_block.read().unwrap().height(), outbound_scid_alias)
+				.and_then(|channel| if channel.context.get_channel_type().supports_anchors_zero_fee_htlc_tx() {
+					anchor_reserve_check.map(|()| channel)
+				} else {
+					Ok(channel)
+				})
 			{
 				Ok(res) => res,
 				Err(e) => {
@@ -2232,6 +2567,112 @@ where
 		Ok(temporary_channel_id)
 	}
 
+	/// Creates a new outbound dual-funded channel to the given remote node and with the given
+	/// value, contributing `funding_satoshis` from the given inputs.
+	///
+	/// Each of the `funding_inputs` must spend a SegWit output of the accompanying previous
+	/// transaction, and together they must cover `funding_satoshis` as well as the fees for our
+	/// share of the funding transaction. Any excess is returned to us in a change output paying to
+	/// [`SignerProvider::get_destination_script`]. Our counterparty may contribute inputs of their
+	/// own, in which case the channel's value will be the sum of both contributions.
+	///
+	/// Once the funding transaction has been negotiated, an
+	/// [`Event::FundingTransactionReadyForSigning`] will be generated, after which the transaction
+	/// should be signed and handed back via [`ChannelManager::funding_transaction_signed`].
+	///
+	/// `user_channel_id` will be provided back as in
+	/// [`Event::FundingTransactionReadyForSigning::user_channel_id`] to allow tracking of which
+	/// events correspond with which `create_dual_funded_channel` call.
+	///
+	/// Raises [`APIError::APIMisuseError`] when `funding_satoshis` < 1000, the counterparty does
+	/// not support `option_dual_fund`, any of the previous transactions is too large, or the
+	/// inputs cannot cover our contribution.
+	///
+	/// Returns the new Channel's temporary `channel_id`. This ID will appear in
+	/// [`ChannelDetails::channel_id`] until the counterparty accepts the channel, swapping the
+	/// Channel's ID for one derived from both parties' revocation basepoints.
+	///
+	/// [`Event::FundingTransactionReadyForSigning::user_channel_id`]: events::Event::FundingTransactionReadyForSigning::user_channel_id
+	pub fn create_dual_funded_channel(&self, their_network_key: PublicKey, funding_satoshis: u64,
+		funding_inputs: Vec<(TxIn, Transaction)>, user_channel_id: u128, override_config: Option<UserConfig>
+	) -> Result<[u8; 32], APIError> {
+		if funding_satoshis < 1000 {
+			return Err(APIError::APIMisuseError { err: format!("Channel value must be at least 1000 satoshis. It was {}", funding_satoshis) });
+		}
+		let funding_inputs = Self::length_limit_funding_inputs(funding_inputs)?;
+
+		let _persistence_guard = PersistenceNotifierGuard::notify_on_drop(self);
+		// We want to make sure the lock is actually acquired by PersistenceNotifierGuard.
+		debug_assert!(&self.total_consistency_lock.try_write().is_err());
+
+		let anchor_reserve_check = self.check_anchor_channel_reserve_for_new_channel();
+		let per_peer_state = self.per_peer_state.read().unwrap();
+
+		let peer_state_mutex = per_peer_state.get(&their_network_key)
+			.ok_or_else(|| APIError::APIMisuseError{ err: format!("Not connected to node: {}", their_network_key) })?;
+
+		let mut peer_state = peer_state_mutex.lock().unwrap();
+		if !peer_state.latest_features.supports_dual_fund() {
+			return Err(APIError::APIMisuseError { err: format!("Peer {} does not support dual-funded channels", their_network_key) });
+		}
+		let channel = {
+			let outbound_scid_alias = self.create_and_insert_outbound_scid_alias();
+			let their_features = &peer_state.latest_features;
+			let config = if override_config.is_some() { override_config.as_ref().unwrap() } else { &self.default_configuration };
+			match OutboundV2Channel::new(&self.fee_estimator, &self.entropy_source, &self.signer_provider, their_network_key,
+				their_features, funding_satoshis, funding_inputs, user_channel_id, config,
+				self.best_block.read().unwrap().height(), outbound_scid_alias)
+				.and_then(|channel| if channel.context.get_channel_type().supports_anchors_zero_fee_htlc_tx() {
+					anchor_reserve_check.map(|()| channel)
+				} else {
+					Ok(channel)
+				})
+			{
+				Ok(res) => res,
+				Err(e) => {
+					self.outbound_scid_aliases.lock().unwrap().remove(&outbound_scid_alias);
+					return Err(e);
+				},
+			}
+		};
+		let res = channel.get_open_channel_v2(self.genesis_hash.clone());
+
+		let temporary_channel_id = channel.context.channel_id();
+		match peer_state.outbound_v2_channel_by_id.entry(temporary_channel_id) {
+			hash_map::Entry::Occupied(_) => {
+				if cfg!(fuzzing) {
+					return Err(APIError::APIMisuseError { err: "Fuzzy bad RNG".to_owned() });
+				} else {
+					panic!("RNG is bad???");
+				}
+			},
+			hash_map::Entry::Vacant(entry) => { entry.insert(channel); }
+		}
+
+		peer_state.pending_msg_events.push(events::MessageSendEvent::SendOpenChannelV2 {
+			node_id: their_network_key,
+			msg: res,
+		});
+		Ok(temporary_channel_id)
+	}
+
+	/// Checks that the previous transactions of the given funding inputs may be sent in
+	/// `tx_add_input` messages.
+	fn length_limit_funding_inputs(funding_inputs: Vec<(TxIn, Transaction)>)
+	-> Result<Vec<(TxIn, TransactionU16LenLimited)>, APIError> {
+		funding_inputs.into_iter().map(|(txin, tx)| {
+			let txid = tx.txid();
+			TransactionU16LenLimited::new(tx).map(|tx| (txin, tx)).map_err(|_| APIError::APIMisuseError {
+				err: format!("Funding input's previous transaction {} is too large", txid) })
+		}).collect()
+	}
+
+	/// Gets the `channel_id` of the channel with the given funding outpoint.
+	fn channel_id_for_funding_txo(&self, funding_txo: &OutPoint) -> [u8; 32] {
+		self.funding_txo_to_channel_id.lock().unwrap().get(funding_txo).copied()
+			.unwrap_or_else(|| funding_txo.to_channel_id())
+	}
+
 	fn list_funded_channels_with_filter<Fn: FnMut(&(&[u8; 32], &Channel<<SP::Target as SignerProvider>::Signer>)) -> bool + Copy>(&self, f: Fn) -> Vec<ChannelDetails> {
 		// Allocate our best estimate of the number of channels we have in the `res`
 		// Vec. Sadly the `short_to_chan_info` map doesn't cover channels without
@@ -2288,6 +2729,16 @@ where
 						peer_state.latest_features.clone(), &self.fee_estimator);
 					res.push(details);
 				}
+				for (_channel_id, channel) in peer_state.inbound_v2_channel_by_id.iter() {
+					let details = ChannelDetails::from_channel_context(&channel.context, best_block_height,
+						peer_state.latest_features.clone(), &self.fee_estimator);
+					res.push(details);
+				}
+				for (_channel_id, channel) in peer_state.outbound_v2_channel_by_id.iter() {
+					let details = ChannelDetails::from_channel_context(&channel.context, best_block_height,
+						peer_state.latest_features.clone(), &self.fee_estimator);
+					res.push(details);
+				}
 			}
 		}
 		res
@@ -2323,6 +2774,8 @@ where
 				.map(|(_, channel)| &channel.context)
 				.chain(peer_state.outbound_v1_channel_by_id.iter().map(|(_, channel)| &channel.context))
 				.chain(peer_state.inbound_v1_channel_by_id.iter().map(|(_, channel)| &channel.context))
+				.chain(peer_state.outbound_v2_channel_by_id.iter().map(|(_, channel)| &channel.context))
+				.chain(peer_state.inbound_v2_channel_by_id.iter().map(|(_, channel)| &channel.context))
 				.map(chan_context_to_details)
 				.collect();
 		}
@@ -2339,7 +2792,12 @@ where
 	/// [`Event::PaymentSent`]: events::Event::PaymentSent
 	pub fn list_recent_payments(&self) -> Vec<RecentPaymentDetails> {
 		self.pending_outbound_payments.pending_outbound_payments.lock().unwrap().iter()
-			.filter_map(|(_, pending_outbound_payment)| match pending_outbound_payment {
+			.filter_map(|(payment_id, pending_outbound_payment)| match pending_outbound_payment {
+				// InvoiceReceived is an intermediate state and doesn't need to be exposed
+				PendingOutboundPayment::AwaitingInvoice { .. } |
+					PendingOutboundPayment::InvoiceReceived { .. } => {
+					Some(RecentPaymentDetails::AwaitingInvoice { payment_id: *payment_id })
+				},
 				PendingOutboundPayment::Retryable { payment_hash, total_msat, .. } => {
 					Some(RecentPaymentDetails::Pending {
 						payment_hash: *payment_hash,
@@ -2358,15 +2816,17 @@ where
 	}
 
 	/// Helper function that issues the channel close events
+	///
+	/// Note that the funding transaction of a channel funded as part of a batch is only discarded
+	/// once for the entire batch, in [`Self::close_funding_batch`].
 	fn issue_channel_close_events(&self, context: &ChannelContext<<SP::Target as SignerProvider>::Signer>, closure_reason: ClosureReason) {
 		let mut pending_events_lock = self.pending_events.lock().unwrap();
-		match context.unbroadcasted_funding() {
-			Some(transaction) => {
+		if !context.is_awaiting_batch_broadcast() {
+			if let Some(transaction) = context.unbroadcasted_funding() {
 				pending_events_lock.push_back((events::Event::DiscardFunding {
 					channel_id: context.channel_id(), transaction
 				}, None));
-			},
-			None => {},
+			}
 		}
 		pending_events_lock.push_back((events::Event::ChannelClosed {
 			channel_id: context.channel_id(),
@@ -2390,7 +2850,7 @@ where
 				let peer_state = &mut *peer_state_lock;
 
 				match peer_state.channel_by_id.entry(channel_id.clone()) {
-					hash_map::Entry::Occupied(mut chan_entry) => {
+					hash_map::Entry::Occupied(mut chan_entry) if !chan_entry.get().context.is_awaiting_batch_broadcast() => {
 						let funding_txo_opt = chan_entry.get().context.get_funding_txo();
 						let their_features = &peer_state.latest_features;
 						let (shutdown_msg, mut monitor_update_opt, htlcs) = chan_entry.get_mut()
@@ -2422,11 +2882,12 @@ where
 						}
 						break Ok(());
 					},
-					hash_map::Entry::Vacant(_) => (),
+					_ => (),
 				}
 			}
-			// If we reach this point, it means that the channel_id either refers to an unfunded channel or
-			// it does not exist for this peer. Either way, we can attempt to force-close it.
+			// If we reach this point, it means that the channel_id either refers to an unfunded channel,
+			// a channel whose batch funding transaction has not yet been broadcast, or it does not exist
+			// for this peer. Either way, we can attempt to force-close it.
 			//
 			// An appropriate error will be returned for non-existence of the channel if that's the case.
 			return self.force_close_channel_with_peer(&channel_id, counterparty_node_id, None, false).map(|_| ())
@@ -2507,7 +2968,7 @@ where
 
 	#[inline]
 	fn finish_force_close_channel(&self, shutdown_res: ShutdownResult) {
-		let (monitor_update_option, mut failed_htlcs) = shutdown_res;
+		let (monitor_update_option, mut failed_htlcs, splice_monitor_updates, unbroadcasted_batch_funding_txid) = shutdown_res;
 		log_debug!(self.logger, "Finishing force-closure of channel with {} HTLCs to fail", failed_htlcs.len());
 		for htlc_source in failed_htlcs.drain(..) {
 			let (source, payment_hash, counterparty_node_id, channel_id) = htlc_source;
@@ -2522,6 +2983,99 @@ where
 			// ignore the result here.
 			let _ = self.chain_monitor.update_channel(funding_txo, &monitor_update);
 		}
+		for (funding_txo, monitor_update) in splice_monitor_updates {
+			// Likewise, the monitors for a pending splice's new funding outputs may need to
+			// broadcast their commitment transaction should a splice transaction confirm.
+			let _ = self.chain_monitor.update_channel(funding_txo, &monitor_update);
+		}
+		if let Some(funding_txid) = unbroadcasted_batch_funding_txid {
+			self.close_funding_batch(&funding_txid);
+		}
+	}
+
+	/// Closes all remaining channels funded by the batch funding transaction with the given txid,
+	/// which may no longer be broadcast as one of the channels in the batch has closed.
+	fn close_funding_batch(&self, funding_txid: &Txid) {
+		debug_assert_ne!(self.per_peer_state.held_by_thread(), LockHeldState::HeldByThread);
+		let batch_channels = self.funding_batch_states.lock().unwrap().remove(funding_txid);
+		let mut shutdown_results = Vec::new();
+		{
+			let mut discarded_funding = false;
+			let per_peer_state = self.per_peer_state.read().unwrap();
+			for (channel_id, counterparty_node_id, _) in batch_channels.into_iter().flatten() {
+				let peer_state_mutex = match per_peer_state.get(&counterparty_node_id) {
+					Some(peer_state_mutex) => peer_state_mutex,
+					None => continue,
+				};
+				let mut peer_state_lock = peer_state_mutex.lock().unwrap();
+				let peer_state = &mut *peer_state_lock;
+				if let hash_map::Entry::Occupied(chan_entry) = peer_state.channel_by_id.entry(channel_id) {
+					log_error!(self.logger, "Force-closing channel {} as another channel in its funding batch closed",
+						log_bytes!(channel_id[..]));
+					if !discarded_funding {
+						if let Some(transaction) = chan_entry.get().context.unbroadcasted_funding() {
+							self.pending_events.lock().unwrap().push_back((events::Event::DiscardFunding {
+								channel_id, transaction
+							}, None));
+							discarded_funding = true;
+						}
+					}
+					self.issue_channel_close_events(&chan_entry.get().context, ClosureReason::FundingBatchClosure);
+					let mut chan = remove_channel!(self, chan_entry);
+					let (monitor_update, failed_htlcs, splice_monitor_update, _) = chan.context.force_shutdown(false);
+					shutdown_results.push((monitor_update, failed_htlcs, splice_monitor_update, None));
+					peer_state.pending_msg_events.push(events::MessageSendEvent::HandleError {
+						node_id: counterparty_node_id,
+						action: msgs::ErrorAction::SendErrorMessage {
+							msg: msgs::ErrorMessage { channel_id, data: "Another channel in the funding batch closed".to_owned() }
+						},
+					});
+				}
+			}
+		}
+		for shutdown_res in shutdown_results {
+			self.finish_force_close_channel(shutdown_res);
+		}
+	}
+
+	/// Broadcasts the funding transaction of a batch once all of its channels have received their
+	/// counterparty's `funding_signed` and had their initial [`ChannelMonitor`] persisted.
+	fn complete_funding_batch(&self, batch_channels: Vec<([u8; 32], PublicKey, bool)>) {
+		debug_assert_ne!(self.per_peer_state.held_by_thread(), LockHeldState::HeldByThread);
+		let mut funding_tx = None;
+		{
+			let per_peer_state = self.per_peer_state.read().unwrap();
+			for (channel_id, counterparty_node_id, _) in batch_channels {
+				let peer_state_mutex = match per_peer_state.get(&counterparty_node_id) {
+					Some(peer_state_mutex) => peer_state_mutex,
+					None => continue,
+				};
+				let mut peer_state_lock = peer_state_mutex.lock().unwrap();
+				let peer_state = &mut *peer_state_lock;
+				if let Some(chan) = peer_state.channel_by_id.get_mut(&channel_id) {
+					let (tx, channel_ready) = chan.set_batch_ready(&self.logger);
+					if funding_tx.is_none() { funding_tx = tx; }
+					if let Some(msg) = channel_ready {
+						send_channel_ready!(self, peer_state.pending_msg_events, chan, msg);
+						if chan.context.is_usable() {
+							if let Ok(msg) = self.get_channel_update_for_unicast(chan) {
+								peer_state.pending_msg_events.push(events::MessageSendEvent::SendChannelUpdate {
+									node_id: counterparty_node_id,
+									msg,
+								});
+							}
+						}
+					}
+					let mut pending_events = self.pending_events.lock().unwrap();
+					emit_channel_pending_event!(pending_events, chan);
+					emit_channel_ready_event!(pending_events, chan);
+				}
+			}
+		}
+		if let Some(tx) = funding_tx {
+			log_info!(self.logger, "Broadcasting batch funding transaction with txid {}", tx.txid());
+			self.tx_broadcaster.broadcast_transactions(&[&tx]);
+		}
 	}
 
 	/// `peer_msg` should be set when we receive a message from a peer, but not set when the
@@ -2531,7 +3085,7 @@ where
 		let per_peer_state = self.per_peer_state.read().unwrap();
 		let peer_state_mutex = per_peer_state.get(peer_node_id)
 			.ok_or_else(|| APIError::ChannelUnavailable { err: format!("Can't find a peer matching the passed counterparty node_id {}", peer_node_id) })?;
-		let (update_opt, counterparty_node_id) = {
+		let (update_opt, counterparty_node_id, shutdown_res) = {
 			let mut peer_state_lock = peer_state_mutex.lock().unwrap();
 			let peer_state = &mut *peer_state_lock;
 			let closure_reason = if let Some(peer_msg) = peer_msg {
@@ -2543,22 +3097,36 @@ where
 				log_error!(self.logger, "Force-closing channel {}", log_bytes!(channel_id[..]));
 				self.issue_channel_close_events(&chan.get().context, closure_reason);
 				let mut chan = remove_channel!(self, chan);
-				self.finish_force_close_channel(chan.context.force_shutdown(broadcast));
-				(self.get_channel_update_for_broadcast(&chan).ok(), chan.context.get_counterparty_node_id())
+				let shutdown_res = chan.context.force_shutdown(broadcast);
+				(self.get_channel_update_for_broadcast(&chan).ok(), chan.context.get_counterparty_node_id(), shutdown_res)
 			} else if let hash_map::Entry::Occupied(chan) = peer_state.outbound_v1_channel_by_id.entry(channel_id.clone()) {
 				log_error!(self.logger, "Force-closing channel {}", log_bytes!(channel_id[..]));
 				self.issue_channel_close_events(&chan.get().context, closure_reason);
 				let mut chan = remove_channel!(self, chan);
-				self.finish_force_close_channel(chan.context.force_shutdown(false));
+				let shutdown_res = chan.context.force_shutdown(false);
 				// Unfunded channel has no update
-				(None, chan.context.get_counterparty_node_id())
+				(None, chan.context.get_counterparty_node_id(), shutdown_res)
 			} else if let hash_map::Entry::Occupied(chan) = peer_state.inbound_v1_channel_by_id.entry(channel_id.clone()) {
 				log_error!(self.logger, "Force-closing channel {}", log_bytes!(channel_id[..]));
 				self.issue_channel_close_events(&chan.get().context, closure_reason);
 				let mut chan = remove_channel!(self, chan);
-				self.finish_force_close_channel(chan.context.force_shutdown(false));
+				let shutdown_res = chan.context.force_shutdown(false);
+				// Unfunded channel has no update
+				(None, chan.context.get_counterparty_node_id(), shutdown_res)
+			} else if let hash_map::Entry::Occupied(chan) = peer_state.outbound_v2_channel_by_id.entry(channel_id.clone()) {
+				log_error!(self.logger, "Force-closing channel {}", log_bytes!(channel_id[..]));
+				self.issue_channel_close_events(&chan.get().context, closure_reason);
+				let mut chan = remove_channel!(self, chan);
+				let shutdown_res = chan.context.force_shutdown(false);
+				// Unfunded channel has no update
+				(None, chan.context.get_counterparty_node_id(), shutdown_res)
+			} else if let hash_map::Entry::Occupied(chan) = peer_state.inbound_v2_channel_by_id.entry(channel_id.clone()) {
+				log_error!(self.logger, "Force-closing channel {}", log_bytes!(channel_id[..]));
+				self.issue_channel_close_events(&chan.get().context, closure_reason);
+				let mut chan = remove_channel!(self, chan);
+				let shutdown_res = chan.context.force_shutdown(false);
 				// Unfunded channel has no update
-				(None, chan.context.get_counterparty_node_id())
+				(None, chan.context.get_counterparty_node_id(), shutdown_res)
 			} else {
 				return Err(APIError::ChannelUnavailable{ err: format!("Channel with id {} not found for the passed counterparty node_id {}", log_bytes!(*channel_id), peer_node_id) });
 			}
@@ -2569,6 +3137,8 @@ where
 				msg: update
 			});
 		}
+		mem::drop(per_peer_state);
+		self.finish_force_close_channel(shutdown_res);
 
 		Ok(counterparty_node_id)
 	}
@@ -2632,12 +3202,51 @@ where
 	}
 
 	fn construct_recv_pending_htlc_info(
-		&self, hop_data: msgs::OnionHopData, shared_secret: [u8; 32], payment_hash: PaymentHash,
+		&self, hop_data: msgs::InboundOnionPayload, shared_secret: [u8; 32], payment_hash: PaymentHash,
 		amt_msat: u64, cltv_expiry: u32, phantom_shared_secret: Option<[u8; 32]>, allow_underpay: bool,
 		counterparty_skimmed_fee_msat: Option<u64>,
 	) -> Result<PendingHTLCInfo, ReceiveError> {
+		let (payment_data, keysend_preimage, payment_metadata, onion_amt_msat, outgoing_cltv_value, requires_blinded_error) = match hop_data {
+			msgs::InboundOnionPayload::Receive {
+				payment_data, keysend_preimage, payment_metadata, amt_msat, outgoing_cltv_value, ..
+			} =>
+				(payment_data, keysend_preimage, payment_metadata, amt_msat, outgoing_cltv_value, false),
+			msgs::InboundOnionPayload::BlindedReceive {
+				amt_msat, total_msat, outgoing_cltv_value, payment_secret, intro_node_blinding_point,
+				payment_constraints, ..
+			} => {
+				if amt_msat < payment_constraints.htlc_minimum_msat ||
+					cltv_expiry > payment_constraints.max_cltv_expiry
+				{
+					return Err(ReceiveError {
+						err_code: INVALID_ONION_BLINDING,
+						err_data: vec![0; 32],
+						msg: "Inbound HTLC violates the constraints of the blinded path",
+					});
+				}
+				if outgoing_cltv_value > cltv_expiry {
+					return Err(ReceiveError {
+						err_code: INVALID_ONION_BLINDING,
+						err_data: vec![0; 32],
+						msg: "Upstream node set CLTV to less than the CLTV set by the sender",
+					});
+				}
+				// The sender only learns the blinded path's aggregate CLTV delta, so the onion value is
+				// merely a lower bound. Our payment deadline is instead the HTLC's own expiry.
+				let payment_data = msgs::FinalOnionHopData { payment_secret, total_msat };
+				(Some(payment_data), None, None, amt_msat, cltv_expiry,
+				 intro_node_blinding_point.is_none())
+			},
+			msgs::InboundOnionPayload::Forward { .. } | msgs::InboundOnionPayload::BlindedForward { .. } => {
+				return Err(ReceiveError {
+					err_code: 0x4000|22,
+					err_data: Vec::new(),
+					msg: "Got non final data with an HMAC of 0",
+				});
+			},
+		};
 		// final_incorrect_cltv_expiry
-		if hop_data.outgoing_cltv_value > cltv_expiry {
+		if outgoing_cltv_value > cltv_expiry {
 			return Err(ReceiveError {
 				msg: "Upstream node set CLTV to less than the CLTV set by the sender",
 				err_code: 18,
@@ -2652,7 +3261,7 @@ where
 		// payment logic has enough time to fail the HTLC backward before our onchain logic triggers a
 		// channel closure (see HTLC_FAIL_BACK_BUFFER rationale).
 		let current_height: u32 = self.best_block.read().unwrap().height();
-		if (hop_data.outgoing_cltv_value as u64) <= current_height as u64 + HTLC_FAIL_BACK_BUFFER as u64 + 1 {
+		if (outgoing_cltv_value as u64) <= current_height as u64 + HTLC_FAIL_BACK_BUFFER as u64 + 1 {
 			let mut err_data = Vec::with_capacity(12);
 			err_data.extend_from_slice(&amt_msat.to_be_bytes());
 			err_data.extend_from_slice(&current_height.to_be_bytes());
@@ -2661,8 +3270,8 @@ where
 				msg: "The final CLTV expiry is too soon to handle",
 			});
 		}
-		if (!allow_underpay && hop_data.amt_to_forward > amt_msat) ||
-			(allow_underpay && hop_data.amt_to_forward >
+		if (!allow_underpay && onion_amt_msat > amt_msat) ||
+			(allow_underpay && onion_amt_msat >
 			 amt_msat.saturating_add(counterparty_skimmed_fee_msat.unwrap_or(0)))
 		{
 			return Err(ReceiveError {
@@ -2672,65 +3281,55 @@ where
 			});
 		}
 
-		let routing = match hop_data.format {
-			msgs::OnionHopDataFormat::NonFinalNode { .. } => {
+		let routing = if let Some(payment_preimage) = keysend_preimage {
+			// We need to check that the sender knows the keysend preimage before processing this
+			// payment further. Otherwise, an intermediary routing hop forwarding non-keysend-HTLC X
+			// could discover the final destination of X, by probing the adjacent nodes on the route
+			// with a keysend payment of identical payment hash to X and observing the processing
+			// time discrepancies due to a hash collision with X.
+			let hashed_preimage = PaymentHash(Sha256::hash(&payment_preimage.0).into_inner());
+			if hashed_preimage != payment_hash {
 				return Err(ReceiveError {
 					err_code: 0x4000|22,
 					err_data: Vec::new(),
-					msg: "Got non final data with an HMAC of 0",
+					msg: "Payment preimage didn't match payment hash",
 				});
-			},
-			msgs::OnionHopDataFormat::FinalNode { payment_data, keysend_preimage, payment_metadata } => {
-				if let Some(payment_preimage) = keysend_preimage {
-					// We need to check that the sender knows the keysend preimage before processing this
-					// payment further. Otherwise, an intermediary routing hop forwarding non-keysend-HTLC X
-					// could discover the final destination of X, by probing the adjacent nodes on the route
-					// with a keysend payment of identical payment hash to X and observing the processing
-					// time discrepancies due to a hash collision with X.
-					let hashed_preimage = PaymentHash(Sha256::hash(&payment_preimage.0).into_inner());
-					if hashed_preimage != payment_hash {
-						return Err(ReceiveError {
-							err_code: 0x4000|22,
-							err_data: Vec::new(),
-							msg: "Payment preimage didn't match payment hash",
-						});
-					}
-					if !self.default_configuration.accept_mpp_keysend && payment_data.is_some() {
-						return Err(ReceiveError {
-							err_code: 0x4000|22,
-							err_data: Vec::new(),
-							msg: "We don't support MPP keysend payments",
-						});
-					}
-					PendingHTLCRouting::ReceiveKeysend {
-						payment_data,
-						payment_preimage,
-						payment_metadata,
-						incoming_cltv_expiry: hop_data.outgoing_cltv_value,
-					}
-				} else if let Some(data) = payment_data {
-					PendingHTLCRouting::Receive {
-						payment_data: data,
-						payment_metadata,
-						incoming_cltv_expiry: hop_data.outgoing_cltv_value,
-						phantom_shared_secret,
-					}
-				} else {
-					return Err(ReceiveError {
-						err_code: 0x4000|0x2000|3,
-						err_data: Vec::new(),
-						msg: "We require payment_secrets",
-					});
-				}
-			},
+			}
+			if !self.default_configuration.accept_mpp_keysend && payment_data.is_some() {
+				return Err(ReceiveError {
+					err_code: 0x4000|22,
+					err_data: Vec::new(),
+					msg: "We don't support MPP keysend payments",
+				});
+			}
+			PendingHTLCRouting::ReceiveKeysend {
+				payment_data,
+				payment_preimage,
+				payment_metadata,
+				incoming_cltv_expiry: outgoing_cltv_value,
+			}
+		} else if let Some(data) = payment_data {
+			PendingHTLCRouting::Receive {
+				payment_data: data,
+				payment_metadata,
+				incoming_cltv_expiry: outgoing_cltv_value,
+				phantom_shared_secret,
+				requires_blinded_error,
+			}
+		} else {
+			return Err(ReceiveError {
+				err_code: 0x4000|0x2000|3,
+				err_data: Vec::new(),
+				msg: "We require payment_secrets",
+			});
 		};
 		Ok(PendingHTLCInfo {
 			routing,
 			payment_hash,
 			incoming_shared_secret: shared_secret,
 			incoming_amt_msat: Some(amt_msat),
-			outgoing_amt_msat: hop_data.amt_to_forward,
-			outgoing_cltv_value: hop_data.outgoing_cltv_value,
+			outgoing_amt_msat: onion_amt_msat,
+			outgoing_cltv_value,
 			skimmed_fee_msat: counterparty_skimmed_fee_msat,
 		})
 	}
@@ -2742,11 +3341,16 @@ where
 			($msg: expr, $err_code: expr) => {
 				{
 					log_info!(self.logger, "Failed to accept/forward incoming HTLC: {}", $msg);
+					let (sha256_of_onion, failure_code) = if msg.blinding_point.is_some() {
+						([0; 32], INVALID_ONION_BLINDING)
+					} else {
+						(Sha256::hash(&msg.onion_routing_packet.hop_data).into_inner(), $err_code)
+					};
 					return Err(HTLCFailureMsg::Malformed(msgs::UpdateFailMalformedHTLC {
 						channel_id: msg.channel_id,
 						htlc_id: msg.htlc_id,
-						sha256_of_onion: Sha256::hash(&msg.onion_routing_packet.hop_data).into_inner(),
-						failure_code: $err_code,
+						sha256_of_onion,
+						failure_code,
 					}));
 				}
 			}
@@ -2756,8 +3360,17 @@ where
 			return_malformed_err!("invalid ephemeral pubkey", 0x8000 | 0x4000 | 6);
 		}
 
+		// If we're a non-introduction node within a blinded path, the onion was encrypted to our
+		// blinded node id, so tweak our node secret by the blinding factor when computing the shared
+		// secret.
+		let blinded_node_id_tweak = msg.blinding_point.map(|bp| {
+			let blinded_tlvs_ss = self.node_signer.ecdh(Recipient::Node, &bp, None).unwrap().secret_bytes();
+			let mut hmac = HmacEngine::<Sha256>::new(b"blinded_node_id");
+			hmac.input(blinded_tlvs_ss.as_ref());
+			Scalar::from_be_bytes(Hmac::from_engine(hmac).into_inner()).unwrap()
+		});
 		let shared_secret = self.node_signer.ecdh(
-			Recipient::Node, &msg.onion_routing_packet.public_key.unwrap(), None
+			Recipient::Node, &msg.onion_routing_packet.public_key.unwrap(), blinded_node_id_tweak.as_ref()
 		).unwrap().secret_bytes();
 
 		if msg.onion_routing_packet.version != 0 {
@@ -2772,6 +3385,10 @@ where
 		macro_rules! return_err {
 			($msg: expr, $err_code: expr, $data: expr) => {
 				{
+					if msg.blinding_point.is_some() {
+						return_malformed_err!($msg, INVALID_ONION_BLINDING)
+					}
+
 					log_info!(self.logger, "Failed to accept/forward incoming HTLC: {}", $msg);
 					return Err(HTLCFailureMsg::Relay(msgs::UpdateFailHTLC {
 						channel_id: msg.channel_id,
@@ -2783,7 +3400,10 @@ where
 			}
 		}
 
-		let next_hop = match onion_utils::decode_next_payment_hop(shared_secret, &msg.onion_routing_packet.hop_data[..], msg.onion_routing_packet.hmac, msg.payment_hash) {
+		let next_hop = match onion_utils::decode_next_payment_hop(
+			shared_secret, &msg.onion_routing_packet.hop_data[..], msg.onion_routing_packet.hmac,
+			msg.payment_hash, msg.blinding_point, &self.node_signer
+		) {
 			Ok(res) => res,
 			Err(onion_utils::OnionDecodeErr::Malformed { err_msg, err_code }) => {
 				return_malformed_err!(err_msg, err_code);
@@ -2792,23 +3412,40 @@ where
 				return_err!(err_msg, err_code, &[0; 0]);
 			},
 		};
-		let (outgoing_scid, outgoing_amt_msat, outgoing_cltv_value, next_packet_pk_opt) = match next_hop {
+		let (outgoing_scid, outgoing_amt_msat, outgoing_cltv_value, next_packet_pk_opt, is_blinded) = match next_hop {
 			onion_utils::Hop::Forward {
-				next_hop_data: msgs::OnionHopData {
-					format: msgs::OnionHopDataFormat::NonFinalNode { short_channel_id }, amt_to_forward,
-					outgoing_cltv_value,
+				next_hop_data: msgs::InboundOnionPayload::Forward {
+					short_channel_id, amt_to_forward, outgoing_cltv_value
+				}, ..
+			} => {
+				let next_pk = onion_utils::next_hop_packet_pubkey(&self.secp_ctx,
+					msg.onion_routing_packet.public_key.unwrap(), &shared_secret);
+				(short_channel_id, amt_to_forward, outgoing_cltv_value, Some(next_pk), false)
+			},
+			onion_utils::Hop::Forward {
+				next_hop_data: msgs::InboundOnionPayload::BlindedForward {
+					short_channel_id, ref payment_relay, ref payment_constraints, ref features, ..
 				}, ..
 			} => {
+				let (amt_to_forward, outgoing_cltv_value) = match payment::check_blinded_forward(
+					msg.amount_msat, msg.cltv_expiry, payment_relay, payment_constraints, features
+				) {
+					Ok((amt, cltv)) => (amt, cltv),
+					Err(()) => {
+						return_err!("Underflow calculating outbound amount or cltv value for blinded forward",
+							INVALID_ONION_BLINDING, &[0; 32]);
+					}
+				};
 				let next_pk = onion_utils::next_hop_packet_pubkey(&self.secp_ctx,
 					msg.onion_routing_packet.public_key.unwrap(), &shared_secret);
-				(short_channel_id, amt_to_forward, outgoing_cltv_value, Some(next_pk))
+				(short_channel_id, amt_to_forward, outgoing_cltv_value, Some(next_pk), true)
 			},
 			// We'll do receive checks in [`Self::construct_pending_htlc_info`] so we have access to the
 			// inbound channel's state.
 			onion_utils::Hop::Receive { .. } => return Ok((next_hop, shared_secret, None)),
-			onion_utils::Hop::Forward {
-				next_hop_data: msgs::OnionHopData { format: msgs::OnionHopDataFormat::FinalNode { .. }, .. }, ..
-			} => {
+			onion_utils::Hop::Forward { next_hop_data: msgs::InboundOnionPayload::Receive { .. }, .. } |
+				onion_utils::Hop::Forward { next_hop_data: msgs::InboundOnionPayload::BlindedReceive { .. }, .. } =>
+			{
 				return_err!("Final Node OnionHopData provided for us as an intermediary node", 0x4000 | 22, &[0; 0]);
 			}
 		};
@@ -2922,6 +3559,11 @@ where
 			break None;
 		}
 		{
+			if is_blinded {
+				// Failures forwarding within a blinded path must not reveal anything about the
+				// outbound channel, so always return `invalid_onion_blinding`.
+				return_err!(err, INVALID_ONION_BLINDING, &[0; 32]);
+			}
 			let mut res = VecWriter(Vec::with_capacity(chan_update.serialized_length() + 2 + 8 + 2));
 			if let Some(chan_update) = chan_update {
 				if code == 0x1000 | 11 || code == 0x1000 | 12 {
@@ -2957,6 +3599,16 @@ where
 			($msg: expr, $err_code: expr, $data: expr) => {
 				{
 					log_info!(self.logger, "Failed to accept/forward incoming HTLC: {}", $msg);
+					if msg.blinding_point.is_some() {
+						return PendingHTLCStatus::Fail(HTLCFailureMsg::Malformed(
+							msgs::UpdateFailMalformedHTLC {
+								channel_id: msg.channel_id,
+								htlc_id: msg.htlc_id,
+								sha256_of_onion: [0; 32],
+								failure_code: INVALID_ONION_BLINDING,
+							}
+						))
+					}
 					return PendingHTLCStatus::Fail(HTLCFailureMsg::Relay(msgs::UpdateFailHTLC {
 						channel_id: msg.channel_id,
 						htlc_id: msg.htlc_id,
@@ -2991,9 +3643,37 @@ where
 					hmac: next_hop_hmac.clone(),
 				};
 
-				let short_channel_id = match next_hop_data.format {
-					msgs::OnionHopDataFormat::NonFinalNode { short_channel_id } => short_channel_id,
-					msgs::OnionHopDataFormat::FinalNode { .. } => {
+				let (short_channel_id, amt_to_forward, outgoing_cltv_value, blinded) = match next_hop_data {
+					msgs::InboundOnionPayload::Forward { short_channel_id, amt_to_forward, outgoing_cltv_value } =>
+						(short_channel_id, amt_to_forward, outgoing_cltv_value, None),
+					msgs::InboundOnionPayload::BlindedForward {
+						short_channel_id, payment_relay, payment_constraints, features, intro_node_blinding_point,
+					} => {
+						let (amt_to_forward, outgoing_cltv_value) = match payment::check_blinded_forward(
+							msg.amount_msat, msg.cltv_expiry, &payment_relay, &payment_constraints, &features
+						) {
+							Ok((amt, cltv)) => (amt, cltv),
+							Err(()) => {
+								return_err!("Underflow calculating outbound amount or cltv value for blinded forward",
+									INVALID_ONION_BLINDING, &[0; 32]);
+							}
+						};
+						let inbound_blinding_point = match intro_node_blinding_point.or(msg.blinding_point) {
+							Some(bp) => bp,
+							None => {
+								debug_assert!(false, "Blinded forwards should always have a blinding point");
+								return_err!("Missing blinding point for blinded forward", INVALID_ONION_BLINDING, &[0; 32]);
+							},
+						};
+						let blinded = BlindedForward {
+							inbound_blinding_point,
+							failure: intro_node_blinding_point
+								.map(|_| BlindedFailure::FromIntroductionNode)
+								.unwrap_or(BlindedFailure::FromBlindedNode),
+						};
+						(short_channel_id, amt_to_forward, outgoing_cltv_value, Some(blinded))
+					},
+					msgs::InboundOnionPayload::Receive { .. } | msgs::InboundOnionPayload::BlindedReceive { .. } => {
 						return_err!("Final Node OnionHopData provided for us as an intermediary node", 0x4000 | 22, &[0;0]);
 					},
 				};
@@ -3002,12 +3682,13 @@ where
 					routing: PendingHTLCRouting::Forward {
 						onion_packet: outgoing_packet,
 						short_channel_id,
+						blinded,
 					},
 					payment_hash: msg.payment_hash.clone(),
 					incoming_shared_secret: shared_secret,
 					incoming_amt_msat: Some(msg.amount_msat),
-					outgoing_amt_msat: next_hop_data.amt_to_forward,
-					outgoing_cltv_value: next_hop_data.outgoing_cltv_value,
+					outgoing_amt_msat: amt_to_forward,
+					outgoing_cltv_value,
 					skimmed_fee_msat: None,
 				})
 			}
@@ -3254,6 +3935,16 @@ where
 				&self.pending_events, |args| self.send_payment_along_path(args))
 	}
 
+	pub(super) fn send_payment_for_bolt12_invoice(&self, invoice: &Bolt12Invoice, payment_id: PaymentId) -> Result<(), Bolt12PaymentError> {
+		let best_block_height = self.best_block.read().unwrap().height();
+		let _persistence_guard = PersistenceNotifierGuard::notify_on_drop(self);
+		self.pending_outbound_payments
+			.send_payment_for_bolt12_invoice(invoice, payment_id, &self.router, self.list_usable_channels(),
+				|| self.compute_inflight_htlcs(), &self.entropy_source, &self.node_signer,
+				best_block_height, &self.logger, &self.pending_events,
+				|args| self.send_payment_along_path(args))
+	}
+
 	#[cfg(test)]
 	pub(super) fn test_send_payment_internal(&self, route: &Route, payment_hash: PaymentHash, recipient_onion: RecipientOnionFields, keysend_preimage: Option<PaymentPreimage>, payment_id: PaymentId, recv_value_msat: Option<u64>, onion_session_privs: Vec<[u8; 32]>) -> Result<(), PaymentSendFailure> {
 		let best_block_height = self.best_block.read().unwrap().height();
@@ -3354,7 +4045,8 @@ where
 	/// Handles the generation of a funding transaction, optionally (for tests) with a function
 	/// which checks the correctness of the funding transaction given the associated channel.
 	fn funding_transaction_generated_intern<FundingOutput: Fn(&OutboundV1Channel<<SP::Target as SignerProvider>::Signer>, &Transaction) -> Result<OutPoint, APIError>>(
-		&self, temporary_channel_id: &[u8; 32], counterparty_node_id: &PublicKey, funding_transaction: Transaction, find_funding_output: FundingOutput
+		&self, temporary_channel_id: &[u8; 32], counterparty_node_id: &PublicKey, funding_transaction: Transaction, is_batch_funding: bool,
+		find_funding_output: FundingOutput
 	) -> Result<(), APIError> {
 		let per_peer_state = self.per_peer_state.read().unwrap();
 		let peer_state_mutex = per_peer_state.get(counterparty_node_id)
@@ -3366,7 +4058,7 @@ where
 			Some(chan) => {
 				let funding_txo = find_funding_output(&chan, &funding_transaction)?;
 
-				let funding_res = chan.get_funding_created(funding_transaction, funding_txo, &self.logger)
+				let funding_res = chan.get_funding_created(funding_transaction, funding_txo, is_batch_funding, &self.logger)
 					.map_err(|(mut chan, e)| if let ChannelError::Close(msg) = e {
 						let channel_id = chan.context.channel_id();
 						let user_id = chan.context.get_user_id();
@@ -3416,11 +4108,42 @@ where
 
 	#[cfg(test)]
 	pub(crate) fn funding_transaction_generated_unchecked(&self, temporary_channel_id: &[u8; 32], counterparty_node_id: &PublicKey, funding_transaction: Transaction, output_index: u16) -> Result<(), APIError> {
-		self.funding_transaction_generated_intern(temporary_channel_id, counterparty_node_id, funding_transaction, |_, tx| {
+		self.funding_transaction_generated_intern(temporary_channel_id, counterparty_node_id, funding_transaction, false, |_, tx| {
 			Ok(OutPoint { txid: tx.txid(), index: output_index })
 		})
 	}
 
+	/// Finds the output of the given funding transaction which funds the given channel, as
+	/// described by its [`Event::FundingGenerationReady`].
+	fn find_funding_output(
+		chan: &OutboundV1Channel<<SP::Target as SignerProvider>::Signer>, tx: &Transaction
+	) -> Result<OutPoint, APIError> {
+		if tx.output.len() > u16::max_value() as usize {
+			return Err(APIError::APIMisuseError {
+				err: "Transaction had more than 2^16 outputs, which is not supported".to_owned()
+			});
+		}
+
+		let mut output_index = None;
+		let expected_spk = chan.context.get_funding_redeemscript().to_v0_p2wsh();
+		for (idx, outp) in tx.output.iter().enumerate() {
+			if outp.script_pubkey == expected_spk && outp.value == chan.context.get_value_satoshis() {
+				if output_index.is_some() {
+					return Err(APIError::APIMisuseError {
+						err: "Multiple outputs matched the expected script and value".to_owned()
+					});
+				}
+				output_index = Some(idx as u16);
+			}
+		}
+		if output_index.is_none() {
+			return Err(APIError::APIMisuseError {
+				err: "No output matched the script_pubkey and value in the FundingGenerationReady event".to_owned()
+			});
+		}
+		Ok(OutPoint { txid: tx.txid(), index: output_index.unwrap() })
+	}
+
 	/// Call this upon creation of a funding transaction for the given channel.
 	///
 	/// Returns an [`APIError::APIMisuseError`] if the funding_transaction spent non-SegWit outputs
@@ -3452,6 +4175,30 @@ where
 	/// [`Event::FundingGenerationReady`]: crate::events::Event::FundingGenerationReady
 	/// [`Event::ChannelClosed`]: crate::events::Event::ChannelClosed
 	pub fn funding_transaction_generated(&self, temporary_channel_id: &[u8; 32], counterparty_node_id: &PublicKey, funding_transaction: Transaction) -> Result<(), APIError> {
+		self.batch_funding_transaction_generated(&[(temporary_channel_id, counterparty_node_id)], funding_transaction)
+	}
+
+	/// Call this upon creation of a batch funding transaction for the given channels, which may be
+	/// with different counterparties.
+	///
+	/// Return values are identical to [`Self::funding_transaction_generated`], respective to
+	/// each individual channel and transaction output. Additionally, returns
+	/// [`APIError::APIMisuseError`] if no channels, or the same channel more than once, are
+	/// given, or if another batch with the same funding transaction is pending. If any channel
+	/// cannot be funded, none of the channels in the batch are.
+	///
+	/// Do NOT broadcast the funding transaction yourself. It will only be broadcast via the
+	/// [`BroadcasterInterface`] provided when this `ChannelManager` was constructed once all
+	/// counterparties have returned their signature. Should any of the channels close before then,
+	/// all other channels in the batch are closed with [`ClosureReason::FundingBatchClosure`] and
+	/// a single [`Event::DiscardFunding`] is generated for the funding transaction.
+	///
+	/// Note that channels in a batch cannot be used, even if they are 0-conf, until the funding
+	/// transaction has been broadcast. Channels in a batch whose funding transaction has not been
+	/// broadcast are closed when the `ChannelManager` is reloaded.
+	///
+	/// [`Event::DiscardFunding`]: crate::events::Event::DiscardFunding
+	pub fn batch_funding_transaction_generated(&self, temporary_channels: &[(&[u8; 32], &PublicKey)], funding_transaction: Transaction) -> Result<(), APIError> {
 		let _persistence_guard = PersistenceNotifierGuard::notify_on_drop(self);
 
 		for inp in funding_transaction.input.iter() {
@@ -3473,32 +4220,330 @@ where
 				});
 			}
 		}
-		self.funding_transaction_generated_intern(temporary_channel_id, counterparty_node_id, funding_transaction, |chan, tx| {
-			if tx.output.len() > u16::max_value() as usize {
+		if temporary_channels.is_empty() {
+			return Err(APIError::APIMisuseError {
+				err: "No channels were given to be funded".to_owned()
+			});
+		}
+		for (idx, (temporary_channel_id, counterparty_node_id)) in temporary_channels.iter().enumerate() {
+			if temporary_channels[..idx].iter().any(|(id, node_id)| id == temporary_channel_id && node_id == counterparty_node_id) {
 				return Err(APIError::APIMisuseError {
-					err: "Transaction had more than 2^16 outputs, which is not supported".to_owned()
+					err: format!("Channel with id {} was given more than once", log_bytes!(**temporary_channel_id)),
 				});
 			}
+		}
+		let funding_txid = funding_transaction.txid();
+		if self.funding_batch_states.lock().unwrap().contains_key(&funding_txid) {
+			return Err(APIError::APIMisuseError {
+				err: "A funding batch with the same funding transaction is already pending".to_owned()
+			});
+		}
 
-			let mut output_index = None;
-			let expected_spk = chan.context.get_funding_redeemscript().to_v0_p2wsh();
-			for (idx, outp) in tx.output.iter().enumerate() {
-				if outp.script_pubkey == expected_spk && outp.value == chan.context.get_value_satoshis() {
-					if output_index.is_some() {
-						return Err(APIError::APIMisuseError {
-							err: "Multiple outputs matched the expected script and value".to_owned()
-						});
-					}
-					output_index = Some(idx as u16);
-				}
-			}
-			if output_index.is_none() {
-				return Err(APIError::APIMisuseError {
-					err: "No output matched the script_pubkey and value in the FundingGenerationReady event".to_owned()
-				});
+		// Find the funding outputs for all channels before we fund any of them, so that the batch
+		// doesn't have to be failed if we're given a bogus funding transaction.
+		let mut funding_txos = Vec::with_capacity(temporary_channels.len());
+		for (temporary_channel_id, counterparty_node_id) in temporary_channels.iter() {
+			let per_peer_state = self.per_peer_state.read().unwrap();
+			let peer_state_mutex = per_peer_state.get(*counterparty_node_id)
+				.ok_or_else(|| APIError::ChannelUnavailable { err: format!("Can't find a peer matching the passed counterparty node_id {}", counterparty_node_id) })?;
+			let peer_state = peer_state_mutex.lock().unwrap();
+			let chan = peer_state.outbound_v1_channel_by_id.get(*temporary_channel_id)
+				.ok_or_else(|| APIError::ChannelUnavailable {
+					err: format!(
+						"Channel with id {} not found for the passed counterparty node_id {}",
+						log_bytes!(**temporary_channel_id), counterparty_node_id),
+				})?;
+			funding_txos.push(Self::find_funding_output(chan, &funding_transaction)?);
+		}
+
+		let is_batch_funding = temporary_channels.len() > 1;
+		if is_batch_funding {
+			self.funding_batch_states.lock().unwrap().insert(funding_txid,
+				temporary_channels.iter().zip(funding_txos.iter())
+					.map(|((_, counterparty_node_id), funding_txo)| (funding_txo.to_channel_id(), **counterparty_node_id, false))
+					.collect());
+		}
+		for ((temporary_channel_id, counterparty_node_id), funding_txo) in temporary_channels.iter().zip(funding_txos) {
+			let res = self.funding_transaction_generated_intern(temporary_channel_id, counterparty_node_id,
+				funding_transaction.clone(), is_batch_funding, |_, _| Ok(funding_txo));
+			if res.is_err() && is_batch_funding {
+				// Fail the channels of the batch we've already funded.
+				self.close_funding_batch(&funding_txid);
 			}
-			Ok(OutPoint { txid: tx.txid(), index: output_index.unwrap() })
-		})
+			res?;
+		}
+		Ok(())
+	}
+
+	/// Call this upon receiving an [`Event::FundingTransactionReadyForSigning`], providing the
+	/// funding transaction of a dual-funded channel, or the new funding transaction of a splice,
+	/// with witnesses for all the inputs we contributed to it.
+	///
+	/// Our `tx_signatures` will be sent to our counterparty once allowed, after which the fully
+	/// signed funding transaction is broadcast as soon as our counterparty's signatures are known.
+	///
+	/// Returns [`APIError::APIMisuseError`] if the transaction does not match the negotiated
+	/// funding transaction, is missing witnesses for any of our inputs, or the channel is not
+	/// awaiting signatures for its funding transaction. Returns [`APIError::ChannelUnavailable`]
+	/// if the channel cannot be found.
+	///
+	/// [`Event::FundingTransactionReadyForSigning`]: events::Event::FundingTransactionReadyForSigning
+	pub fn funding_transaction_signed(&self, channel_id: &[u8; 32], counterparty_node_id: &PublicKey, signed_transaction: Transaction) -> Result<(), APIError> {
+		let _persistence_guard = PersistenceNotifierGuard::notify_on_drop(self);
+
+		let per_peer_state = self.per_peer_state.read().unwrap();
+		let peer_state_mutex = per_peer_state.get(counterparty_node_id)
+			.ok_or_else(|| APIError::ChannelUnavailable { err: format!("Can't find a peer matching the passed counterparty node_id {}", counterparty_node_id) })?;
+		let mut peer_state_lock = peer_state_mutex.lock().unwrap();
+		let peer_state = &mut *peer_state_lock;
+		match peer_state.channel_by_id.get_mut(channel_id) {
+			Some(chan) => {
+				let (tx_signatures, funding_tx) = chan.funding_transaction_signed(&signed_transaction, &self.logger)?;
+				if let Some(msg) = tx_signatures {
+					peer_state.pending_msg_events.push(events::MessageSendEvent::SendTxSignatures {
+						node_id: *counterparty_node_id,
+						msg,
+					});
+				}
+				if let Some(tx) = funding_tx {
+					log_info!(self.logger, "Broadcasting funding transaction with txid {}", tx.txid());
+					self.tx_broadcaster.broadcast_transactions(&[&tx]);
+					let mut pending_events = self.pending_events.lock().unwrap();
+					emit_channel_pending_event!(pending_events, chan);
+				}
+				Ok(())
+			},
+			None => Err(APIError::ChannelUnavailable {
+				err: format!("Channel with id {} not found for the passed counterparty node_id {}", log_bytes!(*channel_id), counterparty_node_id)
+			}),
+		}
+	}
+
+	/// Initiates a splice of the channel with the given `channel_id`, replacing its funding output
+	/// with a new one through a funding transaction negotiated interactively with our counterparty.
+	///
+	/// A positive `our_funding_contribution_satoshis` splices funds into the channel from the given
+	/// `funding_inputs`, while a negative one splices funds out of our balance, paying them to a
+	/// change output (as does any excess value of `funding_inputs`). In either case, our share of
+	/// the fees for the new funding transaction at `funding_feerate_per_kw` is paid for by our
+	/// inputs or withdrawn balance.
+	///
+	/// The splice is negotiated once the channel has become quiescent, which may take a while if
+	/// updates are in flight, see [`Self::quiesce_channel`]. Once the new funding transaction has
+	/// been negotiated, an [`Event::FundingTransactionReadyForSigning`] is generated if we
+	/// contributed inputs to it, which must be answered through
+	/// [`Self::funding_transaction_signed`], after which the channel is no longer quiescent.
+	///
+	/// The channel remains usable while the splice is pending: HTLCs can be sent, received and
+	/// forwarded over it, each update being committed to both its current funding output and the
+	/// pending splice's. The channel switches over to its new funding output once the splice
+	/// transaction reaches the channel's required depth and both parties have sent
+	/// `splice_locked`. Until then, the splice transaction can be fee-bumped through
+	/// [`Self::rbf_splice_channel`]. To abandon a splice instead, a transaction double-spending
+	/// one of its inputs (e.g. one of our `funding_inputs`) has to be confirmed. Once it reaches
+	/// [`ANTI_REORG_DELAY`] confirmations the splice is abandoned by both parties and the channel
+	/// keeps its previous funding output. Note that a splice-out to which neither party
+	/// contributed inputs cannot be abandoned.
+	///
+	/// Returns [`APIError::ChannelUnavailable`] if the channel cannot be found, the peer does not
+	/// support splicing and quiescence or the channel isn't in a state allowing it to be spliced,
+	/// and [`APIError::APIMisuseError`] if the splice is invalid, e.g. because the inputs are
+	/// insufficient or our balance cannot cover a splice-out.
+	///
+	/// [`Event::FundingTransactionReadyForSigning`]: events::Event::FundingTransactionReadyForSigning
+	/// [`ANTI_REORG_DELAY`]: crate::chain::channelmonitor::ANTI_REORG_DELAY
+	pub fn splice_channel(&self, channel_id: &[u8; 32], counterparty_node_id: &PublicKey,
+		our_funding_contribution_satoshis: i64, funding_inputs: Vec<(TxIn, Transaction)>, funding_feerate_per_kw: u32
+	) -> Result<(), APIError> {
+		let funding_inputs = Self::length_limit_funding_inputs(funding_inputs)?;
+		self.request_splice(channel_id, counterparty_node_id, |chan, height, logger|
+			chan.splice_channel(our_funding_contribution_satoshis, funding_inputs, funding_feerate_per_kw, height, logger))
+	}
+
+	/// Replaces the latest transaction of the pending splice we initiated on the channel with the
+	/// given `channel_id` with one paying a higher feerate, as negotiated interactively with our
+	/// counterparty once the channel is quiescent again.
+	///
+	/// The replacement keeps both parties' contributions to the channel, our part of which is
+	/// funded by `funding_inputs` as for [`Self::splice_channel`], and must pay a
+	/// `funding_feerate_per_kw` of at least 25/24 of the transaction it replaces. Any of the
+	/// splice's transactions may then confirm, with the channel committing to each of their
+	/// funding outputs until one of them is locked.
+	///
+	/// Returns [`APIError::ChannelUnavailable`] if the channel cannot be found or the peer does
+	/// not support splicing and quiescence, and [`APIError::APIMisuseError`] if the channel has no
+	/// signed and unlocked splice transaction we initiated or the replacement is invalid.
+	pub fn rbf_splice_channel(&self, channel_id: &[u8; 32], counterparty_node_id: &PublicKey,
+		funding_inputs: Vec<(TxIn, Transaction)>, funding_feerate_per_kw: u32
+	) -> Result<(), APIError> {
+		let funding_inputs = Self::length_limit_funding_inputs(funding_inputs)?;
+		self.request_splice(channel_id, counterparty_node_id, |chan, height, logger|
+			chan.rbf_splice_channel(funding_inputs, funding_feerate_per_kw, height, logger))
+	}
+
+	fn request_splice<Req>(&self, channel_id: &[u8; 32], counterparty_node_id: &PublicKey, request: Req) -> Result<(), APIError>
+	where Req: FnOnce(&mut Channel<<SP::Target as SignerProvider>::Signer>, u32, &L) -> Result<Option<msgs::Stfu>, APIError> {
+		let _persistence_guard = PersistenceNotifierGuard::notify_on_drop(self);
+
+		let per_peer_state = self.per_peer_state.read().unwrap();
+		let peer_state_mutex = per_peer_state.get(counterparty_node_id)
+			.ok_or_else(|| APIError::ChannelUnavailable { err: format!("Can't find a peer matching the passed counterparty node_id {}", counterparty_node_id) })?;
+		let mut peer_state_lock = peer_state_mutex.lock().unwrap();
+		let peer_state = &mut *peer_state_lock;
+		if !peer_state.latest_features.supports_splicing() || !peer_state.latest_features.supports_quiescence() {
+			return Err(APIError::ChannelUnavailable { err: format!("Peer {} does not support splicing", counterparty_node_id) });
+		}
+		match peer_state.channel_by_id.get_mut(channel_id) {
+			Some(chan) => {
+				let height = self.best_block.read().unwrap().height();
+				if let Some(msg) = request(chan, height, &self.logger)? {
+					peer_state.pending_msg_events.push(events::MessageSendEvent::SendStfu {
+						node_id: *counterparty_node_id,
+						msg,
+					});
+				}
+				Ok(())
+			},
+			None => Err(APIError::ChannelUnavailable {
+				err: format!("Channel with id {} not found for the passed counterparty node_id {}", log_bytes!(*channel_id), counterparty_node_id)
+			}),
+		}
+	}
+
+	/// Begins making the channel with the given `channel_id` quiescent by exchanging `stfu`
+	/// messages with our counterparty, pausing all updates to it.
+	///
+	/// Any new HTLCs or fee updates will be held in the holding cell while the channel is (or is
+	/// becoming) quiescent. If updates are still pending on the channel, our `stfu` will only be
+	/// sent once they have been irrevocably committed. The channel's progress can be tracked
+	/// through [`ChannelDetails::channel_quiescence_state`].
+	///
+	/// If the channel hasn't become quiescent after a few calls to [`Self::timer_tick_occurred`],
+	/// the peer is disconnected and quiescence will be proposed again upon reconnection. Once
+	/// quiescent, the channel remains so for however long the protocol requiring quiescence takes,
+	/// until its completion is signaled through [`Self::unquiesce_channel`] by both sides, or the
+	/// peer disconnects. The same applies to quiescence proposed by our counterparty.
+	///
+	/// Returns [`APIError::ChannelUnavailable`] if the channel cannot be found, the peer does not
+	/// support quiescence or the channel isn't connected and usable, and
+	/// [`APIError::APIMisuseError`] if the channel is already quiescent or becoming so.
+	pub fn quiesce_channel(&self, channel_id: &[u8; 32], counterparty_node_id: &PublicKey) -> Result<(), APIError> {
+		let _persistence_guard = PersistenceNotifierGuard::notify_on_drop(self);
+
+		let per_peer_state = self.per_peer_state.read().unwrap();
+		let peer_state_mutex = per_peer_state.get(counterparty_node_id)
+			.ok_or_else(|| APIError::ChannelUnavailable { err: format!("Can't find a peer matching the passed counterparty node_id {}", counterparty_node_id) })?;
+		let mut peer_state_lock = peer_state_mutex.lock().unwrap();
+		let peer_state = &mut *peer_state_lock;
+		if !peer_state.latest_features.supports_quiescence() {
+			return Err(APIError::ChannelUnavailable { err: format!("Peer {} does not support quiescence", counterparty_node_id) });
+		}
+		match peer_state.channel_by_id.get_mut(channel_id) {
+			Some(chan) => {
+				if let Some(msg) = chan.propose_quiescence(&self.logger)? {
+					peer_state.pending_msg_events.push(events::MessageSendEvent::SendStfu {
+						node_id: *counterparty_node_id,
+						msg,
+					});
+				}
+				Ok(())
+			},
+			None => Err(APIError::ChannelUnavailable {
+				err: format!("Channel with id {} not found for the passed counterparty node_id {}", log_bytes!(*channel_id), counterparty_node_id)
+			}),
+		}
+	}
+
+	/// Ends quiescence of the channel with the given `channel_id` once the protocol which required
+	/// it, as initiated by either side through [`Self::quiesce_channel`], has completed. Any
+	/// updates held in the meantime are then sent to our counterparty.
+	///
+	/// As there's no message ending quiescence, both sides have to do so on their own, and the
+	/// protocol requiring quiescence has to let each side know once it has completed for both of
+	/// them, e.g., through its final message. Should we send an update, such as an HTLC held while
+	/// quiescent, before our counterparty has also ended quiescence, they will disconnect us.
+	/// Quiescence then ends for both sides with the disconnection, and the update is sent again
+	/// once the peer reconnects.
+	///
+	/// This must not be called for channels which are quiescent to upgrade their channel type,
+	/// which ends quiescence on its own.
+	///
+	/// Returns [`APIError::ChannelUnavailable`] if the channel cannot be found and
+	/// [`APIError::APIMisuseError`] if the channel isn't quiescent or is being upgraded.
+	pub fn unquiesce_channel(&self, channel_id: &[u8; 32], counterparty_node_id: &PublicKey) -> Result<(), APIError> {
+		let _persistence_guard = PersistenceNotifierGuard::notify_on_drop(self);
+
+		let per_peer_state = self.per_peer_state.read().unwrap();
+		let peer_state_mutex = per_peer_state.get(counterparty_node_id)
+			.ok_or_else(|| APIError::ChannelUnavailable { err: format!("Can't find a peer matching the passed counterparty node_id {}", counterparty_node_id) })?;
+		let mut peer_state_lock = peer_state_mutex.lock().unwrap();
+		let peer_state = &mut *peer_state_lock;
+		match peer_state.channel_by_id.get_mut(channel_id) {
+			Some(chan) => chan.unquiesce(&self.logger),
+			None => Err(APIError::ChannelUnavailable {
+				err: format!("Channel with id {} not found for the passed counterparty node_id {}", log_bytes!(*channel_id), counterparty_node_id)
+			}),
+		}
+	}
+
+	/// Upgrades the channel with the given `channel_id` to anchor outputs without closing it, by
+	/// making it quiescent (see [`Self::quiesce_channel`]) and renegotiating its channel type with
+	/// our counterparty through `dyn_propose`.
+	///
+	/// Once our counterparty accepts, both sides exchange commitment transactions using anchor
+	/// outputs, at which point [`ChannelDetails::channel_type`] reflects the upgrade and the
+	/// channel's [`ChannelMonitor`] switches over to it. If our counterparty rejects the upgrade,
+	/// the channel simply resumes operating with its current channel type. An upgrade which hasn't
+	/// been accepted yet is proposed again after a disconnection.
+	///
+	/// Only channels without any pending HTLCs can be upgraded, and the funder of the channel must
+	/// be able to afford the anchor outputs. Much like for opening anchor channels,
+	/// [`ChannelHandshakeConfig::negotiate_anchors_zero_fee_htlc_tx`] must be set, and on-chain
+	/// funds must be available to bump the channel's transactions if we're configured to require
+	/// them, see [`UserConfig::anchor_channel_reserve_config`].
+	///
+	/// Returns [`APIError::ChannelUnavailable`] if the channel cannot be found, the peer does not
+	/// support quiescence, anchor outputs and dynamic commitments, or the channel cannot be
+	/// upgraded at this time, and [`APIError::APIMisuseError`] if we don't support anchor outputs
+	/// or an upgrade is already pending.
+	///
+	/// [`ChannelHandshakeConfig::negotiate_anchors_zero_fee_htlc_tx`]: crate::util::config::ChannelHandshakeConfig::negotiate_anchors_zero_fee_htlc_tx
+	pub fn upgrade_channel_to_anchors(&self, channel_id: &[u8; 32], counterparty_node_id: &PublicKey) -> Result<(), APIError> {
+		let _persistence_guard = PersistenceNotifierGuard::notify_on_drop(self);
+
+		// Channels with anchor outputs can only be read back if we support them.
+		if !self.default_configuration.channel_handshake_config.negotiate_anchors_zero_fee_htlc_tx {
+			return Err(APIError::APIMisuseError {
+				err: "Upgrading channels to anchor outputs requires negotiate_anchors_zero_fee_htlc_tx to be set".to_owned()
+			});
+		}
+		self.check_anchor_channel_reserve_for_new_channel()?;
+
+		let per_peer_state = self.per_peer_state.read().unwrap();
+		let peer_state_mutex = per_peer_state.get(counterparty_node_id)
+			.ok_or_else(|| APIError::ChannelUnavailable { err: format!("Can't find a peer matching the passed counterparty node_id {}", counterparty_node_id) })?;
+		let mut peer_state_lock = peer_state_mutex.lock().unwrap();
+		let peer_state = &mut *peer_state_lock;
+		if !peer_state.latest_features.supports_quiescence() ||
+			!peer_state.latest_features.supports_anchors_zero_fee_htlc_tx() ||
+			!peer_state.latest_features.supports_dynamic_commitments()
+		{
+			return Err(APIError::ChannelUnavailable { err: format!("Peer {} does not support upgrading channels to anchor outputs", counterparty_node_id) });
+		}
+		match peer_state.channel_by_id.get_mut(channel_id) {
+			Some(chan) => {
+				if let Some(msg) = chan.upgrade_to_anchors(&self.logger)? {
+					peer_state.pending_msg_events.push(events::MessageSendEvent::SendStfu {
+						node_id: *counterparty_node_id,
+						msg,
+					});
+				}
+				Ok(())
+			},
+			None => Err(APIError::ChannelUnavailable {
+				err: format!("Channel with id {} not found for the passed counterparty node_id {}", log_bytes!(*channel_id), counterparty_node_id)
+			}),
+		}
 	}
 
 	/// Atomically applies partial updates to the [`ChannelConfig`] of the given channels.
@@ -3567,6 +4612,10 @@ where
 				&mut channel.context
 			} else if let Some(channel) = peer_state.outbound_v1_channel_by_id.get_mut(channel_id) {
 				&mut channel.context
+			} else if let Some(channel) = peer_state.inbound_v2_channel_by_id.get_mut(channel_id) {
+				&mut channel.context
+			} else if let Some(channel) = peer_state.outbound_v2_channel_by_id.get_mut(channel_id) {
+				&mut channel.context
 			} else {
 				// This should not be reachable as we've already checked for non-existence in the previous channel_id loop.
 				debug_assert!(false);
@@ -3669,8 +4718,8 @@ where
 			})?;
 
 		let routing = match payment.forward_info.routing {
-			PendingHTLCRouting::Forward { onion_packet, .. } => {
-				PendingHTLCRouting::Forward { onion_packet, short_channel_id: next_hop_scid }
+			PendingHTLCRouting::Forward { onion_packet, blinded, .. } => {
+				PendingHTLCRouting::Forward { onion_packet, blinded, short_channel_id: next_hop_scid }
 			},
 			_ => unreachable!() // Only `PendingHTLCRouting::Forward`s are intercepted
 		};
@@ -3713,6 +4762,7 @@ where
 				htlc_id: payment.prev_htlc_id,
 				incoming_packet_shared_secret: payment.forward_info.incoming_shared_secret,
 				phantom_shared_secret: None,
+				blinded_failure: payment.forward_info.routing.blinded_failure(),
 			});
 
 			let failure_reason = HTLCFailReason::from_failure_code(0x4000 | 10);
@@ -3750,6 +4800,7 @@ where
 											outgoing_cltv_value, ..
 										}
 									}) => {
+										let blinded_failure = routing.blinded_failure();
 										macro_rules! failure_handler {
 											($msg: expr, $err_code: expr, $err_data: expr, $phantom_ss: expr, $next_hop_unknown: expr) => {
 												log_info!(self.logger, "Failed to accept/forward incoming HTLC: {}", $msg);
@@ -3760,6 +4811,7 @@ where
 													htlc_id: prev_htlc_id,
 													incoming_packet_shared_secret: incoming_shared_secret,
 													phantom_shared_secret: $phantom_ss,
+													blinded_failure,
 												});
 
 												let reason = if $next_hop_unknown {
@@ -3793,7 +4845,10 @@ where
 											let phantom_pubkey_res = self.node_signer.get_node_id(Recipient::PhantomNode);
 											if phantom_pubkey_res.is_ok() && fake_scid::is_valid_phantom(&self.fake_scid_rand_bytes, short_chan_id, &self.genesis_hash) {
 												let phantom_shared_secret = self.node_signer.ecdh(Recipient::PhantomNode, &onion_packet.public_key.unwrap(), None).unwrap().secret_bytes();
-												let next_hop = match onion_utils::decode_next_payment_hop(phantom_shared_secret, &onion_packet.hop_data, onion_packet.hmac, payment_hash) {
+												let next_hop = match onion_utils::decode_next_payment_hop(
+													phantom_shared_secret, &onion_packet.hop_data, onion_packet.hmac,
+													payment_hash, None, &self.node_signer
+												) {
 													Ok(res) => res,
 													Err(onion_utils::OnionDecodeErr::Malformed { err_msg, err_code }) => {
 														let sha256_of_onion = Sha256::hash(&onion_packet.hop_data).into_inner();
@@ -3826,7 +4881,7 @@ where
 											fail_forward!(format!("Unknown short channel id {} for forward HTLC", short_chan_id), 0x4000 | 10, Vec::new(), None);
 										}
 									},
-									HTLCForwardInfo::FailHTLC { .. } => {
+									HTLCForwardInfo::FailHTLC { .. } | HTLCForwardInfo::FailMalformedHTLC { .. } => {
 										// Channel went away before we could fail it. This implies
 										// the channel is now on chain and our counterparty is
 										// trying to broadcast the HTLC-Timeout, but that's their
@@ -3863,7 +4918,7 @@ where
 										prev_short_channel_id, prev_htlc_id, prev_funding_outpoint, prev_user_channel_id: _,
 										forward_info: PendingHTLCInfo {
 											incoming_shared_secret, payment_hash, outgoing_amt_msat, outgoing_cltv_value,
-											routing: PendingHTLCRouting::Forward { onion_packet, .. }, skimmed_fee_msat, ..
+											routing: PendingHTLCRouting::Forward { onion_packet, blinded, .. }, skimmed_fee_msat, ..
 										},
 									}) => {
 										log_trace!(self.logger, "Adding HTLC from short id {} with payment_hash {} to channel with short id {} after delay", prev_short_channel_id, log_bytes!(payment_hash.0), short_chan_id);
@@ -3874,10 +4929,19 @@ where
 											incoming_packet_shared_secret: incoming_shared_secret,
 											// Phantom payments are only PendingHTLCRouting::Receive.
 											phantom_shared_secret: None,
+											blinded_failure: blinded.map(|b| b.failure),
+										});
+										let next_blinding_point = blinded.and_then(|b| {
+											let encrypted_tlvs_ss = self.node_signer.ecdh(
+												Recipient::Node, &b.inbound_blinding_point, None
+											).unwrap().secret_bytes();
+											onion_utils::next_hop_packet_pubkey(
+												&self.secp_ctx, b.inbound_blinding_point, &encrypted_tlvs_ss
+											).ok()
 										});
 										if let Err(e) = chan.get_mut().queue_add_htlc(outgoing_amt_msat,
 											payment_hash, outgoing_cltv_value, htlc_source.clone(),
-											onion_packet, skimmed_fee_msat, &self.fee_estimator,
+											onion_packet, skimmed_fee_msat, next_blinding_point, &self.fee_estimator,
 											&self.logger)
 										{
 											if let ChannelError::Ignore(msg) = e {
@@ -3912,6 +4976,22 @@ where
 											continue;
 										}
 									},
+									HTLCForwardInfo::FailMalformedHTLC { htlc_id, failure_code, sha256_of_onion } => {
+										log_trace!(self.logger, "Failing malformed HTLC back to channel with short id {} (backward HTLC ID {}) after delay", short_chan_id, htlc_id);
+										if let Err(e) = chan.get_mut().queue_fail_malformed_htlc(
+											htlc_id, failure_code, sha256_of_onion, &self.logger
+										) {
+											if let ChannelError::Ignore(msg) = e {
+												log_trace!(self.logger, "Failed to fail HTLC with ID {} backwards to short_id {}: {}", htlc_id, short_chan_id, msg);
+											} else {
+												panic!("Stated return value requirements in queue_fail_malformed_htlc() were not met");
+											}
+											// fail-backs are best-effort, we probably already have one
+											// pending, and if not that's OK, if not, the channel is on
+											// the chain and sending the HTLC-Timeout is their problem.
+											continue;
+										}
+									},
 								}
 							}
 						}
@@ -3926,8 +5006,11 @@ where
 									skimmed_fee_msat, ..
 								}
 							}) => {
+								let blinded_failure = routing.blinded_failure();
 								let (cltv_expiry, onion_payload, payment_data, phantom_shared_secret, mut onion_fields) = match routing {
-									PendingHTLCRouting::Receive { payment_data, payment_metadata, incoming_cltv_expiry, phantom_shared_secret } => {
+									PendingHTLCRouting::Receive {
+										payment_data, payment_metadata, incoming_cltv_expiry, phantom_shared_secret, ..
+									} => {
 										let _legacy_hop_data = Some(payment_data.clone());
 										let onion_fields =
 											RecipientOnionFields { payment_secret: Some(payment_data.payment_secret), payment_metadata };
@@ -3953,6 +5036,7 @@ where
 										htlc_id: prev_htlc_id,
 										incoming_packet_shared_secret: incoming_shared_secret,
 										phantom_shared_secret,
+										blinded_failure,
 									},
 									// We differentiate the received value from the sender intended value
 									// if possible so that we don't prematurely mark MPP payments complete
@@ -3982,6 +5066,7 @@ where
 												htlc_id: $htlc.prev_hop.htlc_id,
 												incoming_packet_shared_secret: $htlc.prev_hop.incoming_packet_shared_secret,
 												phantom_shared_secret,
+												blinded_failure,
 											}), payment_hash,
 											HTLCFailReason::reason(0x4000 | 15, htlc_msat_height_data),
 											HTLCDestination::FailedPayment { payment_hash: $payment_hash },
@@ -4057,7 +5142,7 @@ where
 											#[allow(unused_assignments)] {
 												committed_to_claimable = true;
 											}
-											let prev_channel_id = prev_funding_outpoint.to_channel_id();
+											let prev_channel_id = self.channel_id_for_funding_txo(&prev_funding_outpoint);
 											htlcs.push(claimable_htlc);
 											let amount_msat = htlcs.iter().map(|htlc| htlc.value).sum();
 											htlcs.iter_mut().for_each(|htlc| htlc.total_value_received = Some(amount_msat));
@@ -4155,7 +5240,7 @@ where
 									},
 								};
 							},
-							HTLCForwardInfo::FailHTLC { .. } => {
+							HTLCForwardInfo::FailHTLC { .. } | HTLCForwardInfo::FailMalformedHTLC { .. } => {
 								panic!("Got pending fail of our own HTLC");
 							}
 						}
@@ -4213,11 +5298,11 @@ where
 						if let Some(peer_state_mutex) = per_peer_state.get(&counterparty_node_id) {
 							let mut peer_state_lock = peer_state_mutex.lock().unwrap();
 							let peer_state = &mut *peer_state_lock;
-							match peer_state.channel_by_id.entry(funding_txo.to_channel_id()) {
+							match peer_state.channel_by_id.entry(self.channel_id_for_funding_txo(&funding_txo)) {
 								hash_map::Entry::Occupied(mut chan) => {
 									updated_chan = true;
 									handle_new_monitor_update!(self, funding_txo, update.clone(),
-										peer_state_lock, peer_state, per_peer_state, chan).map(|_| ())
+										peer_state_lock, peer_state, per_peer_state, chan, REPLAYING).map(|_| ())
 								},
 								hash_map::Entry::Vacant(_) => Ok(()),
 							}
@@ -4416,6 +5501,18 @@ where
 									},
 								},
 							});
+						} else if chan.should_disconnect_peer_awaiting_quiescence() {
+							log_debug!(self.logger, "Disconnecting peer {} as channel {} did not become quiescent or complete its channel type upgrade in time",
+									counterparty_node_id, log_bytes!(*chan_id));
+							pending_msg_events.push(MessageSendEvent::HandleError {
+								node_id: counterparty_node_id,
+								action: msgs::ErrorAction::DisconnectPeerWithWarning {
+									msg: msgs::WarningMessage {
+										channel_id: *chan_id,
+										data: "Disconnecting due to timeout awaiting quiescence".to_owned(),
+									},
+								},
+							});
 						}
 
 						true
@@ -4439,6 +5536,8 @@ where
 					};
 					peer_state.outbound_v1_channel_by_id.retain(|chan_id, chan| process_unfunded_channel_tick(chan_id, &mut chan.context, &mut chan.unfunded_context));
 					peer_state.inbound_v1_channel_by_id.retain(|chan_id, chan| process_unfunded_channel_tick(chan_id, &mut chan.context, &mut chan.unfunded_context));
+					peer_state.outbound_v2_channel_by_id.retain(|chan_id, chan| process_unfunded_channel_tick(chan_id, &mut chan.context, &mut chan.unfunded_context));
+					peer_state.inbound_v2_channel_by_id.retain(|chan_id, chan| process_unfunded_channel_tick(chan_id, &mut chan.context, &mut chan.unfunded_context));
 
 					if peer_state.ok_to_remove(true) {
 						pending_peers_awaiting_removal.push(counterparty_node_id);
@@ -4511,7 +5610,18 @@ where
 				let _ = handle_error!(self, err, counterparty_node_id);
 			}
 
-			self.pending_outbound_payments.remove_stale_resolved_payments(&self.pending_events);
+			#[cfg(feature = "std")]
+			let duration_since_epoch = std::time::SystemTime::now()
+				.duration_since(std::time::SystemTime::UNIX_EPOCH)
+				.expect("SystemTime::now() should come after SystemTime::UNIX_EPOCH");
+			#[cfg(not(feature = "std"))]
+			let duration_since_epoch = Duration::from_secs(
+				self.highest_seen_timestamp.load(Ordering::Acquire).saturating_sub(7200) as u64
+			);
+
+			self.pending_outbound_payments.remove_stale_payments(
+				duration_since_epoch, &self.pending_events
+			);
 
 			// Technically we don't need to do this here, but if we have holding cell entries in a
 			// channel that need freeing, it's better to do that here and block a background task
@@ -4673,9 +5783,34 @@ where
 					&self.pending_events, &self.logger)
 				{ self.push_pending_forwards_ev(); }
 			},
-			HTLCSource::PreviousHopData(HTLCPreviousHopData { ref short_channel_id, ref htlc_id, ref incoming_packet_shared_secret, ref phantom_shared_secret, ref outpoint }) => {
-				log_trace!(self.logger, "Failing HTLC with payment_hash {} backwards from us with {:?}", log_bytes!(payment_hash.0), onion_error);
-				let err_packet = onion_error.get_encrypted_failure_packet(incoming_packet_shared_secret, phantom_shared_secret);
+			HTLCSource::PreviousHopData(HTLCPreviousHopData {
+				ref short_channel_id, ref htlc_id, ref incoming_packet_shared_secret,
+				ref phantom_shared_secret, ref outpoint, ref blinded_failure,
+			}) => {
+				log_trace!(self.logger, "Failing {}HTLC with payment_hash {} backwards from us: {:?}",
+					if blinded_failure.is_some() { "blinded " } else { "" }, log_bytes!(payment_hash.0), onion_error);
+				let failure = match blinded_failure {
+					Some(BlindedFailure::FromIntroductionNode) => {
+						let blinded_onion_error = HTLCFailReason::reason(INVALID_ONION_BLINDING, vec![0; 32]);
+						let err_packet = blinded_onion_error.get_encrypted_failure_packet(
+							incoming_packet_shared_secret, phantom_shared_secret
+						);
+						HTLCForwardInfo::FailHTLC { htlc_id: *htlc_id, err_packet }
+					},
+					Some(BlindedFailure::FromBlindedNode) => {
+						HTLCForwardInfo::FailMalformedHTLC {
+							htlc_id: *htlc_id,
+							failure_code: INVALID_ONION_BLINDING,
+							sha256_of_onion: [0; 32]
+						}
+					},
+					None => {
+						let err_packet = onion_error.get_encrypted_failure_packet(
+							incoming_packet_shared_secret, phantom_shared_secret
+						);
+						HTLCForwardInfo::FailHTLC { htlc_id: *htlc_id, err_packet }
+					}
+				};
 
 				let mut push_forward_ev = false;
 				let mut forward_htlcs = self.forward_htlcs.lock().unwrap();
@@ -4684,17 +5819,17 @@ where
 				}
 				match forward_htlcs.entry(*short_channel_id) {
 					hash_map::Entry::Occupied(mut entry) => {
-						entry.get_mut().push(HTLCForwardInfo::FailHTLC { htlc_id: *htlc_id, err_packet });
+						entry.get_mut().push(failure);
 					},
 					hash_map::Entry::Vacant(entry) => {
-						entry.insert(vec!(HTLCForwardInfo::FailHTLC { htlc_id: *htlc_id, err_packet }));
+						entry.insert(vec!(failure));
 					}
 				}
 				mem::drop(forward_htlcs);
 				if push_forward_ev { self.push_pending_forwards_ev(); }
 				let mut pending_events = self.pending_events.lock().unwrap();
 				pending_events.push_back((events::Event::HTLCHandlingFailed {
-					prev_channel_id: outpoint.to_channel_id(),
+					prev_channel_id: self.channel_id_for_funding_txo(&outpoint),
 					failed_next_destination: destination,
 				}, None));
 			},
@@ -4836,11 +5971,14 @@ where
 		let during_init = !self.background_events_processed_since_startup.load(Ordering::Acquire);
 
 		{
+			let chan_id = self.channel_id_for_funding_txo(&prev_hop.outpoint);
+			// The channel may have been spliced since the HTLC was received, replacing the short
+			// channel id and funding outpoint we know it by.
+			let spliced_counterparty_node_id_opt = self.id_to_peer.lock().unwrap().get(&chan_id).copied();
 			let per_peer_state = self.per_peer_state.read().unwrap();
-			let chan_id = prev_hop.outpoint.to_channel_id();
 			let counterparty_node_id_opt = match self.short_to_chan_info.read().unwrap().get(&prev_hop.short_channel_id) {
 				Some((cp_id, _dup_chan_id)) => Some(cp_id.clone()),
-				None => None
+				None => spliced_counterparty_node_id_opt,
 			};
 
 			let peer_state_opt = counterparty_node_id_opt.as_ref().map(
@@ -4853,6 +5991,7 @@ where
 				let peer_state = &mut *peer_state_lock;
 				if let hash_map::Entry::Occupied(mut chan) = peer_state.channel_by_id.entry(chan_id) {
 					let counterparty_node_id = chan.get().context.get_counterparty_node_id();
+					let funding_txo = chan.get().context.get_funding_txo().unwrap();
 					let fulfill_res = chan.get_mut().get_update_fulfill_htlc_and_commit(prev_hop.htlc_id, payment_preimage, &self.logger);
 
 					if let UpdateFulfillCommitFetch::NewClaim { htlc_value_msat, monitor_update } = fulfill_res {
@@ -4862,7 +6001,7 @@ where
 							peer_state.monitor_update_blocked_actions.entry(chan_id).or_insert(Vec::new()).push(action);
 						}
 						if !during_init {
-							let res = handle_new_monitor_update!(self, prev_hop.outpoint, monitor_update, peer_state_lock,
+							let res = handle_new_monitor_update!(self, funding_txo, monitor_update, peer_state_lock,
 								peer_state, per_peer_state, chan);
 							if let Err(e) = res {
 								// TODO: This is a *critical* error - we probably updated the outbound edge
@@ -4874,13 +6013,17 @@ where
 						} else {
 							// If we're running during init we cannot update a monitor directly -
 							// they probably haven't actually been loaded yet. Instead, push the
-							// monitor update as a background event.
-							self.pending_background_events.lock().unwrap().push(
-								BackgroundEvent::MonitorUpdateRegeneratedOnStartup {
-									counterparty_node_id,
-									funding_txo: prev_hop.outpoint,
-									update: monitor_update.clone(),
-								});
+							// monitor update as a background event, once for each monitor which
+							// would have had it mirrored to it.
+							let mut pending_background_events = self.pending_background_events.lock().unwrap();
+							for funding_txo in core::iter::once(funding_txo).chain(chan.get().context.get_pending_splice_funding_txos()) {
+								pending_background_events.push(
+									BackgroundEvent::MonitorUpdateRegeneratedOnStartup {
+										counterparty_node_id,
+										funding_txo,
+										update: monitor_update.clone(),
+									});
+							}
 						}
 					}
 					return Ok(());
@@ -4955,7 +6098,7 @@ where
 								event: events::Event::PaymentForwarded {
 									fee_earned_msat,
 									claim_from_onchain_tx: from_onchain,
-									prev_channel_id: Some(prev_outpoint.to_channel_id()),
+									prev_channel_id: Some(self.channel_id_for_funding_txo(&prev_outpoint)),
 									next_channel_id: Some(next_channel_id),
 									outbound_amount_forwarded_msat: forwarded_htlc_value_msat,
 								},
@@ -5005,15 +6148,17 @@ where
 		channel: &mut Channel<<SP::Target as SignerProvider>::Signer>, raa: Option<msgs::RevokeAndACK>,
 		commitment_update: Option<msgs::CommitmentUpdate>, order: RAACommitmentOrder,
 		pending_forwards: Vec<(PendingHTLCInfo, u64)>, funding_broadcastable: Option<Transaction>,
-		channel_ready: Option<msgs::ChannelReady>, announcement_sigs: Option<msgs::AnnouncementSignatures>)
+		channel_ready: Option<msgs::ChannelReady>, announcement_sigs: Option<msgs::AnnouncementSignatures>,
+		tx_signatures: Option<msgs::TxSignatures>)
 	-> Option<(u64, OutPoint, u128, Vec<(PendingHTLCInfo, u64)>)> {
-		log_trace!(self.logger, "Handling channel resumption for channel {} with {} RAA, {} commitment update, {} pending forwards, {}broadcasting funding, {} channel ready, {} announcement",
+		log_trace!(self.logger, "Handling channel resumption for channel {} with {} RAA, {} commitment update, {} pending forwards, {}broadcasting funding, {} channel ready, {} announcement, {} tx_signatures",
 			log_bytes!(channel.context.channel_id()),
 			if raa.is_some() { "an" } else { "no" },
 			if commitment_update.is_some() { "a" } else { "no" }, pending_forwards.len(),
 			if funding_broadcastable.is_some() { "" } else { "not " },
 			if channel_ready.is_some() { "sending" } else { "without" },
-			if announcement_sigs.is_some() { "sending" } else { "without" });
+			if announcement_sigs.is_some() { "sending" } else { "without" },
+			if tx_signatures.is_some() { "sending" } else { "without" });
 
 		let mut htlc_forwards = None;
 
@@ -5060,6 +6205,13 @@ where
 			},
 		}
 
+		if let Some(msg) = tx_signatures {
+			pending_msg_events.push(events::MessageSendEvent::SendTxSignatures {
+				node_id: counterparty_node_id,
+				msg,
+			});
+		}
+
 		if let Some(tx) = funding_broadcastable {
 			log_info!(self.logger, "Broadcasting funding transaction with txid {}", tx.txid());
 			self.tx_broadcaster.broadcast_transactions(&[&tx]);
@@ -5077,13 +6229,14 @@ where
 	fn channel_monitor_updated(&self, funding_txo: &OutPoint, highest_applied_update_id: u64, counterparty_node_id: Option<&PublicKey>) {
 		debug_assert!(self.total_consistency_lock.try_write().is_err()); // Caller holds read lock
 
+		let channel_id = self.channel_id_for_funding_txo(funding_txo);
 		let counterparty_node_id = match counterparty_node_id {
 			Some(cp_id) => cp_id.clone(),
 			None => {
 				// TODO: Once we can rely on the counterparty_node_id from the
 				// monitor event, this and the id_to_peer map should be removed.
 				let id_to_peer = self.id_to_peer.lock().unwrap();
-				match id_to_peer.get(&funding_txo.to_channel_id()) {
+				match id_to_peer.get(&channel_id) {
 					Some(cp_id) => cp_id.clone(),
 					None => return,
 				}
@@ -5096,11 +6249,11 @@ where
 		peer_state_lock = peer_state_mutex_opt.unwrap().lock().unwrap();
 		let peer_state = &mut *peer_state_lock;
 		let channel =
-			if let Some(chan) = peer_state.channel_by_id.get_mut(&funding_txo.to_channel_id()) {
+			if let Some(chan) = peer_state.channel_by_id.get_mut(&channel_id) {
 				chan
 			} else {
 				let update_actions = peer_state.monitor_update_blocked_actions
-					.remove(&funding_txo.to_channel_id()).unwrap_or(Vec::new());
+					.remove(&channel_id).unwrap_or(Vec::new());
 				mem::drop(peer_state_lock);
 				mem::drop(per_peer_state);
 				self.handle_monitor_update_completion_actions(update_actions);
@@ -5117,6 +6270,14 @@ where
 		if !channel.is_awaiting_monitor_update() || channel.context.get_latest_monitor_update_id() != highest_applied_update_id {
 			return;
 		}
+		// The monitors watching any pending splice's funding outputs must have caught up as well.
+		let in_flight_monitor_updates = &peer_state.in_flight_monitor_updates;
+		let all_monitors_updated = channel.context.get_funding_txo().into_iter()
+			.chain(channel.context.get_pending_splice_funding_txos())
+			.all(|txo| in_flight_monitor_updates.get(&txo).map_or(true, |pending| pending.is_empty()));
+		if !all_monitors_updated {
+			return;
+		}
 		handle_monitor_update_completion!(self, peer_state_lock, peer_state, per_peer_state, channel);
 	}
 
@@ -5134,8 +6295,19 @@ where
 	/// for zero confirmations. Instead, `accept_inbound_channel_from_trusted_peer_0conf` must be
 	/// used to accept such channels.
 	///
+	/// If the request is for a dual-funded channel, it is accepted without contributing any funds
+	/// to the channel. Use [`ChannelManager::accept_inbound_channel_with_contribution`] to
+	/// contribute funds instead.
+	///
+	/// If [`AnchorChannelReserveConfig::refuse_channels_without_reserve`] is set and the request is
+	/// for an anchor channel, [`APIError::ChannelUnavailable`] is returned if we lack the on-chain
+	/// funds to bump its transactions. The channel remains pending in that case, i.e., it may be
+	/// accepted once sufficient funds are available or rejected via
+	/// [`ChannelManager::force_close_without_broadcasting_txn`].
+	///
 	/// [`Event::OpenChannelRequest`]: events::Event::OpenChannelRequest
 	/// [`Event::ChannelClosed::user_channel_id`]: events::Event::ChannelClosed::user_channel_id
+	/// [`AnchorChannelReserveConfig::refuse_channels_without_reserve`]: crate::util::config::AnchorChannelReserveConfig::refuse_channels_without_reserve
 	pub fn accept_inbound_channel(&self, temporary_channel_id: &[u8; 32], counterparty_node_id: &PublicKey, user_channel_id: u128) -> Result<(), APIError> {
 		self.do_accept_inbound_channel(temporary_channel_id, counterparty_node_id, false, user_channel_id)
 	}
@@ -5167,17 +6339,31 @@ where
 
 		let peers_without_funded_channels =
 			self.peers_without_funded_channels(|peer| { peer.total_channel_count() > 0 });
+		let anchor_reserve_check = self.check_anchor_channel_reserve_for_new_channel();
 		let per_peer_state = self.per_peer_state.read().unwrap();
 		let peer_state_mutex = per_peer_state.get(counterparty_node_id)
 			.ok_or_else(|| APIError::ChannelUnavailable { err: format!("Can't find a peer matching the passed counterparty node_id {}", counterparty_node_id) })?;
 		let mut peer_state_lock = peer_state_mutex.lock().unwrap();
 		let peer_state = &mut *peer_state_lock;
+		if peer_state.inbound_v2_channel_by_id.contains_key(temporary_channel_id) {
+			if accept_0conf {
+				return Err(APIError::APIMisuseError { err: "Zero-conf is not supported for dual-funded channels".to_owned() });
+			}
+			// Accepting a dual-funded channel this way means we don't contribute any funds to it.
+			mem::drop(peer_state_lock);
+			mem::drop(per_peer_state);
+			return self.do_accept_inbound_dual_funded_channel(temporary_channel_id, counterparty_node_id,
+				user_channel_id, 0, Vec::new(), peers_without_funded_channels, anchor_reserve_check);
+		}
 		let is_only_peer_channel = peer_state.total_channel_count() == 1;
 		match peer_state.inbound_v1_channel_by_id.entry(temporary_channel_id.clone()) {
 			hash_map::Entry::Occupied(mut channel) => {
 				if !channel.get().is_awaiting_accept() {
 					return Err(APIError::APIMisuseError { err: "The channel isn't currently awaiting to be accepted.".to_owned() });
 				}
+				if channel.get().context.get_channel_type().supports_anchors_zero_fee_htlc_tx() {
+					anchor_reserve_check?;
+				}
 				if accept_0conf {
 					channel.get_mut().set_0conf();
 				} else if channel.get().context.get_channel_type().requires_zero_conf() {
@@ -5219,27 +6405,131 @@ where
 		Ok(())
 	}
 
-	/// Gets the number of peers which match the given filter and do not have any funded, outbound,
-	/// or 0-conf channels.
+	/// Accepts a request to open a dual-funded channel after an [`Event::OpenChannelRequest`],
+	/// contributing `funding_satoshis` from the given inputs to the channel.
 	///
-	/// The filter is called for each peer and provided with the number of unfunded, inbound, and
-	/// non-0-conf channels we have with the peer.
-	fn peers_without_funded_channels<Filter>(&self, maybe_count_peer: Filter) -> usize
-	where Filter: Fn(&PeerState<<SP::Target as SignerProvider>::Signer>) -> bool {
-		let mut peers_without_funded_channels = 0;
-		let best_block_height = self.best_block.read().unwrap().height();
-		{
-			let peer_state_lock = self.per_peer_state.read().unwrap();
-			for (_, peer_mtx) in peer_state_lock.iter() {
-				let peer = peer_mtx.lock().unwrap();
-				if !maybe_count_peer(&*peer) { continue; }
-				let num_unfunded_channels = Self::unfunded_channel_count(&peer, best_block_height);
-				if num_unfunded_channels == peer.total_channel_count() {
-					peers_without_funded_channels += 1;
-				}
-			}
-		}
-		return peers_without_funded_channels;
+	/// The `temporary_channel_id` parameter indicates which inbound channel should be accepted,
+	/// and the `counterparty_node_id` parameter is the id of the peer which has requested to open
+	/// the channel.
+	///
+	/// The `user_channel_id` parameter will be provided back in
+	/// [`Event::ChannelClosed::user_channel_id`] and
+	/// [`Event::FundingTransactionReadyForSigning::user_channel_id`] to allow tracking of which
+	/// events correspond with which `accept_inbound_channel_with_contribution` call.
+	///
+	/// Each of the `funding_inputs` must spend a SegWit output of the accompanying previous
+	/// transaction, and together they must cover `funding_satoshis` as well as the fees for our
+	/// share of the funding transaction. Any excess is returned to us in a change output paying to
+	/// [`SignerProvider::get_destination_script`]. Once the funding transaction has been
+	/// negotiated, an [`Event::FundingTransactionReadyForSigning`] will be generated if we
+	/// contributed any inputs.
+	///
+	/// Note that the channel will not be accepted if an error is returned, but may be accepted
+	/// again with a different contribution.
+	///
+	/// [`Event::OpenChannelRequest`]: events::Event::OpenChannelRequest
+	/// [`Event::ChannelClosed::user_channel_id`]: events::Event::ChannelClosed::user_channel_id
+	/// [`Event::FundingTransactionReadyForSigning`]: events::Event::FundingTransactionReadyForSigning
+	/// [`Event::FundingTransactionReadyForSigning::user_channel_id`]: events::Event::FundingTransactionReadyForSigning::user_channel_id
+	pub fn accept_inbound_channel_with_contribution(&self, temporary_channel_id: &[u8; 32],
+		counterparty_node_id: &PublicKey, user_channel_id: u128, funding_satoshis: u64,
+		funding_inputs: Vec<(TxIn, Transaction)>
+	) -> Result<(), APIError> {
+		let funding_inputs = Self::length_limit_funding_inputs(funding_inputs)?;
+		let _persistence_guard = PersistenceNotifierGuard::notify_on_drop(self);
+
+		let peers_without_funded_channels =
+			self.peers_without_funded_channels(|peer| { peer.total_channel_count() > 0 });
+		let anchor_reserve_check = self.check_anchor_channel_reserve_for_new_channel();
+		self.do_accept_inbound_dual_funded_channel(temporary_channel_id, counterparty_node_id,
+			user_channel_id, funding_satoshis, funding_inputs, peers_without_funded_channels,
+			anchor_reserve_check)
+	}
+
+	fn do_accept_inbound_dual_funded_channel(&self, temporary_channel_id: &[u8; 32],
+		counterparty_node_id: &PublicKey, user_channel_id: u128, funding_satoshis: u64,
+		funding_inputs: Vec<(TxIn, TransactionU16LenLimited)>, peers_without_funded_channels: usize,
+		anchor_reserve_check: Result<(), APIError>,
+	) -> Result<(), APIError> {
+		let per_peer_state = self.per_peer_state.read().unwrap();
+		let peer_state_mutex = per_peer_state.get(counterparty_node_id)
+			.ok_or_else(|| APIError::ChannelUnavailable { err: format!("Can't find a peer matching the passed counterparty node_id {}", counterparty_node_id) })?;
+		let mut peer_state_lock = peer_state_mutex.lock().unwrap();
+		let peer_state = &mut *peer_state_lock;
+		let is_only_peer_channel = peer_state.total_channel_count() == 1;
+		match peer_state.inbound_v2_channel_by_id.entry(temporary_channel_id.clone()) {
+			hash_map::Entry::Occupied(mut channel) => {
+				if !channel.get().is_awaiting_accept() {
+					return Err(APIError::APIMisuseError { err: "The channel isn't currently awaiting to be accepted.".to_owned() });
+				}
+				if channel.get().context.get_channel_type().supports_anchors_zero_fee_htlc_tx() {
+					anchor_reserve_check?;
+				}
+				if channel.get().context.get_channel_type().requires_zero_conf() {
+					let send_msg_err_event = events::MessageSendEvent::HandleError {
+						node_id: channel.get().context.get_counterparty_node_id(),
+						action: msgs::ErrorAction::SendErrorMessage{
+							msg: msgs::ErrorMessage { channel_id: temporary_channel_id.clone(), data: "No zero confirmation channels accepted".to_owned(), }
+						}
+					};
+					peer_state.pending_msg_events.push(send_msg_err_event);
+					let _ = remove_channel!(self, channel);
+					return Err(APIError::APIMisuseError { err: "Zero-conf is not supported for dual-funded channels".to_owned() });
+				}
+				// If this peer already has some channels, a new channel won't increase our number of peers
+				// with unfunded channels, so as long as we aren't over the maximum number of unfunded
+				// channels per-peer we can accept channels from a peer with existing ones.
+				if is_only_peer_channel && peers_without_funded_channels >= MAX_UNFUNDED_CHANNEL_PEERS {
+					let send_msg_err_event = events::MessageSendEvent::HandleError {
+						node_id: channel.get().context.get_counterparty_node_id(),
+						action: msgs::ErrorAction::SendErrorMessage{
+							msg: msgs::ErrorMessage { channel_id: temporary_channel_id.clone(), data: "Have too many peers with unfunded channels, not accepting new ones".to_owned(), }
+						}
+					};
+					peer_state.pending_msg_events.push(send_msg_err_event);
+					let _ = remove_channel!(self, channel);
+					return Err(APIError::APIMisuseError { err: "Too many peers with unfunded channels, refusing to accept new ones".to_owned() });
+				}
+
+				let msg = channel.get_mut().accept_inbound_dual_funded_channel(user_channel_id,
+					funding_satoshis, funding_inputs, &self.entropy_source, &self.signer_provider)?;
+				// Once accepted, the channel is known by its final V2 channel_id, which our
+				// counterparty will use for the funding transaction negotiation.
+				let channel = channel.remove();
+				peer_state.pending_msg_events.push(events::MessageSendEvent::SendAcceptChannelV2 {
+					node_id: channel.context.get_counterparty_node_id(),
+					msg,
+				});
+				peer_state.inbound_v2_channel_by_id.insert(channel.context.channel_id(), channel);
+			}
+			hash_map::Entry::Vacant(_) => {
+				return Err(APIError::ChannelUnavailable { err: format!("Channel with id {} not found for the passed counterparty node_id {}", log_bytes!(*temporary_channel_id), counterparty_node_id) });
+			}
+		}
+		Ok(())
+	}
+
+	/// Gets the number of peers which match the given filter and do not have any funded, outbound,
+	/// or 0-conf channels.
+	///
+	/// The filter is called for each peer and provided with the number of unfunded, inbound, and
+	/// non-0-conf channels we have with the peer.
+	fn peers_without_funded_channels<Filter>(&self, maybe_count_peer: Filter) -> usize
+	where Filter: Fn(&PeerState<<SP::Target as SignerProvider>::Signer>) -> bool {
+		let mut peers_without_funded_channels = 0;
+		let best_block_height = self.best_block.read().unwrap().height();
+		{
+			let peer_state_lock = self.per_peer_state.read().unwrap();
+			for (_, peer_mtx) in peer_state_lock.iter() {
+				let peer = peer_mtx.lock().unwrap();
+				if !maybe_count_peer(&*peer) { continue; }
+				let num_unfunded_channels = Self::unfunded_channel_count(&peer, best_block_height);
+				if num_unfunded_channels == peer.total_channel_count() {
+					peers_without_funded_channels += 1;
+				}
+			}
+		}
+		return peers_without_funded_channels;
 	}
 
 	fn unfunded_channel_count(
@@ -5260,6 +6550,8 @@ where
 				num_unfunded_channels += 1;
 			}
 		}
+		// Dual-funded channels are never zero-conf.
+		num_unfunded_channels += peer.inbound_v2_channel_by_id.len();
 		num_unfunded_channels
 	}
 
@@ -5285,102 +6577,736 @@ where
 
 		let per_peer_state = self.per_peer_state.read().unwrap();
 		let peer_state_mutex = per_peer_state.get(counterparty_node_id)
-		    .ok_or_else(|| {
+		    .ok_or_else(|| {
+				debug_assert!(false);
+				MsgHandleErrInternal::send_err_msg_no_close(format!("Can't find a peer matching the passed counterparty node_id {}", counterparty_node_id), msg.temporary_channel_id.clone())
+			})?;
+		let mut peer_state_lock = peer_state_mutex.lock().unwrap();
+		let peer_state = &mut *peer_state_lock;
+
+		// If this peer already has some channels, a new channel won't increase our number of peers
+		// with unfunded channels, so as long as we aren't over the maximum number of unfunded
+		// channels per-peer we can accept channels from a peer with existing ones.
+		if peer_state.total_channel_count() == 0 &&
+			channeled_peers_without_funding >= MAX_UNFUNDED_CHANNEL_PEERS &&
+			!self.default_configuration.manually_accept_inbound_channels
+		{
+			return Err(MsgHandleErrInternal::send_err_msg_no_close(
+				"Have too many peers with unfunded channels, not accepting new ones".to_owned(),
+				msg.temporary_channel_id.clone()));
+		}
+
+		let best_block_height = self.best_block.read().unwrap().height();
+		if Self::unfunded_channel_count(peer_state, best_block_height) >= MAX_UNFUNDED_CHANS_PER_PEER {
+			return Err(MsgHandleErrInternal::send_err_msg_no_close(
+				format!("Refusing more than {} unfunded channels.", MAX_UNFUNDED_CHANS_PER_PEER),
+				msg.temporary_channel_id.clone()));
+		}
+
+		let mut channel = match InboundV1Channel::new(&self.fee_estimator, &self.entropy_source, &self.signer_provider,
+			counterparty_node_id.clone(), &self.channel_type_features(), &peer_state.latest_features, msg, user_channel_id,
+			&self.default_configuration, best_block_height, &self.logger, outbound_scid_alias)
+		{
+			Err(e) => {
+				self.outbound_scid_aliases.lock().unwrap().remove(&outbound_scid_alias);
+				return Err(MsgHandleErrInternal::from_chan_no_close(e, msg.temporary_channel_id));
+			},
+			Ok(res) => res
+		};
+		let channel_id = channel.context.channel_id();
+		let channel_exists = peer_state.has_channel(&channel_id);
+		if channel_exists {
+			self.outbound_scid_aliases.lock().unwrap().remove(&outbound_scid_alias);
+			return Err(MsgHandleErrInternal::send_err_msg_no_close("temporary_channel_id collision for the same peer!".to_owned(), msg.temporary_channel_id.clone()))
+		} else {
+			if !self.default_configuration.manually_accept_inbound_channels {
+				let channel_type = channel.context.get_channel_type();
+				if channel_type.requires_zero_conf() {
+					return Err(MsgHandleErrInternal::send_err_msg_no_close("No zero confirmation channels accepted".to_owned(), msg.temporary_channel_id.clone()));
+				}
+				if channel_type.requires_anchors_zero_fee_htlc_tx() {
+					return Err(MsgHandleErrInternal::send_err_msg_no_close("No channels with anchor outputs accepted".to_owned(), msg.temporary_channel_id.clone()));
+				}
+				peer_state.pending_msg_events.push(events::MessageSendEvent::SendAcceptChannel {
+					node_id: counterparty_node_id.clone(),
+					msg: channel.accept_inbound_channel(user_channel_id),
+				});
+			} else {
+				let mut pending_events = self.pending_events.lock().unwrap();
+				pending_events.push_back((events::Event::OpenChannelRequest {
+					temporary_channel_id: msg.temporary_channel_id.clone(),
+					counterparty_node_id: counterparty_node_id.clone(),
+					funding_satoshis: msg.funding_satoshis,
+					channel_negotiation_type: InboundChannelFunds::PushMsat(msg.push_msat),
+					channel_type: channel.context.get_channel_type().clone(),
+				}, None));
+			}
+			peer_state.inbound_v1_channel_by_id.insert(channel_id, channel);
+		}
+		Ok(())
+	}
+
+	fn internal_accept_channel(&self, counterparty_node_id: &PublicKey, msg: &msgs::AcceptChannel) -> Result<(), MsgHandleErrInternal> {
+		let (value, output_script, user_id) = {
+			let per_peer_state = self.per_peer_state.read().unwrap();
+			let peer_state_mutex = per_peer_state.get(counterparty_node_id)
+				.ok_or_else(|| {
+					debug_assert!(false);
+					MsgHandleErrInternal::send_err_msg_no_close(format!("Can't find a peer matching the passed counterparty node_id {}", counterparty_node_id), msg.temporary_channel_id)
+				})?;
+			let mut peer_state_lock = peer_state_mutex.lock().unwrap();
+			let peer_state = &mut *peer_state_lock;
+			match peer_state.outbound_v1_channel_by_id.entry(msg.temporary_channel_id) {
+				hash_map::Entry::Occupied(mut chan) => {
+					try_v1_outbound_chan_entry!(self, chan.get_mut().accept_channel(&msg, &self.default_configuration.channel_handshake_limits, &peer_state.latest_features), chan);
+					(chan.get().context.get_value_satoshis(), chan.get().context.get_funding_redeemscript().to_v0_p2wsh(), chan.get().context.get_user_id())
+				},
+				hash_map::Entry::Vacant(_) => return Err(MsgHandleErrInternal::send_err_msg_no_close(format!("Got a message for a channel from the wrong node! No such channel for the passed counterparty_node_id {}", counterparty_node_id), msg.temporary_channel_id))
+			}
+		};
+		let mut pending_events = self.pending_events.lock().unwrap();
+		pending_events.push_back((events::Event::FundingGenerationReady {
+			temporary_channel_id: msg.temporary_channel_id,
+			counterparty_node_id: *counterparty_node_id,
+			channel_value_satoshis: value,
+			output_script,
+			user_channel_id: user_id,
+		}, None));
+		Ok(())
+	}
+
+	fn internal_open_channel_v2(&self, counterparty_node_id: &PublicKey, msg: &msgs::OpenChannelV2) -> Result<(), MsgHandleErrInternal> {
+		if msg.chain_hash != self.genesis_hash {
+			return Err(MsgHandleErrInternal::send_err_msg_no_close("Unknown genesis block hash".to_owned(), msg.temporary_channel_id.clone()));
+		}
+
+		if !self.default_configuration.accept_inbound_channels {
+			return Err(MsgHandleErrInternal::send_err_msg_no_close("No inbound channels accepted".to_owned(), msg.temporary_channel_id.clone()));
+		}
+
+		let mut random_bytes = [0u8; 16];
+		random_bytes.copy_from_slice(&self.entropy_source.get_secure_random_bytes()[..16]);
+		let user_channel_id = u128::from_be_bytes(random_bytes);
+		let outbound_scid_alias = self.create_and_insert_outbound_scid_alias();
+
+		// Get the number of peers with channels, but without funded ones. We don't care too much
+		// about peers that never open a channel, so we filter by peers that have at least one
+		// channel, and then limit the number of those with unfunded channels.
+		let channeled_peers_without_funding =
+			self.peers_without_funded_channels(|node| node.total_channel_count() > 0);
+
+		let per_peer_state = self.per_peer_state.read().unwrap();
+		let peer_state_mutex = per_peer_state.get(counterparty_node_id)
+			.ok_or_else(|| {
+				debug_assert!(false);
+				MsgHandleErrInternal::send_err_msg_no_close(format!("Can't find a peer matching the passed counterparty node_id {}", counterparty_node_id), msg.temporary_channel_id.clone())
+			})?;
+		let mut peer_state_lock = peer_state_mutex.lock().unwrap();
+		let peer_state = &mut *peer_state_lock;
+
+		// If this peer already has some channels, a new channel won't increase our number of peers
+		// with unfunded channels, so as long as we aren't over the maximum number of unfunded
+		// channels per-peer we can accept channels from a peer with existing ones.
+		if peer_state.total_channel_count() == 0 &&
+			channeled_peers_without_funding >= MAX_UNFUNDED_CHANNEL_PEERS &&
+			!self.default_configuration.manually_accept_inbound_channels
+		{
+			return Err(MsgHandleErrInternal::send_err_msg_no_close(
+				"Have too many peers with unfunded channels, not accepting new ones".to_owned(),
+				msg.temporary_channel_id.clone()));
+		}
+
+		let best_block_height = self.best_block.read().unwrap().height();
+		if Self::unfunded_channel_count(peer_state, best_block_height) >= MAX_UNFUNDED_CHANS_PER_PEER {
+			return Err(MsgHandleErrInternal::send_err_msg_no_close(
+				format!("Refusing more than {} unfunded channels.", MAX_UNFUNDED_CHANS_PER_PEER),
+				msg.temporary_channel_id.clone()));
+		}
+
+		let mut channel = match InboundV2Channel::new(&self.fee_estimator, &self.entropy_source, &self.signer_provider,
+			counterparty_node_id.clone(), &self.channel_type_features(), &peer_state.latest_features, msg, user_channel_id,
+			&self.default_configuration, best_block_height, &self.logger, outbound_scid_alias)
+		{
+			Err(e) => {
+				self.outbound_scid_aliases.lock().unwrap().remove(&outbound_scid_alias);
+				return Err(MsgHandleErrInternal::from_chan_no_close(e, msg.temporary_channel_id));
+			},
+			Ok(res) => res
+		};
+		let channel_id = channel.context.channel_id();
+		let channel_exists = peer_state.has_channel(&channel_id);
+		if channel_exists {
+			self.outbound_scid_aliases.lock().unwrap().remove(&outbound_scid_alias);
+			return Err(MsgHandleErrInternal::send_err_msg_no_close("temporary_channel_id collision for the same peer!".to_owned(), msg.temporary_channel_id.clone()))
+		}
+		if !self.default_configuration.manually_accept_inbound_channels {
+			let channel_type = channel.context.get_channel_type();
+			if channel_type.requires_zero_conf() {
+				return Err(MsgHandleErrInternal::send_err_msg_no_close("No zero confirmation channels accepted".to_owned(), msg.temporary_channel_id.clone()));
+			}
+			if channel_type.requires_anchors_zero_fee_htlc_tx() {
+				return Err(MsgHandleErrInternal::send_err_msg_no_close("No channels with anchor outputs accepted".to_owned(), msg.temporary_channel_id.clone()));
+			}
+			// Without the user's input we don't contribute any funds to the channel.
+			let accept_msg = channel.accept_inbound_dual_funded_channel(user_channel_id, 0, Vec::new(),
+				&self.entropy_source, &self.signer_provider)
+				.map_err(|e| MsgHandleErrInternal::send_err_msg_no_close(format!("{:?}", e), msg.temporary_channel_id.clone()))?;
+			peer_state.pending_msg_events.push(events::MessageSendEvent::SendAcceptChannelV2 {
+				node_id: counterparty_node_id.clone(),
+				msg: accept_msg,
+			});
+		} else {
+			let mut pending_events = self.pending_events.lock().unwrap();
+			pending_events.push_back((events::Event::OpenChannelRequest {
+				temporary_channel_id: msg.temporary_channel_id.clone(),
+				counterparty_node_id: counterparty_node_id.clone(),
+				funding_satoshis: msg.funding_satoshis,
+				channel_negotiation_type: InboundChannelFunds::DualFunded,
+				channel_type: channel.context.get_channel_type().clone(),
+			}, None));
+		}
+		peer_state.inbound_v2_channel_by_id.insert(channel.context.channel_id(), channel);
+		Ok(())
+	}
+
+	fn internal_accept_channel_v2(&self, counterparty_node_id: &PublicKey, msg: &msgs::AcceptChannelV2) -> Result<(), MsgHandleErrInternal> {
+		let per_peer_state = self.per_peer_state.read().unwrap();
+		let peer_state_mutex = per_peer_state.get(counterparty_node_id)
+			.ok_or_else(|| {
+				debug_assert!(false);
+				MsgHandleErrInternal::send_err_msg_no_close(format!("Can't find a peer matching the passed counterparty node_id {}", counterparty_node_id), msg.temporary_channel_id)
+			})?;
+		let mut peer_state_lock = peer_state_mutex.lock().unwrap();
+		let peer_state = &mut *peer_state_lock;
+		match peer_state.outbound_v2_channel_by_id.entry(msg.temporary_channel_id) {
+			hash_map::Entry::Occupied(mut chan) => {
+				let tx_msg = try_v1_outbound_chan_entry!(self, chan.get_mut().accept_channel_v2(&msg,
+					&self.default_configuration.channel_handshake_limits, &peer_state.latest_features,
+					&self.entropy_source, &self.signer_provider), chan);
+				// The channel is now known by its final V2 channel_id, which is used for the funding
+				// transaction negotiation.
+				let channel = chan.remove();
+				peer_state.pending_msg_events.push(tx_msg.into_msg_send_event(*counterparty_node_id));
+				peer_state.outbound_v2_channel_by_id.insert(channel.context.channel_id(), channel);
+				Ok(())
+			},
+			hash_map::Entry::Vacant(_) => Err(MsgHandleErrInternal::send_err_msg_no_close(format!("Got a message for a channel from the wrong node! No such channel for the passed counterparty_node_id {}", counterparty_node_id), msg.temporary_channel_id))
+		}
+	}
+
+	/// Handles a message which is part of the interactive construction of a dual-funded channel's
+	/// funding transaction, or a splice's new funding transaction, aborting the negotiation upon
+	/// failure.
+	fn internal_tx_msg<HandleTxMsgFn: FnOnce(&mut dyn InteractivelyFunded) -> Result<InteractiveTxMessageSend, AbortReason>>(
+		&self, counterparty_node_id: &PublicKey, channel_id: [u8; 32], tx_msg_handler: HandleTxMsgFn
+	) -> Result<(), MsgHandleErrInternal> {
+		let per_peer_state = self.per_peer_state.read().unwrap();
+		let peer_state_mutex = per_peer_state.get(counterparty_node_id)
+			.ok_or_else(|| {
+				debug_assert!(false);
+				MsgHandleErrInternal::send_err_msg_no_close(format!("Can't find a peer matching the passed counterparty node_id {}", counterparty_node_id), channel_id)
+			})?;
+		let mut peer_state_lock = peer_state_mutex.lock().unwrap();
+		let peer_state = &mut *peer_state_lock;
+		let res = if let Some(chan) = peer_state.outbound_v2_channel_by_id.get_mut(&channel_id) {
+			tx_msg_handler(chan)
+		} else if let Some(chan) = peer_state.inbound_v2_channel_by_id.get_mut(&channel_id) {
+			tx_msg_handler(chan)
+		} else if let Some(chan) = peer_state.channel_by_id.get_mut(&channel_id) {
+			tx_msg_handler(chan)
+		} else {
+			return Err(MsgHandleErrInternal::send_err_msg_no_close(format!("Got a message for a channel from the wrong node! No such channel for the passed counterparty_node_id {}", counterparty_node_id), channel_id));
+		};
+		match res {
+			Ok(tx_msg) => {
+				peer_state.pending_msg_events.push(tx_msg.into_msg_send_event(*counterparty_node_id));
+				Ok(())
+			},
+			Err(reason) => Err(self.abort_interactive_tx_negotiation(peer_state, channel_id, reason)),
+		}
+	}
+
+	/// Sends a `tx_abort` to our counterparty and forgets the (unfunded) dual-funded channel with
+	/// the given `channel_id`, returning the error with which the channel should be closed. For a
+	/// funded channel, only its pending splice is abandoned.
+	fn abort_interactive_tx_negotiation(&self, peer_state: &mut PeerState<<SP::Target as SignerProvider>::Signer>,
+		channel_id: [u8; 32], reason: AbortReason
+	) -> MsgHandleErrInternal {
+		let err = format!("Aborted funding transaction negotiation: {}", reason);
+		if let Some(chan) = peer_state.channel_by_id.get_mut(&channel_id) {
+			chan.abandon_unsigned_splice(&self.logger);
+			peer_state.pending_msg_events.push(events::MessageSendEvent::SendTxAbort {
+				node_id: chan.context.get_counterparty_node_id(),
+				msg: reason.into_tx_abort_msg(channel_id),
+			});
+			return MsgHandleErrInternal::from_chan_no_close(ChannelError::Ignore(err), channel_id);
+		}
+		let mut context = if let Some(chan) = peer_state.outbound_v2_channel_by_id.remove(&channel_id) {
+			chan.context
+		} else if let Some(chan) = peer_state.inbound_v2_channel_by_id.remove(&channel_id) {
+			chan.context
+		} else {
+			debug_assert!(false);
+			return MsgHandleErrInternal::send_err_msg_no_close(err, channel_id);
+		};
+		log_error!(self.logger, "Closing unfunded channel {} due to an error: {}", log_bytes!(channel_id), err);
+		peer_state.pending_msg_events.push(events::MessageSendEvent::SendTxAbort {
+			node_id: context.get_counterparty_node_id(),
+			msg: reason.into_tx_abort_msg(channel_id),
+		});
+		update_maps_on_chan_removal!(self, &context);
+		let shutdown_res = context.force_shutdown(false);
+		MsgHandleErrInternal::from_tx_abort(err, channel_id, context.get_user_id(), shutdown_res)
+	}
+
+	fn internal_tx_complete(&self, counterparty_node_id: &PublicKey, msg: &msgs::TxComplete) -> Result<(), MsgHandleErrInternal> {
+		let per_peer_state = self.per_peer_state.read().unwrap();
+		let peer_state_mutex = per_peer_state.get(counterparty_node_id)
+			.ok_or_else(|| {
+				debug_assert!(false);
+				MsgHandleErrInternal::send_err_msg_no_close(format!("Can't find a peer matching the passed counterparty node_id {}", counterparty_node_id), msg.channel_id)
+			})?;
+		let mut peer_state_lock = peer_state_mutex.lock().unwrap();
+		let peer_state = &mut *peer_state_lock;
+		let res = if let Some(chan) = peer_state.outbound_v2_channel_by_id.get_mut(&msg.channel_id) {
+			chan.tx_complete(msg)
+		} else if let Some(chan) = peer_state.inbound_v2_channel_by_id.get_mut(&msg.channel_id) {
+			chan.tx_complete(msg)
+		} else if let Some(chan) = peer_state.channel_by_id.get_mut(&msg.channel_id) {
+			chan.tx_complete(msg)
+		} else {
+			return Err(MsgHandleErrInternal::send_err_msg_no_close(format!("Got a message for a channel from the wrong node! No such channel for the passed counterparty_node_id {}", counterparty_node_id), msg.channel_id));
+		};
+		let (tx_msg, constructed_tx) = match res {
+			Ok(res) => res,
+			Err(reason) => return Err(self.abort_interactive_tx_negotiation(peer_state, msg.channel_id, reason)),
+		};
+		if let Some(tx_msg) = tx_msg {
+			peer_state.pending_msg_events.push(tx_msg.into_msg_send_event(*counterparty_node_id));
+		}
+		let constructed_tx = match constructed_tx {
+			Some(constructed_tx) => constructed_tx,
+			None => return Ok(()),
+		};
+
+		let our_node_id = self.get_our_node_id();
+		if let hash_map::Entry::Occupied(mut chan) = peer_state.channel_by_id.entry(msg.channel_id) {
+			// The new funding transaction of a splice has been negotiated, so we may now exchange
+			// signatures for the commitment transactions spending it.
+			let commitment_signed = try_chan_entry!(self,
+				chan.get_mut().splice_tx_constructed(constructed_tx, &our_node_id, &self.logger), chan);
+			peer_state.pending_msg_events.push(events::MessageSendEvent::UpdateHTLCs {
+				node_id: *counterparty_node_id,
+				updates: msgs::CommitmentUpdate {
+					update_add_htlcs: Vec::new(),
+					update_fulfill_htlcs: Vec::new(),
+					update_fail_htlcs: Vec::new(),
+					update_fail_malformed_htlcs: Vec::new(),
+					update_fee: None,
+					commitment_signed,
+					splice_commitment_signed: Vec::new(),
+				},
+			});
+			return Ok(());
+		}
+
+		// The funding transaction has been negotiated, so the channel may now be funded by
+		// exchanging signatures for the initial commitment transactions.
+		let funded_res = if let Some(chan) = peer_state.outbound_v2_channel_by_id.remove(&msg.channel_id) {
+			chan.funding_tx_constructed(constructed_tx, &our_node_id, &self.logger)
+				.map_err(|(chan, e)| (chan.context, e))
+		} else if let Some(chan) = peer_state.inbound_v2_channel_by_id.remove(&msg.channel_id) {
+			chan.funding_tx_constructed(constructed_tx, &our_node_id, &self.logger)
+				.map_err(|(chan, e)| (chan.context, e))
+		} else { unreachable!() };
+		let (chan, commitment_signed) = match funded_res {
+			Ok(res) => res,
+			Err((mut context, e)) => {
+				let (_, err) = convert_chan_err!(self, e, context, &msg.channel_id, UNFUNDED);
+				return Err(err);
+			},
+		};
+		let channel_id = chan.context.channel_id();
+		let funding_txo = chan.context.get_funding_txo().unwrap();
+		match self.id_to_peer.lock().unwrap().entry(channel_id) {
+			hash_map::Entry::Occupied(_) => {
+				return Err(MsgHandleErrInternal::send_err_msg_no_close("Already had channel with the new channel_id".to_owned(), channel_id))
+			},
+			hash_map::Entry::Vacant(i_e) => {
+				i_e.insert(chan.context.get_counterparty_node_id());
+			}
+		}
+		self.funding_txo_to_channel_id.lock().unwrap().insert(funding_txo, channel_id);
+		peer_state.pending_msg_events.push(events::MessageSendEvent::UpdateHTLCs {
+			node_id: *counterparty_node_id,
+			updates: msgs::CommitmentUpdate {
+				update_add_htlcs: Vec::new(),
+				update_fulfill_htlcs: Vec::new(),
+				update_fail_htlcs: Vec::new(),
+				update_fail_malformed_htlcs: Vec::new(),
+				update_fee: None,
+				commitment_signed,
+				splice_commitment_signed: Vec::new(),
+			},
+		});
+		peer_state.channel_by_id.insert(channel_id, chan);
+		Ok(())
+	}
+
+	fn internal_tx_signatures(&self, counterparty_node_id: &PublicKey, msg: &msgs::TxSignatures) -> Result<(), MsgHandleErrInternal> {
+		let per_peer_state = self.per_peer_state.read().unwrap();
+		let peer_state_mutex = per_peer_state.get(counterparty_node_id)
+			.ok_or_else(|| {
+				debug_assert!(false);
+				MsgHandleErrInternal::send_err_msg_no_close(format!("Can't find a peer matching the passed counterparty node_id {}", counterparty_node_id), msg.channel_id)
+			})?;
+		let mut peer_state_lock = peer_state_mutex.lock().unwrap();
+		let peer_state = &mut *peer_state_lock;
+		match peer_state.channel_by_id.entry(msg.channel_id) {
+			hash_map::Entry::Occupied(mut chan) => {
+				let (tx_signatures, funding_tx) = try_chan_entry!(self, chan.get_mut().tx_signatures(&msg, &self.logger), chan);
+				if let Some(msg) = tx_signatures {
+					peer_state.pending_msg_events.push(events::MessageSendEvent::SendTxSignatures {
+						node_id: *counterparty_node_id,
+						msg,
+					});
+				}
+				if let Some(tx) = funding_tx {
+					log_info!(self.logger, "Broadcasting funding transaction with txid {}", tx.txid());
+					self.tx_broadcaster.broadcast_transactions(&[&tx]);
+					let mut pending_events = self.pending_events.lock().unwrap();
+					emit_channel_pending_event!(pending_events, chan.get_mut());
+				}
+				Ok(())
+			},
+			hash_map::Entry::Vacant(_) => Err(MsgHandleErrInternal::send_err_msg_no_close(format!("Got a message for a channel from the wrong node! No such channel for the passed counterparty_node_id {}", counterparty_node_id), msg.channel_id))
+		}
+	}
+
+	fn internal_tx_abort(&self, counterparty_node_id: &PublicKey, msg: &msgs::TxAbort) -> Result<(), MsgHandleErrInternal> {
+		let per_peer_state = self.per_peer_state.read().unwrap();
+		let peer_state_mutex = per_peer_state.get(counterparty_node_id)
+			.ok_or_else(|| {
+				debug_assert!(false);
+				MsgHandleErrInternal::send_err_msg_no_close(format!("Can't find a peer matching the passed counterparty node_id {}", counterparty_node_id), msg.channel_id)
+			})?;
+		let mut peer_state_lock = peer_state_mutex.lock().unwrap();
+		let peer_state = &mut *peer_state_lock;
+		if peer_state.outbound_v2_channel_by_id.contains_key(&msg.channel_id) ||
+			peer_state.inbound_v2_channel_by_id.contains_key(&msg.channel_id)
+		{
+			// We echo back the counterparty's `tx_abort` before forgetting the channel.
+			return Err(self.abort_interactive_tx_negotiation(peer_state, msg.channel_id, AbortReason::CounterpartyAborted));
+		}
+		match peer_state.channel_by_id.entry(msg.channel_id) {
+			hash_map::Entry::Occupied(mut chan) => {
+				// We may only forget the channel if we've yet to persist a monitor for it, as we may
+				// otherwise have already handed out our signatures for the funding transaction.
+				if chan.get().is_awaiting_initial_commitment_signed() {
+					peer_state.pending_msg_events.push(events::MessageSendEvent::SendTxAbort {
+						node_id: *counterparty_node_id,
+						msg: AbortReason::CounterpartyAborted.into_tx_abort_msg(msg.channel_id),
+					});
+					try_chan_entry!(self, Err(ChannelError::Close("Counterparty aborted funding transaction negotiation".to_owned())), chan);
+				}
+				if !chan.get().context.has_pending_splice() {
+					// Our counterparty is likely acknowledging a `tx_abort` we sent them.
+					return Ok(());
+				}
+				if chan.get_mut().abandon_unsigned_splice(&self.logger) {
+					peer_state.pending_msg_events.push(events::MessageSendEvent::SendTxAbort {
+						node_id: *counterparty_node_id,
+						msg: AbortReason::CounterpartyAborted.into_tx_abort_msg(msg.channel_id),
+					});
+					return Ok(());
+				}
+				Err(MsgHandleErrInternal::from_chan_no_close(ChannelError::Warn(
+					"Ignoring tx_abort for a channel whose funding transaction may have been signed".to_owned()), msg.channel_id))
+			},
+			hash_map::Entry::Vacant(_) => Err(MsgHandleErrInternal::send_err_msg_no_close(format!("Got a message for a channel from the wrong node! No such channel for the passed counterparty_node_id {}", counterparty_node_id), msg.channel_id))
+		}
+	}
+
+	/// Rejects a splice proposed or acknowledged by our counterparty with a `tx_abort`, unless the
+	/// error requires closing the channel, returning the error to handle.
+	fn reject_splice(&self, pending_msg_events: &mut Vec<events::MessageSendEvent>,
+		counterparty_node_id: &PublicKey, channel_id: [u8; 32], err: ChannelError
+	) -> ChannelError {
+		let err = match err {
+			ChannelError::Warn(err) => err,
+			_ => return err,
+		};
+		log_debug!(self.logger, "Rejecting splice of channel {}: {}", log_bytes!(channel_id), err);
+		pending_msg_events.push(events::MessageSendEvent::SendTxAbort {
+			node_id: *counterparty_node_id,
+			msg: msgs::TxAbort { channel_id, data: err.clone().into_bytes() },
+		});
+		ChannelError::Ignore(err)
+	}
+
+	fn internal_splice(&self, counterparty_node_id: &PublicKey, msg: &msgs::Splice) -> Result<(), MsgHandleErrInternal> {
+		let per_peer_state = self.per_peer_state.read().unwrap();
+		let peer_state_mutex = per_peer_state.get(counterparty_node_id)
+			.ok_or_else(|| {
+				debug_assert!(false);
+				MsgHandleErrInternal::send_err_msg_no_close(format!("Can't find a peer matching the passed counterparty node_id {}", counterparty_node_id), msg.channel_id)
+			})?;
+		let mut peer_state_lock = peer_state_mutex.lock().unwrap();
+		let peer_state = &mut *peer_state_lock;
+		match peer_state.channel_by_id.entry(msg.channel_id) {
+			hash_map::Entry::Occupied(mut chan) => {
+				let splice_ack = match chan.get_mut().splice(msg, self.genesis_hash, &self.entropy_source, &self.signer_provider, &self.logger) {
+					Ok(splice_ack) => splice_ack,
+					Err(e) => try_chan_entry!(self, Err(self.reject_splice(&mut peer_state.pending_msg_events,
+						counterparty_node_id, msg.channel_id, e)), chan),
+				};
+				peer_state.pending_msg_events.push(events::MessageSendEvent::SendSpliceAck {
+					node_id: *counterparty_node_id,
+					msg: splice_ack,
+				});
+				Ok(())
+			},
+			hash_map::Entry::Vacant(_) => Err(MsgHandleErrInternal::send_err_msg_no_close(format!("Got a message for a channel from the wrong node! No such channel for the passed counterparty_node_id {}", counterparty_node_id), msg.channel_id))
+		}
+	}
+
+	fn internal_splice_ack(&self, counterparty_node_id: &PublicKey, msg: &msgs::SpliceAck) -> Result<(), MsgHandleErrInternal> {
+		let per_peer_state = self.per_peer_state.read().unwrap();
+		let peer_state_mutex = per_peer_state.get(counterparty_node_id)
+			.ok_or_else(|| {
+				debug_assert!(false);
+				MsgHandleErrInternal::send_err_msg_no_close(format!("Can't find a peer matching the passed counterparty node_id {}", counterparty_node_id), msg.channel_id)
+			})?;
+		let mut peer_state_lock = peer_state_mutex.lock().unwrap();
+		let peer_state = &mut *peer_state_lock;
+		match peer_state.channel_by_id.entry(msg.channel_id) {
+			hash_map::Entry::Occupied(mut chan) => {
+				let tx_msg = match chan.get_mut().splice_ack(msg, self.genesis_hash, &self.entropy_source, &self.signer_provider) {
+					Ok(tx_msg) => tx_msg,
+					Err(e) => try_chan_entry!(self, Err(self.reject_splice(&mut peer_state.pending_msg_events,
+						counterparty_node_id, msg.channel_id, e)), chan),
+				};
+				peer_state.pending_msg_events.push(tx_msg.into_msg_send_event(*counterparty_node_id));
+				Ok(())
+			},
+			hash_map::Entry::Vacant(_) => Err(MsgHandleErrInternal::send_err_msg_no_close(format!("Got a message for a channel from the wrong node! No such channel for the passed counterparty_node_id {}", counterparty_node_id), msg.channel_id))
+		}
+	}
+
+	fn internal_tx_init_rbf(&self, counterparty_node_id: &PublicKey, msg: &msgs::TxInitRbf) -> Result<(), MsgHandleErrInternal> {
+		let per_peer_state = self.per_peer_state.read().unwrap();
+		let peer_state_mutex = per_peer_state.get(counterparty_node_id)
+			.ok_or_else(|| {
+				debug_assert!(false);
+				MsgHandleErrInternal::send_err_msg_no_close(format!("Can't find a peer matching the passed counterparty node_id {}", counterparty_node_id), msg.channel_id)
+			})?;
+		let mut peer_state_lock = peer_state_mutex.lock().unwrap();
+		let peer_state = &mut *peer_state_lock;
+		match peer_state.channel_by_id.entry(msg.channel_id) {
+			hash_map::Entry::Occupied(mut chan) => {
+				let tx_ack_rbf = match chan.get_mut().tx_init_rbf(msg, &self.entropy_source, &self.signer_provider, &self.logger) {
+					Ok(tx_ack_rbf) => tx_ack_rbf,
+					Err(e) => try_chan_entry!(self, Err(self.reject_splice(&mut peer_state.pending_msg_events,
+						counterparty_node_id, msg.channel_id, e)), chan),
+				};
+				peer_state.pending_msg_events.push(events::MessageSendEvent::SendTxAckRbf {
+					node_id: *counterparty_node_id,
+					msg: tx_ack_rbf,
+				});
+				Ok(())
+			},
+			hash_map::Entry::Vacant(_) => Err(MsgHandleErrInternal::send_err_msg_no_close(format!("Got a message for a channel from the wrong node! No such channel for the passed counterparty_node_id {}", counterparty_node_id), msg.channel_id))
+		}
+	}
+
+	fn internal_tx_ack_rbf(&self, counterparty_node_id: &PublicKey, msg: &msgs::TxAckRbf) -> Result<(), MsgHandleErrInternal> {
+		let per_peer_state = self.per_peer_state.read().unwrap();
+		let peer_state_mutex = per_peer_state.get(counterparty_node_id)
+			.ok_or_else(|| {
+				debug_assert!(false);
+				MsgHandleErrInternal::send_err_msg_no_close(format!("Can't find a peer matching the passed counterparty node_id {}", counterparty_node_id), msg.channel_id)
+			})?;
+		let mut peer_state_lock = peer_state_mutex.lock().unwrap();
+		let peer_state = &mut *peer_state_lock;
+		match peer_state.channel_by_id.entry(msg.channel_id) {
+			hash_map::Entry::Occupied(mut chan) => {
+				let tx_msg = match chan.get_mut().tx_ack_rbf(msg, &self.entropy_source, &self.signer_provider) {
+					Ok(tx_msg) => tx_msg,
+					Err(e) => try_chan_entry!(self, Err(self.reject_splice(&mut peer_state.pending_msg_events,
+						counterparty_node_id, msg.channel_id, e)), chan),
+				};
+				peer_state.pending_msg_events.push(tx_msg.into_msg_send_event(*counterparty_node_id));
+				Ok(())
+			},
+			hash_map::Entry::Vacant(_) => Err(MsgHandleErrInternal::send_err_msg_no_close(format!("Got a message for a channel from the wrong node! No such channel for the passed counterparty_node_id {}", counterparty_node_id), msg.channel_id))
+		}
+	}
+
+	fn internal_splice_locked(&self, counterparty_node_id: &PublicKey, msg: &msgs::SpliceLocked) -> Result<(), MsgHandleErrInternal> {
+		let per_peer_state = self.per_peer_state.read().unwrap();
+		let peer_state_mutex = per_peer_state.get(counterparty_node_id)
+			.ok_or_else(|| {
+				debug_assert!(false);
+				MsgHandleErrInternal::send_err_msg_no_close(format!("Can't find a peer matching the passed counterparty node_id {}", counterparty_node_id), msg.channel_id)
+			})?;
+		let mut peer_state_lock = peer_state_mutex.lock().unwrap();
+		let peer_state = &mut *peer_state_lock;
+		match peer_state.channel_by_id.entry(msg.channel_id) {
+			hash_map::Entry::Occupied(mut chan) => {
+				try_chan_entry!(self, chan.get_mut().splice_locked(msg), chan);
+				if let Some((_, prev_short_channel_id, replaced_monitor_updates)) = chan.get_mut().maybe_promote_splice(&self.logger) {
+					self.update_maps_on_splice_promotion(chan.get(), prev_short_channel_id, &replaced_monitor_updates);
+					for (funding_txo, update) in replaced_monitor_updates {
+						// The replaced splice transactions can no longer confirm, so there's nothing
+						// to do if the update fails.
+						let _ = self.chain_monitor.update_channel(funding_txo, &update);
+					}
+				}
+				Ok(())
+			},
+			hash_map::Entry::Vacant(_) => Err(MsgHandleErrInternal::send_err_msg_no_close(format!("Got a message for a channel from the wrong node! No such channel for the passed counterparty_node_id {}", counterparty_node_id), msg.channel_id))
+		}
+	}
+
+	fn internal_stfu(&self, counterparty_node_id: &PublicKey, msg: &msgs::Stfu) -> Result<(), MsgHandleErrInternal> {
+		let per_peer_state = self.per_peer_state.read().unwrap();
+		let peer_state_mutex = per_peer_state.get(counterparty_node_id)
+			.ok_or_else(|| {
+				debug_assert!(false);
+				MsgHandleErrInternal::send_err_msg_no_close(format!("Can't find a peer matching the passed counterparty node_id {}", counterparty_node_id), msg.channel_id)
+			})?;
+		let mut peer_state_lock = peer_state_mutex.lock().unwrap();
+		let peer_state = &mut *peer_state_lock;
+		match peer_state.channel_by_id.entry(msg.channel_id) {
+			hash_map::Entry::Occupied(mut chan) => {
+				if let Some(stfu) = try_chan_entry!(self, chan.get_mut().stfu(msg, &self.logger), chan) {
+					peer_state.pending_msg_events.push(events::MessageSendEvent::SendStfu {
+						node_id: *counterparty_node_id,
+						msg: stfu,
+					});
+				}
+				if let Some(dyn_propose) = chan.get_mut().maybe_propose_channel_type_upgrade(&self.logger) {
+					peer_state.pending_msg_events.push(events::MessageSendEvent::SendDynPropose {
+						node_id: *counterparty_node_id,
+						msg: dyn_propose,
+					});
+				}
+				if let Some(splice) = chan.get_mut().maybe_propose_splice(self.genesis_hash, &self.logger) {
+					peer_state.pending_msg_events.push(events::MessageSendEvent::SendSplice {
+						node_id: *counterparty_node_id,
+						msg: splice,
+					});
+				}
+				if let Some(tx_init_rbf) = chan.get_mut().maybe_propose_splice_rbf(&self.logger) {
+					peer_state.pending_msg_events.push(events::MessageSendEvent::SendTxInitRbf {
+						node_id: *counterparty_node_id,
+						msg: tx_init_rbf,
+					});
+				}
+				Ok(())
+			},
+			hash_map::Entry::Vacant(_) => Err(MsgHandleErrInternal::send_err_msg_no_close(format!("Got a message for a channel from the wrong node! No such channel for the passed counterparty_node_id {}", counterparty_node_id), msg.channel_id))
+		}
+	}
+
+	fn internal_dyn_propose(&self, counterparty_node_id: &PublicKey, msg: &msgs::DynPropose) -> Result<(), MsgHandleErrInternal> {
+		// Accepting an upgrade to anchor outputs requires the same on-chain reserve as accepting a
+		// new anchor channel.
+		let has_anchor_channel_reserve = self.check_anchor_channel_reserve_for_new_channel().is_ok();
+
+		let per_peer_state = self.per_peer_state.read().unwrap();
+		let peer_state_mutex = per_peer_state.get(counterparty_node_id)
+			.ok_or_else(|| {
 				debug_assert!(false);
-				MsgHandleErrInternal::send_err_msg_no_close(format!("Can't find a peer matching the passed counterparty node_id {}", counterparty_node_id), msg.temporary_channel_id.clone())
+				MsgHandleErrInternal::send_err_msg_no_close(format!("Can't find a peer matching the passed counterparty node_id {}", counterparty_node_id), msg.channel_id)
 			})?;
 		let mut peer_state_lock = peer_state_mutex.lock().unwrap();
 		let peer_state = &mut *peer_state_lock;
-
-		// If this peer already has some channels, a new channel won't increase our number of peers
-		// with unfunded channels, so as long as we aren't over the maximum number of unfunded
-		// channels per-peer we can accept channels from a peer with existing ones.
-		if peer_state.total_channel_count() == 0 &&
-			channeled_peers_without_funding >= MAX_UNFUNDED_CHANNEL_PEERS &&
-			!self.default_configuration.manually_accept_inbound_channels
-		{
-			return Err(MsgHandleErrInternal::send_err_msg_no_close(
-				"Have too many peers with unfunded channels, not accepting new ones".to_owned(),
-				msg.temporary_channel_id.clone()));
+		match peer_state.channel_by_id.entry(msg.channel_id) {
+			hash_map::Entry::Occupied(mut chan) => {
+				let dyn_ack = match chan.get_mut().dyn_propose(msg, &self.signer_provider, &self.default_configuration,
+					has_anchor_channel_reserve, &self.logger)
+				{
+					Ok(dyn_ack) => dyn_ack,
+					Err(ChannelError::Warn(err)) => {
+						log_debug!(self.logger, "Rejecting upgrade of channel {}: {}", log_bytes!(msg.channel_id), err);
+						peer_state.pending_msg_events.push(events::MessageSendEvent::SendDynReject {
+							node_id: *counterparty_node_id,
+							msg: msgs::DynReject { channel_id: msg.channel_id, data: err.into_bytes() },
+						});
+						return Ok(());
+					},
+					Err(e) => try_chan_entry!(self, Err(e), chan),
+				};
+				peer_state.pending_msg_events.push(events::MessageSendEvent::SendDynAck {
+					node_id: *counterparty_node_id,
+					msg: dyn_ack,
+				});
+				Ok(())
+			},
+			hash_map::Entry::Vacant(_) => Err(MsgHandleErrInternal::send_err_msg_no_close(format!("Got a message for a channel from the wrong node! No such channel for the passed counterparty_node_id {}", counterparty_node_id), msg.channel_id))
 		}
+	}
 
-		let best_block_height = self.best_block.read().unwrap().height();
-		if Self::unfunded_channel_count(peer_state, best_block_height) >= MAX_UNFUNDED_CHANS_PER_PEER {
-			return Err(MsgHandleErrInternal::send_err_msg_no_close(
-				format!("Refusing more than {} unfunded channels.", MAX_UNFUNDED_CHANS_PER_PEER),
-				msg.temporary_channel_id.clone()));
+	fn internal_dyn_ack(&self, counterparty_node_id: &PublicKey, msg: &msgs::DynAck) -> Result<(), MsgHandleErrInternal> {
+		let per_peer_state = self.per_peer_state.read().unwrap();
+		let peer_state_mutex = per_peer_state.get(counterparty_node_id)
+			.ok_or_else(|| {
+				debug_assert!(false);
+				MsgHandleErrInternal::send_err_msg_no_close(format!("Can't find a peer matching the passed counterparty node_id {}", counterparty_node_id), msg.channel_id)
+			})?;
+		let mut peer_state_lock = peer_state_mutex.lock().unwrap();
+		let peer_state = &mut *peer_state_lock;
+		match peer_state.channel_by_id.entry(msg.channel_id) {
+			hash_map::Entry::Occupied(mut chan) => {
+				let funding_txo = chan.get().context.get_funding_txo();
+				let monitor_update_opt = try_chan_entry!(self, chan.get_mut().dyn_ack(msg, &self.signer_provider, &self.logger), chan);
+				if let Some(monitor_update) = monitor_update_opt {
+					handle_new_monitor_update!(self, funding_txo.unwrap(), monitor_update, peer_state_lock,
+						peer_state, per_peer_state, chan).map(|_| ())
+				} else { Ok(()) }
+			},
+			hash_map::Entry::Vacant(_) => Err(MsgHandleErrInternal::send_err_msg_no_close(format!("Got a message for a channel from the wrong node! No such channel for the passed counterparty_node_id {}", counterparty_node_id), msg.channel_id))
 		}
+	}
 
-		let mut channel = match InboundV1Channel::new(&self.fee_estimator, &self.entropy_source, &self.signer_provider,
-			counterparty_node_id.clone(), &self.channel_type_features(), &peer_state.latest_features, msg, user_channel_id,
-			&self.default_configuration, best_block_height, &self.logger, outbound_scid_alias)
-		{
-			Err(e) => {
-				self.outbound_scid_aliases.lock().unwrap().remove(&outbound_scid_alias);
-				return Err(MsgHandleErrInternal::from_chan_no_close(e, msg.temporary_channel_id));
+	fn internal_dyn_reject(&self, counterparty_node_id: &PublicKey, msg: &msgs::DynReject) -> Result<(), MsgHandleErrInternal> {
+		let per_peer_state = self.per_peer_state.read().unwrap();
+		let peer_state_mutex = per_peer_state.get(counterparty_node_id)
+			.ok_or_else(|| {
+				debug_assert!(false);
+				MsgHandleErrInternal::send_err_msg_no_close(format!("Can't find a peer matching the passed counterparty node_id {}", counterparty_node_id), msg.channel_id)
+			})?;
+		let mut peer_state_lock = peer_state_mutex.lock().unwrap();
+		let peer_state = &mut *peer_state_lock;
+		match peer_state.channel_by_id.entry(msg.channel_id) {
+			hash_map::Entry::Occupied(mut chan) => {
+				try_chan_entry!(self, chan.get_mut().dyn_reject(msg, &self.logger), chan);
+				Ok(())
 			},
-			Ok(res) => res
-		};
-		let channel_id = channel.context.channel_id();
-		let channel_exists = peer_state.has_channel(&channel_id);
-		if channel_exists {
-			self.outbound_scid_aliases.lock().unwrap().remove(&outbound_scid_alias);
-			return Err(MsgHandleErrInternal::send_err_msg_no_close("temporary_channel_id collision for the same peer!".to_owned(), msg.temporary_channel_id.clone()))
-		} else {
-			if !self.default_configuration.manually_accept_inbound_channels {
-				let channel_type = channel.context.get_channel_type();
-				if channel_type.requires_zero_conf() {
-					return Err(MsgHandleErrInternal::send_err_msg_no_close("No zero confirmation channels accepted".to_owned(), msg.temporary_channel_id.clone()));
-				}
-				if channel_type.requires_anchors_zero_fee_htlc_tx() {
-					return Err(MsgHandleErrInternal::send_err_msg_no_close("No channels with anchor outputs accepted".to_owned(), msg.temporary_channel_id.clone()));
-				}
-				peer_state.pending_msg_events.push(events::MessageSendEvent::SendAcceptChannel {
-					node_id: counterparty_node_id.clone(),
-					msg: channel.accept_inbound_channel(user_channel_id),
-				});
-			} else {
-				let mut pending_events = self.pending_events.lock().unwrap();
-				pending_events.push_back((events::Event::OpenChannelRequest {
-					temporary_channel_id: msg.temporary_channel_id.clone(),
-					counterparty_node_id: counterparty_node_id.clone(),
-					funding_satoshis: msg.funding_satoshis,
-					push_msat: msg.push_msat,
-					channel_type: channel.context.get_channel_type().clone(),
-				}, None));
-			}
-			peer_state.inbound_v1_channel_by_id.insert(channel_id, channel);
+			hash_map::Entry::Vacant(_) => Err(MsgHandleErrInternal::send_err_msg_no_close(format!("Got a message for a channel from the wrong node! No such channel for the passed counterparty_node_id {}", counterparty_node_id), msg.channel_id))
 		}
-		Ok(())
 	}
 
-	fn internal_accept_channel(&self, counterparty_node_id: &PublicKey, msg: &msgs::AcceptChannel) -> Result<(), MsgHandleErrInternal> {
-		let (value, output_script, user_id) = {
-			let per_peer_state = self.per_peer_state.read().unwrap();
-			let peer_state_mutex = per_peer_state.get(counterparty_node_id)
-				.ok_or_else(|| {
-					debug_assert!(false);
-					MsgHandleErrInternal::send_err_msg_no_close(format!("Can't find a peer matching the passed counterparty node_id {}", counterparty_node_id), msg.temporary_channel_id)
-				})?;
-			let mut peer_state_lock = peer_state_mutex.lock().unwrap();
-			let peer_state = &mut *peer_state_lock;
-			match peer_state.outbound_v1_channel_by_id.entry(msg.temporary_channel_id) {
-				hash_map::Entry::Occupied(mut chan) => {
-					try_v1_outbound_chan_entry!(self, chan.get_mut().accept_channel(&msg, &self.default_configuration.channel_handshake_limits, &peer_state.latest_features), chan);
-					(chan.get().context.get_value_satoshis(), chan.get().context.get_funding_redeemscript().to_v0_p2wsh(), chan.get().context.get_user_id())
-				},
-				hash_map::Entry::Vacant(_) => return Err(MsgHandleErrInternal::send_err_msg_no_close(format!("Got a message for a channel from the wrong node! No such channel for the passed counterparty_node_id {}", counterparty_node_id), msg.temporary_channel_id))
+	/// Replaces the previous short channel id of a channel whose splice was just promoted with its
+	/// new one. Note that we keep mapping the previous funding outpoint to the channel, as its
+	/// [`ChannelMonitor`] may still generate events.
+	fn update_maps_on_splice_promotion(&self, channel: &Channel<<SP::Target as SignerProvider>::Signer>,
+		prev_short_channel_id: Option<u64>, replaced_monitor_updates: &[(OutPoint, ChannelMonitorUpdate)]
+	) {
+		{
+			let mut funding_txo_to_channel_id = self.funding_txo_to_channel_id.lock().unwrap();
+			for (funding_txo, _) in replaced_monitor_updates {
+				funding_txo_to_channel_id.remove(funding_txo);
 			}
-		};
-		let mut pending_events = self.pending_events.lock().unwrap();
-		pending_events.push_back((events::Event::FundingGenerationReady {
-			temporary_channel_id: msg.temporary_channel_id,
-			counterparty_node_id: *counterparty_node_id,
-			channel_value_satoshis: value,
-			output_script,
-			user_channel_id: user_id,
-		}, None));
-		Ok(())
+		}
+		let mut short_to_chan_info = self.short_to_chan_info.write().unwrap();
+		if let Some(prev_short_channel_id) = prev_short_channel_id {
+			short_to_chan_info.remove(&prev_short_channel_id);
+		}
+		if let Some(short_channel_id) = channel.context.get_short_channel_id() {
+			short_to_chan_info.insert(short_channel_id, (channel.context.get_counterparty_node_id(), channel.context.channel_id()));
+		}
 	}
 
 	fn internal_funding_created(&self, counterparty_node_id: &PublicKey, msg: &msgs::FundingCreated) -> Result<(), MsgHandleErrInternal> {
@@ -5782,6 +7708,55 @@ where
 		let peer_state = &mut *peer_state_lock;
 		match peer_state.channel_by_id.entry(msg.channel_id) {
 			hash_map::Entry::Occupied(mut chan) => {
+				if msg.batch.is_none() && chan.get().is_awaiting_splice_commitment_signed() {
+					let best_block = *self.best_block.read().unwrap();
+					let monitor = try_chan_entry!(self,
+						chan.get_mut().splice_commitment_signed(&msg, best_block, &self.signer_provider, &self.logger), chan);
+					let splice_funding_txo = monitor.get_funding_txo().0;
+					self.funding_txo_to_channel_id.lock().unwrap().insert(splice_funding_txo, msg.channel_id);
+					if let Some(unsigned_transaction) = chan.get().unsigned_funding_transaction_to_sign() {
+						self.pending_events.lock().unwrap().push_back((events::Event::FundingTransactionReadyForSigning {
+							channel_id: msg.channel_id,
+							counterparty_node_id: *counterparty_node_id,
+							user_channel_id: chan.get().context.get_user_id(),
+							unsigned_transaction: unsigned_transaction.clone(),
+						}, None));
+					}
+					let update_res = self.chain_monitor.watch_channel(splice_funding_txo, monitor);
+					let mut res = handle_new_monitor_update!(self, update_res, peer_state_lock, peer_state, per_peer_state, chan, INITIAL_MONITOR);
+					if let Err(MsgHandleErrInternal { ref mut shutdown_finish, .. }) = res {
+						// We weren't able to watch the splice's new funding output, so no updates should
+						// be made on its monitor.
+						if let Some((ref mut shutdown_finish, _)) = shutdown_finish {
+							shutdown_finish.2.retain(|(funding_txo, _)| *funding_txo != splice_funding_txo);
+						}
+					}
+					return res.map(|_| ());
+				}
+				if chan.get().is_awaiting_initial_commitment_signed() {
+					let best_block = *self.best_block.read().unwrap();
+					let monitor = try_chan_entry!(self,
+						chan.get_mut().initial_commitment_signed(&msg, best_block, &self.signer_provider, &self.logger), chan);
+					if let Some(unsigned_transaction) = chan.get().unsigned_funding_transaction_to_sign() {
+						self.pending_events.lock().unwrap().push_back((events::Event::FundingTransactionReadyForSigning {
+							channel_id: msg.channel_id,
+							counterparty_node_id: *counterparty_node_id,
+							user_channel_id: chan.get().context.get_user_id(),
+							unsigned_transaction: unsigned_transaction.clone(),
+						}, None));
+					}
+					let update_res = self.chain_monitor.watch_channel(chan.get().context.get_funding_txo().unwrap(), monitor);
+					let mut res = handle_new_monitor_update!(self, update_res, peer_state_lock, peer_state, per_peer_state, chan, INITIAL_MONITOR);
+					if let Err(MsgHandleErrInternal { ref mut shutdown_finish, .. }) = res {
+						// We weren't able to watch the channel to begin with, so no updates should be made on
+						// it. Previously, full_stack_target found an (unreachable) panic when the
+						// monitor update contained within `shutdown_finish` was applied.
+						if let Some((ref mut shutdown_finish, _)) = shutdown_finish {
+							shutdown_finish.0.take();
+						}
+					}
+					return res.map(|_| ());
+				}
 				let funding_txo = chan.get().context.get_funding_txo();
 				let monitor_update_opt = try_chan_entry!(self, chan.get_mut().commitment_signed(&msg, &self.logger), chan);
 				if let Some(monitor_update) = monitor_update_opt {
@@ -5842,6 +7817,7 @@ where
 											htlc_id: prev_htlc_id,
 											incoming_packet_shared_secret: forward_info.incoming_shared_secret,
 											phantom_shared_secret: None,
+											blinded_failure: forward_info.routing.blinded_failure(),
 										});
 
 										failed_intercept_forwards.push((htlc_source, forward_info.payment_hash,
@@ -5904,7 +7880,7 @@ where
 		channel_funding_outpoint: OutPoint, counterparty_node_id: PublicKey
 	) -> bool {
 		actions_blocking_raa_monitor_updates
-			.get(&channel_funding_outpoint.to_channel_id()).map(|v| !v.is_empty()).unwrap_or(false)
+			.get(&self.channel_id_for_funding_txo(&channel_funding_outpoint)).map(|v| !v.is_empty()).unwrap_or(false)
 		|| self.pending_events.lock().unwrap().iter().any(|(_, action)| {
 			action == &Some(EventCompletionAction::ReleaseRAAChannelMonitorUpdate {
 				channel_funding_outpoint,
@@ -6069,10 +8045,22 @@ where
 					let need_lnd_workaround = chan.get_mut().context.workaround_lnd_bug_4006.take();
 					htlc_forwards = self.handle_channel_resumption(
 						&mut peer_state.pending_msg_events, chan.get_mut(), responses.raa, responses.commitment_update, responses.order,
-						Vec::new(), None, responses.channel_ready, responses.announcement_sigs);
+						Vec::new(), None, responses.channel_ready, responses.announcement_sigs,
+						responses.tx_signatures);
+					if let Some(msg) = responses.splice_locked {
+						peer_state.pending_msg_events.push(events::MessageSendEvent::SendSpliceLocked {
+							node_id: counterparty_node_id.clone(),
+							msg,
+						});
+					}
 					if let Some(upd) = channel_update {
 						peer_state.pending_msg_events.push(upd);
 					}
+					if let Some(monitor_update) = responses.monitor_update {
+						let funding_txo = chan.get().context.get_funding_txo();
+						handle_new_monitor_update!(self, funding_txo.unwrap(), monitor_update, peer_state_lock,
+							peer_state, per_peer_state, chan)?;
+					}
 					need_lnd_workaround
 				},
 				hash_map::Entry::Vacant(_) => return Err(MsgHandleErrInternal::send_err_msg_no_close(format!("Got a message for a channel from the wrong node! No such channel for the passed counterparty_node_id {}", counterparty_node_id), msg.channel_id))
@@ -6097,28 +8085,29 @@ where
 		let mut pending_monitor_events = self.chain_monitor.release_pending_monitor_events();
 		let has_pending_monitor_events = !pending_monitor_events.is_empty();
 		for (funding_outpoint, mut monitor_events, counterparty_node_id) in pending_monitor_events.drain(..) {
+			let channel_id = self.channel_id_for_funding_txo(&funding_outpoint);
 			for monitor_event in monitor_events.drain(..) {
 				match monitor_event {
 					MonitorEvent::HTLCEvent(htlc_update) => {
 						if let Some(preimage) = htlc_update.payment_preimage {
 							log_trace!(self.logger, "Claiming HTLC with preimage {} from our monitor", log_bytes!(preimage.0));
-							self.claim_funds_internal(htlc_update.source, preimage, htlc_update.htlc_value_satoshis.map(|v| v * 1000), true, funding_outpoint.to_channel_id());
+							self.claim_funds_internal(htlc_update.source, preimage, htlc_update.htlc_value_satoshis.map(|v| v * 1000), true, channel_id);
 						} else {
 							log_trace!(self.logger, "Failing HTLC with hash {} from our monitor", log_bytes!(htlc_update.payment_hash.0));
-							let receiver = HTLCDestination::NextHopChannel { node_id: counterparty_node_id, channel_id: funding_outpoint.to_channel_id() };
+							let receiver = HTLCDestination::NextHopChannel { node_id: counterparty_node_id, channel_id: channel_id };
 							let reason = HTLCFailReason::from_failure_code(0x4000 | 8);
 							self.fail_htlc_backwards_internal(&htlc_update.source, &htlc_update.payment_hash, &reason, receiver);
 						}
 					},
-					MonitorEvent::CommitmentTxConfirmed(funding_outpoint) |
-					MonitorEvent::UpdateFailed(funding_outpoint) => {
+					MonitorEvent::CommitmentTxConfirmed(_) |
+					MonitorEvent::UpdateFailed(_) => {
 						let counterparty_node_id_opt = match counterparty_node_id {
 							Some(cp_id) => Some(cp_id),
 							None => {
 								// TODO: Once we can rely on the counterparty_node_id from the
 								// monitor event, this and the id_to_peer map should be removed.
 								let id_to_peer = self.id_to_peer.lock().unwrap();
-								id_to_peer.get(&funding_outpoint.to_channel_id()).cloned()
+								id_to_peer.get(&channel_id).cloned()
 							}
 						};
 						if let Some(counterparty_node_id) = counterparty_node_id_opt {
@@ -6127,7 +8116,7 @@ where
 								let mut peer_state_lock = peer_state_mutex.lock().unwrap();
 								let peer_state = &mut *peer_state_lock;
 								let pending_msg_events = &mut peer_state.pending_msg_events;
-								if let hash_map::Entry::Occupied(chan_entry) = peer_state.channel_by_id.entry(funding_outpoint.to_channel_id()) {
+								if let hash_map::Entry::Occupied(chan_entry) = peer_state.channel_by_id.entry(channel_id) {
 									let mut chan = remove_channel!(self, chan_entry);
 									failed_channels.push(chan.context.force_shutdown(false));
 									if let Ok(update) = self.get_channel_update_for_broadcast(&chan) {
@@ -6262,58 +8251,419 @@ where
 									});
 								}
 
-								self.issue_channel_close_events(&chan.context, ClosureReason::CooperativeClosure);
+								self.issue_channel_close_events(&chan.context, ClosureReason::CooperativeClosure);
+
+								log_info!(self.logger, "Broadcasting {}", log_tx!(tx));
+								self.tx_broadcaster.broadcast_transactions(&[&tx]);
+								update_maps_on_chan_removal!(self, &chan.context);
+								false
+							} else { true }
+						},
+						Err(e) => {
+							has_update = true;
+							let (close_channel, res) = convert_chan_err!(self, e, chan, channel_id);
+							handle_errors.push((chan.context.get_counterparty_node_id(), Err(res)));
+							!close_channel
+						}
+					}
+				});
+			}
+		}
+
+		for (counterparty_node_id, err) in handle_errors.drain(..) {
+			let _ = handle_error!(self, err, counterparty_node_id);
+		}
+
+		has_update
+	}
+
+	/// Sends `stfu` on any channel which is waiting to do so once its pending updates have been
+	/// committed, as well as any `dyn_propose` for a channel which just became quiescent.
+	fn maybe_send_stfu(&self) {
+		let per_peer_state = self.per_peer_state.read().unwrap();
+		for (counterparty_node_id, peer_state_mutex) in per_peer_state.iter() {
+			let mut peer_state_lock = peer_state_mutex.lock().unwrap();
+			let peer_state = &mut *peer_state_lock;
+			let pending_msg_events = &mut peer_state.pending_msg_events;
+			for (_, chan) in peer_state.channel_by_id.iter_mut() {
+				if let Some(msg) = chan.try_send_stfu(&self.logger) {
+					pending_msg_events.push(events::MessageSendEvent::SendStfu {
+						node_id: *counterparty_node_id,
+						msg,
+					});
+				}
+				if let Some(msg) = chan.maybe_propose_channel_type_upgrade(&self.logger) {
+					pending_msg_events.push(events::MessageSendEvent::SendDynPropose {
+						node_id: *counterparty_node_id,
+						msg,
+					});
+				}
+				if let Some(msg) = chan.maybe_propose_splice(self.genesis_hash, &self.logger) {
+					pending_msg_events.push(events::MessageSendEvent::SendSplice {
+						node_id: *counterparty_node_id,
+						msg,
+					});
+				}
+				if let Some(msg) = chan.maybe_propose_splice_rbf(&self.logger) {
+					pending_msg_events.push(events::MessageSendEvent::SendTxInitRbf {
+						node_id: *counterparty_node_id,
+						msg,
+					});
+				}
+			}
+		}
+	}
+
+	/// Handle a list of channel failures during a block_connected or block_disconnected call,
+	/// pushing the channel monitor update (if any) to the background events queue and removing the
+	/// Channel object.
+	fn handle_init_event_channel_failures(&self, mut failed_channels: Vec<ShutdownResult>) {
+		for mut failure in failed_channels.drain(..) {
+			// Either a commitment transactions has been confirmed on-chain or
+			// Channel::block_disconnected detected that the funding transaction has been
+			// reorganized out of the main chain.
+			// We cannot broadcast our latest local state via monitor update (as
+			// Channel::force_shutdown tries to make us do) as we may still be in initialization,
+			// so we track the update internally and handle it when the user next calls
+			// timer_tick_occurred, guaranteeing we're running normally.
+			if let Some((counterparty_node_id, funding_txo, update)) = failure.0.take() {
+				assert_eq!(update.updates.len(), 1);
+				if let ChannelMonitorUpdateStep::ChannelForceClosed { should_broadcast } = update.updates[0] {
+					assert!(should_broadcast);
+				} else { unreachable!(); }
+				self.pending_background_events.lock().unwrap().push(
+					BackgroundEvent::MonitorUpdateRegeneratedOnStartup {
+						counterparty_node_id, funding_txo, update
+					});
+				for (splice_funding_txo, splice_update) in failure.2.drain(..) {
+					self.pending_background_events.lock().unwrap().push(
+						BackgroundEvent::MonitorUpdateRegeneratedOnStartup {
+							counterparty_node_id, funding_txo: splice_funding_txo, update: splice_update
+						});
+				}
+			}
+			self.finish_force_close_channel(failure);
+		}
+	}
+
+	/// Creates an [`OfferBuilder`] such that the [`Offer`] it builds is recognized by the
+	/// [`ChannelManager`] when handling [`InvoiceRequest`] messages for the offer. The offer will
+	/// not have an expiration unless otherwise set on the builder.
+	///
+	/// # Privacy
+	///
+	/// Uses a two-hop [`BlindedPath`] for the offer with a connected peer as the introduction node,
+	/// along with a signing pubkey derived from the [`ExpandedKey`] so that the offer cannot be
+	/// linked to [`ChannelManager::get_our_node_id`]. If no such peer is available, the offer will
+	/// not contain any paths and our node id will be used as the signing pubkey instead.
+	///
+	/// # Limitations
+	///
+	/// Requires a direct connection to the introduction node in the responding [`InvoiceRequest`]'s
+	/// reply path.
+	///
+	/// This is not exported to bindings users as builder patterns don't map outside of move semantics.
+	///
+	/// [`InvoiceRequest`]: crate::offers::invoice_request::InvoiceRequest
+	/// [`ExpandedKey`]: inbound_payment::ExpandedKey
+	pub fn create_offer_builder(
+		&self, description: String
+	) -> OfferBuilder<DerivedMetadata, secp256k1::All> {
+		let node_id = self.get_our_node_id();
+		let expanded_key = &self.inbound_payment_key;
+		let entropy = &*self.entropy_source;
+		let secp_ctx = &self.secp_ctx;
+
+		let builder = OfferBuilder::deriving_signing_pubkey(
+			description, node_id, expanded_key, entropy, secp_ctx
+		)
+			.chain_hash(ChainHash::from(&self.genesis_hash[..]));
+
+		match self.create_blinded_path() {
+			Ok(path) => builder.path(path),
+			Err(()) => builder,
+		}
+	}
+
+	/// Creates a [`RefundBuilder`] such that the [`Refund`] it builds is recognized by the
+	/// [`ChannelManager`] when handling [`Bolt12Invoice`] messages for the refund.
+	///
+	/// # Payment
+	///
+	/// The provided `payment_id` is used to ensure that only one invoice is paid for the refund.
+	/// See [Avoiding Duplicate Payments] for other requirements once the payment has been sent.
+	///
+	/// The builder will have the provided expiration set. Any changes to the expiration on the
+	/// returned builder will not be honored by [`ChannelManager`]. For `no-std`, the highest seen
+	/// block time minus two hours is used for the current time when determining if the refund has
+	/// expired.
+	///
+	/// To revoke the refund, use [`ChannelManager::abandon_payment`] prior to receiving the
+	/// invoice. If abandoned, or an invoice isn't received before expiration, the payment will fail
+	/// with an [`Event::InvoiceRequestFailed`].
+	///
+	/// # Privacy
+	///
+	/// Uses a two-hop [`BlindedPath`] for the refund with a connected peer as the introduction node
+	/// and a payer id derived from the [`ExpandedKey`], if such a peer is available. Otherwise, the
+	/// refund will not contain any paths and [`ChannelManager::get_our_node_id`] is used as the
+	/// payer id.
+	///
+	/// # Errors
+	///
+	/// Errors if a duplicate `payment_id` is provided given the caveats in the aforementioned link
+	/// or if `amount_msats` is invalid.
+	///
+	/// This is not exported to bindings users as builder patterns don't map outside of move semantics.
+	///
+	/// [`Bolt12Invoice`]: crate::offers::invoice::Bolt12Invoice
+	/// [`ExpandedKey`]: inbound_payment::ExpandedKey
+	/// [Avoiding Duplicate Payments]: Self::send_payment_with_route#avoiding-duplicate-payments
+	pub fn create_refund_builder(
+		&self, description: String, amount_msats: u64, absolute_expiry: Duration,
+		payment_id: PaymentId, retry_strategy: Retry
+	) -> Result<RefundBuilder<secp256k1::All>, Bolt12SemanticError> {
+		let node_id = self.get_our_node_id();
+		let expanded_key = &self.inbound_payment_key;
+		let entropy = &*self.entropy_source;
+		let secp_ctx = &self.secp_ctx;
+
+		let builder = RefundBuilder::deriving_payer_id(
+			description, node_id, expanded_key, entropy, secp_ctx, amount_msats, payment_id
+		)?
+			.chain_hash(ChainHash::from(&self.genesis_hash[..]))
+			.absolute_expiry(absolute_expiry);
+		let builder = match self.create_blinded_path() {
+			Ok(path) => builder.path(path),
+			Err(()) => builder,
+		};
+
+		let _persistence_guard = PersistenceNotifierGuard::notify_on_drop(self);
+		let expiration = StaleExpiration::AbsoluteTimeout(absolute_expiry);
+		self.pending_outbound_payments
+			.add_new_awaiting_invoice(payment_id, expiration, retry_strategy)
+			.map_err(|_| Bolt12SemanticError::DuplicatePaymentId)?;
+
+		Ok(builder)
+	}
+
+	/// Pays for an [`Offer`] using the given parameters by creating an [`InvoiceRequest`] and
+	/// enqueuing it to be sent via an onion message. [`ChannelManager`] will pay the actual
+	/// [`Bolt12Invoice`] once it is received.
+	///
+	/// Uses [`InvoiceRequestBuilder`] such that the [`InvoiceRequest`] it builds is recognized by
+	/// the [`ChannelManager`] when handling a [`Bolt12Invoice`] message in response to the request.
+	/// The optional parameters are used in the builder, if `Some`:
+	/// - `quantity` for [`InvoiceRequest::quantity`] which must be set if
+	///   [`Offer::expects_quantity`] is `true`.
+	/// - `amount_msats` if overpaying what is required for the given `quantity` is desired, and
+	/// - `payer_note` for [`InvoiceRequest::payer_note`].
+	///
+	/// # Payment
+	///
+	/// The provided `payment_id` is used to ensure that only one invoice is paid for the request
+	/// when received. See [Avoiding Duplicate Payments] for other requirements once the payment has
+	/// been sent.
+	///
+	/// To revoke the request, use [`ChannelManager::abandon_payment`] prior to receiving the
+	/// invoice. If abandoned, or an invoice isn't received in a reasonable amount of time, the
+	/// payment will fail with an [`Event::InvoiceRequestFailed`].
+	///
+	/// # Privacy
+	///
+	/// Uses a derived payer id and uses a two-hop [`BlindedPath`] for the reply path with a
+	/// connected peer as the introduction node.
+	///
+	/// # Limitations
+	///
+	/// Requires a direct connection to an introduction node in [`Offer::paths`] or to
+	/// [`Offer::signing_pubkey`], if empty. A similar restriction applies to the responding
+	/// [`Bolt12Invoice::payment_paths`].
+	///
+	/// # Errors
+	///
+	/// Errors if:
+	/// - a duplicate `payment_id` is provided given the caveats in the aforementioned link,
+	/// - the provided parameters are invalid for the offer, or
+	/// - no connected peer supporting onion messages is available for the invoice request's reply
+	///   path.
+	///
+	/// [`InvoiceRequest`]: crate::offers::invoice_request::InvoiceRequest
+	/// [`InvoiceRequest::quantity`]: crate::offers::invoice_request::InvoiceRequest::quantity
+	/// [`InvoiceRequest::payer_note`]: crate::offers::invoice_request::InvoiceRequest::payer_note
+	/// [`InvoiceRequestBuilder`]: crate::offers::invoice_request::InvoiceRequestBuilder
+	/// [`Bolt12Invoice`]: crate::offers::invoice::Bolt12Invoice
+	/// [`Bolt12Invoice::payment_paths`]: crate::offers::invoice::Bolt12Invoice::payment_paths
+	/// [Avoiding Duplicate Payments]: Self::send_payment_with_route#avoiding-duplicate-payments
+	pub fn pay_for_offer(
+		&self, offer: &Offer, quantity: Option<u64>, amount_msats: Option<u64>,
+		payer_note: Option<String>, payment_id: PaymentId, retry_strategy: Retry
+	) -> Result<(), Bolt12SemanticError> {
+		let expanded_key = &self.inbound_payment_key;
+		let entropy = &*self.entropy_source;
+		let secp_ctx = &self.secp_ctx;
+
+		let builder = offer
+			.request_invoice_deriving_payer_id(expanded_key, entropy, secp_ctx, payment_id)?
+			.chain_hash(ChainHash::from(&self.genesis_hash[..]))?;
+		let builder = match quantity {
+			None => builder,
+			Some(quantity) => builder.quantity(quantity)?,
+		};
+		let builder = match amount_msats {
+			None => builder,
+			Some(amount_msats) => builder.amount_msats(amount_msats)?,
+		};
+		let builder = match payer_note {
+			None => builder,
+			Some(payer_note) => builder.payer_note(payer_note),
+		};
+
+		let invoice_request = builder.build_and_sign()?;
+		let reply_path = self.create_blinded_path().map_err(|_| Bolt12SemanticError::MissingPaths)?;
 
-								log_info!(self.logger, "Broadcasting {}", log_tx!(tx));
-								self.tx_broadcaster.broadcast_transactions(&[&tx]);
-								update_maps_on_chan_removal!(self, &chan.context);
-								false
-							} else { true }
-						},
-						Err(e) => {
-							has_update = true;
-							let (close_channel, res) = convert_chan_err!(self, e, chan, channel_id);
-							handle_errors.push((chan.context.get_counterparty_node_id(), Err(res)));
-							!close_channel
-						}
-					}
+		let _persistence_guard = PersistenceNotifierGuard::notify_on_drop(self);
+		let expiration = StaleExpiration::TimerTicks(1);
+		self.pending_outbound_payments
+			.add_new_awaiting_invoice(payment_id, expiration, retry_strategy)
+			.map_err(|_| Bolt12SemanticError::DuplicatePaymentId)?;
+
+		let mut pending_offers_messages = self.pending_offers_messages.lock().unwrap();
+		if offer.paths().is_empty() {
+			pending_offers_messages.push(PendingOnionMessage {
+				contents: OffersMessage::InvoiceRequest(invoice_request),
+				destination: Destination::Node(offer.signing_pubkey()),
+				reply_path: Some(reply_path),
+			});
+		} else {
+			// Send as many invoice requests as there are paths in the offer (with an upper bound).
+			// Using only one path could result in a failure if the path no longer exists. But only
+			// one invoice for a given payment id will be paid, even if more than one is received.
+			for path in offer.paths().iter().take(OFFERS_MESSAGE_REQUEST_LIMIT) {
+				pending_offers_messages.push(PendingOnionMessage {
+					contents: OffersMessage::InvoiceRequest(invoice_request.clone()),
+					destination: Destination::BlindedPath(path.clone()),
+					reply_path: Some(reply_path.clone()),
 				});
 			}
 		}
 
-		for (counterparty_node_id, err) in handle_errors.drain(..) {
-			let _ = handle_error!(self, err, counterparty_node_id);
-		}
-
-		has_update
+		Ok(())
 	}
 
-	/// Handle a list of channel failures during a block_connected or block_disconnected call,
-	/// pushing the channel monitor update (if any) to the background events queue and removing the
-	/// Channel object.
-	fn handle_init_event_channel_failures(&self, mut failed_channels: Vec<ShutdownResult>) {
-		for mut failure in failed_channels.drain(..) {
-			// Either a commitment transactions has been confirmed on-chain or
-			// Channel::block_disconnected detected that the funding transaction has been
-			// reorganized out of the main chain.
-			// We cannot broadcast our latest local state via monitor update (as
-			// Channel::force_shutdown tries to make us do) as we may still be in initialization,
-			// so we track the update internally and handle it when the user next calls
-			// timer_tick_occurred, guaranteeing we're running normally.
-			if let Some((counterparty_node_id, funding_txo, update)) = failure.0.take() {
-				assert_eq!(update.updates.len(), 1);
-				if let ChannelMonitorUpdateStep::ChannelForceClosed { should_broadcast } = update.updates[0] {
-					assert!(should_broadcast);
-				} else { unreachable!(); }
-				self.pending_background_events.lock().unwrap().push(
-					BackgroundEvent::MonitorUpdateRegeneratedOnStartup {
-						counterparty_node_id, funding_txo, update
+	/// Creates a [`Bolt12Invoice`] for a [`Refund`] and enqueues it to be sent via an onion
+	/// message.
+	///
+	/// The resulting invoice uses a [`PaymentHash`] recognized by the [`ChannelManager`] and a
+	/// [`BlindedPath`] containing the [`PaymentSecret`] needed to reconstruct the corresponding
+	/// [`PaymentPreimage`].
+	///
+	/// # Limitations
+	///
+	/// Requires a direct connection to an introduction node in [`Refund::paths`] or to
+	/// [`Refund::payer_id`], if empty. This request is best effort; an invoice will be sent to each
+	/// node meeting the aforementioned criteria, but there's no guarantee that they will be
+	/// received and no retries will be made.
+	///
+	/// # Errors
+	///
+	/// Errors if the refund's amount is invalid or contains unknown required features, or if a
+	/// blinded payment path could not be created for the invoice.
+	///
+	/// [`Bolt12Invoice`]: crate::offers::invoice::Bolt12Invoice
+	pub fn request_refund_payment(&self, refund: &Refund) -> Result<(), Bolt12SemanticError> {
+		let expanded_key = &self.inbound_payment_key;
+		let entropy = &*self.entropy_source;
+		let secp_ctx = &self.secp_ctx;
+
+		let amount_msats = refund.amount_msats();
+		let relative_expiry = DEFAULT_RELATIVE_EXPIRY.as_secs() as u32;
+
+		match self.create_inbound_payment(Some(amount_msats), relative_expiry, None) {
+			Ok((payment_hash, payment_secret)) => {
+				let payment_paths = vec![
+					self.create_one_hop_blinded_payment_path(payment_secret)
+						.map_err(|_| Bolt12SemanticError::MissingPaths)?,
+				];
+				#[cfg(feature = "std")]
+				let builder = refund.respond_using_derived_keys(
+					payment_paths, payment_hash, expanded_key, entropy
+				)?;
+				#[cfg(not(feature = "std"))]
+				let created_at = Duration::from_secs(
+					self.highest_seen_timestamp.load(Ordering::Acquire) as u64
+				);
+				#[cfg(not(feature = "std"))]
+				let builder = refund.respond_using_derived_keys_no_std(
+					payment_paths, payment_hash, created_at, expanded_key, entropy
+				)?;
+				let invoice = builder.allow_mpp().build_and_sign(secp_ctx)?;
+				let reply_path = self.create_blinded_path().ok();
+
+				let mut pending_offers_messages = self.pending_offers_messages.lock().unwrap();
+				if refund.paths().is_empty() {
+					pending_offers_messages.push(PendingOnionMessage {
+						contents: OffersMessage::Invoice(invoice),
+						destination: Destination::Node(refund.payer_id()),
+						reply_path,
 					});
-			}
-			self.finish_force_close_channel(failure);
+				} else {
+					for path in refund.paths().iter().take(OFFERS_MESSAGE_REQUEST_LIMIT) {
+						pending_offers_messages.push(PendingOnionMessage {
+							contents: OffersMessage::Invoice(invoice.clone()),
+							destination: Destination::BlindedPath(path.clone()),
+							reply_path: reply_path.clone(),
+						});
+					}
+				}
+
+				Ok(())
+			},
+			Err(()) => Err(Bolt12SemanticError::InvalidAmount),
 		}
 	}
 
+	/// Creates a two-hop blinded path for an onion message, using a connected peer which supports
+	/// onion messages and with which we have a usable channel as the introduction node.
+	fn create_blinded_path(&self) -> Result<BlindedPath, ()> {
+		let our_node_id = self.get_our_node_id();
+		let peer_node_id = {
+			let per_peer_state = self.per_peer_state.read().unwrap();
+			per_peer_state.iter()
+				.find(|(_, peer_state_mutex)| {
+					let peer_state = peer_state_mutex.lock().unwrap();
+					peer_state.is_connected &&
+						peer_state.latest_features.supports_onion_messages() &&
+						peer_state.channel_by_id.values().any(|chan| chan.context.is_usable())
+				})
+				.map(|(node_id, _)| *node_id)
+				.ok_or(())?
+		};
+
+		BlindedPath::new_for_message(
+			&[peer_node_id, our_node_id], &*self.entropy_source, &self.secp_ctx
+		)
+	}
+
+	/// Creates a one-hop blinded payment path with [`ChannelManager::get_our_node_id`] as the
+	/// introduction node.
+	fn create_one_hop_blinded_payment_path(
+		&self, payment_secret: PaymentSecret
+	) -> Result<(BlindedPayInfo, BlindedPath), ()> {
+		let max_cltv_expiry = self.best_block.read().unwrap().height() + CLTV_FAR_FAR_AWAY
+			+ LATENCY_GRACE_PERIOD_BLOCKS;
+		let payee_tlvs = ReceiveTlvs {
+			payment_secret,
+			payment_constraints: PaymentConstraints {
+				max_cltv_expiry,
+				htlc_minimum_msat: 1,
+			},
+		};
+		BlindedPath::one_hop_for_payment(
+			self.get_our_node_id(), payee_tlvs, &*self.entropy_source, &self.secp_ctx
+		)
+	}
+
 	/// Gets a payment secret and payment hash for use in an invoice given to a third party wishing
 	/// to pay us.
 	///
@@ -6483,7 +8833,10 @@ where
 	#[cfg(any(test, feature = "_test_utils"))]
 	pub fn get_and_clear_pending_events(&self) -> Vec<events::Event> {
 		let events = core::cell::RefCell::new(Vec::new());
-		let event_handler = |event: events::Event| events.borrow_mut().push(event);
+		let event_handler = |event: events::Event| {
+			events.borrow_mut().push(event);
+			Ok(())
+		};
 		self.process_pending_events(&event_handler);
 		events.into_inner()
 	}
@@ -6515,6 +8868,7 @@ where
 	/// operation. It will double-check that nothing *else* is also blocking the same channel from
 	/// making progress and then let any blocked [`ChannelMonitorUpdate`]s fly.
 	fn handle_monitor_update_release(&self, counterparty_node_id: PublicKey, channel_funding_outpoint: OutPoint, mut completed_blocker: Option<RAAMonitorUpdateBlockingAction>) {
+		let channel_id = self.channel_id_for_funding_txo(&channel_funding_outpoint);
 		let mut errors = Vec::new();
 		loop {
 			let per_peer_state = self.per_peer_state.read().unwrap();
@@ -6525,7 +8879,7 @@ where
 				if let Some(blocker) = completed_blocker.take() {
 					// Only do this on the first iteration of the loop.
 					if let Some(blockers) = peer_state.actions_blocking_raa_monitor_updates
-						.get_mut(&channel_funding_outpoint.to_channel_id())
+						.get_mut(&channel_id)
 					{
 						blockers.retain(|iter| iter != &blocker);
 					}
@@ -6537,15 +8891,15 @@ where
 					// blocking monitor updates for this channel. If we do, release the monitor
 					// update(s) when those blockers complete.
 					log_trace!(self.logger, "Delaying monitor unlock for channel {} as another channel's mon update needs to complete first",
-						log_bytes!(&channel_funding_outpoint.to_channel_id()[..]));
+						log_bytes!(&channel_id[..]));
 					break;
 				}
 
-				if let hash_map::Entry::Occupied(mut chan) = peer_state.channel_by_id.entry(channel_funding_outpoint.to_channel_id()) {
+				if let hash_map::Entry::Occupied(mut chan) = peer_state.channel_by_id.entry(channel_id) {
 					debug_assert_eq!(chan.get().context.get_funding_txo().unwrap(), channel_funding_outpoint);
 					if let Some((monitor_update, further_update_exists)) = chan.get_mut().unblock_next_blocked_monitor_update() {
 						log_debug!(self.logger, "Unlocking monitor updating for channel {} and updating monitor",
-							log_bytes!(&channel_funding_outpoint.to_channel_id()[..]));
+							log_bytes!(&channel_id[..]));
 						if let Err(e) = handle_new_monitor_update!(self, channel_funding_outpoint, monitor_update,
 							peer_state_lck, peer_state, per_peer_state, chan)
 						{
@@ -6558,7 +8912,7 @@ where
 						}
 					} else {
 						log_trace!(self.logger, "Unlocked monitor updating for channel {} without monitors to update",
-							log_bytes!(&channel_funding_outpoint.to_channel_id()[..]));
+							log_bytes!(&channel_id[..]));
 					}
 				}
 			} else {
@@ -6589,8 +8943,11 @@ where
 	/// Processes any events asynchronously in the order they were generated since the last call
 	/// using the given event handler.
 	///
+	/// If the handler returns a [`ReplayEvent`] error, the failed event and any events queued after
+	/// it are kept and will be passed to the handler again on the next call.
+	///
 	/// See the trait-level documentation of [`EventsProvider`] for requirements.
-	pub async fn process_pending_events_async<Future: core::future::Future, H: Fn(Event) -> Future>(
+	pub async fn process_pending_events_async<Future: core::future::Future<Output = Result<(), ReplayEvent>>, H: Fn(Event) -> Future>(
 		&self, handler: H
 	) {
 		let mut ev;
@@ -6639,6 +8996,7 @@ where
 			if self.maybe_generate_initial_closing_signed() {
 				result = NotifyOption::DoPersist;
 			}
+			self.maybe_send_stfu();
 
 			let mut pending_events = Vec::new();
 			let per_peer_state = self.per_peer_state.read().unwrap();
@@ -6799,6 +9157,9 @@ where
 				if let (Some(funding_txo), Some(block_hash)) = (chan.context.get_funding_txo(), chan.context.get_funding_tx_confirmed_in()) {
 					res.push((funding_txo.txid, Some(block_hash)));
 				}
+				for (txid, block_hash) in chan.context.get_pending_splice_txs_confirmed_in() {
+					res.push((txid, Some(block_hash)));
+				}
 			}
 		}
 		res
@@ -6811,7 +9172,9 @@ where
 			if let Some(funding_txo) = channel.context.get_funding_txo() {
 				if funding_txo.txid == *txid {
 					channel.funding_transaction_unconfirmed(&self.logger).map(|()| (None, Vec::new(), None))
-				} else { Ok((None, Vec::new(), None)) }
+				} else {
+					channel.splice_transaction_unconfirmed(txid).map(|()| (None, Vec::new(), None))
+				}
 			} else { Ok((None, Vec::new(), None)) }
 		});
 	}
@@ -6839,6 +9202,7 @@ where
 
 		let mut failed_channels = Vec::new();
 		let mut timed_out_htlcs = Vec::new();
+		let mut closed_splice_monitor_updates = Vec::new();
 		{
 			let per_peer_state = self.per_peer_state.read().unwrap();
 			for (_cp_id, peer_state_mutex) in per_peer_state.iter() {
@@ -6905,6 +9269,23 @@ where
 									fake_scid::MAX_SCID_BLOCKS_FROM_NOW);
 							}
 						}
+						if let Some(height) = height_opt {
+							if let Some(splice_locked) = channel.check_get_splice_locked(height) {
+								log_info!(self.logger, "Sending splice_locked to our peer for channel {}", log_bytes!(channel.context.channel_id()));
+								pending_msg_events.push(events::MessageSendEvent::SendSpliceLocked {
+									node_id: channel.context.get_counterparty_node_id(),
+									msg: splice_locked,
+								});
+								if let Some((_, prev_short_channel_id, replaced_monitor_updates)) = channel.maybe_promote_splice(&self.logger) {
+									self.update_maps_on_splice_promotion(channel, prev_short_channel_id, &replaced_monitor_updates);
+									closed_splice_monitor_updates.extend(replaced_monitor_updates);
+								}
+							}
+							for (splice_funding_txo, update) in channel.check_abandon_double_spent_splice(height, &self.logger) {
+								self.funding_txo_to_channel_id.lock().unwrap().remove(&splice_funding_txo);
+								closed_splice_monitor_updates.push((splice_funding_txo, update));
+							}
+						}
 					} else if let Err(reason) = res {
 						update_maps_on_chan_removal!(self, &channel.context);
 						// It looks like our counterparty went on-chain or funding transaction was
@@ -6960,6 +9341,7 @@ where
 						incoming_packet_shared_secret: htlc.forward_info.incoming_shared_secret,
 						phantom_shared_secret: None,
 						outpoint: htlc.prev_funding_outpoint,
+						blinded_failure: htlc.forward_info.routing.blinded_failure(),
 					});
 
 					let requested_forward_scid /* intercept scid */ = match htlc.forward_info.routing {
@@ -6976,6 +9358,12 @@ where
 		}
 
 		self.handle_init_event_channel_failures(failed_channels);
+		// The monitor watching the new funding output of an abandoned splice is no longer needed.
+		// As with closed channels, its update is regenerated on startup if lost.
+		for update in closed_splice_monitor_updates {
+			self.pending_background_events.lock().unwrap().push(
+				BackgroundEvent::ClosedMonitorUpdateRegeneratedOnStartup(update));
+		}
 
 		for (source, payment_hash, reason, destination) in timed_out_htlcs.drain(..) {
 			self.fail_htlc_backwards_internal(&source, &payment_hash, &reason, destination);
@@ -7055,9 +9443,8 @@ where
 	}
 
 	fn handle_open_channel_v2(&self, counterparty_node_id: &PublicKey, msg: &msgs::OpenChannelV2) {
-		let _: Result<(), _> = handle_error!(self, Err(MsgHandleErrInternal::send_err_msg_no_close(
-			"Dual-funded channels not supported".to_owned(),
-			 msg.temporary_channel_id.clone())), *counterparty_node_id);
+		let _persistence_guard = PersistenceNotifierGuard::notify_on_drop(self);
+		let _ = handle_error!(self, self.internal_open_channel_v2(counterparty_node_id, msg), *counterparty_node_id);
 	}
 
 	fn handle_accept_channel(&self, counterparty_node_id: &PublicKey, msg: &msgs::AcceptChannel) {
@@ -7066,9 +9453,8 @@ where
 	}
 
 	fn handle_accept_channel_v2(&self, counterparty_node_id: &PublicKey, msg: &msgs::AcceptChannelV2) {
-		let _: Result<(), _> = handle_error!(self, Err(MsgHandleErrInternal::send_err_msg_no_close(
-			"Dual-funded channels not supported".to_owned(),
-			 msg.temporary_channel_id.clone())), *counterparty_node_id);
+		let _persistence_guard = PersistenceNotifierGuard::notify_on_drop(self);
+		let _ = handle_error!(self, self.internal_accept_channel_v2(counterparty_node_id, msg), *counterparty_node_id);
 	}
 
 	fn handle_funding_created(&self, counterparty_node_id: &PublicKey, msg: &msgs::FundingCreated) {
@@ -7155,6 +9541,7 @@ where
 	fn peer_disconnected(&self, counterparty_node_id: &PublicKey) {
 		let _persistence_guard = PersistenceNotifierGuard::notify_on_drop(self);
 		let mut failed_channels = Vec::new();
+		let mut failed_funding_batches = Vec::new();
 		let mut per_peer_state = self.per_peer_state.write().unwrap();
 		let remove_peer = {
 			log_debug!(self.logger, "Marking channels with {} disconnected and generating channel_updates.",
@@ -7168,6 +9555,9 @@ where
 					if chan.is_shutdown() {
 						update_maps_on_chan_removal!(self, &chan.context);
 						self.issue_channel_close_events(&chan.context, ClosureReason::DisconnectedPeer);
+						if let Some(funding_txid) = chan.context.unbroadcasted_batch_funding_txid() {
+							failed_funding_batches.push(funding_txid);
+						}
 						return false;
 					}
 					true
@@ -7182,6 +9572,16 @@ where
 					self.issue_channel_close_events(&chan.context, ClosureReason::DisconnectedPeer);
 					false
 				});
+				peer_state.inbound_v2_channel_by_id.retain(|_, chan| {
+					update_maps_on_chan_removal!(self, &chan.context);
+					self.issue_channel_close_events(&chan.context, ClosureReason::DisconnectedPeer);
+					false
+				});
+				peer_state.outbound_v2_channel_by_id.retain(|_, chan| {
+					update_maps_on_chan_removal!(self, &chan.context);
+					self.issue_channel_close_events(&chan.context, ClosureReason::DisconnectedPeer);
+					false
+				});
 				pending_msg_events.retain(|msg| {
 					match msg {
 						// V1 Channel Establishment
@@ -7205,6 +9605,16 @@ where
 						&events::MessageSendEvent::SendTxInitRbf { .. } => false,
 						&events::MessageSendEvent::SendTxAckRbf { .. } => false,
 						&events::MessageSendEvent::SendTxAbort { .. } => false,
+						// Splicing
+						&events::MessageSendEvent::SendSplice { .. } => false,
+						&events::MessageSendEvent::SendSpliceAck { .. } => false,
+						&events::MessageSendEvent::SendSpliceLocked { .. } => false,
+						// Quiescence
+						&events::MessageSendEvent::SendStfu { .. } => false,
+						// Channel type upgrades
+						&events::MessageSendEvent::SendDynPropose { .. } => false,
+						&events::MessageSendEvent::SendDynAck { .. } => false,
+						&events::MessageSendEvent::SendDynReject { .. } => false,
 						// Channel Operations
 						&events::MessageSendEvent::UpdateHTLCs { .. } => false,
 						&events::MessageSendEvent::SendRevokeAndACK { .. } => false,
@@ -7237,6 +9647,9 @@ where
 		for failure in failed_channels.drain(..) {
 			self.finish_force_close_channel(failure);
 		}
+		for funding_txid in failed_funding_batches {
+			self.close_funding_batch(&funding_txid);
+		}
 	}
 
 	fn peer_connected(&self, counterparty_node_id: &PublicKey, init_msg: &msgs::Init, inbound: bool) -> Result<(), ()> {
@@ -7265,6 +9678,8 @@ where
 						channel_by_id: HashMap::new(),
 						outbound_v1_channel_by_id: HashMap::new(),
 						inbound_v1_channel_by_id: HashMap::new(),
+						outbound_v2_channel_by_id: HashMap::new(),
+						inbound_v2_channel_by_id: HashMap::new(),
 						latest_features: init_msg.features.clone(),
 						pending_msg_events: Vec::new(),
 						in_flight_monitor_updates: BTreeMap::new(),
@@ -7326,7 +9741,9 @@ where
 				let peer_state = &mut *peer_state_lock;
 				peer_state.channel_by_id.keys().cloned()
 					.chain(peer_state.outbound_v1_channel_by_id.keys().cloned())
-					.chain(peer_state.inbound_v1_channel_by_id.keys().cloned()).collect()
+					.chain(peer_state.inbound_v1_channel_by_id.keys().cloned())
+					.chain(peer_state.outbound_v2_channel_by_id.keys().cloned())
+					.chain(peer_state.inbound_v2_channel_by_id.keys().cloned()).collect()
 			};
 			for channel_id in channel_ids {
 				// Untrusted messages from peer, we throw away the error if id points to a non-existent channel
@@ -7369,57 +9786,174 @@ where
 	}
 
 	fn handle_tx_add_input(&self, counterparty_node_id: &PublicKey, msg: &msgs::TxAddInput) {
-		let _: Result<(), _> = handle_error!(self, Err(MsgHandleErrInternal::send_err_msg_no_close(
-			"Dual-funded channels not supported".to_owned(),
-			 msg.channel_id.clone())), *counterparty_node_id);
+		let _persistence_guard = PersistenceNotifierGuard::notify_on_drop(self);
+		let _ = handle_error!(self, self.internal_tx_msg(counterparty_node_id, msg.channel_id,
+			|chan| chan.tx_add_input(msg)), *counterparty_node_id);
 	}
 
 	fn handle_tx_add_output(&self, counterparty_node_id: &PublicKey, msg: &msgs::TxAddOutput) {
-		let _: Result<(), _> = handle_error!(self, Err(MsgHandleErrInternal::send_err_msg_no_close(
-			"Dual-funded channels not supported".to_owned(),
-			 msg.channel_id.clone())), *counterparty_node_id);
+		let _persistence_guard = PersistenceNotifierGuard::notify_on_drop(self);
+		let _ = handle_error!(self, self.internal_tx_msg(counterparty_node_id, msg.channel_id,
+			|chan| chan.tx_add_output(msg)), *counterparty_node_id);
 	}
 
 	fn handle_tx_remove_input(&self, counterparty_node_id: &PublicKey, msg: &msgs::TxRemoveInput) {
-		let _: Result<(), _> = handle_error!(self, Err(MsgHandleErrInternal::send_err_msg_no_close(
-			"Dual-funded channels not supported".to_owned(),
-			 msg.channel_id.clone())), *counterparty_node_id);
+		let _persistence_guard = PersistenceNotifierGuard::notify_on_drop(self);
+		let _ = handle_error!(self, self.internal_tx_msg(counterparty_node_id, msg.channel_id,
+			|chan| chan.tx_remove_input(msg)), *counterparty_node_id);
 	}
 
 	fn handle_tx_remove_output(&self, counterparty_node_id: &PublicKey, msg: &msgs::TxRemoveOutput) {
-		let _: Result<(), _> = handle_error!(self, Err(MsgHandleErrInternal::send_err_msg_no_close(
-			"Dual-funded channels not supported".to_owned(),
-			 msg.channel_id.clone())), *counterparty_node_id);
+		let _persistence_guard = PersistenceNotifierGuard::notify_on_drop(self);
+		let _ = handle_error!(self, self.internal_tx_msg(counterparty_node_id, msg.channel_id,
+			|chan| chan.tx_remove_output(msg)), *counterparty_node_id);
 	}
 
 	fn handle_tx_complete(&self, counterparty_node_id: &PublicKey, msg: &msgs::TxComplete) {
-		let _: Result<(), _> = handle_error!(self, Err(MsgHandleErrInternal::send_err_msg_no_close(
-			"Dual-funded channels not supported".to_owned(),
-			 msg.channel_id.clone())), *counterparty_node_id);
+		let _persistence_guard = PersistenceNotifierGuard::notify_on_drop(self);
+		let _ = handle_error!(self, self.internal_tx_complete(counterparty_node_id, msg), *counterparty_node_id);
 	}
 
 	fn handle_tx_signatures(&self, counterparty_node_id: &PublicKey, msg: &msgs::TxSignatures) {
-		let _: Result<(), _> = handle_error!(self, Err(MsgHandleErrInternal::send_err_msg_no_close(
-			"Dual-funded channels not supported".to_owned(),
-			 msg.channel_id.clone())), *counterparty_node_id);
+		let _persistence_guard = PersistenceNotifierGuard::notify_on_drop(self);
+		let _ = handle_error!(self, self.internal_tx_signatures(counterparty_node_id, msg), *counterparty_node_id);
 	}
 
 	fn handle_tx_init_rbf(&self, counterparty_node_id: &PublicKey, msg: &msgs::TxInitRbf) {
-		let _: Result<(), _> = handle_error!(self, Err(MsgHandleErrInternal::send_err_msg_no_close(
-			"Dual-funded channels not supported".to_owned(),
-			 msg.channel_id.clone())), *counterparty_node_id);
+		let _persistence_guard = PersistenceNotifierGuard::notify_on_drop(self);
+		let _ = handle_error!(self, self.internal_tx_init_rbf(counterparty_node_id, msg), *counterparty_node_id);
 	}
 
 	fn handle_tx_ack_rbf(&self, counterparty_node_id: &PublicKey, msg: &msgs::TxAckRbf) {
-		let _: Result<(), _> = handle_error!(self, Err(MsgHandleErrInternal::send_err_msg_no_close(
-			"Dual-funded channels not supported".to_owned(),
-			 msg.channel_id.clone())), *counterparty_node_id);
+		let _persistence_guard = PersistenceNotifierGuard::notify_on_drop(self);
+		let _ = handle_error!(self, self.internal_tx_ack_rbf(counterparty_node_id, msg), *counterparty_node_id);
 	}
 
 	fn handle_tx_abort(&self, counterparty_node_id: &PublicKey, msg: &msgs::TxAbort) {
-		let _: Result<(), _> = handle_error!(self, Err(MsgHandleErrInternal::send_err_msg_no_close(
-			"Dual-funded channels not supported".to_owned(),
-			 msg.channel_id.clone())), *counterparty_node_id);
+		let _persistence_guard = PersistenceNotifierGuard::notify_on_drop(self);
+		let _ = handle_error!(self, self.internal_tx_abort(counterparty_node_id, msg), *counterparty_node_id);
+	}
+
+	fn handle_splice(&self, counterparty_node_id: &PublicKey, msg: &msgs::Splice) {
+		let _persistence_guard = PersistenceNotifierGuard::notify_on_drop(self);
+		let _ = handle_error!(self, self.internal_splice(counterparty_node_id, msg), *counterparty_node_id);
+	}
+
+	fn handle_splice_ack(&self, counterparty_node_id: &PublicKey, msg: &msgs::SpliceAck) {
+		let _persistence_guard = PersistenceNotifierGuard::notify_on_drop(self);
+		let _ = handle_error!(self, self.internal_splice_ack(counterparty_node_id, msg), *counterparty_node_id);
+	}
+
+	fn handle_splice_locked(&self, counterparty_node_id: &PublicKey, msg: &msgs::SpliceLocked) {
+		let _persistence_guard = PersistenceNotifierGuard::notify_on_drop(self);
+		let _ = handle_error!(self, self.internal_splice_locked(counterparty_node_id, msg), *counterparty_node_id);
+	}
+
+	fn handle_stfu(&self, counterparty_node_id: &PublicKey, msg: &msgs::Stfu) {
+		let _persistence_guard = PersistenceNotifierGuard::notify_on_drop(self);
+		let _ = handle_error!(self, self.internal_stfu(counterparty_node_id, msg), *counterparty_node_id);
+	}
+
+	fn handle_dyn_propose(&self, counterparty_node_id: &PublicKey, msg: &msgs::DynPropose) {
+		let _persistence_guard = PersistenceNotifierGuard::notify_on_drop(self);
+		let _ = handle_error!(self, self.internal_dyn_propose(counterparty_node_id, msg), *counterparty_node_id);
+	}
+
+	fn handle_dyn_ack(&self, counterparty_node_id: &PublicKey, msg: &msgs::DynAck) {
+		let _persistence_guard = PersistenceNotifierGuard::notify_on_drop(self);
+		let _ = handle_error!(self, self.internal_dyn_ack(counterparty_node_id, msg), *counterparty_node_id);
+	}
+
+	fn handle_dyn_reject(&self, counterparty_node_id: &PublicKey, msg: &msgs::DynReject) {
+		let _persistence_guard = PersistenceNotifierGuard::notify_on_drop(self);
+		let _ = handle_error!(self, self.internal_dyn_reject(counterparty_node_id, msg), *counterparty_node_id);
+	}
+}
+
+impl<M: Deref, T: Deref, ES: Deref, NS: Deref, SP: Deref, F: Deref, R: Deref, L: Deref>
+OffersMessageHandler for ChannelManager<M, T, ES, NS, SP, F, R, L>
+where
+	M::Target: chain::Watch<<SP::Target as SignerProvider>::Signer>,
+	T::Target: BroadcasterInterface,
+	ES::Target: EntropySource,
+	NS::Target: NodeSigner,
+	SP::Target: SignerProvider,
+	F::Target: FeeEstimator,
+	R::Target: Router,
+	L::Target: Logger,
+{
+	fn handle_message(&self, message: OffersMessage) -> Option<OffersMessage> {
+		let secp_ctx = &self.secp_ctx;
+		let expanded_key = &self.inbound_payment_key;
+
+		match message {
+			OffersMessage::InvoiceRequest(invoice_request) => {
+				let amount_msats = match InvoiceBuilder::<DerivedSigningPubkey>::check_amount_msats(
+					&invoice_request
+				) {
+					Ok(amount_msats) => Some(amount_msats),
+					Err(error) => return Some(OffersMessage::InvoiceError(error.into())),
+				};
+				let relative_expiry = DEFAULT_RELATIVE_EXPIRY.as_secs() as u32;
+
+				match self.create_inbound_payment(amount_msats, relative_expiry, None) {
+					Ok((payment_hash, payment_secret)) => {
+						let payment_paths = match self.create_one_hop_blinded_payment_path(payment_secret) {
+							Ok(payment_path) => vec![payment_path],
+							Err(()) => {
+								let error = Bolt12SemanticError::MissingPaths;
+								return Some(OffersMessage::InvoiceError(error.into()));
+							},
+						};
+						#[cfg(not(feature = "std"))]
+						let created_at = Duration::from_secs(
+							self.highest_seen_timestamp.load(Ordering::Acquire) as u64
+						);
+
+						#[cfg(feature = "std")]
+						let response = invoice_request.verify_and_respond_using_derived_keys(
+							payment_paths, payment_hash, expanded_key, secp_ctx
+						);
+						#[cfg(not(feature = "std"))]
+						let response = invoice_request.verify_and_respond_using_derived_keys_no_std(
+							payment_paths, payment_hash, created_at, expanded_key, secp_ctx
+						);
+
+						match response.and_then(|builder| builder.allow_mpp().build_and_sign(secp_ctx)) {
+							Ok(invoice) => Some(OffersMessage::Invoice(invoice)),
+							Err(error) => Some(OffersMessage::InvoiceError(error.into())),
+						}
+					},
+					Err(()) => {
+						Some(OffersMessage::InvoiceError(Bolt12SemanticError::InvalidAmount.into()))
+					},
+				}
+			},
+			OffersMessage::Invoice(invoice) => {
+				match invoice.verify(expanded_key, secp_ctx) {
+					Err(()) => {
+						Some(OffersMessage::InvoiceError(InvoiceError::from_string("Unrecognized invoice".to_owned())))
+					},
+					Ok(_) if invoice.features().requires_unknown_bits() => {
+						Some(OffersMessage::InvoiceError(Bolt12SemanticError::UnknownRequiredFeatures.into()))
+					},
+					Ok(payment_id) => {
+						if let Err(e) = self.send_payment_for_bolt12_invoice(&invoice, payment_id) {
+							log_trace!(self.logger, "Failed paying invoice: {:?}", e);
+						}
+						None
+					},
+				}
+			},
+			OffersMessage::InvoiceError(invoice_error) => {
+				log_trace!(self.logger, "Received invoice_error: {}", invoice_error);
+				None
+			},
+		}
+	}
+
+	fn release_pending_messages(&self) -> Vec<PendingOnionMessage<OffersMessage>> {
+		core::mem::take(&mut self.pending_offers_messages.lock().unwrap())
 	}
 }
 
@@ -7466,9 +10000,13 @@ pub fn provided_init_features(config: &UserConfig) -> InitFeatures {
 	features.set_basic_mpp_optional();
 	features.set_wumbo_optional();
 	features.set_shutdown_any_segwit_optional();
+	features.set_dual_fund_optional();
 	features.set_channel_type_optional();
 	features.set_scid_privacy_optional();
 	features.set_zero_conf_optional();
+	features.set_splicing_optional();
+	features.set_quiescence_optional();
+	features.set_dynamic_commitments_optional();
 	if config.channel_handshake_config.negotiate_anchors_zero_fee_htlc_tx {
 		features.set_anchors_zero_fee_htlc_tx_optional();
 	}
@@ -7528,6 +10066,7 @@ impl Writeable for ChannelDetails {
 			(37, user_channel_id_high_opt, option),
 			(39, self.feerate_sat_per_1000_weight, option),
 			(41, self.channel_shutdown_state, option),
+			(43, self.channel_quiescence_state, option),
 		});
 		Ok(())
 	}
@@ -7566,6 +10105,7 @@ impl Readable for ChannelDetails {
 			(37, user_channel_id_high_opt, option),
 			(39, feerate_sat_per_1000_weight, option),
 			(41, channel_shutdown_state, option),
+			(43, channel_quiescence_state, option),
 		});
 
 		// `user_channel_id` used to be a single u64 value. In order to remain backwards compatible with
@@ -7602,6 +10142,7 @@ impl Readable for ChannelDetails {
 			inbound_htlc_maximum_msat,
 			feerate_sat_per_1000_weight,
 			channel_shutdown_state,
+			channel_quiescence_state,
 		})
 	}
 }
@@ -7615,6 +10156,7 @@ impl_writeable_tlv_based!(PhantomRouteHints, {
 impl_writeable_tlv_based_enum!(PendingHTLCRouting,
 	(0, Forward) => {
 		(0, onion_packet, required),
+		(1, blinded, option),
 		(2, short_channel_id, required),
 	},
 	(1, Receive) => {
@@ -7622,6 +10164,7 @@ impl_writeable_tlv_based_enum!(PendingHTLCRouting,
 		(1, phantom_shared_secret, option),
 		(2, incoming_cltv_expiry, required),
 		(3, payment_metadata, option),
+		(5, requires_blinded_error, (default_value, false)),
 	},
 	(2, ReceiveKeysend) => {
 		(0, payment_preimage, required),
@@ -7631,6 +10174,11 @@ impl_writeable_tlv_based_enum!(PendingHTLCRouting,
 	},
 ;);
 
+impl_writeable_tlv_based!(BlindedForward, {
+	(0, inbound_blinding_point, required),
+	(2, failure, required),
+});
+
 impl_writeable_tlv_based!(PendingHTLCInfo, {
 	(0, routing, required),
 	(2, incoming_shared_secret, required),
@@ -7714,10 +10262,16 @@ impl_writeable_tlv_based_enum!(PendingHTLCStatus, ;
 	(1, Fail),
 );
 
+impl_writeable_tlv_based_enum!(BlindedFailure,
+	(0, FromIntroductionNode) => {},
+	(2, FromBlindedNode) => {},
+;);
+
 impl_writeable_tlv_based!(HTLCPreviousHopData, {
 	(0, short_channel_id, required),
 	(1, phantom_shared_secret, option),
 	(2, outpoint, required),
+	(3, blinded_failure, option),
 	(4, htlc_id, required),
 	(6, incoming_packet_shared_secret, required)
 });
@@ -7873,13 +10427,68 @@ impl_writeable_tlv_based!(PendingAddHTLCInfo, {
 	(6, prev_funding_outpoint, required),
 });
 
-impl_writeable_tlv_based_enum!(HTLCForwardInfo,
-	(1, FailHTLC) => {
-		(0, htlc_id, required),
-		(2, err_packet, required),
-	};
-	(0, AddHTLC)
-);
+impl Writeable for HTLCForwardInfo {
+	fn write<W: Writer>(&self, w: &mut W) -> Result<(), io::Error> {
+		const FAIL_HTLC_VARIANT_ID: u8 = 1;
+		match self {
+			Self::AddHTLC(info) => {
+				0u8.write(w)?;
+				info.write(w)?;
+			},
+			Self::FailHTLC { htlc_id, err_packet } => {
+				FAIL_HTLC_VARIANT_ID.write(w)?;
+				write_tlv_fields!(w, {
+					(0, htlc_id, required),
+					(2, err_packet, required),
+				});
+			},
+			Self::FailMalformedHTLC { htlc_id, failure_code, sha256_of_onion } => {
+				// This variant was added after `FailHTLC`, so write it as a `FailHTLC` with an empty
+				// error packet, allowing older versions to fail the HTLC back, with the malformed data
+				// in odd TLVs for newer versions.
+				FAIL_HTLC_VARIANT_ID.write(w)?;
+				let dummy_err_packet = msgs::OnionErrorPacket { data: Vec::new() };
+				write_tlv_fields!(w, {
+					(0, htlc_id, required),
+					(1, failure_code, required),
+					(2, dummy_err_packet, required),
+					(3, sha256_of_onion, required),
+				});
+			},
+		}
+		Ok(())
+	}
+}
+
+impl Readable for HTLCForwardInfo {
+	fn read<R: Read>(r: &mut R) -> Result<Self, DecodeError> {
+		let id: u8 = Readable::read(r)?;
+		Ok(match id {
+			0 => Self::AddHTLC(Readable::read(r)?),
+			1 => {
+				_init_and_read_tlv_fields!(r, {
+					(0, htlc_id, required),
+					(1, malformed_htlc_failure_code, option),
+					(2, err_packet, required),
+					(3, sha256_of_onion, option),
+				});
+				if let Some(failure_code) = malformed_htlc_failure_code {
+					Self::FailMalformedHTLC {
+						htlc_id: _init_tlv_based_struct_field!(htlc_id, required),
+						failure_code,
+						sha256_of_onion: sha256_of_onion.ok_or(DecodeError::InvalidValue)?,
+					}
+				} else {
+					Self::FailHTLC {
+						htlc_id: _init_tlv_based_struct_field!(htlc_id, required),
+						err_packet: _init_tlv_based_struct_field!(err_packet, required),
+					}
+				}
+			},
+			_ => return Err(DecodeError::InvalidValue),
+		})
+	}
+}
 
 impl_writeable_tlv_based!(PendingInboundPayment, {
 	(0, payment_secret, required),
@@ -8052,6 +10661,8 @@ where
 						session_priv.write(writer)?;
 					}
 				}
+				PendingOutboundPayment::AwaitingInvoice { .. } => {},
+				PendingOutboundPayment::InvoiceReceived { .. } => {},
 				PendingOutboundPayment::Fulfilled { .. } => {},
 				PendingOutboundPayment::Abandoned { .. } => {},
 			}
@@ -8092,6 +10703,13 @@ where
 			}
 		}
 
+		let funding_txo_to_channel_id = self.funding_txo_to_channel_id.lock().unwrap();
+		let funding_txo_to_channel_id_opt = if funding_txo_to_channel_id.is_empty() {
+			None
+		} else {
+			Some(&*funding_txo_to_channel_id)
+		};
+
 		write_tlv_fields!(writer, {
 			(1, pending_outbound_payments_no_retry, required),
 			(2, pending_intercepted_htlcs, option),
@@ -8104,6 +10722,7 @@ where
 			(9, htlc_purposes, required_vec),
 			(10, in_flight_monitor_updates, option),
 			(11, self.probing_cookie_secret, required),
+			(12, funding_txo_to_channel_id_opt, option),
 			(13, htlc_onion_fields, optional_vec),
 		});
 
@@ -8160,6 +10779,12 @@ impl_writeable_tlv_based_enum!(ChannelShutdownState,
 	(8, ShutdownComplete) => {}, ;
 );
 
+impl_writeable_tlv_based_enum!(ChannelQuiescenceState,
+	(0, NotQuiescent) => {},
+	(2, AwaitingQuiescence) => {},
+	(4, Quiescent) => {}, ;
+);
+
 /// Arguments for the creation of a ChannelManager that are not deserialized.
 ///
 /// At a high-level, the process for deserializing a ChannelManager and resuming normal operation
@@ -8328,12 +10953,16 @@ where
 		let mut short_to_chan_info = HashMap::with_capacity(cmp::min(channel_count as usize, 128));
 		let mut channel_closures = VecDeque::new();
 		let mut close_background_events = Vec::new();
+		let mut discarded_batch_funding_txids = HashSet::new();
 		for _ in 0..channel_count {
 			let mut channel: Channel<<SP::Target as SignerProvider>::Signer> = Channel::read(reader, (
 				&args.entropy_source, &args.signer_provider, best_block_height, &provided_channel_type_features(&args.default_config)
 			))?;
 			let funding_txo = channel.context.get_funding_txo().ok_or(DecodeError::InvalidValue)?;
 			funding_txo_set.insert(funding_txo.clone());
+			for splice_funding_txo in channel.context.get_pending_splice_funding_txos() {
+				funding_txo_set.insert(splice_funding_txo);
+			}
 			if let Some(ref mut monitor) = args.channel_monitors.get_mut(&funding_txo) {
 				if channel.get_cur_holder_commitment_transaction_number() > monitor.get_cur_holder_commitment_number() ||
 						channel.get_revoked_counterparty_commitment_transaction_number() > monitor.get_min_seen_secret() ||
@@ -8344,12 +10973,17 @@ where
 					log_error!(args.logger, " The channel will be force-closed and the latest commitment transaction from the ChannelMonitor broadcast.");
 					log_error!(args.logger, " The ChannelMonitor for channel {} is at update_id {} but the ChannelManager is at update_id {}.",
 						log_bytes!(channel.context.channel_id()), monitor.get_latest_update_id(), channel.context.get_latest_monitor_update_id());
-					let (monitor_update, mut new_failed_htlcs) = channel.context.force_shutdown(true);
+					let (monitor_update, mut new_failed_htlcs, splice_monitor_updates, _) = channel.context.force_shutdown(true);
 					if let Some((counterparty_node_id, funding_txo, update)) = monitor_update {
 						close_background_events.push(BackgroundEvent::MonitorUpdateRegeneratedOnStartup {
 							counterparty_node_id, funding_txo, update
 						});
 					}
+					for (funding_txo, update) in splice_monitor_updates {
+						close_background_events.push(BackgroundEvent::MonitorUpdateRegeneratedOnStartup {
+							counterparty_node_id: channel.context.get_counterparty_node_id(), funding_txo, update
+						});
+					}
 					failed_htlcs.append(&mut new_failed_htlcs);
 					channel_closures.push_back((events::Event::ChannelClosed {
 						channel_id: channel.context.channel_id(),
@@ -8375,6 +11009,31 @@ where
 							failed_htlcs.push((channel_htlc_source.clone(), *payment_hash, channel.context.get_counterparty_node_id(), channel.context.channel_id()));
 						}
 					}
+				} else if channel.context.is_awaiting_batch_broadcast() {
+					// We don't persist the state of funding batches, so we can't tell whether the other
+					// channels in the batch are ready. As the funding transaction was never broadcast,
+					// we can safely close the channel (and thus the rest of the batch) instead.
+					log_info!(args.logger, "Closing channel {} as its batch funding transaction was not broadcast",
+						log_bytes!(channel.context.channel_id()));
+					if let Some(transaction) = channel.context.unbroadcasted_funding() {
+						if discarded_batch_funding_txids.insert(transaction.txid()) {
+							channel_closures.push_back((events::Event::DiscardFunding {
+								channel_id: channel.context.channel_id(), transaction
+							}, None));
+						}
+					}
+					let (monitor_update, mut new_failed_htlcs, _, _) = channel.context.force_shutdown(false);
+					if let Some((counterparty_node_id, funding_txo, update)) = monitor_update {
+						close_background_events.push(BackgroundEvent::MonitorUpdateRegeneratedOnStartup {
+							counterparty_node_id, funding_txo, update
+						});
+					}
+					failed_htlcs.append(&mut new_failed_htlcs);
+					channel_closures.push_back((events::Event::ChannelClosed {
+						channel_id: channel.context.channel_id(),
+						user_channel_id: channel.context.get_user_id(),
+						reason: ClosureReason::FundingBatchClosure,
+					}, None));
 				} else {
 					log_info!(args.logger, "Successfully loaded channel {} at update_id {} against monitor at update id {}",
 						log_bytes!(channel.context.channel_id()), channel.context.get_latest_monitor_update_id(),
@@ -8459,6 +11118,8 @@ where
 				channel_by_id,
 				outbound_v1_channel_by_id: HashMap::new(),
 				inbound_v1_channel_by_id: HashMap::new(),
+				outbound_v2_channel_by_id: HashMap::new(),
+				inbound_v2_channel_by_id: HashMap::new(),
 				latest_features: InitFeatures::empty(),
 				pending_msg_events: Vec::new(),
 				in_flight_monitor_updates: BTreeMap::new(),
@@ -8539,6 +11200,7 @@ where
 		let mut monitor_update_blocked_actions_per_peer: Option<Vec<(_, BTreeMap<_, Vec<_>>)>> = Some(Vec::new());
 		let mut events_override = None;
 		let mut in_flight_monitor_updates: Option<HashMap<(PublicKey, OutPoint), Vec<ChannelMonitorUpdate>>> = None;
+		let mut funding_txo_to_channel_id: Option<HashMap<OutPoint, [u8; 32]>> = None;
 		read_tlv_fields!(reader, {
 			(1, pending_outbound_payments_no_retry, option),
 			(2, pending_intercepted_htlcs, option),
@@ -8551,6 +11213,7 @@ where
 			(9, claimable_htlc_purposes, optional_vec),
 			(10, in_flight_monitor_updates, option),
 			(11, probing_cookie_secret, option),
+			(12, funding_txo_to_channel_id, option),
 			(13, claimable_htlc_onion_fields, optional_vec),
 		});
 		if fake_scid_rand_bytes.is_none() {
@@ -8561,6 +11224,11 @@ where
 			probing_cookie_secret = Some(args.entropy_source.get_secure_random_bytes());
 		}
 
+		let funding_txo_to_channel_id = funding_txo_to_channel_id.unwrap_or_else(HashMap::new);
+		let channel_id_for_funding_txo = |funding_txo: &OutPoint| {
+			funding_txo_to_channel_id.get(funding_txo).copied().unwrap_or_else(|| funding_txo.to_channel_id())
+		};
+
 		if let Some(events) = events_override {
 			pending_events_read = events;
 		}
@@ -8619,7 +11287,7 @@ where
 					pending_background_events.push(
 						BackgroundEvent::MonitorUpdatesComplete {
 							counterparty_node_id: $counterparty_node_id,
-							channel_id: $funding_txo.to_channel_id(),
+							channel_id: channel_id_for_funding_txo(&$funding_txo),
 						});
 				}
 				if $peer_state.in_flight_monitor_updates.insert($funding_txo, $chan_in_flight_upds).is_some() {
@@ -8705,7 +11373,7 @@ where
 			// We only rebuild the pending payments map if we were most recently serialized by
 			// 0.0.102+
 			for (_, monitor) in args.channel_monitors.iter() {
-				let counterparty_opt = id_to_peer.get(&monitor.get_funding_txo().0.to_channel_id());
+				let counterparty_opt = id_to_peer.get(&channel_id_for_funding_txo(&monitor.get_funding_txo().0));
 				if counterparty_opt.is_none() {
 					for (htlc_source, (htlc, _)) in monitor.get_pending_or_resolved_outbound_htlcs() {
 						if let HTLCSource::OutboundRoute { payment_id, session_priv, path, .. } = htlc_source {
@@ -8814,7 +11482,7 @@ where
 									// downstream chan is closed (because we don't have a
 									// channel_id -> peer map entry).
 									counterparty_opt.is_none(),
-									monitor.get_funding_txo().0.to_channel_id()))
+									channel_id_for_funding_txo(&monitor.get_funding_txo().0)))
 							} else { None }
 						} else {
 							// If it was an outbound payment, we've handled it above - if a preimage
@@ -8979,7 +11647,7 @@ where
 						// this channel as well. On the flip side, there's no harm in restarting
 						// without the new monitor persisted - we'll end up right back here on
 						// restart.
-						let previous_channel_id = claimable_htlc.prev_hop.outpoint.to_channel_id();
+						let previous_channel_id = channel_id_for_funding_txo(&claimable_htlc.prev_hop.outpoint);
 						if let Some(peer_node_id) = id_to_peer.get(&previous_channel_id){
 							let peer_state_mutex = per_peer_state.get(peer_node_id).unwrap();
 							let mut peer_state_lock = peer_state_mutex.lock().unwrap();
@@ -9012,7 +11680,7 @@ where
 						} = action {
 							if let Some(blocked_peer_state) = per_peer_state.get(&blocked_node_id) {
 								blocked_peer_state.lock().unwrap().actions_blocking_raa_monitor_updates
-									.entry(blocked_channel_outpoint.to_channel_id())
+									.entry(channel_id_for_funding_txo(&blocked_channel_outpoint))
 									.or_insert_with(Vec::new).push(blocking_action.clone());
 							} else {
 								// If the channel we were blocking has closed, we don't need to
@@ -9049,6 +11717,7 @@ where
 			claimable_payments: Mutex::new(ClaimablePayments { claimable_payments, pending_claiming_payments: pending_claiming_payments.unwrap() }),
 			outbound_scid_aliases: Mutex::new(outbound_scid_aliases),
 			id_to_peer: Mutex::new(id_to_peer),
+			funding_txo_to_channel_id: Mutex::new(funding_txo_to_channel_id),
 			short_to_chan_info: FairRwLock::new(short_to_chan_info),
 			fake_scid_rand_bytes: fake_scid_rand_bytes.unwrap(),
 
@@ -9063,7 +11732,10 @@ where
 
 			pending_events: Mutex::new(pending_events_read),
 			pending_events_processor: AtomicBool::new(false),
+			pending_offers_messages: Mutex::new(Vec::new()),
 			pending_background_events: Mutex::new(pending_background_events),
+			funding_batch_states: Mutex::new(BTreeMap::new()),
+			anchor_channel_reserve_funds_sat: Mutex::new(None),
 			total_consistency_lock: RwLock::new(()),
 			background_events_processed_since_startup: AtomicBool::new(false),
 			persistence_notifier: Notifier::new(),
@@ -9103,8 +11775,10 @@ mod tests {
 	use bitcoin::hashes::Hash;
 	use bitcoin::hashes::sha256::Hash as Sha256;
 	use bitcoin::secp256k1::{PublicKey, Secp256k1, SecretKey};
+	use core::cell::RefCell;
 	use core::sync::atomic::Ordering;
-	use crate::events::{Event, HTLCDestination, MessageSendEvent, MessageSendEventsProvider, ClosureReason};
+	use crate::events::{Event, EventsProvider, HTLCDestination, MessageSendEvent, MessageSendEventsProvider, ClosureReason, ReplayEvent};
+	use crate::events::bump_transaction::anchor_channel_reserve_sat;
 	use crate::ln::{PaymentPreimage, PaymentHash, PaymentSecret};
 	use crate::ln::channelmanager::{inbound_payment, PaymentId, PaymentSendFailure, RecipientOnionFields, InterceptId};
 	use crate::ln::functional_test_utils::*;
@@ -9196,6 +11870,59 @@ mod tests {
 		assert_ne!(nodes[1].node.list_channels()[0], node_b_chan_info);
 	}
 
+	#[test]
+	fn test_event_handler_failure_replays_events() {
+		// Test that if the event handler fails to handle an event, that event and any events after
+		// it remain queued and are replayed on the next call to `process_pending_events`.
+		let chanmon_cfgs = create_chanmon_cfgs(2);
+		let node_cfgs = create_node_cfgs(2, &chanmon_cfgs);
+		let node_chanmgrs = create_node_chanmgrs(2, &node_cfgs, &[None, None]);
+		let nodes = create_network(2, &node_cfgs, &node_chanmgrs);
+
+		let chan_id_1 = create_announced_chan_between_nodes(&nodes, 0, 1).2;
+		let chan_id_2 = create_announced_chan_between_nodes(&nodes, 0, 1).2;
+
+		nodes[0].node.force_close_broadcasting_latest_txn(&chan_id_1, &nodes[1].node.get_our_node_id()).unwrap();
+		nodes[0].node.force_close_broadcasting_latest_txn(&chan_id_2, &nodes[1].node.get_our_node_id()).unwrap();
+		check_added_monitors(&nodes[0], 2);
+		check_closed_broadcast(&nodes[0], 2, true);
+		assert!(nodes[0].node.get_persistable_update_future().poll_is_complete());
+
+		// If the handler fails on the very first event, nothing is handled, so we shouldn't need to
+		// persist, and the event should be handed to us again on the next call.
+		let attempts = RefCell::new(0);
+		let failing_handler = |event: Event| {
+			assert!(matches!(event, Event::ChannelClosed { channel_id, .. } if channel_id == chan_id_1));
+			*attempts.borrow_mut() += 1;
+			Err(ReplayEvent())
+		};
+		nodes[0].node.process_pending_events(&failing_handler);
+		nodes[0].node.process_pending_events(&failing_handler);
+		assert_eq!(*attempts.borrow(), 2);
+		assert!(!nodes[0].node.get_persistable_update_future().poll_is_complete());
+
+		// If the handler fails part way through, only the events before the failure are removed.
+		let handled_events = RefCell::new(Vec::new());
+		let fail_second_handler = |event: Event| {
+			if handled_events.borrow().len() == 1 { return Err(ReplayEvent()); }
+			handled_events.borrow_mut().push(event);
+			Ok(())
+		};
+		nodes[0].node.process_pending_events(&fail_second_handler);
+		assert_eq!(handled_events.borrow().len(), 1);
+		assert!(nodes[0].node.get_persistable_update_future().poll_is_complete());
+
+		let events = nodes[0].node.get_and_clear_pending_events();
+		assert_eq!(events.len(), 1);
+		match events[0] {
+			Event::ChannelClosed { channel_id, reason: ClosureReason::HolderForceClosed, .. } => {
+				assert_eq!(channel_id, chan_id_2);
+			},
+			_ => panic!("Unexpected event"),
+		}
+		assert!(nodes[0].node.get_and_clear_pending_events().is_empty());
+	}
+
 	#[test]
 	fn test_keysend_dup_hash_partial_mpp() {
 		// Test that a keysend payment with a duplicate hash to an existing partial MPP payment fails as
@@ -10068,16 +12795,14 @@ mod tests {
 		let node = create_network(1, &node_cfg, &node_chanmgr);
 		let sender_intended_amt_msat = 100;
 		let extra_fee_msat = 10;
-		let hop_data = msgs::OnionHopData {
-			amt_to_forward: 100,
+		let hop_data = msgs::InboundOnionPayload::Receive {
+			amt_msat: 100,
 			outgoing_cltv_value: 42,
-			format: msgs::OnionHopDataFormat::FinalNode {
-				keysend_preimage: None,
-				payment_metadata: None,
-				payment_data: Some(msgs::FinalOnionHopData {
-					payment_secret: PaymentSecret([0; 32]), total_msat: sender_intended_amt_msat,
-				}),
-			}
+			keysend_preimage: None,
+			payment_metadata: None,
+			payment_data: Some(msgs::FinalOnionHopData {
+				payment_secret: PaymentSecret([0; 32]), total_msat: sender_intended_amt_msat,
+			}),
 		};
 		// Check that if the amount we received + the penultimate hop extra fee is less than the sender
 		// intended amount, we fail the payment.
@@ -10089,16 +12814,14 @@ mod tests {
 		} else { panic!(); }
 
 		// If amt_received + extra_fee is equal to the sender intended amount, we're fine.
-		let hop_data = msgs::OnionHopData { // This is the same hop_data as above, OnionHopData doesn't implement Clone
-			amt_to_forward: 100,
+		let hop_data = msgs::InboundOnionPayload::Receive { // This is the same hop_data as above, InboundOnionPayload doesn't implement Clone
+			amt_msat: 100,
 			outgoing_cltv_value: 42,
-			format: msgs::OnionHopDataFormat::FinalNode {
-				keysend_preimage: None,
-				payment_metadata: None,
-				payment_data: Some(msgs::FinalOnionHopData {
-					payment_secret: PaymentSecret([0; 32]), total_msat: sender_intended_amt_msat,
-				}),
-			}
+			keysend_preimage: None,
+			payment_metadata: None,
+			payment_data: Some(msgs::FinalOnionHopData {
+				payment_secret: PaymentSecret([0; 32]), total_msat: sender_intended_amt_msat,
+			}),
 		};
 		assert!(node[0].node.construct_recv_pending_htlc_info(hop_data, [0; 32], PaymentHash([0; 32]),
 			sender_intended_amt_msat - extra_fee_msat, 42, None, true, Some(extra_fee_msat)).is_ok());
@@ -10183,6 +12906,66 @@ mod tests {
 		check_closed_event!(nodes[1], 1, ClosureReason::HolderForceClosed);
 	}
 
+	#[test]
+	fn test_anchor_channel_reserve() {
+		// Tests that we refuse to open or accept anchor channels if configured to do so and we lack
+		// the on-chain funds to bump their transactions.
+		let chanmon_cfgs = create_chanmon_cfgs(2);
+		let node_cfgs = create_node_cfgs(2, &chanmon_cfgs);
+		let mut anchors_config = test_default_channel_config();
+		anchors_config.channel_handshake_config.negotiate_anchors_zero_fee_htlc_tx = true;
+		anchors_config.manually_accept_inbound_channels = true;
+		anchors_config.anchor_channel_reserve_config.refuse_channels_without_reserve = true;
+		let node_chanmgrs = create_node_chanmgrs(2, &node_cfgs, &[Some(anchors_config), Some(anchors_config)]);
+		let nodes = create_network(2, &node_cfgs, &node_chanmgrs);
+
+		let reserve_config = anchors_config.anchor_channel_reserve_config;
+		let channel_reserve_sat = anchor_channel_reserve_sat(
+			reserve_config.expected_max_htlcs_per_channel as usize, reserve_config.feerate_sat_per_1000_weight);
+		let wallet = test_utils::TestWalletSource::new(SecretKey::from_slice(&[42; 32]).unwrap());
+		wallet.add_utxo(bitcoin::OutPoint { txid: bitcoin::Txid::from_inner([42; 32]), vout: 0 }, channel_reserve_sat);
+
+		// Without any funds reported, we refuse to open anchor channels.
+		match nodes[0].node.create_channel(nodes[1].node.get_our_node_id(), 100_000, 0, 42, None) {
+			Err(APIError::ChannelUnavailable { .. }) => {},
+			_ => panic!("Unexpected result"),
+		}
+		assert!(nodes[0].node.list_channels().is_empty());
+		assert!(nodes[0].node.get_and_clear_pending_msg_events().is_empty());
+
+		assert_eq!(nodes[0].node.update_anchor_channel_reserve_funds(&wallet), Ok(channel_reserve_sat));
+		nodes[0].node.create_channel(nodes[1].node.get_our_node_id(), 100_000, 0, 42, None).unwrap();
+		assert_eq!(nodes[0].node.get_anchor_channel_reserve_sat(), channel_reserve_sat);
+		let open_channel_msg = get_event_msg!(nodes[0], MessageSendEvent::SendOpenChannel, nodes[1].node.get_our_node_id());
+		assert!(open_channel_msg.channel_type.as_ref().unwrap().supports_anchors_zero_fee_htlc_tx());
+
+		// Our funds only cover a single anchor channel, while non-anchor channels are unaffected.
+		assert!(nodes[0].node.create_channel(nodes[1].node.get_our_node_id(), 100_000, 0, 42, None).is_err());
+		let mut no_anchors_config = anchors_config;
+		no_anchors_config.channel_handshake_config.negotiate_anchors_zero_fee_htlc_tx = false;
+		nodes[0].node.create_channel(nodes[1].node.get_our_node_id(), 100_000, 0, 42, Some(no_anchors_config)).unwrap();
+		assert_eq!(nodes[0].node.get_anchor_channel_reserve_sat(), channel_reserve_sat);
+		get_event_msg!(nodes[0], MessageSendEvent::SendOpenChannel, nodes[1].node.get_our_node_id());
+
+		// An inbound anchor channel remains pending until sufficient funds are available.
+		nodes[1].node.handle_open_channel(&nodes[0].node.get_our_node_id(), &open_channel_msg);
+		let temporary_channel_id = match nodes[1].node.get_and_clear_pending_events()[0] {
+			Event::OpenChannelRequest { temporary_channel_id, .. } => temporary_channel_id,
+			_ => panic!("Unexpected event"),
+		};
+		match nodes[1].node.accept_inbound_channel(&temporary_channel_id, &nodes[0].node.get_our_node_id(), 0) {
+			Err(APIError::ChannelUnavailable { .. }) => {},
+			_ => panic!("Unexpected result"),
+		}
+		assert!(nodes[1].node.get_and_clear_pending_msg_events().is_empty());
+		assert_eq!(nodes[1].node.get_anchor_channel_reserve_sat(), 0);
+
+		nodes[1].node.update_anchor_channel_reserve_funds(&wallet).unwrap();
+		nodes[1].node.accept_inbound_channel(&temporary_channel_id, &nodes[0].node.get_our_node_id(), 0).unwrap();
+		assert_eq!(nodes[1].node.get_anchor_channel_reserve_sat(), channel_reserve_sat);
+		get_event_msg!(nodes[1], MessageSendEvent::SendAcceptChannel, nodes[0].node.get_our_node_id());
+	}
+
 	#[test]
 	fn test_update_channel_config() {
 		let chanmon_cfg = create_chanmon_cfgs(2);
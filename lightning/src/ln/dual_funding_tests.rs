@@ -0,0 +1,217 @@
+// This file is Copyright its original authors, visible in version control
+// history.
+//
+// This file is licensed under the Apache License, Version 2.0 <LICENSE-APACHE
+// or http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your option.
+// You may not use this file except in accordance with one or both of these
+// licenses.
+
+//! Tests that test the establishment of dual-funded channels, whose funding transaction is
+//! constructed interactively with inputs from both parties.
+
+use crate::events::{Event, InboundChannelFunds, MessageSendEvent, MessageSendEventsProvider};
+use crate::ln::functional_test_utils::*;
+use crate::ln::msgs::ChannelMessageHandler;
+use crate::util::errors::APIError;
+
+use bitcoin::hashes::Hash;
+use bitcoin::{OutPoint, PackedLockTime, Script, Sequence, Transaction, TxIn, TxOut, WPubkeyHash, Witness};
+
+use crate::prelude::*;
+
+fn funding_input(seed: u8, value: u64) -> (TxIn, Transaction) {
+	let prev_tx = Transaction {
+		version: 2,
+		lock_time: PackedLockTime(seed as u32),
+		input: vec![],
+		output: vec![TxOut { value, script_pubkey: Script::new_v0_p2wpkh(&WPubkeyHash::from_slice(&[seed; 20]).unwrap()) }],
+	};
+	let txin = TxIn {
+		previous_output: OutPoint { txid: prev_tx.txid(), vout: 0 },
+		sequence: Sequence::ENABLE_RBF_NO_LOCKTIME,
+		..Default::default()
+	};
+	(txin, prev_tx)
+}
+
+/// Delivers all interactive funding messages between the two nodes until neither has anything
+/// left to send.
+fn pass_interactive_funding_msgs<'a, 'b, 'c>(node_a: &Node<'a, 'b, 'c>, node_b: &Node<'a, 'b, 'c>) {
+	loop {
+		let mut delivered_msg = false;
+		for (from, to) in [(node_a, node_b), (node_b, node_a)] {
+			let from_node_id = from.node.get_our_node_id();
+			for event in from.node.get_and_clear_pending_msg_events() {
+				delivered_msg = true;
+				match event {
+					MessageSendEvent::SendTxAddInput { node_id, msg } => {
+						assert_eq!(node_id, to.node.get_our_node_id());
+						to.node.handle_tx_add_input(&from_node_id, &msg);
+					},
+					MessageSendEvent::SendTxAddOutput { node_id, msg } => {
+						assert_eq!(node_id, to.node.get_our_node_id());
+						to.node.handle_tx_add_output(&from_node_id, &msg);
+					},
+					MessageSendEvent::SendTxComplete { node_id, msg } => {
+						assert_eq!(node_id, to.node.get_our_node_id());
+						to.node.handle_tx_complete(&from_node_id, &msg);
+					},
+					MessageSendEvent::UpdateHTLCs { node_id, updates } => {
+						assert_eq!(node_id, to.node.get_our_node_id());
+						assert!(updates.update_add_htlcs.is_empty());
+						to.node.handle_commitment_signed(&from_node_id, &updates.commitment_signed);
+						check_added_monitors!(to, 1);
+					},
+					MessageSendEvent::SendTxSignatures { node_id, msg } => {
+						assert_eq!(node_id, to.node.get_our_node_id());
+						to.node.handle_tx_signatures(&from_node_id, &msg);
+					},
+					_ => panic!("Unexpected event {:?}", event),
+				}
+			}
+		}
+		if !delivered_msg { break; }
+	}
+}
+
+/// Handles the [`Event::FundingTransactionReadyForSigning`] generated by `node`, adding witnesses
+/// for the inputs spending the given previous transactions.
+fn sign_funding_transaction_inputs<'a, 'b, 'c>(node: &Node<'a, 'b, 'c>, counterparty: &Node<'a, 'b, 'c>, expected_user_channel_id: u128, prev_txs: &[&Transaction]) {
+	let events = node.node.get_and_clear_pending_events();
+	assert_eq!(events.len(), 1);
+	match events[0] {
+		Event::FundingTransactionReadyForSigning { ref channel_id, ref counterparty_node_id, user_channel_id, ref unsigned_transaction } => {
+			assert_eq!(*counterparty_node_id, counterparty.node.get_our_node_id());
+			assert_eq!(user_channel_id, expected_user_channel_id);
+			let mut signed_tx = unsigned_transaction.clone();
+			for input in signed_tx.input.iter_mut() {
+				if prev_txs.iter().any(|tx| tx.txid() == input.previous_output.txid) {
+					input.witness = Witness::from_vec(vec![vec![1; 72], vec![2; 33]]);
+				}
+			}
+			node.node.funding_transaction_signed(channel_id, counterparty_node_id, signed_tx).unwrap();
+		},
+		_ => panic!("Unexpected event"),
+	}
+}
+
+fn get_broadcast_funding_transaction<'a, 'b, 'c>(node: &Node<'a, 'b, 'c>) -> Transaction {
+	let mut txn = node.tx_broadcaster.txn_broadcasted.lock().unwrap();
+	assert_eq!(txn.len(), 1);
+	txn.remove(0)
+}
+
+#[test]
+fn test_v2_channel_establishment_with_contributions_from_both_sides() {
+	let chanmon_cfgs = create_chanmon_cfgs(2);
+	let node_cfgs = create_node_cfgs(2, &chanmon_cfgs);
+	let mut accept_config = test_default_channel_config();
+	accept_config.manually_accept_inbound_channels = true;
+	let node_chanmgrs = create_node_chanmgrs(2, &node_cfgs, &[None, Some(accept_config)]);
+	let nodes = create_network(2, &node_cfgs, &node_chanmgrs);
+
+	let (as_input, as_prev_tx) = funding_input(1, 150_000);
+	let (bs_input, bs_prev_tx) = funding_input(2, 80_000);
+
+	nodes[0].node.create_dual_funded_channel(nodes[1].node.get_our_node_id(), 100_000,
+		vec![(as_input, as_prev_tx.clone())], 42, None).unwrap();
+	let open_channel = get_event_msg!(nodes[0], MessageSendEvent::SendOpenChannelV2, nodes[1].node.get_our_node_id());
+	assert_eq!(open_channel.funding_satoshis, 100_000);
+	nodes[1].node.handle_open_channel_v2(&nodes[0].node.get_our_node_id(), &open_channel);
+
+	let events = nodes[1].node.get_and_clear_pending_events();
+	assert_eq!(events.len(), 1);
+	match events[0] {
+		Event::OpenChannelRequest { temporary_channel_id, funding_satoshis, ref channel_negotiation_type, .. } => {
+			assert_eq!(funding_satoshis, 100_000);
+			assert_eq!(*channel_negotiation_type, InboundChannelFunds::DualFunded);
+			nodes[1].node.accept_inbound_channel_with_contribution(&temporary_channel_id,
+				&nodes[0].node.get_our_node_id(), 43, 50_000, vec![(bs_input, bs_prev_tx.clone())]).unwrap();
+		},
+		_ => panic!("Unexpected event"),
+	}
+	let accept_channel = get_event_msg!(nodes[1], MessageSendEvent::SendAcceptChannelV2, nodes[0].node.get_our_node_id());
+	assert_eq!(accept_channel.funding_satoshis, 50_000);
+	nodes[0].node.handle_accept_channel_v2(&nodes[1].node.get_our_node_id(), &accept_channel);
+
+	// Negotiate the funding transaction and exchange the initial commitment signatures, after which
+	// both sides have to sign for the inputs they contributed.
+	pass_interactive_funding_msgs(&nodes[0], &nodes[1]);
+	assert_eq!(nodes[0].node.list_channels()[0].channel_value_satoshis, 150_000);
+	assert_eq!(nodes[1].node.list_channels()[0].channel_value_satoshis, 150_000);
+	sign_funding_transaction_inputs(&nodes[0], &nodes[1], 42, &[&as_prev_tx]);
+	sign_funding_transaction_inputs(&nodes[1], &nodes[0], 43, &[&bs_prev_tx]);
+	pass_interactive_funding_msgs(&nodes[0], &nodes[1]);
+
+	let funding_tx = get_broadcast_funding_transaction(&nodes[0]);
+	assert_eq!(funding_tx, get_broadcast_funding_transaction(&nodes[1]));
+	assert_eq!(funding_tx.input.len(), 2);
+	assert!(funding_tx.input.iter().all(|input| !input.witness.is_empty()));
+	expect_channel_pending_event(&nodes[0], &nodes[1].node.get_our_node_id());
+	expect_channel_pending_event(&nodes[1], &nodes[0].node.get_our_node_id());
+
+	let (channel_ready, _) = create_chan_between_nodes_with_value_confirm(&nodes[0], &nodes[1], &funding_tx);
+	let (announcement, as_update, bs_update) = create_chan_between_nodes_with_value_b(&nodes[0], &nodes[1], &channel_ready);
+	update_nodes_with_chan_announce(&nodes, 0, 1, &announcement, &as_update, &bs_update);
+
+	// Both sides start out with their contribution to the channel and may thus send payments.
+	send_payment(&nodes[0], &[&nodes[1]], 10_000_000);
+	send_payment(&nodes[1], &[&nodes[0]], 10_000_000);
+}
+
+#[test]
+fn test_v2_channel_establishment_without_acceptor_contribution() {
+	let chanmon_cfgs = create_chanmon_cfgs(2);
+	let node_cfgs = create_node_cfgs(2, &chanmon_cfgs);
+	let node_chanmgrs = create_node_chanmgrs(2, &node_cfgs, &[None, None]);
+	let nodes = create_network(2, &node_cfgs, &node_chanmgrs);
+
+	let (input, prev_tx) = funding_input(1, 150_000);
+	nodes[0].node.create_dual_funded_channel(nodes[1].node.get_our_node_id(), 100_000,
+		vec![(input, prev_tx.clone())], 42, None).unwrap();
+	let open_channel = get_event_msg!(nodes[0], MessageSendEvent::SendOpenChannelV2, nodes[1].node.get_our_node_id());
+	nodes[1].node.handle_open_channel_v2(&nodes[0].node.get_our_node_id(), &open_channel);
+
+	// Inbound channels which are accepted automatically never contribute any funds.
+	let accept_channel = get_event_msg!(nodes[1], MessageSendEvent::SendAcceptChannelV2, nodes[0].node.get_our_node_id());
+	assert_eq!(accept_channel.funding_satoshis, 0);
+	nodes[0].node.handle_accept_channel_v2(&nodes[1].node.get_our_node_id(), &accept_channel);
+
+	pass_interactive_funding_msgs(&nodes[0], &nodes[1]);
+	assert!(nodes[1].node.get_and_clear_pending_events().is_empty());
+	sign_funding_transaction_inputs(&nodes[0], &nodes[1], 42, &[&prev_tx]);
+	pass_interactive_funding_msgs(&nodes[0], &nodes[1]);
+
+	let funding_tx = get_broadcast_funding_transaction(&nodes[0]);
+	assert_eq!(funding_tx, get_broadcast_funding_transaction(&nodes[1]));
+	assert_eq!(funding_tx.input.len(), 1);
+	expect_channel_pending_event(&nodes[0], &nodes[1].node.get_our_node_id());
+	expect_channel_pending_event(&nodes[1], &nodes[0].node.get_our_node_id());
+
+	let channel = &nodes[0].node.list_channels()[0];
+	assert_eq!(channel.channel_value_satoshis, 100_000);
+	assert_eq!(channel.funding_txo.unwrap().txid, funding_tx.txid());
+	assert_eq!(nodes[1].node.list_channels()[0].channel_id, channel.channel_id);
+}
+
+#[test]
+fn test_v2_channel_establishment_requires_sufficient_inputs() {
+	let chanmon_cfgs = create_chanmon_cfgs(2);
+	let node_cfgs = create_node_cfgs(2, &chanmon_cfgs);
+	let node_chanmgrs = create_node_chanmgrs(2, &node_cfgs, &[None, None]);
+	let nodes = create_network(2, &node_cfgs, &node_chanmgrs);
+
+	// The input can't cover our contribution together with the fees for our share of the funding
+	// transaction.
+	let (input, prev_tx) = funding_input(1, 100_000);
+	match nodes[0].node.create_dual_funded_channel(nodes[1].node.get_our_node_id(), 100_000,
+		vec![(input, prev_tx)], 42, None)
+	{
+		Err(APIError::APIMisuseError { err }) =>
+			assert_eq!(err, "Provided inputs are insufficient to fund our contribution of 100000 sats"),
+		_ => panic!("Unexpected result"),
+	}
+	assert!(nodes[0].node.get_and_clear_pending_msg_events().is_empty());
+	assert!(nodes[0].node.list_channels().is_empty());
+}
@@ -35,6 +35,9 @@
 //!     (see [BOLT-2](https://github.com/lightning/bolts/blob/master/02-peer-protocol.md#the-open_channel-message) for more information).
 //! - `GossipQueries` - requires/supports more sophisticated gossip control
 //!     (see [BOLT-7](https://github.com/lightning/bolts/blob/master/07-routing-gossip.md) for more information).
+//! - `GossipQueriesEx` - requires/supports additional information in gossip queries, such as
+//!     update timestamps and checksums
+//!     (see [BOLT-7](https://github.com/lightning/bolts/blob/master/07-routing-gossip.md#query-messages) for more information).
 //! - `PaymentSecret` - requires/supports that a node supports payment_secret field
 //!     (see [BOLT-4](https://github.com/lightning/bolts/blob/master/04-onion-routing.md) for more information).
 //! - `BasicMPP` - requires/supports that a node can receive basic multi-part payments
@@ -43,6 +46,9 @@
 //!     (see [BOLT-2](https://github.com/lightning/bolts/blob/master/02-peer-protocol.md#the-open_channel-message) for more information).
 //! - `ShutdownAnySegwit` - requires/supports that future segwit versions are allowed in `shutdown`
 //!     (see [BOLT-2](https://github.com/lightning/bolts/blob/master/02-peer-protocol.md) for more information).
+//! - `DualFund` - requires/supports V2 channel establishment, in which both parties may contribute
+//!     to the funding transaction
+//!     (see [BOLT-2](https://github.com/lightning/bolts/pull/851/files) for more information).
 //! - `OnionMessages` - requires/supports forwarding onion messages
 //!     (see [BOLT-7](https://github.com/lightning/bolts/pull/759/files) for more information).
 //     TODO: update link
@@ -60,6 +66,12 @@
 //!      for more info).
 //! - `Keysend` - send funds to a node without an invoice
 //!     (see the [`Keysend` feature assignment proposal](https://github.com/lightning/bolts/issues/605#issuecomment-606679798) for more information).
+//! - `Splicing` - requires/supports splicing funds into or out of a channel without closing it
+//!     (see [BOLT-2](https://github.com/lightning/bolts/pull/863/files) for more information).
+//! - `Quiescence` - requires/supports pausing updates to a channel via the `stfu` message
+//!     (see [BOLT-2](https://github.com/lightning/bolts/pull/869/files) for more information).
+//! - `DynamicCommitments` - requires/supports upgrading the type of an existing channel, e.g. to
+//!     anchor outputs, without closing it.
 //! - `AnchorsZeroFeeHtlcTx` - requires/supports that commitment transactions include anchor outputs
 //!     and HTLC transactions are pre-signed with zero fee (see
 //!     [BOLT-3](https://github.com/lightning/bolts/blob/master/03-transactions.md) for more
@@ -139,33 +151,37 @@ mod sealed {
 		// Byte 0
 		DataLossProtect | InitialRoutingSync | UpfrontShutdownScript | GossipQueries,
 		// Byte 1
-		VariableLengthOnion | StaticRemoteKey | PaymentSecret,
+		VariableLengthOnion | GossipQueriesEx | StaticRemoteKey | PaymentSecret,
 		// Byte 2
 		BasicMPP | Wumbo | AnchorsNonzeroFeeHtlcTx | AnchorsZeroFeeHtlcTx,
 		// Byte 3
-		ShutdownAnySegwit,
+		ShutdownAnySegwit | DualFund,
 		// Byte 4
-		OnionMessages,
+		Quiescence | DynamicCommitments | OnionMessages,
 		// Byte 5
 		ChannelType | SCIDPrivacy,
 		// Byte 6
 		ZeroConf,
+		// Byte 7
+		Splicing,
 	]);
 	define_context!(NodeContext, [
 		// Byte 0
 		DataLossProtect | UpfrontShutdownScript | GossipQueries,
 		// Byte 1
-		VariableLengthOnion | StaticRemoteKey | PaymentSecret,
+		VariableLengthOnion | GossipQueriesEx | StaticRemoteKey | PaymentSecret,
 		// Byte 2
 		BasicMPP | Wumbo | AnchorsNonzeroFeeHtlcTx | AnchorsZeroFeeHtlcTx,
 		// Byte 3
-		ShutdownAnySegwit,
+		ShutdownAnySegwit | DualFund,
 		// Byte 4
-		OnionMessages,
+		Quiescence | DynamicCommitments | OnionMessages,
 		// Byte 5
 		ChannelType | SCIDPrivacy,
 		// Byte 6
 		ZeroConf | Keysend,
+		// Byte 7
+		Splicing,
 	]);
 	define_context!(ChannelContext, []);
 	define_context!(Bolt11InvoiceContext, [
@@ -373,6 +389,9 @@ mod sealed {
 		"Feature flags for `var_onion_optin`.", set_variable_length_onion_optional,
 		set_variable_length_onion_required, supports_variable_length_onion,
 		requires_variable_length_onion);
+	define_feature!(11, GossipQueriesEx, [InitContext, NodeContext],
+		"Feature flags for `gossip_queries_ex`.", set_gossip_queries_ex_optional,
+		set_gossip_queries_ex_required, supports_gossip_queries_ex, requires_gossip_queries_ex);
 	define_feature!(13, StaticRemoteKey, [InitContext, NodeContext, ChannelTypeContext],
 		"Feature flags for `option_static_remotekey`.", set_static_remote_key_optional,
 		set_static_remote_key_required, supports_static_remote_key, requires_static_remote_key);
@@ -394,6 +413,15 @@ mod sealed {
 	define_feature!(27, ShutdownAnySegwit, [InitContext, NodeContext],
 		"Feature flags for `opt_shutdown_anysegwit`.", set_shutdown_any_segwit_optional,
 		set_shutdown_any_segwit_required, supports_shutdown_anysegwit, requires_shutdown_anysegwit);
+	define_feature!(29, DualFund, [InitContext, NodeContext],
+		"Feature flags for `option_dual_fund`.", set_dual_fund_optional, set_dual_fund_required,
+		supports_dual_fund, requires_dual_fund);
+	define_feature!(35, Quiescence, [InitContext, NodeContext],
+		"Feature flags for `option_quiesce`.", set_quiescence_optional, set_quiescence_required,
+		supports_quiescence, requires_quiescence);
+	define_feature!(37, DynamicCommitments, [InitContext, NodeContext],
+		"Feature flags for `option_dyn_commitments`.", set_dynamic_commitments_optional,
+		set_dynamic_commitments_required, supports_dynamic_commitments, requires_dynamic_commitments);
 	define_feature!(39, OnionMessages, [InitContext, NodeContext],
 		"Feature flags for `option_onion_messages`.", set_onion_messages_optional,
 		set_onion_messages_required, supports_onion_messages, requires_onion_messages);
@@ -412,6 +440,9 @@ mod sealed {
 	define_feature!(55, Keysend, [NodeContext],
 		"Feature flags for keysend payments.", set_keysend_optional, set_keysend_required,
 		supports_keysend, requires_keysend);
+	define_feature!(63, Splicing, [InitContext, NodeContext],
+		"Feature flags for `option_splice`.", set_splicing_optional, set_splicing_required,
+		supports_splicing, requires_splicing);
 	// Note: update the module-level docs when a new feature bit is added!
 
 	#[cfg(test)]
@@ -368,7 +368,7 @@ pub struct NodeCfg<'a> {
 	pub override_init_features: Rc<RefCell<Option<InitFeatures>>>,
 }
 
-type TestChannelManager<'a, 'b, 'c> = ChannelManager<&'b TestChainMonitor<'c>, &'c test_utils::TestBroadcaster, &'b test_utils::TestKeysInterface, &'b test_utils::TestKeysInterface, &'b test_utils::TestKeysInterface, &'c test_utils::TestFeeEstimator, &'b test_utils::TestRouter<'c>, &'c test_utils::TestLogger>;
+pub type TestChannelManager<'a, 'b, 'c> = ChannelManager<&'b TestChainMonitor<'c>, &'c test_utils::TestBroadcaster, &'b test_utils::TestKeysInterface, &'b test_utils::TestKeysInterface, &'b test_utils::TestKeysInterface, &'c test_utils::TestFeeEstimator, &'b test_utils::TestRouter<'c>, &'c test_utils::TestLogger>;
 
 pub struct Node<'a, 'b: 'a, 'c: 'b> {
 	pub chain_source: &'c test_utils::TestChainSource,
@@ -783,6 +783,27 @@ pub fn remove_first_msg_event_to_node(msg_node_id: &PublicKey, msg_events: &mut
 		MessageSendEvent::SendTxAbort { node_id, .. } => {
 			node_id == msg_node_id
 		},
+		MessageSendEvent::SendSplice { node_id, .. } => {
+			node_id == msg_node_id
+		},
+		MessageSendEvent::SendSpliceAck { node_id, .. } => {
+			node_id == msg_node_id
+		},
+		MessageSendEvent::SendSpliceLocked { node_id, .. } => {
+			node_id == msg_node_id
+		},
+		MessageSendEvent::SendStfu { node_id, .. } => {
+			node_id == msg_node_id
+		},
+		MessageSendEvent::SendDynPropose { node_id, .. } => {
+			node_id == msg_node_id
+		},
+		MessageSendEvent::SendDynAck { node_id, .. } => {
+			node_id == msg_node_id
+		},
+		MessageSendEvent::SendDynReject { node_id, .. } => {
+			node_id == msg_node_id
+		},
 	}});
 	if ev_index.is_some() {
 		msg_events.remove(ev_index.unwrap())
@@ -1043,6 +1064,41 @@ pub fn sign_funding_transaction<'a, 'b, 'c>(node_a: &Node<'a, 'b, 'c>, node_b: &
 	tx
 }
 
+/// Opens a channel from `funding_node` to each of the given counterparties with the given channel
+/// values, funding all of them with a single batch funding transaction, which is returned along
+/// with the funding outpoint of each channel.
+pub fn create_batch_channel_funding<'a, 'b, 'c>(funding_node: &Node<'a, 'b, 'c>, counterparties: &[(&Node<'a, 'b, 'c>, u64)]) -> (Transaction, Vec<OutPoint>) {
+	let mut temporary_channels = Vec::new();
+	let mut tx_outs = Vec::new();
+	for (other_node, channel_value) in counterparties.iter() {
+		let create_chan_id = funding_node.node.create_channel(other_node.node.get_our_node_id(), *channel_value, 0, 42, None).unwrap();
+		let open_channel_msg = get_event_msg!(funding_node, MessageSendEvent::SendOpenChannel, other_node.node.get_our_node_id());
+		other_node.node.handle_open_channel(&funding_node.node.get_our_node_id(), &open_channel_msg);
+		let accept_channel_msg = get_event_msg!(other_node, MessageSendEvent::SendAcceptChannel, funding_node.node.get_our_node_id());
+		funding_node.node.handle_accept_channel(&other_node.node.get_our_node_id(), &accept_channel_msg);
+
+		let events = funding_node.node.get_and_clear_pending_events();
+		assert_eq!(events.len(), 1);
+		match events[0] {
+			Event::FundingGenerationReady { ref temporary_channel_id, ref counterparty_node_id, ref channel_value_satoshis, ref output_script, .. } => {
+				assert_eq!(*temporary_channel_id, create_chan_id);
+				assert_eq!(*counterparty_node_id, other_node.node.get_our_node_id());
+				assert_eq!(channel_value_satoshis, channel_value);
+				temporary_channels.push((create_chan_id, other_node.node.get_our_node_id()));
+				tx_outs.push(TxOut { value: *channel_value_satoshis, script_pubkey: output_script.clone() });
+			},
+			_ => panic!("Unexpected event"),
+		}
+	}
+
+	let tx = Transaction { version: 2, lock_time: PackedLockTime::ZERO, input: Vec::new(), output: tx_outs };
+	let funding_outpoints = (0..counterparties.len()).map(|index| OutPoint { txid: tx.txid(), index: index as u16 }).collect();
+	let temporary_channel_refs: Vec<_> = temporary_channels.iter().map(|(chan_id, node_id)| (chan_id, node_id)).collect();
+	funding_node.node.batch_funding_transaction_generated(&temporary_channel_refs, tx.clone()).unwrap();
+	check_added_monitors!(funding_node, 0);
+	(tx, funding_outpoints)
+}
+
 // Receiver must have been initialized with manually_accept_inbound_channels set to true.
 pub fn open_zero_conf_channel<'a, 'b, 'c, 'd>(initiator: &'a Node<'b, 'c, 'd>, receiver: &'a Node<'b, 'c, 'd>, initiator_config: Option<UserConfig>) -> (bitcoin::Transaction, [u8; 32]) {
 	let initiator_channels = initiator.node.list_usable_channels().len();
@@ -1136,7 +1192,7 @@ pub fn create_chan_between_nodes_with_value_init<'a, 'b, 'c>(node_a: &Node<'a, '
 	sign_funding_transaction(node_a, node_b, channel_value, create_chan_id)
 }
 
-pub fn create_chan_between_nodes_with_value_confirm_first<'a, 'b, 'c, 'd>(node_recv: &'a Node<'b, 'c, 'c>, node_conf: &'a Node<'b, 'c, 'd>, tx: &Transaction, conf_height: u32) {
+pub fn create_chan_between_nodes_with_value_confirm_first<'a, 'b, 'c, 'd>(node_recv: &'a Node<'b, 'c, 'd>, node_conf: &'a Node<'b, 'c, 'd>, tx: &Transaction, conf_height: u32) {
 	confirm_transaction_at(node_conf, tx, conf_height);
 	connect_blocks(node_conf, CHAN_CONFIRM_DEPTH - 1);
 	node_recv.node.handle_channel_ready(&node_conf.node.get_our_node_id(), &get_event_msg!(node_conf, MessageSendEvent::SendChannelReady, node_recv.node.get_our_node_id()));
@@ -2269,7 +2325,7 @@ pub fn do_claim_payment_along_route_with_extra_penultimate_hop_fees<'a, 'b, 'c>(
 	macro_rules! msgs_from_ev {
 		($ev: expr) => {
 			match $ev {
-				&MessageSendEvent::UpdateHTLCs { ref node_id, updates: msgs::CommitmentUpdate { ref update_add_htlcs, ref update_fulfill_htlcs, ref update_fail_htlcs, ref update_fail_malformed_htlcs, ref update_fee, ref commitment_signed } } => {
+				&MessageSendEvent::UpdateHTLCs { ref node_id, updates: msgs::CommitmentUpdate { ref update_add_htlcs, ref update_fulfill_htlcs, ref update_fail_htlcs, ref update_fail_malformed_htlcs, ref update_fee, ref commitment_signed, .. } } => {
 					assert!(update_add_htlcs.is_empty());
 					assert_eq!(update_fulfill_htlcs.len(), 1);
 					assert!(update_fail_htlcs.is_empty());
@@ -2457,7 +2513,7 @@ pub fn pass_failed_payment_back<'a, 'b, 'c>(origin_node: &Node<'a, 'b, 'c>, expe
 	assert_eq!(events.len(), expected_paths.len());
 	for ev in events.iter() {
 		let (update_fail, commitment_signed, node_id) = match ev {
-			&MessageSendEvent::UpdateHTLCs { ref node_id, updates: msgs::CommitmentUpdate { ref update_add_htlcs, ref update_fulfill_htlcs, ref update_fail_htlcs, ref update_fail_malformed_htlcs, ref update_fee, ref commitment_signed } } => {
+			&MessageSendEvent::UpdateHTLCs { ref node_id, updates: msgs::CommitmentUpdate { ref update_add_htlcs, ref update_fulfill_htlcs, ref update_fail_htlcs, ref update_fail_malformed_htlcs, ref update_fee, ref commitment_signed, .. } } => {
 				assert!(update_add_htlcs.is_empty());
 				assert!(update_fulfill_htlcs.is_empty());
 				assert_eq!(update_fail_htlcs.len(), 1);
@@ -2491,7 +2547,7 @@ pub fn pass_failed_payment_back<'a, 'b, 'c>(origin_node: &Node<'a, 'b, 'c>, expe
 			if update_next_node {
 				assert_eq!(events.len(), 1);
 				match events[0] {
-					MessageSendEvent::UpdateHTLCs { ref node_id, updates: msgs::CommitmentUpdate { ref update_add_htlcs, ref update_fulfill_htlcs, ref update_fail_htlcs, ref update_fail_malformed_htlcs, ref update_fee, ref commitment_signed } } => {
+					MessageSendEvent::UpdateHTLCs { ref node_id, updates: msgs::CommitmentUpdate { ref update_add_htlcs, ref update_fulfill_htlcs, ref update_fail_htlcs, ref update_fail_malformed_htlcs, ref update_fee, ref commitment_signed, .. } } => {
 						assert!(update_add_htlcs.is_empty());
 						assert!(update_fulfill_htlcs.is_empty());
 						assert_eq!(update_fail_htlcs.len(), 1);
@@ -614,7 +614,7 @@ fn test_update_fee_vanilla() {
 	let events_0 = nodes[0].node.get_and_clear_pending_msg_events();
 	assert_eq!(events_0.len(), 1);
 	let (update_msg, commitment_signed) = match events_0[0] {
-			MessageSendEvent::UpdateHTLCs { node_id:_, updates: msgs::CommitmentUpdate { update_add_htlcs:_, update_fulfill_htlcs:_, update_fail_htlcs:_, update_fail_malformed_htlcs:_, ref update_fee, ref commitment_signed } } => {
+			MessageSendEvent::UpdateHTLCs { node_id:_, updates: msgs::CommitmentUpdate { update_add_htlcs:_, update_fulfill_htlcs:_, update_fail_htlcs:_, update_fail_malformed_htlcs:_, ref update_fee, ref commitment_signed, .. } } => {
 			(update_fee.as_ref(), commitment_signed)
 		},
 		_ => panic!("Unexpected event"),
@@ -745,6 +745,7 @@ fn test_update_fee_that_funder_cannot_afford() {
 		channel_id: chan.2,
 		signature: res.0,
 		htlc_signatures: res.1,
+		batch: None,
 		#[cfg(taproot)]
 		partial_signature_with_nonce: None,
 	};
@@ -787,7 +788,7 @@ fn test_update_fee_with_fundee_update_add_htlc() {
 	let events_0 = nodes[0].node.get_and_clear_pending_msg_events();
 	assert_eq!(events_0.len(), 1);
 	let (update_msg, commitment_signed) = match events_0[0] {
-			MessageSendEvent::UpdateHTLCs { node_id:_, updates: msgs::CommitmentUpdate { update_add_htlcs:_, update_fulfill_htlcs:_, update_fail_htlcs:_, update_fail_malformed_htlcs:_, ref update_fee, ref commitment_signed } } => {
+			MessageSendEvent::UpdateHTLCs { node_id:_, updates: msgs::CommitmentUpdate { update_add_htlcs:_, update_fulfill_htlcs:_, update_fail_htlcs:_, update_fail_malformed_htlcs:_, ref update_fee, ref commitment_signed, .. } } => {
 			(update_fee.as_ref(), commitment_signed)
 		},
 		_ => panic!("Unexpected event"),
@@ -902,7 +903,7 @@ fn test_update_fee() {
 	let events_0 = nodes[0].node.get_and_clear_pending_msg_events();
 	assert_eq!(events_0.len(), 1);
 	let (update_msg, commitment_signed) = match events_0[0] {
-			MessageSendEvent::UpdateHTLCs { node_id:_, updates: msgs::CommitmentUpdate { update_add_htlcs:_, update_fulfill_htlcs:_, update_fail_htlcs:_, update_fail_malformed_htlcs:_, ref update_fee, ref commitment_signed } } => {
+			MessageSendEvent::UpdateHTLCs { node_id:_, updates: msgs::CommitmentUpdate { update_add_htlcs:_, update_fulfill_htlcs:_, update_fail_htlcs:_, update_fail_malformed_htlcs:_, ref update_fee, ref commitment_signed, .. } } => {
 			(update_fee.as_ref(), commitment_signed)
 		},
 		_ => panic!("Unexpected event"),
@@ -929,7 +930,7 @@ fn test_update_fee() {
 	let events_0 = nodes[0].node.get_and_clear_pending_msg_events();
 	assert_eq!(events_0.len(), 1);
 	let (update_msg, commitment_signed) = match events_0[0] {
-			MessageSendEvent::UpdateHTLCs { node_id:_, updates: msgs::CommitmentUpdate { update_add_htlcs:_, update_fulfill_htlcs:_, update_fail_htlcs:_, update_fail_malformed_htlcs:_, ref update_fee, ref commitment_signed } } => {
+			MessageSendEvent::UpdateHTLCs { node_id:_, updates: msgs::CommitmentUpdate { update_add_htlcs:_, update_fulfill_htlcs:_, update_fail_htlcs:_, update_fail_malformed_htlcs:_, ref update_fee, ref commitment_signed, .. } } => {
 			(update_fee.as_ref(), commitment_signed)
 		},
 		_ => panic!("Unexpected event"),
@@ -1396,6 +1397,7 @@ fn test_fee_spike_violation_fails_htlc() {
 		cltv_expiry: htlc_cltv,
 		onion_routing_packet: onion_packet,
 		skimmed_fee_msat: None,
+		blinding_point: None,
 	};
 
 	nodes[1].node.handle_update_add_htlc(&nodes[0].node.get_our_node_id(), &msg);
@@ -1475,6 +1477,7 @@ fn test_fee_spike_violation_fails_htlc() {
 		channel_id: chan.2,
 		signature: res.0,
 		htlc_signatures: res.1,
+		batch: None,
 		#[cfg(taproot)]
 		partial_signature_with_nonce: None,
 	};
@@ -1586,6 +1589,7 @@ fn test_chan_reserve_violation_inbound_htlc_outbound_channel() {
 		cltv_expiry: htlc_cltv,
 		onion_routing_packet: onion_packet,
 		skimmed_fee_msat: None,
+		blinding_point: None,
 	};
 
 	nodes[0].node.handle_update_add_htlc(&nodes[1].node.get_our_node_id(), &msg);
@@ -1763,6 +1767,7 @@ fn test_chan_reserve_violation_inbound_htlc_inbound_chan() {
 		cltv_expiry: htlc_cltv,
 		onion_routing_packet: onion_packet,
 		skimmed_fee_msat: None,
+		blinding_point: None,
 	};
 
 	nodes[1].node.handle_update_add_htlc(&nodes[0].node.get_our_node_id(), &msg);
@@ -3416,6 +3421,7 @@ fn fail_backward_pending_htlc_upon_channel_failure() {
 			cltv_expiry,
 			onion_routing_packet,
 			skimmed_fee_msat: None,
+			blinding_point: None,
 		};
 		nodes[0].node.handle_update_add_htlc(&nodes[1].node.get_our_node_id(), &update_add_htlc);
 	}
@@ -4009,7 +4015,7 @@ fn test_drop_messages_peer_disconnect_dual_htlc() {
 	let events_2 = nodes[1].node.get_and_clear_pending_msg_events();
 	assert_eq!(events_2.len(), 1);
 	match events_2[0] {
-		MessageSendEvent::UpdateHTLCs { ref node_id, updates: msgs::CommitmentUpdate { ref update_add_htlcs, ref update_fulfill_htlcs, ref update_fail_htlcs, ref update_fail_malformed_htlcs, ref update_fee, ref commitment_signed } } => {
+		MessageSendEvent::UpdateHTLCs { ref node_id, updates: msgs::CommitmentUpdate { ref update_add_htlcs, ref update_fulfill_htlcs, ref update_fail_htlcs, ref update_fail_malformed_htlcs, ref update_fee, ref commitment_signed, .. } } => {
 			assert_eq!(*node_id, nodes[0].node.get_our_node_id());
 			assert!(update_add_htlcs.is_empty());
 			assert_eq!(update_fulfill_htlcs.len(), 1);
@@ -6266,6 +6272,7 @@ fn test_update_add_htlc_bolt2_receiver_check_max_htlc_limit() {
 		cltv_expiry: htlc_cltv,
 		onion_routing_packet: onion_packet.clone(),
 		skimmed_fee_msat: None,
+		blinding_point: None,
 	};
 
 	for i in 0..50 {
@@ -6654,7 +6661,7 @@ fn test_update_fulfill_htlc_bolt2_after_malformed_htlc_message_must_forward_upda
 	assert_eq!(events_3.len(), 1);
 	let update_msg : (msgs::UpdateFailMalformedHTLC, msgs::CommitmentSigned) = {
 		match events_3[0] {
-			MessageSendEvent::UpdateHTLCs { node_id: _ , updates: msgs::CommitmentUpdate { ref update_add_htlcs, ref update_fulfill_htlcs, ref update_fail_htlcs, ref update_fail_malformed_htlcs, ref update_fee, ref commitment_signed } } => {
+			MessageSendEvent::UpdateHTLCs { node_id: _ , updates: msgs::CommitmentUpdate { ref update_add_htlcs, ref update_fulfill_htlcs, ref update_fail_htlcs, ref update_fail_malformed_htlcs, ref update_fee, ref commitment_signed, .. } } => {
 				assert!(update_add_htlcs.is_empty());
 				assert!(update_fulfill_htlcs.is_empty());
 				assert!(update_fail_htlcs.is_empty());
@@ -7063,7 +7070,7 @@ fn test_check_htlc_underpaying() {
 	let events = nodes[1].node.get_and_clear_pending_msg_events();
 	assert_eq!(events.len(), 1);
 	let (update_fail_htlc, commitment_signed) = match events[0] {
-		MessageSendEvent::UpdateHTLCs { node_id: _ , updates: msgs::CommitmentUpdate { ref update_add_htlcs, ref update_fulfill_htlcs, ref update_fail_htlcs, ref update_fail_malformed_htlcs, ref update_fee, ref commitment_signed } } => {
+		MessageSendEvent::UpdateHTLCs { node_id: _ , updates: msgs::CommitmentUpdate { ref update_add_htlcs, ref update_fulfill_htlcs, ref update_fail_htlcs, ref update_fail_malformed_htlcs, ref update_fee, ref commitment_signed, .. } } => {
 			assert!(update_add_htlcs.is_empty());
 			assert!(update_fulfill_htlcs.is_empty());
 			assert_eq!(update_fail_htlcs.len(), 1);
@@ -8889,7 +8896,7 @@ fn test_duplicate_chan_id() {
 		// channelmanager in a possibly nonsense state instead).
 		let mut as_chan = a_peer_state.outbound_v1_channel_by_id.remove(&open_chan_2_msg.temporary_channel_id).unwrap();
 		let logger = test_utils::TestLogger::new();
-		as_chan.get_funding_created(tx.clone(), funding_outpoint, &&logger).map_err(|_| ()).unwrap()
+		as_chan.get_funding_created(tx.clone(), funding_outpoint, false, &&logger).map_err(|_| ()).unwrap()
 	};
 	check_added_monitors!(nodes[0], 0);
 	nodes[1].node.handle_funding_created(&nodes[0].node.get_our_node_id(), &funding_created);
@@ -10105,3 +10112,182 @@ fn test_remove_expired_inbound_unfunded_channels() {
 
 	check_closed_event!(nodes[1], 1, ClosureReason::HolderForceClosed);
 }
+
+fn do_batch_funding_handshake<'a, 'b, 'c>(funding_node: &Node<'a, 'b, 'c>, other_node: &Node<'a, 'b, 'c>, msg_events: &mut Vec<MessageSendEvent>) -> msgs::FundingSigned {
+	let funding_created_msg = match remove_first_msg_event_to_node(&other_node.node.get_our_node_id(), msg_events) {
+		MessageSendEvent::SendFundingCreated { msg, .. } => msg,
+		_ => panic!("Unexpected event"),
+	};
+	other_node.node.handle_funding_created(&funding_node.node.get_our_node_id(), &funding_created_msg);
+	check_added_monitors!(other_node, 1);
+	expect_channel_pending_event(other_node, &funding_node.node.get_our_node_id());
+	get_event_msg!(other_node, MessageSendEvent::SendFundingSigned, funding_node.node.get_our_node_id())
+}
+
+#[test]
+fn test_batch_channel_open() {
+	let chanmon_cfgs = create_chanmon_cfgs(3);
+	let node_cfgs = create_node_cfgs(3, &chanmon_cfgs);
+	let node_chanmgrs = create_node_chanmgrs(3, &node_cfgs, &[None, None, None]);
+	let nodes = create_network(3, &node_cfgs, &node_chanmgrs);
+
+	let (tx, funding_outpoints) = create_batch_channel_funding(&nodes[0], &[(&nodes[1], 100_000), (&nodes[2], 200_000)]);
+	assert_eq!(tx.output.len(), 2);
+
+	let mut msg_events = nodes[0].node.get_and_clear_pending_msg_events();
+	assert_eq!(msg_events.len(), 2);
+	let funding_signed_1 = do_batch_funding_handshake(&nodes[0], &nodes[1], &mut msg_events);
+	let funding_signed_2 = do_batch_funding_handshake(&nodes[0], &nodes[2], &mut msg_events);
+
+	// Once the first counterparty signs, we still can't broadcast the funding transaction as the
+	// second channel in the batch is not yet ready.
+	nodes[0].node.handle_funding_signed(&nodes[1].node.get_our_node_id(), &funding_signed_1);
+	check_added_monitors!(nodes[0], 1);
+	assert!(nodes[0].tx_broadcaster.txn_broadcasted.lock().unwrap().is_empty());
+	assert!(nodes[0].node.get_and_clear_pending_events().is_empty());
+	assert!(nodes[0].node.list_channels().iter().all(|chan| !chan.is_channel_ready));
+
+	// Once the second one does too, the funding transaction is broadcast exactly once.
+	nodes[0].node.handle_funding_signed(&nodes[2].node.get_our_node_id(), &funding_signed_2);
+	check_added_monitors!(nodes[0], 1);
+	{
+		let broadcasted = nodes[0].tx_broadcaster.txn_broadcasted.lock().unwrap();
+		assert_eq!(broadcasted.len(), 1);
+		assert_eq!(broadcasted[0], tx);
+	}
+
+	let events = nodes[0].node.get_and_clear_pending_events();
+	assert_eq!(events.len(), 2);
+	for event in events.iter() {
+		match event {
+			Event::ChannelPending { funding_txo, .. } => {
+				assert!(funding_outpoints.iter().any(|outpoint| outpoint.into_bitcoin_outpoint() == *funding_txo));
+			},
+			_ => panic!("Unexpected event"),
+		}
+	}
+
+	// The channels then confirm as usual.
+	mine_transaction(&nodes[0], &tx);
+	mine_transaction(&nodes[1], &tx);
+	mine_transaction(&nodes[2], &tx);
+	connect_blocks(&nodes[0], CHAN_CONFIRM_DEPTH - 1);
+	connect_blocks(&nodes[1], CHAN_CONFIRM_DEPTH - 1);
+	connect_blocks(&nodes[2], CHAN_CONFIRM_DEPTH - 1);
+	let events = nodes[0].node.get_and_clear_pending_msg_events();
+	assert_eq!(events.len(), 2);
+	for event in events.iter() {
+		match event {
+			MessageSendEvent::SendChannelReady { msg, .. } => {
+				assert!(funding_outpoints.iter().any(|outpoint| outpoint.to_channel_id() == msg.channel_id));
+			},
+			_ => panic!("Unexpected event"),
+		}
+	}
+	get_event_msg!(nodes[1], MessageSendEvent::SendChannelReady, nodes[0].node.get_our_node_id());
+	get_event_msg!(nodes[2], MessageSendEvent::SendChannelReady, nodes[0].node.get_our_node_id());
+}
+
+#[test]
+fn test_batch_funding_misuse() {
+	let chanmon_cfgs = create_chanmon_cfgs(3);
+	let node_cfgs = create_node_cfgs(3, &chanmon_cfgs);
+	let node_chanmgrs = create_node_chanmgrs(3, &node_cfgs, &[None, None, None]);
+	let nodes = create_network(3, &node_cfgs, &node_chanmgrs);
+
+	let temp_chan_id = nodes[0].node.create_channel(nodes[1].node.get_our_node_id(), 100_000, 0, 42, None).unwrap();
+	let open_channel_msg = get_event_msg!(nodes[0], MessageSendEvent::SendOpenChannel, nodes[1].node.get_our_node_id());
+	nodes[1].node.handle_open_channel(&nodes[0].node.get_our_node_id(), &open_channel_msg);
+	let accept_channel_msg = get_event_msg!(nodes[1], MessageSendEvent::SendAcceptChannel, nodes[0].node.get_our_node_id());
+	nodes[0].node.handle_accept_channel(&nodes[1].node.get_our_node_id(), &accept_channel_msg);
+	let (_, tx, _) = create_funding_transaction(&nodes[0], &nodes[1].node.get_our_node_id(), 100_000, 42);
+
+	let node_id_1 = nodes[1].node.get_our_node_id();
+	let node_id_2 = nodes[2].node.get_our_node_id();
+	match nodes[0].node.batch_funding_transaction_generated(&[], tx.clone()) {
+		Err(APIError::APIMisuseError { .. }) => {},
+		_ => panic!("Unexpected result"),
+	}
+	match nodes[0].node.batch_funding_transaction_generated(&[(&temp_chan_id, &node_id_1), (&temp_chan_id, &node_id_1)], tx.clone()) {
+		Err(APIError::APIMisuseError { .. }) => {},
+		_ => panic!("Unexpected result"),
+	}
+	// If any of the channels can't be funded, none of them are.
+	match nodes[0].node.batch_funding_transaction_generated(&[(&temp_chan_id, &node_id_1), (&[42; 32], &node_id_2)], tx.clone()) {
+		Err(APIError::ChannelUnavailable { .. }) => {},
+		_ => panic!("Unexpected result"),
+	}
+	assert!(nodes[0].node.get_and_clear_pending_msg_events().is_empty());
+	assert!(nodes[0].node.get_and_clear_pending_events().is_empty());
+
+	// The channel can still be funded on its own afterwards.
+	nodes[0].node.funding_transaction_generated(&temp_chan_id, &node_id_1, tx).unwrap();
+	get_event_msg!(nodes[0], MessageSendEvent::SendFundingCreated, node_id_1);
+}
+
+#[test]
+fn test_batch_channel_open_fails_all_on_close() {
+	let chanmon_cfgs = create_chanmon_cfgs(3);
+	let node_cfgs = create_node_cfgs(3, &chanmon_cfgs);
+	let node_chanmgrs = create_node_chanmgrs(3, &node_cfgs, &[None, None, None]);
+	let nodes = create_network(3, &node_cfgs, &node_chanmgrs);
+
+	let (tx, funding_outpoints) = create_batch_channel_funding(&nodes[0], &[(&nodes[1], 100_000), (&nodes[2], 200_000)]);
+	let mut msg_events = nodes[0].node.get_and_clear_pending_msg_events();
+	assert_eq!(msg_events.len(), 2);
+	let funding_signed_1 = do_batch_funding_handshake(&nodes[0], &nodes[1], &mut msg_events);
+	nodes[0].node.handle_funding_signed(&nodes[1].node.get_our_node_id(), &funding_signed_1);
+	check_added_monitors!(nodes[0], 1);
+
+	// The second counterparty aborts the channel before sending its funding_signed, which fails
+	// the whole batch, including the channel which is already awaiting broadcast.
+	nodes[0].node.handle_error(&nodes[2].node.get_our_node_id(), &msgs::ErrorMessage {
+		channel_id: funding_outpoints[1].to_channel_id(), data: "Abort".to_owned()
+	});
+	// The closed channel in the batch had its monitor, which must not broadcast, closed.
+	check_added_monitors!(nodes[0], 1);
+	{
+		let monitor_updates = nodes[0].chain_monitor.monitor_updates.lock().unwrap();
+		let updates = monitor_updates.get(&funding_outpoints[0].to_channel_id()).unwrap();
+		match updates.last().unwrap().updates[0] {
+			channelmonitor::ChannelMonitorUpdateStep::ChannelForceClosed { should_broadcast } => assert!(!should_broadcast),
+			_ => panic!("Unexpected update"),
+		}
+	}
+	assert!(nodes[0].tx_broadcaster.txn_broadcasted.lock().unwrap().is_empty());
+	assert!(nodes[0].node.list_channels().is_empty());
+
+	let events = nodes[0].node.get_and_clear_pending_events();
+	assert_eq!(events.len(), 3);
+	let mut discarded_funding = false;
+	for event in events {
+		match event {
+			Event::ChannelClosed { channel_id, reason, .. } => {
+				if channel_id == funding_outpoints[0].to_channel_id() {
+					assert_eq!(reason, ClosureReason::FundingBatchClosure);
+				} else {
+					assert_eq!(channel_id, funding_outpoints[1].to_channel_id());
+					assert_eq!(reason, ClosureReason::CounterpartyForceClosed { peer_msg: UntrustedString("Abort".to_owned()) });
+				}
+			},
+			Event::DiscardFunding { transaction, .. } => {
+				assert!(!discarded_funding);
+				assert_eq!(transaction, tx);
+				discarded_funding = true;
+			},
+			_ => panic!("Unexpected event"),
+		}
+	}
+	assert!(discarded_funding);
+
+	// The counterparty of the channel which was already signed is told it has been closed.
+	let msg_events = nodes[0].node.get_and_clear_pending_msg_events();
+	assert_eq!(msg_events.len(), 1);
+	match msg_events[0] {
+		MessageSendEvent::HandleError { ref node_id, action: ErrorAction::SendErrorMessage { ref msg } } => {
+			assert_eq!(*node_id, nodes[1].node.get_our_node_id());
+			assert_eq!(msg.channel_id, funding_outpoints[0].to_channel_id());
+		},
+		_ => panic!("Unexpected event"),
+	}
+}
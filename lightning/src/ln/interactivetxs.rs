@@ -0,0 +1,1397 @@
+// This file is Copyright its original authors, visible in version control
+// history.
+//
+// This file is licensed under the Apache License, Version 2.0 <LICENSE-APACHE
+// or http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your option.
+// You may not use this file except in accordance with one or both of these
+// licenses.
+
+//! Interactive transaction construction, as used to collaboratively build the funding transaction
+//! of a dual-funded channel, or the new funding transaction of a spliced channel, with our
+//! counterparty.
+//!
+//! The protocol is a simple turn-based negotiation in which each side adds or removes inputs and
+//! outputs (identified by a "serial id") until both sides send consecutive `tx_complete` messages,
+//! after which the resulting transaction is validated and handed off for signing.
+
+use core::ops::Deref;
+
+use bitcoin::blockdata::constants::WITNESS_SCALE_FACTOR;
+use bitcoin::consensus::Encodable;
+use bitcoin::{EcdsaSighashType, OutPoint, PackedLockTime, Script, Sequence, Transaction, TxIn, TxOut, Witness};
+use bitcoin::secp256k1::PublicKey;
+use bitcoin::secp256k1::ecdsa::Signature;
+
+use crate::chain::chaininterface::fee_for_weight;
+use crate::events::MessageSendEvent;
+use crate::io_extras::sink;
+use crate::ln::channel::TOTAL_BITCOIN_SUPPLY_SATOSHIS;
+use crate::ln::msgs;
+use crate::prelude::*;
+use crate::sign::EntropySource;
+use crate::util::ser::TransactionU16LenLimited;
+
+/// The number of received `tx_add_input` messages during a negotiation at which point the
+/// negotiation MUST be failed.
+const MAX_RECEIVED_TX_ADD_INPUT_COUNT: u16 = 4096;
+
+/// The number of received `tx_add_output` messages during a negotiation at which point the
+/// negotiation MUST be failed.
+const MAX_RECEIVED_TX_ADD_OUTPUT_COUNT: u16 = 4096;
+
+/// The number of inputs or outputs that the state machine can have, before it MUST fail the
+/// negotiation.
+const MAX_INPUTS_OUTPUTS_COUNT: usize = 252;
+
+/// The maximum weight of a transaction which is still considered standard by Bitcoin Core.
+const MAX_STANDARD_TX_WEIGHT: u64 = 400_000;
+
+/// The weight of the fields common to every transaction (version, input count, output count,
+/// locktime) plus the segwit marker and flag, paid for by the initiator.
+pub(crate) const TX_COMMON_FIELDS_WEIGHT: u64 =
+	(4 /* version */ + 4 /* locktime */ + 1 /* input count */ + 1 /* output count */) *
+		WITNESS_SCALE_FACTOR as u64 + 2 /* segwit marker + flag */;
+
+/// The weight of an input's non-witness data, assuming an empty `script_sig`.
+pub(crate) const BASE_INPUT_WEIGHT: u64 =
+	(32 /* txid */ + 4 /* vout */ + 4 /* sequence */ + 1 /* empty script_sig */) *
+		WITNESS_SCALE_FACTOR as u64;
+
+/// The weight of the witness spending a P2WPKH output.
+pub(crate) const P2WPKH_WITNESS_WEIGHT: u64 = 1 /* num stack items */ +
+	1 /* sig length */ + 73 /* sig including sighash flag */ +
+	1 /* pubkey length */ + 33 /* pubkey */;
+
+/// The weight of the witness spending a P2TR output via the key path.
+pub(crate) const P2TR_KEY_PATH_WITNESS_WEIGHT: u64 = 1 /* num stack items */ +
+	1 /* sig length */ + 64 /* sig */;
+
+/// The weight of the witness spending a channel's 2-of-2 multisig funding output.
+pub(crate) const FUNDING_INPUT_WITNESS_WEIGHT: u64 = 1 /* num stack items */ +
+	1 /* empty item for OP_CHECKMULTISIG */ + 2 * (1 /* sig length */ + 73 /* sig including sighash flag */) +
+	1 /* witness script length */ + 71 /* witness script */;
+
+/// A unique identifier for an input or output, chosen by the party which added it. Serial ids
+/// added by the initiator are even, those added by the non-initiator are odd.
+pub(crate) type SerialId = u64;
+
+/// The serial id of the shared input of a splice. As both parties already know about it, it is
+/// never explicitly added, and its serial id is reserved so that it always comes first.
+const SHARED_INPUT_SERIAL_ID: SerialId = 0;
+
+trait SerialIdExt {
+	fn is_for_initiator(&self) -> bool;
+	fn is_for_non_initiator(&self) -> bool;
+}
+
+impl SerialIdExt for SerialId {
+	fn is_for_initiator(&self) -> bool { self % 2 == 0 }
+	fn is_for_non_initiator(&self) -> bool { !self.is_for_initiator() }
+}
+
+/// The reason an interactive transaction negotiation was aborted.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) enum AbortReason {
+	/// A message was received which is not valid at the current point in the negotiation.
+	UnexpectedCounterpartyMessage,
+	/// The counterparty sent more `tx_add_input` messages than allowed.
+	ReceivedTooManyTxAddInputs,
+	/// The counterparty sent more `tx_add_output` messages than allowed.
+	ReceivedTooManyTxAddOutputs,
+	/// An input's sequence number would signal finality or disable relative locktimes.
+	IncorrectInputSequenceValue,
+	/// A serial id had the parity reserved for the other party.
+	IncorrectSerialIdParity,
+	/// A removal referenced a serial id which was never added.
+	SerialIdUnknown,
+	/// A serial id was added twice.
+	DuplicateSerialId,
+	/// The previous output referenced by an input is missing, non-segwit or already spent by
+	/// another input.
+	PrevTxOutInvalid,
+	/// An input's or output's value, or the total value of the inputs or outputs, exceeded the
+	/// total bitcoin supply.
+	ExceededMaximumSatsAllowed,
+	/// The transaction has too many inputs or outputs.
+	ExceededNumberOfInputsOrOutputs,
+	/// The transaction's estimated weight exceeds the standardness limit.
+	TransactionTooLarge,
+	/// An output's value is below its dust limit.
+	BelowDustLimit,
+	/// An output's script is not a standard witness program.
+	InvalidOutputScript,
+	/// The counterparty's contributions do not pay for their share of the fee.
+	InsufficientFees,
+	/// The value of the outputs exceeds the value of the inputs.
+	OutputsValueExceedsInputsValue,
+	/// The negotiated transaction does not contain the shared funding output.
+	MissingFundingOutput,
+	/// The negotiated transaction contains the shared funding output more than once.
+	DuplicateFundingOutput,
+	/// The counterparty aborted the negotiation by sending a `tx_abort`.
+	CounterpartyAborted,
+}
+
+impl AbortReason {
+	/// Builds the `tx_abort` message we send to our counterparty to abort the negotiation.
+	pub fn into_tx_abort_msg(self, channel_id: [u8; 32]) -> msgs::TxAbort {
+		msgs::TxAbort { channel_id, data: self.to_string().into_bytes() }
+	}
+}
+
+impl core::fmt::Display for AbortReason {
+	fn fmt(&self, f: &mut core::fmt::Formatter) -> core::fmt::Result {
+		f.write_str(match self {
+			AbortReason::UnexpectedCounterpartyMessage => "Unexpected message",
+			AbortReason::ReceivedTooManyTxAddInputs => "Too many tx_add_input messages",
+			AbortReason::ReceivedTooManyTxAddOutputs => "Too many tx_add_output messages",
+			AbortReason::IncorrectInputSequenceValue => "Input has a sequence value greater than 0xFFFFFFFD",
+			AbortReason::IncorrectSerialIdParity => "Parity for serial_id was incorrect",
+			AbortReason::SerialIdUnknown => "The serial_id is unknown",
+			AbortReason::DuplicateSerialId => "The serial_id already exists",
+			AbortReason::PrevTxOutInvalid => "Invalid previous transaction output",
+			AbortReason::ExceededMaximumSatsAllowed => "Amount exceeded total bitcoin supply",
+			AbortReason::ExceededNumberOfInputsOrOutputs => "Too many inputs or outputs",
+			AbortReason::TransactionTooLarge => "Transaction weight is too large",
+			AbortReason::BelowDustLimit => "Output amount is below the dust limit",
+			AbortReason::InvalidOutputScript => "The output script is non-standard",
+			AbortReason::InsufficientFees => "Insufficient fees paid",
+			AbortReason::OutputsValueExceedsInputsValue => "Total value of outputs exceeds total value of inputs",
+			AbortReason::MissingFundingOutput => "No shared funding output found",
+			AbortReason::DuplicateFundingOutput => "More than one funding output found",
+			AbortReason::CounterpartyAborted => "Counterparty aborted the negotiation",
+		})
+	}
+}
+
+/// The shared output of a negotiated transaction, i.e. the funding output of a channel.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub(crate) struct SharedOutput {
+	/// The output's script and total value.
+	pub tx_out: TxOut,
+	/// The portion of the output's value contributed by us.
+	pub holder_value: u64,
+}
+
+/// The shared input of a negotiated transaction, i.e. the funding output of a channel being
+/// spliced.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub(crate) struct SharedInput {
+	/// The funding outpoint being spent.
+	pub outpoint: OutPoint,
+	/// The funding output being spent.
+	pub prev_output: TxOut,
+	/// The portion of the output's value which belongs to us.
+	pub holder_value: u64,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+struct InteractiveTxInput {
+	tx_in: TxIn,
+	prev_output: TxOut,
+}
+
+impl InteractiveTxInput {
+	fn estimated_weight(&self) -> u64 {
+		BASE_INPUT_WEIGHT + estimate_input_witness_weight(&self.prev_output.script_pubkey)
+	}
+}
+
+/// Estimates the witness weight required to spend an output with the given script. Only P2WPKH and
+/// P2TR key-path spends can be estimated, other witness programs are assumed to carry no witness.
+fn estimate_input_witness_weight(prev_script_pubkey: &Script) -> u64 {
+	if prev_script_pubkey.is_v0_p2wpkh() {
+		P2WPKH_WITNESS_WEIGHT
+	} else if prev_script_pubkey.is_v1_p2tr() {
+		P2TR_KEY_PATH_WITNESS_WEIGHT
+	} else {
+		0
+	}
+}
+
+fn output_weight(tx_out: &TxOut) -> u64 {
+	tx_out.consensus_encode(&mut sink()).unwrap() as u64 * WITNESS_SCALE_FACTOR as u64
+}
+
+/// Estimates the weight a party's contribution adds to an interactively constructed transaction,
+/// which that party must pay fees for. The initiator also pays for the common transaction fields
+/// and, when splicing, the shared input.
+pub(crate) fn estimate_contribution_weight(
+	is_initiator: bool, spends_shared_input: bool, inputs: &[(TxIn, TransactionU16LenLimited)],
+	outputs: &[TxOut],
+) -> u64 {
+	let inputs_weight = inputs.iter().map(|(input, prevtx)| {
+		let witness_weight = prevtx.as_transaction().output.get(input.previous_output.vout as usize)
+			.map(|prev_output| estimate_input_witness_weight(&prev_output.script_pubkey))
+			.unwrap_or(0);
+		BASE_INPUT_WEIGHT + witness_weight
+	}).sum::<u64>();
+	let outputs_weight = outputs.iter().map(output_weight).sum::<u64>();
+	let initiator_weight = if !is_initiator {
+		0
+	} else if spends_shared_input {
+		TX_COMMON_FIELDS_WEIGHT + BASE_INPUT_WEIGHT + FUNDING_INPUT_WITNESS_WEIGHT
+	} else {
+		TX_COMMON_FIELDS_WEIGHT
+	};
+	inputs_weight + outputs_weight + initiator_weight
+}
+
+/// A transaction negotiated through interactive transaction construction which is ready to be
+/// signed.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub(crate) struct ConstructedTransaction {
+	/// The negotiated, unsigned transaction.
+	pub tx: Transaction,
+	/// The indices of the inputs in [`Self::tx`] which were contributed by us and thus which we
+	/// must provide witnesses for.
+	pub holder_input_indices: Vec<u16>,
+	/// The index of the shared input in [`Self::tx`] when splicing, which both parties sign.
+	pub shared_input_index: Option<u16>,
+	/// The total value of the inputs we contributed.
+	pub holder_inputs_value: u64,
+	/// The total value of the inputs our counterparty contributed.
+	pub counterparty_inputs_value: u64,
+	/// The scripts of the outputs spent by each input of [`Self::tx`], in order.
+	pub prev_output_scripts: Vec<Script>,
+}
+
+/// The inputs and outputs added by both parties so far, along with the parameters of the
+/// negotiation required to validate them.
+#[derive(Debug)]
+struct NegotiationContext {
+	holder_is_initiator: bool,
+	received_tx_add_input_count: u16,
+	received_tx_add_output_count: u16,
+	inputs: HashMap<SerialId, InteractiveTxInput>,
+	prevtx_outpoints: HashSet<OutPoint>,
+	outputs: HashMap<SerialId, TxOut>,
+	shared_output: SharedOutput,
+	shared_input: Option<SharedInput>,
+	tx_locktime: PackedLockTime,
+	feerate_sat_per_kw: u32,
+}
+
+impl NegotiationContext {
+	fn is_serial_id_valid_for_counterparty(&self, serial_id: &SerialId) -> bool {
+		// A received `SerialId`'s parity must match the role of the counterparty.
+		self.holder_is_initiator == serial_id.is_for_non_initiator()
+	}
+
+	fn is_holder_serial_id(&self, serial_id: &SerialId) -> bool {
+		self.holder_is_initiator == serial_id.is_for_initiator()
+	}
+
+	fn is_shared_input_serial_id(&self, serial_id: &SerialId) -> bool {
+		self.shared_input.is_some() && *serial_id == SHARED_INPUT_SERIAL_ID
+	}
+
+	fn receive_tx_add_input(&mut self, msg: &msgs::TxAddInput) -> Result<(), AbortReason> {
+		if !self.is_serial_id_valid_for_counterparty(&msg.serial_id) {
+			return Err(AbortReason::IncorrectSerialIdParity);
+		}
+		self.received_tx_add_input_count += 1;
+		if self.received_tx_add_input_count > MAX_RECEIVED_TX_ADD_INPUT_COUNT {
+			return Err(AbortReason::ReceivedTooManyTxAddInputs);
+		}
+		if msg.sequence >= 0xFFFFFFFE {
+			return Err(AbortReason::IncorrectInputSequenceValue);
+		}
+		let prevtx = msg.prevtx.as_transaction();
+		let prev_output = match prevtx.output.get(msg.prevtx_out as usize) {
+			Some(tx_out) if tx_out.script_pubkey.is_witness_program() => tx_out.clone(),
+			_ => return Err(AbortReason::PrevTxOutInvalid),
+		};
+		if prev_output.value > TOTAL_BITCOIN_SUPPLY_SATOSHIS {
+			return Err(AbortReason::ExceededMaximumSatsAllowed);
+		}
+		if self.inputs.contains_key(&msg.serial_id) {
+			return Err(AbortReason::DuplicateSerialId);
+		}
+		let previous_output = OutPoint { txid: prevtx.txid(), vout: msg.prevtx_out };
+		if !self.prevtx_outpoints.insert(previous_output) {
+			return Err(AbortReason::PrevTxOutInvalid);
+		}
+		self.inputs.insert(msg.serial_id, InteractiveTxInput {
+			tx_in: TxIn {
+				previous_output,
+				sequence: Sequence(msg.sequence),
+				..Default::default()
+			},
+			prev_output,
+		});
+		Ok(())
+	}
+
+	fn receive_tx_remove_input(&mut self, msg: &msgs::TxRemoveInput) -> Result<(), AbortReason> {
+		if !self.is_serial_id_valid_for_counterparty(&msg.serial_id) {
+			return Err(AbortReason::IncorrectSerialIdParity);
+		}
+		if self.is_shared_input_serial_id(&msg.serial_id) {
+			// The shared input was never added by our counterparty, so it can't be removed either.
+			return Err(AbortReason::SerialIdUnknown);
+		}
+		match self.inputs.remove(&msg.serial_id) {
+			Some(input) => {
+				self.prevtx_outpoints.remove(&input.tx_in.previous_output);
+				Ok(())
+			},
+			None => Err(AbortReason::SerialIdUnknown),
+		}
+	}
+
+	fn receive_tx_add_output(&mut self, msg: &msgs::TxAddOutput) -> Result<(), AbortReason> {
+		if !self.is_serial_id_valid_for_counterparty(&msg.serial_id) {
+			return Err(AbortReason::IncorrectSerialIdParity);
+		}
+		self.received_tx_add_output_count += 1;
+		if self.received_tx_add_output_count > MAX_RECEIVED_TX_ADD_OUTPUT_COUNT {
+			return Err(AbortReason::ReceivedTooManyTxAddOutputs);
+		}
+		if msg.sats > TOTAL_BITCOIN_SUPPLY_SATOSHIS {
+			return Err(AbortReason::ExceededMaximumSatsAllowed);
+		}
+		if !(msg.script.is_v0_p2wpkh() || msg.script.is_v0_p2wsh() || msg.script.is_v1_p2tr()) {
+			return Err(AbortReason::InvalidOutputScript);
+		}
+		if msg.sats < msg.script.dust_value().to_sat() {
+			return Err(AbortReason::BelowDustLimit);
+		}
+		if self.outputs.contains_key(&msg.serial_id) {
+			return Err(AbortReason::DuplicateSerialId);
+		}
+		self.outputs.insert(msg.serial_id, TxOut { value: msg.sats, script_pubkey: msg.script.clone() });
+		Ok(())
+	}
+
+	fn receive_tx_remove_output(&mut self, msg: &msgs::TxRemoveOutput) -> Result<(), AbortReason> {
+		if !self.is_serial_id_valid_for_counterparty(&msg.serial_id) {
+			return Err(AbortReason::IncorrectSerialIdParity);
+		}
+		match self.outputs.remove(&msg.serial_id) {
+			Some(_) => Ok(()),
+			None => Err(AbortReason::SerialIdUnknown),
+		}
+	}
+
+	fn send_tx_add_input(&mut self, msg: &msgs::TxAddInput) {
+		let prevtx = msg.prevtx.as_transaction();
+		let previous_output = OutPoint { txid: prevtx.txid(), vout: msg.prevtx_out };
+		self.prevtx_outpoints.insert(previous_output);
+		self.inputs.insert(msg.serial_id, InteractiveTxInput {
+			tx_in: TxIn {
+				previous_output,
+				sequence: Sequence(msg.sequence),
+				..Default::default()
+			},
+			prev_output: prevtx.output[msg.prevtx_out as usize].clone(),
+		});
+	}
+
+	fn send_tx_add_output(&mut self, msg: &msgs::TxAddOutput) {
+		self.outputs.insert(msg.serial_id, TxOut { value: msg.sats, script_pubkey: msg.script.clone() });
+	}
+
+	/// Checks the negotiated inputs and outputs against the protocol's rules, returning the
+	/// resulting transaction if they are valid.
+	fn validate_tx(self) -> Result<ConstructedTransaction, AbortReason> {
+		if self.inputs.len() > MAX_INPUTS_OUTPUTS_COUNT || self.outputs.len() > MAX_INPUTS_OUTPUTS_COUNT {
+			return Err(AbortReason::ExceededNumberOfInputsOrOutputs);
+		}
+
+		let mut shared_output_serial_id = None;
+		for (serial_id, output) in self.outputs.iter() {
+			if output.script_pubkey == self.shared_output.tx_out.script_pubkey {
+				if shared_output_serial_id.is_some() {
+					return Err(AbortReason::DuplicateFundingOutput);
+				}
+				if output.value != self.shared_output.tx_out.value {
+					return Err(AbortReason::MissingFundingOutput);
+				}
+				shared_output_serial_id = Some(*serial_id);
+			}
+		}
+		let shared_output_serial_id = shared_output_serial_id.ok_or(AbortReason::MissingFundingOutput)?;
+
+		// While each value was checked to be within the total supply on receipt, there may be
+		// enough of them to overflow a running total.
+		fn add_sats(total: u64, value: u64) -> Result<u64, AbortReason> {
+			total.checked_add(value).filter(|sum| *sum <= TOTAL_BITCOIN_SUPPLY_SATOSHIS)
+				.ok_or(AbortReason::ExceededMaximumSatsAllowed)
+		}
+
+		let mut holder_inputs_value = 0;
+		let mut counterparty_inputs_value = 0;
+		let mut counterparty_weight = 0;
+		let mut total_weight = TX_COMMON_FIELDS_WEIGHT;
+		for (serial_id, input) in self.inputs.iter() {
+			if self.is_shared_input_serial_id(serial_id) {
+				continue;
+			}
+			let weight = input.estimated_weight();
+			total_weight += weight;
+			if self.is_holder_serial_id(serial_id) {
+				holder_inputs_value = add_sats(holder_inputs_value, input.prev_output.value)?;
+			} else {
+				counterparty_inputs_value = add_sats(counterparty_inputs_value, input.prev_output.value)?;
+				counterparty_weight += weight;
+			}
+		}
+
+		let mut counterparty_outputs_value = 0;
+		let mut total_outputs_value = 0;
+		for (serial_id, output) in self.outputs.iter() {
+			total_weight += output_weight(output);
+			total_outputs_value = add_sats(total_outputs_value, output.value)?;
+			if *serial_id == shared_output_serial_id {
+				continue;
+			}
+			if !self.is_holder_serial_id(serial_id) {
+				counterparty_outputs_value = add_sats(counterparty_outputs_value, output.value)?;
+				counterparty_weight += output_weight(output);
+			}
+		}
+		// Whoever added it, the shared output is split by each party's contribution, while its
+		// weight, along with the common transaction fields, is paid for by the initiator.
+		counterparty_outputs_value = add_sats(counterparty_outputs_value,
+			self.shared_output.tx_out.value.saturating_sub(self.shared_output.holder_value))?;
+		if !self.holder_is_initiator {
+			counterparty_weight += TX_COMMON_FIELDS_WEIGHT + output_weight(&self.shared_output.tx_out);
+		}
+		// Similarly, the shared input of a splice is split by each party's balance in the channel
+		// being spliced, with its weight paid for by the initiator.
+		if let Some(shared_input) = &self.shared_input {
+			let weight = BASE_INPUT_WEIGHT + FUNDING_INPUT_WITNESS_WEIGHT;
+			total_weight += weight;
+			holder_inputs_value = add_sats(holder_inputs_value, shared_input.holder_value)?;
+			counterparty_inputs_value = add_sats(counterparty_inputs_value,
+				shared_input.prev_output.value.saturating_sub(shared_input.holder_value))?;
+			if !self.holder_is_initiator {
+				counterparty_weight += weight;
+			}
+		}
+
+		if total_outputs_value > add_sats(holder_inputs_value, counterparty_inputs_value)? {
+			return Err(AbortReason::OutputsValueExceedsInputsValue);
+		}
+		if total_weight > MAX_STANDARD_TX_WEIGHT {
+			return Err(AbortReason::TransactionTooLarge);
+		}
+		let counterparty_fees = counterparty_inputs_value.checked_sub(counterparty_outputs_value)
+			.ok_or(AbortReason::InsufficientFees)?;
+		if counterparty_fees < fee_for_weight(self.feerate_sat_per_kw, counterparty_weight) {
+			return Err(AbortReason::InsufficientFees);
+		}
+
+		let mut inputs = self.inputs.into_iter().collect::<Vec<_>>();
+		inputs.sort_unstable_by_key(|(serial_id, _)| *serial_id);
+		let mut outputs = self.outputs.into_iter().collect::<Vec<_>>();
+		outputs.sort_unstable_by_key(|(serial_id, _)| *serial_id);
+
+		let holder_is_initiator = self.holder_is_initiator;
+		let has_shared_input = self.shared_input.is_some();
+		let shared_input_index = if has_shared_input { Some(0) } else { None };
+		let prev_output_scripts = inputs.iter().map(|(_, input)| input.prev_output.script_pubkey.clone()).collect();
+		let holder_input_indices = inputs.iter().enumerate()
+			.filter(|(idx, (serial_id, _))| holder_is_initiator == serial_id.is_for_initiator() &&
+				!(has_shared_input && *idx == 0))
+			.map(|(idx, _)| idx as u16)
+			.collect();
+		let tx = Transaction {
+			version: 2,
+			lock_time: self.tx_locktime,
+			input: inputs.into_iter().map(|(_, input)| input.tx_in).collect(),
+			output: outputs.into_iter().map(|(_, output)| output).collect(),
+		};
+
+		Ok(ConstructedTransaction {
+			tx, holder_input_indices, shared_input_index, holder_inputs_value, counterparty_inputs_value,
+			prev_output_scripts,
+		})
+	}
+}
+
+/// The state of an interactive transaction negotiation. Parties alternate sending messages, so
+/// the state tracks whose turn it is and whether the last message sent was a `tx_complete`.
+///
+/// As the initiator always sends the first message, a non-initiator starts out in
+/// [`StateMachine::SentChangeMsg`], waiting for the initiator's turn to finish.
+#[derive(Debug)]
+enum StateMachine {
+	/// Placeholder while transitioning between states.
+	Indeterminate,
+	/// We sent `tx_add_*` or `tx_remove_*` and await the counterparty's message.
+	SentChangeMsg(NegotiationContext),
+	/// We received `tx_add_*` or `tx_remove_*` (or are the initiator about to send our first
+	/// message) and it is our turn.
+	ReceivedChangeMsg(NegotiationContext),
+	/// We sent `tx_complete` and await the counterparty's message.
+	SentTxComplete(NegotiationContext),
+	/// We received `tx_complete` and it is our turn.
+	ReceivedTxComplete(NegotiationContext),
+	/// Both parties sent consecutive `tx_complete` messages and the resulting transaction was
+	/// validated.
+	NegotiationComplete(ConstructedTransaction),
+	/// The negotiation was aborted.
+	NegotiationAborted,
+}
+
+macro_rules! define_receive_change_msg {
+	($fn_name: ident, $msg_type: ty, $ctx_fn: ident) => {
+		fn $fn_name(&mut self, msg: &$msg_type) -> Result<(), AbortReason> {
+			let mut context = match core::mem::replace(self, StateMachine::Indeterminate) {
+				StateMachine::SentChangeMsg(context) | StateMachine::SentTxComplete(context) => context,
+				_ => return self.abort(AbortReason::UnexpectedCounterpartyMessage),
+			};
+			if let Err(reason) = context.$ctx_fn(msg) {
+				return self.abort(reason);
+			}
+			*self = StateMachine::ReceivedChangeMsg(context);
+			Ok(())
+		}
+	}
+}
+
+impl StateMachine {
+	fn new(context: NegotiationContext) -> Self {
+		if context.holder_is_initiator {
+			StateMachine::ReceivedChangeMsg(context)
+		} else {
+			StateMachine::SentChangeMsg(context)
+		}
+	}
+
+	fn abort(&mut self, reason: AbortReason) -> Result<(), AbortReason> {
+		*self = StateMachine::NegotiationAborted;
+		Err(reason)
+	}
+
+	define_receive_change_msg!(receive_tx_add_input, msgs::TxAddInput, receive_tx_add_input);
+	define_receive_change_msg!(receive_tx_remove_input, msgs::TxRemoveInput, receive_tx_remove_input);
+	define_receive_change_msg!(receive_tx_add_output, msgs::TxAddOutput, receive_tx_add_output);
+	define_receive_change_msg!(receive_tx_remove_output, msgs::TxRemoveOutput, receive_tx_remove_output);
+
+	fn receive_tx_complete(&mut self) -> Result<(), AbortReason> {
+		match core::mem::replace(self, StateMachine::Indeterminate) {
+			StateMachine::SentChangeMsg(context) => {
+				*self = StateMachine::ReceivedTxComplete(context);
+				Ok(())
+			},
+			StateMachine::SentTxComplete(context) => self.complete(context),
+			_ => self.abort(AbortReason::UnexpectedCounterpartyMessage),
+		}
+	}
+
+	fn take_context_for_sending(&mut self) -> Option<NegotiationContext> {
+		match core::mem::replace(self, StateMachine::Indeterminate) {
+			StateMachine::ReceivedChangeMsg(context) | StateMachine::ReceivedTxComplete(context) => Some(context),
+			other => {
+				*self = other;
+				None
+			},
+		}
+	}
+
+	fn send_tx_add_input(&mut self, msg: &msgs::TxAddInput) {
+		let mut context = self.take_context_for_sending().expect("Not our turn to send");
+		context.send_tx_add_input(msg);
+		*self = StateMachine::SentChangeMsg(context);
+	}
+
+	fn send_tx_add_output(&mut self, msg: &msgs::TxAddOutput) {
+		let mut context = self.take_context_for_sending().expect("Not our turn to send");
+		context.send_tx_add_output(msg);
+		*self = StateMachine::SentChangeMsg(context);
+	}
+
+	fn send_tx_complete(&mut self) -> Result<(), AbortReason> {
+		match core::mem::replace(self, StateMachine::Indeterminate) {
+			StateMachine::ReceivedChangeMsg(context) => {
+				*self = StateMachine::SentTxComplete(context);
+				Ok(())
+			},
+			StateMachine::ReceivedTxComplete(context) => self.complete(context),
+			_ => panic!("Not our turn to send"),
+		}
+	}
+
+	fn complete(&mut self, context: NegotiationContext) -> Result<(), AbortReason> {
+		match context.validate_tx() {
+			Ok(tx) => {
+				*self = StateMachine::NegotiationComplete(tx);
+				Ok(())
+			},
+			Err(reason) => self.abort(reason),
+		}
+	}
+}
+
+/// A message we need to send to our counterparty as part of an interactive transaction
+/// negotiation.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub(crate) enum InteractiveTxMessageSend {
+	TxAddInput(msgs::TxAddInput),
+	TxAddOutput(msgs::TxAddOutput),
+	TxComplete(msgs::TxComplete),
+}
+
+impl InteractiveTxMessageSend {
+	/// Wraps the message in the [`MessageSendEvent`] used to send it to `counterparty_node_id`.
+	pub fn into_msg_send_event(self, counterparty_node_id: PublicKey) -> MessageSendEvent {
+		match self {
+			InteractiveTxMessageSend::TxAddInput(msg) =>
+				MessageSendEvent::SendTxAddInput { node_id: counterparty_node_id, msg },
+			InteractiveTxMessageSend::TxAddOutput(msg) =>
+				MessageSendEvent::SendTxAddOutput { node_id: counterparty_node_id, msg },
+			InteractiveTxMessageSend::TxComplete(msg) =>
+				MessageSendEvent::SendTxComplete { node_id: counterparty_node_id, msg },
+		}
+	}
+}
+
+/// Drives an interactive transaction negotiation on behalf of one party, contributing the given
+/// inputs and outputs and validating those of the counterparty.
+#[derive(Debug)]
+pub(crate) struct InteractiveTxConstructor {
+	state_machine: StateMachine,
+	channel_id: [u8; 32],
+	inputs_to_contribute: Vec<(SerialId, TxIn, TransactionU16LenLimited)>,
+	outputs_to_contribute: Vec<(SerialId, TxOut)>,
+}
+
+fn generate_holder_serial_id<ES: Deref>(entropy_source: &ES, is_initiator: bool) -> SerialId
+where ES::Target: EntropySource,
+{
+	let rand_bytes = entropy_source.get_secure_random_bytes();
+	let mut serial_id_bytes = [0u8; 8];
+	serial_id_bytes.copy_from_slice(&rand_bytes[..8]);
+	let serial_id = u64::from_be_bytes(serial_id_bytes);
+	if is_initiator { serial_id & !1 } else { serial_id | 1 }
+}
+
+fn generate_input_serial_id<ES: Deref>(entropy_source: &ES, is_initiator: bool) -> SerialId
+where ES::Target: EntropySource,
+{
+	loop {
+		let serial_id = generate_holder_serial_id(entropy_source, is_initiator);
+		if serial_id != SHARED_INPUT_SERIAL_ID {
+			return serial_id;
+		}
+	}
+}
+
+impl InteractiveTxConstructor {
+	/// Starts a new negotiation, returning the first message to send if we are the initiator.
+	///
+	/// The `shared_output` must be included in `outputs_to_contribute` if we are the initiator and
+	/// will be validated to exist in the resulting transaction either way. When splicing, the
+	/// `shared_input` spending the current funding output is implicitly included by both parties.
+	pub fn new<ES: Deref>(
+		entropy_source: &ES, channel_id: [u8; 32], feerate_sat_per_kw: u32, is_initiator: bool,
+		tx_locktime: PackedLockTime, inputs_to_contribute: Vec<(TxIn, TransactionU16LenLimited)>,
+		outputs_to_contribute: Vec<TxOut>, shared_output: SharedOutput, shared_input: Option<SharedInput>,
+	) -> (Self, Option<InteractiveTxMessageSend>)
+	where ES::Target: EntropySource,
+	{
+		let mut inputs = HashMap::new();
+		let mut prevtx_outpoints = HashSet::new();
+		if let Some(shared_input) = &shared_input {
+			prevtx_outpoints.insert(shared_input.outpoint);
+			inputs.insert(SHARED_INPUT_SERIAL_ID, InteractiveTxInput {
+				tx_in: TxIn {
+					previous_output: shared_input.outpoint,
+					sequence: Sequence::ENABLE_RBF_NO_LOCKTIME,
+					..Default::default()
+				},
+				prev_output: shared_input.prev_output.clone(),
+			});
+		}
+		let context = NegotiationContext {
+			holder_is_initiator: is_initiator,
+			received_tx_add_input_count: 0,
+			received_tx_add_output_count: 0,
+			inputs,
+			prevtx_outpoints,
+			outputs: HashMap::new(),
+			shared_output,
+			shared_input,
+			tx_locktime,
+			feerate_sat_per_kw,
+		};
+		let mut inputs_to_contribute = inputs_to_contribute.into_iter()
+			.map(|(input, prevtx)| (generate_input_serial_id(entropy_source, is_initiator), input, prevtx))
+			.collect::<Vec<_>>();
+		let mut outputs_to_contribute = outputs_to_contribute.into_iter()
+			.map(|output| (generate_holder_serial_id(entropy_source, is_initiator), output))
+			.collect::<Vec<_>>();
+		// We pop contributions off the back, so sort them in reverse to send them in increasing
+		// serial id order.
+		inputs_to_contribute.sort_unstable_by(|(a, _, _), (b, _, _)| b.cmp(a));
+		outputs_to_contribute.sort_unstable_by(|(a, _), (b, _)| b.cmp(a));
+
+		let mut constructor = Self {
+			state_machine: StateMachine::new(context),
+			channel_id,
+			inputs_to_contribute,
+			outputs_to_contribute,
+		};
+		let message_send = if is_initiator {
+			// Our own contributions are never validated, so this cannot fail.
+			constructor.maybe_send_message().ok()
+		} else {
+			None
+		};
+		(constructor, message_send)
+	}
+
+	fn maybe_send_message(&mut self) -> Result<InteractiveTxMessageSend, AbortReason> {
+		if let Some((serial_id, input, prevtx)) = self.inputs_to_contribute.pop() {
+			let msg = msgs::TxAddInput {
+				channel_id: self.channel_id,
+				serial_id,
+				prevtx,
+				prevtx_out: input.previous_output.vout,
+				sequence: input.sequence.to_consensus_u32(),
+			};
+			self.state_machine.send_tx_add_input(&msg);
+			Ok(InteractiveTxMessageSend::TxAddInput(msg))
+		} else if let Some((serial_id, output)) = self.outputs_to_contribute.pop() {
+			let msg = msgs::TxAddOutput {
+				channel_id: self.channel_id,
+				serial_id,
+				sats: output.value,
+				script: output.script_pubkey,
+			};
+			self.state_machine.send_tx_add_output(&msg);
+			Ok(InteractiveTxMessageSend::TxAddOutput(msg))
+		} else {
+			self.state_machine.send_tx_complete()?;
+			Ok(InteractiveTxMessageSend::TxComplete(msgs::TxComplete { channel_id: self.channel_id }))
+		}
+	}
+
+	/// Returns the negotiated transaction if the negotiation has completed.
+	pub fn constructed_transaction(&self) -> Option<&ConstructedTransaction> {
+		match &self.state_machine {
+			StateMachine::NegotiationComplete(tx) => Some(tx),
+			_ => None,
+		}
+	}
+
+	pub fn handle_tx_add_input(&mut self, msg: &msgs::TxAddInput) -> Result<InteractiveTxMessageSend, AbortReason> {
+		self.state_machine.receive_tx_add_input(msg)?;
+		self.maybe_send_message()
+	}
+
+	pub fn handle_tx_remove_input(&mut self, msg: &msgs::TxRemoveInput) -> Result<InteractiveTxMessageSend, AbortReason> {
+		self.state_machine.receive_tx_remove_input(msg)?;
+		self.maybe_send_message()
+	}
+
+	pub fn handle_tx_add_output(&mut self, msg: &msgs::TxAddOutput) -> Result<InteractiveTxMessageSend, AbortReason> {
+		self.state_machine.receive_tx_add_output(msg)?;
+		self.maybe_send_message()
+	}
+
+	pub fn handle_tx_remove_output(&mut self, msg: &msgs::TxRemoveOutput) -> Result<InteractiveTxMessageSend, AbortReason> {
+		self.state_machine.receive_tx_remove_output(msg)?;
+		self.maybe_send_message()
+	}
+
+	/// Handles a `tx_complete`, returning our response, if any, and the negotiated transaction if
+	/// the negotiation has now completed.
+	pub fn handle_tx_complete(&mut self, _msg: &msgs::TxComplete)
+	-> Result<(Option<InteractiveTxMessageSend>, Option<ConstructedTransaction>), AbortReason> {
+		self.state_machine.receive_tx_complete()?;
+		let message_send = match &self.state_machine {
+			StateMachine::ReceivedTxComplete(_) => Some(self.maybe_send_message()?),
+			_ => None,
+		};
+		Ok((message_send, self.constructed_transaction().cloned()))
+	}
+}
+
+/// Our signature for the shared input of a splice transaction, along with what's needed to build
+/// the input's witness once our counterparty's signature is known.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub(crate) struct SharedInputSignature {
+	/// Our signature spending the shared input.
+	pub holder_signature: Signature,
+	/// The 2-of-2 multisig script of the funding output being spent.
+	pub witness_script: Script,
+	/// Whether our signature comes first in the witness, i.e. whether our funding pubkey is the
+	/// lesser of the two.
+	pub holder_signature_first: bool,
+}
+
+impl_writeable_tlv_based!(SharedInputSignature, {
+	(0, holder_signature, required),
+	(2, witness_script, required),
+	(4, holder_signature_first, required),
+});
+
+/// Tracks the exchange of `tx_signatures` for a transaction negotiated interactively, once the
+/// initial `commitment_signed` messages have been exchanged (or are about to be).
+///
+/// We may only release our signatures after receiving our counterparty's `commitment_signed`, and
+/// only after our counterparty's `tx_signatures` if they are the one which must send first.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub(crate) struct InteractiveTxSigningSession {
+	unsigned_tx: Transaction,
+	holder_input_indices: Vec<u16>,
+	holder_sends_tx_signatures_first: bool,
+	received_commitment_signed: bool,
+	holder_tx_signatures: Option<msgs::TxSignatures>,
+	counterparty_witnesses: Option<Vec<Witness>>,
+	shared_input_index: Option<u16>,
+	holder_shared_input_signature: Option<SharedInputSignature>,
+	counterparty_shared_input_signature: Option<Signature>,
+	prev_output_scripts: Vec<Script>,
+}
+
+impl InteractiveTxSigningSession {
+	/// Starts a signing session for the given negotiated transaction. If we did not contribute any
+	/// inputs our (empty) signatures are immediately available.
+	///
+	/// If the transaction spends a shared input, our signature for it must be provided as
+	/// `holder_shared_input_signature`.
+	pub fn new(
+		channel_id: [u8; 32], constructed_tx: ConstructedTransaction, holder_sends_tx_signatures_first: bool,
+		holder_shared_input_signature: Option<SharedInputSignature>,
+	) -> Self {
+		debug_assert_eq!(constructed_tx.shared_input_index.is_some(), holder_shared_input_signature.is_some());
+		let holder_tx_signatures = if constructed_tx.holder_input_indices.is_empty() {
+			Some(msgs::TxSignatures {
+				channel_id, tx_hash: constructed_tx.tx.txid(), witnesses: Vec::new(),
+				funding_outpoint_sig: holder_shared_input_signature.as_ref().map(|sig| sig.holder_signature),
+			})
+		} else {
+			None
+		};
+		Self {
+			unsigned_tx: constructed_tx.tx,
+			holder_input_indices: constructed_tx.holder_input_indices,
+			holder_sends_tx_signatures_first,
+			received_commitment_signed: false,
+			holder_tx_signatures,
+			counterparty_witnesses: None,
+			shared_input_index: constructed_tx.shared_input_index,
+			holder_shared_input_signature,
+			counterparty_shared_input_signature: None,
+			prev_output_scripts: constructed_tx.prev_output_scripts,
+		}
+	}
+
+	pub fn unsigned_tx(&self) -> &Transaction {
+		&self.unsigned_tx
+	}
+
+	/// Returns the outpoints spent by the inputs of the negotiated transaction other than the
+	/// shared input, along with the scripts of the outputs they spend.
+	pub fn contributed_inputs(&self) -> Vec<(OutPoint, Script)> {
+		self.unsigned_tx.input.iter().zip(self.prev_output_scripts.iter()).enumerate()
+			.filter(|(idx, _)| self.shared_input_index != Some(*idx as u16))
+			.map(|(_, (input, script))| (input.previous_output, script.clone()))
+			.collect()
+	}
+
+	/// Returns whether `tx` is a different transaction spending any of the inputs of the
+	/// negotiated transaction other than the shared input, such that the latter can never confirm.
+	pub fn is_double_spent_by(&self, tx: &Transaction) -> bool {
+		if tx.txid() == self.unsigned_tx.txid() {
+			return false;
+		}
+		self.unsigned_tx.input.iter().enumerate()
+			.filter(|(idx, _)| self.shared_input_index != Some(*idx as u16))
+			.any(|(_, input)| tx.input.iter().any(|spend| spend.previous_output == input.previous_output))
+	}
+
+	/// Whether we contributed inputs and thus need to sign the transaction.
+	pub fn holder_has_inputs(&self) -> bool {
+		!self.holder_input_indices.is_empty()
+	}
+
+	pub fn has_received_commitment_signed(&self) -> bool {
+		self.received_commitment_signed
+	}
+
+	pub fn has_holder_tx_signatures(&self) -> bool {
+		self.holder_tx_signatures.is_some()
+	}
+
+	pub fn has_received_tx_signatures(&self) -> bool {
+		self.counterparty_witnesses.is_some()
+	}
+
+	/// Returns our `tx_signatures` if they may be sent to our counterparty now.
+	pub fn holder_tx_signatures_to_send(&self) -> Option<msgs::TxSignatures> {
+		if !self.received_commitment_signed {
+			return None;
+		}
+		if !self.holder_sends_tx_signatures_first && self.counterparty_witnesses.is_none() {
+			return None;
+		}
+		self.holder_tx_signatures.clone()
+	}
+
+	/// Marks the counterparty's initial `commitment_signed` as received, returning our
+	/// `tx_signatures` if they can now be sent.
+	pub fn received_commitment_signed(&mut self) -> Option<msgs::TxSignatures> {
+		self.received_commitment_signed = true;
+		self.holder_tx_signatures_to_send()
+	}
+
+	/// Provides the witnesses for our inputs, taken from the given fully-signed transaction,
+	/// returning our `tx_signatures` if they can now be sent.
+	pub fn provide_holder_witnesses(&mut self, channel_id: [u8; 32], signed_tx: &Transaction) -> Result<Option<msgs::TxSignatures>, ()> {
+		if signed_tx.txid() != self.unsigned_tx.txid() {
+			return Err(());
+		}
+		let mut witnesses = Vec::with_capacity(self.holder_input_indices.len());
+		for idx in self.holder_input_indices.iter() {
+			match signed_tx.input.get(*idx as usize) {
+				Some(input) if !input.witness.is_empty() => witnesses.push(input.witness.clone()),
+				_ => return Err(()),
+			}
+		}
+		self.holder_tx_signatures = Some(msgs::TxSignatures {
+			channel_id, tx_hash: signed_tx.txid(), witnesses,
+			funding_outpoint_sig: self.holder_shared_input_signature.as_ref().map(|sig| sig.holder_signature),
+		});
+		Ok(self.holder_tx_signatures_to_send())
+	}
+
+	/// Handles our counterparty's `tx_signatures`, returning our own `tx_signatures` if they can now
+	/// be sent.
+	///
+	/// Note that the signature for the shared input, if any, must have already been checked by the
+	/// caller.
+	pub fn received_tx_signatures(&mut self, msg: &msgs::TxSignatures) -> Result<Option<msgs::TxSignatures>, ()> {
+		if msg.tx_hash != self.unsigned_tx.txid() {
+			return Err(());
+		}
+		let shared_inputs = if self.shared_input_index.is_some() { 1 } else { 0 };
+		if msg.witnesses.len() != self.unsigned_tx.input.len() - self.holder_input_indices.len() - shared_inputs {
+			return Err(());
+		}
+		if msg.witnesses.iter().any(|witness| witness.is_empty()) {
+			return Err(());
+		}
+		if self.shared_input_index.is_some() && msg.funding_outpoint_sig.is_none() {
+			return Err(());
+		}
+		if self.counterparty_witnesses.is_some() {
+			// Our counterparty may resend their signatures after a reconnection.
+			return Ok(None);
+		}
+		self.counterparty_witnesses = Some(msg.witnesses.clone());
+		self.counterparty_shared_input_signature = msg.funding_outpoint_sig;
+		Ok(self.holder_tx_signatures_to_send())
+	}
+
+	/// Returns the index of the shared input, if any.
+	pub fn shared_input_index(&self) -> Option<u16> {
+		self.shared_input_index
+	}
+
+	fn shared_input_witness(&self) -> Option<Witness> {
+		let holder_sig = self.holder_shared_input_signature.as_ref()?;
+		let counterparty_sig = self.counterparty_shared_input_signature.as_ref()?;
+		let mut holder_sig_bytes = holder_sig.holder_signature.serialize_der().to_vec();
+		holder_sig_bytes.push(EcdsaSighashType::All as u8);
+		let mut counterparty_sig_bytes = counterparty_sig.serialize_der().to_vec();
+		counterparty_sig_bytes.push(EcdsaSighashType::All as u8);
+
+		let mut witness = Witness::new();
+		witness.push(Vec::new()); // First is the multisig dummy
+		if holder_sig.holder_signature_first {
+			witness.push(holder_sig_bytes);
+			witness.push(counterparty_sig_bytes);
+		} else {
+			witness.push(counterparty_sig_bytes);
+			witness.push(holder_sig_bytes);
+		}
+		witness.push(holder_sig.witness_script.as_bytes());
+		Some(witness)
+	}
+
+	/// Returns the fully signed transaction once both parties have provided their witnesses.
+	pub fn finalized_tx(&self) -> Option<Transaction> {
+		let holder_witnesses = &self.holder_tx_signatures.as_ref()?.witnesses;
+		let counterparty_witnesses = self.counterparty_witnesses.as_ref()?;
+		let mut holder_witnesses = holder_witnesses.iter();
+		let mut counterparty_witnesses = counterparty_witnesses.iter();
+		let mut tx = self.unsigned_tx.clone();
+		for (idx, input) in tx.input.iter_mut().enumerate() {
+			input.witness = if self.shared_input_index == Some(idx as u16) {
+				self.shared_input_witness()?
+			} else if self.holder_input_indices.contains(&(idx as u16)) {
+				holder_witnesses.next()?.clone()
+			} else {
+				counterparty_witnesses.next()?.clone()
+			};
+		}
+		Some(tx)
+	}
+}
+
+impl_writeable_tlv_based!(InteractiveTxSigningSession, {
+	(0, unsigned_tx, required),
+	(2, holder_input_indices, required_vec),
+	(4, holder_sends_tx_signatures_first, required),
+	(6, received_commitment_signed, required),
+	(8, holder_tx_signatures, option),
+	(10, counterparty_witnesses, option),
+	(11, shared_input_index, option),
+	(13, holder_shared_input_signature, option),
+	(15, counterparty_shared_input_signature, option),
+	(17, prev_output_scripts, optional_vec),
+});
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::util::test_utils::TestKeysInterface;
+
+	use bitcoin::blockdata::opcodes;
+	use bitcoin::blockdata::script::Builder;
+	use bitcoin::hashes::Hash;
+	use bitcoin::{Network, WPubkeyHash, WScriptHash};
+
+	const FEERATE: u32 = 253;
+	const CHANNEL_ID: [u8; 32] = [42; 32];
+
+	fn p2wpkh_script(seed: u8) -> Script {
+		Script::new_v0_p2wpkh(&WPubkeyHash::from_slice(&[seed; 20]).unwrap())
+	}
+
+	fn funding_script() -> Script {
+		Script::new_v0_p2wsh(&WScriptHash::from_slice(&[0xfd; 32]).unwrap())
+	}
+
+	fn build_prevtx(seed: u8, value: u64, script_pubkey: Script) -> TransactionU16LenLimited {
+		TransactionU16LenLimited::new(Transaction {
+			version: 2,
+			lock_time: PackedLockTime(seed as u32),
+			input: vec![],
+			output: vec![TxOut { value, script_pubkey }],
+		}).unwrap()
+	}
+
+	fn input(seed: u8, value: u64) -> (TxIn, TransactionU16LenLimited) {
+		let prevtx = build_prevtx(seed, value, p2wpkh_script(seed));
+		let tx_in = TxIn {
+			previous_output: OutPoint { txid: prevtx.as_transaction().txid(), vout: 0 },
+			sequence: Sequence::ENABLE_RBF_NO_LOCKTIME,
+			..Default::default()
+		};
+		(tx_in, prevtx)
+	}
+
+	fn shared_output(value: u64, holder_value: u64) -> SharedOutput {
+		SharedOutput { tx_out: TxOut { value, script_pubkey: funding_script() }, holder_value }
+	}
+
+	fn constructor(
+		keys: &TestKeysInterface, is_initiator: bool, inputs: Vec<(TxIn, TransactionU16LenLimited)>,
+		outputs: Vec<TxOut>, shared_output: SharedOutput,
+	) -> (InteractiveTxConstructor, Option<InteractiveTxMessageSend>) {
+		InteractiveTxConstructor::new(&keys, CHANNEL_ID, FEERATE, is_initiator, PackedLockTime::ZERO,
+			inputs, outputs, shared_output, None)
+	}
+
+	fn handle(constructor: &mut InteractiveTxConstructor, msg: InteractiveTxMessageSend)
+	-> Result<(Option<InteractiveTxMessageSend>, Option<ConstructedTransaction>), AbortReason> {
+		match msg {
+			InteractiveTxMessageSend::TxAddInput(msg) =>
+				constructor.handle_tx_add_input(&msg).map(|msg| (Some(msg), None)),
+			InteractiveTxMessageSend::TxAddOutput(msg) =>
+				constructor.handle_tx_add_output(&msg).map(|msg| (Some(msg), None)),
+			InteractiveTxMessageSend::TxComplete(msg) => constructor.handle_tx_complete(&msg),
+		}
+	}
+
+	/// Runs a negotiation between an initiator and a non-initiator until it either completes or is
+	/// aborted by either party, returning the initiator's and non-initiator's transactions.
+	fn do_negotiation(
+		initiator_inputs: Vec<(TxIn, TransactionU16LenLimited)>, initiator_outputs: Vec<TxOut>,
+		acceptor_inputs: Vec<(TxIn, TransactionU16LenLimited)>, acceptor_outputs: Vec<TxOut>,
+		shared_output_value: u64, initiator_contribution: u64,
+	) -> Result<(ConstructedTransaction, ConstructedTransaction), AbortReason> {
+		let keys_a = TestKeysInterface::new(&[1; 32], Network::Testnet);
+		let keys_b = TestKeysInterface::new(&[2; 32], Network::Testnet);
+		let (mut initiator, first_msg) = constructor(&keys_a, true, initiator_inputs, initiator_outputs,
+			shared_output(shared_output_value, initiator_contribution));
+		let (mut acceptor, no_msg) = constructor(&keys_b, false, acceptor_inputs, acceptor_outputs,
+			shared_output(shared_output_value, shared_output_value - initiator_contribution));
+		assert!(no_msg.is_none());
+		exchange_messages(&mut initiator, &mut acceptor, first_msg)
+	}
+
+	/// Passes messages back and forth, starting with the initiator's first message, until the
+	/// negotiation either completes or is aborted.
+	fn exchange_messages(
+		initiator: &mut InteractiveTxConstructor, acceptor: &mut InteractiveTxConstructor,
+		first_msg: Option<InteractiveTxMessageSend>,
+	) -> Result<(ConstructedTransaction, ConstructedTransaction), AbortReason> {
+		let mut msg = first_msg;
+		let mut initiator_turn = false;
+		while let Some(next_msg) = msg.take() {
+			let handler = if initiator_turn { &mut *initiator } else { &mut *acceptor };
+			msg = handle(handler, next_msg)?.0;
+			initiator_turn = !initiator_turn;
+		}
+		Ok((initiator.constructed_transaction().unwrap().clone(), acceptor.constructed_transaction().unwrap().clone()))
+	}
+
+	#[test]
+	fn test_interactive_tx_both_contribute() {
+		let funding_output = TxOut { value: 200_000, script_pubkey: funding_script() };
+		let initiator_change = TxOut { value: 40_000, script_pubkey: p2wpkh_script(10) };
+		let acceptor_change = TxOut { value: 40_000, script_pubkey: p2wpkh_script(11) };
+		let (initiator_tx, acceptor_tx) = do_negotiation(
+			vec![input(1, 100_000), input(2, 50_000)], vec![funding_output.clone(), initiator_change.clone()],
+			vec![input(3, 150_000)], vec![acceptor_change.clone()], 200_000, 100_000).unwrap();
+		assert_eq!(initiator_tx.tx, acceptor_tx.tx);
+		assert_eq!(initiator_tx.tx.input.len(), 3);
+		assert_eq!(initiator_tx.tx.output.len(), 3);
+		assert_eq!(initiator_tx.holder_input_indices.len(), 2);
+		assert_eq!(acceptor_tx.holder_input_indices.len(), 1);
+		assert_eq!(initiator_tx.holder_inputs_value, 150_000);
+		assert_eq!(initiator_tx.counterparty_inputs_value, 150_000);
+		assert_eq!(acceptor_tx.holder_inputs_value, 150_000);
+		assert!(initiator_tx.tx.output.contains(&funding_output));
+		assert!(initiator_tx.tx.output.contains(&initiator_change));
+		assert!(initiator_tx.tx.output.contains(&acceptor_change));
+	}
+
+	#[test]
+	fn test_interactive_tx_acceptor_no_contribution() {
+		let funding_output = TxOut { value: 100_000, script_pubkey: funding_script() };
+		let (initiator_tx, acceptor_tx) = do_negotiation(
+			vec![input(1, 110_000)], vec![funding_output], vec![], vec![], 100_000, 100_000).unwrap();
+		assert_eq!(initiator_tx.tx, acceptor_tx.tx);
+		assert!(acceptor_tx.holder_input_indices.is_empty());
+	}
+
+	#[test]
+	fn test_interactive_tx_insufficient_fees() {
+		// The acceptor claims half the funding output but adds nothing to pay for it.
+		let funding_output = TxOut { value: 200_000, script_pubkey: funding_script() };
+		assert_eq!(do_negotiation(vec![input(1, 210_000)], vec![funding_output], vec![], vec![], 200_000, 100_000),
+			Err(AbortReason::InsufficientFees));
+
+		// The initiator's contributions don't cover the fee for the common fields and their input.
+		let funding_output = TxOut { value: 100_000, script_pubkey: funding_script() };
+		assert_eq!(do_negotiation(vec![input(1, 100_100)], vec![funding_output], vec![], vec![], 100_000, 100_000),
+			Err(AbortReason::InsufficientFees));
+	}
+
+	#[test]
+	fn test_interactive_tx_missing_funding_output() {
+		assert_eq!(do_negotiation(vec![input(1, 110_000)], vec![TxOut { value: 100_000, script_pubkey: p2wpkh_script(10) }],
+			vec![], vec![], 100_000, 100_000), Err(AbortReason::MissingFundingOutput));
+
+		let funding_output = TxOut { value: 50_000, script_pubkey: funding_script() };
+		assert_eq!(do_negotiation(vec![input(1, 120_000)], vec![funding_output.clone(), funding_output],
+			vec![], vec![], 50_000, 50_000), Err(AbortReason::DuplicateFundingOutput));
+	}
+
+	#[test]
+	fn test_interactive_tx_outputs_exceed_inputs() {
+		let funding_output = TxOut { value: 100_000, script_pubkey: funding_script() };
+		assert_eq!(do_negotiation(vec![input(1, 50_000)], vec![funding_output], vec![], vec![], 100_000, 100_000),
+			Err(AbortReason::OutputsValueExceedsInputsValue));
+	}
+
+	#[test]
+	fn test_interactive_tx_total_value_overflow() {
+		// Each output is individually within the total supply, but together they exceed it.
+		let funding_output = TxOut { value: 100_000, script_pubkey: funding_script() };
+		let acceptor_outputs = vec![
+			TxOut { value: TOTAL_BITCOIN_SUPPLY_SATOSHIS, script_pubkey: p2wpkh_script(11) },
+			TxOut { value: TOTAL_BITCOIN_SUPPLY_SATOSHIS, script_pubkey: p2wpkh_script(12) },
+		];
+		assert_eq!(do_negotiation(vec![input(1, 110_000)], vec![funding_output], vec![], acceptor_outputs, 100_000, 100_000),
+			Err(AbortReason::ExceededMaximumSatsAllowed));
+	}
+
+	#[test]
+	fn test_interactive_tx_invalid_messages() {
+		let keys = TestKeysInterface::new(&[1; 32], Network::Testnet);
+		let new_acceptor = || constructor(&keys, false, vec![], vec![], shared_output(100_000, 0)).0;
+		let (_, prevtx) = input(1, 100_000);
+		let add_input = msgs::TxAddInput {
+			channel_id: CHANNEL_ID, serial_id: 2, prevtx: prevtx.clone(), prevtx_out: 0, sequence: 0xFFFFFFFD,
+		};
+
+		let mut acceptor = new_acceptor();
+		assert_eq!(acceptor.handle_tx_add_input(&msgs::TxAddInput { serial_id: 3, ..add_input.clone() }),
+			Err(AbortReason::IncorrectSerialIdParity));
+		// Once aborted, all further messages are rejected.
+		assert_eq!(acceptor.handle_tx_add_input(&add_input), Err(AbortReason::UnexpectedCounterpartyMessage));
+
+		let mut acceptor = new_acceptor();
+		assert_eq!(acceptor.handle_tx_add_input(&msgs::TxAddInput { sequence: 0xFFFFFFFE, ..add_input.clone() }),
+			Err(AbortReason::IncorrectInputSequenceValue));
+
+		let mut acceptor = new_acceptor();
+		assert_eq!(acceptor.handle_tx_add_input(&msgs::TxAddInput { prevtx_out: 1, ..add_input.clone() }),
+			Err(AbortReason::PrevTxOutInvalid));
+
+		let mut acceptor = new_acceptor();
+		let (_, huge_prevtx) = input(1, TOTAL_BITCOIN_SUPPLY_SATOSHIS + 1);
+		assert_eq!(acceptor.handle_tx_add_input(&msgs::TxAddInput { prevtx: huge_prevtx, ..add_input.clone() }),
+			Err(AbortReason::ExceededMaximumSatsAllowed));
+
+		let mut acceptor = new_acceptor();
+		let p2pkh_prevtx = build_prevtx(1, 100_000, Script::new_p2pkh(&bitcoin::PubkeyHash::from_slice(&[1; 20]).unwrap()));
+		assert_eq!(acceptor.handle_tx_add_input(&msgs::TxAddInput { prevtx: p2pkh_prevtx, ..add_input.clone() }),
+			Err(AbortReason::PrevTxOutInvalid));
+
+		let mut acceptor = new_acceptor();
+		assert!(acceptor.handle_tx_add_input(&add_input).is_ok());
+		assert_eq!(acceptor.handle_tx_add_input(&msgs::TxAddInput { serial_id: 4, ..add_input.clone() }),
+			Err(AbortReason::PrevTxOutInvalid));
+
+		let mut acceptor = new_acceptor();
+		assert!(acceptor.handle_tx_add_input(&add_input).is_ok());
+		assert_eq!(acceptor.handle_tx_remove_input(&msgs::TxRemoveInput { channel_id: CHANNEL_ID, serial_id: 4 }),
+			Err(AbortReason::SerialIdUnknown));
+
+		let mut acceptor = new_acceptor();
+		assert!(acceptor.handle_tx_add_input(&add_input).is_ok());
+		assert!(acceptor.handle_tx_remove_input(&msgs::TxRemoveInput { channel_id: CHANNEL_ID, serial_id: 2 }).is_ok());
+		// The outpoint may be re-added after it was removed.
+		assert!(acceptor.handle_tx_add_input(&add_input).is_ok());
+
+		let add_output = msgs::TxAddOutput { channel_id: CHANNEL_ID, serial_id: 2, sats: 1_000, script: p2wpkh_script(1) };
+		let mut acceptor = new_acceptor();
+		assert_eq!(acceptor.handle_tx_add_output(&msgs::TxAddOutput { sats: 100, ..add_output.clone() }),
+			Err(AbortReason::BelowDustLimit));
+
+		let mut acceptor = new_acceptor();
+		assert_eq!(acceptor.handle_tx_add_output(&msgs::TxAddOutput { sats: TOTAL_BITCOIN_SUPPLY_SATOSHIS + 1, ..add_output.clone() }),
+			Err(AbortReason::ExceededMaximumSatsAllowed));
+
+		let mut acceptor = new_acceptor();
+		let op_return = Builder::new().push_opcode(opcodes::all::OP_RETURN).into_script();
+		assert_eq!(acceptor.handle_tx_add_output(&msgs::TxAddOutput { script: op_return, ..add_output.clone() }),
+			Err(AbortReason::InvalidOutputScript));
+
+		let mut acceptor = new_acceptor();
+		assert!(acceptor.handle_tx_add_output(&add_output).is_ok());
+		assert_eq!(acceptor.handle_tx_add_output(&add_output), Err(AbortReason::DuplicateSerialId));
+	}
+
+	#[test]
+	fn test_interactive_tx_too_many_add_input() {
+		let keys = TestKeysInterface::new(&[1; 32], Network::Testnet);
+		let (mut acceptor, _) = constructor(&keys, false, vec![], vec![], shared_output(100_000, 0));
+		let (_, prevtx) = input(1, 100_000);
+		for i in 0..MAX_RECEIVED_TX_ADD_INPUT_COUNT as u64 {
+			let add_input = msgs::TxAddInput {
+				channel_id: CHANNEL_ID, serial_id: i * 2, prevtx: prevtx.clone(), prevtx_out: 0, sequence: 0,
+			};
+			assert!(acceptor.handle_tx_add_input(&add_input).is_ok());
+			let remove_input = msgs::TxRemoveInput { channel_id: CHANNEL_ID, serial_id: i * 2 };
+			assert!(acceptor.handle_tx_remove_input(&remove_input).is_ok());
+		}
+		let add_input = msgs::TxAddInput {
+			channel_id: CHANNEL_ID, serial_id: 0, prevtx, prevtx_out: 0, sequence: 0,
+		};
+		assert_eq!(acceptor.handle_tx_add_input(&add_input), Err(AbortReason::ReceivedTooManyTxAddInputs));
+	}
+
+	#[test]
+	fn test_interactive_tx_splice() {
+		let keys_a = TestKeysInterface::new(&[1; 32], Network::Testnet);
+		let keys_b = TestKeysInterface::new(&[2; 32], Network::Testnet);
+		let shared_input = |holder_value| SharedInput {
+			outpoint: OutPoint { txid: build_prevtx(0, 100_000, funding_script()).as_transaction().txid(), vout: 0 },
+			prev_output: TxOut { value: 100_000, script_pubkey: funding_script() },
+			holder_value,
+		};
+		let new_funding_output = TxOut { value: 145_000, script_pubkey: funding_script() };
+
+		// The initiator splices in 45_000 sats on top of its 60_000 sat balance, while the acceptor
+		// keeps its 40_000 sat balance without contributing anything.
+		let (mut initiator, first_msg) = InteractiveTxConstructor::new(&&keys_a, CHANNEL_ID, FEERATE, true,
+			PackedLockTime::ZERO, vec![input(1, 50_000)], vec![new_funding_output.clone()],
+			shared_output(145_000, 105_000), Some(shared_input(60_000)));
+		let (mut acceptor, _) = InteractiveTxConstructor::new(&&keys_b, CHANNEL_ID, FEERATE, false,
+			PackedLockTime::ZERO, vec![], vec![], shared_output(145_000, 40_000), Some(shared_input(40_000)));
+		let (initiator_tx, acceptor_tx) = exchange_messages(&mut initiator, &mut acceptor, first_msg).unwrap();
+		assert_eq!(initiator_tx.tx, acceptor_tx.tx);
+		assert_eq!(initiator_tx.tx.input.len(), 2);
+		assert_eq!(initiator_tx.tx.input[0].previous_output, shared_input(0).outpoint);
+		assert_eq!(initiator_tx.shared_input_index, Some(0));
+		assert_eq!(initiator_tx.holder_input_indices, vec![1]);
+		assert!(acceptor_tx.holder_input_indices.is_empty());
+		assert_eq!(initiator_tx.holder_inputs_value, 110_000);
+		assert_eq!(acceptor_tx.holder_inputs_value, 40_000);
+
+		// The acceptor may not take more than its balance from the shared input.
+		let (mut initiator, first_msg) = InteractiveTxConstructor::new(&&keys_a, CHANNEL_ID, FEERATE, true,
+			PackedLockTime::ZERO, vec![], vec![TxOut { value: 98_000, script_pubkey: funding_script() }],
+			shared_output(98_000, 59_000), Some(shared_input(60_000)));
+		let (mut acceptor, _) = InteractiveTxConstructor::new(&&keys_b, CHANNEL_ID, FEERATE, false,
+			PackedLockTime::ZERO, vec![], vec![TxOut { value: 1_000, script_pubkey: p2wpkh_script(11) }],
+			shared_output(98_000, 39_000), Some(shared_input(40_000)));
+		assert_eq!(exchange_messages(&mut initiator, &mut acceptor, first_msg), Err(AbortReason::InsufficientFees));
+
+		// Neither party may remove the shared input.
+		let (mut acceptor, _) = InteractiveTxConstructor::new(&&keys_b, CHANNEL_ID, FEERATE, false,
+			PackedLockTime::ZERO, vec![], vec![], shared_output(100_000, 40_000), Some(shared_input(40_000)));
+		assert_eq!(acceptor.handle_tx_remove_input(&msgs::TxRemoveInput { channel_id: CHANNEL_ID, serial_id: 0 }),
+			Err(AbortReason::SerialIdUnknown));
+	}
+
+	#[test]
+	fn test_signing_session_ordering() {
+		let (holder_input, _) = input(1, 100_000);
+		let (counterparty_input, _) = input(2, 100_000);
+		let unsigned_tx = Transaction {
+			version: 2, lock_time: PackedLockTime::ZERO,
+			input: vec![holder_input, counterparty_input],
+			output: vec![TxOut { value: 190_000, script_pubkey: funding_script() }],
+		};
+		let constructed_tx = ConstructedTransaction {
+			tx: unsigned_tx.clone(), holder_input_indices: vec![0], shared_input_index: None,
+			holder_inputs_value: 100_000, counterparty_inputs_value: 100_000,
+			prev_output_scripts: vec![p2wpkh_script(1), p2wpkh_script(2)],
+		};
+		let mut signed_tx = unsigned_tx.clone();
+		signed_tx.input[0].witness = Witness::from_vec(vec![vec![1]]);
+		let counterparty_sigs = msgs::TxSignatures {
+			channel_id: CHANNEL_ID, tx_hash: unsigned_tx.txid(), witnesses: vec![Witness::from_vec(vec![vec![2]])],
+			funding_outpoint_sig: None,
+		};
+
+		// If we send first, our signatures are released once we've received `commitment_signed`.
+		let mut session = InteractiveTxSigningSession::new(CHANNEL_ID, constructed_tx.clone(), true, None);
+		assert!(!session.has_holder_tx_signatures());
+		assert!(session.provide_holder_witnesses(CHANNEL_ID, &signed_tx).unwrap().is_none());
+		let holder_sigs = session.received_commitment_signed().unwrap();
+		assert_eq!(holder_sigs.witnesses, vec![Witness::from_vec(vec![vec![1]])]);
+		assert!(session.finalized_tx().is_none());
+		assert!(session.received_tx_signatures(&counterparty_sigs).unwrap().is_some());
+		let final_tx = session.finalized_tx().unwrap();
+		assert_eq!(final_tx.input[1].witness, Witness::from_vec(vec![vec![2]]));
+
+		// Otherwise, they're only released after our counterparty's signatures are received.
+		let mut session = InteractiveTxSigningSession::new(CHANNEL_ID, constructed_tx, false, None);
+		assert!(session.received_commitment_signed().is_none());
+		assert!(session.provide_holder_witnesses(CHANNEL_ID, &signed_tx).unwrap().is_none());
+		let bad_sigs = msgs::TxSignatures { witnesses: vec![], ..counterparty_sigs.clone() };
+		assert!(session.received_tx_signatures(&bad_sigs).is_err());
+		assert!(session.received_tx_signatures(&counterparty_sigs).unwrap().is_some());
+		assert_eq!(session.finalized_tx().unwrap(), final_tx);
+
+		// Witnesses for a different transaction are rejected.
+		let mut other_tx = signed_tx.clone();
+		other_tx.lock_time = PackedLockTime(1);
+		assert!(session.provide_holder_witnesses(CHANNEL_ID, &other_tx).is_err());
+	}
+}
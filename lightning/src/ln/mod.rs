@@ -32,6 +32,7 @@ pub mod channel;
 pub(crate) mod channel;
 
 pub(crate) mod onion_utils;
+pub(crate) mod interactivetxs;
 mod outbound_payment;
 pub mod wire;
 
@@ -66,6 +67,24 @@ mod monitor_tests;
 #[cfg(test)]
 #[allow(unused_mut)]
 mod shutdown_tests;
+#[cfg(test)]
+#[allow(unused_mut)]
+mod dual_funding_tests;
+#[cfg(test)]
+#[allow(unused_mut)]
+mod splicing_tests;
+#[cfg(test)]
+#[allow(unused_mut)]
+mod quiescence_tests;
+#[cfg(test)]
+#[allow(unused_mut)]
+mod channel_type_upgrade_tests;
+#[cfg(test)]
+#[allow(unused_mut)]
+mod offers_tests;
+#[cfg(test)]
+#[allow(unused_mut)]
+mod blinded_payment_tests;
 
 pub use self::peer_channel_encryptor::LN_MAX_MSG_LEN;
 
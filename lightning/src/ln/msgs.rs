@@ -31,19 +31,23 @@ use bitcoin::{secp256k1, Witness};
 use bitcoin::blockdata::script::Script;
 use bitcoin::hash_types::{Txid, BlockHash};
 
+use crate::blinded_path::payment::{BlindedPaymentTlvs, ForwardTlvs, ReceiveTlvs};
 use crate::ln::features::{ChannelFeatures, ChannelTypeFeatures, InitFeatures, NodeFeatures};
 use crate::ln::onion_utils;
 use crate::onion_message;
+use crate::sign::{NodeSigner, Recipient};
 
 use crate::prelude::*;
 use core::fmt;
 use core::fmt::Debug;
-use crate::io::{self, Read};
+use core::ops::Deref;
+use crate::io::{self, Cursor, Read};
 use crate::io_extras::read_to_end;
 
 use crate::events::{MessageSendEventsProvider, OnionMessageProvider};
+use crate::util::chacha20poly1305rfc::ChaChaPolyReadAdapter;
 use crate::util::logger;
-use crate::util::ser::{LengthReadable, Readable, ReadableArgs, Writeable, Writer, WithoutLength, FixedLengthReader, HighZeroBytesDroppedBigSize, Hostname, TransactionU16LenLimited};
+use crate::util::ser::{BigSize, LengthReadable, LengthReadableArgs, Readable, ReadableArgs, Writeable, Writer, WithoutLength, FixedLengthReader, HighZeroBytesDroppedBigSize, Hostname, TransactionU16LenLimited, RequiredWrapper};
 
 use crate::ln::{PaymentPreimage, PaymentHash, PaymentSecret};
 
@@ -509,6 +513,9 @@ pub struct TxSignatures {
 	pub tx_hash: Txid,
 	/// The list of witnesses
 	pub witnesses: Vec<Witness>,
+	/// The sender's signature for the channel's current funding output, when the transaction is
+	/// a splice which spends it.
+	pub funding_outpoint_sig: Option<Signature>,
 }
 
 /// A tx_init_rbf message which initiates a replacement of the transaction after it's been
@@ -552,6 +559,104 @@ pub struct TxAbort {
 	pub data: Vec<u8>,
 }
 
+/// A splice message to be sent by or received from the initiator of a splice, proposing to add
+/// funds to or remove funds from an existing channel.
+///
+// TODO(splicing): Add spec link for `splice`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Splice {
+	/// The channel ID
+	pub channel_id: [u8; 32],
+	/// The genesis hash of the blockchain where the channel is to be opened
+	pub chain_hash: BlockHash,
+	/// The number of satoshis the sender will add to (if positive) or remove from (if negative)
+	/// the channel
+	pub relative_satoshis: i64,
+	/// The feerate for the new funding transaction
+	pub funding_feerate_perkw: u32,
+	/// The locktime for the new funding transaction
+	pub locktime: u32,
+	/// The key of the sender (splice initiator) controlling the new funding transaction
+	pub funding_pubkey: PublicKey,
+}
+
+/// A splice_ack message to be received by or sent to the splice initiator, accepting a proposed
+/// splice.
+///
+// TODO(splicing): Add spec link for `splice_ack`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct SpliceAck {
+	/// The channel ID
+	pub channel_id: [u8; 32],
+	/// The genesis hash of the blockchain where the channel is to be opened
+	pub chain_hash: BlockHash,
+	/// The number of satoshis the sender will add to (if positive) or remove from (if negative)
+	/// the channel
+	pub relative_satoshis: i64,
+	/// The key of the sender (splice acceptor) controlling the new funding transaction
+	pub funding_pubkey: PublicKey,
+}
+
+/// A splice_locked message to be sent to or received from a peer, indicating that the sender
+/// considers the new funding transaction of a splice sufficiently confirmed.
+///
+// TODO(splicing): Add spec link for `splice_locked`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct SpliceLocked {
+	/// The channel ID
+	pub channel_id: [u8; 32],
+	/// The ID of the new funding transaction that has been locked
+	pub splice_txid: Txid,
+}
+
+/// An [`stfu`] (SomeThing Fundamental is Underway) message to be sent to or received from a
+/// peer, indicating that the sender wishes the channel to become quiescent, i.e. that no further
+/// updates be made to the channel until some other protocol has run.
+///
+/// [`stfu`]: https://github.com/lightning/bolts/pull/869
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Stfu {
+	/// The channel ID
+	pub channel_id: [u8; 32],
+	/// Whether the sender is the initiator of the quiescence attempt, i.e. whether this message
+	/// isn't a response to one sent by the recipient.
+	pub initiator: bool,
+}
+
+/// A dyn_propose message to be sent by the initiator of a quiescent channel, proposing to upgrade
+/// the channel to a new channel type without closing it.
+///
+// TODO(dyn_commitments): Add spec link for `dyn_propose`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct DynPropose {
+	/// The channel ID
+	pub channel_id: [u8; 32],
+	/// The channel type the sender wishes to upgrade the channel to
+	pub channel_type: ChannelTypeFeatures,
+}
+
+/// A dyn_ack message to be sent in response to a [`DynPropose`], accepting the proposed upgrade
+/// of the channel's type.
+///
+// TODO(dyn_commitments): Add spec link for `dyn_ack`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct DynAck {
+	/// The channel ID
+	pub channel_id: [u8; 32],
+}
+
+/// A dyn_reject message to be sent in response to a [`DynPropose`], rejecting the proposed
+/// upgrade of the channel's type.
+///
+// TODO(dyn_commitments): Add spec link for `dyn_reject`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct DynReject {
+	/// The channel ID
+	pub channel_id: [u8; 32],
+	/// Message data, usually a human-readable reason for the rejection
+	pub data: Vec<u8>,
+}
+
 /// A [`shutdown`] message to be sent to or received from a peer.
 ///
 /// [`shutdown`]: https://github.com/lightning/bolts/blob/master/02-peer-protocol.md#closing-initiation-shutdown
@@ -616,6 +721,9 @@ pub struct UpdateAddHTLC {
 	/// [`ChannelConfig::accept_underpaying_htlcs`]: crate::util::config::ChannelConfig::accept_underpaying_htlcs
 	pub skimmed_fee_msat: Option<u64>,
 	pub(crate) onion_routing_packet: OnionPacket,
+	/// Provided if we are relaying or receiving a payment within a blinded path, to decrypt the onion
+	/// routing packet and the recipient-provided encrypted payload within.
+	pub blinding_point: Option<PublicKey>,
 }
 
  /// An onion message to be sent to or received from a peer.
@@ -678,11 +786,24 @@ pub struct CommitmentSigned {
 	pub signature: Signature,
 	/// Signatures on the HTLC transactions
 	pub htlc_signatures: Vec<Signature>,
+	/// Set when the commitment transaction is signed for each funding output of a channel with a
+	/// pending splice, identifying the funding output this one spends
+	pub batch: Option<CommitmentSignedBatch>,
 	#[cfg(taproot)]
 	/// The partial Taproot signature on the commitment transaction
 	pub partial_signature_with_nonce: Option<PartialSignatureWithNonce>,
 }
 
+/// Identifies a [`CommitmentSigned`] as part of a batch, in which one is sent for each funding
+/// output of a channel while a splice is pending.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct CommitmentSignedBatch {
+	/// The number of [`CommitmentSigned`] messages in the batch
+	pub batch_size: u16,
+	/// The ID of the funding transaction whose output the commitment transaction spends
+	pub funding_txid: Txid,
+}
+
 /// A [`revoke_and_ack`] message to be sent to or received from a peer.
 ///
 /// [`revoke_and_ack`]: https://github.com/lightning/bolts/blob/master/02-peer-protocol.md#completing-the-transition-to-the-updated-state-revoke_and_ack
@@ -728,6 +849,10 @@ pub struct ChannelReestablish {
 	pub my_current_per_commitment_point: PublicKey,
 	/// The next funding transaction ID
 	pub next_funding_txid: Option<Txid>,
+	/// The sender's current channel type, which may differ from the one negotiated when the
+	/// channel was opened if it has since been upgraded, or the channel type of an upgrade the
+	/// sender accepted but has not yet switched over to
+	pub channel_type: Option<ChannelTypeFeatures>,
 }
 
 /// An [`announcement_signatures`] message to be sent to or received from a peer.
@@ -1059,8 +1184,16 @@ pub struct QueryChannelRange {
 	pub first_blocknum: u32,
 	/// The number of blocks to include in the query results
 	pub number_of_blocks: u32,
+	/// Additional information requested for each channel in the replies, as a bitfield of the
+	/// `QUERY_OPTION_*` constants. Only supported by peers which support `gossip_queries_ex`.
+	pub query_option: Option<u64>,
 }
 
+/// A [`QueryChannelRange::query_option`] bit requesting [`ReplyChannelRange::timestamps`].
+pub const QUERY_OPTION_TIMESTAMPS: u64 = 1 << 0;
+/// A [`QueryChannelRange::query_option`] bit requesting [`ReplyChannelRange::checksums`].
+pub const QUERY_OPTION_CHECKSUMS: u64 = 1 << 1;
+
 /// A [`reply_channel_range`] message is a reply to a [`QueryChannelRange`]
 /// message.
 ///
@@ -1084,6 +1217,17 @@ pub struct ReplyChannelRange {
 	pub sync_complete: bool,
 	/// The `short_channel_id`s in the channel range
 	pub short_channel_ids: Vec<u64>,
+	/// The timestamps of the latest [`ChannelUpdate`] from `node_id_1` and `node_id_2`,
+	/// respectively, for each of the `short_channel_ids`, or 0 if there is none. Only set if
+	/// requested via [`QUERY_OPTION_TIMESTAMPS`].
+	pub timestamps: Option<Vec<(u32, u32)>>,
+	/// The checksums of the latest [`ChannelUpdate`] from `node_id_1` and `node_id_2`,
+	/// respectively, for each of the `short_channel_ids`, or 0 if there is none. Only set if
+	/// requested via [`QUERY_OPTION_CHECKSUMS`].
+	///
+	/// Checksums are a CRC32C over the update's contents, excluding its signature and timestamp,
+	/// allowing updates which only refresh the timestamp to be skipped.
+	pub checksums: Option<Vec<(u32, u32)>>,
 }
 
 /// A [`query_short_channel_ids`] message is used to query a peer for
@@ -1103,8 +1247,25 @@ pub struct QueryShortChannelIds {
 	pub chain_hash: BlockHash,
 	/// The short_channel_ids that are being queried
 	pub short_channel_ids: Vec<u64>,
+	/// The messages requested for each of the `short_channel_ids`, as a bitfield of the
+	/// `QUERY_FLAG_*` constants, if only some of them are wanted. Must contain exactly one entry
+	/// per `short_channel_id` when set. If `None`, all messages are requested.
+	pub query_flags: Option<Vec<u64>>,
 }
 
+/// A [`QueryShortChannelIds::query_flags`] bit requesting the [`ChannelAnnouncement`].
+pub const QUERY_FLAG_CHANNEL_ANNOUNCEMENT: u64 = 1 << 0;
+/// A [`QueryShortChannelIds::query_flags`] bit requesting the [`ChannelUpdate`] of `node_id_1`.
+pub const QUERY_FLAG_CHANNEL_UPDATE_NODE_1: u64 = 1 << 1;
+/// A [`QueryShortChannelIds::query_flags`] bit requesting the [`ChannelUpdate`] of `node_id_2`.
+pub const QUERY_FLAG_CHANNEL_UPDATE_NODE_2: u64 = 1 << 2;
+/// A [`QueryShortChannelIds::query_flags`] bit requesting the [`NodeAnnouncement`] of
+/// `node_id_1`.
+pub const QUERY_FLAG_NODE_ANNOUNCEMENT_NODE_1: u64 = 1 << 3;
+/// A [`QueryShortChannelIds::query_flags`] bit requesting the [`NodeAnnouncement`] of
+/// `node_id_2`.
+pub const QUERY_FLAG_NODE_ANNOUNCEMENT_NODE_2: u64 = 1 << 4;
+
 /// A [`reply_short_channel_ids_end`] message is sent as a reply to a
 /// message. The query recipient makes a best
 /// effort to respond based on their local network view which may not be
@@ -1143,6 +1304,19 @@ enum EncodingType {
 	Uncompressed = 0x00,
 }
 
+/// A gossip message sent to a peer in reply to its [`QueryShortChannelIds`].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum GossipQueryReply {
+	/// A [`ChannelAnnouncement`] for one of the queried `short_channel_id`s.
+	ChannelAnnouncement(ChannelAnnouncement),
+	/// A [`ChannelUpdate`] for one of the queried `short_channel_id`s.
+	ChannelUpdate(ChannelUpdate),
+	/// A [`NodeAnnouncement`] for a node of one of the queried `short_channel_id`s.
+	NodeAnnouncement(NodeAnnouncement),
+	/// The [`ReplyShortChannelIdsEnd`] marking that the reply is complete.
+	ReplyShortChannelIdsEnd(ReplyShortChannelIdsEnd),
+}
+
 /// Used to put an error message in a [`LightningError`].
 #[derive(Clone, Debug)]
 pub enum ErrorAction {
@@ -1207,6 +1381,9 @@ pub struct CommitmentUpdate {
 	pub update_fee: Option<UpdateFee>,
 	/// A `commitment_signed` message which should be sent
 	pub commitment_signed: CommitmentSigned,
+	/// The `commitment_signed` messages for the new funding outputs of a pending splice, which
+	/// should be sent right after `commitment_signed` as part of the same batch
+	pub splice_commitment_signed: Vec<CommitmentSigned>,
 }
 
 /// A trait to describe an object which can receive channel messages.
@@ -1256,6 +1433,26 @@ pub trait ChannelMessageHandler : MessageSendEventsProvider {
 	/// Handle an incoming `tx_abort message` from the given peer.
 	fn handle_tx_abort(&self, their_node_id: &PublicKey, msg: &TxAbort);
 
+	// Splicing
+	/// Handle an incoming `splice` message from the given peer.
+	fn handle_splice(&self, their_node_id: &PublicKey, msg: &Splice);
+	/// Handle an incoming `splice_ack` message from the given peer.
+	fn handle_splice_ack(&self, their_node_id: &PublicKey, msg: &SpliceAck);
+	/// Handle an incoming `splice_locked` message from the given peer.
+	fn handle_splice_locked(&self, their_node_id: &PublicKey, msg: &SpliceLocked);
+
+	// Quiescence
+	/// Handle an incoming `stfu` message from the given peer.
+	fn handle_stfu(&self, their_node_id: &PublicKey, msg: &Stfu);
+
+	// Channel type upgrades
+	/// Handle an incoming `dyn_propose` message from the given peer.
+	fn handle_dyn_propose(&self, their_node_id: &PublicKey, msg: &DynPropose);
+	/// Handle an incoming `dyn_ack` message from the given peer.
+	fn handle_dyn_ack(&self, their_node_id: &PublicKey, msg: &DynAck);
+	/// Handle an incoming `dyn_reject` message from the given peer.
+	fn handle_dyn_reject(&self, their_node_id: &PublicKey, msg: &DynReject);
+
 	// HTLC handling:
 	/// Handle an incoming `update_add_htlc` message from the given peer.
 	fn handle_update_add_htlc(&self, their_node_id: &PublicKey, msg: &UpdateAddHTLC);
@@ -1343,6 +1540,10 @@ pub trait RoutingMessageHandler : MessageSendEventsProvider {
 	/// higher (as defined by `<PublicKey as Ord>::cmp`) than `starting_point`.
 	/// If `None` is provided for `starting_point`, we start at the first node.
 	fn get_next_node_announcement(&self, starting_point: Option<&NodeId>) -> Option<NodeAnnouncement>;
+	/// Gets the next message to send to the given peer in reply to a [`QueryShortChannelIds`] it
+	/// sent, if any. This is called whenever the peer's outbound buffer has room, so that replies
+	/// to large queries are paced with the peer's ability to receive them.
+	fn get_next_query_reply(&self, their_node_id: &PublicKey) -> Option<GossipQueryReply>;
 	/// Called when a connection is established with a peer. This can be used to
 	/// perform routing table synchronization using a strategy defined by the
 	/// implementor.
@@ -1351,6 +1552,11 @@ pub trait RoutingMessageHandler : MessageSendEventsProvider {
 	/// with us. Implementors should be somewhat conservative about doing so, however, as other
 	/// message handlers may still wish to communicate with this peer.
 	fn peer_connected(&self, their_node_id: &PublicKey, init: &Init, inbound: bool) -> Result<(), ()>;
+	/// Indicates a connection to the peer failed/an existing connection was lost. Allows handlers to
+	/// drop any query replies or sync state kept for this peer.
+	///
+	/// Does nothing by default.
+	fn peer_disconnected(&self, _their_node_id: &PublicKey) {}
 	/// Handles the reply of a query we initiated to learn about channels
 	/// for a given range of blocks. We can expect to receive one or more
 	/// replies to a single query.
@@ -1417,8 +1623,11 @@ pub trait OnionMessageHandler : OnionMessageProvider {
 }
 
 mod fuzzy_internal_msgs {
+	use bitcoin::secp256k1::PublicKey;
+	use crate::blinded_path::payment::{PaymentConstraints, PaymentRelay};
 	use crate::prelude::*;
 	use crate::ln::{PaymentPreimage, PaymentSecret};
+	use crate::ln::features::BlindedHopFeatures;
 
 	// These types aren't intended to be pub, but are exposed for direct fuzzing (as we deserialize
 	// them from untrusted input):
@@ -1439,6 +1648,56 @@ mod fuzzy_internal_msgs {
 			payment_metadata: Option<Vec<u8>>,
 			keysend_preimage: Option<PaymentPreimage>,
 		},
+		/// A hop within a blinded path, for which the forwarding parameters are provided by the
+		/// recipient in the `encrypted_tlvs` rather than by us, so the amount and CLTV are omitted.
+		BlindedNode {
+			encrypted_tlvs: Vec<u8>,
+			/// Set for the introduction node of the blinded path.
+			intro_node_blinding_point: Option<PublicKey>,
+		},
+		/// The recipient at the end of a blinded path.
+		BlindedFinalNode {
+			total_msat: u64,
+			encrypted_tlvs: Vec<u8>,
+			/// Set if the recipient is also the introduction node of the blinded path.
+			intro_node_blinding_point: Option<PublicKey>,
+		},
+	}
+
+	/// The decoded onion payload of an incoming HTLC, as read from the onion packet addressed to us.
+	pub(crate) enum InboundOnionPayload {
+		Forward {
+			short_channel_id: u64,
+			/// The value, in msat, of the payment after this hop's fee is deducted.
+			amt_to_forward: u64,
+			outgoing_cltv_value: u32,
+		},
+		Receive {
+			payment_data: Option<FinalOnionHopData>,
+			payment_metadata: Option<Vec<u8>>,
+			keysend_preimage: Option<PaymentPreimage>,
+			amt_msat: u64,
+			outgoing_cltv_value: u32,
+		},
+		BlindedForward {
+			short_channel_id: u64,
+			payment_relay: PaymentRelay,
+			payment_constraints: PaymentConstraints,
+			features: BlindedHopFeatures,
+			/// Set if we are the introduction node of the blinded path, in which case the blinding
+			/// point was provided in the onion rather than in the `update_add_htlc`.
+			intro_node_blinding_point: Option<PublicKey>,
+		},
+		BlindedReceive {
+			amt_msat: u64,
+			total_msat: u64,
+			outgoing_cltv_value: u32,
+			payment_secret: PaymentSecret,
+			payment_constraints: PaymentConstraints,
+			/// Set if we are the introduction node of the blinded path, in which case the blinding
+			/// point was provided in the onion rather than in the `update_add_htlc`.
+			intro_node_blinding_point: Option<PublicKey>,
+		},
 	}
 
 	pub struct OnionHopData {
@@ -1632,7 +1891,9 @@ impl_writeable_msg!(TxSignatures, {
 	channel_id,
 	tx_hash,
 	witnesses,
-}, {});
+}, {
+	(0, funding_outpoint_sig, option),
+});
 
 impl_writeable_msg!(TxInitRbf, {
 	channel_id,
@@ -1653,6 +1914,27 @@ impl_writeable_msg!(TxAbort, {
 	data,
 }, {});
 
+impl_writeable_msg!(Splice, {
+	channel_id,
+	chain_hash,
+	relative_satoshis,
+	funding_feerate_perkw,
+	locktime,
+	funding_pubkey,
+}, {});
+
+impl_writeable_msg!(SpliceAck, {
+	channel_id,
+	chain_hash,
+	relative_satoshis,
+	funding_pubkey,
+}, {});
+
+impl_writeable_msg!(SpliceLocked, {
+	channel_id,
+	splice_txid,
+}, {});
+
 impl_writeable_msg!(AnnouncementSignatures, {
 	channel_id,
 	short_channel_id,
@@ -1668,6 +1950,7 @@ impl_writeable_msg!(ChannelReestablish, {
 	my_current_per_commitment_point,
 }, {
 	(0, next_funding_txid, option),
+	(1, channel_type, option),
 });
 
 impl_writeable_msg!(ClosingSigned,
@@ -1680,12 +1963,19 @@ impl_writeable!(ClosingSignedFeeRange, {
 	max_fee_satoshis
 });
 
+impl_writeable!(CommitmentSignedBatch, {
+	batch_size,
+	funding_txid
+});
+
 #[cfg(not(taproot))]
 impl_writeable_msg!(CommitmentSigned, {
 	channel_id,
 	signature,
 	htlc_signatures
-}, {});
+}, {
+	(0, batch, option),
+});
 
 #[cfg(taproot)]
 impl_writeable_msg!(CommitmentSigned, {
@@ -1693,6 +1983,7 @@ impl_writeable_msg!(CommitmentSigned, {
 	signature,
 	htlc_signatures
 }, {
+	(0, batch, option),
 	(2, partial_signature_with_nonce, option)
 });
 
@@ -1839,6 +2130,46 @@ impl_writeable_msg!(RevokeAndACK, {
 	(4, next_local_nonce, option)
 });
 
+impl_writeable_msg!(Stfu, {
+	channel_id,
+	initiator,
+}, {});
+
+impl Writeable for DynPropose {
+	fn write<W: Writer>(&self, w: &mut W) -> Result<(), io::Error> {
+		self.channel_id.write(w)?;
+		// The channel type isn't length-prefixed on its own, so we write it as a TLV to allow
+		// extending the message later on.
+		encode_tlv_stream!(w, {
+			(0, self.channel_type, required),
+		});
+		Ok(())
+	}
+}
+
+impl Readable for DynPropose {
+	fn read<R: Read>(r: &mut R) -> Result<Self, DecodeError> {
+		let channel_id: [u8; 32] = Readable::read(r)?;
+		let mut channel_type = RequiredWrapper(None);
+		decode_tlv_stream!(r, {
+			(0, channel_type, required),
+		});
+		Ok(DynPropose {
+			channel_id,
+			channel_type: channel_type.0.unwrap(),
+		})
+	}
+}
+
+impl_writeable_msg!(DynAck, {
+	channel_id,
+}, {});
+
+impl_writeable_msg!(DynReject, {
+	channel_id,
+	data,
+}, {});
+
 impl_writeable_msg!(Shutdown, {
 	channel_id,
 	scriptpubkey
@@ -1914,6 +2245,7 @@ impl_writeable_msg!(UpdateAddHTLC, {
 	cltv_expiry,
 	onion_routing_packet,
 }, {
+	(0, blinding_point, option),
 	(65537, skimmed_fee_msat, option)
 });
 
@@ -1974,6 +2306,21 @@ impl Writeable for OnionHopData {
 					(5482373484, keysend_preimage, option)
 				});
 			},
+			OnionHopDataFormat::BlindedNode { ref encrypted_tlvs, intro_node_blinding_point } => {
+				_encode_varint_length_prefixed_tlv!(w, {
+					(10, WithoutLength(encrypted_tlvs), required),
+					(12, intro_node_blinding_point, option)
+				});
+			},
+			OnionHopDataFormat::BlindedFinalNode { total_msat, ref encrypted_tlvs, intro_node_blinding_point } => {
+				_encode_varint_length_prefixed_tlv!(w, {
+					(2, HighZeroBytesDroppedBigSize(self.amt_to_forward), required),
+					(4, HighZeroBytesDroppedBigSize(self.outgoing_cltv_value), required),
+					(10, WithoutLength(encrypted_tlvs), required),
+					(12, intro_node_blinding_point, option),
+					(18, HighZeroBytesDroppedBigSize(total_msat), required)
+				});
+			},
 		}
 		Ok(())
 	}
@@ -2027,11 +2374,110 @@ impl Readable for OnionHopData {
 	}
 }
 
-// ReadableArgs because we need onion_utils::decode_next_hop to accommodate payment packets and
-// onion message packets.
-impl ReadableArgs<()> for OnionHopData {
-	fn read<R: Read>(r: &mut R, _arg: ()) -> Result<Self, DecodeError> {
-		<Self as Readable>::read(r)
+// The `update_add_htlc` blinding point, if any, and a `NodeSigner` are needed to decrypt the
+// recipient-provided `encrypted_recipient_data` of a blinded hop.
+impl<NS: Deref> ReadableArgs<(Option<PublicKey>, &NS)> for InboundOnionPayload where NS::Target: NodeSigner {
+	fn read<R: Read>(r: &mut R, args: (Option<PublicKey>, &NS)) -> Result<Self, DecodeError> {
+		let (update_add_blinding_point, node_signer) = args;
+
+		let mut amt = None;
+		let mut cltv_value = None;
+		let mut short_id: Option<u64> = None;
+		let mut payment_data: Option<FinalOnionHopData> = None;
+		let mut encrypted_tlvs_opt: Option<WithoutLength<Vec<u8>>> = None;
+		let mut intro_node_blinding_point: Option<PublicKey> = None;
+		let mut payment_metadata: Option<WithoutLength<Vec<u8>>> = None;
+		let mut total_msat = None;
+		let mut keysend_preimage: Option<PaymentPreimage> = None;
+		read_tlv_fields!(r, {
+			(2, amt, (option, encoding: (u64, HighZeroBytesDroppedBigSize))),
+			(4, cltv_value, (option, encoding: (u32, HighZeroBytesDroppedBigSize))),
+			(6, short_id, option),
+			(8, payment_data, option),
+			(10, encrypted_tlvs_opt, option),
+			(12, intro_node_blinding_point, option),
+			(16, payment_metadata, option),
+			(18, total_msat, (option, encoding: (u64, HighZeroBytesDroppedBigSize))),
+			// See https://github.com/lightning/blips/blob/master/blip-0003.md
+			(5482373484, keysend_preimage, option)
+		});
+
+		if amt.unwrap_or(0) > MAX_VALUE_MSAT { return Err(DecodeError::InvalidValue) }
+		if intro_node_blinding_point.is_some() && update_add_blinding_point.is_some() {
+			return Err(DecodeError::InvalidValue)
+		}
+
+		if let Some(blinding_point) = intro_node_blinding_point.or(update_add_blinding_point) {
+			if short_id.is_some() || payment_data.is_some() || payment_metadata.is_some() {
+				return Err(DecodeError::InvalidValue)
+			}
+			let enc_tlvs = encrypted_tlvs_opt.ok_or(DecodeError::InvalidValue)?.0;
+			let enc_tlvs_ss = node_signer.ecdh(Recipient::Node, &blinding_point, None)
+				.map_err(|_| DecodeError::InvalidValue)?;
+			let rho = onion_utils::gen_rho_from_shared_secret(&enc_tlvs_ss.secret_bytes());
+			let mut s = Cursor::new(&enc_tlvs);
+			let mut reader = FixedLengthReader::new(&mut s, enc_tlvs.len() as u64);
+			match ChaChaPolyReadAdapter::read(&mut reader, rho)? {
+				ChaChaPolyReadAdapter { readable: BlindedPaymentTlvs::Forward(ForwardTlvs {
+					short_channel_id, payment_relay, payment_constraints, features
+				})} => {
+					if amt.is_some() || cltv_value.is_some() || total_msat.is_some() ||
+						keysend_preimage.is_some()
+					{
+						return Err(DecodeError::InvalidValue)
+					}
+					Ok(Self::BlindedForward {
+						short_channel_id,
+						payment_relay,
+						payment_constraints,
+						features,
+						intro_node_blinding_point,
+					})
+				},
+				ChaChaPolyReadAdapter { readable: BlindedPaymentTlvs::Receive(ReceiveTlvs {
+					payment_secret, payment_constraints
+				})} => {
+					if total_msat.unwrap_or(0) > MAX_VALUE_MSAT || keysend_preimage.is_some() {
+						return Err(DecodeError::InvalidValue)
+					}
+					Ok(Self::BlindedReceive {
+						amt_msat: amt.ok_or(DecodeError::InvalidValue)?,
+						total_msat: total_msat.ok_or(DecodeError::InvalidValue)?,
+						outgoing_cltv_value: cltv_value.ok_or(DecodeError::InvalidValue)?,
+						payment_secret,
+						payment_constraints,
+						intro_node_blinding_point,
+					})
+				},
+			}
+		} else if let Some(short_channel_id) = short_id {
+			if payment_data.is_some() || payment_metadata.is_some() || encrypted_tlvs_opt.is_some() ||
+				total_msat.is_some()
+			{
+				return Err(DecodeError::InvalidValue)
+			}
+			Ok(Self::Forward {
+				short_channel_id,
+				amt_to_forward: amt.ok_or(DecodeError::InvalidValue)?,
+				outgoing_cltv_value: cltv_value.ok_or(DecodeError::InvalidValue)?,
+			})
+		} else {
+			if encrypted_tlvs_opt.is_some() || total_msat.is_some() {
+				return Err(DecodeError::InvalidValue)
+			}
+			if let Some(data) = &payment_data {
+				if data.total_msat > MAX_VALUE_MSAT {
+					return Err(DecodeError::InvalidValue);
+				}
+			}
+			Ok(Self::Receive {
+				payment_data,
+				payment_metadata: payment_metadata.map(|w| w.0),
+				keysend_preimage,
+				amt_msat: amt.ok_or(DecodeError::InvalidValue)?,
+				outgoing_cltv_value: cltv_value.ok_or(DecodeError::InvalidValue)?,
+			})
+		}
 	}
 }
 
@@ -2327,9 +2773,15 @@ impl Readable for QueryShortChannelIds {
 			short_channel_ids.push(Readable::read(r)?);
 		}
 
+		let mut query_flags: Option<EncodedQueryFlags> = None;
+		decode_tlv_stream!(r, {
+			(1, query_flags, option),
+		});
+
 		Ok(QueryShortChannelIds {
 			chain_hash,
 			short_channel_ids,
+			query_flags: query_flags.map(|flags| flags.0),
 		})
 	}
 }
@@ -2349,6 +2801,42 @@ impl Writeable for QueryShortChannelIds {
 			scid.write(w)?;
 		}
 
+		encode_tlv_stream!(w, {
+			(1, self.query_flags.as_ref().map(|flags| EncodedQueryFlags(flags.clone())), option),
+		});
+
+		Ok(())
+	}
+}
+
+/// The `query_flags` TLV of a [`QueryShortChannelIds`], consisting of an encoding type followed
+/// by a `BigSize` for each `short_channel_id`.
+struct EncodedQueryFlags(Vec<u64>);
+
+impl Readable for EncodedQueryFlags {
+	fn read<R: Read>(r: &mut R) -> Result<Self, DecodeError> {
+		let encoding_type: u8 = Readable::read(r)?;
+		if encoding_type != EncodingType::Uncompressed as u8 {
+			return Err(DecodeError::UnsupportedCompression);
+		}
+
+		let encoded_flags = read_to_end(r)?;
+		let mut reader = Cursor::new(&encoded_flags[..]);
+		let mut flags = Vec::new();
+		while (reader.position() as usize) < encoded_flags.len() {
+			let flag: BigSize = Readable::read(&mut reader)?;
+			flags.push(flag.0);
+		}
+		Ok(EncodedQueryFlags(flags))
+	}
+}
+
+impl Writeable for EncodedQueryFlags {
+	fn write<W: Writer>(&self, w: &mut W) -> Result<(), io::Error> {
+		(EncodingType::Uncompressed as u8).write(w)?;
+		for flag in self.0.iter() {
+			BigSize(*flag).write(w)?;
+		}
 		Ok(())
 	}
 }
@@ -2370,11 +2858,39 @@ impl QueryChannelRange {
 	}
 }
 
-impl_writeable_msg!(QueryChannelRange, {
-	chain_hash,
-	first_blocknum,
-	number_of_blocks
-}, {});
+impl Readable for QueryChannelRange {
+	fn read<R: Read>(r: &mut R) -> Result<Self, DecodeError> {
+		let chain_hash: BlockHash = Readable::read(r)?;
+		let first_blocknum: u32 = Readable::read(r)?;
+		let number_of_blocks: u32 = Readable::read(r)?;
+
+		let mut query_option: Option<BigSize> = None;
+		decode_tlv_stream!(r, {
+			(1, query_option, option),
+		});
+
+		Ok(QueryChannelRange {
+			chain_hash,
+			first_blocknum,
+			number_of_blocks,
+			query_option: query_option.map(|option| option.0),
+		})
+	}
+}
+
+impl Writeable for QueryChannelRange {
+	fn write<W: Writer>(&self, w: &mut W) -> Result<(), io::Error> {
+		self.chain_hash.write(w)?;
+		self.first_blocknum.write(w)?;
+		self.number_of_blocks.write(w)?;
+
+		encode_tlv_stream!(w, {
+			(1, self.query_option.map(BigSize), option),
+		});
+
+		Ok(())
+	}
+}
 
 impl Readable for ReplyChannelRange {
 	fn read<R: Read>(r: &mut R) -> Result<Self, DecodeError> {
@@ -2406,12 +2922,29 @@ impl Readable for ReplyChannelRange {
 			short_channel_ids.push(Readable::read(r)?);
 		}
 
+		let mut timestamps: Option<EncodedTimestamps> = None;
+		let mut checksums: Option<EncodedChecksums> = None;
+		decode_tlv_stream!(r, {
+			(1, timestamps, option),
+			(3, checksums, option),
+		});
+
+		let timestamps = timestamps.map(|timestamps| timestamps.0);
+		let checksums = checksums.map(|checksums| checksums.0);
+		if timestamps.as_ref().map_or(false, |timestamps| timestamps.len() != short_channel_ids.len()) ||
+			checksums.as_ref().map_or(false, |checksums| checksums.len() != short_channel_ids.len())
+		{
+			return Err(DecodeError::InvalidValue);
+		}
+
 		Ok(ReplyChannelRange {
 			chain_hash,
 			first_blocknum,
 			number_of_blocks,
 			sync_complete,
-			short_channel_ids
+			short_channel_ids,
+			timestamps,
+			checksums,
 		})
 	}
 }
@@ -2430,10 +2963,75 @@ impl Writeable for ReplyChannelRange {
 			scid.write(w)?;
 		}
 
+		encode_tlv_stream!(w, {
+			(1, self.timestamps.as_ref().map(|timestamps| EncodedTimestamps(timestamps.clone())), option),
+			(3, self.checksums.as_ref().map(|checksums| EncodedChecksums(checksums.clone())), option),
+		});
+
 		Ok(())
 	}
 }
 
+/// The `timestamps_tlv` of a [`ReplyChannelRange`], consisting of an encoding type followed by a
+/// pair of `u32` timestamps for each `short_channel_id`.
+struct EncodedTimestamps(Vec<(u32, u32)>);
+
+impl Readable for EncodedTimestamps {
+	fn read<R: Read>(r: &mut R) -> Result<Self, DecodeError> {
+		let encoding_type: u8 = Readable::read(r)?;
+		if encoding_type != EncodingType::Uncompressed as u8 {
+			return Err(DecodeError::UnsupportedCompression);
+		}
+		Ok(EncodedTimestamps(read_u32_pairs(r)?))
+	}
+}
+
+impl Writeable for EncodedTimestamps {
+	fn write<W: Writer>(&self, w: &mut W) -> Result<(), io::Error> {
+		(EncodingType::Uncompressed as u8).write(w)?;
+		write_u32_pairs(&self.0, w)
+	}
+}
+
+/// The `checksums_tlv` of a [`ReplyChannelRange`], consisting of a pair of `u32` checksums for
+/// each `short_channel_id`.
+struct EncodedChecksums(Vec<(u32, u32)>);
+
+impl Readable for EncodedChecksums {
+	fn read<R: Read>(r: &mut R) -> Result<Self, DecodeError> {
+		Ok(EncodedChecksums(read_u32_pairs(r)?))
+	}
+}
+
+impl Writeable for EncodedChecksums {
+	fn write<W: Writer>(&self, w: &mut W) -> Result<(), io::Error> {
+		write_u32_pairs(&self.0, w)
+	}
+}
+
+fn read_u32_pairs<R: Read>(r: &mut R) -> Result<Vec<(u32, u32)>, DecodeError> {
+	let encoded_pairs = read_to_end(r)?;
+	if encoded_pairs.len() % 8 != 0 {
+		return Err(DecodeError::InvalidValue);
+	}
+	let mut reader = Cursor::new(&encoded_pairs[..]);
+	let mut pairs = Vec::with_capacity(encoded_pairs.len() / 8);
+	for _ in 0..encoded_pairs.len() / 8 {
+		let first: u32 = Readable::read(&mut reader)?;
+		let second: u32 = Readable::read(&mut reader)?;
+		pairs.push((first, second));
+	}
+	Ok(pairs)
+}
+
+fn write_u32_pairs<W: Writer>(pairs: &[(u32, u32)], w: &mut W) -> Result<(), io::Error> {
+	for (first, second) in pairs.iter() {
+		first.write(w)?;
+		second.write(w)?;
+	}
+	Ok(())
+}
+
 impl_writeable_msg!(GossipTimestampFilter, {
 	chain_hash,
 	first_timestamp,
@@ -2482,6 +3080,7 @@ mod tests {
 			your_last_per_commitment_secret: [9;32],
 			my_current_per_commitment_point: public_key,
 			next_funding_txid: None,
+			channel_type: None,
 		};
 
 		let encoded_value = cr.encode();
@@ -2513,6 +3112,7 @@ mod tests {
 			next_funding_txid: Some(Txid::from_hash(bitcoin::hashes::Hash::from_slice(&[
 				48, 167, 250, 69, 152, 48, 103, 172, 164, 99, 59, 19, 23, 11, 92, 84, 15, 80, 4, 12, 98, 82, 75, 31, 201, 11, 91, 23, 98, 23, 53, 124,
 			]).unwrap())),
+			channel_type: None,
 		};
 
 		let encoded_value = cr.encode();
@@ -3165,8 +3765,10 @@ mod tests {
 		assert_eq!(encoded_value, target_value);
 	}
 
-	#[test]
-	fn encoding_tx_signatures() {
+	fn do_encoding_tx_signatures(with_funding_outpoint_sig: bool) {
+		let secp_ctx = Secp256k1::new();
+		let (privkey, _) = get_keys_from!("0101010101010101010101010101010101010101010101010101010101010101", secp_ctx);
+		let sig = get_sig_on!(privkey, secp_ctx, String::from("01010101010101010101010101010101"));
 		let tx_signatures = msgs::TxSignatures {
 			channel_id: [2; 32],
 			tx_hash: Txid::from_hex("c2d4449afa8d26140898dd54d3390b057ba2a5afcf03ba29d7dc0d8b9ffe966e").unwrap(),
@@ -3178,6 +3780,7 @@ mod tests {
 					hex::decode("3045022100ee00dbf4a862463e837d7c08509de814d620e4d9830fa84818713e0fa358f145022021c3c7060c4d53fe84fd165d60208451108a778c13b92ca4c6bad439236126cc01").unwrap(),
 					hex::decode("028fbbf0b16f5ba5bcb5dd37cd4047ce6f726a21c06682f9ec2f52b057de1dbdb5").unwrap()]),
 			],
+			funding_outpoint_sig: if with_funding_outpoint_sig { Some(sig) } else { None },
 		};
 		let encoded_value = tx_signatures.encode();
 		let mut target_value = hex::decode("0202020202020202020202020202020202020202020202020202020202020202").unwrap(); // channel_id
@@ -3197,9 +3800,20 @@ mod tests {
 		target_value.append(&mut hex::decode("3045022100ee00dbf4a862463e837d7c08509de814d620e4d9830fa84818713e0fa358f145022021c3c7060c4d53fe84fd165d60208451108a778c13b92ca4c6bad439236126cc01").unwrap());
 		target_value.append(&mut hex::decode("21").unwrap()); // len of witness element data (VarInt)
 		target_value.append(&mut hex::decode("028fbbf0b16f5ba5bcb5dd37cd4047ce6f726a21c06682f9ec2f52b057de1dbdb5").unwrap());
+		if with_funding_outpoint_sig {
+			target_value.push(0x00); // Type
+			target_value.push(0x40); // Length
+			target_value.append(&mut sig.serialize_compact().to_vec());
+		}
 		assert_eq!(encoded_value, target_value);
 	}
 
+	#[test]
+	fn encoding_tx_signatures() {
+		do_encoding_tx_signatures(true);
+		do_encoding_tx_signatures(false);
+	}
+
 	fn do_encoding_tx_init_rbf(funding_value_with_hex_target: Option<(i64, &str)>) {
 		let tx_init_rbf = msgs::TxInitRbf {
 			channel_id: [2; 32],
@@ -3259,6 +3873,99 @@ mod tests {
 		assert_eq!(encoded_value, target_value);
 	}
 
+	#[test]
+	fn encoding_splice() {
+		let secp_ctx = Secp256k1::new();
+		let (_, pubkey_1) = get_keys_from!("0101010101010101010101010101010101010101010101010101010101010101", secp_ctx);
+		let splice = msgs::Splice {
+			channel_id: [2; 32],
+			chain_hash: BlockHash::from_hex("6fe28c0ab6f1b372c1a6a246ae63f74f931e8365e15a089c68d6190000000000").unwrap(),
+			relative_satoshis: -123456,
+			funding_feerate_perkw: 2000,
+			locktime: 305419896,
+			funding_pubkey: pubkey_1,
+		};
+		let encoded_value = splice.encode();
+		let mut target_value = hex::decode("0202020202020202020202020202020202020202020202020202020202020202").unwrap(); // channel_id
+		target_value.append(&mut hex::decode("000000000019d6689c085ae165831e934ff763ae46a2a6c172b3f1b60a8ce26f").unwrap()); // chain_hash
+		target_value.append(&mut hex::decode("fffffffffffe1dc0").unwrap()); // relative_satoshis
+		target_value.append(&mut hex::decode("000007d0").unwrap()); // funding_feerate_perkw
+		target_value.append(&mut hex::decode("12345678").unwrap()); // locktime
+		target_value.append(&mut pubkey_1.serialize().to_vec()); // funding_pubkey
+		assert_eq!(encoded_value, target_value);
+		assert_eq!(msgs::Splice::read(&mut Cursor::new(&encoded_value)).unwrap(), splice);
+	}
+
+	#[test]
+	fn encoding_splice_ack() {
+		let secp_ctx = Secp256k1::new();
+		let (_, pubkey_1) = get_keys_from!("0101010101010101010101010101010101010101010101010101010101010101", secp_ctx);
+		let splice_ack = msgs::SpliceAck {
+			channel_id: [2; 32],
+			chain_hash: BlockHash::from_hex("6fe28c0ab6f1b372c1a6a246ae63f74f931e8365e15a089c68d6190000000000").unwrap(),
+			relative_satoshis: 123456,
+			funding_pubkey: pubkey_1,
+		};
+		let encoded_value = splice_ack.encode();
+		let mut target_value = hex::decode("0202020202020202020202020202020202020202020202020202020202020202").unwrap(); // channel_id
+		target_value.append(&mut hex::decode("000000000019d6689c085ae165831e934ff763ae46a2a6c172b3f1b60a8ce26f").unwrap()); // chain_hash
+		target_value.append(&mut hex::decode("000000000001e240").unwrap()); // relative_satoshis
+		target_value.append(&mut pubkey_1.serialize().to_vec()); // funding_pubkey
+		assert_eq!(encoded_value, target_value);
+		assert_eq!(msgs::SpliceAck::read(&mut Cursor::new(&encoded_value)).unwrap(), splice_ack);
+	}
+
+	#[test]
+	fn encoding_splice_locked() {
+		let splice_locked = msgs::SpliceLocked {
+			channel_id: [2; 32],
+			splice_txid: Txid::from_hex("c2d4449afa8d26140898dd54d3390b057ba2a5afcf03ba29d7dc0d8b9ffe966e").unwrap(),
+		};
+		let encoded_value = splice_locked.encode();
+		let target_value = hex::decode("02020202020202020202020202020202020202020202020202020202020202026e96fe9f8b0ddcd729ba03cfafa5a27b050b39d354dd980814268dfa9a44d4c2").unwrap();
+		assert_eq!(encoded_value, target_value);
+		assert_eq!(msgs::SpliceLocked::read(&mut Cursor::new(&encoded_value)).unwrap(), splice_locked);
+	}
+
+	#[test]
+	fn encoding_stfu() {
+		let stfu = msgs::Stfu {
+			channel_id: [2; 32],
+			initiator: true,
+		};
+		let encoded_value = stfu.encode();
+		let mut target_value = hex::decode("0202020202020202020202020202020202020202020202020202020202020202").unwrap(); // channel_id
+		target_value.append(&mut hex::decode("01").unwrap()); // initiator
+		assert_eq!(encoded_value, target_value);
+		assert_eq!(msgs::Stfu::read(&mut Cursor::new(&encoded_value)).unwrap(), stfu);
+	}
+
+	#[test]
+	fn encoding_dyn_propose() {
+		let dyn_propose = msgs::DynPropose {
+			channel_id: [2; 32],
+			channel_type: ChannelTypeFeatures::anchors_zero_htlc_fee_and_dependencies(),
+		};
+		let encoded_value = dyn_propose.encode();
+		let mut target_value = hex::decode("0202020202020202020202020202020202020202020202020202020202020202").unwrap(); // channel_id
+		target_value.append(&mut hex::decode("0003401000").unwrap()); // channel_type TLV
+		assert_eq!(encoded_value, target_value);
+		assert_eq!(msgs::DynPropose::read(&mut Cursor::new(&encoded_value)).unwrap(), dyn_propose);
+	}
+
+	#[test]
+	fn encoding_dyn_reject() {
+		let dyn_reject = msgs::DynReject {
+			channel_id: [2; 32],
+			data: vec![0x01, 0x02],
+		};
+		let encoded_value = dyn_reject.encode();
+		let mut target_value = hex::decode("0202020202020202020202020202020202020202020202020202020202020202").unwrap(); // channel_id
+		target_value.append(&mut hex::decode("00020102").unwrap()); // data
+		assert_eq!(encoded_value, target_value);
+		assert_eq!(msgs::DynReject::read(&mut Cursor::new(&encoded_value)).unwrap(), dyn_reject);
+	}
+
 	fn do_encoding_shutdown(script_type: u8) {
 		let secp_ctx = Secp256k1::new();
 		let (_, pubkey_1) = get_keys_from!("0101010101010101010101010101010101010101010101010101010101010101", secp_ctx);
@@ -3343,6 +4050,7 @@ mod tests {
 			cltv_expiry: 821716,
 			onion_routing_packet,
 			skimmed_fee_msat: None,
+			blinding_point: None,
 		};
 		let encoded_value = update_add_htlc.encode();
 		let target_value = hex::decode("020202020202020202020202020202020202020202020202020202020202020200083a840000034d32144668701144760101010101010101010101010101010101010101010101010101010101010101000c89d4ff031b84c5567b126440995d3ed5aaba0565d71e1834604819ff9c17f5e9d5dd078f010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010202020202020202020202020202020202020202020202020202020202020202").unwrap();
@@ -3389,7 +4097,7 @@ mod tests {
 		assert_eq!(encoded_value, target_value);
 	}
 
-	fn do_encoding_commitment_signed(htlcs: bool) {
+	fn do_encoding_commitment_signed(htlcs: bool, batch: bool) {
 		let secp_ctx = Secp256k1::new();
 		let (privkey_1, _) = get_keys_from!("0101010101010101010101010101010101010101010101010101010101010101", secp_ctx);
 		let (privkey_2, _) = get_keys_from!("0202020202020202020202020202020202020202020202020202020202020202", secp_ctx);
@@ -3403,6 +4111,10 @@ mod tests {
 			channel_id: [2; 32],
 			signature: sig_1,
 			htlc_signatures: if htlcs { vec![sig_2, sig_3, sig_4] } else { Vec::new() },
+			batch: if batch { Some(msgs::CommitmentSignedBatch {
+				batch_size: 2,
+				funding_txid: Txid::from_hex("c2d4449afa8d26140898dd54d3390b057ba2a5afcf03ba29d7dc0d8b9ffe966e").unwrap(),
+			}) } else { None },
 			#[cfg(taproot)]
 			partial_signature_with_nonce: None,
 		};
@@ -3413,13 +4125,20 @@ mod tests {
 		} else {
 			target_value.append(&mut hex::decode("0000").unwrap());
 		}
+		if batch {
+			target_value.append(&mut hex::decode("0022").unwrap());
+			target_value.append(&mut hex::decode("0002").unwrap()); // batch_size
+			target_value.append(&mut hex::decode("6e96fe9f8b0ddcd729ba03cfafa5a27b050b39d354dd980814268dfa9a44d4c2").unwrap()); // funding_txid
+		}
 		assert_eq!(encoded_value, target_value);
+		assert_eq!(msgs::CommitmentSigned::read(&mut Cursor::new(&encoded_value)).unwrap(), commitment_signed);
 	}
 
 	#[test]
 	fn encoding_commitment_signed() {
-		do_encoding_commitment_signed(true);
-		do_encoding_commitment_signed(false);
+		do_encoding_commitment_signed(true, false);
+		do_encoding_commitment_signed(false, false);
+		do_encoding_commitment_signed(true, true);
 	}
 
 	#[test]
@@ -3601,6 +4320,70 @@ mod tests {
 		assert_eq!(msg.outgoing_cltv_value, 0xffffffff);
 	}
 
+	#[test]
+	fn decoding_blinded_receive_onion_hop_data() {
+		use crate::blinded_path::BlindedPath;
+		use crate::blinded_path::payment::{PaymentConstraints, ReceiveTlvs};
+		use crate::ln::msgs::InboundOnionPayload;
+		use crate::sign::{KeysManager, NodeSigner, Recipient};
+		use crate::util::ser::ReadableArgs;
+
+		let secp_ctx = Secp256k1::new();
+		let keys_manager = KeysManager::new(&[42; 32], 42, 42);
+		let our_node_id = keys_manager.get_node_id(Recipient::Node).unwrap();
+		let payee_tlvs = ReceiveTlvs {
+			payment_secret: PaymentSecret([0x42; 32]),
+			payment_constraints: PaymentConstraints { max_cltv_expiry: 0xffffffff, htlc_minimum_msat: 1 },
+		};
+		let (_, path) = BlindedPath::one_hop_for_payment(
+			our_node_id, payee_tlvs, &keys_manager, &secp_ctx
+		).unwrap();
+
+		// We're the introduction node, so the blinding point is provided in the onion.
+		let encoded_payload = encode_blinded_receive_payload(&path, true).unwrap();
+		let payload = <InboundOnionPayload as ReadableArgs<(Option<PublicKey>, &&KeysManager)>>::read(
+			&mut Cursor::new(&encoded_payload[..]), (None, &&keys_manager)
+		).unwrap();
+		if let InboundOnionPayload::BlindedReceive {
+			amt_msat, total_msat, outgoing_cltv_value, payment_secret, payment_constraints,
+			intro_node_blinding_point
+		} = payload {
+			assert_eq!(amt_msat, 0x0badf00d01020304);
+			assert_eq!(total_msat, 0x1badca1f);
+			assert_eq!(outgoing_cltv_value, 0xffffffff);
+			assert_eq!(payment_secret, PaymentSecret([0x42; 32]));
+			assert_eq!(payment_constraints.htlc_minimum_msat, 1);
+			assert_eq!(intro_node_blinding_point, Some(path.blinding_point));
+		} else { panic!(); }
+
+		// The blinding point may not be provided both in the onion and in the `update_add_htlc`.
+		assert!(<InboundOnionPayload as ReadableArgs<(Option<PublicKey>, &&KeysManager)>>::read(
+			&mut Cursor::new(&encoded_payload[..]), (Some(path.blinding_point), &&keys_manager)
+		).is_err());
+
+		// Without any blinding point, the encrypted TLVs can't be decrypted.
+		let encoded_payload = encode_blinded_receive_payload(&path, false).unwrap();
+		assert!(<InboundOnionPayload as ReadableArgs<(Option<PublicKey>, &&KeysManager)>>::read(
+			&mut Cursor::new(&encoded_payload[..]), (None, &&keys_manager)
+		).is_err());
+	}
+	// see above test, needs to be a separate method for use of the serialization macros.
+	fn encode_blinded_receive_payload(
+		path: &crate::blinded_path::BlindedPath, include_blinding_point: bool
+	) -> Result<Vec<u8>, io::Error> {
+		use crate::util::ser::{HighZeroBytesDroppedBigSize, WithoutLength};
+		let blinding_point = if include_blinding_point { Some(path.blinding_point) } else { None };
+		let mut encoded_payload = Vec::new();
+		_encode_varint_length_prefixed_tlv!(&mut encoded_payload, {
+			(2, HighZeroBytesDroppedBigSize(0x0badf00d01020304u64), required),
+			(4, HighZeroBytesDroppedBigSize(0xffffffffu32), required),
+			(10, WithoutLength(&path.blinded_hops[0].encrypted_payload), required),
+			(12, blinding_point, option),
+			(18, HighZeroBytesDroppedBigSize(0x1badca1fu64), required)
+		});
+		Ok(encoded_payload)
+	}
+
 	#[test]
 	fn query_channel_range_end_blocknum() {
 		let tests: Vec<(u32, u32, u32)> = vec![
@@ -3614,6 +4397,7 @@ mod tests {
 				chain_hash: BlockHash::from_hex("06226e46111a0b59caaf126043eb5bbf28c34f3a5e332a1fc7b2b73cf188910f").unwrap(),
 				first_blocknum,
 				number_of_blocks,
+				query_option: None,
 			};
 			assert_eq!(sut.end_blocknum(), expected);
 		}
@@ -3625,6 +4409,7 @@ mod tests {
 			chain_hash: BlockHash::from_hex("06226e46111a0b59caaf126043eb5bbf28c34f3a5e332a1fc7b2b73cf188910f").unwrap(),
 			first_blocknum: 100000,
 			number_of_blocks: 1500,
+			query_option: None,
 		};
 		let encoded_value = query_channel_range.encode();
 		let target_value = hex::decode("0f9188f13cb7b2c71f2a335e3a4fc328bf5beb436012afca590b1a11466e2206000186a0000005dc").unwrap();
@@ -3633,6 +4418,15 @@ mod tests {
 		query_channel_range = Readable::read(&mut Cursor::new(&target_value[..])).unwrap();
 		assert_eq!(query_channel_range.first_blocknum, 100000);
 		assert_eq!(query_channel_range.number_of_blocks, 1500);
+		assert_eq!(query_channel_range.query_option, None);
+
+		query_channel_range.query_option = Some(msgs::QUERY_OPTION_TIMESTAMPS | msgs::QUERY_OPTION_CHECKSUMS);
+		let encoded_value = query_channel_range.encode();
+		let target_value = hex::decode("0f9188f13cb7b2c71f2a335e3a4fc328bf5beb436012afca590b1a11466e2206000186a0000005dc010103").unwrap();
+		assert_eq!(encoded_value, target_value);
+
+		let decoded: msgs::QueryChannelRange = Readable::read(&mut Cursor::new(&target_value[..])).unwrap();
+		assert_eq!(decoded, query_channel_range);
 	}
 
 	#[test]
@@ -3650,6 +4444,8 @@ mod tests {
 			number_of_blocks: 1500,
 			sync_complete: true,
 			short_channel_ids: vec![0x000000000000008e, 0x0000000000003c69, 0x000000000045a6c4],
+			timestamps: None,
+			checksums: None,
 		};
 
 		if encoding_type == 0 {
@@ -3672,6 +4468,35 @@ mod tests {
 		}
 	}
 
+	#[test]
+	fn encoding_reply_channel_range_with_timestamps_and_checksums() {
+		let mut target_value = hex::decode("0f9188f13cb7b2c71f2a335e3a4fc328bf5beb436012afca590b1a11466e2206000b8a06000005dc01").unwrap();
+		let reply_channel_range = msgs::ReplyChannelRange {
+			chain_hash: BlockHash::from_hex("06226e46111a0b59caaf126043eb5bbf28c34f3a5e332a1fc7b2b73cf188910f").unwrap(),
+			first_blocknum: 756230,
+			number_of_blocks: 1500,
+			sync_complete: true,
+			short_channel_ids: vec![0x000000000000008e, 0x0000000000003c69],
+			timestamps: Some(vec![(0x5f5e1000, 0), (0x5f5e1001, 0x5f5e1002)]),
+			checksums: Some(vec![(0xdeadbeef, 0), (0x01020304, 0x05060708)]),
+		};
+
+		target_value.append(&mut hex::decode("001100000000000000008e0000000000003c69").unwrap());
+		target_value.append(&mut hex::decode("0111005f5e1000000000005f5e10015f5e1002").unwrap());
+		target_value.append(&mut hex::decode("0310deadbeef000000000102030405060708").unwrap());
+		let encoded_value = reply_channel_range.encode();
+		assert_eq!(encoded_value, target_value);
+
+		let decoded: msgs::ReplyChannelRange = Readable::read(&mut Cursor::new(&target_value[..])).unwrap();
+		assert_eq!(decoded, reply_channel_range);
+
+		// The number of checksums must match the number of short_channel_ids.
+		let mut mismatched_reply = reply_channel_range.clone();
+		mismatched_reply.checksums.as_mut().unwrap().pop();
+		let result: Result<msgs::ReplyChannelRange, msgs::DecodeError> = Readable::read(&mut Cursor::new(&mismatched_reply.encode()[..]));
+		assert_eq!(result, Err(msgs::DecodeError::InvalidValue));
+	}
+
 	#[test]
 	fn encoding_query_short_channel_ids() {
 		do_encoding_query_short_channel_ids(0);
@@ -3684,6 +4509,7 @@ mod tests {
 		let mut query_short_channel_ids = msgs::QueryShortChannelIds {
 			chain_hash: expected_chain_hash,
 			short_channel_ids: vec![0x0000000000008e, 0x0000000000003c69, 0x000000000045a6c4],
+			query_flags: None,
 		};
 
 		if encoding_type == 0 {
@@ -3703,6 +4529,35 @@ mod tests {
 		}
 	}
 
+	#[test]
+	fn encoding_query_short_channel_ids_with_query_flags() {
+		let mut target_value = hex::decode("0f9188f13cb7b2c71f2a335e3a4fc328bf5beb436012afca590b1a11466e2206").unwrap();
+		let expected_chain_hash = BlockHash::from_hex("06226e46111a0b59caaf126043eb5bbf28c34f3a5e332a1fc7b2b73cf188910f").unwrap();
+		let query_short_channel_ids = msgs::QueryShortChannelIds {
+			chain_hash: expected_chain_hash,
+			short_channel_ids: vec![0x0000000000008e, 0x0000000000003c69, 0x000000000045a6c4],
+			query_flags: Some(vec![
+				msgs::QUERY_FLAG_CHANNEL_ANNOUNCEMENT,
+				msgs::QUERY_FLAG_CHANNEL_UPDATE_NODE_1 | msgs::QUERY_FLAG_CHANNEL_UPDATE_NODE_2,
+				0x1f,
+			]),
+		};
+
+		target_value.append(&mut hex::decode("001900000000000000008e0000000000003c69000000000045a6c4").unwrap());
+		target_value.append(&mut hex::decode("01040001061f").unwrap());
+		let encoded_value = query_short_channel_ids.encode();
+		assert_eq!(encoded_value, target_value);
+
+		let decoded: msgs::QueryShortChannelIds = Readable::read(&mut Cursor::new(&target_value[..])).unwrap();
+		assert_eq!(decoded, query_short_channel_ids);
+
+		// zlib-encoded query flags are not supported.
+		let len = target_value.len();
+		target_value[len - 4] = 1;
+		let result: Result<msgs::QueryShortChannelIds, msgs::DecodeError> = Readable::read(&mut Cursor::new(&target_value[..]));
+		assert_eq!(result, Err(msgs::DecodeError::UnsupportedCompression));
+	}
+
 	#[test]
 	fn encoding_reply_short_channel_ids_end() {
 		let expected_chain_hash = BlockHash::from_hex("06226e46111a0b59caaf126043eb5bbf28c34f3a5e332a1fc7b2b73cf188910f").unwrap();
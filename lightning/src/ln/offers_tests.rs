@@ -0,0 +1,324 @@
+// This file is Copyright its original authors, visible in version control
+// history.
+//
+// This file is licensed under the Apache License, Version 2.0 <LICENSE-APACHE
+// or http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your option.
+// You may not use this file except in accordance with one or both of these
+// licenses.
+
+//! Tests that test the payment flow for BOLT 12 offers and refunds, where [`InvoiceRequest`] and
+//! [`Bolt12Invoice`] messages are exchanged via [`OnionMessenger`] on behalf of the
+//! [`ChannelManager`].
+//!
+//! [`InvoiceRequest`]: crate::offers::invoice_request::InvoiceRequest
+//! [`Bolt12Invoice`]: crate::offers::invoice::Bolt12Invoice
+//! [`ChannelManager`]: crate::ln::channelmanager::ChannelManager
+
+use core::time::Duration;
+use crate::events::{Event, MessageSendEventsProvider, OnionMessageProvider, PaymentPurpose};
+use crate::ln::channelmanager::{self, PaymentId, RecentPaymentDetails, Retry};
+use crate::ln::features::InitFeatures;
+use crate::ln::functional_test_utils::*;
+use crate::ln::msgs::{self, ChannelMessageHandler, OnionMessageHandler};
+use crate::ln::peer_handler::IgnoringMessageHandler;
+use crate::offers::parse::Bolt12SemanticError;
+use crate::onion_message::{Destination, MessageRouter, OffersMessage, OffersMessageHandler, OnionMessagePath, OnionMessenger};
+use crate::util::test_utils;
+
+use bitcoin::secp256k1::PublicKey;
+
+use crate::prelude::*;
+
+/// A [`MessageRouter`] which sends onion messages directly to the destination, as all nodes in
+/// these tests are connected to each other.
+struct DirectMessageRouter {}
+
+impl MessageRouter for DirectMessageRouter {
+	fn find_path(
+		&self, _sender: PublicKey, _peers: Vec<PublicKey>, destination: Destination
+	) -> Result<OnionMessagePath, ()> {
+		Ok(OnionMessagePath { intermediate_nodes: vec![], destination })
+	}
+}
+
+type TestOnionMessenger<'a, 'b, 'c> = OnionMessenger<
+	&'b test_utils::TestKeysInterface,
+	&'b test_utils::TestKeysInterface,
+	&'c test_utils::TestLogger,
+	&'a DirectMessageRouter,
+	&'a TestChannelManager<'a, 'b, 'c>,
+	IgnoringMessageHandler,
+>;
+
+fn onion_message_init_features() -> InitFeatures {
+	let mut features = channelmanager::provided_init_features(&test_default_channel_config());
+	features.set_onion_messages_optional();
+	features
+}
+
+/// Creates an [`OnionMessenger`] for each node, using its [`ChannelManager`] as the offers message
+/// handler, and connects them to each other.
+///
+/// [`ChannelManager`]: crate::ln::channelmanager::ChannelManager
+fn create_onion_messengers<'a, 'b, 'c>(
+	nodes: &'a [Node<'a, 'b, 'c>], message_router: &'a DirectMessageRouter
+) -> Vec<TestOnionMessenger<'a, 'b, 'c>> {
+	let messengers: Vec<TestOnionMessenger> = nodes.iter()
+		.map(|node| OnionMessenger::new(
+			node.keys_manager, node.keys_manager, node.logger, message_router, node.node,
+			IgnoringMessageHandler {}
+		))
+		.collect();
+
+	for (i, messenger) in messengers.iter().enumerate() {
+		for (j, node) in nodes.iter().enumerate() {
+			if i == j { continue; }
+			let init = msgs::Init {
+				features: onion_message_init_features(), networks: None, remote_network_address: None,
+			};
+			messenger.peer_connected(&node.node.get_our_node_id(), &init, i < j).unwrap();
+		}
+	}
+
+	messengers
+}
+
+/// Delivers all onion messages pending from `from` to `to`, returning how many were delivered.
+fn pass_onion_messages<'a, 'b, 'c>(
+	from: (&Node<'a, 'b, 'c>, &TestOnionMessenger<'a, 'b, 'c>),
+	to: (&Node<'a, 'b, 'c>, &TestOnionMessenger<'a, 'b, 'c>),
+) -> usize {
+	let (from_node, from_messenger) = from;
+	let (to_node, to_messenger) = to;
+	let from_node_id = from_node.node.get_our_node_id();
+	let to_node_id = to_node.node.get_our_node_id();
+
+	let mut delivered = 0;
+	while let Some(onion_message) = from_messenger.next_onion_message_for_peer(to_node_id) {
+		to_messenger.handle_onion_message(&from_node_id, &onion_message);
+		delivered += 1;
+	}
+	delivered
+}
+
+/// Has each node advertise support for onion messages to its peers' [`ChannelManager`]s, which is
+/// needed to use them as an introduction node for blinded paths.
+///
+/// [`ChannelManager`]: crate::ln::channelmanager::ChannelManager
+fn enable_onion_messages(node_cfgs: &Vec<NodeCfg>) {
+	for node_cfg in node_cfgs.iter() {
+		*node_cfg.override_init_features.borrow_mut() = Some(onion_message_init_features());
+	}
+}
+
+#[test]
+fn creates_and_pays_for_offer() {
+	let chanmon_cfgs = create_chanmon_cfgs(2);
+	let node_cfgs = create_node_cfgs(2, &chanmon_cfgs);
+	enable_onion_messages(&node_cfgs);
+	let node_chanmgrs = create_node_chanmgrs(2, &node_cfgs, &[None, None]);
+	let nodes = create_network(2, &node_cfgs, &node_chanmgrs);
+	create_announced_chan_between_nodes(&nodes, 0, 1);
+
+	let message_router = DirectMessageRouter {};
+	let messengers = create_onion_messengers(&nodes, &message_router);
+
+	// The offer uses a blinded path through the recipient's only peer and a derived signing pubkey.
+	let offer = nodes[1].node
+		.create_offer_builder("coffee".to_string())
+		.amount_msats(10_000_000)
+		.build().unwrap();
+	assert_ne!(offer.signing_pubkey(), nodes[1].node.get_our_node_id());
+	assert_eq!(offer.paths().len(), 1);
+	assert_eq!(offer.paths()[0].introduction_node_id, nodes[0].node.get_our_node_id());
+
+	let payment_id = PaymentId([1; 32]);
+	nodes[0].node.pay_for_offer(&offer, None, None, None, payment_id, Retry::Attempts(0)).unwrap();
+	assert_eq!(
+		nodes[0].node.list_recent_payments(),
+		vec![RecentPaymentDetails::AwaitingInvoice { payment_id }]
+	);
+
+	// The same payment id can't be used for another request while one is pending.
+	assert_eq!(
+		nodes[0].node.pay_for_offer(&offer, None, None, None, payment_id, Retry::Attempts(0)),
+		Err(Bolt12SemanticError::DuplicatePaymentId)
+	);
+
+	// Deliver the invoice request and the responding invoice.
+	assert_eq!(pass_onion_messages((&nodes[0], &messengers[0]), (&nodes[1], &messengers[1])), 1);
+	assert_eq!(pass_onion_messages((&nodes[1], &messengers[1]), (&nodes[0], &messengers[0])), 1);
+	assert_eq!(pass_onion_messages((&nodes[0], &messengers[0]), (&nodes[1], &messengers[1])), 0);
+
+	// Upon receiving the invoice, the payer pays it using the invoice's blinded path.
+	route_bolt12_payment(&nodes[0], &nodes[1]);
+	claim_bolt12_payment(&nodes[0], &nodes[1], 10_000_000);
+}
+
+#[test]
+fn creates_refund_and_requests_payment() {
+	let chanmon_cfgs = create_chanmon_cfgs(2);
+	let node_cfgs = create_node_cfgs(2, &chanmon_cfgs);
+	enable_onion_messages(&node_cfgs);
+	let node_chanmgrs = create_node_chanmgrs(2, &node_cfgs, &[None, None]);
+	let nodes = create_network(2, &node_cfgs, &node_chanmgrs);
+	create_announced_chan_between_nodes(&nodes, 0, 1);
+
+	let message_router = DirectMessageRouter {};
+	let messengers = create_onion_messengers(&nodes, &message_router);
+
+	let absolute_expiry = Duration::from_secs(u64::max_value());
+	let payment_id = PaymentId([1; 32]);
+	let refund = nodes[0].node
+		.create_refund_builder(
+			"refund".to_string(), 10_000_000, absolute_expiry, payment_id, Retry::Attempts(0)
+		)
+		.unwrap()
+		.build().unwrap();
+	assert_ne!(refund.payer_id(), nodes[0].node.get_our_node_id());
+	assert_eq!(refund.paths().len(), 1);
+	assert_eq!(refund.paths()[0].introduction_node_id, nodes[1].node.get_our_node_id());
+	assert_eq!(
+		nodes[0].node.list_recent_payments(),
+		vec![RecentPaymentDetails::AwaitingInvoice { payment_id }]
+	);
+
+	// An invoice for a refund not created by the node isn't recognized.
+	let other_refund = nodes[1].node
+		.create_refund_builder(
+			"refund".to_string(), 10_000_000, absolute_expiry, PaymentId([2; 32]), Retry::Attempts(0)
+		)
+		.unwrap()
+		.build().unwrap();
+	nodes[0].node.request_refund_payment(&other_refund).unwrap();
+	let invoice = match nodes[0].node.release_pending_messages().pop().unwrap().contents {
+		OffersMessage::Invoice(invoice) => invoice,
+		_ => panic!("Expected an invoice"),
+	};
+	match nodes[0].node.handle_message(OffersMessage::Invoice(invoice)) {
+		Some(OffersMessage::InvoiceError(_)) => {},
+		_ => panic!("Expected an invoice error"),
+	}
+
+	// Deliver the invoice for the refund.
+	nodes[1].node.request_refund_payment(&refund).unwrap();
+	assert_eq!(pass_onion_messages((&nodes[1], &messengers[1]), (&nodes[0], &messengers[0])), 1);
+	assert_eq!(pass_onion_messages((&nodes[0], &messengers[0]), (&nodes[1], &messengers[1])), 0);
+
+	// Upon receiving the invoice, the payer pays it using the invoice's blinded path.
+	route_bolt12_payment(&nodes[0], &nodes[1]);
+	claim_bolt12_payment(&nodes[0], &nodes[1], 10_000_000);
+}
+
+#[test]
+fn fails_invoice_request_for_unknown_offer() {
+	let chanmon_cfgs = create_chanmon_cfgs(2);
+	let node_cfgs = create_node_cfgs(2, &chanmon_cfgs);
+	enable_onion_messages(&node_cfgs);
+	let node_chanmgrs = create_node_chanmgrs(2, &node_cfgs, &[None, None]);
+	let nodes = create_network(2, &node_cfgs, &node_chanmgrs);
+	create_announced_chan_between_nodes(&nodes, 0, 1);
+
+	// An offer built by the payer itself is unknown to the payee's expanded key.
+	let offer = nodes[0].node
+		.create_offer_builder("coffee".to_string())
+		.amount_msats(10_000_000)
+		.build().unwrap();
+	nodes[0].node.pay_for_offer(&offer, None, None, None, PaymentId([1; 32]), Retry::Attempts(0))
+		.unwrap();
+
+	let invoice_request = match nodes[0].node.release_pending_messages().pop().unwrap().contents {
+		OffersMessage::InvoiceRequest(invoice_request) => invoice_request,
+		_ => panic!("Expected an invoice request"),
+	};
+	match nodes[1].node.handle_message(OffersMessage::InvoiceRequest(invoice_request)) {
+		Some(OffersMessage::InvoiceError(_)) => {},
+		_ => panic!("Expected an invoice error"),
+	}
+}
+
+#[test]
+fn expires_or_abandons_invoice_request() {
+	let chanmon_cfgs = create_chanmon_cfgs(2);
+	let node_cfgs = create_node_cfgs(2, &chanmon_cfgs);
+	enable_onion_messages(&node_cfgs);
+	let node_chanmgrs = create_node_chanmgrs(2, &node_cfgs, &[None, None]);
+	let nodes = create_network(2, &node_cfgs, &node_chanmgrs);
+	create_announced_chan_between_nodes(&nodes, 0, 1);
+
+	let offer = nodes[1].node
+		.create_offer_builder("coffee".to_string())
+		.amount_msats(10_000_000)
+		.build().unwrap();
+
+	// An unanswered invoice request fails after a couple of timer ticks.
+	let payment_id = PaymentId([1; 32]);
+	nodes[0].node.pay_for_offer(&offer, None, None, None, payment_id, Retry::Attempts(0)).unwrap();
+	assert_eq!(nodes[0].node.release_pending_messages().len(), 1);
+
+	nodes[0].node.timer_tick_occurred();
+	assert!(nodes[0].node.get_and_clear_pending_events().is_empty());
+	nodes[0].node.timer_tick_occurred();
+	expect_invoice_request_failed(&nodes[0], payment_id);
+	assert!(nodes[0].node.list_recent_payments().is_empty());
+
+	// An abandoned invoice request fails immediately, and a later invoice is not paid.
+	let payment_id = PaymentId([2; 32]);
+	nodes[0].node.pay_for_offer(&offer, None, None, None, payment_id, Retry::Attempts(0)).unwrap();
+	let invoice_request = match nodes[0].node.release_pending_messages().pop().unwrap().contents {
+		OffersMessage::InvoiceRequest(invoice_request) => invoice_request,
+		_ => panic!("Expected an invoice request"),
+	};
+
+	nodes[0].node.abandon_payment(payment_id);
+	expect_invoice_request_failed(&nodes[0], payment_id);
+
+	let invoice = match nodes[1].node.handle_message(OffersMessage::InvoiceRequest(invoice_request)) {
+		Some(OffersMessage::Invoice(invoice)) => invoice,
+		_ => panic!("Expected an invoice"),
+	};
+	assert!(nodes[0].node.handle_message(OffersMessage::Invoice(invoice)).is_none());
+	assert!(nodes[0].node.get_and_clear_pending_events().is_empty());
+	assert!(nodes[0].node.get_and_clear_pending_msg_events().is_empty());
+}
+
+fn expect_invoice_request_failed<'a, 'b, 'c>(node: &Node<'a, 'b, 'c>, payment_id: PaymentId) {
+	let events = node.node.get_and_clear_pending_events();
+	assert_eq!(events.len(), 1, "{:?}", events);
+	match events[0] {
+		Event::InvoiceRequestFailed { payment_id: event_payment_id } => {
+			assert_eq!(event_payment_id, payment_id);
+		},
+		_ => panic!("Unexpected event {:?}", events[0]),
+	}
+}
+
+/// Delivers the HTLC sent by `payer` over a one-hop blinded path to `payee`.
+fn route_bolt12_payment<'a, 'b, 'c>(payer: &Node<'a, 'b, 'c>, payee: &Node<'a, 'b, 'c>) {
+	check_added_monitors(payer, 1);
+	let payment_event = SendEvent::from_node(payer);
+	assert_eq!(payment_event.node_id, payee.node.get_our_node_id());
+	payee.node.handle_update_add_htlc(&payer.node.get_our_node_id(), &payment_event.msgs[0]);
+	commitment_signed_dance!(payee, payer, payment_event.commitment_msg, false);
+	expect_pending_htlcs_forwardable!(payee);
+}
+
+fn claim_bolt12_payment<'a, 'b, 'c>(
+	payer: &Node<'a, 'b, 'c>, payee: &Node<'a, 'b, 'c>, amount_msats: u64
+) {
+	let events = payee.node.get_and_clear_pending_events();
+	assert_eq!(events.len(), 1, "{:?}", events);
+	let payment_preimage = match events[0] {
+		Event::PaymentClaimable {
+			amount_msat, purpose: PaymentPurpose::InvoicePayment {
+				payment_preimage: Some(payment_preimage), ..
+			}, ..
+		} => {
+			assert_eq!(amount_msat, amount_msats);
+			payment_preimage
+		},
+		_ => panic!("Unexpected event {:?}", events[0]),
+	};
+	claim_payment(payer, &[payee], payment_preimage);
+}
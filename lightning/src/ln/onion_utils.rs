@@ -12,7 +12,8 @@ use crate::ln::channelmanager::{HTLCSource, RecipientOnionFields};
 use crate::ln::msgs;
 use crate::ln::wire::Encode;
 use crate::routing::gossip::NetworkUpdate;
-use crate::routing::router::{Path, RouteHop};
+use crate::routing::router::{BlindedTail, Path, RouteHop};
+use crate::sign::NodeSigner;
 use crate::util::chacha20::{ChaCha20, ChaChaReader};
 use crate::util::errors::{self, APIError};
 use crate::util::ser::{Readable, ReadableArgs, Writeable, Writer, LengthCalculatingWriter};
@@ -103,13 +104,21 @@ pub(crate) fn next_hop_packet_pubkey<T: secp256k1::Signing + secp256k1::Verifica
 }
 
 // can only fail if an intermediary hop has an invalid public key or session_priv is invalid
+//
+// The callback is provided the `RouteHop` for each unblinded hop, and `None` for each hop within
+// the blinded tail after the introduction node.
 #[inline]
-pub(super) fn construct_onion_keys_callback<T: secp256k1::Signing, FType: FnMut(SharedSecret, [u8; 32], PublicKey, &RouteHop, usize)> (secp_ctx: &Secp256k1<T>, path: &Vec<RouteHop>, session_priv: &SecretKey, mut callback: FType) -> Result<(), secp256k1::Error> {
+pub(super) fn construct_onion_keys_callback<T: secp256k1::Signing, FType: FnMut(SharedSecret, [u8; 32], PublicKey, Option<&RouteHop>, usize)> (secp_ctx: &Secp256k1<T>, path: &Path, session_priv: &SecretKey, mut callback: FType) -> Result<(), secp256k1::Error> {
 	let mut blinded_priv = session_priv.clone();
 	let mut blinded_pub = PublicKey::from_secret_key(secp_ctx, &blinded_priv);
 
-	for (idx, hop) in path.iter().enumerate() {
-		let shared_secret = SharedSecret::new(&hop.pubkey, &blinded_priv);
+	let unblinded_hops_iter = path.hops.iter().map(|h| (&h.pubkey, Some(h)));
+	let blinded_pks_iter = path.blinded_tail.as_ref()
+		.map(|t| t.hops.iter()).unwrap_or([].iter())
+		.skip(1) // Skip the intro node because it's included in the unblinded hops
+		.map(|h| (&h.blinded_node_id, None));
+	for (idx, (pubkey, route_hop_opt)) in unblinded_hops_iter.chain(blinded_pks_iter).enumerate() {
+		let shared_secret = SharedSecret::new(pubkey, &blinded_priv);
 
 		let mut sha = Sha256::engine();
 		sha.input(&blinded_pub.serialize()[..]);
@@ -121,7 +130,7 @@ pub(super) fn construct_onion_keys_callback<T: secp256k1::Signing, FType: FnMut(
 		blinded_priv = blinded_priv.mul_tweak(&Scalar::from_be_bytes(blinding_factor).unwrap())?;
 		blinded_pub = PublicKey::from_secret_key(secp_ctx, &blinded_priv);
 
-		callback(shared_secret, blinding_factor, ephemeral_pubkey, hop, idx);
+		callback(shared_secret, blinding_factor, ephemeral_pubkey, route_hop_opt, idx);
 	}
 
 	Ok(())
@@ -129,9 +138,9 @@ pub(super) fn construct_onion_keys_callback<T: secp256k1::Signing, FType: FnMut(
 
 // can only fail if an intermediary hop has an invalid public key or session_priv is invalid
 pub(super) fn construct_onion_keys<T: secp256k1::Signing>(secp_ctx: &Secp256k1<T>, path: &Path, session_priv: &SecretKey) -> Result<Vec<OnionKeys>, secp256k1::Error> {
-	let mut res = Vec::with_capacity(path.hops.len());
+	let mut res = Vec::with_capacity(path.hops.len() + path.blinded_tail.as_ref().map_or(0, |t| t.hops.len()));
 
-	construct_onion_keys_callback(secp_ctx, &path.hops, session_priv, |shared_secret, _blinding_factor, ephemeral_pubkey, _, _| {
+	construct_onion_keys_callback(secp_ctx, &path, session_priv, |shared_secret, _blinding_factor, ephemeral_pubkey, _, _| {
 		let (rho, mu) = gen_rho_mu_from_shared_secret(shared_secret.as_ref());
 
 		res.push(OnionKeys {
@@ -153,7 +162,9 @@ pub(super) fn build_onion_payloads(path: &Path, total_msat: u64, mut recipient_o
 	let mut cur_value_msat = 0u64;
 	let mut cur_cltv = starting_htlc_offset;
 	let mut last_short_channel_id = 0;
-	let mut res: Vec<msgs::OnionHopData> = Vec::with_capacity(path.hops.len());
+	let mut res: Vec<msgs::OnionHopData> = Vec::with_capacity(
+		path.hops.len() + path.blinded_tail.as_ref().map_or(0, |t| t.hops.len())
+	);
 
 	for (idx, hop) in path.hops.iter().rev().enumerate() {
 		// First hop gets special values so that it can check, on receipt, that everything is
@@ -161,26 +172,63 @@ pub(super) fn build_onion_payloads(path: &Path, total_msat: u64, mut recipient_o
 		// the intended recipient).
 		let value_msat = if cur_value_msat == 0 { hop.fee_msat } else { cur_value_msat };
 		let cltv = if cur_cltv == starting_htlc_offset { hop.cltv_expiry_delta + starting_htlc_offset } else { cur_cltv };
-		res.insert(0, msgs::OnionHopData {
-			format: if idx == 0 {
-				msgs::OnionHopDataFormat::FinalNode {
-					payment_data: if let Some(secret) = recipient_onion.payment_secret.take() {
-						Some(msgs::FinalOnionHopData {
-							payment_secret: secret,
+		if idx == 0 {
+			if let Some(BlindedTail {
+				blinding_point, hops, final_value_msat, excess_final_cltv_expiry_delta, ..
+			}) = &path.blinded_tail {
+				// The last unblinded hop is the introduction node, whose payload (along with those of
+				// the rest of the blinded path) is built from the recipient-provided encrypted TLVs.
+				if keysend_preimage.is_some() || recipient_onion.payment_metadata.is_some() {
+					return Err(APIError::InvalidRoute {
+						err: "Keysend and payment metadata are not supported when paying to a blinded path".to_owned()
+					});
+				}
+				let mut blinding_point = Some(*blinding_point);
+				for (i, blinded_hop) in hops.iter().enumerate() {
+					let format = if i == hops.len() - 1 {
+						msgs::OnionHopDataFormat::BlindedFinalNode {
 							total_msat,
-						})
-					} else { None },
-					payment_metadata: recipient_onion.payment_metadata.take(),
-					keysend_preimage: *keysend_preimage,
+							encrypted_tlvs: blinded_hop.encrypted_payload.clone(),
+							intro_node_blinding_point: blinding_point.take(),
+						}
+					} else {
+						msgs::OnionHopDataFormat::BlindedNode {
+							encrypted_tlvs: blinded_hop.encrypted_payload.clone(),
+							intro_node_blinding_point: blinding_point.take(),
+						}
+					};
+					res.push(msgs::OnionHopData {
+						format,
+						amt_to_forward: *final_value_msat,
+						outgoing_cltv_value: starting_htlc_offset + excess_final_cltv_expiry_delta,
+					});
 				}
+				cur_value_msat += final_value_msat;
 			} else {
-				msgs::OnionHopDataFormat::NonFinalNode {
+				res.insert(0, msgs::OnionHopData {
+					format: msgs::OnionHopDataFormat::FinalNode {
+						payment_data: if let Some(secret) = recipient_onion.payment_secret.take() {
+							Some(msgs::FinalOnionHopData {
+								payment_secret: secret,
+								total_msat,
+							})
+						} else { None },
+						payment_metadata: recipient_onion.payment_metadata.take(),
+						keysend_preimage: *keysend_preimage,
+					},
+					amt_to_forward: value_msat,
+					outgoing_cltv_value: cltv,
+				});
+			}
+		} else {
+			res.insert(0, msgs::OnionHopData {
+				format: msgs::OnionHopDataFormat::NonFinalNode {
 					short_channel_id: last_short_channel_id,
-				}
-			},
-			amt_to_forward: value_msat,
-			outgoing_cltv_value: cltv,
-		});
+				},
+				amt_to_forward: value_msat,
+				outgoing_cltv_value: cltv,
+			});
+		}
 		cur_value_msat += hop.fee_msat;
 		if cur_value_msat >= 21000000 * 100000000 * 1000 {
 			return Err(APIError::InvalidRoute{err: "Channel fees overflowed?".to_owned()});
@@ -198,6 +246,10 @@ pub(super) fn build_onion_payloads(path: &Path, total_msat: u64, mut recipient_o
 /// the hops can be of variable length.
 pub(crate) const ONION_DATA_LEN: usize = 20*65;
 
+/// The failure code returned by nodes within a blinded path when they can't forward or receive an
+/// HTLC, so as not to leak where in the path the failure occurred.
+pub(crate) const INVALID_ONION_BLINDING: u16 = 0x8000 | 0x4000 | 24;
+
 #[inline]
 fn shift_slice_right(arr: &mut [u8], amt: usize) {
 	for i in (amt..arr.len()).rev() {
@@ -376,23 +428,47 @@ pub(super) fn build_first_hop_failure_packet(shared_secret: &[u8], failure_type:
 	encrypt_failure_packet(shared_secret, &failure_packet.encode()[..])
 }
 
+/// The result of decoding a failure we got back from upstream on a payment we sent.
+pub(super) struct DecodedOnionFailure {
+	pub(super) network_update: Option<NetworkUpdate>,
+	pub(super) short_channel_id: Option<u64>,
+	pub(super) payment_retryable: bool,
+	/// Whether the failure originated from within the blinded path at the end of the failed
+	/// [`Path`], in which case that blinded path should be avoided when retrying.
+	pub(super) failed_within_blinded_path: bool,
+	#[cfg(test)]
+	pub(super) onion_error_code: Option<u16>,
+	#[cfg(test)]
+	pub(super) onion_error_data: Option<Vec<u8>>,
+}
+
 /// Process failure we got back from upstream on a payment we sent (implying htlc_source is an
 /// OutboundRoute).
-/// Returns update, a boolean indicating that the payment itself failed, the short channel id of
-/// the responsible channel, and the error code.
 #[inline]
-pub(super) fn process_onion_failure<T: secp256k1::Signing, L: Deref>(secp_ctx: &Secp256k1<T>, logger: &L, htlc_source: &HTLCSource, mut packet_decrypted: Vec<u8>) -> (Option<NetworkUpdate>, Option<u64>, bool, Option<u16>, Option<Vec<u8>>) where L::Target: Logger {
+pub(super) fn process_onion_failure<T: secp256k1::Signing, L: Deref>(secp_ctx: &Secp256k1<T>, logger: &L, htlc_source: &HTLCSource, mut packet_decrypted: Vec<u8>) -> DecodedOnionFailure where L::Target: Logger {
 	if let &HTLCSource::OutboundRoute { ref path, ref session_priv, ref first_hop_htlc_msat, .. } = htlc_source {
 		let mut res = None;
 		let mut htlc_msat = *first_hop_htlc_msat;
 		let mut error_code_ret = None;
 		let mut error_packet_ret = None;
 		let mut is_from_final_node = false;
+		let mut failed_within_blinded_path = false;
+		let num_blinded_hops = path.blinded_tail.as_ref().map_or(0, |bt| bt.hops.len());
 
 		// Handle packed channel/node updates for passing back for the route handler
-		construct_onion_keys_callback(secp_ctx, &path.hops, session_priv, |shared_secret, _, _, route_hop, route_hop_idx| {
+		construct_onion_keys_callback(secp_ctx, &path, session_priv, |shared_secret, _, _, route_hop_opt, route_hop_idx| {
 			if res.is_some() { return; }
 
+			let route_hop = match route_hop_opt {
+				Some(hop) => hop,
+				None => {
+					// Nodes within a blinded path after the introduction node fail back with
+					// `update_fail_malformed_htlc`, so we should never be able to decrypt an error
+					// from them.
+					return
+				},
+			};
+
 			let amt_to_forward = htlc_msat - route_hop.fee_msat;
 			htlc_msat = amt_to_forward;
 
@@ -405,9 +481,13 @@ pub(super) fn process_onion_failure<T: secp256k1::Signing, L: Deref>(secp_ctx: &
 			packet_decrypted = decryption_tmp;
 
 			// The failing hop includes either the inbound channel to the recipient or the outbound
-			// channel from the current hop (i.e., the next hop's inbound channel).
-			is_from_final_node = route_hop_idx + 1 == path.hops.len();
-			let failing_route_hop = if is_from_final_node { route_hop } else { &path.hops[route_hop_idx + 1] };
+			// channel from the current hop (i.e., the next hop's inbound channel). For 1-hop blinded
+			// paths, the final `path.hops` entry is the recipient.
+			is_from_final_node = route_hop_idx + 1 == path.hops.len() && num_blinded_hops <= 1;
+			let is_from_blinded_intro_node = route_hop_idx + 1 == path.hops.len() && num_blinded_hops > 1;
+			let failing_route_hop = if is_from_final_node || is_from_blinded_intro_node {
+				route_hop
+			} else { &path.hops[route_hop_idx + 1] };
 
 			if let Ok(err_packet) = msgs::DecodedOnionErrorPacket::read(&mut Cursor::new(&packet_decrypted)) {
 				let um = gen_um_from_shared_secret(shared_secret.as_ref());
@@ -425,6 +505,16 @@ pub(super) fn process_onion_failure<T: secp256k1::Signing, L: Deref>(secp_ctx: &
 						error_code_ret = Some(error_code);
 						error_packet_ret = Some(err_packet.failuremsg[2..].to_vec());
 
+						if is_from_blinded_intro_node {
+							// The introduction node of a multi-hop blinded path is failing the HTLC
+							// on behalf of itself or a node within the blinded path, which we can't
+							// attribute any further, so just avoid the blinded path when retrying.
+							log_info!(logger, "Onion Error[from blinded path with introduction node {}: {:#x}]", route_hop.pubkey, error_code);
+							failed_within_blinded_path = true;
+							res = Some((None, None, true));
+							return
+						}
+
 						let (debug_field, debug_field_size) = errors::get_onion_debug_field(error_code);
 
 						// indicate that payment parameter has failed and no need to
@@ -591,12 +681,25 @@ pub(super) fn process_onion_failure<T: secp256k1::Signing, L: Deref>(secp_ctx: &
 				}
 			}
 		}).expect("Route that we sent via spontaneously grew invalid keys in the middle of it?");
-		if let Some((channel_update, short_channel_id, payment_retryable)) = res {
-			(channel_update, short_channel_id, payment_retryable, error_code_ret, error_packet_ret)
+		if let Some((network_update, short_channel_id, payment_retryable)) = res {
+			DecodedOnionFailure {
+				network_update, short_channel_id, payment_retryable, failed_within_blinded_path,
+				#[cfg(test)]
+				onion_error_code: error_code_ret,
+				#[cfg(test)]
+				onion_error_data: error_packet_ret,
+			}
 		} else {
 			// only not set either packet unparseable or hmac does not match with any
 			// payment not retryable only when garbage is from the final node
-			(None, None, !is_from_final_node, None, None)
+			DecodedOnionFailure {
+				network_update: None, short_channel_id: None, payment_retryable: !is_from_final_node,
+				failed_within_blinded_path: false,
+				#[cfg(test)]
+				onion_error_code: None,
+				#[cfg(test)]
+				onion_error_data: None,
+			}
 		}
 	} else { unreachable!(); }
 }
@@ -684,6 +787,7 @@ impl HTLCFailReason {
 		else if failure_code == 21 { debug_assert!(data.is_empty()) }
 		else if failure_code == 22 | PERM { debug_assert!(data.len() <= 11) }
 		else if failure_code == 23 { debug_assert!(data.is_empty()) }
+		else if failure_code == INVALID_ONION_BLINDING { debug_assert_eq!(data.len(), 32) }
 		else if failure_code & BADONION != 0 {
 			// We set some bogus BADONION failure codes in test, so ignore unknown ones.
 		}
@@ -721,12 +825,13 @@ impl HTLCFailReason {
 
 	pub(super) fn decode_onion_failure<T: secp256k1::Signing, L: Deref>(
 		&self, secp_ctx: &Secp256k1<T>, logger: &L, htlc_source: &HTLCSource
-	) -> (Option<NetworkUpdate>, Option<u64>, bool, Option<u16>, Option<Vec<u8>>)
+	) -> DecodedOnionFailure
 	where L::Target: Logger {
 		match self.0 {
 			HTLCFailReasonRepr::LightningError { ref err } => {
 				process_onion_failure(secp_ctx, logger, &htlc_source, err.data.clone())
 			},
+			#[allow(unused)]
 			HTLCFailReasonRepr::Reason { ref failure_code, ref data, .. } => {
 				// we get a fail_malformed_htlc from the first hop
 				// TODO: We'd like to generate a NetworkUpdate for temporary
@@ -734,7 +839,16 @@ impl HTLCFailReason {
 				// generally ignores its view of our own channels as we provide them via
 				// ChannelDetails.
 				if let &HTLCSource::OutboundRoute { ref path, .. } = htlc_source {
-					(None, Some(path.hops[0].short_channel_id), true, Some(*failure_code), Some(data.clone()))
+					DecodedOnionFailure {
+						network_update: None,
+						short_channel_id: Some(path.hops[0].short_channel_id),
+						payment_retryable: true,
+						failed_within_blinded_path: false,
+						#[cfg(test)]
+						onion_error_code: Some(*failure_code),
+						#[cfg(test)]
+						onion_error_data: Some(data.clone()),
+					}
 				} else { unreachable!(); }
 			}
 		}
@@ -763,11 +877,11 @@ impl NextPacketBytes for Vec<u8> {
 pub(crate) enum Hop {
 	/// This onion payload was for us, not for forwarding to a next-hop. Contains information for
 	/// verifying the incoming payment.
-	Receive(msgs::OnionHopData),
+	Receive(msgs::InboundOnionPayload),
 	/// This onion payload needs to be forwarded to a next-hop.
 	Forward {
 		/// Onion payload data used in forwarding the payment.
-		next_hop_data: msgs::OnionHopData,
+		next_hop_data: msgs::InboundOnionPayload,
 		/// HMAC of the next hop's onion packet.
 		next_hop_hmac: [u8; 32],
 		/// Bytes of the onion packet we're forwarding.
@@ -790,8 +904,11 @@ pub(crate) enum OnionDecodeErr {
 	},
 }
 
-pub(crate) fn decode_next_payment_hop(shared_secret: [u8; 32], hop_data: &[u8], hmac_bytes: [u8; 32], payment_hash: PaymentHash) -> Result<Hop, OnionDecodeErr> {
-	match decode_next_hop(shared_secret, hop_data, hmac_bytes, Some(payment_hash), ()) {
+pub(crate) fn decode_next_payment_hop<NS: Deref>(
+	shared_secret: [u8; 32], hop_data: &[u8], hmac_bytes: [u8; 32], payment_hash: PaymentHash,
+	blinding_point: Option<PublicKey>, node_signer: &NS
+) -> Result<Hop, OnionDecodeErr> where NS::Target: NodeSigner {
+	match decode_next_hop(shared_secret, hop_data, hmac_bytes, Some(payment_hash), (blinding_point, node_signer)) {
 		Ok((next_hop_data, None)) => Ok(Hop::Receive(next_hop_data)),
 		Ok((next_hop_data, Some((next_hop_hmac, FixedSizeOnionPacket(new_packet_bytes))))) => {
 			Ok(Hop::Forward {
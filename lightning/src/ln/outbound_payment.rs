@@ -15,19 +15,23 @@ use bitcoin::secp256k1::{self, Secp256k1, SecretKey};
 
 use crate::sign::{EntropySource, NodeSigner, Recipient};
 use crate::events::{self, PaymentFailureReason};
+use crate::io;
 use crate::ln::{PaymentHash, PaymentPreimage, PaymentSecret};
 use crate::ln::channelmanager::{ChannelDetails, EventCompletionAction, HTLCSource, IDEMPOTENCY_TIMEOUT_TICKS, PaymentId};
-use crate::ln::onion_utils::HTLCFailReason;
-use crate::routing::router::{InFlightHtlcs, Path, PaymentParameters, Route, RouteParameters, Router};
+use crate::ln::msgs::DecodeError;
+use crate::ln::onion_utils::{DecodedOnionFailure, HTLCFailReason};
+use crate::offers::invoice::Bolt12Invoice;
+use crate::routing::router::{BlindedTail, InFlightHtlcs, Path, PaymentParameters, Route, RouteParameters, Router};
 use crate::util::errors::APIError;
 use crate::util::logger::Logger;
 use crate::util::time::Time;
 #[cfg(all(not(feature = "no-std"), test))]
 use crate::util::time::tests::SinceEpoch;
-use crate::util::ser::ReadableArgs;
+use crate::util::ser::{Readable, ReadableArgs, Writeable, Writer};
 
 use core::fmt::{self, Display, Formatter};
 use core::ops::Deref;
+use core::time::Duration;
 
 use crate::prelude::*;
 use crate::sync::Mutex;
@@ -38,6 +42,23 @@ pub(crate) enum PendingOutboundPayment {
 	Legacy {
 		session_privs: HashSet<[u8; 32]>,
 	},
+	/// We have requested an invoice (e.g., via an [`InvoiceRequest`] for an [`Offer`] or by
+	/// creating a [`Refund`]) and are waiting to receive it. Once received, the payment for it is
+	/// sent using `retry_strategy`.
+	///
+	/// [`InvoiceRequest`]: crate::offers::invoice_request::InvoiceRequest
+	/// [`Offer`]: crate::offers::offer::Offer
+	/// [`Refund`]: crate::offers::refund::Refund
+	AwaitingInvoice {
+		expiration: StaleExpiration,
+		retry_strategy: Retry,
+	},
+	/// We have received the invoice we were waiting for and are about to route and send the
+	/// payment for it.
+	InvoiceReceived {
+		payment_hash: PaymentHash,
+		retry_strategy: Retry,
+	},
 	Retryable {
 		retry_strategy: Option<Retry>,
 		attempts: PaymentAttempts,
@@ -107,6 +128,11 @@ impl PendingOutboundPayment {
 			params.previously_failed_channels.push(scid);
 		}
 	}
+	fn insert_previously_failed_blinded_path(&mut self, blinded_tail: &BlindedTail) {
+		if let PendingOutboundPayment::Retryable { payment_params: Some(params), .. } = self {
+			params.insert_previously_failed_blinded_path(blinded_tail);
+		}
+	}
 	pub(super) fn is_fulfilled(&self) -> bool {
 		match self {
 			PendingOutboundPayment::Fulfilled { .. } => true,
@@ -119,6 +145,12 @@ impl PendingOutboundPayment {
 			_ => false,
 		}
 	}
+	fn is_awaiting_invoice(&self) -> bool {
+		match self {
+			PendingOutboundPayment::AwaitingInvoice { .. } => true,
+			_ => false,
+		}
+	}
 	fn get_pending_fee_msat(&self) -> Option<u64> {
 		match self {
 			PendingOutboundPayment::Retryable { pending_fee_msat, .. } => pending_fee_msat.clone(),
@@ -129,6 +161,8 @@ impl PendingOutboundPayment {
 	fn payment_hash(&self) -> Option<PaymentHash> {
 		match self {
 			PendingOutboundPayment::Legacy { .. } => None,
+			PendingOutboundPayment::AwaitingInvoice { .. } => None,
+			PendingOutboundPayment::InvoiceReceived { payment_hash, .. } => Some(*payment_hash),
 			PendingOutboundPayment::Retryable { payment_hash, .. } => Some(*payment_hash),
 			PendingOutboundPayment::Fulfilled { payment_hash, .. } => *payment_hash,
 			PendingOutboundPayment::Abandoned { payment_hash, .. } => Some(*payment_hash),
@@ -141,8 +175,9 @@ impl PendingOutboundPayment {
 			PendingOutboundPayment::Legacy { session_privs } |
 				PendingOutboundPayment::Retryable { session_privs, .. } |
 				PendingOutboundPayment::Fulfilled { session_privs, .. } |
-				PendingOutboundPayment::Abandoned { session_privs, .. }
-			=> session_privs,
+				PendingOutboundPayment::Abandoned { session_privs, .. } => session_privs,
+			PendingOutboundPayment::AwaitingInvoice { .. } |
+				PendingOutboundPayment::InvoiceReceived { .. } => { debug_assert!(false); return; },
 		});
 		let payment_hash = self.payment_hash();
 		*self = PendingOutboundPayment::Fulfilled { session_privs, payment_hash, timer_ticks_without_htlcs: 0 };
@@ -157,6 +192,12 @@ impl PendingOutboundPayment {
 				payment_hash: *payment_hash,
 				reason: Some(reason)
 			};
+		} else if let PendingOutboundPayment::InvoiceReceived { payment_hash, .. } = self {
+			*self = PendingOutboundPayment::Abandoned {
+				session_privs: HashSet::new(),
+				payment_hash: *payment_hash,
+				reason: Some(reason)
+			};
 		}
 	}
 
@@ -168,7 +209,9 @@ impl PendingOutboundPayment {
 				PendingOutboundPayment::Fulfilled { session_privs, .. } |
 				PendingOutboundPayment::Abandoned { session_privs, .. } => {
 					session_privs.remove(session_priv)
-				}
+				},
+			PendingOutboundPayment::AwaitingInvoice { .. } |
+				PendingOutboundPayment::InvoiceReceived { .. } => { debug_assert!(false); false },
 		};
 		if remove_res {
 			if let PendingOutboundPayment::Retryable { ref mut pending_amt_msat, ref mut pending_fee_msat, .. } = self {
@@ -188,6 +231,8 @@ impl PendingOutboundPayment {
 				PendingOutboundPayment::Retryable { session_privs, .. } => {
 					session_privs.insert(session_priv)
 				}
+			PendingOutboundPayment::AwaitingInvoice { .. } |
+				PendingOutboundPayment::InvoiceReceived { .. } => { debug_assert!(false); false },
 			PendingOutboundPayment::Fulfilled { .. } => false,
 			PendingOutboundPayment::Abandoned { .. } => false,
 		};
@@ -209,7 +254,9 @@ impl PendingOutboundPayment {
 				PendingOutboundPayment::Fulfilled { session_privs, .. } |
 				PendingOutboundPayment::Abandoned { session_privs, .. } => {
 					session_privs.len()
-				}
+				},
+			PendingOutboundPayment::AwaitingInvoice { .. } => 0,
+			PendingOutboundPayment::InvoiceReceived { .. } => 0,
 		}
 	}
 }
@@ -231,6 +278,36 @@ pub enum Retry {
 	Timeout(core::time::Duration),
 }
 
+impl Writeable for Retry {
+	fn write<W: Writer>(&self, w: &mut W) -> Result<(), io::Error> {
+		match self {
+			Retry::Attempts(max_retry_count) => {
+				0u8.write(w)?;
+				(*max_retry_count as u64).write(w)
+			},
+			#[cfg(not(feature = "no-std"))]
+			Retry::Timeout(max_duration) => {
+				2u8.write(w)?;
+				max_duration.write(w)
+			},
+		}
+	}
+}
+
+impl Readable for Retry {
+	fn read<R: io::Read>(r: &mut R) -> Result<Self, DecodeError> {
+		match <u8 as Readable>::read(r)? {
+			0 => {
+				let max_retry_count: u64 = Readable::read(r)?;
+				Ok(Retry::Attempts(core::cmp::min(max_retry_count, usize::max_value() as u64) as usize))
+			},
+			#[cfg(not(feature = "no-std"))]
+			2 => Ok(Retry::Timeout(Readable::read(r)?)),
+			_ => Err(DecodeError::UnknownRequiredFeature),
+		}
+	}
+}
+
 impl Retry {
 	pub(crate) fn is_retryable_now(&self, attempts: &PaymentAttempts) -> bool {
 		match (self, attempts) {
@@ -306,6 +383,24 @@ impl<T: Time> Display for PaymentAttemptsUsingTime<T> {
 	}
 }
 
+/// How long before a [`PendingOutboundPayment::AwaitingInvoice`] should be considered stale and
+/// abandoned, generating an [`Event::InvoiceRequestFailed`].
+///
+/// [`Event::InvoiceRequestFailed`]: crate::events::Event::InvoiceRequestFailed
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) enum StaleExpiration {
+	/// Number of times [`OutboundPayments::remove_stale_payments`] is called.
+	TimerTicks(u64),
+	/// Duration since the Unix epoch.
+	AbsoluteTimeout(Duration),
+}
+
+impl_writeable_tlv_based_enum!(StaleExpiration,
+	;
+	(0, TimerTicks),
+	(2, AbsoluteTimeout)
+);
+
 /// Indicates an immediate error on [`ChannelManager::send_payment`]. Further errors may be
 /// surfaced later via [`Event::PaymentPathFailed`] and [`Event::PaymentFailed`].
 ///
@@ -400,6 +495,15 @@ pub enum PaymentSendFailure {
 	},
 }
 
+/// An error when attempting to pay a BOLT 12 invoice.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub(super) enum Bolt12PaymentError {
+	/// The invoice was not requested.
+	UnexpectedInvoice,
+	/// Payment for an invoice with the corresponding [`PaymentId`] was already initiated.
+	DuplicateInvoice,
+}
+
 /// Information which is provided, encrypted, to the payment recipient when sending HTLCs.
 ///
 /// This should generally be constructed with data communicated to us from the recipient (via a
@@ -585,6 +689,49 @@ impl OutboundPayments {
 		}
 	}
 
+	pub(super) fn send_payment_for_bolt12_invoice<R: Deref, ES: Deref, NS: Deref, IH, SP, L: Deref>(
+		&self, invoice: &Bolt12Invoice, payment_id: PaymentId, router: &R,
+		first_hops: Vec<ChannelDetails>, inflight_htlcs: IH, entropy_source: &ES, node_signer: &NS,
+		best_block_height: u32, logger: &L,
+		pending_events: &Mutex<VecDeque<(events::Event, Option<EventCompletionAction>)>>,
+		send_payment_along_path: SP,
+	) -> Result<(), Bolt12PaymentError>
+	where
+		R::Target: Router,
+		ES::Target: EntropySource,
+		NS::Target: NodeSigner,
+		L::Target: Logger,
+		IH: Fn() -> InFlightHtlcs,
+		SP: Fn(SendAlongPathArgs) -> Result<(), APIError>,
+	{
+		let payment_hash = invoice.payment_hash();
+		match self.pending_outbound_payments.lock().unwrap().entry(payment_id) {
+			hash_map::Entry::Occupied(entry) => match entry.get() {
+				PendingOutboundPayment::AwaitingInvoice { retry_strategy, .. } => {
+					*entry.into_mut() = PendingOutboundPayment::InvoiceReceived {
+						payment_hash,
+						retry_strategy: *retry_strategy,
+					};
+				},
+				_ => return Err(Bolt12PaymentError::DuplicateInvoice),
+			},
+			hash_map::Entry::Vacant(_) => return Err(Bolt12PaymentError::UnexpectedInvoice),
+		};
+
+		let route_params = RouteParameters {
+			payment_params: PaymentParameters::from_bolt12_invoice(&invoice),
+			final_value_msat: invoice.amount_msats(),
+		};
+
+		self.retry_payment_internal(
+			payment_hash, payment_id, route_params, router, first_hops, &inflight_htlcs,
+			entropy_source, node_signer, best_block_height, logger, pending_events,
+			&send_payment_along_path
+		);
+
+		Ok(())
+	}
+
 	pub(super) fn check_retry_payments<R: Deref, ES: Deref, NS: Deref, SP, IH, FH, L: Deref>(
 		&self, router: &R, first_hops: FH, inflight_htlcs: IH, entropy_source: &ES, node_signer: &NS,
 		best_block_height: u32,
@@ -626,7 +773,7 @@ impl OutboundPayments {
 		let mut outbounds = self.pending_outbound_payments.lock().unwrap();
 		outbounds.retain(|pmt_id, pmt| {
 			let mut retain = true;
-			if !pmt.is_auto_retryable_now() && pmt.remaining_parts() == 0 {
+			if !pmt.is_auto_retryable_now() && pmt.remaining_parts() == 0 && !pmt.is_awaiting_invoice() {
 				pmt.mark_abandoned(PaymentFailureReason::RetriesExhausted);
 				if let PendingOutboundPayment::Abandoned { payment_hash, reason, .. } = pmt {
 					pending_events.lock().unwrap().push_back((events::Event::PaymentFailed {
@@ -644,7 +791,8 @@ impl OutboundPayments {
 	pub(super) fn needs_abandon(&self) -> bool {
 		let outbounds = self.pending_outbound_payments.lock().unwrap();
 		outbounds.iter().any(|(_, pmt)|
-			!pmt.is_auto_retryable_now() && pmt.remaining_parts() == 0 && !pmt.is_fulfilled())
+			!pmt.is_auto_retryable_now() && pmt.remaining_parts() == 0 && !pmt.is_fulfilled() &&
+			!pmt.is_awaiting_invoice())
 	}
 
 	/// Errors immediately on [`RetryableSendFailure`] error conditions. Otherwise, further errors may
@@ -736,10 +884,6 @@ impl OutboundPayments {
 		}
 
 		const RETRY_OVERFLOW_PERCENTAGE: u64 = 10;
-		let mut onion_session_privs = Vec::with_capacity(route.paths.len());
-		for _ in 0..route.paths.len() {
-			onion_session_privs.push(entropy_source.get_secure_random_bytes());
-		}
 
 		macro_rules! abandon_with_entry {
 			($payment: expr, $reason: expr) => {
@@ -756,11 +900,11 @@ impl OutboundPayments {
 				}
 			}
 		}
-		let (total_msat, recipient_onion, keysend_preimage) = {
+		let (total_msat, recipient_onion, keysend_preimage, onion_session_privs) = {
 			let mut outbounds = self.pending_outbound_payments.lock().unwrap();
 			match outbounds.entry(payment_id) {
 				hash_map::Entry::Occupied(mut payment) => {
-					let res = match payment.get() {
+					match payment.get() {
 						PendingOutboundPayment::Retryable {
 							total_msat, keysend_preimage, payment_secret, payment_metadata, pending_amt_msat, ..
 						} => {
@@ -770,15 +914,53 @@ impl OutboundPayments {
 								abandon_with_entry!(payment, PaymentFailureReason::UnexpectedError);
 								return
 							}
-							(*total_msat, RecipientOnionFields {
-									payment_secret: *payment_secret,
-									payment_metadata: payment_metadata.clone(),
-								}, *keysend_preimage)
+
+							if !payment.get().is_retryable_now() {
+								log_error!(logger, "Retries exhausted for payment id {}", log_bytes!(payment_id.0));
+								abandon_with_entry!(payment, PaymentFailureReason::RetriesExhausted);
+								return
+							}
+
+							let total_msat = *total_msat;
+							let recipient_onion = RecipientOnionFields {
+								payment_secret: *payment_secret,
+								payment_metadata: payment_metadata.clone(),
+							};
+							let keysend_preimage = *keysend_preimage;
+
+							let mut onion_session_privs = Vec::with_capacity(route.paths.len());
+							for _ in 0..route.paths.len() {
+								onion_session_privs.push(entropy_source.get_secure_random_bytes());
+							}
+
+							for (path, session_priv_bytes) in route.paths.iter().zip(onion_session_privs.iter()) {
+								assert!(payment.get_mut().insert(*session_priv_bytes, path));
+							}
+
+							payment.get_mut().increment_attempts();
+
+							(total_msat, recipient_onion, keysend_preimage, onion_session_privs)
 						},
 						PendingOutboundPayment::Legacy { .. } => {
 							log_error!(logger, "Unable to retry payments that were initially sent on LDK versions prior to 0.0.102");
 							return
 						},
+						PendingOutboundPayment::AwaitingInvoice { .. } => {
+							log_error!(logger, "Payment not yet sent");
+							return
+						},
+						PendingOutboundPayment::InvoiceReceived { payment_hash, retry_strategy } => {
+							let total_amount = route_params.final_value_msat;
+							let recipient_onion = RecipientOnionFields::spontaneous_empty();
+							let retry_strategy = Some(*retry_strategy);
+							let payment_params = Some(route_params.payment_params.clone());
+							let (retryable_payment, onion_session_privs) = self.create_pending_payment(
+								*payment_hash, recipient_onion.clone(), None, &route,
+								retry_strategy, payment_params, entropy_source, best_block_height
+							);
+							*payment.into_mut() = retryable_payment;
+							(total_amount, recipient_onion, None, onion_session_privs)
+						},
 						PendingOutboundPayment::Fulfilled { .. } => {
 							log_error!(logger, "Payment already completed");
 							return
@@ -787,17 +969,7 @@ impl OutboundPayments {
 							log_error!(logger, "Payment already abandoned (with some HTLCs still pending)");
 							return
 						},
-					};
-					if !payment.get().is_retryable_now() {
-						log_error!(logger, "Retries exhausted for payment id {}", log_bytes!(payment_id.0));
-						abandon_with_entry!(payment, PaymentFailureReason::RetriesExhausted);
-						return
-					}
-					payment.get_mut().increment_attempts();
-					for (path, session_priv_bytes) in route.paths.iter().zip(onion_session_privs.iter()) {
-						assert!(payment.get_mut().insert(*session_priv_bytes, path));
 					}
-					res
 				},
 				hash_map::Entry::Vacant(_) => {
 					log_error!(logger, "Payment with ID {} not found", log_bytes!(payment_id.0));
@@ -951,35 +1123,68 @@ impl OutboundPayments {
 		keysend_preimage: Option<PaymentPreimage>, route: &Route, retry_strategy: Option<Retry>,
 		payment_params: Option<PaymentParameters>, entropy_source: &ES, best_block_height: u32
 	) -> Result<Vec<[u8; 32]>, PaymentSendFailure> where ES::Target: EntropySource {
+		let mut pending_outbounds = self.pending_outbound_payments.lock().unwrap();
+		match pending_outbounds.entry(payment_id) {
+			hash_map::Entry::Occupied(_) => Err(PaymentSendFailure::DuplicatePayment),
+			hash_map::Entry::Vacant(entry) => {
+				let (payment, onion_session_privs) = self.create_pending_payment(
+					payment_hash, recipient_onion, keysend_preimage, route, retry_strategy,
+					payment_params, entropy_source, best_block_height
+				);
+				entry.insert(payment);
+				Ok(onion_session_privs)
+			},
+		}
+	}
+
+	fn create_pending_payment<ES: Deref>(
+		&self, payment_hash: PaymentHash, recipient_onion: RecipientOnionFields,
+		keysend_preimage: Option<PaymentPreimage>, route: &Route, retry_strategy: Option<Retry>,
+		payment_params: Option<PaymentParameters>, entropy_source: &ES, best_block_height: u32
+	) -> (PendingOutboundPayment, Vec<[u8; 32]>)
+	where
+		ES::Target: EntropySource,
+	{
 		let mut onion_session_privs = Vec::with_capacity(route.paths.len());
 		for _ in 0..route.paths.len() {
 			onion_session_privs.push(entropy_source.get_secure_random_bytes());
 		}
 
+		let mut payment = PendingOutboundPayment::Retryable {
+			retry_strategy,
+			attempts: PaymentAttempts::new(),
+			payment_params,
+			session_privs: HashSet::new(),
+			pending_amt_msat: 0,
+			pending_fee_msat: Some(0),
+			payment_hash,
+			payment_secret: recipient_onion.payment_secret,
+			payment_metadata: recipient_onion.payment_metadata,
+			keysend_preimage,
+			starting_block_height: best_block_height,
+			total_msat: route.get_total_amount(),
+		};
+
+		for (path, session_priv_bytes) in route.paths.iter().zip(onion_session_privs.iter()) {
+			assert!(payment.insert(*session_priv_bytes, path));
+		}
+
+		(payment, onion_session_privs)
+	}
+
+	pub(super) fn add_new_awaiting_invoice(
+		&self, payment_id: PaymentId, expiration: StaleExpiration, retry_strategy: Retry
+	) -> Result<(), ()> {
 		let mut pending_outbounds = self.pending_outbound_payments.lock().unwrap();
 		match pending_outbounds.entry(payment_id) {
-			hash_map::Entry::Occupied(_) => Err(PaymentSendFailure::DuplicatePayment),
+			hash_map::Entry::Occupied(_) => Err(()),
 			hash_map::Entry::Vacant(entry) => {
-				let payment = entry.insert(PendingOutboundPayment::Retryable {
+				entry.insert(PendingOutboundPayment::AwaitingInvoice {
+					expiration,
 					retry_strategy,
-					attempts: PaymentAttempts::new(),
-					payment_params,
-					session_privs: HashSet::new(),
-					pending_amt_msat: 0,
-					pending_fee_msat: Some(0),
-					payment_hash,
-					payment_secret: recipient_onion.payment_secret,
-					payment_metadata: recipient_onion.payment_metadata,
-					keysend_preimage,
-					starting_block_height: best_block_height,
-					total_msat: route.get_total_amount(),
 				});
 
-				for (path, session_priv_bytes) in route.paths.iter().zip(onion_session_privs.iter()) {
-					assert!(payment.insert(*session_priv_bytes, path));
-				}
-
-				Ok(onion_session_privs)
+				Ok(())
 			},
 		}
 	}
@@ -997,7 +1202,10 @@ impl OutboundPayments {
 		if route.paths.len() < 1 {
 			return Err(PaymentSendFailure::ParameterError(APIError::InvalidRoute{err: "There must be at least one path to send over".to_owned()}));
 		}
-		if recipient_onion.payment_secret.is_none() && route.paths.len() > 1 {
+		// Payments to blinded paths carry the payment secret in the recipient's encrypted TLVs.
+		if recipient_onion.payment_secret.is_none() && route.paths.len() > 1 &&
+			route.paths.iter().any(|path| path.blinded_tail.is_none())
+		{
 			return Err(PaymentSendFailure::ParameterError(APIError::APIMisuseError{err: "Payment secret is required for multi-path payments".to_owned()}));
 		}
 		let mut total_value = 0;
@@ -1008,10 +1216,6 @@ impl OutboundPayments {
 				path_errs.push(Err(APIError::InvalidRoute{err: "Path didn't go anywhere/had bogus size".to_owned()}));
 				continue 'path_check;
 			}
-			if path.blinded_tail.is_some() {
-				path_errs.push(Err(APIError::InvalidRoute{err: "Sending to blinded paths isn't supported yet".to_owned()}));
-				continue 'path_check;
-			}
 			let dest_hop_idx = if path.blinded_tail.is_some() && path.blinded_tail.as_ref().unwrap().hops.len() > 1 {
 				usize::max_value() } else { path.hops.len() - 1 };
 			for (idx, hop) in path.hops.iter().enumerate() {
@@ -1188,7 +1392,8 @@ impl OutboundPayments {
 		}
 	}
 
-	pub(super) fn remove_stale_resolved_payments(&self,
+	pub(super) fn remove_stale_payments(
+		&self, duration_since_epoch: Duration,
 		pending_events: &Mutex<VecDeque<(events::Event, Option<EventCompletionAction>)>>)
 	{
 		// If an outbound payment was completed, and no pending HTLCs remain, we should remove it
@@ -1198,8 +1403,11 @@ impl OutboundPayments {
 		// removal. This should be more than sufficient to ensure the idempotency of any
 		// `send_payment` calls that were made at the same time the `PaymentSent` event was being
 		// processed.
+		//
+		// Payments awaiting an invoice are also removed once they've expired, generating an
+		// `InvoiceRequestFailed` event.
 		let mut pending_outbound_payments = self.pending_outbound_payments.lock().unwrap();
-		let pending_events = pending_events.lock().unwrap();
+		let mut pending_events = pending_events.lock().unwrap();
 		pending_outbound_payments.retain(|payment_id, payment| {
 			if let PendingOutboundPayment::Fulfilled { session_privs, timer_ticks_without_htlcs, .. } = payment {
 				let mut no_remaining_entries = session_privs.is_empty();
@@ -1225,6 +1433,28 @@ impl OutboundPayments {
 					*timer_ticks_without_htlcs = 0;
 					true
 				}
+			} else if let PendingOutboundPayment::AwaitingInvoice { expiration, .. } = payment {
+				let is_stale = match expiration {
+					StaleExpiration::AbsoluteTimeout(absolute_expiry) => {
+						*absolute_expiry <= duration_since_epoch
+					},
+					StaleExpiration::TimerTicks(timer_ticks_remaining) => {
+						if *timer_ticks_remaining > 0 {
+							*timer_ticks_remaining -= 1;
+							false
+						} else {
+							true
+						}
+					},
+				};
+				if is_stale {
+					pending_events.push_back(
+						(events::Event::InvoiceRequestFailed { payment_id: *payment_id }, None)
+					);
+					false
+				} else {
+					true
+				}
 			} else { true }
 		});
 	}
@@ -1237,9 +1467,14 @@ impl OutboundPayments {
 		pending_events: &Mutex<VecDeque<(events::Event, Option<EventCompletionAction>)>>, logger: &L,
 	) -> bool where L::Target: Logger {
 		#[cfg(test)]
-		let (network_update, short_channel_id, payment_retryable, onion_error_code, onion_error_data) = onion_error.decode_onion_failure(secp_ctx, logger, &source);
+		let DecodedOnionFailure {
+			network_update, short_channel_id, payment_retryable, failed_within_blinded_path,
+			onion_error_code, onion_error_data
+		} = onion_error.decode_onion_failure(secp_ctx, logger, &source);
 		#[cfg(not(test))]
-		let (network_update, short_channel_id, payment_retryable, _, _) = onion_error.decode_onion_failure(secp_ctx, logger, &source);
+		let DecodedOnionFailure {
+			network_update, short_channel_id, payment_retryable, failed_within_blinded_path, ..
+		} = onion_error.decode_onion_failure(secp_ctx, logger, &source);
 
 		let payment_is_probe = payment_is_probe(payment_hash, &payment_id, probing_cookie_secret);
 		let mut session_priv_bytes = [0; 32];
@@ -1277,6 +1512,10 @@ impl OutboundPayments {
 				// process_onion_failure we should close that channel as it implies our
 				// next-hop is needlessly blaming us!
 				payment.get_mut().insert_previously_failed_scid(scid);
+			} else if failed_within_blinded_path {
+				if let Some(blinded_tail) = &path.blinded_tail {
+					payment.get_mut().insert_previously_failed_blinded_path(blinded_tail);
+				}
 			}
 
 			if payment_is_probe || !is_retryable_now || !payment_retryable {
@@ -1367,6 +1606,11 @@ impl OutboundPayments {
 					}, None));
 					payment.remove();
 				}
+			} else if let PendingOutboundPayment::AwaitingInvoice { .. } = payment.get() {
+				pending_events.lock().unwrap().push_back((events::Event::InvoiceRequestFailed {
+					payment_id,
+				}, None));
+				payment.remove();
 			}
 		}
 	}
@@ -1429,6 +1673,14 @@ impl_writeable_tlv_based_enum_upgradable!(PendingOutboundPayment,
 		(1, reason, option),
 		(2, payment_hash, required),
 	},
+	(5, AwaitingInvoice) => {
+		(0, expiration, required),
+		(2, retry_strategy, required),
+	},
+	(7, InvoiceReceived) => {
+		(0, payment_hash, required),
+		(2, retry_strategy, required),
+	},
 );
 
 #[cfg(test)]
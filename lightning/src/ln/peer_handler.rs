@@ -43,6 +43,7 @@ use core::{cmp, hash, fmt, mem};
 use core::ops::Deref;
 use core::convert::Infallible;
 #[cfg(feature = "std")] use std::error;
+#[cfg(feature = "std")] use std::time::{SystemTime, UNIX_EPOCH};
 
 use bitcoin::hashes::sha256::Hash as Sha256;
 use bitcoin::hashes::sha256::HashEngine as Sha256Engine;
@@ -95,6 +96,7 @@ impl RoutingMessageHandler for IgnoringMessageHandler {
 	fn get_next_channel_announcement(&self, _starting_point: u64) ->
 		Option<(msgs::ChannelAnnouncement, Option<msgs::ChannelUpdate>, Option<msgs::ChannelUpdate>)> { None }
 	fn get_next_node_announcement(&self, _starting_point: Option<&NodeId>) -> Option<msgs::NodeAnnouncement> { None }
+	fn get_next_query_reply(&self, _their_node_id: &PublicKey) -> Option<msgs::GossipQueryReply> { None }
 	fn peer_connected(&self, _their_node_id: &PublicKey, _init: &msgs::Init, _inbound: bool) -> Result<(), ()> { Ok(()) }
 	fn handle_reply_channel_range(&self, _their_node_id: &PublicKey, _msg: msgs::ReplyChannelRange) -> Result<(), LightningError> { Ok(()) }
 	fn handle_reply_short_channel_ids_end(&self, _their_node_id: &PublicKey, _msg: msgs::ReplyShortChannelIdsEnd) -> Result<(), LightningError> { Ok(()) }
@@ -328,6 +330,34 @@ impl ChannelMessageHandler for ErroringMessageHandler {
 	fn handle_tx_abort(&self, their_node_id: &PublicKey, msg: &msgs::TxAbort) {
 		ErroringMessageHandler::push_error(self, their_node_id, msg.channel_id);
 	}
+
+	fn handle_splice(&self, their_node_id: &PublicKey, msg: &msgs::Splice) {
+		ErroringMessageHandler::push_error(self, their_node_id, msg.channel_id);
+	}
+
+	fn handle_splice_ack(&self, their_node_id: &PublicKey, msg: &msgs::SpliceAck) {
+		ErroringMessageHandler::push_error(self, their_node_id, msg.channel_id);
+	}
+
+	fn handle_splice_locked(&self, their_node_id: &PublicKey, msg: &msgs::SpliceLocked) {
+		ErroringMessageHandler::push_error(self, their_node_id, msg.channel_id);
+	}
+
+	fn handle_stfu(&self, their_node_id: &PublicKey, msg: &msgs::Stfu) {
+		ErroringMessageHandler::push_error(self, their_node_id, msg.channel_id);
+	}
+
+	fn handle_dyn_propose(&self, their_node_id: &PublicKey, msg: &msgs::DynPropose) {
+		ErroringMessageHandler::push_error(self, their_node_id, msg.channel_id);
+	}
+
+	fn handle_dyn_ack(&self, their_node_id: &PublicKey, msg: &msgs::DynAck) {
+		ErroringMessageHandler::push_error(self, their_node_id, msg.channel_id);
+	}
+
+	fn handle_dyn_reject(&self, their_node_id: &PublicKey, msg: &msgs::DynReject) {
+		ErroringMessageHandler::push_error(self, their_node_id, msg.channel_id);
+	}
 }
 
 impl Deref for ErroringMessageHandler {
@@ -470,6 +500,13 @@ const MAX_BUFFER_DRAIN_TICK_INTERVALS_PER_PEER: i8 = 4;
 /// [`FORWARD_INIT_SYNC_BUFFER_LIMIT_RATIO`]) than a hard limit.
 const BUFFER_DRAIN_MSGS_PER_TICK: usize = 32;
 
+/// The maximum number of channels and nodes we look at while backfilling gossip to a peer each
+/// time we attempt to write data to it. As we skip over any gossip outside of the peer's
+/// `gossip_timestamp_filter`, this bounds the time we spend walking the network graph while
+/// holding the peer's lock, with the backfill resuming where it left off on the next attempt
+/// (e.g. on the next call to [`PeerManager::process_events`]).
+const GOSSIP_BACKFILL_MAX_SCANNED_PER_WRITE: usize = 128;
+
 struct Peer {
 	channel_encryptor: PeerChannelEncryptor,
 	/// We cache a `NodeId` here to avoid serializing peers' keys every time we forward gossip
@@ -502,7 +539,18 @@ struct Peer {
 	msgs_sent_since_pong: usize,
 	awaiting_pong_timer_tick_intervals: i64,
 	received_message_since_timer_tick: bool,
-	sent_gossip_timestamp_filter: bool,
+	/// The most recent [`msgs::GossipTimestampFilter`] the peer sent us, if any, restricting which
+	/// gossip we send it.
+	gossip_timestamp_filter: Option<msgs::GossipTimestampFilter>,
+	/// The earliest timestamp we've backfilled (or are backfilling) this peer with gossip from, if
+	/// any. A later filter only restarts the backfill if it reaches further back.
+	gossip_backfill_first_timestamp: Option<u32>,
+	/// Whether we (re)started a backfill since the last timer tick, limiting restarts to one per
+	/// tick.
+	gossip_backfill_started_since_timer_tick: bool,
+	/// Whether the peer asked for a backfill reaching further back which we'll start on the next
+	/// timer tick, as we already started one since the last.
+	gossip_backfill_pending: bool,
 
 	/// Indicates we've received a `channel_announcement` since the last time we had
 	/// [`PeerManager::gossip_processing_backlogged`] set (or, really, that we've received a
@@ -530,7 +578,7 @@ impl Peer {
 	fn should_forward_channel_announcement(&self, channel_id: u64) -> bool {
 		if !self.handshake_complete() { return false; }
 		if self.their_features.as_ref().unwrap().supports_gossip_queries() &&
-			!self.sent_gossip_timestamp_filter {
+			self.gossip_timestamp_filter.is_none() {
 				return false;
 			}
 		match self.sync_status {
@@ -544,7 +592,7 @@ impl Peer {
 	fn should_forward_node_announcement(&self, node_id: NodeId) -> bool {
 		if !self.handshake_complete() { return false; }
 		if self.their_features.as_ref().unwrap().supports_gossip_queries() &&
-			!self.sent_gossip_timestamp_filter {
+			self.gossip_timestamp_filter.is_none() {
 				return false;
 			}
 		match self.sync_status {
@@ -554,6 +602,16 @@ impl Peer {
 		}
 	}
 
+	/// Returns true if gossip with the given timestamp falls within the window the peer requested
+	/// via its [`msgs::GossipTimestampFilter`], or if it didn't send us one.
+	fn gossip_timestamp_in_filter(&self, timestamp: u32) -> bool {
+		match self.gossip_timestamp_filter {
+			Some(ref filter) => timestamp >= filter.first_timestamp &&
+				(timestamp as u64) < filter.first_timestamp as u64 + filter.timestamp_range as u64,
+			None => true,
+		}
+	}
+
 	/// Returns whether we should be reading bytes from this peer, based on whether its outbound
 	/// buffer still has space and we don't need to pause reads to get some writes out.
 	fn should_read(&mut self, gossip_processing_backlogged: bool) -> bool {
@@ -631,7 +689,7 @@ pub type SimpleRefPeerManager<
 	SD,
 	&'n SimpleRefChannelManager<'a, 'b, 'c, 'd, 'e, 'f, 'g, 'm, M, T, F, L>,
 	&'f P2PGossipSync<&'g NetworkGraph<&'f L>, &'h C, &'f L>,
-	&'i SimpleRefOnionMessenger<'g, 'm, 'n, L>,
+	&'i SimpleRefOnionMessenger<'g, 'f, 'n, 'g, L>,
 	&'f L,
 	IgnoringMessageHandler,
 	&'c KeysManager
@@ -851,6 +909,17 @@ impl core::fmt::Display for OptionalFromDebugger<'_> {
 	}
 }
 
+/// Returns the current UNIX timestamp in seconds, or `None` if we don't know the time as we're
+/// built without `std`.
+pub(crate) fn current_unix_timestamp() -> Option<u64> {
+	#[cfg(feature = "std")] {
+		Some(SystemTime::now().duration_since(UNIX_EPOCH).expect("Time must be > 1970").as_secs())
+	}
+	#[cfg(not(feature = "std"))] {
+		None
+	}
+}
+
 /// A function used to filter out local or private addresses
 /// <https://www.iana.org./assignments/ipv4-address-space/ipv4-address-space.xhtml>
 /// <https://www.iana.org/assignments/ipv6-address-space/ipv6-address-space.xhtml>
@@ -1004,7 +1073,10 @@ impl<Descriptor: SocketDescriptor, CM: Deref, RM: Deref, OM: Deref, L: Deref, CM
 					msgs_sent_since_pong: 0,
 					awaiting_pong_timer_tick_intervals: 0,
 					received_message_since_timer_tick: false,
-					sent_gossip_timestamp_filter: false,
+					gossip_timestamp_filter: None,
+					gossip_backfill_first_timestamp: None,
+					gossip_backfill_started_since_timer_tick: false,
+					gossip_backfill_pending: false,
 
 					received_channel_announce_since_backlogged: false,
 					inbound_connection: false,
@@ -1060,7 +1132,10 @@ impl<Descriptor: SocketDescriptor, CM: Deref, RM: Deref, OM: Deref, L: Deref, CM
 					msgs_sent_since_pong: 0,
 					awaiting_pong_timer_tick_intervals: 0,
 					received_message_since_timer_tick: false,
-					sent_gossip_timestamp_filter: false,
+					gossip_timestamp_filter: None,
+					gossip_backfill_first_timestamp: None,
+					gossip_backfill_started_since_timer_tick: false,
+					gossip_backfill_pending: false,
 
 					received_channel_announce_since_backlogged: false,
 					inbound_connection: true,
@@ -1084,6 +1159,7 @@ impl<Descriptor: SocketDescriptor, CM: Deref, RM: Deref, OM: Deref, L: Deref, CM
 
 	fn do_attempt_write_data(&self, descriptor: &mut Descriptor, peer: &mut Peer, force_one_write: bool) {
 		let mut have_written = false;
+		let mut gossip_backfill_scanned = 0;
 		while !peer.awaiting_write_event {
 			if peer.should_buffer_onion_message() {
 				if let Some((peer_node_id, _)) = peer.their_node_id {
@@ -1099,41 +1175,78 @@ impl<Descriptor: SocketDescriptor, CM: Deref, RM: Deref, OM: Deref, L: Deref, CM
 				}
 			}
 			if peer.should_buffer_gossip_backfill() {
-				match peer.sync_status {
-					InitSyncTracker::NoSyncRequested => {},
-					InitSyncTracker::ChannelsSyncing(c) if c < 0xffff_ffff_ffff_ffff => {
-						if let Some((announce, update_a_option, update_b_option)) =
-							self.message_handler.route_handler.get_next_channel_announcement(c)
-						{
-							self.enqueue_message(peer, &announce);
-							if let Some(update_a) = update_a_option {
-								self.enqueue_message(peer, &update_a);
-							}
-							if let Some(update_b) = update_b_option {
-								self.enqueue_message(peer, &update_b);
-							}
-							peer.sync_status = InitSyncTracker::ChannelsSyncing(announce.contents.short_channel_id + 1);
-						} else {
-							peer.sync_status = InitSyncTracker::ChannelsSyncing(0xffff_ffff_ffff_ffff);
-						}
-					},
-					InitSyncTracker::ChannelsSyncing(c) if c == 0xffff_ffff_ffff_ffff => {
-						if let Some(msg) = self.message_handler.route_handler.get_next_node_announcement(None) {
-							self.enqueue_message(peer, &msg);
-							peer.sync_status = InitSyncTracker::NodesSyncing(msg.contents.node_id);
-						} else {
-							peer.sync_status = InitSyncTracker::NoSyncRequested;
-						}
-					},
-					InitSyncTracker::ChannelsSyncing(_) => unreachable!(),
-					InitSyncTracker::NodesSyncing(sync_node_id) => {
-						if let Some(msg) = self.message_handler.route_handler.get_next_node_announcement(Some(&sync_node_id)) {
-							self.enqueue_message(peer, &msg);
-							peer.sync_status = InitSyncTracker::NodesSyncing(msg.contents.node_id);
-						} else {
-							peer.sync_status = InitSyncTracker::NoSyncRequested;
+				// Replies to the peer's gossip queries take precedence over our own backfill, as the
+				// peer is waiting on them to complete its sync.
+				let query_reply = peer.their_node_id.and_then(|(node_id, _)|
+					self.message_handler.route_handler.get_next_query_reply(&node_id));
+				if let Some(reply) = query_reply {
+					match reply {
+						msgs::GossipQueryReply::ChannelAnnouncement(msg) => self.enqueue_message(peer, &msg),
+						msgs::GossipQueryReply::ChannelUpdate(msg) => self.enqueue_message(peer, &msg),
+						msgs::GossipQueryReply::NodeAnnouncement(msg) => self.enqueue_message(peer, &msg),
+						msgs::GossipQueryReply::ReplyShortChannelIdsEnd(msg) => self.enqueue_message(peer, &msg),
+					}
+				} else {
+					// Skip over any gossip outside of the window requested by the peer's
+					// `gossip_timestamp_filter` until we either find some to send, are done, or
+					// have looked at enough gossip for now.
+					let mut enqueued_backfill = false;
+					while !enqueued_backfill && gossip_backfill_scanned < GOSSIP_BACKFILL_MAX_SCANNED_PER_WRITE {
+						gossip_backfill_scanned += 1;
+						match peer.sync_status {
+							InitSyncTracker::NoSyncRequested => break,
+							InitSyncTracker::ChannelsSyncing(c) if c < 0xffff_ffff_ffff_ffff => {
+								if let Some((announce, update_a_option, update_b_option)) =
+									self.message_handler.route_handler.get_next_channel_announcement(c)
+								{
+									let update_a_option = update_a_option
+										.filter(|update| peer.gossip_timestamp_in_filter(update.contents.timestamp));
+									let update_b_option = update_b_option
+										.filter(|update| peer.gossip_timestamp_in_filter(update.contents.timestamp));
+									// A channel announcement takes the timestamp of its updates, so
+									// only send it to filtering peers along with an update.
+									if peer.gossip_timestamp_filter.is_none() ||
+										update_a_option.is_some() || update_b_option.is_some()
+									{
+										self.enqueue_message(peer, &announce);
+										if let Some(update_a) = update_a_option {
+											self.enqueue_message(peer, &update_a);
+										}
+										if let Some(update_b) = update_b_option {
+											self.enqueue_message(peer, &update_b);
+										}
+										enqueued_backfill = true;
+									}
+									peer.sync_status = InitSyncTracker::ChannelsSyncing(announce.contents.short_channel_id + 1);
+								} else {
+									peer.sync_status = InitSyncTracker::ChannelsSyncing(0xffff_ffff_ffff_ffff);
+								}
+							},
+							InitSyncTracker::ChannelsSyncing(c) if c == 0xffff_ffff_ffff_ffff => {
+								if let Some(msg) = self.message_handler.route_handler.get_next_node_announcement(None) {
+									if peer.gossip_timestamp_in_filter(msg.contents.timestamp) {
+										self.enqueue_message(peer, &msg);
+										enqueued_backfill = true;
+									}
+									peer.sync_status = InitSyncTracker::NodesSyncing(msg.contents.node_id);
+								} else {
+									peer.sync_status = InitSyncTracker::NoSyncRequested;
+								}
+							},
+							InitSyncTracker::ChannelsSyncing(_) => unreachable!(),
+							InitSyncTracker::NodesSyncing(sync_node_id) => {
+								if let Some(msg) = self.message_handler.route_handler.get_next_node_announcement(Some(&sync_node_id)) {
+									if peer.gossip_timestamp_in_filter(msg.contents.timestamp) {
+										self.enqueue_message(peer, &msg);
+										enqueued_backfill = true;
+									}
+									peer.sync_status = InitSyncTracker::NodesSyncing(msg.contents.node_id);
+								} else {
+									peer.sync_status = InitSyncTracker::NoSyncRequested;
+								}
+							},
 						}
-					},
+					}
 				}
 			}
 			if peer.msgs_sent_since_pong >= BUFFER_DRAIN_MSGS_PER_TICK {
@@ -1556,13 +1669,37 @@ impl<Descriptor: SocketDescriptor, CM: Deref, RM: Deref, OM: Deref, L: Deref, CM
 			return Err(PeerHandleError { }.into());
 		}
 
-		if let wire::Message::GossipTimestampFilter(_msg) = message {
+		if let wire::Message::GossipTimestampFilter(msg) = message {
 			// When supporting gossip messages, start inital gossip sync only after we receive
-			// a GossipTimestampFilter
-			if peer_lock.their_features.as_ref().unwrap().supports_gossip_queries() &&
-				!peer_lock.sent_gossip_timestamp_filter {
-				peer_lock.sent_gossip_timestamp_filter = true;
-				peer_lock.sync_status = InitSyncTracker::ChannelsSyncing(0);
+			// a GossipTimestampFilter, sending only the gossip within the requested window. Any
+			// later filter replaces the previous one, restarting the sync only if it reaches
+			// further back than what we've already backfilled. To keep peers from having us walk
+			// the whole network graph over and over, we restart the sync at most once per timer
+			// tick, deferring any further restart to the next tick.
+			if peer_lock.their_features.as_ref().unwrap().supports_gossip_queries() {
+				let requests_past_gossip = match current_unix_timestamp() {
+					Some(now) => (msg.first_timestamp as u64) <= now,
+					None => true,
+				};
+				log_debug!(self.logger, "Received gossip_timestamp_filter from {} for timestamps {} to {}",
+					log_pubkey!(their_node_id), msg.first_timestamp,
+					msg.first_timestamp as u64 + msg.timestamp_range as u64);
+				let extends_backfill = match peer_lock.gossip_backfill_first_timestamp {
+					Some(first_timestamp) => msg.first_timestamp < first_timestamp,
+					None => true,
+				};
+				if requests_past_gossip && extends_backfill {
+					peer_lock.gossip_backfill_first_timestamp = Some(msg.first_timestamp);
+					if peer_lock.gossip_backfill_started_since_timer_tick {
+						log_debug!(self.logger, "Deferring gossip backfill for {} to the next timer tick",
+							log_pubkey!(their_node_id));
+						peer_lock.gossip_backfill_pending = true;
+					} else {
+						peer_lock.sync_status = InitSyncTracker::ChannelsSyncing(0);
+						peer_lock.gossip_backfill_started_since_timer_tick = true;
+					}
+				}
+				peer_lock.gossip_timestamp_filter = Some(msg);
 			}
 			return Ok(None);
 		}
@@ -1665,6 +1802,33 @@ impl<Descriptor: SocketDescriptor, CM: Deref, RM: Deref, OM: Deref, L: Deref, CM
 				self.message_handler.chan_handler.handle_tx_abort(&their_node_id, &msg);
 			}
 
+			// Splicing messages:
+			wire::Message::Splice(msg) => {
+				self.message_handler.chan_handler.handle_splice(&their_node_id, &msg);
+			},
+			wire::Message::SpliceAck(msg) => {
+				self.message_handler.chan_handler.handle_splice_ack(&their_node_id, &msg);
+			},
+			wire::Message::SpliceLocked(msg) => {
+				self.message_handler.chan_handler.handle_splice_locked(&their_node_id, &msg);
+			},
+
+			// Quiescence messages:
+			wire::Message::Stfu(msg) => {
+				self.message_handler.chan_handler.handle_stfu(&their_node_id, &msg);
+			},
+
+			// Channel type upgrade messages:
+			wire::Message::DynPropose(msg) => {
+				self.message_handler.chan_handler.handle_dyn_propose(&their_node_id, &msg);
+			},
+			wire::Message::DynAck(msg) => {
+				self.message_handler.chan_handler.handle_dyn_ack(&their_node_id, &msg);
+			},
+			wire::Message::DynReject(msg) => {
+				self.message_handler.chan_handler.handle_dyn_reject(&their_node_id, &msg);
+			},
+
 			wire::Message::Shutdown(msg) => {
 				self.message_handler.chan_handler.handle_shutdown(&their_node_id, &msg);
 			},
@@ -1763,6 +1927,9 @@ impl<Descriptor: SocketDescriptor, CM: Deref, RM: Deref, OM: Deref, L: Deref, CM
 			wire::Message::ChannelAnnouncement(ref msg) => {
 				log_gossip!(self.logger, "Sending message to all peers except {:?} or the announced channel's counterparties: {:?}", except_node, msg);
 				let encoded_msg = encode_msg!(msg);
+				// A channel announcement takes the timestamp of its updates, which for a channel
+				// we're only learning about now should be around the current time.
+				let announcement_timestamp = current_unix_timestamp().map(|now| now as u32);
 
 				for (_, peer_mutex) in peers.iter() {
 					let mut peer = peer_mutex.lock().unwrap();
@@ -1770,6 +1937,11 @@ impl<Descriptor: SocketDescriptor, CM: Deref, RM: Deref, OM: Deref, L: Deref, CM
 							!peer.should_forward_channel_announcement(msg.contents.short_channel_id) {
 						continue
 					}
+					if let Some(timestamp) = announcement_timestamp {
+						if !peer.gossip_timestamp_in_filter(timestamp) {
+							continue;
+						}
+					}
 					debug_assert!(peer.their_node_id.is_some());
 					debug_assert!(peer.channel_encryptor.is_ready_for_encryption());
 					if peer.buffer_full_drop_gossip_broadcast() {
@@ -1794,7 +1966,8 @@ impl<Descriptor: SocketDescriptor, CM: Deref, RM: Deref, OM: Deref, L: Deref, CM
 				for (_, peer_mutex) in peers.iter() {
 					let mut peer = peer_mutex.lock().unwrap();
 					if !peer.handshake_complete() ||
-							!peer.should_forward_node_announcement(msg.contents.node_id) {
+							!peer.should_forward_node_announcement(msg.contents.node_id) ||
+							!peer.gossip_timestamp_in_filter(msg.contents.timestamp) {
 						continue
 					}
 					debug_assert!(peer.their_node_id.is_some());
@@ -1821,7 +1994,8 @@ impl<Descriptor: SocketDescriptor, CM: Deref, RM: Deref, OM: Deref, L: Deref, CM
 				for (_, peer_mutex) in peers.iter() {
 					let mut peer = peer_mutex.lock().unwrap();
 					if !peer.handshake_complete() ||
-							!peer.should_forward_channel_announcement(msg.contents.short_channel_id)  {
+							!peer.should_forward_channel_announcement(msg.contents.short_channel_id) ||
+							!peer.gossip_timestamp_in_filter(msg.contents.timestamp) {
 						continue
 					}
 					debug_assert!(peer.their_node_id.is_some());
@@ -2009,13 +2183,55 @@ impl<Descriptor: SocketDescriptor, CM: Deref, RM: Deref, OM: Deref, L: Deref, CM
 									log_bytes!(msg.channel_id));
 							self.enqueue_message(&mut *get_peer_for_forwarding!(node_id), msg);
 						},
+						MessageSendEvent::SendSplice { ref node_id, ref msg } => {
+							log_debug!(self.logger, "Handling SendSplice event in peer_handler for node {} for channel {}",
+									log_pubkey!(node_id),
+									log_bytes!(msg.channel_id));
+							self.enqueue_message(&mut *get_peer_for_forwarding!(node_id), msg);
+						},
+						MessageSendEvent::SendSpliceAck { ref node_id, ref msg } => {
+							log_debug!(self.logger, "Handling SendSpliceAck event in peer_handler for node {} for channel {}",
+									log_pubkey!(node_id),
+									log_bytes!(msg.channel_id));
+							self.enqueue_message(&mut *get_peer_for_forwarding!(node_id), msg);
+						},
+						MessageSendEvent::SendSpliceLocked { ref node_id, ref msg } => {
+							log_debug!(self.logger, "Handling SendSpliceLocked event in peer_handler for node {} for channel {}",
+									log_pubkey!(node_id),
+									log_bytes!(msg.channel_id));
+							self.enqueue_message(&mut *get_peer_for_forwarding!(node_id), msg);
+						},
+						MessageSendEvent::SendStfu { ref node_id, ref msg } => {
+							log_debug!(self.logger, "Handling SendStfu event in peer_handler for node {} for channel {}",
+									log_pubkey!(node_id),
+									log_bytes!(msg.channel_id));
+							self.enqueue_message(&mut *get_peer_for_forwarding!(node_id), msg);
+						},
+						MessageSendEvent::SendDynPropose { ref node_id, ref msg } => {
+							log_debug!(self.logger, "Handling SendDynPropose event in peer_handler for node {} for channel {}",
+									log_pubkey!(node_id),
+									log_bytes!(msg.channel_id));
+							self.enqueue_message(&mut *get_peer_for_forwarding!(node_id), msg);
+						},
+						MessageSendEvent::SendDynAck { ref node_id, ref msg } => {
+							log_debug!(self.logger, "Handling SendDynAck event in peer_handler for node {} for channel {}",
+									log_pubkey!(node_id),
+									log_bytes!(msg.channel_id));
+							self.enqueue_message(&mut *get_peer_for_forwarding!(node_id), msg);
+						},
+						MessageSendEvent::SendDynReject { ref node_id, ref msg } => {
+							log_debug!(self.logger, "Handling SendDynReject event in peer_handler for node {} for channel {}",
+									log_pubkey!(node_id),
+									log_bytes!(msg.channel_id));
+							self.enqueue_message(&mut *get_peer_for_forwarding!(node_id), msg);
+						},
 						MessageSendEvent::SendAnnouncementSignatures { ref node_id, ref msg } => {
 							log_debug!(self.logger, "Handling SendAnnouncementSignatures event in peer_handler for node {} for channel {})",
 									log_pubkey!(node_id),
 									log_bytes!(msg.channel_id));
 							self.enqueue_message(&mut *get_peer_for_forwarding!(node_id), msg);
 						},
-						MessageSendEvent::UpdateHTLCs { ref node_id, updates: msgs::CommitmentUpdate { ref update_add_htlcs, ref update_fulfill_htlcs, ref update_fail_htlcs, ref update_fail_malformed_htlcs, ref update_fee, ref commitment_signed } } => {
+						MessageSendEvent::UpdateHTLCs { ref node_id, updates: msgs::CommitmentUpdate { ref update_add_htlcs, ref update_fulfill_htlcs, ref update_fail_htlcs, ref update_fail_malformed_htlcs, ref update_fee, ref commitment_signed, ref splice_commitment_signed } } => {
 							log_debug!(self.logger, "Handling UpdateHTLCs event in peer_handler for node {} with {} adds, {} fulfills, {} fails for channel {}",
 									log_pubkey!(node_id),
 									update_add_htlcs.len(),
@@ -2039,6 +2255,9 @@ impl<Descriptor: SocketDescriptor, CM: Deref, RM: Deref, OM: Deref, L: Deref, CM
 								self.enqueue_message(&mut *peer, msg);
 							}
 							self.enqueue_message(&mut *peer, commitment_signed);
+							for msg in splice_commitment_signed {
+								self.enqueue_message(&mut *peer, msg);
+							}
 						},
 						MessageSendEvent::SendRevokeAndACK { ref node_id, ref msg } => {
 							log_debug!(self.logger, "Handling SendRevokeAndACK event in peer_handler for node {} for channel {}",
@@ -2235,6 +2454,7 @@ impl<Descriptor: SocketDescriptor, CM: Deref, RM: Deref, OM: Deref, L: Deref, CM
 		if let Some((node_id, _)) = peer.their_node_id {
 			log_trace!(self.logger, "Disconnecting peer with id {} due to {}", node_id, reason);
 			self.message_handler.chan_handler.peer_disconnected(&node_id);
+			self.message_handler.route_handler.peer_disconnected(&node_id);
 			self.message_handler.onion_message_handler.peer_disconnected(&node_id);
 		}
 		descriptor.disconnect_socket();
@@ -2257,6 +2477,7 @@ impl<Descriptor: SocketDescriptor, CM: Deref, RM: Deref, OM: Deref, L: Deref, CM
 					debug_assert!(removed.is_some(), "descriptor maps should be consistent");
 					if !peer.handshake_complete() { return; }
 					self.message_handler.chan_handler.peer_disconnected(&node_id);
+					self.message_handler.route_handler.peer_disconnected(&node_id);
 					self.message_handler.onion_message_handler.peer_disconnected(&node_id);
 				}
 			}
@@ -2343,6 +2564,12 @@ impl<Descriptor: SocketDescriptor, CM: Deref, RM: Deref, OM: Deref, L: Deref, CM
 				debug_assert!(peer.channel_encryptor.is_ready_for_encryption());
 				debug_assert!(peer.their_node_id.is_some());
 
+				peer.gossip_backfill_started_since_timer_tick = peer.gossip_backfill_pending;
+				if peer.gossip_backfill_pending {
+					peer.gossip_backfill_pending = false;
+					peer.sync_status = InitSyncTracker::ChannelsSyncing(0);
+				}
+
 				loop { // Used as a `goto` to skip writing a Ping message.
 					if peer.awaiting_pong_timer_tick_intervals == -1 {
 						// Magic value set in `maybe_send_extra_ping`.
@@ -2979,6 +3206,132 @@ mod tests {
 		assert_eq!(cfgs[1].routing_handler.chan_anns_recvd.load(Ordering::Acquire), 54);
 	}
 
+	#[test]
+	#[cfg(feature = "std")]
+	fn test_gossip_timestamp_filter_backfill() {
+		// Tests that we only backfill a peer with the gossip within the window it requested via its
+		// gossip_timestamp_filter, that a later filter for a past window replays its gossip, and that
+		// we only skip over so much gossip outside of the window at once.
+		use crate::ln::features::ChannelFeatures;
+		use crate::routing::gossip::{NetworkGraph, P2PGossipSync};
+		use crate::routing::test_utils::{add_channel, get_nodes, update_channel};
+		use bitcoin::blockdata::constants::genesis_block;
+		use bitcoin::secp256k1::Secp256k1;
+		use std::time::{SystemTime, UNIX_EPOCH};
+
+		let secp_ctx = Secp256k1::new();
+		let logger = Arc::new(test_utils::TestLogger::new());
+		let network_graph = Arc::new(NetworkGraph::new(Network::Testnet, Arc::clone(&logger)));
+		let gossip_sync = P2PGossipSync::new(Arc::clone(&network_graph), None, Arc::clone(&logger));
+		let (_, _, privkeys, _) = get_nodes(&secp_ctx);
+
+		// Channels without any updates are never sent to a filtering peer, so we have to skip over
+		// them all before finding the gossip we're looking for.
+		let skipped_channels = super::GOSSIP_BACKFILL_MAX_SCANNED_PER_WRITE as u64;
+		for short_channel_id in 1..=skipped_channels {
+			add_channel(&gossip_sync, &secp_ctx, &privkeys[0], &privkeys[1], ChannelFeatures::empty(), short_channel_id);
+		}
+		let now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_secs() as u32;
+		let day_ago = now - 60 * 60 * 24;
+		for (short_channel_id, timestamp) in [(skipped_channels + 1, now), (skipped_channels + 2, day_ago)].iter() {
+			add_channel(&gossip_sync, &secp_ctx, &privkeys[0], &privkeys[1], ChannelFeatures::empty(), *short_channel_id);
+			update_channel(&gossip_sync, &secp_ctx, &privkeys[0], msgs::UnsignedChannelUpdate {
+				chain_hash: genesis_block(Network::Testnet).header.block_hash(),
+				short_channel_id: *short_channel_id,
+				timestamp: *timestamp,
+				flags: 0,
+				cltv_expiry_delta: 0,
+				htlc_minimum_msat: 0,
+				htlc_maximum_msat: msgs::MAX_VALUE_MSAT,
+				fee_base_msat: 0,
+				fee_proportional_millionths: 0,
+				excess_data: Vec::new(),
+			});
+		}
+
+		// The peer backfilling from the network graph replaces the first of our usual test peers.
+		let cfgs = create_peermgr_cfgs(2);
+		let peers = create_network(2, &cfgs);
+		let msg_handler = MessageHandler {
+			chan_handler: &cfgs[0].chan_handler, route_handler: &gossip_sync,
+			onion_message_handler: IgnoringMessageHandler {}, custom_message_handler: &cfgs[0].custom_handler
+		};
+		let peer_a = PeerManager::new(msg_handler, 0, &[0; 32], &cfgs[0].logger, &cfgs[0].node_signer);
+		let peer_b = &peers[1];
+
+		let id_a = peer_a.node_signer.get_node_id(Recipient::Node).unwrap();
+		let mut fd_a = FileDescriptor {
+			fd: 1, outbound_data: Arc::new(Mutex::new(Vec::new())),
+			disconnect: Arc::new(AtomicBool::new(false)),
+		};
+		let mut fd_b = FileDescriptor {
+			fd: 1, outbound_data: Arc::new(Mutex::new(Vec::new())),
+			disconnect: Arc::new(AtomicBool::new(false)),
+		};
+		let initial_data = peer_b.new_outbound_connection(id_a, fd_b.clone(), None).unwrap();
+		peer_a.new_inbound_connection(fd_a.clone(), None).unwrap();
+		assert!(!peer_a.read_event(&mut fd_a, &initial_data).unwrap());
+
+		let deliver_messages = |fd_a: &mut FileDescriptor, fd_b: &mut FileDescriptor| {
+			for _ in 0..20 {
+				peer_a.process_events();
+				let a_data = fd_a.outbound_data.lock().unwrap().split_off(0);
+				peer_b.read_event(fd_b, &a_data).unwrap();
+				peer_b.process_events();
+				let b_data = fd_b.outbound_data.lock().unwrap().split_off(0);
+				peer_a.read_event(fd_a, &b_data).unwrap();
+			}
+		};
+
+		// Our test peer asks for the gossip of the past hour, which only includes the first channel.
+		deliver_messages(&mut fd_a, &mut fd_b);
+		assert_eq!(cfgs[1].routing_handler.chan_anns_recvd.load(Ordering::Acquire), 1);
+		assert_eq!(cfgs[1].routing_handler.chan_upds_recvd.load(Ordering::Acquire), 1);
+
+		// After a timer tick, asking for an earlier window replays the gossip of the second channel
+		// only.
+		peer_a.timer_tick_occurred();
+		cfgs[1].routing_handler.pending_events.lock().unwrap().push(events::MessageSendEvent::SendGossipTimestampFilter {
+			node_id: id_a,
+			msg: msgs::GossipTimestampFilter {
+				chain_hash: genesis_block(Network::Testnet).header.block_hash(),
+				first_timestamp: day_ago - 60 * 60,
+				timestamp_range: 2 * 60 * 60,
+			},
+		});
+		peer_b.process_events();
+		let b_data = fd_b.outbound_data.lock().unwrap().split_off(0);
+		peer_a.read_event(&mut fd_a, &b_data).unwrap();
+		// The first attempt to write to the peer only gets through the channels without updates.
+		peer_a.process_events();
+		let a_data = fd_a.outbound_data.lock().unwrap().split_off(0);
+		peer_b.read_event(&mut fd_b, &a_data).unwrap();
+		assert_eq!(cfgs[1].routing_handler.chan_anns_recvd.load(Ordering::Acquire), 1);
+		deliver_messages(&mut fd_a, &mut fd_b);
+		assert_eq!(cfgs[1].routing_handler.chan_anns_recvd.load(Ordering::Acquire), 2);
+		assert_eq!(cfgs[1].routing_handler.chan_upds_recvd.load(Ordering::Acquire), 2);
+
+		// Sending the same filter again doesn't replay anything, while a filter reaching even further
+		// back is only replayed after the next timer tick, limiting restarts to one per tick.
+		for first_timestamp in [day_ago - 60 * 60, day_ago - 2 * 60 * 60].iter() {
+			cfgs[1].routing_handler.pending_events.lock().unwrap().push(events::MessageSendEvent::SendGossipTimestampFilter {
+				node_id: id_a,
+				msg: msgs::GossipTimestampFilter {
+					chain_hash: genesis_block(Network::Testnet).header.block_hash(),
+					first_timestamp: *first_timestamp,
+					timestamp_range: 4 * 60 * 60,
+				},
+			});
+			deliver_messages(&mut fd_a, &mut fd_b);
+			assert_eq!(cfgs[1].routing_handler.chan_anns_recvd.load(Ordering::Acquire), 2);
+			assert_eq!(cfgs[1].routing_handler.chan_upds_recvd.load(Ordering::Acquire), 2);
+		}
+		peer_a.timer_tick_occurred();
+		deliver_messages(&mut fd_a, &mut fd_b);
+		assert_eq!(cfgs[1].routing_handler.chan_anns_recvd.load(Ordering::Acquire), 3);
+		assert_eq!(cfgs[1].routing_handler.chan_upds_recvd.load(Ordering::Acquire), 3);
+	}
+
 	#[test]
 	fn test_handshake_timeout() {
 		// Tests that we time out a peer still waiting on handshake completion after a full timer
@@ -0,0 +1,395 @@
+// This file is Copyright its original authors, visible in version control
+// history.
+//
+// This file is licensed under the Apache License, Version 2.0 <LICENSE-APACHE
+// or http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your option.
+// You may not use this file except in accordance with one or both of these
+// licenses.
+
+//! Tests that test the quiescence (`stfu`) protocol, which pauses all updates to a channel.
+
+use crate::chain::ChannelMonitorUpdateStatus;
+use crate::events::{MessageSendEvent, MessageSendEventsProvider};
+use crate::ln::channel::DISCONNECT_PEER_AWAITING_QUIESCENCE_TICKS;
+use crate::ln::channelmanager::{ChannelQuiescenceState, PaymentId, PaymentSendFailure, RecipientOnionFields};
+use crate::ln::functional_test_utils::*;
+use crate::ln::msgs;
+use crate::ln::msgs::{ChannelMessageHandler, ErrorAction};
+use crate::util::errors::APIError;
+
+use crate::prelude::*;
+
+fn expect_quiescence_state<'a, 'b, 'c>(node: &Node<'a, 'b, 'c>, channel_id: &[u8; 32], state: ChannelQuiescenceState) {
+	let channel = node.node.list_channels().into_iter().find(|channel| channel.channel_id == *channel_id).unwrap();
+	assert_eq!(channel.channel_quiescence_state, Some(state));
+}
+
+/// Disconnects the two nodes, then reconnects them and delivers their `channel_reestablish`
+/// messages, leaving any messages generated in response pending.
+fn disconnect_and_reestablish<'a, 'b, 'c>(node_a: &Node<'a, 'b, 'c>, node_b: &Node<'a, 'b, 'c>) {
+	node_a.node.peer_disconnected(&node_b.node.get_our_node_id());
+	node_b.node.peer_disconnected(&node_a.node.get_our_node_id());
+
+	node_a.node.peer_connected(&node_b.node.get_our_node_id(), &msgs::Init {
+		features: node_b.node.init_features(), networks: None, remote_network_address: None
+	}, true).unwrap();
+	let reestablish_a = get_chan_reestablish_msgs!(node_a, node_b);
+	node_b.node.peer_connected(&node_a.node.get_our_node_id(), &msgs::Init {
+		features: node_a.node.init_features(), networks: None, remote_network_address: None
+	}, false).unwrap();
+	let reestablish_b = get_chan_reestablish_msgs!(node_b, node_a);
+	assert_eq!(reestablish_a.len(), 1);
+	assert_eq!(reestablish_b.len(), 1);
+
+	node_b.node.handle_channel_reestablish(&node_a.node.get_our_node_id(), &reestablish_a[0]);
+	node_a.node.handle_channel_reestablish(&node_b.node.get_our_node_id(), &reestablish_b[0]);
+}
+
+/// Returns the pending messages of `node` other than any `channel_update`s sent after
+/// reestablishing the channel.
+fn get_non_update_msg_events<'a, 'b, 'c>(node: &Node<'a, 'b, 'c>) -> Vec<MessageSendEvent> {
+	node.node.get_and_clear_pending_msg_events().into_iter()
+		.filter(|event| !matches!(event, MessageSendEvent::SendChannelUpdate { .. }))
+		.collect()
+}
+
+#[test]
+fn test_quiescence_holds_updates() {
+	let chanmon_cfgs = create_chanmon_cfgs(2);
+	let node_cfgs = create_node_cfgs(2, &chanmon_cfgs);
+	let node_chanmgrs = create_node_chanmgrs(2, &node_cfgs, &[None, None]);
+	let nodes = create_network(2, &node_cfgs, &node_chanmgrs);
+	let channel_id = create_announced_chan_between_nodes(&nodes, 0, 1).2;
+	send_payment(&nodes[0], &[&nodes[1]], 10_000_000);
+	expect_quiescence_state(&nodes[0], &channel_id, ChannelQuiescenceState::NotQuiescent);
+
+	nodes[0].node.quiesce_channel(&channel_id, &nodes[1].node.get_our_node_id()).unwrap();
+	let stfu = get_event_msg!(nodes[0], MessageSendEvent::SendStfu, nodes[1].node.get_our_node_id());
+	assert!(stfu.initiator);
+	expect_quiescence_state(&nodes[0], &channel_id, ChannelQuiescenceState::AwaitingQuiescence);
+	match nodes[0].node.quiesce_channel(&channel_id, &nodes[1].node.get_our_node_id()) {
+		Err(APIError::APIMisuseError { .. }) => {},
+		_ => panic!("Unexpected result"),
+	}
+
+	nodes[1].node.handle_stfu(&nodes[0].node.get_our_node_id(), &stfu);
+	let stfu = get_event_msg!(nodes[1], MessageSendEvent::SendStfu, nodes[0].node.get_our_node_id());
+	assert!(!stfu.initiator);
+	nodes[0].node.handle_stfu(&nodes[1].node.get_our_node_id(), &stfu);
+	assert!(nodes[0].node.get_and_clear_pending_msg_events().is_empty());
+	expect_quiescence_state(&nodes[0], &channel_id, ChannelQuiescenceState::Quiescent);
+	expect_quiescence_state(&nodes[1], &channel_id, ChannelQuiescenceState::Quiescent);
+
+	// New HTLCs are held in the holding cell while the channel is quiescent, and we can't begin
+	// closing it either.
+	let (route, payment_hash, payment_preimage, payment_secret) = get_route_and_payment_hash!(nodes[1], nodes[0], 1_000_000);
+	nodes[1].node.send_payment_with_route(&route, payment_hash,
+		RecipientOnionFields::secret_only(payment_secret), PaymentId(payment_hash.0)).unwrap();
+	check_added_monitors!(nodes[1], 0);
+	assert!(nodes[1].node.get_and_clear_pending_msg_events().is_empty());
+	match nodes[0].node.close_channel(&channel_id, &nodes[1].node.get_our_node_id()) {
+		Err(APIError::ChannelUnavailable { .. }) => {},
+		_ => panic!("Unexpected result"),
+	}
+
+	// Once both sides end quiescence, the held HTLC is finally sent.
+	nodes[0].node.unquiesce_channel(&channel_id, &nodes[1].node.get_our_node_id()).unwrap();
+	nodes[1].node.unquiesce_channel(&channel_id, &nodes[0].node.get_our_node_id()).unwrap();
+	expect_quiescence_state(&nodes[0], &channel_id, ChannelQuiescenceState::NotQuiescent);
+	expect_quiescence_state(&nodes[1], &channel_id, ChannelQuiescenceState::NotQuiescent);
+	match nodes[0].node.unquiesce_channel(&channel_id, &nodes[1].node.get_our_node_id()) {
+		Err(APIError::APIMisuseError { .. }) => {},
+		_ => panic!("Unexpected result"),
+	}
+	assert!(nodes[0].node.get_and_clear_pending_msg_events().is_empty());
+	let payment_event = SendEvent::from_node(&nodes[1]);
+	check_added_monitors!(nodes[1], 1);
+	nodes[0].node.handle_update_add_htlc(&nodes[1].node.get_our_node_id(), &payment_event.msgs[0]);
+	commitment_signed_dance!(nodes[0], nodes[1], payment_event.commitment_msg, false);
+	expect_pending_htlcs_forwardable!(nodes[0]);
+	expect_payment_claimable!(nodes[0], payment_hash, payment_secret, 1_000_000);
+	claim_payment(&nodes[1], &[&nodes[0]], payment_preimage);
+}
+
+#[test]
+fn test_unquiesce_one_side_first() {
+	// If one side ends quiescence before the other and sends an update, the other side disconnects
+	// it, after which the update is sent again with quiescence having ended on both sides.
+	let chanmon_cfgs = create_chanmon_cfgs(2);
+	let node_cfgs = create_node_cfgs(2, &chanmon_cfgs);
+	let node_chanmgrs = create_node_chanmgrs(2, &node_cfgs, &[None, None]);
+	let nodes = create_network(2, &node_cfgs, &node_chanmgrs);
+	let channel_id = create_announced_chan_between_nodes(&nodes, 0, 1).2;
+	send_payment(&nodes[0], &[&nodes[1]], 10_000_000);
+
+	nodes[0].node.quiesce_channel(&channel_id, &nodes[1].node.get_our_node_id()).unwrap();
+	let stfu = get_event_msg!(nodes[0], MessageSendEvent::SendStfu, nodes[1].node.get_our_node_id());
+	nodes[1].node.handle_stfu(&nodes[0].node.get_our_node_id(), &stfu);
+	let stfu = get_event_msg!(nodes[1], MessageSendEvent::SendStfu, nodes[0].node.get_our_node_id());
+	nodes[0].node.handle_stfu(&nodes[1].node.get_our_node_id(), &stfu);
+
+	// The channel remains quiescent for however long the protocol requiring it takes.
+	for _ in 0..DISCONNECT_PEER_AWAITING_QUIESCENCE_TICKS * 2 {
+		nodes[0].node.timer_tick_occurred();
+		nodes[1].node.timer_tick_occurred();
+	}
+	assert!(nodes[0].node.get_and_clear_pending_msg_events().is_empty());
+	assert!(nodes[1].node.get_and_clear_pending_msg_events().is_empty());
+	expect_quiescence_state(&nodes[0], &channel_id, ChannelQuiescenceState::Quiescent);
+	expect_quiescence_state(&nodes[1], &channel_id, ChannelQuiescenceState::Quiescent);
+
+	nodes[0].node.unquiesce_channel(&channel_id, &nodes[1].node.get_our_node_id()).unwrap();
+	let (route, payment_hash, payment_preimage, payment_secret) = get_route_and_payment_hash!(nodes[0], nodes[1], 1_000_000);
+	nodes[0].node.send_payment_with_route(&route, payment_hash,
+		RecipientOnionFields::secret_only(payment_secret), PaymentId(payment_hash.0)).unwrap();
+	check_added_monitors!(nodes[0], 1);
+	let payment_event = SendEvent::from_node(&nodes[0]);
+	nodes[1].node.handle_update_add_htlc(&nodes[0].node.get_our_node_id(), &payment_event.msgs[0]);
+	let events = nodes[1].node.get_and_clear_pending_msg_events();
+	assert_eq!(events.len(), 1);
+	match events[0] {
+		MessageSendEvent::HandleError { action: ErrorAction::DisconnectPeerWithWarning { .. }, .. } => {},
+		_ => panic!("Unexpected event"),
+	}
+
+	disconnect_and_reestablish(&nodes[0], &nodes[1]);
+	expect_quiescence_state(&nodes[0], &channel_id, ChannelQuiescenceState::NotQuiescent);
+	expect_quiescence_state(&nodes[1], &channel_id, ChannelQuiescenceState::NotQuiescent);
+	assert!(get_non_update_msg_events(&nodes[1]).is_empty());
+	let mut events = get_non_update_msg_events(&nodes[0]);
+	assert_eq!(events.len(), 1);
+	match events.remove(0) {
+		MessageSendEvent::UpdateHTLCs { updates, .. } => {
+			nodes[1].node.handle_update_add_htlc(&nodes[0].node.get_our_node_id(), &updates.update_add_htlcs[0]);
+			commitment_signed_dance!(nodes[1], nodes[0], updates.commitment_signed, false);
+		},
+		_ => panic!("Unexpected event"),
+	}
+	expect_pending_htlcs_forwardable!(nodes[1]);
+	expect_payment_claimable!(nodes[1], payment_hash, payment_secret, 1_000_000);
+	claim_payment(&nodes[0], &[&nodes[1]], payment_preimage);
+}
+
+#[test]
+fn test_quiescence_waits_for_pending_updates() {
+	let chanmon_cfgs = create_chanmon_cfgs(2);
+	let node_cfgs = create_node_cfgs(2, &chanmon_cfgs);
+	let node_chanmgrs = create_node_chanmgrs(2, &node_cfgs, &[None, None]);
+	let nodes = create_network(2, &node_cfgs, &node_chanmgrs);
+	let channel_id = create_announced_chan_between_nodes(&nodes, 0, 1).2;
+
+	// nodes[0] adds an HTLC concurrently with nodes[1] proposing quiescence, so it has to wait
+	// for the HTLC to be irrevocably committed before responding.
+	let (route, payment_hash, payment_preimage, payment_secret) = get_route_and_payment_hash!(nodes[0], nodes[1], 1_000_000);
+	nodes[0].node.send_payment_with_route(&route, payment_hash,
+		RecipientOnionFields::secret_only(payment_secret), PaymentId(payment_hash.0)).unwrap();
+	check_added_monitors!(nodes[0], 1);
+	let payment_event = SendEvent::from_node(&nodes[0]);
+
+	nodes[1].node.quiesce_channel(&channel_id, &nodes[0].node.get_our_node_id()).unwrap();
+	let stfu = get_event_msg!(nodes[1], MessageSendEvent::SendStfu, nodes[0].node.get_our_node_id());
+	nodes[0].node.handle_stfu(&nodes[1].node.get_our_node_id(), &stfu);
+	assert!(nodes[0].node.get_and_clear_pending_msg_events().is_empty());
+	expect_quiescence_state(&nodes[0], &channel_id, ChannelQuiescenceState::AwaitingQuiescence);
+
+	nodes[1].node.handle_update_add_htlc(&nodes[0].node.get_our_node_id(), &payment_event.msgs[0]);
+	nodes[1].node.handle_commitment_signed(&nodes[0].node.get_our_node_id(), &payment_event.commitment_msg);
+	check_added_monitors!(nodes[1], 1);
+	let (bs_revoke_and_ack, bs_commitment_signed) = get_revoke_commit_msgs!(nodes[1], nodes[0].node.get_our_node_id());
+	nodes[0].node.handle_revoke_and_ack(&nodes[1].node.get_our_node_id(), &bs_revoke_and_ack);
+	check_added_monitors!(nodes[0], 1);
+	nodes[0].node.handle_commitment_signed(&nodes[1].node.get_our_node_id(), &bs_commitment_signed);
+	check_added_monitors!(nodes[0], 1);
+
+	// Our final revoke_and_ack commits the HTLC, so our stfu follows it.
+	let events = nodes[0].node.get_and_clear_pending_msg_events();
+	assert_eq!(events.len(), 2);
+	match events[0] {
+		MessageSendEvent::SendRevokeAndACK { ref msg, .. } => {
+			nodes[1].node.handle_revoke_and_ack(&nodes[0].node.get_our_node_id(), msg);
+			check_added_monitors!(nodes[1], 1);
+		},
+		_ => panic!("Unexpected event"),
+	}
+	match events[1] {
+		MessageSendEvent::SendStfu { ref msg, .. } => {
+			assert!(!msg.initiator);
+			nodes[1].node.handle_stfu(&nodes[0].node.get_our_node_id(), msg);
+		},
+		_ => panic!("Unexpected event"),
+	}
+	expect_quiescence_state(&nodes[0], &channel_id, ChannelQuiescenceState::Quiescent);
+	expect_quiescence_state(&nodes[1], &channel_id, ChannelQuiescenceState::Quiescent);
+
+	// Claiming the HTLC while quiescent places the claim in the holding cell, though the
+	// preimage still makes it into the ChannelMonitor.
+	expect_pending_htlcs_forwardable!(nodes[1]);
+	expect_payment_claimable!(nodes[1], payment_hash, payment_secret, 1_000_000);
+	nodes[1].node.claim_funds(payment_preimage);
+	check_added_monitors!(nodes[1], 1);
+	expect_payment_claimed!(nodes[1], payment_hash, 1_000_000);
+	assert!(nodes[1].node.get_and_clear_pending_msg_events().is_empty());
+
+	// Any update sent by our counterparty while quiescent is a protocol violation.
+	nodes[1].node.handle_update_add_htlc(&nodes[0].node.get_our_node_id(), &payment_event.msgs[0]);
+	let events = nodes[1].node.get_and_clear_pending_msg_events();
+	assert_eq!(events.len(), 1);
+	match events[0] {
+		MessageSendEvent::HandleError { action: ErrorAction::DisconnectPeerWithWarning { .. }, .. } => {},
+		_ => panic!("Unexpected event"),
+	}
+
+	disconnect_and_reestablish(&nodes[0], &nodes[1]);
+	assert!(get_non_update_msg_events(&nodes[0]).is_empty());
+	let mut events = get_non_update_msg_events(&nodes[1]);
+	assert_eq!(events.len(), 1);
+	check_added_monitors!(nodes[1], 1);
+	match events.remove(0) {
+		MessageSendEvent::UpdateHTLCs { updates, .. } => {
+			nodes[0].node.handle_update_fulfill_htlc(&nodes[1].node.get_our_node_id(), &updates.update_fulfill_htlcs[0]);
+			commitment_signed_dance!(nodes[0], nodes[1], updates.commitment_signed, false);
+		},
+		_ => panic!("Unexpected event"),
+	}
+	expect_payment_sent!(nodes[0], payment_preimage);
+}
+
+#[test]
+fn test_concurrent_quiescence_proposals() {
+	// If both sides propose quiescence at the same time, both consider themselves the initiator
+	// until the tie is broken in favor of the channel funder.
+	let chanmon_cfgs = create_chanmon_cfgs(2);
+	let node_cfgs = create_node_cfgs(2, &chanmon_cfgs);
+	let node_chanmgrs = create_node_chanmgrs(2, &node_cfgs, &[None, None]);
+	let nodes = create_network(2, &node_cfgs, &node_chanmgrs);
+	let channel_id = create_announced_chan_between_nodes(&nodes, 0, 1).2;
+
+	nodes[0].node.quiesce_channel(&channel_id, &nodes[1].node.get_our_node_id()).unwrap();
+	let as_stfu = get_event_msg!(nodes[0], MessageSendEvent::SendStfu, nodes[1].node.get_our_node_id());
+	nodes[1].node.quiesce_channel(&channel_id, &nodes[0].node.get_our_node_id()).unwrap();
+	let bs_stfu = get_event_msg!(nodes[1], MessageSendEvent::SendStfu, nodes[0].node.get_our_node_id());
+	assert!(as_stfu.initiator && bs_stfu.initiator);
+
+	nodes[0].node.handle_stfu(&nodes[1].node.get_our_node_id(), &bs_stfu);
+	nodes[1].node.handle_stfu(&nodes[0].node.get_our_node_id(), &as_stfu);
+	assert!(nodes[0].node.get_and_clear_pending_msg_events().is_empty());
+	assert!(nodes[1].node.get_and_clear_pending_msg_events().is_empty());
+	expect_quiescence_state(&nodes[0], &channel_id, ChannelQuiescenceState::Quiescent);
+	expect_quiescence_state(&nodes[1], &channel_id, ChannelQuiescenceState::Quiescent);
+
+	// A further stfu is a protocol violation.
+	nodes[1].node.handle_stfu(&nodes[0].node.get_our_node_id(), &as_stfu);
+	let events = nodes[1].node.get_and_clear_pending_msg_events();
+	assert_eq!(events.len(), 1);
+	match events[0] {
+		MessageSendEvent::HandleError { action: ErrorAction::DisconnectPeerWithWarning { .. }, .. } => {},
+		_ => panic!("Unexpected event"),
+	}
+}
+
+#[test]
+fn test_quiescence_timeout() {
+	// If our counterparty never responds to our stfu, we disconnect them and propose quiescence
+	// again once they reconnect.
+	let chanmon_cfgs = create_chanmon_cfgs(2);
+	let node_cfgs = create_node_cfgs(2, &chanmon_cfgs);
+	let node_chanmgrs = create_node_chanmgrs(2, &node_cfgs, &[None, None]);
+	let nodes = create_network(2, &node_cfgs, &node_chanmgrs);
+	let channel_id = create_announced_chan_between_nodes(&nodes, 0, 1).2;
+	send_payment(&nodes[0], &[&nodes[1]], 10_000_000);
+
+	nodes[0].node.quiesce_channel(&channel_id, &nodes[1].node.get_our_node_id()).unwrap();
+	get_event_msg!(nodes[0], MessageSendEvent::SendStfu, nodes[1].node.get_our_node_id());
+
+	for _ in 0..DISCONNECT_PEER_AWAITING_QUIESCENCE_TICKS - 1 {
+		nodes[0].node.timer_tick_occurred();
+		assert!(nodes[0].node.get_and_clear_pending_msg_events().is_empty());
+	}
+	nodes[0].node.timer_tick_occurred();
+	let events = nodes[0].node.get_and_clear_pending_msg_events();
+	assert_eq!(events.len(), 1);
+	match events[0] {
+		MessageSendEvent::HandleError { action: ErrorAction::DisconnectPeerWithWarning { ref msg }, .. } => {
+			assert_eq!(msg.data, "Disconnecting due to timeout awaiting quiescence");
+		},
+		_ => panic!("Unexpected event"),
+	}
+
+	disconnect_and_reestablish(&nodes[0], &nodes[1]);
+	expect_quiescence_state(&nodes[0], &channel_id, ChannelQuiescenceState::AwaitingQuiescence);
+	expect_quiescence_state(&nodes[1], &channel_id, ChannelQuiescenceState::NotQuiescent);
+	let events = get_non_update_msg_events(&nodes[0]);
+	assert_eq!(events.len(), 1);
+	let stfu = match events[0] {
+		MessageSendEvent::SendStfu { ref msg, .. } => msg.clone(),
+		_ => panic!("Unexpected event"),
+	};
+	assert!(get_non_update_msg_events(&nodes[1]).is_empty());
+
+	nodes[1].node.handle_stfu(&nodes[0].node.get_our_node_id(), &stfu);
+	let stfu = get_event_msg!(nodes[1], MessageSendEvent::SendStfu, nodes[0].node.get_our_node_id());
+	nodes[0].node.handle_stfu(&nodes[1].node.get_our_node_id(), &stfu);
+	expect_quiescence_state(&nodes[0], &channel_id, ChannelQuiescenceState::Quiescent);
+	expect_quiescence_state(&nodes[1], &channel_id, ChannelQuiescenceState::Quiescent);
+
+	// Once quiescent, the timer stops, as the protocol requiring quiescence may take a while.
+	for _ in 0..DISCONNECT_PEER_AWAITING_QUIESCENCE_TICKS {
+		nodes[0].node.timer_tick_occurred();
+		nodes[1].node.timer_tick_occurred();
+	}
+	assert!(nodes[0].node.get_and_clear_pending_msg_events().is_empty());
+	assert!(nodes[1].node.get_and_clear_pending_msg_events().is_empty());
+
+	// Having not proposed quiescence, nodes[1] doesn't do so again upon reconnection.
+	disconnect_and_reestablish(&nodes[0], &nodes[1]);
+	expect_quiescence_state(&nodes[0], &channel_id, ChannelQuiescenceState::NotQuiescent);
+	expect_quiescence_state(&nodes[1], &channel_id, ChannelQuiescenceState::NotQuiescent);
+	assert!(get_non_update_msg_events(&nodes[0]).is_empty());
+	assert!(get_non_update_msg_events(&nodes[1]).is_empty());
+}
+
+#[test]
+fn test_remote_quiescence_timeout() {
+	// If our counterparty proposed quiescence but the channel doesn't become quiescent in time, we
+	// disconnect them as well, without proposing quiescence ourselves upon reconnection.
+	let chanmon_cfgs = create_chanmon_cfgs(2);
+	let node_cfgs = create_node_cfgs(2, &chanmon_cfgs);
+	let node_chanmgrs = create_node_chanmgrs(2, &node_cfgs, &[None, None]);
+	let nodes = create_network(2, &node_cfgs, &node_chanmgrs);
+	let channel_id = create_announced_chan_between_nodes(&nodes, 0, 1).2;
+
+	// nodes[0] adds an HTLC whose monitor update never completes, so it can't respond to the stfu.
+	let (route, payment_hash, _, payment_secret) = get_route_and_payment_hash!(nodes[0], nodes[1], 1_000_000);
+	chanmon_cfgs[0].persister.set_update_ret(ChannelMonitorUpdateStatus::InProgress);
+	unwrap_send_err!(nodes[0].node.send_payment_with_route(&route, payment_hash,
+		RecipientOnionFields::secret_only(payment_secret), PaymentId(payment_hash.0)
+	), false, APIError::MonitorUpdateInProgress, {});
+	check_added_monitors!(nodes[0], 1);
+	assert!(nodes[0].node.get_and_clear_pending_msg_events().is_empty());
+
+	nodes[1].node.quiesce_channel(&channel_id, &nodes[0].node.get_our_node_id()).unwrap();
+	let stfu = get_event_msg!(nodes[1], MessageSendEvent::SendStfu, nodes[0].node.get_our_node_id());
+	nodes[0].node.handle_stfu(&nodes[1].node.get_our_node_id(), &stfu);
+	assert!(nodes[0].node.get_and_clear_pending_msg_events().is_empty());
+	expect_quiescence_state(&nodes[0], &channel_id, ChannelQuiescenceState::AwaitingQuiescence);
+
+	for _ in 0..DISCONNECT_PEER_AWAITING_QUIESCENCE_TICKS - 1 {
+		nodes[0].node.timer_tick_occurred();
+		assert!(nodes[0].node.get_and_clear_pending_msg_events().is_empty());
+	}
+	nodes[0].node.timer_tick_occurred();
+	let events = nodes[0].node.get_and_clear_pending_msg_events();
+	assert_eq!(events.len(), 1);
+	match events[0] {
+		MessageSendEvent::HandleError { action: ErrorAction::DisconnectPeerWithWarning { ref msg }, .. } => {
+			assert_eq!(msg.data, "Disconnecting due to timeout awaiting quiescence");
+		},
+		_ => panic!("Unexpected event"),
+	}
+
+	nodes[0].node.peer_disconnected(&nodes[1].node.get_our_node_id());
+	nodes[1].node.peer_disconnected(&nodes[0].node.get_our_node_id());
+	expect_quiescence_state(&nodes[0], &channel_id, ChannelQuiescenceState::NotQuiescent);
+	expect_quiescence_state(&nodes[1], &channel_id, ChannelQuiescenceState::AwaitingQuiescence);
+}
@@ -0,0 +1,519 @@
+// This file is Copyright its original authors, visible in version control
+// history.
+//
+// This file is licensed under the Apache License, Version 2.0 <LICENSE-APACHE
+// or http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your option.
+// You may not use this file except in accordance with one or both of these
+// licenses.
+
+//! Tests that test splicing funds into and out of live channels, replacing their funding output
+//! with one spent by a new funding transaction constructed interactively.
+
+use crate::chain::channelmonitor::ANTI_REORG_DELAY;
+use crate::events::{Event, MessageSendEvent, MessageSendEventsProvider};
+use crate::ln::{PaymentHash, PaymentPreimage};
+use crate::ln::channelmanager::{PaymentId, RecipientOnionFields};
+use crate::ln::functional_test_utils::*;
+use crate::ln::msgs;
+use crate::ln::msgs::ChannelMessageHandler;
+use crate::util::errors::APIError;
+
+use bitcoin::hashes::Hash;
+use bitcoin::hashes::sha256::Hash as Sha256;
+use bitcoin::secp256k1::{PublicKey, Secp256k1, SecretKey};
+use bitcoin::{OutPoint, PackedLockTime, Script, Sequence, Transaction, TxIn, TxOut, WPubkeyHash, Witness};
+
+use crate::prelude::*;
+
+fn funding_input_pubkey(seed: u8) -> bitcoin::PublicKey {
+	let secret_key = SecretKey::from_slice(&[seed; 32]).unwrap();
+	bitcoin::PublicKey::new(PublicKey::from_secret_key(&Secp256k1::new(), &secret_key))
+}
+
+/// Builds a P2WPKH witness for an input created by [`funding_input`] with the same `seed`. The
+/// signature is bogus, but the witness is enough for the input to be matched by chain filters.
+fn funding_input_witness(seed: u8) -> Witness {
+	Witness::from_vec(vec![vec![1; 72], funding_input_pubkey(seed).to_bytes()])
+}
+
+fn funding_input(seed: u8, value: u64) -> (TxIn, Transaction) {
+	let prev_tx = Transaction {
+		version: 2,
+		lock_time: PackedLockTime(seed as u32),
+		input: vec![],
+		output: vec![TxOut { value, script_pubkey: Script::new_v0_p2wpkh(&funding_input_pubkey(seed).wpubkey_hash().unwrap()) }],
+	};
+	let txin = TxIn {
+		previous_output: OutPoint { txid: prev_tx.txid(), vout: 0 },
+		sequence: Sequence::ENABLE_RBF_NO_LOCKTIME,
+		..Default::default()
+	};
+	(txin, prev_tx)
+}
+
+/// Delivers all messages making the channel quiescent and negotiating and signing a splice's new
+/// funding transaction between the two nodes until neither has anything left to send.
+fn pass_splice_msgs<'a, 'b, 'c>(node_a: &Node<'a, 'b, 'c>, node_b: &Node<'a, 'b, 'c>) {
+	loop {
+		let mut delivered_msg = false;
+		for (from, to) in [(node_a, node_b), (node_b, node_a)] {
+			let from_node_id = from.node.get_our_node_id();
+			for event in from.node.get_and_clear_pending_msg_events() {
+				delivered_msg = true;
+				match event {
+					MessageSendEvent::SendStfu { node_id, msg } => {
+						assert_eq!(node_id, to.node.get_our_node_id());
+						to.node.handle_stfu(&from_node_id, &msg);
+					},
+					MessageSendEvent::SendSplice { node_id, msg } => {
+						assert_eq!(node_id, to.node.get_our_node_id());
+						to.node.handle_splice(&from_node_id, &msg);
+					},
+					MessageSendEvent::SendTxInitRbf { node_id, msg } => {
+						assert_eq!(node_id, to.node.get_our_node_id());
+						to.node.handle_tx_init_rbf(&from_node_id, &msg);
+					},
+					MessageSendEvent::SendTxAckRbf { node_id, msg } => {
+						assert_eq!(node_id, to.node.get_our_node_id());
+						to.node.handle_tx_ack_rbf(&from_node_id, &msg);
+					},
+					MessageSendEvent::SendSpliceAck { node_id, msg } => {
+						assert_eq!(node_id, to.node.get_our_node_id());
+						to.node.handle_splice_ack(&from_node_id, &msg);
+					},
+					MessageSendEvent::SendTxAddInput { node_id, msg } => {
+						assert_eq!(node_id, to.node.get_our_node_id());
+						to.node.handle_tx_add_input(&from_node_id, &msg);
+					},
+					MessageSendEvent::SendTxAddOutput { node_id, msg } => {
+						assert_eq!(node_id, to.node.get_our_node_id());
+						to.node.handle_tx_add_output(&from_node_id, &msg);
+					},
+					MessageSendEvent::SendTxComplete { node_id, msg } => {
+						assert_eq!(node_id, to.node.get_our_node_id());
+						to.node.handle_tx_complete(&from_node_id, &msg);
+					},
+					MessageSendEvent::UpdateHTLCs { node_id, updates } => {
+						assert_eq!(node_id, to.node.get_our_node_id());
+						assert!(updates.update_add_htlcs.is_empty());
+						assert!(updates.splice_commitment_signed.is_empty());
+						// The first commitment_signed for a splice's new funding output has a new
+						// monitor watch it.
+						to.node.handle_commitment_signed(&from_node_id, &updates.commitment_signed);
+						check_added_monitors!(to, 1);
+					},
+					MessageSendEvent::SendTxSignatures { node_id, msg } => {
+						assert_eq!(node_id, to.node.get_our_node_id());
+						to.node.handle_tx_signatures(&from_node_id, &msg);
+					},
+					_ => panic!("Unexpected event {:?}", event),
+				}
+			}
+		}
+		if !delivered_msg { break; }
+	}
+}
+
+fn has_pending_splice<'a, 'b, 'c>(node: &Node<'a, 'b, 'c>, counterparty: &Node<'a, 'b, 'c>, channel_id: [u8; 32]) -> bool {
+	let per_peer_state_lock;
+	let mut peer_state_lock;
+	get_channel_ref!(node, counterparty, per_peer_state_lock, peer_state_lock, channel_id).context.has_pending_splice()
+}
+
+fn get_broadcast_splice_transaction<'a, 'b, 'c>(node: &Node<'a, 'b, 'c>) -> Transaction {
+	let mut txn = node.tx_broadcaster.txn_broadcasted.lock().unwrap();
+	assert_eq!(txn.len(), 1);
+	txn.remove(0)
+}
+
+/// Signs our input created by [`funding_input`] with the given `seed` in the splice transaction
+/// we were asked to sign through an [`Event::FundingTransactionReadyForSigning`].
+fn sign_splice_transaction<'a, 'b, 'c>(node: &Node<'a, 'b, 'c>, channel_id: [u8; 32], prev_tx: &Transaction, seed: u8) {
+	let events = node.node.get_and_clear_pending_events();
+	assert_eq!(events.len(), 1);
+	match events[0] {
+		Event::FundingTransactionReadyForSigning { channel_id: event_channel_id, ref counterparty_node_id, ref unsigned_transaction, .. } => {
+			assert_eq!(event_channel_id, channel_id);
+			let mut signed_tx = unsigned_transaction.clone();
+			for input in signed_tx.input.iter_mut() {
+				if input.previous_output.txid == prev_tx.txid() {
+					input.witness = funding_input_witness(seed);
+				}
+			}
+			node.node.funding_transaction_signed(&channel_id, counterparty_node_id, signed_tx).unwrap();
+		},
+		_ => panic!("Unexpected event"),
+	}
+}
+
+/// Delivers the updates in `updates` from `from` to `to`, along with the batch of
+/// `commitment_signed`s for the current funding output and each of a pending splice's.
+fn handle_commitment_update<'a, 'b, 'c>(to: &Node<'a, 'b, 'c>, from: &Node<'a, 'b, 'c>, updates: &msgs::CommitmentUpdate) {
+	let from_node_id = from.node.get_our_node_id();
+	for msg in updates.update_add_htlcs.iter() {
+		to.node.handle_update_add_htlc(&from_node_id, msg);
+	}
+	for msg in updates.update_fulfill_htlcs.iter() {
+		to.node.handle_update_fulfill_htlc(&from_node_id, msg);
+	}
+	for msg in updates.update_fail_htlcs.iter() {
+		to.node.handle_update_fail_htlc(&from_node_id, msg);
+	}
+	to.node.handle_commitment_signed(&from_node_id, &updates.commitment_signed);
+	for msg in updates.splice_commitment_signed.iter() {
+		to.node.handle_commitment_signed(&from_node_id, msg);
+	}
+}
+
+/// Irrevocably commits the updates in `updates` sent by `from` to `to` while a splice is pending,
+/// with every update being applied to the monitors for the current funding output and each of
+/// the splice's.
+fn splice_commitment_signed_dance<'a, 'b, 'c>(to: &Node<'a, 'b, 'c>, from: &Node<'a, 'b, 'c>, updates: &msgs::CommitmentUpdate) {
+	let monitor_count = updates.splice_commitment_signed.len() + 1;
+	assert!(updates.commitment_signed.batch.is_some());
+	handle_commitment_update(to, from, updates);
+	check_added_monitors!(to, monitor_count);
+	let events = to.node.get_and_clear_pending_msg_events();
+	assert_eq!(events.len(), 2);
+	match events[0] {
+		MessageSendEvent::SendRevokeAndACK { ref msg, .. } => {
+			from.node.handle_revoke_and_ack(&to.node.get_our_node_id(), msg);
+			check_added_monitors!(from, monitor_count);
+		},
+		_ => panic!("Unexpected event"),
+	}
+	match events[1] {
+		MessageSendEvent::UpdateHTLCs { ref updates, .. } => {
+			assert_eq!(updates.splice_commitment_signed.len() + 1, monitor_count);
+			handle_commitment_update(from, to, updates);
+			check_added_monitors!(from, monitor_count);
+		},
+		_ => panic!("Unexpected event"),
+	}
+	let raa = get_event_msg!(from, MessageSendEvent::SendRevokeAndACK, to.node.get_our_node_id());
+	to.node.handle_revoke_and_ack(&from.node.get_our_node_id(), &raa);
+	check_added_monitors!(to, monitor_count);
+}
+
+/// Sends an HTLC from `from` to `to` over a channel with a pending splice, returning its payment
+/// preimage once it is claimable.
+fn send_htlc_during_splice<'a, 'b, 'c>(from: &Node<'a, 'b, 'c>, to: &Node<'a, 'b, 'c>, amount_msat: u64, monitor_count: usize) -> PaymentPreimage {
+	let (route, payment_hash, payment_preimage, payment_secret) = get_route_and_payment_hash!(from, to, amount_msat);
+	from.node.send_payment_with_route(&route, payment_hash,
+		RecipientOnionFields::secret_only(payment_secret), PaymentId(payment_hash.0)).unwrap();
+	check_added_monitors!(from, monitor_count);
+	let updates = get_htlc_update_msgs!(from, to.node.get_our_node_id());
+	assert_eq!(updates.splice_commitment_signed.len() + 1, monitor_count);
+	splice_commitment_signed_dance(to, from, &updates);
+	expect_pending_htlcs_forwardable!(to);
+	expect_payment_claimable!(to, payment_hash, payment_secret, amount_msat);
+	payment_preimage
+}
+
+/// Claims an HTLC sent by `from` to `to` over a channel with a pending splice.
+fn claim_htlc_during_splice<'a, 'b, 'c>(from: &Node<'a, 'b, 'c>, to: &Node<'a, 'b, 'c>, payment_preimage: PaymentPreimage, amount_msat: u64, monitor_count: usize) {
+	to.node.claim_funds(payment_preimage);
+	check_added_monitors!(to, monitor_count);
+	let payment_hash = PaymentHash(Sha256::hash(&payment_preimage.0).into_inner());
+	expect_payment_claimed!(to, payment_hash, amount_msat);
+	let updates = get_htlc_update_msgs!(to, from.node.get_our_node_id());
+	splice_commitment_signed_dance(from, to, &updates);
+	expect_payment_sent!(from, payment_preimage);
+}
+
+/// Confirms the splice transaction on both nodes and exchanges `splice_locked`, after which both
+/// nodes must have switched over to the new funding output, closing the monitors watching the
+/// funding outputs of the `replaced_tx_count` other transactions of the splice.
+fn lock_splice<'a, 'b, 'c>(nodes: &Vec<Node<'a, 'b, 'c>>, splice_tx: &Transaction, replaced_tx_count: usize) {
+	let conf_height = core::cmp::max(nodes[0].best_block_info().1 + 1, nodes[1].best_block_info().1 + 1);
+	confirm_transaction_at(&nodes[0], splice_tx, conf_height);
+	connect_blocks(&nodes[0], CHAN_CONFIRM_DEPTH - 1);
+	confirm_transaction_at(&nodes[1], splice_tx, conf_height);
+	connect_blocks(&nodes[1], CHAN_CONFIRM_DEPTH - 1);
+
+	let as_splice_locked = get_event_msg!(nodes[0], MessageSendEvent::SendSpliceLocked, nodes[1].node.get_our_node_id());
+	let bs_splice_locked = get_event_msg!(nodes[1], MessageSendEvent::SendSpliceLocked, nodes[0].node.get_our_node_id());
+	nodes[0].node.handle_splice_locked(&nodes[1].node.get_our_node_id(), &bs_splice_locked);
+	check_added_monitors!(nodes[0], replaced_tx_count);
+	nodes[1].node.handle_splice_locked(&nodes[0].node.get_our_node_id(), &as_splice_locked);
+	check_added_monitors!(nodes[1], replaced_tx_count);
+
+	for node in nodes.iter() {
+		let channel = &node.node.list_channels()[0];
+		assert_eq!(channel.funding_txo.unwrap().txid, splice_tx.txid());
+		assert_eq!(channel.short_channel_id.unwrap() >> 40, conf_height as u64);
+	}
+}
+
+#[test]
+fn test_splice_in() {
+	let chanmon_cfgs = create_chanmon_cfgs(2);
+	let node_cfgs = create_node_cfgs(2, &chanmon_cfgs);
+	let node_chanmgrs = create_node_chanmgrs(2, &node_cfgs, &[None, None]);
+	let nodes = create_network(2, &node_cfgs, &node_chanmgrs);
+	let (channel_ready, funding_tx) = create_unannounced_chan_between_nodes_with_value(&nodes, 0, 1, 100_000, 0);
+	let channel_id = channel_ready.channel_id;
+	send_payment(&nodes[0], &[&nodes[1]], 10_000_000);
+
+	let (input, prev_tx) = funding_input(1, 100_000);
+	nodes[0].node.splice_channel(&channel_id, &nodes[1].node.get_our_node_id(), 50_000,
+		vec![(input, prev_tx.clone())], 253).unwrap();
+
+	// The splice is proposed once the channel is quiescent. We then negotiate the splice
+	// transaction and exchange signatures for the commitment transactions spending it, after
+	// which only the splice initiator has inputs to sign.
+	let stfu = get_event_msg!(nodes[0], MessageSendEvent::SendStfu, nodes[1].node.get_our_node_id());
+	nodes[1].node.handle_stfu(&nodes[0].node.get_our_node_id(), &stfu);
+	let stfu = get_event_msg!(nodes[1], MessageSendEvent::SendStfu, nodes[0].node.get_our_node_id());
+	nodes[0].node.handle_stfu(&nodes[1].node.get_our_node_id(), &stfu);
+	let splice = get_event_msg!(nodes[0], MessageSendEvent::SendSplice, nodes[1].node.get_our_node_id());
+	assert_eq!(splice.relative_satoshis, 50_000);
+	nodes[1].node.handle_splice(&nodes[0].node.get_our_node_id(), &splice);
+	pass_splice_msgs(&nodes[0], &nodes[1]);
+	assert!(nodes[1].node.get_and_clear_pending_events().is_empty());
+	sign_splice_transaction(&nodes[0], channel_id, &prev_tx, 1);
+	pass_splice_msgs(&nodes[0], &nodes[1]);
+
+	let splice_tx = get_broadcast_splice_transaction(&nodes[0]);
+	assert_eq!(splice_tx, get_broadcast_splice_transaction(&nodes[1]));
+	assert_eq!(splice_tx.input.len(), 2);
+	assert!(splice_tx.input.iter().any(|input| input.previous_output.txid == funding_tx.txid()));
+	assert!(splice_tx.input.iter().all(|input| !input.witness.is_empty()));
+	assert!(splice_tx.output.iter().any(|output| output.value == 150_000));
+
+	// Until the splice is locked the channel keeps its previous funding output, while remaining
+	// usable with each update committed to both funding outputs.
+	let channel = &nodes[0].node.list_channels()[0];
+	assert_eq!(channel.funding_txo.unwrap().txid, funding_tx.txid());
+	assert_eq!(channel.channel_value_satoshis, 100_000);
+	let payment_preimage = send_htlc_during_splice(&nodes[0], &nodes[1], 5_000_000, 2);
+	claim_htlc_during_splice(&nodes[0], &nodes[1], payment_preimage, 5_000_000, 2);
+
+	lock_splice(&nodes, &splice_tx, 0);
+	assert_eq!(nodes[0].node.list_channels()[0].channel_value_satoshis, 150_000);
+	assert_eq!(nodes[1].node.list_channels()[0].channel_value_satoshis, 150_000);
+
+	// The spliced-in funds are available to spend from the channel.
+	assert_eq!(nodes[0].node.list_channels()[0].balance_msat, 135_000_000);
+	send_payment(&nodes[0], &[&nodes[1]], 10_000_000);
+	send_payment(&nodes[1], &[&nodes[0]], 10_000_000);
+}
+
+#[test]
+fn test_splice_out() {
+	let chanmon_cfgs = create_chanmon_cfgs(2);
+	let node_cfgs = create_node_cfgs(2, &chanmon_cfgs);
+	let node_chanmgrs = create_node_chanmgrs(2, &node_cfgs, &[None, None]);
+	let nodes = create_network(2, &node_cfgs, &node_chanmgrs);
+	let (channel_ready, funding_tx) = create_unannounced_chan_between_nodes_with_value(&nodes, 0, 1, 100_000, 0);
+	let channel_id = channel_ready.channel_id;
+	send_payment(&nodes[0], &[&nodes[1]], 10_000_000);
+
+	nodes[0].node.splice_channel(&channel_id, &nodes[1].node.get_our_node_id(), -40_000, Vec::new(), 253).unwrap();
+
+	// Neither side contributes inputs, so signatures for the shared input are exchanged without
+	// any user involvement.
+	pass_splice_msgs(&nodes[0], &nodes[1]);
+	assert!(nodes[0].node.get_and_clear_pending_events().is_empty());
+	assert!(nodes[1].node.get_and_clear_pending_events().is_empty());
+
+	let splice_tx = get_broadcast_splice_transaction(&nodes[0]);
+	assert_eq!(splice_tx, get_broadcast_splice_transaction(&nodes[1]));
+	assert_eq!(splice_tx.input.len(), 1);
+	assert_eq!(splice_tx.input[0].previous_output.txid, funding_tx.txid());
+	assert_eq!(splice_tx.output.len(), 2);
+	let fee = 100_000 - splice_tx.output.iter().map(|output| output.value).sum::<u64>();
+	assert!(splice_tx.output.iter().any(|output| output.value == 60_000));
+	assert!(splice_tx.output.iter().any(|output| output.value == 40_000 - fee));
+
+	lock_splice(&nodes, &splice_tx, 0);
+	let channel = &nodes[0].node.list_channels()[0];
+	assert_eq!(channel.channel_value_satoshis, 60_000);
+	assert_eq!(channel.balance_msat, 50_000_000);
+
+	send_payment(&nodes[0], &[&nodes[1]], 10_000_000);
+	send_payment(&nodes[1], &[&nodes[0]], 10_000_000);
+}
+
+#[test]
+fn test_splice_with_htlcs_in_flight() {
+	// Tests that a splice waits for HTLCs being added to be irrevocably committed before being
+	// negotiated, and that HTLCs pending while the splice is negotiated or locked are carried over
+	// to the new funding output.
+	let chanmon_cfgs = create_chanmon_cfgs(2);
+	let node_cfgs = create_node_cfgs(2, &chanmon_cfgs);
+	let node_chanmgrs = create_node_chanmgrs(2, &node_cfgs, &[None, None]);
+	let nodes = create_network(2, &node_cfgs, &node_chanmgrs);
+	let (channel_ready, _) = create_unannounced_chan_between_nodes_with_value(&nodes, 0, 1, 100_000, 0);
+	let channel_id = channel_ready.channel_id;
+
+	// We can't splice out more than our balance allows.
+	match nodes[1].node.splice_channel(&channel_id, &nodes[0].node.get_our_node_id(), -1_000, Vec::new(), 253) {
+		Err(APIError::APIMisuseError { err }) =>
+			assert_eq!(err, "Splicing out 1000 sats would leave our balance below our reserve"),
+		_ => panic!("Unexpected result"),
+	}
+	send_payment(&nodes[0], &[&nodes[1]], 10_000_000);
+
+	// A splice requested while an HTLC is being added only begins once the HTLC is committed.
+	let (route, payment_hash, payment_preimage, payment_secret) = get_route_and_payment_hash!(nodes[0], nodes[1], 1_000_000);
+	nodes[0].node.send_payment_with_route(&route, payment_hash,
+		RecipientOnionFields::secret_only(payment_secret), PaymentId(payment_hash.0)).unwrap();
+	check_added_monitors!(nodes[0], 1);
+	let payment_event = SendEvent::from_node(&nodes[0]);
+	nodes[0].node.splice_channel(&channel_id, &nodes[1].node.get_our_node_id(), -10_000, Vec::new(), 253).unwrap();
+	assert!(nodes[0].node.get_and_clear_pending_msg_events().is_empty());
+	match nodes[0].node.splice_channel(&channel_id, &nodes[1].node.get_our_node_id(), -10_000, Vec::new(), 253) {
+		Err(APIError::APIMisuseError { .. }) => {},
+		_ => panic!("Unexpected result"),
+	}
+
+	nodes[1].node.handle_update_add_htlc(&nodes[0].node.get_our_node_id(), &payment_event.msgs[0]);
+	nodes[1].node.handle_commitment_signed(&nodes[0].node.get_our_node_id(), &payment_event.commitment_msg);
+	check_added_monitors!(nodes[1], 1);
+	let (bs_revoke_and_ack, bs_commitment_signed) = get_revoke_commit_msgs!(nodes[1], nodes[0].node.get_our_node_id());
+	nodes[0].node.handle_revoke_and_ack(&nodes[1].node.get_our_node_id(), &bs_revoke_and_ack);
+	check_added_monitors!(nodes[0], 1);
+	nodes[0].node.handle_commitment_signed(&nodes[1].node.get_our_node_id(), &bs_commitment_signed);
+	check_added_monitors!(nodes[0], 1);
+	let events = nodes[0].node.get_and_clear_pending_msg_events();
+	assert_eq!(events.len(), 2);
+	match events[0] {
+		MessageSendEvent::SendRevokeAndACK { ref msg, .. } => {
+			nodes[1].node.handle_revoke_and_ack(&nodes[0].node.get_our_node_id(), msg);
+			check_added_monitors!(nodes[1], 1);
+		},
+		_ => panic!("Unexpected event"),
+	}
+	match events[1] {
+		MessageSendEvent::SendStfu { ref msg, .. } => nodes[1].node.handle_stfu(&nodes[0].node.get_our_node_id(), msg),
+		_ => panic!("Unexpected event"),
+	}
+	expect_pending_htlcs_forwardable!(nodes[1]);
+	expect_payment_claimable!(nodes[1], payment_hash, payment_secret, 1_000_000);
+
+	// The splice is negotiated with the HTLC still pending, which the new funding output's
+	// commitment transactions include.
+	pass_splice_msgs(&nodes[0], &nodes[1]);
+	let splice_tx = get_broadcast_splice_transaction(&nodes[0]);
+	assert_eq!(splice_tx, get_broadcast_splice_transaction(&nodes[1]));
+
+	// The HTLC is claimed while the splice is pending, and another one is added which remains
+	// pending once the splice is locked.
+	claim_htlc_during_splice(&nodes[0], &nodes[1], payment_preimage, 1_000_000, 2);
+	let payment_preimage = send_htlc_during_splice(&nodes[1], &nodes[0], 2_000_000, 2);
+
+	lock_splice(&nodes, &splice_tx, 0);
+	assert_eq!(nodes[0].node.list_channels()[0].channel_value_satoshis, 90_000);
+	claim_payment(&nodes[1], &[&nodes[0]], payment_preimage);
+	send_payment(&nodes[0], &[&nodes[1]], 10_000_000);
+}
+
+#[test]
+fn test_splice_rbf() {
+	// Tests that the transaction of a splice can be replaced by one paying a higher feerate, after
+	// which the channel commits to the funding outputs of both until either confirms.
+	let chanmon_cfgs = create_chanmon_cfgs(2);
+	let node_cfgs = create_node_cfgs(2, &chanmon_cfgs);
+	let node_chanmgrs = create_node_chanmgrs(2, &node_cfgs, &[None, None]);
+	let nodes = create_network(2, &node_cfgs, &node_chanmgrs);
+	let (channel_ready, funding_tx) = create_unannounced_chan_between_nodes_with_value(&nodes, 0, 1, 100_000, 0);
+	let channel_id = channel_ready.channel_id;
+	send_payment(&nodes[0], &[&nodes[1]], 10_000_000);
+
+	// Only the splice initiator may replace a signed splice transaction.
+	match nodes[0].node.rbf_splice_channel(&channel_id, &nodes[1].node.get_our_node_id(), Vec::new(), 300) {
+		Err(APIError::APIMisuseError { .. }) => {},
+		_ => panic!("Unexpected result"),
+	}
+	nodes[0].node.splice_channel(&channel_id, &nodes[1].node.get_our_node_id(), -40_000, Vec::new(), 253).unwrap();
+	pass_splice_msgs(&nodes[0], &nodes[1]);
+	let splice_tx = get_broadcast_splice_transaction(&nodes[0]);
+	assert_eq!(splice_tx, get_broadcast_splice_transaction(&nodes[1]));
+	match nodes[1].node.rbf_splice_channel(&channel_id, &nodes[0].node.get_our_node_id(), Vec::new(), 300) {
+		Err(APIError::APIMisuseError { .. }) => {},
+		_ => panic!("Unexpected result"),
+	}
+
+	// The replacement must pay at least 25/24 of the previous feerate.
+	match nodes[0].node.rbf_splice_channel(&channel_id, &nodes[1].node.get_our_node_id(), Vec::new(), 260) {
+		Err(APIError::APIMisuseError { err }) => assert_eq!(err,
+			"Replacing a splice transaction paying a feerate of 253 sat/kw requires a feerate of at least 264 sat/kw"),
+		_ => panic!("Unexpected result"),
+	}
+	nodes[0].node.rbf_splice_channel(&channel_id, &nodes[1].node.get_our_node_id(), Vec::new(), 300).unwrap();
+	pass_splice_msgs(&nodes[0], &nodes[1]);
+	let rbf_tx = get_broadcast_splice_transaction(&nodes[0]);
+	assert_eq!(rbf_tx, get_broadcast_splice_transaction(&nodes[1]));
+	assert_eq!(rbf_tx.input.len(), 1);
+	assert_eq!(rbf_tx.input[0].previous_output, splice_tx.input[0].previous_output);
+	assert_eq!(rbf_tx.input[0].previous_output.txid, funding_tx.txid());
+	let total_value = |tx: &Transaction| tx.output.iter().map(|output| output.value).sum::<u64>();
+	assert!(total_value(&rbf_tx) < total_value(&splice_tx));
+	assert!(rbf_tx.output.iter().any(|output| output.value == 60_000));
+
+	// Updates are now committed to the current funding output as well as both splice
+	// transactions' funding outputs.
+	let payment_preimage = send_htlc_during_splice(&nodes[0], &nodes[1], 5_000_000, 3);
+	claim_htlc_during_splice(&nodes[0], &nodes[1], payment_preimage, 5_000_000, 3);
+
+	// The original splice transaction may still confirm, in which case the monitor watching the
+	// replacement's funding output is closed.
+	lock_splice(&nodes, &splice_tx, 1);
+	let channel = &nodes[0].node.list_channels()[0];
+	assert_eq!(channel.channel_value_satoshis, 60_000);
+	assert_eq!(channel.balance_msat, 45_000_000);
+	send_payment(&nodes[0], &[&nodes[1]], 10_000_000);
+	send_payment(&nodes[1], &[&nodes[0]], 10_000_000);
+}
+
+#[test]
+fn test_splice_abandoned_on_double_spend() {
+	// Tests that a splice whose signed transaction can no longer confirm, as one of its inputs was
+	// double-spent, is abandoned by both parties once the double-spend is buried deep enough,
+	// after which the channel can be used again.
+	let chanmon_cfgs = create_chanmon_cfgs(2);
+	let node_cfgs = create_node_cfgs(2, &chanmon_cfgs);
+	let node_chanmgrs = create_node_chanmgrs(2, &node_cfgs, &[None, None]);
+	let nodes = create_network(2, &node_cfgs, &node_chanmgrs);
+	let (channel_ready, funding_tx) = create_unannounced_chan_between_nodes_with_value(&nodes, 0, 1, 100_000, 0);
+	let channel_id = channel_ready.channel_id;
+	send_payment(&nodes[0], &[&nodes[1]], 10_000_000);
+
+	let (input, prev_tx) = funding_input(1, 100_000);
+	nodes[0].node.splice_channel(&channel_id, &nodes[1].node.get_our_node_id(), 50_000,
+		vec![(input.clone(), prev_tx.clone())], 253).unwrap();
+	pass_splice_msgs(&nodes[0], &nodes[1]);
+	sign_splice_transaction(&nodes[0], channel_id, &prev_tx, 1);
+	pass_splice_msgs(&nodes[0], &nodes[1]);
+	let splice_tx = get_broadcast_splice_transaction(&nodes[0]);
+	assert_eq!(splice_tx, get_broadcast_splice_transaction(&nodes[1]));
+
+	// The input nodes[0] contributed is spent elsewhere instead.
+	let double_spend_tx = Transaction {
+		version: 2,
+		lock_time: PackedLockTime::ZERO,
+		input: vec![TxIn { witness: funding_input_witness(1), ..input }],
+		output: vec![TxOut { value: 99_000, script_pubkey: Script::new_v0_p2wpkh(&WPubkeyHash::from_slice(&[2; 20]).unwrap()) }],
+	};
+	for node in nodes.iter() {
+		mine_transaction(node, &double_spend_tx);
+		connect_blocks(node, ANTI_REORG_DELAY - 2);
+	}
+	// Until the double-spend is buried deep enough, the splice remains pending.
+	assert!(has_pending_splice(&nodes[0], &nodes[1], channel_id));
+	assert!(has_pending_splice(&nodes[1], &nodes[0], channel_id));
+
+	for (node, counterparty) in [(&nodes[0], &nodes[1]), (&nodes[1], &nodes[0])] {
+		connect_blocks(node, 1);
+		assert!(!has_pending_splice(node, counterparty, channel_id));
+		// The monitor watching the splice's new funding output is closed.
+		assert!(node.node.get_and_clear_pending_msg_events().is_empty());
+		check_added_monitors!(node, 1);
+		assert_eq!(node.node.list_channels()[0].funding_txo.unwrap().txid, funding_tx.txid());
+	}
+	send_payment(&nodes[0], &[&nodes[1]], 10_000_000);
+	send_payment(&nodes[1], &[&nodes[0]], 10_000_000);
+	assert_eq!(nodes[0].node.list_channels()[0].channel_value_satoshis, 100_000);
+}
@@ -68,6 +68,13 @@ pub(crate) enum Message<T> where T: core::fmt::Debug + Type + TestEq {
 	TxInitRbf(msgs::TxInitRbf),
 	TxAckRbf(msgs::TxAckRbf),
 	TxAbort(msgs::TxAbort),
+	Splice(msgs::Splice),
+	SpliceAck(msgs::SpliceAck),
+	SpliceLocked(msgs::SpliceLocked),
+	Stfu(msgs::Stfu),
+	DynPropose(msgs::DynPropose),
+	DynAck(msgs::DynAck),
+	DynReject(msgs::DynReject),
 	ChannelReady(msgs::ChannelReady),
 	Shutdown(msgs::Shutdown),
 	ClosingSigned(msgs::ClosingSigned),
@@ -119,6 +126,13 @@ impl<T> Writeable for Message<T> where T: core::fmt::Debug + Type + TestEq {
 			&Message::TxInitRbf(ref msg) => msg.write(writer),
 			&Message::TxAckRbf(ref msg) => msg.write(writer),
 			&Message::TxAbort(ref msg) => msg.write(writer),
+			&Message::Splice(ref msg) => msg.write(writer),
+			&Message::SpliceAck(ref msg) => msg.write(writer),
+			&Message::SpliceLocked(ref msg) => msg.write(writer),
+			&Message::Stfu(ref msg) => msg.write(writer),
+			&Message::DynPropose(ref msg) => msg.write(writer),
+			&Message::DynAck(ref msg) => msg.write(writer),
+			&Message::DynReject(ref msg) => msg.write(writer),
 			&Message::ChannelReady(ref msg) => msg.write(writer),
 			&Message::Shutdown(ref msg) => msg.write(writer),
 			&Message::ClosingSigned(ref msg) => msg.write(writer),
@@ -170,6 +184,13 @@ impl<T> Type for Message<T> where T: core::fmt::Debug + Type + TestEq {
 			&Message::TxInitRbf(ref msg) => msg.type_id(),
 			&Message::TxAckRbf(ref msg) => msg.type_id(),
 			&Message::TxAbort(ref msg) => msg.type_id(),
+			&Message::Splice(ref msg) => msg.type_id(),
+			&Message::SpliceAck(ref msg) => msg.type_id(),
+			&Message::SpliceLocked(ref msg) => msg.type_id(),
+			&Message::Stfu(ref msg) => msg.type_id(),
+			&Message::DynPropose(ref msg) => msg.type_id(),
+			&Message::DynAck(ref msg) => msg.type_id(),
+			&Message::DynReject(ref msg) => msg.type_id(),
 			&Message::ChannelReady(ref msg) => msg.type_id(),
 			&Message::Shutdown(ref msg) => msg.type_id(),
 			&Message::ClosingSigned(ref msg) => msg.type_id(),
@@ -285,6 +306,27 @@ fn do_read<R: io::Read, T, H: core::ops::Deref>(buffer: &mut R, message_type: u1
 		msgs::TxAbort::TYPE => {
 			Ok(Message::TxAbort(Readable::read(buffer)?))
 		},
+		msgs::Splice::TYPE => {
+			Ok(Message::Splice(Readable::read(buffer)?))
+		},
+		msgs::SpliceAck::TYPE => {
+			Ok(Message::SpliceAck(Readable::read(buffer)?))
+		},
+		msgs::SpliceLocked::TYPE => {
+			Ok(Message::SpliceLocked(Readable::read(buffer)?))
+		},
+		msgs::Stfu::TYPE => {
+			Ok(Message::Stfu(Readable::read(buffer)?))
+		},
+		msgs::DynPropose::TYPE => {
+			Ok(Message::DynPropose(Readable::read(buffer)?))
+		},
+		msgs::DynAck::TYPE => {
+			Ok(Message::DynAck(Readable::read(buffer)?))
+		},
+		msgs::DynReject::TYPE => {
+			Ok(Message::DynReject(Readable::read(buffer)?))
+		},
 		msgs::ChannelReady::TYPE => {
 			Ok(Message::ChannelReady(Readable::read(buffer)?))
 		},
@@ -500,6 +542,34 @@ impl Encode for msgs::TxAbort {
 	const TYPE: u16 = 74;
 }
 
+impl Encode for msgs::Splice {
+	const TYPE: u16 = 75;
+}
+
+impl Encode for msgs::SpliceAck {
+	const TYPE: u16 = 76;
+}
+
+impl Encode for msgs::SpliceLocked {
+	const TYPE: u16 = 77;
+}
+
+impl Encode for msgs::Stfu {
+	const TYPE: u16 = 2;
+}
+
+impl Encode for msgs::DynPropose {
+	const TYPE: u16 = 111;
+}
+
+impl Encode for msgs::DynAck {
+	const TYPE: u16 = 113;
+}
+
+impl Encode for msgs::DynReject {
+	const TYPE: u16 = 115;
+}
+
 impl Encode for msgs::OnionMessage {
 	const TYPE: u16 = 513;
 }
@@ -683,8 +753,9 @@ mod tests {
 	#[test]
 	fn read_clightning_init_msg() {
 		// Taken from c-lightning v0.8.0.
+		// Its only feature unknown to us used to be `gossip_queries_ex`.
 		let buffer = vec![0, 16, 0, 2, 34, 0, 0, 3, 2, 170, 162, 1, 32, 6, 34, 110, 70, 17, 26, 11, 89, 202, 175, 18, 96, 67, 235, 91, 191, 40, 195, 79, 58, 94, 51, 42, 31, 199, 178, 183, 60, 241, 136, 145, 15];
-		check_init_msg(buffer, true);
+		check_init_msg(buffer, false);
 	}
 
 	fn check_init_msg(buffer: Vec<u8>, expect_unknown: bool) {
@@ -11,10 +11,11 @@
 
 use crate::blinded_path::BlindedPath;
 use crate::sign::{NodeSigner, Recipient};
-use crate::ln::features::InitFeatures;
+use crate::ln::features::{InitFeatures, NodeFeatures};
 use crate::ln::msgs::{self, DecodeError, OnionMessageHandler};
-use super::{CustomOnionMessageContents, CustomOnionMessageHandler, Destination, MessageRouter, OffersMessage, OffersMessageHandler, OnionMessageContents, OnionMessagePath, OnionMessenger, SendError};
+use super::{CustomOnionMessageContents, CustomOnionMessageHandler, DefaultMessageRouter, Destination, MessageRouter, OffersMessage, OffersMessageHandler, OnionMessageContents, OnionMessagePath, OnionMessenger, SendError};
 use crate::util::ser::{Writeable, Writer};
+use crate::routing::test_utils as routing_test_utils;
 use crate::util::test_utils;
 
 use bitcoin::network::constants::Network;
@@ -429,3 +430,63 @@ fn many_hops() {
 	nodes[num_nodes-1].custom_message_handler.expect_message(TestCustomMessage::Response);
 	pass_along_path(&nodes);
 }
+
+#[test]
+fn default_message_router_finds_path() {
+	// Build network from our_id to node19:
+	// our_id - node0 - node1 - ... - node19
+	let (secp_ctx, network_graph, gossip_sync, _, _) = routing_test_utils::build_line_graph();
+	let (_, our_id, privkeys, nodes) = routing_test_utils::get_nodes(&secp_ctx);
+	let message_router = DefaultMessageRouter::new(Arc::clone(&network_graph));
+
+	let mut onion_message_features = NodeFeatures::empty();
+	onion_message_features.set_onion_messages_optional();
+	for privkey in privkeys[1..4].iter() {
+		routing_test_utils::add_or_update_node(
+			&gossip_sync, &secp_ctx, privkey, onion_message_features.clone(), 1
+		);
+	}
+
+	// Peers are sent to directly, regardless of their announced features.
+	let path = message_router.find_path(our_id, vec![nodes[0]], Destination::Node(nodes[0])).unwrap();
+	assert!(path.intermediate_nodes.is_empty());
+
+	// Otherwise, the path goes through our peers and nodes supporting onion messages.
+	let path = message_router.find_path(our_id, vec![nodes[0]], Destination::Node(nodes[3])).unwrap();
+	assert_eq!(path.intermediate_nodes, vec![nodes[0], nodes[1], nodes[2]]);
+
+	// Blinded paths are routed to their introduction node.
+	let keys_manager = test_utils::TestKeysInterface::new(&[42; 32], Network::Testnet);
+	let blinded_path = BlindedPath::new_for_message(&[nodes[3], nodes[4]], &keys_manager, &secp_ctx)
+		.unwrap();
+	let path = message_router.find_path(our_id, vec![nodes[0]], Destination::BlindedPath(blinded_path))
+		.unwrap();
+	assert_eq!(path.intermediate_nodes, vec![nodes[0], nodes[1], nodes[2]]);
+
+	// The path must start at a peer.
+	assert!(message_router.find_path(our_id, vec![], Destination::Node(nodes[3])).is_err());
+
+	// The destination must support onion messages.
+	assert!(message_router.find_path(our_id, vec![nodes[0]], Destination::Node(nodes[4])).is_err());
+}
+
+#[test]
+fn default_message_router_avoids_nodes_without_onion_message_support() {
+	let (secp_ctx, network_graph, gossip_sync, _, _) = routing_test_utils::build_line_graph();
+	let (_, our_id, privkeys, nodes) = routing_test_utils::get_nodes(&secp_ctx);
+	let message_router = DefaultMessageRouter::new(Arc::clone(&network_graph));
+
+	// Only node2 and node3 support onion messages, so node1 can't forward to them.
+	let mut onion_message_features = NodeFeatures::empty();
+	onion_message_features.set_onion_messages_optional();
+	for privkey in privkeys[2..4].iter() {
+		routing_test_utils::add_or_update_node(
+			&gossip_sync, &secp_ctx, privkey, onion_message_features.clone(), 1
+		);
+	}
+	assert!(message_router.find_path(our_id, vec![nodes[0]], Destination::Node(nodes[3])).is_err());
+
+	// Though being connected to node1 makes it usable.
+	let path = message_router.find_path(our_id, vec![nodes[1]], Destination::Node(nodes[3])).unwrap();
+	assert_eq!(path.intermediate_nodes, vec![nodes[1], nodes[2]]);
+}
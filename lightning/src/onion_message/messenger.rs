@@ -22,20 +22,22 @@ use crate::ln::features::{InitFeatures, NodeFeatures};
 use crate::ln::msgs::{self, OnionMessageHandler};
 use crate::ln::onion_utils;
 use crate::ln::peer_handler::IgnoringMessageHandler;
+use crate::routing::gossip::{NetworkGraph, NodeId};
 pub use super::packet::{CustomOnionMessageContents, OnionMessageContents};
 use super::offers::OffersMessageHandler;
 use super::packet::{BIG_PACKET_HOP_DATA_LEN, ForwardControlTlvs, Packet, Payload, ReceiveControlTlvs, SMALL_PACKET_HOP_DATA_LEN};
 use crate::util::logger::Logger;
 use crate::util::ser::Writeable;
 
+use core::fmt;
 use core::ops::Deref;
 use crate::io;
 use crate::sync::{Arc, Mutex};
 use crate::prelude::*;
 
-/// A sender, receiver and forwarder of onion messages. In upcoming releases, this object will be
-/// used to retrieve invoices and fulfill invoice requests from [offers]. Currently, only sending
-/// and receiving custom onion messages is supported.
+/// A sender, receiver and forwarder of onion messages. Used to retrieve invoices and fulfill
+/// invoice requests from [offers] when given an [`OffersMessageHandler`], such as a
+/// [`ChannelManager`], as well as to send and receive custom onion messages.
 ///
 /// # Example
 ///
@@ -120,6 +122,7 @@ use crate::prelude::*;
 /// ```
 ///
 /// [offers]: <https://github.com/lightning/bolts/pull/798>
+/// [`ChannelManager`]: crate::ln::channelmanager::ChannelManager
 /// [`OnionMessenger`]: crate::onion_message::OnionMessenger
 pub struct OnionMessenger<ES: Deref, NS: Deref, L: Deref, MR: Deref, OMH: Deref, CMH: Deref>
 where
@@ -152,13 +155,98 @@ pub trait MessageRouter {
 	) -> Result<OnionMessagePath, ()>;
 }
 
-/// A [`MessageRouter`] that always fails.
-pub struct DefaultMessageRouter;
+/// A [`MessageRouter`] that finds paths using the [`NetworkGraph`].
+///
+/// If the [`Destination`], or the introduction node of its [`BlindedPath`], is a peer then the
+/// message is sent to it directly. Otherwise, the path with the fewest hops is found through the
+/// announced channels of nodes which have announced support for onion messages, starting from one
+/// of our peers.
+pub struct DefaultMessageRouter<G: Deref<Target=NetworkGraph<L>>, L: Deref>
+where
+	L::Target: Logger,
+{
+	network_graph: G,
+}
+
+impl<G: Deref<Target=NetworkGraph<L>>, L: Deref> DefaultMessageRouter<G, L>
+where
+	L::Target: Logger,
+{
+	/// Creates a [`DefaultMessageRouter`] using the given [`NetworkGraph`].
+	pub fn new(network_graph: G) -> Self {
+		Self { network_graph }
+	}
+}
 
-impl MessageRouter for DefaultMessageRouter {
+impl<G: Deref<Target=NetworkGraph<L>>, L: Deref> MessageRouter for DefaultMessageRouter<G, L>
+where
+	L::Target: Logger,
+{
 	fn find_path(
-		&self, _sender: PublicKey, _peers: Vec<PublicKey>, _destination: Destination
+		&self, sender: PublicKey, peers: Vec<PublicKey>, destination: Destination
 	) -> Result<OnionMessagePath, ()> {
+		let first_node = destination.first_node();
+		if first_node == sender || peers.contains(&first_node) {
+			return Ok(OnionMessagePath { intermediate_nodes: vec![], destination });
+		}
+
+		let network_graph = self.network_graph.deref().read_only();
+		let supports_onion_messages = |node_id: &NodeId| {
+			network_graph.node(node_id)
+				.and_then(|node_info| node_info.announcement_info.as_ref())
+				.map_or(false, |announcement_info| announcement_info.features.supports_onion_messages())
+		};
+
+		let target = NodeId::from_pubkey(&first_node);
+		if !supports_onion_messages(&target) {
+			return Err(());
+		}
+
+		// Search breadth-first from our peers, which are known to support onion messages, tracking
+		// the node preceding each visited node so the path can be reconstructed once found.
+		let mut previous_nodes: HashMap<NodeId, Option<NodeId>> = HashMap::new();
+		let mut nodes_to_visit = VecDeque::new();
+		previous_nodes.insert(NodeId::from_pubkey(&sender), None);
+		for peer in peers.iter() {
+			let node_id = NodeId::from_pubkey(peer);
+			if previous_nodes.insert(node_id, None).is_none() {
+				nodes_to_visit.push_back(node_id);
+			}
+		}
+
+		while let Some(node_id) = nodes_to_visit.pop_front() {
+			let node_info = match network_graph.node(&node_id) {
+				Some(node_info) => node_info,
+				None => continue,
+			};
+			for short_channel_id in node_info.channels.iter() {
+				let next_node_id = match network_graph.channel(*short_channel_id) {
+					Some(channel) if channel.node_one == node_id => channel.node_two,
+					Some(channel) => channel.node_one,
+					None => continue,
+				};
+				if previous_nodes.contains_key(&next_node_id) {
+					continue;
+				}
+
+				if next_node_id == target {
+					let mut intermediate_nodes = vec![node_id.as_pubkey().map_err(|_| ())?];
+					let mut current_node_id = node_id;
+					while let Some(Some(previous_node_id)) = previous_nodes.get(&current_node_id) {
+						intermediate_nodes.push(previous_node_id.as_pubkey().map_err(|_| ())?);
+						current_node_id = *previous_node_id;
+					}
+					intermediate_nodes.reverse();
+					return Ok(OnionMessagePath { intermediate_nodes, destination });
+				}
+
+				if supports_onion_messages(&next_node_id) {
+					previous_nodes.insert(next_node_id, Some(node_id));
+					nodes_to_visit.push_back(next_node_id);
+				}
+			}
+		}
+
 		Err(())
 	}
 }
@@ -173,6 +261,27 @@ pub struct OnionMessagePath {
 	pub destination: Destination,
 }
 
+/// An [`OnionMessage`] for [`OnionMessenger`] to send.
+///
+/// These are obtained when released from [`OnionMessenger`]'s handlers after which they are
+/// enqueued for sending.
+///
+/// [`OnionMessage`]: msgs::OnionMessage
+pub struct PendingOnionMessage<T> {
+	/// The message contents to send in an [`OnionMessage`].
+	///
+	/// [`OnionMessage`]: msgs::OnionMessage
+	pub contents: T,
+
+	/// The destination of the message.
+	pub destination: Destination,
+
+	/// A reply path to include in the [`OnionMessage`] for a response.
+	///
+	/// [`OnionMessage`]: msgs::OnionMessage
+	pub reply_path: Option<BlindedPath>,
+}
+
 /// The destination of an onion message.
 #[derive(Clone)]
 pub enum Destination {
@@ -189,6 +298,15 @@ impl Destination {
 			Destination::BlindedPath(BlindedPath { blinded_hops, .. }) => blinded_hops.len(),
 		}
 	}
+
+	/// Returns the first node of the destination, i.e. the node itself or the introduction node of
+	/// the blinded path.
+	pub fn first_node(&self) -> PublicKey {
+		match self {
+			Destination::Node(node_id) => *node_id,
+			Destination::BlindedPath(BlindedPath { introduction_node_id, .. }) => *introduction_node_id,
+		}
+	}
 }
 
 /// Errors that may occur when [sending an onion message].
@@ -333,23 +451,39 @@ where
 		}
 	}
 
-	fn respond_with_onion_message<T: CustomOnionMessageContents>(
-		&self, response: OnionMessageContents<T>, path_id: Option<[u8; 32]>,
-		reply_path: Option<BlindedPath>
+	fn find_path_and_enqueue_onion_message<T: CustomOnionMessageContents>(
+		&self, contents: OnionMessageContents<T>, destination: Destination,
+		reply_path: Option<BlindedPath>, log_suffix: fmt::Arguments
 	) {
 		let sender = match self.node_signer.get_node_id(Recipient::Node) {
 			Ok(node_id) => node_id,
 			Err(_) => {
-				log_warn!(
-					self.logger, "Unable to retrieve node id when responding to onion message with \
-					path_id {:02x?}", path_id
-				);
+				log_warn!(self.logger, "Unable to retrieve node id {}", log_suffix);
 				return;
 			}
 		};
 
 		let peers = self.pending_messages.lock().unwrap().keys().copied().collect();
+		let path = match self.message_router.find_path(sender, peers, destination) {
+			Ok(path) => path,
+			Err(()) => {
+				log_trace!(self.logger, "Failed to find path {}", log_suffix);
+				return;
+			},
+		};
+
+		log_trace!(self.logger, "Sending onion message {}", log_suffix);
 
+		if let Err(e) = self.send_onion_message(path, contents, reply_path) {
+			log_trace!(self.logger, "Failed sending onion message {}: {:?}", log_suffix, e);
+			return;
+		}
+	}
+
+	fn respond_with_onion_message<T: CustomOnionMessageContents>(
+		&self, response: OnionMessageContents<T>, path_id: Option<[u8; 32]>,
+		reply_path: Option<BlindedPath>
+	) {
 		let destination = match reply_path {
 			Some(reply_path) => Destination::BlindedPath(reply_path),
 			None => {
@@ -361,26 +495,10 @@ where
 			},
 		};
 
-		let path = match self.message_router.find_path(sender, peers, destination) {
-			Ok(path) => path,
-			Err(()) => {
-				log_trace!(
-					self.logger, "Failed to find path when responding to onion message with \
-					path_id {:02x?}", path_id
-				);
-				return;
-			},
-		};
-
-		log_trace!(self.logger, "Responding to onion message with path_id {:02x?}", path_id);
-
-		if let Err(e) = self.send_onion_message(path, response, None) {
-			log_trace!(
-				self.logger, "Failed responding to onion message with path_id {:02x?}: {:?}",
-				path_id, e
-			);
-			return;
-		}
+		self.find_path_and_enqueue_onion_message(
+			response, destination, None,
+			format_args!("when responding to onion message with path_id {:02x?}", path_id)
+		);
 	}
 
 	#[cfg(test)]
@@ -592,6 +710,16 @@ where
 	CMH::Target: CustomOnionMessageHandler,
 {
 	fn next_onion_message_for_peer(&self, peer_node_id: PublicKey) -> Option<msgs::OnionMessage> {
+		// Enqueue any initiating `OffersMessage`s to send.
+		for message in self.offers_handler.release_pending_messages() {
+			let PendingOnionMessage { contents, destination, reply_path } = message;
+			self.find_path_and_enqueue_onion_message(
+				OnionMessageContents::<<CMH::Target as CustomOnionMessageHandler>::CustomMessage>
+					::Offers(contents),
+				destination, reply_path, format_args!("when sending OffersMessage")
+			);
+		}
+
 		let mut pending_msgs = self.pending_messages.lock().unwrap();
 		if let Some(msgs) = pending_msgs.get_mut(&peer_node_id) {
 			return msgs.pop_front()
@@ -613,7 +741,7 @@ pub type SimpleArcOnionMessenger<L> = OnionMessenger<
 	Arc<KeysManager>,
 	Arc<KeysManager>,
 	Arc<L>,
-	Arc<DefaultMessageRouter>,
+	Arc<DefaultMessageRouter<Arc<NetworkGraph<Arc<L>>>, Arc<L>>>,
 	IgnoringMessageHandler,
 	IgnoringMessageHandler
 >;
@@ -625,11 +753,11 @@ pub type SimpleArcOnionMessenger<L> = OnionMessenger<
 ///
 /// [`SimpleRefChannelManager`]: crate::ln::channelmanager::SimpleRefChannelManager
 /// [`SimpleRefPeerManager`]: crate::ln::peer_handler::SimpleRefPeerManager
-pub type SimpleRefOnionMessenger<'a, 'b, 'c, L> = OnionMessenger<
+pub type SimpleRefOnionMessenger<'a, 'b, 'c, 'd, L> = OnionMessenger<
 	&'a KeysManager,
 	&'a KeysManager,
 	&'b L,
-	&'c DefaultMessageRouter,
+	&'c DefaultMessageRouter<&'d NetworkGraph<&'b L>, &'b L>,
 	IgnoringMessageHandler,
 	IgnoringMessageHandler
 >;
@@ -26,7 +26,7 @@ use crate::events::{MessageSendEvent, MessageSendEventsProvider};
 use crate::ln::features::{ChannelFeatures, NodeFeatures, InitFeatures};
 use crate::ln::msgs::{DecodeError, ErrorAction, Init, LightningError, RoutingMessageHandler, NetAddress, MAX_VALUE_MSAT};
 use crate::ln::msgs::{ChannelAnnouncement, ChannelUpdate, NodeAnnouncement, GossipTimestampFilter};
-use crate::ln::msgs::{QueryChannelRange, ReplyChannelRange, QueryShortChannelIds, ReplyShortChannelIdsEnd};
+use crate::ln::msgs::{QueryChannelRange, ReplyChannelRange, QueryShortChannelIds, ReplyShortChannelIdsEnd, GossipQueryReply};
 use crate::ln::msgs;
 use crate::routing::utxo::{self, UtxoLookup, UtxoResolver};
 use crate::util::ser::{Readable, ReadableArgs, Writeable, Writer, MaybeReadable};
@@ -65,6 +65,40 @@ const MAX_EXCESS_BYTES_FOR_RELAY: usize = 1024;
 /// This value ensures a reply fits within the 65k payload limit and is consistent with other implementations.
 const MAX_SCIDS_PER_REPLY: usize = 8000;
 
+/// Maximum number of short_channel_ids that will be encoded in one gossip reply message which
+/// also includes update timestamps and checksums, each taking an additional 8 bytes per channel.
+const MAX_SCIDS_PER_EXTENDED_REPLY: usize = 2500;
+
+/// Maximum number of short_channel_ids we'll request the gossip of in a single
+/// [`QueryShortChannelIds`] when syncing the graph from a peer.
+const MAX_SCIDS_PER_QUERY: usize = 1000;
+
+/// All the `QUERY_FLAG_*` bits, requesting every message known for a channel.
+const ALL_QUERY_FLAGS: u64 = msgs::QUERY_FLAG_CHANNEL_ANNOUNCEMENT
+	| msgs::QUERY_FLAG_CHANNEL_UPDATE_NODE_1 | msgs::QUERY_FLAG_CHANNEL_UPDATE_NODE_2
+	| msgs::QUERY_FLAG_NODE_ANNOUNCEMENT_NODE_1 | msgs::QUERY_FLAG_NODE_ANNOUNCEMENT_NODE_2;
+
+/// Computes the CRC32C (Castagnoli) checksum of the given data.
+fn crc32c(data: &[u8]) -> u32 {
+	let mut crc = 0xffff_ffffu32;
+	for byte in data {
+		crc ^= *byte as u32;
+		for _ in 0..8 {
+			crc = if crc & 1 != 0 { (crc >> 1) ^ 0x82f6_3b78 } else { crc >> 1 };
+		}
+	}
+	!crc
+}
+
+/// Computes the checksum of a [`ChannelUpdate`] used in [`ReplyChannelRange::checksums`], which
+/// covers all of its fields but the signature and timestamp.
+fn channel_update_checksum(msg: &msgs::UnsignedChannelUpdate) -> u32 {
+	let mut encoded = msg.encode();
+	// The timestamp directly follows the 32-byte chain hash and 8-byte short_channel_id.
+	encoded.drain(32 + 8..32 + 8 + 4);
+	crc32c(&encoded)
+}
+
 /// Represents the compressed public key of a node
 #[derive(Clone, Copy)]
 pub struct NodeId([u8; PUBLIC_KEY_SIZE]);
@@ -254,13 +288,65 @@ pub struct P2PGossipSync<G: Deref<Target=NetworkGraph<L>>, U: Deref, L: Deref>
 where U::Target: UtxoLookup, L::Target: Logger
 {
 	network_graph: G,
-	utxo_lookup: Option<U>,
+	utxo_lookup: RwLock<Option<U>>,
 	#[cfg(feature = "std")]
 	full_syncs_requested: AtomicUsize,
 	pending_events: Mutex<Vec<MessageSendEvent>>,
+	pending_query_replies: Mutex<HashMap<PublicKey, PendingQueryReply>>,
+	active_syncs: Mutex<HashMap<PublicKey, ActiveGossipSync>>,
 	logger: L,
 }
 
+/// The state of our reply to a peer's [`QueryShortChannelIds`], which is streamed out as the
+/// peer's outbound buffer drains rather than being enqueued all at once.
+struct PendingQueryReply {
+	chain_hash: BlockHash,
+	full_information: bool,
+	/// The `short_channel_id`s still to be replied to, along with the query flags for each.
+	short_channel_ids: VecDeque<(u64, u64)>,
+	/// Messages for the last `short_channel_id` which have not yet been sent.
+	pending_messages: VecDeque<GossipQueryReply>,
+	/// The nodes we've already sent a [`NodeAnnouncement`] for as part of this reply.
+	sent_node_announcements: HashSet<NodeId>,
+}
+
+/// The state of a sync of the routing graph we're performing with a peer, in which we learn the
+/// channels it knows of via [`QueryChannelRange`] and then query the gossip of the ones we're
+/// missing or have outdated information for via [`QueryShortChannelIds`].
+struct ActiveGossipSync {
+	/// Whether the peer supports `gossip_queries_ex`, allowing us to request update timestamps and
+	/// checksums as well as only the specific messages we need.
+	gossip_queries_ex: bool,
+	/// Whether the peer has sent its final [`ReplyChannelRange`].
+	channel_range_complete: bool,
+	/// The `short_channel_id`s we still have to query, along with the query flags for each.
+	pending_short_channel_ids: VecDeque<(u64, u64)>,
+	/// The number of `short_channel_id`s in our [`QueryShortChannelIds`] awaiting a reply, if any.
+	short_channel_ids_in_flight: usize,
+	short_channel_ids_received: usize,
+	short_channel_ids_synced: usize,
+}
+
+/// The progress of a sync of the routing graph with a peer, as returned by
+/// [`P2PGossipSync::sync_progress`].
+///
+/// Only the channels which we were missing or had outdated information for are queried, so
+/// `channels_synced` and `channels_pending` will generally not add up to
+/// `channels_known_by_peer`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct GossipSyncProgress {
+	/// The node id of the peer we're syncing from.
+	pub counterparty_node_id: PublicKey,
+	/// Whether the peer has finished telling us about all the channels it knows of.
+	pub channel_range_complete: bool,
+	/// The number of channels the peer has told us about so far.
+	pub channels_known_by_peer: usize,
+	/// The number of channels whose gossip we've requested and received from the peer.
+	pub channels_synced: usize,
+	/// The number of channels whose gossip we've yet to request or receive from the peer.
+	pub channels_pending: usize,
+}
+
 impl<G: Deref<Target=NetworkGraph<L>>, U: Deref, L: Deref> P2PGossipSync<G, U, L>
 where U::Target: UtxoLookup, L::Target: Logger
 {
@@ -273,8 +359,10 @@ where U::Target: UtxoLookup, L::Target: Logger
 			network_graph,
 			#[cfg(feature = "std")]
 			full_syncs_requested: AtomicUsize::new(0),
-			utxo_lookup,
+			utxo_lookup: RwLock::new(utxo_lookup),
 			pending_events: Mutex::new(vec![]),
+			pending_query_replies: Mutex::new(HashMap::new()),
+			active_syncs: Mutex::new(HashMap::new()),
 			logger,
 		}
 	}
@@ -282,8 +370,8 @@ where U::Target: UtxoLookup, L::Target: Logger
 	/// Adds a provider used to check new announcements. Does not affect
 	/// existing announcements unless they are updated.
 	/// Add, update or remove the provider would replace the current one.
-	pub fn add_utxo_lookup(&mut self, utxo_lookup: Option<U>) {
-		self.utxo_lookup = utxo_lookup;
+	pub fn add_utxo_lookup(&self, utxo_lookup: Option<U>) {
+		*self.utxo_lookup.write().unwrap() = utxo_lookup;
 	}
 
 	/// Gets a reference to the underlying [`NetworkGraph`] which was provided in
@@ -307,6 +395,146 @@ where U::Target: UtxoLookup, L::Target: Logger
 		}
 	}
 
+	/// Returns the progress of the syncs of the routing graph currently in progress with our peers.
+	///
+	/// A sync is started with the first few peers we connect to which support `gossip_queries`,
+	/// and is no longer returned once it completes or the peer disconnects.
+	pub fn sync_progress(&self) -> Vec<GossipSyncProgress> {
+		self.active_syncs.lock().unwrap().iter().map(|(node_id, sync)| GossipSyncProgress {
+			counterparty_node_id: *node_id,
+			channel_range_complete: sync.channel_range_complete,
+			channels_known_by_peer: sync.short_channel_ids_received,
+			channels_synced: sync.short_channel_ids_synced,
+			channels_pending: sync.pending_short_channel_ids.len() + sync.short_channel_ids_in_flight,
+		}).collect()
+	}
+
+	/// Starts a sync of the routing graph with the given peer by asking it for all the channels it
+	/// knows of.
+	#[cfg(feature = "std")]
+	fn start_sync(&self, their_node_id: &PublicKey, init_msg: &Init) {
+		let gossip_queries_ex = init_msg.features.supports_gossip_queries_ex();
+		self.active_syncs.lock().unwrap().insert(*their_node_id, ActiveGossipSync {
+			gossip_queries_ex,
+			channel_range_complete: false,
+			pending_short_channel_ids: VecDeque::new(),
+			short_channel_ids_in_flight: 0,
+			short_channel_ids_received: 0,
+			short_channel_ids_synced: 0,
+		});
+
+		let query_option = if gossip_queries_ex {
+			Some(msgs::QUERY_OPTION_TIMESTAMPS | msgs::QUERY_OPTION_CHECKSUMS)
+		} else {
+			None
+		};
+		let mut pending_events = self.pending_events.lock().unwrap();
+		pending_events.push(MessageSendEvent::SendChannelRangeQuery {
+			node_id: their_node_id.clone(),
+			msg: QueryChannelRange {
+				chain_hash: self.network_graph.genesis_hash,
+				first_blocknum: 0,
+				number_of_blocks: u32::max_value(),
+				query_option,
+			},
+		});
+	}
+
+	/// Determines the messages we need to request for each channel in the given
+	/// [`ReplyChannelRange`], returning the `short_channel_id`s to query along with their query
+	/// flags.
+	///
+	/// Channels we don't know of are always queried. For channels we do know of, we only query
+	/// updates which are newer than ours (and actually differ in contents, if the peer provided
+	/// checksums) or, absent timestamps, updates and node announcements we're missing entirely.
+	fn short_channel_ids_to_query(&self, msg: &ReplyChannelRange) -> Vec<(u64, u64)> {
+		let channels = self.network_graph.channels.read().unwrap();
+		let nodes = self.network_graph.nodes.read().unwrap();
+		let removed_channels = self.network_graph.removed_channels.lock().unwrap();
+
+		let mut short_channel_ids = Vec::new();
+		for (idx, scid) in msg.short_channel_ids.iter().enumerate() {
+			let chan = match channels.get(scid) {
+				Some(chan) => chan,
+				None => {
+					// Don't bother re-fetching channels we've pruned, they'll likely be pruned again.
+					if !removed_channels.contains_key(scid) {
+						short_channel_ids.push((*scid, ALL_QUERY_FLAGS));
+					}
+					continue;
+				},
+			};
+
+			let mut flags = 0;
+			let timestamps = msg.timestamps.as_ref().map(|timestamps| timestamps[idx]);
+			let checksums = msg.checksums.as_ref().map(|checksums| checksums[idx]);
+			let directions = [
+				(msgs::QUERY_FLAG_CHANNEL_UPDATE_NODE_1, &chan.one_to_two,
+					timestamps.map(|(timestamp, _)| timestamp), checksums.map(|(checksum, _)| checksum)),
+				(msgs::QUERY_FLAG_CHANNEL_UPDATE_NODE_2, &chan.two_to_one,
+					timestamps.map(|(_, timestamp)| timestamp), checksums.map(|(_, checksum)| checksum)),
+			];
+			for (flag, update_info, their_timestamp, their_checksum) in directions.iter() {
+				let update_needed = match (update_info, their_timestamp) {
+					(None, None) => true,
+					(None, Some(their_timestamp)) => *their_timestamp != 0,
+					(Some(_), None) => false,
+					(Some(update_info), Some(their_timestamp)) => {
+						let our_checksum = update_info.last_update_message.as_ref()
+							.map(|msg| channel_update_checksum(&msg.contents));
+						*their_timestamp > update_info.last_update &&
+							(their_checksum.is_none() || our_checksum != *their_checksum)
+					},
+				};
+				if update_needed {
+					flags |= flag;
+				}
+			}
+
+			let node_ids = [
+				(msgs::QUERY_FLAG_NODE_ANNOUNCEMENT_NODE_1, &chan.node_one),
+				(msgs::QUERY_FLAG_NODE_ANNOUNCEMENT_NODE_2, &chan.node_two),
+			];
+			for (flag, node_id) in node_ids.iter() {
+				if nodes.get(node_id).map_or(true, |node| node.announcement_info.is_none()) {
+					flags |= flag;
+				}
+			}
+
+			if flags != 0 {
+				short_channel_ids.push((*scid, flags));
+			}
+		}
+		short_channel_ids
+	}
+
+	/// Sends the next [`QueryShortChannelIds`] of a sync if we aren't awaiting a reply to the last
+	/// one, returning whether the sync is complete.
+	fn continue_sync(&self, their_node_id: &PublicKey, sync: &mut ActiveGossipSync) -> bool {
+		if sync.short_channel_ids_in_flight != 0 {
+			return false;
+		}
+		if sync.pending_short_channel_ids.is_empty() {
+			return sync.channel_range_complete;
+		}
+
+		let batch_size = cmp::min(sync.pending_short_channel_ids.len(), MAX_SCIDS_PER_QUERY);
+		let (short_channel_ids, query_flags): (Vec<u64>, Vec<u64>) =
+			sync.pending_short_channel_ids.drain(..batch_size).unzip();
+		sync.short_channel_ids_in_flight = batch_size;
+
+		let mut pending_events = self.pending_events.lock().unwrap();
+		pending_events.push(MessageSendEvent::SendShortIdsQuery {
+			node_id: their_node_id.clone(),
+			msg: QueryShortChannelIds {
+				chain_hash: self.network_graph.genesis_hash,
+				short_channel_ids,
+				query_flags: if sync.gossip_queries_ex { Some(query_flags) } else { None },
+			},
+		});
+		false
+	}
+
 	/// Used to broadcast forward gossip messages which were validated async.
 	///
 	/// Note that this will ignore events other than `Broadcast*` or messages with too much excess
@@ -443,7 +671,7 @@ where U::Target: UtxoLookup, L::Target: Logger
 	}
 
 	fn handle_channel_announcement(&self, msg: &msgs::ChannelAnnouncement) -> Result<bool, LightningError> {
-		self.network_graph.update_channel_from_announcement(msg, &self.utxo_lookup)?;
+		self.network_graph.update_channel_from_announcement(msg, &*self.utxo_lookup.read().unwrap())?;
 		Ok(msg.contents.excess_data.len() <= MAX_EXCESS_BYTES_FOR_RELAY)
 	}
 
@@ -491,15 +719,71 @@ where U::Target: UtxoLookup, L::Target: Logger
 		None
 	}
 
-	/// Initiates a stateless sync of routing gossip information with a peer
-	/// using [`gossip_queries`]. The default strategy used by this implementation
-	/// is to sync the full block range with several peers.
+	fn get_next_query_reply(&self, their_node_id: &PublicKey) -> Option<GossipQueryReply> {
+		let mut pending_query_replies = self.pending_query_replies.lock().unwrap();
+		let reply = pending_query_replies.get_mut(their_node_id)?;
+		loop {
+			if let Some(msg) = reply.pending_messages.pop_front() {
+				return Some(msg);
+			}
+
+			let (scid, flags) = match reply.short_channel_ids.pop_front() {
+				Some(scid_and_flags) => scid_and_flags,
+				None => break,
+			};
+			let channels = self.network_graph.channels.read().unwrap();
+			let chan = match channels.get(&scid) {
+				Some(chan) if chan.announcement_message.is_some() => chan,
+				_ => continue,
+			};
+			if flags & msgs::QUERY_FLAG_CHANNEL_ANNOUNCEMENT != 0 {
+				let msg = chan.announcement_message.clone().unwrap();
+				reply.pending_messages.push_back(GossipQueryReply::ChannelAnnouncement(msg));
+			}
+			let updates = [
+				(msgs::QUERY_FLAG_CHANNEL_UPDATE_NODE_1, &chan.one_to_two),
+				(msgs::QUERY_FLAG_CHANNEL_UPDATE_NODE_2, &chan.two_to_one),
+			];
+			for (flag, direction) in updates.iter() {
+				if flags & flag == 0 { continue; }
+				if let Some(msg) = direction.as_ref().and_then(|info| info.last_update_message.clone()) {
+					reply.pending_messages.push_back(GossipQueryReply::ChannelUpdate(msg));
+				}
+			}
+			let nodes = self.network_graph.nodes.read().unwrap();
+			let node_ids = [
+				(msgs::QUERY_FLAG_NODE_ANNOUNCEMENT_NODE_1, chan.node_one),
+				(msgs::QUERY_FLAG_NODE_ANNOUNCEMENT_NODE_2, chan.node_two),
+			];
+			for (flag, node_id) in node_ids.iter() {
+				if flags & flag == 0 || reply.sent_node_announcements.contains(node_id) { continue; }
+				let msg = nodes.get(node_id)
+					.and_then(|node| node.announcement_info.as_ref())
+					.and_then(|info| info.announcement_message.clone());
+				if let Some(msg) = msg {
+					reply.sent_node_announcements.insert(*node_id);
+					reply.pending_messages.push_back(GossipQueryReply::NodeAnnouncement(msg));
+				}
+			}
+		}
+
+		let reply = pending_query_replies.remove(their_node_id).unwrap();
+		Some(GossipQueryReply::ReplyShortChannelIdsEnd(ReplyShortChannelIdsEnd {
+			chain_hash: reply.chain_hash,
+			full_information: reply.full_information,
+		}))
+	}
+
+	/// Initiates a sync of routing gossip information with a peer using
+	/// [`gossip_queries`]. The default strategy used by this implementation is to
+	/// sync the full block range with several peers.
 	///
 	/// We should expect one or more [`reply_channel_range`] messages in response
-	/// to our [`query_channel_range`]. Each reply will enqueue a [`query_scid`] message
-	/// to request gossip messages for each channel. The sync is considered complete
-	/// when the final [`reply_scids_end`] message is received, though we are not
-	/// tracking this directly.
+	/// to our [`query_channel_range`]. The channels in each reply are diffed against
+	/// the [`NetworkGraph`], and the gossip of those we're missing or have outdated
+	/// information for is requested in batches via [`query_scid`] messages. The sync
+	/// is considered complete when the [`reply_scids_end`] to the last batch is
+	/// received, and its progress can be tracked via [`P2PGossipSync::sync_progress`].
 	///
 	/// [`gossip_queries`]: https://github.com/lightning/bolts/blob/master/07-routing-gossip.md#query-messages
 	/// [`reply_channel_range`]: msgs::ReplyChannelRange
@@ -507,6 +791,10 @@ where U::Target: UtxoLookup, L::Target: Logger
 	/// [`query_scid`]: msgs::QueryShortChannelIds
 	/// [`reply_scids_end`]: msgs::ReplyShortChannelIdsEnd
 	fn peer_connected(&self, their_node_id: &PublicKey, init_msg: &Init, _inbound: bool) -> Result<(), ()> {
+		// Drop any reply to a query or sync started over a previous connection with this peer.
+		self.pending_query_replies.lock().unwrap().remove(their_node_id);
+		self.active_syncs.lock().unwrap().remove(their_node_id);
+
 		// We will only perform a sync with peers that support gossip_queries.
 		if !init_msg.features.supports_gossip_queries() {
 			// Don't disconnect peers for not supporting gossip queries. We may wish to have
@@ -560,8 +848,14 @@ where U::Target: UtxoLookup, L::Target: Logger
 		// propagate fully if there are cuts in the gossiping subgraph.
 		//
 		// In an attempt to cut a middle ground between always fetching the full graph from all of
-		// our peers and never receiving gossip from peers at all, we send all of our peers a
-		// `gossip_timestamp_filter`, with the filter time set either two weeks ago or an hour ago.
+		// our peers and never receiving gossip from peers at all, we actively sync the graph from
+		// the first few peers we connect to, and send all of our peers a `gossip_timestamp_filter`
+		// with the filter time set an hour ago so that we receive gossip as they see it.
+		//
+		// The active sync asks the peer for the set of channels it knows of via
+		// `query_channel_range` and then requests the gossip of only those channels we're missing
+		// or, if the peer supports `gossip_queries_ex`, whose updates have since changed, allowing
+		// us to quickly both build the graph initially and catch up on restart.
 		//
 		// For no-std builds, we bury our head in the sand and do a full sync on each connection.
 		#[allow(unused_mut, unused_assignments)]
@@ -569,15 +863,10 @@ where U::Target: UtxoLookup, L::Target: Logger
 		#[cfg(feature = "std")]
 		{
 			gossip_start_time = SystemTime::now().duration_since(UNIX_EPOCH).expect("Time must be > 1970").as_secs();
-			if self.should_request_full_sync(&their_node_id) {
-				gossip_start_time -= 60 * 60 * 24 * 7 * 2; // 2 weeks ago
-			} else {
-				gossip_start_time -= 60 * 60; // an hour ago
-			}
+			gossip_start_time -= 60 * 60; // an hour ago
 		}
 
-		let mut pending_events = self.pending_events.lock().unwrap();
-		pending_events.push(MessageSendEvent::SendGossipTimestampFilter {
+		self.pending_events.lock().unwrap().push(MessageSendEvent::SendGossipTimestampFilter {
 			node_id: their_node_id.clone(),
 			msg: GossipTimestampFilter {
 				chain_hash: self.network_graph.genesis_hash,
@@ -585,20 +874,86 @@ where U::Target: UtxoLookup, L::Target: Logger
 				timestamp_range: u32::max_value(),
 			},
 		});
+
+		#[cfg(feature = "std")]
+		{
+			if self.should_request_full_sync(their_node_id) {
+				self.start_sync(their_node_id, init_msg);
+			}
+		}
 		Ok(())
 	}
 
-	fn handle_reply_channel_range(&self, _their_node_id: &PublicKey, _msg: ReplyChannelRange) -> Result<(), LightningError> {
-		// We don't make queries, so should never receive replies. If, in the future, the set
-		// reconciliation extensions to gossip queries become broadly supported, we should revert
-		// this code to its state pre-0.0.106.
+	fn peer_disconnected(&self, their_node_id: &PublicKey) {
+		// Neither a partially-sent query reply nor our sync with the peer can be resumed over a
+		// new connection, so don't hold onto them until the peer reconnects (if ever).
+		self.pending_query_replies.lock().unwrap().remove(their_node_id);
+		self.active_syncs.lock().unwrap().remove(their_node_id);
+	}
+
+	/// Processes a reply to the [`QueryChannelRange`] we sent when starting a sync with a peer,
+	/// queueing the channels we need the gossip of to be queried via [`QueryShortChannelIds`].
+	fn handle_reply_channel_range(&self, their_node_id: &PublicKey, msg: ReplyChannelRange) -> Result<(), LightningError> {
+		log_debug!(self.logger, "Handling reply_channel_range peer={}, first_blocknum={}, number_of_blocks={}, sync_complete={}, short_channel_ids={}", log_pubkey!(their_node_id), msg.first_blocknum, msg.number_of_blocks, msg.sync_complete, msg.short_channel_ids.len());
+
+		let mut active_syncs = self.active_syncs.lock().unwrap();
+		let sync = match active_syncs.get_mut(their_node_id) {
+			Some(sync) if !sync.channel_range_complete => sync,
+			_ => return Err(LightningError {
+				err: String::from("Received unsolicited reply_channel_range"),
+				action: ErrorAction::IgnoreError,
+			}),
+		};
+
+		if msg.chain_hash != self.network_graph.genesis_hash {
+			active_syncs.remove(their_node_id);
+			return Err(LightningError {
+				err: String::from("Received reply_channel_range for an unknown chain, aborting sync"),
+				action: ErrorAction::IgnoreError,
+			});
+		}
+
+		sync.short_channel_ids_received += msg.short_channel_ids.len();
+		sync.pending_short_channel_ids.extend(self.short_channel_ids_to_query(&msg));
+		sync.channel_range_complete = msg.sync_complete;
+
+		if self.continue_sync(their_node_id, sync) {
+			log_info!(self.logger, "Completed gossip sync with peer {}", log_pubkey!(their_node_id));
+			active_syncs.remove(their_node_id);
+		}
 		Ok(())
 	}
 
-	fn handle_reply_short_channel_ids_end(&self, _their_node_id: &PublicKey, _msg: ReplyShortChannelIdsEnd) -> Result<(), LightningError> {
-		// We don't make queries, so should never receive replies. If, in the future, the set
-		// reconciliation extensions to gossip queries become broadly supported, we should revert
-		// this code to its state pre-0.0.106.
+	/// Processes the end of a reply to a [`QueryShortChannelIds`] we sent as part of a sync with a
+	/// peer, sending the next query if there are more channels left to sync.
+	fn handle_reply_short_channel_ids_end(&self, their_node_id: &PublicKey, msg: ReplyShortChannelIdsEnd) -> Result<(), LightningError> {
+		log_debug!(self.logger, "Handling reply_short_channel_ids_end peer={}, full_information={}", log_pubkey!(their_node_id), msg.full_information);
+
+		let mut active_syncs = self.active_syncs.lock().unwrap();
+		let sync = match active_syncs.get_mut(their_node_id) {
+			Some(sync) if sync.short_channel_ids_in_flight != 0 => sync,
+			_ => return Err(LightningError {
+				err: String::from("Received unsolicited reply_short_channel_ids_end"),
+				action: ErrorAction::IgnoreError,
+			}),
+		};
+
+		if msg.chain_hash != self.network_graph.genesis_hash || !msg.full_information {
+			active_syncs.remove(their_node_id);
+			return Err(LightningError {
+				err: String::from("Received reply_short_channel_ids_end without full information, aborting sync"),
+				action: ErrorAction::IgnoreError,
+			});
+		}
+
+		sync.short_channel_ids_synced += sync.short_channel_ids_in_flight;
+		sync.short_channel_ids_in_flight = 0;
+		log_debug!(self.logger, "Synced gossip for {} channels from peer {}, {} remaining", sync.short_channel_ids_synced, log_pubkey!(their_node_id), sync.pending_short_channel_ids.len());
+
+		if self.continue_sync(their_node_id, sync) {
+			log_info!(self.logger, "Completed gossip sync with peer {}", log_pubkey!(their_node_id));
+			active_syncs.remove(their_node_id);
+		}
 		Ok(())
 	}
 
@@ -629,6 +984,8 @@ where U::Target: UtxoLookup, L::Target: Logger
 					number_of_blocks: msg.number_of_blocks,
 					sync_complete: true,
 					short_channel_ids: vec![],
+					timestamps: None,
+					checksums: None,
 				}
 			});
 			return Err(LightningError {
@@ -637,20 +994,41 @@ where U::Target: UtxoLookup, L::Target: Logger
 			});
 		}
 
+		let include_timestamps = msg.query_option.map_or(false, |option| option & msgs::QUERY_OPTION_TIMESTAMPS != 0);
+		let include_checksums = msg.query_option.map_or(false, |option| option & msgs::QUERY_OPTION_CHECKSUMS != 0);
+		let max_scids_per_reply = if include_timestamps || include_checksums {
+			MAX_SCIDS_PER_EXTENDED_REPLY
+		} else {
+			MAX_SCIDS_PER_REPLY
+		};
+		let update_timestamp = |info: &Option<ChannelUpdateInfo>| {
+			info.as_ref().map_or(0, |info| info.last_update)
+		};
+		let update_checksum = |info: &Option<ChannelUpdateInfo>| {
+			info.as_ref().and_then(|info| info.last_update_message.as_ref())
+				.map_or(0, |msg| channel_update_checksum(&msg.contents))
+		};
+
 		// Creates channel batches. We are not checking if the channel is routable
 		// (has at least one update). A peer may still want to know the channel
 		// exists even if its not yet routable.
-		let mut batches: Vec<Vec<u64>> = vec![Vec::with_capacity(MAX_SCIDS_PER_REPLY)];
+		let mut batches: Vec<Vec<(u64, (u32, u32), (u32, u32))>> = vec![Vec::with_capacity(max_scids_per_reply)];
 		let mut channels = self.network_graph.channels.write().unwrap();
 		for (_, ref chan) in channels.range(inclusive_start_scid.unwrap()..exclusive_end_scid.unwrap()) {
 			if let Some(chan_announcement) = &chan.announcement_message {
 				// Construct a new batch if last one is full
-				if batches.last().unwrap().len() == batches.last().unwrap().capacity() {
-					batches.push(Vec::with_capacity(MAX_SCIDS_PER_REPLY));
+				if batches.last().unwrap().len() == max_scids_per_reply {
+					batches.push(Vec::with_capacity(max_scids_per_reply));
 				}
 
 				let batch = batches.last_mut().unwrap();
-				batch.push(chan_announcement.contents.short_channel_id);
+				let timestamps = if include_timestamps {
+					(update_timestamp(&chan.one_to_two), update_timestamp(&chan.two_to_one))
+				} else { (0, 0) };
+				let checksums = if include_checksums {
+					(update_checksum(&chan.one_to_two), update_checksum(&chan.two_to_one))
+				} else { (0, 0) };
+				batch.push((chan_announcement.contents.short_channel_id, timestamps, checksums));
 			}
 		}
 		drop(channels);
@@ -686,11 +1064,17 @@ where U::Target: UtxoLookup, L::Target: Logger
 			// Prior replies should use the number of blocks that fit into the reply. Overflow
 			// safe since first_blocknum is always <= last SCID's block.
 			else {
-				(false, block_from_scid(batch.last().unwrap()) - first_blocknum)
+				(false, block_from_scid(&batch.last().unwrap().0) - first_blocknum)
 			};
 
 			prev_batch_endblock = first_blocknum + number_of_blocks;
 
+			let timestamps = if include_timestamps {
+				Some(batch.iter().map(|(_, timestamps, _)| *timestamps).collect())
+			} else { None };
+			let checksums = if include_checksums {
+				Some(batch.iter().map(|(_, _, checksums)| *checksums).collect())
+			} else { None };
 			pending_events.push(MessageSendEvent::SendReplyChannelRange {
 				node_id: their_node_id.clone(),
 				msg: ReplyChannelRange {
@@ -698,7 +1082,9 @@ where U::Target: UtxoLookup, L::Target: Logger
 					first_blocknum,
 					number_of_blocks,
 					sync_complete,
-					short_channel_ids: batch,
+					short_channel_ids: batch.into_iter().map(|(scid, _, _)| scid).collect(),
+					timestamps,
+					checksums,
 				}
 			});
 		}
@@ -706,23 +1092,67 @@ where U::Target: UtxoLookup, L::Target: Logger
 		Ok(())
 	}
 
-	fn handle_query_short_channel_ids(&self, _their_node_id: &PublicKey, _msg: QueryShortChannelIds) -> Result<(), LightningError> {
-		// TODO
-		Err(LightningError {
-			err: String::from("Not implemented"),
-			action: ErrorAction::IgnoreError,
-		})
+	/// Processes a query from a peer for the gossip messages of specific channels. Rather than
+	/// enqueueing all reply messages into pending events, the reply is streamed to the peer via
+	/// [`Self::get_next_query_reply`] as its outbound buffer drains, followed by a final
+	/// [`ReplyShortChannelIdsEnd`].
+	fn handle_query_short_channel_ids(&self, their_node_id: &PublicKey, msg: QueryShortChannelIds) -> Result<(), LightningError> {
+		log_debug!(self.logger, "Handling query_short_channel_ids peer={}, short_channel_ids={}", log_pubkey!(their_node_id), msg.short_channel_ids.len());
+
+		if let Some(query_flags) = msg.query_flags.as_ref() {
+			if query_flags.len() != msg.short_channel_ids.len() {
+				return Err(LightningError {
+					err: String::from("query_short_channel_ids had a mismatched number of query_flags"),
+					action: ErrorAction::IgnoreError,
+				});
+			}
+		}
+
+		let mut pending_query_replies = self.pending_query_replies.lock().unwrap();
+		if pending_query_replies.contains_key(their_node_id) {
+			// Per spec, peers must not send a new query before the previous one has been replied
+			// to.
+			return Err(LightningError {
+				err: String::from("Received query_short_channel_ids before replying to the previous one"),
+				action: ErrorAction::IgnoreError,
+			});
+		}
+
+		// If we don't track the requested chain, we reply with no information and mark it as such.
+		let full_information = msg.chain_hash == self.network_graph.genesis_hash;
+		let short_channel_ids = if full_information {
+			let all_flags = msgs::QUERY_FLAG_CHANNEL_ANNOUNCEMENT
+				| msgs::QUERY_FLAG_CHANNEL_UPDATE_NODE_1 | msgs::QUERY_FLAG_CHANNEL_UPDATE_NODE_2
+				| msgs::QUERY_FLAG_NODE_ANNOUNCEMENT_NODE_1 | msgs::QUERY_FLAG_NODE_ANNOUNCEMENT_NODE_2;
+			match msg.query_flags {
+				Some(query_flags) => msg.short_channel_ids.into_iter().zip(query_flags.into_iter()).collect(),
+				None => msg.short_channel_ids.into_iter().map(|scid| (scid, all_flags)).collect(),
+			}
+		} else {
+			VecDeque::new()
+		};
+
+		pending_query_replies.insert(*their_node_id, PendingQueryReply {
+			chain_hash: msg.chain_hash,
+			full_information,
+			short_channel_ids,
+			pending_messages: VecDeque::new(),
+			sent_node_announcements: HashSet::new(),
+		});
+		Ok(())
 	}
 
 	fn provided_node_features(&self) -> NodeFeatures {
 		let mut features = NodeFeatures::empty();
 		features.set_gossip_queries_optional();
+		features.set_gossip_queries_ex_optional();
 		features
 	}
 
 	fn provided_init_features(&self, _their_node_id: &PublicKey) -> InitFeatures {
 		let mut features = InitFeatures::empty();
 		features.set_gossip_queries_optional();
+		features.set_gossip_queries_ex_optional();
 		features
 	}
 
@@ -2042,16 +2472,18 @@ pub(crate) mod tests {
 	use crate::ln::features::InitFeatures;
 	use crate::routing::gossip::{P2PGossipSync, NetworkGraph, NetworkUpdate, NodeAlias, MAX_EXCESS_BYTES_FOR_RELAY, NodeId, RoutingFees, ChannelUpdateInfo, ChannelInfo, NodeAnnouncementInfo, NodeInfo};
 	use crate::routing::utxo::{UtxoLookupError, UtxoResult};
+	use crate::ln::msgs;
 	use crate::ln::msgs::{RoutingMessageHandler, UnsignedNodeAnnouncement, NodeAnnouncement,
 		UnsignedChannelAnnouncement, ChannelAnnouncement, UnsignedChannelUpdate, ChannelUpdate,
-		ReplyChannelRange, QueryChannelRange, QueryShortChannelIds, MAX_VALUE_MSAT};
+		ReplyChannelRange, QueryChannelRange, QueryShortChannelIds, ReplyShortChannelIdsEnd,
+		GossipQueryReply, MAX_VALUE_MSAT};
 	use crate::util::config::UserConfig;
 	use crate::util::test_utils;
 	use crate::util::ser::{ReadableArgs, Readable, Writeable};
 	use crate::util::scid_utils::scid_from_parts;
 
 	use crate::routing::gossip::REMOVED_ENTRIES_TRACKING_AGE_LIMIT_SECS;
-	use super::STALE_CHANNEL_UPDATE_AGE_LIMIT_SECS;
+	use super::{STALE_CHANNEL_UPDATE_AGE_LIMIT_SECS, ALL_QUERY_FLAGS, GossipSyncProgress, channel_update_checksum, crc32c};
 
 	use bitcoin::hashes::sha256d::Hash as Sha256dHash;
 	use bitcoin::hashes::Hash;
@@ -2923,28 +3355,230 @@ pub(crate) mod tests {
 			assert_eq!(events.len(), 0);
 		}
 
-		// It should send a gossip_timestamp_filter with the correct information
+		// It should send a gossip_timestamp_filter with the correct information and start a sync
+		// of the full block range
 		{
 			let mut features = InitFeatures::empty();
 			features.set_gossip_queries_optional();
 			let init_msg = Init { features, networks: None, remote_network_address: None };
 			gossip_sync.peer_connected(&node_id_1, &init_msg, true).unwrap();
 			let events = gossip_sync.get_and_clear_pending_msg_events();
-			assert_eq!(events.len(), 1);
+			assert_eq!(events.len(), 2);
 			match &events[0] {
 				MessageSendEvent::SendGossipTimestampFilter{ node_id, msg } => {
 					assert_eq!(node_id, &node_id_1);
 					assert_eq!(msg.chain_hash, chain_hash);
 					let expected_timestamp = SystemTime::now().duration_since(UNIX_EPOCH).expect("Time must be > 1970").as_secs();
-					assert!((msg.first_timestamp as u64) >= expected_timestamp - 60*60*24*7*2);
-					assert!((msg.first_timestamp as u64) < expected_timestamp - 60*60*24*7*2 + 10);
+					assert!((msg.first_timestamp as u64) >= expected_timestamp - 60*60);
+					assert!((msg.first_timestamp as u64) < expected_timestamp - 60*60 + 10);
 					assert_eq!(msg.timestamp_range, u32::max_value());
 				},
+				_ => panic!("Expected MessageSendEvent::SendGossipTimestampFilter")
+			};
+			match &events[1] {
+				MessageSendEvent::SendChannelRangeQuery{ node_id, msg } => {
+					assert_eq!(node_id, &node_id_1);
+					assert_eq!(msg.chain_hash, chain_hash);
+					assert_eq!(msg.first_blocknum, 0);
+					assert_eq!(msg.number_of_blocks, u32::max_value());
+					// The peer doesn't support gossip_queries_ex
+					assert_eq!(msg.query_option, None);
+				},
 				_ => panic!("Expected MessageSendEvent::SendChannelRangeQuery")
 			};
 		}
 	}
 
+	#[test]
+	#[cfg(feature = "std")]
+	fn syncing_routing_table_via_gossip_queries() {
+		use crate::ln::msgs::Init;
+
+		let network_graph = create_network_graph();
+		let (secp_ctx, gossip_sync) = create_gossip_sync(&network_graph);
+		let node_1_privkey = &SecretKey::from_slice(&[42; 32]).unwrap();
+		let node_2_privkey = &SecretKey::from_slice(&[41; 32]).unwrap();
+		let peer_privkey = &SecretKey::from_slice(&[43; 32]).unwrap();
+		let peer_node_id = PublicKey::from_secret_key(&secp_ctx, peer_privkey);
+		let chain_hash = genesis_block(Network::Testnet).header.block_hash();
+
+		// We know of channel 1 with an update in one direction and both node announcements, and
+		// channel 2 with updates in both directions.
+		let mut updates = Vec::new();
+		for scid in 1..=2 {
+			let announcement = get_signed_channel_announcement(|unsigned_announcement| {
+				unsigned_announcement.short_channel_id = scid;
+			}, node_1_privkey, node_2_privkey, &secp_ctx);
+			gossip_sync.handle_channel_announcement(&announcement).unwrap();
+		}
+		for (scid, flags, privkey) in [(1, 0, node_1_privkey), (2, 0, node_1_privkey), (2, 1, node_2_privkey)].iter() {
+			let update = get_signed_channel_update(|unsigned_channel_update| {
+				unsigned_channel_update.short_channel_id = *scid;
+				unsigned_channel_update.flags = *flags;
+			}, privkey, &secp_ctx);
+			gossip_sync.handle_channel_update(&update).unwrap();
+			updates.push(update);
+		}
+		gossip_sync.handle_node_announcement(&get_signed_node_announcement(|_| {}, node_1_privkey, &secp_ctx)).unwrap();
+		gossip_sync.handle_node_announcement(&get_signed_node_announcement(|_| {}, node_2_privkey, &secp_ctx)).unwrap();
+
+		// Replies are rejected if we haven't started a sync.
+		let mut reply = ReplyChannelRange {
+			chain_hash,
+			first_blocknum: 0,
+			number_of_blocks: u32::max_value(),
+			sync_complete: true,
+			short_channel_ids: vec![1, 2, 3],
+			timestamps: None,
+			checksums: None,
+		};
+		assert!(gossip_sync.handle_reply_channel_range(&peer_node_id, reply.clone()).is_err());
+		assert!(gossip_sync.sync_progress().is_empty());
+
+		let mut features = InitFeatures::empty();
+		features.set_gossip_queries_optional();
+		features.set_gossip_queries_ex_optional();
+		let init_msg = Init { features, networks: None, remote_network_address: None };
+		gossip_sync.peer_connected(&peer_node_id, &init_msg, true).unwrap();
+		let events = gossip_sync.get_and_clear_pending_msg_events();
+		assert_eq!(events.len(), 2);
+		match &events[1] {
+			MessageSendEvent::SendChannelRangeQuery { msg, .. } => {
+				assert_eq!(msg.query_option, Some(msgs::QUERY_OPTION_TIMESTAMPS | msgs::QUERY_OPTION_CHECKSUMS));
+			},
+			_ => panic!("Expected MessageSendEvent::SendChannelRangeQuery"),
+		}
+
+		// The peer has an update we lack for channel 1, a refreshed but otherwise identical update
+		// for the first direction of channel 2, and a changed one for its second direction. It also
+		// knows of channel 3, which we don't know of at all.
+		reply.timestamps = Some(vec![(100, 101), (101, 101), (0, 0)]);
+		reply.checksums = Some(vec![
+			(channel_update_checksum(&updates[0].contents), 42),
+			(channel_update_checksum(&updates[1].contents), 42),
+			(0, 0),
+		]);
+		gossip_sync.handle_reply_channel_range(&peer_node_id, reply).unwrap();
+
+		let events = gossip_sync.get_and_clear_pending_msg_events();
+		assert_eq!(events.len(), 1);
+		match &events[0] {
+			MessageSendEvent::SendShortIdsQuery { node_id, msg } => {
+				assert_eq!(node_id, &peer_node_id);
+				assert_eq!(msg.chain_hash, chain_hash);
+				assert_eq!(msg.short_channel_ids, vec![1, 2, 3]);
+				assert_eq!(msg.query_flags, Some(vec![
+					msgs::QUERY_FLAG_CHANNEL_UPDATE_NODE_2,
+					msgs::QUERY_FLAG_CHANNEL_UPDATE_NODE_2,
+					ALL_QUERY_FLAGS,
+				]));
+			},
+			_ => panic!("Expected MessageSendEvent::SendShortIdsQuery"),
+		}
+		assert_eq!(gossip_sync.sync_progress(), vec![GossipSyncProgress {
+			counterparty_node_id: peer_node_id,
+			channel_range_complete: true,
+			channels_known_by_peer: 3,
+			channels_synced: 0,
+			channels_pending: 3,
+		}]);
+
+		// Once the peer has replied to our only query, the sync is complete.
+		let reply_end = ReplyShortChannelIdsEnd { chain_hash, full_information: true };
+		gossip_sync.handle_reply_short_channel_ids_end(&peer_node_id, reply_end.clone()).unwrap();
+		assert!(gossip_sync.get_and_clear_pending_msg_events().is_empty());
+		assert!(gossip_sync.sync_progress().is_empty());
+		assert!(gossip_sync.handle_reply_short_channel_ids_end(&peer_node_id, reply_end).is_err());
+
+		// A sync with a peer which disconnects before completing it is dropped.
+		let other_peer_node_id = PublicKey::from_secret_key(&secp_ctx, &SecretKey::from_slice(&[44; 32]).unwrap());
+		gossip_sync.peer_connected(&other_peer_node_id, &init_msg, true).unwrap();
+		gossip_sync.get_and_clear_pending_msg_events();
+		assert_eq!(gossip_sync.sync_progress().len(), 1);
+		gossip_sync.peer_disconnected(&other_peer_node_id);
+		assert!(gossip_sync.sync_progress().is_empty());
+	}
+
+	#[test]
+	fn handling_query_channel_range_with_query_option() {
+		let network_graph = create_network_graph();
+		let (secp_ctx, gossip_sync) = create_gossip_sync(&network_graph);
+		let node_1_privkey = &SecretKey::from_slice(&[42; 32]).unwrap();
+		let node_2_privkey = &SecretKey::from_slice(&[41; 32]).unwrap();
+		let node_id_2 = PublicKey::from_secret_key(&secp_ctx, node_2_privkey);
+		let chain_hash = genesis_block(Network::Testnet).header.block_hash();
+
+		let announcement = get_signed_channel_announcement(|_| {}, node_1_privkey, node_2_privkey, &secp_ctx);
+		gossip_sync.handle_channel_announcement(&announcement).unwrap();
+		let update = get_signed_channel_update(|unsigned_channel_update| {
+			unsigned_channel_update.timestamp = 101;
+		}, node_1_privkey, &secp_ctx);
+		gossip_sync.handle_channel_update(&update).unwrap();
+
+		do_handling_query_channel_range(
+			&gossip_sync,
+			&node_id_2,
+			QueryChannelRange {
+				chain_hash,
+				first_blocknum: 0,
+				number_of_blocks: u32::max_value(),
+				query_option: Some(msgs::QUERY_OPTION_TIMESTAMPS | msgs::QUERY_OPTION_CHECKSUMS),
+			},
+			true,
+			vec![ReplyChannelRange {
+				chain_hash,
+				first_blocknum: 0,
+				number_of_blocks: u32::max_value(),
+				sync_complete: true,
+				short_channel_ids: vec![0],
+				timestamps: Some(vec![(101, 0)]),
+				checksums: Some(vec![(channel_update_checksum(&update.contents), 0)]),
+			}]
+		);
+
+		do_handling_query_channel_range(
+			&gossip_sync,
+			&node_id_2,
+			QueryChannelRange {
+				chain_hash,
+				first_blocknum: 0,
+				number_of_blocks: u32::max_value(),
+				query_option: Some(msgs::QUERY_OPTION_TIMESTAMPS),
+			},
+			true,
+			vec![ReplyChannelRange {
+				chain_hash,
+				first_blocknum: 0,
+				number_of_blocks: u32::max_value(),
+				sync_complete: true,
+				short_channel_ids: vec![0],
+				timestamps: Some(vec![(101, 0)]),
+				checksums: None,
+			}]
+		);
+	}
+
+	#[test]
+	fn computes_channel_update_checksums() {
+		// Test vector from RFC 3720
+		assert_eq!(crc32c(b"123456789"), 0xe3069283);
+
+		// Refreshing an update's timestamp and signature doesn't change its checksum.
+		let secp_ctx = Secp256k1::new();
+		let node_1_privkey = &SecretKey::from_slice(&[42; 32]).unwrap();
+		let node_2_privkey = &SecretKey::from_slice(&[41; 32]).unwrap();
+		let update = get_signed_channel_update(|_| {}, node_1_privkey, &secp_ctx);
+		let refreshed_update = get_signed_channel_update(|unsigned_channel_update| {
+			unsigned_channel_update.timestamp += 1;
+		}, node_2_privkey, &secp_ctx);
+		assert_eq!(channel_update_checksum(&update.contents), channel_update_checksum(&refreshed_update.contents));
+
+		let changed_update = get_signed_channel_update(|unsigned_channel_update| {
+			unsigned_channel_update.fee_base_msat += 1;
+		}, node_1_privkey, &secp_ctx);
+		assert_ne!(channel_update_checksum(&update.contents), channel_update_checksum(&changed_update.contents));
+	}
+
 	#[test]
 	fn handling_query_channel_range() {
 		let network_graph = create_network_graph();
@@ -2986,6 +3620,7 @@ pub(crate) mod tests {
 				chain_hash: chain_hash.clone(),
 				first_blocknum: 0,
 				number_of_blocks: 0,
+				query_option: None,
 			},
 			false,
 			vec![ReplyChannelRange {
@@ -2993,7 +3628,9 @@ pub(crate) mod tests {
 				first_blocknum: 0,
 				number_of_blocks: 0,
 				sync_complete: true,
-				short_channel_ids: vec![]
+				short_channel_ids: vec![],
+				timestamps: None,
+				checksums: None,
 			}]
 		);
 
@@ -3005,6 +3642,7 @@ pub(crate) mod tests {
 				chain_hash: genesis_block(Network::Bitcoin).header.block_hash(),
 				first_blocknum: 0,
 				number_of_blocks: 0xffff_ffff,
+				query_option: None,
 			},
 			false,
 			vec![ReplyChannelRange {
@@ -3013,6 +3651,8 @@ pub(crate) mod tests {
 				number_of_blocks: 0xffff_ffff,
 				sync_complete: true,
 				short_channel_ids: vec![],
+				timestamps: None,
+				checksums: None,
 			}]
 		);
 
@@ -3024,6 +3664,7 @@ pub(crate) mod tests {
 				chain_hash: chain_hash.clone(),
 				first_blocknum: 0x01000000,
 				number_of_blocks: 0xffff_ffff,
+				query_option: None,
 			},
 			false,
 			vec![ReplyChannelRange {
@@ -3031,7 +3672,9 @@ pub(crate) mod tests {
 				first_blocknum: 0x01000000,
 				number_of_blocks: 0xffff_ffff,
 				sync_complete: true,
-				short_channel_ids: vec![]
+				short_channel_ids: vec![],
+				timestamps: None,
+				checksums: None,
 			}]
 		);
 
@@ -3043,6 +3686,7 @@ pub(crate) mod tests {
 				chain_hash: chain_hash.clone(),
 				first_blocknum: 0xffffff,
 				number_of_blocks: 1,
+				query_option: None,
 			},
 			true,
 			vec![
@@ -3051,7 +3695,9 @@ pub(crate) mod tests {
 					first_blocknum: 0xffffff,
 					number_of_blocks: 1,
 					sync_complete: true,
-					short_channel_ids: vec![]
+					short_channel_ids: vec![],
+					timestamps: None,
+					checksums: None,
 				},
 			]
 		);
@@ -3064,6 +3710,7 @@ pub(crate) mod tests {
 				chain_hash: chain_hash.clone(),
 				first_blocknum: 1000,
 				number_of_blocks: 1000,
+				query_option: None,
 			},
 			true,
 			vec![
@@ -3073,6 +3720,8 @@ pub(crate) mod tests {
 					number_of_blocks: 1000,
 					sync_complete: true,
 					short_channel_ids: vec![],
+					timestamps: None,
+					checksums: None,
 				}
 			]
 		);
@@ -3085,6 +3734,7 @@ pub(crate) mod tests {
 				chain_hash: chain_hash.clone(),
 				first_blocknum: 0xfe0000,
 				number_of_blocks: 0xffffffff,
+				query_option: None,
 			},
 			true,
 			vec![
@@ -3095,7 +3745,9 @@ pub(crate) mod tests {
 					sync_complete: true,
 					short_channel_ids: vec![
 						0xfffffe_ffffff_ffff, // max
-					]
+					],
+					timestamps: None,
+					checksums: None,
 				}
 			]
 		);
@@ -3108,6 +3760,7 @@ pub(crate) mod tests {
 				chain_hash: chain_hash.clone(),
 				first_blocknum: 100000,
 				number_of_blocks: 8000,
+				query_option: None,
 			},
 			true,
 			vec![
@@ -3119,6 +3772,8 @@ pub(crate) mod tests {
 					short_channel_ids: (100000..=107999)
 						.map(|block| scid_from_parts(block, 0, 0).unwrap())
 						.collect(),
+					timestamps: None,
+					checksums: None,
 				},
 			]
 		);
@@ -3131,6 +3786,7 @@ pub(crate) mod tests {
 				chain_hash: chain_hash.clone(),
 				first_blocknum: 100000,
 				number_of_blocks: 8001,
+				query_option: None,
 			},
 			true,
 			vec![
@@ -3142,6 +3798,8 @@ pub(crate) mod tests {
 					short_channel_ids: (100000..=107999)
 						.map(|block| scid_from_parts(block, 0, 0).unwrap())
 						.collect(),
+					timestamps: None,
+					checksums: None,
 				},
 				ReplyChannelRange {
 					chain_hash: chain_hash.clone(),
@@ -3151,6 +3809,8 @@ pub(crate) mod tests {
 					short_channel_ids: vec![
 						scid_from_parts(108000, 0, 0).unwrap(),
 					],
+					timestamps: None,
+					checksums: None,
 				}
 			]
 		);
@@ -3163,6 +3823,7 @@ pub(crate) mod tests {
 				chain_hash: chain_hash.clone(),
 				first_blocknum: 100002,
 				number_of_blocks: 8000,
+				query_option: None,
 			},
 			true,
 			vec![
@@ -3174,6 +3835,8 @@ pub(crate) mod tests {
 					short_channel_ids: (100002..=108001)
 						.map(|block| scid_from_parts(block, 0, 0).unwrap())
 						.collect(),
+					timestamps: None,
+					checksums: None,
 				},
 				ReplyChannelRange {
 					chain_hash: chain_hash.clone(),
@@ -3183,6 +3846,8 @@ pub(crate) mod tests {
 					short_channel_ids: vec![
 						scid_from_parts(108001, 1, 0).unwrap(),
 					],
+					timestamps: None,
+					checksums: None,
 				}
 			]
 		);
@@ -3219,6 +3884,8 @@ pub(crate) mod tests {
 					assert_eq!(msg.number_of_blocks, expected_reply.number_of_blocks);
 					assert_eq!(msg.sync_complete, expected_reply.sync_complete);
 					assert_eq!(msg.short_channel_ids, expected_reply.short_channel_ids);
+					assert_eq!(msg.timestamps, expected_reply.timestamps);
+					assert_eq!(msg.checksums, expected_reply.checksums);
 
 					// Enforce exactly the sequencing requirements present on c-lightning v0.9.3
 					assert!(msg.first_blocknum == c_lightning_0_9_prev_end_blocknum || msg.first_blocknum == c_lightning_0_9_prev_end_blocknum.saturating_add(1));
@@ -3244,12 +3911,108 @@ pub(crate) mod tests {
 		let node_id = PublicKey::from_secret_key(&secp_ctx, node_privkey);
 
 		let chain_hash = genesis_block(Network::Testnet).header.block_hash();
+		let node_1_privkey = &SecretKey::from_slice(&[42; 32]).unwrap();
+		let node_2_privkey = &SecretKey::from_slice(&[43; 32]).unwrap();
+
+		// Announce two channels between the same nodes, only one of which has an update.
+		let chan_announcement_1 = get_signed_channel_announcement(|unsigned_announcement| {
+			unsigned_announcement.short_channel_id = 1;
+		}, node_1_privkey, node_2_privkey, &secp_ctx);
+		let chan_announcement_2 = get_signed_channel_announcement(|unsigned_announcement| {
+			unsigned_announcement.short_channel_id = 2;
+		}, node_1_privkey, node_2_privkey, &secp_ctx);
+		let chan_update_1 = get_signed_channel_update(|unsigned_channel_update| {
+			unsigned_channel_update.short_channel_id = 1;
+		}, node_1_privkey, &secp_ctx);
+		let node_announcement_1 = get_signed_node_announcement(|_| {}, node_1_privkey, &secp_ctx);
+		let node_announcement_2 = get_signed_node_announcement(|_| {}, node_2_privkey, &secp_ctx);
+		gossip_sync.handle_channel_announcement(&chan_announcement_1).unwrap();
+		gossip_sync.handle_channel_announcement(&chan_announcement_2).unwrap();
+		gossip_sync.handle_channel_update(&chan_update_1).unwrap();
+		gossip_sync.handle_node_announcement(&node_announcement_1).unwrap();
+		gossip_sync.handle_node_announcement(&node_announcement_2).unwrap();
+
+		let drain_replies = || {
+			let mut replies = Vec::new();
+			while let Some(reply) = gossip_sync.get_next_query_reply(&node_id) {
+				replies.push(reply);
+			}
+			replies
+		};
 
-		let result = gossip_sync.handle_query_short_channel_ids(&node_id, QueryShortChannelIds {
+		// Without query flags, we reply with everything we know about the channels, sending each
+		// node_announcement only once and skipping unknown channels.
+		gossip_sync.handle_query_short_channel_ids(&node_id, QueryShortChannelIds {
 			chain_hash,
-			short_channel_ids: vec![0x0003e8_000000_0000],
-		});
-		assert!(result.is_err());
+			short_channel_ids: vec![1, 2, 0x0003e8_000000_0000],
+			query_flags: None,
+		}).unwrap();
+
+		// A second query may not be sent until the first has been replied to.
+		assert!(gossip_sync.handle_query_short_channel_ids(&node_id, QueryShortChannelIds {
+			chain_hash,
+			short_channel_ids: vec![1],
+			query_flags: None,
+		}).is_err());
+
+		assert_eq!(drain_replies(), vec![
+			GossipQueryReply::ChannelAnnouncement(chan_announcement_1.clone()),
+			GossipQueryReply::ChannelUpdate(chan_update_1.clone()),
+			GossipQueryReply::NodeAnnouncement(node_announcement_1),
+			GossipQueryReply::NodeAnnouncement(node_announcement_2.clone()),
+			GossipQueryReply::ChannelAnnouncement(chan_announcement_2),
+			GossipQueryReply::ReplyShortChannelIdsEnd(ReplyShortChannelIdsEnd {
+				chain_hash, full_information: true,
+			}),
+		]);
+
+		// With query flags, we only reply with the requested messages.
+		gossip_sync.handle_query_short_channel_ids(&node_id, QueryShortChannelIds {
+			chain_hash,
+			short_channel_ids: vec![1],
+			query_flags: Some(vec![
+				msgs::QUERY_FLAG_CHANNEL_UPDATE_NODE_1 | msgs::QUERY_FLAG_NODE_ANNOUNCEMENT_NODE_2
+			]),
+		}).unwrap();
+		assert_eq!(drain_replies(), vec![
+			GossipQueryReply::ChannelUpdate(chan_update_1),
+			GossipQueryReply::NodeAnnouncement(node_announcement_2),
+			GossipQueryReply::ReplyShortChannelIdsEnd(ReplyShortChannelIdsEnd {
+				chain_hash, full_information: true,
+			}),
+		]);
+
+		// Query flags must match the short_channel_ids one-to-one.
+		assert!(gossip_sync.handle_query_short_channel_ids(&node_id, QueryShortChannelIds {
+			chain_hash,
+			short_channel_ids: vec![1, 2],
+			query_flags: Some(vec![msgs::QUERY_FLAG_CHANNEL_ANNOUNCEMENT]),
+		}).is_err());
+		assert!(gossip_sync.get_next_query_reply(&node_id).is_none());
+
+		// Queries for a chain we don't track are answered without any information.
+		let mainnet_hash = genesis_block(Network::Bitcoin).header.block_hash();
+		gossip_sync.handle_query_short_channel_ids(&node_id, QueryShortChannelIds {
+			chain_hash: mainnet_hash,
+			short_channel_ids: vec![1],
+			query_flags: None,
+		}).unwrap();
+		assert_eq!(drain_replies(), vec![
+			GossipQueryReply::ReplyShortChannelIdsEnd(ReplyShortChannelIdsEnd {
+				chain_hash: mainnet_hash, full_information: false,
+			}),
+		]);
+
+		// A reply still pending when the peer disconnects is dropped.
+		gossip_sync.handle_query_short_channel_ids(&node_id, QueryShortChannelIds {
+			chain_hash,
+			short_channel_ids: vec![1, 2],
+			query_flags: None,
+		}).unwrap();
+		assert!(gossip_sync.get_next_query_reply(&node_id).is_some());
+		gossip_sync.peer_disconnected(&node_id);
+		assert!(gossip_sync.pending_query_replies.lock().unwrap().is_empty());
+		assert!(gossip_sync.get_next_query_reply(&node_id).is_none());
 	}
 
 	#[test]
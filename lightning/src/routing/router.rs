@@ -547,6 +547,11 @@ pub struct PaymentParameters {
 	/// payment to fail. Future attempts for the same payment shouldn't be relayed through any of
 	/// these SCIDs.
 	pub previously_failed_channels: Vec<u64>,
+
+	/// A list of indices corresponding to blinded paths in [`Payee::Blinded::route_hints`] which this
+	/// payment was previously attempted over and which caused the payment to fail. Future attempts
+	/// for the same payment shouldn't be relayed through any of these blinded paths.
+	pub previously_failed_blinded_path_idxs: Vec<u64>,
 }
 
 impl Writeable for PaymentParameters {
@@ -568,6 +573,7 @@ impl Writeable for PaymentParameters {
 			(7, self.previously_failed_channels, required_vec),
 			(8, *blinded_hints, optional_vec),
 			(9, self.payee.final_cltv_expiry_delta(), option),
+			(11, self.previously_failed_blinded_path_idxs, optional_vec),
 		});
 		Ok(())
 	}
@@ -586,6 +592,7 @@ impl ReadableArgs<u32> for PaymentParameters {
 			(7, previously_failed_channels, optional_vec),
 			(8, blinded_route_hints, optional_vec),
 			(9, final_cltv_expiry_delta, (default_value, default_final_cltv_expiry_delta)),
+			(11, previously_failed_blinded_path_idxs, optional_vec),
 		});
 		let blinded_route_hints = blinded_route_hints.unwrap_or(vec![]);
 		let payee = if blinded_route_hints.len() != 0 {
@@ -609,6 +616,7 @@ impl ReadableArgs<u32> for PaymentParameters {
 			max_channel_saturation_power_of_half: _init_tlv_based_struct_field!(max_channel_saturation_power_of_half, (default_value, unused)),
 			expiry_time,
 			previously_failed_channels: previously_failed_channels.unwrap_or(Vec::new()),
+			previously_failed_blinded_path_idxs: previously_failed_blinded_path_idxs.unwrap_or(Vec::new()),
 		})
 	}
 }
@@ -627,6 +635,7 @@ impl PaymentParameters {
 			max_path_count: DEFAULT_MAX_PATH_COUNT,
 			max_channel_saturation_power_of_half: DEFAULT_MAX_CHANNEL_SATURATION_POW_HALF,
 			previously_failed_channels: Vec::new(),
+			previously_failed_blinded_path_idxs: Vec::new(),
 		}
 	}
 
@@ -656,7 +665,8 @@ impl PaymentParameters {
 			.with_expiry_time(invoice.created_at().as_secs().saturating_add(invoice.relative_expiry().as_secs()))
 	}
 
-	fn blinded(blinded_route_hints: Vec<(BlindedPayInfo, BlindedPath)>) -> Self {
+	/// Creates parameters for paying to a blinded payee from the provided blinded route hints.
+	pub fn blinded(blinded_route_hints: Vec<(BlindedPayInfo, BlindedPath)>) -> Self {
 		Self {
 			payee: Payee::Blinded { route_hints: blinded_route_hints, features: None },
 			expiry_time: None,
@@ -664,6 +674,7 @@ impl PaymentParameters {
 			max_path_count: DEFAULT_MAX_PATH_COUNT,
 			max_channel_saturation_power_of_half: DEFAULT_MAX_CHANNEL_SATURATION_POW_HALF,
 			previously_failed_channels: Vec::new(),
+			previously_failed_blinded_path_idxs: Vec::new(),
 		}
 	}
 
@@ -739,6 +750,19 @@ impl PaymentParameters {
 	pub fn with_max_channel_saturation_power_of_half(self, max_channel_saturation_power_of_half: u8) -> Self {
 		Self { max_channel_saturation_power_of_half, ..self }
 	}
+
+	pub(crate) fn insert_previously_failed_blinded_path(&mut self, failed_blinded_tail: &BlindedTail) {
+		let mut found_blinded_tail = false;
+		for (idx, (_, path)) in self.payee.blinded_route_hints().iter().enumerate() {
+			if failed_blinded_tail.hops == path.blinded_hops &&
+				failed_blinded_tail.blinding_point == path.blinding_point
+			{
+				self.previously_failed_blinded_path_idxs.push(idx as u64);
+				found_blinded_tail = true;
+			}
+		}
+		debug_assert!(found_blinded_tail);
+	}
 }
 
 /// The recipient of a payment, differing based on whether they've hidden their identity with route
@@ -973,10 +997,11 @@ enum CandidateRouteHop<'a> {
 		hint: &'a (BlindedPayInfo, BlindedPath),
 		hint_idx: usize,
 	},
-	/// Similar to [`Self::Blinded`], but the path here has 1 blinded hop. `BlindedPayInfo` provided
-	/// for 1-hop blinded paths is ignored because it is meant to apply to the hops *between* the
-	/// introduction node and the destination. Useful for tracking that we need to include a blinded
-	/// path at the end of our [`Route`].
+	/// Similar to [`Self::Blinded`], but the path here has 1 blinded hop. The fees and HTLC limits of
+	/// the `BlindedPayInfo` provided for 1-hop blinded paths are ignored because they are meant to
+	/// apply to the hops *between* the introduction node and the destination, though its
+	/// `cltv_expiry_delta` is used as the recipient's final CLTV delta. Useful for tracking that we
+	/// need to include a blinded path at the end of our [`Route`].
 	OneHopBlinded {
 		hint: &'a (BlindedPayInfo, BlindedPath),
 		hint_idx: usize,
@@ -1010,8 +1035,8 @@ impl<'a> CandidateRouteHop<'a> {
 			CandidateRouteHop::FirstHop { .. } => 0,
 			CandidateRouteHop::PublicHop { info, .. } => info.direction().cltv_expiry_delta as u32,
 			CandidateRouteHop::PrivateHop { hint } => hint.cltv_expiry_delta as u32,
-			CandidateRouteHop::Blinded { hint, .. } => hint.0.cltv_expiry_delta as u32,
-			CandidateRouteHop::OneHopBlinded { .. } => 0,
+			CandidateRouteHop::Blinded { hint, .. } | CandidateRouteHop::OneHopBlinded { hint, .. } =>
+				hint.0.cltv_expiry_delta as u32,
 		}
 	}
 
@@ -2014,6 +2039,9 @@ where L::Target: Logger {
 		// earlier than general path finding, they will be somewhat prioritized, although currently
 		// it matters only if the fees are exactly the same.
 		for (hint_idx, hint) in payment_params.payee.blinded_route_hints().iter().enumerate() {
+			if payment_params.previously_failed_blinded_path_idxs.contains(&(hint_idx as u64)) {
+				continue
+			}
 			let intro_node_id = NodeId::from_pubkey(&hint.1.introduction_node_id);
 			let have_intro_node_in_graph =
 				// Only add the hops in this route to our candidate set if either
@@ -2740,6 +2768,7 @@ mod tests {
 			config: None,
 			feerate_sat_per_1000_weight: None,
 			channel_shutdown_state: Some(channelmanager::ChannelShutdownState::NotShuttingDown),
+			channel_quiescence_state: Some(channelmanager::ChannelQuiescenceState::NotQuiescent),
 		}
 	}
 
@@ -3897,7 +3926,7 @@ mod tests {
 	fn available_amount_while_routing_test() {
 		// Tests whether we choose the correct available channel amount while routing.
 
-		let (secp_ctx, network_graph, mut gossip_sync, chain_monitor, logger) = build_graph();
+		let (secp_ctx, network_graph, gossip_sync, chain_monitor, logger) = build_graph();
 		let (our_privkey, our_id, privkeys, nodes) = get_nodes(&secp_ctx);
 		let scorer = ln_test_utils::TestScorer::new();
 		let keys_manager = ln_test_utils::TestKeysInterface::new(&[0u8; 32], Network::Testnet);
@@ -4361,7 +4390,7 @@ mod tests {
 			blinding_point: ln_test_utils::pubkey(42),
 			blinded_hops: vec![BlindedHop { blinded_node_id: ln_test_utils::pubkey(42 as u8), encrypted_payload: Vec::new() }],
 		};
-		let blinded_payinfo = BlindedPayInfo { // These fields are ignored for 1-hop blinded paths
+		let blinded_payinfo = BlindedPayInfo { // Fees and HTLC limits are ignored for 1-hop blinded paths
 			fee_base_msat: 0,
 			fee_proportional_millionths: 0,
 			htlc_minimum_msat: 0,
@@ -6580,11 +6609,10 @@ mod tests {
 		if tail.hops.len() > 1 {
 			assert_eq!(final_hop.fee_msat,
 				blinded_payinfo.fee_base_msat as u64 + blinded_payinfo.fee_proportional_millionths as u64 * tail.final_value_msat / 1000000);
-			assert_eq!(final_hop.cltv_expiry_delta, blinded_payinfo.cltv_expiry_delta as u32);
 		} else {
 			assert_eq!(final_hop.fee_msat, 0);
-			assert_eq!(final_hop.cltv_expiry_delta, 0);
 		}
+		assert_eq!(final_hop.cltv_expiry_delta, blinded_payinfo.cltv_expiry_delta as u32);
 	}
 
 	#[test]
@@ -6811,6 +6839,7 @@ pub(crate) mod bench_utils {
 			config: None,
 			feerate_sat_per_1000_weight: None,
 			channel_shutdown_state: Some(channelmanager::ChannelShutdownState::NotShuttingDown),
+			channel_quiescence_state: Some(channelmanager::ChannelQuiescenceState::NotQuiescent),
 		}
 	}
 
@@ -0,0 +1,673 @@
+// This file is Copyright its original authors, visible in version control
+// history.
+//
+// This file is licensed under the Apache License, Version 2.0 <LICENSE-APACHE
+// or http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your option.
+// You may not use this file except in accordance with one or both of these
+// licenses.
+
+//! A minimal on-chain wallet deriving its keys from the seed of a [`KeysManager`], which may be
+//! used to fund fee bumps of anchor channel transactions without requiring a separate wallet.
+
+use crate::chain::{BestBlock, Confirm, Filter, Listen, WatchedOutput};
+use crate::chain::channelmonitor::ANTI_REORG_DELAY;
+use crate::chain::transaction::TransactionData;
+use crate::events::bump_transaction::{Utxo, WalletSource};
+use crate::io;
+use crate::ln::msgs::DecodeError;
+use crate::prelude::*;
+use crate::sign::KeysManager;
+use crate::sync::Mutex;
+use crate::util::crypto::sign;
+use crate::util::logger::Logger;
+use crate::util::persist::{KVStore, WALLET_PERSISTENCE_KEY, WALLET_PERSISTENCE_NAMESPACE, WALLET_PERSISTENCE_SUB_NAMESPACE};
+use crate::util::ser::{Readable, ReadableArgs, Writeable};
+
+use bitcoin::{BlockHash, EcdsaSighashType, Network, OutPoint, Script, Transaction, Txid, TxOut, Witness, WPubkeyHash};
+use bitcoin::blockdata::block::BlockHeader;
+use bitcoin::hashes::Hash;
+use bitcoin::secp256k1::{self, PublicKey, Secp256k1, SecretKey};
+use bitcoin::util::bip32::{ChildNumber, ExtendedPrivKey};
+use bitcoin::util::sighash;
+
+use core::ops::Deref;
+
+/// The hardened child index of the [`KeysManager`] master key from which all wallet keys are
+/// derived.
+const WALLET_DERIVATION_INDEX: u32 = 6;
+
+/// The number of scripts beyond the last used one we derive and watch for on each keychain.
+const SCRIPT_LOOKAHEAD: u32 = 20;
+
+#[derive(Clone, Copy, Debug, Hash, PartialEq, Eq)]
+enum Keychain {
+	/// Used for scripts handed out to receive funds.
+	External,
+	/// Used for change outputs of transactions we sign.
+	Internal,
+}
+
+impl_writeable_tlv_based_enum!(Keychain,
+	(0, External) => {},
+	(2, Internal) => {};
+);
+
+struct WalletUtxo {
+	outpoint: OutPoint,
+	output: TxOut,
+	keychain: Keychain,
+	derivation_index: u32,
+	confirmation_height: u32,
+	confirmation_hash: BlockHash,
+	/// The transaction spending the output along with the height and hash of the block it was
+	/// confirmed in. Spent outputs are kept until the spend reached [`ANTI_REORG_DELAY`]
+	/// confirmations.
+	spent_in: Option<(Txid, u32, BlockHash)>,
+}
+
+impl_writeable_tlv_based!(WalletUtxo, {
+	(0, outpoint, required),
+	(2, output, required),
+	(4, keychain, required),
+	(6, derivation_index, required),
+	(8, confirmation_height, required),
+	(10, confirmation_hash, required),
+	(12, spent_in, option),
+});
+
+/// An output paying to us in a transaction we signed which has yet to confirm.
+struct UnconfirmedOutput {
+	output: TxOut,
+	keychain: Keychain,
+	derivation_index: u32,
+	/// The outpoints spent by the transaction, any of which being spent by a different transaction
+	/// means the output will never exist.
+	spends: Vec<OutPoint>,
+}
+
+struct WalletState {
+	best_block: BestBlock,
+	next_external_index: u32,
+	next_internal_index: u32,
+	utxos: Vec<WalletUtxo>,
+}
+
+impl_writeable_tlv_based!(WalletState, {
+	(0, best_block, required),
+	(2, next_external_index, required),
+	(4, next_internal_index, required),
+	(6, utxos, required_vec),
+});
+
+/// A minimal single-signature wallet implementing [`WalletSource`], deriving its keys from the
+/// seed of a [`KeysManager`] at `seed/6'`.
+///
+/// Funds are received to P2WPKH scripts derived at `seed/6'/0/*`, as returned by
+/// [`Self::get_new_script_pubkey`], while change is sent to ones derived at `seed/6'/1/*`.
+///
+/// The wallet only considers outputs with at least one confirmation and thus needs to be kept in
+/// sync with the chain via its [`Listen`] or [`Confirm`] implementation. Its state is persisted via
+/// the given [`KVStore`] upon any change. If chain data is provided via the [`Confirm`] interface
+/// or via filtered blocks, the given [`Filter`] is used to learn about spends of the wallet's
+/// outputs as well as the confirmation of transactions signed by it. Note that incoming payments
+/// to scripts handed out by [`Self::get_new_script_pubkey`] are only detected if they are included
+/// in the chain data given, i.e., when using [`Confirm`], their transactions need to be registered
+/// with the chain source.
+///
+/// To fund fee bumps, wrap the wallet in a [`Wallet`], which implements coin selection and locks
+/// UTXOs used in pending claims, and give it to a [`BumpTransactionEventHandler`]. As the wallet
+/// is able to sign for the change of its transactions before they confirm, the [`Wallet`] may
+/// spend it right away to fund further fee bumps.
+///
+/// [`Wallet`]: crate::events::bump_transaction::Wallet
+/// [`BumpTransactionEventHandler`]: crate::events::bump_transaction::BumpTransactionEventHandler
+pub struct SimpleWallet<F: Deref, K: Deref, L: Deref>
+where
+	F::Target: Filter,
+	K::Target: KVStore,
+	L::Target: Logger,
+{
+	external_key: ExtendedPrivKey,
+	internal_key: ExtendedPrivKey,
+	secp_ctx: Secp256k1<secp256k1::All>,
+	state: Mutex<WalletState>,
+	/// All scripts derived so far, including those looked ahead, mapped to their derivation.
+	scripts: Mutex<HashMap<Script, (Keychain, u32)>>,
+	/// Outputs paying to us in transactions we signed which have yet to confirm, allowing us to
+	/// sign for them before they do. Outputs are dropped once their transaction is conflicted by a
+	/// confirmed one.
+	unconfirmed_outputs: Mutex<HashMap<OutPoint, UnconfirmedOutput>>,
+	chain_source: Option<F>,
+	kv_store: K,
+	logger: L,
+}
+
+impl<F: Deref, K: Deref, L: Deref> SimpleWallet<F, K, L>
+where
+	F::Target: Filter,
+	K::Target: KVStore,
+	L::Target: Logger,
+{
+	/// Constructs a new, empty [`SimpleWallet`] deriving its keys from the given [`KeysManager`].
+	///
+	/// The given `best_block` should be that at which the seed was created, as outputs confirmed
+	/// before will not be detected.
+	pub fn new(
+		keys_manager: &KeysManager, best_block: BestBlock, chain_source: Option<F>, kv_store: K,
+		logger: L,
+	) -> Self {
+		let state = WalletState { best_block, next_external_index: 0, next_internal_index: 0, utxos: Vec::new() };
+		Self::from_state(keys_manager, state, chain_source, kv_store, logger)
+	}
+
+	fn from_state(
+		keys_manager: &KeysManager, state: WalletState, chain_source: Option<F>, kv_store: K,
+		logger: L,
+	) -> Self {
+		let secp_ctx = Secp256k1::new();
+		// Note that when we aren't serializing the key, network doesn't matter
+		let wallet_key = match ExtendedPrivKey::new_master(Network::Testnet, &keys_manager.seed) {
+			Ok(master_key) => master_key.ckd_priv(&secp_ctx, ChildNumber::from_hardened_idx(WALLET_DERIVATION_INDEX).unwrap())
+				.expect("Your RNG is busted"),
+			Err(_) => panic!("Your rng is busted"),
+		};
+		let external_key = wallet_key.ckd_priv(&secp_ctx, ChildNumber::from_normal_idx(0).unwrap())
+			.expect("Your RNG is busted");
+		let internal_key = wallet_key.ckd_priv(&secp_ctx, ChildNumber::from_normal_idx(1).unwrap())
+			.expect("Your RNG is busted");
+
+		let wallet = Self {
+			external_key,
+			internal_key,
+			secp_ctx,
+			state: Mutex::new(state),
+			scripts: Mutex::new(HashMap::new()),
+			unconfirmed_outputs: Mutex::new(HashMap::new()),
+			chain_source,
+			kv_store,
+			logger,
+		};
+		{
+			let state_lock = wallet.state.lock().unwrap();
+			wallet.derive_scripts_up_to(Keychain::External, state_lock.next_external_index + SCRIPT_LOOKAHEAD);
+			wallet.derive_scripts_up_to(Keychain::Internal, state_lock.next_internal_index + SCRIPT_LOOKAHEAD);
+			for utxo in state_lock.utxos.iter() {
+				wallet.watch_utxo(utxo);
+			}
+		}
+		wallet
+	}
+
+	/// Returns a new P2WPKH script to receive funds to.
+	pub fn get_new_script_pubkey(&self) -> Script {
+		let mut state_lock = self.state.lock().unwrap();
+		let index = state_lock.next_external_index;
+		state_lock.next_external_index += 1;
+		self.derive_scripts_up_to(Keychain::External, state_lock.next_external_index + SCRIPT_LOOKAHEAD);
+		self.persist_state(&state_lock);
+		self.script_pubkey(Keychain::External, index)
+	}
+
+	/// Returns the sum of the values of all confirmed and unspent outputs controlled by the wallet.
+	pub fn get_confirmed_balance_sat(&self) -> u64 {
+		self.state.lock().unwrap().utxos.iter()
+			.filter(|utxo| utxo.spent_in.is_none())
+			.map(|utxo| utxo.output.value)
+			.sum()
+	}
+
+	/// Gets the latest best block which was connected either via the [`Listen`] or [`Confirm`]
+	/// interfaces.
+	pub fn current_best_block(&self) -> BestBlock {
+		self.state.lock().unwrap().best_block
+	}
+
+	fn secret_key(&self, keychain: Keychain, index: u32) -> SecretKey {
+		let keychain_key = match keychain {
+			Keychain::External => &self.external_key,
+			Keychain::Internal => &self.internal_key,
+		};
+		keychain_key.ckd_priv(&self.secp_ctx, ChildNumber::from_normal_idx(index).expect("key space exhausted"))
+			.expect("Your RNG is busted")
+			.private_key
+	}
+
+	fn script_pubkey(&self, keychain: Keychain, index: u32) -> Script {
+		let pubkey = PublicKey::from_secret_key(&self.secp_ctx, &self.secret_key(keychain, index));
+		Script::new_v0_p2wpkh(&WPubkeyHash::hash(&pubkey.serialize()))
+	}
+
+	fn derive_scripts_up_to(&self, keychain: Keychain, end_index: u32) {
+		let mut scripts = self.scripts.lock().unwrap();
+		let derived = scripts.values().filter(|(script_keychain, _)| *script_keychain == keychain).count() as u32;
+		for index in derived..end_index {
+			scripts.insert(self.script_pubkey(keychain, index), (keychain, index));
+		}
+	}
+
+	fn watch_utxo(&self, utxo: &WalletUtxo) {
+		if let Some(chain_source) = self.chain_source.as_ref() {
+			chain_source.register_output(WatchedOutput {
+				block_hash: Some(utxo.confirmation_hash),
+				outpoint: crate::chain::transaction::OutPoint { txid: utxo.outpoint.txid, index: utxo.outpoint.vout as u16 },
+				script_pubkey: utxo.output.script_pubkey.clone(),
+			});
+		}
+	}
+
+	fn persist_state(&self, state: &WalletState) {
+		if let Err(e) = self.kv_store.write(
+			WALLET_PERSISTENCE_NAMESPACE, WALLET_PERSISTENCE_SUB_NAMESPACE, WALLET_PERSISTENCE_KEY,
+			&state.encode(),
+		) {
+			log_error!(self.logger, "Error persisting SimpleWallet: {:?}", e);
+		}
+	}
+
+	fn transactions_confirmed_internal(
+		&self, state: &mut WalletState, header: &BlockHeader, txdata: &TransactionData, height: u32,
+	) {
+		let block_hash = header.block_hash();
+		for (_, tx) in txdata {
+			let txid = tx.txid();
+			for input in tx.input.iter() {
+				if let Some(utxo) = state.utxos.iter_mut().find(|utxo| utxo.outpoint == input.previous_output) {
+					log_debug!(self.logger, "Wallet output {} was spent in transaction {}", utxo.outpoint, txid);
+					utxo.spent_in = Some((txid, height, block_hash));
+				}
+			}
+			self.remove_conflicted_outputs(tx);
+
+			for (vout, output) in tx.output.iter().enumerate() {
+				let derivation = self.scripts.lock().unwrap().get(&output.script_pubkey).copied();
+				let (keychain, derivation_index) = match derivation {
+					Some(derivation) => derivation,
+					None => continue,
+				};
+				let outpoint = OutPoint { txid, vout: vout as u32 };
+				if state.utxos.iter().any(|utxo| utxo.outpoint == outpoint) {
+					continue;
+				}
+
+				match keychain {
+					Keychain::External if derivation_index >= state.next_external_index =>
+						state.next_external_index = derivation_index + 1,
+					Keychain::Internal if derivation_index >= state.next_internal_index =>
+						state.next_internal_index = derivation_index + 1,
+					_ => {},
+				}
+				self.derive_scripts_up_to(Keychain::External, state.next_external_index + SCRIPT_LOOKAHEAD);
+				self.derive_scripts_up_to(Keychain::Internal, state.next_internal_index + SCRIPT_LOOKAHEAD);
+
+				log_info!(self.logger, "Wallet received {} sat in output {}", output.value, outpoint);
+				let utxo = WalletUtxo {
+					outpoint,
+					output: output.clone(),
+					keychain,
+					derivation_index,
+					confirmation_height: height,
+					confirmation_hash: block_hash,
+					spent_in: None,
+				};
+				self.watch_utxo(&utxo);
+				self.unconfirmed_outputs.lock().unwrap().remove(&outpoint);
+				state.utxos.push(utxo);
+			}
+		}
+	}
+
+	/// Drops any unconfirmed outputs of transactions double spent by the given confirmed
+	/// transaction, as well as those of transactions spending them in turn.
+	fn remove_conflicted_outputs(&self, tx: &Transaction) {
+		let txid = tx.txid();
+		let mut unconfirmed_outputs = self.unconfirmed_outputs.lock().unwrap();
+		let mut conflicted_outpoints: Vec<OutPoint> = tx.input.iter().map(|input| input.previous_output).collect();
+		loop {
+			let newly_conflicted: Vec<OutPoint> = unconfirmed_outputs.iter()
+				.filter(|(outpoint, unconfirmed_output)| outpoint.txid != txid &&
+					unconfirmed_output.spends.iter().any(|spent| conflicted_outpoints.contains(spent)))
+				.map(|(outpoint, _)| *outpoint)
+				.collect();
+			if newly_conflicted.is_empty() {
+				break;
+			}
+			for outpoint in newly_conflicted {
+				log_debug!(self.logger, "Dropping unconfirmed wallet output {} conflicted by transaction {}", outpoint, txid);
+				unconfirmed_outputs.remove(&outpoint);
+				conflicted_outpoints.push(outpoint);
+			}
+		}
+	}
+
+	fn best_block_updated_internal(&self, state: &mut WalletState, header: &BlockHeader, height: u32) {
+		state.best_block = BestBlock::new(header.block_hash(), height);
+		// Forget outputs once their spend can't be reorged out anymore.
+		state.utxos.retain(|utxo| match utxo.spent_in {
+			Some((_, spend_height, _)) => height < spend_height + ANTI_REORG_DELAY - 1,
+			None => true,
+		});
+	}
+}
+
+impl<F: Deref, K: Deref, L: Deref> WalletSource for SimpleWallet<F, K, L>
+where
+	F::Target: Filter,
+	K::Target: KVStore,
+	L::Target: Logger,
+{
+	fn list_confirmed_utxos(&self) -> Result<Vec<Utxo>, ()> {
+		let state_lock = self.state.lock().unwrap();
+		let mut utxos = Vec::new();
+		for utxo in state_lock.utxos.iter().filter(|utxo| utxo.spent_in.is_none()) {
+			// All of our scripts are P2WPKH, i.e., a witness version byte and a push of the hash.
+			let pubkey_hash = WPubkeyHash::from_slice(&utxo.output.script_pubkey.as_bytes()[2..]).map_err(|_| ())?;
+			utxos.push(Utxo::new_v0_p2wpkh(utxo.outpoint, utxo.output.value, &pubkey_hash));
+		}
+		Ok(utxos)
+	}
+
+	fn get_change_script(&self) -> Result<Script, ()> {
+		let mut state_lock = self.state.lock().unwrap();
+		let index = state_lock.next_internal_index;
+		state_lock.next_internal_index += 1;
+		self.derive_scripts_up_to(Keychain::Internal, state_lock.next_internal_index + SCRIPT_LOOKAHEAD);
+		self.persist_state(&state_lock);
+		Ok(self.script_pubkey(Keychain::Internal, index))
+	}
+
+	fn sign_tx(&self, mut tx: Transaction) -> Result<Transaction, ()> {
+		let state_lock = self.state.lock().unwrap();
+		let mut unconfirmed_outputs = self.unconfirmed_outputs.lock().unwrap();
+		let mut signed_inputs = Vec::new();
+		for (input_idx, input) in tx.input.iter().enumerate() {
+			let (value, keychain, derivation_index) = match state_lock.utxos.iter()
+				.find(|utxo| utxo.outpoint == input.previous_output)
+			{
+				Some(utxo) => (utxo.output.value, utxo.keychain, utxo.derivation_index),
+				None => match unconfirmed_outputs.get(&input.previous_output) {
+					Some(unconfirmed_output) => (unconfirmed_output.output.value, unconfirmed_output.keychain,
+						unconfirmed_output.derivation_index),
+					None => continue,
+				},
+			};
+			let secret_key = self.secret_key(keychain, derivation_index);
+			let pubkey = bitcoin::PublicKey::new(PublicKey::from_secret_key(&self.secp_ctx, &secret_key));
+			let witness_script = Script::new_p2pkh(&pubkey.pubkey_hash());
+			let sighash = hash_to_message!(&sighash::SighashCache::new(&tx)
+				.segwit_signature_hash(input_idx, &witness_script, value, EcdsaSighashType::All)
+				.map_err(|_| ())?[..]);
+			let sig = sign(&self.secp_ctx, &sighash, &secret_key);
+			let mut sig_ser = sig.serialize_der().to_vec();
+			sig_ser.push(EcdsaSighashType::All as u8);
+			signed_inputs.push((input_idx, Witness::from_vec(vec![sig_ser, pubkey.inner.serialize().to_vec()])));
+		}
+		for (input_idx, witness) in signed_inputs {
+			tx.input[input_idx].witness = witness;
+		}
+
+		// Make sure we learn about the confirmation of any change paid back to us.
+		let txid = tx.txid();
+		let scripts = self.scripts.lock().unwrap();
+		for (vout, output) in tx.output.iter().enumerate() {
+			if let Some((keychain, derivation_index)) = scripts.get(&output.script_pubkey) {
+				let outpoint = OutPoint { txid, vout: vout as u32 };
+				unconfirmed_outputs.insert(outpoint, UnconfirmedOutput {
+					output: output.clone(),
+					keychain: *keychain,
+					derivation_index: *derivation_index,
+					spends: tx.input.iter().map(|input| input.previous_output).collect(),
+				});
+				if let Some(chain_source) = self.chain_source.as_ref() {
+					chain_source.register_tx(&txid, &output.script_pubkey);
+				}
+			}
+		}
+		Ok(tx)
+	}
+
+	fn can_sign_unconfirmed_change(&self) -> bool {
+		true
+	}
+}
+
+impl<F: Deref, K: Deref, L: Deref> Listen for SimpleWallet<F, K, L>
+where
+	F::Target: Filter,
+	K::Target: KVStore,
+	L::Target: Logger,
+{
+	fn filtered_block_connected(&self, header: &BlockHeader, txdata: &TransactionData, height: u32) {
+		let mut state_lock = self.state.lock().unwrap();
+		assert_eq!(state_lock.best_block.block_hash(), header.prev_blockhash,
+			"Blocks must be connected in chain-order - the connected header must build on the last connected header");
+		assert_eq!(state_lock.best_block.height(), height - 1,
+			"Blocks must be connected in chain-order - the connected block height must be one greater than the previous height");
+
+		self.transactions_confirmed_internal(&mut state_lock, header, txdata, height);
+		self.best_block_updated_internal(&mut state_lock, header, height);
+		self.persist_state(&state_lock);
+	}
+
+	fn block_disconnected(&self, header: &BlockHeader, height: u32) {
+		let mut state_lock = self.state.lock().unwrap();
+		let block_hash = header.block_hash();
+		assert_eq!(state_lock.best_block.block_hash(), block_hash,
+			"Blocks must be disconnected in chain-order - the disconnected header must be the last connected header");
+		assert_eq!(state_lock.best_block.height(), height,
+			"Blocks must be disconnected in chain-order - the disconnected block must have the correct height");
+		state_lock.best_block = BestBlock::new(header.prev_blockhash, height - 1);
+
+		state_lock.utxos.retain(|utxo| utxo.confirmation_hash != block_hash);
+		for utxo in state_lock.utxos.iter_mut() {
+			if matches!(utxo.spent_in, Some((_, _, spend_hash)) if spend_hash == block_hash) {
+				utxo.spent_in = None;
+			}
+		}
+		self.persist_state(&state_lock);
+	}
+}
+
+impl<F: Deref, K: Deref, L: Deref> Confirm for SimpleWallet<F, K, L>
+where
+	F::Target: Filter,
+	K::Target: KVStore,
+	L::Target: Logger,
+{
+	fn transactions_confirmed(&self, header: &BlockHeader, txdata: &TransactionData, height: u32) {
+		let mut state_lock = self.state.lock().unwrap();
+		self.transactions_confirmed_internal(&mut state_lock, header, txdata, height);
+		self.persist_state(&state_lock);
+	}
+
+	fn transaction_unconfirmed(&self, txid: &Txid) {
+		let mut state_lock = self.state.lock().unwrap();
+		state_lock.utxos.retain(|utxo| utxo.outpoint.txid != *txid);
+		for utxo in state_lock.utxos.iter_mut() {
+			if matches!(utxo.spent_in, Some((spend_txid, _, _)) if spend_txid == *txid) {
+				utxo.spent_in = None;
+			}
+		}
+		self.persist_state(&state_lock);
+	}
+
+	fn best_block_updated(&self, header: &BlockHeader, height: u32) {
+		let mut state_lock = self.state.lock().unwrap();
+		self.best_block_updated_internal(&mut state_lock, header, height);
+		self.persist_state(&state_lock);
+	}
+
+	fn get_relevant_txids(&self) -> Vec<(Txid, Option<BlockHash>)> {
+		let state_lock = self.state.lock().unwrap();
+		let mut relevant_txids = Vec::new();
+		for utxo in state_lock.utxos.iter() {
+			let mut txids = vec![(utxo.outpoint.txid, utxo.confirmation_hash)];
+			if let Some((spend_txid, _, spend_hash)) = utxo.spent_in {
+				txids.push((spend_txid, spend_hash));
+			}
+			for (txid, block_hash) in txids {
+				if !relevant_txids.iter().any(|(relevant_txid, _)| *relevant_txid == txid) {
+					relevant_txids.push((txid, Some(block_hash)));
+				}
+			}
+		}
+		relevant_txids
+	}
+}
+
+impl<'a, F: Deref, K: Deref, L: Deref> ReadableArgs<(&'a KeysManager, Option<F>, K, L)> for SimpleWallet<F, K, L>
+where
+	F::Target: Filter,
+	K::Target: KVStore,
+	L::Target: Logger,
+{
+	#[inline]
+	fn read<R: io::Read>(reader: &mut R, args: (&'a KeysManager, Option<F>, K, L)) -> Result<Self, DecodeError> {
+		let (keys_manager, chain_source, kv_store, logger) = args;
+		let state = WalletState::read(reader)?;
+		Ok(Self::from_state(keys_manager, state, chain_source, kv_store, logger))
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::chain::ClaimId;
+	use crate::events::bump_transaction::{CoinSelectionSource, Wallet};
+	use crate::util::test_utils::{TestChainSource, TestLogger, TestStore};
+
+	use bitcoin::{PackedLockTime, Sequence, TxIn};
+	use bitcoin::blockdata::constants::genesis_block;
+
+	use crate::io::Cursor;
+
+	fn connect_block<S: Listen>(listener: &S, headers: &mut Vec<BlockHeader>, txdata: &[&Transaction]) {
+		let height = headers.len() as u32;
+		let header = BlockHeader {
+			version: 2,
+			prev_blockhash: headers.last().unwrap().block_hash(),
+			merkle_root: bitcoin::TxMerkleNode::all_zeros(),
+			time: height,
+			bits: 42,
+			nonce: 42,
+		};
+		let txdata: Vec<_> = txdata.iter().enumerate().map(|(idx, tx)| (idx + 1, *tx)).collect();
+		listener.filtered_block_connected(&header, &txdata, height);
+		headers.push(header);
+	}
+
+	fn funding_tx(script_pubkey: Script, value: u64) -> Transaction {
+		Transaction {
+			version: 2,
+			lock_time: PackedLockTime::ZERO,
+			input: vec![TxIn { previous_output: OutPoint { txid: Txid::all_zeros(), vout: 42 }, ..Default::default() }],
+			output: vec![TxOut { value, script_pubkey }],
+		}
+	}
+
+	#[test]
+	fn tracks_and_spends_confirmed_utxos() {
+		let network = Network::Testnet;
+		let keys_manager = KeysManager::new(&[42; 32], 42, 42);
+		let chain_source = TestChainSource::new(network);
+		let store = TestStore::new(false);
+		let logger = TestLogger::new();
+
+		let mut headers = vec![genesis_block(network).header];
+		let wallet = SimpleWallet::new(&keys_manager, BestBlock::from_network(network), Some(&chain_source), &store, &logger);
+		assert!(wallet.list_confirmed_utxos().unwrap().is_empty());
+
+		// Outputs paying to handed out scripts, as well as ones within the lookahead, are detected
+		// once confirmed.
+		let first_script = wallet.get_new_script_pubkey();
+		let lookahead_script = wallet.script_pubkey(Keychain::External, SCRIPT_LOOKAHEAD);
+		assert_ne!(first_script, wallet.get_new_script_pubkey());
+		let first_tx = funding_tx(first_script, 100_000);
+		let second_tx = funding_tx(lookahead_script, 50_000);
+		connect_block(&wallet, &mut headers, &[&first_tx, &second_tx]);
+		assert_eq!(wallet.get_confirmed_balance_sat(), 150_000);
+		assert_eq!(wallet.list_confirmed_utxos().unwrap().len(), 2);
+		assert_eq!(chain_source.watched_outputs.lock().unwrap().len(), 2);
+		assert_eq!(wallet.state.lock().unwrap().next_external_index, SCRIPT_LOOKAHEAD + 1);
+
+		// Coin selection via the `Wallet` wrapper yields a transaction we can sign for.
+		let coin_selection_source = Wallet::new(&wallet, &logger);
+		let coin_selection = coin_selection_source.select_confirmed_utxos(ClaimId([42; 32]), Vec::new(), &[], 1000).unwrap();
+		let mut spend_tx = Transaction {
+			version: 2,
+			lock_time: PackedLockTime::ZERO,
+			input: coin_selection.confirmed_utxos.iter().map(|utxo| TxIn {
+				previous_output: utxo.outpoint,
+				sequence: Sequence::ZERO,
+				..Default::default()
+			}).collect(),
+			output: coin_selection.change_output.into_iter().collect(),
+		};
+		spend_tx = coin_selection_source.sign_tx(spend_tx).unwrap();
+		assert_eq!(spend_tx.output.len(), 1);
+		assert!(spend_tx.input.iter().all(|input| input.witness.len() == 2));
+		let spent_utxos: Vec<_> = coin_selection.confirmed_utxos.iter().map(|utxo| (utxo.outpoint, utxo.output.clone())).collect();
+
+		// Once the spend confirms, the change output becomes available while the spent outputs are
+		// only forgotten after ANTI_REORG_DELAY confirmations.
+		assert_eq!(chain_source.watched_txn.lock().unwrap().len(), 1);
+		connect_block(&wallet, &mut headers, &[&spend_tx]);
+		let change_value = spend_tx.output[0].value;
+		assert_eq!(wallet.get_confirmed_balance_sat(), 150_000 - spent_utxos.iter().map(|(_, output)| output.value).sum::<u64>() + change_value);
+		let spend_txid = spend_tx.txid();
+		assert!(wallet.get_relevant_txids().iter().any(|(txid, _)| *txid == spend_txid));
+
+		// A reorg unconfirms the spend.
+		let tip = headers.pop().unwrap();
+		wallet.block_disconnected(&tip, headers.len() as u32);
+		assert_eq!(wallet.get_confirmed_balance_sat(), 150_000);
+
+		connect_block(&wallet, &mut headers, &[&spend_tx]);
+		let num_utxos = wallet.state.lock().unwrap().utxos.len();
+		for _ in 0..ANTI_REORG_DELAY - 1 {
+			connect_block(&wallet, &mut headers, &[]);
+		}
+		assert_eq!(wallet.state.lock().unwrap().utxos.len(), num_utxos - spent_utxos.len());
+
+		// The state survives a restart.
+		let persisted = store.read(WALLET_PERSISTENCE_NAMESPACE, WALLET_PERSISTENCE_SUB_NAMESPACE, WALLET_PERSISTENCE_KEY).unwrap();
+		let read_wallet: SimpleWallet<_, _, _> = ReadableArgs::read(&mut Cursor::new(&persisted),
+			(&keys_manager, Some(&chain_source), &store, &logger)).unwrap();
+		assert_eq!(read_wallet.list_confirmed_utxos().unwrap(), wallet.list_confirmed_utxos().unwrap());
+		assert!(read_wallet.current_best_block() == wallet.current_best_block());
+		assert_eq!(read_wallet.get_new_script_pubkey(), wallet.get_new_script_pubkey());
+	}
+
+	#[test]
+	fn drops_conflicted_unconfirmed_outputs() {
+		let network = Network::Testnet;
+		let keys_manager = KeysManager::new(&[42; 32], 42, 42);
+		let store = TestStore::new(false);
+		let logger = TestLogger::new();
+
+		let mut headers = vec![genesis_block(network).header];
+		let wallet: SimpleWallet<&TestChainSource, _, _> = SimpleWallet::new(&keys_manager, BestBlock::from_network(network), None, &store, &logger);
+		let funding_tx = funding_tx(wallet.get_new_script_pubkey(), 100_000);
+		connect_block(&wallet, &mut headers, &[&funding_tx]);
+		let funding_outpoint = OutPoint { txid: funding_tx.txid(), vout: 0 };
+
+		// Sign a transaction paying change back to us, as well as one spending that change.
+		let spend_tx = |previous_output: OutPoint, script_pubkey: Script| Transaction {
+			version: 2,
+			lock_time: PackedLockTime::ZERO,
+			input: vec![TxIn { previous_output, ..Default::default() }],
+			output: vec![TxOut { value: 90_000, script_pubkey }],
+		};
+		let change_script = wallet.get_change_script().unwrap();
+		let parent_tx = wallet.sign_tx(spend_tx(funding_outpoint, change_script.clone())).unwrap();
+		let child_tx = wallet.sign_tx(spend_tx(OutPoint { txid: parent_tx.txid(), vout: 0 }, change_script)).unwrap();
+		assert_eq!(child_tx.input[0].witness.len(), 2);
+		assert_eq!(wallet.unconfirmed_outputs.lock().unwrap().len(), 2);
+
+		// Once a conflicting transaction confirms, neither output can confirm anymore.
+		let conflicting_tx = spend_tx(funding_outpoint, Script::new());
+		connect_block(&wallet, &mut headers, &[&conflicting_tx]);
+		assert!(wallet.unconfirmed_outputs.lock().unwrap().is_empty());
+		assert_eq!(wallet.get_confirmed_balance_sat(), 0);
+	}
+}
@@ -696,6 +696,53 @@ impl crate::util::ser::Readable for LegacyChannelConfig {
 	}
 }
 
+/// Options for how LDK accounts for the on-chain funds required to get the transactions of anchor
+/// channels confirmed, see [`ChannelManager::get_anchor_channel_reserve_sat`].
+///
+/// As the commitment and HTLC transactions of anchor channels don't carry sufficient fees on their
+/// own, they have to be bumped via CPFP using funds from an on-chain wallet whenever a channel is
+/// force-closed. Failing to do so in time may result in loss of funds.
+///
+/// [`ChannelManager::get_anchor_channel_reserve_sat`]: crate::ln::channelmanager::ChannelManager::get_anchor_channel_reserve_sat
+#[derive(Copy, Clone, Debug)]
+pub struct AnchorChannelReserveConfig {
+	/// The feerate, in satoshis per 1000 weight units, at which we assume the transactions of
+	/// anchor channels will have to be bumped. This should be an upper bound of the feerates
+	/// expected to be required to get a transaction confirmed in time over the lifetime of a
+	/// channel.
+	///
+	/// Default value: 12,500 satoshis per 1000 weight units (i.e., 50 satoshis per vbyte).
+	pub feerate_sat_per_1000_weight: u32,
+	/// The number of HTLCs we assume to be pending on each anchor channel at the time it is
+	/// force-closed, each of which requires its own HTLC transaction to be confirmed. If a channel
+	/// currently has more HTLCs pending, we account for those instead.
+	///
+	/// Default value: 10.
+	pub expected_max_htlcs_per_channel: u16,
+	/// If this is set to true, [`ChannelManager::create_channel`] as well as
+	/// [`ChannelManager::accept_inbound_channel`] and its variants will refuse to open new anchor
+	/// channels if the on-chain funds last reported via
+	/// [`ChannelManager::update_anchor_channel_reserve_funds`] don't cover the reserve required by
+	/// all existing anchor channels plus the new one.
+	///
+	/// Default value: false.
+	///
+	/// [`ChannelManager::create_channel`]: crate::ln::channelmanager::ChannelManager::create_channel
+	/// [`ChannelManager::accept_inbound_channel`]: crate::ln::channelmanager::ChannelManager::accept_inbound_channel
+	/// [`ChannelManager::update_anchor_channel_reserve_funds`]: crate::ln::channelmanager::ChannelManager::update_anchor_channel_reserve_funds
+	pub refuse_channels_without_reserve: bool,
+}
+
+impl Default for AnchorChannelReserveConfig {
+	fn default() -> Self {
+		AnchorChannelReserveConfig {
+			feerate_sat_per_1000_weight: 12_500,
+			expected_max_htlcs_per_channel: 10,
+			refuse_channels_without_reserve: false,
+		}
+	}
+}
+
 /// Top-level config which holds ChannelHandshakeLimits and ChannelConfig.
 ///
 /// Default::default() provides sane defaults for most configurations
@@ -764,6 +811,14 @@ pub struct UserConfig {
 	///
 	/// [`ChannelManager`]: crate::ln::channelmanager::ChannelManager
 	pub accept_mpp_keysend: bool,
+	/// Config which determines the on-chain reserve required for anchor channels.
+	///
+	/// Note that only the [`ChannelManager`]'s default configuration is considered, i.e., this is
+	/// ignored in configs passed to [`ChannelManager::create_channel`].
+	///
+	/// [`ChannelManager`]: crate::ln::channelmanager::ChannelManager
+	/// [`ChannelManager::create_channel`]: crate::ln::channelmanager::ChannelManager::create_channel
+	pub anchor_channel_reserve_config: AnchorChannelReserveConfig,
 }
 
 impl Default for UserConfig {
@@ -777,6 +832,7 @@ impl Default for UserConfig {
 			manually_accept_inbound_channels: false,
 			accept_intercept_htlcs: false,
 			accept_mpp_keysend: false,
+			anchor_channel_reserve_config: AnchorChannelReserveConfig::default(),
 		}
 	}
 }
@@ -19,7 +19,6 @@ pub mod errors;
 pub mod ser;
 pub mod message_signing;
 pub mod invoice;
-pub mod persist;
 pub mod string;
 pub mod wakers;
 
@@ -49,6 +48,8 @@ pub(crate) mod crypto;
 // These have to come after macro_logger to build
 pub mod logger;
 pub mod config;
+pub mod persist;
+pub mod sweep;
 
 #[cfg(any(test, feature = "_test_utils"))]
 pub mod test_utils;
@@ -4,13 +4,16 @@
 // You may not use this file except in accordance with one or both of these
 // licenses.
 
-//! This module contains a simple key-value store trait KVStorePersister that
+//! This module contains a simple key-value store trait [`KVStore`] that
 //! allows one to implement the persistence for [`ChannelManager`], [`NetworkGraph`],
 //! and [`ChannelMonitor`] all in one place.
 
 use core::ops::Deref;
-use bitcoin::hashes::hex::ToHex;
+use bitcoin::hashes::hex::{FromHex, ToHex};
+use bitcoin::{BlockHash, Txid};
+
 use crate::io;
+use crate::prelude::{Vec, String};
 use crate::routing::scoring::WriteableScore;
 
 use crate::chain;
@@ -18,19 +21,132 @@ use crate::chain::chaininterface::{BroadcasterInterface, FeeEstimator};
 use crate::chain::chainmonitor::{Persist, MonitorUpdateId};
 use crate::sign::{EntropySource, NodeSigner, WriteableEcdsaChannelSigner, SignerProvider};
 use crate::chain::transaction::OutPoint;
-use crate::chain::channelmonitor::{ChannelMonitor, ChannelMonitorUpdate};
+use crate::chain::channelmonitor::{ChannelMonitor, ChannelMonitorUpdate, CLOSED_CHANNEL_UPDATE_ID};
 use crate::ln::channelmanager::ChannelManager;
 use crate::routing::router::Router;
 use crate::routing::gossip::NetworkGraph;
-use super::{logger::Logger, ser::Writeable};
-
-/// Trait for a key-value store for persisting some writeable object at some key
-/// Implementing `KVStorePersister` provides auto-implementations for [`Persister`]
-/// and [`Persist`] traits.  It uses "manager", "network_graph",
-/// and "monitors/{funding_txo_id}_{funding_txo_index}" for keys.
-pub trait KVStorePersister {
-	/// Persist the given writeable using the provided key
-	fn persist<W: Writeable>(&self, key: &str, object: &W) -> io::Result<()>;
+use crate::util::logger::Logger;
+use crate::util::ser::{Readable, ReadableArgs, Writeable};
+
+/// The alphabet of characters allowed for namespaces and keys.
+pub const KVSTORE_NAMESPACE_KEY_ALPHABET: &str = "abcdefghijklmnopqrstuvwxyzABCDEFGHIJKLMNOPQRSTUVWXYZ0123456789_-";
+
+/// The maximum number of characters namespaces and keys may have.
+pub const KVSTORE_NAMESPACE_KEY_MAX_LEN: usize = 120;
+
+/// The namespace under which the [`ChannelManager`] will be persisted.
+pub const CHANNEL_MANAGER_PERSISTENCE_NAMESPACE: &str = "";
+/// The sub-namespace under which the [`ChannelManager`] will be persisted.
+pub const CHANNEL_MANAGER_PERSISTENCE_SUB_NAMESPACE: &str = "";
+/// The key under which the [`ChannelManager`] will be persisted.
+pub const CHANNEL_MANAGER_PERSISTENCE_KEY: &str = "manager";
+
+/// The namespace under which [`ChannelMonitor`]s will be persisted.
+pub const CHANNEL_MONITOR_PERSISTENCE_NAMESPACE: &str = "monitors";
+/// The sub-namespace under which [`ChannelMonitor`]s will be persisted.
+pub const CHANNEL_MONITOR_PERSISTENCE_SUB_NAMESPACE: &str = "";
+/// The namespace under which [`ChannelMonitorUpdate`]s will be persisted by the
+/// [`MonitorUpdatingPersister`]. The sub-namespace is the key of the respective [`ChannelMonitor`].
+pub const CHANNEL_MONITOR_UPDATE_PERSISTENCE_NAMESPACE: &str = "monitor_updates";
+
+/// The namespace under which the [`NetworkGraph`] will be persisted.
+pub const NETWORK_GRAPH_PERSISTENCE_NAMESPACE: &str = "";
+/// The sub-namespace under which the [`NetworkGraph`] will be persisted.
+pub const NETWORK_GRAPH_PERSISTENCE_SUB_NAMESPACE: &str = "";
+/// The key under which the [`NetworkGraph`] will be persisted.
+pub const NETWORK_GRAPH_PERSISTENCE_KEY: &str = "network_graph";
+
+/// The namespace under which the [`WriteableScore`] will be persisted.
+pub const SCORER_PERSISTENCE_NAMESPACE: &str = "";
+/// The sub-namespace under which the [`WriteableScore`] will be persisted.
+pub const SCORER_PERSISTENCE_SUB_NAMESPACE: &str = "";
+/// The key under which the [`WriteableScore`] will be persisted.
+pub const SCORER_PERSISTENCE_KEY: &str = "scorer";
+
+/// The namespace under which the [`OutputSweeper`] state will be persisted.
+///
+/// [`OutputSweeper`]: crate::util::sweep::OutputSweeper
+pub const OUTPUT_SWEEPER_PERSISTENCE_NAMESPACE: &str = "";
+/// The sub-namespace under which the [`OutputSweeper`] state will be persisted.
+///
+/// [`OutputSweeper`]: crate::util::sweep::OutputSweeper
+pub const OUTPUT_SWEEPER_PERSISTENCE_SUB_NAMESPACE: &str = "";
+/// The key under which the [`OutputSweeper`] state will be persisted.
+///
+/// [`OutputSweeper`]: crate::util::sweep::OutputSweeper
+pub const OUTPUT_SWEEPER_PERSISTENCE_KEY: &str = "output_sweeper";
+
+/// The namespace under which the [`SimpleWallet`] state will be persisted.
+///
+/// [`SimpleWallet`]: crate::sign::wallet::SimpleWallet
+pub const WALLET_PERSISTENCE_NAMESPACE: &str = "";
+/// The sub-namespace under which the [`SimpleWallet`] state will be persisted.
+///
+/// [`SimpleWallet`]: crate::sign::wallet::SimpleWallet
+pub const WALLET_PERSISTENCE_SUB_NAMESPACE: &str = "";
+/// The key under which the [`SimpleWallet`] state will be persisted.
+///
+/// [`SimpleWallet`]: crate::sign::wallet::SimpleWallet
+pub const WALLET_PERSISTENCE_KEY: &str = "wallet";
+
+/// A sentinel value to be prepended to monitors persisted by the [`MonitorUpdatingPersister`].
+///
+/// This serves to prevent someone from accidentally loading such monitors (which may need
+/// updates applied to be current) with another implementation.
+pub const MONITOR_UPDATING_PERSISTER_PREPEND_SENTINEL: &[u8] = &[0xFF; 2];
+
+/// Provides an interface that allows storage and retrieval of persisted values that are associated
+/// with given keys.
+///
+/// In order to avoid collisions the key space is segmented based on the given `namespace`s and
+/// `sub_namespace`s. Implementations of this trait are free to handle them in different ways, as
+/// long as per-namespace key uniqueness is asserted.
+///
+/// Keys and namespaces are required to be valid ASCII strings in the range of
+/// [`KVSTORE_NAMESPACE_KEY_ALPHABET`] and no longer than [`KVSTORE_NAMESPACE_KEY_MAX_LEN`]. Empty
+/// namespaces and sub-namespaces (`""`) are assumed to be a valid, however, if `namespace` is
+/// empty, `sub_namespace` is required to be empty, too. This means that concerns should always be
+/// separated by namespace first, before sub-namespaces are used. While the number of namespaces
+/// will be relatively small and is determined at compile time, there may be many sub-namespaces
+/// per namespace. Note that per-namespace uniqueness needs to also hold for keys *and*
+/// namespaces/sub-namespaces in any given namespace/sub-namespace, i.e., conflicts between keys
+/// and equally named namespaces/sub-namespaces must be avoided.
+///
+/// **Note:** Users migrating custom persistence backends from the pre-v0.0.117 `KVStorePersister`
+/// interface can use a concatenation of `[{namespace}/[{sub_namespace}/]]{key}` to recover a `key`
+/// compatible with the data model previously assumed by `KVStorePersister::persist`.
+pub trait KVStore {
+	/// Returns the data stored for the given `namespace`, `sub_namespace`, and `key`.
+	///
+	/// Returns an [`ErrorKind::NotFound`] if the given `key` could not be found in the given
+	/// `namespace` and `sub_namespace`.
+	///
+	/// [`ErrorKind::NotFound`]: io::ErrorKind::NotFound
+	fn read(&self, namespace: &str, sub_namespace: &str, key: &str) -> io::Result<Vec<u8>>;
+	/// Persists the given data under the given `key`.
+	///
+	/// Will create the given `namespace` and `sub_namespace` if not already present in the store.
+	fn write(&self, namespace: &str, sub_namespace: &str, key: &str, buf: &[u8]) -> io::Result<()>;
+	/// Removes any data that had previously been persisted under the given `key`.
+	///
+	/// If the `lazy` flag is set to `true`, the backend implementation might choose to lazily
+	/// remove the given `key` at some point in time after the method returns, e.g., as part of an
+	/// eventual batch deletion of multiple keys. As a consequence, subsequent calls to
+	/// [`KVStore::list`] might include the removed key until the changes are actually persisted.
+	///
+	/// Note that while setting the `lazy` flag reduces the I/O burden of multiple subsequent
+	/// `remove` calls, it also influences the atomicity guarantees as lazy `remove`s could
+	/// potentially get lost on crash after the method returns. Therefore, this flag should only be
+	/// set for `remove` operations that can be safely replayed at a later time.
+	///
+	/// Returns successfully if no data will be stored for the given `namespace`, `sub_namespace`, and
+	/// `key`, independently of whether it was present before its invokation or not.
+	fn remove(&self, namespace: &str, sub_namespace: &str, key: &str, lazy: bool) -> io::Result<()>;
+	/// Returns a list of keys that are stored under the given `sub_namespace` in `namespace`.
+	///
+	/// Returns the keys in arbitrary order, so users requiring a particular order need to sort the
+	/// returned keys. Returns an empty list if `namespace` or `sub_namespace` is unknown.
+	fn list(&self, namespace: &str, sub_namespace: &str) -> io::Result<Vec<String>>;
 }
 
 /// Trait that handles persisting a [`ChannelManager`], [`NetworkGraph`], and [`WriteableScore`] to disk.
@@ -54,7 +170,7 @@ pub trait Persister<'a, M: Deref, T: Deref, ES: Deref, NS: Deref, SP: Deref, F:
 	fn persist_scorer(&self, scorer: &S) -> Result<(), io::Error>;
 }
 
-impl<'a, A: KVStorePersister, M: Deref, T: Deref, ES: Deref, NS: Deref, SP: Deref, F: Deref, R: Deref, L: Deref, S: WriteableScore<'a>> Persister<'a, M, T, ES, NS, SP, F, R, L, S> for A
+impl<'a, A: KVStore, M: Deref, T: Deref, ES: Deref, NS: Deref, SP: Deref, F: Deref, R: Deref, L: Deref, S: WriteableScore<'a>> Persister<'a, M, T, ES, NS, SP, F, R, L, S> for A
 	where M::Target: 'static + chain::Watch<<SP::Target as SignerProvider>::Signer>,
 		T::Target: 'static + BroadcasterInterface,
 		ES::Target: 'static + EntropySource,
@@ -64,41 +180,573 @@ impl<'a, A: KVStorePersister, M: Deref, T: Deref, ES: Deref, NS: Deref, SP: Dere
 		R::Target: 'static + Router,
 		L::Target: 'static + Logger,
 {
-	/// Persist the given ['ChannelManager'] to disk with the name "manager", returning an error if persistence failed.
+	/// Persist the given [`ChannelManager`] to disk, returning an error if persistence failed.
 	fn persist_manager(&self, channel_manager: &ChannelManager<M, T, ES, NS, SP, F, R, L>) -> Result<(), io::Error> {
-		self.persist("manager", channel_manager)
+		self.write(CHANNEL_MANAGER_PERSISTENCE_NAMESPACE,
+			CHANNEL_MANAGER_PERSISTENCE_SUB_NAMESPACE,
+			CHANNEL_MANAGER_PERSISTENCE_KEY,
+			&channel_manager.encode())
 	}
 
-	/// Persist the given [`NetworkGraph`] to disk with the name "network_graph", returning an error if persistence failed.
+	/// Persist the given [`NetworkGraph`] to disk, returning an error if persistence failed.
 	fn persist_graph(&self, network_graph: &NetworkGraph<L>) -> Result<(), io::Error> {
-		self.persist("network_graph", network_graph)
+		self.write(NETWORK_GRAPH_PERSISTENCE_NAMESPACE,
+			NETWORK_GRAPH_PERSISTENCE_SUB_NAMESPACE,
+			NETWORK_GRAPH_PERSISTENCE_KEY,
+			&network_graph.encode())
 	}
 
-	/// Persist the given [`WriteableScore`] to disk with name "scorer", returning an error if persistence failed.
+	/// Persist the given [`WriteableScore`] to disk, returning an error if persistence failed.
 	fn persist_scorer(&self, scorer: &S) -> Result<(), io::Error> {
-		self.persist("scorer", &scorer)
+		self.write(SCORER_PERSISTENCE_NAMESPACE,
+			SCORER_PERSISTENCE_SUB_NAMESPACE,
+			SCORER_PERSISTENCE_KEY,
+			&scorer.encode())
 	}
 }
 
-impl<ChannelSigner: WriteableEcdsaChannelSigner, K: KVStorePersister> Persist<ChannelSigner> for K {
+/// Returns the key under which the [`ChannelMonitor`] for the given funding outpoint is stored.
+fn monitor_key(funding_txo: &OutPoint) -> String {
+	format!("{}_{}", funding_txo.txid.to_hex(), funding_txo.index)
+}
+
+/// Parses a key created by [`monitor_key`] back into the funding outpoint.
+fn monitor_key_to_outpoint(key: &str) -> io::Result<OutPoint> {
+	if key.len() < 66 {
+		return Err(io::Error::new(
+			io::ErrorKind::InvalidData,
+			"Stored key has invalid length"));
+	}
+
+	let txid = Txid::from_hex(key.split_at(64).0).map_err(|_| {
+		io::Error::new(io::ErrorKind::InvalidData, "Invalid tx ID in stored key")
+	})?;
+
+	let index: u16 = key.split_at(65).1.parse().map_err(|_| {
+		io::Error::new(io::ErrorKind::InvalidData, "Invalid tx index in stored key")
+	})?;
+
+	Ok(OutPoint { txid, index })
+}
+
+impl<ChannelSigner: WriteableEcdsaChannelSigner, K: KVStore> Persist<ChannelSigner> for K {
 	// TODO: We really need a way for the persister to inform the user that its time to crash/shut
 	// down once these start returning failure.
 	// A PermanentFailure implies we should probably just shut down the node since we're
 	// force-closing channels without even broadcasting!
 
 	fn persist_new_channel(&self, funding_txo: OutPoint, monitor: &ChannelMonitor<ChannelSigner>, _update_id: MonitorUpdateId) -> chain::ChannelMonitorUpdateStatus {
-		let key = format!("monitors/{}_{}", funding_txo.txid.to_hex(), funding_txo.index);
-		match self.persist(&key, monitor) {
+		let key = monitor_key(&funding_txo);
+		match self.write(
+			CHANNEL_MONITOR_PERSISTENCE_NAMESPACE,
+			CHANNEL_MONITOR_PERSISTENCE_SUB_NAMESPACE,
+			&key, &monitor.encode())
+		{
 			Ok(()) => chain::ChannelMonitorUpdateStatus::Completed,
 			Err(_) => chain::ChannelMonitorUpdateStatus::PermanentFailure,
 		}
 	}
 
 	fn update_persisted_channel(&self, funding_txo: OutPoint, _update: Option<&ChannelMonitorUpdate>, monitor: &ChannelMonitor<ChannelSigner>, _update_id: MonitorUpdateId) -> chain::ChannelMonitorUpdateStatus {
-		let key = format!("monitors/{}_{}", funding_txo.txid.to_hex(), funding_txo.index);
-		match self.persist(&key, monitor) {
+		let key = monitor_key(&funding_txo);
+		match self.write(
+			CHANNEL_MONITOR_PERSISTENCE_NAMESPACE,
+			CHANNEL_MONITOR_PERSISTENCE_SUB_NAMESPACE,
+			&key, &monitor.encode())
+		{
 			Ok(()) => chain::ChannelMonitorUpdateStatus::Completed,
 			Err(_) => chain::ChannelMonitorUpdateStatus::PermanentFailure,
 		}
 	}
 }
+
+/// Read previously persisted [`ChannelMonitor`]s from the store.
+pub fn read_channel_monitors<K: Deref, ES: Deref, SP: Deref>(
+	kv_store: K, entropy_source: ES, signer_provider: SP,
+) -> io::Result<Vec<(BlockHash, ChannelMonitor<<SP::Target as SignerProvider>::Signer>)>>
+where
+	K::Target: KVStore,
+	ES::Target: EntropySource + Sized,
+	SP::Target: SignerProvider + Sized
+{
+	let mut res = Vec::new();
+
+	for stored_key in kv_store.list(
+		CHANNEL_MONITOR_PERSISTENCE_NAMESPACE, CHANNEL_MONITOR_PERSISTENCE_SUB_NAMESPACE)?
+	{
+		let funding_txo = monitor_key_to_outpoint(&stored_key)?;
+
+		match <(BlockHash, ChannelMonitor<<SP::Target as SignerProvider>::Signer>)>::read(
+			&mut io::Cursor::new(
+				kv_store.read(CHANNEL_MONITOR_PERSISTENCE_NAMESPACE, CHANNEL_MONITOR_PERSISTENCE_SUB_NAMESPACE, &stored_key)?),
+			(&*entropy_source, &*signer_provider),
+		) {
+			Ok((block_hash, channel_monitor)) => {
+				if channel_monitor.get_funding_txo().0 != funding_txo {
+					return Err(io::Error::new(
+						io::ErrorKind::InvalidData,
+						"ChannelMonitor was stored under the wrong key",
+					));
+				}
+				res.push((block_hash, channel_monitor));
+			}
+			Err(_) => {
+				return Err(io::Error::new(
+					io::ErrorKind::InvalidData,
+					"Failed to deserialize ChannelMonitor"
+				))
+			}
+		}
+	}
+	Ok(res)
+}
+
+/// Implements [`Persist`] in a way that writes and reads both [`ChannelMonitor`]s and
+/// [`ChannelMonitorUpdate`]s.
+///
+/// The [`Persist`] implementation provided for any [`KVStore`] rewrites the full
+/// [`ChannelMonitor`] on every update. For busy channels, monitors may be megabytes in size while
+/// each [`ChannelMonitorUpdate`] is a few hundred bytes at most, so this persister instead writes
+/// each update under its own key and only writes a full monitor every `maximum_pending_updates`
+/// updates, when the channel is closed, or when chain data is synced.
+///
+/// # Storage layout
+///
+/// Full [`ChannelMonitor`]s are stored in the [`CHANNEL_MONITOR_PERSISTENCE_NAMESPACE`] under the
+/// same key as used by the [`KVStore`]'s [`Persist`] implementation, i.e., the hex-encoded funding
+/// transaction ID followed by `_` and the output index. Each [`ChannelMonitorUpdate`] is stored in
+/// the [`CHANNEL_MONITOR_UPDATE_PERSISTENCE_NAMESPACE`], using the monitor's key as the
+/// sub-namespace and the decimal `update_id` as the key.
+///
+/// Monitors written by this persister are prefixed with
+/// [`MONITOR_UPDATING_PERSISTER_PREPEND_SENTINEL`] to avoid them being loaded via
+/// [`read_channel_monitors`], which would ignore any pending updates. Monitors written by the
+/// [`KVStore`]'s [`Persist`] implementation can be read by this persister, however.
+///
+/// # Reading channel state
+///
+/// On startup, channel state must be loaded via
+/// [`MonitorUpdatingPersister::read_all_channel_monitors_with_updates`], which applies any pending
+/// updates to each stored monitor.
+///
+/// Note that pending updates are found by reading consecutive `update_id`s until a key is missing,
+/// so it is crucial that [`KVStore::read`] returns an [`io::ErrorKind::NotFound`] error if and only
+/// if the key does not exist.
+///
+/// # Pruning stale updates
+///
+/// Whenever a full monitor is written in response to a [`ChannelMonitorUpdate`], any updates it
+/// already includes are removed lazily. Stale updates are never applied on read, however, so they
+/// only waste space. Updates left behind otherwise, e.g., after a crash, can be removed via
+/// [`MonitorUpdatingPersister::cleanup_stale_updates`].
+pub struct MonitorUpdatingPersister<K: Deref, L: Deref, ES: Deref, SP: Deref>
+where
+	K::Target: KVStore,
+	L::Target: Logger,
+	ES::Target: EntropySource + Sized,
+	SP::Target: SignerProvider + Sized,
+{
+	kv_store: K,
+	logger: L,
+	maximum_pending_updates: u64,
+	entropy_source: ES,
+	signer_provider: SP,
+}
+
+impl<K: Deref, L: Deref, ES: Deref, SP: Deref> MonitorUpdatingPersister<K, L, ES, SP>
+where
+	K::Target: KVStore,
+	L::Target: Logger,
+	ES::Target: EntropySource + Sized,
+	SP::Target: SignerProvider + Sized,
+{
+	/// Constructs a new [`MonitorUpdatingPersister`].
+	///
+	/// `maximum_pending_updates` is the number of [`ChannelMonitorUpdate`]s after which the full
+	/// [`ChannelMonitor`] is written again, bounding both the number of updates stored per channel
+	/// and the work required to replay them on startup. A value of `0` causes the full monitor to
+	/// be written on every update.
+	pub fn new(
+		kv_store: K, logger: L, maximum_pending_updates: u64, entropy_source: ES,
+		signer_provider: SP,
+	) -> Self {
+		MonitorUpdatingPersister {
+			kv_store,
+			logger,
+			maximum_pending_updates,
+			entropy_source,
+			signer_provider,
+		}
+	}
+
+	/// Reads all stored [`ChannelMonitor`]s and applies any pending [`ChannelMonitorUpdate`]s to
+	/// them.
+	///
+	/// The broadcaster and fee estimator are required as applying updates may result in
+	/// transactions being (re-)broadcast.
+	pub fn read_all_channel_monitors_with_updates<B: Deref, F: Deref>(
+		&self, broadcaster: &B, fee_estimator: &F,
+	) -> io::Result<Vec<(BlockHash, ChannelMonitor<<SP::Target as SignerProvider>::Signer>)>>
+	where
+		B::Target: BroadcasterInterface,
+		F::Target: FeeEstimator,
+	{
+		let monitor_keys = self.kv_store.list(
+			CHANNEL_MONITOR_PERSISTENCE_NAMESPACE, CHANNEL_MONITOR_PERSISTENCE_SUB_NAMESPACE)?;
+		let mut res = Vec::with_capacity(monitor_keys.len());
+		for monitor_key in monitor_keys {
+			res.push(self.read_channel_monitor_with_updates(broadcaster, fee_estimator, &monitor_key)?);
+		}
+		Ok(res)
+	}
+
+	/// Reads the [`ChannelMonitor`] stored under the given key and applies any pending
+	/// [`ChannelMonitorUpdate`]s to it.
+	///
+	/// The key is the one returned by [`KVStore::list`] for the
+	/// [`CHANNEL_MONITOR_PERSISTENCE_NAMESPACE`], i.e., the hex-encoded funding transaction ID
+	/// followed by `_` and the output index.
+	pub fn read_channel_monitor_with_updates<B: Deref, F: Deref>(
+		&self, broadcaster: &B, fee_estimator: &F, monitor_key: &str,
+	) -> io::Result<(BlockHash, ChannelMonitor<<SP::Target as SignerProvider>::Signer>)>
+	where
+		B::Target: BroadcasterInterface,
+		F::Target: FeeEstimator,
+	{
+		let (block_hash, monitor) = self.read_monitor(monitor_key)?;
+		let mut current_update_id = monitor.get_latest_update_id();
+		while let Some(update_id) = current_update_id.checked_add(1) {
+			let update_key = update_id.to_string();
+			let update = match self.read_monitor_update(monitor_key, &update_key) {
+				Ok(update) => update,
+				Err(e) if e.kind() == io::ErrorKind::NotFound => break,
+				Err(e) => return Err(e),
+			};
+			monitor.update_monitor(&update, broadcaster, &**fee_estimator, &self.logger).map_err(|_| {
+				log_error!(self.logger, "Failed to apply ChannelMonitorUpdate {} to ChannelMonitor {}",
+					update_key, monitor_key);
+				io::Error::new(io::ErrorKind::Other, "Failed to apply ChannelMonitorUpdate")
+			})?;
+			current_update_id = update_id;
+		}
+		Ok((block_hash, monitor))
+	}
+
+	/// Removes all [`ChannelMonitorUpdate`]s which were already applied to the respective stored
+	/// [`ChannelMonitor`].
+	///
+	/// This is done automatically whenever a full monitor is written in response to an update, so
+	/// it only needs to be called to clean up after a crash or if `lazy` removals were lost.
+	pub fn cleanup_stale_updates(&self, lazy: bool) -> io::Result<()> {
+		let monitor_keys = self.kv_store.list(
+			CHANNEL_MONITOR_PERSISTENCE_NAMESPACE, CHANNEL_MONITOR_PERSISTENCE_SUB_NAMESPACE)?;
+		for monitor_key in monitor_keys {
+			let (_, monitor) = self.read_monitor(&monitor_key)?;
+			self.remove_stale_updates(&monitor_key, monitor.get_latest_update_id(), lazy)?;
+		}
+		Ok(())
+	}
+
+	fn read_monitor(
+		&self, monitor_key: &str,
+	) -> io::Result<(BlockHash, ChannelMonitor<<SP::Target as SignerProvider>::Signer>)> {
+		let funding_txo = monitor_key_to_outpoint(monitor_key)?;
+		let mut monitor_cursor = io::Cursor::new(self.kv_store.read(
+			CHANNEL_MONITOR_PERSISTENCE_NAMESPACE, CHANNEL_MONITOR_PERSISTENCE_SUB_NAMESPACE,
+			monitor_key)?);
+		// Skip the sentinel if present, monitors written by other persisters are read as-is.
+		if monitor_cursor.get_ref().starts_with(MONITOR_UPDATING_PERSISTER_PREPEND_SENTINEL) {
+			monitor_cursor.set_position(MONITOR_UPDATING_PERSISTER_PREPEND_SENTINEL.len() as u64);
+		}
+		match <(BlockHash, ChannelMonitor<<SP::Target as SignerProvider>::Signer>)>::read(
+			&mut monitor_cursor, (&*self.entropy_source, &*self.signer_provider)
+		) {
+			Ok((block_hash, monitor)) => {
+				if monitor.get_funding_txo().0 != funding_txo {
+					log_error!(self.logger, "ChannelMonitor {} was stored under the wrong key", monitor_key);
+					return Err(io::Error::new(
+						io::ErrorKind::InvalidData,
+						"ChannelMonitor was stored under the wrong key"));
+				}
+				Ok((block_hash, monitor))
+			},
+			Err(e) => {
+				log_error!(self.logger, "Failed to read ChannelMonitor {}: {:?}", monitor_key, e);
+				Err(io::Error::new(io::ErrorKind::InvalidData, "Failed to deserialize ChannelMonitor"))
+			},
+		}
+	}
+
+	fn read_monitor_update(&self, monitor_key: &str, update_key: &str) -> io::Result<ChannelMonitorUpdate> {
+		let update_bytes = self.kv_store.read(
+			CHANNEL_MONITOR_UPDATE_PERSISTENCE_NAMESPACE, monitor_key, update_key)?;
+		ChannelMonitorUpdate::read(&mut io::Cursor::new(update_bytes)).map_err(|e| {
+			log_error!(self.logger, "Failed to read ChannelMonitorUpdate {}/{}: {:?}",
+				monitor_key, update_key, e);
+			io::Error::new(io::ErrorKind::InvalidData, "Failed to deserialize ChannelMonitorUpdate")
+		})
+	}
+
+	fn remove_stale_updates(&self, monitor_key: &str, latest_update_id: u64, lazy: bool) -> io::Result<()> {
+		for update_key in self.kv_store.list(CHANNEL_MONITOR_UPDATE_PERSISTENCE_NAMESPACE, monitor_key)? {
+			let update_id: u64 = update_key.parse().map_err(|_| {
+				io::Error::new(io::ErrorKind::InvalidData, "Invalid ChannelMonitorUpdate key")
+			})?;
+			if update_id <= latest_update_id {
+				self.kv_store.remove(CHANNEL_MONITOR_UPDATE_PERSISTENCE_NAMESPACE, monitor_key,
+					&update_key, lazy)?;
+			}
+		}
+		Ok(())
+	}
+}
+
+impl<ChannelSigner: WriteableEcdsaChannelSigner, K: Deref, L: Deref, ES: Deref, SP: Deref>
+	Persist<ChannelSigner> for MonitorUpdatingPersister<K, L, ES, SP>
+where
+	K::Target: KVStore,
+	L::Target: Logger,
+	ES::Target: EntropySource + Sized,
+	SP::Target: SignerProvider + Sized,
+{
+	fn persist_new_channel(&self, funding_txo: OutPoint, monitor: &ChannelMonitor<ChannelSigner>, _update_id: MonitorUpdateId) -> chain::ChannelMonitorUpdateStatus {
+		let key = monitor_key(&funding_txo);
+		let mut monitor_bytes = Vec::with_capacity(
+			MONITOR_UPDATING_PERSISTER_PREPEND_SENTINEL.len() + monitor.serialized_length());
+		monitor_bytes.extend_from_slice(MONITOR_UPDATING_PERSISTER_PREPEND_SENTINEL);
+		monitor.write(&mut monitor_bytes).unwrap();
+		match self.kv_store.write(
+			CHANNEL_MONITOR_PERSISTENCE_NAMESPACE,
+			CHANNEL_MONITOR_PERSISTENCE_SUB_NAMESPACE,
+			&key, &monitor_bytes)
+		{
+			Ok(()) => chain::ChannelMonitorUpdateStatus::Completed,
+			Err(e) => {
+				log_error!(self.logger, "Failed to write ChannelMonitor {}: {}", key, e);
+				chain::ChannelMonitorUpdateStatus::PermanentFailure
+			},
+		}
+	}
+
+	fn update_persisted_channel(&self, funding_txo: OutPoint, update: Option<&ChannelMonitorUpdate>, monitor: &ChannelMonitor<ChannelSigner>, update_id: MonitorUpdateId) -> chain::ChannelMonitorUpdateStatus {
+		let key = monitor_key(&funding_txo);
+		if let Some(update) = update {
+			let persist_update = update.update_id != CLOSED_CHANNEL_UPDATE_ID
+				&& self.maximum_pending_updates != 0
+				&& update.update_id % self.maximum_pending_updates != 0;
+			if persist_update {
+				let update_key = update.update_id.to_string();
+				return match self.kv_store.write(
+					CHANNEL_MONITOR_UPDATE_PERSISTENCE_NAMESPACE, &key, &update_key, &update.encode())
+				{
+					Ok(()) => chain::ChannelMonitorUpdateStatus::Completed,
+					Err(e) => {
+						log_error!(self.logger, "Failed to write ChannelMonitorUpdate {}/{}: {}", key, update_key, e);
+						chain::ChannelMonitorUpdateStatus::PermanentFailure
+					},
+				};
+			}
+		}
+
+		// Either a consolidation is due, the channel was closed, or we're persisting after chain
+		// data was synced, so write the full monitor.
+		let res = self.persist_new_channel(funding_txo, monitor, update_id);
+		// Updates already included in the written monitor are never read again. We only bother
+		// removing them if we were given an update, as chain syncs happen for every block and any
+		// leftovers will be removed on the next consolidation.
+		if update.is_some() && res == chain::ChannelMonitorUpdateStatus::Completed {
+			if let Err(e) = self.remove_stale_updates(&key, monitor.get_latest_update_id(), true) {
+				log_error!(self.logger, "Failed to remove stale ChannelMonitorUpdates for {}: {}", key, e);
+			}
+		}
+		res
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::chain::chainmonitor::Persist;
+	use crate::events::ClosureReason;
+	use crate::ln::functional_test_utils::*;
+	use crate::sync::Mutex;
+	use crate::util::test_utils::{self, TestStore};
+	use crate::{check_added_monitors, check_closed_broadcast, check_closed_event};
+	use bitcoin::network::constants::Network;
+
+	#[test]
+	fn persists_and_reads_channel_monitors() {
+		let chanmon_cfgs = create_chanmon_cfgs(2);
+		let node_cfgs = create_node_cfgs(2, &chanmon_cfgs);
+		let node_chanmgrs = create_node_chanmgrs(2, &node_cfgs, &[None, None]);
+		let nodes = create_network(2, &node_cfgs, &node_chanmgrs);
+		let (_, _, _, tx) = create_announced_chan_between_nodes(&nodes, 0, 1);
+		let funding_txo = OutPoint { txid: tx.txid(), index: 0 };
+
+		let store = TestStore::new(false);
+		assert!(read_channel_monitors(&store, nodes[0].keys_manager, nodes[0].keys_manager).unwrap().is_empty());
+
+		{
+			let monitor = nodes[0].chain_monitor.chain_monitor.get_monitor(funding_txo).unwrap();
+			let update_id = MonitorUpdateId::from_new_monitor(&*monitor);
+			assert_eq!(store.persist_new_channel(funding_txo, &*monitor, update_id),
+				chain::ChannelMonitorUpdateStatus::Completed);
+		}
+		let expected_key = format!("{}_{}", funding_txo.txid.to_hex(), funding_txo.index);
+		assert_eq!(store.list(CHANNEL_MONITOR_PERSISTENCE_NAMESPACE, CHANNEL_MONITOR_PERSISTENCE_SUB_NAMESPACE).unwrap(),
+			vec![expected_key.clone()]);
+
+		let read_monitors = read_channel_monitors(&store, nodes[0].keys_manager, nodes[0].keys_manager).unwrap();
+		assert_eq!(read_monitors.len(), 1);
+		assert_eq!(read_monitors[0].1.get_funding_txo().0, funding_txo);
+
+		// A monitor stored under a key which doesn't match its funding outpoint must be rejected.
+		let monitor_bytes = store.read(CHANNEL_MONITOR_PERSISTENCE_NAMESPACE,
+			CHANNEL_MONITOR_PERSISTENCE_SUB_NAMESPACE, &expected_key).unwrap();
+		let wrong_key = format!("{}_{}", funding_txo.txid.to_hex(), funding_txo.index + 1);
+		store.write(CHANNEL_MONITOR_PERSISTENCE_NAMESPACE, CHANNEL_MONITOR_PERSISTENCE_SUB_NAMESPACE,
+			&wrong_key, &monitor_bytes).unwrap();
+		assert!(read_channel_monitors(&store, nodes[0].keys_manager, nodes[0].keys_manager).is_err());
+
+		store.remove(CHANNEL_MONITOR_PERSISTENCE_NAMESPACE, CHANNEL_MONITOR_PERSISTENCE_SUB_NAMESPACE,
+			&wrong_key, false).unwrap();
+		assert_eq!(read_channel_monitors(&store, nodes[0].keys_manager, nodes[0].keys_manager).unwrap().len(), 1);
+	}
+
+	#[test]
+	fn read_only_store_fails_monitor_persistence() {
+		let chanmon_cfgs = create_chanmon_cfgs(2);
+		let node_cfgs = create_node_cfgs(2, &chanmon_cfgs);
+		let node_chanmgrs = create_node_chanmgrs(2, &node_cfgs, &[None, None]);
+		let nodes = create_network(2, &node_cfgs, &node_chanmgrs);
+		let (_, _, _, tx) = create_announced_chan_between_nodes(&nodes, 0, 1);
+		let funding_txo = OutPoint { txid: tx.txid(), index: 0 };
+
+		let store = TestStore::new(true);
+		let monitor = nodes[0].chain_monitor.chain_monitor.get_monitor(funding_txo).unwrap();
+		let update_id = MonitorUpdateId::from_new_monitor(&*monitor);
+		assert_eq!(store.persist_new_channel(funding_txo, &*monitor, update_id),
+			chain::ChannelMonitorUpdateStatus::PermanentFailure);
+		assert_eq!(store.update_persisted_channel(funding_txo, None, &*monitor, update_id),
+			chain::ChannelMonitorUpdateStatus::PermanentFailure);
+	}
+
+	// Integration-test the MonitorUpdatingPersister. Relay a few payments and check that updates are
+	// written individually, consolidated into full monitors and replayed when reading.
+	#[test]
+	fn monitor_updating_persister_with_real_monitors() {
+		let max_pending_updates_0 = 7;
+		let max_pending_updates_1 = 3;
+		let chanmon_cfgs = create_chanmon_cfgs(2);
+		let store_0 = TestStore::new(false);
+		let store_1 = TestStore::new(false);
+		let persister_0 = MonitorUpdatingPersister::new(&store_0, &chanmon_cfgs[0].logger,
+			max_pending_updates_0, &chanmon_cfgs[0].keys_manager, &chanmon_cfgs[0].keys_manager);
+		let persister_1 = MonitorUpdatingPersister::new(&store_1, &chanmon_cfgs[1].logger,
+			max_pending_updates_1, &chanmon_cfgs[1].keys_manager, &chanmon_cfgs[1].keys_manager);
+		let mut node_cfgs = create_node_cfgs(2, &chanmon_cfgs);
+		let chain_mon_0 = test_utils::TestChainMonitor::new(Some(&chanmon_cfgs[0].chain_source), &chanmon_cfgs[0].tx_broadcaster, &chanmon_cfgs[0].logger, &chanmon_cfgs[0].fee_estimator, &persister_0, node_cfgs[0].keys_manager);
+		let chain_mon_1 = test_utils::TestChainMonitor::new(Some(&chanmon_cfgs[1].chain_source), &chanmon_cfgs[1].tx_broadcaster, &chanmon_cfgs[1].logger, &chanmon_cfgs[1].fee_estimator, &persister_1, node_cfgs[1].keys_manager);
+		node_cfgs[0].chain_monitor = chain_mon_0;
+		node_cfgs[1].chain_monitor = chain_mon_1;
+		let node_chanmgrs = create_node_chanmgrs(2, &node_cfgs, &[None, None]);
+		let nodes = create_network(2, &node_cfgs, &node_chanmgrs);
+
+		// Replaying updates may rebroadcast transactions, which we don't want to end up in the
+		// nodes' broadcasters.
+		let replay_broadcaster = test_utils::TestBroadcaster::new(Network::Testnet);
+		let replay_fee_estimator = test_utils::TestFeeEstimator { sat_per_kw: Mutex::new(253) };
+
+		assert!(persister_0.read_all_channel_monitors_with_updates(&&replay_broadcaster, &&replay_fee_estimator).unwrap().is_empty());
+		assert!(persister_1.read_all_channel_monitors_with_updates(&&replay_broadcaster, &&replay_fee_estimator).unwrap().is_empty());
+
+		// Helper to make sure the channel is on the expected update ID and that only the updates
+		// since the last full monitor was written are stored.
+		macro_rules! check_persisted_data {
+			($expected_update_id: expr) => {
+				for (persister, store, max_pending_updates) in [
+					(&persister_0, &store_0, max_pending_updates_0),
+					(&persister_1, &store_1, max_pending_updates_1),
+				].iter() {
+					let monitors = persister.read_all_channel_monitors_with_updates(&&replay_broadcaster, &&replay_fee_estimator).unwrap();
+					assert_eq!(monitors.len(), 1);
+					let monitor = &monitors[0].1;
+					assert_eq!(monitor.get_latest_update_id(), $expected_update_id);
+
+					let expected_pending_updates = if $expected_update_id == CLOSED_CHANNEL_UPDATE_ID {
+						0
+					} else {
+						$expected_update_id % max_pending_updates
+					};
+					let key = monitor_key(&monitor.get_funding_txo().0);
+					assert_eq!(store.list(CHANNEL_MONITOR_UPDATE_PERSISTENCE_NAMESPACE, &key).unwrap().len() as u64,
+						expected_pending_updates);
+				}
+			}
+		}
+
+		// Create some initial channel and check that a channel was persisted.
+		let _ = create_announced_chan_between_nodes(&nodes, 0, 1);
+		check_persisted_data!(0);
+
+		// Send a few payments and make sure the monitors are updated to the latest.
+		send_payment(&nodes[0], &vec!(&nodes[1])[..], 8000000);
+		check_persisted_data!(5);
+		send_payment(&nodes[1], &vec!(&nodes[0])[..], 4000000);
+		check_persisted_data!(10);
+
+		// Monitors written by the MonitorUpdatingPersister may not be current, thus they can't be
+		// read without applying the pending updates.
+		assert!(read_channel_monitors(&store_0, nodes[0].keys_manager, nodes[0].keys_manager).is_err());
+
+		// Force close because cooperative close doesn't result in any persisted
+		// updates.
+		nodes[0].node.force_close_broadcasting_latest_txn(&nodes[0].node.list_channels()[0].channel_id, &nodes[1].node.get_our_node_id()).unwrap();
+		check_closed_event!(nodes[0], 1, ClosureReason::HolderForceClosed);
+		check_closed_broadcast!(nodes[0], true);
+		check_added_monitors!(nodes[0], 1);
+
+		let node_txn = nodes[0].tx_broadcaster.txn_broadcasted.lock().unwrap();
+		assert_eq!(node_txn.len(), 1);
+
+		connect_block(&nodes[1], &create_dummy_block(nodes[0].best_block_hash(), 42, vec![node_txn[0].clone(), node_txn[0].clone()]));
+		check_closed_broadcast!(nodes[1], true);
+		check_closed_event!(nodes[1], 1, ClosureReason::CommitmentTxConfirmed);
+		check_added_monitors!(nodes[1], 1);
+
+		// Make sure everything is persisted as expected after close.
+		check_persisted_data!(CLOSED_CHANNEL_UPDATE_ID);
+	}
+
+	#[test]
+	fn monitor_updating_persister_cleans_up_stale_updates() {
+		let chanmon_cfgs = create_chanmon_cfgs(2);
+		let store = TestStore::new(false);
+		let persister = MonitorUpdatingPersister::new(&store, &chanmon_cfgs[0].logger, 100,
+			&chanmon_cfgs[0].keys_manager, &chanmon_cfgs[0].keys_manager);
+		let mut node_cfgs = create_node_cfgs(2, &chanmon_cfgs);
+		let chain_mon_0 = test_utils::TestChainMonitor::new(Some(&chanmon_cfgs[0].chain_source), &chanmon_cfgs[0].tx_broadcaster, &chanmon_cfgs[0].logger, &chanmon_cfgs[0].fee_estimator, &persister, node_cfgs[0].keys_manager);
+		node_cfgs[0].chain_monitor = chain_mon_0;
+		let node_chanmgrs = create_node_chanmgrs(2, &node_cfgs, &[None, None]);
+		let nodes = create_network(2, &node_cfgs, &node_chanmgrs);
+
+		let (_, _, _, tx) = create_announced_chan_between_nodes(&nodes, 0, 1);
+		send_payment(&nodes[0], &vec!(&nodes[1])[..], 8000000);
+		let funding_txo = OutPoint { txid: tx.txid(), index: 0 };
+		let key = monitor_key(&funding_txo);
+		assert_eq!(store.list(CHANNEL_MONITOR_UPDATE_PERSISTENCE_NAMESPACE, &key).unwrap().len(), 5);
+
+		// Writing the full monitor without an update (as happens during chain sync) leaves the
+		// updates it includes behind.
+		{
+			let monitor = nodes[0].chain_monitor.chain_monitor.get_monitor(funding_txo).unwrap();
+			let update_id = MonitorUpdateId::from_new_monitor(&*monitor);
+			assert_eq!(persister.update_persisted_channel(funding_txo, None, &*monitor, update_id),
+				chain::ChannelMonitorUpdateStatus::Completed);
+		}
+		assert_eq!(store.list(CHANNEL_MONITOR_UPDATE_PERSISTENCE_NAMESPACE, &key).unwrap().len(), 5);
+
+		persister.cleanup_stale_updates(false).unwrap();
+		assert!(store.list(CHANNEL_MONITOR_UPDATE_PERSISTENCE_NAMESPACE, &key).unwrap().is_empty());
+	}
+}
@@ -0,0 +1,812 @@
+// This file is Copyright its original authors, visible in version control
+// history.
+//
+// This file is licensed under the Apache License, Version 2.0 <LICENSE-APACHE
+// or http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your option.
+// You may not use this file except in accordance with one or both of these
+// licenses.
+
+//! This module contains an [`OutputSweeper`] utility that keeps track of
+//! [`SpendableOutputDescriptor`]s, i.e., persists them in a given [`KVStore`] and regularly retries
+//! sweeping them until their spending transaction is irrevocably confirmed.
+
+use crate::chain::{BestBlock, Confirm, Filter, Listen, WatchedOutput};
+use crate::chain::chaininterface::{BroadcasterInterface, ConfirmationTarget, FeeEstimator, LowerBoundedFeeEstimator};
+use crate::chain::channelmonitor::ANTI_REORG_DELAY;
+use crate::chain::transaction::{OutPoint, TransactionData};
+use crate::io;
+use crate::ln::msgs::DecodeError;
+use crate::prelude::*;
+use crate::sign::{ChangeDestinationSource, OutputSpender, SpendableOutputDescriptor};
+use crate::sync::Mutex;
+use crate::util::logger::Logger;
+use crate::util::persist::{KVStore, OUTPUT_SWEEPER_PERSISTENCE_KEY, OUTPUT_SWEEPER_PERSISTENCE_NAMESPACE, OUTPUT_SWEEPER_PERSISTENCE_SUB_NAMESPACE};
+use crate::util::ser::{Readable, ReadableArgs, Writeable};
+
+use bitcoin::{BlockHash, PackedLockTime, Transaction, Txid};
+use bitcoin::blockdata::block::BlockHeader;
+use bitcoin::secp256k1::{self, Secp256k1};
+
+use core::cmp;
+use core::ops::Deref;
+
+/// The number of blocks a sweeping transaction may remain unconfirmed at
+/// [`ConfirmationTarget::Background`] before we bump it to [`ConfirmationTarget::Normal`], matching
+/// the horizon given for the former.
+const BACKGROUND_SWEEP_DEADLINE_BLOCKS: u32 = 144;
+
+/// The number of blocks a sweeping transaction may remain unconfirmed at
+/// [`ConfirmationTarget::Normal`] before we bump it to [`ConfirmationTarget::HighPriority`].
+const NORMAL_SWEEP_DEADLINE_BLOCKS: u32 = 24;
+
+/// The minimum amount by which we increase the feerate of a replacement sweeping transaction,
+/// ensuring it pays for its own relay at the default incremental relay feerate of 1 sat/vB, as
+/// required by BIP 125.
+const RBF_FEERATE_INCREMENT_SAT_PER_1000_WEIGHT: u32 = 253;
+
+/// Returns the [`ConfirmationTarget`] at which outputs are swept after the first sweeping
+/// transaction was broadcast the given number of blocks ago.
+fn sweep_confirmation_target(blocks_since_first_broadcast: u32) -> ConfirmationTarget {
+	if blocks_since_first_broadcast < BACKGROUND_SWEEP_DEADLINE_BLOCKS {
+		ConfirmationTarget::Background
+	} else if blocks_since_first_broadcast < BACKGROUND_SWEEP_DEADLINE_BLOCKS + NORMAL_SWEEP_DEADLINE_BLOCKS {
+		ConfirmationTarget::Normal
+	} else {
+		ConfirmationTarget::HighPriority
+	}
+}
+
+/// The state of a spendable output currently tracked by an [`OutputSweeper`].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct TrackedSpendableOutput {
+	/// The tracked output descriptor.
+	pub descriptor: SpendableOutputDescriptor,
+	/// The current status of the output spend.
+	pub status: OutputSpendStatus,
+}
+
+impl TrackedSpendableOutput {
+	/// Returns the outpoint of the tracked output.
+	pub fn outpoint(&self) -> OutPoint {
+		match &self.descriptor {
+			SpendableOutputDescriptor::StaticOutput { outpoint, .. } => *outpoint,
+			SpendableOutputDescriptor::DelayedPaymentOutput(descriptor) => descriptor.outpoint,
+			SpendableOutputDescriptor::StaticPaymentOutput(descriptor) => descriptor.outpoint,
+		}
+	}
+
+	fn is_spent_in(&self, tx: &Transaction) -> bool {
+		let prev_outpoint = self.outpoint().into_bitcoin_outpoint();
+		tx.input.iter().any(|input| input.previous_output == prev_outpoint)
+	}
+}
+
+impl_writeable_tlv_based!(TrackedSpendableOutput, {
+	(0, descriptor, required),
+	(2, status, required),
+});
+
+/// The current status of the output spend.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum OutputSpendStatus {
+	/// The output is tracked but an initial spending transaction hasn't been generated and
+	/// broadcasted yet.
+	PendingInitialBroadcast {
+		/// The height at which we will first generate and broadcast a spending transaction.
+		delayed_until_height: Option<u32>,
+	},
+	/// A transaction spending the output has been broadcasted but is pending its first
+	/// confirmation on-chain.
+	PendingFirstConfirmation {
+		/// The height at which we first broadcasted a transaction spending the output.
+		first_broadcast_height: u32,
+		/// The height at which we last broadcasted a transaction spending the output.
+		latest_broadcast_height: u32,
+		/// The transaction spending the output we last broadcasted.
+		latest_spending_tx: Transaction,
+		/// The feerate, in satoshis per 1000 weight units, of `latest_spending_tx`.
+		latest_feerate_sat_per_1000_weight: u32,
+	},
+	/// A transaction spending the output has been confirmed on-chain but will be tracked until it
+	/// reaches [`ANTI_REORG_DELAY`] confirmations.
+	PendingThresholdConfirmations {
+		/// The height at which we first broadcasted a transaction spending the output.
+		first_broadcast_height: u32,
+		/// The height at which we last broadcasted a transaction spending the output.
+		latest_broadcast_height: u32,
+		/// The transaction spending the output which was confirmed.
+		latest_spending_tx: Transaction,
+		/// The feerate, in satoshis per 1000 weight units, at which we last broadcasted a
+		/// transaction spending the output, or `0` if the output was spent by a transaction we
+		/// never broadcasted.
+		latest_feerate_sat_per_1000_weight: u32,
+		/// The height at which the spending transaction was confirmed.
+		confirmation_height: u32,
+		/// The hash of the block in which the spending transaction was confirmed.
+		confirmation_hash: BlockHash,
+	},
+}
+
+impl OutputSpendStatus {
+	fn confirmed(&mut self, spending_tx: &Transaction, confirmation_height: u32, confirmation_hash: BlockHash) {
+		let (first_broadcast_height, latest_broadcast_height, latest_feerate_sat_per_1000_weight) = match self {
+			Self::PendingInitialBroadcast { .. } => (confirmation_height, confirmation_height, 0),
+			Self::PendingFirstConfirmation { first_broadcast_height, latest_broadcast_height, latest_feerate_sat_per_1000_weight, .. } |
+			Self::PendingThresholdConfirmations { first_broadcast_height, latest_broadcast_height, latest_feerate_sat_per_1000_weight, .. } =>
+				(*first_broadcast_height, *latest_broadcast_height, *latest_feerate_sat_per_1000_weight),
+		};
+		*self = Self::PendingThresholdConfirmations {
+			first_broadcast_height,
+			latest_broadcast_height,
+			latest_spending_tx: spending_tx.clone(),
+			latest_feerate_sat_per_1000_weight,
+			confirmation_height,
+			confirmation_hash,
+		};
+	}
+
+	fn unconfirmed(&mut self) {
+		if let Self::PendingThresholdConfirmations {
+			first_broadcast_height, latest_broadcast_height, latest_spending_tx,
+			latest_feerate_sat_per_1000_weight, ..
+		} = self {
+			*self = Self::PendingFirstConfirmation {
+				first_broadcast_height: *first_broadcast_height,
+				latest_broadcast_height: *latest_broadcast_height,
+				latest_spending_tx: latest_spending_tx.clone(),
+				latest_feerate_sat_per_1000_weight: *latest_feerate_sat_per_1000_weight,
+			};
+		}
+	}
+
+	fn latest_spending_tx(&self) -> Option<&Transaction> {
+		match self {
+			Self::PendingInitialBroadcast { .. } => None,
+			Self::PendingFirstConfirmation { latest_spending_tx, .. } => Some(latest_spending_tx),
+			Self::PendingThresholdConfirmations { latest_spending_tx, .. } => Some(latest_spending_tx),
+		}
+	}
+
+	fn confirmation_hash(&self) -> Option<BlockHash> {
+		match self {
+			Self::PendingThresholdConfirmations { confirmation_hash, .. } => Some(*confirmation_hash),
+			_ => None,
+		}
+	}
+}
+
+impl_writeable_tlv_based_enum!(OutputSpendStatus,
+	(0, PendingInitialBroadcast) => {
+		(0, delayed_until_height, option),
+	},
+	(2, PendingFirstConfirmation) => {
+		(0, first_broadcast_height, required),
+		(2, latest_broadcast_height, required),
+		(4, latest_spending_tx, required),
+		(6, latest_feerate_sat_per_1000_weight, required),
+	},
+	(4, PendingThresholdConfirmations) => {
+		(0, first_broadcast_height, required),
+		(2, latest_broadcast_height, required),
+		(4, latest_spending_tx, required),
+		(6, latest_feerate_sat_per_1000_weight, required),
+		(8, confirmation_height, required),
+		(10, confirmation_hash, required),
+	};
+);
+
+struct SweeperState {
+	outputs: Vec<TrackedSpendableOutput>,
+	best_block: BestBlock,
+}
+
+impl_writeable_tlv_based!(SweeperState, {
+	(0, outputs, required_vec),
+	(2, best_block, required),
+});
+
+/// A utility that keeps track of [`SpendableOutputDescriptor`]s, persists them in a given
+/// [`KVStore`] and regularly retries sweeping them based on a callback given to the constructor
+/// methods.
+///
+/// Users should call [`Self::track_spendable_outputs`] for any [`SpendableOutputDescriptor`]s
+/// received via [`Event::SpendableOutputs`].
+///
+/// This needs to be notified of chain state changes either via its [`Listen`] or [`Confirm`]
+/// implementation and hence has to be connected with the utilized chain data sources. Upon each
+/// new best block, all outputs which aren't confirmed yet are swept in a single batch transaction
+/// at a feerate retrieved for a [`ConfirmationTarget`] which gets more urgent the longer they
+/// remain unconfirmed. If the resulting feerate exceeds that of the previous sweeping transaction
+/// or new outputs were added to the batch, the previous transaction is replaced, otherwise it is
+/// merely rebroadcast. Outputs are only dropped from tracking once their spending transaction
+/// reached [`ANTI_REORG_DELAY`] confirmations.
+///
+/// If chain data is provided via the [`Confirm`] interface or via filtered blocks, users are
+/// required to give a [`Filter`] as `chain_data_source`, with which the tracked outputs are
+/// registered so that their spends are detected.
+///
+/// [`Event::SpendableOutputs`]: crate::events::Event::SpendableOutputs
+pub struct OutputSweeper<B: Deref, D: Deref, E: Deref, F: Deref, K: Deref, L: Deref, O: Deref>
+where
+	B::Target: BroadcasterInterface,
+	D::Target: ChangeDestinationSource,
+	E::Target: FeeEstimator,
+	F::Target: Filter,
+	K::Target: KVStore,
+	L::Target: Logger,
+	O::Target: OutputSpender,
+{
+	sweeper_state: Mutex<SweeperState>,
+	broadcaster: B,
+	fee_estimator: E,
+	chain_data_source: Option<F>,
+	output_spender: O,
+	change_destination_source: D,
+	kv_store: K,
+	logger: L,
+	secp_ctx: Secp256k1<secp256k1::All>,
+}
+
+impl<B: Deref, D: Deref, E: Deref, F: Deref, K: Deref, L: Deref, O: Deref> OutputSweeper<B, D, E, F, K, L, O>
+where
+	B::Target: BroadcasterInterface,
+	D::Target: ChangeDestinationSource,
+	E::Target: FeeEstimator,
+	F::Target: Filter,
+	K::Target: KVStore,
+	L::Target: Logger,
+	O::Target: OutputSpender,
+{
+	/// Constructs a new [`OutputSweeper`].
+	///
+	/// If chain data is provided via the [`Confirm`] interface or via filtered blocks, users also
+	/// need to register their [`Filter`] implementation via the given `chain_data_source`.
+	pub fn new(
+		best_block: BestBlock, broadcaster: B, fee_estimator: E, chain_data_source: Option<F>,
+		output_spender: O, change_destination_source: D, kv_store: K, logger: L,
+	) -> Self {
+		let outputs = Vec::new();
+		let sweeper_state = Mutex::new(SweeperState { outputs, best_block });
+		Self {
+			sweeper_state,
+			broadcaster,
+			fee_estimator,
+			chain_data_source,
+			output_spender,
+			change_destination_source,
+			kv_store,
+			logger,
+			secp_ctx: Secp256k1::new(),
+		}
+	}
+
+	/// Tells the sweeper to track the given outputs descriptors.
+	///
+	/// Usually, this should be called based on the values emitted by the
+	/// [`Event::SpendableOutputs`].
+	///
+	/// The given `exclude_static_outputs` flag controls whether the sweeper will filter out
+	/// [`SpendableOutputDescriptor::StaticOutput`]s, which may be handled directly by the on-chain
+	/// wallet implementation.
+	///
+	/// If `delay_until_height` is set, we will delay the spending until the respective block
+	/// height is reached. This can be used to batch spends, e.g., to reduce on-chain fees.
+	///
+	/// Returns `Err` on persistence failure, in which case the call may be safely retried.
+	///
+	/// [`Event::SpendableOutputs`]: crate::events::Event::SpendableOutputs
+	pub fn track_spendable_outputs(
+		&self, output_descriptors: Vec<SpendableOutputDescriptor>, exclude_static_outputs: bool,
+		delay_until_height: Option<u32>,
+	) -> Result<(), ()> {
+		let relevant_descriptors = output_descriptors.into_iter().filter(|descriptor| {
+			!(exclude_static_outputs && matches!(descriptor, SpendableOutputDescriptor::StaticOutput { .. }))
+		}).collect::<Vec<_>>();
+
+		if relevant_descriptors.is_empty() {
+			return Ok(());
+		}
+
+		let mut state_lock = self.sweeper_state.lock().unwrap();
+		let cur_height = state_lock.best_block.height();
+		let mut spend_now = false;
+		for descriptor in relevant_descriptors {
+			let output_info = TrackedSpendableOutput {
+				descriptor,
+				status: OutputSpendStatus::PendingInitialBroadcast { delayed_until_height: delay_until_height },
+			};
+
+			if state_lock.outputs.iter().any(|o| o.outpoint() == output_info.outpoint()) {
+				continue;
+			}
+
+			self.watch_output(&output_info);
+			state_lock.outputs.push(output_info);
+			spend_now = delay_until_height.map_or(true, |delay_height| cur_height >= delay_height);
+		}
+		if spend_now {
+			self.rebroadcast_if_necessary(&mut state_lock);
+		}
+		self.persist_state(&state_lock).map_err(|e| {
+			log_error!(self.logger, "Error persisting OutputSweeper: {:?}", e);
+		})
+	}
+
+	/// Returns a list of the currently tracked spendable outputs.
+	pub fn tracked_spendable_outputs(&self) -> Vec<TrackedSpendableOutput> {
+		self.sweeper_state.lock().unwrap().outputs.clone()
+	}
+
+	/// Gets the latest best block which was connected either via the [`Listen`] or
+	/// [`Confirm`] interfaces.
+	pub fn current_best_block(&self) -> BestBlock {
+		self.sweeper_state.lock().unwrap().best_block
+	}
+
+	fn watch_output(&self, output_info: &TrackedSpendableOutput) {
+		if let Some(filter) = self.chain_data_source.as_ref() {
+			let script_pubkey = match &output_info.descriptor {
+				SpendableOutputDescriptor::StaticOutput { output, .. } => output.script_pubkey.clone(),
+				SpendableOutputDescriptor::DelayedPaymentOutput(descriptor) => descriptor.output.script_pubkey.clone(),
+				SpendableOutputDescriptor::StaticPaymentOutput(descriptor) => descriptor.output.script_pubkey.clone(),
+			};
+			filter.register_output(WatchedOutput {
+				block_hash: None,
+				outpoint: output_info.outpoint(),
+				script_pubkey,
+			});
+		}
+	}
+
+	/// Sweeps all outputs which aren't confirmed yet, replacing the previous sweeping transaction
+	/// if its feerate needs bumping or new outputs are to be swept, and rebroadcasting it otherwise.
+	fn rebroadcast_if_necessary(&self, sweeper_state: &mut SweeperState) {
+		let cur_height = sweeper_state.best_block.height();
+
+		let mut respend_descriptors = Vec::new();
+		let mut has_new_outputs = false;
+		let mut prev_feerate = None;
+		let mut first_broadcast_height = cur_height;
+		for output_info in sweeper_state.outputs.iter() {
+			match output_info.status {
+				OutputSpendStatus::PendingInitialBroadcast { delayed_until_height } => {
+					if delayed_until_height.map_or(true, |delay_height| cur_height >= delay_height) {
+						respend_descriptors.push(&output_info.descriptor);
+						has_new_outputs = true;
+					}
+				},
+				OutputSpendStatus::PendingFirstConfirmation {
+					first_broadcast_height: output_first_broadcast_height,
+					latest_feerate_sat_per_1000_weight, ..
+				} => {
+					respend_descriptors.push(&output_info.descriptor);
+					prev_feerate = cmp::max(prev_feerate, Some(latest_feerate_sat_per_1000_weight));
+					first_broadcast_height = cmp::min(first_broadcast_height, output_first_broadcast_height);
+				},
+				OutputSpendStatus::PendingThresholdConfirmations { .. } => {},
+			}
+		}
+
+		if respend_descriptors.is_empty() {
+			return;
+		}
+
+		let confirmation_target = sweep_confirmation_target(cur_height.saturating_sub(first_broadcast_height));
+		let estimated_feerate = LowerBoundedFeeEstimator::new(&*self.fee_estimator)
+			.bounded_sat_per_1000_weight(confirmation_target);
+		let feerate_sat_per_1000_weight = match prev_feerate {
+			Some(prev_feerate) if !has_new_outputs && estimated_feerate <= prev_feerate => {
+				let mut pending_txs: Vec<&Transaction> = Vec::new();
+				for output_info in sweeper_state.outputs.iter() {
+					if let OutputSpendStatus::PendingFirstConfirmation { latest_spending_tx, .. } = &output_info.status {
+						if !pending_txs.iter().any(|tx| tx.txid() == latest_spending_tx.txid()) {
+							pending_txs.push(latest_spending_tx);
+						}
+					}
+				}
+				log_debug!(self.logger, "Rebroadcasting {} pending output sweeping transaction(s)", pending_txs.len());
+				self.broadcaster.broadcast_transactions(&pending_txs);
+				return;
+			},
+			Some(prev_feerate) => cmp::max(estimated_feerate, prev_feerate + RBF_FEERATE_INCREMENT_SAT_PER_1000_WEIGHT),
+			None => estimated_feerate,
+		};
+
+		let change_destination_script = match self.change_destination_source.get_change_destination_script() {
+			Ok(script) => script,
+			Err(()) => {
+				log_error!(self.logger, "Failed to retrieve a change destination script, not sweeping outputs");
+				return;
+			},
+		};
+		let spending_tx = match self.output_spender.spend_spendable_outputs(
+			&respend_descriptors, Vec::new(), change_destination_script, feerate_sat_per_1000_weight,
+			Some(PackedLockTime(cur_height)), &self.secp_ctx,
+		) {
+			Ok(spending_tx) => spending_tx,
+			Err(()) => {
+				log_error!(self.logger, "Failed to spend {} outputs at {} sat/kW", respend_descriptors.len(), feerate_sat_per_1000_weight);
+				return;
+			},
+		};
+		let num_respent = respend_descriptors.len();
+
+		for output_info in sweeper_state.outputs.iter_mut() {
+			if !output_info.is_spent_in(&spending_tx) {
+				continue;
+			}
+			let output_first_broadcast_height = match output_info.status {
+				OutputSpendStatus::PendingFirstConfirmation { first_broadcast_height, .. } => first_broadcast_height,
+				_ => cur_height,
+			};
+			output_info.status = OutputSpendStatus::PendingFirstConfirmation {
+				first_broadcast_height: output_first_broadcast_height,
+				latest_broadcast_height: cur_height,
+				latest_spending_tx: spending_tx.clone(),
+				latest_feerate_sat_per_1000_weight: feerate_sat_per_1000_weight,
+			};
+		}
+
+		log_info!(self.logger, "Broadcasting transaction {} sweeping {} outputs at {} sat/kW",
+			spending_tx.txid(), num_respent, feerate_sat_per_1000_weight);
+		self.broadcaster.broadcast_transactions(&[&spending_tx]);
+	}
+
+	fn prune_confirmed_outputs(&self, sweeper_state: &mut SweeperState) {
+		let cur_height = sweeper_state.best_block.height();
+
+		// Prune all outputs that have sufficient depth by now.
+		sweeper_state.outputs.retain(|output_info| {
+			if let OutputSpendStatus::PendingThresholdConfirmations { confirmation_height, latest_spending_tx, .. } = &output_info.status {
+				if cur_height >= confirmation_height + ANTI_REORG_DELAY - 1 {
+					log_debug!(self.logger, "Pruning swept output {} as its spending transaction {} reached {} confirmations",
+						output_info.outpoint().into_bitcoin_outpoint(), latest_spending_tx.txid(), ANTI_REORG_DELAY);
+					return false;
+				}
+			}
+			true
+		});
+	}
+
+	fn persist_state(&self, sweeper_state: &SweeperState) -> Result<(), io::Error> {
+		self.kv_store.write(
+			OUTPUT_SWEEPER_PERSISTENCE_NAMESPACE,
+			OUTPUT_SWEEPER_PERSISTENCE_SUB_NAMESPACE,
+			OUTPUT_SWEEPER_PERSISTENCE_KEY,
+			&sweeper_state.encode(),
+		)
+	}
+
+	fn persist_state_logging_errors(&self, sweeper_state: &SweeperState) {
+		if let Err(e) = self.persist_state(sweeper_state) {
+			log_error!(self.logger, "Error persisting OutputSweeper: {:?}", e);
+		}
+	}
+
+	fn transactions_confirmed_internal(
+		&self, sweeper_state: &mut SweeperState, header: &BlockHeader, txdata: &TransactionData,
+		height: u32,
+	) {
+		let confirmation_hash = header.block_hash();
+		for (_, tx) in txdata {
+			for output_info in sweeper_state.outputs.iter_mut() {
+				if output_info.is_spent_in(tx) {
+					output_info.status.confirmed(tx, height, confirmation_hash);
+				}
+			}
+		}
+	}
+
+	fn best_block_updated_internal(&self, sweeper_state: &mut SweeperState, header: &BlockHeader, height: u32) {
+		sweeper_state.best_block = BestBlock::new(header.block_hash(), height);
+		self.prune_confirmed_outputs(sweeper_state);
+		self.rebroadcast_if_necessary(sweeper_state);
+	}
+}
+
+impl<B: Deref, D: Deref, E: Deref, F: Deref, K: Deref, L: Deref, O: Deref> Listen for OutputSweeper<B, D, E, F, K, L, O>
+where
+	B::Target: BroadcasterInterface,
+	D::Target: ChangeDestinationSource,
+	E::Target: FeeEstimator,
+	F::Target: Filter,
+	K::Target: KVStore,
+	L::Target: Logger,
+	O::Target: OutputSpender,
+{
+	fn filtered_block_connected(&self, header: &BlockHeader, txdata: &TransactionData, height: u32) {
+		let mut state_lock = self.sweeper_state.lock().unwrap();
+		assert_eq!(state_lock.best_block.block_hash(), header.prev_blockhash,
+			"Blocks must be connected in chain-order - the connected header must build on the last connected header");
+		assert_eq!(state_lock.best_block.height(), height - 1,
+			"Blocks must be connected in chain-order - the connected block height must be one greater than the previous height");
+
+		self.transactions_confirmed_internal(&mut state_lock, header, txdata, height);
+		self.best_block_updated_internal(&mut state_lock, header, height);
+		self.persist_state_logging_errors(&state_lock);
+	}
+
+	fn block_disconnected(&self, header: &BlockHeader, height: u32) {
+		let mut state_lock = self.sweeper_state.lock().unwrap();
+
+		let new_height = height - 1;
+		let block_hash = header.block_hash();
+
+		assert_eq!(state_lock.best_block.block_hash(), block_hash,
+			"Blocks must be disconnected in chain-order - the disconnected header must be the last connected header");
+		assert_eq!(state_lock.best_block.height(), height,
+			"Blocks must be disconnected in chain-order - the disconnected block must have the correct height");
+		state_lock.best_block = BestBlock::new(header.prev_blockhash, new_height);
+
+		for output_info in state_lock.outputs.iter_mut() {
+			if output_info.status.confirmation_hash() == Some(block_hash) {
+				output_info.status.unconfirmed();
+			}
+		}
+
+		self.persist_state_logging_errors(&state_lock);
+	}
+}
+
+impl<B: Deref, D: Deref, E: Deref, F: Deref, K: Deref, L: Deref, O: Deref> Confirm for OutputSweeper<B, D, E, F, K, L, O>
+where
+	B::Target: BroadcasterInterface,
+	D::Target: ChangeDestinationSource,
+	E::Target: FeeEstimator,
+	F::Target: Filter,
+	K::Target: KVStore,
+	L::Target: Logger,
+	O::Target: OutputSpender,
+{
+	fn transactions_confirmed(&self, header: &BlockHeader, txdata: &TransactionData, height: u32) {
+		let mut state_lock = self.sweeper_state.lock().unwrap();
+		self.transactions_confirmed_internal(&mut state_lock, header, txdata, height);
+		self.persist_state_logging_errors(&state_lock);
+	}
+
+	fn transaction_unconfirmed(&self, txid: &Txid) {
+		let mut state_lock = self.sweeper_state.lock().unwrap();
+
+		for output_info in state_lock.outputs.iter_mut() {
+			if output_info.status.confirmation_hash().is_some() &&
+				output_info.status.latest_spending_tx().map(|tx| tx.txid()) == Some(*txid)
+			{
+				output_info.status.unconfirmed();
+			}
+		}
+
+		self.persist_state_logging_errors(&state_lock);
+	}
+
+	fn best_block_updated(&self, header: &BlockHeader, height: u32) {
+		let mut state_lock = self.sweeper_state.lock().unwrap();
+		self.best_block_updated_internal(&mut state_lock, header, height);
+		self.persist_state_logging_errors(&state_lock);
+	}
+
+	fn get_relevant_txids(&self) -> Vec<(Txid, Option<BlockHash>)> {
+		let state_lock = self.sweeper_state.lock().unwrap();
+		let mut relevant_txids = Vec::new();
+		for output_info in state_lock.outputs.iter() {
+			if let OutputSpendStatus::PendingThresholdConfirmations { latest_spending_tx, confirmation_hash, .. } = &output_info.status {
+				let txid = latest_spending_tx.txid();
+				if !relevant_txids.iter().any(|(relevant_txid, _)| *relevant_txid == txid) {
+					relevant_txids.push((txid, Some(*confirmation_hash)));
+				}
+			}
+		}
+		relevant_txids
+	}
+}
+
+impl<B: Deref, D: Deref, E: Deref, F: Deref, K: Deref, L: Deref, O: Deref> ReadableArgs<(B, E, Option<F>, O, D, K, L)> for OutputSweeper<B, D, E, F, K, L, O>
+where
+	B::Target: BroadcasterInterface,
+	D::Target: ChangeDestinationSource,
+	E::Target: FeeEstimator,
+	F::Target: Filter,
+	K::Target: KVStore,
+	L::Target: Logger,
+	O::Target: OutputSpender,
+{
+	#[inline]
+	fn read<R: io::Read>(reader: &mut R, args: (B, E, Option<F>, O, D, K, L)) -> Result<Self, DecodeError> {
+		let (broadcaster, fee_estimator, chain_data_source, output_spender, change_destination_source, kv_store, logger) = args;
+		let state = SweeperState::read(reader)?;
+
+		let sweeper = Self {
+			sweeper_state: Mutex::new(SweeperState { outputs: Vec::new(), best_block: state.best_block }),
+			broadcaster,
+			fee_estimator,
+			chain_data_source,
+			output_spender,
+			change_destination_source,
+			kv_store,
+			logger,
+			secp_ctx: Secp256k1::new(),
+		};
+		for output_info in state.outputs.iter() {
+			sweeper.watch_output(output_info);
+		}
+		*sweeper.sweeper_state.lock().unwrap() = state;
+		Ok(sweeper)
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::chain::chaininterface::FEERATE_FLOOR_SATS_PER_KW;
+	use crate::sign::{KeysManager, SignerProvider};
+	use crate::util::test_utils::{TestBroadcaster, TestChainSource, TestFeeEstimator, TestLogger, TestStore};
+
+	use bitcoin::{Block, Script, TxOut};
+	use bitcoin::blockdata::constants::genesis_block;
+	use bitcoin::hashes::Hash;
+	use bitcoin::network::constants::Network;
+
+	use crate::io::Cursor;
+
+	struct TestChangeDestinationSource(Script);
+
+	impl ChangeDestinationSource for TestChangeDestinationSource {
+		fn get_change_destination_script(&self) -> Result<Script, ()> {
+			Ok(self.0.clone())
+		}
+	}
+
+	fn header_building_on(prev_blockhash: BlockHash, time: u32) -> BlockHeader {
+		BlockHeader {
+			version: 2,
+			prev_blockhash,
+			merkle_root: bitcoin::TxMerkleNode::all_zeros(),
+			time,
+			bits: 42,
+			nonce: 42,
+		}
+	}
+
+	fn static_output(keys_manager: &KeysManager, txid_byte: u8, value: u64) -> SpendableOutputDescriptor {
+		SpendableOutputDescriptor::StaticOutput {
+			outpoint: OutPoint { txid: Txid::from_slice(&[txid_byte; 32]).unwrap(), index: 0 },
+			output: TxOut { value, script_pubkey: keys_manager.get_destination_script().unwrap() },
+		}
+	}
+
+	fn connect_block<S: Listen>(
+		sweeper: &S, broadcaster: &TestBroadcaster, headers: &mut Vec<BlockHeader>, txdata: &[&Transaction]
+	) {
+		let height = headers.len() as u32;
+		let header = header_building_on(headers.last().unwrap().block_hash(), height * 10 + txdata.len() as u32);
+		let block = Block { header, txdata: txdata.iter().map(|tx| (*tx).clone()).collect() };
+		broadcaster.blocks.lock().unwrap().push((block, height));
+
+		let txdata: Vec<_> = txdata.iter().enumerate().map(|(idx, tx)| (idx + 1, *tx)).collect();
+		sweeper.filtered_block_connected(&header, &txdata, height);
+		headers.push(header);
+	}
+
+	#[test]
+	fn sweeps_outputs_until_irrevocably_confirmed() {
+		let network = Network::Testnet;
+		let keys_manager = KeysManager::new(&[42; 32], 42, 42);
+		let broadcaster = TestBroadcaster::new(network);
+		let fee_estimator = TestFeeEstimator { sat_per_kw: Mutex::new(FEERATE_FLOOR_SATS_PER_KW) };
+		let chain_source = TestChainSource::new(network);
+		let change_destination = TestChangeDestinationSource(Script::new_op_return(&[42; 20]).to_v0_p2wsh());
+		let store = TestStore::new(false);
+		let logger = TestLogger::new();
+
+		let mut headers = vec![genesis_block(network).header];
+		let best_block = BestBlock::from_network(network);
+		let sweeper = OutputSweeper::new(best_block, &broadcaster, &fee_estimator, Some(&chain_source),
+			&keys_manager, &change_destination, &store, &logger);
+
+		// Tracking outputs sweeps them right away and registers them to be watched.
+		let first_output = static_output(&keys_manager, 1, 100_000);
+		sweeper.track_spendable_outputs(vec![first_output.clone()], false, None).unwrap();
+		assert_eq!(chain_source.watched_outputs.lock().unwrap().len(), 1);
+		let first_tx = {
+			let mut txn = broadcaster.txn_broadcasted.lock().unwrap();
+			assert_eq!(txn.len(), 1);
+			txn.pop().unwrap()
+		};
+		assert_eq!(first_tx.input.len(), 1);
+
+		// Tracking the same output again doesn't change anything.
+		sweeper.track_spendable_outputs(vec![first_output], false, None).unwrap();
+		assert!(broadcaster.txn_broadcasted.lock().unwrap().is_empty());
+
+		// Without a feerate increase, the pending transaction is merely rebroadcast.
+		connect_block(&sweeper, &broadcaster, &mut headers, &[]);
+		assert_eq!(*broadcaster.txn_broadcasted.lock().unwrap(), vec![first_tx.clone()]);
+		broadcaster.txn_broadcasted.lock().unwrap().clear();
+
+		// New outputs are added to the batch, replacing the pending transaction at a higher feerate.
+		sweeper.track_spendable_outputs(vec![static_output(&keys_manager, 2, 100_000)], false, Some(3)).unwrap();
+		assert!(broadcaster.txn_broadcasted.lock().unwrap().is_empty());
+		connect_block(&sweeper, &broadcaster, &mut headers, &[]);
+		assert_eq!(*broadcaster.txn_broadcasted.lock().unwrap(), vec![first_tx.clone()]);
+		broadcaster.txn_broadcasted.lock().unwrap().clear();
+		connect_block(&sweeper, &broadcaster, &mut headers, &[]);
+		let second_tx = broadcaster.txn_broadcasted.lock().unwrap().pop().unwrap();
+		assert_eq!(second_tx.input.len(), 2);
+		match &sweeper.tracked_spendable_outputs()[0].status {
+			OutputSpendStatus::PendingFirstConfirmation { first_broadcast_height, latest_feerate_sat_per_1000_weight, .. } => {
+				assert_eq!(*first_broadcast_height, 0);
+				assert_eq!(*latest_feerate_sat_per_1000_weight, FEERATE_FLOOR_SATS_PER_KW + RBF_FEERATE_INCREMENT_SAT_PER_1000_WEIGHT);
+			},
+			status => panic!("Unexpected status {:?}", status),
+		}
+
+		// Once the sweeping transaction confirms, we only rebroadcast until ANTI_REORG_DELAY
+		// confirmations are reached, at which point the outputs are pruned.
+		connect_block(&sweeper, &broadcaster, &mut headers, &[&second_tx]);
+		assert!(broadcaster.txn_broadcasted.lock().unwrap().is_empty());
+		assert_eq!(sweeper.get_relevant_txids(), vec![(second_tx.txid(), Some(headers.last().unwrap().block_hash()))]);
+
+		// A reorg unconfirms the transaction, leading to it being rebroadcast.
+		let tip = headers.pop().unwrap();
+		sweeper.block_disconnected(&tip, headers.len() as u32);
+		broadcaster.blocks.lock().unwrap().pop();
+		assert!(sweeper.get_relevant_txids().is_empty());
+		connect_block(&sweeper, &broadcaster, &mut headers, &[]);
+		assert_eq!(*broadcaster.txn_broadcasted.lock().unwrap(), vec![second_tx.clone()]);
+		broadcaster.txn_broadcasted.lock().unwrap().clear();
+
+		connect_block(&sweeper, &broadcaster, &mut headers, &[&second_tx]);
+		for _ in 0..ANTI_REORG_DELAY - 2 {
+			connect_block(&sweeper, &broadcaster, &mut headers, &[]);
+			assert_eq!(sweeper.tracked_spendable_outputs().len(), 2);
+		}
+		connect_block(&sweeper, &broadcaster, &mut headers, &[]);
+		assert!(sweeper.tracked_spendable_outputs().is_empty());
+		assert!(broadcaster.txn_broadcasted.lock().unwrap().is_empty());
+	}
+
+	#[test]
+	fn bumps_feerate_by_deadline_and_persists_state() {
+		let network = Network::Testnet;
+		let keys_manager = KeysManager::new(&[42; 32], 42, 42);
+		let broadcaster = TestBroadcaster::new(network);
+		let fee_estimator = TestFeeEstimator { sat_per_kw: Mutex::new(1000) };
+		let change_destination = TestChangeDestinationSource(Script::new_op_return(&[42; 20]).to_v0_p2wsh());
+		let store = TestStore::new(false);
+		let logger = TestLogger::new();
+
+		let mut headers = vec![genesis_block(network).header];
+		let best_block = BestBlock::from_network(network);
+		let sweeper = OutputSweeper::new(best_block, &broadcaster, &fee_estimator, None::<&TestChainSource>,
+			&keys_manager, &change_destination, &store, &logger);
+
+		sweeper.track_spendable_outputs(vec![static_output(&keys_manager, 1, 100_000)], false, None).unwrap();
+		assert_eq!(broadcaster.txn_broadcasted.lock().unwrap().len(), 1);
+		assert_eq!(sweep_confirmation_target(0), ConfirmationTarget::Background);
+
+		// An increased estimate for the current target leads to a replacement at that feerate.
+		*fee_estimator.sat_per_kw.lock().unwrap() = 2000;
+		connect_block(&sweeper, &broadcaster, &mut headers, &[]);
+		let bumped_tx = broadcaster.txn_broadcasted.lock().unwrap().pop().unwrap();
+		match &sweeper.tracked_spendable_outputs()[0].status {
+			OutputSpendStatus::PendingFirstConfirmation { latest_spending_tx, latest_feerate_sat_per_1000_weight, .. } => {
+				assert_eq!(*latest_spending_tx, bumped_tx);
+				assert_eq!(*latest_feerate_sat_per_1000_weight, 2000);
+			},
+			status => panic!("Unexpected status {:?}", status),
+		}
+
+		// The target gets more urgent as deadlines pass.
+		assert_eq!(sweep_confirmation_target(BACKGROUND_SWEEP_DEADLINE_BLOCKS), ConfirmationTarget::Normal);
+		assert_eq!(sweep_confirmation_target(BACKGROUND_SWEEP_DEADLINE_BLOCKS + NORMAL_SWEEP_DEADLINE_BLOCKS), ConfirmationTarget::HighPriority);
+
+		// The state survives a restart.
+		let persisted = store.read(OUTPUT_SWEEPER_PERSISTENCE_NAMESPACE, OUTPUT_SWEEPER_PERSISTENCE_SUB_NAMESPACE, OUTPUT_SWEEPER_PERSISTENCE_KEY).unwrap();
+		let chain_source = TestChainSource::new(network);
+		let read_sweeper: OutputSweeper<_, _, _, _, _, _, _> = ReadableArgs::read(&mut Cursor::new(&persisted),
+			(&broadcaster, &fee_estimator, Some(&chain_source), &keys_manager, &change_destination, &store, &logger)).unwrap();
+		assert_eq!(read_sweeper.tracked_spendable_outputs(), sweeper.tracked_spendable_outputs());
+		assert!(read_sweeper.current_best_block() == sweeper.current_best_block());
+		assert_eq!(chain_source.watched_outputs.lock().unwrap().len(), 1);
+	}
+}
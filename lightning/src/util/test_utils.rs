@@ -31,6 +31,7 @@ use crate::routing::scoring::{ChannelUsage, Score};
 use crate::util::config::UserConfig;
 use crate::util::enforcing_trait_impls::{EnforcingSigner, EnforcementState};
 use crate::util::logger::{Logger, Level, Record};
+use crate::util::persist::KVStore;
 use crate::util::ser::{Readable, ReadableArgs, Writer, Writeable};
 
 use bitcoin::EcdsaSighashType;
@@ -316,6 +317,95 @@ impl<Signer: sign::WriteableEcdsaChannelSigner> chainmonitor::Persist<Signer> fo
 	}
 }
 
+pub struct TestStore {
+	persisted_bytes: Mutex<HashMap<String, HashMap<String, Vec<u8>>>>,
+	read_only: bool,
+}
+
+impl TestStore {
+	pub fn new(read_only: bool) -> Self {
+		let persisted_bytes = Mutex::new(HashMap::new());
+		Self { persisted_bytes, read_only }
+	}
+}
+
+impl KVStore for TestStore {
+	fn read(&self, namespace: &str, sub_namespace: &str, key: &str) -> io::Result<Vec<u8>> {
+		let persisted_lock = self.persisted_bytes.lock().unwrap();
+		let prefixed = if sub_namespace.is_empty() {
+			namespace.to_string()
+		} else {
+			format!("{}/{}", namespace, sub_namespace)
+		};
+
+		if let Some(outer_ref) = persisted_lock.get(&prefixed) {
+			if let Some(inner_ref) = outer_ref.get(key) {
+				let bytes = inner_ref.clone();
+				Ok(bytes)
+			} else {
+				Err(io::Error::new(io::ErrorKind::NotFound, "Key not found"))
+			}
+		} else {
+			Err(io::Error::new(io::ErrorKind::NotFound, "Namespace not found"))
+		}
+	}
+
+	fn write(&self, namespace: &str, sub_namespace: &str, key: &str, buf: &[u8]) -> io::Result<()> {
+		if self.read_only {
+			return Err(io::Error::new(
+				io::ErrorKind::PermissionDenied,
+				"Cannot modify read-only store",
+			));
+		}
+		let mut persisted_lock = self.persisted_bytes.lock().unwrap();
+
+		let prefixed = if sub_namespace.is_empty() {
+			namespace.to_string()
+		} else {
+			format!("{}/{}", namespace, sub_namespace)
+		};
+		let outer_e = persisted_lock.entry(prefixed).or_default();
+		outer_e.insert(key.to_string(), buf.to_vec());
+		Ok(())
+	}
+
+	fn remove(&self, namespace: &str, sub_namespace: &str, key: &str, _lazy: bool) -> io::Result<()> {
+		if self.read_only {
+			return Err(io::Error::new(
+				io::ErrorKind::PermissionDenied,
+				"Cannot modify read-only store",
+			));
+		}
+
+		let mut persisted_lock = self.persisted_bytes.lock().unwrap();
+
+		let prefixed = if sub_namespace.is_empty() {
+			namespace.to_string()
+		} else {
+			format!("{}/{}", namespace, sub_namespace)
+		};
+		if let Some(outer_ref) = persisted_lock.get_mut(&prefixed) {
+			outer_ref.remove(&key.to_string());
+		}
+
+		Ok(())
+	}
+
+	fn list(&self, namespace: &str, sub_namespace: &str) -> io::Result<Vec<String>> {
+		let mut persisted_lock = self.persisted_bytes.lock().unwrap();
+
+		let prefixed = if sub_namespace.is_empty() {
+			namespace.to_string()
+		} else {
+			format!("{}/{}", namespace, sub_namespace)
+		};
+		match persisted_lock.entry(prefixed) {
+			hash_map::Entry::Occupied(e) => Ok(e.get().keys().cloned().collect()),
+			hash_map::Entry::Vacant(_) => Ok(Vec::new()),
+		}
+	}
+}
+
 pub struct TestBroadcaster {
 	pub txn_broadcasted: Mutex<Vec<Transaction>>,
 	pub blocks: Arc<Mutex<Vec<(Block, u32)>>>,
@@ -529,6 +619,34 @@ impl msgs::ChannelMessageHandler for TestChannelMessageHandler {
 	fn handle_tx_abort(&self, _their_node_id: &PublicKey, msg: &msgs::TxAbort) {
 		self.received_msg(wire::Message::TxAbort(msg.clone()));
 	}
+
+	fn handle_splice(&self, _their_node_id: &PublicKey, msg: &msgs::Splice) {
+		self.received_msg(wire::Message::Splice(msg.clone()));
+	}
+
+	fn handle_splice_ack(&self, _their_node_id: &PublicKey, msg: &msgs::SpliceAck) {
+		self.received_msg(wire::Message::SpliceAck(msg.clone()));
+	}
+
+	fn handle_splice_locked(&self, _their_node_id: &PublicKey, msg: &msgs::SpliceLocked) {
+		self.received_msg(wire::Message::SpliceLocked(msg.clone()));
+	}
+
+	fn handle_stfu(&self, _their_node_id: &PublicKey, msg: &msgs::Stfu) {
+		self.received_msg(wire::Message::Stfu(msg.clone()));
+	}
+
+	fn handle_dyn_propose(&self, _their_node_id: &PublicKey, msg: &msgs::DynPropose) {
+		self.received_msg(wire::Message::DynPropose(msg.clone()));
+	}
+
+	fn handle_dyn_ack(&self, _their_node_id: &PublicKey, msg: &msgs::DynAck) {
+		self.received_msg(wire::Message::DynAck(msg.clone()));
+	}
+
+	fn handle_dyn_reject(&self, _their_node_id: &PublicKey, msg: &msgs::DynReject) {
+		self.received_msg(wire::Message::DynReject(msg.clone()));
+	}
 }
 
 impl events::MessageSendEventsProvider for TestChannelMessageHandler {
@@ -574,12 +692,14 @@ fn get_dummy_channel_announcement(short_chan_id: u64) -> msgs::ChannelAnnounceme
 fn get_dummy_channel_update(short_chan_id: u64) -> msgs::ChannelUpdate {
 	use bitcoin::secp256k1::ffi::Signature as FFISignature;
 	let network = Network::Testnet;
+	// Use the current time so that the update falls within any `gossip_timestamp_filter` we send.
+	let timestamp = crate::ln::peer_handler::current_unix_timestamp().unwrap_or(0) as u32;
 	msgs::ChannelUpdate {
 		signature: Signature::from(unsafe { FFISignature::new() }),
 		contents: msgs::UnsignedChannelUpdate {
 			chain_hash: genesis_block(network).header.block_hash(),
 			short_channel_id: short_chan_id,
-			timestamp: 0,
+			timestamp,
 			flags: 0,
 			cltv_expiry_delta: 0,
 			htlc_minimum_msat: 0,
@@ -632,6 +752,10 @@ impl msgs::RoutingMessageHandler for TestRoutingMessageHandler {
 		None
 	}
 
+	fn get_next_query_reply(&self, _their_node_id: &PublicKey) -> Option<msgs::GossipQueryReply> {
+		None
+	}
+
 	fn peer_connected(&self, their_node_id: &PublicKey, init_msg: &msgs::Init, _inbound: bool) -> Result<(), ()> {
 		if !init_msg.features.supports_gossip_queries() {
 			return Ok(());